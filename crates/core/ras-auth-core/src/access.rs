@@ -0,0 +1,71 @@
+//! Refusals of request-specific authorization checks.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Why an authorization callback refused a request, such as a user editing a
+/// task they don't own.
+///
+/// Generated services answer it as they do missing permissions: REST services
+/// with `403 Forbidden`, JSON-RPC services with an insufficient permissions
+/// error. Either way the client sees the `access_denied` code along with the
+/// reason and details.
+#[derive(Debug, Clone, PartialEq, Error, Serialize, Deserialize)]
+#[error("Access denied: {reason}")]
+pub struct AccessDenied {
+    /// Short machine-readable reason, such as `"not_owner"`
+    pub reason: String,
+    /// More for the client about why, such as who the owner is
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+impl AccessDenied {
+    /// The code clients see for these refusals
+    pub const CODE: &'static str = "access_denied";
+
+    /// A refusal for `reason`
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+            details: serde_json::Map::new(),
+        }
+    }
+
+    /// Tell the client `key` about the refusal
+    pub fn with_detail(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<serde_json::Value>,
+    ) -> Self {
+        self.details.insert(key.into(), value.into());
+        self
+    }
+
+    /// The details of error responses: the reason, then the details
+    pub fn response_details(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut details = serde_json::Map::new();
+        details.insert("reason".to_string(), self.reason.clone().into());
+        details.extend(self.details.clone());
+        details
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_details_lead_with_the_reason() {
+        let denied = AccessDenied::new("not_owner").with_detail("owner", "user-2");
+        assert_eq!(denied.to_string(), "Access denied: not_owner");
+        assert_eq!(
+            serde_json::Value::Object(denied.response_details()),
+            serde_json::json!({ "reason": "not_owner", "owner": "user-2" })
+        );
+        assert_eq!(
+            serde_json::to_value(AccessDenied::new("suspended")).unwrap(),
+            serde_json::json!({ "reason": "suspended" })
+        );
+    }
+}
@@ -0,0 +1,589 @@
+//! Caching decorator for [`AuthProvider`]s.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use futures::FutureExt;
+use futures::future::Shared;
+use sha2::{Digest, Sha256};
+
+use crate::{AuthError, AuthFuture, AuthProvider, AuthResult, AuthenticatedUser, PermissionExpr};
+
+type TokenKey = [u8; 32];
+
+/// A token and the binding its validation was checked against, see
+/// [`AuthProvider::request_binding`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    token: TokenKey,
+    binding: TokenKey,
+}
+
+impl CacheKey {
+    fn new(token: &str, binding: &[u8]) -> Self {
+        Self {
+            token: token_key(token),
+            binding: Sha256::digest(binding).into(),
+        }
+    }
+}
+
+/// An [`AuthProvider`] that remembers successful token validations for a while.
+///
+/// Tokens are keyed by their SHA-256 hash, so the cache never holds the tokens
+/// themselves. Only successful validations are cached: failures go to the inner
+/// provider every time, and an [`AuthError::TokenExpired`] drops any entry held
+/// for the token. Concurrent validations of the same token, such as the entries
+/// of one batch request, share a single call to the inner provider.
+///
+/// A revoked token keeps authenticating until its entry is older than `ttl`;
+/// call [`invalidate`](Self::invalidate) when signing a session out. Clones
+/// share the cache, so keep one around to read [`stats`](Self::stats) after
+/// handing the provider to a builder.
+///
+/// Requests are validated by the inner provider's
+/// [`authenticate_request`](AuthProvider::authenticate_request) and cached by
+/// their token and [`request_binding`](AuthProvider::request_binding), so a
+/// session bound to one client is checked again when another uses its token.
+pub struct CachingAuthProvider<P> {
+    inner: Arc<P>,
+    cache: Arc<TokenCache>,
+}
+
+impl<P: AuthProvider> CachingAuthProvider<P> {
+    /// Wrap `inner`, keeping up to `max_entries` validated tokens for `ttl` each.
+    ///
+    /// When the cache is full, the least recently used token is evicted.
+    pub fn new(inner: P, ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            cache: Arc::new(TokenCache {
+                ttl,
+                max_entries,
+                state: Mutex::new(CacheState::default()),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                evictions: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// The wrapped provider.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Drop the cached validations of `token`, if any.
+    pub fn invalidate(&self, token: &str) {
+        self.cache.lock().remove(&token_key(token));
+    }
+
+    /// Drop every cached validation.
+    pub fn clear(&self) {
+        let mut state = self.cache.lock();
+        state.entries.clear();
+        state.recency.clear();
+    }
+
+    /// Number of validations currently cached, including ones past their TTL
+    /// that have not been looked up since.
+    pub fn len(&self) -> usize {
+        self.cache.lock().entries.len()
+    }
+
+    /// Whether no tokens are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hit, miss and eviction counts since the provider was created.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.cache.hits.load(Ordering::Relaxed),
+            misses: self.cache.misses.load(Ordering::Relaxed),
+            evictions: self.cache.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The cached validation under `key`, joining one in flight or starting
+    /// one with `validate` if there is none
+    fn validate(
+        &self,
+        key: CacheKey,
+        validate: impl FnOnce() -> AuthFuture<'static>,
+    ) -> AuthFuture<'_> {
+        let validation = {
+            let mut state = self.cache.lock();
+            match state.lookup(&key, Instant::now()) {
+                Lookup::Cached(user) => {
+                    self.cache.hits.fetch_add(1, Ordering::Relaxed);
+                    return Box::pin(async move { Ok(user) });
+                }
+                Lookup::Pending(validation) => {
+                    self.cache.hits.fetch_add(1, Ordering::Relaxed);
+                    validation
+                }
+                Lookup::Missing => {
+                    self.cache.misses.fetch_add(1, Ordering::Relaxed);
+                    let validation = validate().shared();
+                    state.pending.insert(key, validation.clone());
+                    validation
+                }
+            }
+        };
+
+        Box::pin(async move {
+            let result = validation.clone().await;
+            self.cache.complete(key, &validation, &result);
+            result
+        })
+    }
+}
+
+impl<P> Clone for CachingAuthProvider<P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<P: AuthProvider> AuthProvider for CachingAuthProvider<P> {
+    fn credential(&self, headers: &http::HeaderMap) -> Option<String> {
+        self.inner.credential(headers)
+    }
+
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        let key = CacheKey::new(&token, &[]);
+        let inner = self.inner.clone();
+        self.validate(key, move || {
+            Box::pin(async move { inner.authenticate(token).await })
+        })
+    }
+
+    fn authenticate_request(&self, token: String, headers: &http::HeaderMap) -> AuthFuture<'_> {
+        let headers = headers.clone();
+        Box::pin(async move {
+            // Read as the request is authenticated, within its origin's scope
+            let key = CacheKey::new(&token, &self.inner.request_binding(&token, &headers));
+            let inner = self.inner.clone();
+            let validate = move || -> AuthFuture<'static> {
+                Box::pin(async move { inner.authenticate_request(token, &headers).await })
+            };
+            self.validate(key, validate).await
+        })
+    }
+
+    fn request_binding(&self, token: &str, headers: &http::HeaderMap) -> Vec<u8> {
+        self.inner.request_binding(token, headers)
+    }
+
+    fn check_permissions(
+        &self,
+        user: &AuthenticatedUser,
+        required_permissions: &[String],
+    ) -> AuthResult<()> {
+        self.inner.check_permissions(user, required_permissions)
+    }
+
+    fn check_permission_expr(
+        &self,
+        user: &AuthenticatedUser,
+        expression: &PermissionExpr,
+    ) -> AuthResult<()> {
+        self.inner.check_permission_expr(user, expression)
+    }
+}
+
+/// Counters describing how a [`CachingAuthProvider`] has been used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Validations answered from the cache or by joining an in-flight validation.
+    pub hits: u64,
+
+    /// Validations passed on to the inner provider.
+    pub misses: u64,
+
+    /// Cached validations dropped to make room for new ones.
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// Fraction of validations that did not reach the inner provider, or `0.0`
+    /// before the first validation.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+struct TokenCache {
+    ttl: Duration,
+    max_entries: usize,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl TokenCache {
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Record the outcome of a validation; every caller sharing it may report
+    /// it, and only the first report for the validation counts.
+    fn complete(
+        &self,
+        key: CacheKey,
+        validation: &Shared<AuthFuture<'static>>,
+        result: &AuthResult,
+    ) {
+        let mut state = self.lock();
+        match state.pending.get(&key) {
+            Some(pending) if pending.ptr_eq(validation) => {
+                state.pending.remove(&key);
+            }
+            _ => return,
+        }
+
+        match result {
+            Ok(user) if self.max_entries > 0 => {
+                let expires_at = Instant::now() + self.ttl;
+                let evicted = state.insert(key, user.clone(), expires_at, self.max_entries);
+                self.evictions.fetch_add(evicted, Ordering::Relaxed);
+            }
+            Err(AuthError::TokenExpired) => state.remove(&key.token),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    pending: HashMap<CacheKey, Shared<AuthFuture<'static>>>,
+    /// Keys in order of use, oldest first. A key reappears each time it is
+    /// used, and only the occurrence matching the entry's `last_used` is live.
+    recency: VecDeque<(CacheKey, u64)>,
+    clock: u64,
+}
+
+struct CacheEntry {
+    user: AuthenticatedUser,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+enum Lookup {
+    Cached(AuthenticatedUser),
+    Pending(Shared<AuthFuture<'static>>),
+    Missing,
+}
+
+impl CacheState {
+    fn lookup(&mut self, key: &CacheKey, now: Instant) -> Lookup {
+        if let Some(entry) = self.entries.get(key) {
+            if entry.expires_at > now {
+                let user = entry.user.clone();
+                self.touch(*key);
+                return Lookup::Cached(user);
+            }
+            self.entries.remove(key);
+        }
+
+        match self.pending.get(key) {
+            Some(validation) => Lookup::Pending(validation.clone()),
+            None => Lookup::Missing,
+        }
+    }
+
+    /// Cache `user` under `key`, returning how many entries were evicted.
+    fn insert(
+        &mut self,
+        key: CacheKey,
+        user: AuthenticatedUser,
+        expires_at: Instant,
+        max_entries: usize,
+    ) -> u64 {
+        let mut evicted = 0;
+        if !self.entries.contains_key(&key) {
+            while self.entries.len() >= max_entries {
+                let Some((oldest, used)) = self.recency.pop_front() else {
+                    break;
+                };
+                if self.entries.get(&oldest).map(|entry| entry.last_used) == Some(used) {
+                    self.entries.remove(&oldest);
+                    evicted += 1;
+                }
+            }
+        }
+
+        self.entries.insert(
+            key,
+            CacheEntry {
+                user,
+                expires_at,
+                last_used: 0,
+            },
+        );
+        self.touch(key);
+        evicted
+    }
+
+    fn touch(&mut self, key: CacheKey) {
+        self.clock += 1;
+        let used = self.clock;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.last_used = used;
+        }
+        self.recency.push_back((key, used));
+
+        // Drop stale occurrences once they outnumber the live ones
+        if self.recency.len() > 2 * self.entries.len() + 16 {
+            let entries = &self.entries;
+            self.recency
+                .retain(|(key, used)| entries.get(key).map(|entry| entry.last_used) == Some(*used));
+        }
+    }
+
+    /// Drop the validations of the token with `token` as its key, whatever
+    /// their binding
+    fn remove(&mut self, token: &TokenKey) {
+        self.entries.retain(|key, _| key.token != *token);
+    }
+}
+
+fn token_key(token: &str) -> TokenKey {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrincipalKind;
+    use std::collections::HashSet;
+    use std::sync::atomic::AtomicUsize;
+
+    #[derive(Default)]
+    struct CountingProvider {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl AuthProvider for CountingProvider {
+        fn authenticate(&self, token: String) -> AuthFuture<'_> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                match token.as_str() {
+                    "expired" => Err(AuthError::TokenExpired),
+                    "invalid" => Err(AuthError::InvalidToken),
+                    _ => Ok(AuthenticatedUser {
+                        user_id: token,
+                        permissions: HashSet::new(),
+                        metadata: None,
+                        kind: PrincipalKind::User,
+                    }),
+                }
+            })
+        }
+    }
+
+    fn caching(
+        ttl: Duration,
+        max_entries: usize,
+    ) -> (CachingAuthProvider<CountingProvider>, Arc<AtomicUsize>) {
+        let inner = CountingProvider::default();
+        let calls = inner.calls.clone();
+        (CachingAuthProvider::new(inner, ttl, max_entries), calls)
+    }
+
+    async fn user_id(provider: &impl AuthProvider, token: &str) -> String {
+        provider
+            .authenticate(token.to_string())
+            .await
+            .unwrap()
+            .user_id
+    }
+
+    #[tokio::test]
+    async fn repeated_tokens_are_served_from_the_cache() {
+        let (provider, calls) = caching(Duration::from_secs(60), 10);
+
+        assert_eq!(user_id(&provider, "alice").await, "alice");
+        assert_eq!(user_id(&provider, "alice").await, "alice");
+        assert_eq!(user_id(&provider, "bob").await, "bob");
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let stats = provider.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        assert!((stats.hit_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test]
+    async fn entries_expire_after_the_ttl() {
+        let (provider, calls) = caching(Duration::from_millis(30), 10);
+
+        user_id(&provider, "alice").await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        user_id(&provider, "alice").await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_tokens_are_evicted() {
+        let (provider, calls) = caching(Duration::from_secs(60), 2);
+
+        user_id(&provider, "alice").await;
+        user_id(&provider, "bob").await;
+        user_id(&provider, "alice").await;
+        user_id(&provider, "carol").await;
+        assert_eq!(provider.len(), 2);
+        assert_eq!(provider.stats().evictions, 1);
+
+        user_id(&provider, "alice").await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        user_id(&provider, "bob").await;
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn failures_are_not_cached() {
+        let (provider, calls) = caching(Duration::from_secs(60), 10);
+
+        for token in ["expired", "expired", "invalid", "invalid"] {
+            assert!(provider.authenticate(token.to_string()).await.is_err());
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert!(provider.is_empty());
+    }
+
+    #[tokio::test]
+    async fn concurrent_validations_of_a_token_are_shared() {
+        let (provider, calls) = caching(Duration::from_secs(60), 10);
+
+        let users = futures::future::join_all((0..8).map(|_| user_id(&provider, "alice"))).await;
+
+        assert!(users.iter().all(|user| user == "alice"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            provider.stats(),
+            CacheStats {
+                hits: 7,
+                misses: 1,
+                evictions: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn invalidated_tokens_are_validated_again() {
+        let (provider, calls) = caching(Duration::from_secs(60), 10);
+
+        user_id(&provider, "alice").await;
+        provider.invalidate("alice");
+        user_id(&provider, "alice").await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// Accepts tokens from requests of the `laptop` device only
+    struct DeviceBound(CountingProvider);
+
+    impl AuthProvider for DeviceBound {
+        fn authenticate(&self, token: String) -> AuthFuture<'_> {
+            self.0.authenticate(token)
+        }
+
+        fn authenticate_request(&self, token: String, headers: &http::HeaderMap) -> AuthFuture<'_> {
+            let device = headers.get("x-device-id").cloned();
+            Box::pin(async move {
+                let user = self.0.authenticate(token).await?;
+                match device {
+                    Some(device) if device == "laptop" => Ok(user),
+                    _ => Err(AuthError::InvalidToken),
+                }
+            })
+        }
+
+        fn request_binding(&self, _token: &str, headers: &http::HeaderMap) -> Vec<u8> {
+            headers
+                .get("x-device-id")
+                .map(|device| device.as_bytes().to_vec())
+                .unwrap_or_default()
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_are_checked_against_their_binding() {
+        let inner = DeviceBound(CountingProvider::default());
+        let calls = inner.0.calls.clone();
+        let provider = CachingAuthProvider::new(inner, Duration::from_secs(60), 10);
+        let from = |device: &str| {
+            let mut headers = http::HeaderMap::new();
+            headers.insert("x-device-id", device.parse().unwrap());
+            headers
+        };
+
+        for _ in 0..2 {
+            let user = provider
+                .authenticate_request("alice".into(), &from("laptop"))
+                .await;
+            assert_eq!(user.unwrap().user_id, "alice");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The token is cached, but not for requests from another device
+        let refused = provider
+            .authenticate_request("alice".into(), &from("phone"))
+            .await;
+        assert!(matches!(refused, Err(AuthError::InvalidToken)));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Invalidating a token drops its validations from every device
+        provider.invalidate("alice");
+        assert!(provider.is_empty());
+    }
+
+    #[test]
+    fn token_expiry_drops_the_cached_entry() {
+        let mut state = CacheState::default();
+        let key = CacheKey::new("alice", &[]);
+        let user = AuthenticatedUser {
+            user_id: "alice".to_string(),
+            permissions: HashSet::new(),
+            metadata: None,
+            kind: PrincipalKind::User,
+        };
+        state.insert(key, user, Instant::now() + Duration::from_secs(60), 10);
+
+        let validation: AuthFuture<'static> = Box::pin(async { Err(AuthError::TokenExpired) });
+        let validation = validation.shared();
+        state.pending.insert(key, validation.clone());
+        let cache = TokenCache {
+            ttl: Duration::from_secs(60),
+            max_entries: 10,
+            state: Mutex::new(state),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        };
+
+        cache.complete(key, &validation, &Err(AuthError::TokenExpired));
+
+        let state = cache.lock();
+        assert!(state.entries.is_empty());
+        assert!(state.pending.is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+//! Trying several [`AuthProvider`]s in turn.
+
+use std::sync::Arc;
+
+use crate::{AuthError, AuthFuture, AuthProvider};
+
+/// An [`AuthProvider`] trying each of several providers in turn, such as
+/// session tokens for people and API keys for machines on the same endpoints.
+///
+/// Providers added with [`with_prefix`](Self::with_prefix) are only given
+/// tokens starting with the prefix. The first provider accepting a token
+/// authenticates it. When every provider refuses it, the most telling of
+/// their errors is returned: an expired or not yet valid token over an
+/// internal error, and any of them over an invalid token, so a client is told
+/// to renew its token whichever provider recognised it. A token no provider
+/// is given is invalid.
+///
+/// A request's credential is the first any provider finds in it, and
+/// permissions are checked against the user's permissions, as by default.
+#[derive(Clone, Default)]
+pub struct AuthProviderChain {
+    links: Vec<Link>,
+    stop_on_invalid_token: bool,
+}
+
+#[derive(Clone)]
+struct Link {
+    prefix: Option<String>,
+    provider: Arc<dyn AuthProvider>,
+}
+
+impl AuthProviderChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Try `provider` next, with every token
+    pub fn with(mut self, provider: impl AuthProvider) -> Self {
+        self.links.push(Link {
+            prefix: None,
+            provider: Arc::new(provider),
+        });
+        self
+    }
+
+    /// Try `provider` next, with tokens starting with `prefix`, such as the
+    /// `rak_` of API keys
+    pub fn with_prefix(mut self, prefix: impl Into<String>, provider: impl AuthProvider) -> Self {
+        self.links.push(Link {
+            prefix: Some(prefix.into()),
+            provider: Arc::new(provider),
+        });
+        self
+    }
+
+    /// Stop at the first provider refusing a token as invalid, rather than
+    /// trying the next, such as when later providers call remote services
+    pub fn stop_on_invalid_token(mut self) -> Self {
+        self.stop_on_invalid_token = true;
+        self
+    }
+
+    /// The providers given `token`, in order
+    fn providers_for<'a>(&'a self, token: &'a str) -> impl Iterator<Item = &'a dyn AuthProvider> {
+        self.links
+            .iter()
+            .filter(move |link| {
+                link.prefix
+                    .as_deref()
+                    .is_none_or(|prefix| token.starts_with(prefix))
+            })
+            .map(|link| link.provider.as_ref())
+    }
+
+    /// Whether to stop after a provider refused a token with `error`
+    fn stops_at(&self, error: &AuthError) -> bool {
+        self.stop_on_invalid_token && matches!(error, AuthError::InvalidToken)
+    }
+}
+
+/// How much an error says about a token, so the most telling is reported
+fn specificity(error: &AuthError) -> u8 {
+    match error {
+        AuthError::TokenExpired
+        | AuthError::TokenNotYetValid
+        | AuthError::AccountLocked { .. }
+        | AuthError::RateLimited { .. } => 3,
+        AuthError::Internal(_) => 2,
+        AuthError::InsufficientPermissions { .. } => 1,
+        AuthError::InvalidToken | AuthError::AuthenticationRequired => 0,
+    }
+}
+
+/// Keep the more telling of `refused` and `error`, the earlier on a tie
+fn most_telling(refused: Option<AuthError>, error: AuthError) -> AuthError {
+    match refused {
+        Some(refused) if specificity(&refused) >= specificity(&error) => refused,
+        _ => error,
+    }
+}
+
+impl AuthProvider for AuthProviderChain {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        Box::pin(async move {
+            let mut refused = None;
+            for provider in self.providers_for(&token) {
+                match provider.authenticate(token.clone()).await {
+                    Ok(user) => return Ok(user),
+                    Err(error) => {
+                        let stop = self.stops_at(&error);
+                        refused = Some(most_telling(refused, error));
+                        if stop {
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(refused.unwrap_or(AuthError::InvalidToken))
+        })
+    }
+
+    fn authenticate_request(&self, token: String, headers: &http::HeaderMap) -> AuthFuture<'_> {
+        let headers = headers.clone();
+        Box::pin(async move {
+            let mut refused = None;
+            for provider in self.providers_for(&token) {
+                match provider.authenticate_request(token.clone(), &headers).await {
+                    Ok(user) => return Ok(user),
+                    Err(error) => {
+                        let stop = self.stops_at(&error);
+                        refused = Some(most_telling(refused, error));
+                        if stop {
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(refused.unwrap_or(AuthError::InvalidToken))
+        })
+    }
+
+    /// The bindings of every provider given the token, each prefixed by its
+    /// length
+    fn request_binding(&self, token: &str, headers: &http::HeaderMap) -> Vec<u8> {
+        let mut bindings = Vec::new();
+        for provider in self.providers_for(token) {
+            let binding = provider.request_binding(token, headers);
+            bindings.extend_from_slice(&(binding.len() as u64).to_be_bytes());
+            bindings.extend_from_slice(&binding);
+        }
+        bindings
+    }
+
+    fn credential(&self, headers: &http::HeaderMap) -> Option<String> {
+        self.links
+            .iter()
+            .find_map(|link| link.provider.credential(headers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthenticatedUser, PrincipalKind};
+    use std::sync::Mutex;
+
+    /// Accepts `token` as `user`, refusing others with `error`, and records
+    /// the tokens it is given in `seen`
+    struct Fixed {
+        token: &'static str,
+        error: AuthError,
+        seen: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Fixed {
+        fn new(token: &'static str, error: AuthError, seen: &Arc<Mutex<Vec<String>>>) -> Self {
+            Self {
+                token,
+                error,
+                seen: seen.clone(),
+            }
+        }
+    }
+
+    impl AuthProvider for Fixed {
+        fn authenticate(&self, token: String) -> AuthFuture<'_> {
+            Box::pin(async move {
+                self.seen
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}:{token}", self.token));
+                if token == self.token {
+                    Ok(AuthenticatedUser {
+                        user_id: token,
+                        permissions: Default::default(),
+                        metadata: None,
+                        kind: PrincipalKind::User,
+                    })
+                } else {
+                    Err(self.error.clone())
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn providers_are_tried_in_order_until_one_accepts() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let chain = AuthProviderChain::new()
+            .with_prefix(
+                "rak_",
+                Fixed::new("rak_key", AuthError::InvalidToken, &seen),
+            )
+            .with(Fixed::new("jwt", AuthError::InvalidToken, &seen))
+            .with(Fixed::new("other", AuthError::InvalidToken, &seen));
+
+        assert_eq!(
+            chain.authenticate("jwt".to_string()).await.unwrap().user_id,
+            "jwt"
+        );
+        assert_eq!(
+            chain
+                .authenticate("rak_key".to_string())
+                .await
+                .unwrap()
+                .user_id,
+            "rak_key"
+        );
+        assert_eq!(
+            chain
+                .authenticate("other".to_string())
+                .await
+                .unwrap()
+                .user_id,
+            "other"
+        );
+        assert_eq!(
+            *seen.lock().unwrap(),
+            ["jwt:jwt", "rak_key:rak_key", "jwt:other", "other:other",]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_most_telling_error_is_reported() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let chain = AuthProviderChain::new()
+            .with(Fixed::new("a", AuthError::InvalidToken, &seen))
+            .with(Fixed::new("b", AuthError::TokenExpired, &seen))
+            .with(Fixed::new("c", AuthError::Internal("down".into()), &seen))
+            .with(Fixed::new("d", AuthError::InvalidToken, &seen));
+        assert!(matches!(
+            chain.authenticate("stale".to_string()).await,
+            Err(AuthError::TokenExpired)
+        ));
+
+        let chain = AuthProviderChain::new()
+            .with(Fixed::new("a", AuthError::InvalidToken, &seen))
+            .with(Fixed::new("b", AuthError::Internal("down".into()), &seen));
+        assert!(matches!(
+            chain.authenticate("x".to_string()).await,
+            Err(AuthError::Internal(_))
+        ));
+
+        // Tokens no provider is given, and empty chains, are invalid
+        let chain = AuthProviderChain::new().with_prefix(
+            "rak_",
+            Fixed::new("rak_key", AuthError::TokenExpired, &seen),
+        );
+        assert!(matches!(
+            chain.authenticate("jwt".to_string()).await,
+            Err(AuthError::InvalidToken)
+        ));
+        assert!(matches!(
+            AuthProviderChain::new()
+                .authenticate("jwt".to_string())
+                .await,
+            Err(AuthError::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn invalid_tokens_can_stop_the_chain() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let chain = AuthProviderChain::new()
+            .with(Fixed::new("a", AuthError::TokenExpired, &seen))
+            .with(Fixed::new("b", AuthError::InvalidToken, &seen))
+            .with(Fixed::new("c", AuthError::InvalidToken, &seen))
+            .stop_on_invalid_token();
+
+        assert!(matches!(
+            chain.authenticate("c".to_string()).await,
+            Err(AuthError::TokenExpired)
+        ));
+        assert_eq!(*seen.lock().unwrap(), ["a:c", "b:c"]);
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::AUTHORIZATION, "Bearer a".parse().unwrap());
+        assert_eq!(chain.credential(&headers).as_deref(), Some("a"));
+        assert_eq!(
+            chain
+                .authenticate_request("a".to_string(), &headers)
+                .await
+                .unwrap()
+                .user_id,
+            "a"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! The user of the request being handled, for code the handler calls.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::AuthenticatedUser;
+
+tokio::task_local! {
+    static CURRENT_USER: Option<Arc<AuthenticatedUser>>;
+}
+
+/// The user of the request being handled, if it was authenticated.
+///
+/// Generated REST, JSON-RPC and bidirectional services set it while their
+/// handlers run, so functions a handler calls can read it rather than take
+/// the user as a parameter. It belongs to the handler's task: futures handed
+/// to `tokio::spawn` run without it, and see `None`, unless wrapped in
+/// [`propagate_user`]. Streamed JSON-RPC results are produced after the
+/// handler returns, so they see `None` too.
+pub fn current_user() -> Option<Arc<AuthenticatedUser>> {
+    CURRENT_USER.try_with(Clone::clone).ok().flatten()
+}
+
+/// Run `future` with `user` as its [`current_user`], restoring the previous
+/// one when it completes
+pub async fn scope_user<F: Future>(user: Option<Arc<AuthenticatedUser>>, future: F) -> F::Output {
+    CURRENT_USER.scope(user, future).await
+}
+
+/// Carry the [`current_user`] into `future`, such as before spawning it:
+///
+/// ```ignore
+/// tokio::spawn(propagate_user(async move { audit(&order).await }));
+/// ```
+pub fn propagate_user<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    scope_user(current_user(), future)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrincipalKind;
+    use std::time::Duration;
+
+    fn user(id: &str) -> Option<Arc<AuthenticatedUser>> {
+        Some(Arc::new(AuthenticatedUser {
+            user_id: id.to_string(),
+            permissions: Default::default(),
+            metadata: None,
+            kind: PrincipalKind::User,
+        }))
+    }
+
+    fn current_id() -> Option<String> {
+        current_user().map(|user| user.user_id.clone())
+    }
+
+    async fn nested() -> Option<String> {
+        tokio::task::yield_now().await;
+        current_id()
+    }
+
+    #[tokio::test]
+    async fn nested_calls_see_the_user_of_their_scope() {
+        assert_eq!(current_id(), None);
+        let seen = scope_user(user("alice"), async {
+            let outer = nested().await;
+            let inner = scope_user(user("bob"), nested()).await;
+            (outer, inner, nested().await)
+        })
+        .await;
+        assert_eq!(
+            seen,
+            (
+                Some("alice".to_string()),
+                Some("bob".to_string()),
+                Some("alice".to_string())
+            )
+        );
+        assert_eq!(current_id(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_requests_keep_their_own_user() {
+        let requests = (0..32).map(|i| {
+            tokio::spawn(scope_user(user(&format!("user-{i}")), async move {
+                for _ in 0..10 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    assert_eq!(current_id(), Some(format!("user-{i}")));
+                }
+            }))
+        });
+        for request in requests.collect::<Vec<_>>() {
+            request.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn spawned_tasks_only_see_a_propagated_user() {
+        let (lost, kept) = scope_user(user("alice"), async {
+            let lost = tokio::spawn(nested()).await.unwrap();
+            let kept = tokio::spawn(propagate_user(nested())).await.unwrap();
+            (lost, kept)
+        })
+        .await;
+        assert_eq!(lost, None);
+        assert_eq!(kept, Some("alice".to_string()));
+    }
+}
@@ -3,10 +3,25 @@
 use std::collections::HashSet;
 use std::future::Future;
 use std::pin::Pin;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+mod access;
+mod cache;
+mod chain;
+mod context;
+mod permission_expr;
+mod static_token;
+
+pub use access::AccessDenied;
+pub use cache::{CacheStats, CachingAuthProvider};
+pub use chain::AuthProviderChain;
+pub use context::{current_user, propagate_user, scope_user};
+pub use permission_expr::{PermissionExpr, PermissionExprError};
+pub use static_token::StaticTokenAuthProvider;
+
 /// Errors that can occur during authentication or authorization.
 #[derive(Debug, Error, Clone, Serialize, Deserialize)]
 pub enum AuthError {
@@ -18,6 +33,10 @@ pub enum AuthError {
     #[error("Token expired")]
     TokenExpired,
 
+    /// The token is not valid yet, such as when the issuer's clock is ahead.
+    #[error("Token not yet valid")]
+    TokenNotYetValid,
+
     /// The token does not have the required permissions.
     #[error("Insufficient permissions: required {required:?}, has {has:?}")]
     InsufficientPermissions {
@@ -32,6 +51,95 @@ pub enum AuthError {
     /// An internal error occurred during authentication.
     #[error("Authentication error: {0}")]
     Internal(String),
+
+    /// The account is locked, such as after too many failed sign-ins, until
+    /// `retry_after` has passed if it unlocks by itself.
+    #[error("Account locked")]
+    AccountLocked { retry_after: Option<Duration> },
+
+    /// Too many attempts were made; they may be retried after `retry_after`.
+    #[error("Too many attempts, retry after {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+}
+
+impl AuthError {
+    /// A stable, machine-readable code for the error, which generated
+    /// services include in their failure responses and clients expose, so
+    /// applications can tell errors apart without matching their messages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::InvalidToken => "invalid_token",
+            AuthError::TokenExpired => "token_expired",
+            AuthError::TokenNotYetValid => "token_not_yet_valid",
+            AuthError::InsufficientPermissions { .. } => "insufficient_permissions",
+            AuthError::AuthenticationRequired => "authentication_required",
+            AuthError::Internal(_) => "internal_error",
+            AuthError::AccountLocked { .. } => "account_locked",
+            AuthError::RateLimited { .. } => "rate_limited",
+        }
+    }
+
+    /// How long the client should wait before trying again, if it should
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AuthError::AccountLocked { retry_after } => *retry_after,
+            AuthError::RateLimited { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    /// Details of the error safe to show its client: the required and held
+    /// permissions of [`InsufficientPermissions`](Self::InsufficientPermissions)
+    pub fn details(&self) -> Option<serde_json::Map<String, serde_json::Value>> {
+        match self {
+            AuthError::InsufficientPermissions { required, has } => {
+                let mut details = serde_json::Map::new();
+                details.insert("required".to_string(), required.clone().into());
+                details.insert("has".to_string(), has.clone().into());
+                Some(details)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What kind of caller an [`AuthenticatedUser`] is, so services and their
+/// dashboards can tell people from machines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrincipalKind {
+    /// A person signed in to the service.
+    #[default]
+    User,
+
+    /// A machine, such as a cron job calling with a static token.
+    ServiceAccount,
+
+    /// A caller that didn't identify itself, such as a guest.
+    Anonymous,
+}
+
+impl PrincipalKind {
+    /// The kind of `user`, where requests without a user are anonymous
+    pub fn of(user: Option<&AuthenticatedUser>) -> Self {
+        user.map_or(PrincipalKind::Anonymous, |user| user.kind)
+    }
+
+    /// The name of the kind, used as a metric label: `user`,
+    /// `service_account` or `anonymous`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrincipalKind::User => "user",
+            PrincipalKind::ServiceAccount => "service_account",
+            PrincipalKind::Anonymous => "anonymous",
+        }
+    }
+}
+
+impl std::fmt::Display for PrincipalKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Represents an authenticated user with their permissions.
@@ -45,6 +153,10 @@ pub struct AuthenticatedUser {
 
     /// Optional additional metadata about the user.
     pub metadata: Option<serde_json::Value>,
+
+    /// What kind of caller this is; a person unless the provider says otherwise.
+    #[serde(default)]
+    pub kind: PrincipalKind,
 }
 
 /// Result type for authentication operations.
@@ -54,6 +166,25 @@ pub type AuthResult<T = AuthenticatedUser> = Result<T, AuthError>;
 pub type AuthFuture<'a, T = AuthenticatedUser> =
     Pin<Box<dyn Future<Output = AuthResult<T>> + Send + 'a>>;
 
+/// The credential carried by an `Authorization` header value.
+///
+/// Accepts the `Bearer` scheme used for session tokens and the `ApiKey` scheme
+/// used for API keys; the [`AuthProvider`] decides which credentials it takes.
+pub fn authorization_credential(header_value: &str) -> Option<&str> {
+    header_value
+        .strip_prefix("Bearer ")
+        .or_else(|| header_value.strip_prefix("ApiKey "))
+}
+
+/// The credential of the `Authorization` header in `headers`, if it has one
+pub fn header_credential(headers: &http::HeaderMap) -> Option<String> {
+    headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(authorization_credential)
+        .map(str::to_string)
+}
+
 /// Trait for implementing authentication providers.
 ///
 /// This trait allows for flexible authentication mechanisms while providing
@@ -69,6 +200,36 @@ pub trait AuthProvider: Send + Sync + 'static {
     /// * `Err(AuthError)` if validation fails
     fn authenticate(&self, token: String) -> AuthFuture<'_>;
 
+    /// The credential a request carries, to pass to [`authenticate`](Self::authenticate).
+    ///
+    /// That of the `Authorization` header by default; providers taking
+    /// credentials from elsewhere, such as a cookie, override it.
+    fn credential(&self, headers: &http::HeaderMap) -> Option<String> {
+        header_credential(headers)
+    }
+
+    /// [`authenticate`](Self::authenticate) the `token` of a request with
+    /// `headers`, which generated services call.
+    ///
+    /// Providers checking more of the request than its token, such as that a
+    /// session is used from the client it began on, override it.
+    fn authenticate_request(&self, token: String, headers: &http::HeaderMap) -> AuthFuture<'_> {
+        let _ = headers;
+        self.authenticate(token)
+    }
+
+    /// What [`authenticate_request`](Self::authenticate_request) checks of a
+    /// request with `headers` besides its `token`, so that requests with the
+    /// same token and binding are authenticated alike.
+    ///
+    /// Nothing by default. Providers overriding `authenticate_request` to
+    /// check more of a request override it too, which caches such as
+    /// [`CachingAuthProvider`] key validations by.
+    fn request_binding(&self, token: &str, headers: &http::HeaderMap) -> Vec<u8> {
+        let _ = (token, headers);
+        Vec::new()
+    }
+
     /// Checks if the authenticated user has the required permissions.
     ///
     /// # Arguments
@@ -98,4 +259,88 @@ pub trait AuthProvider: Send + Sync + 'static {
             })
         }
     }
+
+    /// Checks that the authenticated user's permissions satisfy `expression`,
+    /// as generated services do for `WITH_PERMISSIONS(expr = "...")`.
+    ///
+    /// # Returns
+    /// * `Ok(())` if they do
+    /// * `Err(AuthError::InsufficientPermissions)` requiring the expression if not
+    fn check_permission_expr(
+        &self,
+        user: &AuthenticatedUser,
+        expression: &PermissionExpr,
+    ) -> AuthResult<()> {
+        if expression.evaluate(&user.permissions) {
+            Ok(())
+        } else {
+            Err(AuthError::InsufficientPermissions {
+                required: vec![expression.to_string()],
+                has: user.permissions.iter().cloned().collect(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorization_credential() {
+        assert_eq!(authorization_credential("Bearer abc.def"), Some("abc.def"));
+        assert_eq!(
+            authorization_credential("ApiKey rak_1.secret"),
+            Some("rak_1.secret")
+        );
+        assert_eq!(authorization_credential("Basic dXNlcjpwYXNz"), None);
+        assert_eq!(authorization_credential("abc.def"), None);
+    }
+
+    #[test]
+    fn test_principal_kind() {
+        // Users serialized before principal kinds existed are people
+        let user: AuthenticatedUser = serde_json::from_value(serde_json::json!({
+            "user_id": "alice",
+            "permissions": ["user"],
+            "metadata": null
+        }))
+        .unwrap();
+        assert_eq!(user.kind, PrincipalKind::User);
+        assert_eq!(PrincipalKind::of(Some(&user)), PrincipalKind::User);
+        assert_eq!(PrincipalKind::of(None), PrincipalKind::Anonymous);
+
+        let service = AuthenticatedUser {
+            kind: PrincipalKind::ServiceAccount,
+            ..user
+        };
+        assert_eq!(
+            serde_json::to_value(&service).unwrap()["kind"],
+            "service_account"
+        );
+        assert_eq!(service.kind.to_string(), "service_account");
+    }
+
+    #[test]
+    fn test_auth_error_metadata() {
+        assert_eq!(AuthError::TokenExpired.code(), "token_expired");
+        assert_eq!(AuthError::TokenExpired.retry_after(), None);
+
+        let locked = AuthError::AccountLocked {
+            retry_after: Some(Duration::from_secs(60)),
+        };
+        assert_eq!(locked.code(), "account_locked");
+        assert_eq!(locked.retry_after(), Some(Duration::from_secs(60)));
+        assert!(locked.details().is_none());
+
+        let denied = AuthError::InsufficientPermissions {
+            required: vec!["admin".to_string()],
+            has: vec!["user".to_string()],
+        };
+        assert_eq!(denied.code(), "insufficient_permissions");
+        assert_eq!(
+            serde_json::Value::Object(denied.details().unwrap()),
+            serde_json::json!({ "required": ["admin"], "has": ["user"] })
+        );
+    }
 }
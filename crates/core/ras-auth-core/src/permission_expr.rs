@@ -0,0 +1,467 @@
+//! Permission requirements beyond groups of permissions, such as
+//! `admin | (tasks:write & !suspended)`.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A requirement on a user's permissions, combining permissions with `&`
+/// (and), `|` (or) and `!` (not).
+///
+/// `!` binds tightest and `|` loosest, so `a | b & !c` is `a | (b & (!c))`;
+/// parentheses group. Permissions are any run of characters other than
+/// whitespace, parentheses, the operators, quotes and commas, such as
+/// `tasks:write`.
+///
+/// Generated services parse the expressions of
+/// `WITH_PERMISSIONS(expr = "...")` when they are compiled, and check them
+/// with [`AuthProvider::check_permission_expr`](crate::AuthProvider::check_permission_expr).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionExpr {
+    /// Holding the permission.
+    Permission(String),
+
+    /// Not satisfying the expression.
+    Not(Box<PermissionExpr>),
+
+    /// Satisfying every expression.
+    All(Vec<PermissionExpr>),
+
+    /// Satisfying any of the expressions.
+    Any(Vec<PermissionExpr>),
+}
+
+/// Why a permission expression could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message} at column {column}")]
+pub struct PermissionExprError {
+    /// What was wrong.
+    pub message: String,
+
+    /// The 1-based column, in characters, where it went wrong.
+    pub column: usize,
+}
+
+impl PermissionExpr {
+    /// Parse an expression such as `admin | (tasks:write & !suspended)`.
+    pub fn parse(source: &str) -> Result<Self, PermissionExprError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            end: source.chars().count() + 1,
+        };
+        let expr = parser.any()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some((Token::Close, column)) => Err(error("unmatched `)`", column)),
+            Some((token, column)) => Err(error(
+                format!("expected `&`, `|` or the end of the expression, found {token}"),
+                column,
+            )),
+        }
+    }
+
+    /// Whether a user with `permissions` satisfies the expression.
+    pub fn evaluate(&self, permissions: &HashSet<String>) -> bool {
+        match self {
+            PermissionExpr::Permission(permission) => permissions.contains(permission),
+            PermissionExpr::Not(expr) => !expr.evaluate(permissions),
+            PermissionExpr::All(exprs) => exprs.iter().all(|expr| expr.evaluate(permissions)),
+            PermissionExpr::Any(exprs) => exprs.iter().any(|expr| expr.evaluate(permissions)),
+        }
+    }
+
+    /// The permissions the expression names, in order, without repeats.
+    pub fn permissions(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_permissions(&mut names);
+        names
+    }
+
+    fn collect_permissions<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            PermissionExpr::Permission(permission) => {
+                if !names.contains(&permission.as_str()) {
+                    names.push(permission);
+                }
+            }
+            PermissionExpr::Not(expr) => expr.collect_permissions(names),
+            PermissionExpr::All(exprs) | PermissionExpr::Any(exprs) => {
+                for expr in exprs {
+                    expr.collect_permissions(names);
+                }
+            }
+        }
+    }
+
+    /// How tightly the expression's operator binds, to parenthesize it inside
+    /// another
+    fn precedence(&self) -> u8 {
+        match self {
+            PermissionExpr::Any(exprs) | PermissionExpr::All(exprs) if exprs.len() == 1 => {
+                exprs[0].precedence()
+            }
+            PermissionExpr::Any(_) => 0,
+            PermissionExpr::All(_) => 1,
+            PermissionExpr::Not(_) | PermissionExpr::Permission(_) => 2,
+        }
+    }
+
+    fn fmt_within(&self, f: &mut fmt::Formatter<'_>, precedence: u8) -> fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+/// Written with as few parentheses as its meaning allows, so it parses back
+/// to the same expression.
+impl fmt::Display for PermissionExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (exprs, separator, precedence) = match self {
+            PermissionExpr::Permission(permission) => return f.write_str(permission),
+            PermissionExpr::Not(expr) => {
+                f.write_str("!")?;
+                return expr.fmt_within(f, 2);
+            }
+            PermissionExpr::All(exprs) => (exprs, " & ", 1),
+            PermissionExpr::Any(exprs) => (exprs, " | ", 0),
+        };
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                f.write_str(separator)?;
+            }
+            expr.fmt_within(f, precedence)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Permission(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Permission(permission) => write!(f, "`{permission}`"),
+            Token::And => f.write_str("`&`"),
+            Token::Or => f.write_str("`|`"),
+            Token::Not => f.write_str("`!`"),
+            Token::Open => f.write_str("`(`"),
+            Token::Close => f.write_str("`)`"),
+        }
+    }
+}
+
+fn error(message: impl Into<String>, column: usize) -> PermissionExprError {
+    PermissionExprError {
+        message: message.into(),
+        column,
+    }
+}
+
+/// Split `source` into tokens with the columns they start at
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, PermissionExprError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().zip(1..).peekable();
+    while let Some((c, column)) = chars.next() {
+        let token = match c {
+            '&' => Token::And,
+            '|' => Token::Or,
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if c.is_whitespace() => continue,
+            c if c.is_control() || c == '"' || c == ',' => {
+                return Err(error(format!("unexpected character {c:?}"), column));
+            }
+            c => {
+                let mut permission = c.to_string();
+                while let Some(&(c, _)) = chars.peek() {
+                    if c.is_whitespace() || "&|!()\",".contains(c) || c.is_control() {
+                        break;
+                    }
+                    permission.push(c);
+                    chars.next();
+                }
+                Token::Permission(permission)
+            }
+        };
+        tokens.push((token, column));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    /// The column just past the end of the source
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(&Token, usize)> {
+        self.tokens
+            .get(self.position)
+            .map(|(token, column)| (token, *column))
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek().is_some_and(|(next, _)| next == token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    /// `all ('|' all)*`
+    fn any(&mut self) -> Result<PermissionExpr, PermissionExprError> {
+        let mut exprs = Vec::new();
+        loop {
+            match self.all()? {
+                PermissionExpr::Any(grouped) => exprs.extend(grouped),
+                expr => exprs.push(expr),
+            }
+            if !self.eat(&Token::Or) {
+                break;
+            }
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            PermissionExpr::Any(exprs)
+        })
+    }
+
+    /// `unary ('&' unary)*`
+    fn all(&mut self) -> Result<PermissionExpr, PermissionExprError> {
+        let mut exprs = Vec::new();
+        loop {
+            match self.unary()? {
+                PermissionExpr::All(grouped) => exprs.extend(grouped),
+                expr => exprs.push(expr),
+            }
+            if !self.eat(&Token::And) {
+                break;
+            }
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            PermissionExpr::All(exprs)
+        })
+    }
+
+    /// `'!' unary | '(' any ')' | permission`
+    fn unary(&mut self) -> Result<PermissionExpr, PermissionExprError> {
+        let Some((token, column)) = self.peek() else {
+            return Err(error(
+                "expected a permission, `!` or `(`, found the end of the expression",
+                self.end,
+            ));
+        };
+        match token.clone() {
+            Token::Not => {
+                self.position += 1;
+                Ok(PermissionExpr::Not(Box::new(self.unary()?)))
+            }
+            Token::Open => {
+                self.position += 1;
+                let expr = self.any()?;
+                if !self.eat(&Token::Close) {
+                    return Err(error("unclosed `(`", column));
+                }
+                Ok(expr)
+            }
+            Token::Permission(permission) => {
+                self.position += 1;
+                Ok(PermissionExpr::Permission(permission))
+            }
+            token => Err(error(
+                format!("expected a permission, `!` or `(`, found {token}"),
+                column,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permission(name: &str) -> PermissionExpr {
+        PermissionExpr::Permission(name.to_string())
+    }
+
+    fn not(expr: PermissionExpr) -> PermissionExpr {
+        PermissionExpr::Not(Box::new(expr))
+    }
+
+    fn parse(source: &str) -> PermissionExpr {
+        PermissionExpr::parse(source).unwrap()
+    }
+
+    fn parse_error(source: &str) -> String {
+        PermissionExpr::parse(source).unwrap_err().to_string()
+    }
+
+    fn permissions(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn operators_bind_not_then_and_then_or() {
+        assert_eq!(parse("admin"), permission("admin"));
+        assert_eq!(
+            parse("a | b & !c"),
+            PermissionExpr::Any(vec![
+                permission("a"),
+                PermissionExpr::All(vec![permission("b"), not(permission("c"))]),
+            ])
+        );
+        assert_eq!(
+            parse("a & b | c & d"),
+            PermissionExpr::Any(vec![
+                PermissionExpr::All(vec![permission("a"), permission("b")]),
+                PermissionExpr::All(vec![permission("c"), permission("d")]),
+            ])
+        );
+        assert_eq!(
+            parse("a | b | c"),
+            PermissionExpr::Any(vec![permission("a"), permission("b"), permission("c")])
+        );
+        assert_eq!(parse("!!a"), not(not(permission("a"))));
+    }
+
+    #[test]
+    fn parentheses_group() {
+        assert_eq!(
+            parse("(a | b) & c"),
+            PermissionExpr::All(vec![
+                PermissionExpr::Any(vec![permission("a"), permission("b")]),
+                permission("c"),
+            ])
+        );
+        assert_eq!(
+            parse("!(a & b)"),
+            not(PermissionExpr::All(vec![permission("a"), permission("b")]))
+        );
+        assert_eq!(parse("((a))"), permission("a"));
+        // Groups of the same operator are flattened
+        assert_eq!(
+            parse("a & (b & c)"),
+            PermissionExpr::All(vec![permission("a"), permission("b"), permission("c")])
+        );
+        assert_eq!(
+            parse("  admin|(tasks:write&!suspended) "),
+            parse("admin | (tasks:write & !suspended)")
+        );
+    }
+
+    #[test]
+    fn expressions_evaluate_against_permissions() {
+        let expr = parse("admin | (tasks:write & !suspended)");
+        assert!(expr.evaluate(&permissions(&["admin"])));
+        assert!(expr.evaluate(&permissions(&["admin", "suspended"])));
+        assert!(expr.evaluate(&permissions(&["tasks:write"])));
+        assert!(!expr.evaluate(&permissions(&["tasks:write", "suspended"])));
+        assert!(!expr.evaluate(&permissions(&[])));
+
+        assert!(parse("!banned").evaluate(&permissions(&[])));
+        assert!(!parse("(a | b) & c").evaluate(&permissions(&["a"])));
+        assert!(parse("(a | b) & c").evaluate(&permissions(&["b", "c"])));
+        assert!(parse("a | b & c").evaluate(&permissions(&["a"])));
+    }
+
+    #[test]
+    fn display_parses_back_to_the_same_expression() {
+        for (source, displayed) in [
+            ("admin", "admin"),
+            (
+                "admin|(tasks:write&!suspended)",
+                "admin | tasks:write & !suspended",
+            ),
+            ("(a | b) & c", "(a | b) & c"),
+            ("!(a & b)", "!(a & b)"),
+            ("!!a", "!!a"),
+            ("a & (b & c)", "a & b & c"),
+            ("((a))", "a"),
+        ] {
+            let expr = parse(source);
+            assert_eq!(expr.to_string(), displayed);
+            assert_eq!(parse(displayed), expr.clone(), "{displayed}");
+        }
+    }
+
+    #[test]
+    fn permissions_lists_the_names_once() {
+        assert_eq!(
+            parse("admin | (tasks:write & !suspended) | admin").permissions(),
+            ["admin", "tasks:write", "suspended"]
+        );
+    }
+
+    #[test]
+    fn invalid_expressions_say_where_they_went_wrong() {
+        assert_eq!(
+            parse_error(""),
+            "expected a permission, `!` or `(`, found the end of the expression at column 1"
+        );
+        assert_eq!(
+            parse_error("admin |"),
+            "expected a permission, `!` or `(`, found the end of the expression at column 8"
+        );
+        assert_eq!(
+            parse_error("admin && owner"),
+            "expected a permission, `!` or `(`, found `&` at column 8"
+        );
+        assert_eq!(
+            parse_error("admin owner"),
+            "expected `&`, `|` or the end of the expression, found `owner` at column 7"
+        );
+        assert_eq!(parse_error("(admin | owner"), "unclosed `(` at column 1");
+        assert_eq!(parse_error("admin)"), "unmatched `)` at column 6");
+        assert_eq!(
+            parse_error("admin | ()"),
+            "expected a permission, `!` or `(`, found `)` at column 10"
+        );
+        assert_eq!(
+            parse_error("!"),
+            "expected a permission, `!` or `(`, found the end of the expression at column 2"
+        );
+        assert_eq!(
+            parse_error("\"admin\""),
+            "unexpected character '\"' at column 1"
+        );
+        assert_eq!(
+            parse_error("admin, owner"),
+            "unexpected character ',' at column 6"
+        );
+    }
+
+    #[test]
+    fn expressions_round_trip_through_serde() {
+        let expr = parse("admin | !suspended");
+        let json = serde_json::to_value(&expr).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "any": [{ "permission": "admin" }, { "not": { "permission": "suspended" } }] })
+        );
+        assert_eq!(
+            serde_json::from_value::<PermissionExpr>(json).unwrap(),
+            expr
+        );
+    }
+}
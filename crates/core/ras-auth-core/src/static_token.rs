@@ -0,0 +1,85 @@
+//! Fixed tokens for service accounts.
+
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+use crate::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
+
+/// An [`AuthProvider`] accepting a fixed set of tokens, each of which
+/// authenticates a service account with fixed permissions, such as the token
+/// an internal cron job calls with.
+///
+/// Tokens are kept as SHA-256 digests. Any other token is invalid; combine it
+/// with the provider for people in an [`AuthProviderChain`](crate::AuthProviderChain)
+/// to accept both on the same endpoints.
+#[derive(Clone, Default)]
+pub struct StaticTokenAuthProvider {
+    accounts: HashMap<[u8; 32], AuthenticatedUser>,
+}
+
+impl StaticTokenAuthProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `token` as the service account `service_id`, granted `permissions`
+    pub fn with_token<P: Into<String>>(
+        mut self,
+        token: impl AsRef<str>,
+        service_id: impl Into<String>,
+        permissions: impl IntoIterator<Item = P>,
+    ) -> Self {
+        self.accounts.insert(
+            token_digest(token.as_ref()),
+            AuthenticatedUser {
+                user_id: service_id.into(),
+                permissions: permissions.into_iter().map(Into::into).collect(),
+                metadata: None,
+                kind: PrincipalKind::ServiceAccount,
+            },
+        );
+        self
+    }
+}
+
+impl AuthProvider for StaticTokenAuthProvider {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        let account = self.accounts.get(&token_digest(&token)).cloned();
+        Box::pin(async move { account.ok_or(AuthError::InvalidToken) })
+    }
+}
+
+fn token_digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn configured_tokens_authenticate_service_accounts() {
+        let provider = StaticTokenAuthProvider::new()
+            .with_token("cron-secret", "nightly-report", ["reports:write"])
+            .with_token("sync-secret", "crm-sync", ["users:read", "users:write"]);
+
+        let account = provider.authenticate("cron-secret".into()).await.unwrap();
+        assert_eq!(account.user_id, "nightly-report");
+        assert_eq!(account.kind, PrincipalKind::ServiceAccount);
+        assert_eq!(account.permissions, ["reports:write".to_string()].into());
+
+        let account = provider.authenticate("sync-secret".into()).await.unwrap();
+        assert_eq!(account.user_id, "crm-sync");
+        assert!(
+            provider
+                .check_permissions(&account, &["users:write".to_string()])
+                .is_ok()
+        );
+
+        assert!(matches!(
+            provider.authenticate("nightly-report".into()).await,
+            Err(AuthError::InvalidToken)
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+//! Runtime support shared by the clients generated for Rust Agent Stack services.
+//!
+//! Generated JSON-RPC and REST clients use this crate, through `ras-jsonrpc-types` and
+//! `ras-rest-core`, to pace their requests and to back off when a server answers
+//! `429 Too Many Requests`.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Longest `Retry-After` a generated client waits for. A server asking for a
+/// longer wait gets its 429 returned to the caller instead.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Attempts per call, including the first, that clients without a retry policy
+/// spend on requests answered with 429.
+pub const DEFAULT_RATE_LIMITED_ATTEMPTS: u32 = 3;
+
+/// HTTP status of responses to rate-limited requests.
+pub const TOO_MANY_REQUESTS: u16 = 429;
+
+/// Client-side request budget, spacing requests evenly at a fixed rate.
+///
+/// Clones share the budget, so a limiter handed to several clients, or a client
+/// and its clones, applies one process-wide rate.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    state: Arc<Mutex<LimiterState>>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    interval: Duration,
+    /// Earliest time, on the [`now`] clock, the next request may be sent.
+    next_slot: Duration,
+}
+
+impl RateLimiter {
+    /// Allow `requests_per_second` requests per second; `0` is treated as 1.
+    pub fn new(requests_per_second: u32) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(LimiterState {
+                interval: Duration::from_secs(1) / requests_per_second.max(1),
+                next_slot: Duration::ZERO,
+            })),
+        }
+    }
+
+    /// Wait until the budget allows another request, and claim it.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut state = self.lock();
+            let now = now();
+            let slot = state.next_slot.max(now);
+            state.next_slot = slot + state.interval;
+            slot - now
+        };
+
+        if !wait.is_zero() {
+            retry_sleep(wait).await;
+        }
+    }
+
+    /// Hold every request back for at least `delay`, e.g. after a server
+    /// answered with `Retry-After`.
+    pub fn defer(&self, delay: Duration) {
+        let mut state = self.lock();
+        state.next_slot = state.next_slot.max(now() + delay);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Parse the `Retry-After` header of a response, given either as seconds or as
+/// an HTTP date. Dates in the past give [`Duration::ZERO`].
+pub fn retry_after(headers: &http::HeaderMap) -> Option<Duration> {
+    let value = headers
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// How long to wait before retrying a request answered with 429: the server's
+/// `Retry-After`, or `fallback` without one. `None` when the server asks for
+/// more than [`MAX_RETRY_AFTER`].
+pub fn rate_limited_delay(retry_after: Option<Duration>, fallback: Duration) -> Option<Duration> {
+    match retry_after {
+        Some(retry_after) if retry_after > MAX_RETRY_AFTER => None,
+        Some(retry_after) => Some(retry_after),
+        None => Some(fallback),
+    }
+}
+
+/// Wait before retry number `retry` of a rate-limited request whose response had
+/// no `Retry-After`: 100ms, doubling for every retry up to 2s.
+pub fn rate_limited_backoff(retry: u32) -> Duration {
+    let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+    Duration::from_millis(100)
+        .saturating_mul(factor)
+        .min(Duration::from_secs(2))
+}
+
+/// Log that `operation` was rate limited and is retried after `delay`.
+pub fn warn_rate_limited(operation: &str, delay: Duration, attempt: u32) {
+    tracing::warn!(
+        operation,
+        attempt,
+        delay_ms = delay.as_millis() as u64,
+        "Request was rate limited by the server, retrying"
+    );
+}
+
+/// Wait for `duration` on the client's runtime, before a retry or for the rate limiter.
+pub async fn retry_sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(duration).await;
+}
+
+/// Monotonic-enough clock for the rate limiter.
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> Duration {
+    static ORIGIN: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    ORIGIN.get_or_init(std::time::Instant::now).elapsed()
+}
+
+/// Monotonic-enough clock for the rate limiter; `Instant` is unavailable in browsers.
+#[cfg(target_arch = "wasm32")]
+fn now() -> Duration {
+    Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn headers(retry_after: &str) -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::RETRY_AFTER, retry_after.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_dates() {
+        assert_eq!(retry_after(&headers("7")), Some(Duration::from_secs(7)));
+        assert_eq!(
+            retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let wait = retry_after(&headers(&later)).unwrap();
+        assert!(wait > Duration::from_secs(25) && wait <= Duration::from_secs(30));
+
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&http::HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn long_retry_after_is_not_waited_for() {
+        let fallback = Duration::from_millis(100);
+        assert_eq!(rate_limited_delay(None, fallback), Some(fallback));
+        assert_eq!(
+            rate_limited_delay(Some(Duration::from_secs(2)), fallback),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            rate_limited_delay(Some(Duration::from_secs(3600)), fallback),
+            None
+        );
+    }
+
+    #[test]
+    fn backoff_without_retry_after_doubles() {
+        let delays: Vec<_> = (1..=6).map(rate_limited_backoff).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1600, 2000]
+                .map(Duration::from_millis)
+                .to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn clones_share_one_budget() {
+        let limiter = RateLimiter::new(50);
+        let clone = limiter.clone();
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+            clone.acquire().await;
+        }
+
+        // Six requests at 20ms intervals: the last one waits for 100ms
+        assert!(start.elapsed() >= Duration::from_millis(95));
+    }
+
+    #[tokio::test]
+    async fn deferred_limiters_hold_requests_back() {
+        let limiter = RateLimiter::new(1000);
+        limiter.defer(Duration::from_millis(50));
+
+        let start = Instant::now();
+        limiter.acquire().await;
+
+        assert!(start.elapsed() >= Duration::from_millis(45));
+    }
+}
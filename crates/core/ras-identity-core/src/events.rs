@@ -0,0 +1,350 @@
+//! Audit events from identity providers and session services.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{Mutex, Notify, mpsc};
+
+/// Events waiting for a sink before new ones are dropped
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+/// Where an authentication attempt came from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthSource {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+/// What happened
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum IdentityEventKind {
+    VerificationSucceeded,
+    /// `reason` is for the audit trail only, and may say more than the caller was told
+    VerificationFailed {
+        reason: String,
+    },
+    SessionStarted {
+        session_id: String,
+    },
+    SessionEnded {
+        session_id: String,
+    },
+    /// Every session of the subject was ended at once
+    SessionsRevoked {
+        count: usize,
+    },
+    /// A session token was presented and refused
+    SessionRejected {
+        reason: String,
+    },
+    /// A session was used from a client other than the one it began on, by
+    /// the parts of its binding named in `mismatched`, and refused if `enforced`
+    SessionBindingMismatch {
+        session_id: String,
+        mismatched: Vec<String>,
+        enforced: bool,
+    },
+    UserAdded,
+    UserRemoved,
+    PasswordChanged,
+    AccountLocked,
+}
+
+impl IdentityEventKind {
+    /// The `type` the event is serialized with, such as `verification_failed`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::VerificationSucceeded => "verification_succeeded",
+            Self::VerificationFailed { .. } => "verification_failed",
+            Self::SessionStarted { .. } => "session_started",
+            Self::SessionEnded { .. } => "session_ended",
+            Self::SessionsRevoked { .. } => "sessions_revoked",
+            Self::SessionRejected { .. } => "session_rejected",
+            Self::SessionBindingMismatch { .. } => "session_binding_mismatch",
+            Self::UserAdded => "user_added",
+            Self::UserRemoved => "user_removed",
+            Self::PasswordChanged => "password_changed",
+            Self::AccountLocked => "account_locked",
+        }
+    }
+
+    /// Whether the event records something being refused
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::VerificationFailed { .. }
+                | Self::SessionRejected { .. }
+                | Self::SessionBindingMismatch { enforced: true, .. }
+                | Self::AccountLocked
+        )
+    }
+}
+
+/// Something that happened to an identity, for the audit trail
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityEvent {
+    pub kind: IdentityEventKind,
+    pub provider_id: Option<String>,
+    /// The user the event is about, or claimed to be about for failed attempts
+    pub subject: Option<String>,
+    pub source: Option<AuthSource>,
+    pub at: DateTime<Utc>,
+}
+
+impl IdentityEvent {
+    pub fn new(kind: IdentityEventKind) -> Self {
+        Self {
+            kind,
+            provider_id: None,
+            subject: None,
+            source: None,
+            at: Utc::now(),
+        }
+    }
+
+    pub fn with_provider(mut self, provider_id: impl Into<String>) -> Self {
+        self.provider_id = Some(provider_id.into());
+        self
+    }
+
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = Some(subject.into());
+        self
+    }
+
+    pub fn with_source(mut self, source: Option<AuthSource>) -> Self {
+        self.source = source;
+        self
+    }
+}
+
+/// Receives identity events, such as to write an audit trail.
+#[async_trait]
+pub trait IdentityEventSink: Send + Sync {
+    async fn on_event(&self, event: IdentityEvent);
+}
+
+/// Logs events with `tracing` under the `ras_identity::audit` target.
+///
+/// Failures are logged as warnings, everything else as info.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingEventSink;
+
+#[async_trait]
+impl IdentityEventSink for TracingEventSink {
+    async fn on_event(&self, event: IdentityEvent) {
+        let source = event.source.unwrap_or_default();
+        let ip = source.ip.map(|ip| ip.to_string());
+        let (reason, session_id) = match &event.kind {
+            IdentityEventKind::VerificationFailed { reason }
+            | IdentityEventKind::SessionRejected { reason } => (Some(reason.as_str()), None),
+            IdentityEventKind::SessionStarted { session_id }
+            | IdentityEventKind::SessionEnded { session_id }
+            | IdentityEventKind::SessionBindingMismatch { session_id, .. } => {
+                (None, Some(session_id.as_str()))
+            }
+            _ => (None, None),
+        };
+        let count = match &event.kind {
+            IdentityEventKind::SessionsRevoked { count } => Some(*count),
+            _ => None,
+        };
+        let mismatched = match &event.kind {
+            IdentityEventKind::SessionBindingMismatch { mismatched, .. } => {
+                Some(mismatched.join(","))
+            }
+            _ => None,
+        };
+
+        if event.kind.is_failure() {
+            tracing::warn!(
+                target: "ras_identity::audit",
+                event = event.kind.name(),
+                provider_id = event.provider_id.as_deref(),
+                subject = event.subject.as_deref(),
+                reason,
+                session_id,
+                mismatched = mismatched.as_deref(),
+                ip = ip.as_deref(),
+                user_agent = source.user_agent.as_deref(),
+                "identity event"
+            );
+        } else {
+            tracing::info!(
+                target: "ras_identity::audit",
+                event = event.kind.name(),
+                provider_id = event.provider_id.as_deref(),
+                subject = event.subject.as_deref(),
+                session_id,
+                count,
+                mismatched = mismatched.as_deref(),
+                ip = ip.as_deref(),
+                user_agent = source.user_agent.as_deref(),
+                "identity event"
+            );
+        }
+    }
+}
+
+/// Keeps events in memory, for tests.
+#[derive(Debug, Default)]
+pub struct MemoryEventSink {
+    events: Mutex<Vec<IdentityEvent>>,
+    received: Notify,
+}
+
+impl MemoryEventSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The events received so far
+    pub async fn events(&self) -> Vec<IdentityEvent> {
+        self.events.lock().await.clone()
+    }
+
+    /// The events received once there are at least `count`, waiting for them if needed
+    pub async fn wait_for(&self, count: usize) -> Vec<IdentityEvent> {
+        loop {
+            let received = self.received.notified();
+            let events = self.events().await;
+            if events.len() >= count {
+                return events;
+            }
+            received.await;
+        }
+    }
+}
+
+#[async_trait]
+impl IdentityEventSink for MemoryEventSink {
+    async fn on_event(&self, event: IdentityEvent) {
+        self.events.lock().await.push(event);
+        self.received.notify_waiters();
+    }
+}
+
+/// Hands events to a sink from a background task, so emitting never waits.
+///
+/// Events that arrive while the queue is full are dropped and counted.
+#[derive(Clone)]
+pub struct EventDispatcher {
+    sender: mpsc::Sender<IdentityEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl EventDispatcher {
+    /// Deliver events to `sink`, queueing up to [`DEFAULT_EVENT_CAPACITY`].
+    ///
+    /// Must be called within a Tokio runtime, which the delivery task runs on.
+    pub fn new(sink: Arc<dyn IdentityEventSink>) -> Self {
+        Self::with_capacity(sink, DEFAULT_EVENT_CAPACITY)
+    }
+
+    /// Deliver events to `sink`, queueing up to `capacity` of them
+    pub fn with_capacity(sink: Arc<dyn IdentityEventSink>, capacity: usize) -> Self {
+        let (sender, mut receiver) = mpsc::channel(capacity.max(1));
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                sink.on_event(event).await;
+            }
+        });
+
+        Self {
+            sender,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn emit(&self, event: IdentityEvent) {
+        if self.sender.try_send(event).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// How many events were dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl std::fmt::Debug for EventDispatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventDispatcher")
+            .field("dropped", &self.dropped())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Holds every event until released
+    struct GatedSink {
+        gate: tokio::sync::Semaphore,
+        inner: MemoryEventSink,
+    }
+
+    #[async_trait]
+    impl IdentityEventSink for GatedSink {
+        async fn on_event(&self, event: IdentityEvent) {
+            self.gate.acquire().await.unwrap().forget();
+            self.inner.on_event(event).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatcher_delivers_events_in_order() {
+        let sink = Arc::new(MemoryEventSink::new());
+        let dispatcher = EventDispatcher::new(sink.clone());
+
+        dispatcher.emit(IdentityEvent::new(IdentityEventKind::UserAdded).with_subject("alice"));
+        dispatcher.emit(IdentityEvent::new(IdentityEventKind::UserRemoved).with_subject("alice"));
+
+        let events = sink.wait_for(2).await;
+        assert_eq!(events[0].kind, IdentityEventKind::UserAdded);
+        assert_eq!(events[1].kind, IdentityEventKind::UserRemoved);
+        assert_eq!(dispatcher.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn dispatcher_drops_and_counts_events_when_full() {
+        let sink = Arc::new(GatedSink {
+            gate: tokio::sync::Semaphore::new(0),
+            inner: MemoryEventSink::new(),
+        });
+        let dispatcher = EventDispatcher::with_capacity(sink.clone(), 2);
+
+        // One is taken by the blocked delivery task, two wait in the queue
+        for _ in 0..10 {
+            dispatcher.emit(IdentityEvent::new(IdentityEventKind::PasswordChanged));
+            tokio::task::yield_now().await;
+        }
+        assert!(dispatcher.dropped() >= 7);
+
+        let accepted = 10 - dispatcher.dropped() as usize;
+        sink.gate.add_permits(10);
+        assert_eq!(sink.inner.wait_for(accepted).await.len(), accepted);
+    }
+
+    #[test]
+    fn events_serialize_with_a_type_tag() {
+        let event = IdentityEvent::new(IdentityEventKind::VerificationFailed {
+            reason: "Invalid credentials".into(),
+        })
+        .with_provider("local")
+        .with_subject("alice");
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["kind"]["type"], "verification_failed");
+        assert_eq!(json["kind"]["reason"], "Invalid credentials");
+        assert_eq!(json["kind"]["type"], event.kind.name());
+        assert_eq!(json["provider_id"], "local");
+    }
+}
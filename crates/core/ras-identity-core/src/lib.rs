@@ -4,6 +4,17 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+mod events;
+mod roles;
+
+pub use events::{
+    AuthSource, DEFAULT_EVENT_CAPACITY, EventDispatcher, IdentityEvent, IdentityEventKind,
+    IdentityEventSink, MemoryEventSink, TracingEventSink,
+};
+pub use roles::{
+    MetadataRoles, RoleDefinition, RoleDefinitions, RolePermissions, RoleSource, StaticRoles,
+};
+
 #[derive(Debug, Error)]
 pub enum IdentityError {
     #[error("Invalid credentials")]
@@ -26,6 +37,60 @@ pub enum IdentityError {
 
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
+
+    #[error("Weak password: {}", join_violations(.0))]
+    WeakPassword(Vec<PolicyViolation>),
+
+    #[error("Version conflict: the user is now at version {current}")]
+    VersionConflict { current: u64 },
+
+    #[error("Email already in use")]
+    EmailInUse,
+
+    #[error("Invalid or expired verification token")]
+    InvalidVerificationToken,
+
+    #[error("A second factor is required")]
+    MfaRequired,
+
+    #[error("Invalid second factor")]
+    MfaInvalid,
+
+    #[error("Invalid role definitions: {0}")]
+    InvalidRoles(String),
+}
+
+/// A password policy rule that a new password broke.
+///
+/// Serialized with a `violation` tag so API layers can report each one against
+/// the password field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
+#[serde(tag = "violation", rename_all = "snake_case")]
+pub enum PolicyViolation {
+    #[error("must be at least {min} characters")]
+    TooShort { min: usize },
+
+    #[error("must be at most {max} characters")]
+    TooLong { max: usize },
+
+    #[error(
+        "must mix at least {required} of lowercase letters, uppercase letters, digits and symbols"
+    )]
+    TooFewCharacterClasses { required: usize },
+
+    #[error("is too common")]
+    Common,
+
+    #[error("must not contain the username")]
+    ContainsUsername,
+}
+
+fn join_violations(violations: &[PolicyViolation]) -> String {
+    violations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 pub type IdentityResult<T> = Result<T, IdentityError>;
@@ -39,6 +104,24 @@ pub struct VerifiedIdentity {
     pub metadata: Option<serde_json::Value>,
 }
 
+impl VerifiedIdentity {
+    /// How the subject authenticated (RFC 8176 values such as `pwd` and `otp`),
+    /// as listed by the provider under the `amr` metadata field.
+    pub fn amr(&self) -> Vec<String> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get("amr"))
+            .and_then(serde_json::Value::as_array)
+            .map(|methods| {
+                methods
+                    .iter()
+                    .filter_map(|method| method.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
 #[async_trait]
 pub trait IdentityProvider: Send + Sync {
     fn provider_id(&self) -> &str;
@@ -147,4 +230,15 @@ mod tests {
         assert_eq!(parsed.subject, "alice");
         assert_eq!(parsed.provider_id, "test");
     }
+
+    #[test]
+    fn amr_is_read_from_metadata() {
+        assert!(vi().amr().is_empty());
+
+        let v = VerifiedIdentity {
+            metadata: Some(serde_json::json!({ "amr": ["pwd", "otp", 3] })),
+            ..vi()
+        };
+        assert_eq!(v.amr(), ["pwd", "otp"]);
+    }
 }
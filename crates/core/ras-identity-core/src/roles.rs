@@ -0,0 +1,397 @@
+//! Permissions granted through roles rather than to users one by one.
+
+use crate::{IdentityError, IdentityResult, UserPermissions, VerifiedIdentity};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// The permissions a role grants itself, and the roles whose permissions it
+/// also grants, such as `editor` inheriting `viewer`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoleDefinition {
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    #[serde(default)]
+    pub inherits: Vec<String>,
+}
+
+/// Roles by name, as loaded from configuration:
+///
+/// ```toml
+/// [viewer]
+/// permissions = ["documents:read"]
+///
+/// [editor]
+/// permissions = ["documents:write"]
+/// inherits = ["viewer"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RoleDefinitions {
+    roles: BTreeMap<String, RoleDefinition>,
+}
+
+impl RoleDefinitions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Roles from a JSON object of role names to definitions
+    pub fn from_json(json: &str) -> IdentityResult<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Roles from a TOML table of role names to definitions
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> IdentityResult<Self> {
+        toml::from_str(toml).map_err(|e| IdentityError::InvalidRoles(e.to_string()))
+    }
+
+    /// Define `name`, replacing any definition it had
+    pub fn define(mut self, name: impl Into<String>, definition: RoleDefinition) -> Self {
+        self.roles.insert(name.into(), definition);
+        self
+    }
+}
+
+/// Which roles an identity has
+#[async_trait]
+pub trait RoleSource: Send + Sync {
+    async fn roles(&self, identity: &VerifiedIdentity) -> IdentityResult<Vec<String>>;
+}
+
+/// Roles assigned to subjects in configuration
+#[derive(Debug, Clone, Default)]
+pub struct StaticRoles {
+    assignments: HashMap<String, Vec<String>>,
+}
+
+impl StaticRoles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give `subject` the `roles`, in addition to any it has
+    pub fn assign<I, R>(mut self, subject: impl Into<String>, roles: I) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: Into<String>,
+    {
+        self.assignments
+            .entry(subject.into())
+            .or_default()
+            .extend(roles.into_iter().map(Into::into));
+        self
+    }
+}
+
+#[async_trait]
+impl RoleSource for StaticRoles {
+    async fn roles(&self, identity: &VerifiedIdentity) -> IdentityResult<Vec<String>> {
+        Ok(self
+            .assignments
+            .get(&identity.subject)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Roles listed in a metadata field of identities, such as the `roles` claim
+/// of an OIDC provider or the `groups` of a directory.
+///
+/// Values are role names, unless mapped to roles with [`map`](Self::map), in
+/// which case values that aren't mapped grant nothing. Mapped values are
+/// compared ignoring ASCII case, as directory group names are.
+#[derive(Debug, Clone)]
+pub struct MetadataRoles {
+    field: String,
+    mapping: Vec<(String, String)>,
+}
+
+impl MetadataRoles {
+    pub fn new(field: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            mapping: Vec::new(),
+        }
+    }
+
+    /// Give identities listing `value`, such as a group DN, the `role`
+    pub fn map(mut self, value: impl Into<String>, role: impl Into<String>) -> Self {
+        self.mapping.push((value.into(), role.into()));
+        self
+    }
+}
+
+#[async_trait]
+impl RoleSource for MetadataRoles {
+    async fn roles(&self, identity: &VerifiedIdentity) -> IdentityResult<Vec<String>> {
+        let values = identity
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(&self.field))
+            .and_then(serde_json::Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(serde_json::Value::as_str);
+        if self.mapping.is_empty() {
+            return Ok(values.map(str::to_string).collect());
+        }
+        let mut roles = Vec::new();
+        for value in values {
+            roles.extend(
+                self.mapping
+                    .iter()
+                    .filter(|(mapped, _)| mapped.eq_ignore_ascii_case(value))
+                    .map(|(_, role)| role.clone()),
+            );
+        }
+        Ok(roles)
+    }
+}
+
+/// Grants identities the permissions of the roles `source` gives them,
+/// including those of the roles they inherit.
+///
+/// Each permission is granted once, however many roles grant it. Roles that
+/// aren't defined grant nothing.
+pub struct RolePermissions {
+    source: Box<dyn RoleSource>,
+    /// The permissions of each role, with those it inherits
+    expanded: HashMap<String, Vec<String>>,
+}
+
+impl RolePermissions {
+    /// Fails with `IdentityError::InvalidRoles` if a role inherits one that
+    /// isn't defined, or inherits itself through others.
+    pub fn new(
+        definitions: RoleDefinitions,
+        source: impl RoleSource + 'static,
+    ) -> IdentityResult<Self> {
+        let mut expanded = HashMap::new();
+        for name in definitions.roles.keys() {
+            expand(&definitions.roles, name, &mut Vec::new(), &mut expanded)?;
+        }
+        Ok(Self {
+            source: Box::new(source),
+            expanded,
+        })
+    }
+
+    /// The permissions of `roles` and the roles they inherit
+    pub fn permissions_of<R: AsRef<str>>(&self, roles: &[R]) -> Vec<String> {
+        let mut permissions = Vec::new();
+        for role in roles {
+            for permission in self.expanded.get(role.as_ref()).into_iter().flatten() {
+                if !permissions.contains(permission) {
+                    permissions.push(permission.clone());
+                }
+            }
+        }
+        permissions
+    }
+}
+
+/// Store the permissions of `name` and the roles it inherits in `expanded`,
+/// `path` being the roles inheriting it, to find cycles
+fn expand(
+    roles: &BTreeMap<String, RoleDefinition>,
+    name: &str,
+    path: &mut Vec<String>,
+    expanded: &mut HashMap<String, Vec<String>>,
+) -> IdentityResult<Vec<String>> {
+    if let Some(permissions) = expanded.get(name) {
+        return Ok(permissions.clone());
+    }
+    if let Some(start) = path.iter().position(|role| role == name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(name.to_string());
+        return Err(IdentityError::InvalidRoles(format!(
+            "roles inherit themselves: {}",
+            cycle.join(" -> ")
+        )));
+    }
+    let Some(definition) = roles.get(name) else {
+        return Err(IdentityError::InvalidRoles(format!(
+            "{} inherits the undefined role {name}",
+            path.last().map_or("a role", String::as_str)
+        )));
+    };
+
+    path.push(name.to_string());
+    let mut permissions = definition.permissions.clone();
+    for parent in &definition.inherits {
+        permissions.extend(expand(roles, parent, path, expanded)?);
+    }
+    path.pop();
+
+    let mut unique: Vec<String> = Vec::new();
+    for permission in permissions {
+        if !unique.contains(&permission) {
+            unique.push(permission);
+        }
+    }
+    expanded.insert(name.to_string(), unique.clone());
+    Ok(unique)
+}
+
+#[async_trait]
+impl UserPermissions for RolePermissions {
+    async fn get_permissions(&self, identity: &VerifiedIdentity) -> IdentityResult<Vec<String>> {
+        let roles = self.source.roles(identity).await?;
+        Ok(self.permissions_of(&roles))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(permissions: &[&str], inherits: &[&str]) -> RoleDefinition {
+        RoleDefinition {
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            inherits: inherits.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    fn identity(subject: &str, metadata: Option<serde_json::Value>) -> VerifiedIdentity {
+        VerifiedIdentity {
+            provider_id: "test".into(),
+            subject: subject.into(),
+            email: None,
+            display_name: None,
+            metadata,
+        }
+    }
+
+    fn hierarchy() -> RoleDefinitions {
+        RoleDefinitions::new()
+            .define("viewer", role(&["read"], &[]))
+            .define("editor", role(&["write", "read"], &["viewer"]))
+            .define("auditor", role(&["audit"], &["viewer"]))
+            .define("admin", role(&["admin"], &["editor", "auditor"]))
+    }
+
+    #[tokio::test]
+    async fn roles_grant_inherited_permissions_once() {
+        let roles = RolePermissions::new(
+            hierarchy(),
+            StaticRoles::new()
+                .assign("alice", ["admin"])
+                .assign("bob", ["viewer", "editor", "undefined"]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            roles
+                .get_permissions(&identity("alice", None))
+                .await
+                .unwrap(),
+            ["admin", "write", "read", "audit"]
+        );
+        assert_eq!(
+            roles.get_permissions(&identity("bob", None)).await.unwrap(),
+            ["read", "write"]
+        );
+        assert!(
+            roles
+                .get_permissions(&identity("carol", None))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn cycles_and_undefined_parents_are_refused() {
+        let cyclic = hierarchy().define("viewer", role(&["read"], &["admin"]));
+        let Err(IdentityError::InvalidRoles(message)) =
+            RolePermissions::new(cyclic, StaticRoles::new())
+        else {
+            panic!("a cycle was accepted");
+        };
+        assert!(
+            message.contains("admin -> editor -> viewer -> admin"),
+            "{message}"
+        );
+
+        let own = RoleDefinitions::new().define("loop", role(&[], &["loop"]));
+        assert!(RolePermissions::new(own, StaticRoles::new()).is_err());
+
+        let orphan = RoleDefinitions::new().define("editor", role(&[], &["viewer"]));
+        let Err(IdentityError::InvalidRoles(message)) =
+            RolePermissions::new(orphan, StaticRoles::new())
+        else {
+            panic!("an undefined parent was accepted");
+        };
+        assert_eq!(message, "editor inherits the undefined role viewer");
+    }
+
+    #[tokio::test]
+    async fn roles_are_read_from_metadata() {
+        let claims = RolePermissions::new(hierarchy(), MetadataRoles::new("roles")).unwrap();
+        let identity_with = |metadata| identity("alice", Some(metadata));
+        assert_eq!(
+            claims
+                .get_permissions(&identity_with(serde_json::json!({ "roles": ["auditor"] })))
+                .await
+                .unwrap(),
+            ["audit", "read"]
+        );
+
+        let groups = RolePermissions::new(
+            hierarchy(),
+            MetadataRoles::new("groups").map("cn=Editors,ou=groups,dc=example,dc=com", "editor"),
+        )
+        .unwrap();
+        let member = identity_with(serde_json::json!({
+            "groups": ["cn=editors,ou=groups,dc=example,dc=com", "admin"]
+        }));
+        assert_eq!(
+            groups.get_permissions(&member).await.unwrap(),
+            ["write", "read"]
+        );
+    }
+
+    #[test]
+    fn definitions_load_from_json() {
+        let definitions = RoleDefinitions::from_json(
+            r#"{
+                "viewer": { "permissions": ["read"] },
+                "editor": { "permissions": ["write"], "inherits": ["viewer"] }
+            }"#,
+        )
+        .unwrap();
+        let roles = RolePermissions::new(definitions, StaticRoles::new()).unwrap();
+        assert_eq!(roles.permissions_of(&["editor"]), ["write", "read"]);
+
+        assert!(RoleDefinitions::from_json(r#"{ "viewer": { "permission": [] } }"#).is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn definitions_load_from_toml() {
+        let definitions = RoleDefinitions::from_toml(
+            r#"
+            [viewer]
+            permissions = ["read"]
+
+            [editor]
+            permissions = ["write"]
+            inherits = ["viewer"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            definitions,
+            RoleDefinitions::new()
+                .define("viewer", role(&["read"], &[]))
+                .define("editor", role(&["write"], &["viewer"]))
+        );
+        assert!(matches!(
+            RoleDefinitions::from_toml("viewer = 1"),
+            Err(IdentityError::InvalidRoles(_))
+        ));
+    }
+}
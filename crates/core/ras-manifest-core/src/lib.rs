@@ -0,0 +1,357 @@
+//! Service manifests: the methods or endpoints a service exposes, with their auth requirements.
+//!
+//! `jsonrpc_service!` and `rest_service!` generate a `{servicename}_service_manifest()`
+//! function returning a [`ServiceManifest`], and generated builders can serve it with
+//! [`manifest_response`].
+
+use std::fmt;
+
+use ras_auth_core::AuthProvider;
+use serde::{Deserialize, Serialize};
+
+/// Path, relative to the service's base path, that generated builders serve the manifest on.
+pub const MANIFEST_PATH: &str = "/_manifest";
+
+/// Description of every method or endpoint a service exposes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceManifest {
+    /// Name of the service as declared in the macro.
+    pub service_name: String,
+
+    /// Protocol the service speaks.
+    pub protocol: ServiceProtocol,
+
+    /// The service's methods or endpoints, in declaration order.
+    pub operations: Vec<OperationManifest>,
+}
+
+impl ServiceManifest {
+    /// Look up an operation by name.
+    pub fn operation(&self, name: &str) -> Option<&OperationManifest> {
+        self.operations
+            .iter()
+            .find(|operation| operation.name == name)
+    }
+
+    /// Operations callable without a token.
+    pub fn unauthorized_operations(&self) -> impl Iterator<Item = &OperationManifest> {
+        self.operations
+            .iter()
+            .filter(|operation| operation.auth == AuthMode::Unauthorized)
+    }
+}
+
+impl fmt::Display for ServiceManifest {
+    /// One line per operation, suitable for a startup log.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.service_name, self.protocol)?;
+        for operation in &self.operations {
+            write!(f, "\n  {operation}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Protocol of a [`ServiceManifest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceProtocol {
+    JsonRpc,
+    Rest,
+}
+
+impl fmt::Display for ServiceProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceProtocol::JsonRpc => write!(f, "jsonrpc"),
+            ServiceProtocol::Rest => write!(f, "rest"),
+        }
+    }
+}
+
+/// A JSON-RPC method or REST endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationManifest {
+    /// Method name on the wire for JSON-RPC, handler name for REST.
+    pub name: String,
+
+    /// HTTP method of a REST endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_method: Option<String>,
+
+    /// Full path of a REST endpoint, including the service's base path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    /// Whether a token is required.
+    pub auth: AuthMode,
+
+    /// Permission groups, any one of which grants access; all permissions in a group
+    /// are required. Empty for unauthorized operations.
+    pub permission_groups: Vec<Vec<String>>,
+
+    /// Permission expression the caller's permissions must satisfy, such as
+    /// `admin | (tasks:write & !suspended)`, for operations requiring one
+    /// rather than permission groups.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_expression: Option<String>,
+
+    /// Rust type of the request, if the operation takes one.
+    pub request_type: Option<String>,
+
+    /// Rust type of the response.
+    pub response_type: String,
+
+    /// Doc comment of the operation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docs: Option<String>,
+
+    /// API version the operation belongs to, for versioned operations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Whether the operation is marked `#[deprecated]`.
+    #[serde(default)]
+    pub deprecated: bool,
+}
+
+impl fmt::Display for OperationManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.http_method, &self.path) {
+            (Some(method), Some(path)) => write!(f, "{method} {path}")?,
+            _ => write!(f, "{}", self.name)?,
+        }
+        if let Some(version) = &self.version {
+            write!(f, " [{version}]")?;
+        }
+        match self.auth {
+            AuthMode::Unauthorized => write!(f, ": unauthorized")?,
+            AuthMode::Authenticated => write!(f, ": any valid token")?,
+            AuthMode::Permissions => match &self.permission_expression {
+                Some(expression) => write!(f, ": {expression}")?,
+                None => {
+                    let groups: Vec<String> = self
+                        .permission_groups
+                        .iter()
+                        .map(|group| format!("[{}]", group.join(", ")))
+                        .collect();
+                    write!(f, ": {}", groups.join(" | "))?;
+                }
+            },
+        }
+        if self.deprecated {
+            write!(f, " (deprecated)")?;
+        }
+        Ok(())
+    }
+}
+
+/// How an operation authenticates its callers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// No token is required.
+    Unauthorized,
+
+    /// Any valid token is accepted.
+    Authenticated,
+
+    /// The caller needs the permissions of one of the operation's permission groups.
+    Permissions,
+}
+
+impl AuthMode {
+    /// Auth mode of an operation requiring a token with one of `permission_groups`.
+    pub fn for_permission_groups(permission_groups: &[Vec<String>]) -> Self {
+        if permission_groups.is_empty() || permission_groups.iter().any(Vec::is_empty) {
+            AuthMode::Authenticated
+        } else {
+            AuthMode::Permissions
+        }
+    }
+}
+
+/// Answer a manifest request: the manifest as JSON when the bearer token in `headers`
+/// carries `permission`, otherwise a 401 or 403 JSON error.
+pub async fn manifest_response(
+    manifest: &ServiceManifest,
+    headers: &http::HeaderMap,
+    provider: Option<&dyn AuthProvider>,
+    permission: &str,
+) -> http::Response<String> {
+    let json_response = |status: http::StatusCode, body: serde_json::Value| {
+        let mut response = http::Response::new(body.to_string());
+        *response.status_mut() = status;
+        response.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("application/json"),
+        );
+        response
+    };
+    let denied = |status: http::StatusCode, message: &str| {
+        json_response(status, serde_json::json!({ "error": message }))
+    };
+
+    let Some(provider) = provider else {
+        return denied(
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+            "No auth provider configured",
+        );
+    };
+    let token = headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(ras_auth_core::authorization_credential);
+    let Some(token) = token else {
+        return denied(http::StatusCode::UNAUTHORIZED, "Authentication required");
+    };
+    let user = match provider
+        .authenticate_request(token.to_string(), headers)
+        .await
+    {
+        Ok(user) => user,
+        Err(_) => return denied(http::StatusCode::UNAUTHORIZED, "Authentication failed"),
+    };
+    if provider
+        .check_permissions(&user, &[permission.to_string()])
+        .is_err()
+    {
+        return denied(http::StatusCode::FORBIDDEN, "Insufficient permissions");
+    }
+
+    match serde_json::to_value(manifest) {
+        Ok(body) => json_response(http::StatusCode::OK, body),
+        Err(_) => denied(
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to serialize the service manifest",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_auth_core::{AuthError, AuthFuture, AuthenticatedUser, PrincipalKind};
+
+    struct TokenProvider;
+
+    impl AuthProvider for TokenProvider {
+        fn authenticate(&self, token: String) -> AuthFuture<'_> {
+            Box::pin(async move {
+                match token.as_str() {
+                    "admin" => Ok(AuthenticatedUser {
+                        user_id: "admin".to_string(),
+                        permissions: ["ops".to_string()].into(),
+                        metadata: None,
+                        kind: PrincipalKind::User,
+                    }),
+                    "user" => Ok(AuthenticatedUser {
+                        user_id: "user".to_string(),
+                        permissions: Default::default(),
+                        metadata: None,
+                        kind: PrincipalKind::User,
+                    }),
+                    _ => Err(AuthError::InvalidToken),
+                }
+            })
+        }
+    }
+
+    fn operation(name: &str, permission_groups: Vec<Vec<String>>) -> OperationManifest {
+        OperationManifest {
+            name: name.to_string(),
+            http_method: None,
+            path: None,
+            auth: if permission_groups.is_empty() {
+                AuthMode::Unauthorized
+            } else {
+                AuthMode::for_permission_groups(&permission_groups)
+            },
+            permission_groups,
+            permission_expression: None,
+            request_type: Some("String".to_string()),
+            response_type: "u32".to_string(),
+            docs: None,
+            version: None,
+            deprecated: false,
+        }
+    }
+
+    fn manifest() -> ServiceManifest {
+        ServiceManifest {
+            service_name: "Tasks".to_string(),
+            protocol: ServiceProtocol::JsonRpc,
+            operations: vec![
+                operation("health", vec![]),
+                operation("whoami", vec![vec![]]),
+                operation(
+                    "delete",
+                    vec![vec!["admin".to_string()], vec!["owner".to_string()]],
+                ),
+                OperationManifest {
+                    auth: AuthMode::Permissions,
+                    permission_expression: Some("admin | owner & !suspended".to_string()),
+                    ..operation("archive", vec![])
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn auth_modes_follow_permission_groups() {
+        let manifest = manifest();
+
+        let unauthorized: Vec<&str> = manifest
+            .unauthorized_operations()
+            .map(|operation| operation.name.as_str())
+            .collect();
+        assert_eq!(unauthorized, ["health"]);
+        assert_eq!(
+            manifest.operation("whoami").unwrap().auth,
+            AuthMode::Authenticated
+        );
+        assert_eq!(
+            manifest.operation("delete").unwrap().auth,
+            AuthMode::Permissions
+        );
+    }
+
+    #[test]
+    fn display_lists_each_operation() {
+        assert_eq!(
+            manifest().to_string(),
+            "Tasks (jsonrpc)\n  health: unauthorized\n  whoami: any valid token\n  delete: [admin] | [owner]\n  archive: admin | owner & !suspended"
+        );
+    }
+
+    #[tokio::test]
+    async fn manifest_requires_the_permission() {
+        let manifest = manifest();
+        let provider: &dyn AuthProvider = &TokenProvider;
+        let with_token = |token: &str| {
+            let mut headers = http::HeaderMap::new();
+            headers.insert(
+                http::header::AUTHORIZATION,
+                format!("Bearer {token}").parse().unwrap(),
+            );
+            headers
+        };
+
+        let response =
+            manifest_response(&manifest, &with_token("admin"), Some(provider), "ops").await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let body: ServiceManifest = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(body, manifest);
+
+        let response =
+            manifest_response(&manifest, &with_token("user"), Some(provider), "ops").await;
+        assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+        let response =
+            manifest_response(&manifest, &http::HeaderMap::new(), Some(provider), "ops").await;
+        assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+        let response = manifest_response(&manifest, &with_token("admin"), None, "ops").await;
+        assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
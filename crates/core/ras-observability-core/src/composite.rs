@@ -0,0 +1,386 @@
+//! Trackers fanning out to several others, such as OTel metrics and a billing
+//! pipeline.
+//!
+//! Each inner tracker runs in its own task with a timeout, so one that panics
+//! or hangs neither fails the request nor delays the others beyond the timeout.
+//! Spawning needs a Tokio runtime.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use http::HeaderMap;
+use ras_auth_core::AuthenticatedUser;
+
+use crate::{
+    MethodDurationTracker, MethodDurationTrackerFn, RequestContext, RequestOutcome, UsageTracker,
+    UsageTrackerFn,
+};
+
+/// How long an inner tracker may take before it is cancelled, unless set with
+/// `with_timeout`
+pub const DEFAULT_TRACKER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often an inner tracker of a composite failed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrackerFailures {
+    /// Calls that panicked
+    pub panics: u64,
+    /// Calls cancelled for outliving the timeout
+    pub timeouts: u64,
+}
+
+#[derive(Default)]
+struct FailureCounters {
+    panics: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl FailureCounters {
+    fn snapshot(&self) -> TrackerFailures {
+        TrackerFailures {
+            panics: self.panics.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Inner<T: ?Sized> {
+    tracker: Arc<T>,
+    failures: FailureCounters,
+}
+
+/// Call every tracker in its own task, in order, and wait for them all until
+/// `timeout` has passed, cancelling those still running
+async fn fan_out<T, F>(inners: &[Inner<T>], timeout: Duration, call: impl Fn(Arc<T>) -> F)
+where
+    T: ?Sized,
+    F: Future<Output = ()> + Send + 'static,
+{
+    let deadline = tokio::time::Instant::now() + timeout;
+    let tasks: Vec<_> = inners
+        .iter()
+        .map(|inner| tokio::spawn(call(inner.tracker.clone())))
+        .collect();
+
+    for (inner, mut task) in inners.iter().zip(tasks) {
+        match tokio::time::timeout_at(deadline, &mut task).await {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => {
+                if error.is_panic() {
+                    inner.failures.panics.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Err(_) => {
+                task.abort();
+                inner.failures.timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// A [`UsageTracker`] telling each of its trackers about every request
+pub struct CompositeUsageTracker {
+    trackers: Vec<Inner<dyn UsageTracker>>,
+    timeout: Duration,
+}
+
+impl CompositeUsageTracker {
+    pub fn new() -> Self {
+        Self {
+            trackers: Vec::new(),
+            timeout: DEFAULT_TRACKER_TIMEOUT,
+        }
+    }
+
+    /// Tell `tracker` about requests too, after the trackers added before it
+    pub fn with_tracker(mut self, tracker: Arc<dyn UsageTracker>) -> Self {
+        self.trackers.push(Inner {
+            tracker,
+            failures: FailureCounters::default(),
+        });
+        self
+    }
+
+    /// Cancel a tracker still running `timeout` after a request was reported
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How often each tracker failed, in the order they were added
+    pub fn failures(&self) -> Vec<TrackerFailures> {
+        self.trackers
+            .iter()
+            .map(|inner| inner.failures.snapshot())
+            .collect()
+    }
+}
+
+impl Default for CompositeUsageTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl UsageTracker for CompositeUsageTracker {
+    async fn track_request(
+        &self,
+        headers: &HeaderMap,
+        user: Option<&AuthenticatedUser>,
+        context: &RequestContext,
+    ) {
+        fan_out(&self.trackers, self.timeout, |tracker| {
+            let headers = headers.clone();
+            let user = user.cloned();
+            let context = context.clone();
+            async move {
+                tracker
+                    .track_request(&headers, user.as_ref(), &context)
+                    .await
+            }
+        })
+        .await
+    }
+}
+
+/// A [`MethodDurationTracker`] telling each of its trackers how long every
+/// request took
+pub struct CompositeMethodDurationTracker {
+    trackers: Vec<Inner<dyn MethodDurationTracker>>,
+    timeout: Duration,
+}
+
+impl CompositeMethodDurationTracker {
+    pub fn new() -> Self {
+        Self {
+            trackers: Vec::new(),
+            timeout: DEFAULT_TRACKER_TIMEOUT,
+        }
+    }
+
+    /// Tell `tracker` about durations too, after the trackers added before it
+    pub fn with_tracker(mut self, tracker: Arc<dyn MethodDurationTracker>) -> Self {
+        self.trackers.push(Inner {
+            tracker,
+            failures: FailureCounters::default(),
+        });
+        self
+    }
+
+    /// Cancel a tracker still running `timeout` after a duration was reported
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How often each tracker failed, in the order they were added
+    pub fn failures(&self) -> Vec<TrackerFailures> {
+        self.trackers
+            .iter()
+            .map(|inner| inner.failures.snapshot())
+            .collect()
+    }
+}
+
+impl Default for CompositeMethodDurationTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MethodDurationTracker for CompositeMethodDurationTracker {
+    async fn track_duration(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+    ) {
+        fan_out(&self.trackers, self.timeout, |tracker| {
+            let user = user.cloned();
+            let context = context.clone();
+            async move {
+                tracker
+                    .track_duration(&context, user.as_ref(), duration)
+                    .await
+            }
+        })
+        .await
+    }
+
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+        outcome: RequestOutcome,
+    ) {
+        fan_out(&self.trackers, self.timeout, |tracker| {
+            let user = user.cloned();
+            let context = context.clone();
+            async move {
+                tracker
+                    .track_completion(&context, user.as_ref(), duration, outcome)
+                    .await
+            }
+        })
+        .await
+    }
+}
+
+/// A usage tracker function of [`ObservabilityBuilder`](crate::ObservabilityBuilder)
+struct FnUsageTracker(UsageTrackerFn);
+
+#[async_trait]
+impl UsageTracker for FnUsageTracker {
+    async fn track_request(
+        &self,
+        headers: &HeaderMap,
+        user: Option<&AuthenticatedUser>,
+        context: &RequestContext,
+    ) {
+        (self.0)(headers.clone(), user.cloned(), context.clone()).await
+    }
+}
+
+/// A method duration tracker function of
+/// [`ObservabilityBuilder`](crate::ObservabilityBuilder)
+struct FnMethodDurationTracker(MethodDurationTrackerFn);
+
+#[async_trait]
+impl MethodDurationTracker for FnMethodDurationTracker {
+    async fn track_duration(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+    ) {
+        (self.0)(context.clone(), user.cloned(), duration).await
+    }
+
+    // The function is told the outcome through the `outcome` metadata
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+        outcome: RequestOutcome,
+    ) {
+        (self.0)(
+            context.clone().with_outcome(outcome),
+            user.cloned(),
+            duration,
+        )
+        .await
+    }
+}
+
+/// `tracker` as a [`UsageTracker`]
+pub(crate) fn usage_tracker_from_fn(tracker: UsageTrackerFn) -> Arc<dyn UsageTracker> {
+    Arc::new(FnUsageTracker(tracker))
+}
+
+/// `tracker` as a [`MethodDurationTracker`]
+pub(crate) fn method_duration_tracker_from_fn(
+    tracker: MethodDurationTrackerFn,
+) -> Arc<dyn MethodDurationTracker> {
+    Arc::new(FnMethodDurationTracker(tracker))
+}
+
+/// `tracker` as a usage tracker function
+pub(crate) fn usage_tracker_fn(tracker: Arc<dyn UsageTracker>) -> UsageTrackerFn {
+    Arc::new(move |headers, user, context| {
+        let tracker = tracker.clone();
+        Box::pin(async move {
+            tracker
+                .track_request(&headers, user.as_ref(), &context)
+                .await
+        })
+    })
+}
+
+/// `tracker` as a method duration tracker function, told how requests ended
+/// when the function is, through the `outcome` metadata
+pub(crate) fn method_duration_tracker_fn(
+    tracker: Arc<dyn MethodDurationTracker>,
+) -> MethodDurationTrackerFn {
+    Arc::new(move |mut context, user, duration| {
+        let tracker = tracker.clone();
+        Box::pin(async move {
+            let outcome = context.metadata.remove("outcome");
+            match outcome.as_deref().and_then(RequestOutcome::from_label) {
+                Some(outcome) => {
+                    tracker
+                        .track_completion(&context, user.as_ref(), duration, outcome)
+                        .await
+                }
+                None => {
+                    tracker
+                        .track_duration(&context, user.as_ref(), duration)
+                        .await
+                }
+            }
+        })
+    })
+}
+
+/// One function calling all of `trackers` through a [`CompositeUsageTracker`],
+/// or the only one
+pub(crate) fn compose_usage_trackers(
+    mut trackers: Vec<UsageTrackerFn>,
+    timeout: Duration,
+) -> Option<UsageTrackerFn> {
+    if trackers.len() <= 1 {
+        return trackers.pop();
+    }
+    let composite = Arc::new(
+        trackers
+            .into_iter()
+            .fold(CompositeUsageTracker::new(), |composite, tracker| {
+                composite.with_tracker(Arc::new(FnUsageTracker(tracker)))
+            })
+            .with_timeout(timeout),
+    );
+    Some(Arc::new(move |headers, user, context| {
+        let composite = composite.clone();
+        Box::pin(async move {
+            composite
+                .track_request(&headers, user.as_ref(), &context)
+                .await
+        })
+    }))
+}
+
+/// One function calling all of `trackers` through a
+/// [`CompositeMethodDurationTracker`], or the only one
+pub(crate) fn compose_method_duration_trackers(
+    mut trackers: Vec<MethodDurationTrackerFn>,
+    timeout: Duration,
+) -> Option<MethodDurationTrackerFn> {
+    if trackers.len() <= 1 {
+        return trackers.pop();
+    }
+    let composite = Arc::new(
+        trackers
+            .into_iter()
+            .fold(
+                CompositeMethodDurationTracker::new(),
+                |composite, tracker| {
+                    composite.with_tracker(Arc::new(FnMethodDurationTracker(tracker)))
+                },
+            )
+            .with_timeout(timeout),
+    );
+    Some(Arc::new(move |context, user, duration| {
+        let composite = composite.clone();
+        Box::pin(async move {
+            composite
+                .track_duration(&context, user.as_ref(), duration)
+                .await
+        })
+    }))
+}
@@ -0,0 +1,262 @@
+//! Calling trackers so that one that panics or hangs can't fail or stall the
+//! request, and keeping track of how they fare.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{
+    DEFAULT_TRACKER_TIMEOUT, MethodDurationTrackerFn, ServiceMetrics, TrackerFailures,
+    UsageTrackerFn,
+};
+
+/// How often a failing tracker is warned about at most; failures in between
+/// are counted in the next warning
+const WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Calls trackers, catching their panics and cancelling those outliving a
+/// timeout, and records how each tracker fared
+///
+/// Generated services call their trackers through one, as does an
+/// [`Observability`](crate::Observability) built by
+/// [`ObservabilityBuilder`](crate::ObservabilityBuilder). Failures are counted
+/// through [`ServiceMetrics::record_tracker_error`] and logged as warnings, at
+/// most once a minute per tracker. Clones share what they record, so keep one
+/// to report [`health`](Self::health) from a readiness check.
+#[derive(Clone)]
+pub struct TrackerGuard {
+    timeout: Duration,
+    metrics: Option<Arc<dyn ServiceMetrics>>,
+    records: Arc<Mutex<BTreeMap<String, TrackerRecord>>>,
+}
+
+#[derive(Default)]
+struct TrackerRecord {
+    calls: u64,
+    failures: TrackerFailures,
+    consecutive_failures: u64,
+    last_failure: Option<SystemTime>,
+    last_warning: Option<Instant>,
+    unwarned: u64,
+}
+
+/// How a tracker call failed
+#[derive(Clone, Copy)]
+enum Failure {
+    Panic,
+    Timeout,
+}
+
+impl Failure {
+    fn as_str(self) -> &'static str {
+        match self {
+            Failure::Panic => "panic",
+            Failure::Timeout => "timeout",
+        }
+    }
+}
+
+impl TrackerGuard {
+    /// Cancel trackers after [`DEFAULT_TRACKER_TIMEOUT`]
+    pub fn new() -> Self {
+        Self {
+            timeout: DEFAULT_TRACKER_TIMEOUT,
+            metrics: None,
+            records: Arc::default(),
+        }
+    }
+
+    /// Cancel a tracker call still running after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Count failures in `metrics` when a call isn't given metrics of its own
+    pub fn with_service_metrics(mut self, metrics: Arc<dyn ServiceMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// How long a tracker call may take
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Run the tracker call `call` makes, recorded as `tracker`
+    ///
+    /// A panic, whether making the call or running it, is caught, and a call
+    /// still running after the timeout is cancelled. Either is counted in
+    /// `metrics`, or the guard's own metrics.
+    pub async fn call<F>(
+        &self,
+        tracker: &str,
+        metrics: Option<&dyn ServiceMetrics>,
+        call: impl FnOnce() -> F,
+    ) where
+        F: Future<Output = ()>,
+    {
+        let result = match std::panic::catch_unwind(AssertUnwindSafe(call)) {
+            Ok(future) => match tokio::time::timeout(self.timeout, catch_unwind(future)).await {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(())) => Err(Failure::Panic),
+                Err(_) => Err(Failure::Timeout),
+            },
+            Err(_) => Err(Failure::Panic),
+        };
+        if let Err(failure) = result
+            && let Some(metrics) = metrics.or(self.metrics.as_deref())
+        {
+            metrics.record_tracker_error(tracker, failure.as_str());
+        }
+        self.record(tracker, result);
+    }
+
+    fn record(&self, tracker: &str, result: Result<(), Failure>) {
+        let mut records = self.records.lock().unwrap();
+        let record = match records.get_mut(tracker) {
+            Some(record) => record,
+            None => records.entry(tracker.to_string()).or_default(),
+        };
+        record.calls += 1;
+        let Err(failure) = result else {
+            record.consecutive_failures = 0;
+            return;
+        };
+
+        match failure {
+            Failure::Panic => record.failures.panics += 1,
+            Failure::Timeout => record.failures.timeouts += 1,
+        }
+        record.consecutive_failures += 1;
+        record.last_failure = Some(SystemTime::now());
+        if record
+            .last_warning
+            .is_some_and(|warned| warned.elapsed() < WARNING_INTERVAL)
+        {
+            record.unwarned += 1;
+            return;
+        }
+        tracing::warn!(
+            tracker,
+            error = failure.as_str(),
+            timeout_ms = self.timeout.as_millis() as u64,
+            consecutive_failures = record.consecutive_failures,
+            failures_since_last_warning = record.unwarned,
+            "Tracker failed; the request went on without it"
+        );
+        record.last_warning = Some(Instant::now());
+        record.unwarned = 0;
+    }
+
+    /// How the trackers called through the guard have fared so far
+    pub fn health(&self) -> TrackerHealth {
+        let records = self.records.lock().unwrap();
+        TrackerHealth {
+            trackers: records
+                .iter()
+                .map(|(tracker, record)| TrackerStatus {
+                    tracker: tracker.clone(),
+                    calls: record.calls,
+                    failures: record.failures,
+                    consecutive_failures: record.consecutive_failures,
+                    last_failure: record.last_failure,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Default for TrackerGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `future`'s output, or `Err` if polling it panicked
+async fn catch_unwind<F: Future<Output = ()>>(future: F) -> Result<(), ()> {
+    let mut future = Box::pin(future);
+    std::future::poll_fn(move |cx| {
+        match std::panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(())) => Poll::Ready(Ok(())),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(_) => Poll::Ready(Err(())),
+        }
+    })
+    .await
+}
+
+/// `tracker` called through `guard` as `name`
+pub(crate) fn guard_usage_tracker(
+    guard: &TrackerGuard,
+    name: String,
+    tracker: UsageTrackerFn,
+) -> UsageTrackerFn {
+    let guard = guard.clone();
+    Arc::new(move |headers, user, context| {
+        let (guard, tracker, name) = (guard.clone(), tracker.clone(), name.clone());
+        Box::pin(async move {
+            guard
+                .call(&name, None, || tracker(headers, user, context))
+                .await
+        })
+    })
+}
+
+/// `tracker` called through `guard` as `name`
+pub(crate) fn guard_method_duration_tracker(
+    guard: &TrackerGuard,
+    name: String,
+    tracker: MethodDurationTrackerFn,
+) -> MethodDurationTrackerFn {
+    let guard = guard.clone();
+    Arc::new(move |context, user, duration| {
+        let (guard, tracker, name) = (guard.clone(), tracker.clone(), name.clone());
+        Box::pin(async move {
+            guard
+                .call(&name, None, || tracker(context, user, duration))
+                .await
+        })
+    })
+}
+
+/// A snapshot of how the trackers called through a [`TrackerGuard`] fared,
+/// for readiness checks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackerHealth {
+    /// Each tracker called, by name
+    pub trackers: Vec<TrackerStatus>,
+}
+
+impl TrackerHealth {
+    /// The trackers whose last `threshold` calls or more all failed
+    pub fn failing(&self, threshold: u64) -> Vec<&TrackerStatus> {
+        self.trackers
+            .iter()
+            .filter(|status| status.consecutive_failures >= threshold.max(1))
+            .collect()
+    }
+
+    /// Whether no tracker failed `threshold` calls in a row
+    pub fn is_healthy(&self, threshold: u64) -> bool {
+        self.failing(threshold).is_empty()
+    }
+}
+
+/// How one tracker fared, in a [`TrackerHealth`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackerStatus {
+    /// The name the tracker was called under, such as `usage_tracker`
+    pub tracker: String,
+    /// Calls made to it
+    pub calls: u64,
+    /// Calls that panicked or timed out
+    pub failures: TrackerFailures,
+    /// Calls that failed since the last that didn't
+    pub consecutive_failures: u64,
+    /// When a call last failed
+    pub last_failure: Option<SystemTime>,
+}
@@ -4,14 +4,41 @@
 //! usage tracking, and observability across REST and JSON-RPC services.
 
 use async_trait::async_trait;
-use axum::http::HeaderMap;
-use ras_auth_core::AuthenticatedUser;
+use http::HeaderMap;
+use ras_auth_core::{AuthenticatedUser, PrincipalKind};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::future::Future;
+use std::net::IpAddr;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
 
+mod composite;
+pub use composite::{
+    CompositeMethodDurationTracker, CompositeUsageTracker, DEFAULT_TRACKER_TIMEOUT, TrackerFailures,
+};
+
+mod health;
+pub use health::{TrackerGuard, TrackerHealth, TrackerStatus};
+
+mod origin;
+pub use origin::{InvalidProxyNetwork, RequestOrigin, TrustedProxies};
+
+mod sampling;
+pub use sampling::{CardinalityGuard, OTHER_LABEL, SampledUsageTracker, SamplingPolicy};
+
+mod slow;
+pub use slow::{SlowRequest, SlowRequestTracker};
+
+mod trace_context;
+pub use trace_context::{TRACEPARENT_HEADER, TRACESTATE_HEADER, TraceContext};
+
+#[cfg(feature = "otel")]
+mod propagation;
+#[cfg(feature = "otel")]
+pub use propagation::{install_trace_context_propagator, set_span_parent, trace_context_headers};
+
 /// Protocol type for request context
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Protocol {
@@ -34,7 +61,9 @@ impl std::fmt::Display for Protocol {
 }
 
 /// Common request context that can represent both REST and JSON-RPC requests
-#[derive(Debug, Clone)]
+///
+/// Serializes for log sinks, leaving out fields that aren't set.
+#[derive(Debug, Clone, Serialize)]
 pub struct RequestContext {
     /// The method being called
     /// - For REST: "GET /users" or "POST /api/v1/users"
@@ -44,6 +73,19 @@ pub struct RequestContext {
     /// The protocol being used
     pub protocol: Protocol,
 
+    /// The client's address, see [`client_ip`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<IpAddr>,
+
+    /// The route template the request matched, such as `/users/{id}`, or the
+    /// path of a JSON-RPC endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+
+    /// The id the caller or a proxy gave the request, see [`request_id`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+
     /// Additional metadata about the request
     /// - For REST: could include path parameters, query strings
     /// - For JSON-RPC: could include request ID, version
@@ -51,22 +93,65 @@ pub struct RequestContext {
 }
 
 impl RequestContext {
-    /// Create a new REST request context
-    pub fn rest(http_method: &str, path: &str) -> Self {
+    /// Create a context for `method` called over `protocol`
+    pub fn new(method: impl Into<String>, protocol: Protocol) -> Self {
         Self {
-            method: format!("{} {}", http_method, path),
-            protocol: Protocol::Rest,
+            method: method.into(),
+            protocol,
+            client_ip: None,
+            route: None,
+            request_id: None,
             metadata: HashMap::new(),
         }
     }
 
+    /// Create a new REST request context
+    pub fn rest(http_method: &str, path: &str) -> Self {
+        Self::new(format!("{} {}", http_method, path), Protocol::Rest)
+    }
+
     /// Create a new JSON-RPC request context
     pub fn jsonrpc(method: String) -> Self {
-        Self {
-            method,
-            protocol: Protocol::JsonRpc,
-            metadata: HashMap::new(),
+        Self::new(method, Protocol::JsonRpc)
+    }
+
+    /// Create a new context for a JSON-RPC method called over a WebSocket
+    pub fn websocket(method: String) -> Self {
+        Self::new(method, Protocol::WebSocket)
+    }
+
+    /// Set the client's address
+    pub fn with_client_ip(mut self, client_ip: IpAddr) -> Self {
+        self.client_ip = Some(client_ip);
+        self
+    }
+
+    /// Set the route template the request matched
+    pub fn with_route(mut self, route: impl Into<String>) -> Self {
+        self.route = Some(route.into());
+        self
+    }
+
+    /// Set the id of the request
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Fill in the client address and route of the [`RequestOrigin`] being
+    /// handled, if any, and the request id from `headers`
+    ///
+    /// Generated services build their contexts this way, as should usage
+    /// trackers given only headers.
+    pub fn with_request_info(mut self, headers: &HeaderMap) -> Self {
+        if let Some(origin) = RequestOrigin::current() {
+            self.client_ip = origin.client_ip.or(self.client_ip);
+            self.route = origin.route.or(self.route);
+        }
+        if let Some(request_id) = request_id(headers) {
+            self.request_id = Some(request_id);
         }
+        self
     }
 
     /// Add metadata to the context
@@ -74,10 +159,241 @@ impl RequestContext {
         self.metadata.insert(key.into(), value.into());
         self
     }
+
+    /// Label the context with the kind of caller making the request, as the
+    /// `principal_kind` metadata key: `user`, `service_account` or `anonymous`
+    ///
+    /// Generated services set it on completed requests, so dashboards can
+    /// separate people from machines.
+    pub fn with_principal_kind(self, kind: PrincipalKind) -> Self {
+        self.with_metadata("principal_kind", kind.as_str())
+    }
+
+    /// Record the tenant the request was made for as the `tenant` metadata key
+    pub fn with_tenant(self, tenant: impl Into<String>) -> Self {
+        self.with_metadata("tenant", tenant)
+    }
+
+    /// Record the tenant `extractor` names, if any, as with
+    /// [`with_tenant`](Self::with_tenant)
+    pub fn with_extracted_tenant(
+        self,
+        extractor: &TenantExtractor,
+        headers: &HeaderMap,
+        user: Option<&AuthenticatedUser>,
+    ) -> Self {
+        match extractor(headers, user, &self) {
+            Some(tenant) => self.with_tenant(tenant),
+            None => self,
+        }
+    }
+
+    /// The tenant the request was made for, if recorded
+    pub fn tenant(&self) -> Option<&str> {
+        self.metadata.get("tenant").map(String::as_str)
+    }
+
+    /// Record how the request ended as the `outcome` metadata key, for
+    /// [`RequestOutcome::of`]
+    pub fn with_outcome(self, outcome: RequestOutcome) -> Self {
+        self.with_metadata("outcome", outcome.as_str())
+    }
+
+    /// Record the size of the request body in bytes as the `request_bytes`
+    /// metadata key
+    ///
+    /// Generated services set it on the context given to usage trackers, and
+    /// along with the response size on completed requests when service metrics
+    /// are installed.
+    pub fn with_request_size(self, bytes: usize) -> Self {
+        self.with_metadata("request_bytes", bytes.to_string())
+    }
+
+    /// Record the size of the serialized response body in bytes as the
+    /// `response_bytes` metadata key
+    pub fn with_response_size(self, bytes: usize) -> Self {
+        self.with_metadata("response_bytes", bytes.to_string())
+    }
+
+    /// Record the HTTP status a request was answered with, as the
+    /// `status_class` (see [`status_class`]) and `status_code` metadata keys
+    ///
+    /// Generated REST services set it on completed requests. Metrics backends
+    /// should label with the exact code only when asked to, as it is less
+    /// bounded than the class.
+    pub fn with_http_status(self, status: u16) -> Self {
+        self.with_metadata("status_class", status_class(status))
+            .with_metadata("status_code", status.to_string())
+    }
+
+    /// Record the JSON-RPC error code a request was answered with, as the
+    /// `error_code` metadata key (see [`jsonrpc_error_code_name`])
+    ///
+    /// Generated JSON-RPC services set it on failed requests.
+    pub fn with_jsonrpc_error_code(self, code: i32) -> Self {
+        self.with_metadata("error_code", jsonrpc_error_code_name(code))
+    }
+
+    /// The trace the request belongs to, for linking metrics and logs to it
+    ///
+    /// That of the current `tracing` span when the `otel` feature is on and the
+    /// span has an OpenTelemetry span context, or else the `trace_id` metadata
+    /// set by [`with_trace_context`](Self::with_trace_context).
+    pub fn trace_id(&self) -> Option<String> {
+        #[cfg(feature = "otel")]
+        if let Some(trace_id) = propagation::current_trace_id() {
+            return Some(trace_id);
+        }
+        self.metadata.get("trace_id").cloned()
+    }
+
+    /// Add the W3C trace context sent with the request, if any
+    ///
+    /// Sets the `traceparent`, `trace_id` and (when present) `tracestate` metadata keys.
+    pub fn with_trace_context(mut self, headers: &HeaderMap) -> Self {
+        if let Some(trace) = TraceContext::from_headers(headers) {
+            self.metadata
+                .insert("trace_id".to_string(), trace.trace_id().to_string());
+            if let Some(tracestate) = trace.tracestate() {
+                self.metadata
+                    .insert("tracestate".to_string(), tracestate.to_string());
+            }
+            self.metadata
+                .insert("traceparent".to_string(), trace.traceparent().to_string());
+        }
+        self
+    }
+}
+
+/// The class of an HTTP status code, `1xx` to `5xx`, or `other` for codes
+/// outside the range HTTP defines
+///
+/// Safe as a metric label: it takes one of six values.
+pub fn status_class(status: u16) -> &'static str {
+    match status {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        500..=599 => "5xx",
+        _ => "other",
+    }
+}
+
+/// The name of a JSON-RPC error code, for metric labels
+///
+/// Codes from the JSON-RPC specification and the error codes of this stack's
+/// JSON-RPC services have their own name, such as `insufficient_permissions`.
+/// Other codes in the range the specification reserves are `server_error` and
+/// the rest `application_error`, so any code a handler picks is safe as a label.
+pub fn jsonrpc_error_code_name(code: i32) -> &'static str {
+    match code {
+        -32700 => "parse_error",
+        -32600 => "invalid_request",
+        -32601 => "method_not_found",
+        -32602 => "invalid_params",
+        -32603 => "internal_error",
+        -32001 => "authentication_required",
+        -32002 => "insufficient_permissions",
+        -32003 => "token_expired",
+        -32004 => "token_not_yet_valid",
+        -32005 => "server_busy",
+        -32006 => "account_locked",
+        -32008 => "request_timeout",
+        -32029 => "rate_limited",
+        -32768..=-32000 => "server_error",
+        _ => "application_error",
+    }
+}
+
+/// How a completed request ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestOutcome {
+    /// The request succeeded
+    Success,
+    /// The handler failed, or the request was refused for another reason
+    HandlerError,
+    /// The caller was not authenticated or not allowed to make the request
+    AuthFailure,
+    /// The request or its parameters were malformed
+    InvalidParams,
+    /// The request ran past its deadline
+    Timeout,
+}
+
+impl RequestOutcome {
+    /// The outcome of a request generated services tagged with `error_kind`,
+    /// or of a successful one for `None`
+    pub fn from_error_kind(error_kind: Option<&str>) -> Self {
+        match error_kind {
+            None => RequestOutcome::Success,
+            Some("unauthenticated" | "forbidden") => RequestOutcome::AuthFailure,
+            Some("invalid_params" | "invalid_request" | "parse_error") => {
+                RequestOutcome::InvalidParams
+            }
+            Some("timeout") => RequestOutcome::Timeout,
+            Some(_) => RequestOutcome::HandlerError,
+        }
+    }
+
+    /// The outcome of a request completed with `success`: the `outcome`
+    /// metadata of `context` if set, otherwise derived from its `error_kind`
+    /// metadata if it failed
+    pub fn of(context: &RequestContext, success: bool) -> Self {
+        let recorded = context
+            .metadata
+            .get("outcome")
+            .and_then(|outcome| Self::from_label(outcome));
+        if let Some(outcome) = recorded {
+            return outcome;
+        }
+        if success {
+            return RequestOutcome::Success;
+        }
+        match context.metadata.get("error_kind") {
+            Some(kind) => Self::from_error_kind(Some(kind)),
+            None => RequestOutcome::HandlerError,
+        }
+    }
+
+    /// The outcome named `label`, as by [`as_str`](Self::as_str)
+    pub(crate) fn from_label(label: &str) -> Option<Self> {
+        [
+            RequestOutcome::Success,
+            RequestOutcome::HandlerError,
+            RequestOutcome::AuthFailure,
+            RequestOutcome::InvalidParams,
+            RequestOutcome::Timeout,
+        ]
+        .into_iter()
+        .find(|known| known.as_str() == label)
+    }
+
+    pub fn is_success(self) -> bool {
+        self == RequestOutcome::Success
+    }
+
+    /// The outcome's metric label
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RequestOutcome::Success => "success",
+            RequestOutcome::HandlerError => "handler_error",
+            RequestOutcome::AuthFailure => "auth_failure",
+            RequestOutcome::InvalidParams => "invalid_params",
+            RequestOutcome::Timeout => "timeout",
+        }
+    }
+}
+
+impl std::fmt::Display for RequestOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Type alias for async usage tracking function
-pub type UsageTrackerFn = Box<
+pub type UsageTrackerFn = Arc<
     dyn Fn(
             HeaderMap,
             Option<AuthenticatedUser>,
@@ -88,7 +404,7 @@ pub type UsageTrackerFn = Box<
 >;
 
 /// Type alias for async method duration tracking function
-pub type MethodDurationTrackerFn = Box<
+pub type MethodDurationTrackerFn = Arc<
     dyn Fn(
             RequestContext,
             Option<AuthenticatedUser>,
@@ -98,6 +414,15 @@ pub type MethodDurationTrackerFn = Box<
         + Sync,
 >;
 
+/// Function naming the tenant a request was made for, from its headers, its
+/// authenticated caller, if known yet, and its context
+///
+/// Generated services call it for the contexts they give service metrics, see
+/// [`Observability::tenant_extractor`].
+pub type TenantExtractor = Arc<
+    dyn Fn(&HeaderMap, Option<&AuthenticatedUser>, &RequestContext) -> Option<String> + Send + Sync,
+>;
+
 /// Trait for tracking request usage
 #[async_trait]
 pub trait UsageTracker: Send + Sync {
@@ -120,6 +445,55 @@ pub trait MethodDurationTracker: Send + Sync {
         user: Option<&AuthenticatedUser>,
         duration: Duration,
     );
+
+    /// Track a completed request with how it ended
+    ///
+    /// Generated services call this rather than `track_duration` for every
+    /// request, failed ones included. Tracks only the duration unless
+    /// implemented.
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+        _outcome: RequestOutcome,
+    ) {
+        self.track_duration(context, user, duration).await
+    }
+}
+
+/// Marks a request as executing until dropped, returned by
+/// [`ServiceMetrics::in_flight_guard`]
+///
+/// Dropping the guard releases the request, so a handler that panics while
+/// holding it is not counted as executing forever.
+#[must_use = "the request stops counting as in flight when the guard is dropped"]
+#[derive(Default)]
+pub struct InFlightGuard {
+    release: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl InFlightGuard {
+    /// A guard that calls `release` when dropped
+    pub fn new(release: impl FnOnce() + Send + 'static) -> Self {
+        Self {
+            release: Some(Box::new(release)),
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+impl std::fmt::Debug for InFlightGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InFlightGuard").finish_non_exhaustive()
+    }
 }
 
 /// Common metrics that should be tracked across all services
@@ -132,52 +506,234 @@ pub trait ServiceMetrics: Send + Sync {
 
     /// Record the duration of a method execution
     fn record_method_duration(&self, context: &RequestContext, duration: Duration);
+
+    /// Count a request as executing until the returned guard is dropped
+    ///
+    /// Generated services hold the guard while the handler runs. Counts nothing
+    /// unless implemented.
+    fn in_flight_guard(&self, _context: &RequestContext) -> InFlightGuard {
+        InFlightGuard::default()
+    }
+
+    /// Record the size of a request body in bytes
+    ///
+    /// Generated services report it along with the response size, only when
+    /// service metrics are installed. Does nothing unless implemented.
+    fn record_request_size(&self, _context: &RequestContext, _bytes: usize) {}
+
+    /// Record the size of a serialized response body in bytes
+    ///
+    /// Does nothing unless implemented.
+    fn record_response_size(&self, _context: &RequestContext, _bytes: usize) {}
+
+    /// Record the request and response body sizes of a method call in bytes
+    ///
+    /// Reports them to `record_request_size` and `record_response_size` unless
+    /// implemented.
+    fn record_payload_size(
+        &self,
+        context: &RequestContext,
+        request_bytes: usize,
+        response_bytes: usize,
+    ) {
+        self.record_request_size(context, request_bytes);
+        self.record_response_size(context, response_bytes);
+    }
+
+    /// Record a WebSocket connection being opened
+    ///
+    /// Does nothing unless implemented, like the other connection metrics.
+    fn record_connection_opened(&self) {}
+
+    /// Record a WebSocket connection closing with `close_code`, if a close frame
+    /// was exchanged, for `reason`, such as `client_closed` or `session_expired`
+    fn record_connection_closed(&self, _close_code: Option<u16>, _reason: &str) {}
+
+    /// Record a message received on a WebSocket connection
+    fn record_message_received(&self) {}
+
+    /// Record a message sent on a WebSocket connection
+    fn record_message_sent(&self) {}
+
+    /// Record how many connections a broadcast notification was queued for
+    fn record_broadcast_fanout(&self, _context: &RequestContext, _recipients: usize) {}
+
+    /// Record a login session starting
+    ///
+    /// Does nothing unless implemented, like the other session metrics.
+    fn record_session_started(&self) {}
+
+    /// Record `count` sessions ending for `reason`: `logout`, `expiry` or `revocation`
+    fn record_sessions_ended(&self, _reason: &str, _count: usize) {}
+
+    /// Record how many sessions are currently active
+    fn record_active_sessions(&self, _count: usize) {}
+
+    /// Record a tracker call that failed with `error`, `panic` or `timeout`,
+    /// under the name it was called as, such as `usage_tracker`
+    ///
+    /// Reported by [`TrackerGuard`]. Does nothing unless implemented.
+    fn record_tracker_error(&self, _tracker: &str, _error: &str) {}
+
+    /// Record a request slower than its threshold
+    ///
+    /// Reported by [`SlowRequestTracker`] when given these metrics. Does
+    /// nothing unless implemented.
+    fn record_slow_request(&self, _context: &RequestContext) {}
+
+    /// Record a permission overlay changing the permissions of an
+    /// authenticated user
+    ///
+    /// Does nothing unless implemented, like the other overlay metrics.
+    fn record_permission_overlay_hit(&self) {}
+
+    /// Record a request refused a permission a permission overlay revoked
+    fn record_permission_overlay_denial(&self) {}
+}
+
+/// A metrics backend installed on a generated service in one call, through the
+/// `with_observability` method of its builder
+///
+/// The service metrics count every request, failures included, so the trackers
+/// are for what they add on top, such as logging; trackers that count requests
+/// themselves would count them twice.
+pub trait Observability: Send + Sync {
+    /// Metrics reporting requests started and completed, with their outcome and duration
+    fn service_metrics(&self) -> Arc<dyn ServiceMetrics>;
+
+    /// Tracker told about each request before it is processed, if any
+    fn usage_tracker(&self) -> Option<Arc<dyn UsageTracker>> {
+        None
+    }
+
+    /// Tracker told how long each request took, if any
+    fn method_duration_tracker(&self) -> Option<Arc<dyn MethodDurationTracker>> {
+        None
+    }
+
+    /// Function naming the tenant of each request, if any
+    ///
+    /// Generated services record the tenant it names on the contexts of
+    /// requests started, without the caller, who isn't authenticated yet, and
+    /// completed, with the caller, for the service metrics to label them by.
+    fn tenant_extractor(&self) -> Option<TenantExtractor> {
+        None
+    }
 }
 
 /// Builder for configuring observability
+///
+/// Trackers added more than once are all called, each through a composite
+/// tracker isolating it from the others. Every tracker is called through a
+/// [`TrackerGuard`], as `usage_tracker_0`, `usage_tracker_1` and so on for
+/// usage trackers, and `method_duration_tracker_0` and so on for duration
+/// trackers.
 pub struct ObservabilityBuilder {
-    usage_tracker: Option<UsageTrackerFn>,
-    duration_tracker: Option<MethodDurationTrackerFn>,
+    usage_trackers: Vec<UsageTrackerFn>,
+    duration_trackers: Vec<MethodDurationTrackerFn>,
+    guard: TrackerGuard,
+    service_metrics: Option<Arc<dyn ServiceMetrics>>,
+    tenant_extractor: Option<TenantExtractor>,
 }
 
 impl ObservabilityBuilder {
     /// Create a new builder
     pub fn new() -> Self {
         Self {
-            usage_tracker: None,
-            duration_tracker: None,
+            usage_trackers: Vec::new(),
+            duration_trackers: Vec::new(),
+            guard: TrackerGuard::new(),
+            service_metrics: None,
+            tenant_extractor: None,
         }
     }
 
-    /// Set the usage tracker
+    /// Call the trackers through `guard`, rather than a guard of its own with
+    /// the default timeout, to set the timeout or report its health
+    pub fn with_tracker_guard(mut self, guard: TrackerGuard) -> Self {
+        self.guard = guard;
+        self
+    }
+
+    /// Add a usage tracker, called after those added before it
     pub fn with_usage_tracker<F, Fut>(mut self, tracker: F) -> Self
     where
         F: Fn(HeaderMap, Option<AuthenticatedUser>, RequestContext) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = ()> + Send + 'static,
     {
-        self.usage_tracker = Some(Box::new(move |headers, user, context| {
-            Box::pin(tracker(headers, user, context))
-        }));
+        self.usage_trackers
+            .push(Arc::new(move |headers, user, context| {
+                Box::pin(tracker(headers, user, context))
+            }));
         self
     }
 
-    /// Set the method duration tracker
+    /// Add a method duration tracker, called after those added before it
     pub fn with_method_duration_tracker<F, Fut>(mut self, tracker: F) -> Self
     where
         F: Fn(RequestContext, Option<AuthenticatedUser>, Duration) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = ()> + Send + 'static,
     {
-        self.duration_tracker = Some(Box::new(move |context, user, duration| {
-            Box::pin(tracker(context, user, duration))
-        }));
+        self.duration_trackers
+            .push(Arc::new(move |context, user, duration| {
+                Box::pin(tracker(context, user, duration))
+            }));
+        self
+    }
+
+    /// Report each request to `metrics`, started and then completed with its
+    /// outcome and duration
+    pub fn with_service_metrics(mut self, metrics: Arc<dyn ServiceMetrics>) -> Self {
+        self.service_metrics = Some(metrics);
+        self
+    }
+
+    /// Name the tenant of each request with `extractor`, see
+    /// [`Observability::tenant_extractor`]
+    pub fn with_tenant_extractor<F>(mut self, extractor: F) -> Self
+    where
+        F: Fn(&HeaderMap, Option<&AuthenticatedUser>, &RequestContext) -> Option<String>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.tenant_extractor = Some(Arc::new(extractor));
         self
     }
 
     /// Build the observability configuration
     pub fn build(self) -> ObservabilityConfig {
+        let guard = self.guard;
+        let usage_trackers = self
+            .usage_trackers
+            .into_iter()
+            .enumerate()
+            .map(|(index, tracker)| {
+                health::guard_usage_tracker(&guard, format!("usage_tracker_{index}"), tracker)
+            })
+            .collect();
+        let duration_trackers = self
+            .duration_trackers
+            .into_iter()
+            .enumerate()
+            .map(|(index, tracker)| {
+                health::guard_method_duration_tracker(
+                    &guard,
+                    format!("method_duration_tracker_{index}"),
+                    tracker,
+                )
+            })
+            .collect();
+        // Trackers are cancelled by the guard first, so it records why
+        let timeout = guard.timeout() * 2;
         ObservabilityConfig {
-            usage_tracker: self.usage_tracker,
-            duration_tracker: self.duration_tracker,
+            usage_tracker: composite::compose_usage_trackers(usage_trackers, timeout),
+            duration_tracker: composite::compose_method_duration_trackers(
+                duration_trackers,
+                timeout,
+            ),
+            service_metrics: self.service_metrics,
+            tenant_extractor: self.tenant_extractor,
         }
     }
 }
@@ -189,9 +745,70 @@ impl Default for ObservabilityBuilder {
 }
 
 /// Configuration for observability
+///
+/// Generated REST and JSON-RPC builders install it with their
+/// `with_observability_config` method, which also takes the
+/// [`ObservabilityBuilder`] itself. Clones share the trackers, so one
+/// configuration can drive several services.
+///
+/// It is the one way those builders take these trackers: their
+/// `with_observability` and `with_completion_tracker` methods install a
+/// configuration made from what they are given. A service has one of each, so
+/// what a configuration sets replaces what an earlier call set, and what it
+/// leaves unset is kept.
+#[derive(Clone, Default)]
 pub struct ObservabilityConfig {
     pub usage_tracker: Option<UsageTrackerFn>,
     pub duration_tracker: Option<MethodDurationTrackerFn>,
+    pub service_metrics: Option<Arc<dyn ServiceMetrics>>,
+    pub tenant_extractor: Option<TenantExtractor>,
+}
+
+impl ObservabilityConfig {
+    /// The usage tracker function as a [`UsageTracker`], if any
+    pub fn shared_usage_tracker(&self) -> Option<Arc<dyn UsageTracker>> {
+        self.usage_tracker
+            .clone()
+            .map(composite::usage_tracker_from_fn)
+    }
+
+    /// The method duration tracker function as a [`MethodDurationTracker`],
+    /// if any
+    pub fn shared_method_duration_tracker(&self) -> Option<Arc<dyn MethodDurationTracker>> {
+        self.duration_tracker
+            .clone()
+            .map(composite::method_duration_tracker_from_fn)
+    }
+
+    /// Report each completed request to `tracker` with how it ended, through
+    /// [`MethodDurationTracker::track_completion`], as the duration tracker
+    pub fn with_completion_tracker(mut self, tracker: Arc<dyn MethodDurationTracker>) -> Self {
+        self.duration_tracker = Some(composite::method_duration_tracker_fn(tracker));
+        self
+    }
+}
+
+impl From<ObservabilityBuilder> for ObservabilityConfig {
+    fn from(builder: ObservabilityBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<&dyn Observability> for ObservabilityConfig {
+    /// What `observability` reports to: its service metrics, trackers and
+    /// tenant extractor
+    fn from(observability: &dyn Observability) -> Self {
+        ObservabilityConfig {
+            usage_tracker: observability
+                .usage_tracker()
+                .map(composite::usage_tracker_fn),
+            duration_tracker: observability
+                .method_duration_tracker()
+                .map(composite::method_duration_tracker_fn),
+            service_metrics: Some(observability.service_metrics()),
+            tenant_extractor: observability.tenant_extractor(),
+        }
+    }
 }
 
 /// Helper functions for extracting common attributes from requests
@@ -207,6 +824,72 @@ pub mod extractors {
             .to_string()
     }
 
+    /// Size of the request body from its `Content-Length` header, 0 when the
+    /// header is missing or invalid
+    pub fn request_body_size(headers: &HeaderMap) -> usize {
+        headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// The id of the request from its `X-Request-Id` header, as set by the
+    /// caller or a proxy
+    pub fn request_id(headers: &HeaderMap) -> Option<String> {
+        headers
+            .get("x-request-id")
+            .and_then(|h| h.to_str().ok())
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+    }
+
+    /// The address of the client behind the proxies in `trusted`, given the
+    /// address of the `peer` that connected
+    ///
+    /// Walks the `X-Forwarded-For` addresses back from the peer for as long as
+    /// each hop is a trusted proxy, so addresses a client forged in front of
+    /// the ones its proxies appended are ignored. Without a known peer, only a
+    /// service trusting [`any`](TrustedProxies::any) proxy gets an address.
+    pub fn client_ip(
+        headers: &HeaderMap,
+        peer: Option<IpAddr>,
+        trusted: &TrustedProxies,
+    ) -> Option<IpAddr> {
+        let mut client = match peer {
+            Some(peer) => peer.to_canonical(),
+            None if trusted.trusts_any() => return forwarded_for(headers).next()?,
+            None => return None,
+        };
+        if trusted.is_empty() {
+            return Some(client);
+        }
+
+        let forwarded: Vec<_> = forwarded_for(headers).collect();
+        for hop in forwarded.into_iter().rev() {
+            if !trusted.contains(client) {
+                break;
+            }
+            match hop {
+                Some(hop) => client = hop.to_canonical(),
+                // A proxy passed on an address it couldn't parse
+                None => break,
+            }
+        }
+        Some(client)
+    }
+
+    /// Each `X-Forwarded-For` address, in order, `None` for those that don't parse
+    fn forwarded_for(headers: &HeaderMap) -> impl Iterator<Item = Option<IpAddr>> + '_ {
+        headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+            .filter(|address| !address.trim().is_empty())
+            .map(crate::origin::parse_forwarded_address)
+    }
+
     /// Extract common user attributes
     pub fn user_attributes(user: Option<&AuthenticatedUser>) -> HashMap<String, String> {
         let mut attrs = HashMap::new();
@@ -230,13 +913,17 @@ pub mod extractors {
             attrs.insert("user_id".to_string(), "anonymous".to_string());
             attrs.insert("authenticated".to_string(), "false".to_string());
         }
+        attrs.insert(
+            "principal_kind".to_string(),
+            PrincipalKind::of(user).as_str().to_string(),
+        );
 
         attrs
     }
 }
 
 // Re-export commonly used types
-pub use extractors::{user_agent, user_attributes};
+pub use extractors::{client_ip, request_body_size, request_id, user_agent, user_attributes};
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,176 @@
+//! Where a request came from: its client address behind trusted proxies, and
+//! the route it matched.
+
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+
+tokio::task_local! {
+    static REQUEST_ORIGIN: RequestOrigin;
+}
+
+/// The client address and matched route of the request being handled
+///
+/// Generated services resolve it once per HTTP request and handle the request
+/// within [`scope`](Self::scope), so the contexts they and the trackers they
+/// install build with [`RequestContext::with_request_info`](crate::RequestContext::with_request_info)
+/// carry it. Handlers can read it through [`current`](Self::current), though
+/// not in tasks they spawn.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestOrigin {
+    /// The client's address, see [`client_ip`](crate::client_ip)
+    pub client_ip: Option<IpAddr>,
+    /// The route template the request matched, such as `/users/{id}`, or the
+    /// path of a JSON-RPC endpoint
+    pub route: Option<String>,
+}
+
+impl RequestOrigin {
+    /// A request from `client_ip` that matched `route`
+    pub fn new(client_ip: Option<IpAddr>, route: impl Into<String>) -> Self {
+        Self {
+            client_ip,
+            route: Some(route.into()),
+        }
+    }
+
+    /// The origin of the request being handled, if handled within one
+    pub fn current() -> Option<Self> {
+        REQUEST_ORIGIN.try_with(Clone::clone).ok()
+    }
+
+    /// Run `future` as the handling of a request from this origin
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        REQUEST_ORIGIN.scope(self, future).await
+    }
+}
+
+/// A network of addresses, such as `10.0.0.0/8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Network {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+impl Network {
+    fn parse(network: &str) -> Option<Self> {
+        let (address, prefix_len) = match network.trim().split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (network.trim(), None),
+        };
+        let address: IpAddr = address.parse().ok()?;
+        let max_len = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse().ok().filter(|len| *len <= max_len)?,
+            None => max_len,
+        };
+        Some(Self {
+            address: address.to_canonical(),
+            prefix_len,
+        })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A network given to [`TrustedProxies::new`] that isn't an address or an
+/// address with a prefix length, such as `10.0.0.0/8`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidProxyNetwork(pub String);
+
+impl std::fmt::Display for InvalidProxyNetwork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid proxy network `{}`", self.0)
+    }
+}
+
+impl std::error::Error for InvalidProxyNetwork {}
+
+/// The proxies whose `X-Forwarded-For` headers are believed, for
+/// [`client_ip`](crate::client_ip)
+///
+/// None by default: the client is whoever connected, and forwarded addresses,
+/// which any client can send, are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    networks: Vec<Network>,
+    any: bool,
+}
+
+impl TrustedProxies {
+    /// Trust the proxies in `networks`, each an address such as `10.0.0.1` or
+    /// a network such as `10.0.0.0/8` or `fd00::/8`
+    pub fn new<I>(networks: I) -> Result<Self, InvalidProxyNetwork>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let networks = networks
+            .into_iter()
+            .map(|network| {
+                let network = network.as_ref();
+                Network::parse(network).ok_or_else(|| InvalidProxyNetwork(network.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            networks,
+            any: false,
+        })
+    }
+
+    /// Trust no proxy
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Trust every proxy, so the client is the first forwarded address
+    ///
+    /// Only for services no client can reach but through proxies that
+    /// overwrite `X-Forwarded-For`.
+    pub fn any() -> Self {
+        Self {
+            networks: Vec::new(),
+            any: true,
+        }
+    }
+
+    /// Whether `ip` is a trusted proxy
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.any || self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    /// Whether no proxy is trusted
+    pub fn is_empty(&self) -> bool {
+        !self.any && self.networks.is_empty()
+    }
+
+    pub(crate) fn trusts_any(&self) -> bool {
+        self.any
+    }
+}
+
+/// A forwarded address, with or without a port
+pub(crate) fn parse_forwarded_address(address: &str) -> Option<IpAddr> {
+    let address = address.trim().trim_matches('"');
+    address.parse().ok().or_else(|| {
+        address
+            .parse::<SocketAddr>()
+            .ok()
+            .map(|address| address.ip())
+    })
+}
@@ -0,0 +1,75 @@
+//! OpenTelemetry context propagation between `tracing` spans and HTTP headers.
+//!
+//! Requires a `tracing-opentelemetry` layer on the subscriber; without one the
+//! injected headers are empty and parents are ignored.
+
+use http::{HeaderMap, HeaderName};
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::trace_context::TRACEPARENT_HEADER;
+
+/// Use the W3C Trace Context format for propagation.
+///
+/// OpenTelemetry propagates nothing until a global propagator is installed.
+pub fn install_trace_context_propagator() {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+}
+
+/// Headers carrying the context of the current `tracing` span.
+pub fn trace_context_headers() -> Vec<(String, String)> {
+    let context = tracing::Span::current().context();
+    let mut injector = HeaderInjector::default();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut injector);
+    });
+    injector.0
+}
+
+/// The trace id of the current `tracing` span, if it has a valid
+/// OpenTelemetry span context.
+pub(crate) fn current_trace_id() -> Option<String> {
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+/// Make the remote caller's span, sent in `traceparent`, the parent of `span`.
+///
+/// Requests without a `traceparent` keep the span's local parent.
+pub fn set_span_parent(span: &tracing::Span, headers: &HeaderMap) {
+    if !headers.contains_key(TRACEPARENT_HEADER) {
+        return;
+    }
+
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    span.set_parent(parent);
+}
+
+#[derive(Default)]
+struct HeaderInjector(Vec<(String, String)>);
+
+impl Injector for HeaderInjector {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.push((key.to_string(), value));
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
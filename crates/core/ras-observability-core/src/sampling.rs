@@ -0,0 +1,255 @@
+//! Passing only some requests on to a tracker, and bounding the method names
+//! it sees.
+//!
+//! Sample trackers that log, not metrics: counters fed a sample undercount.
+//! The service metrics of an [`Observability`](crate::Observability) count
+//! every request whatever its trackers sample, so wrap its trackers, such as
+//! the logging-only OTel trackers, rather than trackers counting requests
+//! themselves.
+
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use http::HeaderMap;
+use ras_auth_core::AuthenticatedUser;
+
+use crate::{MethodDurationTracker, RequestContext, RequestOutcome, UsageTracker};
+
+/// The method name reported in place of those past a [`CardinalityGuard`]'s limit
+pub const OTHER_LABEL: &str = "__other__";
+
+/// Keeps the number of distinct values of a label, such as method names that
+/// path parameters leaked into, from growing without bound
+///
+/// The first `max_values` distinct values pass through, and any other is
+/// replaced with [`OTHER_LABEL`]. Values are remembered by their hash, so the
+/// guard holds at most `max_values` hashes.
+#[derive(Debug)]
+pub struct CardinalityGuard {
+    max_values: usize,
+    seen: Mutex<HashSet<u64>>,
+    hasher: RandomState,
+}
+
+impl CardinalityGuard {
+    /// Let through the first `max_values` distinct values
+    pub fn new(max_values: usize) -> Self {
+        Self {
+            max_values,
+            seen: Mutex::new(HashSet::new()),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// `value` if it is one of the values let through, [`OTHER_LABEL`] otherwise
+    pub fn label<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let hash = self.hasher.hash_one(value);
+        let mut seen = self.seen.lock().unwrap();
+        if seen.contains(&hash) {
+            return Cow::Borrowed(value);
+        }
+        if seen.len() < self.max_values {
+            seen.insert(hash);
+            Cow::Borrowed(value)
+        } else {
+            Cow::Borrowed(OTHER_LABEL)
+        }
+    }
+}
+
+/// Which requests a [`SampledUsageTracker`] passes on
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingPolicy {
+    /// Share of requests passed on, from `0.0` to `1.0`
+    pub rate: f64,
+    /// Pass on every failed request reported through `track_completion`,
+    /// whatever the rate and caps
+    pub always_on_error: bool,
+    /// Most requests to one method passed on per second
+    pub max_per_method_per_second: Option<u32>,
+    /// Distinct method names passed on before the others are reported as
+    /// [`OTHER_LABEL`]
+    pub max_methods: Option<usize>,
+}
+
+impl SamplingPolicy {
+    /// Pass on `rate` of the requests, from `0.0` to `1.0`
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate: rate.clamp(0.0, 1.0),
+            always_on_error: false,
+            max_per_method_per_second: None,
+            max_methods: None,
+        }
+    }
+
+    /// Pass on every request, subject to the caps added
+    pub fn always() -> Self {
+        Self::new(1.0)
+    }
+
+    /// Pass on every failed request, whatever the rate and caps
+    pub fn with_always_on_error(mut self) -> Self {
+        self.always_on_error = true;
+        self
+    }
+
+    /// Pass on at most `requests` requests to each method per second
+    pub fn with_method_rate_cap(mut self, requests: u32) -> Self {
+        self.max_per_method_per_second = Some(requests);
+        self
+    }
+
+    /// Report methods past the first `max_methods` distinct names as
+    /// [`OTHER_LABEL`]
+    pub fn with_max_methods(mut self, max_methods: usize) -> Self {
+        self.max_methods = Some(max_methods);
+        self
+    }
+}
+
+/// A tracker passing only a sample of requests on to `inner`, as set by a
+/// [`SamplingPolicy`]
+///
+/// Requests sampled out never reach `inner`, so they cost it nothing. Usage is
+/// tracked before a request is processed, when whether it fails isn't known,
+/// so `always_on_error` applies only to completions reported through
+/// [`MethodDurationTracker::track_completion`]. The method names `inner` sees
+/// are bounded by `max_methods`, and rate caps count per bounded name, so
+/// cap only bounded names when methods are named after paths.
+///
+/// Sample logging trackers only; see the [module documentation](self).
+pub struct SampledUsageTracker<T> {
+    inner: T,
+    policy: SamplingPolicy,
+    methods: Option<CardinalityGuard>,
+    random: AtomicU64,
+    windows: Mutex<HashMap<String, (u64, u32)>>,
+    started: Instant,
+}
+
+impl<T> SampledUsageTracker<T> {
+    /// Pass the requests `policy` samples on to `inner`
+    pub fn new(inner: T, policy: SamplingPolicy) -> Self {
+        Self {
+            inner,
+            methods: policy.max_methods.map(CardinalityGuard::new),
+            policy,
+            random: AtomicU64::new(RandomState::new().hash_one(0u8)),
+            windows: Mutex::new(HashMap::new()),
+            started: Instant::now(),
+        }
+    }
+
+    /// The tracker requests are passed on to
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Whether a uniformly random draw falls within the sampling rate
+    fn sampled(&self) -> bool {
+        if self.policy.rate >= 1.0 {
+            return true;
+        }
+        if self.policy.rate <= 0.0 {
+            return false;
+        }
+        // SplitMix64 over an atomic counter
+        let mut z = self
+            .random
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < self.policy.rate
+    }
+
+    /// Whether `method` is under its rate cap, counting the request if so
+    fn within_cap(&self, method: &str) -> bool {
+        let Some(cap) = self.policy.max_per_method_per_second else {
+            return true;
+        };
+        let second = self.started.elapsed().as_secs();
+        let mut windows = self.windows.lock().unwrap();
+        let (window, count) = windows.entry(method.to_string()).or_insert((second, 0));
+        if *window != second {
+            *window = second;
+            *count = 0;
+        }
+        if *count < cap {
+            *count += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// `context` with its method bounded, if it is to be passed on
+    fn pass_on(&self, context: &RequestContext, failed: bool) -> Option<RequestContext> {
+        let forced = failed && self.policy.always_on_error;
+        if !forced && !self.sampled() {
+            return None;
+        }
+        let method = match &self.methods {
+            Some(methods) => methods.label(&context.method),
+            None => Cow::Borrowed(context.method.as_str()),
+        };
+        if !forced && !self.within_cap(&method) {
+            return None;
+        }
+        let mut context = context.clone();
+        if method != context.method {
+            context.method = method.into_owned();
+        }
+        Some(context)
+    }
+}
+
+#[async_trait]
+impl<T: UsageTracker> UsageTracker for SampledUsageTracker<T> {
+    async fn track_request(
+        &self,
+        headers: &HeaderMap,
+        user: Option<&AuthenticatedUser>,
+        context: &RequestContext,
+    ) {
+        if let Some(context) = self.pass_on(context, false) {
+            self.inner.track_request(headers, user, &context).await
+        }
+    }
+}
+
+#[async_trait]
+impl<T: MethodDurationTracker> MethodDurationTracker for SampledUsageTracker<T> {
+    async fn track_duration(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+    ) {
+        if let Some(context) = self.pass_on(context, false) {
+            self.inner.track_duration(&context, user, duration).await
+        }
+    }
+
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+        outcome: RequestOutcome,
+    ) {
+        if let Some(context) = self.pass_on(context, !outcome.is_success()) {
+            self.inner
+                .track_completion(&context, user, duration, outcome)
+                .await
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Logging and counting requests slower than a threshold.
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ras_auth_core::AuthenticatedUser;
+
+use crate::{MethodDurationTracker, RequestContext, RequestOutcome, ServiceMetrics};
+
+/// A request that took longer than its threshold, passed to the callback of a
+/// [`SlowRequestTracker`]
+#[derive(Debug, Clone)]
+pub struct SlowRequest {
+    /// The request, with its metadata
+    pub context: RequestContext,
+    /// The user who made the request, if authenticated
+    pub user_id: Option<String>,
+    /// How long the request took
+    pub duration: Duration,
+    /// The threshold it exceeded
+    pub threshold: Duration,
+    /// How the request ended, if reported through `track_completion`
+    pub outcome: Option<RequestOutcome>,
+}
+
+type SlowRequestCallback =
+    Arc<dyn Fn(SlowRequest) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// A duration tracker logging a warning for each request slower than a
+/// threshold before passing it on to `inner`
+///
+/// The warning carries the method, protocol, duration, threshold, user id,
+/// trace id, request metadata and, for completions, the outcome. Slow requests
+/// are also counted through [`ServiceMetrics::record_slow_request`] when
+/// service metrics are set, and passed to a callback, such as one paging
+/// someone, when set.
+pub struct SlowRequestTracker<T> {
+    inner: T,
+    threshold: Duration,
+    method_thresholds: HashMap<String, Duration>,
+    metrics: Option<Arc<dyn ServiceMetrics>>,
+    callback: Option<SlowRequestCallback>,
+}
+
+impl<T> SlowRequestTracker<T> {
+    /// Warn about requests slower than `threshold`, passing every request on
+    /// to `inner`
+    pub fn new(inner: T, threshold: Duration) -> Self {
+        Self {
+            inner,
+            threshold,
+            method_thresholds: HashMap::new(),
+            metrics: None,
+            callback: None,
+        }
+    }
+
+    /// Warn about requests to `method` slower than `threshold`, rather than
+    /// the default threshold
+    pub fn with_method_threshold(mut self, method: impl Into<String>, threshold: Duration) -> Self {
+        self.method_thresholds.insert(method.into(), threshold);
+        self
+    }
+
+    /// Count slow requests in `metrics`
+    pub fn with_service_metrics(mut self, metrics: Arc<dyn ServiceMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Call `callback` with each slow request
+    ///
+    /// The callback runs in a task of its own, so a slow pager doesn't hold up
+    /// the request.
+    pub fn on_slow_request<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(SlowRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callback = Some(Arc::new(move |request| Box::pin(callback(request))));
+        self
+    }
+
+    /// The threshold requests to `method` are held to
+    pub fn threshold_for(&self, method: &str) -> Duration {
+        self.method_thresholds
+            .get(method)
+            .copied()
+            .unwrap_or(self.threshold)
+    }
+
+    /// The tracker requests are passed on to
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Warn about, count and report the request if it was slow
+    fn check(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+        outcome: Option<RequestOutcome>,
+    ) {
+        let threshold = self.threshold_for(&context.method);
+        if duration <= threshold {
+            return;
+        }
+
+        let user_id = user.map(|user| user.user_id.as_str());
+        // Sorted, so warnings about the same request read alike
+        let metadata: BTreeMap<_, _> = context.metadata.iter().collect();
+        tracing::warn!(
+            method = %context.method,
+            protocol = %context.protocol,
+            duration_ms = duration.as_millis() as u64,
+            threshold_ms = threshold.as_millis() as u64,
+            user_id = user_id.unwrap_or("anonymous"),
+            outcome = outcome.map(|outcome| outcome.as_str()),
+            trace_id = context.trace_id().as_deref(),
+            metadata = ?metadata,
+            "Slow request"
+        );
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record_slow_request(context);
+        }
+        if let Some(callback) = &self.callback {
+            tokio::spawn(callback(SlowRequest {
+                context: context.clone(),
+                user_id: user_id.map(str::to_string),
+                duration,
+                threshold,
+                outcome,
+            }));
+        }
+    }
+}
+
+#[async_trait]
+impl<T: MethodDurationTracker> MethodDurationTracker for SlowRequestTracker<T> {
+    async fn track_duration(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+    ) {
+        self.check(context, user, duration, None);
+        self.inner.track_duration(context, user, duration).await
+    }
+
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+        outcome: RequestOutcome,
+    ) {
+        self.check(context, user, duration, Some(outcome));
+        self.inner
+            .track_completion(context, user, duration, outcome)
+            .await
+    }
+}
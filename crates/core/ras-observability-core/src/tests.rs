@@ -1,7 +1,9 @@
 //! Tests for observability core traits and types
 
 use super::*;
+use ras_auth_core::PrincipalKind;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 use tokio::sync::Mutex;
 
@@ -42,6 +44,14 @@ fn test_request_context_jsonrpc() {
     assert!(ctx.metadata.is_empty());
 }
 
+#[test]
+fn test_request_context_websocket() {
+    let ctx = RequestContext::websocket("sendMessage".to_string());
+    assert_eq!(ctx.method, "sendMessage");
+    assert_eq!(ctx.protocol, Protocol::WebSocket);
+    assert!(ctx.metadata.is_empty());
+}
+
 #[test]
 fn test_request_context_with_metadata() {
     let ctx = RequestContext::rest("POST", "/api/users")
@@ -52,6 +62,17 @@ fn test_request_context_with_metadata() {
     assert_eq!(ctx.metadata.get("version"), Some(&"v1".to_string()));
 }
 
+#[test]
+fn test_request_context_with_principal_kind() {
+    let ctx = RequestContext::jsonrpc("sync".to_string())
+        .with_principal_kind(PrincipalKind::ServiceAccount);
+
+    assert_eq!(
+        ctx.metadata.get("principal_kind"),
+        Some(&"service_account".to_string())
+    );
+}
+
 #[test]
 fn test_user_agent_extraction() {
     let mut headers = HeaderMap::new();
@@ -73,6 +94,7 @@ fn test_user_attributes_authenticated() {
             .into_iter()
             .collect(),
         metadata: None,
+        kind: PrincipalKind::User,
     };
 
     let attrs = extractors::user_attributes(Some(&user));
@@ -80,6 +102,7 @@ fn test_user_attributes_authenticated() {
     assert_eq!(attrs.get("user_id"), Some(&"user123".to_string()));
     assert_eq!(attrs.get("authenticated"), Some(&"true".to_string()));
     assert_eq!(attrs.get("has_admin"), Some(&"true".to_string()));
+    assert_eq!(attrs.get("principal_kind"), Some(&"user".to_string()));
 
     // Permissions order might vary, so check if both exist
     let perms = attrs.get("permissions").unwrap();
@@ -94,6 +117,7 @@ fn test_user_attributes_anonymous() {
 
     assert_eq!(attrs.get("user_id"), Some(&"anonymous".to_string()));
     assert_eq!(attrs.get("authenticated"), Some(&"false".to_string()));
+    assert_eq!(attrs.get("principal_kind"), Some(&"anonymous".to_string()));
 }
 
 // Mock implementations for testing traits
@@ -159,6 +183,8 @@ struct MockServiceMetrics {
     requests_started: Arc<Mutex<Vec<(String, String)>>>, // method, protocol
     requests_completed: Arc<Mutex<Vec<(String, String, bool)>>>, // method, protocol, success
     method_durations: Arc<Mutex<Vec<(String, Duration)>>>, // method, duration
+    slow_requests: Arc<Mutex<Vec<String>>>,              // method
+    tracker_errors: Arc<Mutex<Vec<(String, String)>>>,   // tracker, error
 }
 
 impl MockServiceMetrics {
@@ -167,6 +193,8 @@ impl MockServiceMetrics {
             requests_started: Arc::new(Mutex::new(Vec::new())),
             requests_completed: Arc::new(Mutex::new(Vec::new())),
             method_durations: Arc::new(Mutex::new(Vec::new())),
+            slow_requests: Arc::new(Mutex::new(Vec::new())),
+            tracker_errors: Arc::new(Mutex::new(Vec::new())),
         }
     }
 }
@@ -193,6 +221,20 @@ impl ServiceMetrics for MockServiceMetrics {
             .unwrap()
             .push((context.method.clone(), duration));
     }
+
+    fn record_slow_request(&self, context: &RequestContext) {
+        self.slow_requests
+            .try_lock()
+            .unwrap()
+            .push(context.method.clone());
+    }
+
+    fn record_tracker_error(&self, tracker: &str, error: &str) {
+        self.tracker_errors
+            .try_lock()
+            .unwrap()
+            .push((tracker.to_string(), error.to_string()));
+    }
 }
 
 #[tokio::test]
@@ -202,6 +244,7 @@ async fn test_usage_tracker_trait() {
         user_id: "test_user".to_string(),
         permissions: vec!["read".to_string()].into_iter().collect(),
         metadata: None,
+        kind: PrincipalKind::User,
     };
     let context = RequestContext::jsonrpc("testMethod".to_string());
 
@@ -235,6 +278,70 @@ async fn test_method_duration_tracker_trait() {
     assert_eq!(durations[0].1, duration);
 }
 
+#[tokio::test]
+async fn test_track_completion_defaults_to_track_duration() {
+    let tracker = MockMethodDurationTracker::new();
+    let context = RequestContext::jsonrpc("sync".to_string());
+
+    tracker
+        .track_completion(
+            &context,
+            None,
+            Duration::from_millis(5),
+            RequestOutcome::Timeout,
+        )
+        .await;
+
+    assert_eq!(tracker.durations.lock().await[0].0, "sync");
+}
+
+#[test]
+fn test_request_outcome_from_error_kind() {
+    let failed = |kind| RequestOutcome::from_error_kind(Some(kind));
+
+    assert_eq!(
+        RequestOutcome::from_error_kind(None),
+        RequestOutcome::Success
+    );
+    assert_eq!(failed("forbidden"), RequestOutcome::AuthFailure);
+    assert_eq!(failed("unauthenticated"), RequestOutcome::AuthFailure);
+    assert_eq!(failed("parse_error"), RequestOutcome::InvalidParams);
+    assert_eq!(failed("timeout"), RequestOutcome::Timeout);
+    assert_eq!(failed("server_busy"), RequestOutcome::HandlerError);
+
+    let context =
+        RequestContext::rest("GET", "/users").with_metadata("error_kind", "invalid_params");
+    assert_eq!(
+        RequestOutcome::of(&context, false),
+        RequestOutcome::InvalidParams
+    );
+    assert_eq!(RequestOutcome::of(&context, true), RequestOutcome::Success);
+    assert_eq!(RequestOutcome::InvalidParams.to_string(), "invalid_params");
+
+    let context = RequestContext::jsonrpc("sync".to_string()).with_outcome(RequestOutcome::Timeout);
+    assert_eq!(RequestOutcome::of(&context, false), RequestOutcome::Timeout);
+}
+
+#[test]
+fn test_status_and_error_code_labels() {
+    assert_eq!(status_class(204), "2xx");
+    assert_eq!(status_class(403), "4xx");
+    assert_eq!(status_class(503), "5xx");
+    assert_eq!(status_class(99), "other");
+    assert_eq!(status_class(600), "other");
+
+    assert_eq!(jsonrpc_error_code_name(-32602), "invalid_params");
+    assert_eq!(jsonrpc_error_code_name(-32002), "insufficient_permissions");
+    assert_eq!(jsonrpc_error_code_name(-32050), "server_error");
+    assert_eq!(jsonrpc_error_code_name(1001), "application_error");
+
+    let context = RequestContext::rest("GET", "/users").with_http_status(404);
+    assert_eq!(context.metadata["status_class"], "4xx");
+    assert_eq!(context.metadata["status_code"], "404");
+    let context = RequestContext::jsonrpc("sync".to_string()).with_jsonrpc_error_code(-32603);
+    assert_eq!(context.metadata["error_code"], "internal_error");
+}
+
 #[test]
 fn test_service_metrics_trait() {
     let metrics = MockServiceMetrics::new();
@@ -253,6 +360,53 @@ fn test_service_metrics_trait() {
     let duration = Duration::from_secs(1);
     metrics.record_method_duration(&context, duration);
     assert_eq!(metrics.method_durations.try_lock().unwrap().len(), 1);
+
+    // Connection metrics default to no-ops
+    metrics.record_connection_opened();
+    metrics.record_connection_closed(Some(1000), "client_closed");
+    metrics.record_message_received();
+    metrics.record_message_sent();
+    metrics.record_broadcast_fanout(&RequestContext::websocket("tick".to_string()), 3);
+    drop(metrics.in_flight_guard(&context));
+    metrics.record_payload_size(&context, 12, 34);
+}
+
+#[test]
+fn test_request_body_size_and_size_metadata() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(request_body_size(&headers), 0);
+    headers.insert("content-length", "512".parse().unwrap());
+    assert_eq!(request_body_size(&headers), 512);
+    headers.insert("content-length", "lots".parse().unwrap());
+    assert_eq!(request_body_size(&headers), 0);
+
+    let context = RequestContext::jsonrpc("sync".to_string())
+        .with_request_size(512)
+        .with_response_size(48);
+    assert_eq!(context.metadata["request_bytes"], "512");
+    assert_eq!(context.metadata["response_bytes"], "48");
+}
+
+#[test]
+fn test_in_flight_guard_releases_on_drop_and_panic() {
+    let released = Arc::new(AtomicUsize::new(0));
+    let guard = |released: &Arc<AtomicUsize>| {
+        let released = released.clone();
+        InFlightGuard::new(move || {
+            released.fetch_add(1, Ordering::SeqCst);
+        })
+    };
+
+    drop(guard(&released));
+    assert_eq!(released.load(Ordering::SeqCst), 1);
+
+    let held = guard(&released);
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+        let _held = held;
+        panic!("handler failed");
+    }));
+    assert!(panicked.is_err());
+    assert_eq!(released.load(Ordering::SeqCst), 2);
 }
 
 #[tokio::test]
@@ -309,6 +463,500 @@ async fn test_observability_builder_with_duration_tracker() {
     assert_eq!(*duration_sum.lock().await, test_duration);
 }
 
+#[tokio::test]
+async fn test_observability_builder_composes_repeated_trackers() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let recorder = |name: &'static str| {
+        let calls = calls.clone();
+        move |_headers, _user, _context| {
+            let calls = calls.clone();
+            async move { calls.lock().await.push(name) }
+        }
+    };
+
+    let config = ObservabilityBuilder::new()
+        .with_usage_tracker(recorder("metrics"))
+        .with_usage_tracker(recorder("billing"))
+        .build();
+    let tracker_fn = config.usage_tracker.unwrap();
+    tracker_fn(HeaderMap::new(), None, RequestContext::rest("GET", "/a")).await;
+
+    let mut calls = calls.lock().await.clone();
+    calls.sort();
+    assert_eq!(calls, ["billing", "metrics"]);
+}
+
+struct CountingTracker(AtomicUsize);
+
+#[async_trait]
+impl UsageTracker for CountingTracker {
+    async fn track_request(
+        &self,
+        _headers: &HeaderMap,
+        _user: Option<&AuthenticatedUser>,
+        _context: &RequestContext,
+    ) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+struct PanickingTracker;
+
+#[async_trait]
+impl UsageTracker for PanickingTracker {
+    async fn track_request(
+        &self,
+        _headers: &HeaderMap,
+        _user: Option<&AuthenticatedUser>,
+        _context: &RequestContext,
+    ) {
+        panic!("billing pipeline unavailable");
+    }
+}
+
+struct HangingTracker;
+
+#[async_trait]
+impl UsageTracker for HangingTracker {
+    async fn track_request(
+        &self,
+        _headers: &HeaderMap,
+        _user: Option<&AuthenticatedUser>,
+        _context: &RequestContext,
+    ) {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_composite_usage_tracker_isolates_failing_trackers() {
+    let counting = Arc::new(CountingTracker(AtomicUsize::new(0)));
+    let composite = CompositeUsageTracker::new()
+        .with_tracker(Arc::new(PanickingTracker))
+        .with_tracker(Arc::new(HangingTracker))
+        .with_tracker(counting.clone())
+        .with_timeout(Duration::from_millis(50));
+
+    let started = std::time::Instant::now();
+    for _ in 0..2 {
+        composite
+            .track_request(&HeaderMap::new(), None, &RequestContext::rest("GET", "/a"))
+            .await;
+    }
+
+    // The hanging tracker is cancelled rather than holding up the request
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(counting.0.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        composite.failures(),
+        [
+            TrackerFailures {
+                panics: 2,
+                timeouts: 0
+            },
+            TrackerFailures {
+                panics: 0,
+                timeouts: 2
+            },
+            TrackerFailures::default(),
+        ]
+    );
+}
+
+struct OutcomeTracker(Mutex<Vec<(String, RequestOutcome)>>);
+
+#[async_trait]
+impl MethodDurationTracker for OutcomeTracker {
+    async fn track_duration(
+        &self,
+        _context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: Duration,
+    ) {
+    }
+
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: Duration,
+        outcome: RequestOutcome,
+    ) {
+        self.0.lock().await.push((context.method.clone(), outcome));
+    }
+}
+
+#[tokio::test]
+async fn test_composite_method_duration_tracker_forwards_outcomes() {
+    let first = Arc::new(OutcomeTracker(Mutex::new(Vec::new())));
+    let second = Arc::new(OutcomeTracker(Mutex::new(Vec::new())));
+    let composite = CompositeMethodDurationTracker::new()
+        .with_tracker(first.clone())
+        .with_tracker(second.clone());
+
+    composite
+        .track_completion(
+            &RequestContext::jsonrpc("sync".to_string()),
+            None,
+            Duration::from_millis(3),
+            RequestOutcome::Timeout,
+        )
+        .await;
+
+    for tracker in [first, second] {
+        assert_eq!(
+            *tracker.0.lock().await,
+            [("sync".to_string(), RequestOutcome::Timeout)]
+        );
+    }
+    assert_eq!(composite.failures(), [TrackerFailures::default(); 2]);
+}
+
+#[tokio::test]
+async fn test_tracker_guard_catches_panics_and_timeouts() {
+    let metrics = Arc::new(MockServiceMetrics::new());
+    let guard = TrackerGuard::new()
+        .with_timeout(Duration::from_millis(50))
+        .with_service_metrics(metrics.clone());
+    let headers = HeaderMap::new();
+    let context = RequestContext::rest("GET", "/a");
+    let counting = CountingTracker(AtomicUsize::new(0));
+
+    let started = std::time::Instant::now();
+    for _ in 0..3 {
+        guard
+            .call("billing", None, || {
+                PanickingTracker.track_request(&headers, None, &context)
+            })
+            .await;
+    }
+    guard
+        .call("audit", None, || {
+            HangingTracker.track_request(&headers, None, &context)
+        })
+        .await;
+    // Panicking while making the call rather than running it
+    guard
+        .call("audit", None, || -> std::future::Ready<()> {
+            panic!("no audit sink configured")
+        })
+        .await;
+    guard
+        .call("counting", None, || {
+            counting.track_request(&headers, None, &context)
+        })
+        .await;
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(counting.0.load(Ordering::SeqCst), 1);
+
+    let health = guard.health();
+    let names: Vec<_> = health
+        .trackers
+        .iter()
+        .map(|status| status.tracker.as_str())
+        .collect();
+    assert_eq!(names, ["audit", "billing", "counting"]);
+    assert_eq!(
+        health.trackers[0].failures,
+        TrackerFailures {
+            panics: 1,
+            timeouts: 1
+        }
+    );
+    assert_eq!(health.trackers[1].consecutive_failures, 3);
+    assert!(health.trackers[1].last_failure.is_some());
+    assert_eq!(health.trackers[2].calls, 1);
+    assert_eq!(health.trackers[2].last_failure, None);
+
+    let failing: Vec<_> = health
+        .failing(3)
+        .iter()
+        .map(|status| status.tracker.as_str())
+        .collect();
+    assert_eq!(failing, ["billing"]);
+    assert!(!health.is_healthy(2));
+    assert!(health.is_healthy(4));
+
+    let errors = metrics.tracker_errors.lock().await;
+    assert_eq!(errors.len(), 5);
+    assert_eq!(errors[3], ("audit".to_string(), "timeout".to_string()));
+
+    // A success ends the run of failures
+    drop(errors);
+    guard.call("billing", None, || async {}).await;
+    let health = guard.health();
+    let failing: Vec<_> = health
+        .failing(1)
+        .iter()
+        .map(|status| status.tracker.as_str())
+        .collect();
+    assert_eq!(failing, ["audit"]);
+}
+
+#[tokio::test]
+async fn test_observability_builder_guards_its_trackers() {
+    let guard = TrackerGuard::new().with_timeout(Duration::from_millis(50));
+    let config = ObservabilityBuilder::new()
+        .with_tracker_guard(guard.clone())
+        .with_usage_tracker(|_headers, _user, _context| async {
+            panic!("usage backend unavailable")
+        })
+        .with_method_duration_tracker(|_context, _user, _duration| {
+            tokio::time::sleep(Duration::from_secs(60))
+        })
+        .with_method_duration_tracker(|_context, _user, _duration| async {})
+        .build();
+
+    let context = RequestContext::jsonrpc("sync".to_string());
+    (config.usage_tracker.unwrap())(HeaderMap::new(), None, context.clone()).await;
+    (config.duration_tracker.unwrap())(context, None, Duration::from_millis(3)).await;
+
+    let health = guard.health();
+    let failures: Vec<_> = health
+        .trackers
+        .iter()
+        .map(|status| (status.tracker.as_str(), status.failures))
+        .collect();
+    assert_eq!(
+        failures,
+        [
+            (
+                "method_duration_tracker_0",
+                TrackerFailures {
+                    panics: 0,
+                    timeouts: 1
+                }
+            ),
+            ("method_duration_tracker_1", TrackerFailures::default()),
+            (
+                "usage_tracker_0",
+                TrackerFailures {
+                    panics: 1,
+                    timeouts: 0
+                }
+            ),
+        ]
+    );
+}
+
+/// Counts the requests and completions it is told about, by method
+#[derive(Default)]
+struct CountingByMethod {
+    requests: std::sync::Mutex<HashMap<String, usize>>,
+    completions: std::sync::Mutex<Vec<RequestOutcome>>,
+}
+
+#[async_trait]
+impl UsageTracker for CountingByMethod {
+    async fn track_request(
+        &self,
+        _headers: &HeaderMap,
+        _user: Option<&AuthenticatedUser>,
+        context: &RequestContext,
+    ) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry(context.method.clone())
+            .or_default() += 1;
+    }
+}
+
+#[async_trait]
+impl MethodDurationTracker for CountingByMethod {
+    async fn track_duration(
+        &self,
+        _context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: Duration,
+    ) {
+    }
+
+    async fn track_completion(
+        &self,
+        _context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: Duration,
+        outcome: RequestOutcome,
+    ) {
+        self.completions.lock().unwrap().push(outcome);
+    }
+}
+
+impl CountingByMethod {
+    fn total(&self) -> usize {
+        self.requests.lock().unwrap().values().sum()
+    }
+}
+
+#[tokio::test]
+async fn test_sampled_out_requests_never_reach_the_inner_tracker() {
+    let context = RequestContext::rest("GET", "/tasks");
+    let headers = HeaderMap::new();
+
+    let none = SampledUsageTracker::new(CountingByMethod::default(), SamplingPolicy::new(0.0));
+    for _ in 0..1000 {
+        none.track_request(&headers, None, &context).await;
+    }
+    assert_eq!(none.inner().total(), 0);
+
+    let all = SampledUsageTracker::new(CountingByMethod::default(), SamplingPolicy::always());
+    for _ in 0..1000 {
+        all.track_request(&headers, None, &context).await;
+    }
+    assert_eq!(all.inner().total(), 1000);
+
+    let some = SampledUsageTracker::new(CountingByMethod::default(), SamplingPolicy::new(0.1));
+    for _ in 0..10_000 {
+        some.track_request(&headers, None, &context).await;
+    }
+    let passed = some.inner().total();
+    assert!((700..1300).contains(&passed), "{passed} of 10000 sampled");
+}
+
+#[tokio::test]
+async fn test_sampling_caps_methods_and_keeps_failures() {
+    let headers = HeaderMap::new();
+    let tracker = SampledUsageTracker::new(
+        CountingByMethod::default(),
+        SamplingPolicy::new(0.0)
+            .with_always_on_error()
+            .with_method_rate_cap(5),
+    );
+    let context = RequestContext::jsonrpc("sync".to_string());
+    for outcome in [RequestOutcome::Success, RequestOutcome::HandlerError] {
+        for _ in 0..3 {
+            tracker
+                .track_completion(&context, None, Duration::from_millis(1), outcome)
+                .await;
+        }
+    }
+    assert_eq!(
+        *tracker.inner().completions.lock().unwrap(),
+        [RequestOutcome::HandlerError; 3]
+    );
+
+    let capped = SampledUsageTracker::new(
+        CountingByMethod::default(),
+        SamplingPolicy::always().with_method_rate_cap(5),
+    );
+    for method in ["a", "b"] {
+        for _ in 0..50 {
+            capped
+                .track_request(&headers, None, &RequestContext::jsonrpc(method.to_string()))
+                .await;
+        }
+    }
+    // Both fit in one second unless the test straddles a boundary
+    let total = capped.inner().total();
+    assert!((10..=20).contains(&total), "{total} passed");
+}
+
+#[tokio::test]
+async fn test_method_names_past_the_limit_are_reported_as_other() {
+    let tracker = SampledUsageTracker::new(
+        CountingByMethod::default(),
+        SamplingPolicy::always().with_max_methods(2),
+    );
+    for id in 0..10 {
+        tracker
+            .track_request(
+                &HeaderMap::new(),
+                None,
+                &RequestContext::rest("GET", &format!("/tasks/{}", id % 5)),
+            )
+            .await;
+    }
+    let requests = tracker.inner().requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[OTHER_LABEL], 6);
+    assert_eq!(requests.values().sum::<usize>(), 10);
+
+    let guard = CardinalityGuard::new(1);
+    assert_eq!(guard.label("first"), "first");
+    assert_eq!(guard.label("second"), OTHER_LABEL);
+    assert_eq!(guard.label("first"), "first");
+}
+
+#[tokio::test]
+async fn test_slow_requests_are_counted_against_their_threshold() {
+    let metrics = Arc::new(MockServiceMetrics::new());
+    let tracker = SlowRequestTracker::new(CountingByMethod::default(), Duration::from_millis(500))
+        .with_method_threshold("export", Duration::from_secs(5))
+        .with_service_metrics(metrics.clone());
+    assert_eq!(tracker.threshold_for("export"), Duration::from_secs(5));
+    assert_eq!(tracker.threshold_for("sync"), Duration::from_millis(500));
+
+    for (method, millis) in [
+        ("sync", 100),
+        ("sync", 500),
+        ("sync", 501),
+        ("export", 2_000),
+        ("export", 6_000),
+    ] {
+        tracker
+            .track_completion(
+                &RequestContext::jsonrpc(method.to_string()),
+                None,
+                Duration::from_millis(millis),
+                RequestOutcome::Success,
+            )
+            .await;
+    }
+
+    assert_eq!(*metrics.slow_requests.lock().await, ["sync", "export"]);
+    // Fast or slow, every request reaches the inner tracker
+    assert_eq!(tracker.inner().completions.lock().unwrap().len(), 5);
+}
+
+#[tokio::test]
+async fn test_slow_request_callback_gets_the_request() {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let tracker = SlowRequestTracker::new(CountingByMethod::default(), Duration::from_millis(200))
+        .on_slow_request(move |request| {
+            let sender = sender.clone();
+            async move {
+                sender.send(request).unwrap();
+            }
+        });
+    let user = AuthenticatedUser {
+        user_id: "alice".to_string(),
+        permissions: Default::default(),
+        metadata: None,
+        kind: PrincipalKind::User,
+    };
+    let mut context = RequestContext::rest("GET", "/reports");
+    context
+        .metadata
+        .insert("request_id".to_string(), "r-1".to_string());
+
+    tracker
+        .track_duration(&context, Some(&user), Duration::from_millis(150))
+        .await;
+    tracker
+        .track_completion(
+            &context,
+            Some(&user),
+            Duration::from_millis(900),
+            RequestOutcome::Timeout,
+        )
+        .await;
+
+    let request = tokio::time::timeout(Duration::from_secs(1), receiver.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(request.context.method, "GET /reports");
+    assert_eq!(request.context.metadata["request_id"], "r-1");
+    assert_eq!(request.user_id.as_deref(), Some("alice"));
+    assert_eq!(request.duration, Duration::from_millis(900));
+    assert_eq!(request.threshold, Duration::from_millis(200));
+    assert_eq!(request.outcome, Some(RequestOutcome::Timeout));
+    // The fast request wasn't reported
+    assert!(receiver.try_recv().is_err());
+}
+
 #[test]
 fn test_observability_builder_default() {
     let builder = ObservabilityBuilder::default();
@@ -318,6 +966,51 @@ fn test_observability_builder_default() {
     assert!(config.duration_tracker.is_none());
 }
 
+#[tokio::test]
+async fn test_observability_config_clones_share_their_trackers() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let config = ObservabilityConfig::from(ObservabilityBuilder::new().with_usage_tracker({
+        let calls = calls.clone();
+        move |_headers, _user, _context| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async {}
+        }
+    }));
+    assert!(config.shared_method_duration_tracker().is_none());
+    assert!(config.service_metrics.is_none());
+
+    let context = RequestContext::rest("GET", "/reports");
+    for config in [config.clone(), config] {
+        let tracker = config.shared_usage_tracker().unwrap();
+        tracker
+            .track_request(&HeaderMap::new(), None, &context)
+            .await;
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_observability_config_completion_trackers_are_told_the_outcome() {
+    let tracker = Arc::new(OutcomeTracker(Mutex::new(Vec::new())));
+    let config = ObservabilityConfig::default().with_completion_tracker(tracker.clone());
+
+    config
+        .shared_method_duration_tracker()
+        .unwrap()
+        .track_completion(
+            &RequestContext::jsonrpc("sync".to_string()),
+            None,
+            Duration::from_millis(3),
+            RequestOutcome::Timeout,
+        )
+        .await;
+
+    assert_eq!(
+        *tracker.0.lock().await,
+        [("sync".to_string(), RequestOutcome::Timeout)]
+    );
+}
+
 #[test]
 fn test_request_context_cloning() {
     let ctx = RequestContext::rest("PUT", "/api/resource").with_metadata("key", "value");
@@ -334,3 +1027,121 @@ fn test_protocol_equality() {
     assert_ne!(Protocol::Rest, Protocol::JsonRpc);
     assert_ne!(Protocol::JsonRpc, Protocol::WebSocket);
 }
+
+#[test]
+fn test_trace_context_parsing() {
+    let trace = TraceContext::parse(
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        Some("congo=t61rcWkgMzE"),
+    )
+    .unwrap();
+    assert_eq!(trace.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(trace.parent_id(), "00f067aa0ba902b7");
+    assert!(trace.is_sampled());
+    assert_eq!(trace.tracestate(), Some("congo=t61rcWkgMzE"));
+
+    // Wrong version, bad lengths, uppercase hex and all-zero ids are rejected
+    for invalid in [
+        "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+    ] {
+        assert!(TraceContext::parse(invalid, None).is_none(), "{invalid}");
+    }
+}
+
+#[test]
+fn test_request_context_with_trace_context() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        TRACEPARENT_HEADER,
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"
+            .parse()
+            .unwrap(),
+    );
+
+    let context = RequestContext::jsonrpc("getUser".to_string()).with_trace_context(&headers);
+    assert_eq!(
+        context.metadata.get("trace_id").map(String::as_str),
+        Some("4bf92f3577b34da6a3ce929d0e0e4736")
+    );
+    assert!(context.metadata.contains_key("traceparent"));
+    assert!(!context.metadata.contains_key("tracestate"));
+
+    // Requests without a trace context leave the metadata untouched
+    let context = RequestContext::rest("GET", "/users").with_trace_context(&HeaderMap::new());
+    assert!(context.metadata.is_empty());
+}
+
+#[test]
+fn test_client_ip_behind_trusted_proxies() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "x-forwarded-for",
+        "203.0.113.9, 198.51.100.7, 10.0.0.5".parse().unwrap(),
+    );
+    let peer = Some("10.0.0.1".parse().unwrap());
+
+    // Forwarded addresses are ignored unless the peer is a trusted proxy
+    assert_eq!(client_ip(&headers, peer, &TrustedProxies::none()), peer);
+
+    // Hops are walked back while trusted; 198.51.100.7 isn't
+    let trusted = TrustedProxies::new(["10.0.0.0/8"]).unwrap();
+    assert_eq!(
+        client_ip(&headers, peer, &trusted),
+        Some("198.51.100.7".parse().unwrap())
+    );
+    assert_eq!(
+        client_ip(&headers, Some("192.0.2.1".parse().unwrap()), &trusted),
+        Some("192.0.2.1".parse().unwrap())
+    );
+    assert_eq!(client_ip(&headers, None, &trusted), None);
+
+    assert_eq!(
+        client_ip(&headers, None, &TrustedProxies::any()),
+        Some("203.0.113.9".parse().unwrap())
+    );
+
+    // IPv4-mapped peers match IPv4 networks, and forwarded ports are dropped
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", "[2001:db8::1]:443".parse().unwrap());
+    let mapped = Some("::ffff:10.1.2.3".parse().unwrap());
+    assert_eq!(
+        client_ip(&headers, mapped, &trusted),
+        Some("2001:db8::1".parse().unwrap())
+    );
+
+    assert_eq!(
+        TrustedProxies::new(["10.0.0.0/33"]),
+        Err(InvalidProxyNetwork("10.0.0.0/33".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_request_context_with_request_info() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-request-id", "req-42".parse().unwrap());
+
+    let context = RequestContext::rest("GET", "/users/{id}").with_request_info(&headers);
+    assert_eq!(context.request_id.as_deref(), Some("req-42"));
+    assert_eq!(context.client_ip, None);
+
+    let origin = RequestOrigin::new(Some("192.0.2.1".parse().unwrap()), "/users/{id}");
+    let context = origin
+        .scope(async {
+            RequestContext::rest("GET", "/users/{id}").with_request_info(&HeaderMap::new())
+        })
+        .await;
+    assert_eq!(context.client_ip, Some("192.0.2.1".parse().unwrap()));
+    assert_eq!(context.route.as_deref(), Some("/users/{id}"));
+    assert_eq!(context.request_id, None);
+
+    // Unset fields are left out for log sinks
+    let json = serde_json::to_value(&context).unwrap();
+    assert_eq!(json["client_ip"], "192.0.2.1");
+    assert_eq!(json["protocol"], "Rest");
+    assert!(json.get("request_id").is_none());
+}
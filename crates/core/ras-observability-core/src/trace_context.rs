@@ -0,0 +1,91 @@
+//! W3C Trace Context (`traceparent` / `tracestate`) handling.
+
+use http::HeaderMap;
+
+/// Header carrying the W3C trace parent.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Header carrying vendor-specific W3C trace state.
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// Trace context received with a request.
+///
+/// Only the version 00 `traceparent` format
+/// (`00-<trace-id>-<parent-id>-<flags>`) is accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    traceparent: String,
+    tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// Parse a `traceparent` value and optional `tracestate`.
+    ///
+    /// Returns `None` if the `traceparent` is malformed or uses all-zero ids.
+    pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<Self> {
+        let traceparent = traceparent.trim();
+        let mut parts = traceparent.split('-');
+        let (version, trace_id, parent_id, flags) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+
+        let valid = parts.next().is_none()
+            && version == "00"
+            && is_lower_hex(trace_id, 32)
+            && is_lower_hex(parent_id, 16)
+            && is_lower_hex(flags, 2)
+            && trace_id.bytes().any(|b| b != b'0')
+            && parent_id.bytes().any(|b| b != b'0');
+        if !valid {
+            return None;
+        }
+
+        Some(Self {
+            traceparent: traceparent.to_string(),
+            tracestate: tracestate
+                .map(str::trim)
+                .filter(|state| !state.is_empty())
+                .map(str::to_string),
+        })
+    }
+
+    /// Read the trace context from request headers, if a valid one is present.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let traceparent = headers.get(TRACEPARENT_HEADER)?.to_str().ok()?;
+        let tracestate = headers
+            .get(TRACESTATE_HEADER)
+            .and_then(|value| value.to_str().ok());
+        Self::parse(traceparent, tracestate)
+    }
+
+    /// The full `traceparent` value.
+    pub fn traceparent(&self) -> &str {
+        &self.traceparent
+    }
+
+    /// The `tracestate` value, if one was sent.
+    pub fn tracestate(&self) -> Option<&str> {
+        self.tracestate.as_deref()
+    }
+
+    /// The 32 character hex trace id shared by every span in the trace.
+    pub fn trace_id(&self) -> &str {
+        &self.traceparent[3..35]
+    }
+
+    /// The 16 character hex id of the caller's span.
+    pub fn parent_id(&self) -> &str {
+        &self.traceparent[36..52]
+    }
+
+    /// Whether the caller sampled this trace.
+    pub fn is_sampled(&self) -> bool {
+        u8::from_str_radix(&self.traceparent[53..55], 16).is_ok_and(|flags| flags & 0x01 == 1)
+    }
+}
+
+fn is_lower_hex(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
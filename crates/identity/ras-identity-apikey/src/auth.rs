@@ -0,0 +1,71 @@
+//! Accepting API keys directly in services.
+
+use crate::{ApiKeyProvider, is_api_key};
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
+use ras_identity_core::IdentityError;
+use std::sync::Arc;
+
+/// An [`AuthProvider`] taking API keys in place of session tokens.
+///
+/// REST and JSON-RPC services pass it the credential of an
+/// `Authorization: ApiKey <key>` header. The user's permissions are the key's
+/// scopes. Other credentials, such as JWTs sent as `Bearer` tokens, go to the
+/// fallback provider if there is one, so a service can take both.
+#[derive(Clone)]
+pub struct ApiKeyAuthProvider {
+    keys: ApiKeyProvider,
+    fallback: Option<Arc<dyn AuthProvider>>,
+}
+
+impl ApiKeyAuthProvider {
+    pub fn new(keys: ApiKeyProvider) -> Self {
+        Self {
+            keys,
+            fallback: None,
+        }
+    }
+
+    /// Authenticate credentials that aren't API keys with `fallback`
+    pub fn with_fallback(mut self, fallback: Arc<dyn AuthProvider>) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+}
+
+impl AuthProvider for ApiKeyAuthProvider {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        Box::pin(async move {
+            if !is_api_key(&token) {
+                return match &self.fallback {
+                    Some(fallback) => fallback.authenticate(token).await,
+                    None => Err(AuthError::InvalidToken),
+                };
+            }
+
+            let key = self.keys.verify_key(&token).await.map_err(|e| match e {
+                IdentityError::InvalidCredentials => AuthError::InvalidToken,
+                other => AuthError::Internal(other.to_string()),
+            })?;
+            Ok(AuthenticatedUser {
+                user_id: key.owner.clone(),
+                permissions: key.scopes.iter().cloned().collect(),
+                metadata: Some(crate::key_metadata(&key)),
+                kind: PrincipalKind::User,
+            })
+        })
+    }
+
+    fn authenticate_request(&self, token: String, headers: &http::HeaderMap) -> AuthFuture<'_> {
+        match &self.fallback {
+            Some(fallback) if !is_api_key(&token) => fallback.authenticate_request(token, headers),
+            _ => self.authenticate(token),
+        }
+    }
+
+    fn request_binding(&self, token: &str, headers: &http::HeaderMap) -> Vec<u8> {
+        match &self.fallback {
+            Some(fallback) if !is_api_key(token) => fallback.request_binding(token, headers),
+            _ => Vec::new(),
+        }
+    }
+}
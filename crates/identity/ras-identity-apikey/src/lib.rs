@@ -0,0 +1,622 @@
+//! API key identity provider for machine-to-machine callers.
+
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use rand_core::{OsRng, RngCore};
+use ras_identity_core::{IdentityError, IdentityProvider, IdentityResult, VerifiedIdentity};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+mod auth;
+mod store;
+
+pub use auth::ApiKeyAuthProvider;
+pub use store::{ApiKeyStore, MemoryApiKeyStore};
+
+/// What every key id starts with, telling API keys apart from other credentials
+pub const KEY_ID_PREFIX: &str = "rak_";
+
+/// A stored API key, without its secret
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    /// The part of the key before the dot, used to look it up
+    pub id: String,
+    /// SHA-256 hash of the part of the key after the dot
+    pub secret_hash: String,
+    /// The subject the key authenticates as
+    pub owner: String,
+    pub label: String,
+    /// The permissions the key grants
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// The key that replaced this one when it was rotated
+    pub replaced_by: Option<String>,
+}
+
+/// A key as listed to its owner or administrators, without the secret hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub owner: String,
+    pub label: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub replaced_by: Option<String>,
+}
+
+impl From<ApiKeyRecord> for ApiKeyInfo {
+    fn from(record: ApiKeyRecord) -> Self {
+        Self {
+            id: record.id,
+            owner: record.owner,
+            label: record.label,
+            scopes: record.scopes,
+            created_at: record.created_at,
+            expires_at: record.expires_at,
+            revoked_at: record.revoked_at,
+            replaced_by: record.replaced_by,
+        }
+    }
+}
+
+impl ApiKeyInfo {
+    /// Whether the key authenticates at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+/// The key to create
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewApiKey {
+    pub owner: String,
+    pub label: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A newly created key. `key` is shown to its owner once and can't be read back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreatedApiKey {
+    /// The whole key, `<id>.<secret>`
+    pub key: String,
+    pub info: ApiKeyInfo,
+}
+
+/// The payload [`ApiKeyProvider`] verifies as an [`IdentityProvider`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyPayload {
+    pub api_key: String,
+}
+
+/// Issues, verifies and revokes API keys.
+///
+/// Keys look like `rak_1f2e3d4c5b6a7980.<secret>`: the id before the dot finds
+/// the stored key, and the secret after it is compared against the stored hash
+/// in constant time.
+#[derive(Clone)]
+pub struct ApiKeyProvider {
+    store: Arc<dyn ApiKeyStore>,
+}
+
+impl ApiKeyProvider {
+    /// Issue and verify the keys kept in `store`.
+    pub fn new(store: Arc<dyn ApiKeyStore>) -> Self {
+        Self { store }
+    }
+
+    /// The store the provider keeps its keys in
+    pub fn store(&self) -> &Arc<dyn ApiKeyStore> {
+        &self.store
+    }
+
+    /// Create a key, returning it with the only copy of its secret.
+    pub async fn create_key(&self, new_key: NewApiKey) -> IdentityResult<CreatedApiKey> {
+        let (key, record) = new_record(new_key, Utc::now());
+        self.store.insert(record.clone()).await?;
+        Ok(CreatedApiKey {
+            key,
+            info: record.into(),
+        })
+    }
+
+    /// The key `key` is, if it is known, matches and is active.
+    ///
+    /// Fails with `InvalidCredentials` otherwise, whatever the reason.
+    pub async fn verify_key(&self, key: &str) -> IdentityResult<ApiKeyInfo> {
+        let (id, secret) = key
+            .split_once('.')
+            .filter(|(id, _)| id.starts_with(KEY_ID_PREFIX))
+            .ok_or(IdentityError::InvalidCredentials)?;
+        let record = self.store.get(id).await?;
+
+        // Compare against a hash even for unknown ids, so they take as long
+        let stored_hash = record
+            .as_ref()
+            .map_or(&[0u8; 64][..], |record| record.secret_hash.as_bytes());
+        let matches = bool::from(hash_secret(secret).as_bytes().ct_eq(stored_hash));
+
+        match record.map(ApiKeyInfo::from) {
+            Some(info) if matches && info.is_active(Utc::now()) => Ok(info),
+            _ => Err(IdentityError::InvalidCredentials),
+        }
+    }
+
+    /// Revoke the key with `id`, returning `false` if there is no such key.
+    ///
+    /// Revoking a key again keeps the time of the first revocation.
+    pub async fn revoke_key(&self, id: &str) -> IdentityResult<bool> {
+        let Some(mut record) = self.store.get(id).await? else {
+            return Ok(false);
+        };
+        record.revoked_at.get_or_insert_with(Utc::now);
+        self.store.update(record).await
+    }
+
+    /// Replace the key with `id` by a new one with the same owner, label,
+    /// scopes and expiry.
+    ///
+    /// The old key keeps working for `overlap`, so callers can switch over,
+    /// and then expires. Returns `None` if there is no such key or it is no
+    /// longer active.
+    pub async fn rotate_key(
+        &self,
+        id: &str,
+        overlap: chrono::Duration,
+    ) -> IdentityResult<Option<CreatedApiKey>> {
+        let now = Utc::now();
+        let Some(mut old) = self.store.get(id).await? else {
+            return Ok(None);
+        };
+        if !ApiKeyInfo::from(old.clone()).is_active(now) {
+            return Ok(None);
+        }
+
+        let (key, record) = new_record(
+            NewApiKey {
+                owner: old.owner.clone(),
+                label: old.label.clone(),
+                scopes: old.scopes.clone(),
+                expires_at: old.expires_at,
+            },
+            now,
+        );
+        self.store.insert(record.clone()).await?;
+
+        let overlap_end = now + overlap;
+        old.expires_at = Some(old.expires_at.map_or(overlap_end, |at| at.min(overlap_end)));
+        old.replaced_by = Some(record.id.clone());
+        self.store.update(old).await?;
+
+        Ok(Some(CreatedApiKey {
+            key,
+            info: record.into(),
+        }))
+    }
+
+    /// The keys owned by `owner`, or all keys with `None`, ordered by id.
+    ///
+    /// Revoked and expired keys are listed too.
+    pub async fn list_keys(&self, owner: Option<&str>) -> IdentityResult<Vec<ApiKeyInfo>> {
+        Ok(self
+            .store
+            .list(owner)
+            .await?
+            .into_iter()
+            .map(ApiKeyInfo::from)
+            .collect())
+    }
+}
+
+impl Default for ApiKeyProvider {
+    /// A provider keeping its keys in memory
+    fn default() -> Self {
+        Self::new(Arc::new(MemoryApiKeyStore::new()))
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for ApiKeyProvider {
+    fn provider_id(&self) -> &str {
+        "api_key"
+    }
+
+    async fn verify(&self, auth_payload: serde_json::Value) -> IdentityResult<VerifiedIdentity> {
+        let payload: ApiKeyPayload =
+            serde_json::from_value(auth_payload).map_err(|_| IdentityError::InvalidPayload)?;
+        let key = self.verify_key(&payload.api_key).await?;
+
+        Ok(VerifiedIdentity {
+            provider_id: self.provider_id().to_string(),
+            subject: key.owner.clone(),
+            email: None,
+            display_name: None,
+            metadata: Some(key_metadata(&key)),
+        })
+    }
+}
+
+/// The scopes of the API key `identity` was verified with, if it was.
+///
+/// For [`UserPermissions`](ras_identity_core::UserPermissions) implementations
+/// granting sessions started with a key no more than the key allows.
+pub fn api_key_scopes(identity: &VerifiedIdentity) -> Option<Vec<String>> {
+    let metadata = identity.metadata.as_ref()?;
+    metadata.get("api_key_id")?;
+    let scopes = metadata.get("scopes")?.as_array()?;
+    Some(
+        scopes
+            .iter()
+            .filter_map(|scope| scope.as_str().map(str::to_string))
+            .collect(),
+    )
+}
+
+/// Whether `credential` looks like an API key rather than another kind of token
+pub(crate) fn is_api_key(credential: &str) -> bool {
+    credential.starts_with(KEY_ID_PREFIX) && credential.contains('.')
+}
+
+/// The metadata identities and users authenticated with `key` carry
+pub(crate) fn key_metadata(key: &ApiKeyInfo) -> serde_json::Value {
+    serde_json::json!({
+        "api_key_id": key.id,
+        "label": key.label,
+        "scopes": key.scopes,
+    })
+}
+
+/// A new key and the record to store for it
+fn new_record(new_key: NewApiKey, now: DateTime<Utc>) -> (String, ApiKeyRecord) {
+    let mut id = [0u8; 8];
+    OsRng.fill_bytes(&mut id);
+    let id: String = id.iter().map(|b| format!("{:02x}", b)).collect();
+    let id = format!("{}{}", KEY_ID_PREFIX, id);
+
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let secret = URL_SAFE_NO_PAD.encode(secret);
+
+    let record = ApiKeyRecord {
+        id: id.clone(),
+        secret_hash: hash_secret(&secret),
+        owner: new_key.owner,
+        label: new_key.label,
+        scopes: new_key.scopes,
+        created_at: now,
+        expires_at: new_key.expires_at,
+        revoked_at: None,
+        replaced_by: None,
+    };
+    (format!("{}.{}", id, secret), record)
+}
+
+fn hash_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
+
+    fn new_key(owner: &str, scopes: &[&str]) -> NewApiKey {
+        NewApiKey {
+            owner: owner.to_string(),
+            label: "ci deploys".to_string(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            expires_at: None,
+        }
+    }
+
+    fn assert_invalid(result: IdentityResult<ApiKeyInfo>) {
+        assert!(
+            matches!(result, Err(IdentityError::InvalidCredentials)),
+            "Expected InvalidCredentials, got: {:?}",
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_and_verify_key() {
+        let provider = ApiKeyProvider::default();
+        let created = provider
+            .create_key(new_key("svc-builder", &["deploy"]))
+            .await
+            .unwrap();
+
+        let (id, secret) = created.key.split_once('.').unwrap();
+        assert_eq!(id, created.info.id);
+        assert!(id.starts_with(KEY_ID_PREFIX));
+        assert_eq!(secret.len(), 43);
+
+        let verified = provider.verify_key(&created.key).await.unwrap();
+        assert_eq!(verified, created.info);
+
+        // Only a hash of the secret is stored
+        let stored = provider.store().get(id).await.unwrap().unwrap();
+        assert_ne!(stored.secret_hash, secret);
+        assert!(!stored.secret_hash.contains(secret));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_and_malformed_keys_are_refused() {
+        let provider = ApiKeyProvider::default();
+        let created = provider
+            .create_key(new_key("svc-builder", &["deploy"]))
+            .await
+            .unwrap();
+        let (id, _) = created.key.split_once('.').unwrap();
+
+        assert_invalid(provider.verify_key(&format!("{}.wrong", id)).await);
+        assert_invalid(provider.verify_key("rak_0000000000000000.anything").await);
+        assert_invalid(provider.verify_key(id).await);
+        assert_invalid(provider.verify_key("").await);
+        // Another key's id with this key's secret
+        let other = provider
+            .create_key(new_key("svc-other", &[]))
+            .await
+            .unwrap();
+        let (other_id, _) = other.key.split_once('.').unwrap();
+        let (_, secret) = created.key.split_once('.').unwrap();
+        assert_invalid(
+            provider
+                .verify_key(&format!("{}.{}", other_id, secret))
+                .await,
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revoked_and_expired_keys_are_refused() {
+        let provider = ApiKeyProvider::default();
+        let created = provider
+            .create_key(new_key("svc-builder", &["deploy"]))
+            .await
+            .unwrap();
+
+        assert!(provider.revoke_key(&created.info.id).await.unwrap());
+        assert_invalid(provider.verify_key(&created.key).await);
+        let revoked_at = provider.list_keys(None).await.unwrap()[0].revoked_at;
+        assert!(revoked_at.is_some());
+        assert!(provider.revoke_key(&created.info.id).await.unwrap());
+        assert_eq!(
+            provider.list_keys(None).await.unwrap()[0].revoked_at,
+            revoked_at
+        );
+        assert!(!provider.revoke_key("rak_0000000000000000").await.unwrap());
+
+        let expired = provider
+            .create_key(NewApiKey {
+                expires_at: Some(Utc::now() - chrono::Duration::seconds(1)),
+                ..new_key("svc-builder", &["deploy"])
+            })
+            .await
+            .unwrap();
+        assert_invalid(provider.verify_key(&expired.key).await);
+    }
+
+    #[tokio::test]
+    async fn test_list_keys() {
+        let provider = ApiKeyProvider::default();
+        let first = provider
+            .create_key(new_key("svc-a", &["read"]))
+            .await
+            .unwrap();
+        let second = provider
+            .create_key(new_key("svc-a", &["write"]))
+            .await
+            .unwrap();
+        provider
+            .create_key(new_key("svc-b", &["read"]))
+            .await
+            .unwrap();
+
+        let mut expected = vec![first.info, second.info];
+        expected.sort_by(|a, b| a.id.cmp(&b.id));
+        assert_eq!(provider.list_keys(Some("svc-a")).await.unwrap(), expected);
+        assert_eq!(provider.list_keys(None).await.unwrap().len(), 3);
+        assert!(provider.list_keys(Some("nobody")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rotate_key() {
+        let provider = ApiKeyProvider::default();
+        let old = provider
+            .create_key(new_key("svc-builder", &["deploy"]))
+            .await
+            .unwrap();
+
+        let new = provider
+            .rotate_key(&old.info.id, chrono::Duration::hours(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(new.info.id, old.info.id);
+        assert_eq!(new.info.owner, "svc-builder");
+        assert_eq!(new.info.scopes, ["deploy"]);
+
+        // Both keys work during the overlap
+        assert!(provider.verify_key(&new.key).await.is_ok());
+        let old_info = provider.verify_key(&old.key).await.unwrap();
+        assert_eq!(old_info.replaced_by.as_ref(), Some(&new.info.id));
+        let expires_at = old_info.expires_at.unwrap();
+        assert!(expires_at > Utc::now() + chrono::Duration::minutes(59));
+        assert!(expires_at <= Utc::now() + chrono::Duration::hours(1));
+
+        // Without an overlap the old key stops working at once
+        let newer = provider
+            .rotate_key(&new.info.id, chrono::Duration::zero())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_invalid(provider.verify_key(&new.key).await);
+        assert!(provider.verify_key(&newer.key).await.is_ok());
+
+        // Inactive and unknown keys can't be rotated
+        assert_eq!(
+            provider
+                .rotate_key(&new.info.id, chrono::Duration::hours(1))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            provider
+                .rotate_key("rak_0000000000000000", chrono::Duration::hours(1))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_an_earlier_expiry() {
+        let provider = ApiKeyProvider::default();
+        let expires_at = Utc::now() + chrono::Duration::minutes(5);
+        let old = provider
+            .create_key(NewApiKey {
+                expires_at: Some(expires_at),
+                ..new_key("svc-builder", &["deploy"])
+            })
+            .await
+            .unwrap();
+
+        let new = provider
+            .rotate_key(&old.info.id, chrono::Duration::days(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(new.info.expires_at, Some(expires_at));
+        let old_info = provider.verify_key(&old.key).await.unwrap();
+        assert_eq!(old_info.expires_at, Some(expires_at));
+    }
+
+    #[tokio::test]
+    async fn test_identity_provider() {
+        let provider = ApiKeyProvider::default();
+        let created = provider
+            .create_key(new_key("svc-builder", &["deploy", "read"]))
+            .await
+            .unwrap();
+
+        let identity = provider
+            .verify(serde_json::json!({ "api_key": created.key }))
+            .await
+            .unwrap();
+        assert_eq!(identity.provider_id, "api_key");
+        assert_eq!(identity.subject, "svc-builder");
+        assert_eq!(
+            api_key_scopes(&identity),
+            Some(vec!["deploy".to_string(), "read".to_string()])
+        );
+
+        assert!(matches!(
+            provider
+                .verify(serde_json::json!({ "api_key": "rak_0000000000000000.x" }))
+                .await,
+            Err(IdentityError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            provider.verify(serde_json::json!({ "key": "x" })).await,
+            Err(IdentityError::InvalidPayload)
+        ));
+
+        let other = VerifiedIdentity {
+            provider_id: "local".to_string(),
+            metadata: Some(serde_json::json!({ "scopes": ["admin"] })),
+            ..identity
+        };
+        assert_eq!(api_key_scopes(&other), None);
+    }
+
+    struct FixedUser;
+
+    impl AuthProvider for FixedUser {
+        fn authenticate(&self, token: String) -> AuthFuture<'_> {
+            Box::pin(async move {
+                if token == "session-token" {
+                    Ok(AuthenticatedUser {
+                        user_id: "alice".to_string(),
+                        permissions: ["admin".to_string()].into(),
+                        metadata: None,
+                        kind: PrincipalKind::User,
+                    })
+                } else {
+                    Err(AuthError::InvalidToken)
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_provider_maps_scopes_to_permissions() {
+        let keys = ApiKeyProvider::default();
+        let created = keys
+            .create_key(new_key("svc-builder", &["deploy", "read"]))
+            .await
+            .unwrap();
+        let auth = ApiKeyAuthProvider::new(keys.clone());
+
+        let user = auth.authenticate(created.key.clone()).await.unwrap();
+        assert_eq!(user.user_id, "svc-builder");
+        assert_eq!(
+            user.permissions,
+            ["deploy".to_string(), "read".to_string()].into()
+        );
+        assert_eq!(
+            user.metadata.as_ref().unwrap()["api_key_id"],
+            created.info.id
+        );
+        assert!(
+            auth.check_permissions(&user, &["deploy".to_string()])
+                .is_ok()
+        );
+        assert!(
+            auth.check_permissions(&user, &["admin".to_string()])
+                .is_err()
+        );
+
+        keys.revoke_key(&created.info.id).await.unwrap();
+        assert!(matches!(
+            auth.authenticate(created.key).await,
+            Err(AuthError::InvalidToken)
+        ));
+        assert!(matches!(
+            auth.authenticate("session-token".to_string()).await,
+            Err(AuthError::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_auth_provider_falls_back_for_other_tokens() {
+        let keys = ApiKeyProvider::default();
+        let created = keys
+            .create_key(new_key("svc-builder", &["deploy"]))
+            .await
+            .unwrap();
+        let auth = ApiKeyAuthProvider::new(keys).with_fallback(Arc::new(FixedUser));
+
+        let user = auth
+            .authenticate("session-token".to_string())
+            .await
+            .unwrap();
+        assert_eq!(user.user_id, "alice");
+        let user = auth.authenticate(created.key).await.unwrap();
+        assert_eq!(user.user_id, "svc-builder");
+
+        // Keys that fail aren't handed to the fallback
+        assert!(matches!(
+            auth.authenticate("rak_0000000000000000.x".to_string())
+                .await,
+            Err(AuthError::InvalidToken)
+        ));
+    }
+}
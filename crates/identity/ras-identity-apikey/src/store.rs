@@ -0,0 +1,73 @@
+//! Where API keys are kept.
+
+use crate::ApiKeyRecord;
+use async_trait::async_trait;
+use ras_identity_core::IdentityResult;
+use std::collections::BTreeMap;
+use tokio::sync::RwLock;
+
+/// Storage for the keys of an [`ApiKeyProvider`](crate::ApiKeyProvider).
+///
+/// Keys are kept by their id, the part of the key before the dot. Only a hash
+/// of the secret part is stored. Implementations report storage failures as
+/// `IdentityError::ProviderError`.
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    /// The key with `id`, if any.
+    async fn get(&self, id: &str) -> IdentityResult<Option<ApiKeyRecord>>;
+
+    /// Add `record`, replacing any key with the same id.
+    async fn insert(&self, record: ApiKeyRecord) -> IdentityResult<()>;
+
+    /// Replace the stored key with `record`'s id, returning `false` if there is none.
+    async fn update(&self, record: ApiKeyRecord) -> IdentityResult<bool>;
+
+    /// The keys owned by `owner`, or all keys with `None`, ordered by id.
+    async fn list(&self, owner: Option<&str>) -> IdentityResult<Vec<ApiKeyRecord>>;
+}
+
+/// Keeps keys in memory, losing them when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryApiKeyStore {
+    keys: RwLock<BTreeMap<String, ApiKeyRecord>>,
+}
+
+impl MemoryApiKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for MemoryApiKeyStore {
+    async fn get(&self, id: &str) -> IdentityResult<Option<ApiKeyRecord>> {
+        Ok(self.keys.read().await.get(id).cloned())
+    }
+
+    async fn insert(&self, record: ApiKeyRecord) -> IdentityResult<()> {
+        self.keys.write().await.insert(record.id.clone(), record);
+        Ok(())
+    }
+
+    async fn update(&self, record: ApiKeyRecord) -> IdentityResult<bool> {
+        let mut keys = self.keys.write().await;
+        match keys.get_mut(&record.id) {
+            Some(stored) => {
+                *stored = record;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn list(&self, owner: Option<&str>) -> IdentityResult<Vec<ApiKeyRecord>> {
+        Ok(self
+            .keys
+            .read()
+            .await
+            .values()
+            .filter(|record| owner.is_none_or(|owner| record.owner == owner))
+            .cloned()
+            .collect())
+    }
+}
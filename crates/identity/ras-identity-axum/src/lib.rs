@@ -0,0 +1,432 @@
+//! Axum routes signing users in and out of a [`SessionService`].
+
+use axum::extract::{ConnectInfo, FromRequestParts, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router, middleware};
+use ras_auth_core::AuthProvider;
+use ras_identity_core::AuthSource;
+use ras_identity_session::{
+    BindingInfo, CookieAuthProvider, CookieConfig, JwtAuthProvider, JwtClaims, SessionError,
+    SessionService, TokenPair, clear_session_cookie, csrf_protection, issue_session_cookie,
+};
+use ras_observability_core::{TrustedProxies, client_ip};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+mod openapi;
+
+pub use openapi::merge_session_openapi;
+
+/// Body of `POST /auth/login`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SignInRequest {
+    /// The identity provider verifying `payload`, such as `local`
+    pub provider_id: String,
+    /// What the provider verifies, such as `{"username": ..., "password": ...}`
+    pub payload: serde_json::Value,
+}
+
+/// Body of `POST /auth/logout`, which may be left out
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SignOutRequest {
+    /// Revoked along with the refresh tokens it was rotated from and into
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// Body of `POST /auth/refresh`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RefreshSessionRequest {
+    pub refresh_token: String,
+}
+
+/// A session begun by `POST /auth/login` or renewed by `POST /auth/refresh`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SessionTokens {
+    /// The session token, unless it was set in a cookie
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// When the session token expires, in seconds since the Unix epoch
+    pub expires_at: i64,
+    /// For `POST /auth/refresh`, when the session service issues refresh tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_at: Option<i64>,
+    /// The token requests presenting the session cookie repeat in the CSRF
+    /// header, when the session token was set in a cookie
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
+}
+
+impl From<TokenPair> for SessionTokens {
+    fn from(pair: TokenPair) -> Self {
+        Self {
+            token: Some(pair.access_token),
+            expires_at: pair.expires_at,
+            refresh_token: Some(pair.refresh_token),
+            refresh_expires_at: Some(pair.refresh_expires_at),
+            csrf_token: None,
+        }
+    }
+}
+
+/// The claims of the caller's session, as `GET /auth/me` echoes them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SessionInfo {
+    pub sub: String,
+    pub provider_id: String,
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    /// In order
+    pub permissions: Vec<String>,
+    pub metadata: Option<serde_json::Value>,
+    /// How the user authenticated, such as `["pwd", "otp", "mfa"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<String>,
+    pub iat: i64,
+    pub exp: i64,
+    /// The session id
+    pub jti: String,
+    /// Claims added by the session service's `ClaimsAugmenter`
+    #[serde(flatten)]
+    pub custom: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<JwtClaims> for SessionInfo {
+    fn from(claims: JwtClaims) -> Self {
+        let mut permissions: Vec<String> = claims.permissions.into_iter().collect();
+        permissions.sort();
+        Self {
+            sub: claims.sub,
+            provider_id: claims.provider_id,
+            email: claims.email,
+            display_name: claims.display_name,
+            permissions,
+            metadata: claims.metadata,
+            amr: claims.amr,
+            iat: claims.iat,
+            exp: claims.exp,
+            jti: claims.jti,
+            custom: claims.custom,
+        }
+    }
+}
+
+/// Body of the routes' error responses
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SessionErrorResponse {
+    pub error: String,
+}
+
+/// Serve sign-in, sign-out, refresh and current session routes for the
+/// sessions of `session_service`, answering with session tokens that callers
+/// send in `Authorization: Bearer` headers:
+///
+/// - `POST /auth/login` verifies a [`SignInRequest`] with its provider and
+///   answers with [`SessionTokens`], including a refresh token when the
+///   service's `refresh_enabled` is set
+/// - `POST /auth/logout` ends the caller's session, and revokes the refresh
+///   token of a [`SignOutRequest`] body
+/// - `POST /auth/refresh` exchanges a [`RefreshSessionRequest`] for new
+///   [`SessionTokens`]
+/// - `GET /auth/me` answers with the [`SessionInfo`] of the caller's session
+///
+/// Sessions are bound to the client logging in when the service's `binding`
+/// policy checks any part of it. The client's address is that of the peer
+/// that connected, when served with connect info, or the client behind it
+/// when it is one of the proxies of a [`TrustedProxies`] extension, such as
+/// added with `.layer(Extension(trusted_proxies))`. Merge the router into a
+/// service's, and document the routes with [`merge_session_openapi`].
+pub fn session_routes(session_service: Arc<SessionService>) -> Router {
+    let credentials = JwtAuthProvider::new(session_service.clone());
+    SessionRoutes {
+        session_service,
+        credentials: Arc::new(credentials),
+        cookies: None,
+    }
+    .router()
+}
+
+/// [`session_routes`] setting the session token in the cookies of `config`
+/// rather than answering with it, for browser clients.
+///
+/// Sign-in and refresh answer with the CSRF token in place of the session
+/// token. Requests presenting the session cookie must repeat it in the CSRF
+/// header, as checked by [`csrf_protection`]; requests with an
+/// `Authorization` header work as with [`session_routes`]. Sign-out clears
+/// the cookies.
+pub fn session_routes_with_cookies(
+    session_service: Arc<SessionService>,
+    config: CookieConfig,
+) -> Router {
+    let credentials = CookieAuthProvider::new(
+        JwtAuthProvider::new(session_service.clone()),
+        config.clone(),
+    );
+    let cookies = Arc::new(config);
+    SessionRoutes {
+        session_service,
+        credentials: Arc::new(credentials),
+        cookies: Some(cookies.clone()),
+    }
+    .router()
+    .layer(middleware::from_fn_with_state(cookies, csrf_protection))
+}
+
+#[derive(Clone)]
+struct SessionRoutes {
+    session_service: Arc<SessionService>,
+    /// Finds the session token of requests
+    credentials: Arc<dyn AuthProvider>,
+    /// Where browsers keep the session token, if in cookies
+    cookies: Option<Arc<CookieConfig>>,
+}
+
+impl SessionRoutes {
+    fn router(self) -> Router {
+        Router::new()
+            .route("/auth/login", post(sign_in))
+            .route("/auth/logout", post(sign_out))
+            .route("/auth/refresh", post(refresh))
+            .route("/auth/me", get(current_session))
+            .with_state(self)
+    }
+
+    /// The claims of the session of a request with `headers` from
+    /// `client_ip`, checked against its client if sessions are bound
+    async fn verify(
+        &self,
+        headers: &HeaderMap,
+        client_ip: Option<IpAddr>,
+    ) -> Result<JwtClaims, SessionError> {
+        let token = self
+            .credentials
+            .credential(headers)
+            .ok_or(SessionError::InvalidSession)?;
+        if self.session_service.config().binding.is_off() {
+            self.session_service.verify_session(&token).await
+        } else {
+            let binding = BindingInfo::from_request(headers, client_ip);
+            self.session_service
+                .verify_bound_session(&token, &binding)
+                .await
+        }
+    }
+
+    /// Answer with `tokens`, moving the session token into a cookie if
+    /// sessions are kept in cookies
+    fn issue(&self, mut tokens: SessionTokens) -> Response {
+        let no_store = [(header::CACHE_CONTROL, "no-store")];
+        let Some(config) = &self.cookies else {
+            return (no_store, Json(tokens)).into_response();
+        };
+
+        let token = tokens.token.take().unwrap_or_default();
+        let mut cookies = Response::new(());
+        match issue_session_cookie(&mut cookies, &token, config) {
+            Ok(csrf_token) => tokens.csrf_token = Some(csrf_token),
+            Err(error) => return error_response(error),
+        }
+        let (cookies, ()) = cookies.into_parts();
+        (cookies.headers, no_store, Json(tokens)).into_response()
+    }
+}
+
+/// The address of the client of a request: the peer that connected, when
+/// served with connect info, or the client behind it when it is one of the
+/// proxies of a [`TrustedProxies`] extension
+struct ClientIp(Option<IpAddr>);
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Infallible> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        Ok(Self(match parts.extensions.get::<TrustedProxies>() {
+            Some(trusted) => client_ip(&parts.headers, peer, trusted),
+            None => peer,
+        }))
+    }
+}
+
+async fn sign_in(
+    State(routes): State<SessionRoutes>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Json(request): Json<SignInRequest>,
+) -> Response {
+    // Limits attempts per address, when the client's address is known
+    let source = AuthSource {
+        ip,
+        user_agent: headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    };
+    let provider_id = request.provider_id.clone();
+    match begin_session(&routes.session_service, request, source, &headers).await {
+        Ok(tokens) => routes.issue(tokens),
+        Err(error) => {
+            tracing::debug!(provider = %provider_id, %error, "sign-in failed");
+            error_response(error)
+        }
+    }
+}
+
+/// Begin the session `request` asks for, bound to the client if sessions are
+/// bound, and with a refresh token if the service issues them
+async fn begin_session(
+    session_service: &SessionService,
+    request: SignInRequest,
+    source: AuthSource,
+    headers: &HeaderMap,
+) -> Result<SessionTokens, SessionError> {
+    let config = session_service.config();
+    if config.refresh_enabled {
+        let pair = if config.binding.is_off() {
+            session_service
+                .begin_session_with_refresh(&request.provider_id, request.payload, Some(source))
+                .await?
+        } else {
+            let binding = BindingInfo::from_request(headers, source.ip);
+            session_service
+                .begin_bound_session_with_refresh(
+                    &request.provider_id,
+                    request.payload,
+                    Some(source),
+                    binding,
+                )
+                .await?
+        };
+        return Ok(SessionTokens::from(pair));
+    }
+
+    let token = if config.binding.is_off() {
+        session_service
+            .begin_session_from(&request.provider_id, request.payload, source)
+            .await?
+    } else {
+        let binding = BindingInfo::from_request(headers, source.ip);
+        session_service
+            .begin_bound_session(&request.provider_id, request.payload, Some(source), binding)
+            .await?
+    };
+    let claims = session_service.verify_session(&token).await?;
+    Ok(SessionTokens {
+        token: Some(token),
+        expires_at: claims.exp,
+        refresh_token: None,
+        refresh_expires_at: None,
+        csrf_token: None,
+    })
+}
+
+async fn sign_out(
+    State(routes): State<SessionRoutes>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    request: Option<Json<SignOutRequest>>,
+) -> Response {
+    let claims = match routes.verify(&headers, ip).await {
+        Ok(claims) => claims,
+        Err(error) => return error_response(error),
+    };
+    if let Err(error) = routes.session_service.end_session(&claims.jti).await {
+        return error_response(error);
+    }
+    if let Some(Json(SignOutRequest {
+        refresh_token: Some(refresh_token),
+    })) = request
+        && let Err(error) = routes.session_service.revoke_refresh(&refresh_token).await
+    {
+        return error_response(error);
+    }
+
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    if let Some(config) = &routes.cookies
+        && let Err(error) = clear_session_cookie(&mut response, config)
+    {
+        return error_response(error);
+    }
+    response
+}
+
+async fn refresh(
+    State(routes): State<SessionRoutes>,
+    Json(request): Json<RefreshSessionRequest>,
+) -> Response {
+    match routes.session_service.refresh(&request.refresh_token).await {
+        Ok(pair) => routes.issue(pair.into()),
+        Err(error) => error_response(error),
+    }
+}
+
+async fn current_session(
+    State(routes): State<SessionRoutes>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> Response {
+    match routes.verify(&headers, ip).await {
+        Ok(claims) => (
+            [(header::CACHE_CONTROL, "no-store")],
+            Json(SessionInfo::from(claims)),
+        )
+            .into_response(),
+        Err(error) => error_response(error),
+    }
+}
+
+/// Answer a failed request, without saying more about credentials than that
+/// they were refused
+fn error_response(error: SessionError) -> Response {
+    let (status, message) = match &error {
+        SessionError::RateLimited { retry_after } => {
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, seconds.to_string())],
+                Json(SessionErrorResponse {
+                    error: error.to_string(),
+                }),
+            )
+                .into_response();
+        }
+        SessionError::IdentityError(_) => (StatusCode::UNAUTHORIZED, "Invalid credentials"),
+        SessionError::RefreshTokenReused => (StatusCode::UNAUTHORIZED, "Refresh token reused"),
+        SessionError::JwtError(_)
+        | SessionError::SessionNotFound
+        | SessionError::InvalidSession
+        | SessionError::BindingMismatch(_) => {
+            (StatusCode::UNAUTHORIZED, "Invalid or expired session")
+        }
+        SessionError::RefreshDisabled => (StatusCode::BAD_REQUEST, "Refresh tokens are disabled"),
+        SessionError::StoreError(_) => {
+            tracing::warn!(%error, "session request failed");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Sessions are temporarily unavailable",
+            )
+        }
+        _ => {
+            tracing::warn!(%error, "session request failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    };
+    (
+        status,
+        Json(SessionErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+        .into_response()
+}
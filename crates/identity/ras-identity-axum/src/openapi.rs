@@ -0,0 +1,179 @@
+//! Documenting the session routes in a service's OpenAPI document.
+
+use crate::{
+    RefreshSessionRequest, SessionErrorResponse, SessionInfo, SessionTokens, SignInRequest,
+    SignOutRequest,
+};
+use schemars::JsonSchema;
+use serde_json::{Map, Value, json};
+
+/// Add the routes of [`session_routes`](crate::session_routes), and the
+/// schemas of their bodies, to the OpenAPI `document`, such as the one a
+/// `rest_service!` generates with `generate_<service>_openapi()`.
+///
+/// Routes and schemas already in the document are replaced, and the
+/// `bearerAuth` security scheme is added unless it has one.
+pub fn merge_session_openapi(document: &mut Value) {
+    let Some(document) = document.as_object_mut() else {
+        return;
+    };
+
+    let paths = object(document, "paths");
+    let operations = [
+        Operation {
+            method: "post",
+            path: "/auth/login",
+            summary: "Sign in",
+            description: "Verifies the payload with the identity provider and begins a session",
+            request: Some(("SignInRequest", true)),
+            response: Some("SessionTokens"),
+            authenticated: false,
+        },
+        Operation {
+            method: "post",
+            path: "/auth/logout",
+            summary: "Sign out",
+            description: "Ends the caller's session, and revokes the refresh token if given",
+            request: Some(("SignOutRequest", false)),
+            response: None,
+            authenticated: true,
+        },
+        Operation {
+            method: "post",
+            path: "/auth/refresh",
+            summary: "Refresh the session",
+            description: "Exchanges a refresh token for a new session token and refresh token",
+            request: Some(("RefreshSessionRequest", true)),
+            response: Some("SessionTokens"),
+            authenticated: false,
+        },
+        Operation {
+            method: "get",
+            path: "/auth/me",
+            summary: "Current session",
+            description: "The claims of the caller's session",
+            request: None,
+            response: Some("SessionInfo"),
+            authenticated: true,
+        },
+    ];
+    for operation in operations {
+        paths.insert(
+            operation.path.to_string(),
+            json!({ operation.method: operation.to_json() }),
+        );
+    }
+
+    let components = object(document, "components");
+    let schemas = object(components, "schemas");
+    schemas.insert("SignInRequest".to_string(), schema::<SignInRequest>());
+    schemas.insert("SignOutRequest".to_string(), schema::<SignOutRequest>());
+    schemas.insert(
+        "RefreshSessionRequest".to_string(),
+        schema::<RefreshSessionRequest>(),
+    );
+    schemas.insert("SessionTokens".to_string(), schema::<SessionTokens>());
+    schemas.insert("SessionInfo".to_string(), schema::<SessionInfo>());
+    schemas.insert(
+        "SessionErrorResponse".to_string(),
+        schema::<SessionErrorResponse>(),
+    );
+    object(components, "securitySchemes")
+        .entry("bearerAuth")
+        .or_insert_with(|| {
+            json!({
+                "type": "http",
+                "scheme": "bearer",
+                "bearerFormat": "JWT",
+                "description": "JWT token for authentication"
+            })
+        });
+}
+
+/// The object at `key` of `parent`, replacing anything else there
+fn object<'a>(parent: &'a mut Map<String, Value>, key: &str) -> &'a mut Map<String, Value> {
+    let value = parent
+        .entry(key)
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !value.is_object() {
+        *value = Value::Object(Map::new());
+    }
+    value.as_object_mut().expect("just made an object")
+}
+
+/// The schema of `T`, as an OpenAPI component
+fn schema<T: JsonSchema>() -> Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(T)).unwrap_or_else(|_| json!({}));
+    if let Some(schema) = schema.as_object_mut() {
+        schema.remove("$schema");
+    }
+    schema
+}
+
+fn reference(schema: &str) -> Value {
+    json!({
+        "application/json": {
+            "schema": { "$ref": format!("#/components/schemas/{schema}") }
+        }
+    })
+}
+
+/// One of the session routes
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    description: &'static str,
+    /// The schema of the request body, and whether the body is required
+    request: Option<(&'static str, bool)>,
+    /// The schema of the body of successful responses, which have none otherwise
+    response: Option<&'static str>,
+    authenticated: bool,
+}
+
+impl Operation {
+    fn to_json(&self) -> Value {
+        let mut responses = Map::new();
+        match self.response {
+            Some(schema) => responses.insert(
+                "200".to_string(),
+                json!({ "description": "Successful response", "content": reference(schema) }),
+            ),
+            None => responses.insert(
+                "204".to_string(),
+                json!({ "description": "Successful response" }),
+            ),
+        };
+        let mut errors = vec![
+            ("400", "Bad request"),
+            ("401", "Invalid credentials or session"),
+        ];
+        if self.path == "/auth/login" {
+            errors.push(("429", "Too many sign-in attempts"));
+        }
+        for (status, description) in errors {
+            responses.insert(
+                status.to_string(),
+                json!({ "description": description, "content": reference("SessionErrorResponse") }),
+            );
+        }
+
+        let mut operation = json!({
+            "summary": self.summary,
+            "description": self.description,
+            "operationId": format!("{}{}", self.method, self.path.replace('/', "_")),
+            "tags": ["auth"],
+            "responses": responses,
+        });
+        if let Some((schema, required)) = self.request {
+            operation["requestBody"] = json!({
+                "required": required,
+                "content": reference(schema),
+            });
+        }
+        if self.authenticated {
+            operation["security"] = json!([{ "bearerAuth": [] }]);
+        }
+        operation
+    }
+}
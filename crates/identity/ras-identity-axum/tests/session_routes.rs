@@ -0,0 +1,392 @@
+//! Signing in and out through the session routes.
+
+use axum::Extension;
+use axum::http::header;
+use ras_identity_axum::{
+    SessionInfo, SessionTokens, merge_session_openapi, session_routes, session_routes_with_cookies,
+};
+use ras_identity_local::LocalUserProvider;
+use ras_identity_session::{BindingMode, CookieConfig, SessionConfig, SessionService};
+use ras_observability_core::TrustedProxies;
+use reqwest::StatusCode;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+const TEST_SECRET: &str = "test-secret-that-is-long-enough-for-hs256";
+
+async fn session_service(refresh_enabled: bool) -> Arc<SessionService> {
+    let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+    config.refresh_enabled = refresh_enabled;
+    let service = SessionService::new(config).unwrap();
+    let local_provider = LocalUserProvider::default();
+    local_provider
+        .add_user(
+            "alice".to_string(),
+            "password123".to_string(),
+            Some("alice@example.com".to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+    service.register_provider(Box::new(local_provider)).await;
+    Arc::new(service)
+}
+
+fn alice_login(password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "provider_id": "local",
+        "payload": { "username": "alice", "password": password }
+    })
+}
+
+/// Serve `router` on a random port, returning its base URL
+async fn serve(router: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    format!("http://{}", address)
+}
+
+async fn sign_in(base: &str, password: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{base}/auth/login"))
+        .json(&alice_login(password))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn me(base: &str, token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{base}/auth/me"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn signing_in_returns_a_token_the_current_session_echoes() {
+    let base = serve(session_routes(session_service(false).await)).await;
+
+    let response = sign_in(&base, "password123").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+    let tokens: SessionTokens = response.json().await.unwrap();
+    assert!(tokens.expires_at > chrono::Utc::now().timestamp());
+    assert_eq!(tokens.refresh_token, None);
+    let token = tokens.token.unwrap();
+
+    let response = me(&base, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let session: SessionInfo = response.json().await.unwrap();
+    assert_eq!(session.sub, "alice");
+    assert_eq!(session.provider_id, "local");
+    assert_eq!(session.email.as_deref(), Some("alice@example.com"));
+    assert_eq!(session.exp, tokens.expires_at);
+
+    let response = sign_in(&base, "wrong-password").await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "Invalid credentials");
+    assert_eq!(
+        me(&base, "not-a-token").await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn signing_out_ends_the_session() {
+    let base = serve(session_routes(session_service(false).await)).await;
+    let tokens: SessionTokens = sign_in(&base, "password123").await.json().await.unwrap();
+    let token = tokens.token.unwrap();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{base}/auth/logout"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .post(format!("{base}/auth/logout"))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(me(&base, &token).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn refresh_tokens_are_rotated_and_revoked_at_sign_out() {
+    let base = serve(session_routes(session_service(true).await)).await;
+    let client = reqwest::Client::new();
+    let refresh = |refresh_token: String| {
+        client
+            .post(format!("{base}/auth/refresh"))
+            .json(&serde_json::json!({ "refresh_token": refresh_token }))
+            .send()
+    };
+
+    let first: SessionTokens = sign_in(&base, "password123").await.json().await.unwrap();
+    let first_refresh = first.refresh_token.clone().unwrap();
+    let response = refresh(first_refresh.clone()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let second: SessionTokens = response.json().await.unwrap();
+    assert_ne!(second.token, first.token);
+    let token = second.token.unwrap();
+    assert_eq!(me(&base, &token).await.status(), StatusCode::OK);
+
+    let response = refresh(first_refresh).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "Refresh token reused");
+
+    // Reuse revoked the family, so sign in again to sign out with a refresh token
+    let third: SessionTokens = sign_in(&base, "password123").await.json().await.unwrap();
+    let response = client
+        .post(format!("{base}/auth/logout"))
+        .bearer_auth(third.token.unwrap())
+        .json(&serde_json::json!({ "refresh_token": third.refresh_token }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = refresh(third.refresh_token.unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn cookie_sessions_need_the_csrf_token_to_sign_out() {
+    let config = CookieConfig {
+        secure: false,
+        ..CookieConfig::default()
+    };
+    let base = serve(session_routes_with_cookies(
+        session_service(false).await,
+        config,
+    ))
+    .await;
+    let client = reqwest::Client::new();
+
+    let response = sign_in(&base, "password123").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookies = response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|value| {
+            value
+                .to_str()
+                .unwrap()
+                .split(';')
+                .next()
+                .unwrap()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    let tokens: SessionTokens = response.json().await.unwrap();
+    assert_eq!(tokens.token, None);
+    let csrf_token = tokens.csrf_token.unwrap();
+
+    let response = client
+        .get(format!("{base}/auth/me"))
+        .header(header::COOKIE, &cookies)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .post(format!("{base}/auth/logout"))
+        .header(header::COOKIE, &cookies)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = client
+        .post(format!("{base}/auth/logout"))
+        .header(header::COOKIE, &cookies)
+        .header("x-csrf-token", csrf_token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let cleared: Vec<_> = response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .collect();
+    assert_eq!(cleared.len(), 2);
+    assert!(
+        cleared
+            .iter()
+            .all(|cookie| cookie.to_str().unwrap().contains("Max-Age=0"))
+    );
+
+    let response = client
+        .get(format!("{base}/auth/me"))
+        .header(header::COOKIE, &cookies)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn the_routes_are_merged_into_an_openapi_document() {
+    let mut document = serde_json::json!({
+        "openapi": "3.0.3",
+        "paths": {
+            "/api/deposits": { "post": { "operationId": "post_api_deposits" } }
+        },
+        "components": {
+            "schemas": { "Deposit": { "type": "object" } }
+        }
+    });
+
+    merge_session_openapi(&mut document);
+
+    let paths = document["paths"].as_object().unwrap();
+    assert!(paths.contains_key("/api/deposits"));
+    assert_eq!(
+        document["paths"]["/auth/login"]["post"]["requestBody"]["content"]["application/json"]["schema"]
+            ["$ref"],
+        "#/components/schemas/SignInRequest"
+    );
+    assert_eq!(
+        document["paths"]["/auth/me"]["get"]["security"],
+        serde_json::json!([{ "bearerAuth": [] }])
+    );
+    assert!(document["paths"]["/auth/logout"]["post"]["responses"]["204"].is_object());
+    assert!(document["paths"]["/auth/refresh"]["post"].is_object());
+
+    let schemas = document["components"]["schemas"].as_object().unwrap();
+    assert!(schemas.contains_key("Deposit"));
+    for schema in [
+        "SignInRequest",
+        "SignOutRequest",
+        "RefreshSessionRequest",
+        "SessionTokens",
+        "SessionInfo",
+        "SessionErrorResponse",
+    ] {
+        assert!(schemas.contains_key(schema), "{schema} is missing");
+        assert!(schemas[schema].get("$schema").is_none());
+    }
+    assert_eq!(
+        schemas["SessionTokens"]["properties"]["expires_at"]["type"],
+        "integer"
+    );
+    assert_eq!(
+        document["components"]["securitySchemes"]["bearerAuth"]["scheme"],
+        "bearer"
+    );
+}
+
+#[tokio::test]
+async fn sessions_bind_to_the_client_behind_trusted_proxies() {
+    let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+    config.refresh_enabled = false;
+    config.binding.ip_prefix = BindingMode::Enforce;
+    let service = SessionService::new(config).unwrap();
+    let local_provider = LocalUserProvider::default();
+    local_provider
+        .add_user("alice".to_string(), "password123".to_string(), None, None)
+        .await
+        .unwrap();
+    service.register_provider(Box::new(local_provider)).await;
+    // The test client connects from 127.0.0.1, standing in for the proxy
+    let trusted = TrustedProxies::new(["127.0.0.1"]).unwrap();
+    let router = session_routes(Arc::new(service)).layer(Extension(trusted));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let app = router.into_make_service_with_connect_info::<SocketAddr>();
+        axum::serve(listener, app).await.unwrap();
+    });
+    let client = reqwest::Client::new();
+
+    let tokens: SessionTokens = client
+        .post(format!("{base}/auth/login"))
+        .header("x-forwarded-for", "203.0.113.9")
+        .json(&alice_login("password123"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let token = tokens.token.unwrap();
+    let me = |forwarded_for: &'static str| {
+        client
+            .get(format!("{base}/auth/me"))
+            .bearer_auth(&token)
+            .header("x-forwarded-for", forwarded_for)
+            .send()
+    };
+    assert_eq!(me("203.0.113.9").await.unwrap().status(), StatusCode::OK);
+
+    // A thief elsewhere claiming the victim's address in a leading hop is
+    // known by the hop the proxy appended
+    assert_eq!(
+        me("203.0.113.9, 198.51.100.7").await.unwrap().status(),
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn refreshable_sessions_bind_to_the_client_signing_in() {
+    let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+    config.refresh_enabled = true;
+    config.binding.user_agent = BindingMode::Enforce;
+    let service = SessionService::new(config).unwrap();
+    let local_provider = LocalUserProvider::default();
+    local_provider
+        .add_user("alice".to_string(), "password123".to_string(), None, None)
+        .await
+        .unwrap();
+    service.register_provider(Box::new(local_provider)).await;
+    let base = serve(session_routes(Arc::new(service))).await;
+    let client = reqwest::Client::new();
+    let me = |token: String, user_agent: &'static str| {
+        client
+            .get(format!("{base}/auth/me"))
+            .bearer_auth(token)
+            .header(header::USER_AGENT, user_agent)
+            .send()
+    };
+
+    let first: SessionTokens = client
+        .post(format!("{base}/auth/login"))
+        .header(header::USER_AGENT, "Firefox/140")
+        .json(&alice_login("password123"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let refreshed: SessionTokens = client
+        .post(format!("{base}/auth/refresh"))
+        .json(&serde_json::json!({ "refresh_token": first.refresh_token.unwrap() }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    for token in [first.token.unwrap(), refreshed.token.unwrap()] {
+        let response = me(token.clone(), "Firefox/140").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = me(token, "curl/8.0").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,126 @@
+//! Settings for reaching the directory and finding users in it.
+
+use ras_identity_core::{IdentityError, IdentityResult};
+use std::time::Duration;
+
+/// What is replaced with the username in DN templates and search filters
+pub const USERNAME_PLACEHOLDER: &str = "{username}";
+
+/// How to find the entry to bind as for a username
+#[derive(Clone)]
+pub enum UserLookup {
+    /// Bind straight away as the DN made by replacing `{username}`, such as
+    /// `uid={username},ou=people,dc=example,dc=com`.
+    DnTemplate(String),
+    /// Bind as a service account, search for the user, then bind as them.
+    Search {
+        bind_dn: String,
+        bind_password: String,
+        base_dn: String,
+        /// Filter matching exactly one user, such as `(uid={username})` or
+        /// `(sAMAccountName={username})` for Active Directory
+        filter: String,
+    },
+}
+
+impl std::fmt::Debug for UserLookup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DnTemplate(template) => f.debug_tuple("DnTemplate").field(template).finish(),
+            Self::Search {
+                bind_dn,
+                base_dn,
+                filter,
+                ..
+            } => f
+                .debug_struct("Search")
+                .field("bind_dn", bind_dn)
+                .field("bind_password", &"<redacted>")
+                .field("base_dn", base_dn)
+                .field("filter", filter)
+                .finish(),
+        }
+    }
+}
+
+/// The attributes identities are read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeMapping {
+    pub email: String,
+    pub display_name: String,
+    /// Multi-valued attribute listing the DNs of the user's groups
+    pub groups: String,
+}
+
+impl Default for AttributeMapping {
+    fn default() -> Self {
+        Self {
+            email: "mail".to_string(),
+            display_name: "displayName".to_string(),
+            groups: "memberOf".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LdapConfig {
+    /// `ldap://` or `ldaps://` URL of the server
+    pub url: String,
+    pub user_lookup: UserLookup,
+    pub attributes: AttributeMapping,
+    /// Upgrade `ldap://` connections with StartTLS before binding
+    pub starttls: bool,
+    /// Skip certificate verification, for test servers only
+    pub accept_invalid_certs: bool,
+    pub connect_timeout: Duration,
+    /// Longest to wait for each bind or search
+    pub operation_timeout: Duration,
+    /// Most connections open at once, and so most logins checked at once
+    pub pool_size: usize,
+}
+
+impl LdapConfig {
+    pub fn new(url: impl Into<String>, user_lookup: UserLookup) -> Self {
+        Self {
+            url: url.into(),
+            user_lookup,
+            attributes: AttributeMapping::default(),
+            starttls: false,
+            accept_invalid_certs: false,
+            connect_timeout: Duration::from_secs(5),
+            operation_timeout: Duration::from_secs(5),
+            pool_size: 5,
+        }
+    }
+
+    /// Check the settings can be used to authenticate anyone.
+    pub fn validate(&self) -> IdentityResult<()> {
+        let ldaps = self.url.starts_with("ldaps://");
+        if !ldaps && !self.url.starts_with("ldap://") {
+            return Err(invalid_config("url must start with ldap:// or ldaps://"));
+        }
+        if ldaps && self.starttls {
+            return Err(invalid_config("StartTLS can't be used with ldaps://"));
+        }
+        match &self.user_lookup {
+            UserLookup::DnTemplate(template) if !template.contains(USERNAME_PLACEHOLDER) => {
+                return Err(invalid_config("the DN template must contain {username}"));
+            }
+            UserLookup::Search { filter, .. } if !filter.contains(USERNAME_PLACEHOLDER) => {
+                return Err(invalid_config("the search filter must contain {username}"));
+            }
+            _ => {}
+        }
+        if self.connect_timeout.is_zero() || self.operation_timeout.is_zero() {
+            return Err(invalid_config("timeouts must be greater than zero"));
+        }
+        if self.pool_size == 0 {
+            return Err(invalid_config("pool_size must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+fn invalid_config(reason: &str) -> IdentityError {
+    IdentityError::ProviderError(format!("invalid LDAP configuration: {}", reason))
+}
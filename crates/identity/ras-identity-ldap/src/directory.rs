@@ -0,0 +1,176 @@
+//! Connections to a directory server.
+
+use async_trait::async_trait;
+use ldap3::{LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// LDAP result code for a bind with the wrong DN or password
+const INVALID_CREDENTIALS: u32 = 49;
+/// LDAP result code for a search base that doesn't exist
+const NO_SUCH_OBJECT: u32 = 32;
+
+/// A failure talking to the directory.
+///
+/// Carries details for the logs; they are never returned to callers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryError(pub String);
+
+impl std::fmt::Display for DirectoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for DirectoryError {}
+
+impl From<ldap3::LdapError> for DirectoryError {
+    fn from(error: ldap3::LdapError) -> Self {
+        Self(error.to_string())
+    }
+}
+
+/// An entry read from the directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    pub dn: String,
+    /// Attribute values by attribute name
+    pub attributes: HashMap<String, Vec<String>>,
+}
+
+impl DirectoryEntry {
+    /// The first value of `attribute`, matching its name ignoring ASCII case
+    pub fn first(&self, attribute: &str) -> Option<&str> {
+        self.values(attribute).first().map(String::as_str)
+    }
+
+    /// The values of `attribute`, matching its name ignoring ASCII case
+    pub fn values(&self, attribute: &str) -> &[String] {
+        self.attributes
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(attribute))
+            .map_or(&[], |(_, values)| values.as_slice())
+    }
+}
+
+/// How much of the tree under a search base to search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchScope {
+    /// Just the base entry
+    Base,
+    /// The base entry and everything under it
+    Subtree,
+}
+
+/// One connection to a directory server, used by one login at a time.
+#[async_trait]
+pub trait DirectoryConnection: Send {
+    /// Bind as `dn` with `password`, returning `false` if the directory
+    /// rejects the credentials.
+    async fn bind(&mut self, dn: &str, password: &str) -> Result<bool, DirectoryError>;
+
+    /// The entries in `scope` of `base` matching `filter`, with `attributes`.
+    ///
+    /// A `base` that doesn't exist gives no entries.
+    async fn search(
+        &mut self,
+        base: &str,
+        scope: SearchScope,
+        filter: &str,
+        attributes: &[&str],
+    ) -> Result<Vec<DirectoryEntry>, DirectoryError>;
+
+    /// Whether the connection can still be used
+    fn is_open(&mut self) -> bool;
+}
+
+/// Opens connections to a directory server.
+#[async_trait]
+pub trait DirectoryConnector: Send + Sync {
+    async fn connect(&self) -> Result<Box<dyn DirectoryConnection>, DirectoryError>;
+}
+
+/// Connects to an LDAP server with `ldap3`.
+#[derive(Debug, Clone)]
+pub struct Ldap3Connector {
+    pub url: String,
+    pub starttls: bool,
+    pub accept_invalid_certs: bool,
+    pub connect_timeout: Duration,
+    pub operation_timeout: Duration,
+}
+
+#[async_trait]
+impl DirectoryConnector for Ldap3Connector {
+    async fn connect(&self) -> Result<Box<dyn DirectoryConnection>, DirectoryError> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(self.connect_timeout)
+            .set_starttls(self.starttls)
+            .set_no_tls_verify(self.accept_invalid_certs);
+        let (connection, ldap) = LdapConnAsync::with_settings(settings, &self.url).await?;
+        ldap3::drive!(connection);
+
+        Ok(Box::new(Ldap3Connection {
+            ldap,
+            timeout: self.operation_timeout,
+        }))
+    }
+}
+
+struct Ldap3Connection {
+    ldap: ldap3::Ldap,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl DirectoryConnection for Ldap3Connection {
+    async fn bind(&mut self, dn: &str, password: &str) -> Result<bool, DirectoryError> {
+        let result = self
+            .ldap
+            .with_timeout(self.timeout)
+            .simple_bind(dn, password)
+            .await?;
+        match result.rc {
+            0 => Ok(true),
+            INVALID_CREDENTIALS => Ok(false),
+            _ => Err(DirectoryError(format!("bind failed: {}", result))),
+        }
+    }
+
+    async fn search(
+        &mut self,
+        base: &str,
+        scope: SearchScope,
+        filter: &str,
+        attributes: &[&str],
+    ) -> Result<Vec<DirectoryEntry>, DirectoryError> {
+        let scope = match scope {
+            SearchScope::Base => Scope::Base,
+            SearchScope::Subtree => Scope::Subtree,
+        };
+        let result = self
+            .ldap
+            .with_timeout(self.timeout)
+            .search(base, scope, filter, attributes.to_vec())
+            .await?;
+        if result.1.rc == NO_SUCH_OBJECT {
+            return Ok(Vec::new());
+        }
+
+        let (entries, _) = result.success()?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                let entry = SearchEntry::construct(entry);
+                DirectoryEntry {
+                    dn: entry.dn,
+                    attributes: entry.attrs,
+                }
+            })
+            .collect())
+    }
+
+    fn is_open(&mut self) -> bool {
+        !self.ldap.is_closed()
+    }
+}
@@ -0,0 +1,66 @@
+//! Permissions from directory groups.
+
+use async_trait::async_trait;
+use ras_identity_core::{IdentityResult, UserPermissions, VerifiedIdentity};
+
+/// The DNs of the groups an identity verified by an
+/// [`LdapProvider`](crate::LdapProvider) belongs to, if it was.
+pub fn ldap_groups(identity: &VerifiedIdentity) -> Option<Vec<String>> {
+    if identity.provider_id != crate::PROVIDER_ID {
+        return None;
+    }
+    let groups = identity.metadata.as_ref()?.get("groups")?.as_array()?;
+    Some(
+        groups
+            .iter()
+            .filter_map(|group| group.as_str().map(str::to_string))
+            .collect(),
+    )
+}
+
+/// Grants permissions to members of directory groups.
+///
+/// Group DNs are compared ignoring ASCII case. Identities from other providers
+/// get no permissions.
+#[derive(Debug, Clone, Default)]
+pub struct GroupPermissions {
+    grants: Vec<(String, Vec<String>)>,
+}
+
+impl GroupPermissions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give members of `group_dn` the `permissions`
+    pub fn grant<I, P>(mut self, group_dn: impl Into<String>, permissions: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        self.grants.push((
+            group_dn.into(),
+            permissions.into_iter().map(Into::into).collect(),
+        ));
+        self
+    }
+}
+
+#[async_trait]
+impl UserPermissions for GroupPermissions {
+    async fn get_permissions(&self, identity: &VerifiedIdentity) -> IdentityResult<Vec<String>> {
+        let groups = ldap_groups(identity).unwrap_or_default();
+        let mut permissions: Vec<String> = Vec::new();
+        for (group_dn, granted) in &self.grants {
+            if !groups.iter().any(|g| g.eq_ignore_ascii_case(group_dn)) {
+                continue;
+            }
+            for permission in granted {
+                if !permissions.contains(permission) {
+                    permissions.push(permission.clone());
+                }
+            }
+        }
+        Ok(permissions)
+    }
+}
@@ -0,0 +1,518 @@
+//! LDAP and Active Directory identity provider.
+
+use async_trait::async_trait;
+use ldap3::{dn_escape, ldap_escape};
+use ras_identity_core::{IdentityError, IdentityProvider, IdentityResult, VerifiedIdentity};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+mod config;
+mod directory;
+mod groups;
+mod memory;
+
+pub use config::{AttributeMapping, LdapConfig, USERNAME_PLACEHOLDER, UserLookup};
+pub use directory::{
+    DirectoryConnection, DirectoryConnector, DirectoryEntry, DirectoryError, Ldap3Connector,
+    SearchScope,
+};
+pub use groups::{GroupPermissions, ldap_groups};
+pub use memory::MemoryDirectory;
+
+pub(crate) const PROVIDER_ID: &str = "ldap";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LdapAuthPayload {
+    pub username: String,
+    pub password: String,
+}
+
+/// Authenticates users by binding to an LDAP directory as them.
+///
+/// The identity's subject is the username, with the user's DN and group DNs
+/// in its metadata. Anything going wrong with the directory is logged and
+/// reported as `IdentityError::ProviderError` without the details.
+#[derive(Clone)]
+pub struct LdapProvider {
+    config: Arc<LdapConfig>,
+    pool: Arc<ConnectionPool>,
+}
+
+impl LdapProvider {
+    /// Authenticate against the server in `config`.
+    pub fn new(config: LdapConfig) -> IdentityResult<Self> {
+        let connector = Ldap3Connector {
+            url: config.url.clone(),
+            starttls: config.starttls,
+            accept_invalid_certs: config.accept_invalid_certs,
+            connect_timeout: config.connect_timeout,
+            operation_timeout: config.operation_timeout,
+        };
+        Self::with_connector(config, Arc::new(connector))
+    }
+
+    /// Authenticate against the directory `connector` opens connections to,
+    /// such as a [`MemoryDirectory`] in tests.
+    pub fn with_connector(
+        config: LdapConfig,
+        connector: Arc<dyn DirectoryConnector>,
+    ) -> IdentityResult<Self> {
+        config.validate()?;
+        let pool = ConnectionPool {
+            connector,
+            idle: Mutex::new(Vec::new()),
+            permits: Arc::new(Semaphore::new(config.pool_size)),
+        };
+
+        Ok(Self {
+            config: Arc::new(config),
+            pool: Arc::new(pool),
+        })
+    }
+
+    /// The user's entry if `password` is theirs, or `None`
+    async fn authenticate(
+        &self,
+        connection: &mut dyn DirectoryConnection,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<DirectoryEntry>, DirectoryError> {
+        let mapping = &self.config.attributes;
+        let attributes = [
+            mapping.email.as_str(),
+            mapping.display_name.as_str(),
+            mapping.groups.as_str(),
+        ];
+
+        match &self.config.user_lookup {
+            UserLookup::DnTemplate(template) => {
+                let dn = template.replace(USERNAME_PLACEHOLDER, &dn_escape(username));
+                if !connection.bind(&dn, password).await? {
+                    return Ok(None);
+                }
+                let entry = connection
+                    .search(&dn, SearchScope::Base, "(objectClass=*)", &attributes)
+                    .await?
+                    .pop();
+                Ok(Some(entry.unwrap_or(DirectoryEntry {
+                    dn,
+                    ..Default::default()
+                })))
+            }
+            UserLookup::Search {
+                bind_dn,
+                bind_password,
+                base_dn,
+                filter,
+            } => {
+                if !connection.bind(bind_dn, bind_password).await? {
+                    return Err(DirectoryError("service account bind rejected".into()));
+                }
+                let filter = filter.replace(USERNAME_PLACEHOLDER, &ldap_escape(username));
+                let mut entries = connection
+                    .search(base_dn, SearchScope::Subtree, &filter, &attributes)
+                    .await?;
+                if entries.len() != 1 {
+                    return Ok(None);
+                }
+                let entry = entries.remove(0);
+                if !connection.bind(&entry.dn, password).await? {
+                    return Ok(None);
+                }
+                Ok(Some(entry))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for LdapProvider {
+    fn provider_id(&self) -> &str {
+        PROVIDER_ID
+    }
+
+    async fn verify(&self, auth_payload: serde_json::Value) -> IdentityResult<VerifiedIdentity> {
+        let payload: LdapAuthPayload =
+            serde_json::from_value(auth_payload).map_err(|_| IdentityError::InvalidPayload)?;
+        // Servers treat a bind with an empty password as an anonymous bind,
+        // which succeeds whoever the DN names
+        if payload.username.is_empty() || payload.password.is_empty() {
+            return Err(IdentityError::InvalidCredentials);
+        }
+
+        let mut connection = self.pool.get().await.map_err(unavailable)?;
+        let entry = match self
+            .authenticate(
+                &mut *connection.connection,
+                &payload.username,
+                &payload.password,
+            )
+            .await
+        {
+            Ok(entry) => {
+                self.pool.put(connection);
+                entry
+            }
+            Err(e) => return Err(unavailable(e)),
+        };
+        let entry = entry.ok_or(IdentityError::InvalidCredentials)?;
+
+        let mapping = &self.config.attributes;
+        Ok(VerifiedIdentity {
+            provider_id: PROVIDER_ID.to_string(),
+            subject: payload.username,
+            email: entry.first(&mapping.email).map(str::to_string),
+            display_name: entry.first(&mapping.display_name).map(str::to_string),
+            metadata: Some(json!({
+                "dn": entry.dn,
+                "groups": entry.values(&mapping.groups),
+            })),
+        })
+    }
+}
+
+fn unavailable(error: DirectoryError) -> IdentityError {
+    tracing::warn!(error = %error, "LDAP directory request failed");
+    IdentityError::ProviderError("directory unavailable".to_string())
+}
+
+/// Reuses open connections, opening at most as many as there are permits.
+struct ConnectionPool {
+    connector: Arc<dyn DirectoryConnector>,
+    idle: Mutex<Vec<Box<dyn DirectoryConnection>>>,
+    permits: Arc<Semaphore>,
+}
+
+struct PooledConnection {
+    connection: Box<dyn DirectoryConnection>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl ConnectionPool {
+    async fn get(&self) -> Result<PooledConnection, DirectoryError> {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| DirectoryError("connection pool closed".into()))?;
+
+        while let Some(mut connection) = self.idle.lock().unwrap().pop() {
+            if connection.is_open() {
+                return Ok(PooledConnection {
+                    connection,
+                    _permit: permit,
+                });
+            }
+        }
+        let connection = self.connector.connect().await?;
+        Ok(PooledConnection {
+            connection,
+            _permit: permit,
+        })
+    }
+
+    /// Return a connection that completed its work for reuse.
+    ///
+    /// Connections that failed are dropped instead, as they may be broken.
+    fn put(&self, mut pooled: PooledConnection) {
+        if pooled.connection.is_open() {
+            self.idle.lock().unwrap().push(pooled.connection);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_identity_core::UserPermissions;
+    use std::collections::HashMap;
+
+    const ADMINS: &str = "cn=admins,ou=groups,dc=example,dc=com";
+    const DEVELOPERS: &str = "cn=developers,ou=groups,dc=example,dc=com";
+
+    fn entry(dn: &str, attributes: &[(&str, &[&str])]) -> DirectoryEntry {
+        DirectoryEntry {
+            dn: dn.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(name, values)| {
+                    (
+                        name.to_string(),
+                        values.iter().map(|v| v.to_string()).collect(),
+                    )
+                })
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    fn directory() -> MemoryDirectory {
+        let directory = MemoryDirectory::new();
+        directory.add_entry(
+            entry(
+                "uid=alice,ou=people,dc=example,dc=com",
+                &[
+                    ("uid", &["alice"]),
+                    ("mail", &["alice@example.com"]),
+                    ("displayName", &["Alice Example"]),
+                    ("memberOf", &[ADMINS, DEVELOPERS]),
+                ],
+            ),
+            Some("alice-password"),
+        );
+        directory.add_entry(
+            entry(
+                "uid=bob,ou=people,dc=example,dc=com",
+                &[("uid", &["bob"]), ("mail", &["bob@example.com"])],
+            ),
+            Some("bob-password"),
+        );
+        directory.add_entry(
+            entry("cn=service,dc=example,dc=com", &[]),
+            Some("service-password"),
+        );
+        directory
+    }
+
+    fn template_config() -> LdapConfig {
+        LdapConfig::new(
+            "ldap://localhost",
+            UserLookup::DnTemplate("uid={username},ou=people,dc=example,dc=com".to_string()),
+        )
+    }
+
+    fn search_config(bind_password: &str) -> LdapConfig {
+        LdapConfig::new(
+            "ldaps://localhost",
+            UserLookup::Search {
+                bind_dn: "cn=service,dc=example,dc=com".to_string(),
+                bind_password: bind_password.to_string(),
+                base_dn: "ou=people,dc=example,dc=com".to_string(),
+                filter: "(uid={username})".to_string(),
+            },
+        )
+    }
+
+    fn provider(config: LdapConfig, directory: &MemoryDirectory) -> LdapProvider {
+        LdapProvider::with_connector(config, Arc::new(directory.clone())).unwrap()
+    }
+
+    fn login(username: &str, password: &str) -> serde_json::Value {
+        json!({ "username": username, "password": password })
+    }
+
+    #[tokio::test]
+    async fn test_dn_template_login_maps_attributes() {
+        let provider = provider(template_config(), &directory());
+
+        let identity = provider
+            .verify(login("alice", "alice-password"))
+            .await
+            .unwrap();
+        assert_eq!(identity.provider_id, "ldap");
+        assert_eq!(identity.subject, "alice");
+        assert_eq!(identity.email.as_deref(), Some("alice@example.com"));
+        assert_eq!(identity.display_name.as_deref(), Some("Alice Example"));
+        assert_eq!(
+            identity.metadata,
+            Some(json!({
+                "dn": "uid=alice,ou=people,dc=example,dc=com",
+                "groups": [ADMINS, DEVELOPERS],
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_then_bind_login() {
+        let provider = provider(search_config("service-password"), &directory());
+
+        let identity = provider.verify(login("bob", "bob-password")).await.unwrap();
+        assert_eq!(identity.subject, "bob");
+        assert_eq!(identity.email.as_deref(), Some("bob@example.com"));
+        assert_eq!(identity.display_name, None);
+        assert_eq!(ldap_groups(&identity), Some(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_or_missing_credentials_are_rejected() {
+        let directory = directory();
+        for config in [template_config(), search_config("service-password")] {
+            let provider = provider(config, &directory);
+            for (username, password) in [
+                ("alice", "bob-password"),
+                ("carol", "alice-password"),
+                ("alice", ""),
+                ("", "alice-password"),
+            ] {
+                let result = provider.verify(login(username, password)).await;
+                assert!(
+                    matches!(result, Err(IdentityError::InvalidCredentials)),
+                    "{username:?} / {password:?}: {result:?}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usernames_are_escaped() {
+        let directory = directory();
+        let search = provider(search_config("service-password"), &directory);
+        for username in ["*", "alice)(uid=*", "ali*"] {
+            let result = search.verify(login(username, "alice-password")).await;
+            assert!(matches!(result, Err(IdentityError::InvalidCredentials)));
+        }
+
+        let template = provider(template_config(), &directory);
+        let result = template
+            .verify(login("alice,ou=people", "alice-password"))
+            .await;
+        assert!(matches!(result, Err(IdentityError::InvalidCredentials)));
+    }
+
+    #[tokio::test]
+    async fn test_directory_failures_do_not_leak_details() {
+        let directory = directory();
+        let provider = provider(template_config(), &directory);
+        directory.set_unavailable(true);
+
+        let error = provider
+            .verify(login("alice", "alice-password"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            IdentityError::ProviderError("directory unavailable".to_string()).to_string()
+        );
+
+        directory.set_unavailable(false);
+        assert!(
+            provider
+                .verify(login("alice", "alice-password"))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_service_account_is_a_provider_error() {
+        let provider = provider(search_config("wrong-password"), &directory());
+
+        let result = provider.verify(login("alice", "alice-password")).await;
+        assert!(matches!(result, Err(IdentityError::ProviderError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_connections_are_reused() {
+        let directory = directory();
+        let provider = provider(search_config("service-password"), &directory);
+
+        for _ in 0..3 {
+            provider
+                .verify(login("alice", "alice-password"))
+                .await
+                .unwrap();
+            let _ = provider.verify(login("alice", "wrong")).await;
+        }
+        assert_eq!(directory.connections(), 1);
+
+        // A connection broken by an outage is replaced
+        directory.set_unavailable(true);
+        let _ = provider.verify(login("alice", "alice-password")).await;
+        directory.set_unavailable(false);
+        provider
+            .verify(login("alice", "alice-password"))
+            .await
+            .unwrap();
+        assert_eq!(directory.connections(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_logins_are_limited_by_pool_size() {
+        let directory = directory();
+        let mut config = template_config();
+        config.pool_size = 2;
+        let provider = provider(config, &directory);
+
+        let logins = (0..10).map(|_| {
+            let provider = provider.clone();
+            tokio::spawn(async move { provider.verify(login("alice", "alice-password")).await })
+        });
+        for login in logins {
+            login.await.unwrap().unwrap();
+        }
+        assert!(directory.connections() <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_group_permissions() {
+        let directory = directory();
+        let provider = provider(template_config(), &directory);
+        let permissions = GroupPermissions::new()
+            .grant(ADMINS.to_uppercase(), ["admin", "read"])
+            .grant(DEVELOPERS, ["read", "deploy"]);
+
+        let alice = provider
+            .verify(login("alice", "alice-password"))
+            .await
+            .unwrap();
+        assert_eq!(
+            permissions.get_permissions(&alice).await.unwrap(),
+            vec!["admin", "read", "deploy"]
+        );
+
+        let bob = provider.verify(login("bob", "bob-password")).await.unwrap();
+        assert!(permissions.get_permissions(&bob).await.unwrap().is_empty());
+
+        let mut elsewhere = alice.clone();
+        elsewhere.provider_id = "local".to_string();
+        assert!(
+            permissions
+                .get_permissions(&elsewhere)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_payload() {
+        let provider = provider(template_config(), &directory());
+
+        let result = provider.verify(json!({ "username": "alice" })).await;
+        assert!(matches!(result, Err(IdentityError::InvalidPayload)));
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(template_config().validate().is_ok());
+        assert!(search_config("service-password").validate().is_ok());
+
+        let mut config = template_config();
+        config.url = "http://localhost".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = search_config("service-password");
+        config.starttls = true;
+        assert!(config.validate().is_err());
+
+        let mut config = template_config();
+        config.user_lookup = UserLookup::DnTemplate("uid=alice,dc=example,dc=com".to_string());
+        assert!(config.validate().is_err());
+
+        let mut config = template_config();
+        config.pool_size = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = template_config();
+        config.operation_timeout = std::time::Duration::ZERO;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_debug_redacts_bind_password() {
+        let debug = format!("{:?}", search_config("service-password"));
+        assert!(!debug.contains("service-password"));
+    }
+}
@@ -0,0 +1,183 @@
+//! An in-process directory for tests.
+
+use crate::directory::{
+    DirectoryConnection, DirectoryConnector, DirectoryEntry, DirectoryError, SearchScope,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// A directory kept in memory, standing in for an LDAP server in tests.
+///
+/// Searches understand presence filters such as `(objectClass=*)` and equality
+/// filters such as `(uid=alice)`, with RFC 4515 escapes. DNs are compared
+/// ignoring ASCII case. Searching needs a successful bind first, as on servers
+/// that refuse anonymous searches.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryDirectory {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: RwLock<Vec<(DirectoryEntry, Option<String>)>>,
+    unavailable: AtomicBool,
+    connections: AtomicUsize,
+}
+
+impl MemoryDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry that can be bound as with `password`, or not at all with `None`
+    pub fn add_entry(&self, entry: DirectoryEntry, password: Option<&str>) {
+        self.inner
+            .entries
+            .write()
+            .unwrap()
+            .push((entry, password.map(str::to_string)));
+    }
+
+    /// Make new connections and operations on open ones fail, as if the server went down
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.inner.unavailable.store(unavailable, Ordering::SeqCst);
+    }
+
+    /// How many connections have been opened
+    pub fn connections(&self) -> usize {
+        self.inner.connections.load(Ordering::SeqCst)
+    }
+
+    fn check_available(&self) -> Result<(), DirectoryError> {
+        if self.inner.unavailable.load(Ordering::SeqCst) {
+            Err(DirectoryError("memory directory is unavailable".into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl DirectoryConnector for MemoryDirectory {
+    async fn connect(&self) -> Result<Box<dyn DirectoryConnection>, DirectoryError> {
+        self.check_available()?;
+        self.inner.connections.fetch_add(1, Ordering::SeqCst);
+        Ok(Box::new(MemoryConnection {
+            directory: self.clone(),
+            bound: false,
+            open: true,
+        }))
+    }
+}
+
+struct MemoryConnection {
+    directory: MemoryDirectory,
+    bound: bool,
+    open: bool,
+}
+
+impl MemoryConnection {
+    fn check_available(&mut self) -> Result<(), DirectoryError> {
+        let available = self.directory.check_available();
+        if available.is_err() {
+            self.open = false;
+        }
+        available
+    }
+}
+
+#[async_trait]
+impl DirectoryConnection for MemoryConnection {
+    async fn bind(&mut self, dn: &str, password: &str) -> Result<bool, DirectoryError> {
+        self.check_available()?;
+        let entries = self.directory.inner.entries.read().unwrap();
+        self.bound = entries.iter().any(|(entry, stored)| {
+            entry.dn.eq_ignore_ascii_case(dn) && stored.as_deref() == Some(password)
+        });
+        Ok(self.bound)
+    }
+
+    async fn search(
+        &mut self,
+        base: &str,
+        scope: SearchScope,
+        filter: &str,
+        attributes: &[&str],
+    ) -> Result<Vec<DirectoryEntry>, DirectoryError> {
+        self.check_available()?;
+        if !self.bound {
+            return Err(DirectoryError("search before bind".into()));
+        }
+        let (attribute, value) = parse_filter(filter)?;
+
+        let entries = self.directory.inner.entries.read().unwrap();
+        Ok(entries
+            .iter()
+            .map(|(entry, _)| entry)
+            .filter(|entry| in_scope(&entry.dn, base, scope))
+            .filter(|entry| match &value {
+                None => {
+                    attribute.eq_ignore_ascii_case("objectClass")
+                        || !entry.values(&attribute).is_empty()
+                }
+                Some(value) => entry.values(&attribute).iter().any(|v| v == value),
+            })
+            .map(|entry| DirectoryEntry {
+                dn: entry.dn.clone(),
+                attributes: entry
+                    .attributes
+                    .iter()
+                    .filter(|(name, _)| attributes.iter().any(|a| a.eq_ignore_ascii_case(name)))
+                    .map(|(name, values)| (name.clone(), values.clone()))
+                    .collect::<HashMap<_, _>>(),
+            })
+            .collect())
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.open
+    }
+}
+
+fn in_scope(dn: &str, base: &str, scope: SearchScope) -> bool {
+    let (dn, base) = (dn.to_ascii_lowercase(), base.to_ascii_lowercase());
+    match scope {
+        SearchScope::Base => dn == base,
+        SearchScope::Subtree => dn == base || dn.ends_with(&format!(",{}", base)),
+    }
+}
+
+/// The attribute and unescaped value of `(attribute=value)`, with no value for `(attribute=*)`
+fn parse_filter(filter: &str) -> Result<(String, Option<String>), DirectoryError> {
+    let unsupported = || DirectoryError(format!("unsupported filter {}", filter));
+    let (attribute, value) = filter
+        .strip_prefix('(')
+        .and_then(|filter| filter.strip_suffix(')'))
+        .and_then(|filter| filter.split_once('='))
+        .ok_or_else(unsupported)?;
+    if value == "*" {
+        return Ok((attribute.to_string(), None));
+    }
+
+    let mut unescaped = Vec::new();
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'\\' => {
+                let hex = [
+                    bytes.next().ok_or_else(unsupported)?,
+                    bytes.next().ok_or_else(unsupported)?,
+                ];
+                let hex = std::str::from_utf8(&hex).map_err(|_| unsupported())?;
+                unescaped.push(u8::from_str_radix(hex, 16).map_err(|_| unsupported())?);
+            }
+            // Substring filters aren't supported
+            b'*' | b'(' | b')' => return Err(unsupported()),
+            byte => unescaped.push(byte),
+        }
+    }
+    let value = String::from_utf8(unescaped).map_err(|_| unsupported())?;
+    Ok((attribute.to_string(), Some(value)))
+}
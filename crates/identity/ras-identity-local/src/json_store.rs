@@ -0,0 +1,188 @@
+//! A user store kept in a JSON file.
+
+use crate::LocalUser;
+use crate::store::{EmailVerification, Page, UserFilter, UserStore, list_in, update_in};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ras_identity_core::{IdentityError, IdentityResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::sync::RwLock;
+
+/// Keeps users in memory and writes them all to a JSON file on every change.
+///
+/// Suited to a handful of users on one node. The file holds an object with the
+/// [`LocalUser`]s ordered by username and the pending email verifications; it
+/// is written to a temporary file next to it first and renamed into place, so a
+/// crash never leaves it half written. Files holding just an array of users are
+/// read too.
+#[derive(Debug)]
+pub struct JsonFileUserStore {
+    path: PathBuf,
+    contents: RwLock<Contents>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Contents {
+    users: BTreeMap<String, LocalUser>,
+    verifications: BTreeMap<String, EmailVerification>,
+}
+
+/// The layout of the file
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredFile {
+    Users(Vec<LocalUser>),
+    Full {
+        users: Vec<LocalUser>,
+        #[serde(default)]
+        verifications: Vec<EmailVerification>,
+    },
+}
+
+impl JsonFileUserStore {
+    /// Load the users in the file at `path`, starting empty if it doesn't exist.
+    pub async fn open(path: impl AsRef<Path>) -> IdentityResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (users, verifications) = match tokio::fs::read(&path).await {
+            Ok(contents) => match serde_json::from_slice(&contents)? {
+                StoredFile::Users(users) => (users, Vec::new()),
+                StoredFile::Full {
+                    users,
+                    verifications,
+                } => (users, verifications),
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => (Vec::new(), Vec::new()),
+            Err(e) => return Err(storage_error(&path, e)),
+        };
+
+        let contents = Contents {
+            users: users
+                .into_iter()
+                .map(|user| (user.username.clone(), user))
+                .collect(),
+            verifications: verifications
+                .into_iter()
+                .map(|verification| (verification.token_hash.clone(), verification))
+                .collect(),
+        };
+        Ok(Self {
+            path,
+            contents: RwLock::new(contents),
+        })
+    }
+
+    /// Write `contents` to the file.
+    async fn save(&self, contents: &Contents) -> IdentityResult<()> {
+        let file = StoredFile::Full {
+            users: contents.users.values().cloned().collect(),
+            verifications: contents.verifications.values().cloned().collect(),
+        };
+        let json = serde_json::to_vec_pretty(&file)?;
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        tokio::fs::write(&temporary, json)
+            .await
+            .map_err(|e| storage_error(&self.path, e))?;
+        tokio::fs::rename(&temporary, &self.path)
+            .await
+            .map_err(|e| storage_error(&self.path, e))
+    }
+
+    /// Apply `change` to a copy of the contents, saving and keeping it if
+    /// `change` returns `Ok((true, _))`.
+    async fn change<T>(
+        &self,
+        change: impl FnOnce(&mut Contents) -> IdentityResult<(bool, T)>,
+    ) -> IdentityResult<T> {
+        let mut contents = self.contents.write().await;
+        let mut changed = contents.clone();
+        let (modified, result) = change(&mut changed)?;
+        if modified {
+            self.save(&changed).await?;
+            *contents = changed;
+        }
+        Ok(result)
+    }
+}
+
+fn storage_error(path: &Path, error: std::io::Error) -> IdentityError {
+    IdentityError::ProviderError(format!("user store {}: {}", path.display(), error))
+}
+
+#[async_trait]
+impl UserStore for JsonFileUserStore {
+    async fn get(&self, username: &str) -> IdentityResult<Option<LocalUser>> {
+        Ok(self.contents.read().await.users.get(username).cloned())
+    }
+
+    async fn insert(&self, user: LocalUser) -> IdentityResult<()> {
+        self.change(|contents| {
+            contents.users.insert(user.username.clone(), user);
+            Ok((true, ()))
+        })
+        .await
+    }
+
+    async fn update(&self, user: LocalUser) -> IdentityResult<bool> {
+        self.change(|contents| {
+            let updated = update_in(&mut contents.users, user)?;
+            Ok((updated, updated))
+        })
+        .await
+    }
+
+    async fn delete(&self, username: &str) -> IdentityResult<Option<LocalUser>> {
+        self.change(|contents| {
+            let removed = contents.users.remove(username);
+            Ok((removed.is_some(), removed))
+        })
+        .await
+    }
+
+    async fn list(
+        &self,
+        filter: &UserFilter,
+        offset: usize,
+        limit: usize,
+    ) -> IdentityResult<Page<LocalUser>> {
+        let contents = self.contents.read().await;
+        Ok(list_in(&contents.users, filter, offset, limit))
+    }
+
+    async fn insert_verification(&self, verification: EmailVerification) -> IdentityResult<()> {
+        self.change(|contents| {
+            contents
+                .verifications
+                .insert(verification.token_hash.clone(), verification);
+            Ok((true, ()))
+        })
+        .await
+    }
+
+    async fn take_verification(
+        &self,
+        token_hash: &str,
+    ) -> IdentityResult<Option<EmailVerification>> {
+        self.change(|contents| {
+            let taken = contents.verifications.remove(token_hash);
+            Ok((taken.is_some(), taken))
+        })
+        .await
+    }
+
+    async fn delete_expired_verifications(&self, now: DateTime<Utc>) -> IdentityResult<usize> {
+        self.change(|contents| {
+            let before = contents.verifications.len();
+            contents
+                .verifications
+                .retain(|_, verification| verification.expires_at > now);
+            let deleted = before - contents.verifications.len();
+            Ok((deleted > 0, deleted))
+        })
+        .await
+    }
+}
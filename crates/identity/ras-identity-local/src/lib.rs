@@ -1,44 +1,178 @@
 //! Local user identity provider with username/password authentication.
 
-use argon2::{
-    Argon2,
-    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-};
 use async_trait::async_trait;
-use rand_core::OsRng;
-use ras_identity_core::{IdentityError, IdentityProvider, IdentityResult, VerifiedIdentity};
+use ras_identity_core::{
+    EventDispatcher, IdentityError, IdentityEvent, IdentityEventKind, IdentityEventSink,
+    IdentityProvider, IdentityResult, VerifiedIdentity,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use tracing::warn;
+
+mod json_store;
+mod mfa;
+mod password;
+mod policy;
+#[cfg(feature = "sqlite")]
+mod sqlite_store;
+mod store;
+mod verification;
+
+pub use json_store::JsonFileUserStore;
+pub use mfa::{MfaConfig, MfaKey, TotpEnrollment, TotpSecret};
+pub use password::Argon2Config;
+pub use policy::PasswordPolicy;
+#[cfg(feature = "sqlite")]
+pub use sqlite_store::SqliteUserStore;
+pub use store::{EmailVerification, MemoryUserStore, Page, UserFilter, UserStore};
+pub use verification::{EMAIL_VERIFIED, VerificationToken, is_email_verified};
+
+use password::PasswordCheck;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LocalUser {
     pub username: String,
     pub password_hash: String,
     pub email: Option<String>,
     pub display_name: Option<String>,
     pub metadata: Option<serde_json::Value>,
+    /// Set by [`LocalUserProvider::verify_email`], and cleared when the email changes
+    #[serde(default)]
+    pub email_verified: bool,
+    /// Bumped by the store on every update, for optimistic concurrency
+    #[serde(default)]
+    pub version: u64,
+    /// Set by [`LocalUserProvider::enroll_totp`]; logins then need a second factor
+    #[serde(default)]
+    pub mfa: Option<TotpEnrollment>,
+}
+
+/// A local user without their password hash, safe to hand to other layers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublicUser {
+    pub username: String,
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+    pub email_verified: bool,
+    pub version: u64,
+    pub mfa_enabled: bool,
+}
+
+impl From<LocalUser> for PublicUser {
+    fn from(user: LocalUser) -> Self {
+        Self {
+            username: user.username,
+            email: user.email,
+            display_name: user.display_name,
+            metadata: user.metadata,
+            email_verified: user.email_verified,
+            version: user.version,
+            mfa_enabled: user.mfa.is_some(),
+        }
+    }
+}
+
+/// The new profile of a user, replacing all of the old one
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UpdateProfile {
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+    /// The version of the user the update was made from
+    pub version: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LocalAuthPayload {
     pub username: String,
     pub password: String,
+    /// The current code of the user's authenticator, if they enrolled one
+    #[serde(default)]
+    pub totp_code: Option<String>,
+    /// One of the user's backup codes, in place of `totp_code`
+    #[serde(default)]
+    pub backup_code: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct LocalUserProvider {
-    users: Arc<RwLock<HashMap<String, LocalUser>>>,
+    store: Arc<dyn UserStore>,
+    argon2: Argon2Config,
+    policy: Option<PasswordPolicy>,
+    unique_emails: bool,
+    verification_ttl: chrono::Duration,
+    mfa: Option<MfaConfig>,
+    audit: Option<EventDispatcher>,
+    /// Checked in place of a missing user's hash, costing as much as a real one
+    dummy_hash: Arc<str>,
     semaphore: Arc<tokio::sync::Semaphore>,
 }
 
 impl LocalUserProvider {
-    pub fn new() -> Self {
-        Self {
-            users: Arc::new(RwLock::new(HashMap::new())),
+    /// Authenticate the users kept in `store`, hashing passwords with `argon2`.
+    ///
+    /// New passwords must follow the default [`PasswordPolicy`].
+    pub fn new(store: Arc<dyn UserStore>, argon2: Argon2Config) -> IdentityResult<Self> {
+        argon2.validate()?;
+        let dummy_hash = argon2.hash("dummy_password")?.into();
+
+        Ok(Self {
+            store,
+            argon2,
+            policy: Some(PasswordPolicy::default()),
+            unique_emails: false,
+            verification_ttl: chrono::Duration::hours(24),
+            mfa: None,
+            audit: None,
+            dummy_hash,
             semaphore: Arc::new(tokio::sync::Semaphore::new(5)),
-        }
+        })
+    }
+
+    /// Check new passwords against `policy` instead, or accept any with `None`
+    pub fn with_password_policy(mut self, policy: Option<PasswordPolicy>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Refuse to give two users the same email, ignoring ASCII case.
+    ///
+    /// Checked before each write, so concurrent writes can still race.
+    pub fn with_unique_emails(mut self, unique_emails: bool) -> Self {
+        self.unique_emails = unique_emails;
+        self
+    }
+
+    /// Make email verification tokens expire after `ttl` instead of 24 hours
+    pub fn with_verification_ttl(mut self, ttl: chrono::Duration) -> Self {
+        self.verification_ttl = ttl;
+        self
+    }
+
+    /// Let users enroll TOTP authenticators, encrypting their secrets with `config.key`
+    pub fn with_mfa(mut self, config: MfaConfig) -> Self {
+        self.mfa = Some(config);
+        self
+    }
+
+    /// Report logins, users being added and removed, and password changes to `sink`.
+    ///
+    /// Events are delivered from a background task, so this must be called
+    /// within a Tokio runtime.
+    pub fn with_event_sink(mut self, sink: Arc<dyn IdentityEventSink>) -> Self {
+        self.audit = Some(EventDispatcher::new(sink));
+        self
+    }
+
+    /// How many events were dropped because the event sink fell behind
+    pub fn dropped_events(&self) -> u64 {
+        self.audit.as_ref().map_or(0, EventDispatcher::dropped)
+    }
+
+    /// The store the provider keeps its users in
+    pub fn store(&self) -> &Arc<dyn UserStore> {
+        &self.store
     }
 
     pub async fn add_user(
@@ -47,100 +181,456 @@ impl LocalUserProvider {
         password: String,
         email: Option<String>,
         display_name: Option<String>,
-    ) -> Result<(), argon2::password_hash::Error> {
-        let argon2 = Argon2::default();
-        let salt = SaltString::generate(&mut OsRng);
-        let password_hash = argon2
-            .hash_password(password.as_bytes(), &salt)?
-            .to_string();
-
+    ) -> IdentityResult<()> {
+        self.check_policy(&username, &password)?;
+        self.check_email_free(&username, email.as_deref()).await?;
         let user = LocalUser {
-            username: username.clone(),
-            password_hash,
+            username,
+            password_hash: self.argon2.hash(&password)?,
             email,
             display_name,
             metadata: None,
+            email_verified: false,
+            version: 0,
+            mfa: None,
         };
 
-        let mut users = self.users.write().await;
+        let username = user.username.clone();
+        self.store.insert(user).await?;
+        self.emit(IdentityEvent::new(IdentityEventKind::UserAdded).with_subject(&username));
+        Ok(())
+    }
 
-        users.insert(username, user);
+    pub async fn remove_user(&self, username: &str) -> IdentityResult<Option<LocalUser>> {
+        let removed = self.store.delete(username).await?;
+        if removed.is_some() {
+            self.emit(IdentityEvent::new(IdentityEventKind::UserRemoved).with_subject(username));
+        }
+        Ok(removed)
+    }
 
-        Ok(())
+    /// The user named `username`, without their password hash.
+    pub async fn get_user(&self, username: &str) -> IdentityResult<Option<PublicUser>> {
+        Ok(self.store.get(username).await?.map(PublicUser::from))
     }
 
-    pub async fn remove_user(&self, username: &str) -> Option<LocalUser> {
-        let mut users = self.users.write().await;
-        users.remove(username)
+    /// Up to `limit` users matching `filter`, ordered by username, after
+    /// skipping the first `offset`.
+    pub async fn list_users(
+        &self,
+        offset: usize,
+        limit: usize,
+        filter: &UserFilter,
+    ) -> IdentityResult<Page<PublicUser>> {
+        Ok(self
+            .store
+            .list(filter, offset, limit)
+            .await?
+            .map(PublicUser::from))
     }
 
-    async fn verify_user(&self, username: &str, password: &str) -> IdentityResult<LocalUser> {
-        let _semlock = self.semaphore.clone().acquire_owned().await.unwrap();
-        let users = self.users.read().await;
+    /// Replace a user's email, display name and metadata, returning the updated user.
+    ///
+    /// Fails with `VersionConflict` if the user changed since `profile.version`,
+    /// and with `EmailInUse` if emails are unique and another user has the new
+    /// one. Returns `None` if there is no such user.
+    pub async fn update_profile(
+        &self,
+        username: &str,
+        profile: UpdateProfile,
+    ) -> IdentityResult<Option<PublicUser>> {
+        let Some(user) = self.store.get(username).await? else {
+            return Ok(None);
+        };
+        if user.version != profile.version {
+            return Err(IdentityError::VersionConflict {
+                current: user.version,
+            });
+        }
+        self.check_email_free(username, profile.email.as_deref())
+            .await?;
 
-        // Use a dummy hash to prevent timing attacks
-        // This is a real Argon2 hash of "dummy_password" to ensure consistent timing
-        const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$9QsJRKgzJkKaOUvlp7gl2Q$qmE3qIFBNJ6nZYbLYXEI2uo0zZc7T0Q8LU1ZsqsZ3QE";
+        let updated = LocalUser {
+            email_verified: user.email_verified && user.email == profile.email,
+            email: profile.email,
+            display_name: profile.display_name,
+            metadata: profile.metadata,
+            ..user
+        };
+        if !self.store.update(updated.clone()).await? {
+            return Ok(None);
+        }
+        Ok(Some(PublicUser {
+            version: updated.version + 1,
+            ..updated.into()
+        }))
+    }
 
-        let (user_exists, password_hash) = if let Some(user) = users.get(username) {
-            (true, user.password_hash.as_str())
-        } else {
-            (false, DUMMY_HASH)
+    /// Start verifying a user's email, returning the token to send to it.
+    ///
+    /// Returns `None` if there is no such user or they have no email. Tokens
+    /// expire after the verification TTL and work once.
+    pub async fn create_email_verification(
+        &self,
+        username: &str,
+    ) -> IdentityResult<Option<VerificationToken>> {
+        let Some(user) = self.store.get(username).await? else {
+            return Ok(None);
+        };
+        let Some(email) = user.email else {
+            return Ok(None);
         };
 
-        let parsed_hash = PasswordHash::new(password_hash)
-            .map_err(|e| IdentityError::ProviderError(e.to_string()))?;
+        let now = chrono::Utc::now();
+        self.store.delete_expired_verifications(now).await?;
 
-        let password_valid = Argon2::default()
-            .verify_password(password.as_bytes(), &parsed_hash)
-            .is_ok();
+        let token = VerificationToken {
+            token: verification::new_token(),
+            expires_at: now + self.verification_ttl,
+        };
+        self.store
+            .insert_verification(EmailVerification {
+                token_hash: verification::hash_token(&token.token),
+                username: user.username,
+                email,
+                expires_at: token.expires_at,
+            })
+            .await?;
+        Ok(Some(token))
+    }
 
-        // Only succeed if both user exists AND password is valid
-        if user_exists && password_valid {
-            Ok(users.get(username).unwrap().clone())
+    /// Mark the email `token` was created for as verified, returning its user.
+    ///
+    /// Fails with `InvalidVerificationToken` if the token is unknown, expired,
+    /// already used, or its user has changed email since.
+    pub async fn verify_email(&self, token: &str) -> IdentityResult<PublicUser> {
+        let verification = self
+            .store
+            .take_verification(&verification::hash_token(token))
+            .await?
+            .ok_or(IdentityError::InvalidVerificationToken)?;
+        if verification.expires_at <= chrono::Utc::now() {
+            return Err(IdentityError::InvalidVerificationToken);
+        }
+
+        loop {
+            let user = self
+                .store
+                .get(&verification.username)
+                .await?
+                .filter(|user| user.email.as_ref() == Some(&verification.email))
+                .ok_or(IdentityError::InvalidVerificationToken)?;
+
+            let verified = LocalUser {
+                email_verified: true,
+                ..user
+            };
+            match self.store.update(verified.clone()).await {
+                Ok(true) => {
+                    return Ok(PublicUser {
+                        version: verified.version + 1,
+                        ..verified.into()
+                    });
+                }
+                Ok(false) => return Err(IdentityError::InvalidVerificationToken),
+                // Changed meanwhile; check the email again
+                Err(IdentityError::VersionConflict { .. }) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Change a user's password, given their current one.
+    ///
+    /// Fails with `InvalidCredentials` if the current password is wrong, and
+    /// with `WeakPassword` if the new one breaks the policy.
+    pub async fn change_password(
+        &self,
+        username: &str,
+        current_password: &str,
+        new_password: &str,
+    ) -> IdentityResult<()> {
+        let user = self.verify_user(username, current_password).await?;
+        self.check_policy(username, new_password)?;
+
+        let changed = LocalUser {
+            password_hash: self.argon2.hash(new_password)?,
+            ..user
+        };
+        if self.store.update(changed).await? {
+            self.emit(
+                IdentityEvent::new(IdentityEventKind::PasswordChanged).with_subject(username),
+            );
+            Ok(())
         } else {
-            // Always return the same error regardless of whether user exists or password is wrong
+            // Removed since it was verified
             Err(IdentityError::InvalidCredentials)
         }
     }
-}
 
-impl Default for LocalUserProvider {
-    fn default() -> Self {
-        Self::new()
+    /// Set a user's password without their current one, for administrators.
+    ///
+    /// Returns `false` if there is no such user.
+    pub async fn admin_set_password(
+        &self,
+        username: &str,
+        new_password: &str,
+    ) -> IdentityResult<bool> {
+        self.check_policy(username, new_password)?;
+        let Some(user) = self.store.get(username).await? else {
+            return Ok(false);
+        };
+
+        let changed = LocalUser {
+            password_hash: self.argon2.hash(new_password)?,
+            ..user
+        };
+        let updated = self.store.update(changed).await?;
+        if updated {
+            self.emit(
+                IdentityEvent::new(IdentityEventKind::PasswordChanged).with_subject(username),
+            );
+        }
+        Ok(updated)
     }
-}
 
-#[async_trait]
-impl IdentityProvider for LocalUserProvider {
-    fn provider_id(&self) -> &str {
-        "local"
+    /// Enroll a new TOTP authenticator for a user, replacing any they had.
+    ///
+    /// From then on logging in needs a code from it or one of the returned
+    /// backup codes. Fails with `ProviderError` unless the provider was given
+    /// an [`MfaConfig`]; returns `None` if there is no such user.
+    pub async fn enroll_totp(&self, username: &str) -> IdentityResult<Option<TotpSecret>> {
+        let config = self.mfa_config()?;
+        let (enrollment, secret) = config.enroll(username)?;
+        let enrolled = self
+            .modify_user(username, |user| {
+                user.mfa = Some(enrollment.clone());
+                Ok(())
+            })
+            .await?;
+        Ok(enrolled.map(|_| secret))
     }
 
-    async fn verify(&self, auth_payload: serde_json::Value) -> IdentityResult<VerifiedIdentity> {
-        let payload: LocalAuthPayload =
-            serde_json::from_value(auth_payload).map_err(|_| IdentityError::InvalidPayload)?;
+    /// Stop asking a user for a second factor.
+    ///
+    /// Returns `false` if there is no such user.
+    pub async fn disable_totp(&self, username: &str) -> IdentityResult<bool> {
+        let disabled = self
+            .modify_user(username, |user| {
+                user.mfa = None;
+                Ok(())
+            })
+            .await?;
+        Ok(disabled.is_some())
+    }
+
+    fn mfa_config(&self) -> IdentityResult<&MfaConfig> {
+        self.mfa
+            .as_ref()
+            .ok_or_else(|| IdentityError::ProviderError("MFA is not configured".into()))
+    }
 
+    /// The identity `payload` proves, without reporting the attempt
+    async fn verify_payload(&self, payload: &LocalAuthPayload) -> IdentityResult<VerifiedIdentity> {
         let user = self
             .verify_user(&payload.username, &payload.password)
             .await?;
+        let (user, amr) = if user.mfa.is_some() {
+            let user = self.verify_second_factor(payload).await?;
+            (user, ["pwd", "otp", "mfa"].as_slice())
+        } else {
+            (user, ["pwd"].as_slice())
+        };
 
         Ok(VerifiedIdentity {
             provider_id: self.provider_id().to_string(),
             subject: user.username,
             email: user.email,
             display_name: user.display_name,
-            metadata: user.metadata,
+            metadata: Some(verification::identity_metadata(
+                user.metadata,
+                user.email_verified,
+                amr,
+            )),
         })
     }
+
+    fn emit(&self, event: IdentityEvent) {
+        if let Some(audit) = &self.audit {
+            audit.emit(event.with_provider(self.provider_id()));
+        }
+    }
+
+    /// Check the second factor in `payload` for its user, who has MFA enabled,
+    /// using up the code so it can't be replayed.
+    async fn verify_second_factor(&self, payload: &LocalAuthPayload) -> IdentityResult<LocalUser> {
+        if payload.totp_code.is_none() && payload.backup_code.is_none() {
+            return Err(IdentityError::MfaRequired);
+        }
+        let config = self.mfa_config()?;
+        let now = u64::try_from(chrono::Utc::now().timestamp()).unwrap_or(0);
+
+        let verified = self
+            .modify_user(&payload.username, |user| {
+                let Some(enrollment) = user.mfa.as_mut() else {
+                    // Disabled since the password was checked
+                    return Ok(());
+                };
+                if let Some(code) = &payload.totp_code {
+                    let step = config.accept_code(&user.username, enrollment, code, now)?;
+                    enrollment.last_used_step = Some(step.ok_or(IdentityError::MfaInvalid)?);
+                } else if let Some(code) = &payload.backup_code {
+                    let index =
+                        mfa::find_backup_code(enrollment, code).ok_or(IdentityError::MfaInvalid)?;
+                    enrollment.backup_code_hashes.remove(index);
+                }
+                Ok(())
+            })
+            .await?;
+        // Removed since the password was checked
+        verified.ok_or(IdentityError::InvalidCredentials)
+    }
+
+    /// Apply `change` to the user named `username` and store them, starting
+    /// over if they change meanwhile. Returns the stored user, or `None` if
+    /// there is no such user.
+    async fn modify_user(
+        &self,
+        username: &str,
+        mut change: impl FnMut(&mut LocalUser) -> IdentityResult<()>,
+    ) -> IdentityResult<Option<LocalUser>> {
+        loop {
+            let Some(mut user) = self.store.get(username).await? else {
+                return Ok(None);
+            };
+            change(&mut user)?;
+            match self.store.update(user.clone()).await {
+                Ok(true) => {
+                    user.version += 1;
+                    return Ok(Some(user));
+                }
+                Ok(false) => return Ok(None),
+                Err(IdentityError::VersionConflict { .. }) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Fail with `EmailInUse` if emails are unique and a user other than
+    /// `username` has `email`
+    async fn check_email_free(&self, username: &str, email: Option<&str>) -> IdentityResult<()> {
+        let Some(email) = email.filter(|_| self.unique_emails) else {
+            return Ok(());
+        };
+        let filter = UserFilter {
+            email: Some(email.to_string()),
+            ..UserFilter::default()
+        };
+        let holders = self.store.list(&filter, 0, 2).await?;
+        if holders.items.iter().any(|user| user.username != username) {
+            Err(IdentityError::EmailInUse)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_policy(&self, username: &str, password: &str) -> IdentityResult<()> {
+        match &self.policy {
+            Some(policy) => policy.check(username, password),
+            None => Ok(()),
+        }
+    }
+
+    async fn verify_user(&self, username: &str, password: &str) -> IdentityResult<LocalUser> {
+        let _semlock = self.semaphore.clone().acquire_owned().await.unwrap();
+        let user = self.store.get(username).await?;
+
+        // Check a dummy hash for unknown users so they take as long as wrong passwords
+        let password_hash = user
+            .as_ref()
+            .map_or(&*self.dummy_hash, |user| user.password_hash.as_str());
+        let check = self.argon2.check(password_hash, password)?;
+
+        // Only succeed if both user exists AND password is valid
+        match (user, check) {
+            (Some(user), PasswordCheck::Match) => Ok(user),
+            (Some(user), PasswordCheck::Outdated) => Ok(self.upgrade_hash(user, password).await),
+            // Always return the same error regardless of whether user exists or password is wrong
+            _ => Err(IdentityError::InvalidCredentials),
+        }
+    }
+
+    /// Replace `user`'s hash with one made with the current parameters.
+    ///
+    /// The login succeeds even if the store can't be written; the upgrade is
+    /// retried on the next one.
+    async fn upgrade_hash(&self, user: LocalUser, password: &str) -> LocalUser {
+        let upgraded = match self.argon2.hash(password) {
+            Ok(password_hash) => LocalUser {
+                password_hash,
+                ..user.clone()
+            },
+            Err(e) => {
+                warn!(username = %user.username, "Failed to re-hash password: {}", e);
+                return user;
+            }
+        };
+
+        match self.store.update(upgraded.clone()).await {
+            Ok(_) => upgraded,
+            Err(e) => {
+                warn!(username = %user.username, "Failed to store upgraded password hash: {}", e);
+                user
+            }
+        }
+    }
+}
+
+impl Default for LocalUserProvider {
+    /// A provider keeping its users in memory, with the default Argon2 parameters
+    fn default() -> Self {
+        Self::new(Arc::new(MemoryUserStore::new()), Argon2Config::default())
+            .expect("the default Argon2 parameters are valid")
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for LocalUserProvider {
+    fn provider_id(&self) -> &str {
+        "local"
+    }
+
+    async fn verify(&self, auth_payload: serde_json::Value) -> IdentityResult<VerifiedIdentity> {
+        let payload: LocalAuthPayload = match serde_json::from_value(auth_payload) {
+            Ok(payload) => payload,
+            Err(_) => {
+                let error = IdentityError::InvalidPayload;
+                self.emit(IdentityEvent::new(IdentityEventKind::VerificationFailed {
+                    reason: error.to_string(),
+                }));
+                return Err(error);
+            }
+        };
+
+        let verified = self.verify_payload(&payload).await;
+        let kind = match &verified {
+            Ok(_) => IdentityEventKind::VerificationSucceeded,
+            Err(error) => IdentityEventKind::VerificationFailed {
+                reason: error.to_string(),
+            },
+        };
+        self.emit(IdentityEvent::new(kind).with_subject(&payload.username));
+        verified
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ras_identity_core::PolicyViolation;
 
-    async fn setup_test_provider() -> LocalUserProvider {
-        let provider = LocalUserProvider::new();
+    async fn setup_test_provider(store: Arc<dyn UserStore>) -> LocalUserProvider {
+        let provider = LocalUserProvider::new(store, Argon2Config::default()).unwrap();
 
         // Add test users
         provider
@@ -166,9 +656,8 @@ mod tests {
         provider
     }
 
-    #[tokio::test]
-    async fn test_basic_authentication_success() {
-        let provider = setup_test_provider().await;
+    async fn test_basic_authentication_success(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
 
         let auth_payload = serde_json::json!({
             "username": "testuser",
@@ -183,9 +672,8 @@ mod tests {
         assert_eq!(identity.provider_id, "local");
     }
 
-    #[tokio::test]
-    async fn test_wrong_password_fails() {
-        let provider = setup_test_provider().await;
+    async fn test_wrong_password_fails(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
 
         let bad_payload = serde_json::json!({
             "username": "testuser",
@@ -201,9 +689,8 @@ mod tests {
         }
     }
 
-    #[tokio::test]
-    async fn test_username_enumeration_prevention() {
-        let provider = setup_test_provider().await;
+    async fn test_username_enumeration_prevention(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
 
         // Test with non-existent username
         let nonexistent_user_payload = serde_json::json!({
@@ -236,12 +723,10 @@ mod tests {
     }
 
     #[cfg(feature = "timing-tests")]
-    #[tokio::test]
-    #[ignore = "Timing test disabled - see issue with Argon2 parameter differences"]
-    async fn test_timing_attack_resistance() {
+    async fn test_timing_attack_resistance(store: Arc<dyn UserStore>) {
         use std::time::{Duration, Instant};
 
-        let provider = setup_test_provider().await;
+        let provider = setup_test_provider(store).await;
 
         const NUM_ATTEMPTS: usize = 10;
         let mut nonexistent_times = Vec::new();
@@ -299,9 +784,8 @@ mod tests {
     }
 
     #[cfg(feature = "timing-tests")]
-    #[tokio::test]
-    async fn test_brute_force_simulation() {
-        let provider = setup_test_provider().await;
+    async fn test_brute_force_simulation(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
 
         const ATTACK_ATTEMPTS: usize = 50;
         let mut consecutive_failures = 0;
@@ -336,9 +820,8 @@ mod tests {
         );
     }
 
-    #[tokio::test]
-    async fn test_malformed_payload_handling() {
-        let provider = setup_test_provider().await;
+    async fn test_malformed_payload_handling(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
 
         // Test with missing username
         let missing_username = serde_json::json!({
@@ -376,9 +859,8 @@ mod tests {
         assert!(matches!(result.unwrap_err(), IdentityError::InvalidPayload));
     }
 
-    #[tokio::test]
-    async fn test_empty_credentials() {
-        let provider = setup_test_provider().await;
+    async fn test_empty_credentials(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
 
         // Test with empty username
         let empty_username = serde_json::json!({
@@ -420,9 +902,8 @@ mod tests {
         ));
     }
 
-    #[tokio::test]
-    async fn test_special_characters_in_credentials() {
-        let provider = LocalUserProvider::new();
+    async fn test_special_characters_in_credentials(store: Arc<dyn UserStore>) {
+        let provider = LocalUserProvider::new(store, Argon2Config::default()).unwrap();
 
         // Add user with special characters in username and password
         provider
@@ -458,9 +939,8 @@ mod tests {
         ));
     }
 
-    #[tokio::test]
-    async fn test_very_long_credentials() {
-        let provider = setup_test_provider().await;
+    async fn test_very_long_credentials(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
 
         // Test with extremely long username
         let long_username = "a".repeat(10000);
@@ -491,9 +971,8 @@ mod tests {
         ));
     }
 
-    #[tokio::test]
-    async fn test_concurrent_authentication_attempts() {
-        let provider = setup_test_provider().await;
+    async fn test_concurrent_authentication_attempts(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
         let provider = Arc::new(provider);
 
         const CONCURRENT_ATTEMPTS: usize = 20;
@@ -538,4 +1017,1183 @@ mod tests {
         assert_eq!(successful_auths, CONCURRENT_ATTEMPTS / 2);
         assert_eq!(failed_auths, CONCURRENT_ATTEMPTS / 2);
     }
+
+    fn user(username: &str, email: &str) -> LocalUser {
+        LocalUser {
+            username: username.to_string(),
+            password_hash: "not-a-real-hash".to_string(),
+            email: Some(email.to_string()),
+            display_name: None,
+            metadata: Some(serde_json::json!({ "roles": ["reader"] })),
+            email_verified: false,
+            version: 0,
+            mfa: None,
+        }
+    }
+
+    async fn test_store_operations(store: Arc<dyn UserStore>) {
+        assert_eq!(store.get("carol").await.unwrap(), None);
+
+        store
+            .insert(user("carol", "carol@example.com"))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get("carol").await.unwrap(),
+            Some(user("carol", "carol@example.com"))
+        );
+
+        // Inserting again replaces the user
+        store
+            .insert(user("carol", "carol@example.org"))
+            .await
+            .unwrap();
+        let stored = store.get("carol").await.unwrap().unwrap();
+        assert_eq!(stored.email.as_deref(), Some("carol@example.org"));
+
+        assert!(store.update(user("carol", "c@example.com")).await.unwrap());
+        assert!(
+            !store
+                .update(user("dave", "dave@example.com"))
+                .await
+                .unwrap()
+        );
+        assert_eq!(store.get("dave").await.unwrap(), None);
+        let stored = store.get("carol").await.unwrap().unwrap();
+        assert_eq!(stored.email.as_deref(), Some("c@example.com"));
+        assert_eq!(stored.version, 1);
+
+        // An update made from an older version is refused
+        assert!(matches!(
+            store.update(user("carol", "stale@example.com")).await,
+            Err(IdentityError::VersionConflict { current: 1 })
+        ));
+        let stored = store.get("carol").await.unwrap().unwrap();
+        assert_eq!(stored.email.as_deref(), Some("c@example.com"));
+
+        assert_eq!(
+            store.delete("carol").await.unwrap(),
+            Some(LocalUser {
+                version: 1,
+                ..user("carol", "c@example.com")
+            })
+        );
+        assert_eq!(store.delete("carol").await.unwrap(), None);
+        assert_eq!(store.get("carol").await.unwrap(), None);
+    }
+
+    async fn test_store_pagination(store: Arc<dyn UserStore>) {
+        for name in ["erin", "bob", "dave", "alice", "carol"] {
+            store.insert(user(name, "user@example.com")).await.unwrap();
+        }
+
+        let all = UserFilter::default();
+        let names = |page: Page<LocalUser>| {
+            assert_eq!(page.total, 5);
+            page.items
+                .into_iter()
+                .map(|user| user.username)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(store.list(&all, 0, 2).await.unwrap()),
+            ["alice", "bob"]
+        );
+        assert_eq!(
+            names(store.list(&all, 2, 2).await.unwrap()),
+            ["carol", "dave"]
+        );
+        assert_eq!(names(store.list(&all, 4, 2).await.unwrap()), ["erin"]);
+        assert!(names(store.list(&all, 5, 2).await.unwrap()).is_empty());
+        assert_eq!(
+            names(store.list(&all, 0, usize::MAX).await.unwrap()).len(),
+            5
+        );
+    }
+
+    async fn test_store_filters(store: Arc<dyn UserStore>) {
+        for (name, email) in [
+            ("anna", "anna@example.com"),
+            ("annabel", "Shared@Example.com"),
+            ("ann", "ann@example.com"),
+            ("bob", "shared@example.com"),
+            ("carl", "carl@example.com"),
+        ] {
+            store.insert(user(name, email)).await.unwrap();
+        }
+        store
+            .insert(LocalUser {
+                email: None,
+                ..user("annie", "")
+            })
+            .await
+            .unwrap();
+
+        let list = |filter: UserFilter, offset, limit| {
+            let store = store.clone();
+            async move {
+                let page = store.list(&filter, offset, limit).await.unwrap();
+                let names = page
+                    .items
+                    .into_iter()
+                    .map(|user| user.username)
+                    .collect::<Vec<_>>();
+                (names, page.total)
+            }
+        };
+        let prefix = |prefix: &str| UserFilter {
+            username_prefix: Some(prefix.to_string()),
+            ..UserFilter::default()
+        };
+        let email = |email: &str| UserFilter {
+            email: Some(email.to_string()),
+            ..UserFilter::default()
+        };
+
+        assert_eq!(
+            list(prefix("ann"), 0, 10).await,
+            (
+                vec![
+                    "ann".into(),
+                    "anna".into(),
+                    "annabel".into(),
+                    "annie".into()
+                ],
+                4
+            )
+        );
+        assert_eq!(
+            list(prefix("ann"), 1, 2).await,
+            (vec!["anna".into(), "annabel".into()], 4)
+        );
+        assert_eq!(list(prefix("ANN"), 0, 10).await, (vec![], 0));
+        assert_eq!(
+            list(email("SHARED@example.COM"), 0, 10).await,
+            (vec!["annabel".into(), "bob".into()], 2)
+        );
+        assert_eq!(
+            list(
+                UserFilter {
+                    username_prefix: Some("b".to_string()),
+                    email: Some("shared@example.com".to_string()),
+                },
+                0,
+                10
+            )
+            .await,
+            (vec!["bob".into()], 1)
+        );
+        assert_eq!(list(email("nobody@example.com"), 0, 10).await, (vec![], 0));
+    }
+
+    async fn test_removed_user_cannot_authenticate(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
+
+        let removed = provider.remove_user("alice").await.unwrap().unwrap();
+        assert_eq!(removed.username, "alice");
+        assert_eq!(provider.remove_user("alice").await.unwrap(), None);
+
+        let payload = serde_json::json!({
+            "username": "alice",
+            "password": "supersecret"
+        });
+        assert!(matches!(
+            provider.verify(payload).await.unwrap_err(),
+            IdentityError::InvalidCredentials
+        ));
+    }
+
+    async fn authenticates(provider: &LocalUserProvider, username: &str, password: &str) -> bool {
+        let payload = serde_json::json!({ "username": username, "password": password });
+        provider.verify(payload).await.is_ok()
+    }
+
+    fn assert_weak(result: IdentityResult<impl std::fmt::Debug>, expected: &[PolicyViolation]) {
+        match result {
+            Err(IdentityError::WeakPassword(violations)) => assert_eq!(violations, expected),
+            other => panic!("Expected WeakPassword, got: {:?}", other),
+        }
+    }
+
+    async fn test_weak_passwords_are_refused(store: Arc<dyn UserStore>) {
+        let provider = LocalUserProvider::new(store.clone(), Argon2Config::default()).unwrap();
+
+        let result = provider
+            .add_user("carol".to_string(), "short".to_string(), None, None)
+            .await;
+        assert_weak(result, &[PolicyViolation::TooShort { min: 8 }]);
+        assert_eq!(store.get("carol").await.unwrap(), None);
+
+        let strict = PasswordPolicy {
+            reject_username: true,
+            ..PasswordPolicy::default()
+        };
+        let provider = provider.with_password_policy(Some(strict));
+        let result = provider
+            .add_user("carol".to_string(), "carol-2024".to_string(), None, None)
+            .await;
+        assert_weak(result, &[PolicyViolation::ContainsUsername]);
+
+        // Without a policy anything goes
+        let provider = provider.with_password_policy(None);
+        provider
+            .add_user("carol".to_string(), "x".to_string(), None, None)
+            .await
+            .unwrap();
+        assert!(authenticates(&provider, "carol", "x").await);
+    }
+
+    async fn test_change_password(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
+
+        assert!(matches!(
+            provider
+                .change_password("alice", "wrong-password", "brand-new-secret")
+                .await,
+            Err(IdentityError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            provider
+                .change_password("nobody", "supersecret", "brand-new-secret")
+                .await,
+            Err(IdentityError::InvalidCredentials)
+        ));
+        assert_weak(
+            provider
+                .change_password("alice", "supersecret", "tiny")
+                .await,
+            &[PolicyViolation::TooShort { min: 8 }],
+        );
+        assert!(authenticates(&provider, "alice", "supersecret").await);
+
+        provider
+            .change_password("alice", "supersecret", "brand-new-secret")
+            .await
+            .unwrap();
+        assert!(authenticates(&provider, "alice", "brand-new-secret").await);
+        assert!(!authenticates(&provider, "alice", "supersecret").await);
+
+        // The rest of the user is kept
+        let identity = provider
+            .verify(serde_json::json!({ "username": "alice", "password": "brand-new-secret" }))
+            .await
+            .unwrap();
+        assert_eq!(identity.email.as_deref(), Some("alice@example.com"));
+    }
+
+    async fn test_admin_set_password(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
+
+        assert!(
+            !provider
+                .admin_set_password("nobody", "brand-new-secret")
+                .await
+                .unwrap()
+        );
+        assert_weak(
+            provider.admin_set_password("alice", &"x".repeat(513)).await,
+            &[PolicyViolation::TooLong { max: 512 }],
+        );
+        assert!(authenticates(&provider, "alice", "supersecret").await);
+
+        assert!(
+            provider
+                .admin_set_password("alice", "brand-new-secret")
+                .await
+                .unwrap()
+        );
+        assert!(authenticates(&provider, "alice", "brand-new-secret").await);
+        assert!(!authenticates(&provider, "alice", "supersecret").await);
+    }
+
+    async fn test_get_and_list_users(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
+
+        let alice = provider.get_user("alice").await.unwrap().unwrap();
+        assert_eq!(
+            alice,
+            PublicUser {
+                username: "alice".to_string(),
+                email: Some("alice@example.com".to_string()),
+                display_name: Some("Alice Smith".to_string()),
+                metadata: None,
+                email_verified: false,
+                version: 0,
+                mfa_enabled: false,
+            }
+        );
+        assert_eq!(provider.get_user("nobody").await.unwrap(), None);
+
+        let page = provider
+            .list_users(0, 1, &UserFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items, [alice]);
+
+        let filter = UserFilter {
+            username_prefix: Some("test".to_string()),
+            ..UserFilter::default()
+        };
+        let page = provider.list_users(0, 10, &filter).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].username, "testuser");
+    }
+
+    async fn test_update_profile(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
+        let profile = UpdateProfile {
+            email: Some("alice@example.org".to_string()),
+            display_name: None,
+            metadata: Some(serde_json::json!({ "team": "blue" })),
+            version: 0,
+        };
+
+        assert_eq!(
+            provider
+                .update_profile("nobody", profile.clone())
+                .await
+                .unwrap(),
+            None
+        );
+
+        let updated = provider
+            .update_profile("alice", profile.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.email.as_deref(), Some("alice@example.org"));
+        assert_eq!(updated.display_name, None);
+        assert_eq!(updated.version, 1);
+        assert_eq!(provider.get_user("alice").await.unwrap(), Some(updated));
+
+        // A second update made from the same version loses
+        assert!(matches!(
+            provider.update_profile("alice", profile).await,
+            Err(IdentityError::VersionConflict { current: 1 })
+        ));
+
+        // The password is untouched
+        assert!(authenticates(&provider, "alice", "supersecret").await);
+    }
+
+    async fn test_unique_emails(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
+
+        // Not enforced unless asked for
+        provider
+            .add_user(
+                "twin".to_string(),
+                "password123".to_string(),
+                Some("alice@example.com".to_string()),
+                None,
+            )
+            .await
+            .unwrap();
+        provider.remove_user("twin").await.unwrap();
+
+        let provider = provider.with_unique_emails(true);
+        assert!(matches!(
+            provider
+                .add_user(
+                    "twin".to_string(),
+                    "password123".to_string(),
+                    Some("ALICE@example.com".to_string()),
+                    None,
+                )
+                .await,
+            Err(IdentityError::EmailInUse)
+        ));
+        assert_eq!(provider.get_user("twin").await.unwrap(), None);
+
+        let taken = UpdateProfile {
+            email: Some("alice@example.com".to_string()),
+            ..UpdateProfile::default()
+        };
+        assert!(matches!(
+            provider.update_profile("testuser", taken.clone()).await,
+            Err(IdentityError::EmailInUse)
+        ));
+
+        // Users may keep their own email
+        let updated = provider.update_profile("alice", taken).await.unwrap();
+        assert_eq!(updated.unwrap().display_name, None);
+    }
+
+    async fn test_store_verifications(store: Arc<dyn UserStore>) {
+        let now = chrono::Utc::now();
+        let verification = |token_hash: &str, expires_at| EmailVerification {
+            token_hash: token_hash.to_string(),
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            expires_at,
+        };
+        let live = verification("live", now + chrono::Duration::hours(1));
+        store.insert_verification(live.clone()).await.unwrap();
+        store
+            .insert_verification(verification("stale", now - chrono::Duration::hours(1)))
+            .await
+            .unwrap();
+
+        assert_eq!(store.delete_expired_verifications(now).await.unwrap(), 1);
+        assert_eq!(store.take_verification("stale").await.unwrap(), None);
+
+        let taken = store.take_verification("live").await.unwrap().unwrap();
+        assert_eq!(taken.username, live.username);
+        assert_eq!(taken.email, live.email);
+        assert_eq!(
+            taken.expires_at.timestamp_millis(),
+            live.expires_at.timestamp_millis()
+        );
+        assert_eq!(store.take_verification("live").await.unwrap(), None);
+    }
+
+    struct VerifiedEmailsOnly;
+
+    #[async_trait]
+    impl ras_identity_core::UserPermissions for VerifiedEmailsOnly {
+        async fn get_permissions(
+            &self,
+            identity: &VerifiedIdentity,
+        ) -> IdentityResult<Vec<String>> {
+            if is_email_verified(identity) {
+                Ok(vec!["post".to_string()])
+            } else {
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    async fn test_email_verification(store: Arc<dyn UserStore>) {
+        use ras_identity_core::UserPermissions;
+
+        let provider = setup_test_provider(store).await;
+        let login = serde_json::json!({ "username": "alice", "password": "supersecret" });
+
+        let identity = provider.verify(login.clone()).await.unwrap();
+        assert!(!is_email_verified(&identity));
+        assert!(
+            VerifiedEmailsOnly
+                .get_permissions(&identity)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let token = provider
+            .create_email_verification("alice")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(token.expires_at > chrono::Utc::now() + chrono::Duration::hours(23));
+
+        let user = provider.verify_email(&token.token).await.unwrap();
+        assert!(user.email_verified);
+        assert_eq!(provider.get_user("alice").await.unwrap(), Some(user));
+
+        let identity = provider.verify(login).await.unwrap();
+        assert!(is_email_verified(&identity));
+        assert_eq!(
+            VerifiedEmailsOnly.get_permissions(&identity).await.unwrap(),
+            ["post"]
+        );
+
+        // Tokens work once
+        assert!(matches!(
+            provider.verify_email(&token.token).await,
+            Err(IdentityError::InvalidVerificationToken)
+        ));
+        assert!(matches!(
+            provider.verify_email("made-up").await,
+            Err(IdentityError::InvalidVerificationToken)
+        ));
+
+        assert_eq!(
+            provider.create_email_verification("nobody").await.unwrap(),
+            None
+        );
+        provider
+            .add_user("noemail".to_string(), "password123".to_string(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            provider.create_email_verification("noemail").await.unwrap(),
+            None
+        );
+    }
+
+    async fn test_expired_verification_is_refused(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store)
+            .await
+            .with_verification_ttl(chrono::Duration::seconds(-1));
+
+        let token = provider
+            .create_email_verification("alice")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            provider.verify_email(&token.token).await,
+            Err(IdentityError::InvalidVerificationToken)
+        ));
+        assert!(
+            !provider
+                .get_user("alice")
+                .await
+                .unwrap()
+                .unwrap()
+                .email_verified
+        );
+    }
+
+    async fn test_changing_email_needs_new_verification(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
+        let change_email = |email: &str, version| UpdateProfile {
+            email: Some(email.to_string()),
+            version,
+            ..UpdateProfile::default()
+        };
+
+        let token = provider
+            .create_email_verification("alice")
+            .await
+            .unwrap()
+            .unwrap();
+        let user = provider.verify_email(&token.token).await.unwrap();
+
+        // Keeping the email keeps it verified
+        let user = provider
+            .update_profile("alice", change_email("alice@example.com", user.version))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(user.email_verified);
+
+        let user = provider
+            .update_profile("alice", change_email("alice@example.org", user.version))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!user.email_verified);
+
+        // A token for the old email doesn't verify the new one
+        let token = provider
+            .create_email_verification("alice")
+            .await
+            .unwrap()
+            .unwrap();
+        provider
+            .update_profile("alice", change_email("alice@example.net", user.version))
+            .await
+            .unwrap();
+        assert!(matches!(
+            provider.verify_email(&token.token).await,
+            Err(IdentityError::InvalidVerificationToken)
+        ));
+        assert!(
+            !provider
+                .get_user("alice")
+                .await
+                .unwrap()
+                .unwrap()
+                .email_verified
+        );
+    }
+
+    async fn test_verification_tokens_are_single_use_under_concurrency(store: Arc<dyn UserStore>) {
+        let provider = setup_test_provider(store).await;
+        let token = provider
+            .create_email_verification("alice")
+            .await
+            .unwrap()
+            .unwrap();
+
+        let attempts = (0..16).map(|_| {
+            let provider = provider.clone();
+            let token = token.token.clone();
+            tokio::spawn(async move { provider.verify_email(&token).await })
+        });
+        let mut verified = 0;
+        for attempt in attempts.collect::<Vec<_>>() {
+            match attempt.await.unwrap() {
+                Ok(_) => verified += 1,
+                Err(IdentityError::InvalidVerificationToken) => {}
+                Err(other) => panic!("Unexpected error: {:?}", other),
+            }
+        }
+        assert_eq!(verified, 1);
+    }
+
+    async fn mfa_provider(store: Arc<dyn UserStore>) -> (LocalUserProvider, TotpSecret) {
+        let provider = setup_test_provider(store)
+            .await
+            .with_mfa(MfaConfig::new(MfaKey::generate(), "Agent Stack"));
+        let secret = provider.enroll_totp("alice").await.unwrap().unwrap();
+        (provider, secret)
+    }
+
+    fn unix_now() -> u64 {
+        chrono::Utc::now().timestamp() as u64
+    }
+
+    /// A code none of the steps around now would accept
+    fn wrong_code(secret: &TotpSecret) -> String {
+        let now = unix_now();
+        let valid = [now - 60, now - 30, now, now + 30, now + 60].map(|t| mfa::code_at(secret, t));
+        (0..)
+            .map(|n| format!("{:06}", n))
+            .find(|code| !valid.contains(code))
+            .unwrap()
+    }
+
+    async fn test_totp_login(store: Arc<dyn UserStore>) {
+        let (provider, secret) = mfa_provider(store).await;
+        assert_eq!(provider.enroll_totp("nobody").await.unwrap(), None);
+        assert!(
+            provider
+                .get_user("alice")
+                .await
+                .unwrap()
+                .unwrap()
+                .mfa_enabled
+        );
+
+        let password_only = serde_json::json!({ "username": "alice", "password": "supersecret" });
+        assert!(matches!(
+            provider.verify(password_only).await,
+            Err(IdentityError::MfaRequired)
+        ));
+
+        // The password is checked first
+        let code = mfa::code_at(&secret, unix_now());
+        let wrong_password = serde_json::json!({
+            "username": "alice",
+            "password": "wrong",
+            "totp_code": code
+        });
+        assert!(matches!(
+            provider.verify(wrong_password).await,
+            Err(IdentityError::InvalidCredentials)
+        ));
+
+        let wrong = serde_json::json!({
+            "username": "alice",
+            "password": "supersecret",
+            "totp_code": wrong_code(&secret)
+        });
+        assert!(matches!(
+            provider.verify(wrong).await,
+            Err(IdentityError::MfaInvalid)
+        ));
+
+        let right = serde_json::json!({
+            "username": "alice",
+            "password": "supersecret",
+            "totp_code": code
+        });
+        let identity = provider.verify(right.clone()).await.unwrap();
+        assert_eq!(identity.subject, "alice");
+        assert_eq!(identity.amr(), ["pwd", "otp", "mfa"]);
+
+        // A code works once
+        assert!(matches!(
+            provider.verify(right).await,
+            Err(IdentityError::MfaInvalid)
+        ));
+
+        // The next one is accepted a step early
+        let next = serde_json::json!({
+            "username": "alice",
+            "password": "supersecret",
+            "totp_code": mfa::code_at(&secret, unix_now() + 30)
+        });
+        assert!(provider.verify(next).await.is_ok());
+
+        // Users without MFA log in with their password alone
+        let other = serde_json::json!({ "username": "testuser", "password": "password123" });
+        assert_eq!(provider.verify(other).await.unwrap().amr(), ["pwd"]);
+    }
+
+    async fn test_totp_codes_are_single_use_under_concurrency(store: Arc<dyn UserStore>) {
+        let (provider, secret) = mfa_provider(store).await;
+        let payload = serde_json::json!({
+            "username": "alice",
+            "password": "supersecret",
+            "totp_code": mfa::code_at(&secret, unix_now())
+        });
+
+        let attempts = (0..8).map(|_| {
+            let provider = provider.clone();
+            let payload = payload.clone();
+            tokio::spawn(async move { provider.verify(payload).await })
+        });
+        let mut verified = 0;
+        for attempt in attempts.collect::<Vec<_>>() {
+            match attempt.await.unwrap() {
+                Ok(_) => verified += 1,
+                Err(IdentityError::MfaInvalid) => {}
+                Err(other) => panic!("Unexpected error: {:?}", other),
+            }
+        }
+        assert_eq!(verified, 1);
+    }
+
+    async fn test_backup_codes_are_single_use(store: Arc<dyn UserStore>) {
+        let (provider, secret) = mfa_provider(store).await;
+        let with_backup_code = |code: &str| {
+            serde_json::json!({
+                "username": "alice",
+                "password": "supersecret",
+                "backup_code": code
+            })
+        };
+
+        let identity = provider
+            .verify(with_backup_code(&secret.backup_codes[0]))
+            .await
+            .unwrap();
+        assert_eq!(identity.amr(), ["pwd", "otp", "mfa"]);
+        assert!(matches!(
+            provider
+                .verify(with_backup_code(&secret.backup_codes[0]))
+                .await,
+            Err(IdentityError::MfaInvalid)
+        ));
+        assert!(matches!(
+            provider.verify(with_backup_code("aaaaa-aaaaa")).await,
+            Err(IdentityError::MfaInvalid)
+        ));
+        assert!(
+            provider
+                .verify(with_backup_code(&secret.backup_codes[1]))
+                .await
+                .is_ok()
+        );
+    }
+
+    async fn test_disable_totp(store: Arc<dyn UserStore>) {
+        let (provider, _) = mfa_provider(store).await;
+        assert!(!provider.disable_totp("nobody").await.unwrap());
+
+        assert!(provider.disable_totp("alice").await.unwrap());
+        assert!(
+            !provider
+                .get_user("alice")
+                .await
+                .unwrap()
+                .unwrap()
+                .mfa_enabled
+        );
+        let identity = provider
+            .verify(serde_json::json!({ "username": "alice", "password": "supersecret" }))
+            .await
+            .unwrap();
+        assert_eq!(identity.amr(), ["pwd"]);
+    }
+
+    async fn test_audit_events(store: Arc<dyn UserStore>) {
+        let sink = Arc::new(ras_identity_core::MemoryEventSink::new());
+        let provider = LocalUserProvider::new(store, Argon2Config::default())
+            .unwrap()
+            .with_event_sink(sink.clone());
+
+        provider
+            .add_user("alice".to_string(), "supersecret".to_string(), None, None)
+            .await
+            .unwrap();
+        let login =
+            |password: &str| serde_json::json!({ "username": "alice", "password": password });
+        assert!(provider.verify(login("wrong")).await.is_err());
+        assert!(provider.verify(login("supersecret")).await.is_ok());
+        provider
+            .change_password("alice", "supersecret", "even-more-secret")
+            .await
+            .unwrap();
+        assert!(
+            provider
+                .admin_set_password("alice", "reset-secret")
+                .await
+                .unwrap()
+        );
+        provider.remove_user("alice").await.unwrap();
+        assert!(provider.remove_user("alice").await.unwrap().is_none());
+        assert!(provider.verify(serde_json::json!({})).await.is_err());
+
+        let events = sink.wait_for(7).await;
+        let kinds: Vec<_> = events.iter().map(|event| event.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [
+                IdentityEventKind::UserAdded,
+                IdentityEventKind::VerificationFailed {
+                    reason: "Invalid credentials".to_string()
+                },
+                IdentityEventKind::VerificationSucceeded,
+                IdentityEventKind::PasswordChanged,
+                IdentityEventKind::PasswordChanged,
+                IdentityEventKind::UserRemoved,
+                IdentityEventKind::VerificationFailed {
+                    reason: "Invalid authentication payload".to_string()
+                },
+            ]
+        );
+        assert!(
+            events
+                .iter()
+                .all(|event| event.provider_id.as_deref() == Some("local"))
+        );
+        assert!(
+            events[..6]
+                .iter()
+                .all(|event| event.subject.as_deref() == Some("alice"))
+        );
+        assert_eq!(events[6].subject, None);
+        assert_eq!(provider.dropped_events(), 0);
+    }
+
+    fn hash_params(hash: &str) -> (u32, u32, u32) {
+        let hash = argon2::PasswordHash::new(hash).unwrap();
+        let params = argon2::Params::try_from(&hash).unwrap();
+        (params.m_cost(), params.t_cost(), params.p_cost())
+    }
+
+    async fn stored_hash(store: &Arc<dyn UserStore>, username: &str) -> String {
+        store.get(username).await.unwrap().unwrap().password_hash
+    }
+
+    async fn test_weaker_hash_is_upgraded_on_login(store: Arc<dyn UserStore>) {
+        let weak = Argon2Config {
+            memory_kib: Argon2Config::MIN_MEMORY_KIB,
+            iterations: 1,
+            parallelism: 1,
+        };
+        LocalUserProvider::new(store.clone(), weak)
+            .unwrap()
+            .add_user("alice".to_string(), "supersecret".to_string(), None, None)
+            .await
+            .unwrap();
+        let provider = LocalUserProvider::new(store.clone(), Argon2Config::default()).unwrap();
+
+        // A wrong password leaves the hash alone
+        let wrong = serde_json::json!({ "username": "alice", "password": "wrong" });
+        assert!(matches!(
+            provider.verify(wrong).await.unwrap_err(),
+            IdentityError::InvalidCredentials
+        ));
+        assert_eq!(
+            hash_params(&stored_hash(&store, "alice").await),
+            (Argon2Config::MIN_MEMORY_KIB, 1, 1)
+        );
+
+        let right = serde_json::json!({ "username": "alice", "password": "supersecret" });
+        assert_eq!(
+            provider.verify(right.clone()).await.unwrap().subject,
+            "alice"
+        );
+        let default = Argon2Config::default();
+        assert_eq!(
+            hash_params(&stored_hash(&store, "alice").await),
+            (default.memory_kib, default.iterations, default.parallelism)
+        );
+
+        // The upgraded hash still verifies
+        assert_eq!(provider.verify(right).await.unwrap().subject, "alice");
+    }
+
+    async fn test_stronger_hash_is_kept(store: Arc<dyn UserStore>) {
+        let strong = Argon2Config {
+            memory_kib: 32 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        };
+        LocalUserProvider::new(store.clone(), strong)
+            .unwrap()
+            .add_user("alice".to_string(), "supersecret".to_string(), None, None)
+            .await
+            .unwrap();
+        let hash = stored_hash(&store, "alice").await;
+
+        let provider = LocalUserProvider::new(store.clone(), Argon2Config::default()).unwrap();
+        let payload = serde_json::json!({ "username": "alice", "password": "supersecret" });
+        assert_eq!(provider.verify(payload).await.unwrap().subject, "alice");
+        assert_eq!(stored_hash(&store, "alice").await, hash);
+    }
+
+    #[cfg(feature = "bcrypt")]
+    async fn test_bcrypt_hash_is_migrated_on_login(store: Arc<dyn UserStore>) {
+        store
+            .insert(LocalUser {
+                password_hash: bcrypt::hash("hunter2", 4).unwrap(),
+                ..user("legacy", "legacy@example.com")
+            })
+            .await
+            .unwrap();
+        let provider = LocalUserProvider::new(store.clone(), Argon2Config::default()).unwrap();
+
+        let wrong = serde_json::json!({ "username": "legacy", "password": "hunter3" });
+        assert!(matches!(
+            provider.verify(wrong).await.unwrap_err(),
+            IdentityError::InvalidCredentials
+        ));
+        assert!(stored_hash(&store, "legacy").await.starts_with("$2b$"));
+
+        let right = serde_json::json!({ "username": "legacy", "password": "hunter2" });
+        let identity = provider.verify(right.clone()).await.unwrap();
+        assert_eq!(identity.email.as_deref(), Some("legacy@example.com"));
+        assert!(
+            stored_hash(&store, "legacy")
+                .await
+                .starts_with("$argon2id$")
+        );
+        assert_eq!(provider.verify(right).await.unwrap().subject, "legacy");
+    }
+
+    /// Run each test of the suite against every store, each test getting an empty one
+    macro_rules! store_suite {
+        ($($(#[$attr:meta])* $test:ident),* $(,)?) => {
+            mod memory_store {
+                use super::*;
+                $(
+                    $(#[$attr])*
+                    #[tokio::test]
+                    async fn $test() {
+                        super::$test(Arc::new(MemoryUserStore::new())).await;
+                    }
+                )*
+            }
+
+            mod json_file_store {
+                use super::*;
+                $(
+                    $(#[$attr])*
+                    #[tokio::test]
+                    async fn $test() {
+                        let dir = tempfile::tempdir().unwrap();
+                        let store = JsonFileUserStore::open(dir.path().join("users.json"))
+                            .await
+                            .unwrap();
+                        super::$test(Arc::new(store)).await;
+                    }
+                )*
+            }
+
+            #[cfg(feature = "sqlite")]
+            mod sqlite_store {
+                use super::*;
+                $(
+                    $(#[$attr])*
+                    #[tokio::test]
+                    async fn $test() {
+                        let dir = tempfile::tempdir().unwrap();
+                        let url = format!("sqlite://{}", dir.path().join("users.db").display());
+                        let store = SqliteUserStore::connect(&url).await.unwrap();
+                        super::$test(Arc::new(store)).await;
+                    }
+                )*
+            }
+        };
+    }
+
+    store_suite!(
+        test_basic_authentication_success,
+        test_wrong_password_fails,
+        test_username_enumeration_prevention,
+        #[cfg(feature = "timing-tests")]
+        #[ignore = "Timing test disabled - see issue with Argon2 parameter differences"]
+        test_timing_attack_resistance,
+        #[cfg(feature = "timing-tests")]
+        test_brute_force_simulation,
+        test_malformed_payload_handling,
+        test_empty_credentials,
+        test_special_characters_in_credentials,
+        test_very_long_credentials,
+        test_concurrent_authentication_attempts,
+        test_store_operations,
+        test_store_pagination,
+        test_removed_user_cannot_authenticate,
+        test_weaker_hash_is_upgraded_on_login,
+        test_stronger_hash_is_kept,
+        test_weak_passwords_are_refused,
+        test_change_password,
+        test_admin_set_password,
+        test_store_filters,
+        test_get_and_list_users,
+        test_update_profile,
+        test_unique_emails,
+        test_store_verifications,
+        test_email_verification,
+        test_expired_verification_is_refused,
+        test_changing_email_needs_new_verification,
+        test_verification_tokens_are_single_use_under_concurrency,
+        test_totp_login,
+        test_totp_codes_are_single_use_under_concurrency,
+        test_backup_codes_are_single_use,
+        test_disable_totp,
+        test_audit_events,
+        #[cfg(feature = "bcrypt")]
+        test_bcrypt_hash_is_migrated_on_login,
+    );
+
+    #[test]
+    fn test_argon2_config_validation() {
+        assert!(Argon2Config::default().validate().is_ok());
+
+        let default = Argon2Config::default();
+        let too_weak = [
+            Argon2Config {
+                memory_kib: 1024,
+                ..default
+            },
+            Argon2Config {
+                iterations: 0,
+                ..default
+            },
+            Argon2Config {
+                parallelism: 0,
+                ..default
+            },
+            Argon2Config {
+                parallelism: Argon2Config::MAX_PARALLELISM + 1,
+                ..default
+            },
+        ];
+        for config in too_weak {
+            assert!(matches!(
+                config.validate(),
+                Err(IdentityError::ProviderError(_))
+            ));
+            assert!(LocalUserProvider::new(Arc::new(MemoryUserStore::new()), config).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_file_store_persists_users() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+
+        let store = JsonFileUserStore::open(&path).await.unwrap();
+        let provider = setup_test_provider(Arc::new(store)).await;
+        provider.remove_user("testuser").await.unwrap();
+        drop(provider);
+
+        let provider = LocalUserProvider::new(
+            Arc::new(JsonFileUserStore::open(&path).await.unwrap()),
+            Argon2Config::default(),
+        )
+        .unwrap();
+        let payload = serde_json::json!({
+            "username": "alice",
+            "password": "supersecret"
+        });
+        assert_eq!(provider.verify(payload).await.unwrap().subject, "alice");
+        assert_eq!(provider.store().get("testuser").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_json_file_store_reads_user_arrays() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+        let mut stored = serde_json::to_value(user("alice", "alice@example.com")).unwrap();
+        stored.as_object_mut().unwrap().remove("version");
+        stored.as_object_mut().unwrap().remove("email_verified");
+        stored.as_object_mut().unwrap().remove("mfa");
+        std::fs::write(&path, serde_json::to_vec(&[stored]).unwrap()).unwrap();
+
+        let store = JsonFileUserStore::open(&path).await.unwrap();
+        assert_eq!(
+            store.get("alice").await.unwrap(),
+            Some(user("alice", "alice@example.com"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_file_store_persists_verifications() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+
+        let store = JsonFileUserStore::open(&path).await.unwrap();
+        let provider = setup_test_provider(Arc::new(store)).await;
+        let token = provider
+            .create_email_verification("alice")
+            .await
+            .unwrap()
+            .unwrap();
+        drop(provider);
+
+        let store = JsonFileUserStore::open(&path).await.unwrap();
+        let provider = LocalUserProvider::new(Arc::new(store), Argon2Config::default()).unwrap();
+        assert!(
+            provider
+                .verify_email(&token.token)
+                .await
+                .unwrap()
+                .email_verified
+        );
+    }
+
+    #[test]
+    fn test_identity_metadata_carries_the_verification_flag() {
+        assert_eq!(
+            verification::identity_metadata(None, false, &["pwd"]),
+            serde_json::json!({ "email_verified": false, "amr": ["pwd"] })
+        );
+        assert_eq!(
+            verification::identity_metadata(
+                Some(serde_json::json!({ "team": "blue", "email_verified": true })),
+                false,
+                &["pwd", "otp", "mfa"]
+            ),
+            serde_json::json!({
+                "team": "blue",
+                "email_verified": false,
+                "amr": ["pwd", "otp", "mfa"]
+            })
+        );
+        assert_eq!(
+            verification::identity_metadata(Some(serde_json::json!(["a"])), true, &["pwd"]),
+            serde_json::json!({ "metadata": ["a"], "email_verified": true, "amr": ["pwd"] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enrolling_totp_needs_mfa_config() {
+        let provider = setup_test_provider(Arc::new(MemoryUserStore::new())).await;
+        assert!(matches!(
+            provider.enroll_totp("alice").await,
+            Err(IdentityError::ProviderError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_json_file_store_rejects_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+        std::fs::write(&path, "not json").unwrap();
+
+        assert!(JsonFileUserStore::open(&path).await.is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_store_persists_users() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("users.db").display());
+
+        let store = SqliteUserStore::connect(&url).await.unwrap();
+        let provider = setup_test_provider(Arc::new(store)).await;
+        provider.remove_user("testuser").await.unwrap();
+        drop(provider);
+
+        let provider = LocalUserProvider::new(
+            Arc::new(SqliteUserStore::connect(&url).await.unwrap()),
+            Argon2Config::default(),
+        )
+        .unwrap();
+        let payload = serde_json::json!({
+            "username": "alice",
+            "password": "supersecret"
+        });
+        assert_eq!(provider.verify(payload).await.unwrap().subject, "alice");
+        assert_eq!(provider.store().get("testuser").await.unwrap(), None);
+    }
 }
@@ -0,0 +1,405 @@
+//! Time-based one-time passwords (RFC 6238) as a second factor.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use ras_identity_core::{IdentityError, IdentityResult};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// Digits in a code
+const DIGITS: u32 = 6;
+/// Seconds each code is valid for
+const STEP_SECONDS: u64 = 30;
+/// Steps before or after the current one whose codes are still accepted
+const SKEW_STEPS: u64 = 1;
+/// Bytes of a TOTP secret, the size of an HMAC-SHA1 key
+const SECRET_BYTES: usize = 20;
+const BACKUP_CODES: usize = 10;
+const NONCE_BYTES: usize = 12;
+
+/// The AES-256 key TOTP secrets are encrypted with at rest.
+///
+/// Keep it outside the user store, e.g. in a secrets manager: losing it
+/// disables every enrolled user's second factor.
+#[derive(Clone)]
+pub struct MfaKey([u8; 32]);
+
+impl MfaKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// A new random key
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+}
+
+impl std::fmt::Debug for MfaKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MfaKey(..)")
+    }
+}
+
+/// How a [`LocalUserProvider`](crate::LocalUserProvider) handles TOTP enrollment
+#[derive(Debug, Clone)]
+pub struct MfaConfig {
+    pub key: MfaKey,
+    /// The service name authenticator apps show next to the account
+    pub issuer: String,
+}
+
+impl MfaConfig {
+    pub fn new(key: MfaKey, issuer: impl Into<String>) -> Self {
+        Self {
+            key,
+            issuer: issuer.into(),
+        }
+    }
+}
+
+/// A user's TOTP enrollment, as stored with them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+    /// Base64 of the nonce and the secret encrypted with the [`MfaKey`]
+    pub encrypted_secret: String,
+    /// SHA-256 hashes of the backup codes not used yet
+    pub backup_code_hashes: Vec<String>,
+    /// The time step of the last accepted code, so it can't be used again
+    pub last_used_step: Option<u64>,
+}
+
+/// What a user needs to set up their authenticator, shown to them once
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TotpSecret {
+    /// The secret in base32, for typing into an authenticator
+    pub secret: String,
+    /// An `otpauth://` URL, usually shown as a QR code
+    pub otpauth_url: String,
+    /// Single-use codes to log in with when the authenticator is lost
+    pub backup_codes: Vec<String>,
+}
+
+impl MfaConfig {
+    /// Create a new secret and backup codes for `username`.
+    pub(crate) fn enroll(&self, username: &str) -> IdentityResult<(TotpEnrollment, TotpSecret)> {
+        let mut secret = [0u8; SECRET_BYTES];
+        OsRng.fill_bytes(&mut secret);
+        let backup_codes: Vec<String> = (0..BACKUP_CODES).map(|_| new_backup_code()).collect();
+
+        let encoded = BASE32_NOPAD.encode(&secret);
+        let label = format!("{}:{}", self.issuer, username);
+        let otpauth_url = format!(
+            "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            url_encode(&label),
+            encoded,
+            url_encode(&self.issuer),
+            DIGITS,
+            STEP_SECONDS
+        );
+
+        let enrollment = TotpEnrollment {
+            encrypted_secret: self.encrypt(username, &secret)?,
+            backup_code_hashes: backup_codes
+                .iter()
+                .map(|code| hash_backup_code(code))
+                .collect(),
+            last_used_step: None,
+        };
+        let secret = TotpSecret {
+            secret: encoded,
+            otpauth_url,
+            backup_codes,
+        };
+        Ok((enrollment, secret))
+    }
+
+    /// The step of the code `code` matches at `unix_time`, if it matches one
+    /// within the allowed skew that is later than the last one used.
+    pub(crate) fn accept_code(
+        &self,
+        username: &str,
+        enrollment: &TotpEnrollment,
+        code: &str,
+        unix_time: u64,
+    ) -> IdentityResult<Option<u64>> {
+        let code = code.trim();
+        if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return Ok(None);
+        }
+
+        let secret = self.decrypt(username, &enrollment.encrypted_secret)?;
+        let now = unix_time / STEP_SECONDS;
+        let accepted = (now.saturating_sub(SKEW_STEPS)..=now + SKEW_STEPS)
+            .filter(|&step| enrollment.last_used_step.is_none_or(|last| step > last))
+            .find(|&step| {
+                let expected = format!("{:0width$}", totp(&secret, step), width = DIGITS as usize);
+                bool::from(expected.as_bytes().ct_eq(code.as_bytes()))
+            });
+        Ok(accepted)
+    }
+
+    /// Bind the ciphertext to the user, so it can't be copied to another one
+    fn encrypt(&self, username: &str, secret: &[u8]) -> IdentityResult<String> {
+        let mut nonce = [0u8; NONCE_BYTES];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher()
+            .encrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: secret,
+                    aad: username.as_bytes(),
+                },
+            )
+            .map_err(|_| IdentityError::ProviderError("failed to encrypt TOTP secret".into()))?;
+
+        let mut stored = nonce.to_vec();
+        stored.extend(ciphertext);
+        Ok(STANDARD.encode(stored))
+    }
+
+    fn decrypt(&self, username: &str, encrypted: &str) -> IdentityResult<Vec<u8>> {
+        let undecryptable = || IdentityError::ProviderError("failed to decrypt TOTP secret".into());
+        let stored = STANDARD.decode(encrypted).map_err(|_| undecryptable())?;
+        if stored.len() < NONCE_BYTES {
+            return Err(undecryptable());
+        }
+        let (nonce, ciphertext) = stored.split_at(NONCE_BYTES);
+        let nonce: [u8; NONCE_BYTES] = nonce.try_into().expect("split at the nonce size");
+        self.cipher()
+            .decrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: username.as_bytes(),
+                },
+            )
+            .map_err(|_| undecryptable())
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new((&self.key.0).into())
+    }
+}
+
+/// The index in `enrollment` of the backup code `code`, if it is an unused one.
+pub(crate) fn find_backup_code(enrollment: &TotpEnrollment, code: &str) -> Option<usize> {
+    let hash = hash_backup_code(code);
+    // Compare with every hash, so the time taken doesn't tell which matched
+    enrollment
+        .backup_code_hashes
+        .iter()
+        .enumerate()
+        .fold(None, |found, (index, stored)| {
+            let matches = bool::from(stored.as_bytes().ct_eq(hash.as_bytes()));
+            found.or(matches.then_some(index))
+        })
+}
+
+/// The code for `step` (RFC 4226 with a time step as the counter)
+fn totp(secret: &[u8], step: u64) -> u32 {
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = usize::from(digest[digest.len() - 1] & 0x0f);
+    let truncated = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    truncated % 10u32.pow(DIGITS)
+}
+
+/// Ten characters of lowercase base32 in two groups, like `abcde-fghij`
+fn new_backup_code() -> String {
+    let mut bytes = [0u8; 5];
+    OsRng.fill_bytes(&mut bytes);
+    let code = BASE32_NOPAD.encode(&bytes).to_ascii_lowercase();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Backup codes are compared ignoring case, spaces and dashes
+fn hash_backup_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+/// Percent-encode everything but unreserved characters (RFC 3986)
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The current code for an enrolled secret, as an authenticator app would show it
+#[cfg(test)]
+pub(crate) fn code_at(secret: &TotpSecret, unix_time: u64) -> String {
+    let secret = BASE32_NOPAD.decode(secret.secret.as_bytes()).unwrap();
+    format!(
+        "{:0width$}",
+        totp(&secret, unix_time / STEP_SECONDS),
+        width = DIGITS as usize
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> MfaConfig {
+        MfaConfig::new(MfaKey::generate(), "Agent Stack")
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // The SHA-1 test vectors of RFC 6238, truncated to 6 digits
+        let secret = b"12345678901234567890";
+        for (time, code) in [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ] {
+            assert_eq!(totp(secret, time / STEP_SECONDS), code, "at {}", time);
+        }
+    }
+
+    #[test]
+    fn test_codes_within_one_step_are_accepted() {
+        let config = config();
+        let (enrollment, secret) = config.enroll("alice").unwrap();
+        let now = 1_700_000_000;
+
+        for offset in [-30i64, 0, 30] {
+            let code = code_at(&secret, (now as i64 + offset) as u64);
+            assert_eq!(
+                config
+                    .accept_code("alice", &enrollment, &code, now)
+                    .unwrap(),
+                Some((now as i64 + offset) as u64 / STEP_SECONDS)
+            );
+        }
+        for offset in [-90i64, -60, 60, 90] {
+            let code = code_at(&secret, (now as i64 + offset) as u64);
+            assert_eq!(
+                config
+                    .accept_code("alice", &enrollment, &code, now)
+                    .unwrap(),
+                None,
+                "code from {}s away",
+                offset
+            );
+        }
+        assert_eq!(
+            config
+                .accept_code("alice", &enrollment, "12345", now)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            config
+                .accept_code("alice", &enrollment, "abcdef", now)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_used_steps_are_not_accepted_again() {
+        let config = config();
+        let (mut enrollment, secret) = config.enroll("alice").unwrap();
+        let now = 1_700_000_000;
+        let code = code_at(&secret, now);
+
+        let step = config
+            .accept_code("alice", &enrollment, &code, now)
+            .unwrap()
+            .unwrap();
+        enrollment.last_used_step = Some(step);
+        assert_eq!(
+            config
+                .accept_code("alice", &enrollment, &code, now)
+                .unwrap(),
+            None
+        );
+        // Nor are earlier ones
+        let earlier = code_at(&secret, now - 30);
+        assert_eq!(
+            config
+                .accept_code("alice", &enrollment, &earlier, now)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_secrets_are_encrypted_for_their_user() {
+        let config = config();
+        let (enrollment, secret) = config.enroll("alice").unwrap();
+        let code = code_at(&secret, 1_700_000_000);
+
+        assert!(!enrollment.encrypted_secret.contains(&secret.secret));
+        // Copied to another user, or read with another key, it doesn't decrypt
+        assert!(
+            config
+                .accept_code("mallory", &enrollment, &code, 1_700_000_000)
+                .is_err()
+        );
+        let other_key = MfaConfig::new(MfaKey::generate(), "Agent Stack");
+        assert!(
+            other_key
+                .accept_code("alice", &enrollment, &code, 1_700_000_000)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_enrollment() {
+        let (enrollment, secret) = config().enroll("alice@example.com").unwrap();
+
+        assert_eq!(secret.backup_codes.len(), BACKUP_CODES);
+        assert_eq!(enrollment.backup_code_hashes.len(), BACKUP_CODES);
+        assert!(
+            secret
+                .otpauth_url
+                .starts_with("otpauth://totp/Agent%20Stack%3Aalice%40example.com?secret=")
+        );
+        assert!(secret.otpauth_url.contains("&issuer=Agent%20Stack&"));
+
+        let code = &secret.backup_codes[3];
+        assert_eq!(find_backup_code(&enrollment, code), Some(3));
+        assert_eq!(
+            find_backup_code(&enrollment, &code.to_uppercase().replace('-', " ")),
+            Some(3)
+        );
+        assert_eq!(find_backup_code(&enrollment, "aaaaa-aaaaa"), None);
+    }
+}
@@ -0,0 +1,139 @@
+//! Password hashing and verification.
+
+use argon2::{
+    Algorithm, Argon2, Params, Version,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+};
+use rand_core::OsRng;
+use ras_identity_core::{IdentityError, IdentityResult};
+use serde::{Deserialize, Serialize};
+
+/// The cost of the Argon2id hashes a [`LocalUserProvider`](crate::LocalUserProvider) creates.
+///
+/// Stored hashes made with weaker parameters are re-hashed with these the next
+/// time their user logs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Argon2Config {
+    /// Memory used per hash, in KiB
+    pub memory_kib: u32,
+    /// Passes over the memory
+    pub iterations: u32,
+    /// Lanes hashed in parallel
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Config {
+    /// The Argon2 crate's defaults: 19 MiB, 2 iterations, 1 lane
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl Argon2Config {
+    /// The least memory accepted, 8 MiB
+    pub const MIN_MEMORY_KIB: u32 = 8 * 1024;
+    /// The most lanes accepted
+    pub const MAX_PARALLELISM: u32 = 64;
+
+    /// Check the parameters are strong enough to protect passwords and usable by Argon2.
+    pub fn validate(&self) -> IdentityResult<()> {
+        if self.memory_kib < Self::MIN_MEMORY_KIB {
+            return Err(invalid_config(format!(
+                "memory_kib must be at least {}",
+                Self::MIN_MEMORY_KIB
+            )));
+        }
+        if self.iterations == 0 {
+            return Err(invalid_config("iterations must be at least 1".to_string()));
+        }
+        if self.parallelism == 0 || self.parallelism > Self::MAX_PARALLELISM {
+            return Err(invalid_config(format!(
+                "parallelism must be between 1 and {}",
+                Self::MAX_PARALLELISM
+            )));
+        }
+        self.params().map(|_| ())
+    }
+
+    fn params(&self) -> IdentityResult<Params> {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| invalid_config(e.to_string()))
+    }
+
+    /// Hash `password` with these parameters.
+    pub(crate) fn hash(&self, password: &str) -> IdentityResult<String> {
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params()?);
+        let salt = SaltString::generate(&mut OsRng);
+        argon2
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| IdentityError::ProviderError(e.to_string()))
+    }
+
+    /// Check `password` against `stored_hash`, an Argon2 hash or, with the
+    /// `bcrypt` feature, a legacy bcrypt one.
+    pub(crate) fn check(&self, stored_hash: &str, password: &str) -> IdentityResult<PasswordCheck> {
+        #[cfg(feature = "bcrypt")]
+        if is_bcrypt(stored_hash) {
+            return match bcrypt::verify(password, stored_hash) {
+                Ok(true) => Ok(PasswordCheck::Outdated),
+                Ok(false) => Ok(PasswordCheck::Mismatch),
+                Err(e) => Err(IdentityError::ProviderError(e.to_string())),
+            };
+        }
+
+        let parsed_hash = PasswordHash::new(stored_hash)
+            .map_err(|e| IdentityError::ProviderError(e.to_string()))?;
+
+        // Verification uses the algorithm and parameters recorded in the hash
+        if Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_err()
+        {
+            Ok(PasswordCheck::Mismatch)
+        } else if self.is_weaker(&parsed_hash) {
+            Ok(PasswordCheck::Outdated)
+        } else {
+            Ok(PasswordCheck::Match)
+        }
+    }
+
+    /// Whether `hash` isn't Argon2id or used less of any resource than these parameters
+    fn is_weaker(&self, hash: &PasswordHash) -> bool {
+        if hash.algorithm != Algorithm::Argon2id.ident() {
+            return true;
+        }
+        match Params::try_from(hash) {
+            Ok(params) => {
+                params.m_cost() < self.memory_kib
+                    || params.t_cost() < self.iterations
+                    || params.p_cost() < self.parallelism
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+/// The outcome of checking a password against a stored hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PasswordCheck {
+    Mismatch,
+    Match,
+    /// The password matches, but the hash should be replaced by a stronger one
+    Outdated,
+}
+
+fn invalid_config(reason: String) -> IdentityError {
+    IdentityError::ProviderError(format!("invalid Argon2 configuration: {}", reason))
+}
+
+#[cfg(feature = "bcrypt")]
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+}
@@ -0,0 +1,300 @@
+//! Rules new passwords must follow.
+
+use ras_identity_core::{IdentityError, IdentityResult, PolicyViolation};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Rules the passwords given to a [`LocalUserProvider`](crate::LocalUserProvider) must follow.
+///
+/// Lengths count Unicode scalar values, so a precomposed `"é"` is one character
+/// while `"e\u{301}"` is two. The default only bounds the length: at least 8
+/// characters, and at most 512 to bound the cost of hashing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Require at least three of lowercase letters, uppercase letters, digits and symbols
+    pub require_mixed_classes: bool,
+    /// Passwords refused regardless of case
+    pub denied_passwords: BTreeSet<String>,
+    /// Refuse passwords containing the username, regardless of case
+    pub reject_username: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 512,
+            require_mixed_classes: false,
+            denied_passwords: BTreeSet::new(),
+            reject_username: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Commonly used passwords long enough to pass the default length rule
+    pub const COMMON_PASSWORDS: &'static [&'static str] = &[
+        "password",
+        "password1",
+        "password123",
+        "12345678",
+        "123456789",
+        "1234567890",
+        "11111111",
+        "00000000",
+        "87654321",
+        "qwertyuiop",
+        "qwerty123",
+        "1q2w3e4r",
+        "1qaz2wsx",
+        "abcd1234",
+        "iloveyou",
+        "sunshine",
+        "princess",
+        "football",
+        "baseball",
+        "welcome1",
+        "letmein1",
+        "trustno1",
+        "superman",
+        "whatever",
+        "passw0rd",
+        "changeme",
+    ];
+
+    const MIXED_CLASSES: usize = 3;
+
+    /// Also refuse the passwords in [`Self::COMMON_PASSWORDS`].
+    pub fn deny_common_passwords(mut self) -> Self {
+        self.denied_passwords
+            .extend(Self::COMMON_PASSWORDS.iter().map(|p| p.to_string()));
+        self
+    }
+
+    /// Every rule `password`, chosen by `username`, breaks.
+    pub fn violations(&self, username: &str, password: &str) -> Vec<PolicyViolation> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            violations.push(PolicyViolation::TooShort {
+                min: self.min_length,
+            });
+        }
+        if length > self.max_length {
+            violations.push(PolicyViolation::TooLong {
+                max: self.max_length,
+            });
+        }
+        if self.require_mixed_classes && character_classes(password) < Self::MIXED_CLASSES {
+            violations.push(PolicyViolation::TooFewCharacterClasses {
+                required: Self::MIXED_CLASSES,
+            });
+        }
+
+        let lowercase = password.to_lowercase();
+        if self
+            .denied_passwords
+            .iter()
+            .any(|denied| denied.to_lowercase() == lowercase)
+        {
+            violations.push(PolicyViolation::Common);
+        }
+        if self.reject_username
+            && !username.is_empty()
+            && lowercase.contains(&username.to_lowercase())
+        {
+            violations.push(PolicyViolation::ContainsUsername);
+        }
+
+        violations
+    }
+
+    /// Fail with [`IdentityError::WeakPassword`] if `password` breaks any rule.
+    pub fn check(&self, username: &str, password: &str) -> IdentityResult<()> {
+        let violations = self.violations(username, password);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(IdentityError::WeakPassword(violations))
+        }
+    }
+}
+
+/// How many of lowercase letters, uppercase letters, digits and other
+/// characters `password` uses
+fn character_classes(password: &str) -> usize {
+    let mut classes = [false; 4];
+    for c in password.chars() {
+        let class = if c.is_lowercase() {
+            0
+        } else if c.is_uppercase() {
+            1
+        } else if c.is_numeric() {
+            2
+        } else {
+            3
+        };
+        classes[class] = true;
+    }
+    classes.iter().filter(|&&used| used).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strict() -> PasswordPolicy {
+        PasswordPolicy {
+            require_mixed_classes: true,
+            reject_username: true,
+            ..PasswordPolicy::default()
+        }
+        .deny_common_passwords()
+    }
+
+    #[test]
+    fn test_default_policy_bounds_length() {
+        let policy = PasswordPolicy::default();
+
+        assert_eq!(policy.violations("alice", "12345678"), []);
+        assert_eq!(
+            policy.violations("alice", ""),
+            [PolicyViolation::TooShort { min: 8 }]
+        );
+        assert_eq!(
+            policy.violations("alice", "x"),
+            [PolicyViolation::TooShort { min: 8 }]
+        );
+        assert_eq!(policy.violations("alice", &"x".repeat(512)), []);
+        assert_eq!(
+            policy.violations("alice", &"x".repeat(513)),
+            [PolicyViolation::TooLong { max: 512 }]
+        );
+
+        // Only length rules apply by default
+        assert_eq!(policy.violations("alice", "password"), []);
+        assert_eq!(policy.violations("alice", "alice-in-wonderland"), []);
+    }
+
+    #[test]
+    fn test_lengths_count_characters_not_bytes() {
+        let policy = PasswordPolicy::default();
+
+        // 7 characters in 21 bytes is still too short
+        assert_eq!(
+            policy.violations("alice", "パスワード長い"),
+            [PolicyViolation::TooShort { min: 8 }]
+        );
+        // 8 characters including an emoji
+        assert_eq!(policy.violations("alice", "ñandú🦀ab"), []);
+        // 512 four-byte characters fit, 513 don't
+        assert_eq!(policy.violations("alice", &"🦀".repeat(512)), []);
+        assert_eq!(
+            policy.violations("alice", &"🦀".repeat(513)),
+            [PolicyViolation::TooLong { max: 512 }]
+        );
+        // A combining accent is a character of its own
+        assert_eq!(
+            policy.violations("alice", "e\u{301}e\u{301}e\u{301}"),
+            [PolicyViolation::TooShort { min: 8 }]
+        );
+        assert_eq!(policy.violations("alice", &"e\u{301}".repeat(4)), []);
+    }
+
+    #[test]
+    fn test_mixed_classes() {
+        let policy = strict();
+        let violation = || PolicyViolation::TooFewCharacterClasses { required: 3 };
+
+        assert_eq!(policy.violations("alice", "onlylowercase"), [violation()]);
+        assert_eq!(policy.violations("alice", "lowerUPPER"), [violation()]);
+        assert_eq!(policy.violations("alice", "lowerUPPER1"), []);
+        assert_eq!(policy.violations("alice", "lower-1234"), []);
+        // Letters outside ASCII count by their case
+        assert_eq!(policy.violations("alice", "ÀÉÎõüñ42"), []);
+        assert_eq!(policy.violations("alice", "àéîõüñçø"), [violation()]);
+    }
+
+    #[test]
+    fn test_denied_passwords_ignore_case() {
+        let policy = PasswordPolicy::default().deny_common_passwords();
+
+        assert_eq!(
+            policy.violations("alice", "password123"),
+            [PolicyViolation::Common]
+        );
+        assert_eq!(
+            policy.violations("alice", "PassWord123"),
+            [PolicyViolation::Common]
+        );
+        assert_eq!(policy.violations("alice", "password1234"), []);
+
+        let mut custom = PasswordPolicy::default();
+        custom.denied_passwords.insert("CorrectHorse".to_string());
+        assert_eq!(
+            custom.violations("alice", "correcthorse"),
+            [PolicyViolation::Common]
+        );
+    }
+
+    #[test]
+    fn test_username_in_password() {
+        let policy = PasswordPolicy {
+            reject_username: true,
+            ..PasswordPolicy::default()
+        };
+
+        assert_eq!(
+            policy.violations("alice", "xxALICExx"),
+            [PolicyViolation::ContainsUsername]
+        );
+        assert_eq!(
+            policy.violations("Ærøskøbing", "ærøskøbing99"),
+            [PolicyViolation::ContainsUsername]
+        );
+        assert_eq!(policy.violations("alice", "al1ce-rocks"), []);
+        assert_eq!(policy.violations("", "anything-goes"), []);
+    }
+
+    #[test]
+    fn test_every_violation_is_reported() {
+        let error = strict().check("abc", "abc").unwrap_err();
+
+        match error {
+            IdentityError::WeakPassword(violations) => assert_eq!(
+                violations,
+                [
+                    PolicyViolation::TooShort { min: 8 },
+                    PolicyViolation::TooFewCharacterClasses { required: 3 },
+                    PolicyViolation::ContainsUsername,
+                ]
+            ),
+            other => panic!("Expected WeakPassword, got: {:?}", other),
+        }
+        assert!(strict().check("abc", "Tr0ub4dor&3").is_ok());
+    }
+
+    #[test]
+    fn test_violations_serialize_with_a_tag() {
+        let violations = serde_json::to_value([
+            PolicyViolation::TooShort { min: 8 },
+            PolicyViolation::Common,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            violations,
+            serde_json::json!([
+                { "violation": "too_short", "min": 8 },
+                { "violation": "common" },
+            ])
+        );
+        assert_eq!(
+            IdentityError::WeakPassword(vec![PolicyViolation::Common]).to_string(),
+            "Weak password: is too common"
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! A user store in a SQLite database.
+
+use crate::LocalUser;
+use crate::store::{EmailVerification, Page, UserFilter, UserStore};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ras_identity_core::{IdentityError, IdentityResult};
+use sqlx::Row;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqliteRow};
+use std::str::FromStr;
+
+/// Keeps users in the `local_users` table of a SQLite database, and pending
+/// email verifications in `local_email_verifications`.
+///
+/// The tables are created if they don't exist yet. Metadata and TOTP
+/// enrollments are stored as JSON text, and expiry times as milliseconds since
+/// the Unix epoch.
+#[derive(Debug, Clone)]
+pub struct SqliteUserStore {
+    pool: SqlitePool,
+}
+
+impl SqliteUserStore {
+    /// Open the database at `url`, such as `sqlite://users.db`, creating it if needed.
+    pub async fn connect(url: &str) -> IdentityResult<Self> {
+        let options = SqliteConnectOptions::from_str(url)
+            .map_err(storage_error)?
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options)
+            .await
+            .map_err(storage_error)?;
+        Self::from_pool(pool).await
+    }
+
+    /// Keep users in the database behind `pool`.
+    pub async fn from_pool(pool: SqlitePool) -> IdentityResult<Self> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS local_users (
+                username TEXT PRIMARY KEY NOT NULL,
+                password_hash TEXT NOT NULL,
+                email TEXT,
+                display_name TEXT,
+                metadata TEXT,
+                email_verified INTEGER NOT NULL DEFAULT 0,
+                version INTEGER NOT NULL DEFAULT 0,
+                mfa TEXT
+            )",
+        )
+        .execute(&pool)
+        .await
+        .map_err(storage_error)?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS local_email_verifications (
+                token_hash TEXT PRIMARY KEY NOT NULL,
+                username TEXT NOT NULL,
+                email TEXT NOT NULL,
+                expires_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await
+        .map_err(storage_error)?;
+
+        Ok(Self { pool })
+    }
+}
+
+fn storage_error(error: sqlx::Error) -> IdentityError {
+    IdentityError::ProviderError(format!("user store: {}", error))
+}
+
+fn user_from_row(row: SqliteRow) -> IdentityResult<LocalUser> {
+    let metadata: Option<String> = row.try_get("metadata").map_err(storage_error)?;
+    let mfa: Option<String> = row.try_get("mfa").map_err(storage_error)?;
+    Ok(LocalUser {
+        username: row.try_get("username").map_err(storage_error)?,
+        password_hash: row.try_get("password_hash").map_err(storage_error)?,
+        email: row.try_get("email").map_err(storage_error)?,
+        display_name: row.try_get("display_name").map_err(storage_error)?,
+        metadata: metadata
+            .map(|metadata| serde_json::from_str(&metadata))
+            .transpose()?,
+        email_verified: row.try_get("email_verified").map_err(storage_error)?,
+        version: version_from(row.try_get("version").map_err(storage_error)?)?,
+        mfa: mfa.map(|mfa| serde_json::from_str(&mfa)).transpose()?,
+    })
+}
+
+fn verification_from_row(row: SqliteRow) -> IdentityResult<EmailVerification> {
+    let expires_at: i64 = row.try_get("expires_at").map_err(storage_error)?;
+    Ok(EmailVerification {
+        token_hash: row.try_get("token_hash").map_err(storage_error)?,
+        username: row.try_get("username").map_err(storage_error)?,
+        email: row.try_get("email").map_err(storage_error)?,
+        expires_at: DateTime::from_timestamp_millis(expires_at).ok_or_else(|| {
+            IdentityError::ProviderError(format!("user store: invalid expiry {}", expires_at))
+        })?,
+    })
+}
+
+fn version_from(version: i64) -> IdentityResult<u64> {
+    u64::try_from(version).map_err(|_| {
+        IdentityError::ProviderError(format!("user store: invalid version {}", version))
+    })
+}
+
+/// SQLite integers are signed, so versions past `i64::MAX` can't be stored
+fn version_to(version: u64) -> IdentityResult<i64> {
+    i64::try_from(version).map_err(|_| {
+        IdentityError::ProviderError(format!("user store: version {} is too large", version))
+    })
+}
+
+/// Matches the rows passing a `UserFilter` bound as `?1` (username prefix) and `?2` (email)
+const FILTER: &str = "(?1 IS NULL OR instr(username, ?1) = 1)
+     AND (?2 IS NULL OR lower(email) = lower(?2))";
+
+fn metadata_text(user: &LocalUser) -> IdentityResult<Option<String>> {
+    Ok(user
+        .metadata
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()?)
+}
+
+fn mfa_text(user: &LocalUser) -> IdentityResult<Option<String>> {
+    Ok(user.mfa.as_ref().map(serde_json::to_string).transpose()?)
+}
+
+#[async_trait]
+impl UserStore for SqliteUserStore {
+    async fn get(&self, username: &str) -> IdentityResult<Option<LocalUser>> {
+        sqlx::query("SELECT * FROM local_users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?
+            .map(user_from_row)
+            .transpose()
+    }
+
+    async fn insert(&self, user: LocalUser) -> IdentityResult<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO local_users
+                (username, password_hash, email, display_name, metadata, email_verified, version, mfa)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&user.username)
+        .bind(&user.password_hash)
+        .bind(&user.email)
+        .bind(&user.display_name)
+        .bind(metadata_text(&user)?)
+        .bind(user.email_verified)
+        .bind(version_to(user.version)?)
+        .bind(mfa_text(&user)?)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn update(&self, user: LocalUser) -> IdentityResult<bool> {
+        let result = sqlx::query(
+            "UPDATE local_users
+             SET password_hash = ?, email = ?, display_name = ?, metadata = ?,
+                 email_verified = ?, mfa = ?, version = version + 1
+             WHERE username = ? AND version = ?",
+        )
+        .bind(&user.password_hash)
+        .bind(&user.email)
+        .bind(&user.display_name)
+        .bind(metadata_text(&user)?)
+        .bind(user.email_verified)
+        .bind(mfa_text(&user)?)
+        .bind(&user.username)
+        .bind(version_to(user.version)?)
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        if result.rows_affected() > 0 {
+            return Ok(true);
+        }
+
+        // Either there is no such user or it has moved on to another version
+        let current: Option<i64> =
+            sqlx::query_scalar("SELECT version FROM local_users WHERE username = ?")
+                .bind(&user.username)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(storage_error)?;
+        match current {
+            Some(current) => Err(IdentityError::VersionConflict {
+                current: version_from(current)?,
+            }),
+            None => Ok(false),
+        }
+    }
+
+    async fn delete(&self, username: &str) -> IdentityResult<Option<LocalUser>> {
+        sqlx::query("DELETE FROM local_users WHERE username = ? RETURNING *")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?
+            .map(user_from_row)
+            .transpose()
+    }
+
+    async fn list(
+        &self,
+        filter: &UserFilter,
+        offset: usize,
+        limit: usize,
+    ) -> IdentityResult<Page<LocalUser>> {
+        let total: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM local_users WHERE {}",
+            FILTER
+        ))
+        .bind(&filter.username_prefix)
+        .bind(&filter.email)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(storage_error)?;
+
+        // SQLite takes a signed limit, so cap it to one that fits
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let offset = i64::try_from(offset).unwrap_or(i64::MAX);
+        let items = sqlx::query(&format!(
+            "SELECT * FROM local_users WHERE {} ORDER BY username LIMIT ?3 OFFSET ?4",
+            FILTER
+        ))
+        .bind(&filter.username_prefix)
+        .bind(&filter.email)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(storage_error)?
+        .into_iter()
+        .map(user_from_row)
+        .collect::<IdentityResult<_>>()?;
+
+        Ok(Page {
+            items,
+            total: usize::try_from(total).unwrap_or(usize::MAX),
+        })
+    }
+
+    async fn insert_verification(&self, verification: EmailVerification) -> IdentityResult<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO local_email_verifications
+                (token_hash, username, email, expires_at)
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(&verification.token_hash)
+        .bind(&verification.username)
+        .bind(&verification.email)
+        .bind(verification.expires_at.timestamp_millis())
+        .execute(&self.pool)
+        .await
+        .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn take_verification(
+        &self,
+        token_hash: &str,
+    ) -> IdentityResult<Option<EmailVerification>> {
+        // A single statement, so concurrent callers can't both get the row
+        sqlx::query("DELETE FROM local_email_verifications WHERE token_hash = ? RETURNING *")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?
+            .map(verification_from_row)
+            .transpose()
+    }
+
+    async fn delete_expired_verifications(&self, now: DateTime<Utc>) -> IdentityResult<usize> {
+        let result = sqlx::query("DELETE FROM local_email_verifications WHERE expires_at <= ?")
+            .bind(now.timestamp_millis())
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        Ok(usize::try_from(result.rows_affected()).unwrap_or(usize::MAX))
+    }
+}
@@ -0,0 +1,204 @@
+//! Where local users are kept.
+
+use crate::LocalUser;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ras_identity_core::{IdentityError, IdentityResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::RwLock;
+
+/// Storage for the users of a [`LocalUserProvider`](crate::LocalUserProvider).
+///
+/// Users are keyed by username. Implementations report storage failures as
+/// `IdentityError::ProviderError`.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// The user named `username`, if any.
+    async fn get(&self, username: &str) -> IdentityResult<Option<LocalUser>>;
+
+    /// Add `user`, replacing any user with the same username.
+    async fn insert(&self, user: LocalUser) -> IdentityResult<()>;
+
+    /// Replace the stored user with `user`'s username, storing it one version later.
+    ///
+    /// `user.version` is the version it was read at; fails with
+    /// `IdentityError::VersionConflict` if the stored user has changed since.
+    /// Returns `false` if there is no such user.
+    async fn update(&self, user: LocalUser) -> IdentityResult<bool>;
+
+    /// Remove the user named `username`, returning it.
+    async fn delete(&self, username: &str) -> IdentityResult<Option<LocalUser>>;
+
+    /// Up to `limit` users matching `filter`, ordered by username, after
+    /// skipping the first `offset`.
+    async fn list(
+        &self,
+        filter: &UserFilter,
+        offset: usize,
+        limit: usize,
+    ) -> IdentityResult<Page<LocalUser>>;
+
+    /// Keep `verification` until it is taken or expires.
+    async fn insert_verification(&self, verification: EmailVerification) -> IdentityResult<()>;
+
+    /// Remove and return the verification with `token_hash`.
+    ///
+    /// Of any concurrent calls for the same token, only one gets it.
+    async fn take_verification(
+        &self,
+        token_hash: &str,
+    ) -> IdentityResult<Option<EmailVerification>>;
+
+    /// Remove the verifications that expired by `now`, returning how many.
+    async fn delete_expired_verifications(&self, now: DateTime<Utc>) -> IdentityResult<usize>;
+}
+
+/// A pending email verification, keyed by the SHA-256 hash of its token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmailVerification {
+    pub token_hash: String,
+    pub username: String,
+    /// The email being verified, so the token can't verify a later one
+    pub email: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Which users to list
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserFilter {
+    /// Only users whose username starts with this
+    pub username_prefix: Option<String>,
+    /// Only users with this email, ignoring ASCII case
+    pub email: Option<String>,
+}
+
+impl UserFilter {
+    /// Whether `user` passes the filter
+    pub fn matches(&self, user: &LocalUser) -> bool {
+        let prefix_matches = self
+            .username_prefix
+            .as_ref()
+            .is_none_or(|prefix| user.username.starts_with(prefix.as_str()));
+        let email_matches = self.email.as_ref().is_none_or(|email| {
+            user.email
+                .as_ref()
+                .is_some_and(|user_email| user_email.eq_ignore_ascii_case(email))
+        });
+        prefix_matches && email_matches
+    }
+}
+
+/// One page of a listing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// How many items match in all, across every page
+    pub total: usize,
+}
+
+impl<T> Page<T> {
+    /// Convert each item of the page
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+        }
+    }
+}
+
+/// Replace the user in `users` the way [`UserStore::update`] describes.
+pub(crate) fn update_in(
+    users: &mut BTreeMap<String, LocalUser>,
+    mut user: LocalUser,
+) -> IdentityResult<bool> {
+    let Some(stored) = users.get_mut(&user.username) else {
+        return Ok(false);
+    };
+    if stored.version != user.version {
+        return Err(IdentityError::VersionConflict {
+            current: stored.version,
+        });
+    }
+    user.version += 1;
+    *stored = user;
+    Ok(true)
+}
+
+/// List the users in `users` the way [`UserStore::list`] describes.
+pub(crate) fn list_in(
+    users: &BTreeMap<String, LocalUser>,
+    filter: &UserFilter,
+    offset: usize,
+    limit: usize,
+) -> Page<LocalUser> {
+    let matching = users.values().filter(|user| filter.matches(user));
+    Page {
+        items: matching.clone().skip(offset).take(limit).cloned().collect(),
+        total: matching.count(),
+    }
+}
+
+/// Keeps users in memory, losing them when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryUserStore {
+    users: RwLock<BTreeMap<String, LocalUser>>,
+    verifications: RwLock<HashMap<String, EmailVerification>>,
+}
+
+impl MemoryUserStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryUserStore {
+    async fn get(&self, username: &str) -> IdentityResult<Option<LocalUser>> {
+        Ok(self.users.read().await.get(username).cloned())
+    }
+
+    async fn insert(&self, user: LocalUser) -> IdentityResult<()> {
+        self.users.write().await.insert(user.username.clone(), user);
+        Ok(())
+    }
+
+    async fn update(&self, user: LocalUser) -> IdentityResult<bool> {
+        update_in(&mut *self.users.write().await, user)
+    }
+
+    async fn delete(&self, username: &str) -> IdentityResult<Option<LocalUser>> {
+        Ok(self.users.write().await.remove(username))
+    }
+
+    async fn list(
+        &self,
+        filter: &UserFilter,
+        offset: usize,
+        limit: usize,
+    ) -> IdentityResult<Page<LocalUser>> {
+        Ok(list_in(&*self.users.read().await, filter, offset, limit))
+    }
+
+    async fn insert_verification(&self, verification: EmailVerification) -> IdentityResult<()> {
+        self.verifications
+            .write()
+            .await
+            .insert(verification.token_hash.clone(), verification);
+        Ok(())
+    }
+
+    async fn take_verification(
+        &self,
+        token_hash: &str,
+    ) -> IdentityResult<Option<EmailVerification>> {
+        Ok(self.verifications.write().await.remove(token_hash))
+    }
+
+    async fn delete_expired_verifications(&self, now: DateTime<Utc>) -> IdentityResult<usize> {
+        let mut verifications = self.verifications.write().await;
+        let before = verifications.len();
+        verifications.retain(|_, verification| verification.expires_at > now);
+        Ok(before - verifications.len())
+    }
+}
@@ -0,0 +1,65 @@
+//! Email verification tokens.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use rand_core::{OsRng, RngCore};
+use ras_identity_core::VerifiedIdentity;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+/// The identity metadata field carrying whether the user's email is verified
+pub const EMAIL_VERIFIED: &str = "email_verified";
+
+/// A token proving its holder received an email, to send to that address.
+///
+/// Only a hash of the token is stored, so it can't be read back later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Whether `identity` was verified by a [`LocalUserProvider`](crate::LocalUserProvider)
+/// for a user whose email is verified.
+///
+/// For [`UserPermissions`](ras_identity_core::UserPermissions) implementations
+/// that withhold permissions from unverified accounts.
+pub fn is_email_verified(identity: &VerifiedIdentity) -> bool {
+    identity
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get(EMAIL_VERIFIED))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// 256 random bits, URL safe
+pub(crate) fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// The metadata of an identity: the user's metadata object with the
+/// [`EMAIL_VERIFIED`] field and the `amr` list of authentication methods set.
+/// Metadata that isn't an object is kept under `metadata`.
+pub(crate) fn identity_metadata(
+    metadata: Option<Value>,
+    email_verified: bool,
+    amr: &[&str],
+) -> Value {
+    let mut object = match metadata {
+        Some(Value::Object(object)) => object,
+        None => Map::new(),
+        Some(other) => Map::from_iter([("metadata".to_string(), other)]),
+    };
+    object.insert(EMAIL_VERIFIED.to_string(), Value::Bool(email_verified));
+    object.insert("amr".to_string(), Value::from(amr));
+    Value::Object(object)
+}
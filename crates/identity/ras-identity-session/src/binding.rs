@@ -0,0 +1,128 @@
+//! Binding sessions to the client they began on, so a stolen token is
+//! refused when presented from elsewhere.
+
+use http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+/// What identifies the client of a request, compared between the login and
+/// later requests of a session.
+///
+/// Each part is only compared if the session was bound with it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BindingInfo {
+    /// The SHA-256 of the `User-Agent`, in hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent_hash: Option<String>,
+    /// The network of the client's address, its /24 for IPv4 and /64 for
+    /// IPv6, so clients moving within it keep their session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_prefix: Option<String>,
+    /// An id the client keeps for itself, such as one generated at install
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+}
+
+impl BindingInfo {
+    pub fn new(user_agent: Option<&str>, ip: Option<IpAddr>, device_id: Option<&str>) -> Self {
+        Self {
+            user_agent_hash: user_agent
+                .map(|user_agent| format!("{:x}", Sha256::digest(user_agent.as_bytes()))),
+            ip_prefix: ip.map(ip_prefix),
+            device_id: device_id.map(str::to_string),
+        }
+    }
+
+    /// The binding of a request with `headers` from the client at
+    /// `client_ip`: its `User-Agent`, the network of `client_ip`, and
+    /// `X-Device-Id`.
+    ///
+    /// `client_ip` must be resolved by the caller, from the peer that
+    /// connected and only through proxies it trusts, such as with
+    /// [`client_ip`](ras_observability_core::client_ip); forwarding headers
+    /// any client can set aren't read here.
+    pub fn from_request(headers: &HeaderMap, client_ip: Option<IpAddr>) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        Self::new(
+            header(http::header::USER_AGENT.as_str()),
+            client_ip,
+            header("x-device-id"),
+        )
+    }
+}
+
+fn ip_prefix(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{a}.{b}.{c}.0/24")
+        }
+        IpAddr::V6(ip) => {
+            let network = u128::from(ip) & (u128::MAX << 64);
+            format!("{}/64", std::net::Ipv6Addr::from(network))
+        }
+    }
+}
+
+/// What to do when part of a request's binding differs from its session's
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BindingMode {
+    /// Don't compare it
+    #[default]
+    Off,
+    /// Report the mismatch to the event sink, and accept the request
+    Warn,
+    /// Report the mismatch and refuse the request with
+    /// `SessionError::BindingMismatch`
+    Enforce,
+}
+
+/// How each part of [`BindingInfo`] is checked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BindingPolicy {
+    pub user_agent: BindingMode,
+    pub ip_prefix: BindingMode,
+    pub device_id: BindingMode,
+}
+
+impl BindingPolicy {
+    /// Whether no part of [`BindingInfo`] is checked, so sessions aren't bound
+    pub fn is_off(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The parts `presented` differs from `bound` in, with how they're checked
+    pub(crate) fn mismatches(
+        &self,
+        bound: &BindingInfo,
+        presented: &BindingInfo,
+    ) -> Vec<(&'static str, BindingMode)> {
+        [
+            (
+                "user_agent",
+                self.user_agent,
+                &bound.user_agent_hash,
+                &presented.user_agent_hash,
+            ),
+            (
+                "ip_prefix",
+                self.ip_prefix,
+                &bound.ip_prefix,
+                &presented.ip_prefix,
+            ),
+            (
+                "device_id",
+                self.device_id,
+                &bound.device_id,
+                &presented.device_id,
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, mode, bound, presented)| {
+            *mode != BindingMode::Off && bound.is_some() && bound != presented
+        })
+        .map(|(name, mode, _, _)| (name, mode))
+        .collect()
+    }
+}
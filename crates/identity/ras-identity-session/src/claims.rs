@@ -0,0 +1,60 @@
+//! Application claims added to tokens at login.
+
+use async_trait::async_trait;
+use ras_identity_core::VerifiedIdentity;
+use serde_json::{Map, Value};
+
+/// Claims an augmenter may not set, because the session service sets them
+/// or tokens are validated by them
+pub const RESERVED_CLAIMS: &[&str] = &[
+    "sub",
+    "exp",
+    "iat",
+    "nbf",
+    "jti",
+    "iss",
+    "aud",
+    "provider_id",
+    "email",
+    "display_name",
+    "permissions",
+    "metadata",
+    "amr",
+];
+
+/// Adds claims to the token of each new session, such as a tenant id, locale
+/// or feature flags, so services reading the token needn't look them up.
+///
+/// Claims are added before signing and kept when the session is refreshed.
+/// Setting any of the [`RESERVED_CLAIMS`] fails the login with
+/// `SessionError::ReservedClaim`.
+#[async_trait]
+pub trait ClaimsAugmenter: Send + Sync {
+    async fn augment(&self, identity: &VerifiedIdentity, claims: &mut Map<String, Value>);
+}
+
+/// The first of `claims` that is reserved
+pub(crate) fn reserved_claim(claims: &Map<String, Value>) -> Option<&str> {
+    claims
+        .keys()
+        .map(String::as_str)
+        .find(|name| RESERVED_CLAIMS.contains(name))
+}
+
+/// `metadata` with the custom `claims` added, replacing entries of the same
+/// name. Metadata that isn't an object is kept under `metadata`.
+pub(crate) fn with_custom_claims(
+    metadata: Option<Value>,
+    claims: Map<String, Value>,
+) -> Option<Value> {
+    if claims.is_empty() {
+        return metadata;
+    }
+    let mut merged = match metadata {
+        None => Map::new(),
+        Some(Value::Object(metadata)) => metadata,
+        Some(metadata) => Map::from_iter([("metadata".to_string(), metadata)]),
+    };
+    merged.extend(claims);
+    Some(Value::Object(merged))
+}
@@ -0,0 +1,228 @@
+//! Keeping session tokens in cookies browsers send by themselves, with
+//! double-submit CSRF protection.
+
+use crate::SessionError;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand_core::{OsRng, RngCore};
+use ras_auth_core::{AuthFuture, AuthProvider, AuthResult, AuthenticatedUser, PermissionExpr};
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+
+/// When browsers send the cookies along with requests from other sites
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Needs `secure`
+    None,
+}
+
+/// The session and CSRF cookies, and the header the CSRF token is repeated in
+#[derive(Debug, Clone)]
+pub struct CookieConfig {
+    /// The cookie holding the session token, hidden from scripts
+    pub name: String,
+    /// The cookie holding the CSRF token, which scripts read to repeat it
+    pub csrf_cookie: String,
+    /// The header state-changing requests repeat the CSRF token in
+    pub csrf_header: HeaderName,
+    pub path: String,
+    pub domain: Option<String>,
+    /// Only send the cookies over HTTPS; turn off for local development
+    pub secure: bool,
+    pub same_site: SameSite,
+    /// How long browsers keep the cookies, until they close if unset
+    pub max_age: Option<Duration>,
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        Self {
+            name: "ras_session".to_string(),
+            csrf_cookie: "ras_csrf".to_string(),
+            csrf_header: HeaderName::from_static("x-csrf-token"),
+            path: "/".to_string(),
+            domain: None,
+            secure: true,
+            same_site: SameSite::Lax,
+            max_age: None,
+        }
+    }
+}
+
+impl CookieConfig {
+    /// A `Set-Cookie` value for `name`, hidden from scripts if `http_only`
+    fn set_cookie(
+        &self,
+        name: &str,
+        value: &str,
+        http_only: bool,
+        max_age: Option<u64>,
+    ) -> Result<HeaderValue, SessionError> {
+        let mut cookie = format!("{name}={value}; Path={}", self.path);
+        if let Some(domain) = &self.domain {
+            cookie.push_str(&format!("; Domain={domain}"));
+        }
+        if let Some(max_age) = max_age {
+            cookie.push_str(&format!("; Max-Age={max_age}"));
+        }
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie.push_str(match self.same_site {
+            SameSite::Strict => "; SameSite=Strict",
+            SameSite::Lax => "; SameSite=Lax",
+            SameSite::None => "; SameSite=None",
+        });
+        HeaderValue::try_from(cookie)
+            .map_err(|_| SessionError::InvalidConfig(format!("invalid cookie {name}")))
+    }
+}
+
+/// Set the session cookie holding `token` on `response`, along with a new
+/// CSRF token in a cookie scripts can read, returning the CSRF token.
+///
+/// Fails if the cookie names, path or domain can't be sent in a header.
+pub fn issue_session_cookie<B>(
+    response: &mut Response<B>,
+    token: &str,
+    config: &CookieConfig,
+) -> Result<String, SessionError> {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let csrf_token = URL_SAFE_NO_PAD.encode(secret);
+
+    let max_age = config.max_age.map(|max_age| max_age.as_secs());
+    let session = config.set_cookie(&config.name, token, true, max_age)?;
+    let csrf = config.set_cookie(&config.csrf_cookie, &csrf_token, false, max_age)?;
+    let headers = response.headers_mut();
+    headers.append(header::SET_COOKIE, session);
+    headers.append(header::SET_COOKIE, csrf);
+    Ok(csrf_token)
+}
+
+/// Tell the browser to forget the session and CSRF cookies, such as at logout
+pub fn clear_session_cookie<B>(
+    response: &mut Response<B>,
+    config: &CookieConfig,
+) -> Result<(), SessionError> {
+    let session = config.set_cookie(&config.name, "", true, Some(0))?;
+    let csrf = config.set_cookie(&config.csrf_cookie, "", false, Some(0))?;
+    let headers = response.headers_mut();
+    headers.append(header::SET_COOKIE, session);
+    headers.append(header::SET_COOKIE, csrf);
+    Ok(())
+}
+
+/// The value of the cookie `name` in the `Cookie` headers of `headers`
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(cookie, _)| *cookie == name)
+        .map(|(_, value)| value)
+}
+
+/// Authenticates requests by the session cookie of [`issue_session_cookie`],
+/// or by their `Authorization` header when they have no cookie, with `inner`.
+///
+/// Give it to a generated service like any other provider, and put
+/// [`csrf_protection`] in front of the service.
+pub struct CookieAuthProvider<P> {
+    inner: P,
+    config: CookieConfig,
+}
+
+impl<P: AuthProvider> CookieAuthProvider<P> {
+    pub fn new(inner: P, config: CookieConfig) -> Self {
+        Self { inner, config }
+    }
+}
+
+impl<P: AuthProvider> AuthProvider for CookieAuthProvider<P> {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        self.inner.authenticate(token)
+    }
+
+    fn authenticate_request(&self, token: String, headers: &HeaderMap) -> AuthFuture<'_> {
+        self.inner.authenticate_request(token, headers)
+    }
+
+    fn request_binding(&self, token: &str, headers: &HeaderMap) -> Vec<u8> {
+        self.inner.request_binding(token, headers)
+    }
+
+    fn credential(&self, headers: &HeaderMap) -> Option<String> {
+        match cookie(headers, &self.config.name) {
+            Some(token) if !token.is_empty() => Some(token.to_string()),
+            _ => self.inner.credential(headers),
+        }
+    }
+
+    fn check_permissions(
+        &self,
+        user: &AuthenticatedUser,
+        required_permissions: &[String],
+    ) -> AuthResult<()> {
+        self.inner.check_permissions(user, required_permissions)
+    }
+
+    fn check_permission_expr(
+        &self,
+        user: &AuthenticatedUser,
+        expression: &PermissionExpr,
+    ) -> AuthResult<()> {
+        self.inner.check_permission_expr(user, expression)
+    }
+}
+
+/// Refuse state-changing requests that carry the session cookie unless they
+/// repeat the CSRF cookie in the CSRF header, since another site can make a
+/// browser send the cookie but not read it.
+///
+/// Requests without the cookie, such as those authenticated by their
+/// `Authorization` header, pass. Add it with
+/// `axum::middleware::from_fn_with_state(Arc::new(cookie_config), csrf_protection)`.
+pub async fn csrf_protection(
+    State(config): State<Arc<CookieConfig>>,
+    request: Request,
+    next: Next,
+) -> axum::response::Response {
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    );
+    let headers = request.headers();
+    if safe || cookie(headers, &config.name).is_none_or(str::is_empty) {
+        return next.run(request).await;
+    }
+
+    let expected = cookie(headers, &config.csrf_cookie).filter(|token| !token.is_empty());
+    let presented = headers
+        .get(&config.csrf_header)
+        .and_then(|value| value.to_str().ok());
+    match (expected, presented) {
+        (Some(expected), Some(presented))
+            if bool::from(expected.as_bytes().ct_eq(presented.as_bytes())) =>
+        {
+            next.run(request).await
+        }
+        _ => (
+            StatusCode::FORBIDDEN,
+            axum::Json(serde_json::json!({ "error": "Missing or invalid CSRF token" })),
+        )
+            .into_response(),
+    }
+}
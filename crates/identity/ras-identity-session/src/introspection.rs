@@ -0,0 +1,201 @@
+//! Letting services that can't validate tokens themselves ask whether a
+//! token is active (RFC 7662), and revoke tokens (RFC 7009).
+
+use crate::{SessionError, SessionService};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderName, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Form, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+/// How callers of [`introspection_router`] prove they may use it
+#[derive(Clone)]
+pub enum IntrospectionClientAuth {
+    /// Callers send `Authorization: Bearer <secret>`, at least 32 bytes long
+    SharedSecret(String),
+    /// A proxy terminating mutual TLS passes the subject of the verified
+    /// client certificate in `header`, which must be one of `subjects`.
+    ///
+    /// The proxy must remove the header from requests it didn't verify.
+    ClientCertificate {
+        header: HeaderName,
+        subjects: Vec<String>,
+    },
+}
+
+impl IntrospectionClientAuth {
+    fn validate(&self) -> Result<(), SessionError> {
+        match self {
+            Self::SharedSecret(secret) if secret.len() < 32 => Err(SessionError::InvalidConfig(
+                "the introspection secret must be at least 32 bytes".to_string(),
+            )),
+            Self::ClientCertificate { subjects, .. }
+                if subjects.is_empty() || subjects.iter().any(|s| s.trim().is_empty()) =>
+            {
+                Err(SessionError::InvalidConfig(
+                    "introspection clients must name at least one certificate subject".to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn allows(&self, headers: &HeaderMap) -> bool {
+        match self {
+            Self::SharedSecret(secret) => headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|presented| presented.as_bytes().ct_eq(secret.as_bytes()).into()),
+            Self::ClientCertificate { header, subjects } => headers
+                .get(header)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|subject| subjects.iter().any(|allowed| allowed == subject)),
+        }
+    }
+}
+
+impl std::fmt::Debug for IntrospectionClientAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SharedSecret(_) => f.write_str("SharedSecret(..)"),
+            Self::ClientCertificate { header, subjects } => f
+                .debug_struct("ClientCertificate")
+                .field("header", header)
+                .field("subjects", subjects)
+                .finish(),
+        }
+    }
+}
+
+/// Serve `POST /introspect` and `POST /revoke` for the tokens of
+/// `session_service`, to callers authenticated by `client_auth`.
+///
+/// Both take form-encoded bodies. `/introspect` takes a `token` and reports
+/// whether it's active, with its subject, expiry and permissions; tokens of
+/// ended sessions are inactive while sessions are tracked with
+/// `enforce_active_sessions`. `/revoke` takes a session `jti` or a `token`,
+/// which may be a refresh token, and ends it.
+///
+/// Fails if `client_auth` has a short secret or no certificate subjects.
+pub fn introspection_router(
+    session_service: Arc<SessionService>,
+    client_auth: IntrospectionClientAuth,
+) -> Result<Router, SessionError> {
+    client_auth.validate()?;
+    if !session_service.config.enforce_active_sessions {
+        tracing::warn!(
+            "sessions aren't tracked, so introspection reports ended sessions active until they expire"
+        );
+    }
+    Ok(Router::new()
+        .route("/introspect", post(introspect))
+        .route("/revoke", post(revoke))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::new(client_auth),
+            authenticate_client,
+        ))
+        .with_state(session_service))
+}
+
+async fn authenticate_client(
+    State(client_auth): State<Arc<IntrospectionClientAuth>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if client_auth.allows(request.headers()) {
+        return next.run(request).await;
+    }
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        Json(serde_json::json!({ "error": "invalid_client" })),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct IntrospectRequest {
+    token: String,
+}
+
+/// What `/introspect` says about a token; only `active` for inactive ones
+#[derive(Default, Serialize)]
+struct Introspection {
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jti: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permissions: Option<BTreeSet<String>>,
+}
+
+async fn introspect(
+    State(session_service): State<Arc<SessionService>>,
+    Form(request): Form<IntrospectRequest>,
+) -> Response {
+    let introspection = match session_service.verify_session(&request.token).await {
+        Ok(claims) => Introspection {
+            active: true,
+            sub: Some(claims.sub),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            jti: Some(claims.jti),
+            permissions: Some(claims.permissions.into_iter().collect()),
+        },
+        Err(error @ SessionError::StoreError(_)) => return server_error(error),
+        Err(_) => Introspection::default(),
+    };
+    ([(header::CACHE_CONTROL, "no-store")], Json(introspection)).into_response()
+}
+
+#[derive(Deserialize)]
+struct RevokeRequest {
+    token: Option<String>,
+    jti: Option<String>,
+}
+
+/// Ends the session or refresh token named, answering 200 whether or not it
+/// was known, as RFC 7009 asks
+async fn revoke(
+    State(session_service): State<Arc<SessionService>>,
+    Form(request): Form<RevokeRequest>,
+) -> Response {
+    let revoked = match (request.jti, request.token) {
+        (Some(jti), _) => session_service.end_session(&jti).await.map(drop),
+        (None, Some(token)) => match session_service.jti(&token) {
+            Some(jti) => session_service.end_session(&jti).await.map(drop),
+            None => session_service.revoke_refresh(&token).await.map(drop),
+        },
+        (None, None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": "invalid_request" })),
+            )
+                .into_response();
+        }
+    };
+    match revoked {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(error) => server_error(error),
+    }
+}
+
+fn server_error(error: SessionError) -> Response {
+    tracing::warn!(%error, "introspection request failed");
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({ "error": "temporarily_unavailable" })),
+    )
+        .into_response()
+}
@@ -0,0 +1,188 @@
+//! Publishing the public keys tokens are signed with, and validating tokens
+//! against keys published elsewhere.
+
+use crate::{JwtClaims, SessionError, SessionService};
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+
+/// Serve the public keys of `session_service` at `/.well-known/jwks.json`
+pub fn jwks_router(session_service: Arc<SessionService>) -> Router {
+    Router::new()
+        .route("/.well-known/jwks.json", get(serve_jwks))
+        .with_state(session_service)
+}
+
+async fn serve_jwks(State(session_service): State<Arc<SessionService>>) -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(session_service.jwks()),
+    )
+}
+
+/// Public keys fetched from a JWKS URL, such as another service's
+/// `/.well-known/jwks.json`.
+///
+/// The keys are fetched again once they're older than the cache TTL, or when
+/// a token names a key that isn't among them, but at most once per minimum
+/// refresh interval so unknown key ids can't flood the publisher.
+pub struct RemoteJwks {
+    url: String,
+    client: reqwest::Client,
+    cache_ttl: Duration,
+    min_refresh_interval: Duration,
+    cache: RwLock<Cached>,
+    /// Held while fetching, so concurrent misses fetch once
+    fetching: Mutex<()>,
+}
+
+struct Cached {
+    keys: JwkSet,
+    fetched_at: Option<Instant>,
+}
+
+impl RemoteJwks {
+    /// Fetch keys from `url`, caching them for 10 minutes and fetching at most
+    /// every 30 seconds
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+            cache_ttl: Duration::from_secs(600),
+            min_refresh_interval: Duration::from_secs(30),
+            cache: RwLock::new(Cached {
+                keys: JwkSet { keys: Vec::new() },
+                fetched_at: None,
+            }),
+            fetching: Mutex::new(()),
+        }
+    }
+
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    pub fn with_min_refresh_interval(mut self, interval: Duration) -> Self {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    /// The key with `kid`, fetching the keys again if they're stale or don't have it
+    pub async fn key(&self, kid: &str) -> Result<Option<Jwk>, SessionError> {
+        if let Some(key) = self.cached(kid, self.cache_ttl).await {
+            return Ok(Some(key));
+        }
+
+        let _fetching = self.fetching.lock().await;
+        // Another caller may have fetched while this one waited
+        if let Some(key) = self.cached(kid, self.cache_ttl).await {
+            return Ok(Some(key));
+        }
+        let recently_fetched = self
+            .cache
+            .read()
+            .await
+            .fetched_at
+            .is_some_and(|at| at.elapsed() < self.min_refresh_interval);
+        if !recently_fetched {
+            self.fetch().await?;
+        }
+        Ok(self.cached(kid, Duration::MAX).await)
+    }
+
+    async fn cached(&self, kid: &str, max_age: Duration) -> Option<Jwk> {
+        let cache = self.cache.read().await;
+        let fresh = cache.fetched_at.is_some_and(|at| at.elapsed() < max_age);
+        fresh.then(|| cache.keys.find(kid).cloned()).flatten()
+    }
+
+    async fn fetch(&self) -> Result<(), SessionError> {
+        let keys = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| SessionError::JwksError(e.to_string()))?
+            .json::<JwkSet>()
+            .await
+            .map_err(|e| SessionError::JwksError(e.to_string()))?;
+        *self.cache.write().await = Cached {
+            keys,
+            fetched_at: Some(Instant::now()),
+        };
+        Ok(())
+    }
+
+    /// Validate `token` against the key its header names.
+    ///
+    /// Only the signature and expiry are checked; whether the session is
+    /// still active is known only to the service that issued it, and the
+    /// audience is checked by [`JwtAuthProvider::with_audience`](crate::JwtAuthProvider::with_audience).
+    pub async fn verify(&self, token: &str) -> Result<JwtClaims, SessionError> {
+        let header = decode_header(token)?;
+        let kid = header.kid.ok_or(SessionError::InvalidSession)?;
+        let jwk = self.key(&kid).await?.ok_or(SessionError::InvalidSession)?;
+        // `alg` is optional in a JWK, so the token's is used, as long as it
+        // suits the key and agrees with the key's own if it has one
+        let algorithm = header.alg;
+        let named = jwk
+            .common
+            .key_algorithm
+            .map(|alg| alg.to_string().parse::<Algorithm>())
+            .transpose()?;
+        if !suits(&jwk.algorithm, algorithm) || named.is_some_and(|named| named != algorithm) {
+            return Err(SessionError::InvalidSession);
+        }
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_required_spec_claims(&["exp"]);
+        validation.validate_aud = false;
+        validation.validate_nbf = true;
+        let token_data = decode::<JwtClaims>(token, &DecodingKey::from_jwk(&jwk)?, &validation)?;
+        Ok(token_data.claims)
+    }
+}
+
+/// Whether tokens signed with `algorithm` can be checked with `key`.
+///
+/// Never for symmetric keys: a published one would let anyone mint tokens.
+fn suits(key: &AlgorithmParameters, algorithm: Algorithm) -> bool {
+    use Algorithm::*;
+    match key {
+        AlgorithmParameters::RSA(_) => {
+            matches!(algorithm, RS256 | RS384 | RS512 | PS256 | PS384 | PS512)
+        }
+        AlgorithmParameters::EllipticCurve(ec) => matches!(
+            (&ec.curve, algorithm),
+            (EllipticCurve::P256, ES256) | (EllipticCurve::P384, ES384)
+        ),
+        AlgorithmParameters::OctetKeyPair(okp) => {
+            okp.curve == EllipticCurve::Ed25519 && algorithm == EdDSA
+        }
+        AlgorithmParameters::OctetKey(_) => false,
+    }
+}
+
+impl std::fmt::Debug for RemoteJwks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteJwks")
+            .field("url", &self.url)
+            .field("cache_ttl", &self.cache_ttl)
+            .field("min_refresh_interval", &self.min_refresh_interval)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,286 @@
+//! Keys tokens are signed with, and the public keys that validate them.
+
+use crate::SessionError;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::jwk::{Jwk, JwkSet, PublicKeyUse, ThumbprintHash};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use p256::pkcs8::EncodePrivateKey;
+use rand_core::OsRng;
+use rsa::pkcs1::EncodeRsaPrivateKey;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// Where a [`SessionService`](crate::SessionService) gets the key it signs tokens with
+#[derive(Debug, Clone, Default)]
+pub enum KeySource {
+    /// `jwt_secret`, for the HMAC algorithms. Every service that can validate
+    /// tokens can also mint them.
+    #[default]
+    Secret,
+    /// The private key in a PEM file, PKCS#1 or PKCS#8 for RS256 and PKCS#8 for ES256
+    PemFile(PathBuf),
+    /// A key pair generated when the service is created, so tokens stop
+    /// validating when the process restarts
+    Generated,
+    /// The current key of a [`KeyStore`], which can be rotated while the service runs
+    KeyStore(Arc<KeyStore>),
+}
+
+/// A private key that signs tokens, with its public half
+#[derive(Clone)]
+pub struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    jwk: Jwk,
+}
+
+impl SigningKey {
+    /// Load a private key in PEM for `algorithm`, RS256 or ES256.
+    ///
+    /// The key id (`kid`) is the key's RFC 7638 thumbprint, unless set with
+    /// [`with_kid`](Self::with_kid).
+    pub fn from_pem(algorithm: Algorithm, pem: &[u8]) -> Result<Self, SessionError> {
+        let encoding = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(pem),
+            Algorithm::ES256 => EncodingKey::from_ec_pem(pem),
+            other => return Err(unsupported(other)),
+        }
+        .map_err(|e| {
+            SessionError::InvalidConfig(format!("invalid {:?} private key: {}", algorithm, e))
+        })?;
+        Self::from_encoding_key(algorithm, encoding)
+    }
+
+    /// Generate a new key pair for `algorithm`, RS256 (2048 bits) or ES256
+    pub fn generate(algorithm: Algorithm) -> Result<Self, SessionError> {
+        let der = match algorithm {
+            Algorithm::RS256 => rsa::RsaPrivateKey::new(&mut OsRng, 2048)
+                .map_err(|e| SessionError::InvalidConfig(e.to_string()))?
+                .to_pkcs1_der()
+                .map_err(|e| SessionError::InvalidConfig(e.to_string()))?,
+            Algorithm::ES256 => p256::SecretKey::random(&mut OsRng)
+                .to_pkcs8_der()
+                .map_err(|e| SessionError::InvalidConfig(e.to_string()))?,
+            other => return Err(unsupported(other)),
+        };
+        let encoding = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_der(der.as_bytes()),
+            _ => EncodingKey::from_ec_der(der.as_bytes()),
+        };
+        Self::from_encoding_key(algorithm, encoding)
+    }
+
+    fn from_encoding_key(
+        algorithm: Algorithm,
+        encoding: EncodingKey,
+    ) -> Result<Self, SessionError> {
+        let mut jwk = Jwk::from_encoding_key(&encoding, algorithm)?;
+        let kid = jwk.thumbprint(ThumbprintHash::SHA256);
+        jwk.common.public_key_use = Some(PublicKeyUse::Signature);
+        jwk.common.key_id = Some(kid.clone());
+        let decoding = DecodingKey::from_jwk(&jwk)?;
+        Ok(Self {
+            kid,
+            algorithm,
+            encoding,
+            decoding,
+            jwk,
+        })
+    }
+
+    /// Set the key id tokens signed with this key name in their header
+    pub fn with_kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = kid.into();
+        self.jwk.common.key_id = Some(self.kid.clone());
+        self
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The public key, as published in a JWKS
+    pub fn jwk(&self) -> &Jwk {
+        &self.jwk
+    }
+
+    pub(crate) fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding
+    }
+
+    pub(crate) fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding
+    }
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Signing keys that can be rotated while tokens signed with earlier keys
+/// stay valid.
+///
+/// New tokens are signed with the current key. A key that was rotated out is
+/// still accepted, and still published, until its overlap ends.
+pub struct KeyStore {
+    ring: RwLock<KeyRing>,
+}
+
+struct KeyRing {
+    current: SigningKey,
+    /// Keys rotated out, with when they stop being accepted
+    retiring: Vec<(SigningKey, DateTime<Utc>)>,
+}
+
+impl KeyStore {
+    pub fn new(key: SigningKey) -> Self {
+        Self {
+            ring: RwLock::new(KeyRing {
+                current: key,
+                retiring: Vec::new(),
+            }),
+        }
+    }
+
+    /// Sign with `key` from now on, accepting tokens signed with the current
+    /// key for another `overlap`.
+    ///
+    /// Make `overlap` at least the lifetime of the tokens the current key
+    /// signed, so they expire before their key is dropped.
+    pub fn rotate(&self, key: SigningKey, overlap: Duration) {
+        let mut ring = self.ring.write().unwrap_or_else(|e| e.into_inner());
+        let now = Utc::now();
+        ring.retiring.retain(|(_, until)| *until > now);
+        let previous = std::mem::replace(&mut ring.current, key);
+        ring.retiring.push((previous, now + overlap));
+    }
+
+    /// The key new tokens are signed with
+    pub fn current(&self) -> SigningKey {
+        self.ring
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .current
+            .clone()
+    }
+
+    /// The key with `kid`, if tokens signed with it are accepted
+    pub fn get(&self, kid: &str) -> Option<SigningKey> {
+        let ring = self.ring.read().unwrap_or_else(|e| e.into_inner());
+        ring.accepted().find(|key| key.kid == kid).cloned()
+    }
+
+    /// The public keys of every accepted key, the current one first
+    pub fn jwks(&self) -> JwkSet {
+        let ring = self.ring.read().unwrap_or_else(|e| e.into_inner());
+        JwkSet {
+            keys: ring.accepted().map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
+
+impl KeyRing {
+    fn accepted(&self) -> impl Iterator<Item = &SigningKey> {
+        let now = Utc::now();
+        std::iter::once(&self.current).chain(
+            self.retiring
+                .iter()
+                .rev()
+                .filter(move |(_, until)| *until > now)
+                .map(|(key, _)| key),
+        )
+    }
+}
+
+impl fmt::Debug for KeyStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ring = self.ring.read().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("KeyStore")
+            .field("current", &ring.current.kid)
+            .field("retiring", &ring.retiring.len())
+            .finish()
+    }
+}
+
+fn unsupported(algorithm: Algorithm) -> SessionError {
+    SessionError::InvalidConfig(format!(
+        "{:?} keys are not supported, use RS256 or ES256",
+        algorithm
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pem_keys_are_named_by_thumbprint() {
+        let rsa = SigningKey::from_pem(
+            Algorithm::RS256,
+            include_bytes!("../tests/fixtures/rs256.pem"),
+        )
+        .unwrap();
+        let ec = SigningKey::from_pem(
+            Algorithm::ES256,
+            include_bytes!("../tests/fixtures/es256.pem"),
+        )
+        .unwrap();
+
+        assert_eq!(rsa.kid(), rsa.jwk().thumbprint(ThumbprintHash::SHA256));
+        assert_eq!(rsa.jwk().common.key_id.as_deref(), Some(rsa.kid()));
+        assert_ne!(rsa.kid(), ec.kid());
+        assert_eq!(
+            ec.with_kid("2026-10").jwk().common.key_id.as_deref(),
+            Some("2026-10")
+        );
+    }
+
+    #[test]
+    fn test_rejects_mismatched_and_symmetric_keys() {
+        let ec_pem = include_bytes!("../tests/fixtures/es256.pem");
+        assert!(matches!(
+            SigningKey::from_pem(Algorithm::RS256, ec_pem),
+            Err(SessionError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            SigningKey::generate(Algorithm::HS256),
+            Err(SessionError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_rotated_keys_are_published_until_their_overlap_ends() {
+        let first = SigningKey::generate(Algorithm::ES256).unwrap();
+        let second = SigningKey::generate(Algorithm::ES256).unwrap();
+        let third = SigningKey::generate(Algorithm::ES256).unwrap();
+        let store = KeyStore::new(first.clone());
+
+        store.rotate(second.clone(), Duration::hours(1));
+        assert_eq!(store.current().kid(), second.kid());
+        let kids: Vec<_> = store
+            .jwks()
+            .keys
+            .into_iter()
+            .filter_map(|jwk| jwk.common.key_id)
+            .collect();
+        assert_eq!(kids, [second.kid(), first.kid()]);
+
+        // The second key is dropped at once, the first lasts its hour
+        store.rotate(third.clone(), Duration::zero());
+        assert!(store.get(second.kid()).is_none());
+        assert!(store.get(first.kid()).is_some());
+        assert_eq!(store.jwks().keys.len(), 2);
+    }
+}
@@ -2,16 +2,70 @@
 
 use async_trait::async_trait;
 use chrono::{Duration, Utc};
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
-use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
-use ras_identity_core::{IdentityError, IdentityProvider, UserPermissions};
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation, decode, decode_header,
+    encode,
+};
+use ras_auth_core::{
+    AuthError, AuthFuture, AuthProvider, AuthResult, AuthenticatedUser, PrincipalKind,
+};
+use ras_identity_core::{
+    AuthSource, EventDispatcher, IdentityError, IdentityEvent, IdentityEventKind,
+    IdentityEventSink, IdentityProvider, UserPermissions,
+};
+use ras_observability_core::{RequestOrigin, ServiceMetrics};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, broadcast};
 use uuid::Uuid;
 
+mod binding;
+mod claims;
+#[cfg(feature = "cookies")]
+mod cookie;
+#[cfg(feature = "introspection")]
+mod introspection;
+#[cfg(feature = "jwks")]
+mod jwks;
+mod keys;
+mod maintenance;
+mod overlay;
+mod rate_limit;
+#[cfg(feature = "redis-store")]
+mod redis_store;
+mod refresh;
+mod revocation;
+mod store;
+
+pub use binding::{BindingInfo, BindingMode, BindingPolicy};
+pub use claims::{ClaimsAugmenter, RESERVED_CLAIMS};
+#[cfg(feature = "cookies")]
+pub use cookie::{
+    CookieAuthProvider, CookieConfig, SameSite, clear_session_cookie, csrf_protection,
+    issue_session_cookie,
+};
+#[cfg(feature = "introspection")]
+pub use introspection::{IntrospectionClientAuth, introspection_router};
+#[cfg(feature = "jwks")]
+pub use jwks::{RemoteJwks, jwks_router};
+pub use keys::{KeySource, KeyStore, SigningKey};
+pub use maintenance::SessionMaintenance;
+pub use overlay::{
+    MemoryOverlay, OverlayAuthProvider, PermissionChanges, PermissionOverlay, PolledOverlay,
+};
+pub use rate_limit::{AuthRateLimiter, RateLimit, RateLimitContext, SlidingWindowLimiter};
+#[cfg(feature = "redis-store")]
+pub use redis_store::RedisSessionStore;
+pub use refresh::TokenPair;
+pub use store::{MemorySessionStore, RefreshRecord, SessionRecord, SessionStore};
+
+use revocation::Revocations;
+
 #[derive(Debug, Error)]
 pub enum SessionError {
     #[error("JWT error: {0}")]
@@ -28,6 +82,41 @@ pub enum SessionError {
 
     #[error("Invalid session configuration: {0}")]
     InvalidConfig(String),
+
+    #[error("Session store error: {0}")]
+    StoreError(String),
+
+    #[error("JWKS error: {0}")]
+    JwksError(String),
+
+    /// A [`PermissionOverlay`] couldn't read its changes
+    #[error("Permission overlay error: {0}")]
+    OverlayError(String),
+
+    #[error("Refresh tokens are disabled")]
+    RefreshDisabled,
+
+    /// A refresh token that was already rotated was presented again, so its
+    /// whole family was revoked
+    #[error("Refresh token reused")]
+    RefreshTokenReused,
+
+    /// A [`ClaimsAugmenter`] set a claim in [`RESERVED_CLAIMS`]
+    #[error("Claim {0} is reserved")]
+    ReservedClaim(String),
+
+    /// The session was used from a client other than the one it began on, by
+    /// the enforced parts of its [`BindingInfo`] named
+    #[error("Session used from another client ({})", .0.join(", "))]
+    BindingMismatch(Vec<String>),
+
+    #[error("Too many login attempts, retry in {} seconds", whole_seconds(*.retry_after))]
+    RateLimited { retry_after: std::time::Duration },
+}
+
+/// `duration` in seconds, rounded up
+fn whole_seconds(duration: std::time::Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,21 +124,120 @@ pub struct JwtClaims {
     pub sub: String,
     pub exp: i64,
     pub iat: i64,
+    /// When the token becomes valid, if not when issued
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<i64>,
     pub jti: String,
+    /// The service that issued the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    /// The services the token is meant for
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "one_or_many"
+    )]
+    pub aud: Vec<String>,
     pub provider_id: String,
     pub email: Option<String>,
     pub display_name: Option<String>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub permissions: HashSet<String>,
     pub metadata: Option<serde_json::Value>,
+    /// How the user authenticated, e.g. `["pwd", "otp", "mfa"]` after a second factor (RFC 8176)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<String>,
+    /// Claims added by the [`ClaimsAugmenter`], alongside the others in the token
+    #[serde(flatten)]
+    pub custom: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Serialize permissions in order, so the same claims always serialize the same
+fn serialize_sorted<S: Serializer>(
+    permissions: &HashSet<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(permissions.iter().collect::<BTreeSet<_>>())
+}
+
+/// A claim that may be a single string or an array of them, as `aud` may
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Something that happened to a session, published to [`SessionService::subscribe`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SessionEvent {
+    /// The session was ended through [`SessionService::end_session`]
+    Ended { jti: String, user_id: String },
+    /// Every session of the user was ended through
+    /// [`SessionService::end_sessions_for_subject`], including any that began
+    /// by `at`, in seconds since the Unix epoch, but weren't tracked
+    SubjectRevoked {
+        provider_id: String,
+        user_id: String,
+        at: i64,
+    },
+}
+
+/// An active session, as listed by [`SessionService::list_sessions_for_subject`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub jti: String,
+    pub iat: i64,
+    /// When the session ends unless it's extended, with `sliding_ttl`
+    pub exp: i64,
+    /// The user agent the session began from, if the login passed it
+    pub user_agent: Option<String>,
 }
 
+/// Session events kept for subscribers that fall behind
+const SESSION_EVENT_CAPACITY: usize = 256;
+
 #[derive(Debug, Clone)]
 pub struct SessionConfig {
     pub jwt_secret: String,
     pub jwt_ttl: Duration,
+    /// Allow [`SessionService::begin_session_with_refresh`]
     pub refresh_enabled: bool,
+    /// Lifetime of each refresh token, restarted when it is rotated
+    pub refresh_ttl: Duration,
+    /// Lifetime of access tokens issued alongside a refresh token, in place of `jwt_ttl`
+    pub refreshable_jwt_ttl: Duration,
     pub enforce_active_sessions: bool,
+    /// End sessions that go this long without being verified. Needs
+    /// `enforce_active_sessions`, and a token's `exp` still bounds its session.
+    pub sliding_ttl: Option<Duration>,
+    /// The longest a login lasts, across refreshes, however active it is
+    pub absolute_max: Option<Duration>,
     pub algorithm: Algorithm,
+    /// The key tokens are signed with, `jwt_secret` unless set
+    pub key_source: KeySource,
+    /// Set as the `iss` of tokens, and required of the tokens verified
+    pub issuer: Option<String>,
+    /// Set as the `aud` of tokens; verified tokens must name one of them.
+    /// Without it, tokens are accepted by any service sharing the signing key.
+    pub audience: Vec<String>,
+    /// How far the clocks of the services issuing and verifying tokens may
+    /// differ, tolerated when checking `exp`, `nbf` and `iat`
+    pub leeway: Duration,
+    /// Set the `nbf` of tokens this long after they're issued; a negative
+    /// offset makes them valid a little before, for verifiers without leeway
+    pub not_before: Option<Duration>,
+    /// How sessions begun with [`SessionService::begin_bound_session`] are
+    /// checked against the client using them. Needs `enforce_active_sessions`.
+    pub binding: BindingPolicy,
 }
 
 impl SessionConfig {
@@ -58,15 +246,76 @@ impl SessionConfig {
             jwt_secret: jwt_secret.into(),
             jwt_ttl: Duration::hours(24),
             refresh_enabled: true,
+            refresh_ttl: Duration::days(30),
+            refreshable_jwt_ttl: Duration::minutes(15),
             enforce_active_sessions: true,
+            sliding_ttl: None,
+            absolute_max: None,
             algorithm: Algorithm::HS256,
+            key_source: KeySource::Secret,
+            issuer: None,
+            audience: Vec::new(),
+            leeway: Duration::seconds(60),
+            not_before: None,
+            binding: BindingPolicy::default(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Sign tokens with `algorithm`, RS256 or ES256, and the private key of
+    /// `key_source`, so services holding only the public keys can validate them
+    pub fn with_key_source(
+        algorithm: Algorithm,
+        key_source: KeySource,
+    ) -> Result<Self, SessionError> {
+        let config = Self {
+            jwt_secret: String::new(),
+            jwt_ttl: Duration::hours(24),
+            refresh_enabled: true,
+            refresh_ttl: Duration::days(30),
+            refreshable_jwt_ttl: Duration::minutes(15),
+            enforce_active_sessions: true,
+            sliding_ttl: None,
+            absolute_max: None,
+            algorithm,
+            key_source,
+            issuer: None,
+            audience: Vec::new(),
+            leeway: Duration::seconds(60),
+            not_before: None,
+            binding: BindingPolicy::default(),
         };
         config.validate()?;
         Ok(config)
     }
 
     pub fn validate(&self) -> Result<(), SessionError> {
-        validate_jwt_secret(&self.jwt_secret)?;
+        let symmetric = matches!(
+            self.algorithm,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        );
+        match &self.key_source {
+            KeySource::Secret if symmetric => validate_jwt_secret(&self.jwt_secret)?,
+            KeySource::Secret => {
+                return Err(SessionError::InvalidConfig(format!(
+                    "{:?} needs a private key, set key_source",
+                    self.algorithm
+                )));
+            }
+            KeySource::KeyStore(keys) if keys.current().algorithm() != self.algorithm => {
+                return Err(SessionError::InvalidConfig(
+                    "algorithm must be that of the key store's current key".to_string(),
+                ));
+            }
+            _ if symmetric => {
+                return Err(SessionError::InvalidConfig(format!(
+                    "{:?} signs with jwt_secret, use RS256 or ES256 with a private key",
+                    self.algorithm
+                )));
+            }
+            _ => {}
+        }
 
         if self.jwt_ttl <= Duration::zero() {
             return Err(SessionError::InvalidConfig(
@@ -74,10 +323,84 @@ impl SessionConfig {
             ));
         }
 
+        if self.refresh_enabled
+            && (self.refresh_ttl <= Duration::zero()
+                || self.refreshable_jwt_ttl <= Duration::zero())
+        {
+            return Err(SessionError::InvalidConfig(
+                "refresh_ttl and refreshable_jwt_ttl must be positive".to_string(),
+            ));
+        }
+
+        if let Some(sliding_ttl) = self.sliding_ttl {
+            if sliding_ttl <= Duration::zero() {
+                return Err(SessionError::InvalidConfig(
+                    "sliding_ttl must be positive".to_string(),
+                ));
+            }
+            if !self.enforce_active_sessions {
+                return Err(SessionError::InvalidConfig(
+                    "sliding_ttl needs enforce_active_sessions".to_string(),
+                ));
+            }
+        }
+
+        if self
+            .absolute_max
+            .is_some_and(|absolute_max| absolute_max <= Duration::zero())
+        {
+            return Err(SessionError::InvalidConfig(
+                "absolute_max must be positive".to_string(),
+            ));
+        }
+
+        if self.leeway < Duration::zero() {
+            return Err(SessionError::InvalidConfig(
+                "leeway must not be negative".to_string(),
+            ));
+        }
+        if self.not_before.is_some_and(|not_before| {
+            not_before >= self.jwt_ttl
+                || (self.refresh_enabled && not_before >= self.refreshable_jwt_ttl)
+        }) {
+            return Err(SessionError::InvalidConfig(
+                "not_before must be shorter than the lifetime of tokens".to_string(),
+            ));
+        }
+
+        // An empty value would issue tokens no service accepts, or be
+        // mistaken for validation that isn't happening
+        if self
+            .issuer
+            .as_deref()
+            .is_some_and(|issuer| issuer.trim().is_empty())
+        {
+            return Err(SessionError::InvalidConfig(
+                "issuer must not be empty, leave it unset instead".to_string(),
+            ));
+        }
+        validate_audience(&self.audience, "audience")?;
+
+        if !self.binding.is_off() && !self.enforce_active_sessions {
+            return Err(SessionError::InvalidConfig(
+                "binding needs enforce_active_sessions".to_string(),
+            ));
+        }
+
         Ok(())
     }
 }
 
+/// Refuse blank audience names
+fn validate_audience(audience: &[String], name: &str) -> Result<(), SessionError> {
+    if audience.iter().any(|audience| audience.trim().is_empty()) {
+        return Err(SessionError::InvalidConfig(format!(
+            "{name} must not contain empty values"
+        )));
+    }
+    Ok(())
+}
+
 fn validate_jwt_secret(secret: &str) -> Result<(), SessionError> {
     let trimmed = secret.trim();
     let insecure_placeholders = [
@@ -109,20 +432,99 @@ fn validate_jwt_secret(secret: &str) -> Result<(), SessionError> {
 pub struct SessionService {
     config: SessionConfig,
     providers: Arc<RwLock<HashMap<String, Box<dyn IdentityProvider>>>>,
-    active_sessions: Arc<RwLock<HashMap<String, JwtClaims>>>,
+    sessions: Arc<dyn SessionStore>,
     permissions_provider: Option<Arc<dyn UserPermissions>>,
+    claims_augmenter: Option<Arc<dyn ClaimsAugmenter>>,
+    events: broadcast::Sender<SessionEvent>,
+    audit: Option<EventDispatcher>,
+    rate_limiter: Option<Arc<dyn AuthRateLimiter>>,
+    metrics: Option<Arc<dyn ServiceMetrics>>,
+    /// Set while [`start_maintenance`](Self::start_maintenance) purges expired
+    /// sessions, so requests don't
+    maintained: Arc<AtomicBool>,
+    /// The keys tokens are signed with, unless signed with `jwt_secret`
+    keys: Option<Arc<KeyStore>>,
 }
 impl SessionService {
     pub fn new(config: SessionConfig) -> Result<Self, SessionError> {
         config.validate()?;
+        let keys = match &config.key_source {
+            KeySource::Secret => None,
+            KeySource::PemFile(path) => {
+                let pem = std::fs::read(path).map_err(|e| {
+                    SessionError::InvalidConfig(format!("cannot read {}: {}", path.display(), e))
+                })?;
+                let key = SigningKey::from_pem(config.algorithm, &pem)?;
+                Some(Arc::new(KeyStore::new(key)))
+            }
+            KeySource::Generated => {
+                let key = SigningKey::generate(config.algorithm)?;
+                Some(Arc::new(KeyStore::new(key)))
+            }
+            KeySource::KeyStore(keys) => Some(keys.clone()),
+        };
+        if config.audience.is_empty() {
+            tracing::warn!(
+                "session tokens carry no audience, so any service sharing the signing key accepts them"
+            );
+        }
         Ok(Self {
             config,
+            keys,
             providers: Arc::new(RwLock::new(HashMap::new())),
-            active_sessions: Arc::new(RwLock::new(HashMap::new())),
+            sessions: Arc::new(MemorySessionStore::new()),
             permissions_provider: None,
+            claims_augmenter: None,
+            events: broadcast::channel(SESSION_EVENT_CAPACITY).0,
+            audit: None,
+            rate_limiter: None,
+            metrics: None,
+            maintained: Arc::new(AtomicBool::new(false)),
         })
     }
 
+    /// The configuration the service was created with
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// Keep active sessions in `store` rather than in this process, such as to
+    /// share them between replicas
+    pub fn with_session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.sessions = store;
+        self
+    }
+
+    /// Report sessions starting and ending, and the number of active sessions
+    /// while [`start_maintenance`](Self::start_maintenance) runs, to `metrics`.
+    ///
+    /// Only tracked sessions are counted, so nothing ends for `expiry` or
+    /// `revocation` unless `enforce_active_sessions` is set.
+    pub fn with_service_metrics(mut self, metrics: Arc<dyn ServiceMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Report logins and sessions starting, ending and being rejected to `sink`.
+    ///
+    /// Events are delivered from a background task, so this must be called
+    /// within a Tokio runtime.
+    pub fn with_event_sink(mut self, sink: Arc<dyn IdentityEventSink>) -> Self {
+        self.audit = Some(EventDispatcher::new(sink));
+        self
+    }
+
+    /// How many events were dropped because the event sink fell behind
+    pub fn dropped_events(&self) -> u64 {
+        self.audit.as_ref().map_or(0, EventDispatcher::dropped)
+    }
+
+    fn emit(&self, event: IdentityEvent) {
+        if let Some(audit) = &self.audit {
+            audit.emit(event);
+        }
+    }
+
     pub fn with_permissions(mut self, provider: Arc<dyn UserPermissions>) -> Self {
         self.permissions_provider = Some(provider);
         self
@@ -132,6 +534,22 @@ impl SessionService {
         self.permissions_provider = Some(provider);
     }
 
+    /// Add the claims `augmenter` sets to the token of each new session
+    pub fn with_claims_augmenter(mut self, augmenter: Arc<dyn ClaimsAugmenter>) -> Self {
+        self.claims_augmenter = Some(augmenter);
+        self
+    }
+
+    /// Ask `limiter` before each login attempt, refusing those it refuses with
+    /// `SessionError::RateLimited`.
+    ///
+    /// Pass the client's address with [`begin_session_from`](Self::begin_session_from)
+    /// so it can limit attempts per address as well as per username.
+    pub fn with_rate_limiter(mut self, limiter: Arc<dyn AuthRateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
     pub async fn register_provider(&self, provider: Box<dyn IdentityProvider>) {
         let mut providers = self.providers.write().await;
         providers.insert(provider.provider_id().to_string(), provider);
@@ -142,19 +560,119 @@ impl SessionService {
         provider_id: &str,
         auth_payload: serde_json::Value,
     ) -> Result<String, SessionError> {
-        if self.config.enforce_active_sessions {
-            self.cleanup_expired_sessions().await;
+        let (token, _) = self
+            .start_session(provider_id, auth_payload, None, None, self.config.jwt_ttl)
+            .await?;
+        Ok(token)
+    }
+
+    /// [`begin_session`](Self::begin_session) for a login from `source`, which
+    /// is recorded in events and limited by the rate limiter's per-address limit.
+    pub async fn begin_session_from(
+        &self,
+        provider_id: &str,
+        auth_payload: serde_json::Value,
+        source: AuthSource,
+    ) -> Result<String, SessionError> {
+        let (token, _) = self
+            .start_session(
+                provider_id,
+                auth_payload,
+                Some(source),
+                None,
+                self.config.jwt_ttl,
+            )
+            .await?;
+        Ok(token)
+    }
+
+    /// [`begin_session_from`](Self::begin_session_from) binding the session to
+    /// the client logging in, which tokens must then be verified from with
+    /// [`verify_bound_session`](Self::verify_bound_session), as checked by
+    /// the config's `binding`.
+    pub async fn begin_bound_session(
+        &self,
+        provider_id: &str,
+        auth_payload: serde_json::Value,
+        source: Option<AuthSource>,
+        binding: BindingInfo,
+    ) -> Result<String, SessionError> {
+        let (token, _) = self
+            .start_session(
+                provider_id,
+                auth_payload,
+                source,
+                Some(binding),
+                self.config.jwt_ttl,
+            )
+            .await?;
+        Ok(token)
+    }
+
+    /// Verify `auth_payload` and issue a token lasting `ttl`, with its claims,
+    /// for a session bound to `binding` if given
+    async fn start_session(
+        &self,
+        provider_id: &str,
+        auth_payload: serde_json::Value,
+        source: Option<AuthSource>,
+        binding: Option<BindingInfo>,
+        ttl: Duration,
+    ) -> Result<(String, SessionRecord), SessionError> {
+        if self.purges_on_request() {
+            self.cleanup_expired_sessions().await?;
+        }
+
+        // Who the caller claims to be, for the events of failed attempts
+        let claimed_subject = auth_payload
+            .get("username")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string);
+        let failed = |reason: String| {
+            let event = IdentityEvent::new(IdentityEventKind::VerificationFailed { reason })
+                .with_provider(provider_id)
+                .with_source(source.clone());
+            match &claimed_subject {
+                Some(subject) => event.with_subject(subject),
+                None => event,
+            }
+        };
+
+        if let Some(limiter) = &self.rate_limiter {
+            let context = RateLimitContext {
+                provider_id: provider_id.to_string(),
+                username: claimed_subject.clone(),
+                ip: source.as_ref().and_then(|source| source.ip),
+            };
+            if let Err(retry_after) = limiter.check(&context).await {
+                let error = SessionError::RateLimited { retry_after };
+                self.emit(failed(error.to_string()));
+                return Err(error);
+            }
         }
 
         let providers = self.providers.read().await;
-        let provider = providers
-            .get(provider_id)
-            .ok_or_else(|| IdentityError::ProviderNotFound(provider_id.to_string()))?;
+        let Some(provider) = providers.get(provider_id) else {
+            let error = IdentityError::ProviderNotFound(provider_id.to_string());
+            self.emit(failed(error.to_string()));
+            return Err(error.into());
+        };
 
-        let identity = provider.verify(auth_payload).await?;
+        let identity = match provider.verify(auth_payload).await {
+            Ok(identity) => identity,
+            Err(error) => {
+                self.emit(failed(error.to_string()));
+                return Err(error.into());
+            }
+        };
+        self.emit(
+            IdentityEvent::new(IdentityEventKind::VerificationSucceeded)
+                .with_provider(&identity.provider_id)
+                .with_subject(&identity.subject)
+                .with_source(source.clone()),
+        );
 
-        let now = Utc::now();
-        let exp = now + self.config.jwt_ttl;
+        let now = Utc::now().timestamp();
         let jti = Uuid::new_v4().to_string();
 
         let permissions = if let Some(ref perm_provider) = self.permissions_provider {
@@ -163,124 +681,627 @@ impl SessionService {
             Vec::new()
         };
 
+        let mut custom = serde_json::Map::new();
+        if let Some(augmenter) = &self.claims_augmenter {
+            augmenter.augment(&identity, &mut custom).await;
+            if let Some(name) = claims::reserved_claim(&custom) {
+                return Err(SessionError::ReservedClaim(name.to_string()));
+            }
+        }
+
         let claims = JwtClaims {
             sub: identity.subject.clone(),
-            exp: exp.timestamp(),
-            iat: now.timestamp(),
+            exp: self.expiry(now, now, ttl),
+            iat: now,
+            nbf: self.not_before(now),
             jti: jti.clone(),
+            iss: self.config.issuer.clone(),
+            aud: self.config.audience.clone(),
             provider_id: identity.provider_id.clone(),
             email: identity.email.clone(),
             display_name: identity.display_name.clone(),
             permissions: permissions.into_iter().collect(),
+            amr: identity.amr(),
             metadata: identity.metadata,
+            custom,
         };
 
-        if self.config.enforce_active_sessions {
-            let mut sessions = self.active_sessions.write().await;
-            sessions.insert(jti.clone(), claims.clone());
+        let session = self.new_session(claims, source, binding, now);
+        let token = self.issue_token(&session).await?;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_session_started();
         }
 
-        let token = encode(
-            &Header::new(self.config.algorithm),
-            &claims,
-            &EncodingKey::from_secret(self.config.jwt_secret.as_bytes()),
-        )?;
+        self.emit(
+            IdentityEvent::new(IdentityEventKind::SessionStarted { session_id: jti })
+                .with_provider(&session.claims.provider_id)
+                .with_subject(&session.claims.sub)
+                .with_source(session.source.clone()),
+        );
+        Ok((token, session))
+    }
+
+    /// When a token issued `now` and lasting `ttl` expires, cut short by
+    /// `absolute_max` from the login at `created_at`
+    fn expiry(&self, created_at: i64, now: i64, ttl: Duration) -> i64 {
+        let exp = now + ttl.num_seconds();
+        self.config.absolute_max.map_or(exp, |absolute_max| {
+            exp.min(created_at + absolute_max.num_seconds())
+        })
+    }
 
+    /// The `nbf` of a token issued `now`
+    fn not_before(&self, now: i64) -> Option<i64> {
+        self.config
+            .not_before
+            .map(|not_before| now + not_before.num_seconds())
+    }
+
+    /// The session of a token with `claims`, continuing the login at
+    /// `created_at` and lasting `sliding_ttl` if set
+    fn new_session(
+        &self,
+        claims: JwtClaims,
+        source: Option<AuthSource>,
+        binding: Option<BindingInfo>,
+        created_at: i64,
+    ) -> SessionRecord {
+        let mut session = SessionRecord::new(claims, source);
+        session.binding = binding;
+        session.created_at = created_at;
+        if let Some(sliding_ttl) = self.config.sliding_ttl {
+            session.expires_at = session
+                .expires_at
+                .min(session.last_seen_at + sliding_ttl.num_seconds());
+        }
+        session
+    }
+
+    /// Sign the claims of `session`, tracking it if active sessions are enforced
+    async fn issue_token(&self, session: &SessionRecord) -> Result<String, SessionError> {
+        if self.config.enforce_active_sessions {
+            self.sessions.insert(session).await?;
+        }
+        let claims = &session.claims;
+
+        let token = match &self.keys {
+            Some(keys) => {
+                let key = keys.current();
+                let mut header = Header::new(key.algorithm());
+                header.kid = Some(key.kid().to_string());
+                encode(&header, claims, key.encoding_key())?
+            }
+            None => encode(
+                &Header::new(self.config.algorithm),
+                claims,
+                &EncodingKey::from_secret(self.config.jwt_secret.as_bytes()),
+            )?,
+        };
         Ok(token)
     }
 
+    /// Decode `token` with the key it was signed with
+    fn decode_claims(
+        &self,
+        token: &str,
+        mut validation: Validation,
+    ) -> Result<TokenData<JwtClaims>, SessionError> {
+        let Some(keys) = &self.keys else {
+            return Ok(decode::<JwtClaims>(
+                token,
+                &DecodingKey::from_secret(self.config.jwt_secret.as_bytes()),
+                &validation,
+            )?);
+        };
+        let key = decode_header(token)?
+            .kid
+            .and_then(|kid| keys.get(&kid))
+            .ok_or(SessionError::InvalidSession)?;
+        validation.algorithms = vec![key.algorithm()];
+        Ok(decode::<JwtClaims>(token, key.decoding_key(), &validation)?)
+    }
+
+    /// The public keys tokens are signed with, for services validating them.
+    ///
+    /// Empty when tokens are signed with `jwt_secret`, which must not be published.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .keys
+                .as_ref()
+                .map(|keys| keys.jwks().keys)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The claims of `token`, if valid and its session is active.
+    ///
+    /// Sessions aren't checked against the client using them; verify tokens
+    /// of requests with [`verify_bound_session`](Self::verify_bound_session).
     pub async fn verify_session(&self, token: &str) -> Result<JwtClaims, SessionError> {
-        if self.config.enforce_active_sessions {
-            self.cleanup_expired_sessions().await;
+        self.verify(token, None).await
+    }
+
+    /// [`verify_session`](Self::verify_session) for a request from the client
+    /// `binding` identifies, which must match the one a session bound with
+    /// [`begin_bound_session`](Self::begin_bound_session) began on, as
+    /// checked by the config's `binding`.
+    ///
+    /// Mismatches are reported to the event sink; enforced ones fail with
+    /// `SessionError::BindingMismatch`.
+    pub async fn verify_bound_session(
+        &self,
+        token: &str,
+        binding: &BindingInfo,
+    ) -> Result<JwtClaims, SessionError> {
+        self.verify(token, Some(binding)).await
+    }
+
+    async fn verify(
+        &self,
+        token: &str,
+        binding: Option<&BindingInfo>,
+    ) -> Result<JwtClaims, SessionError> {
+        let verified = self.check_session(token, binding).await;
+        if let Err(error) = &verified {
+            self.emit(IdentityEvent::new(IdentityEventKind::SessionRejected {
+                reason: error.to_string(),
+            }));
         }
+        verified
+    }
 
-        let mut validation = Validation::new(self.config.algorithm);
-        validation.set_required_spec_claims(&["exp"]);
-        validation.validate_exp = true;
+    async fn check_session(
+        &self,
+        token: &str,
+        binding: Option<&BindingInfo>,
+    ) -> Result<JwtClaims, SessionError> {
+        if self.purges_on_request() {
+            self.cleanup_expired_sessions().await?;
+        }
 
-        let token_data = decode::<JwtClaims>(
-            token,
-            &DecodingKey::from_secret(self.config.jwt_secret.as_bytes()),
-            &validation,
-        )?;
+        let token_data = self.decode_claims(token, self.validation())?;
+        // Nor are tokens used before they were issued
+        if token_data.claims.iat > Utc::now().timestamp() + self.config.leeway.num_seconds() {
+            return Err(SessionError::JwtError(ErrorKind::ImmatureSignature.into()));
+        }
 
         if self.config.enforce_active_sessions {
-            let sessions = self.active_sessions.read().await;
-            if !sessions.contains_key(&token_data.claims.jti) {
+            let Some(session) = self.sessions.get(&token_data.claims.jti).await? else {
                 return Err(SessionError::SessionNotFound);
+            };
+            let now = Utc::now().timestamp();
+            if self
+                .config
+                .absolute_max
+                .is_some_and(|absolute_max| session.created_at + absolute_max.num_seconds() <= now)
+            {
+                return Err(SessionError::SessionNotFound);
+            }
+            if let Some(binding) = binding {
+                self.check_binding(&session, binding)?;
             }
+            self.keep_alive(session, now).await?;
         }
 
         Ok(token_data.claims)
     }
 
-    pub async fn end_session(&self, jti: &str) -> Option<JwtClaims> {
-        let mut sessions = self.active_sessions.write().await;
-        sessions.remove(jti)
+    /// Refuse `session` being used from the client `presented` identifies if
+    /// it differs from the one the session is bound to in enforced parts,
+    /// reporting any mismatch
+    fn check_binding(
+        &self,
+        session: &SessionRecord,
+        presented: &BindingInfo,
+    ) -> Result<(), SessionError> {
+        let Some(bound) = &session.binding else {
+            return Ok(());
+        };
+        let mismatches = self.config.binding.mismatches(bound, presented);
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+
+        let enforced: Vec<String> = mismatches
+            .iter()
+            .filter(|(_, mode)| *mode == BindingMode::Enforce)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        self.emit(
+            IdentityEvent::new(IdentityEventKind::SessionBindingMismatch {
+                session_id: session.claims.jti.clone(),
+                mismatched: mismatches
+                    .iter()
+                    .map(|(name, _)| name.to_string())
+                    .collect(),
+                enforced: !enforced.is_empty(),
+            })
+            .with_provider(&session.claims.provider_id)
+            .with_subject(&session.claims.sub),
+        );
+        if enforced.is_empty() {
+            Ok(())
+        } else {
+            Err(SessionError::BindingMismatch(enforced))
+        }
+    }
+
+    /// How tokens are validated: their expiry and `nbf`, give or take the
+    /// leeway, and their issuer and audience when configured, which they must
+    /// then carry
+    fn validation(&self) -> Validation {
+        let mut validation = Validation::new(self.config.algorithm);
+        validation.leeway = self.config.leeway.num_seconds().unsigned_abs();
+        validation.validate_nbf = true;
+        let mut required = vec!["exp"];
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+            required.push("iss");
+        }
+        if !self.config.audience.is_empty() {
+            validation.set_audience(&self.config.audience);
+            required.push("aud");
+        }
+        validation.set_required_spec_claims(&required);
+        validation
+    }
+
+    /// Extend `session`, verified `now`, by `sliding_ttl`.
+    ///
+    /// It's extended at most once per tenth of `sliding_ttl`, so verifying
+    /// usually only reads the store, and a session may end up to that much
+    /// sooner than `sliding_ttl` after it was last verified.
+    async fn keep_alive(&self, mut session: SessionRecord, now: i64) -> Result<(), SessionError> {
+        let Some(sliding_ttl) = self.config.sliding_ttl else {
+            return Ok(());
+        };
+        let debounce = (sliding_ttl.num_seconds() / 10).max(1);
+        if now - session.last_seen_at < debounce {
+            return Ok(());
+        }
+        session.last_seen_at = now;
+        session.expires_at = session.claims.exp.min(now + sliding_ttl.num_seconds());
+        self.sessions.update(&session).await?;
+        Ok(())
+    }
+
+    pub async fn end_session(&self, jti: &str) -> Result<Option<JwtClaims>, SessionError> {
+        self.end_session_for(jti, "logout").await
+    }
+
+    /// End the session with `jti`, counting it as ended for `reason`
+    async fn end_session_for(
+        &self,
+        jti: &str,
+        reason: &str,
+    ) -> Result<Option<JwtClaims>, SessionError> {
+        let ended = self
+            .sessions
+            .remove(jti)
+            .await?
+            .map(|session| session.claims);
+        if let Some(claims) = &ended {
+            self.record_ended(reason, 1);
+            self.emit(
+                IdentityEvent::new(IdentityEventKind::SessionEnded {
+                    session_id: claims.jti.clone(),
+                })
+                .with_provider(&claims.provider_id)
+                .with_subject(&claims.sub),
+            );
+            // Nobody may be listening, which is fine
+            let _ = self.events.send(SessionEvent::Ended {
+                jti: claims.jti.clone(),
+                user_id: claims.sub.clone(),
+            });
+        }
+        Ok(ended)
+    }
+
+    /// The active sessions of `subject` from `provider_id`, oldest first.
+    ///
+    /// Only tracked sessions are listed, so this is empty unless
+    /// `enforce_active_sessions` is set.
+    pub async fn list_sessions_for_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<SessionSummary>, SessionError> {
+        let sessions = self.sessions.list_by_subject(provider_id, subject).await?;
+        Ok(sessions
+            .into_iter()
+            .map(|session| SessionSummary {
+                jti: session.claims.jti,
+                iat: session.claims.iat,
+                exp: session.expires_at,
+                user_agent: session.source.and_then(|source| source.user_agent),
+            })
+            .collect())
+    }
+
+    /// End every session of `subject` from `provider_id` and revoke their
+    /// refresh tokens, such as after a password change, returning how many
+    /// sessions were ended.
+    ///
+    /// Each ended session is published as [`SessionEvent::Ended`], followed by
+    /// one [`SessionEvent::SubjectRevoked`] that lets a [`JwtAuthProvider`]
+    /// following the events refuse tokens of sessions that weren't tracked.
+    pub async fn end_sessions_for_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<usize, SessionError> {
+        let at = Utc::now().timestamp();
+        self.sessions
+            .remove_refresh_by_subject(provider_id, subject)
+            .await?;
+        let ended = self
+            .sessions
+            .remove_by_subject(provider_id, subject)
+            .await?;
+        self.record_ended("revocation", ended.len());
+
+        self.emit(
+            IdentityEvent::new(IdentityEventKind::SessionsRevoked { count: ended.len() })
+                .with_provider(provider_id)
+                .with_subject(subject),
+        );
+        // Nobody may be listening, which is fine
+        for session in &ended {
+            let _ = self.events.send(SessionEvent::Ended {
+                jti: session.claims.jti.clone(),
+                user_id: session.claims.sub.clone(),
+            });
+        }
+        let _ = self.events.send(SessionEvent::SubjectRevoked {
+            provider_id: provider_id.to_string(),
+            user_id: subject.to_string(),
+            at,
+        });
+        Ok(ended.len())
+    }
+
+    /// Receive the events of sessions from now on, such as sessions being ended
+    ///
+    /// A receiver that falls more than 256 events behind skips the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    /// The session id (`jti`) of `token`, if this service signed it
+    ///
+    /// Expired tokens still have one, so it can be compared with ended sessions.
+    pub fn jti(&self, token: &str) -> Option<String> {
+        let mut validation = Validation::new(self.config.algorithm);
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        validation.required_spec_claims.clear();
+
+        self.decode_claims(token, validation)
+            .ok()
+            .map(|token_data| token_data.claims.jti)
     }
 
-    pub async fn cleanup_expired_sessions(&self) -> usize {
+    /// Forget expired sessions and refresh tokens, returning how many sessions there were
+    pub async fn cleanup_expired_sessions(&self) -> Result<usize, SessionError> {
         let now = Utc::now().timestamp();
-        let mut sessions = self.active_sessions.write().await;
-        let before = sessions.len();
-        sessions.retain(|_, claims| claims.exp > now);
-        before - sessions.len()
+        self.sessions.purge_expired_refresh(now).await?;
+
+        let purged = self.sessions.purge_expired(now).await?;
+        self.record_ended("expiry", purged);
+        Ok(purged)
+    }
+
+    /// Whether requests purge expired sessions, as they do when sessions are
+    /// tracked and no maintenance task does
+    fn purges_on_request(&self) -> bool {
+        self.config.enforce_active_sessions && !self.maintained.load(Ordering::SeqCst)
+    }
+
+    fn record_ended(&self, reason: &str, count: usize) {
+        if let Some(metrics) = &self.metrics
+            && count > 0
+        {
+            metrics.record_sessions_ended(reason, count);
+        }
     }
 }
 
+/// Authenticates requests by their session token
+///
+/// Requests of generated services are also checked against the client their
+/// session is bound to, if it began with [`SessionService::begin_bound_session`].
 #[derive(Clone)]
 pub struct JwtAuthProvider {
-    session_service: Arc<SessionService>,
+    verifier: Verifier,
+    revocations: Option<Arc<Revocations>>,
+    /// The audiences accepted tokens must name one of, if any
+    audience: Vec<String>,
+}
+
+#[derive(Clone)]
+enum Verifier {
+    Session(Arc<SessionService>),
+    #[cfg(feature = "jwks")]
+    Remote(Arc<RemoteJwks>),
 }
 
 impl JwtAuthProvider {
     pub fn new(session_service: Arc<SessionService>) -> Self {
-        Self { session_service }
+        Self {
+            verifier: Verifier::Session(session_service),
+            revocations: None,
+            audience: Vec::new(),
+        }
+    }
+
+    /// Validate tokens against the public keys of `jwks`, for services that
+    /// don't issue tokens themselves.
+    ///
+    /// Sessions ended by the issuing service are accepted until they expire,
+    /// unless its events are followed with [`with_revocations`](Self::with_revocations).
+    #[cfg(feature = "jwks")]
+    pub fn with_jwks(jwks: Arc<RemoteJwks>) -> Self {
+        Self {
+            verifier: Verifier::Remote(jwks),
+            revocations: None,
+            audience: Vec::new(),
+        }
+    }
+
+    /// Refuse tokens of the sessions `events` reports ended, and of subjects
+    /// it reports revoked, as soon as the events arrive, such as from
+    /// [`SessionService::subscribe`] or events relayed from the issuing service.
+    ///
+    /// Revocations are remembered for `retention`, which should be at least
+    /// the lifetime of the tokens. Events are followed from a background task,
+    /// so this must be called within a Tokio runtime.
+    pub fn with_revocations(
+        mut self,
+        events: broadcast::Receiver<SessionEvent>,
+        retention: Duration,
+    ) -> Self {
+        self.revocations = Some(Revocations::follow(events, retention));
+        self
+    }
+
+    /// Accept only tokens whose `aud` names one of `audience`, such as this
+    /// service's name, whatever audiences the issuing service accepts.
+    ///
+    /// Fails if `audience` is empty or has empty names, or if the session
+    /// service tokens are verified with issues none of them, so every token
+    /// would be refused.
+    pub fn with_audience(
+        mut self,
+        audience: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<Self, SessionError> {
+        let audience: Vec<String> = audience.into_iter().map(Into::into).collect();
+        if audience.is_empty() {
+            return Err(SessionError::InvalidConfig(
+                "audience must name at least one service".to_string(),
+            ));
+        }
+        validate_audience(&audience, "audience")?;
+        if let Verifier::Session(session_service) = &self.verifier
+            && !session_service
+                .config
+                .audience
+                .iter()
+                .any(|issued| audience.contains(issued))
+        {
+            return Err(SessionError::InvalidConfig(
+                "the session service issues tokens for none of audience, set its audience"
+                    .to_string(),
+            ));
+        }
+        self.audience = audience;
+        Ok(self)
     }
 }
 
 #[async_trait]
 impl AuthProvider for JwtAuthProvider {
     fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        Box::pin(self.verify(token, None))
+    }
+
+    /// Checks bound sessions against the client of the request, identified
+    /// by [`BindingInfo::from_request`] with the client address of the
+    /// [`RequestOrigin`] generated services resolve behind their trusted
+    /// proxies. Requests handled elsewhere have no address, which differs
+    /// from any bound one.
+    fn authenticate_request(&self, token: String, headers: &http::HeaderMap) -> AuthFuture<'_> {
+        let headers = self.binds_sessions().then(|| headers.clone());
         Box::pin(async move {
-            let claims =
-                self.session_service
-                    .verify_session(&token)
-                    .await
-                    .map_err(|e| match e {
-                        SessionError::JwtError(jwt_err) => {
-                            if jwt_err.to_string().contains("expired") {
-                                AuthError::TokenExpired
-                            } else {
-                                AuthError::InvalidToken
-                            }
-                        }
-                        _ => AuthError::InvalidToken,
-                    })?;
-
-            Ok(AuthenticatedUser {
-                user_id: claims.sub,
-                permissions: claims.permissions,
-                metadata: claims.metadata,
-            })
+            // Read as the request is authenticated, within its origin's scope
+            let binding = headers.map(|headers| request_binding(&headers));
+            self.verify(token, binding).await
         })
     }
+
+    /// The [`BindingInfo`] of the request, if sessions are bound
+    fn request_binding(&self, _token: &str, headers: &http::HeaderMap) -> Vec<u8> {
+        if !self.binds_sessions() {
+            return Vec::new();
+        }
+        serde_json::to_vec(&request_binding(headers)).unwrap_or_default()
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use ras_identity_core::StaticPermissions;
-    use ras_identity_local::LocalUserProvider;
+/// The binding of a request with `headers` from the client of the current
+/// [`RequestOrigin`]
+fn request_binding(headers: &http::HeaderMap) -> BindingInfo {
+    let client_ip = RequestOrigin::current().and_then(|origin| origin.client_ip);
+    BindingInfo::from_request(headers, client_ip)
+}
 
-    const TEST_SECRET: &str = "test-secret-that-is-long-enough-for-hs256";
+impl JwtAuthProvider {
+    /// Whether sessions are checked against the client using them
+    fn binds_sessions(&self) -> bool {
+        matches!(
+            &self.verifier,
+            Verifier::Session(session_service) if !session_service.config.binding.is_off()
+        )
+    }
+
+    /// The user of `token`, from the client `binding` identifies if given
+    async fn verify(
+        &self,
+        token: String,
+        binding: Option<BindingInfo>,
+    ) -> AuthResult<AuthenticatedUser> {
+        let verified = match &self.verifier {
+            Verifier::Session(session_service) => {
+                session_service.verify(&token, binding.as_ref()).await
+            }
+            #[cfg(feature = "jwks")]
+            Verifier::Remote(jwks) => jwks.verify(&token).await,
+        };
+        let claims = verified.map_err(|e| match e {
+            SessionError::JwtError(jwt_err) => match jwt_err.kind() {
+                ErrorKind::ExpiredSignature => AuthError::TokenExpired,
+                ErrorKind::ImmatureSignature => AuthError::TokenNotYetValid,
+                _ => AuthError::InvalidToken,
+            },
+            SessionError::StoreError(error) | SessionError::JwksError(error) => {
+                AuthError::Internal(error)
+            }
+            _ => AuthError::InvalidToken,
+        })?;
+        if let Some(revocations) = &self.revocations
+            && revocations.is_revoked(&claims)
+        {
+            return Err(AuthError::InvalidToken);
+        }
+        if !self.audience.is_empty() && !claims.aud.iter().any(|aud| self.audience.contains(aud)) {
+            return Err(AuthError::InvalidToken);
+        }
+
+        Ok(AuthenticatedUser {
+            user_id: claims.sub,
+            permissions: claims.permissions,
+            metadata: claims::with_custom_claims(claims.metadata, claims.custom),
+            kind: PrincipalKind::User,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_identity_core::{MemoryEventSink, StaticPermissions};
+    use ras_identity_local::LocalUserProvider;
+    use ras_test_helpers::RecordingMetrics;
+
+    const TEST_SECRET: &str = "test-secret-that-is-long-enough-for-hs256";
 
     #[tokio::test]
     async fn test_session_lifecycle() {
         let config = SessionConfig::new(TEST_SECRET).unwrap();
         let session_service = SessionService::new(config).unwrap();
 
-        let local_provider = LocalUserProvider::new();
+        let local_provider = LocalUserProvider::default();
         local_provider
             .add_user(
                 "testuser".to_string(),
@@ -309,8 +1330,9 @@ mod tests {
         assert_eq!(claims.sub, "testuser");
         assert_eq!(claims.provider_id, "local");
         assert!(claims.permissions.is_empty());
+        assert_eq!(claims.amr, ["pwd"]);
 
-        session_service.end_session(&claims.jti).await;
+        session_service.end_session(&claims.jti).await.unwrap();
 
         assert!(session_service.verify_session(&token).await.is_err());
     }
@@ -326,7 +1348,7 @@ mod tests {
             .unwrap()
             .with_permissions(permissions_provider);
 
-        let local_provider = LocalUserProvider::new();
+        let local_provider = LocalUserProvider::default();
         local_provider
             .add_user(
                 "admin".to_string(),
@@ -358,6 +1380,459 @@ mod tests {
         assert!(claims.permissions.contains("write"));
     }
 
+    #[tokio::test]
+    async fn test_ending_a_session_is_published() {
+        let config = SessionConfig::new(TEST_SECRET).unwrap();
+        let service = SessionService::new(config).unwrap();
+        let local_provider = LocalUserProvider::default();
+        local_provider
+            .add_user("alice".to_string(), "password123".to_string(), None, None)
+            .await
+            .unwrap();
+        service.register_provider(Box::new(local_provider)).await;
+
+        let token = service
+            .begin_session(
+                "local",
+                serde_json::json!({ "username": "alice", "password": "password123" }),
+            )
+            .await
+            .unwrap();
+        let jti = service.jti(&token).unwrap();
+        assert_eq!(service.jti("not-a-token"), None);
+
+        let mut events = service.subscribe();
+        assert!(service.end_session(&jti).await.unwrap().is_some());
+        assert!(service.end_session(&jti).await.unwrap().is_none());
+        assert_eq!(
+            events.recv().await.unwrap(),
+            SessionEvent::Ended {
+                jti,
+                user_id: "alice".to_string()
+            }
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    async fn refresh_service() -> SessionService {
+        service_with_alice(SessionConfig::new(TEST_SECRET).unwrap()).await
+    }
+
+    async fn service_with_alice(config: SessionConfig) -> SessionService {
+        let service = SessionService::new(config).unwrap();
+        let local_provider = LocalUserProvider::default();
+        local_provider
+            .add_user("alice".to_string(), "password123".to_string(), None, None)
+            .await
+            .unwrap();
+        service.register_provider(Box::new(local_provider)).await;
+        service
+    }
+
+    fn alice_login() -> serde_json::Value {
+        serde_json::json!({ "username": "alice", "password": "password123" })
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rotates_tokens() {
+        let service = refresh_service().await;
+
+        let first = service
+            .begin_session_with_refresh("local", alice_login(), None)
+            .await
+            .unwrap();
+        let claims = service.verify_session(&first.access_token).await.unwrap();
+        assert_eq!(claims.sub, "alice");
+        assert!(claims.exp - claims.iat <= 15 * 60);
+        assert!(first.refresh_expires_at > claims.exp);
+
+        let second = service.refresh(&first.refresh_token).await.unwrap();
+        assert_ne!(second.refresh_token, first.refresh_token);
+        let renewed = service.verify_session(&second.access_token).await.unwrap();
+        assert_eq!(renewed.sub, "alice");
+        assert_eq!(renewed.amr, ["pwd"]);
+        assert_ne!(renewed.jti, claims.jti);
+
+        let third = service.refresh(&second.refresh_token).await.unwrap();
+        assert!(service.verify_session(&third.access_token).await.is_ok());
+
+        assert!(matches!(
+            service.refresh("not-a-refresh-token").await,
+            Err(SessionError::InvalidSession)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refreshed_sessions_stay_bound() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.binding.user_agent = BindingMode::Enforce;
+        config.binding.ip_prefix = BindingMode::Enforce;
+        let service = service_with_alice(config).await;
+        let client =
+            |user_agent: &str, ip: &str| BindingInfo::new(Some(user_agent), ip.parse().ok(), None);
+        let laptop = client("Firefox/140", "203.0.113.7");
+
+        let first = service
+            .begin_bound_session_with_refresh("local", alice_login(), None, laptop.clone())
+            .await
+            .unwrap();
+        let refreshed = service.refresh(&first.refresh_token).await.unwrap();
+
+        assert!(
+            service
+                .verify_bound_session(&refreshed.access_token, &laptop)
+                .await
+                .is_ok()
+        );
+        for elsewhere in [
+            client("Firefox/140", "198.51.100.1"),
+            client("curl/8.0", "203.0.113.7"),
+        ] {
+            for token in [&first.access_token, &refreshed.access_token] {
+                assert!(matches!(
+                    service.verify_bound_session(token, &elsewhere).await,
+                    Err(SessionError::BindingMismatch(_))
+                ));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reusing_a_rotated_refresh_token_revokes_the_family() {
+        let service = refresh_service().await;
+        let first = service
+            .begin_session_with_refresh("local", alice_login(), None)
+            .await
+            .unwrap();
+        let second = service.refresh(&first.refresh_token).await.unwrap();
+        let other = service
+            .begin_session_with_refresh("local", alice_login(), None)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service.refresh(&first.refresh_token).await,
+            Err(SessionError::RefreshTokenReused)
+        ));
+        // The legitimate holder's latest tokens are revoked too
+        assert!(matches!(
+            service.refresh(&second.refresh_token).await,
+            Err(SessionError::InvalidSession)
+        ));
+        assert!(service.verify_session(&first.access_token).await.is_err());
+        assert!(service.verify_session(&second.access_token).await.is_err());
+
+        // Other families are untouched
+        assert!(service.verify_session(&other.access_token).await.is_ok());
+        assert!(service.refresh(&other.refresh_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_replicas_sharing_a_store_rotate_a_refresh_token_once() {
+        let store: Arc<dyn SessionStore> = Arc::new(MemorySessionStore::new());
+        let replica = refresh_service().await.with_session_store(store.clone());
+        let other_replica = refresh_service().await.with_session_store(store);
+        let pair = replica
+            .begin_session_with_refresh("local", alice_login(), None)
+            .await
+            .unwrap();
+
+        let (first, second) = tokio::join!(
+            replica.refresh(&pair.refresh_token),
+            other_replica.refresh(&pair.refresh_token)
+        );
+        let reused = [&first, &second]
+            .iter()
+            .filter(|refreshed| matches!(refreshed, Err(SessionError::RefreshTokenReused)))
+            .count();
+        assert_eq!(reused, 1);
+        // Whichever rotated it first, the family is revoked on both replicas
+        let renewed = first.or(second).unwrap();
+        assert!(matches!(
+            other_replica.refresh(&renewed.refresh_token).await,
+            Err(SessionError::InvalidSession)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_refresh() {
+        let service = refresh_service().await;
+        let pair = service
+            .begin_session_with_refresh("local", alice_login(), None)
+            .await
+            .unwrap();
+
+        assert!(service.revoke_refresh(&pair.refresh_token).await.unwrap());
+        assert!(!service.revoke_refresh(&pair.refresh_token).await.unwrap());
+        assert!(service.refresh(&pair.refresh_token).await.is_err());
+        assert!(service.verify_session(&pair.access_token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ending_every_session_of_a_subject() {
+        let sink = Arc::new(MemoryEventSink::new());
+        let service = service_with_alice(SessionConfig::new(TEST_SECRET).unwrap())
+            .await
+            .with_event_sink(sink.clone());
+        let source = AuthSource {
+            ip: None,
+            user_agent: Some("curl/8.0".to_string()),
+        };
+        let first = service
+            .begin_session_from("local", alice_login(), source)
+            .await
+            .unwrap();
+        let second = service
+            .begin_session_with_refresh("local", alice_login(), None)
+            .await
+            .unwrap();
+
+        let sessions = service
+            .list_sessions_for_subject("local", "alice")
+            .await
+            .unwrap();
+        assert_eq!(sessions.len(), 2);
+        let agents: HashSet<_> = sessions.iter().map(|s| s.user_agent.clone()).collect();
+        assert_eq!(agents, HashSet::from([Some("curl/8.0".to_string()), None]));
+
+        let mut events = service.subscribe();
+        assert_eq!(
+            service
+                .end_sessions_for_subject("local", "alice")
+                .await
+                .unwrap(),
+            2
+        );
+        assert!(service.verify_session(&first).await.is_err());
+        assert!(service.verify_session(&second.access_token).await.is_err());
+        assert!(service.refresh(&second.refresh_token).await.is_err());
+        assert!(
+            service
+                .list_sessions_for_subject("local", "alice")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        assert!(matches!(
+            events.recv().await,
+            Ok(SessionEvent::Ended { .. })
+        ));
+        assert!(matches!(
+            events.recv().await,
+            Ok(SessionEvent::Ended { .. })
+        ));
+        assert!(matches!(
+            events.recv().await,
+            Ok(SessionEvent::SubjectRevoked { provider_id, user_id, .. })
+                if provider_id == "local" && user_id == "alice"
+        ));
+
+        let events = sink.wait_for(7).await;
+        let revoked = events
+            .iter()
+            .find(|event| matches!(event.kind, IdentityEventKind::SessionsRevoked { .. }))
+            .unwrap();
+        assert_eq!(
+            revoked.kind,
+            IdentityEventKind::SessionsRevoked { count: 2 }
+        );
+        assert_eq!(revoked.subject.as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn test_revocations_refuse_validated_tokens() {
+        let issuer = service_with_alice(SessionConfig::new(TEST_SECRET).unwrap()).await;
+        // Another service sharing the secret, which doesn't track sessions
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.enforce_active_sessions = false;
+        let validator = Arc::new(SessionService::new(config).unwrap());
+        let provider = JwtAuthProvider::new(validator)
+            .with_revocations(issuer.subscribe(), Duration::hours(1));
+
+        async fn refused(provider: &JwtAuthProvider, token: &str) -> bool {
+            for _ in 0..100 {
+                if provider.authenticate(token.to_string()).await.is_err() {
+                    return true;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            false
+        }
+
+        let first = issuer.begin_session("local", alice_login()).await.unwrap();
+        let second = issuer.begin_session("local", alice_login()).await.unwrap();
+        assert!(provider.authenticate(first.clone()).await.is_ok());
+        assert!(provider.authenticate(second.clone()).await.is_ok());
+
+        issuer
+            .end_session(&issuer.jti(&first).unwrap())
+            .await
+            .unwrap();
+        assert!(refused(&provider, &first).await);
+        assert!(provider.authenticate(second.clone()).await.is_ok());
+
+        issuer
+            .end_sessions_for_subject("local", "alice")
+            .await
+            .unwrap();
+        assert!(refused(&provider, &second).await);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_config_and_expiry() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.refresh_ttl = Duration::seconds(-1);
+        config.refresh_enabled = false;
+        let service = SessionService::new(config.clone()).unwrap();
+        assert!(matches!(
+            service
+                .begin_session_with_refresh("local", alice_login(), None)
+                .await,
+            Err(SessionError::RefreshDisabled)
+        ));
+
+        config.refresh_enabled = true;
+        assert!(matches!(
+            SessionService::new(config),
+            Err(SessionError::InvalidConfig(_))
+        ));
+
+        let service = refresh_service().await;
+        let pair = service
+            .begin_session_with_refresh("local", alice_login(), None)
+            .await
+            .unwrap();
+        let hash = refresh::hash_refresh_token(&pair.refresh_token);
+        let mut record = service.sessions.get_refresh(&hash).await.unwrap().unwrap();
+        record.expires_at = Utc::now().timestamp() - 1;
+        service
+            .sessions
+            .insert_refresh(&hash, &record)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service.refresh(&pair.refresh_token).await,
+            Err(SessionError::InvalidSession)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_login_attempts_are_rate_limited() {
+        let limiter = SlidingWindowLimiter::new(
+            Some(RateLimit::new(2, std::time::Duration::from_secs(60))),
+            None,
+        );
+        let config = SessionConfig::new(TEST_SECRET).unwrap();
+        let service = SessionService::new(config)
+            .unwrap()
+            .with_rate_limiter(Arc::new(limiter));
+        let local_provider = LocalUserProvider::default();
+        local_provider
+            .add_user("alice".to_string(), "password123".to_string(), None, None)
+            .await
+            .unwrap();
+        service.register_provider(Box::new(local_provider)).await;
+        let login = |username: &str, password: &str| serde_json::json!({ "username": username, "password": password });
+
+        assert!(
+            service
+                .begin_session("local", login("alice", "wrong"))
+                .await
+                .is_err()
+        );
+        assert!(
+            service
+                .begin_session("local", login("alice", "wrong"))
+                .await
+                .is_err()
+        );
+        // Refused before the password is checked, so even the right one is
+        let result = service
+            .begin_session("local", login("alice", "password123"))
+            .await;
+        let Err(SessionError::RateLimited { retry_after }) = result else {
+            panic!("expected the attempt to be rate limited, got {result:?}");
+        };
+        assert!(retry_after > std::time::Duration::ZERO);
+        assert!(retry_after <= std::time::Duration::from_secs(60));
+
+        assert!(
+            service
+                .begin_session("local", login("bob", "password123"))
+                .await
+                .is_err_and(|e| !matches!(e, SessionError::RateLimited { .. }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audit_events() {
+        let sink = Arc::new(MemoryEventSink::new());
+        let config = SessionConfig::new(TEST_SECRET).unwrap();
+        let service = SessionService::new(config)
+            .unwrap()
+            .with_event_sink(sink.clone());
+        let local_provider = LocalUserProvider::default();
+        local_provider
+            .add_user("alice".to_string(), "password123".to_string(), None, None)
+            .await
+            .unwrap();
+        service.register_provider(Box::new(local_provider)).await;
+        let source = AuthSource {
+            ip: Some("203.0.113.7".parse().unwrap()),
+            user_agent: Some("curl/8.0".to_string()),
+        };
+
+        let result = service
+            .begin_session_from(
+                "local",
+                serde_json::json!({ "username": "alice", "password": "wrong" }),
+                source.clone(),
+            )
+            .await;
+        assert!(result.is_err());
+        let token = service
+            .begin_session_from(
+                "local",
+                serde_json::json!({ "username": "alice", "password": "password123" }),
+                source.clone(),
+            )
+            .await
+            .unwrap();
+        let jti = service.jti(&token).unwrap();
+        service.end_session(&jti).await.unwrap();
+        assert!(service.verify_session(&token).await.is_err());
+
+        let events = sink.wait_for(5).await;
+        let kinds: Vec<_> = events.iter().map(|event| event.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [
+                IdentityEventKind::VerificationFailed {
+                    reason: "Invalid credentials".to_string()
+                },
+                IdentityEventKind::VerificationSucceeded,
+                IdentityEventKind::SessionStarted {
+                    session_id: jti.clone()
+                },
+                IdentityEventKind::SessionEnded {
+                    session_id: jti.clone()
+                },
+                IdentityEventKind::SessionRejected {
+                    reason: "Session not found".to_string()
+                },
+            ]
+        );
+        for event in &events[..4] {
+            assert_eq!(event.provider_id.as_deref(), Some("local"));
+            assert_eq!(event.subject.as_deref(), Some("alice"));
+        }
+        for event in &events[..3] {
+            assert_eq!(event.source.as_ref(), Some(&source));
+        }
+        assert_eq!(service.dropped_events(), 0);
+    }
+
     #[test]
     fn test_rejects_placeholder_secret() {
         let result = SessionConfig::new("change-me-in-production");
@@ -369,25 +1844,142 @@ mod tests {
         let config = SessionConfig::new(TEST_SECRET).unwrap();
         let service = SessionService::new(config).unwrap();
 
-        {
-            let mut sessions = service.active_sessions.write().await;
-            sessions.insert(
-                "expired".to_string(),
+        service
+            .sessions
+            .insert(&SessionRecord::new(
                 JwtClaims {
                     sub: "user".to_string(),
                     exp: Utc::now().timestamp() - 1,
                     iat: Utc::now().timestamp() - 10,
+                    nbf: None,
                     jti: "expired".to_string(),
+                    iss: None,
+                    aud: Vec::new(),
                     provider_id: "local".to_string(),
                     email: None,
                     display_name: None,
                     permissions: HashSet::new(),
                     metadata: None,
+                    amr: Vec::new(),
+                    custom: Default::default(),
                 },
-            );
+                None,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(service.cleanup_expired_sessions().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sliding_sessions_end_when_idle() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.sliding_ttl = Some(Duration::seconds(20));
+        let service = service_with_alice(config.clone()).await;
+        let token = service.begin_session("local", alice_login()).await.unwrap();
+        let jti = service.jti(&token).unwrap();
+        let session = service.sessions.get(&jti).await.unwrap().unwrap();
+        assert_eq!(session.expires_at, session.created_at + 20);
+        assert!(session.claims.exp > session.expires_at);
+
+        // Verified after a while, the session is extended
+        let now = Utc::now().timestamp();
+        let mut idle = session.clone();
+        idle.last_seen_at = now - 15;
+        idle.expires_at = now + 5;
+        service.sessions.update(&idle).await.unwrap();
+        service.verify_session(&token).await.unwrap();
+        let extended = service.sessions.get(&jti).await.unwrap().unwrap();
+        assert!(extended.last_seen_at >= now);
+        assert!(extended.expires_at >= now + 20);
+
+        // Left idle too long, it ends
+        idle.expires_at = now - 1;
+        service.sessions.update(&idle).await.unwrap();
+        assert!(matches!(
+            service.verify_session(&token).await,
+            Err(SessionError::SessionNotFound)
+        ));
+
+        config.enforce_active_sessions = false;
+        assert!(matches!(
+            config.validate(),
+            Err(SessionError::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_absolute_max_bounds_logins_across_refreshes() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.absolute_max = Some(Duration::minutes(10));
+        let service = service_with_alice(config).await;
+        let pair = service
+            .begin_session_with_refresh("local", alice_login(), None)
+            .await
+            .unwrap();
+        let claims = service.verify_session(&pair.access_token).await.unwrap();
+        assert_eq!(claims.exp, claims.iat + 600);
+        assert!(pair.refresh_expires_at <= claims.iat + 600);
+
+        let renewed = service.refresh(&pair.refresh_token).await.unwrap();
+        let renewed_claims = service.verify_session(&renewed.access_token).await.unwrap();
+        assert!(renewed_claims.exp <= claims.iat + 600);
+
+        // Once the login is older than the maximum, nothing it led to is accepted
+        let jti = renewed_claims.jti;
+        let mut session = service.sessions.get(&jti).await.unwrap().unwrap();
+        session.created_at -= 600;
+        service.sessions.update(&session).await.unwrap();
+        assert!(service.verify_session(&renewed.access_token).await.is_err());
+        let hash = refresh::hash_refresh_token(&renewed.refresh_token);
+        let mut record = service.sessions.get_refresh(&hash).await.unwrap().unwrap();
+        record.session.created_at -= 600;
+        service
+            .sessions
+            .insert_refresh(&hash, &record)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service.refresh(&renewed.refresh_token).await,
+            Err(SessionError::InvalidSession)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_purges_and_reports_sessions() {
+        let metrics = RecordingMetrics::default();
+        let service = Arc::new(
+            service_with_alice(SessionConfig::new(TEST_SECRET).unwrap())
+                .await
+                .with_service_metrics(Arc::new(metrics.clone())),
+        );
+        let expiring = service.begin_session("local", alice_login()).await.unwrap();
+        let jti = service.jti(&expiring).unwrap();
+        let mut session = service.sessions.get(&jti).await.unwrap().unwrap();
+        session.expires_at = Utc::now().timestamp() - 1;
+        service.sessions.insert(&session).await.unwrap();
+        let token = service.begin_session("local", alice_login()).await.unwrap();
+        assert_eq!(metrics.sessions_started(), 2);
+
+        let maintenance = service.start_maintenance(std::time::Duration::from_millis(10));
+        assert!(!service.purges_on_request());
+        for _ in 0..100 {
+            if metrics.active_sessions().is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
         }
+        assert_eq!(metrics.sessions_ended("expiry"), 1);
+        assert_eq!(metrics.active_sessions(), Some(1));
 
-        assert_eq!(service.cleanup_expired_sessions().await, 1);
+        service
+            .end_session(&service.jti(&token).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(metrics.sessions_ended("logout"), 1);
+
+        maintenance.shutdown().await;
+        assert!(service.purges_on_request());
     }
 
     #[tokio::test]
@@ -411,4 +2003,543 @@ mod tests {
 
         assert!(service.verify_session(&token).await.is_err());
     }
+
+    #[tokio::test]
+    async fn test_asymmetric_tokens_name_their_key() {
+        let config =
+            SessionConfig::with_key_source(Algorithm::ES256, KeySource::Generated).unwrap();
+        let service = service_with_alice(config).await;
+
+        let token = service.begin_session("local", alice_login()).await.unwrap();
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::ES256);
+        let jwks = service.jwks();
+        assert_eq!(jwks.keys.len(), 1);
+        assert_eq!(jwks.keys[0].common.key_id, header.kid);
+        assert!(service.verify_session(&token).await.is_ok());
+
+        // A token signed with another key isn't accepted
+        let other = SessionConfig::with_key_source(Algorithm::ES256, KeySource::Generated).unwrap();
+        let other = service_with_alice(other).await;
+        let foreign = other.begin_session("local", alice_login()).await.unwrap();
+        assert!(service.verify_session(&foreign).await.is_err());
+
+        // The shared secret is never published
+        assert!(refresh_service().await.jwks().keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_key_sources_are_validated() {
+        assert!(SessionConfig::with_key_source(Algorithm::HS256, KeySource::Generated).is_err());
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.algorithm = Algorithm::RS256;
+        assert!(config.validate().is_err());
+
+        let pem = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rs256.pem");
+        let config =
+            SessionConfig::with_key_source(Algorithm::RS256, KeySource::PemFile(pem.into()))
+                .unwrap();
+        let service = service_with_alice(config).await;
+        let token = service.begin_session("local", alice_login()).await.unwrap();
+        assert!(service.verify_session(&token).await.is_ok());
+
+        let missing = SessionConfig::with_key_source(
+            Algorithm::RS256,
+            KeySource::PemFile("/nonexistent/key.pem".into()),
+        )
+        .unwrap();
+        assert!(matches!(
+            SessionService::new(missing),
+            Err(SessionError::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_key_rotation_keeps_earlier_tokens_valid() {
+        let keys = Arc::new(KeyStore::new(
+            SigningKey::generate(Algorithm::ES256).unwrap(),
+        ));
+        let config =
+            SessionConfig::with_key_source(Algorithm::ES256, KeySource::KeyStore(keys.clone()))
+                .unwrap();
+        let service = service_with_alice(config).await;
+
+        let first = service.begin_session("local", alice_login()).await.unwrap();
+        keys.rotate(
+            SigningKey::generate(Algorithm::ES256).unwrap(),
+            Duration::hours(1),
+        );
+        let second = service.begin_session("local", alice_login()).await.unwrap();
+        assert_ne!(
+            decode_header(&first).unwrap().kid,
+            decode_header(&second).unwrap().kid
+        );
+        assert!(service.verify_session(&first).await.is_ok());
+        assert!(service.verify_session(&second).await.is_ok());
+        assert_eq!(service.jwks().keys.len(), 2);
+
+        // Rotating with no overlap drops the second key at once
+        keys.rotate(
+            SigningKey::generate(Algorithm::ES256).unwrap(),
+            Duration::zero(),
+        );
+        assert!(service.verify_session(&first).await.is_ok());
+        assert!(matches!(
+            service.verify_session(&second).await,
+            Err(SessionError::InvalidSession)
+        ));
+        assert!(service.jti(&second).is_none());
+    }
+
+    /// Adds `claims` to every token
+    struct FixedClaims(serde_json::Value);
+
+    #[async_trait]
+    impl ClaimsAugmenter for FixedClaims {
+        async fn augment(
+            &self,
+            identity: &ras_identity_core::VerifiedIdentity,
+            claims: &mut serde_json::Map<String, serde_json::Value>,
+        ) {
+            assert_eq!(identity.subject, "alice");
+            if let serde_json::Value::Object(fixed) = &self.0 {
+                claims.extend(fixed.clone());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_claims_round_trip() {
+        let service = Arc::new(refresh_service().await.with_claims_augmenter(Arc::new(
+            FixedClaims(serde_json::json!({
+                "tenant_id": "acme",
+                "locale": "de-CH",
+                "features": ["beta-search"],
+            })),
+        )));
+
+        let pair = service
+            .begin_session_with_refresh("local", alice_login(), None)
+            .await
+            .unwrap();
+        let claims = service.verify_session(&pair.access_token).await.unwrap();
+        assert_eq!(claims.custom["tenant_id"], "acme");
+        assert_eq!(
+            claims.custom["features"],
+            serde_json::json!(["beta-search"])
+        );
+
+        // Refreshed tokens keep them, and requests see them in the metadata
+        let refreshed = service.refresh(&pair.refresh_token).await.unwrap();
+        let user = JwtAuthProvider::new(service.clone())
+            .authenticate(refreshed.access_token)
+            .await
+            .unwrap();
+        let metadata = user.metadata.unwrap();
+        assert_eq!(metadata["tenant_id"], "acme");
+        assert_eq!(metadata["locale"], "de-CH");
+    }
+
+    #[tokio::test]
+    async fn test_reserved_claims_are_refused() {
+        for reserved in ["sub", "exp", "iat", "jti", "permissions"] {
+            let mut claims = serde_json::json!({ "tenant_id": "acme" });
+            claims[reserved] = "admin".into();
+            let service = service_with_alice(SessionConfig::new(TEST_SECRET).unwrap())
+                .await
+                .with_claims_augmenter(Arc::new(FixedClaims(claims)));
+
+            let result = service.begin_session("local", alice_login()).await;
+            assert!(
+                matches!(&result, Err(SessionError::ReservedClaim(name)) if name == reserved),
+                "{reserved} was accepted"
+            );
+            assert_eq!(service.sessions.count_active(0).await.unwrap(), 0);
+        }
+    }
+
+    fn config_for(issuer: &str, audience: &[&str]) -> SessionConfig {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.issuer = Some(issuer.to_string());
+        config.audience = audience.iter().map(|aud| aud.to_string()).collect();
+        config
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_bound_to_issuer_and_audience() {
+        let billing =
+            service_with_alice(config_for("https://auth.example.com", &["billing"])).await;
+        let token = billing.begin_session("local", alice_login()).await.unwrap();
+        let claims = billing.verify_session(&token).await.unwrap();
+        assert_eq!(claims.iss.as_deref(), Some("https://auth.example.com"));
+        assert_eq!(claims.aud, ["billing"]);
+
+        // Services sharing the secret refuse tokens meant for others
+        let search = service_with_alice(config_for("https://auth.example.com", &["search"])).await;
+        assert!(matches!(
+            search.verify_session(&token).await,
+            Err(SessionError::JwtError(_))
+        ));
+        let other = service_with_alice(config_for("https://other.example.com", &["billing"])).await;
+        assert!(matches!(
+            other.verify_session(&token).await,
+            Err(SessionError::JwtError(_))
+        ));
+
+        // Tokens without the claims are refused where they're expected
+        let unbound = service_with_alice(SessionConfig::new(TEST_SECRET).unwrap()).await;
+        let token = unbound.begin_session("local", alice_login()).await.unwrap();
+        assert!(matches!(
+            billing.verify_session(&token).await,
+            Err(SessionError::JwtError(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_empty_issuer_and_audience() {
+        let mut config = config_for("", &[]);
+        assert!(matches!(
+            config.validate(),
+            Err(SessionError::InvalidConfig(_))
+        ));
+        config.issuer = None;
+        config.audience = vec!["billing".to_string(), " ".to_string()];
+        assert!(matches!(
+            config.validate(),
+            Err(SessionError::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_provider_checks_its_own_audience() {
+        let service = Arc::new(
+            service_with_alice(config_for(
+                "https://auth.example.com",
+                &["billing", "search"],
+            ))
+            .await,
+        );
+        let token = service.begin_session("local", alice_login()).await.unwrap();
+
+        let provider = JwtAuthProvider::new(service.clone())
+            .with_audience(["search"])
+            .unwrap();
+        assert_eq!(provider.authenticate(token).await.unwrap().user_id, "alice");
+
+        // Audiences no token is issued for are a configuration error
+        for audience in [vec![], vec!["reports"], vec![""]] {
+            assert!(matches!(
+                JwtAuthProvider::new(service.clone()).with_audience(audience),
+                Err(SessionError::InvalidConfig(_))
+            ));
+        }
+        let unbound =
+            Arc::new(SessionService::new(SessionConfig::new(TEST_SECRET).unwrap()).unwrap());
+        assert!(matches!(
+            JwtAuthProvider::new(unbound).with_audience(["search"]),
+            Err(SessionError::InvalidConfig(_))
+        ));
+    }
+
+    /// A token for alice signed with the test secret, with `exp`, `iat` and
+    /// `nbf` offset from now
+    fn skewed_token(exp: i64, iat: i64, nbf: Option<i64>) -> String {
+        let now = Utc::now().timestamp();
+        let claims = JwtClaims {
+            sub: "alice".to_string(),
+            exp: now + exp,
+            iat: now + iat,
+            nbf: nbf.map(|nbf| now + nbf),
+            jti: Uuid::new_v4().to_string(),
+            iss: None,
+            aud: Vec::new(),
+            provider_id: "local".to_string(),
+            email: None,
+            display_name: None,
+            permissions: HashSet::new(),
+            metadata: None,
+            amr: Vec::new(),
+            custom: Default::default(),
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(TEST_SECRET.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_leeway_tolerates_clock_skew() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.enforce_active_sessions = false;
+        config.leeway = Duration::seconds(30);
+        let provider = JwtAuthProvider::new(Arc::new(SessionService::new(config).unwrap()));
+
+        // Within the leeway either side of now
+        for token in [
+            skewed_token(-20, -3600, None),
+            skewed_token(3600, 0, Some(20)),
+            skewed_token(3600, 20, None),
+        ] {
+            assert!(provider.authenticate(token).await.is_ok());
+        }
+
+        // Beyond it, expired and not yet valid tokens are told apart
+        assert!(matches!(
+            provider.authenticate(skewed_token(-40, -3600, None)).await,
+            Err(AuthError::TokenExpired)
+        ));
+        for token in [
+            skewed_token(3600, 0, Some(40)),
+            skewed_token(3600, 40, None),
+        ] {
+            assert!(matches!(
+                provider.authenticate(token).await,
+                Err(AuthError::TokenNotYetValid)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_not_before_offset_is_minted() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.not_before = Some(Duration::seconds(-10));
+        let service = service_with_alice(config).await;
+        let token = service.begin_session("local", alice_login()).await.unwrap();
+        let claims = service.verify_session(&token).await.unwrap();
+        assert_eq!(claims.nbf, Some(claims.iat - 10));
+
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.not_before = Some(config.refreshable_jwt_ttl);
+        assert!(matches!(
+            config.validate(),
+            Err(SessionError::InvalidConfig(_))
+        ));
+        config.not_before = None;
+        config.leeway = Duration::seconds(-1);
+        assert!(matches!(
+            config.validate(),
+            Err(SessionError::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bound_sessions_refuse_other_clients() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.binding = BindingPolicy {
+            user_agent: BindingMode::Enforce,
+            ip_prefix: BindingMode::Warn,
+            device_id: BindingMode::Off,
+        };
+        let sink = Arc::new(MemoryEventSink::new());
+        let service = service_with_alice(config)
+            .await
+            .with_event_sink(sink.clone());
+        let client = |user_agent: &str, ip: &str, device_id: &str| {
+            BindingInfo::new(Some(user_agent), ip.parse().ok(), Some(device_id))
+        };
+        let token = service
+            .begin_bound_session(
+                "local",
+                alice_login(),
+                None,
+                client("Firefox/140", "203.0.113.7", "laptop"),
+            )
+            .await
+            .unwrap();
+        let jti = service.jti(&token).unwrap();
+
+        // Moving within the network, or from another device, isn't checked
+        let same = client("Firefox/140", "203.0.113.99", "phone");
+        assert!(service.verify_bound_session(&token, &same).await.is_ok());
+        // Another network is only reported
+        let moved = client("Firefox/140", "198.51.100.1", "laptop");
+        assert!(service.verify_bound_session(&token, &moved).await.is_ok());
+        // Another browser is refused
+        let stolen = client("curl/8.0", "203.0.113.7", "laptop");
+        assert!(matches!(
+            service.verify_bound_session(&token, &stolen).await,
+            Err(SessionError::BindingMismatch(mismatched)) if mismatched == ["user_agent"]
+        ));
+        // Verifying without a request doesn't check the client
+        assert!(service.verify_session(&token).await.is_ok());
+
+        let mismatches: Vec<_> = sink
+            .wait_for(5)
+            .await
+            .into_iter()
+            .filter(|event| matches!(event.kind, IdentityEventKind::SessionBindingMismatch { .. }))
+            .collect();
+        assert_eq!(
+            mismatches
+                .iter()
+                .map(|event| event.kind.clone())
+                .collect::<Vec<_>>(),
+            [
+                IdentityEventKind::SessionBindingMismatch {
+                    session_id: jti.clone(),
+                    mismatched: vec!["ip_prefix".to_string()],
+                    enforced: false,
+                },
+                IdentityEventKind::SessionBindingMismatch {
+                    session_id: jti,
+                    mismatched: vec!["user_agent".to_string()],
+                    enforced: true,
+                },
+            ]
+        );
+        assert_eq!(mismatches[1].subject.as_deref(), Some("alice"));
+
+        // Unbound sessions are accepted from anywhere
+        let token = service.begin_session("local", alice_login()).await.unwrap();
+        assert!(service.verify_bound_session(&token, &stolen).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_provider_binds_by_request_headers() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.binding.device_id = BindingMode::Enforce;
+        let service = Arc::new(service_with_alice(config).await);
+        let provider = JwtAuthProvider::new(service.clone());
+        let headers = |device_id: &str| {
+            let mut headers = http::HeaderMap::new();
+            headers.insert("x-device-id", device_id.parse().unwrap());
+            headers
+        };
+        let token = service
+            .begin_bound_session(
+                "local",
+                alice_login(),
+                None,
+                BindingInfo::from_request(&headers("laptop"), None),
+            )
+            .await
+            .unwrap();
+
+        let user = provider
+            .authenticate_request(token.clone(), &headers("laptop"))
+            .await
+            .unwrap();
+        assert_eq!(user.user_id, "alice");
+        assert!(matches!(
+            provider
+                .authenticate_request(token.clone(), &headers("elsewhere"))
+                .await,
+            Err(AuthError::InvalidToken)
+        ));
+        assert!(matches!(
+            provider
+                .authenticate_request(token, &http::HeaderMap::new())
+                .await,
+            Err(AuthError::InvalidToken)
+        ));
+
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.enforce_active_sessions = false;
+        config.binding.device_id = BindingMode::Warn;
+        assert!(matches!(
+            config.validate(),
+            Err(SessionError::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_provider_ignores_forwarded_addresses_clients_forge() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.binding.ip_prefix = BindingMode::Enforce;
+        let service = Arc::new(service_with_alice(config).await);
+        let provider = JwtAuthProvider::new(service.clone());
+        let proxies = ras_observability_core::TrustedProxies::new(["10.0.0.0/8"]).unwrap();
+        let proxy = Some("10.0.0.1".parse().unwrap());
+        // The address of a request through the trusted proxy, which appends
+        // the address it was connected from to whatever the client sent
+        let forwarded = |forwarded_for: &str| {
+            let mut headers = http::HeaderMap::new();
+            headers.insert("x-forwarded-for", forwarded_for.parse().unwrap());
+            let client_ip = ras_observability_core::client_ip(&headers, proxy, &proxies);
+            (headers, RequestOrigin::new(client_ip, "/rpc"))
+        };
+
+        let (headers, origin) = forwarded("203.0.113.9");
+        let token = service
+            .begin_bound_session(
+                "local",
+                alice_login(),
+                None,
+                BindingInfo::from_request(&headers, origin.client_ip),
+            )
+            .await
+            .unwrap();
+        let user = origin
+            .scope(provider.authenticate_request(token.clone(), &headers))
+            .await
+            .unwrap();
+        assert_eq!(user.user_id, "alice");
+
+        // A thief elsewhere claiming the victim's address in a leading hop
+        let (headers, origin) = forwarded("203.0.113.9, 198.51.100.7");
+        assert_eq!(origin.client_ip, Some("198.51.100.7".parse().unwrap()));
+        assert!(matches!(
+            origin
+                .scope(provider.authenticate_request(token.clone(), &headers))
+                .await,
+            Err(AuthError::InvalidToken)
+        ));
+
+        // Outside a generated service, forwarded addresses aren't read at all
+        let (headers, _) = forwarded("203.0.113.9");
+        assert!(
+            provider
+                .authenticate_request(token, &headers)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cached_bound_sessions_are_checked_from_other_origins() {
+        let mut config = SessionConfig::new(TEST_SECRET).unwrap();
+        config.binding.ip_prefix = BindingMode::Enforce;
+        let service = Arc::new(service_with_alice(config).await);
+        let provider = ras_auth_core::CachingAuthProvider::new(
+            JwtAuthProvider::new(service.clone()),
+            std::time::Duration::from_secs(60),
+            100,
+        );
+        let origin = |client_ip: &str| RequestOrigin::new(Some(client_ip.parse().unwrap()), "/rpc");
+        let headers = http::HeaderMap::new();
+        let token = service
+            .begin_bound_session(
+                "local",
+                alice_login(),
+                None,
+                BindingInfo::from_request(&headers, Some("203.0.113.9".parse().unwrap())),
+            )
+            .await
+            .unwrap();
+
+        for client_ip in ["203.0.113.9", "203.0.113.20"] {
+            let user = origin(client_ip)
+                .scope(provider.authenticate_request(token.clone(), &headers))
+                .await
+                .unwrap();
+            assert_eq!(user.user_id, "alice");
+        }
+        assert_eq!(provider.stats().hits, 1);
+
+        // The token is cached, but only for its own network
+        assert!(matches!(
+            origin("198.51.100.7")
+                .scope(provider.authenticate_request(token.clone(), &headers))
+                .await,
+            Err(AuthError::InvalidToken)
+        ));
+        assert!(
+            provider
+                .authenticate_request(token, &headers)
+                .await
+                .is_err()
+        );
+    }
 }
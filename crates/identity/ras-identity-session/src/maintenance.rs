@@ -0,0 +1,95 @@
+//! Purging expired sessions in the background.
+
+use crate::SessionService;
+use chrono::Utc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+/// The task started by [`SessionService::start_maintenance`].
+///
+/// Stop it with [`shutdown`](Self::shutdown); dropping the handle aborts it.
+pub struct SessionMaintenance {
+    stop: watch::Sender<bool>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl SessionMaintenance {
+    /// Stop the task, waiting for a purge in progress to finish
+    pub async fn shutdown(mut self) {
+        let _ = self.stop.send(true);
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for SessionMaintenance {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Marks the service as maintained while the task runs, however it ends
+struct Running(Arc<AtomicBool>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl SessionService {
+    /// Purge expired sessions and refresh tokens every `interval` from a
+    /// background task, reporting the active sessions to the service metrics.
+    ///
+    /// While it runs, logins and verifications no longer purge sessions
+    /// themselves. The task stops when the returned handle is shut down or
+    /// dropped, or when the service is dropped. Must be called within a Tokio
+    /// runtime.
+    pub fn start_maintenance(self: &Arc<Self>, interval: Duration) -> SessionMaintenance {
+        let (stop, mut stopped) = watch::channel(false);
+        self.maintained.store(true, Ordering::SeqCst);
+        let running = Running(self.maintained.clone());
+        let service = Arc::downgrade(self);
+
+        let task = tokio::spawn(async move {
+            let _running = running;
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    _ = stopped.changed() => return,
+                }
+                let Some(service) = Weak::upgrade(&service) else {
+                    return;
+                };
+                service.maintain().await;
+            }
+        });
+        SessionMaintenance {
+            stop,
+            task: Some(task),
+        }
+    }
+
+    async fn maintain(&self) {
+        if let Err(error) = self.cleanup_expired_sessions().await {
+            tracing::warn!(%error, "failed to purge expired sessions");
+            return;
+        }
+        let Some(metrics) = &self.metrics else {
+            return;
+        };
+        match self.sessions.count_active(Utc::now().timestamp()).await {
+            Ok(count) => metrics.record_active_sessions(count),
+            Err(error) => tracing::warn!(%error, "failed to count active sessions"),
+        }
+    }
+}
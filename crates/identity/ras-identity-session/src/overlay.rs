@@ -0,0 +1,517 @@
+//! Changing the permissions of users whose tokens were already issued, such
+//! as to revoke a permission before their tokens expire.
+
+use crate::SessionError;
+use async_trait::async_trait;
+use ras_auth_core::{
+    AuthError, AuthFuture, AuthProvider, AuthResult, AuthenticatedUser, PermissionExpr,
+};
+use ras_observability_core::ServiceMetrics;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Subjects whose overlay lookups are cached before expired ones are dropped
+const MAX_CACHED_SUBJECTS: usize = 10_000;
+
+/// Permissions granted to or revoked from a subject since its tokens were
+/// issued. Revoking wins over granting, and over the token's permissions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionChanges {
+    #[serde(default)]
+    pub grant: Vec<String>,
+    #[serde(default)]
+    pub revoke: Vec<String>,
+}
+
+impl PermissionChanges {
+    fn is_empty(&self) -> bool {
+        self.grant.is_empty() && self.revoke.is_empty()
+    }
+}
+
+/// A live source of [`PermissionChanges`], consulted by
+/// [`OverlayAuthProvider`] after a token is validated
+#[async_trait]
+pub trait PermissionOverlay: Send + Sync {
+    /// The changes for `subject`, if any
+    async fn changes(&self, subject: &str) -> Result<Option<PermissionChanges>, SessionError>;
+}
+
+/// Changes kept in memory, such as by an admin API
+#[derive(Debug, Default)]
+pub struct MemoryOverlay {
+    changes: RwLock<HashMap<String, PermissionChanges>>,
+}
+
+impl MemoryOverlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Revoke `permission` from `subject`, withdrawing any grant of it
+    pub fn revoke(&self, subject: &str, permission: impl Into<String>) {
+        let permission = permission.into();
+        let mut changes = self.changes.write().unwrap();
+        let entry = changes.entry(subject.to_string()).or_default();
+        entry.grant.retain(|granted| *granted != permission);
+        if !entry.revoke.contains(&permission) {
+            entry.revoke.push(permission);
+        }
+    }
+
+    /// Grant `permission` to `subject`, withdrawing any revocation of it
+    pub fn grant(&self, subject: &str, permission: impl Into<String>) {
+        let permission = permission.into();
+        let mut changes = self.changes.write().unwrap();
+        let entry = changes.entry(subject.to_string()).or_default();
+        entry.revoke.retain(|revoked| *revoked != permission);
+        if !entry.grant.contains(&permission) {
+            entry.grant.push(permission);
+        }
+    }
+
+    /// Replace the changes for `subject`
+    pub fn set(&self, subject: &str, changes: PermissionChanges) {
+        self.changes
+            .write()
+            .unwrap()
+            .insert(subject.to_string(), changes);
+    }
+
+    /// Drop the changes for `subject`, so its tokens' permissions apply again
+    pub fn clear(&self, subject: &str) {
+        self.changes.write().unwrap().remove(subject);
+    }
+}
+
+#[async_trait]
+impl PermissionOverlay for MemoryOverlay {
+    async fn changes(&self, subject: &str) -> Result<Option<PermissionChanges>, SessionError> {
+        Ok(self.changes.read().unwrap().get(subject).cloned())
+    }
+}
+
+/// Where a [`PolledOverlay`] reads its changes from
+#[derive(Debug, Clone)]
+enum OverlaySource {
+    File(PathBuf),
+    #[cfg(feature = "overlay-http")]
+    Http {
+        client: reqwest::Client,
+        url: String,
+    },
+}
+
+struct Snapshot {
+    changes: HashMap<String, PermissionChanges>,
+    loaded_at: Instant,
+}
+
+/// Changes read from a file or URL, and read again once they're older than
+/// the poll interval, 30 seconds unless set.
+///
+/// The document is a JSON object of subjects to their changes:
+///
+/// ```json
+/// { "alice": { "revoke": ["admin"] }, "bob": { "grant": ["documents:read"] } }
+/// ```
+///
+/// When reading fails, the last changes read are kept, and the failure is
+/// logged; until the first read succeeds, lookups fail.
+pub struct PolledOverlay {
+    source: OverlaySource,
+    interval: Duration,
+    snapshot: tokio::sync::RwLock<Option<Snapshot>>,
+    loading: tokio::sync::Mutex<()>,
+}
+
+impl PolledOverlay {
+    /// Changes read from the JSON file at `path`
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self::with_source(OverlaySource::File(path.into()))
+    }
+
+    /// Changes fetched from `url`, which must answer with the JSON document
+    #[cfg(feature = "overlay-http")]
+    pub fn http(url: impl Into<String>) -> Self {
+        Self::with_source(OverlaySource::Http {
+            client: reqwest::Client::new(),
+            url: url.into(),
+        })
+    }
+
+    fn with_source(source: OverlaySource) -> Self {
+        Self {
+            source,
+            interval: Duration::from_secs(30),
+            snapshot: tokio::sync::RwLock::new(None),
+            loading: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Fetch with `client`, such as one with a timeout or credentials
+    #[cfg(feature = "overlay-http")]
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        if let OverlaySource::Http {
+            client: current, ..
+        } = &mut self.source
+        {
+            *current = client;
+        }
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Read the changes now, however recently they were read
+    pub async fn reload(&self) -> Result<(), SessionError> {
+        let changes = match &self.source {
+            OverlaySource::File(path) => {
+                let document = tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| SessionError::OverlayError(format!("{}: {e}", path.display())))?;
+                serde_json::from_str(&document)
+                    .map_err(|e| SessionError::OverlayError(format!("{}: {e}", path.display())))?
+            }
+            #[cfg(feature = "overlay-http")]
+            OverlaySource::Http { client, url } => client
+                .get(url)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(|e| SessionError::OverlayError(e.to_string()))?
+                .json()
+                .await
+                .map_err(|e| SessionError::OverlayError(e.to_string()))?,
+        };
+        *self.snapshot.write().await = Some(Snapshot {
+            changes,
+            loaded_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    fn is_fresh(&self, snapshot: &Option<Snapshot>) -> bool {
+        snapshot
+            .as_ref()
+            .is_some_and(|snapshot| snapshot.loaded_at.elapsed() < self.interval)
+    }
+}
+
+#[async_trait]
+impl PermissionOverlay for PolledOverlay {
+    async fn changes(&self, subject: &str) -> Result<Option<PermissionChanges>, SessionError> {
+        if !self.is_fresh(&*self.snapshot.read().await) {
+            let _loading = self.loading.lock().await;
+            // Another caller may have read them while this one waited
+            if !self.is_fresh(&*self.snapshot.read().await)
+                && let Err(e) = self.reload().await
+            {
+                let mut snapshot = self.snapshot.write().await;
+                let Some(stale) = snapshot.as_mut() else {
+                    return Err(e);
+                };
+                warn!("Keeping the permission overlay read before: {}", e);
+                // Wait out another interval rather than retry on every lookup
+                stale.loaded_at = Instant::now();
+            }
+        }
+        let snapshot = self.snapshot.read().await;
+        Ok(snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.changes.get(subject).cloned()))
+    }
+}
+
+impl std::fmt::Debug for PolledOverlay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PolledOverlay")
+            .field("source", &self.source)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An [`AuthProvider`] applying a [`PermissionOverlay`] to the users `inner`
+/// authenticates, so permission changes take effect before tokens expire.
+///
+/// Lookups are cached per subject for the cache TTL, 5 seconds unless set, so
+/// changes take up to that long to apply. A failed lookup fails
+/// authentication with `AuthError::Internal` rather than grant a revoked
+/// permission. With [`with_service_metrics`](Self::with_service_metrics),
+/// users whose permissions the overlay changed, and requests refused a
+/// permission it revoked, are recorded.
+pub struct OverlayAuthProvider<P> {
+    inner: P,
+    overlay: Arc<dyn PermissionOverlay>,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, Option<PermissionChanges>)>>,
+    metrics: Option<Arc<dyn ServiceMetrics>>,
+}
+
+impl<P: AuthProvider> OverlayAuthProvider<P> {
+    pub fn new(inner: P, overlay: Arc<dyn PermissionOverlay>) -> Self {
+        Self {
+            inner,
+            overlay,
+            cache_ttl: Duration::from_secs(5),
+            cache: Mutex::new(HashMap::new()),
+            metrics: None,
+        }
+    }
+
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    pub fn with_service_metrics(mut self, metrics: Arc<dyn ServiceMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// The changes for `subject`, from the cache while they're fresh
+    async fn changes(&self, subject: &str) -> AuthResult<Option<PermissionChanges>> {
+        if let Some((at, changes)) = self.cache.lock().unwrap().get(subject)
+            && at.elapsed() < self.cache_ttl
+        {
+            return Ok(changes.clone());
+        }
+
+        let changes = self
+            .overlay
+            .changes(subject)
+            .await
+            .map_err(|e| AuthError::Internal(e.to_string()))?
+            .filter(|changes| !changes.is_empty());
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_SUBJECTS {
+            cache.retain(|_, (at, _)| at.elapsed() < self.cache_ttl);
+            if cache.len() >= MAX_CACHED_SUBJECTS {
+                cache.clear();
+            }
+        }
+        cache.insert(subject.to_string(), (Instant::now(), changes.clone()));
+        Ok(changes)
+    }
+
+    async fn apply(&self, mut user: AuthenticatedUser) -> AuthResult<AuthenticatedUser> {
+        let Some(changes) = self.changes(&user.user_id).await? else {
+            return Ok(user);
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.record_permission_overlay_hit();
+        }
+        user.permissions.extend(changes.grant);
+        for permission in &changes.revoke {
+            user.permissions.remove(permission);
+        }
+        Ok(user)
+    }
+
+    /// Whether the overlay revoked any of `missing` from `subject`, as far as
+    /// the cache remembers
+    fn revoked_any(&self, subject: &str, missing: &[String]) -> bool {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(subject)
+            .and_then(|(_, changes)| changes.as_ref())
+            .is_some_and(|changes| {
+                missing
+                    .iter()
+                    .any(|permission| changes.revoke.contains(permission))
+            })
+    }
+}
+
+impl<P: AuthProvider> AuthProvider for OverlayAuthProvider<P> {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        Box::pin(async move { self.apply(self.inner.authenticate(token).await?).await })
+    }
+
+    fn authenticate_request(&self, token: String, headers: &http::HeaderMap) -> AuthFuture<'_> {
+        let validation = self.inner.authenticate_request(token, headers);
+        Box::pin(async move { self.apply(validation.await?).await })
+    }
+
+    fn request_binding(&self, token: &str, headers: &http::HeaderMap) -> Vec<u8> {
+        self.inner.request_binding(token, headers)
+    }
+
+    fn credential(&self, headers: &http::HeaderMap) -> Option<String> {
+        self.inner.credential(headers)
+    }
+
+    fn check_permissions(
+        &self,
+        user: &AuthenticatedUser,
+        required_permissions: &[String],
+    ) -> AuthResult<()> {
+        let result = self.inner.check_permissions(user, required_permissions);
+        if let (Some(metrics), Err(AuthError::InsufficientPermissions { required, .. })) =
+            (&self.metrics, &result)
+        {
+            let missing: Vec<String> = required
+                .iter()
+                .filter(|permission| !user.permissions.contains(*permission))
+                .cloned()
+                .collect();
+            if self.revoked_any(&user.user_id, &missing) {
+                metrics.record_permission_overlay_denial();
+            }
+        }
+        result
+    }
+
+    fn check_permission_expr(
+        &self,
+        user: &AuthenticatedUser,
+        expression: &PermissionExpr,
+    ) -> AuthResult<()> {
+        let result = self.inner.check_permission_expr(user, expression);
+        if let (Some(metrics), Err(_)) = (&self.metrics, &result) {
+            let missing: Vec<String> = expression
+                .permissions()
+                .into_iter()
+                .filter(|permission| !user.permissions.contains(*permission))
+                .map(str::to_string)
+                .collect();
+            if self.revoked_any(&user.user_id, &missing) {
+                metrics.record_permission_overlay_denial();
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_auth_core::PrincipalKind;
+    use ras_test_helpers::RecordingMetrics;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Accepts any token as the user of that name, with `read` and `admin`
+    struct Users;
+
+    impl AuthProvider for Users {
+        fn authenticate(&self, token: String) -> AuthFuture<'_> {
+            Box::pin(async move {
+                Ok(AuthenticatedUser {
+                    user_id: token,
+                    permissions: HashSet::from(["read".to_string(), "admin".to_string()]),
+                    metadata: None,
+                    kind: PrincipalKind::User,
+                })
+            })
+        }
+    }
+
+    /// Counts the lookups it answers
+    struct Counting {
+        inner: MemoryOverlay,
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PermissionOverlay for Counting {
+        async fn changes(&self, subject: &str) -> Result<Option<PermissionChanges>, SessionError> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            self.inner.changes(subject).await
+        }
+    }
+
+    fn permissions(user: &AuthenticatedUser) -> Vec<&str> {
+        let mut permissions: Vec<&str> = user.permissions.iter().map(String::as_str).collect();
+        permissions.sort();
+        permissions
+    }
+
+    #[tokio::test]
+    async fn overlays_revoke_and_grant_permissions() {
+        let overlay = Arc::new(MemoryOverlay::new());
+        overlay.revoke("alice", "admin");
+        overlay.grant("alice", "write");
+        let metrics = RecordingMetrics::default();
+        let provider = OverlayAuthProvider::new(Users, overlay.clone())
+            .with_service_metrics(Arc::new(metrics.clone()));
+
+        let alice = provider.authenticate("alice".into()).await.unwrap();
+        assert_eq!(permissions(&alice), ["read", "write"]);
+        let bob = provider.authenticate("bob".into()).await.unwrap();
+        assert_eq!(permissions(&bob), ["admin", "read"]);
+        assert_eq!(metrics.permission_overlay_hits(), 1);
+
+        assert!(provider.check_permissions(&alice, &["read".into()]).is_ok());
+        assert!(
+            provider
+                .check_permissions(&alice, &["admin".into()])
+                .is_err()
+        );
+        assert!(
+            provider
+                .check_permissions(&alice, &["delete".into()])
+                .is_err()
+        );
+        assert_eq!(metrics.permission_overlay_denials(), 1);
+    }
+
+    #[tokio::test]
+    async fn lookups_are_cached_for_the_ttl() {
+        let overlay = Arc::new(Counting {
+            inner: MemoryOverlay::new(),
+            lookups: AtomicUsize::new(0),
+        });
+        let provider = OverlayAuthProvider::new(Users, overlay.clone())
+            .with_cache_ttl(Duration::from_millis(50));
+
+        provider.authenticate("alice".into()).await.unwrap();
+        overlay.inner.revoke("alice", "admin");
+        let alice = provider.authenticate("alice".into()).await.unwrap();
+        assert_eq!(permissions(&alice), ["admin", "read"]);
+        assert_eq!(overlay.lookups.load(Ordering::Relaxed), 1);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let alice = provider.authenticate("alice".into()).await.unwrap();
+        assert_eq!(permissions(&alice), ["read"]);
+        assert_eq!(overlay.lookups.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn polled_overlays_reread_their_file() {
+        let path = std::env::temp_dir().join(format!("overlay-{}.json", uuid::Uuid::new_v4()));
+        let overlay = Arc::new(PolledOverlay::file(&path).with_interval(Duration::ZERO));
+        let provider =
+            OverlayAuthProvider::new(Users, overlay.clone()).with_cache_ttl(Duration::ZERO);
+
+        // Nothing was ever read, so revocations can't be honoured
+        assert!(matches!(
+            provider.authenticate("alice".into()).await,
+            Err(AuthError::Internal(_))
+        ));
+
+        std::fs::write(&path, r#"{ "alice": { "revoke": ["admin"] } }"#).unwrap();
+        let alice = provider.authenticate("alice".into()).await.unwrap();
+        assert_eq!(permissions(&alice), ["read"]);
+
+        std::fs::write(&path, r#"{ "alice": { "grant": ["write"] } }"#).unwrap();
+        let alice = provider.authenticate("alice".into()).await.unwrap();
+        assert_eq!(permissions(&alice), ["admin", "read", "write"]);
+
+        // A broken document leaves the last one in effect
+        std::fs::write(&path, "{").unwrap();
+        let alice = provider.authenticate("alice".into()).await.unwrap();
+        assert_eq!(permissions(&alice), ["admin", "read", "write"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
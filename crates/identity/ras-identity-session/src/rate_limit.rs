@@ -0,0 +1,277 @@
+//! Limiting how often logins can be attempted.
+
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Who is attempting a login, as far as the caller knows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitContext {
+    pub provider_id: String,
+    /// The `username` of the login payload, if it has one
+    pub username: Option<String>,
+    /// The address the attempt came from, if the caller passed it
+    pub ip: Option<IpAddr>,
+}
+
+/// Decides whether a login may be attempted.
+///
+/// [`SessionService`](crate::SessionService) asks before each attempt, before
+/// the identity provider checks any credentials.
+#[async_trait]
+pub trait AuthRateLimiter: Send + Sync {
+    /// Count an attempt by `context`, or refuse it with how long to wait
+    async fn check(&self, context: &RateLimitContext) -> Result<(), Duration>;
+}
+
+/// At most `max_attempts` in any `window`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub max_attempts: usize,
+    pub window: Duration,
+}
+
+impl RateLimit {
+    pub fn new(max_attempts: usize, window: Duration) -> Self {
+        Self {
+            max_attempts,
+            window,
+        }
+    }
+}
+
+/// How many checks pass between sweeps for keys with no recent attempts
+const SWEEP_INTERVAL: u64 = 1024;
+
+/// Limits attempts per username and per IP address over a sliding window.
+///
+/// Each username (ignoring ASCII case) and each address has its own window,
+/// and an attempt must fit in both. Refused attempts aren't counted. Attempts
+/// are kept in memory, so each process limits separately.
+#[derive(Debug)]
+pub struct SlidingWindowLimiter {
+    per_username: Option<RateLimit>,
+    per_ip: Option<RateLimit>,
+    state: Mutex<WindowState>,
+}
+
+#[derive(Debug, Default)]
+struct WindowState {
+    attempts: HashMap<String, VecDeque<Instant>>,
+    checks: u64,
+}
+
+impl Default for SlidingWindowLimiter {
+    /// 10 attempts per username and 100 per address every 5 minutes
+    fn default() -> Self {
+        Self::new(
+            Some(RateLimit::new(10, Duration::from_secs(300))),
+            Some(RateLimit::new(100, Duration::from_secs(300))),
+        )
+    }
+}
+
+impl SlidingWindowLimiter {
+    /// Limit attempts per username and per address, or not at all with `None`
+    pub fn new(per_username: Option<RateLimit>, per_ip: Option<RateLimit>) -> Self {
+        Self {
+            per_username,
+            per_ip,
+            state: Mutex::new(WindowState::default()),
+        }
+    }
+
+    fn check_at(&self, context: &RateLimitContext, now: Instant) -> Result<(), Duration> {
+        let keys: Vec<(String, RateLimit)> = [
+            context
+                .username
+                .as_ref()
+                .zip(self.per_username)
+                .map(|(username, limit)| {
+                    (format!("user:{}", username.to_ascii_lowercase()), limit)
+                }),
+            context
+                .ip
+                .zip(self.per_ip)
+                .map(|(ip, limit)| (format!("ip:{}", ip), limit)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let mut state = self.state.lock().unwrap();
+        state.checks += 1;
+        if state.checks.is_multiple_of(SWEEP_INTERVAL) {
+            let longest = self.longest_window();
+            state.attempts.retain(|_, attempts| {
+                attempts
+                    .back()
+                    .is_some_and(|last| now.duration_since(*last) < longest)
+            });
+        }
+
+        let mut retry_after = Duration::ZERO;
+        for (key, limit) in &keys {
+            let Some(attempts) = state.attempts.get_mut(key) else {
+                continue;
+            };
+            while attempts
+                .front()
+                .is_some_and(|first| now.duration_since(*first) >= limit.window)
+            {
+                attempts.pop_front();
+            }
+            if attempts.len() >= limit.max_attempts {
+                // Once the oldest attempt leaves the window there's room again
+                let oldest = attempts
+                    .get(attempts.len() - limit.max_attempts)
+                    .copied()
+                    .unwrap_or(now);
+                retry_after = retry_after.max(limit.window - now.duration_since(oldest));
+            }
+        }
+        if !retry_after.is_zero() {
+            return Err(retry_after);
+        }
+
+        for (key, _) in keys {
+            state.attempts.entry(key).or_default().push_back(now);
+        }
+        Ok(())
+    }
+
+    fn longest_window(&self) -> Duration {
+        [self.per_username, self.per_ip]
+            .into_iter()
+            .flatten()
+            .map(|limit| limit.window)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl AuthRateLimiter for SlidingWindowLimiter {
+    async fn check(&self, context: &RateLimitContext) -> Result<(), Duration> {
+        self.check_at(context, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(username: &str, ip: &str) -> RateLimitContext {
+        RateLimitContext {
+            provider_id: "local".to_string(),
+            username: Some(username.to_string()),
+            ip: Some(ip.parse().unwrap()),
+        }
+    }
+
+    fn limiter() -> SlidingWindowLimiter {
+        SlidingWindowLimiter::new(
+            Some(RateLimit::new(3, Duration::from_secs(60))),
+            Some(RateLimit::new(5, Duration::from_secs(60))),
+        )
+    }
+
+    #[test]
+    fn test_window_rolls_over() {
+        let limiter = limiter();
+        let start = Instant::now();
+        let alice = context("alice", "192.0.2.1");
+
+        for second in 0..3 {
+            let at = start + Duration::from_secs(second * 10);
+            assert!(limiter.check_at(&alice, at).is_ok());
+        }
+        let refused_at = start + Duration::from_secs(30);
+        assert_eq!(
+            limiter.check_at(&alice, refused_at),
+            Err(Duration::from_secs(30))
+        );
+
+        // The first attempt leaves the window after 60 seconds, making room for one more
+        let rolled_over = start + Duration::from_secs(60);
+        assert!(limiter.check_at(&alice, rolled_over).is_ok());
+        assert_eq!(
+            limiter.check_at(&alice, rolled_over),
+            Err(Duration::from_secs(10))
+        );
+        assert!(
+            limiter
+                .check_at(&alice, start + Duration::from_secs(80))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_keys_are_independent() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(
+                limiter
+                    .check_at(&context("alice", "192.0.2.1"), now)
+                    .is_ok()
+            );
+        }
+        assert!(
+            limiter
+                .check_at(&context("ALICE", "192.0.2.2"), now)
+                .is_err()
+        );
+        // Other users from the same address have their own username windows
+        assert!(limiter.check_at(&context("bob", "192.0.2.1"), now).is_ok());
+        assert!(
+            limiter
+                .check_at(&context("carol", "192.0.2.1"), now)
+                .is_ok()
+        );
+        // ...until the address's window of 5 is full
+        assert!(
+            limiter
+                .check_at(&context("dave", "192.0.2.1"), now)
+                .is_err()
+        );
+        assert!(limiter.check_at(&context("dave", "192.0.2.3"), now).is_ok());
+    }
+
+    #[test]
+    fn test_refused_attempts_are_not_counted() {
+        let limiter = limiter();
+        let start = Instant::now();
+        let alice = context("alice", "192.0.2.1");
+
+        for _ in 0..3 {
+            assert!(limiter.check_at(&alice, start).is_ok());
+        }
+        for _ in 0..10 {
+            assert!(limiter.check_at(&alice, start).is_err());
+        }
+        assert!(
+            limiter
+                .check_at(&alice, start + Duration::from_secs(60))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_attempts_without_a_username_or_address() {
+        let limiter = limiter();
+        let now = Instant::now();
+        let anonymous = RateLimitContext {
+            provider_id: "oauth2".to_string(),
+            username: None,
+            ip: None,
+        };
+
+        for _ in 0..10 {
+            assert!(limiter.check_at(&anonymous, now).is_ok());
+        }
+    }
+}
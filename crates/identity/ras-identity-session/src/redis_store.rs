@@ -0,0 +1,470 @@
+//! Keeping sessions in Redis, shared between replicas
+//!
+//! Each session is stored as JSON under its own key, which Redis expires at
+//! the session's `expires_at`. Sessions are indexed by provider and subject in
+//! a sorted set scored by `expires_at`, whose stale members are dropped as the
+//! subject's sessions are listed, and in one sorted set of every session that
+//! [`purge_expired`](SessionStore::purge_expired) drops them from. Requires
+//! Redis 7 or later.
+//!
+//! Refresh tokens are stored likewise under the hash of the token, expiring at
+//! their own `expires_at`, and indexed by family and by the families of each
+//! subject. Rotating a token sets its `rotated` field with a script, so only
+//! one replica can rotate it.
+//!
+//! Keys, under a configurable prefix (`ras` by default):
+//!
+//! | Key                                             | Holds                                         |
+//! |-------------------------------------------------|-----------------------------------------------|
+//! | `{prefix}:session:{jti}`                        | the session, as JSON                          |
+//! | `{prefix}:sessions`                             | sorted set of every `jti` by expiry           |
+//! | `{prefix}:subject:{provider}:{subject}`         | sorted set of the subject's `jti`s by expiry  |
+//! | `{prefix}:refresh:{hash}`                       | hash of the token as JSON, and if `rotated`   |
+//! | `{prefix}:refresh-tokens`                       | sorted set of every token hash by expiry      |
+//! | `{prefix}:refresh-family:{family}`              | set of the family's token hashes              |
+//! | `{prefix}:refresh-subject:{provider}:{subject}` | set of the families of the subject's sessions |
+
+use crate::SessionError;
+use crate::store::{RefreshRecord, SessionRecord, SessionStore};
+use async_trait::async_trait;
+use chrono::Utc;
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Marks the refresh token at `KEYS[1]` as rotated, returning its record and
+/// whether it was rotated already
+const ROTATE_REFRESH: &str = r"
+local record = redis.call('HGET', KEYS[1], 'record')
+if not record then
+    return false
+end
+local added = redis.call('HSET', KEYS[1], 'rotated', '1')
+return {record, added}
+";
+
+/// A [`SessionStore`] backed by Redis
+#[derive(Clone)]
+pub struct RedisSessionStore {
+    connection: MultiplexedConnection,
+    prefix: String,
+}
+
+impl RedisSessionStore {
+    /// Connect to Redis at `url`
+    pub async fn connect(url: &str) -> Result<Self, SessionError> {
+        let client = redis::Client::open(url).map_err(store_error)?;
+        let connection = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(store_error)?;
+        Ok(Self {
+            connection,
+            prefix: "ras".to_string(),
+        })
+    }
+
+    /// Set the prefix of every key, to share a Redis between services
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn session(&self, jti: &str) -> String {
+        format!("{}:session:{}", self.prefix, jti)
+    }
+
+    fn all_sessions(&self) -> String {
+        format!("{}:sessions", self.prefix)
+    }
+
+    fn subject(&self, provider_id: &str, subject: &str) -> String {
+        format!("{}:subject:{}:{}", self.prefix, provider_id, subject)
+    }
+
+    fn refresh(&self, hash: &str) -> String {
+        format!("{}:refresh:{}", self.prefix, hash)
+    }
+
+    fn all_refresh_tokens(&self) -> String {
+        format!("{}:refresh-tokens", self.prefix)
+    }
+
+    fn refresh_family(&self, family: &str) -> String {
+        format!("{}:refresh-family:{}", self.prefix, family)
+    }
+
+    fn refresh_subject(&self, provider_id: &str, subject: &str) -> String {
+        format!(
+            "{}:refresh-subject:{}:{}",
+            self.prefix, provider_id, subject
+        )
+    }
+
+    /// The sessions with `jtis` that haven't expired, oldest first
+    async fn sessions(&self, jtis: &[String]) -> Result<Vec<SessionRecord>, SessionError> {
+        if jtis.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> = jtis.iter().map(|jti| self.session(jti)).collect();
+        let payloads: Vec<Option<String>> = self
+            .connection
+            .clone()
+            .mget(keys)
+            .await
+            .map_err(store_error)?;
+        let now = Utc::now().timestamp();
+        let mut sessions = Vec::with_capacity(payloads.len());
+        for payload in payloads.into_iter().flatten() {
+            let session = decode(&payload)?;
+            if session.expires_at > now {
+                sessions.push(session);
+            }
+        }
+        sessions.sort_by_key(|session| session.claims.iat);
+        Ok(sessions)
+    }
+
+    /// Store `session`, only in place of a stored one if `replace_only`,
+    /// returning whether it was stored
+    async fn write(
+        &self,
+        session: &SessionRecord,
+        replace_only: bool,
+    ) -> Result<bool, SessionError> {
+        let claims = &session.claims;
+        if session.expires_at <= Utc::now().timestamp() {
+            return Ok(false);
+        }
+        let payload = serde_json::to_string(session).map_err(store_error)?;
+        let subject = self.subject(&claims.provider_id, &claims.sub);
+
+        let mut pipe = redis::pipe();
+        pipe.cmd("SET")
+            .arg(self.session(&claims.jti))
+            .arg(payload)
+            .arg("EXAT")
+            .arg(session.expires_at);
+        if replace_only {
+            pipe.arg("XX");
+        }
+        for index in [self.all_sessions(), subject.clone()] {
+            pipe.cmd("ZADD").arg(index);
+            if replace_only {
+                pipe.arg("XX");
+            }
+            pipe.arg(session.expires_at).arg(&claims.jti).ignore();
+        }
+        // The index lasts as long as the subject's last session
+        pipe.cmd("EXPIREAT")
+            .arg(&subject)
+            .arg(session.expires_at)
+            .arg("NX")
+            .ignore()
+            .cmd("EXPIREAT")
+            .arg(&subject)
+            .arg(session.expires_at)
+            .arg("GT")
+            .ignore();
+        let (stored,): (Option<String>,) = pipe
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(store_error)?;
+        Ok(stored.is_some())
+    }
+}
+
+impl fmt::Debug for RedisSessionStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisSessionStore")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn insert(&self, session: &SessionRecord) -> Result<(), SessionError> {
+        self.write(session, false).await.map(|_| ())
+    }
+
+    async fn get(&self, jti: &str) -> Result<Option<SessionRecord>, SessionError> {
+        let payload: Option<String> = self
+            .connection
+            .clone()
+            .get(self.session(jti))
+            .await
+            .map_err(store_error)?;
+        let now = Utc::now().timestamp();
+        Ok(payload
+            .map(|payload| decode(&payload))
+            .transpose()?
+            .filter(|session| session.expires_at > now))
+    }
+
+    async fn update(&self, session: &SessionRecord) -> Result<bool, SessionError> {
+        self.write(session, true).await
+    }
+
+    async fn remove(&self, jti: &str) -> Result<Option<SessionRecord>, SessionError> {
+        let mut connection = self.connection.clone();
+        let payload: Option<String> = connection
+            .get_del(self.session(jti))
+            .await
+            .map_err(store_error)?;
+        let Some(session) = payload.map(|payload| decode(&payload)).transpose()? else {
+            return Ok(None);
+        };
+        redis::pipe()
+            .zrem(
+                self.subject(&session.claims.provider_id, &session.claims.sub),
+                jti,
+            )
+            .ignore()
+            .zrem(self.all_sessions(), jti)
+            .ignore()
+            .query_async::<()>(&mut connection)
+            .await
+            .map_err(store_error)?;
+        Ok((session.expires_at > Utc::now().timestamp()).then_some(session))
+    }
+
+    async fn remove_by_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<SessionRecord>, SessionError> {
+        let key = self.subject(provider_id, subject);
+        let jtis: Vec<String> = self
+            .connection
+            .clone()
+            .zrange(&key, 0, -1)
+            .await
+            .map_err(store_error)?;
+        let sessions = self.sessions(&jtis).await?;
+
+        let mut removal = redis::pipe();
+        for jti in &jtis {
+            removal.del(self.session(jti)).ignore();
+        }
+        if !jtis.is_empty() {
+            removal.zrem(self.all_sessions(), &jtis).ignore();
+        }
+        removal.del(&key).ignore();
+        removal
+            .query_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(store_error)?;
+        Ok(sessions)
+    }
+
+    async fn list_by_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<SessionRecord>, SessionError> {
+        let key = self.subject(provider_id, subject);
+        let now = Utc::now().timestamp();
+        let (jtis,): (Vec<String>,) = redis::pipe()
+            .zrembyscore(&key, "-inf", now)
+            .ignore()
+            .cmd("ZRANGE")
+            .arg(&key)
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(store_error)?;
+        self.sessions(&jtis).await
+    }
+
+    /// Redis expires sessions by itself, so this only drops them from the
+    /// index of every session
+    async fn purge_expired(&self, now: i64) -> Result<usize, SessionError> {
+        self.connection
+            .clone()
+            .zrembyscore(self.all_sessions(), "-inf", now)
+            .await
+            .map_err(store_error)
+    }
+
+    async fn count_active(&self, now: i64) -> Result<usize, SessionError> {
+        self.connection
+            .clone()
+            .zcount(self.all_sessions(), format!("({}", now), "+inf")
+            .await
+            .map_err(store_error)
+    }
+
+    async fn insert_refresh(&self, hash: &str, record: &RefreshRecord) -> Result<(), SessionError> {
+        if record.expires_at <= Utc::now().timestamp() {
+            return Ok(());
+        }
+        let payload = serde_json::to_string(record).map_err(store_error)?;
+        let key = self.refresh(hash);
+        let family = self.refresh_family(&record.family);
+        let claims = &record.session.claims;
+        let subject = self.refresh_subject(&claims.provider_id, &claims.sub);
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .del(&key)
+            .ignore()
+            .hset(&key, "record", payload)
+            .ignore();
+        if record.rotated {
+            pipe.hset(&key, "rotated", "1").ignore();
+        }
+        pipe.cmd("EXPIREAT")
+            .arg(&key)
+            .arg(record.expires_at)
+            .ignore()
+            .zadd(self.all_refresh_tokens(), hash, record.expires_at)
+            .ignore()
+            .sadd(&family, hash)
+            .ignore()
+            .sadd(&subject, &record.family)
+            .ignore();
+        // The indexes last as long as their last token
+        for index in [&family, &subject] {
+            for condition in ["NX", "GT"] {
+                pipe.cmd("EXPIREAT")
+                    .arg(index)
+                    .arg(record.expires_at)
+                    .arg(condition)
+                    .ignore();
+            }
+        }
+        pipe.query_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(store_error)
+    }
+
+    async fn get_refresh(&self, hash: &str) -> Result<Option<RefreshRecord>, SessionError> {
+        let (payload, rotated): (Option<String>, Option<String>) = self
+            .connection
+            .clone()
+            .hget(self.refresh(hash), &["record", "rotated"])
+            .await
+            .map_err(store_error)?;
+        let Some(mut record) = payload
+            .map(|payload| decode_refresh(&payload))
+            .transpose()?
+        else {
+            return Ok(None);
+        };
+        record.rotated |= rotated.is_some();
+        Ok((record.expires_at > Utc::now().timestamp()).then_some(record))
+    }
+
+    async fn rotate_refresh(&self, hash: &str) -> Result<Option<RefreshRecord>, SessionError> {
+        let rotated: Option<(String, i64)> = redis::Script::new(ROTATE_REFRESH)
+            .key(self.refresh(hash))
+            .invoke_async(&mut self.connection.clone())
+            .await
+            .map_err(store_error)?;
+        let Some((payload, added)) = rotated else {
+            return Ok(None);
+        };
+        let mut record = decode_refresh(&payload)?;
+        record.rotated |= added == 0;
+        Ok((record.expires_at > Utc::now().timestamp()).then_some(record))
+    }
+
+    async fn remove_refresh_family(
+        &self,
+        family: &str,
+    ) -> Result<Vec<RefreshRecord>, SessionError> {
+        let key = self.refresh_family(family);
+        let mut connection = self.connection.clone();
+        let hashes: Vec<String> = connection.smembers(&key).await.map_err(store_error)?;
+        if hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut lookup = redis::pipe();
+        for hash in &hashes {
+            lookup.hget(self.refresh(hash), "record");
+        }
+        let payloads: Vec<Option<String>> = lookup
+            .query_async(&mut connection)
+            .await
+            .map_err(store_error)?;
+        let records = payloads
+            .into_iter()
+            .flatten()
+            .map(|payload| decode_refresh(&payload))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut removal = redis::pipe();
+        for hash in &hashes {
+            removal.del(self.refresh(hash)).ignore();
+        }
+        removal
+            .zrem(self.all_refresh_tokens(), &hashes)
+            .ignore()
+            .del(&key)
+            .ignore();
+        for record in &records {
+            let claims = &record.session.claims;
+            removal
+                .srem(
+                    self.refresh_subject(&claims.provider_id, &claims.sub),
+                    family,
+                )
+                .ignore();
+        }
+        removal
+            .query_async::<()>(&mut connection)
+            .await
+            .map_err(store_error)?;
+        Ok(records)
+    }
+
+    async fn remove_refresh_by_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<RefreshRecord>, SessionError> {
+        let key = self.refresh_subject(provider_id, subject);
+        let families: HashSet<String> = self
+            .connection
+            .clone()
+            .smembers(&key)
+            .await
+            .map_err(store_error)?;
+        let mut removed = Vec::new();
+        for family in families {
+            removed.extend(self.remove_refresh_family(&family).await?);
+        }
+        self.connection
+            .clone()
+            .del::<_, ()>(&key)
+            .await
+            .map_err(store_error)?;
+        Ok(removed)
+    }
+
+    /// Redis expires refresh tokens by itself, so this only drops them from
+    /// the index of every refresh token
+    async fn purge_expired_refresh(&self, now: i64) -> Result<usize, SessionError> {
+        self.connection
+            .clone()
+            .zrembyscore(self.all_refresh_tokens(), "-inf", now)
+            .await
+            .map_err(store_error)
+    }
+}
+
+fn decode(payload: &str) -> Result<SessionRecord, SessionError> {
+    serde_json::from_str(payload).map_err(store_error)
+}
+
+fn decode_refresh(payload: &str) -> Result<RefreshRecord, SessionError> {
+    serde_json::from_str(payload).map_err(store_error)
+}
+
+fn store_error(error: impl fmt::Display) -> SessionError {
+    SessionError::StoreError(error.to_string())
+}
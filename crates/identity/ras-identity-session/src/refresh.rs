@@ -0,0 +1,196 @@
+//! Short-lived access tokens renewed with rotating refresh tokens.
+
+use crate::{BindingInfo, JwtClaims, RefreshRecord, SessionError, SessionRecord, SessionService};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use rand_core::{OsRng, RngCore};
+use ras_identity_core::{AuthSource, IdentityEvent, IdentityEventKind};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// An access token and the refresh token that renews it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    /// When the access token expires, in seconds since the Unix epoch
+    pub expires_at: i64,
+    /// Opaque token for [`SessionService::refresh`], usable once
+    pub refresh_token: String,
+    pub refresh_expires_at: i64,
+}
+
+impl SessionService {
+    /// [`begin_session`](Self::begin_session) issuing a short-lived access token
+    /// lasting `refreshable_jwt_ttl` and a refresh token to renew it with.
+    ///
+    /// Fails with `SessionError::RefreshDisabled` unless `refresh_enabled` is set.
+    pub async fn begin_session_with_refresh(
+        &self,
+        provider_id: &str,
+        auth_payload: serde_json::Value,
+        source: Option<AuthSource>,
+    ) -> Result<TokenPair, SessionError> {
+        self.start_refreshable_session(provider_id, auth_payload, source, None)
+            .await
+    }
+
+    /// [`begin_session_with_refresh`](Self::begin_session_with_refresh)
+    /// binding the session to the client logging in, as
+    /// [`begin_bound_session`](Self::begin_bound_session) does. The sessions
+    /// it is refreshed into are bound to the same client.
+    pub async fn begin_bound_session_with_refresh(
+        &self,
+        provider_id: &str,
+        auth_payload: serde_json::Value,
+        source: Option<AuthSource>,
+        binding: BindingInfo,
+    ) -> Result<TokenPair, SessionError> {
+        self.start_refreshable_session(provider_id, auth_payload, source, Some(binding))
+            .await
+    }
+
+    async fn start_refreshable_session(
+        &self,
+        provider_id: &str,
+        auth_payload: serde_json::Value,
+        source: Option<AuthSource>,
+        binding: Option<BindingInfo>,
+    ) -> Result<TokenPair, SessionError> {
+        if !self.config.refresh_enabled {
+            return Err(SessionError::RefreshDisabled);
+        }
+
+        let (access_token, session) = self
+            .start_session(
+                provider_id,
+                auth_payload,
+                source,
+                binding,
+                self.config.refreshable_jwt_ttl,
+            )
+            .await?;
+        let family = Uuid::new_v4().to_string();
+        self.pair(access_token, session, family).await
+    }
+
+    /// Exchange `refresh_token` for a new access token and refresh token.
+    ///
+    /// The access token has the claims of the one the session started with,
+    /// and its session the binding, if any.
+    /// Once the login is older than `absolute_max`, refreshing fails with
+    /// `SessionError::InvalidSession`.
+    /// Each refresh token can be exchanged once: presenting a rotated one
+    /// again, as a thief replaying a stolen token would, revokes every refresh
+    /// token and active session of its family and fails with
+    /// `SessionError::RefreshTokenReused`. Unknown and expired tokens fail with
+    /// `SessionError::InvalidSession`.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair, SessionError> {
+        let refreshed = self.rotate(refresh_token).await;
+        if let Err(error) = &refreshed {
+            self.emit(IdentityEvent::new(IdentityEventKind::SessionRejected {
+                reason: error.to_string(),
+            }));
+        }
+        refreshed
+    }
+
+    async fn rotate(&self, refresh_token: &str) -> Result<TokenPair, SessionError> {
+        let now = Utc::now();
+        let hash = hash_refresh_token(refresh_token);
+        // Rotated in the store, so of concurrent refreshes with the same
+        // token only one sees it unrotated, whichever replica it reached
+        let Some(record) = self.sessions.rotate_refresh(&hash).await? else {
+            return Err(SessionError::InvalidSession);
+        };
+        if record.rotated {
+            self.revoke_family(&record.family).await?;
+            return Err(SessionError::RefreshTokenReused);
+        }
+        let created_at = record.session.created_at;
+        let exceeded_max = self
+            .config
+            .absolute_max
+            .is_some_and(|absolute_max| created_at + absolute_max.num_seconds() <= now.timestamp());
+        if exceeded_max {
+            self.sessions.remove_refresh_family(&record.family).await?;
+            return Err(SessionError::InvalidSession);
+        }
+
+        let claims = JwtClaims {
+            jti: Uuid::new_v4().to_string(),
+            iat: now.timestamp(),
+            nbf: self.not_before(now.timestamp()),
+            exp: self.expiry(created_at, now.timestamp(), self.config.refreshable_jwt_ttl),
+            ..record.session.claims.clone()
+        };
+        let session = self.new_session(
+            claims,
+            record.session.source.clone(),
+            record.session.binding.clone(),
+            created_at,
+        );
+        let access_token = self.issue_token(&session).await?;
+        self.pair(access_token, session, record.family).await
+    }
+
+    /// Revoke `refresh_token` and every other refresh token of its family,
+    /// ending their active sessions. Returns `false` for unknown tokens.
+    pub async fn revoke_refresh(&self, refresh_token: &str) -> Result<bool, SessionError> {
+        let hash = hash_refresh_token(refresh_token);
+        let Some(record) = self.sessions.get_refresh(&hash).await? else {
+            return Ok(false);
+        };
+        self.revoke_family(&record.family).await?;
+        Ok(true)
+    }
+
+    async fn revoke_family(&self, family: &str) -> Result<(), SessionError> {
+        for record in self.sessions.remove_refresh_family(family).await? {
+            self.end_session_for(&record.session.claims.jti, "revocation")
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Store a new refresh token in `family` for the access token of `session`
+    async fn pair(
+        &self,
+        access_token: String,
+        session: SessionRecord,
+        family: String,
+    ) -> Result<TokenPair, SessionError> {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        let refresh_token = URL_SAFE_NO_PAD.encode(secret);
+        let refresh_expires_at = self.expiry(
+            session.created_at,
+            Utc::now().timestamp(),
+            self.config.refresh_ttl,
+        );
+        let expires_at = session.claims.exp;
+
+        self.sessions
+            .insert_refresh(
+                &hash_refresh_token(&refresh_token),
+                &RefreshRecord {
+                    family,
+                    session,
+                    expires_at: refresh_expires_at,
+                    rotated: false,
+                },
+            )
+            .await?;
+        Ok(TokenPair {
+            access_token,
+            expires_at,
+            refresh_token,
+            refresh_expires_at,
+        })
+    }
+}
+
+pub(crate) fn hash_refresh_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,88 @@
+//! Sessions ended elsewhere, remembered so their tokens are refused at once.
+
+use crate::{JwtClaims, SessionEvent};
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, Weak};
+use tokio::sync::broadcast;
+
+/// Ended sessions and revoked subjects, learned from [`SessionEvent`]s
+pub(crate) struct Revocations {
+    revoked: RwLock<Revoked>,
+    /// How long an entry is kept, at least the lifetime of the tokens it refuses
+    retention: Duration,
+}
+
+#[derive(Default)]
+struct Revoked {
+    /// Ended sessions by `jti`, with when they're forgotten
+    sessions: HashMap<String, i64>,
+    /// Revoked subjects by provider and subject, with when they were revoked
+    /// and when that's forgotten
+    subjects: HashMap<(String, String), (i64, i64)>,
+}
+
+impl Revocations {
+    /// Remember what `events` revokes for `retention`, until the returned
+    /// list is dropped or the events end
+    pub(crate) fn follow(
+        mut events: broadcast::Receiver<SessionEvent>,
+        retention: Duration,
+    ) -> Arc<Self> {
+        let revocations = Arc::new(Self {
+            revoked: RwLock::new(Revoked::default()),
+            retention,
+        });
+        let following = Arc::downgrade(&revocations);
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        let Some(revocations) = Weak::upgrade(&following) else {
+                            return;
+                        };
+                        revocations.record(event);
+                    }
+                    // Missed events can't be recovered; ended sessions are
+                    // still refused where they're checked against the store
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+        revocations
+    }
+
+    fn record(&self, event: SessionEvent) {
+        let now = Utc::now().timestamp();
+        let forget_at = now + self.retention.num_seconds();
+        let mut revoked = self.revoked.write().unwrap_or_else(|e| e.into_inner());
+        revoked.sessions.retain(|_, until| *until > now);
+        revoked.subjects.retain(|_, (_, until)| *until > now);
+        match event {
+            SessionEvent::Ended { jti, .. } => {
+                revoked.sessions.insert(jti, forget_at);
+            }
+            SessionEvent::SubjectRevoked {
+                provider_id,
+                user_id,
+                at,
+            } => {
+                revoked
+                    .subjects
+                    .insert((provider_id, user_id), (at, forget_at));
+            }
+        }
+    }
+
+    /// Whether the session with `claims` was ended, or its subject revoked
+    /// after it began
+    pub(crate) fn is_revoked(&self, claims: &JwtClaims) -> bool {
+        let revoked = self.revoked.read().unwrap_or_else(|e| e.into_inner());
+        revoked.sessions.contains_key(&claims.jti)
+            || revoked
+                .subjects
+                .get(&(claims.provider_id.clone(), claims.sub.clone()))
+                .is_some_and(|(at, _)| claims.iat <= *at)
+    }
+}
@@ -0,0 +1,351 @@
+//! Where active sessions are kept.
+
+use crate::{BindingInfo, JwtClaims, SessionError};
+use async_trait::async_trait;
+use chrono::Utc;
+use ras_identity_core::AuthSource;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::RwLock;
+
+/// An active session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub claims: JwtClaims,
+    /// Where the login came from, if the caller passed it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<AuthSource>,
+    /// The client the session is bound to, if it began bound
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binding: Option<BindingInfo>,
+    /// When the login the session continues happened, kept across refreshes,
+    /// in seconds since the Unix epoch
+    pub created_at: i64,
+    /// When the session was last verified, recorded at most once per debounce
+    pub last_seen_at: i64,
+    /// When the session ends unless it's extended, at most `claims.exp`
+    pub expires_at: i64,
+}
+
+impl SessionRecord {
+    /// A session created and last seen at the `iat` of `claims`, lasting until their `exp`
+    pub fn new(claims: JwtClaims, source: Option<AuthSource>) -> Self {
+        Self {
+            created_at: claims.iat,
+            last_seen_at: claims.iat,
+            expires_at: claims.exp,
+            claims,
+            source,
+            binding: None,
+        }
+    }
+
+    fn is_live(&self, now: i64) -> bool {
+        self.expires_at > now
+    }
+
+    fn belongs_to(&self, provider_id: &str, subject: &str) -> bool {
+        self.claims.provider_id == provider_id && self.claims.sub == subject
+    }
+}
+
+/// A refresh token, stored under the hash of the token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshRecord {
+    /// Shared by a refresh token and every token it was rotated into
+    pub family: String,
+    /// The session of the access token issued with this refresh token
+    pub session: SessionRecord,
+    /// When the refresh token expires, in seconds since the Unix epoch
+    pub expires_at: i64,
+    /// Set once it has been exchanged; presenting it again revokes the family
+    #[serde(default)]
+    pub rotated: bool,
+}
+
+impl RefreshRecord {
+    fn is_live(&self, now: i64) -> bool {
+        self.expires_at > now
+    }
+}
+
+/// Keeps the active sessions of a [`SessionService`](crate::SessionService),
+/// by session id (`jti`) and by the provider and subject they belong to, and
+/// their refresh tokens, by the hash of the token and by family.
+///
+/// A store shared between replicas lets a session started on one be accepted
+/// and ended on any of them, and lets sessions and refresh tokens outlive a
+/// restart. Sessions and refresh tokens are only returned until their
+/// `expires_at`, whether or not they have been purged.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Keep `session` until it expires
+    async fn insert(&self, session: &SessionRecord) -> Result<(), SessionError>;
+
+    /// The session with `jti`
+    async fn get(&self, jti: &str) -> Result<Option<SessionRecord>, SessionError>;
+
+    /// Replace the session with the same `jti`, such as to extend its expiry,
+    /// unless it has ended. Returns whether it was replaced.
+    async fn update(&self, session: &SessionRecord) -> Result<bool, SessionError>;
+
+    /// Remove the session with `jti`, returning it
+    async fn remove(&self, jti: &str) -> Result<Option<SessionRecord>, SessionError>;
+
+    /// Remove every session of `subject` from `provider_id`, returning them
+    async fn remove_by_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<SessionRecord>, SessionError>;
+
+    /// The sessions of `subject` from `provider_id`, oldest first
+    async fn list_by_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<SessionRecord>, SessionError>;
+
+    /// Forget sessions that expired by `now`, in seconds since the Unix epoch,
+    /// returning how many there were
+    async fn purge_expired(&self, now: i64) -> Result<usize, SessionError>;
+
+    /// How many sessions haven't expired by `now`
+    async fn count_active(&self, now: i64) -> Result<usize, SessionError>;
+
+    /// Keep refresh token `record` under `hash`, the hash of the token, until
+    /// it expires
+    async fn insert_refresh(&self, hash: &str, record: &RefreshRecord) -> Result<(), SessionError>;
+
+    /// The refresh token stored under `hash`
+    async fn get_refresh(&self, hash: &str) -> Result<Option<RefreshRecord>, SessionError>;
+
+    /// Mark the refresh token stored under `hash` as rotated, returning it as
+    /// it was before.
+    ///
+    /// Atomic, so of callers rotating the same token, even on different
+    /// replicas, only one gets it back with `rotated` unset.
+    async fn rotate_refresh(&self, hash: &str) -> Result<Option<RefreshRecord>, SessionError>;
+
+    /// Remove every refresh token of `family`, returning them
+    async fn remove_refresh_family(&self, family: &str)
+    -> Result<Vec<RefreshRecord>, SessionError>;
+
+    /// Remove every refresh token of the sessions of `subject` from
+    /// `provider_id`, returning them
+    async fn remove_refresh_by_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<RefreshRecord>, SessionError>;
+
+    /// Forget refresh tokens that expired by `now`, returning how many there were
+    async fn purge_expired_refresh(&self, now: i64) -> Result<usize, SessionError>;
+}
+
+/// Keeps sessions in a map in this process, the default store.
+///
+/// Sessions are lost on restart and unknown to other replicas.
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: RwLock<HashMap<String, SessionRecord>>,
+    refresh_tokens: RwLock<RefreshTokens>,
+}
+
+#[derive(Debug, Default)]
+struct RefreshTokens {
+    /// By the hash of the token
+    records: HashMap<String, RefreshRecord>,
+    /// The hashes of the tokens of each family
+    families: HashMap<String, HashSet<String>>,
+}
+
+impl RefreshTokens {
+    fn remove_family(&mut self, family: &str) -> Vec<RefreshRecord> {
+        self.families
+            .remove(family)
+            .into_iter()
+            .flatten()
+            .filter_map(|hash| self.records.remove(&hash))
+            .collect()
+    }
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn insert(&self, session: &SessionRecord) -> Result<(), SessionError> {
+        self.sessions
+            .write()
+            .await
+            .insert(session.claims.jti.clone(), session.clone());
+        Ok(())
+    }
+
+    async fn get(&self, jti: &str) -> Result<Option<SessionRecord>, SessionError> {
+        let now = Utc::now().timestamp();
+        Ok(self
+            .sessions
+            .read()
+            .await
+            .get(jti)
+            .filter(|session| session.is_live(now))
+            .cloned())
+    }
+
+    async fn update(&self, session: &SessionRecord) -> Result<bool, SessionError> {
+        let now = Utc::now().timestamp();
+        let mut sessions = self.sessions.write().await;
+        match sessions.get_mut(&session.claims.jti) {
+            Some(stored) if stored.is_live(now) => {
+                *stored = session.clone();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn remove(&self, jti: &str) -> Result<Option<SessionRecord>, SessionError> {
+        let now = Utc::now().timestamp();
+        Ok(self
+            .sessions
+            .write()
+            .await
+            .remove(jti)
+            .filter(|session| session.is_live(now)))
+    }
+
+    async fn remove_by_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<SessionRecord>, SessionError> {
+        let now = Utc::now().timestamp();
+        let mut removed = Vec::new();
+        self.sessions.write().await.retain(|_, session| {
+            if !session.belongs_to(provider_id, subject) {
+                return true;
+            }
+            if session.is_live(now) {
+                removed.push(session.clone());
+            }
+            false
+        });
+        removed.sort_by_key(|session| session.claims.iat);
+        Ok(removed)
+    }
+
+    async fn list_by_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<SessionRecord>, SessionError> {
+        let now = Utc::now().timestamp();
+        let mut sessions: Vec<SessionRecord> = self
+            .sessions
+            .read()
+            .await
+            .values()
+            .filter(|session| session.belongs_to(provider_id, subject) && session.is_live(now))
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| session.claims.iat);
+        Ok(sessions)
+    }
+
+    async fn purge_expired(&self, now: i64) -> Result<usize, SessionError> {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|_, session| session.is_live(now));
+        Ok(before - sessions.len())
+    }
+
+    async fn count_active(&self, now: i64) -> Result<usize, SessionError> {
+        let sessions = self.sessions.read().await;
+        Ok(sessions
+            .values()
+            .filter(|session| session.is_live(now))
+            .count())
+    }
+
+    async fn insert_refresh(&self, hash: &str, record: &RefreshRecord) -> Result<(), SessionError> {
+        let mut refresh_tokens = self.refresh_tokens.write().await;
+        refresh_tokens
+            .families
+            .entry(record.family.clone())
+            .or_default()
+            .insert(hash.to_string());
+        refresh_tokens
+            .records
+            .insert(hash.to_string(), record.clone());
+        Ok(())
+    }
+
+    async fn get_refresh(&self, hash: &str) -> Result<Option<RefreshRecord>, SessionError> {
+        let now = Utc::now().timestamp();
+        Ok(self
+            .refresh_tokens
+            .read()
+            .await
+            .records
+            .get(hash)
+            .filter(|record| record.is_live(now))
+            .cloned())
+    }
+
+    async fn rotate_refresh(&self, hash: &str) -> Result<Option<RefreshRecord>, SessionError> {
+        let now = Utc::now().timestamp();
+        let mut refresh_tokens = self.refresh_tokens.write().await;
+        Ok(refresh_tokens
+            .records
+            .get_mut(hash)
+            .filter(|record| record.is_live(now))
+            .map(|record| {
+                let before = record.clone();
+                record.rotated = true;
+                before
+            }))
+    }
+
+    async fn remove_refresh_family(
+        &self,
+        family: &str,
+    ) -> Result<Vec<RefreshRecord>, SessionError> {
+        Ok(self.refresh_tokens.write().await.remove_family(family))
+    }
+
+    async fn remove_refresh_by_subject(
+        &self,
+        provider_id: &str,
+        subject: &str,
+    ) -> Result<Vec<RefreshRecord>, SessionError> {
+        let mut refresh_tokens = self.refresh_tokens.write().await;
+        let families: HashSet<String> = refresh_tokens
+            .records
+            .values()
+            .filter(|record| record.session.belongs_to(provider_id, subject))
+            .map(|record| record.family.clone())
+            .collect();
+        Ok(families
+            .iter()
+            .flat_map(|family| refresh_tokens.remove_family(family))
+            .collect())
+    }
+
+    async fn purge_expired_refresh(&self, now: i64) -> Result<usize, SessionError> {
+        let mut refresh_tokens = self.refresh_tokens.write().await;
+        let RefreshTokens { records, families } = &mut *refresh_tokens;
+        let before = records.len();
+        records.retain(|_, record| record.is_live(now));
+        families.retain(|_, hashes| {
+            hashes.retain(|hash| records.contains_key(hash));
+            !hashes.is_empty()
+        });
+        Ok(before - records.len())
+    }
+}
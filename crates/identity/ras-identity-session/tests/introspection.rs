@@ -0,0 +1,188 @@
+//! Services without the signing key asking about and revoking tokens.
+#![cfg(feature = "introspection")]
+
+use axum::http::HeaderName;
+use ras_identity_local::LocalUserProvider;
+use ras_identity_session::{
+    IntrospectionClientAuth, SessionConfig, SessionError, SessionService, introspection_router,
+};
+use reqwest::StatusCode;
+use std::sync::Arc;
+
+const TEST_SECRET: &str = "test-secret-that-is-long-enough-for-hs256";
+const CLIENT_SECRET: &str = "introspection-client-secret-of-32-bytes";
+
+async fn session_service() -> Arc<SessionService> {
+    let service = SessionService::new(SessionConfig::new(TEST_SECRET).unwrap()).unwrap();
+    let local_provider = LocalUserProvider::default();
+    local_provider
+        .add_user("alice".to_string(), "password123".to_string(), None, None)
+        .await
+        .unwrap();
+    service.register_provider(Box::new(local_provider)).await;
+    Arc::new(service)
+}
+
+fn alice_login() -> serde_json::Value {
+    serde_json::json!({ "username": "alice", "password": "password123" })
+}
+
+/// Serve the endpoints on a random port, returning their base URL
+async fn serve(service: Arc<SessionService>, client_auth: IntrospectionClientAuth) -> String {
+    let router = introspection_router(service, client_auth).unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    format!("http://{}", address)
+}
+
+async fn post(url: &str, form: &[(&str, &str)]) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(url)
+        .bearer_auth(CLIENT_SECRET)
+        .form(form)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn introspect(base: &str, token: &str) -> serde_json::Value {
+    let response = post(&format!("{base}/introspect"), &[("token", token)]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_inactive_before_they_expire() {
+    let service = session_service().await;
+    let base = serve(
+        service.clone(),
+        IntrospectionClientAuth::SharedSecret(CLIENT_SECRET.to_string()),
+    )
+    .await;
+    let token = service.begin_session("local", alice_login()).await.unwrap();
+
+    let active = introspect(&base, &token).await;
+    assert_eq!(active["active"], true);
+    assert_eq!(active["sub"], "alice");
+    assert!(active["exp"].as_i64().unwrap() > chrono::Utc::now().timestamp());
+    assert_eq!(active["permissions"], serde_json::json!([]));
+
+    let response = post(&format!("{base}/revoke"), &[("token", &token)]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        introspect(&base, &token).await,
+        serde_json::json!({ "active": false })
+    );
+    assert_eq!(introspect(&base, "not-a-token").await["active"], false);
+}
+
+#[tokio::test]
+async fn revoke_takes_session_ids_and_refresh_tokens() {
+    let service = session_service().await;
+    let base = serve(
+        service.clone(),
+        IntrospectionClientAuth::SharedSecret(CLIENT_SECRET.to_string()),
+    )
+    .await;
+
+    let token = service.begin_session("local", alice_login()).await.unwrap();
+    let jti = service.jti(&token).unwrap();
+    let response = post(&format!("{base}/revoke"), &[("jti", &jti)]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(introspect(&base, &token).await["active"], false);
+
+    let pair = service
+        .begin_session_with_refresh("local", alice_login(), None)
+        .await
+        .unwrap();
+    let response = post(&format!("{base}/revoke"), &[("token", &pair.refresh_token)]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(service.refresh(&pair.refresh_token).await.is_err());
+    assert_eq!(introspect(&base, &pair.access_token).await["active"], false);
+
+    // Unknown tokens are revoked as far as the caller is concerned
+    let response = post(&format!("{base}/revoke"), &[("token", "unknown")]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = post(&format!("{base}/revoke"), &[]).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn callers_must_authenticate() {
+    let service = session_service().await;
+    let base = serve(
+        service.clone(),
+        IntrospectionClientAuth::SharedSecret(CLIENT_SECRET.to_string()),
+    )
+    .await;
+    let token = service.begin_session("local", alice_login()).await.unwrap();
+    let client = reqwest::Client::new();
+
+    for request in [
+        client.post(format!("{base}/introspect")),
+        client
+            .post(format!("{base}/introspect"))
+            .bearer_auth("wrong-secret-that-is-also-32-bytes-long"),
+        client.post(format!("{base}/revoke")).bearer_auth(""),
+    ] {
+        let response = request.form(&[("token", &token)]).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "invalid_client");
+    }
+    assert_eq!(introspect(&base, &token).await["active"], true);
+}
+
+#[tokio::test]
+async fn client_certificates_are_checked_from_the_proxy_header() {
+    let service = session_service().await;
+    let header = HeaderName::from_static("x-client-cert-subject");
+    let base = serve(
+        service.clone(),
+        IntrospectionClientAuth::ClientCertificate {
+            header: header.clone(),
+            subjects: vec!["CN=billing".to_string()],
+        },
+    )
+    .await;
+    let token = service.begin_session("local", alice_login()).await.unwrap();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{base}/introspect"))
+        .header(header.clone(), "CN=billing")
+        .form(&[("token", &token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .post(format!("{base}/introspect"))
+        .header(header, "CN=reports")
+        .form(&[("token", &token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn weak_client_auth_is_refused() {
+    let service = session_service().await;
+    for client_auth in [
+        IntrospectionClientAuth::SharedSecret("short".to_string()),
+        IntrospectionClientAuth::ClientCertificate {
+            header: HeaderName::from_static("x-client-cert-subject"),
+            subjects: Vec::new(),
+        },
+    ] {
+        assert!(matches!(
+            introspection_router(service.clone(), client_auth),
+            Err(SessionError::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,180 @@
+//! Validating tokens on a service that only has the issuer's published keys.
+#![cfg(feature = "jwks")]
+
+use chrono::Duration;
+use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+use ras_auth_core::{AuthError, AuthProvider};
+use ras_identity_local::LocalUserProvider;
+use ras_identity_session::{
+    JwtAuthProvider, KeySource, KeyStore, RemoteJwks, SessionConfig, SessionService, SigningKey,
+    jwks_router,
+};
+use std::sync::Arc;
+
+async fn issuer(keys: Arc<KeyStore>) -> Arc<SessionService> {
+    issuer_for(keys, &[]).await
+}
+
+async fn issuer_for(keys: Arc<KeyStore>, audience: &[&str]) -> Arc<SessionService> {
+    let mut config =
+        SessionConfig::with_key_source(Algorithm::ES256, KeySource::KeyStore(keys)).unwrap();
+    config.audience = audience.iter().map(|aud| aud.to_string()).collect();
+    let service = SessionService::new(config).unwrap();
+    let local_provider = LocalUserProvider::default();
+    local_provider
+        .add_user("alice".to_string(), "password123".to_string(), None, None)
+        .await
+        .unwrap();
+    service.register_provider(Box::new(local_provider)).await;
+    Arc::new(service)
+}
+
+async fn log_in(service: &SessionService) -> String {
+    service
+        .begin_session(
+            "local",
+            serde_json::json!({ "username": "alice", "password": "password123" }),
+        )
+        .await
+        .unwrap()
+}
+
+/// Serve the issuer's keys on a random port, returning their URL
+async fn publish(service: Arc<SessionService>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, jwks_router(service)).await.unwrap();
+    });
+    format!("http://{}/.well-known/jwks.json", address)
+}
+
+#[tokio::test]
+async fn resource_servers_validate_with_published_keys() {
+    let keys = Arc::new(KeyStore::new(
+        SigningKey::generate(Algorithm::ES256).unwrap(),
+    ));
+    let issuer = issuer(keys.clone()).await;
+    let url = publish(issuer.clone()).await;
+
+    let published: serde_json::Value = reqwest::get(&url).await.unwrap().json().await.unwrap();
+    assert_eq!(published["keys"][0]["kty"], "EC");
+    assert!(published["keys"][0].get("d").is_none());
+
+    let jwks = Arc::new(RemoteJwks::new(url).with_min_refresh_interval(std::time::Duration::ZERO));
+    let provider = JwtAuthProvider::with_jwks(jwks);
+    let first = log_in(&issuer).await;
+    let user = provider.authenticate(first.clone()).await.unwrap();
+    assert_eq!(user.user_id, "alice");
+
+    // A token signed with a rotated-in key makes the resource server fetch again
+    keys.rotate(
+        SigningKey::generate(Algorithm::ES256).unwrap(),
+        Duration::hours(1),
+    );
+    let second = log_in(&issuer).await;
+    assert!(provider.authenticate(second).await.is_ok());
+    assert!(provider.authenticate(first).await.is_ok());
+
+    assert!(matches!(
+        provider.authenticate("not-a-token".to_string()).await,
+        Err(AuthError::InvalidToken)
+    ));
+}
+
+#[tokio::test]
+async fn unknown_keys_are_fetched_at_most_once_per_interval() {
+    let keys = Arc::new(KeyStore::new(
+        SigningKey::generate(Algorithm::ES256).unwrap(),
+    ));
+    let issuer = issuer(keys.clone()).await;
+    let jwks = RemoteJwks::new(publish(issuer.clone()).await);
+
+    let first = log_in(&issuer).await;
+    assert!(jwks.verify(&first).await.is_ok());
+
+    // Fetched too recently to look for the new key
+    keys.rotate(
+        SigningKey::generate(Algorithm::ES256).unwrap(),
+        Duration::hours(1),
+    );
+    let second = log_in(&issuer).await;
+    assert!(jwks.verify(&second).await.is_err());
+}
+
+#[tokio::test]
+async fn unreachable_key_sets_are_internal_errors() {
+    let keys = Arc::new(KeyStore::new(
+        SigningKey::generate(Algorithm::ES256).unwrap(),
+    ));
+    let issuer = issuer(keys).await;
+    let provider = JwtAuthProvider::with_jwks(Arc::new(RemoteJwks::new(
+        "http://127.0.0.1:9/.well-known/jwks.json",
+    )));
+
+    assert!(matches!(
+        provider.authenticate(log_in(&issuer).await).await,
+        Err(AuthError::Internal(_))
+    ));
+}
+
+#[tokio::test]
+async fn resource_servers_check_their_own_audience() {
+    let keys = Arc::new(KeyStore::new(
+        SigningKey::generate(Algorithm::ES256).unwrap(),
+    ));
+    let issuer = issuer_for(keys, &["billing"]).await;
+    let url = publish(issuer.clone()).await;
+    let jwks = Arc::new(RemoteJwks::new(url));
+    let token = log_in(&issuer).await;
+
+    let billing = JwtAuthProvider::with_jwks(jwks.clone())
+        .with_audience(["billing"])
+        .unwrap();
+    assert!(billing.authenticate(token.clone()).await.is_ok());
+
+    let search = JwtAuthProvider::with_jwks(jwks)
+        .with_audience(["search"])
+        .unwrap();
+    assert!(matches!(
+        search.authenticate(token).await,
+        Err(AuthError::InvalidToken)
+    ));
+}
+
+#[tokio::test]
+async fn keys_without_alg_take_the_tokens_if_it_suits_them() {
+    let keys = Arc::new(KeyStore::new(
+        SigningKey::generate(Algorithm::ES256).unwrap(),
+    ));
+    let issuer = issuer(keys).await;
+    let mut published = issuer.jwks();
+    for key in &mut published.keys {
+        key.common.key_algorithm = None;
+    }
+    let kid = published.keys[0].common.key_id.clone().unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let router = axum::Router::new().route(
+            "/.well-known/jwks.json",
+            axum::routing::get(move || async move { axum::Json(published) }),
+        );
+        axum::serve(listener, router).await.unwrap();
+    });
+    let jwks = RemoteJwks::new(format!("http://{}/.well-known/jwks.json", address));
+
+    let claims = jwks.verify(&log_in(&issuer).await).await.unwrap();
+    assert_eq!(claims.sub, "alice");
+
+    // A token naming an algorithm the EC key can't be used with is refused
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some(kid);
+    let forged = encode(
+        &header,
+        &serde_json::json!({ "sub": "mallory", "exp": u32::MAX }),
+        &EncodingKey::from_secret(b"guessed"),
+    )
+    .unwrap();
+    assert!(jwks.verify(&forged).await.is_err());
+}
@@ -0,0 +1,382 @@
+//! The behaviour every `SessionStore` must share.
+//!
+//! Each backend runs the same checks. The Redis store is checked with the
+//! `redis-store` feature when `REDIS_URL` points at a Redis 7 server.
+
+use chrono::Utc;
+use ras_identity_core::AuthSource;
+use ras_identity_session::{
+    BindingInfo, JwtClaims, MemorySessionStore, RefreshRecord, SessionRecord, SessionStore,
+};
+use std::collections::HashSet;
+
+fn claims(jti: &str, sub: &str, iat_offset: i64, ttl: i64) -> JwtClaims {
+    let now = Utc::now().timestamp();
+    JwtClaims {
+        sub: sub.to_string(),
+        exp: now + ttl,
+        iat: now + iat_offset,
+        nbf: None,
+        jti: jti.to_string(),
+        iss: Some("https://auth.example.com".to_string()),
+        aud: vec!["api".to_string()],
+        provider_id: "local".to_string(),
+        email: Some(format!("{sub}@example.com")),
+        display_name: None,
+        permissions: ["read", "write"].iter().map(|p| p.to_string()).collect(),
+        metadata: Some(serde_json::json!({ "team": "blue" })),
+        amr: vec!["pwd".to_string()],
+        custom: serde_json::Map::from_iter([("tenant_id".to_string(), "acme".into())]),
+    }
+}
+
+fn session(jti: &str, sub: &str, iat_offset: i64, ttl: i64) -> SessionRecord {
+    SessionRecord::new(claims(jti, sub, iat_offset, ttl), None)
+}
+
+fn refresh(family: &str, jti: &str, sub: &str, ttl: i64) -> RefreshRecord {
+    RefreshRecord {
+        family: family.to_string(),
+        session: session(jti, sub, 0, 900),
+        expires_at: Utc::now().timestamp() + ttl,
+        rotated: false,
+    }
+}
+
+fn refresh_jtis(records: &[RefreshRecord]) -> Vec<&str> {
+    let mut jtis: Vec<&str> = records
+        .iter()
+        .map(|record| record.session.claims.jti.as_str())
+        .collect();
+    jtis.sort();
+    jtis
+}
+
+fn jtis(sessions: &[SessionRecord]) -> Vec<&str> {
+    sessions
+        .iter()
+        .map(|session| session.claims.jti.as_str())
+        .collect()
+}
+
+async fn conformance(store: &dyn SessionStore) {
+    // Sessions are kept whole
+    let mut first = SessionRecord::new(
+        claims("first", "alice", -20, 3600),
+        Some(AuthSource {
+            ip: Some("203.0.113.7".parse().unwrap()),
+            user_agent: Some("curl/8.0".to_string()),
+        }),
+    );
+    first.binding = Some(BindingInfo::new(
+        Some("curl/8.0"),
+        Some("203.0.113.7".parse().unwrap()),
+        None,
+    ));
+    store.insert(&first).await.unwrap();
+    let stored = store.get("first").await.unwrap().unwrap();
+    assert_eq!(stored.claims.sub, "alice");
+    assert_eq!(stored.claims.permissions, first.claims.permissions);
+    assert_eq!(stored.claims.metadata, first.claims.metadata);
+    assert_eq!(stored.claims.amr, first.claims.amr);
+    assert_eq!(stored.claims.aud, first.claims.aud);
+    assert_eq!(stored.claims.custom, first.claims.custom);
+    assert_eq!(stored.source, first.source);
+    assert_eq!(stored.binding, first.binding);
+    assert_eq!(stored.created_at, first.created_at);
+    assert!(store.get("unknown").await.unwrap().is_none());
+
+    // Stored sessions are updated in place, others aren't stored by updating
+    let mut extended = stored.clone();
+    extended.last_seen_at = Utc::now().timestamp();
+    assert!(store.update(&extended).await.unwrap());
+    let stored = store.get("first").await.unwrap().unwrap();
+    assert_eq!(stored.last_seen_at, extended.last_seen_at);
+    assert!(
+        !store
+            .update(&session("unknown", "alice", 0, 3600))
+            .await
+            .unwrap()
+    );
+    assert!(store.get("unknown").await.unwrap().is_none());
+
+    // Removing returns the session, once
+    assert_eq!(
+        store
+            .remove("first")
+            .await
+            .unwrap()
+            .map(|session| session.claims.jti),
+        Some("first".to_string())
+    );
+    assert!(store.remove("first").await.unwrap().is_none());
+    assert!(store.get("first").await.unwrap().is_none());
+
+    // Sessions are listed by provider and subject, oldest first
+    store
+        .insert(&session("newer", "alice", -5, 3600))
+        .await
+        .unwrap();
+    store
+        .insert(&session("older", "alice", -10, 3600))
+        .await
+        .unwrap();
+    store
+        .insert(&session("bobs", "bob", 0, 3600))
+        .await
+        .unwrap();
+    let mut elsewhere = session("elsewhere", "alice", 0, 3600);
+    elsewhere.claims.provider_id = "oauth2".to_string();
+    store.insert(&elsewhere).await.unwrap();
+    let alices = store.list_by_subject("local", "alice").await.unwrap();
+    assert_eq!(jtis(&alices), ["older", "newer"]);
+    assert!(
+        store
+            .list_by_subject("local", "carol")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // A removed session is no longer listed
+    store.remove("newer").await.unwrap();
+    assert_eq!(
+        jtis(&store.list_by_subject("local", "alice").await.unwrap()),
+        ["older"]
+    );
+
+    // Removing by subject leaves other subjects and providers alone
+    store
+        .insert(&session("again", "alice", 0, 3600))
+        .await
+        .unwrap();
+    let removed = store.remove_by_subject("local", "alice").await.unwrap();
+    assert_eq!(jtis(&removed), ["older", "again"]);
+    assert!(
+        store
+            .list_by_subject("local", "alice")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(store.get("older").await.unwrap().is_none());
+    assert!(store.get("bobs").await.unwrap().is_some());
+    assert_eq!(
+        jtis(&store.list_by_subject("oauth2", "alice").await.unwrap()),
+        ["elsewhere"]
+    );
+
+    // Expired sessions are never returned, purged or not
+    store
+        .insert(&session("expired", "bob", -20, -10))
+        .await
+        .unwrap();
+    assert!(store.get("expired").await.unwrap().is_none());
+    let mut idle = session("idle", "bob", -20, 3600);
+    idle.expires_at = Utc::now().timestamp() - 1;
+    store.insert(&idle).await.unwrap();
+    assert!(store.get("idle").await.unwrap().is_none());
+    assert_eq!(
+        jtis(&store.list_by_subject("local", "bob").await.unwrap()),
+        ["bobs"]
+    );
+    store.purge_expired(Utc::now().timestamp()).await.unwrap();
+    assert!(store.get("expired").await.unwrap().is_none());
+    assert!(store.get("bobs").await.unwrap().is_some());
+
+    // Only sessions that haven't expired are counted
+    assert_eq!(store.count_active(Utc::now().timestamp()).await.unwrap(), 2);
+    assert_eq!(
+        store
+            .count_active(Utc::now().timestamp() + 7200)
+            .await
+            .unwrap(),
+        0
+    );
+
+    refresh_conformance(store).await;
+}
+
+async fn refresh_conformance(store: &dyn SessionStore) {
+    // Refresh tokens are kept whole under their hash
+    let first = refresh("family-a", "access-1", "alice", 3600);
+    store.insert_refresh("hash-1", &first).await.unwrap();
+    let stored = store.get_refresh("hash-1").await.unwrap().unwrap();
+    assert_eq!(stored.family, "family-a");
+    assert_eq!(stored.session.claims.jti, "access-1");
+    assert_eq!(
+        stored.session.claims.permissions,
+        first.session.claims.permissions
+    );
+    assert_eq!(stored.expires_at, first.expires_at);
+    assert!(!stored.rotated);
+    assert!(store.get_refresh("unknown").await.unwrap().is_none());
+
+    // Only the first rotation sees the token unrotated
+    let rotated = store.rotate_refresh("hash-1").await.unwrap().unwrap();
+    assert!(!rotated.rotated);
+    assert!(
+        store
+            .rotate_refresh("hash-1")
+            .await
+            .unwrap()
+            .unwrap()
+            .rotated
+    );
+    assert!(store.get_refresh("hash-1").await.unwrap().unwrap().rotated);
+    assert!(store.rotate_refresh("unknown").await.unwrap().is_none());
+
+    // Removing a family removes every token of it, and nothing else
+    store
+        .insert_refresh("hash-2", &refresh("family-a", "access-2", "alice", 3600))
+        .await
+        .unwrap();
+    store
+        .insert_refresh("hash-3", &refresh("family-b", "access-3", "alice", 3600))
+        .await
+        .unwrap();
+    let removed = store.remove_refresh_family("family-a").await.unwrap();
+    assert_eq!(refresh_jtis(&removed), ["access-1", "access-2"]);
+    assert!(store.get_refresh("hash-1").await.unwrap().is_none());
+    assert!(store.get_refresh("hash-2").await.unwrap().is_none());
+    assert!(store.get_refresh("hash-3").await.unwrap().is_some());
+    assert!(
+        store
+            .remove_refresh_family("family-a")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Removing by subject removes the subject's families, and nothing else
+    store
+        .insert_refresh("hash-4", &refresh("family-c", "access-4", "alice", 3600))
+        .await
+        .unwrap();
+    store
+        .insert_refresh("hash-5", &refresh("family-d", "access-5", "bob", 3600))
+        .await
+        .unwrap();
+    let removed = store
+        .remove_refresh_by_subject("local", "alice")
+        .await
+        .unwrap();
+    assert_eq!(refresh_jtis(&removed), ["access-3", "access-4"]);
+    assert!(store.get_refresh("hash-3").await.unwrap().is_none());
+    assert!(store.get_refresh("hash-4").await.unwrap().is_none());
+    assert!(store.get_refresh("hash-5").await.unwrap().is_some());
+
+    // Expired refresh tokens are never returned, purged or not
+    store
+        .insert_refresh("hash-6", &refresh("family-d", "access-6", "bob", -10))
+        .await
+        .unwrap();
+    assert!(store.get_refresh("hash-6").await.unwrap().is_none());
+    assert!(store.rotate_refresh("hash-6").await.unwrap().is_none());
+    store
+        .purge_expired_refresh(Utc::now().timestamp())
+        .await
+        .unwrap();
+    assert!(store.get_refresh("hash-6").await.unwrap().is_none());
+    assert!(store.get_refresh("hash-5").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn memory_store() {
+    let store = MemorySessionStore::new();
+    conformance(&store).await;
+}
+
+#[tokio::test]
+async fn memory_store_purges_expired_sessions() {
+    let store = MemorySessionStore::new();
+    store
+        .insert(&session("live", "alice", 0, 3600))
+        .await
+        .unwrap();
+    store
+        .insert(&session("expired", "alice", -20, -10))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store.purge_expired(Utc::now().timestamp()).await.unwrap(),
+        1
+    );
+    assert_eq!(
+        store.purge_expired(Utc::now().timestamp()).await.unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn memory_store_purges_expired_refresh_tokens() {
+    let store = MemorySessionStore::new();
+    store
+        .insert_refresh("live", &refresh("family", "access-1", "alice", 3600))
+        .await
+        .unwrap();
+    store
+        .insert_refresh("expired", &refresh("family", "access-2", "alice", -10))
+        .await
+        .unwrap();
+
+    let now = Utc::now().timestamp();
+    assert_eq!(store.purge_expired_refresh(now).await.unwrap(), 1);
+    assert_eq!(store.purge_expired_refresh(now).await.unwrap(), 0);
+    let removed = store.remove_refresh_family("family").await.unwrap();
+    assert_eq!(refresh_jtis(&removed), ["access-1"]);
+}
+
+#[cfg(feature = "redis-store")]
+#[tokio::test]
+async fn redis_store() {
+    let Ok(url) = std::env::var("REDIS_URL") else {
+        eprintln!("REDIS_URL is not set, skipping the Redis session store");
+        return;
+    };
+    let store = ras_identity_session::RedisSessionStore::connect(&url)
+        .await
+        .unwrap()
+        .with_prefix(format!("ras-test-{}", uuid::Uuid::new_v4()));
+    conformance(&store).await;
+}
+
+#[test]
+fn claims_serialize_stably() {
+    let claims = JwtClaims {
+        sub: "alice".to_string(),
+        exp: 1_700_003_600,
+        iat: 1_700_000_000,
+        nbf: None,
+        jti: "session".to_string(),
+        iss: None,
+        aud: Vec::new(),
+        provider_id: "local".to_string(),
+        email: None,
+        display_name: Some("Alice".to_string()),
+        permissions: ["write", "admin", "read"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<HashSet<_>>(),
+        metadata: None,
+        amr: Vec::new(),
+        custom: Default::default(),
+    };
+
+    assert_eq!(
+        serde_json::to_string(&claims).unwrap(),
+        r#"{"sub":"alice","exp":1700003600,"iat":1700000000,"jti":"session","provider_id":"local","email":null,"display_name":"Alice","permissions":["admin","read","write"],"metadata":null}"#
+    );
+
+    // Custom claims sit alongside the others, and come back as custom claims
+    let claims = JwtClaims {
+        custom: serde_json::Map::from_iter([("locale".to_string(), "de-CH".into())]),
+        ..claims
+    };
+    let json = serde_json::to_string(&claims).unwrap();
+    assert!(json.ends_with(r#""metadata":null,"locale":"de-CH"}"#));
+    let parsed: JwtClaims = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.custom, claims.custom);
+    assert_eq!(parsed.permissions, claims.permissions);
+}
@@ -0,0 +1,90 @@
+//! Example driving a REST and a JSON-RPC service from one observability
+//! configuration
+
+use axum::Router;
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_observability_core::{ObservabilityBuilder, RequestContext};
+use ras_observability_otel::OtelSetupBuilder;
+use ras_rest_core::{RestResponse, RestResult};
+use ras_rest_macro::rest_service;
+use tracing::info;
+
+rest_service!({
+    service_name: Catalog,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        GET UNAUTHORIZED products() -> Vec<String>,
+    ]
+});
+
+struct CatalogImpl;
+
+#[async_trait::async_trait]
+impl CatalogTrait for CatalogImpl {
+    async fn get_products(&self) -> RestResult<Vec<String>> {
+        Ok(RestResponse::ok(vec!["kettle".to_string()]))
+    }
+}
+
+jsonrpc_service!({
+    service_name: Checkout,
+    methods: [
+        UNAUTHORIZED order(String) -> u32,
+    ]
+});
+
+struct CheckoutImpl;
+
+impl CheckoutTrait for CheckoutImpl {
+    async fn order(
+        &self,
+        _product: String,
+    ) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(1)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt::init();
+
+    let otel = OtelSetupBuilder::new("shop").build()?;
+
+    // One configuration: OpenTelemetry metrics, a usage log and the tenant
+    // named by the caller's `X-Tenant` header
+    let config = ObservabilityBuilder::new()
+        .with_service_metrics(otel.metrics())
+        .with_usage_tracker(|_headers, user, context: RequestContext| async move {
+            let user = user.map(|user| user.user_id);
+            info!(protocol = %context.protocol, method = %context.method, ?user, "request");
+        })
+        .with_tenant_extractor(|headers, _user, _context| {
+            Some(headers.get("x-tenant")?.to_str().ok()?.to_string())
+        })
+        .build();
+
+    // Clones share the trackers
+    let catalog = CatalogBuilder::new(CatalogImpl)
+        .with_observability_config(config.clone())
+        .build();
+    let checkout = CheckoutBuilder::new(CheckoutImpl)
+        .with_observability_config(config)
+        .build()?;
+    let app = Router::new()
+        .merge(catalog)
+        .merge(checkout)
+        .merge(otel.metrics_router());
+
+    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
+
+    println!("Shop running on http://localhost:3000");
+    println!("REST: http://localhost:3000/api/products");
+    println!("JSON-RPC: http://localhost:3000/rpc");
+    println!("Metrics: http://localhost:3000/metrics");
+
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
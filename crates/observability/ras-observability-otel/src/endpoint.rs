@@ -0,0 +1,220 @@
+//! The `/metrics` endpoint: negotiating the format and compression, checking
+//! the scraper's credentials, and sharing one scrape between concurrent
+//! scrapers.
+
+use std::io::Write;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, HeaderName, StatusCode, header},
+    response::Response,
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
+use flate2::{Compression, write::GzEncoder};
+use prometheus::{Encoder, Registry, TEXT_FORMAT, TextEncoder};
+use subtle::ConstantTimeEq;
+
+use crate::OtelMetrics;
+use crate::openmetrics::{self, DurationExemplars, OPENMETRICS_CONTENT_TYPE};
+
+/// Credentials scrapers must present to the metrics endpoint, see
+/// [`OtelSetup::metrics_router_with_auth`](crate::OtelSetup::metrics_router_with_auth)
+#[derive(Clone)]
+pub struct MetricsAuth {
+    scheme: &'static str,
+    credentials: String,
+}
+
+impl MetricsAuth {
+    /// Require `Authorization: Bearer <token>`
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Self {
+            scheme: "Bearer",
+            credentials: token.into(),
+        }
+    }
+
+    /// Require HTTP basic authentication with `username` and `password`
+    pub fn basic(username: impl AsRef<str>, password: impl AsRef<str>) -> Self {
+        let credentials = format!("{}:{}", username.as_ref(), password.as_ref());
+        Self {
+            scheme: "Basic",
+            credentials: BASE64_STANDARD.encode(credentials),
+        }
+    }
+
+    fn authorizes(&self, headers: &HeaderMap) -> bool {
+        let Some((scheme, credentials)) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().split_once(' '))
+        else {
+            return false;
+        };
+        scheme.eq_ignore_ascii_case(self.scheme)
+            && bool::from(
+                credentials
+                    .trim()
+                    .as_bytes()
+                    .ct_eq(self.credentials.as_bytes()),
+            )
+    }
+}
+
+impl std::fmt::Debug for MetricsAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricsAuth")
+            .field("scheme", &self.scheme)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What the metrics endpoint serves
+#[derive(Clone)]
+pub(crate) struct MetricsState {
+    pub(crate) registry: Arc<Registry>,
+    pub(crate) exemplars: Arc<DurationExemplars>,
+    pub(crate) metrics: Arc<OtelMetrics>,
+    pub(crate) auth: Option<MetricsAuth>,
+    pub(crate) prometheus_scrapes: Arc<SingleFlight>,
+    pub(crate) openmetrics_scrapes: Arc<SingleFlight>,
+}
+
+/// An encoded exposition, and its compressed form once a scraper asks for it
+pub(crate) struct Scrape {
+    body: Bytes,
+    gzipped: OnceLock<Bytes>,
+    finished: Instant,
+}
+
+/// Lets scrapes of one format that arrive while another is being encoded
+/// share its result, rather than each gathering the registry again
+#[derive(Default)]
+pub(crate) struct SingleFlight {
+    latest: tokio::sync::Mutex<Option<Arc<Scrape>>>,
+}
+
+impl SingleFlight {
+    pub(crate) async fn scrape(
+        &self,
+        encode: impl FnOnce() -> Result<Vec<u8>, StatusCode>,
+    ) -> Result<Arc<Scrape>, StatusCode> {
+        let arrived = Instant::now();
+        let mut latest = self.latest.lock().await;
+        // Finished while this scrape waited for it
+        if let Some(scrape) = latest.as_ref().filter(|scrape| scrape.finished >= arrived) {
+            return Ok(scrape.clone());
+        }
+        let body = encode()?;
+        let scrape = Arc::new(Scrape {
+            body: Bytes::from(body),
+            gzipped: OnceLock::new(),
+            finished: Instant::now(),
+        });
+        *latest = Some(scrape.clone());
+        Ok(scrape)
+    }
+}
+
+/// Whether any value of the `name` headers, such as `Accept`, lists `token`
+/// without refusing it with `q=0`
+pub(crate) fn accepts(headers: &HeaderMap, name: HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|range| {
+            let mut parts = range.split(';');
+            let value = parts.next().unwrap_or_default().trim();
+            let refused = parts.any(|parameter| {
+                parameter.split_once('=').is_some_and(|(name, q)| {
+                    name.trim() == "q" && q.trim().parse::<f64>().is_ok_and(|q| q == 0.0)
+                })
+            });
+            value.eq_ignore_ascii_case(token) && !refused
+        })
+}
+
+fn gzip(body: &[u8]) -> Result<Bytes, StatusCode> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(body)
+        .and_then(|()| encoder.finish())
+        .map(Bytes::from)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Handler for Prometheus metrics endpoint
+pub(crate) async fn metrics_handler(
+    State(state): State<MetricsState>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    if let Some(auth) = &state.auth
+        && !auth.authorizes(&headers)
+    {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(
+                header::WWW_AUTHENTICATE,
+                format!("{} realm=\"metrics\"", auth.scheme),
+            )
+            .body(Body::empty())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let openmetrics = accepts(&headers, header::ACCEPT, "application/openmetrics-text");
+    let (format, content_type, scrapes) = if openmetrics {
+        (
+            "openmetrics",
+            OPENMETRICS_CONTENT_TYPE,
+            &state.openmetrics_scrapes,
+        )
+    } else {
+        ("prometheus", TEXT_FORMAT, &state.prometheus_scrapes)
+    };
+    let scrape = scrapes
+        .scrape(|| {
+            let started = Instant::now();
+            let metric_families = state.registry.gather();
+            let body = if openmetrics {
+                openmetrics::encode(&metric_families, &state.exemplars).into_bytes()
+            } else {
+                let mut buffer = Vec::new();
+                TextEncoder::new()
+                    .encode(&metric_families, &mut buffer)
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                buffer
+            };
+            state
+                .metrics
+                .record_scrape(format, started.elapsed(), body.len());
+            Ok(body)
+        })
+        .await?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, "no-store")
+        .header(header::VARY, "Accept, Accept-Encoding");
+    if accepts(&headers, header::ACCEPT_ENCODING, "gzip") {
+        let gzipped = match scrape.gzipped.get() {
+            Some(gzipped) => gzipped,
+            None => {
+                let gzipped = gzip(&scrape.body)?;
+                scrape.gzipped.get_or_init(|| gzipped)
+            }
+        };
+        return response
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::from(gzipped.clone()))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    response
+        .body(Body::from(scrape.body.clone()))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
@@ -1,97 +1,455 @@
 //! OpenTelemetry implementation for Rust Agent Stack observability
 //!
 //! This crate provides a production-ready OpenTelemetry implementation
-//! with Prometheus export support and standard metric definitions.
+//! with Prometheus export support and standard metric definitions. With the
+//! `otlp` feature, metrics can also be pushed to an OTLP collector.
 
 use async_trait::async_trait;
-use axum::{
-    Router, body::Body, extract::State, http::StatusCode, response::Response, routing::get,
-};
+use axum::{Router, http::HeaderMap, routing::get};
 use opentelemetry::{
     KeyValue, global,
-    metrics::{Counter, Histogram, Meter},
+    metrics::{Counter, Gauge, Histogram, Meter, MeterProvider, UpDownCounter},
 };
-use opentelemetry_sdk::metrics::SdkMeterProvider;
-use prometheus::{Encoder, Registry, TextEncoder};
-use ras_auth_core::AuthenticatedUser;
+use opentelemetry_sdk::{Resource, metrics::SdkMeterProvider};
+use prometheus::Registry;
+use ras_auth_core::{AuthenticatedUser, PrincipalKind};
 use ras_observability_core::{
-    MethodDurationTracker, RequestContext, ServiceMetrics, UsageTracker, extractors::user_agent,
+    InFlightGuard, MethodDurationTracker, Observability, RequestContext, RequestOutcome,
+    ServiceMetrics, TenantExtractor, UsageTracker, extractors::user_agent,
 };
+#[cfg(feature = "otlp")]
+use std::collections::HashMap;
 use std::{sync::Arc, time::Duration};
 use tracing::info;
 
+mod openmetrics;
+use openmetrics::{DEFAULT_DURATION_BOUNDARIES, DurationExemplars};
+
+mod endpoint;
+pub use endpoint::MetricsAuth;
+use endpoint::{MetricsState, metrics_handler};
+
+mod tenants;
+pub use tenants::TenantLabels;
+
+mod runtime;
+pub use runtime::DEFAULT_RUNTIME_METRICS_INTERVAL;
+use runtime::RuntimeSampler;
+
+/// Bucket boundaries of the request and response size histograms in bytes,
+/// from a small JSON object to a 16 MiB upload
+const PAYLOAD_SIZE_BOUNDARIES: [f64; 11] = [
+    64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 8388608.0,
+    16777216.0,
+];
+
+/// How [`OtelMetrics`] names its instruments and buckets method durations
+#[derive(Debug, Clone, Default)]
+pub struct MetricsOptions {
+    /// Prepended to the name of every instrument, such as `tasksvc_`, so
+    /// services scraped into one Prometheus keep their metrics apart
+    pub prefix: String,
+    /// Bucket boundaries of the method duration histogram in milliseconds,
+    /// or the SDK's defaults
+    pub duration_buckets: Option<Vec<f64>>,
+}
+
+impl MetricsOptions {
+    fn name(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+}
+
 /// Standard metrics for services using OpenTelemetry
 #[derive(Clone)]
 pub struct OtelMetrics {
     requests_started: Counter<u64>,
     requests_completed: Counter<u64>,
     method_duration: Histogram<f64>,
+    requests_in_flight: UpDownCounter<i64>,
+    request_size: Histogram<u64>,
+    response_size: Histogram<u64>,
+    active_connections: UpDownCounter<i64>,
+    connections_opened: Counter<u64>,
+    connections_closed: Counter<u64>,
+    messages_received: Counter<u64>,
+    messages_sent: Counter<u64>,
+    broadcast_fanout: Histogram<u64>,
+    sessions_started: Counter<u64>,
+    sessions_ended: Counter<u64>,
+    active_sessions: Gauge<u64>,
+    slow_requests: Counter<u64>,
+    tracker_errors: Counter<u64>,
+    permission_overlay_hits: Counter<u64>,
+    permission_overlay_denials: Counter<u64>,
+    scrape_duration: Histogram<f64>,
+    scrape_size: Histogram<u64>,
+    exemplars: Arc<DurationExemplars>,
+    exact_status_codes: bool,
+    tenants: TenantLabels,
 }
 
 impl OtelMetrics {
     /// Create new metrics with a given meter
     pub fn new(meter: &Meter) -> Self {
+        Self::with_options(meter, &MetricsOptions::default())
+    }
+
+    /// Create new metrics with a given meter, named and bucketed by `options`
+    pub fn with_options(meter: &Meter, options: &MetricsOptions) -> Self {
+        let mut method_duration = meter
+            .f64_histogram(options.name("method_duration_milliseconds"))
+            .with_description("Duration of method execution in milliseconds")
+            .with_unit("milliseconds");
+        if let Some(buckets) = &options.duration_buckets {
+            method_duration = method_duration.with_boundaries(buckets.clone());
+        }
+        let exemplars = DurationExemplars::new(
+            options.name("method_duration_milliseconds"),
+            options
+                .duration_buckets
+                .clone()
+                .unwrap_or_else(|| DEFAULT_DURATION_BOUNDARIES.to_vec()),
+        );
+
         Self {
             requests_started: meter
-                .u64_counter("requests_started")
+                .u64_counter(options.name("requests_started"))
                 .with_description("Total number of requests started")
                 .with_unit("requests")
                 .build(),
             requests_completed: meter
-                .u64_counter("requests_completed")
+                .u64_counter(options.name("requests_completed"))
                 .with_description("Total number of requests completed")
                 .with_unit("requests")
                 .build(),
-            method_duration: meter
-                .f64_histogram("method_duration_milliseconds")
-                .with_description("Duration of method execution in milliseconds")
+            method_duration: method_duration.build(),
+            requests_in_flight: meter
+                .i64_up_down_counter(options.name("requests_in_flight"))
+                .with_description("Number of requests currently executing")
+                .with_unit("requests")
+                .build(),
+            request_size: meter
+                .u64_histogram(options.name("request_size_bytes"))
+                .with_description("Size of request bodies in bytes")
+                .with_unit("bytes")
+                .with_boundaries(PAYLOAD_SIZE_BOUNDARIES.to_vec())
+                .build(),
+            response_size: meter
+                .u64_histogram(options.name("response_size_bytes"))
+                .with_description("Size of response bodies in bytes")
+                .with_unit("bytes")
+                .with_boundaries(PAYLOAD_SIZE_BOUNDARIES.to_vec())
+                .build(),
+            active_connections: meter
+                .i64_up_down_counter(options.name("websocket_active_connections"))
+                .with_description("Number of open WebSocket connections")
+                .with_unit("connections")
+                .build(),
+            connections_opened: meter
+                .u64_counter(options.name("websocket_connections_opened"))
+                .with_description("Total number of WebSocket connections opened")
+                .with_unit("connections")
+                .build(),
+            connections_closed: meter
+                .u64_counter(options.name("websocket_connections_closed"))
+                .with_description("Total number of WebSocket connections closed")
+                .with_unit("connections")
+                .build(),
+            messages_received: meter
+                .u64_counter(options.name("websocket_messages_received"))
+                .with_description("Total number of WebSocket messages received")
+                .with_unit("messages")
+                .build(),
+            messages_sent: meter
+                .u64_counter(options.name("websocket_messages_sent"))
+                .with_description("Total number of WebSocket messages sent")
+                .with_unit("messages")
+                .build(),
+            broadcast_fanout: meter
+                .u64_histogram(options.name("websocket_broadcast_fanout"))
+                .with_description("Number of connections each broadcast was sent to")
+                .with_unit("connections")
+                .build(),
+            sessions_started: meter
+                .u64_counter(options.name("sessions_started"))
+                .with_description("Total number of login sessions started")
+                .with_unit("sessions")
+                .build(),
+            sessions_ended: meter
+                .u64_counter(options.name("sessions_ended"))
+                .with_description("Total number of login sessions ended, by reason")
+                .with_unit("sessions")
+                .build(),
+            active_sessions: meter
+                .u64_gauge(options.name("active_sessions"))
+                .with_description("Number of active login sessions")
+                .with_unit("sessions")
+                .build(),
+            slow_requests: meter
+                .u64_counter(options.name("slow_requests"))
+                .with_description("Total number of requests slower than their threshold")
+                .with_unit("requests")
+                .build(),
+            tracker_errors: meter
+                .u64_counter(options.name("observability_tracker_errors"))
+                .with_description("Total number of tracker calls that panicked or timed out")
+                .with_unit("calls")
+                .build(),
+            permission_overlay_hits: meter
+                .u64_counter(options.name("permission_overlay_hits"))
+                .with_description("Total number of users whose permissions an overlay changed")
+                .with_unit("users")
+                .build(),
+            permission_overlay_denials: meter
+                .u64_counter(options.name("permission_overlay_denials"))
+                .with_description(
+                    "Total number of requests refused a permission an overlay revoked",
+                )
+                .with_unit("requests")
+                .build(),
+            scrape_duration: meter
+                .f64_histogram(options.name("metrics_scrape_duration_milliseconds"))
+                .with_description("Time taken to gather and encode the metrics for a scrape")
                 .with_unit("milliseconds")
                 .build(),
+            scrape_size: meter
+                .u64_histogram(options.name("metrics_scrape_size_bytes"))
+                .with_description(
+                    "Size of the encoded metrics served to a scrape, before compression",
+                )
+                .with_unit("bytes")
+                .with_boundaries(PAYLOAD_SIZE_BOUNDARIES.to_vec())
+                .build(),
+            exemplars: Arc::new(exemplars),
+            exact_status_codes: false,
+            tenants: TenantLabels::default(),
         }
     }
+
+    /// Label completed REST requests with their exact `status_code` as well as
+    /// their `status_class`
+    ///
+    /// Off by default: services answering with many distinct codes get a
+    /// series for each.
+    pub fn with_exact_status_codes(mut self) -> Self {
+        self.exact_status_codes = true;
+        self
+    }
+
+    /// Label the request counters of requests whose context names their
+    /// tenant, see [`RequestContext::with_tenant`], by the label `tenants`
+    /// gives it
+    ///
+    /// Every tenant is counted as `__other__` until allowed. Histograms are
+    /// never labelled by tenant.
+    pub fn with_tenant_labels(mut self, tenants: TenantLabels) -> Self {
+        self.tenants = tenants;
+        self
+    }
+
+    /// Record a scrape of the metrics endpoint in `format`
+    fn record_scrape(&self, format: &'static str, duration: Duration, bytes: usize) {
+        let attributes = [KeyValue::new("format", format)];
+        self.scrape_duration
+            .record(duration.as_secs_f64() * 1000.0, &attributes);
+        self.scrape_size.record(bytes as u64, &attributes);
+    }
+
+    /// The `tenant` attribute of requests whose context names their tenant
+    fn tenant_attribute(&self, context: &RequestContext) -> Option<KeyValue> {
+        context
+            .tenant()
+            .map(|tenant| KeyValue::new("tenant", self.tenants.label(tenant).into_owned()))
+    }
+}
+
+/// The `principal_kind` attribute of requests labelled with one; one of three
+/// values, so it is safe as a metric label
+fn principal_kind_attribute(context: &RequestContext) -> Option<KeyValue> {
+    context
+        .metadata
+        .get("principal_kind")
+        .map(|kind| KeyValue::new("principal_kind", kind.clone()))
+}
+
+/// Size histograms are labelled by method and protocol only
+fn payload_size_attributes(context: &RequestContext) -> [KeyValue; 2] {
+    [
+        KeyValue::new("method", context.method.clone()),
+        KeyValue::new("protocol", context.protocol.to_string()),
+    ]
 }
 
 impl ServiceMetrics for OtelMetrics {
     fn increment_requests_started(&self, context: &RequestContext) {
-        let attributes = vec![
+        let mut attributes = vec![
             KeyValue::new("method", context.method.clone()),
             KeyValue::new("protocol", context.protocol.to_string()),
         ];
+        attributes.extend(principal_kind_attribute(context));
+        attributes.extend(self.tenant_attribute(context));
 
         self.requests_started.add(1, &attributes);
     }
 
     fn increment_requests_completed(&self, context: &RequestContext, success: bool) {
-        let attributes = vec![
+        let mut attributes = vec![
             KeyValue::new("method", context.method.clone()),
             KeyValue::new("protocol", context.protocol.to_string()),
             KeyValue::new("success", success.to_string()),
+            KeyValue::new("outcome", RequestOutcome::of(context, success).as_str()),
         ];
+        // Set by generated services on failed requests; a small fixed set of values
+        if let Some(error_kind) = context.metadata.get("error_kind") {
+            attributes.push(KeyValue::new("error_kind", error_kind.clone()));
+        }
+        if let Some(error_code) = context.metadata.get("error_code") {
+            attributes.push(KeyValue::new("error_code", error_code.clone()));
+        }
+        // Set by generated REST services on every completed request
+        if let Some(status_class) = context.metadata.get("status_class") {
+            attributes.push(KeyValue::new("status_class", status_class.clone()));
+        }
+        if self.exact_status_codes
+            && let Some(status_code) = context.metadata.get("status_code")
+        {
+            attributes.push(KeyValue::new("status_code", status_code.clone()));
+        }
+        attributes.extend(principal_kind_attribute(context));
+        attributes.extend(self.tenant_attribute(context));
 
         self.requests_completed.add(1, &attributes);
     }
 
     fn record_method_duration(&self, context: &RequestContext, duration: Duration) {
-        // Duration metrics should only include method and protocol to avoid cardinality explosion
+        // Duration metrics should only include method, protocol and principal kind to avoid
+        // cardinality explosion
+        let mut attributes = vec![
+            KeyValue::new("method", context.method.clone()),
+            KeyValue::new("protocol", context.protocol.to_string()),
+        ];
+        attributes.extend(principal_kind_attribute(context));
+
+        let milliseconds = duration.as_secs_f64() * 1000.0;
+        self.method_duration.record(milliseconds, &attributes);
+        // Kept to link the bucket to a trace in the OpenMetrics exposition
+        if let Some(trace_id) = context.trace_id() {
+            self.exemplars.record(&attributes, milliseconds, trace_id);
+        }
+    }
+
+    fn in_flight_guard(&self, context: &RequestContext) -> InFlightGuard {
+        // Labelled by protocol only, so the gauge stays a handful of series
+        let attributes = [KeyValue::new("protocol", context.protocol.to_string())];
+        self.requests_in_flight.add(1, &attributes);
+        let requests_in_flight = self.requests_in_flight.clone();
+        InFlightGuard::new(move || requests_in_flight.add(-1, &attributes))
+    }
+
+    fn record_request_size(&self, context: &RequestContext, bytes: usize) {
+        self.request_size
+            .record(bytes as u64, &payload_size_attributes(context));
+    }
+
+    fn record_response_size(&self, context: &RequestContext, bytes: usize) {
+        self.response_size
+            .record(bytes as u64, &payload_size_attributes(context));
+    }
+
+    fn record_connection_opened(&self) {
+        self.connections_opened.add(1, &[]);
+        self.active_connections.add(1, &[]);
+    }
+
+    fn record_connection_closed(&self, close_code: Option<u16>, reason: &str) {
+        let close_code = close_code.map_or_else(|| "none".to_string(), |code| code.to_string());
+        self.connections_closed.add(
+            1,
+            &[
+                KeyValue::new("close_code", close_code),
+                KeyValue::new("reason", reason.to_string()),
+            ],
+        );
+        self.active_connections.add(-1, &[]);
+    }
+
+    fn record_message_received(&self) {
+        self.messages_received.add(1, &[]);
+    }
+
+    fn record_message_sent(&self) {
+        self.messages_sent.add(1, &[]);
+    }
+
+    fn record_broadcast_fanout(&self, context: &RequestContext, recipients: usize) {
         let attributes = vec![
             KeyValue::new("method", context.method.clone()),
             KeyValue::new("protocol", context.protocol.to_string()),
         ];
 
-        self.method_duration
-            .record(duration.as_secs_f64() * 1000.0, &attributes);
+        self.broadcast_fanout.record(recipients as u64, &attributes);
+    }
+
+    fn record_session_started(&self) {
+        self.sessions_started.add(1, &[]);
+    }
+
+    fn record_sessions_ended(&self, reason: &str, count: usize) {
+        self.sessions_ended
+            .add(count as u64, &[KeyValue::new("reason", reason.to_string())]);
+    }
+
+    fn record_active_sessions(&self, count: usize) {
+        self.active_sessions.record(count as u64, &[]);
+    }
+
+    fn record_tracker_error(&self, tracker: &str, error: &str) {
+        self.tracker_errors.add(
+            1,
+            &[
+                KeyValue::new("tracker", tracker.to_string()),
+                KeyValue::new("error", error.to_string()),
+            ],
+        );
+    }
+
+    fn record_slow_request(&self, context: &RequestContext) {
+        let mut attributes = vec![
+            KeyValue::new("method", context.method.clone()),
+            KeyValue::new("protocol", context.protocol.to_string()),
+        ];
+        attributes.extend(self.tenant_attribute(context));
+
+        self.slow_requests.add(1, &attributes);
+    }
+
+    fn record_permission_overlay_hit(&self) {
+        self.permission_overlay_hits.add(1, &[]);
+    }
+
+    fn record_permission_overlay_denial(&self) {
+        self.permission_overlay_denials.add(1, &[]);
     }
 }
 
 /// Usage tracker implementation that logs and records metrics
 #[derive(Clone)]
 pub struct OtelUsageTracker {
-    metrics: Arc<OtelMetrics>,
+    metrics: Option<Arc<OtelMetrics>>,
 }
 
 impl OtelUsageTracker {
     pub fn new(metrics: Arc<OtelMetrics>) -> Self {
-        Self { metrics }
+        Self {
+            metrics: Some(metrics),
+        }
+    }
+
+    /// A tracker that logs requests without counting them, for services that
+    /// report to the metrics themselves
+    pub fn logging_only() -> Self {
+        Self { metrics: None }
     }
 }
 
@@ -111,7 +469,11 @@ impl UsageTracker for OtelUsageTracker {
                 info!(
                     protocol = %context.protocol,
                     method = %context.method,
+                    route = context.route.as_deref(),
+                    client_ip = context.client_ip.map(tracing::field::display),
+                    request_id = context.request_id.as_deref(),
                     user_id = %u.user_id,
+                    principal_kind = %u.kind,
                     permissions = ?u.permissions,
                     user_agent = %user_agent,
                     "Request started"
@@ -121,27 +483,45 @@ impl UsageTracker for OtelUsageTracker {
                 info!(
                     protocol = %context.protocol,
                     method = %context.method,
+                    route = context.route.as_deref(),
+                    client_ip = context.client_ip.map(tracing::field::display),
+                    request_id = context.request_id.as_deref(),
                     user_id = "anonymous",
+                    principal_kind = "anonymous",
                     user_agent = %user_agent,
                     "Request started"
                 );
             }
         }
 
-        // Record metrics
-        self.metrics.increment_requests_started(context);
+        // Record metrics, labelled with the kind of caller
+        if let Some(metrics) = &self.metrics {
+            let context = context.clone().with_principal_kind(PrincipalKind::of(user));
+            metrics.increment_requests_started(&context);
+        }
     }
 }
 
 /// Method duration tracker implementation
+///
+/// Requests reported through `track_duration` count as successes; generated
+/// services report through `track_completion`, with how the request ended.
 #[derive(Clone)]
 pub struct OtelMethodDurationTracker {
-    metrics: Arc<OtelMetrics>,
+    metrics: Option<Arc<OtelMetrics>>,
 }
 
 impl OtelMethodDurationTracker {
     pub fn new(metrics: Arc<OtelMetrics>) -> Self {
-        Self { metrics }
+        Self {
+            metrics: Some(metrics),
+        }
+    }
+
+    /// A tracker that logs durations without recording them, for services
+    /// that report to the metrics themselves
+    pub fn logging_only() -> Self {
+        Self { metrics: None }
     }
 }
 
@@ -152,6 +532,17 @@ impl MethodDurationTracker for OtelMethodDurationTracker {
         context: &RequestContext,
         user: Option<&AuthenticatedUser>,
         duration: Duration,
+    ) {
+        self.track_completion(context, user, duration, RequestOutcome::Success)
+            .await;
+    }
+
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+        outcome: RequestOutcome,
     ) {
         // Log includes user for debugging, but metrics don't to avoid cardinality issues
         let user_id = user.map(|u| u.user_id.as_str()).unwrap_or("anonymous");
@@ -161,11 +552,18 @@ impl MethodDurationTracker for OtelMethodDurationTracker {
             method = %context.method,
             user_id = %user_id,
             duration_ms = %duration.as_millis(),
+            outcome = %outcome,
             "Request completed"
         );
 
-        self.metrics.record_method_duration(context, duration);
-        self.metrics.increment_requests_completed(context, true);
+        if let Some(metrics) = &self.metrics {
+            let context = context
+                .clone()
+                .with_principal_kind(PrincipalKind::of(user))
+                .with_outcome(outcome);
+            metrics.record_method_duration(&context, duration);
+            metrics.increment_requests_completed(&context, outcome.is_success());
+        }
     }
 }
 
@@ -173,6 +571,24 @@ impl MethodDurationTracker for OtelMethodDurationTracker {
 pub struct OtelSetupBuilder {
     service_name: &'static str,
     prometheus_registry: Option<Registry>,
+    exact_status_codes: bool,
+    metrics_options: MetricsOptions,
+    resource_attributes: Vec<KeyValue>,
+    prometheus: bool,
+    tenant_extractor: Option<TenantExtractor>,
+    tenant_labels: TenantLabels,
+    runtime_metrics: Option<Duration>,
+    #[cfg(feature = "otlp")]
+    otlp: Option<OtlpOptions>,
+}
+
+/// Where and how often metrics are pushed, see
+/// [`OtelSetupBuilder::with_otlp_exporter`]
+#[cfg(feature = "otlp")]
+struct OtlpOptions {
+    endpoint: String,
+    headers: HashMap<String, String>,
+    interval: Duration,
 }
 
 impl OtelSetupBuilder {
@@ -181,6 +597,15 @@ impl OtelSetupBuilder {
         Self {
             service_name,
             prometheus_registry: None,
+            exact_status_codes: false,
+            metrics_options: MetricsOptions::default(),
+            resource_attributes: Vec::new(),
+            prometheus: true,
+            tenant_extractor: None,
+            tenant_labels: TenantLabels::default(),
+            runtime_metrics: None,
+            #[cfg(feature = "otlp")]
+            otlp: None,
         }
     }
 
@@ -190,45 +615,252 @@ impl OtelSetupBuilder {
         self
     }
 
+    /// Label completed requests with their exact HTTP status code, see
+    /// [`OtelMetrics::with_exact_status_codes`]
+    pub fn with_exact_status_codes(mut self) -> Self {
+        self.exact_status_codes = true;
+        self
+    }
+
+    /// Bucket method durations at these boundaries in milliseconds, which
+    /// must be finite and strictly increasing; `build` fails otherwise
+    pub fn with_duration_buckets(mut self, buckets: Vec<f64>) -> Self {
+        self.metrics_options.duration_buckets = Some(buckets);
+        self
+    }
+
+    /// Prepend `prefix`, such as `tasksvc_`, to the name of every metric
+    pub fn with_metric_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.metrics_options.prefix = prefix.into();
+        self
+    }
+
+    /// Describe the service with resource attributes, such as `service.version`
+    /// and `deployment.environment`, exported by Prometheus as `target_info`
+    ///
+    /// `service.name` is set to the service name.
+    pub fn with_resource_attributes<K, V>(
+        mut self,
+        attributes: impl IntoIterator<Item = (K, V)>,
+    ) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.resource_attributes.extend(
+            attributes
+                .into_iter()
+                .map(|(key, value)| KeyValue::new(key.into(), value.into())),
+        );
+        self
+    }
+
+    /// Name the tenant of each request with `extractor`, given its headers,
+    /// its caller once authenticated and its context, for generated services
+    /// set up with [`OtelSetup`] to record on the contexts of their metrics
+    ///
+    /// The request counters are labelled by tenant, as `tenants` allows, see
+    /// [`OtelMetrics::with_tenant_labels`]; keep a clone of `tenants` to
+    /// change the allow-list while the service runs.
+    pub fn with_tenant_extractor<F>(mut self, extractor: F, tenants: TenantLabels) -> Self
+    where
+        F: Fn(&HeaderMap, Option<&AuthenticatedUser>, &RequestContext) -> Option<String>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.tenant_extractor = Some(Arc::new(extractor));
+        self.tenant_labels = tenants;
+        self
+    }
+
+    /// Sample the Tokio runtime `build` is called in, and the process, every
+    /// [`DEFAULT_RUNTIME_METRICS_INTERVAL`] from a background task
+    ///
+    /// Published as gauges: the runtime's workers, alive tasks and global
+    /// queue depth, and with `--cfg tokio_unstable` its blocking threads and
+    /// blocking and per-worker local queue depths; on Linux, the process's
+    /// resident memory, CPU time and open file descriptors. `build` fails
+    /// outside a Tokio runtime; [`OtelSetup::shutdown`] stops the task.
+    pub fn with_runtime_metrics(self) -> Self {
+        self.with_runtime_metrics_interval(DEFAULT_RUNTIME_METRICS_INTERVAL)
+    }
+
+    /// Sample runtime metrics every `interval`, see
+    /// [`with_runtime_metrics`](Self::with_runtime_metrics)
+    pub fn with_runtime_metrics_interval(mut self, interval: Duration) -> Self {
+        self.runtime_metrics = Some(interval);
+        self
+    }
+
+    /// Push metrics every `interval` to the OTLP/HTTP collector at `endpoint`,
+    /// the full URL such as `http://collector:4318/v1/metrics`, sending
+    /// `headers` with each export
+    ///
+    /// Prometheus keeps serving the same metrics unless
+    /// [`without_prometheus`](Self::without_prometheus) is called. `build`
+    /// fails on a malformed endpoint or header rather than the exporter
+    /// dropping every export later; call [`OtelSetup::shutdown`] before exiting
+    /// to push what was recorded since the last export.
+    #[cfg(feature = "otlp")]
+    pub fn with_otlp_exporter<K, V>(
+        mut self,
+        endpoint: impl Into<String>,
+        headers: impl IntoIterator<Item = (K, V)>,
+        interval: Duration,
+    ) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.otlp = Some(OtlpOptions {
+            endpoint: endpoint.into(),
+            headers: headers
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+            interval,
+        });
+        self
+    }
+
+    /// Don't export to Prometheus, for services only pushing over OTLP; the
+    /// metrics router then serves an empty page
+    pub fn without_prometheus(mut self) -> Self {
+        self.prometheus = false;
+        self
+    }
+
     /// Build and initialize OpenTelemetry
     pub fn build(self) -> Result<OtelSetup, Box<dyn std::error::Error>> {
+        if let Some(buckets) = &self.metrics_options.duration_buckets {
+            validate_buckets(buckets)?;
+        }
+        #[cfg(feature = "otlp")]
+        if let Some(otlp) = &self.otlp {
+            validate_otlp_options(otlp)?;
+        }
+
         // Create or use existing Prometheus registry
         let prometheus_registry = self.prometheus_registry.unwrap_or_else(Registry::new);
 
-        // Create Prometheus exporter
-        let prometheus_exporter = opentelemetry_prometheus::exporter()
-            .with_registry(prometheus_registry.clone())
-            .build()?;
-
         // Build meter provider
-        let meter_provider = SdkMeterProvider::builder()
-            .with_reader(prometheus_exporter)
+        let resource = Resource::builder()
+            .with_service_name(self.service_name)
+            .with_attributes(self.resource_attributes)
             .build();
+        let mut meter_provider = SdkMeterProvider::builder().with_resource(resource);
+
+        // Export to Prometheus when scraped
+        if self.prometheus {
+            let prometheus_exporter = opentelemetry_prometheus::exporter()
+                .with_registry(prometheus_registry.clone())
+                .build()?;
+            meter_provider = meter_provider.with_reader(prometheus_exporter);
+        }
+
+        // Push to the OTLP collector from a background thread
+        #[cfg(feature = "otlp")]
+        if let Some(otlp) = self.otlp {
+            use opentelemetry_otlp::{WithExportConfig, WithHttpConfig};
+
+            let exporter = opentelemetry_otlp::MetricExporter::builder()
+                .with_http()
+                .with_endpoint(otlp.endpoint)
+                .with_headers(otlp.headers)
+                .build()?;
+            let reader = opentelemetry_sdk::metrics::PeriodicReader::builder(exporter)
+                .with_interval(otlp.interval)
+                .build();
+            meter_provider = meter_provider.with_reader(reader);
+        }
+
+        let meter_provider = meter_provider.build();
 
         // Set as global provider
         global::set_meter_provider(meter_provider.clone());
 
-        // Create meter
-        let meter = global::meter(self.service_name);
+        // Propagate trace context in the W3C format
+        ras_observability_core::install_trace_context_propagator();
+
+        // Create meter from this provider, even if another replaces the global one
+        let meter = meter_provider.meter(self.service_name);
 
         // Create metrics
-        let metrics = Arc::new(OtelMetrics::new(&meter));
+        let mut metrics = OtelMetrics::with_options(&meter, &self.metrics_options)
+            .with_tenant_labels(self.tenant_labels);
+        metrics.exact_status_codes = self.exact_status_codes;
+        let metrics = Arc::new(metrics);
+
+        let runtime_sampler = self
+            .runtime_metrics
+            .map(|interval| RuntimeSampler::start(&meter, &self.metrics_options, interval))
+            .transpose()?;
 
         Ok(OtelSetup {
             meter_provider: Arc::new(meter_provider),
             prometheus_registry: Arc::new(prometheus_registry),
             metrics,
             service_name: self.service_name.to_string(),
+            tenant_extractor: self.tenant_extractor,
+            runtime_sampler,
         })
     }
 }
 
+/// Check that histogram bucket boundaries are finite and strictly increasing
+fn validate_buckets(buckets: &[f64]) -> Result<(), String> {
+    if buckets.is_empty() {
+        return Err("duration buckets must not be empty".to_string());
+    }
+    if let Some(bound) = buckets.iter().find(|bound| !bound.is_finite()) {
+        return Err(format!("duration bucket {bound} is not finite"));
+    }
+    if let Some(pair) = buckets.windows(2).find(|pair| pair[0] >= pair[1]) {
+        return Err(format!(
+            "duration buckets must be strictly increasing, but {} is followed by {}",
+            pair[0], pair[1]
+        ));
+    }
+    Ok(())
+}
+
+/// Check that the OTLP endpoint is an http(s) URL, that the headers are
+/// valid and that the export interval is not zero
+#[cfg(feature = "otlp")]
+fn validate_otlp_options(options: &OtlpOptions) -> Result<(), String> {
+    use axum::http::{HeaderName, HeaderValue, Uri};
+
+    let endpoint: Uri = options
+        .endpoint
+        .parse()
+        .map_err(|error| format!("invalid OTLP endpoint {:?}: {error}", options.endpoint))?;
+    if !matches!(endpoint.scheme_str(), Some("http" | "https")) || endpoint.host().is_none() {
+        return Err(format!(
+            "OTLP endpoint {:?} must be an http or https URL",
+            options.endpoint
+        ));
+    }
+    for (name, value) in &options.headers {
+        HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("invalid OTLP header name {name:?}"))?;
+        HeaderValue::from_str(value)
+            .map_err(|_| format!("invalid value for OTLP header {name}"))?;
+    }
+    if options.interval.is_zero() {
+        return Err("OTLP export interval must not be zero".to_string());
+    }
+    Ok(())
+}
+
 /// Result of OpenTelemetry setup
 pub struct OtelSetup {
     pub meter_provider: Arc<SdkMeterProvider>,
     pub prometheus_registry: Arc<Registry>,
     pub metrics: Arc<OtelMetrics>,
     pub service_name: String,
+    tenant_extractor: Option<TenantExtractor>,
+    runtime_sampler: Option<RuntimeSampler>,
 }
 
 impl OtelSetup {
@@ -248,11 +880,52 @@ impl OtelSetup {
         Ok(())
     }
 
+    /// Export what was recorded since the last export and stop the exporters
+    /// and the runtime metrics sampler
+    ///
+    /// Call it once before the process exits; with an OTLP exporter, metrics
+    /// recorded after its last periodic push are lost otherwise. Metrics
+    /// recorded afterwards are not exported.
+    pub fn shutdown(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(sampler) = &self.runtime_sampler {
+            sampler.stop();
+        }
+        self.meter_provider.shutdown()?;
+        Ok(())
+    }
+
     /// Create an Axum router for the metrics endpoint
+    ///
+    /// Scrapers asking for `application/openmetrics-text` get the OpenMetrics
+    /// format, whose method duration buckets carry the trace id of the latest
+    /// request recorded in them as an exemplar; others get the Prometheus text
+    /// format. Responses are gzipped for scrapers accepting it and never
+    /// cached. Scrapes arriving while another of the same format is being
+    /// encoded are answered with its result, and each scrape's duration and
+    /// size are recorded as `metrics_scrape_duration_milliseconds` and
+    /// `metrics_scrape_size_bytes`.
     pub fn metrics_router(&self) -> Router {
+        self.router(None)
+    }
+
+    /// Create an Axum router for the metrics endpoint, as
+    /// [`metrics_router`](Self::metrics_router) does, answering scrapers that
+    /// don't present the credentials of `auth` with `401 Unauthorized`
+    pub fn metrics_router_with_auth(&self, auth: MetricsAuth) -> Router {
+        self.router(Some(auth))
+    }
+
+    fn router(&self, auth: Option<MetricsAuth>) -> Router {
         Router::new()
             .route("/metrics", get(metrics_handler))
-            .with_state(self.prometheus_registry.clone())
+            .with_state(MetricsState {
+                registry: self.prometheus_registry.clone(),
+                exemplars: self.metrics.exemplars.clone(),
+                metrics: self.metrics.clone(),
+                auth,
+                prometheus_scrapes: Arc::default(),
+                openmetrics_scrapes: Arc::default(),
+            })
     }
 
     /// Get the metrics instance for custom tracking
@@ -261,22 +934,25 @@ impl OtelSetup {
     }
 }
 
-/// Handler for Prometheus metrics endpoint
-async fn metrics_handler(
-    State(prometheus_registry): State<Arc<Registry>>,
-) -> Result<Response<Body>, StatusCode> {
-    let encoder = TextEncoder::new();
-    let metric_families = prometheus_registry.gather();
-    let mut buffer = Vec::new();
-    encoder
-        .encode(&metric_families, &mut buffer)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", encoder.format_type())
-        .body(Body::from(buffer))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?)
+/// Reports requests to [`OtelMetrics`], outcomes included, and logs them through
+/// logging-only trackers; pass the setup to the `with_observability` method of a
+/// generated service builder
+impl Observability for OtelSetup {
+    fn service_metrics(&self) -> Arc<dyn ServiceMetrics> {
+        self.metrics.clone()
+    }
+
+    fn usage_tracker(&self) -> Option<Arc<dyn UsageTracker>> {
+        Some(Arc::new(OtelUsageTracker::logging_only()))
+    }
+
+    fn method_duration_tracker(&self) -> Option<Arc<dyn MethodDurationTracker>> {
+        Some(Arc::new(OtelMethodDurationTracker::logging_only()))
+    }
+
+    fn tenant_extractor(&self) -> Option<TenantExtractor> {
+        self.tenant_extractor.clone()
+    }
 }
 
 /// Convenience function to create a standard observability setup
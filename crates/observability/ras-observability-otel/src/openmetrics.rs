@@ -0,0 +1,229 @@
+//! The OpenMetrics text exposition, with exemplars linking method durations
+//! to the traces of the requests they were recorded for.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use opentelemetry::KeyValue;
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+
+/// `Content-Type` of the OpenMetrics exposition
+pub(crate) const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Bucket boundaries the SDK gives histograms without explicit ones
+pub(crate) const DEFAULT_DURATION_BOUNDARIES: [f64; 15] = [
+    0.0, 5.0, 10.0, 25.0, 50.0, 75.0, 100.0, 250.0, 500.0, 750.0, 1000.0, 2500.0, 5000.0, 7500.0,
+    10000.0,
+];
+
+/// Labels of a series, sorted by name
+type SeriesLabels = Vec<(String, String)>;
+
+/// A recorded duration and the trace of its request
+#[derive(Debug, Clone)]
+struct Exemplar {
+    trace_id: String,
+    value: f64,
+    timestamp: f64,
+}
+
+/// The latest exemplar of each bucket of each method duration series
+///
+/// Holds one exemplar per bucket and series, so it grows no more than the
+/// histogram itself.
+#[derive(Debug)]
+pub(crate) struct DurationExemplars {
+    family: String,
+    boundaries: Vec<f64>,
+    series: Mutex<HashMap<SeriesLabels, Vec<Option<Exemplar>>>>,
+}
+
+impl DurationExemplars {
+    /// Exemplars for the histogram family named `family`, bucketed at `boundaries`
+    pub(crate) fn new(family: String, boundaries: Vec<f64>) -> Self {
+        Self {
+            family,
+            boundaries,
+            series: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Keep `value`, recorded with `attributes`, as the exemplar of its bucket
+    pub(crate) fn record(&self, attributes: &[KeyValue], value: f64, trace_id: String) {
+        let mut labels: SeriesLabels = attributes
+            .iter()
+            .map(|attribute| (attribute.key.to_string(), attribute.value.to_string()))
+            .collect();
+        labels.sort();
+        let bucket = self
+            .boundaries
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.boundaries.len());
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let mut series = self.series.lock().unwrap();
+        let exemplars = series
+            .entry(labels)
+            .or_insert_with(|| vec![None; self.boundaries.len() + 1]);
+        exemplars[bucket] = Some(Exemplar {
+            trace_id,
+            value,
+            timestamp,
+        });
+    }
+
+    /// The exemplar of the bucket bounded by `upper_bound` in the series of
+    /// `family` with `labels`
+    fn get(&self, family: &str, labels: &[LabelPair], upper_bound: f64) -> Option<Exemplar> {
+        if !family.starts_with(&self.family) {
+            return None;
+        }
+        let bucket = if upper_bound.is_infinite() {
+            self.boundaries.len()
+        } else {
+            self.boundaries
+                .iter()
+                .position(|bound| *bound == upper_bound)?
+        };
+        // The exporter adds the instrumentation scope to every series
+        let mut labels: SeriesLabels = labels
+            .iter()
+            .filter(|label| !label.get_name().starts_with("otel_scope_"))
+            .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+            .collect();
+        labels.sort();
+
+        let series = self.series.lock().unwrap();
+        series.get(&labels)?.get(bucket)?.clone()
+    }
+}
+
+/// Encode `families` in the OpenMetrics text format, with the exemplars of
+/// method duration buckets
+pub(crate) fn encode(families: &[MetricFamily], exemplars: &DurationExemplars) -> String {
+    let mut out = String::new();
+    for family in families {
+        let name = family.get_name();
+        let (name, kind) = match family.get_field_type() {
+            // Counter families are named without the suffix of their samples
+            MetricType::COUNTER => (name.strip_suffix("_total").unwrap_or(name), "counter"),
+            MetricType::GAUGE => (name, "gauge"),
+            MetricType::HISTOGRAM => (name, "histogram"),
+            MetricType::SUMMARY => (name, "summary"),
+            MetricType::UNTYPED => (name, "unknown"),
+        };
+        if !family.get_help().is_empty() {
+            let _ = writeln!(out, "# HELP {name} {}", escape(family.get_help()));
+        }
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            let sample =
+                |out: &mut String, suffix: &str, extra: Option<(&str, f64)>, value: f64| {
+                    write_sample(out, &format!("{name}{suffix}"), labels, extra, value)
+                };
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    sample(&mut out, "_total", None, metric.get_counter().get_value())
+                }
+                MetricType::GAUGE => sample(&mut out, "", None, metric.get_gauge().get_value()),
+                MetricType::UNTYPED => sample(&mut out, "", None, metric.get_untyped().get_value()),
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let label = ("quantile", quantile.get_quantile());
+                        sample(&mut out, "", Some(label), quantile.get_value());
+                    }
+                    sample(&mut out, "_count", None, summary.get_sample_count() as f64);
+                    sample(&mut out, "_sum", None, summary.get_sample_sum());
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let buckets = histogram
+                        .get_bucket()
+                        .iter()
+                        .filter(|bucket| bucket.get_upper_bound().is_finite())
+                        .map(|bucket| (bucket.get_upper_bound(), bucket.get_cumulative_count()))
+                        .chain([(f64::INFINITY, histogram.get_sample_count())]);
+                    for (upper_bound, count) in buckets {
+                        sample(&mut out, "_bucket", Some(("le", upper_bound)), count as f64);
+                        if let Some(exemplar) =
+                            exemplars.get(family.get_name(), labels, upper_bound)
+                        {
+                            // Appended to the bucket's line, before its break
+                            out.pop();
+                            let _ = writeln!(
+                                out,
+                                " # {{trace_id=\"{}\"}} {} {:.3}",
+                                escape(&exemplar.trace_id),
+                                format_value(exemplar.value),
+                                exemplar.timestamp
+                            );
+                        }
+                    }
+                    sample(
+                        &mut out,
+                        "_count",
+                        None,
+                        histogram.get_sample_count() as f64,
+                    );
+                    sample(&mut out, "_sum", None, histogram.get_sample_sum());
+                }
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Write a sample line, with an `extra` label such as a bucket's `le`
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, f64)>,
+    value: f64,
+) {
+    out.push_str(name);
+    let labels = labels
+        .iter()
+        .map(|label| (label.get_name(), escape(label.get_value())))
+        .chain(extra.map(|(label, value)| (label, format_value(value))));
+    let mut first = true;
+    for (label, value) in labels {
+        out.push(if first { '{' } else { ',' });
+        first = false;
+        let _ = write!(out, "{label}=\"{value}\"");
+    }
+    if !first {
+        out.push('}');
+    }
+    let _ = writeln!(out, " {}", format_value(value));
+}
+
+fn format_value(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else if value.is_nan() {
+        "NaN".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escape a label value or help text
+fn escape(text: &str) -> String {
+    text.replace('\\', r"\\")
+        .replace('\n', r"\n")
+        .replace('"', r#"\""#)
+}
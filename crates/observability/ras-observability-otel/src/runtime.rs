@@ -0,0 +1,225 @@
+//! Tokio runtime and process metrics, sampled in the background.
+
+use std::time::Duration;
+
+#[cfg(tokio_unstable)]
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Gauge, Meter};
+use tokio::runtime::{Handle, RuntimeMetrics};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::MetricsOptions;
+
+/// How often runtime metrics are sampled, unless set with
+/// [`OtelSetupBuilder::with_runtime_metrics_interval`](crate::OtelSetupBuilder::with_runtime_metrics_interval)
+pub const DEFAULT_RUNTIME_METRICS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The gauges the sampler sets
+struct RuntimeGauges {
+    workers: Gauge<u64>,
+    alive_tasks: Gauge<u64>,
+    global_queue_depth: Gauge<u64>,
+    #[cfg(tokio_unstable)]
+    blocking_threads: Gauge<u64>,
+    #[cfg(tokio_unstable)]
+    idle_blocking_threads: Gauge<u64>,
+    #[cfg(tokio_unstable)]
+    blocking_queue_depth: Gauge<u64>,
+    #[cfg(tokio_unstable)]
+    local_queue_depth: Gauge<u64>,
+    resident_memory: Gauge<u64>,
+    cpu_seconds: Gauge<f64>,
+    open_fds: Gauge<u64>,
+}
+
+impl RuntimeGauges {
+    fn new(meter: &Meter, options: &MetricsOptions) -> Self {
+        let gauge = |name: &str, description: &'static str| {
+            meter
+                .u64_gauge(options.name(name))
+                .with_description(description)
+                .build()
+        };
+        Self {
+            workers: gauge("tokio_workers", "Number of Tokio runtime worker threads"),
+            alive_tasks: gauge("tokio_alive_tasks", "Number of alive Tokio tasks"),
+            global_queue_depth: gauge(
+                "tokio_global_queue_depth",
+                "Number of tasks in the Tokio runtime's global queue",
+            ),
+            #[cfg(tokio_unstable)]
+            blocking_threads: gauge(
+                "tokio_blocking_threads",
+                "Number of threads spawned for blocking tasks",
+            ),
+            #[cfg(tokio_unstable)]
+            idle_blocking_threads: gauge(
+                "tokio_idle_blocking_threads",
+                "Number of idle threads spawned for blocking tasks",
+            ),
+            #[cfg(tokio_unstable)]
+            blocking_queue_depth: gauge(
+                "tokio_blocking_queue_depth",
+                "Number of blocking tasks waiting for a thread",
+            ),
+            #[cfg(tokio_unstable)]
+            local_queue_depth: gauge(
+                "tokio_worker_local_queue_depth",
+                "Number of tasks in each worker's local queue",
+            ),
+            resident_memory: gauge(
+                "process_resident_memory_bytes",
+                "Resident memory of the process in bytes",
+            ),
+            cpu_seconds: meter
+                .f64_gauge(options.name("process_cpu_seconds"))
+                .with_description("User and system CPU time spent by the process in seconds")
+                .build(),
+            open_fds: gauge(
+                "process_open_fds",
+                "Number of file descriptors the process has open",
+            ),
+        }
+    }
+
+    fn sample(&self, runtime: &RuntimeMetrics) {
+        self.workers.record(runtime.num_workers() as u64, &[]);
+        self.alive_tasks
+            .record(runtime.num_alive_tasks() as u64, &[]);
+        self.global_queue_depth
+            .record(runtime.global_queue_depth() as u64, &[]);
+        #[cfg(tokio_unstable)]
+        {
+            self.blocking_threads
+                .record(runtime.num_blocking_threads() as u64, &[]);
+            self.idle_blocking_threads
+                .record(runtime.num_idle_blocking_threads() as u64, &[]);
+            self.blocking_queue_depth
+                .record(runtime.blocking_queue_depth() as u64, &[]);
+            for worker in 0..runtime.num_workers() {
+                self.local_queue_depth.record(
+                    runtime.worker_local_queue_depth(worker) as u64,
+                    &[KeyValue::new("worker", worker as i64)],
+                );
+            }
+        }
+
+        let process = ProcessStats::read();
+        if let Some(resident_memory) = process.resident_memory {
+            self.resident_memory.record(resident_memory, &[]);
+        }
+        if let Some(cpu_seconds) = process.cpu_seconds {
+            self.cpu_seconds.record(cpu_seconds, &[]);
+        }
+        if let Some(open_fds) = process.open_fds {
+            self.open_fds.record(open_fds, &[]);
+        }
+    }
+}
+
+/// What the process uses, as far as the platform tells
+#[derive(Debug, Default)]
+struct ProcessStats {
+    resident_memory: Option<u64>,
+    cpu_seconds: Option<f64>,
+    open_fds: Option<u64>,
+}
+
+impl ProcessStats {
+    /// Read from `/proc/self`
+    #[cfg(target_os = "linux")]
+    fn read() -> Self {
+        // Clock ticks per second of the times in `/proc`, fixed by the kernel ABI
+        const USER_HZ: f64 = 100.0;
+
+        let resident_memory =
+            std::fs::read_to_string("/proc/self/status")
+                .ok()
+                .and_then(|status| {
+                    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+                    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+                    Some(kib * 1024)
+                });
+        let cpu_seconds = std::fs::read_to_string("/proc/self/stat")
+            .ok()
+            .and_then(|stat| {
+                // The command name in parentheses may contain spaces; `utime`
+                // and `stime` are the 12th and 13th fields after it
+                let (_, fields) = stat.rsplit_once(')')?;
+                let mut fields = fields.split_whitespace().skip(11);
+                let user: u64 = fields.next()?.parse().ok()?;
+                let system: u64 = fields.next()?.parse().ok()?;
+                Some((user + system) as f64 / USER_HZ)
+            });
+        let open_fds = std::fs::read_dir("/proc/self/fd")
+            .ok()
+            .map(|fds| fds.count() as u64);
+        Self {
+            resident_memory,
+            cpu_seconds,
+            open_fds,
+        }
+    }
+
+    /// Not available on this platform
+    #[cfg(not(target_os = "linux"))]
+    fn read() -> Self {
+        Self::default()
+    }
+}
+
+/// The task started by [`OtelSetupBuilder::with_runtime_metrics`](crate::OtelSetupBuilder::with_runtime_metrics),
+/// stopped by [`OtelSetup::shutdown`](crate::OtelSetup::shutdown) and aborted
+/// when dropped
+pub(crate) struct RuntimeSampler {
+    stop: watch::Sender<bool>,
+    pub(crate) task: JoinHandle<()>,
+}
+
+impl RuntimeSampler {
+    /// Sample the runtime the caller runs in now and then every `interval`
+    pub(crate) fn start(
+        meter: &Meter,
+        options: &MetricsOptions,
+        interval: Duration,
+    ) -> Result<Self, String> {
+        let handle = Handle::try_current()
+            .map_err(|_| "runtime metrics must be set up within a Tokio runtime".to_string())?;
+        if interval.is_zero() {
+            return Err("runtime metrics interval must not be zero".to_string());
+        }
+        let gauges = RuntimeGauges::new(meter, options);
+        let runtime = handle.metrics();
+        // So the gauges are there from the first scrape
+        gauges.sample(&runtime);
+
+        let (stop, mut stopped) = watch::channel(false);
+        let task = handle.spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes immediately
+            ticks.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    _ = stopped.changed() => return,
+                }
+                gauges.sample(&runtime);
+            }
+        });
+        Ok(Self { stop, task })
+    }
+
+    /// Stop sampling; the gauges keep their last values
+    pub(crate) fn stop(&self) {
+        let _ = self.stop.send(true);
+    }
+}
+
+impl Drop for RuntimeSampler {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,88 @@
+//! Which tenants the request counters are labelled with, changeable while the
+//! service runs.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use ras_observability_core::{CardinalityGuard, OTHER_LABEL};
+
+/// The tenants given a `tenant` label of their own on the request counters,
+/// all others being counted as [`OTHER_LABEL`]
+///
+/// Tenants on the allow-list are always labelled, and with a
+/// [`limit`](Self::with_limit), so are the first tenants seen that aren't on
+/// it, up to the limit. Clones share the allow-list, so keep one to change it
+/// without a redeploy; counts already exported under a tenant's label stay as
+/// they were.
+#[derive(Clone, Default)]
+pub struct TenantLabels {
+    allowed: Arc<ArcSwap<HashSet<String>>>,
+    unlisted: Option<Arc<CardinalityGuard>>,
+}
+
+impl TenantLabels {
+    /// Label the tenants in `allowed`
+    pub fn new<I>(allowed: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let labels = Self::default();
+        labels.set_allowed(allowed);
+        labels
+    }
+
+    /// Also label the first `limit` distinct tenants seen that aren't on the
+    /// allow-list
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.unlisted = Some(Arc::new(CardinalityGuard::new(limit)));
+        self
+    }
+
+    /// Replace the allow-list with `allowed`
+    pub fn set_allowed<I>(&self, allowed: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.allowed
+            .store(Arc::new(allowed.into_iter().map(Into::into).collect()));
+    }
+
+    /// Add `tenant` to the allow-list
+    pub fn allow(&self, tenant: impl Into<String>) {
+        let tenant = tenant.into();
+        self.allowed.rcu(|allowed| {
+            let mut allowed = HashSet::clone(allowed);
+            allowed.insert(tenant.clone());
+            allowed
+        });
+    }
+
+    /// Remove `tenant` from the allow-list
+    pub fn disallow(&self, tenant: &str) {
+        self.allowed.rcu(|allowed| {
+            let mut allowed = HashSet::clone(allowed);
+            allowed.remove(tenant);
+            allowed
+        });
+    }
+
+    /// Whether `tenant` is on the allow-list
+    pub fn is_allowed(&self, tenant: &str) -> bool {
+        self.allowed.load().contains(tenant)
+    }
+
+    /// The label `tenant` is counted under
+    pub fn label<'a>(&self, tenant: &'a str) -> Cow<'a, str> {
+        if self.is_allowed(tenant) {
+            return Cow::Borrowed(tenant);
+        }
+        match &self.unlisted {
+            Some(unlisted) => unlisted.label(tenant),
+            None => Cow::Borrowed(OTHER_LABEL),
+        }
+    }
+}
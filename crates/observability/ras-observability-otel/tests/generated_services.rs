@@ -0,0 +1,207 @@
+//! Metrics scraped from the Prometheus endpoint after calls to generated
+//! services reporting through `with_observability` and
+//! `with_observability_config`
+
+use std::sync::{Arc, Mutex};
+
+use axum_test::TestServer;
+use ras_auth_core::AuthenticatedUser;
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_observability_core::{ObservabilityBuilder, RequestContext};
+use ras_observability_otel::OtelSetupBuilder;
+use ras_rest_core::{RestResponse, RestResult};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::MockAuthProvider;
+use serde_json::json;
+
+rest_service!({
+    service_name: Inventory,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        GET UNAUTHORIZED stock() -> u32,
+        POST WITH_PERMISSIONS(["admin"]) restock() -> u32,
+    ]
+});
+
+struct InventoryImpl;
+
+#[async_trait::async_trait]
+impl InventoryTrait for InventoryImpl {
+    async fn get_stock(&self) -> RestResult<u32> {
+        Ok(RestResponse::ok(12))
+    }
+
+    async fn post_restock(&self, _user: &AuthenticatedUser) -> RestResult<u32> {
+        Ok(RestResponse::ok(20))
+    }
+}
+
+jsonrpc_service!({
+    service_name: Orders,
+    methods: [
+        UNAUTHORIZED place(u32) -> u32,
+        UNAUTHORIZED cancel(u32) -> (),
+    ]
+});
+
+struct OrdersImpl;
+
+impl OrdersTrait for OrdersImpl {
+    async fn place(&self, quantity: u32) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(quantity)
+    }
+
+    async fn cancel(&self, _order: u32) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Err("order already shipped".into())
+    }
+}
+
+/// The value of the first `metric` sample carrying all of `labels`
+fn sample(body: &str, metric: &str, labels: &[&str]) -> Option<f64> {
+    body.lines()
+        .filter(|line| line.starts_with(metric))
+        .find(|line| labels.iter().all(|label| line.contains(label)))
+        .and_then(|line| line.rsplit(' ').next()?.parse().ok())
+}
+
+#[tokio::test]
+async fn generated_services_report_outcomes_to_prometheus() {
+    let otel = OtelSetupBuilder::new("generated_services")
+        .build()
+        .expect("Failed to build OTel setup");
+    let rest = InventoryBuilder::new(InventoryImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_observability(&otel)
+        .build();
+    let rpc = OrdersBuilder::new(OrdersImpl)
+        .with_observability(&otel)
+        .build()
+        .unwrap();
+    let server = TestServer::new(rest.merge(rpc).merge(otel.metrics_router())).unwrap();
+
+    server.get("/api/stock").await.assert_status_ok();
+    server.get("/api/stock").await.assert_status_ok();
+    server
+        .post("/api/restock")
+        .authorization_bearer("user-token")
+        .await
+        .assert_status_forbidden();
+    for (method, params) in [("place", json!(3)), ("cancel", json!(7))] {
+        server
+            .post("/rpc")
+            .json(&json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 }))
+            .await
+            .assert_status_ok();
+    }
+    otel.force_flush().expect("Failed to flush metrics");
+
+    let body = server.get("/metrics").await.text();
+    let completed = |labels: &[&str]| sample(&body, "requests_completed", labels);
+    assert_eq!(
+        sample(&body, "requests_started", &["method=\"GET /stock\""]),
+        Some(2.0),
+        "{body}"
+    );
+    assert_eq!(
+        completed(&[
+            "method=\"GET /stock\"",
+            "success=\"true\"",
+            "status_class=\"2xx\""
+        ]),
+        Some(2.0)
+    );
+    assert_eq!(
+        completed(&[
+            "method=\"POST /restock\"",
+            "success=\"false\"",
+            "error_kind=\"forbidden\"",
+            "outcome=\"auth_failure\"",
+            "status_class=\"4xx\"",
+            "principal_kind=\"user\"",
+        ]),
+        Some(1.0),
+        "{body}"
+    );
+    assert_eq!(
+        completed(&[
+            "method=\"place\"",
+            "protocol=\"JSON-RPC\"",
+            "success=\"true\""
+        ]),
+        Some(1.0)
+    );
+    assert_eq!(
+        completed(&[
+            "method=\"cancel\"",
+            "success=\"false\"",
+            "error_kind=\"handler_error\"",
+            "error_code=\"internal_error\"",
+            "outcome=\"handler_error\"",
+            "principal_kind=\"anonymous\"",
+        ]),
+        Some(1.0),
+        "{body}"
+    );
+    assert!(body.contains("method_duration"), "{body}");
+}
+
+#[tokio::test]
+async fn one_observability_config_drives_rest_and_jsonrpc_services() {
+    let otel = OtelSetupBuilder::new("shared_observability_config")
+        .build()
+        .expect("Failed to build OTel setup");
+    let tracked = Arc::new(Mutex::new(Vec::new()));
+    let config = ObservabilityBuilder::new()
+        .with_service_metrics(otel.metrics())
+        .with_usage_tracker({
+            let tracked = tracked.clone();
+            move |_headers, _user, context: RequestContext| {
+                tracked
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", context.protocol, context.method));
+                async {}
+            }
+        })
+        .build();
+    let rest = InventoryBuilder::new(InventoryImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_observability_config(config.clone())
+        .build();
+    let rpc = OrdersBuilder::new(OrdersImpl)
+        .with_observability_config(config)
+        .build()
+        .unwrap();
+    let server = TestServer::new(rest.merge(rpc).merge(otel.metrics_router())).unwrap();
+
+    server.get("/api/stock").await.assert_status_ok();
+    server
+        .post("/rpc")
+        .json(&json!({ "jsonrpc": "2.0", "method": "place", "params": 2, "id": 1 }))
+        .await
+        .assert_status_ok();
+    otel.force_flush().expect("Failed to flush metrics");
+
+    assert_eq!(
+        *tracked.lock().unwrap(),
+        ["REST GET /stock", "JSON-RPC place"]
+    );
+    let body = server.get("/metrics").await.text();
+    for labels in [
+        ["method=\"GET /stock\"", "protocol=\"REST\""],
+        ["method=\"place\"", "protocol=\"JSON-RPC\""],
+    ] {
+        assert_eq!(
+            sample(&body, "requests_started", &labels),
+            Some(1.0),
+            "{body}"
+        );
+        assert_eq!(
+            sample(&body, "requests_completed", &labels),
+            Some(1.0),
+            "{body}"
+        );
+    }
+}
@@ -8,7 +8,7 @@ use axum::{
     routing::{get, post},
 };
 use axum_test::TestServer;
-use ras_auth_core::AuthenticatedUser;
+use ras_auth_core::{AuthenticatedUser, PrincipalKind};
 use ras_observability_core::{
     MethodDurationTracker, Protocol, RequestContext, ServiceMetrics, UsageTracker,
 };
@@ -107,6 +107,7 @@ async fn get_profile(
             .into_iter()
             .collect(),
         metadata: None,
+        kind: PrincipalKind::User,
     };
 
     // Track with authenticated user
@@ -284,13 +285,8 @@ async fn test_websocket_protocol_tracking() {
     let ws_operations = ["connect", "subscribe", "publish", "disconnect"];
 
     for operation in &ws_operations {
-        let context = RequestContext {
-            method: operation.to_string(),
-            protocol: Protocol::WebSocket,
-            metadata: [("connection_id".to_string(), "ws-123".to_string())]
-                .into_iter()
-                .collect(),
-        };
+        let context = RequestContext::new(*operation, Protocol::WebSocket)
+            .with_metadata("connection_id", "ws-123");
 
         metrics.increment_requests_started(&context);
         metrics.record_method_duration(&context, Duration::from_millis(5));
@@ -386,6 +382,7 @@ async fn test_high_cardinality_protection() {
             user_id: format!("user-{}", i),
             permissions: vec![format!("perm-{}", i % 10)].into_iter().collect(),
             metadata: None,
+            kind: PrincipalKind::User,
         };
 
         usage_tracker
@@ -0,0 +1,179 @@
+//! Hourly call counts in memory, sharded by user.
+
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+use crate::UsageRecord;
+
+/// Shards of the counts; a call locks only the shard of its user
+const SHARDS: usize = 16;
+
+/// The counts of one user, by method and hour
+type UserCounts = HashMap<(String, DateTime<Utc>), Count>;
+
+#[derive(Debug, Default)]
+struct Count {
+    /// Calls in the hour, flushed or not
+    total: u64,
+    /// Calls not written to the sink yet
+    pending: u64,
+}
+
+/// Calls per user, method and hour
+pub(crate) struct Aggregate {
+    shards: Vec<Mutex<HashMap<String, UserCounts>>>,
+    hasher: RandomState,
+}
+
+impl Aggregate {
+    pub(crate) fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn shard(&self, user_id: &str) -> &Mutex<HashMap<String, UserCounts>> {
+        &self.shards[self.hasher.hash_one(user_id) as usize % SHARDS]
+    }
+
+    /// Count `calls` by `user_id` to `method` in `hour`, to be flushed when
+    /// `pending`, or already in the sink otherwise
+    pub(crate) fn add(
+        &self,
+        user_id: &str,
+        method: &str,
+        hour: DateTime<Utc>,
+        calls: u64,
+        pending: bool,
+    ) {
+        let mut shard = self.shard(user_id).lock().unwrap();
+        let count = shard
+            .entry(user_id.to_string())
+            .or_default()
+            .entry((method.to_string(), hour))
+            .or_default();
+        count.total += calls;
+        if pending {
+            count.pending += calls;
+        }
+    }
+
+    /// Calls by `user_id` in the hours from `since` on, and the earliest of
+    /// those hours with any
+    pub(crate) fn usage(
+        &self,
+        user_id: &str,
+        since: DateTime<Utc>,
+    ) -> (u64, Option<DateTime<Utc>>) {
+        let shard = self.shard(user_id).lock().unwrap();
+        let Some(counts) = shard.get(user_id) else {
+            return (0, None);
+        };
+        counts
+            .iter()
+            .filter(|((_, hour), count)| *hour >= since && count.total > 0)
+            .fold((0, None), |(calls, earliest), ((_, hour), count)| {
+                (
+                    calls + count.total,
+                    Some(earliest.map_or(*hour, |earliest: DateTime<Utc>| earliest.min(*hour))),
+                )
+            })
+    }
+
+    /// The calls not flushed yet, no longer pending
+    pub(crate) fn take_pending(&self) -> Vec<UsageRecord> {
+        let mut records = Vec::new();
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            for (user_id, counts) in shard.iter_mut() {
+                for ((method, hour), count) in counts.iter_mut() {
+                    if count.pending > 0 {
+                        records.push(UsageRecord {
+                            user_id: user_id.clone(),
+                            method: method.clone(),
+                            hour: *hour,
+                            calls: std::mem::take(&mut count.pending),
+                        });
+                    }
+                }
+            }
+        }
+        records
+    }
+
+    /// Mark `records` taken by [`take_pending`](Self::take_pending) pending
+    /// again, after the sink failed to write them
+    pub(crate) fn restore_pending(&self, records: &[UsageRecord]) {
+        for record in records {
+            let mut shard = self.shard(&record.user_id).lock().unwrap();
+            let count = shard
+                .entry(record.user_id.clone())
+                .or_default()
+                .entry((record.method.clone(), record.hour))
+                .or_default();
+            count.pending += record.calls;
+        }
+    }
+
+    /// Forget flushed counts of hours before `before`
+    pub(crate) fn prune(&self, before: DateTime<Utc>) {
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            shard.retain(|_, counts| {
+                counts.retain(|(_, hour), count| *hour >= before || count.pending > 0);
+                !counts.is_empty()
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn pending_calls_are_taken_once_and_restored_on_failure() {
+        let aggregate = Aggregate::new();
+        let hour = crate::hour_of(Utc::now());
+        aggregate.add("alice", "create_task", hour, 2, true);
+        aggregate.add("alice", "create_task", hour, 5, false);
+        aggregate.add("bob", "list_tasks", hour, 1, true);
+
+        let mut taken = aggregate.take_pending();
+        taken.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+        assert_eq!(
+            taken
+                .iter()
+                .map(|r| (r.user_id.as_str(), r.calls))
+                .collect::<Vec<_>>(),
+            [("alice", 2), ("bob", 1)]
+        );
+        assert!(aggregate.take_pending().is_empty());
+
+        aggregate.restore_pending(&taken);
+        assert_eq!(aggregate.take_pending().len(), 2);
+        assert_eq!(aggregate.usage("alice", hour), (7, Some(hour)));
+    }
+
+    #[test]
+    fn usage_counts_hours_from_the_start_and_prune_keeps_pending() {
+        let aggregate = Aggregate::new();
+        let now = crate::hour_of(Utc::now());
+        let earlier = now - TimeDelta::hours(3);
+        aggregate.add("alice", "a", earlier, 4, false);
+        aggregate.add("alice", "b", now, 1, true);
+
+        assert_eq!(aggregate.usage("alice", earlier), (5, Some(earlier)));
+        assert_eq!(aggregate.usage("alice", now), (1, Some(now)));
+        assert_eq!(aggregate.usage("carol", earlier), (0, None));
+
+        aggregate.prune(now + TimeDelta::hours(1));
+        assert_eq!(aggregate.usage("alice", earlier), (1, Some(now)));
+    }
+}
@@ -0,0 +1,61 @@
+//! Per-user usage for billing and quotas in Rust Agent Stack services
+//!
+//! [`QuotaUsageTracker`] is a [`UsageTracker`](ras_observability_core::UsageTracker)
+//! counting the calls of each user to each method per hour. Counts are
+//! aggregated in memory and flushed periodically to a [`UsageSink`]: a
+//! JSON-lines or CSV file, or with the `postgres` feature a Postgres table.
+//! [`QuotaEnforcer`] wraps an [`AuthProvider`](ras_auth_core::AuthProvider) to
+//! refuse callers who used up their plan as rate limited, which generated
+//! services answer with 429 and `Retry-After`, or the JSON-RPC equivalent.
+//! [`FileUsageTracker`] logs each completed request to a rotated JSON-lines
+//! file instead, for deployments without Prometheus.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+mod aggregate;
+mod quota;
+mod sink;
+mod tracker;
+mod usage_log;
+
+#[cfg(feature = "postgres")]
+mod postgres;
+
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresUsageSink;
+pub use quota::{QuotaEnforcer, QuotaLimit};
+pub use sink::{CsvUsageSink, JsonLinesUsageSink, UsageSink};
+pub use tracker::{DEFAULT_RETENTION, QuotaUsageTracker, UsageFlusher};
+pub use usage_log::{
+    FileUsageTracker, FileUsageTrackerBuilder, REDACTED, UsageLogEntry, read_usage_log,
+};
+
+/// The calls one user made to one method within one hour
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub user_id: String,
+    pub method: String,
+    /// The start of the hour
+    pub hour: DateTime<Utc>,
+    pub calls: u64,
+}
+
+/// Errors reading or writing usage
+#[derive(Debug, thiserror::Error)]
+pub enum UsageError {
+    #[error("usage file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("usage record: {0}")]
+    Format(String),
+
+    #[error("usage database: {0}")]
+    Database(String),
+}
+
+/// The start of the hour `at` falls in
+pub(crate) fn hour_of(at: DateTime<Utc>) -> DateTime<Utc> {
+    let seconds = at.timestamp();
+    DateTime::from_timestamp(seconds - seconds.rem_euclid(3600), 0).unwrap_or(at)
+}
@@ -0,0 +1,94 @@
+//! Keeping usage in Postgres.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Row;
+use sqlx::postgres::PgPool;
+
+use crate::{UsageError, UsageRecord, UsageSink};
+
+/// A [`UsageSink`] keeping the calls per user, method and hour in the
+/// `ras_usage` table, created if it doesn't exist yet
+///
+/// Each write adds its calls to the rows of their hours in one transaction.
+/// Hours are stored as seconds since the Unix epoch.
+#[derive(Debug, Clone)]
+pub struct PostgresUsageSink {
+    pool: PgPool,
+}
+
+impl PostgresUsageSink {
+    /// Connect to the database at `url`, such as `postgres://localhost/billing`
+    pub async fn connect(url: &str) -> Result<Self, UsageError> {
+        let pool = PgPool::connect(url).await.map_err(database_error)?;
+        Self::from_pool(pool).await
+    }
+
+    /// Keep usage in the database behind `pool`
+    pub async fn from_pool(pool: PgPool) -> Result<Self, UsageError> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS ras_usage (
+                user_id TEXT NOT NULL,
+                method TEXT NOT NULL,
+                hour BIGINT NOT NULL,
+                calls BIGINT NOT NULL,
+                PRIMARY KEY (user_id, method, hour)
+            )",
+        )
+        .execute(&pool)
+        .await
+        .map_err(database_error)?;
+        Ok(Self { pool })
+    }
+}
+
+fn database_error(error: sqlx::Error) -> UsageError {
+    UsageError::Database(error.to_string())
+}
+
+#[async_trait]
+impl UsageSink for PostgresUsageSink {
+    async fn write(&self, records: &[UsageRecord]) -> Result<(), UsageError> {
+        let mut transaction = self.pool.begin().await.map_err(database_error)?;
+        for record in records {
+            let calls = i64::try_from(record.calls)
+                .map_err(|_| UsageError::Format(format!("{} calls is too many", record.calls)))?;
+            sqlx::query(
+                "INSERT INTO ras_usage (user_id, method, hour, calls) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (user_id, method, hour)
+                 DO UPDATE SET calls = ras_usage.calls + EXCLUDED.calls",
+            )
+            .bind(&record.user_id)
+            .bind(&record.method)
+            .bind(record.hour.timestamp())
+            .bind(calls)
+            .execute(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+        }
+        transaction.commit().await.map_err(database_error)
+    }
+
+    async fn load(&self, since: DateTime<Utc>) -> Result<Vec<UsageRecord>, UsageError> {
+        let rows =
+            sqlx::query("SELECT user_id, method, hour, calls FROM ras_usage WHERE hour >= $1")
+                .bind(since.timestamp())
+                .fetch_all(&self.pool)
+                .await
+                .map_err(database_error)?;
+        rows.into_iter()
+            .map(|row| {
+                let hour: i64 = row.try_get("hour").map_err(database_error)?;
+                let calls: i64 = row.try_get("calls").map_err(database_error)?;
+                Ok(UsageRecord {
+                    user_id: row.try_get("user_id").map_err(database_error)?,
+                    method: row.try_get("method").map_err(database_error)?,
+                    hour: DateTime::from_timestamp(hour, 0)
+                        .ok_or_else(|| UsageError::Format(format!("invalid hour {hour}")))?,
+                    calls: u64::try_from(calls)
+                        .map_err(|_| UsageError::Format(format!("invalid calls {calls}")))?,
+                })
+            })
+            .collect()
+    }
+}
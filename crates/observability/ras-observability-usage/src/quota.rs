@@ -0,0 +1,198 @@
+//! Refusing callers who used up their plan.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use ras_auth_core::{
+    AuthError, AuthFuture, AuthProvider, AuthResult, AuthenticatedUser, PermissionExpr,
+};
+
+use crate::QuotaUsageTracker;
+
+/// How many calls a plan allows within a window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaLimit {
+    pub calls: u64,
+    /// Counted in whole hours, see [`QuotaUsageTracker::current_usage`]
+    pub window: Duration,
+}
+
+impl QuotaLimit {
+    /// Allow `calls` calls within `window`
+    pub fn new(calls: u64, window: Duration) -> Self {
+        Self { calls, window }
+    }
+
+    /// Allow `calls` calls an hour
+    pub fn per_hour(calls: u64) -> Self {
+        Self::new(calls, Duration::from_secs(60 * 60))
+    }
+
+    /// Allow `calls` calls a day
+    pub fn per_day(calls: u64) -> Self {
+        Self::new(calls, Duration::from_secs(24 * 60 * 60))
+    }
+}
+
+/// An [`AuthProvider`] refusing users who used up the calls of their plan,
+/// as counted by a [`QuotaUsageTracker`]
+///
+/// Plans are named by permissions, such as `plan:free`; the first plan a user
+/// has applies, and users with none are limited by the default limit, if any.
+/// Users over their limit are refused as [`AuthError::RateLimited`] until their
+/// earliest counted hour leaves the window, which generated services answer
+/// with 429 and `Retry-After`, or the JSON-RPC equivalent. Everything else is
+/// left to the wrapped provider.
+pub struct QuotaEnforcer<P> {
+    provider: P,
+    usage: Arc<QuotaUsageTracker>,
+    plans: Vec<(String, QuotaLimit)>,
+    default_limit: Option<QuotaLimit>,
+}
+
+impl<P: AuthProvider> QuotaEnforcer<P> {
+    /// Limit the users `provider` authenticates by the calls `usage` counted
+    pub fn new(provider: P, usage: Arc<QuotaUsageTracker>) -> Self {
+        Self {
+            provider,
+            usage,
+            plans: Vec::new(),
+            default_limit: None,
+        }
+    }
+
+    /// Limit users with `permission` to `limit`, unless an earlier plan applies
+    pub fn with_plan(mut self, permission: impl Into<String>, limit: QuotaLimit) -> Self {
+        self.plans.push((permission.into(), limit));
+        self
+    }
+
+    /// Limit users with none of the plans to `limit`, rather than not at all
+    pub fn with_default_limit(mut self, limit: QuotaLimit) -> Self {
+        self.default_limit = Some(limit);
+        self
+    }
+
+    fn limit_for(&self, user: &AuthenticatedUser) -> Option<QuotaLimit> {
+        self.plans
+            .iter()
+            .find(|(permission, _)| user.permissions.contains(permission))
+            .map(|(_, limit)| *limit)
+            .or(self.default_limit)
+    }
+
+    /// Refuse `user` if over their limit
+    fn enforce(&self, user: AuthenticatedUser) -> AuthResult<AuthenticatedUser> {
+        let Some(limit) = self.limit_for(&user) else {
+            return Ok(user);
+        };
+        let (calls, earliest) = self.usage.usage_since(&user.user_id, limit.window);
+        if calls < limit.calls {
+            return Ok(user);
+        }
+        // The earliest counted hour leaves the window an hour after it began
+        let retry_after = earliest
+            .and_then(|hour| {
+                let leaves = hour + TimeDelta::hours(1) + TimeDelta::from_std(limit.window).ok()?;
+                (leaves - Utc::now()).to_std().ok()
+            })
+            .unwrap_or_default()
+            .max(Duration::from_secs(1));
+        Err(AuthError::RateLimited { retry_after })
+    }
+}
+
+impl<P: AuthProvider> AuthProvider for QuotaEnforcer<P> {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        Box::pin(async move { self.enforce(self.provider.authenticate(token).await?) })
+    }
+
+    fn credential(&self, headers: &http::HeaderMap) -> Option<String> {
+        self.provider.credential(headers)
+    }
+
+    fn authenticate_request(&self, token: String, headers: &http::HeaderMap) -> AuthFuture<'_> {
+        let authenticated = self.provider.authenticate_request(token, headers);
+        Box::pin(async move { self.enforce(authenticated.await?) })
+    }
+
+    fn request_binding(&self, token: &str, headers: &http::HeaderMap) -> Vec<u8> {
+        self.provider.request_binding(token, headers)
+    }
+
+    fn check_permissions(
+        &self,
+        user: &AuthenticatedUser,
+        required_permissions: &[String],
+    ) -> AuthResult<()> {
+        self.provider.check_permissions(user, required_permissions)
+    }
+
+    fn check_permission_expr(
+        &self,
+        user: &AuthenticatedUser,
+        expression: &PermissionExpr,
+    ) -> AuthResult<()> {
+        self.provider.check_permission_expr(user, expression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JsonLinesUsageSink;
+    use ras_auth_core::StaticTokenAuthProvider;
+
+    #[tokio::test]
+    async fn users_over_their_plan_are_rate_limited() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = JsonLinesUsageSink::open(dir.path().join("usage.jsonl"))
+            .await
+            .unwrap();
+        let usage = Arc::new(QuotaUsageTracker::new(sink));
+        let provider = StaticTokenAuthProvider::new()
+            .with_token("free-token", "free-user", ["plan:free"])
+            .with_token("pro-token", "pro-user", ["plan:pro", "plan:free"])
+            .with_token("ops-token", "ops", ["ops"]);
+        let enforcer = QuotaEnforcer::new(provider, usage.clone())
+            .with_plan("plan:pro", QuotaLimit::per_hour(100))
+            .with_plan("plan:free", QuotaLimit::per_hour(2));
+
+        for _ in 0..2 {
+            assert!(enforcer.authenticate("free-token".into()).await.is_ok());
+            usage.record_call("free-user", "create_task");
+            usage.record_call("pro-user", "create_task");
+            usage.record_call("ops", "create_task");
+        }
+
+        match enforcer.authenticate("free-token".into()).await {
+            Err(AuthError::RateLimited { retry_after }) => {
+                assert!(retry_after <= Duration::from_secs(2 * 60 * 60));
+                assert!(retry_after >= Duration::from_secs(60 * 60));
+            }
+            other => panic!("expected rate limited, got {other:?}"),
+        }
+        // The first plan a user has applies, and users without one aren't limited
+        assert!(enforcer.authenticate("pro-token".into()).await.is_ok());
+        assert!(enforcer.authenticate("ops-token".into()).await.is_ok());
+        assert!(matches!(
+            enforcer.authenticate("unknown".into()).await,
+            Err(AuthError::InvalidToken)
+        ));
+
+        let enforcer = QuotaEnforcer::new(StaticTokenAuthProvider::new(), usage)
+            .with_default_limit(QuotaLimit::per_day(1));
+        assert!(enforcer.enforce(user("ops")).is_err());
+        assert!(enforcer.enforce(user("nobody")).is_ok());
+    }
+
+    fn user(user_id: &str) -> AuthenticatedUser {
+        AuthenticatedUser {
+            user_id: user_id.to_string(),
+            permissions: Default::default(),
+            metadata: None,
+            kind: Default::default(),
+        }
+    }
+}
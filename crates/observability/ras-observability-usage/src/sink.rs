@@ -0,0 +1,359 @@
+//! Where flushed usage is kept.
+
+use std::io::ErrorKind;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::{UsageError, UsageRecord};
+
+/// Keeps the usage flushed by a [`QuotaUsageTracker`](crate::QuotaUsageTracker)
+#[async_trait]
+pub trait UsageSink: Send + Sync {
+    /// Add `records` to the usage kept, all of them or none
+    ///
+    /// A user, method and hour may be written many times; their calls add up.
+    async fn write(&self, records: &[UsageRecord]) -> Result<(), UsageError>;
+
+    /// The usage kept for the hours from `since` on, to count calls made
+    /// before a restart
+    async fn load(&self, since: DateTime<Utc>) -> Result<Vec<UsageRecord>, UsageError>;
+}
+
+/// How records are laid out in a file
+trait Format: Send + Sync + 'static {
+    /// The first line of a new file, if any
+    const HEADER: Option<&'static str>;
+
+    /// Append `record` to `out` as one line
+    fn encode(record: &UsageRecord, out: &mut String) -> Result<(), UsageError>;
+
+    /// The complete records of `text` and the length of the text holding them;
+    /// what follows was cut short by a crash
+    fn decode(text: &str) -> Result<(Vec<UsageRecord>, usize), UsageError>;
+}
+
+/// Records appended to a file
+///
+/// Each write is appended and synced to disk in one go. A write that fails is
+/// truncated away, and a record cut short by a crash is dropped when the file
+/// is opened again, so no call is counted twice or half written.
+struct FileSink<F> {
+    path: PathBuf,
+    lock: Mutex<()>,
+    format: PhantomData<F>,
+}
+
+impl<F: Format> FileSink<F> {
+    async fn open(path: &Path) -> Result<Self, UsageError> {
+        let path = path.to_path_buf();
+        match tokio::fs::read(&path).await {
+            Ok(contents) => {
+                let text = String::from_utf8_lossy(&contents);
+                let (_, complete) = F::decode(&text)?;
+                if complete < contents.len() {
+                    tracing::warn!(
+                        path = %path.display(),
+                        "dropping a usage record cut short when the file was last written"
+                    );
+                    let file = OpenOptions::new().write(true).open(&path).await?;
+                    file.set_len(complete as u64).await?;
+                    file.sync_all().await?;
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+        Ok(Self {
+            path,
+            lock: Mutex::new(()),
+            format: PhantomData,
+        })
+    }
+
+    async fn write(&self, records: &[UsageRecord]) -> Result<(), UsageError> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let _writing = self.lock.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        let length = file.metadata().await?.len();
+
+        let mut lines = String::new();
+        if length == 0
+            && let Some(header) = F::HEADER
+        {
+            lines.push_str(header);
+            lines.push('\n');
+        }
+        for record in records {
+            F::encode(record, &mut lines)?;
+        }
+
+        if let Err(error) = append(&mut file, lines.as_bytes()).await {
+            // Leave none of the records rather than some
+            file.set_len(length).await?;
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    async fn load(&self, since: DateTime<Utc>) -> Result<Vec<UsageRecord>, UsageError> {
+        let _reading = self.lock.lock().await;
+        let contents = match tokio::fs::read(&self.path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+        let (mut records, _) = F::decode(&String::from_utf8_lossy(&contents))?;
+        records.retain(|record| record.hour >= since);
+        Ok(records)
+    }
+}
+
+async fn append(file: &mut File, bytes: &[u8]) -> std::io::Result<()> {
+    file.write_all(bytes).await?;
+    file.sync_data().await
+}
+
+struct JsonLines;
+
+impl Format for JsonLines {
+    const HEADER: Option<&'static str> = None;
+
+    fn encode(record: &UsageRecord, out: &mut String) -> Result<(), UsageError> {
+        let line =
+            serde_json::to_string(record).map_err(|error| UsageError::Format(error.to_string()))?;
+        out.push_str(&line);
+        out.push('\n');
+        Ok(())
+    }
+
+    fn decode(text: &str) -> Result<(Vec<UsageRecord>, usize), UsageError> {
+        let complete = text.rfind('\n').map_or(0, |end| end + 1);
+        let records = text[..complete]
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line).map_err(|error| UsageError::Format(error.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((records, complete))
+    }
+}
+
+struct Csv;
+
+const CSV_HEADER: &str = "user_id,method,hour,calls";
+
+impl Format for Csv {
+    const HEADER: Option<&'static str> = Some(CSV_HEADER);
+
+    fn encode(record: &UsageRecord, out: &mut String) -> Result<(), UsageError> {
+        csv_field(&record.user_id, out);
+        out.push(',');
+        csv_field(&record.method, out);
+        out.push(',');
+        out.push_str(&record.hour.to_rfc3339());
+        out.push(',');
+        out.push_str(&record.calls.to_string());
+        out.push('\n');
+        Ok(())
+    }
+
+    fn decode(text: &str) -> Result<(Vec<UsageRecord>, usize), UsageError> {
+        let (rows, complete) = csv_rows(text);
+        let records = rows
+            .into_iter()
+            .filter(|row| row.len() > 1 || row.first().is_some_and(|field| !field.is_empty()))
+            .filter(|row| row.join(",") != CSV_HEADER)
+            .map(|row| csv_record(&row))
+            .collect::<Result<_, _>>()?;
+        Ok((records, complete))
+    }
+}
+
+/// Quote `field` if it holds a separator, quote or line break
+fn csv_field(field: &str, out: &mut String) {
+    if field.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+/// The rows of `text` ending in a line break, and the length they take
+fn csv_rows(text: &str) -> (Vec<Vec<String>>, usize) {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut complete = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.next_if(|(_, next)| *next == '"').is_some() {
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                row.push(
+                    std::mem::take(&mut field)
+                        .trim_end_matches('\r')
+                        .to_string(),
+                );
+                rows.push(std::mem::take(&mut row));
+                complete = at + 1;
+            }
+            c => field.push(c),
+        }
+    }
+    (rows, complete)
+}
+
+fn csv_record(row: &[String]) -> Result<UsageRecord, UsageError> {
+    let [user_id, method, hour, calls] = row else {
+        return Err(UsageError::Format(format!(
+            "expected {CSV_HEADER}, found {}",
+            row.join(",")
+        )));
+    };
+    Ok(UsageRecord {
+        user_id: user_id.clone(),
+        method: method.clone(),
+        hour: DateTime::parse_from_rfc3339(hour)
+            .map_err(|error| UsageError::Format(format!("hour {hour:?}: {error}")))?
+            .with_timezone(&Utc),
+        calls: calls
+            .parse()
+            .map_err(|error| UsageError::Format(format!("calls {calls:?}: {error}")))?,
+    })
+}
+
+/// A [`UsageSink`] appending records to a file as JSON lines
+///
+/// Each line holds a [`UsageRecord`], such as
+/// `{"user_id":"alice","method":"create_task","hour":"2026-10-16T09:00:00Z","calls":3}`.
+/// Writes are appended and synced in one go: a failed write is truncated away,
+/// and a line cut short by a crash is dropped when the file is opened again.
+pub struct JsonLinesUsageSink(FileSink<JsonLines>);
+
+impl JsonLinesUsageSink {
+    /// Append to the file at `path`, created on the first write
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, UsageError> {
+        FileSink::open(path.as_ref()).await.map(Self)
+    }
+}
+
+#[async_trait]
+impl UsageSink for JsonLinesUsageSink {
+    async fn write(&self, records: &[UsageRecord]) -> Result<(), UsageError> {
+        self.0.write(records).await
+    }
+
+    async fn load(&self, since: DateTime<Utc>) -> Result<Vec<UsageRecord>, UsageError> {
+        self.0.load(since).await
+    }
+}
+
+/// A [`UsageSink`] appending records to a CSV file
+///
+/// The file starts with a `user_id,method,hour,calls` header, hours being
+/// RFC 3339 times. It is written like [`JsonLinesUsageSink`], and as safely.
+pub struct CsvUsageSink(FileSink<Csv>);
+
+impl CsvUsageSink {
+    /// Append to the file at `path`, created on the first write
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, UsageError> {
+        FileSink::open(path.as_ref()).await.map(Self)
+    }
+}
+
+#[async_trait]
+impl UsageSink for CsvUsageSink {
+    async fn write(&self, records: &[UsageRecord]) -> Result<(), UsageError> {
+        self.0.write(records).await
+    }
+
+    async fn load(&self, since: DateTime<Utc>) -> Result<Vec<UsageRecord>, UsageError> {
+        self.0.load(since).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn record(user_id: &str, method: &str, hour: DateTime<Utc>, calls: u64) -> UsageRecord {
+        UsageRecord {
+            user_id: user_id.to_string(),
+            method: method.to_string(),
+            hour,
+            calls,
+        }
+    }
+
+    #[test]
+    fn csv_quotes_awkward_fields() {
+        let hour = crate::hour_of(Utc::now());
+        let awkward = record("a,\"b\"", "line\nbreak", hour, 3);
+        let mut text = String::new();
+        Csv::encode(&awkward, &mut text).unwrap();
+        Csv::encode(&record("plain", "m", hour, 1), &mut text).unwrap();
+
+        let (records, complete) = Csv::decode(&text).unwrap();
+        assert_eq!(records, [awkward, record("plain", "m", hour, 1)]);
+        assert_eq!(complete, text.len());
+
+        // A record cut short is left out
+        let (records, complete) = Csv::decode(&text[..text.len() - 3]).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(text[..complete].ends_with('\n'));
+    }
+
+    #[tokio::test]
+    async fn files_skip_older_hours_and_torn_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = crate::hour_of(Utc::now());
+        let old = now - TimeDelta::hours(48);
+
+        let path = dir.path().join("usage.jsonl");
+        let sink = JsonLinesUsageSink::open(&path).await.unwrap();
+        sink.write(&[record("alice", "a", old, 1), record("alice", "a", now, 2)])
+            .await
+            .unwrap();
+        assert_eq!(
+            sink.load(now).await.unwrap(),
+            [record("alice", "a", now, 2)]
+        );
+
+        // A crash in the middle of a write
+        let mut contents = tokio::fs::read(&path).await.unwrap();
+        contents.extend_from_slice(br#"{"user_id":"bob","met"#);
+        tokio::fs::write(&path, &contents).await.unwrap();
+
+        let sink = JsonLinesUsageSink::open(&path).await.unwrap();
+        sink.write(&[record("bob", "b", now, 4)]).await.unwrap();
+        assert_eq!(
+            sink.load(now).await.unwrap(),
+            [record("alice", "a", now, 2), record("bob", "b", now, 4)]
+        );
+    }
+}
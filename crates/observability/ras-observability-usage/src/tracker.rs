@@ -0,0 +1,202 @@
+//! Counting calls per user, method and hour, and flushing them to a sink.
+
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use http::HeaderMap;
+use ras_auth_core::AuthenticatedUser;
+use ras_observability_core::{RequestContext, UsageTracker};
+use tokio::sync::{Mutex, watch};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::aggregate::Aggregate;
+use crate::{UsageError, UsageSink, hour_of};
+
+/// How long hourly counts are kept in memory for
+/// [`current_usage`](QuotaUsageTracker::current_usage), unless set with
+/// [`with_retention`](QuotaUsageTracker::with_retention)
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A [`UsageTracker`] counting the calls of each authenticated user to each
+/// method per hour, for usage-based billing and quotas
+///
+/// Calls are counted in memory, sharded by user so concurrent requests rarely
+/// contend, and written to a [`UsageSink`] by [`flush`](Self::flush), usually
+/// from [`start_flushing`](Self::start_flushing). A flush the sink fails is
+/// retried with the next one. Calls counted since the last flush are lost if
+/// the process crashes; after a restart, [`recover`](Self::recover) counts
+/// those flushed before it again. Anonymous calls aren't counted.
+pub struct QuotaUsageTracker {
+    aggregate: Aggregate,
+    sink: Arc<dyn UsageSink>,
+    retention: Duration,
+    flushing: Mutex<()>,
+}
+
+impl QuotaUsageTracker {
+    /// Count calls, flushing them to `sink`
+    pub fn new(sink: impl UsageSink + 'static) -> Self {
+        Self {
+            aggregate: Aggregate::new(),
+            sink: Arc::new(sink),
+            retention: DEFAULT_RETENTION,
+            flushing: Mutex::new(()),
+        }
+    }
+
+    /// Keep hourly counts in memory for `retention`, the longest window
+    /// [`current_usage`](Self::current_usage) is asked about
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Count the calls the sink holds for the retention period, flushed
+    /// before a restart; call it once, before serving requests
+    pub async fn recover(&self) -> Result<(), UsageError> {
+        let since = window_start(self.retention);
+        for record in self.sink.load(since).await? {
+            self.aggregate.add(
+                &record.user_id,
+                &record.method,
+                record.hour,
+                record.calls,
+                false,
+            );
+        }
+        Ok(())
+    }
+
+    /// Count a call by `user_id` to `method` now
+    pub fn record_call(&self, user_id: &str, method: &str) {
+        self.record_call_at(user_id, method, Utc::now());
+    }
+
+    /// Count a call by `user_id` to `method` at `at`
+    pub fn record_call_at(&self, user_id: &str, method: &str, at: DateTime<Utc>) {
+        self.aggregate.add(user_id, method, hour_of(at), 1, true);
+    }
+
+    /// Calls by `user_id` to any method within `window`
+    ///
+    /// Calls are counted per hour, so the window reaches back to the start of
+    /// the hour `window` ago. Windows longer than the retention period count
+    /// only the calls retained.
+    pub fn current_usage(&self, user_id: &str, window: Duration) -> u64 {
+        self.usage_since(user_id, window).0
+    }
+
+    /// Calls by `user_id` within `window`, and the earliest hour with any
+    pub(crate) fn usage_since(
+        &self,
+        user_id: &str,
+        window: Duration,
+    ) -> (u64, Option<DateTime<Utc>>) {
+        self.aggregate.usage(user_id, window_start(window))
+    }
+
+    /// Write the calls counted since the last flush to the sink
+    ///
+    /// If the sink fails, the calls are kept to be written by the next flush.
+    pub async fn flush(&self) -> Result<(), UsageError> {
+        let _flushing = self.flushing.lock().await;
+        let records = self.aggregate.take_pending();
+        if !records.is_empty()
+            && let Err(error) = self.sink.write(&records).await
+        {
+            self.aggregate.restore_pending(&records);
+            return Err(error);
+        }
+        self.aggregate.prune(window_start(self.retention));
+        Ok(())
+    }
+
+    /// Flush every `interval` from a background task, logging failures
+    ///
+    /// The task stops when the returned handle is shut down, flushing once
+    /// more, or dropped, or when the tracker is dropped. Must be called within
+    /// a Tokio runtime.
+    pub fn start_flushing(self: &Arc<Self>, interval: Duration) -> UsageFlusher {
+        let (stop, mut stopped) = watch::channel(false);
+        let tracker = Arc::downgrade(self);
+
+        let task = tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes immediately
+            ticks.tick().await;
+            loop {
+                let stopping = tokio::select! {
+                    _ = ticks.tick() => false,
+                    _ = stopped.changed() => true,
+                };
+                let Some(tracker) = Weak::upgrade(&tracker) else {
+                    return;
+                };
+                if let Err(error) = tracker.flush().await {
+                    tracing::warn!(%error, "failed to flush usage, retrying with the next flush");
+                }
+                if stopping {
+                    return;
+                }
+            }
+        });
+        UsageFlusher {
+            stop,
+            task: Some(task),
+        }
+    }
+}
+
+/// The task started by [`QuotaUsageTracker::start_flushing`]
+///
+/// Stop it with [`shutdown`](Self::shutdown); dropping the handle aborts it.
+pub struct UsageFlusher {
+    stop: watch::Sender<bool>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl UsageFlusher {
+    /// Stop the task after flushing once more, so calls counted since the last
+    /// flush aren't lost
+    pub async fn shutdown(mut self) {
+        let _ = self.stop.send(true);
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for UsageFlusher {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl UsageTracker for QuotaUsageTracker {
+    async fn track_request(
+        &self,
+        _headers: &HeaderMap,
+        user: Option<&AuthenticatedUser>,
+        context: &RequestContext,
+    ) {
+        if let Some(user) = user {
+            self.record_call(&user.user_id, &context.method);
+        }
+    }
+}
+
+/// The start of the hour `window` ago
+fn window_start(window: Duration) -> DateTime<Utc> {
+    let start = TimeDelta::from_std(window)
+        .ok()
+        .and_then(|window| Utc::now().checked_sub_signed(window))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    hour_of(start)
+}
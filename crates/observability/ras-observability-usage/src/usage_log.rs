@@ -0,0 +1,477 @@
+//! An append-only log of completed requests, one JSON object per line.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use http::HeaderMap;
+use ras_auth_core::AuthenticatedUser;
+use ras_observability_core::{
+    MethodDurationTracker, Protocol, RequestContext, RequestOutcome, UsageTracker,
+};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::UsageError;
+
+/// The value logged in place of a redacted header
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Headers redacted unless [`FileUsageTrackerBuilder::without_default_redaction`]
+/// is called
+const DEFAULT_REDACTED_HEADERS: [&str; 5] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+];
+
+/// Headers kept for requests whose completion hasn't been reported yet
+const MAX_PENDING_HEADERS: usize = 10_000;
+
+/// One completed request, as written to the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageLogEntry {
+    /// When the request completed
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    pub protocol: Protocol,
+    /// The authenticated user, if any
+    pub user_id: Option<String>,
+    pub duration_ms: f64,
+    /// How the request ended, if reported through `track_completion`
+    pub outcome: Option<RequestOutcome>,
+    /// The selected request headers, redacted ones as [`REDACTED`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Sets up a [`FileUsageTracker`]
+pub struct FileUsageTrackerBuilder {
+    path: PathBuf,
+    headers: Vec<String>,
+    redacted: Vec<String>,
+    max_file_size: Option<u64>,
+    rotation_interval: Option<Duration>,
+    max_files: usize,
+    queue_capacity: usize,
+}
+
+impl FileUsageTrackerBuilder {
+    /// Log to the file at `path`, appending to it if it exists
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            headers: Vec::new(),
+            redacted: DEFAULT_REDACTED_HEADERS.map(str::to_string).to_vec(),
+            max_file_size: None,
+            rotation_interval: None,
+            max_files: 5,
+            queue_capacity: 10_000,
+        }
+    }
+
+    /// Log the request headers named `headers`, which none are by default
+    pub fn with_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.headers
+            .extend(headers.into_iter().map(|name| name.as_ref().to_lowercase()));
+        self
+    }
+
+    /// Log `headers` as [`REDACTED`] if selected, in addition to
+    /// `authorization`, `proxy-authorization`, `cookie`, `set-cookie` and
+    /// `x-api-key`
+    pub fn with_redacted_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.redacted
+            .extend(headers.into_iter().map(|name| name.as_ref().to_lowercase()));
+        self
+    }
+
+    /// Log the credentials headers redacted by default in the clear
+    pub fn without_default_redaction(mut self) -> Self {
+        self.redacted
+            .retain(|name| !DEFAULT_REDACTED_HEADERS.contains(&name.as_str()));
+        self
+    }
+
+    /// Start a new file once the current one holds `bytes`
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    /// Start a new file once the current one has been written to for
+    /// `interval`
+    pub fn with_rotation_interval(mut self, interval: Duration) -> Self {
+        self.rotation_interval = Some(interval);
+        self
+    }
+
+    /// Keep `max_files` rotated files, 5 by default, deleting older ones
+    ///
+    /// Rotated files are named after the log with a number appended, `.1`
+    /// being the most recent.
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files.max(1);
+        self
+    }
+
+    /// Queue at most `capacity` entries, 10 000 by default, while the file is
+    /// written; entries past it are dropped and counted
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self
+    }
+
+    /// Open the log and start writing it in a task of its own
+    pub async fn start(self) -> Result<FileUsageTracker, UsageError> {
+        let writer = LogWriter::open(
+            self.path,
+            self.max_file_size,
+            self.rotation_interval,
+            self.max_files,
+        )
+        .await?;
+        let (queue, entries) = mpsc::channel(self.queue_capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(writer.run(entries, dropped.clone()));
+        Ok(FileUsageTracker {
+            queue,
+            dropped,
+            headers: self.headers,
+            redacted: self.redacted,
+            pending: Mutex::new(PendingHeaders::default()),
+        })
+    }
+}
+
+/// A tracker writing each completed request to a file as a JSON line, for
+/// deployments keeping a usage or audit log rather than scraping metrics
+///
+/// Entries are queued and written by a task of its own through a buffer, so
+/// requests never wait on the disk. When the queue is full, entries are
+/// dropped and counted by [`dropped`](Self::dropped) rather than holding up
+/// requests. Files are rotated by size or age, as set on the
+/// [`FileUsageTrackerBuilder`].
+///
+/// Entries are written for completions, reported through
+/// [`MethodDurationTracker`]. Headers are only known when usage is tracked,
+/// through [`UsageTracker`], so selected headers are logged when the tracker
+/// is installed as both, as by `with_observability`; they are paired with the
+/// next completion by the same user of the same method.
+pub struct FileUsageTracker {
+    queue: mpsc::Sender<Message>,
+    dropped: Arc<AtomicU64>,
+    headers: Vec<String>,
+    redacted: Vec<String>,
+    pending: Mutex<PendingHeaders>,
+}
+
+impl FileUsageTracker {
+    /// Set up a tracker logging to the file at `path`
+    pub fn builder(path: impl AsRef<Path>) -> FileUsageTrackerBuilder {
+        FileUsageTrackerBuilder::new(path)
+    }
+
+    /// The number of entries dropped, because the queue was full or the file
+    /// couldn't be written
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Wait until the entries queued so far are written and flushed to the
+    /// file, such as before shutting down
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.queue.send(Message::Flush(done)).await.is_ok() {
+            let _ = flushed.await;
+        }
+    }
+
+    /// The selected headers of a request, redacted
+    fn select_headers(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        let mut selected = BTreeMap::new();
+        for name in &self.headers {
+            let values: Vec<_> = headers
+                .get_all(name.as_str())
+                .iter()
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+                .collect();
+            if values.is_empty() {
+                continue;
+            }
+            let value = if self.redacted.contains(name) {
+                REDACTED.to_string()
+            } else {
+                values.join(", ")
+            };
+            selected.insert(name.clone(), value);
+        }
+        selected
+    }
+
+    fn log(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+        outcome: Option<RequestOutcome>,
+    ) {
+        let user_id = user.map(|user| user.user_id.clone());
+        let headers = if self.headers.is_empty() {
+            BTreeMap::new()
+        } else {
+            self.pending
+                .lock()
+                .unwrap()
+                .take(&context.method, user_id.as_deref())
+        };
+        let entry = UsageLogEntry {
+            timestamp: Utc::now(),
+            method: context.method.clone(),
+            protocol: context.protocol,
+            user_id,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            outcome,
+            headers,
+        };
+        if self.queue.try_send(Message::Entry(entry)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[async_trait]
+impl UsageTracker for FileUsageTracker {
+    async fn track_request(
+        &self,
+        headers: &HeaderMap,
+        user: Option<&AuthenticatedUser>,
+        context: &RequestContext,
+    ) {
+        if self.headers.is_empty() {
+            return;
+        }
+        let selected = self.select_headers(headers);
+        self.pending.lock().unwrap().put(
+            &context.method,
+            user.map(|user| user.user_id.as_str()),
+            selected,
+        );
+    }
+}
+
+#[async_trait]
+impl MethodDurationTracker for FileUsageTracker {
+    async fn track_duration(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+    ) {
+        self.log(context, user, duration, None);
+    }
+
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        user: Option<&AuthenticatedUser>,
+        duration: Duration,
+        outcome: RequestOutcome,
+    ) {
+        self.log(context, user, duration, Some(outcome));
+    }
+}
+
+/// The method and user id of a request
+type RequestKey = (String, Option<String>);
+
+/// Headers of requests whose completion hasn't been reported yet, by method
+/// and user
+#[derive(Default)]
+struct PendingHeaders {
+    requests: HashMap<RequestKey, VecDeque<BTreeMap<String, String>>>,
+    count: usize,
+}
+
+impl PendingHeaders {
+    fn put(&mut self, method: &str, user_id: Option<&str>, headers: BTreeMap<String, String>) {
+        // Requests whose completion is never reported would pile up
+        if self.count >= MAX_PENDING_HEADERS {
+            return;
+        }
+        self.requests
+            .entry((method.to_string(), user_id.map(str::to_string)))
+            .or_default()
+            .push_back(headers);
+        self.count += 1;
+    }
+
+    fn take(&mut self, method: &str, user_id: Option<&str>) -> BTreeMap<String, String> {
+        let key = (method.to_string(), user_id.map(str::to_string));
+        let Some(queue) = self.requests.get_mut(&key) else {
+            return BTreeMap::new();
+        };
+        let headers = queue.pop_front().unwrap_or_default();
+        if queue.is_empty() {
+            self.requests.remove(&key);
+        }
+        self.count -= 1;
+        headers
+    }
+}
+
+enum Message {
+    Entry(UsageLogEntry),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Writes entries to the log file, rotating it
+struct LogWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    size: u64,
+    opened: Instant,
+    max_file_size: Option<u64>,
+    rotation_interval: Option<Duration>,
+    max_files: usize,
+}
+
+impl LogWriter {
+    async fn open(
+        path: PathBuf,
+        max_file_size: Option<u64>,
+        rotation_interval: Option<Duration>,
+        max_files: usize,
+    ) -> Result<Self, UsageError> {
+        let (file, size) = open_log(&path).await?;
+        Ok(Self {
+            path,
+            file,
+            size,
+            opened: Instant::now(),
+            max_file_size,
+            rotation_interval,
+            max_files,
+        })
+    }
+
+    async fn run(mut self, mut entries: mpsc::Receiver<Message>, dropped: Arc<AtomicU64>) {
+        while let Some(message) = entries.recv().await {
+            match message {
+                Message::Entry(entry) => {
+                    if let Err(error) = self.write(&entry).await {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!(path = %self.path.display(), %error, "failed to write to the usage log");
+                    }
+                }
+                Message::Flush(done) => {
+                    self.flush().await;
+                    let _ = done.send(());
+                }
+            }
+            // Write in batches, flushing once the queue runs dry
+            if entries.is_empty() {
+                self.flush().await;
+            }
+        }
+        self.flush().await;
+    }
+
+    async fn write(&mut self, entry: &UsageLogEntry) -> Result<(), UsageError> {
+        if self.due_for_rotation() {
+            self.rotate().await?;
+        }
+        let mut line =
+            serde_json::to_string(entry).map_err(|error| UsageError::Format(error.to_string()))?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes()).await?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    async fn flush(&mut self) {
+        if let Err(error) = self.file.flush().await {
+            tracing::warn!(path = %self.path.display(), %error, "failed to flush the usage log");
+        }
+    }
+
+    fn due_for_rotation(&self) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        self.max_file_size.is_some_and(|max| self.size >= max)
+            || self
+                .rotation_interval
+                .is_some_and(|interval| self.opened.elapsed() >= interval)
+    }
+
+    /// Move the log to `.1`, shifting older files along and deleting the
+    /// oldest, and start a new one
+    async fn rotate(&mut self) -> Result<(), UsageError> {
+        self.file.flush().await?;
+        let _ = tokio::fs::remove_file(rotated_path(&self.path, self.max_files)).await;
+        for number in (1..self.max_files).rev() {
+            let from = rotated_path(&self.path, number);
+            if tokio::fs::try_exists(&from).await? {
+                tokio::fs::rename(&from, rotated_path(&self.path, number + 1)).await?;
+            }
+        }
+        tokio::fs::rename(&self.path, rotated_path(&self.path, 1)).await?;
+
+        let (file, size) = open_log(&self.path).await?;
+        self.file = file;
+        self.size = size;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+async fn open_log(path: &Path) -> Result<(BufWriter<File>, u64), UsageError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    let size = file.metadata().await?.len();
+    Ok((BufWriter::new(file), size))
+}
+
+/// The path of the `number`th most recent rotated file of the log at `path`
+fn rotated_path(path: &Path, number: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{number}"));
+    PathBuf::from(rotated)
+}
+
+/// The entries of a usage log file, such as one written by a
+/// [`FileUsageTracker`]
+///
+/// A last line cut short, as by a crash, is left out.
+pub async fn read_usage_log(path: impl AsRef<Path>) -> Result<Vec<UsageLogEntry>, UsageError> {
+    let text = tokio::fs::read_to_string(path).await?;
+    let complete = text.rfind('\n').map_or(0, |end| end + 1);
+    text[..complete]
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|error| UsageError::Format(error.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,172 @@
+//! Usage surviving restarts and failed flushes.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use http::HeaderMap;
+use ras_auth_core::AuthenticatedUser;
+use ras_observability_core::{RequestContext, UsageTracker};
+use ras_observability_usage::{
+    CsvUsageSink, JsonLinesUsageSink, QuotaUsageTracker, UsageError, UsageRecord, UsageSink,
+};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn user(user_id: &str) -> AuthenticatedUser {
+    AuthenticatedUser {
+        user_id: user_id.to_string(),
+        permissions: Default::default(),
+        metadata: None,
+        kind: Default::default(),
+    }
+}
+
+#[tokio::test]
+async fn flushed_usage_is_recovered_after_a_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.jsonl");
+
+    let tracker = QuotaUsageTracker::new(JsonLinesUsageSink::open(&path).await.unwrap());
+    let alice = user("alice");
+    for method in ["create_task", "create_task", "list_tasks"] {
+        tracker
+            .track_request(
+                &HeaderMap::new(),
+                Some(&alice),
+                &RequestContext::jsonrpc(method.to_string()),
+            )
+            .await;
+    }
+    // Anonymous calls aren't anyone's to pay for
+    tracker
+        .track_request(&HeaderMap::new(), None, &RequestContext::rest("GET", "/"))
+        .await;
+    // Older than the retention period
+    tracker.record_call_at("alice", "create_task", Utc::now() - TimeDelta::days(3));
+    tracker.flush().await.unwrap();
+    // Lost with the crash
+    tracker.record_call("alice", "create_task");
+    drop(tracker);
+
+    let restarted = QuotaUsageTracker::new(JsonLinesUsageSink::open(&path).await.unwrap());
+    assert_eq!(restarted.current_usage("alice", DAY), 0);
+    restarted.recover().await.unwrap();
+    assert_eq!(restarted.current_usage("alice", DAY), 3);
+    assert_eq!(restarted.current_usage("bob", DAY), 0);
+
+    // Recovered calls are already in the sink and aren't written again
+    restarted.record_call("alice", "list_tasks");
+    restarted.flush().await.unwrap();
+    let sink = JsonLinesUsageSink::open(&path).await.unwrap();
+    let calls: u64 = sink
+        .load(Utc::now() - TimeDelta::days(1) - TimeDelta::hours(1))
+        .await
+        .unwrap()
+        .iter()
+        .map(|record| record.calls)
+        .sum();
+    assert_eq!(calls, 4);
+}
+
+#[tokio::test]
+async fn a_record_torn_by_a_crash_is_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.csv");
+
+    let tracker = QuotaUsageTracker::new(CsvUsageSink::open(&path).await.unwrap());
+    tracker.record_call("alice", "create_task");
+    tracker.record_call("alice, the second", "create_task");
+    tracker.flush().await.unwrap();
+
+    let mut contents = tokio::fs::read_to_string(&path).await.unwrap();
+    assert!(contents.starts_with("user_id,method,hour,calls\n"));
+    assert!(contents.contains("\"alice, the second\",create_task,"));
+    contents.push_str("bob,create_ta");
+    tokio::fs::write(&path, contents).await.unwrap();
+
+    let restarted = QuotaUsageTracker::new(CsvUsageSink::open(&path).await.unwrap());
+    restarted.recover().await.unwrap();
+    assert_eq!(restarted.current_usage("alice", DAY), 1);
+    assert_eq!(restarted.current_usage("alice, the second", DAY), 1);
+    assert_eq!(restarted.current_usage("bob", DAY), 0);
+
+    restarted.record_call("bob", "create_task");
+    restarted.flush().await.unwrap();
+    let again = QuotaUsageTracker::new(CsvUsageSink::open(&path).await.unwrap());
+    again.recover().await.unwrap();
+    assert_eq!(again.current_usage("bob", DAY), 1);
+}
+
+/// Refuses writes while `failing`
+struct FlakySink {
+    inner: JsonLinesUsageSink,
+    failing: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl UsageSink for FlakySink {
+    async fn write(&self, records: &[UsageRecord]) -> Result<(), UsageError> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(UsageError::Database("connection refused".to_string()));
+        }
+        self.inner.write(records).await
+    }
+
+    async fn load(&self, since: DateTime<Utc>) -> Result<Vec<UsageRecord>, UsageError> {
+        self.inner.load(since).await
+    }
+}
+
+#[tokio::test]
+async fn calls_a_failed_flush_missed_are_written_by_the_next() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.jsonl");
+    let failing = Arc::new(AtomicBool::new(true));
+    let tracker = Arc::new(QuotaUsageTracker::new(FlakySink {
+        inner: JsonLinesUsageSink::open(&path).await.unwrap(),
+        failing: failing.clone(),
+    }));
+
+    tracker.record_call("alice", "create_task");
+    assert!(tracker.flush().await.is_err());
+    tracker.record_call("alice", "create_task");
+    // Failed flushes don't change what's counted
+    assert_eq!(tracker.current_usage("alice", DAY), 2);
+
+    failing.store(false, Ordering::SeqCst);
+    let flusher = tracker.start_flushing(Duration::from_secs(3600));
+    tracker.record_call("alice", "list_tasks");
+    // Shutting down flushes what the periodic flushes haven't
+    flusher.shutdown().await;
+
+    let restarted = QuotaUsageTracker::new(JsonLinesUsageSink::open(&path).await.unwrap());
+    restarted.recover().await.unwrap();
+    assert_eq!(restarted.current_usage("alice", DAY), 3);
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn postgres_sink() {
+    let Ok(url) = std::env::var("POSTGRES_URL") else {
+        eprintln!("POSTGRES_URL is not set, skipping the Postgres usage sink");
+        return;
+    };
+    let sink = ras_observability_usage::PostgresUsageSink::connect(&url)
+        .await
+        .unwrap();
+    let user_id = format!("ras-test-{}", Utc::now().timestamp_nanos_opt().unwrap());
+
+    let tracker = QuotaUsageTracker::new(sink.clone());
+    tracker.record_call(&user_id, "create_task");
+    tracker.record_call(&user_id, "create_task");
+    tracker.flush().await.unwrap();
+    tracker.record_call(&user_id, "create_task");
+    tracker.flush().await.unwrap();
+
+    let restarted = QuotaUsageTracker::new(sink);
+    restarted.recover().await.unwrap();
+    assert_eq!(restarted.current_usage(&user_id, DAY), 3);
+}
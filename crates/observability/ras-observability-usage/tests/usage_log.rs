@@ -0,0 +1,176 @@
+//! Completed requests logged as JSON lines.
+
+use std::time::Duration;
+
+use http::{HeaderMap, HeaderValue};
+use ras_auth_core::AuthenticatedUser;
+use ras_observability_core::{
+    MethodDurationTracker, Protocol, RequestContext, RequestOutcome, UsageTracker,
+};
+use ras_observability_usage::{FileUsageTracker, REDACTED, read_usage_log};
+
+fn user(user_id: &str) -> AuthenticatedUser {
+    AuthenticatedUser {
+        user_id: user_id.to_string(),
+        permissions: Default::default(),
+        metadata: None,
+        kind: Default::default(),
+    }
+}
+
+async fn complete(tracker: &FileUsageTracker, method: &str, outcome: RequestOutcome) {
+    tracker
+        .track_completion(
+            &RequestContext::jsonrpc(method.to_string()),
+            None,
+            Duration::from_millis(12),
+            outcome,
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn completed_requests_are_logged_with_redacted_headers() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.log");
+    let tracker = FileUsageTracker::builder(&path)
+        .with_headers(["User-Agent", "authorization", "x-tenant", "x-request-id"])
+        .with_redacted_headers(["X-Tenant"])
+        .start()
+        .await
+        .unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.insert("user-agent", HeaderValue::from_static("curl/8.0"));
+    headers.insert("authorization", HeaderValue::from_static("Bearer secret"));
+    headers.insert("x-tenant", HeaderValue::from_static("acme"));
+    headers.insert("x-unselected", HeaderValue::from_static("ignored"));
+    let alice = user("alice");
+    let context = RequestContext::rest("GET", "/tasks");
+
+    tracker
+        .track_request(&headers, Some(&alice), &context)
+        .await;
+    tracker
+        .track_completion(
+            &context,
+            Some(&alice),
+            Duration::from_millis(250),
+            RequestOutcome::HandlerError,
+        )
+        .await;
+    // Without usage tracked first, there are no headers to log
+    tracker
+        .track_duration(
+            &RequestContext::jsonrpc("sync".to_string()),
+            None,
+            Duration::from_micros(1500),
+        )
+        .await;
+    tracker.flush().await;
+
+    let entries = read_usage_log(&path).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    let first = &entries[0];
+    assert_eq!(first.method, "GET /tasks");
+    assert_eq!(first.protocol, Protocol::Rest);
+    assert_eq!(first.user_id.as_deref(), Some("alice"));
+    assert_eq!(first.duration_ms, 250.0);
+    assert_eq!(first.outcome, Some(RequestOutcome::HandlerError));
+    assert_eq!(
+        first
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect::<Vec<_>>(),
+        [
+            ("authorization", REDACTED),
+            ("user-agent", "curl/8.0"),
+            ("x-tenant", REDACTED),
+        ]
+    );
+
+    let second = &entries[1];
+    assert_eq!(second.method, "sync");
+    assert_eq!(second.user_id, None);
+    assert_eq!(second.duration_ms, 1.5);
+    assert_eq!(second.outcome, None);
+    assert!(second.headers.is_empty());
+
+    let text = tokio::fs::read_to_string(&path).await.unwrap();
+    assert!(!text.contains("secret"));
+    assert!(!text.contains("acme"));
+}
+
+#[tokio::test]
+async fn files_are_rotated_by_size_and_age() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.log");
+    let tracker = FileUsageTracker::builder(&path)
+        .with_max_file_size(1)
+        .with_max_files(3)
+        .start()
+        .await
+        .unwrap();
+    for method in ["a", "b", "c", "d", "e"] {
+        complete(&tracker, method, RequestOutcome::Success).await;
+    }
+    tracker.flush().await;
+
+    // One entry per file, the two oldest deleted
+    let mut methods = Vec::new();
+    for file in ["usage.log.3", "usage.log.2", "usage.log.1", "usage.log"] {
+        let entries = read_usage_log(dir.path().join(file)).await.unwrap();
+        assert_eq!(entries.len(), 1, "{file}");
+        methods.push(entries[0].method.clone());
+    }
+    assert_eq!(methods, ["b", "c", "d", "e"]);
+    assert!(!dir.path().join("usage.log.4").exists());
+
+    let aged = dir.path().join("aged.log");
+    let tracker = FileUsageTracker::builder(&aged)
+        .with_rotation_interval(Duration::from_millis(50))
+        .start()
+        .await
+        .unwrap();
+    complete(&tracker, "before", RequestOutcome::Success).await;
+    complete(&tracker, "before", RequestOutcome::Success).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    complete(&tracker, "after", RequestOutcome::Success).await;
+    tracker.flush().await;
+
+    assert_eq!(
+        read_usage_log(dir.path().join("aged.log.1"))
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(read_usage_log(&aged).await.unwrap()[0].method, "after");
+}
+
+#[tokio::test]
+async fn entries_past_a_full_queue_are_dropped_and_counted() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.log");
+    let tracker = FileUsageTracker::builder(&path)
+        .with_queue_capacity(2)
+        .start()
+        .await
+        .unwrap();
+
+    // The writer can't run until this test awaits something that yields
+    for _ in 0..5 {
+        complete(&tracker, "burst", RequestOutcome::Success).await;
+    }
+    assert_eq!(tracker.dropped(), 3);
+
+    tracker.flush().await;
+    assert_eq!(read_usage_log(&path).await.unwrap().len(), 2);
+
+    // A line cut short by a crash is left out when reading
+    let mut text = tokio::fs::read_to_string(&path).await.unwrap();
+    text.push_str(r#"{"timestamp":"2026-10-16T09:00:00Z","meth"#);
+    tokio::fs::write(&path, text).await.unwrap();
+    assert_eq!(read_usage_log(&path).await.unwrap().len(), 2);
+}
@@ -206,8 +206,12 @@ fn generate_handlers(
                     quote! { #name, }
                 });
 
-                quote! {
-                    service.0.#method_name(#auth_arg #(#path_args)* multipart).await
+                let call = quote! { service.0.#method_name(#auth_arg #(#path_args)* multipart) };
+                match &endpoint.auth {
+                    AuthRequirement::Unauthorized => quote! { #call.await },
+                    AuthRequirement::WithPermissions(_) => quote! {
+                        ::ras_auth_core::scope_user(Some(::std::sync::Arc::new(user.clone())), #call).await
+                    },
                 }
             }
             Operation::Download => {
@@ -220,8 +224,12 @@ fn generate_handlers(
                     quote! { #name, }
                 });
 
-                quote! {
-                    service.0.#method_name(#auth_arg #(#path_args)*).await
+                let call = quote! { service.0.#method_name(#auth_arg #(#path_args)*) };
+                match &endpoint.auth {
+                    AuthRequirement::Unauthorized => quote! { #call.await },
+                    AuthRequirement::WithPermissions(_) => quote! {
+                        ::ras_auth_core::scope_user(Some(::std::sync::Arc::new(user.clone())), #call).await
+                    },
                 }
             }
         };
@@ -342,6 +350,7 @@ fn generate_auth_check(auth: &AuthRequirement) -> TokenStream {
                 user_id: String::new(),
                 permissions: ::std::collections::HashSet::new(),
                 metadata: None,
+                kind: ::ras_auth_core::PrincipalKind::User,
             };
         },
         AuthRequirement::WithPermissions(_) => quote! {
@@ -352,18 +361,14 @@ fn generate_auth_check(auth: &AuthRequirement) -> TokenStream {
                 ),
             };
 
-            let token = match parts.headers
-                .get(::axum::http::header::AUTHORIZATION)
-                .and_then(|h| h.to_str().ok())
-                .and_then(|h| h.strip_prefix("Bearer "))
-            {
+            let token = match auth_provider.credential(&parts.headers) {
                 Some(t) => t,
                 None => return <(::axum::http::StatusCode, &str) as ::axum::response::IntoResponse>::into_response(
                     (::axum::http::StatusCode::UNAUTHORIZED, "Missing or invalid authorization header")
                 ),
             };
 
-            let user = match auth_provider.authenticate(token.to_string()).await {
+            let user = match auth_provider.authenticate_request(token.to_string(), &parts.headers).await {
                 Ok(u) => u,
                 Err(_) => return <(::axum::http::StatusCode, &str) as ::axum::response::IntoResponse>::into_response(
                     (::axum::http::StatusCode::UNAUTHORIZED, "Invalid authentication")
@@ -37,7 +37,7 @@ mod tests {
     use axum::body::Body;
     use axum::extract::Multipart;
     use axum::response::{IntoResponse, Response};
-    use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+    use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
     use std::collections::HashSet;
 
     // Mock service implementation
@@ -130,6 +130,7 @@ mod tests {
                             .into_iter()
                             .collect::<HashSet<_>>(),
                         metadata: None,
+                        kind: PrincipalKind::User,
                     }),
                     "admin-token" => Ok(AuthenticatedUser {
                         user_id: "admin-456".to_string(),
@@ -141,6 +142,7 @@ mod tests {
                         .into_iter()
                         .collect::<HashSet<_>>(),
                         metadata: None,
+                        kind: PrincipalKind::User,
                     }),
                     _ => Err(AuthError::InvalidToken),
                 }
@@ -0,0 +1,120 @@
+//! Responses of generated REST services to failed authentication and
+//! authorization.
+
+use http::{HeaderMap, HeaderValue, StatusCode};
+use ras_auth_core::{AccessDenied, AuthError};
+
+/// The response to a request refused with `error`, as its status, headers
+/// and JSON body.
+///
+/// Insufficient permissions and locked accounts are `403 Forbidden`, rate
+/// limited attempts `429 Too Many Requests`, and other failures
+/// `401 Unauthorized`. The body carries `message` as its `error`, along with
+/// the error's [`code`](AuthError::code) and any details, and errors saying
+/// when to try again set `Retry-After`, in whole seconds.
+pub fn auth_failure(
+    error: &AuthError,
+    message: &str,
+) -> (StatusCode, HeaderMap, serde_json::Value) {
+    let status = match error {
+        AuthError::InsufficientPermissions { .. } | AuthError::AccountLocked { .. } => {
+            StatusCode::FORBIDDEN
+        }
+        AuthError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        _ => StatusCode::UNAUTHORIZED,
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Some(retry_after) = error.retry_after() {
+        let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        headers.insert(http::header::RETRY_AFTER, HeaderValue::from(seconds));
+    }
+
+    let mut body = serde_json::json!({
+        "error": message,
+        "code": error.code(),
+    });
+    if let Some(details) = error.details() {
+        body["details"] = details.into();
+    }
+
+    (status, headers, body)
+}
+
+/// The `403 Forbidden` response to a request an authorization callback
+/// refused, as its status and JSON body.
+///
+/// The body carries the `access_denied` code, with the reason and any details
+/// under `details`.
+pub fn access_denied(denied: &AccessDenied) -> (StatusCode, serde_json::Value) {
+    let body = serde_json::json!({
+        "error": "Access denied",
+        "code": AccessDenied::CODE,
+        "details": denied.response_details(),
+    });
+    (StatusCode::FORBIDDEN, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn failures_carry_their_code_status_and_retry_after() {
+        let (status, headers, body) =
+            auth_failure(&AuthError::TokenExpired, "Authentication failed");
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(headers.is_empty());
+        assert_eq!(
+            body,
+            serde_json::json!({ "error": "Authentication failed", "code": "token_expired" })
+        );
+
+        let (status, headers, body) = auth_failure(
+            &AuthError::AccountLocked {
+                retry_after: Some(Duration::from_millis(1500)),
+            },
+            "Authentication failed",
+        );
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(headers[http::header::RETRY_AFTER], "2");
+        assert_eq!(body["code"], "account_locked");
+
+        let (status, _, body) = auth_failure(
+            &AuthError::InsufficientPermissions {
+                required: vec!["admin".to_string()],
+                has: vec!["user".to_string()],
+            },
+            "Insufficient permissions",
+        );
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            body["details"],
+            serde_json::json!({ "required": ["admin"], "has": ["user"] })
+        );
+
+        // Internal details stay on the server
+        let (status, _, body) = auth_failure(
+            &AuthError::Internal("database down".to_string()),
+            "Authentication failed",
+        );
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(!body.to_string().contains("database"));
+    }
+
+    #[test]
+    fn access_denied_is_forbidden_with_its_reason() {
+        let (status, body) =
+            access_denied(&AccessDenied::new("not_owner").with_detail("owner", "u2"));
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": "Access denied",
+                "code": "access_denied",
+                "details": { "reason": "not_owner", "owner": "u2" }
+            })
+        );
+    }
+}
@@ -2,14 +2,50 @@
 //!
 //! This crate provides the runtime types needed for REST services, including:
 //! - `RestResult`, `RestResponse`, and `RestError` for explicit HTTP status code handling
+//! - `ResponseEnvelope` for generated clients that need response status and headers
+//! - `HttpError` for the failed responses of generated clients
+//! - `auth_failure` for the responses of generated services refusing a request
 //! - Re-exports of authentication types from `ras-auth-core`
+//! - Re-exports of the service metrics types from `ras-observability-core`
+
+use std::time::Duration;
 
 use thiserror::Error;
 
 // Re-export authentication types for convenience
-pub use ras_auth_core::{AuthError, AuthProvider, AuthResult, AuthenticatedUser};
+pub use ras_auth_core::{AccessDenied, AuthError, AuthProvider, AuthResult, AuthenticatedUser};
+pub use ras_manifest_core::{
+    AuthMode, MANIFEST_PATH, OperationManifest, ServiceManifest, ServiceProtocol, manifest_response,
+};
 pub use ras_version_core::*;
 
+// Rate limiting used by generated clients
+pub use ras_client_core::{
+    DEFAULT_RATE_LIMITED_ATTEMPTS, MAX_RETRY_AFTER, RateLimiter, TOO_MANY_REQUESTS,
+    rate_limited_backoff, rate_limited_delay, retry_after, retry_sleep, warn_rate_limited,
+};
+
+mod auth;
+pub use auth::{access_denied, auth_failure};
+
+mod spans;
+pub use spans::{record_span_outcome, set_span_parent, trace_context_headers};
+
+mod metrics;
+pub use metrics::{error_kind, record_request_completed, request_outcome};
+pub use ras_observability_core::{
+    CompositeMethodDurationTracker, CompositeUsageTracker, InFlightGuard, InvalidProxyNetwork,
+    MethodDurationTracker, Observability, ObservabilityBuilder, ObservabilityConfig, Protocol,
+    RequestContext, RequestOrigin, RequestOutcome, SampledUsageTracker, SamplingPolicy,
+    ServiceMetrics, SlowRequest, SlowRequestTracker, TenantExtractor, TrackerFailures,
+    TrackerGuard, TrackerHealth, TrackerStatus, TrustedProxies, UsageTracker, client_ip,
+    request_body_size, request_id,
+};
+
+// Re-exported so generated code can create spans without a direct `tracing` dependency.
+#[doc(hidden)]
+pub use tracing;
+
 /// Result type for REST handlers that allows explicit HTTP status codes.
 pub type RestResult<T> = Result<RestResponse<T>, RestError>;
 
@@ -20,22 +56,24 @@ pub struct RestResponse<T> {
     pub status: u16,
     /// Response body
     pub body: T,
+    /// Additional response headers (e.g. `ETag`, `Link`, rate-limit counters)
+    pub headers: Vec<(String, String)>,
 }
 
 impl<T> RestResponse<T> {
     /// Create a 200 OK response.
     pub fn ok(body: T) -> Self {
-        Self { status: 200, body }
+        Self::with_status(200, body)
     }
 
     /// Create a 201 Created response.
     pub fn created(body: T) -> Self {
-        Self { status: 201, body }
+        Self::with_status(201, body)
     }
 
     /// Create a 202 Accepted response.
     pub fn accepted(body: T) -> Self {
-        Self { status: 202, body }
+        Self::with_status(202, body)
     }
 
     /// Create a 204 No Content response (requires T to be ()).
@@ -43,15 +81,87 @@ impl<T> RestResponse<T> {
     where
         T: Default,
     {
-        Self {
-            status: 204,
-            body: T::default(),
-        }
+        Self::with_status(204, T::default())
     }
 
     /// Create a response with a custom status code.
     pub fn with_status(status: u16, body: T) -> Self {
-        Self { status, body }
+        Self {
+            status,
+            body,
+            headers: Vec::new(),
+        }
+    }
+
+    /// Add a response header.
+    ///
+    /// Headers with names or values that are not valid HTTP header syntax are
+    /// dropped by the generated server.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// Response body together with the HTTP status and headers, as returned by the
+/// `*_with_meta` methods of generated REST clients.
+#[derive(Debug, Clone)]
+pub struct ResponseEnvelope<T> {
+    /// Deserialized response body
+    pub body: T,
+    /// HTTP status code
+    pub status: u16,
+    /// Response headers
+    pub headers: http::HeaderMap,
+}
+
+impl<T> ResponseEnvelope<T> {
+    /// Get a header value as a string, if present and valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Discard the status and headers, returning only the body.
+    pub fn into_body(self) -> T {
+        self.body
+    }
+}
+
+/// A response with an error status, as returned by generated REST clients.
+///
+/// Callers can downcast the clients' errors to it to read the status, or the
+/// machine-readable code of a refused request, such as `"token_expired"` or
+/// `"account_locked"`, without matching the message.
+#[derive(Debug, Clone, Error)]
+#[error("HTTP error {status}: {body}")]
+pub struct HttpError {
+    /// HTTP status code
+    pub status: http::StatusCode,
+    /// Response body, as text
+    pub body: String,
+    /// How long the server asked the client to wait, from `Retry-After`
+    pub retry_after: Option<Duration>,
+    code: Option<String>,
+}
+
+impl HttpError {
+    /// Create the error for a response with `status`, `headers` and `body`.
+    pub fn new(status: http::StatusCode, headers: &http::HeaderMap, body: String) -> Self {
+        let code = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body.get("code")?.as_str().map(str::to_string));
+        Self {
+            status,
+            body,
+            retry_after: retry_after(headers),
+            code,
+        }
+    }
+
+    /// The machine-readable code of the error in the response body, such as
+    /// the [`AuthError::code`] of a refused request.
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
     }
 }
 
@@ -186,6 +296,60 @@ mod tests {
         assert_eq!(RestResponse::ok(42).body, 42);
     }
 
+    #[test]
+    fn rest_response_with_header_accumulates_headers() {
+        let resp = RestResponse::ok(1)
+            .with_header("ETag", "\"v1\"")
+            .with_header("X-RateLimit-Remaining", "9");
+        assert!(RestResponse::ok(1).headers.is_empty());
+        assert_eq!(
+            resp.headers,
+            vec![
+                ("ETag".to_string(), "\"v1\"".to_string()),
+                ("X-RateLimit-Remaining".to_string(), "9".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn response_envelope_header_lookup() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("x-total-count", http::HeaderValue::from_static("3"));
+        let envelope = ResponseEnvelope {
+            body: "b",
+            status: 200,
+            headers,
+        };
+        assert_eq!(envelope.header("X-Total-Count"), Some("3"));
+        assert_eq!(envelope.header("missing"), None);
+        assert_eq!(envelope.into_body(), "b");
+    }
+
+    #[test]
+    fn http_error_parses_code_and_retry_after() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::RETRY_AFTER,
+            http::HeaderValue::from_static("30"),
+        );
+        let err = HttpError::new(
+            http::StatusCode::FORBIDDEN,
+            &headers,
+            r#"{"error":"Authentication failed","code":"account_locked"}"#.to_string(),
+        );
+        assert_eq!(err.code(), Some("account_locked"));
+        assert_eq!(err.retry_after, Some(Duration::from_secs(30)));
+        assert!(err.to_string().starts_with("HTTP error 403 Forbidden: "));
+
+        let err = HttpError::new(
+            http::StatusCode::BAD_GATEWAY,
+            &http::HeaderMap::new(),
+            "upstream down".to_string(),
+        );
+        assert_eq!(err.code(), None);
+        assert_eq!(err.retry_after, None);
+    }
+
     #[test]
     fn rest_error_constructors_set_correct_status_and_message() {
         let cases = [
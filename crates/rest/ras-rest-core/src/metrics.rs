@@ -0,0 +1,54 @@
+//! Service metrics for generated REST handlers.
+
+use std::time::Duration;
+
+use http::StatusCode;
+use ras_observability_core::{RequestContext, RequestOutcome, ServiceMetrics};
+
+/// Error kind reported for a failed request, derived from its status code.
+///
+/// Returns `None` for successful (1xx-3xx) responses.
+pub fn error_kind(status: StatusCode) -> Option<&'static str> {
+    if !status.is_client_error() && !status.is_server_error() {
+        return None;
+    }
+
+    Some(match status {
+        StatusCode::UNAUTHORIZED => "unauthenticated",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => "invalid_params",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::PAYLOAD_TOO_LARGE => "request_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => "timeout",
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => "server_busy",
+        _ => "handler_error",
+    })
+}
+
+/// Outcome of a request answered with `status`, from its [`error_kind`].
+pub fn request_outcome(status: StatusCode) -> RequestOutcome {
+    RequestOutcome::from_error_kind(error_kind(status))
+}
+
+/// Report a completed request to `metrics`.
+///
+/// Client and server errors count as failures and are tagged with an
+/// `error_kind` metadata entry (see [`error_kind`]); the duration and the
+/// status (see [`RequestContext::with_http_status`]) are recorded either way.
+pub fn record_request_completed(
+    metrics: &dyn ServiceMetrics,
+    context: RequestContext,
+    status: StatusCode,
+    duration: Duration,
+) {
+    let kind = error_kind(status);
+    let context = context.with_http_status(status.as_u16());
+    let context = match kind {
+        Some(kind) => context.with_metadata("error_kind", kind),
+        None => context,
+    };
+
+    metrics.increment_requests_completed(&context, kind.is_none());
+    metrics.record_method_duration(&context, duration);
+}
@@ -0,0 +1,62 @@
+//! Helpers for the request spans created by generated REST handlers.
+
+use std::time::Duration;
+
+use http::StatusCode;
+
+/// Records the outcome of a handled request on its span.
+///
+/// Sets `permission`, `outcome`, `http.status_code` and `duration_ms`. Server
+/// errors additionally mark the span as failed through `otel.status_code`.
+pub fn record_span_outcome(
+    span: &tracing::Span,
+    status: StatusCode,
+    requires_auth: bool,
+    duration: Duration,
+) {
+    let permission = match status {
+        StatusCode::UNAUTHORIZED => "unauthenticated",
+        StatusCode::FORBIDDEN => "denied",
+        _ if requires_auth => "granted",
+        _ => "not_required",
+    };
+    let outcome = if status.is_client_error() || status.is_server_error() {
+        "error"
+    } else {
+        "success"
+    };
+
+    span.record("permission", permission);
+    span.record("outcome", outcome);
+    span.record("http.status_code", status.as_u16());
+    span.record("duration_ms", duration.as_millis() as u64);
+
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+/// Trace context headers for an outgoing request, used by generated clients.
+///
+/// Carries the current span's W3C trace context when the `otel` feature is
+/// enabled and is empty otherwise.
+pub fn trace_context_headers() -> Vec<(String, String)> {
+    #[cfg(feature = "otel")]
+    {
+        ras_observability_core::trace_context_headers()
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        Vec::new()
+    }
+}
+
+/// Makes the caller's span, sent in the `traceparent` header, the parent of `span`.
+///
+/// Only has an effect with the `otel` feature enabled.
+pub fn set_span_parent(span: &tracing::Span, headers: &http::HeaderMap) {
+    #[cfg(feature = "otel")]
+    ras_observability_core::set_span_parent(span, headers);
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, headers);
+}
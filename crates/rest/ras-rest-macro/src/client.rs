@@ -79,6 +79,8 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
             base_path: String,
             bearer_token: Option<String>,
             default_timeout: Option<std::time::Duration>,
+            rate_limiter: Option<ras_rest_core::RateLimiter>,
+            rate_limited_attempts: u32,
         }
 
         #[cfg(feature = "client")]
@@ -86,6 +88,8 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
         pub struct #client_builder_name {
             server_url: String,
             timeout: Option<std::time::Duration>,
+            rate_limiter: Option<ras_rest_core::RateLimiter>,
+            rate_limited_attempts: u32,
         }
 
         #[cfg(feature = "client")]
@@ -95,6 +99,8 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
                 Self {
                     server_url: server_url.into(),
                     timeout: None,
+                    rate_limiter: None,
+                    rate_limited_attempts: ras_rest_core::DEFAULT_RATE_LIMITED_ATTEMPTS,
                 }
             }
 
@@ -104,6 +110,27 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
                 self
             }
 
+            /// Send at most `requests_per_second` requests, spaced evenly
+            ///
+            /// Clones of the client share the budget.
+            pub fn with_rate_limit(mut self, requests_per_second: u32) -> Self {
+                self.rate_limiter = Some(ras_rest_core::RateLimiter::new(requests_per_second));
+                self
+            }
+
+            /// Share `limiter`'s request budget, e.g. with clients of other services
+            pub fn with_rate_limiter(mut self, limiter: ras_rest_core::RateLimiter) -> Self {
+                self.rate_limiter = Some(limiter);
+                self
+            }
+
+            /// Set how many attempts, including the first, a call may spend on
+            /// responses with HTTP 429. Defaults to 3; `1` disables the retries.
+            pub fn with_rate_limited_attempts(mut self, attempts: u32) -> Self {
+                self.rate_limited_attempts = attempts.max(1);
+                self
+            }
+
             /// Build the client
             ///
             /// # Errors
@@ -126,6 +153,8 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
                     base_path: #base_path.to_string(),
                     bearer_token: None,
                     default_timeout: self.timeout,
+                    rate_limiter: self.rate_limiter,
+                    rate_limited_attempts: self.rate_limited_attempts,
                 })
             }
 
@@ -144,6 +173,8 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
                     base_path: #base_path.to_string(),
                     bearer_token: None,
                     default_timeout: self.timeout,
+                    rate_limiter: self.rate_limiter,
+                    rate_limited_attempts: self.rate_limited_attempts,
                 })
             }
         }
@@ -280,12 +311,20 @@ fn generate_client_method(
     }
 
     let method_name_with_timeout = quote::format_ident!("{}_with_timeout", method_name);
+    let method_name_with_meta = quote::format_ident!("{}_with_meta", method_name);
+    let method_name_with_meta_and_timeout =
+        quote::format_ident!("{}_with_meta_and_timeout", method_name);
 
     quote! {
         /// Call the #method_name endpoint
         pub async fn #method_name(&self, #(#params),*) -> Result<#response_type, Box<dyn std::error::Error + Send + Sync>> {
             self.#method_name_with_timeout(#(#call_args,)* None).await
         }
+
+        /// Call the #method_name endpoint, returning the response status and headers alongside the body
+        pub async fn #method_name_with_meta(&self, #(#params),*) -> Result<ras_rest_core::ResponseEnvelope<#response_type>, Box<dyn std::error::Error + Send + Sync>> {
+            self.#method_name_with_meta_and_timeout(#(#call_args,)* None).await
+        }
     }
 }
 
@@ -300,6 +339,9 @@ fn generate_client_method_with_timeout(
     response_type: &Type,
 ) -> proc_macro2::TokenStream {
     let method_name_with_timeout = quote::format_ident!("{}_with_timeout", method_name);
+    let method_name_with_meta_and_timeout =
+        quote::format_ident!("{}_with_meta_and_timeout", method_name);
+    let method_name_str = method_name.to_string();
     let http_method = match method {
         HttpMethod::Get => quote! { reqwest::Method::GET },
         HttpMethod::Post => quote! { reqwest::Method::POST },
@@ -310,6 +352,7 @@ fn generate_client_method_with_timeout(
 
     // Build function parameters
     let mut params = Vec::new();
+    let mut call_args = Vec::new();
     let mut path_substitutions = Vec::new();
     let mut param_names = Vec::new();
     // Build URL construction with proper joining
@@ -322,6 +365,7 @@ fn generate_client_method_with_timeout(
         let param_name = &path_param.name;
         let param_type = &path_param.param_type;
         params.push(quote! { #param_name: #param_type });
+        call_args.push(quote! { #param_name });
         param_names.push(param_name);
 
         // Handle path parameter substitution
@@ -386,11 +430,13 @@ fn generate_client_method_with_timeout(
         let param_name = &query_param.name;
         let param_type = &query_param.param_type;
         params.push(quote! { #param_name: #param_type });
+        call_args.push(quote! { #param_name });
     }
 
     // Add request body parameter if present
     let request_body_handling = if let Some(request_type) = request_type {
         params.push(quote! { body: #request_type });
+        call_args.push(quote! { body });
         quote! {
             request_builder = request_builder.json(&body);
         }
@@ -401,26 +447,26 @@ fn generate_client_method_with_timeout(
     // Check if response type is unit type ()
     let is_unit_type = quote!(#response_type).to_string() == "()";
 
-    let response_handling = if is_unit_type {
-        quote! {
-            if response.status().is_success() {
-                Ok(())
-            } else {
-                let status = response.status();
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                Err(format!("HTTP error {}: {}", status, error_text).into())
-            }
-        }
+    let body_handling = if is_unit_type {
+        quote! { () }
     } else {
-        quote! {
-            if response.status().is_success() {
-                let result = response.json().await?;
-                Ok(result)
-            } else {
-                let status = response.status();
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                Err(format!("HTTP error {}: {}", status, error_text).into())
-            }
+        quote! { response.json().await? }
+    };
+
+    let response_handling = quote! {
+        let status = response.status();
+        if status.is_success() {
+            let headers = response.headers().clone();
+            Ok(ras_rest_core::ResponseEnvelope {
+                body: #body_handling,
+                status: status.as_u16(),
+                headers,
+            })
+        } else {
+            // Callers can downcast to `HttpError` to read the status and error code
+            let headers = response.headers().clone();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            Err(ras_rest_core::HttpError::new(status, &headers, error_text).into())
         }
     };
 
@@ -431,6 +477,19 @@ fn generate_client_method_with_timeout(
             #(#params,)*
             timeout: Option<std::time::Duration>
         ) -> Result<#response_type, Box<dyn std::error::Error + Send + Sync>> {
+            self.#method_name_with_meta_and_timeout(#(#call_args,)* timeout)
+                .await
+                .map(ras_rest_core::ResponseEnvelope::into_body)
+        }
+
+        /// Call the #method_name endpoint with a custom timeout, returning the response status and headers alongside the body
+        ///
+        /// Responses with HTTP 429 are retried after the server's `Retry-After`.
+        pub async fn #method_name_with_meta_and_timeout(
+            &self,
+            #(#params,)*
+            timeout: Option<std::time::Duration>
+        ) -> Result<ras_rest_core::ResponseEnvelope<#response_type>, Box<dyn std::error::Error + Send + Sync>> {
             let url = #url_construction;
 
             let mut request_builder = self.client
@@ -441,6 +500,11 @@ fn generate_client_method_with_timeout(
                 request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
             }
 
+            // Propagate the current trace context (with the `otel` feature)
+            for (name, value) in ras_rest_core::trace_context_headers() {
+                request_builder = request_builder.header(name, value);
+            }
+
             #query_handling
 
             #request_body_handling
@@ -451,7 +515,43 @@ fn generate_client_method_with_timeout(
                 request_builder = request_builder.timeout(timeout);
             }
 
-            let response = request_builder.send().await?;
+            // The call's timeout is a deadline shared by all attempts (not supported in WASM builds)
+            #[cfg(not(target_arch = "wasm32"))]
+            let deadline = timeout
+                .or(self.default_timeout)
+                .map(|timeout| std::time::Instant::now() + timeout);
+
+            let mut attempt = 1;
+            let response = loop {
+                if let Some(limiter) = &self.rate_limiter {
+                    limiter.acquire().await;
+                }
+
+                // Bodies that can't be replayed are sent once
+                let Some(attempt_builder) = request_builder.try_clone() else {
+                    break request_builder.send().await?;
+                };
+                let response = attempt_builder.send().await?;
+                if response.status().as_u16() != ras_rest_core::TOO_MANY_REQUESTS || attempt >= self.rate_limited_attempts {
+                    break response;
+                }
+
+                let retry_after = ras_rest_core::retry_after(response.headers());
+                let Some(delay) = ras_rest_core::rate_limited_delay(retry_after, ras_rest_core::rate_limited_backoff(attempt)) else {
+                    break response;
+                };
+                #[cfg(not(target_arch = "wasm32"))]
+                if deadline.is_some_and(|deadline| std::time::Instant::now() + delay >= deadline) {
+                    break response;
+                }
+
+                ras_rest_core::warn_rate_limited(#method_name_str, delay, attempt);
+                if let Some(limiter) = &self.rate_limiter {
+                    limiter.defer(delay);
+                }
+                ras_rest_core::retry_sleep(delay).await;
+                attempt += 1;
+            };
 
             #response_handling
         }
@@ -3,6 +3,7 @@ use quote::quote;
 use syn::{Ident, LitStr, Token, Type, parse::Parse, parse_macro_input};
 
 mod client;
+mod manifest;
 mod openapi;
 mod static_hosting;
 
@@ -50,6 +51,7 @@ mod static_hosting;
 ///     openapi: true,
 ///     serve_docs: true,
 ///     docs_path: "/docs",
+///     docs_auth: WITH_PERMISSIONS(["docs"]),
 ///     ui_theme: "default",
 ///     endpoints: [
 ///         GET UNAUTHORIZED users() -> UsersResponse,
@@ -180,7 +182,151 @@ struct QueryParam {
 #[derive(Debug)]
 enum AuthRequirement {
     Unauthorized,
-    WithPermissions(Vec<Vec<String>>), // Vec of permission groups - OR between groups, AND within groups
+    WithPermissions(PermissionRequirement),
+}
+
+/// The permissions `WITH_PERMISSIONS(...)` asks of the user
+#[derive(Debug, Clone)]
+enum PermissionRequirement {
+    /// `[...] | [...]`: OR between groups, AND within groups
+    Groups(Vec<Vec<String>>),
+    /// `expr = "..."`, parsed when the macro expands
+    Expression(ras_auth_core::PermissionExpr),
+}
+
+/// Parse `expr = "..."` inside `WITH_PERMISSIONS(...)`, reporting invalid
+/// expressions at the string literal
+fn parse_permission_expression(
+    input: syn::parse::ParseStream,
+) -> syn::Result<ras_auth_core::PermissionExpr> {
+    let key = input.parse::<Ident>()?;
+    if key != "expr" {
+        return Err(syn::Error::new(
+            key.span(),
+            "Expected `expr = \"...\"` or permission groups like [\"admin\"]",
+        ));
+    }
+    let _ = input.parse::<Token![=]>()?;
+    let expression = input.parse::<LitStr>()?;
+    ras_auth_core::PermissionExpr::parse(&expression.value()).map_err(|error| {
+        syn::Error::new(
+            expression.span(),
+            format!("invalid permission expression: {error}"),
+        )
+    })
+}
+
+/// Code building `expression` at runtime
+fn permission_expr_code(expression: &ras_auth_core::PermissionExpr) -> proc_macro2::TokenStream {
+    use ras_auth_core::PermissionExpr;
+    match expression {
+        PermissionExpr::Permission(permission) => {
+            quote! { ::ras_auth_core::PermissionExpr::Permission(#permission.to_string()) }
+        }
+        PermissionExpr::Not(inner) => {
+            let inner = permission_expr_code(inner);
+            quote! { ::ras_auth_core::PermissionExpr::Not(Box::new(#inner)) }
+        }
+        PermissionExpr::All(items) => {
+            let items = items.iter().map(permission_expr_code);
+            quote! { ::ras_auth_core::PermissionExpr::All(vec![#(#items),*]) }
+        }
+        PermissionExpr::Any(items) => {
+            let items = items.iter().map(permission_expr_code);
+            quote! { ::ras_auth_core::PermissionExpr::Any(vec![#(#items),*]) }
+        }
+    }
+}
+
+/// Code evaluating to a `&'static PermissionExpr`, built on first use
+fn static_permission_expr_code(
+    expression: &ras_auth_core::PermissionExpr,
+) -> proc_macro2::TokenStream {
+    let expression_code = permission_expr_code(expression);
+    quote! {
+        {
+            static REQUIRED_PERMISSIONS: ::std::sync::LazyLock<::ras_auth_core::PermissionExpr> =
+                ::std::sync::LazyLock::new(|| #expression_code);
+            &*REQUIRED_PERMISSIONS
+        }
+    }
+}
+
+impl Parse for AuthRequirement {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        // Parse UNAUTHORIZED, WITH_PERMISSIONS([...] | [...]) or WITH_PERMISSIONS(expr = "...")
+        let auth = if input.peek(syn::Ident) {
+            let auth_ident = input.parse::<Ident>()?;
+            match auth_ident.to_string().as_str() {
+                "UNAUTHORIZED" => AuthRequirement::Unauthorized,
+                "WITH_PERMISSIONS" => {
+                    // Parse ([...] | [...] | ...)
+                    let perms_content;
+                    syn::parenthesized!(perms_content in input);
+
+                    if perms_content.peek(syn::Ident) {
+                        let expression = parse_permission_expression(&perms_content)?;
+                        return Ok(AuthRequirement::WithPermissions(
+                            PermissionRequirement::Expression(expression),
+                        ));
+                    }
+
+                    let mut permission_groups = Vec::new();
+
+                    // Parse first permission group
+                    let first_group_content;
+                    syn::bracketed!(first_group_content in perms_content);
+
+                    let mut first_group = Vec::new();
+                    while !first_group_content.is_empty() {
+                        let perm = first_group_content.parse::<LitStr>()?;
+                        first_group.push(perm.value());
+
+                        if first_group_content.peek(Token![,]) {
+                            let _ = first_group_content.parse::<Token![,]>()?;
+                        }
+                    }
+                    permission_groups.push(first_group);
+
+                    // Parse additional permission groups separated by |
+                    while perms_content.peek(Token![|]) {
+                        let _ = perms_content.parse::<Token![|]>()?;
+
+                        let group_content;
+                        syn::bracketed!(group_content in perms_content);
+
+                        let mut group = Vec::new();
+                        while !group_content.is_empty() {
+                            let perm = group_content.parse::<LitStr>()?;
+                            group.push(perm.value());
+
+                            if group_content.peek(Token![,]) {
+                                let _ = group_content.parse::<Token![,]>()?;
+                            }
+                        }
+                        permission_groups.push(group);
+                    }
+
+                    AuthRequirement::WithPermissions(PermissionRequirement::Groups(
+                        permission_groups,
+                    ))
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        auth_ident.span(),
+                        "Expected UNAUTHORIZED or WITH_PERMISSIONS",
+                    ));
+                }
+            }
+        } else {
+            return Err(syn::Error::new(
+                input.span(),
+                "Expected authentication requirement",
+            ));
+        };
+
+        Ok(auth)
+    }
 }
 
 const DOC_COMMENT_EXPECTED: &str = "Expected doc comment in the form `/// ...`";
@@ -242,7 +388,7 @@ impl Parse for ServiceDefinition {
         let base_path = base_path_lit.value();
         let _ = content.parse::<Token![,]>()?;
 
-        // Parse optional fields (openapi, serve_docs, docs_path, ui_theme)
+        // Parse optional fields (openapi, serve_docs, docs_path, docs_auth, ui_theme)
         let mut openapi = None;
         let mut static_hosting = static_hosting::StaticHostingConfig::default();
 
@@ -284,6 +430,14 @@ impl Parse for ServiceDefinition {
                 let path = content.parse::<LitStr>()?;
                 static_hosting.docs_path = path.value();
                 let _ = content.parse::<Token![,]>()?;
+            } else if field_name == "docs_auth" {
+                let _ = content.parse::<Ident>()?; // "docs_auth"
+                let _ = content.parse::<Token![:]>()?;
+                static_hosting.docs_auth = match content.parse::<AuthRequirement>()? {
+                    AuthRequirement::Unauthorized => None,
+                    AuthRequirement::WithPermissions(requirement) => Some(requirement),
+                };
+                let _ = content.parse::<Token![,]>()?;
             } else if field_name == "ui_theme" {
                 let _ = content.parse::<Ident>()?; // "ui_theme"
                 let _ = content.parse::<Token![:]>()?;
@@ -428,67 +582,7 @@ impl Parse for EndpointDefinition {
             }
         };
 
-        // Parse auth requirement (UNAUTHORIZED or WITH_PERMISSIONS([...]))
-        let auth = if input.peek(syn::Ident) {
-            let auth_ident = input.parse::<Ident>()?;
-            match auth_ident.to_string().as_str() {
-                "UNAUTHORIZED" => AuthRequirement::Unauthorized,
-                "WITH_PERMISSIONS" => {
-                    // Parse ([...] | [...] | ...)
-                    let perms_content;
-                    syn::parenthesized!(perms_content in input);
-
-                    let mut permission_groups = Vec::new();
-
-                    // Parse first permission group
-                    let first_group_content;
-                    syn::bracketed!(first_group_content in perms_content);
-
-                    let mut first_group = Vec::new();
-                    while !first_group_content.is_empty() {
-                        let perm = first_group_content.parse::<LitStr>()?;
-                        first_group.push(perm.value());
-
-                        if first_group_content.peek(Token![,]) {
-                            let _ = first_group_content.parse::<Token![,]>()?;
-                        }
-                    }
-                    permission_groups.push(first_group);
-
-                    // Parse additional permission groups separated by |
-                    while perms_content.peek(Token![|]) {
-                        let _ = perms_content.parse::<Token![|]>()?;
-
-                        let group_content;
-                        syn::bracketed!(group_content in perms_content);
-
-                        let mut group = Vec::new();
-                        while !group_content.is_empty() {
-                            let perm = group_content.parse::<LitStr>()?;
-                            group.push(perm.value());
-
-                            if group_content.peek(Token![,]) {
-                                let _ = group_content.parse::<Token![,]>()?;
-                            }
-                        }
-                        permission_groups.push(group);
-                    }
-
-                    AuthRequirement::WithPermissions(permission_groups)
-                }
-                _ => {
-                    return Err(syn::Error::new(
-                        auth_ident.span(),
-                        "Expected UNAUTHORIZED or WITH_PERMISSIONS",
-                    ));
-                }
-            }
-        } else {
-            return Err(syn::Error::new(
-                input.span(),
-                "Expected authentication requirement",
-            ));
-        };
+        let auth = input.parse::<AuthRequirement>()?;
 
         // Parse path with potential path parameters (e.g., users/{id: String}/posts/{post_id: i32})
         let (path, path_params, handler_name_parts) = parse_endpoint_path(input)?;
@@ -677,6 +771,12 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
     // Generate client code
     let client_code = crate::client::generate_client_code(&service_def);
 
+    let manifest_code = manifest::generate_manifest_code(&service_def);
+    let manifest_fn_name = quote::format_ident!(
+        "{}_service_manifest",
+        service_name.to_string().to_lowercase()
+    );
+
     // Generate trait methods
     let trait_methods = service_def.endpoints.iter().map(|endpoint| {
         let handler_name = &endpoint.handler_name;
@@ -718,6 +818,20 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
 
     // No more individual handler fields - we'll store the service implementation instead
 
+    // Authorization callbacks of WITH_PERMISSIONS endpoints
+    let mut authorize_fields = Vec::new();
+    let mut authorize_setters = Vec::new();
+    let mut authorize_inits = Vec::new();
+    for (field, setter, init) in service_def
+        .endpoints
+        .iter()
+        .filter_map(rest_authorize_builder_code)
+    {
+        authorize_fields.push(field);
+        authorize_setters.push(setter);
+        authorize_inits.push(init);
+    }
+
     let request_part_structs = generate_rest_request_part_structs(&service_def);
 
     let mut query_structs: Vec<proc_macro2::TokenStream> = Vec::new();
@@ -774,6 +888,14 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
             auth_provider: Option<std::sync::Arc<dyn ras_auth_core::AuthProvider>>,
             with_usage_tracker: Option<std::sync::Arc<dyn Fn(&axum::http::HeaderMap, Option<&ras_auth_core::AuthenticatedUser>, &str, &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> + Send + Sync>>,
             with_method_duration_tracker: Option<std::sync::Arc<dyn Fn(&str, &str, Option<&ras_auth_core::AuthenticatedUser>, std::time::Duration) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> + Send + Sync>>,
+            service_metrics: Option<std::sync::Arc<dyn ras_rest_core::ServiceMetrics>>,
+            completion_tracker: Option<std::sync::Arc<dyn ras_rest_core::MethodDurationTracker>>,
+            tracker_guard: ras_rest_core::TrackerGuard,
+            trusted_proxies: ras_rest_core::TrustedProxies,
+            tenant_extractor: Option<ras_rest_core::TenantExtractor>,
+            tracing_spans: bool,
+            manifest_permission: Option<String>,
+            #(#authorize_fields)*
         }
 
         #[cfg(feature = "server")]
@@ -787,6 +909,8 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
 
         #static_hosting_code
 
+        #manifest_code
+
         // Define query parameter structs
         #[cfg(feature = "server")]
         use self::query_params::*;
@@ -811,6 +935,14 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
                     auth_provider: None,
                     with_usage_tracker: None,
                     with_method_duration_tracker: None,
+                    service_metrics: None,
+                    completion_tracker: None,
+                    tracker_guard: ras_rest_core::TrackerGuard::new(),
+                    trusted_proxies: ras_rest_core::TrustedProxies::none(),
+                    tenant_extractor: None,
+                    tracing_spans: false,
+                    manifest_permission: None,
+                    #(#authorize_inits)*
                 }
             }
 
@@ -833,8 +965,10 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
                 self
             }
 
-            /// Set the method duration tracker - called after each request completes
-            /// The tracker receives the HTTP method, path, authenticated user (if any), and execution duration
+            /// Set the method duration tracker - called after each request completes, those
+            /// refused for a missing or invalid token, missing permissions or invalid JSON
+            /// included. The tracker receives the HTTP method, path, authenticated user (if any)
+            /// and how long the request took, timed from when it was received
             pub fn with_method_duration_tracker<F, Fut>(mut self, tracker: F) -> Self
             where
                 F: Fn(&str, &str, Option<&ras_auth_core::AuthenticatedUser>, std::time::Duration) -> Fut + Send + Sync + 'static,
@@ -846,12 +980,137 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
                 self
             }
 
+            /// Report each request to `metrics`: started before the handler runs, then
+            /// completed with its duration and whether it succeeded. Client and server
+            /// errors count as failures and carry an `error_kind` metadata entry.
+            pub fn with_service_metrics(mut self, metrics: std::sync::Arc<dyn ras_rest_core::ServiceMetrics>) -> Self {
+                self.service_metrics = Some(metrics);
+                self
+            }
+
+            /// Report each completed request to `tracker` with how it ended, through
+            /// `MethodDurationTracker::track_completion`, under the same method as the
+            /// service metrics: failures before the handler runs included. It is
+            /// installed through `with_observability_config`, replacing any completion
+            /// tracker set before, and is replaced by any set after.
+            pub fn with_completion_tracker(self, tracker: std::sync::Arc<dyn ras_rest_core::MethodDurationTracker>) -> Self {
+                self.with_observability_config(ras_rest_core::ObservabilityConfig::default().with_completion_tracker(tracker))
+            }
+
+            /// Call the trackers through `guard`, rather than a guard of the service's
+            /// own with the default timeout. The guard catches tracker panics, cancels
+            /// trackers outliving its timeout, and counts both in the service metrics
+            /// as `usage_tracker`, `method_duration_tracker` or `completion_tracker`;
+            /// keep a clone to report its `health` from a readiness check.
+            pub fn with_tracker_guard(mut self, guard: ras_rest_core::TrackerGuard) -> Self {
+                self.tracker_guard = guard;
+                self
+            }
+
+            /// Believe the `X-Forwarded-For` addresses appended by `proxies` when
+            /// setting the client address of request contexts. By default none are
+            /// trusted and the client is the address that connected, known when the
+            /// router is served with `into_make_service_with_connect_info::<SocketAddr>()`.
+            pub fn with_trusted_proxies(mut self, proxies: ras_rest_core::TrustedProxies) -> Self {
+                self.trusted_proxies = proxies;
+                self
+            }
+
+            /// Record the tenant `extractor` names on the contexts given to the service
+            /// metrics, which may label their counters by it. It is called with the
+            /// request headers when a request starts, and with its authenticated caller
+            /// as well when it completes.
+            pub fn with_tenant_extractor<F>(mut self, extractor: F) -> Self
+            where
+                F: Fn(&axum::http::HeaderMap, Option<&ras_auth_core::AuthenticatedUser>, &ras_rest_core::RequestContext) -> Option<String> + Send + Sync + 'static,
+            {
+                self.tenant_extractor = Some(std::sync::Arc::new(extractor));
+                self
+            }
+
+            /// Report to `observability`, such as an `OtelSetup`: its service metrics
+            /// as with `with_service_metrics`, its usage tracker with a REST
+            /// `RequestContext` of the method and path, and its duration tracker as
+            /// with `with_completion_tracker`. It is installed through `with_observability_config`.
+            pub fn with_observability(self, observability: &dyn ras_rest_core::Observability) -> Self {
+                self.with_observability_config(observability)
+            }
+
+            /// Report to what `config`, an `ObservabilityConfig` or the
+            /// `ObservabilityBuilder` making one, sets: its service metrics, its usage
+            /// tracker, its duration tracker as the completion tracker and its tenant
+            /// extractor. `with_observability` and `with_completion_tracker` install
+            /// theirs through it. The service has one of each, so what the last call
+            /// sets wins, and what it doesn't set is left as it was. Clones of a config
+            /// share its trackers, so one config can drive several services.
+            pub fn with_observability_config(mut self, config: impl Into<ras_rest_core::ObservabilityConfig>) -> Self {
+                let config = config.into();
+                if let Some(metrics) = config.service_metrics.clone() {
+                    self.service_metrics = Some(metrics);
+                }
+                if let Some(tracker) = config.shared_usage_tracker() {
+                    self = self.with_context_usage_tracker(tracker);
+                }
+                if let Some(tracker) = config.shared_method_duration_tracker() {
+                    self.completion_tracker = Some(tracker);
+                }
+                if let Some(extractor) = config.tenant_extractor {
+                    self.tenant_extractor = Some(extractor);
+                }
+                self
+            }
+
+            /// Call `tracker` before each request with a REST `RequestContext` of
+            /// the method and path
+            fn with_context_usage_tracker(self, tracker: std::sync::Arc<dyn ras_rest_core::UsageTracker>) -> Self {
+                self.with_usage_tracker(move |headers, user, method, path| {
+                    let tracker = tracker.clone();
+                    let headers = headers.clone();
+                    let user = user.cloned();
+                    let context = ras_rest_core::RequestContext::rest(method, path)
+                        .with_request_info(&headers)
+                        .with_request_size(ras_rest_core::request_body_size(&headers));
+                    async move {
+                        ras_rest_core::UsageTracker::track_request(&*tracker, &headers, user.as_ref(), &context).await;
+                    }
+                })
+            }
+
+            /// Run each request inside a `tracing` span named after the handler.
+            /// Spans carry the protocol, route, request id (`x-request-id`), user id,
+            /// permission result, outcome, status and duration. Disabled by default.
+            pub fn with_tracing_spans(mut self, enabled: bool) -> Self {
+                self.tracing_spans = enabled;
+                self
+            }
+
+            /// Serve the service manifest as JSON on `GET {base_path}/_manifest` to callers
+            /// whose token carries `permission`. Requires an auth provider.
+            pub fn with_manifest_route(mut self, permission: impl Into<String>) -> Self {
+                self.manifest_permission = Some(permission.into());
+                self
+            }
+
+            #(#authorize_setters)*
+
             /// Build the axum router for the REST service
             pub fn build(self) -> axum::Router {
                 let mut router = axum::Router::new();
 
                 #(#route_registrations)*
 
+                // Serve the service manifest if enabled
+                if let Some(permission) = self.manifest_permission.clone() {
+                    let auth_provider = self.auth_provider.clone();
+                    router = router.route(ras_rest_core::MANIFEST_PATH, axum::routing::get(move |headers: axum::http::HeaderMap| {
+                        let auth_provider = auth_provider.clone();
+                        let permission = permission.clone();
+                        async move {
+                            ras_rest_core::manifest_response(&#manifest_fn_name(), &headers, auth_provider.as_deref(), &permission).await
+                        }
+                    }));
+                }
+
                 // Add static hosting routes if enabled
                 #static_routes
 
@@ -872,8 +1131,8 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
 
 fn rest_permission_groups_code(auth: &AuthRequirement) -> proc_macro2::TokenStream {
     let permission_groups = match auth {
-        AuthRequirement::Unauthorized => Vec::new(),
-        AuthRequirement::WithPermissions(groups) => groups.clone(),
+        AuthRequirement::WithPermissions(PermissionRequirement::Groups(groups)) => groups.clone(),
+        _ => Vec::new(),
     };
 
     if permission_groups.is_empty() {
@@ -887,6 +1146,180 @@ fn rest_permission_groups_code(auth: &AuthRequirement) -> proc_macro2::TokenStre
     }
 }
 
+/// The permission check of a `WITH_PERMISSIONS` handler, run once `user` is
+/// authenticated
+fn rest_permission_check_code(auth: &AuthRequirement) -> proc_macro2::TokenStream {
+    match auth {
+        AuthRequirement::WithPermissions(PermissionRequirement::Expression(expression)) => {
+            let expression_code = static_permission_expr_code(expression);
+            quote! {
+                let required_permissions: &ras_auth_core::PermissionExpr = #expression_code;
+                if let Err(error) = auth_provider.as_ref().unwrap().check_permission_expr(&user, required_permissions) {
+                    use axum::response::IntoResponse;
+                    let (status, headers, body) = ras_rest_core::auth_failure(&error, "Insufficient permissions");
+                    return (status, headers, axum::Json(body)).into_response();
+                }
+            }
+        }
+        _ => quote! {
+            // Check permissions - AND within groups, OR between groups
+            // Only check permissions if we have non-empty groups
+            let has_non_empty_groups = required_permission_groups.iter().any(|g| !g.is_empty());
+            if has_non_empty_groups {
+                let mut has_permission = false;
+
+                // Check each permission group (OR logic between groups)
+                for permission_group in &required_permission_groups {
+                    // Check if user has ALL permissions in this group (AND logic within group)
+                    if permission_group.is_empty() {
+                        // Empty group means any authenticated user can access
+                        has_permission = true;
+                        break;
+                    } else {
+                        // Check if user has all permissions in this group
+                        let group_result = auth_provider.as_ref().unwrap().check_permissions(&user, permission_group);
+                        if group_result.is_ok() {
+                            has_permission = true;
+                            break;
+                        }
+                    }
+                }
+
+                if !has_permission {
+                    use axum::response::IntoResponse;
+                    let error = ras_auth_core::AuthError::InsufficientPermissions {
+                        required: required_permission_groups.iter()
+                            .find(|g| !g.is_empty())
+                            .cloned()
+                            .unwrap_or_default(),
+                        has: user.permissions.iter().cloned().collect(),
+                    };
+                    let (status, headers, body) = ras_rest_core::auth_failure(&error, "Insufficient permissions");
+                    return (status, headers, axum::Json(body)).into_response();
+                }
+            }
+        },
+    }
+}
+
+/// Name of the builder field and setter holding an endpoint's authorization callback
+fn rest_authorize_ident(endpoint: &EndpointDefinition) -> Ident {
+    quote::format_ident!("authorize_{}", endpoint.handler_name)
+}
+
+/// Type of an endpoint's authorization callback, which gets the user, the
+/// path parameters as a tuple of references and the request body, if any
+fn rest_authorize_fn_type(endpoint: &EndpointDefinition) -> proc_macro2::TokenStream {
+    let path_types = endpoint.path_params.iter().map(|param| &param.param_type);
+    let body_type = endpoint.request_type.iter();
+    quote! {
+        dyn Fn(&ras_auth_core::AuthenticatedUser, (#(&#path_types,)*) #(, &#body_type)*) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ras_auth_core::AccessDenied>> + Send>> + Send + Sync
+    }
+}
+
+/// The builder field and setter of a `WITH_PERMISSIONS` endpoint's
+/// authorization callback, and how `new` initializes the field
+fn rest_authorize_builder_code(
+    endpoint: &EndpointDefinition,
+) -> Option<(
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+)> {
+    if matches!(endpoint.auth, AuthRequirement::Unauthorized) {
+        return None;
+    }
+
+    let ident = rest_authorize_ident(endpoint);
+    let fn_type = rest_authorize_fn_type(endpoint);
+    let path_types: Vec<_> = endpoint
+        .path_params
+        .iter()
+        .map(|param| &param.param_type)
+        .collect();
+    let body_type = endpoint.request_type.iter().collect::<Vec<_>>();
+    let body_arg = endpoint
+        .request_type
+        .iter()
+        .map(|_| quote! { request })
+        .collect::<Vec<_>>();
+    let doc = format!(
+        "Authorize `{}` requests past their permission check, such as letting users edit only \
+         their own records. The callback gets the user, the path parameters as a tuple and the \
+         request body, if any; refusing answers `403 Forbidden` with the `access_denied` code.",
+        endpoint.handler_name
+    );
+
+    Some((
+        quote! { #ident: Option<std::sync::Arc<#fn_type>>, },
+        quote! {
+            #[doc = #doc]
+            pub fn #ident<F, Fut>(mut self, authorize: F) -> Self
+            where
+                F: Fn(&ras_auth_core::AuthenticatedUser, (#(&#path_types,)*) #(, &#body_type)*) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = Result<(), ras_auth_core::AccessDenied>> + Send + 'static,
+            {
+                self.#ident = Some(std::sync::Arc::new(
+                    move |user: &ras_auth_core::AuthenticatedUser, path_params: (#(&#path_types,)*) #(, #body_arg: &#body_type)*|
+                        -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ras_auth_core::AccessDenied>> + Send>> {
+                        Box::pin(authorize(user, path_params #(, #body_arg)*))
+                    },
+                ));
+                self
+            }
+        },
+        quote! { #ident: None, },
+    ))
+}
+
+/// Binds the endpoint's authorization callback as `authorize` for its route:
+/// once when the route is registered, then in each request
+fn rest_authorize_capture_code(
+    endpoint: &EndpointDefinition,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    match endpoint.auth {
+        AuthRequirement::Unauthorized => (quote! {}, quote! {}),
+        AuthRequirement::WithPermissions(_) => {
+            let ident = rest_authorize_ident(endpoint);
+            (
+                quote! { let authorize = self.#ident.clone(); },
+                quote! { let authorize = authorize.clone(); },
+            )
+        }
+    }
+}
+
+/// Runs the endpoint's authorization callback, if set, refusing the request
+/// with 403 when it denies
+fn rest_authorize_call_code(
+    path_args: Vec<proc_macro2::TokenStream>,
+    body_arg: Option<proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream {
+    let body_arg = body_arg.into_iter();
+    quote! {
+        if let Some(authorize) = &authorize {
+            if let Err(denied) = authorize(&user, (#(#path_args,)*) #(, #body_arg)*).await {
+                use axum::response::IntoResponse;
+                let (status, body) = ras_rest_core::access_denied(&denied);
+                return (status, axum::Json(body)).into_response();
+            }
+        }
+    }
+}
+
+fn rest_response_headers_code() -> proc_macro2::TokenStream {
+    quote! {
+        for (name, value) in rest_response.headers {
+            if let (Ok(name), Ok(value)) = (
+                axum::http::HeaderName::try_from(name),
+                axum::http::HeaderValue::try_from(value),
+            ) {
+                response.headers_mut().append(name, value);
+            }
+        }
+    }
+}
+
 fn pascal_ident_segment(value: &str) -> String {
     let mut out = String::new();
     let mut uppercase_next = true;
@@ -1115,6 +1548,9 @@ fn generate_canonical_route_registration(
     );
     let handler_body = generate_handler_body(endpoint, handler_name, method_str, path);
     let permission_groups_code = rest_permission_groups_code(&endpoint.auth);
+    let span_name = handler_name.to_string();
+    let requires_auth = matches!(endpoint.auth, AuthRequirement::WithPermissions(_));
+    let (authorize_outer, authorize_inner) = rest_authorize_capture_code(endpoint);
 
     quote! {
         {
@@ -1123,6 +1559,13 @@ fn generate_canonical_route_registration(
             let required_permission_groups: Vec<Vec<String>> = #permission_groups_code;
             let with_usage_tracker = self.with_usage_tracker.clone();
             let with_method_duration_tracker = self.with_method_duration_tracker.clone();
+            let service_metrics = self.service_metrics.clone();
+            let completion_tracker = self.completion_tracker.clone();
+            let tracker_guard = self.tracker_guard.clone();
+            let trusted_proxies = self.trusted_proxies.clone();
+            let tenant_extractor = self.tenant_extractor.clone();
+            let tracing_spans = self.tracing_spans;
+            #authorize_outer
 
             router = router.route(#path, #method_routing({
                 move |#axum_handler| {
@@ -1131,10 +1574,110 @@ fn generate_canonical_route_registration(
                     let required_permission_groups: Vec<Vec<String>> = required_permission_groups.clone();
                     let with_usage_tracker = with_usage_tracker.clone();
                     let with_method_duration_tracker = with_method_duration_tracker.clone();
+                    let service_metrics = service_metrics.clone();
+                    let completion_tracker = completion_tracker.clone();
+                    let tracker_guard = tracker_guard.clone();
+                    let trusted_proxies = trusted_proxies.clone();
+                    let tenant_extractor = tenant_extractor.clone();
+                    #authorize_inner
+
+                    // Handled as from the client behind any trusted proxies, for the request contexts
+                    let peer = peer.ok().map(|axum::extract::ConnectInfo(peer)| peer.ip());
+                    let origin = ras_rest_core::RequestOrigin::new(ras_rest_core::client_ip(&headers, peer, &trusted_proxies), #path);
+
+                    origin.scope(async move {
+                        // Every exit, requests refused before the handler runs included, is timed from here
+                        let started = std::time::Instant::now();
+                        let span = if tracing_spans {
+                            let request_id = headers
+                                .get("x-request-id")
+                                .and_then(|value| value.to_str().ok())
+                                .unwrap_or_default();
+                            let span = ras_rest_core::tracing::info_span!(
+                                #span_name,
+                                protocol = "rest",
+                                http.method = #method_str,
+                                http.route = #path,
+                                request_id,
+                                user_id = ras_rest_core::tracing::field::Empty,
+                                permission = ras_rest_core::tracing::field::Empty,
+                                outcome = ras_rest_core::tracing::field::Empty,
+                                http.status_code = ras_rest_core::tracing::field::Empty,
+                                duration_ms = ras_rest_core::tracing::field::Empty,
+                                otel.status_code = ras_rest_core::tracing::field::Empty,
+                                traceparent = headers.get("traceparent").and_then(|value| value.to_str().ok()),
+                            );
+                            ras_rest_core::set_span_parent(&span, &headers);
+                            span
+                        } else {
+                            ras_rest_core::tracing::Span::none()
+                        };
 
-                    async move {
-                        #handler_body
-                    }
+                        let request_context = ras_rest_core::RequestContext::rest(#method_str, #path).with_request_info(&headers).with_trace_context(&headers);
+                        // Kept to name the tenant again once the caller is known
+                        let tenant_source = tenant_extractor.map(|extractor| (extractor, headers.clone()));
+                        let request_context = match &tenant_source {
+                            Some((extractor, headers)) => request_context.with_extracted_tenant(extractor, headers, None),
+                            None => request_context,
+                        };
+                        let metrics_context = service_metrics.as_ref().map(|metrics| {
+                            let context = request_context.clone();
+                            metrics.increment_requests_started(&context);
+                            context
+                        });
+                        // Sizes are only measured for service metrics
+                        let request_bytes = metrics_context.as_ref().map(|_| ras_rest_core::request_body_size(&headers));
+
+                        // Set once the caller is authenticated; others are anonymous
+                        let caller_slot = std::sync::OnceLock::new();
+                        let caller = &caller_slot;
+                        // Trackers are called through the guard, counting failures in the service metrics
+                        let tracker_guard = &tracker_guard;
+                        let tracker_metrics = service_metrics.as_deref();
+
+                        // Counted as executing until the handler finishes, or unwinds
+                        let in_flight = service_metrics.as_ref().zip(metrics_context.as_ref()).map(|(metrics, context)| metrics.in_flight_guard(context));
+                        let response: axum::response::Response = ras_rest_core::tracing::Instrument::instrument(
+                            async move { #handler_body },
+                            span.clone(),
+                        )
+                        .await;
+                        let elapsed = started.elapsed();
+                        drop(in_flight);
+                        ras_rest_core::record_span_outcome(&span, response.status(), #requires_auth, elapsed);
+                        let principal_kind = ras_auth_core::PrincipalKind::of(caller_slot.get());
+                        let payload_sizes = request_bytes.map(|request_bytes| {
+                            let response_bytes = axum::body::HttpBody::size_hint(response.body()).exact().unwrap_or_default();
+                            (request_bytes, response_bytes as usize)
+                        });
+                        let with_sizes = |context: ras_rest_core::RequestContext| match payload_sizes {
+                            Some((request_bytes, response_bytes)) => context.with_request_size(request_bytes).with_response_size(response_bytes),
+                            None => context,
+                        };
+                        let with_tenant = |context: ras_rest_core::RequestContext| match &tenant_source {
+                            Some((extractor, headers)) => context.with_extracted_tenant(extractor, headers, caller_slot.get()),
+                            None => context,
+                        };
+                        if let Some(tracker) = &completion_tracker {
+                            let context = with_tenant(with_sizes(request_context.with_principal_kind(principal_kind)));
+                            let outcome = ras_rest_core::request_outcome(response.status());
+                            tracker_guard.call("completion_tracker", tracker_metrics, || ras_rest_core::MethodDurationTracker::track_completion(&**tracker, &context, caller_slot.get(), elapsed, outcome)).await;
+                        }
+                        if let Some(tracker) = &with_method_duration_tracker {
+                            tracker_guard.call("method_duration_tracker", tracker_metrics, || tracker(#method_str, #path, caller_slot.get(), elapsed)).await;
+                        }
+                        if let (Some(metrics), Some(context)) = (&service_metrics, metrics_context) {
+                            // Within the span, so durations are linked to its trace
+                            let _entered = span.enter();
+                            let context = with_tenant(with_sizes(context.with_principal_kind(principal_kind)));
+                            if let Some((request_bytes, response_bytes)) = payload_sizes {
+                                metrics.record_request_size(&context, request_bytes);
+                                metrics.record_response_size(&context, response_bytes);
+                            }
+                            ras_rest_core::record_request_completed(metrics.as_ref(), context, response.status(), elapsed);
+                        }
+                        response
+                    })
                 }
             }));
         }
@@ -1157,6 +1700,10 @@ fn generate_legacy_route_registration(
     );
     let handler_body = generate_legacy_handler_body(service_name, endpoint, version);
     let permission_groups_code = rest_permission_groups_code(&endpoint.auth);
+    let method_str = endpoint.method.as_str();
+    let span_name = format!("{}_{}", endpoint.handler_name, version.version);
+    let requires_auth = matches!(endpoint.auth, AuthRequirement::WithPermissions(_));
+    let (authorize_outer, authorize_inner) = rest_authorize_capture_code(endpoint);
 
     quote! {
         {
@@ -1165,6 +1712,13 @@ fn generate_legacy_route_registration(
             let required_permission_groups: Vec<Vec<String>> = #permission_groups_code;
             let with_usage_tracker = self.with_usage_tracker.clone();
             let with_method_duration_tracker = self.with_method_duration_tracker.clone();
+            let service_metrics = self.service_metrics.clone();
+            let completion_tracker = self.completion_tracker.clone();
+            let tracker_guard = self.tracker_guard.clone();
+            let trusted_proxies = self.trusted_proxies.clone();
+            let tenant_extractor = self.tenant_extractor.clone();
+            let tracing_spans = self.tracing_spans;
+            #authorize_outer
 
             router = router.route(#path, #method_routing({
                 move |#axum_handler| {
@@ -1173,10 +1727,110 @@ fn generate_legacy_route_registration(
                     let required_permission_groups: Vec<Vec<String>> = required_permission_groups.clone();
                     let with_usage_tracker = with_usage_tracker.clone();
                     let with_method_duration_tracker = with_method_duration_tracker.clone();
+                    let service_metrics = service_metrics.clone();
+                    let completion_tracker = completion_tracker.clone();
+                    let tracker_guard = tracker_guard.clone();
+                    let trusted_proxies = trusted_proxies.clone();
+                    let tenant_extractor = tenant_extractor.clone();
+                    #authorize_inner
+
+                    // Handled as from the client behind any trusted proxies, for the request contexts
+                    let peer = peer.ok().map(|axum::extract::ConnectInfo(peer)| peer.ip());
+                    let origin = ras_rest_core::RequestOrigin::new(ras_rest_core::client_ip(&headers, peer, &trusted_proxies), #path);
+
+                    origin.scope(async move {
+                        // Every exit, requests refused before the handler runs included, is timed from here
+                        let started = std::time::Instant::now();
+                        let span = if tracing_spans {
+                            let request_id = headers
+                                .get("x-request-id")
+                                .and_then(|value| value.to_str().ok())
+                                .unwrap_or_default();
+                            let span = ras_rest_core::tracing::info_span!(
+                                #span_name,
+                                protocol = "rest",
+                                http.method = #method_str,
+                                http.route = #path,
+                                request_id,
+                                user_id = ras_rest_core::tracing::field::Empty,
+                                permission = ras_rest_core::tracing::field::Empty,
+                                outcome = ras_rest_core::tracing::field::Empty,
+                                http.status_code = ras_rest_core::tracing::field::Empty,
+                                duration_ms = ras_rest_core::tracing::field::Empty,
+                                otel.status_code = ras_rest_core::tracing::field::Empty,
+                                traceparent = headers.get("traceparent").and_then(|value| value.to_str().ok()),
+                            );
+                            ras_rest_core::set_span_parent(&span, &headers);
+                            span
+                        } else {
+                            ras_rest_core::tracing::Span::none()
+                        };
 
-                    async move {
-                        #handler_body
-                    }
+                        let request_context = ras_rest_core::RequestContext::rest(#method_str, #path).with_request_info(&headers).with_trace_context(&headers);
+                        // Kept to name the tenant again once the caller is known
+                        let tenant_source = tenant_extractor.map(|extractor| (extractor, headers.clone()));
+                        let request_context = match &tenant_source {
+                            Some((extractor, headers)) => request_context.with_extracted_tenant(extractor, headers, None),
+                            None => request_context,
+                        };
+                        let metrics_context = service_metrics.as_ref().map(|metrics| {
+                            let context = request_context.clone();
+                            metrics.increment_requests_started(&context);
+                            context
+                        });
+                        // Sizes are only measured for service metrics
+                        let request_bytes = metrics_context.as_ref().map(|_| ras_rest_core::request_body_size(&headers));
+
+                        // Set once the caller is authenticated; others are anonymous
+                        let caller_slot = std::sync::OnceLock::new();
+                        let caller = &caller_slot;
+                        // Trackers are called through the guard, counting failures in the service metrics
+                        let tracker_guard = &tracker_guard;
+                        let tracker_metrics = service_metrics.as_deref();
+
+                        // Counted as executing until the handler finishes, or unwinds
+                        let in_flight = service_metrics.as_ref().zip(metrics_context.as_ref()).map(|(metrics, context)| metrics.in_flight_guard(context));
+                        let response: axum::response::Response = ras_rest_core::tracing::Instrument::instrument(
+                            async move { #handler_body },
+                            span.clone(),
+                        )
+                        .await;
+                        let elapsed = started.elapsed();
+                        drop(in_flight);
+                        ras_rest_core::record_span_outcome(&span, response.status(), #requires_auth, elapsed);
+                        let principal_kind = ras_auth_core::PrincipalKind::of(caller_slot.get());
+                        let payload_sizes = request_bytes.map(|request_bytes| {
+                            let response_bytes = axum::body::HttpBody::size_hint(response.body()).exact().unwrap_or_default();
+                            (request_bytes, response_bytes as usize)
+                        });
+                        let with_sizes = |context: ras_rest_core::RequestContext| match payload_sizes {
+                            Some((request_bytes, response_bytes)) => context.with_request_size(request_bytes).with_response_size(response_bytes),
+                            None => context,
+                        };
+                        let with_tenant = |context: ras_rest_core::RequestContext| match &tenant_source {
+                            Some((extractor, headers)) => context.with_extracted_tenant(extractor, headers, caller_slot.get()),
+                            None => context,
+                        };
+                        if let Some(tracker) = &completion_tracker {
+                            let context = with_tenant(with_sizes(request_context.with_principal_kind(principal_kind)));
+                            let outcome = ras_rest_core::request_outcome(response.status());
+                            tracker_guard.call("completion_tracker", tracker_metrics, || ras_rest_core::MethodDurationTracker::track_completion(&**tracker, &context, caller_slot.get(), elapsed, outcome)).await;
+                        }
+                        if let Some(tracker) = &with_method_duration_tracker {
+                            tracker_guard.call("method_duration_tracker", tracker_metrics, || tracker(#method_str, #path, caller_slot.get(), elapsed)).await;
+                        }
+                        if let (Some(metrics), Some(context)) = (&service_metrics, metrics_context) {
+                            // Within the span, so durations are linked to its trace
+                            let _entered = span.enter();
+                            let context = with_tenant(with_sizes(context.with_principal_kind(principal_kind)));
+                            if let Some((request_bytes, response_bytes)) = payload_sizes {
+                                metrics.record_request_size(&context, request_bytes);
+                                metrics.record_response_size(&context, response_bytes);
+                            }
+                            ras_rest_core::record_request_completed(metrics.as_ref(), context, response.status(), elapsed);
+                        }
+                        response
+                    })
                 }
             }));
         }
@@ -1195,6 +1849,7 @@ fn generate_legacy_handler_body(
     let canonical_response_type = &endpoint.response_type;
     let legacy_response_type = &version.response_type;
     let canonical_version = endpoint.version.as_deref().unwrap_or("current");
+    let permission_check = rest_permission_check_code(&endpoint.auth);
     let (canonical_request_ident, _, _) =
         rest_request_part_idents(service_name, handler_name, canonical_version);
     let (legacy_request_ident, _, _) =
@@ -1209,6 +1864,21 @@ fn generate_legacy_handler_body(
     );
     let canonical_parts_ident = quote::format_ident!("canonical_parts");
     let mut canonical_args = rest_canonical_args_from_parts(endpoint, &canonical_parts_ident);
+    let authorize_call = rest_authorize_call_code(
+        endpoint
+            .path_params
+            .iter()
+            .map(|param| {
+                let name = &param.name;
+                quote! { &#canonical_parts_ident.path.#name }
+            })
+            .collect(),
+        endpoint
+            .request_type
+            .as_ref()
+            .map(|_| quote! { &#canonical_parts_ident.body }),
+    );
+    let apply_response_headers = rest_response_headers_code();
 
     let json_handling = if version.request_type.is_some() {
         quote! {
@@ -1234,7 +1904,7 @@ fn generate_legacy_handler_body(
             #json_handling
 
             if let Some(tracker) = &with_usage_tracker {
-                tracker(&headers, None, #method, #path).await;
+                tracker_guard.call("usage_tracker", tracker_metrics, || tracker(&headers, None, #method, #path)).await;
             }
 
             let legacy_parts: #legacy_request_ident = #legacy_parts_init;
@@ -1252,9 +1922,7 @@ fn generate_legacy_handler_body(
                     },
                 };
 
-            let start_time = std::time::Instant::now();
-
-            let result = match service.#handler_name(#(#canonical_args),*).await {
+            match service.#handler_name(#(#canonical_args),*).await {
                 Ok(rest_response) => {
                     use axum::response::IntoResponse;
                     let status_code = axum::http::StatusCode::from_u16(rest_response.status)
@@ -1272,10 +1940,12 @@ fn generate_legacy_handler_body(
                                 ).into_response();
                             },
                         };
-                    (
+                    let mut response = (
                         status_code,
                         axum::Json(body)
-                    ).into_response()
+                    ).into_response();
+                    #apply_response_headers
+                    response
                 },
                 Err(rest_error) => {
                     use axum::response::IntoResponse;
@@ -1294,14 +1964,7 @@ fn generate_legacy_handler_body(
                         }))
                     ).into_response()
                 },
-            };
-
-            let duration = start_time.elapsed();
-            if let Some(tracker) = &with_method_duration_tracker {
-                tracker(#method, #path, None, duration).await;
             }
-
-            result
         },
         AuthRequirement::WithPermissions(_) => {
             canonical_args.insert(0, quote! { &user });
@@ -1309,34 +1972,28 @@ fn generate_legacy_handler_body(
             quote! {
                 #json_handling
 
-                let token = match headers
-                    .get("Authorization")
-                    .and_then(|h| h.to_str().ok())
-                    .and_then(|s| s.strip_prefix("Bearer "))
-                {
+                let token = match auth_provider.as_ref().map_or_else(
+                    || ras_auth_core::header_credential(&headers),
+                    |provider| provider.credential(&headers),
+                ) {
                     Some(token) => token,
                     None => {
                         use axum::response::IntoResponse;
-                        return (
-                            axum::http::StatusCode::UNAUTHORIZED,
-                            axum::Json(serde_json::json!({
-                                "error": "Missing or invalid Authorization header"
-                            }))
-                        ).into_response();
+                        let (status, headers, body) = ras_rest_core::auth_failure(
+                            &ras_auth_core::AuthError::AuthenticationRequired,
+                            "Missing or invalid Authorization header",
+                        );
+                        return (status, headers, axum::Json(body)).into_response();
                     },
                 };
 
                 let user = match &auth_provider {
-                    Some(provider) => match provider.authenticate(token.to_string()).await {
+                    Some(provider) => match provider.authenticate_request(token.to_string(), &headers).await {
                         Ok(user) => user,
-                        Err(_) => {
+                        Err(error) => {
                             use axum::response::IntoResponse;
-                            return (
-                                axum::http::StatusCode::UNAUTHORIZED,
-                                axum::Json(serde_json::json!({
-                                    "error": "Authentication failed"
-                                }))
-                            ).into_response();
+                            let (status, headers, body) = ras_rest_core::auth_failure(&error, "Authentication failed");
+                            return (status, headers, axum::Json(body)).into_response();
                         },
                     },
                     None => {
@@ -1350,36 +2007,13 @@ fn generate_legacy_handler_body(
                     },
                 };
 
-                let has_non_empty_groups = required_permission_groups.iter().any(|g| !g.is_empty());
-                if has_non_empty_groups {
-                    let mut has_permission = false;
-
-                    for permission_group in &required_permission_groups {
-                        if permission_group.is_empty() {
-                            has_permission = true;
-                            break;
-                        } else {
-                            let group_result = auth_provider.as_ref().unwrap().check_permissions(&user, permission_group);
-                            if group_result.is_ok() {
-                                has_permission = true;
-                                break;
-                            }
-                        }
-                    }
+                ras_rest_core::tracing::Span::current().record("user_id", user.user_id.as_str());
+                let _ = caller.set(user.clone());
 
-                    if !has_permission {
-                        use axum::response::IntoResponse;
-                        return (
-                            axum::http::StatusCode::FORBIDDEN,
-                            axum::Json(serde_json::json!({
-                                "error": "Insufficient permissions"
-                            }))
-                        ).into_response();
-                    }
-                }
+                #permission_check
 
                 if let Some(tracker) = &with_usage_tracker {
-                    tracker(&headers, Some(&user), #method, #path).await;
+                    tracker_guard.call("usage_tracker", tracker_metrics, || tracker(&headers, Some(&user), #method, #path)).await;
                 }
 
                 let legacy_parts: #legacy_request_ident = #legacy_parts_init;
@@ -1397,9 +2031,9 @@ fn generate_legacy_handler_body(
                         },
                     };
 
-                let start_time = std::time::Instant::now();
+                #authorize_call
 
-                let result = match service.#handler_name(#(#canonical_args),*).await {
+                match ras_auth_core::scope_user(Some(std::sync::Arc::new(user.clone())), service.#handler_name(#(#canonical_args),*)).await {
                     Ok(rest_response) => {
                         use axum::response::IntoResponse;
                         let status_code = axum::http::StatusCode::from_u16(rest_response.status)
@@ -1417,10 +2051,12 @@ fn generate_legacy_handler_body(
                                     ).into_response();
                                 },
                             };
-                        (
+                        let mut response = (
                             status_code,
                             axum::Json(body)
-                        ).into_response()
+                        ).into_response();
+                        #apply_response_headers
+                        response
                     },
                     Err(rest_error) => {
                         use axum::response::IntoResponse;
@@ -1439,14 +2075,7 @@ fn generate_legacy_handler_body(
                             }))
                         ).into_response()
                     },
-                };
-
-                let duration = start_time.elapsed();
-                if let Some(tracker) = &with_method_duration_tracker {
-                    tracker(#method, #path, Some(&user), duration).await;
                 }
-
-                result
             }
         }
     }
@@ -1462,6 +2091,10 @@ fn generate_axum_handler(
 
     // Always add headers extraction for tracking purposes
     extractors.push(quote! { headers: axum::http::HeaderMap });
+    // The connection's address, when served with `into_make_service_with_connect_info`
+    extractors.push(quote! {
+        peer: Result<axum::extract::ConnectInfo<std::net::SocketAddr>, axum::extract::rejection::ExtensionRejection>
+    });
 
     // Add path parameter extractors
     if !path_params.is_empty() {
@@ -1496,6 +2129,9 @@ fn generate_handler_body(
     method: &str,
     path: &str,
 ) -> proc_macro2::TokenStream {
+    let apply_response_headers = rest_response_headers_code();
+    let permission_check = rest_permission_check_code(&endpoint.auth);
+
     // Handle authentication if required
     match &endpoint.auth {
         AuthRequirement::Unauthorized => {
@@ -1545,21 +2181,20 @@ fn generate_handler_body(
 
                 // Call usage tracker if configured (for unauthorized endpoints, headers come from handler params)
                 if let Some(tracker) = &with_usage_tracker {
-                    tracker(&headers, None, #method, #path).await;
+                    tracker_guard.call("usage_tracker", tracker_metrics, || tracker(&headers, None, #method, #path)).await;
                 }
 
-                // Track duration
-                let start_time = std::time::Instant::now();
-
-                let result = match service.#handler_name(#(#args),*).await {
+                match service.#handler_name(#(#args),*).await {
                     Ok(rest_response) => {
                         use axum::response::IntoResponse;
                         let status_code = axum::http::StatusCode::from_u16(rest_response.status)
                             .unwrap_or(axum::http::StatusCode::OK);
-                        (
+                        let mut response = (
                             status_code,
                             axum::Json(rest_response.body)
-                        ).into_response()
+                        ).into_response();
+                        #apply_response_headers
+                        response
                     },
                     Err(rest_error) => {
                         use axum::response::IntoResponse;
@@ -1579,21 +2214,29 @@ fn generate_handler_body(
                             }))
                         ).into_response()
                     },
-                };
-
-                // Call duration tracker if configured
-                let duration = start_time.elapsed();
-                if let Some(tracker) = &with_method_duration_tracker {
-                    tracker(#method, #path, None, duration).await;
                 }
-
-                result
             }
         }
         AuthRequirement::WithPermissions(_) => {
             // Build argument list for authenticated endpoint
             let mut args = vec![quote! { &user }];
 
+            // The authorization callback borrows what the handler is given
+            let authorize_path_args = if endpoint.path_params.len() == 1 {
+                vec![quote! { &path_params }]
+            } else {
+                (0..endpoint.path_params.len())
+                    .map(|i| {
+                        let idx = syn::Index::from(i);
+                        quote! { &path_params.#idx }
+                    })
+                    .collect()
+            };
+            let authorize_call = rest_authorize_call_code(
+                authorize_path_args,
+                endpoint.request_type.as_ref().map(|_| quote! { &body }),
+            );
+
             // Add path parameters
             if endpoint.path_params.len() == 1 {
                 args.push(quote! { path_params });
@@ -1636,35 +2279,29 @@ fn generate_handler_body(
                 #json_handling
 
                 // Extract and validate auth token
-                let token = match headers
-                    .get("Authorization")
-                    .and_then(|h| h.to_str().ok())
-                    .and_then(|s| s.strip_prefix("Bearer "))
-                {
+                let token = match auth_provider.as_ref().map_or_else(
+                    || ras_auth_core::header_credential(&headers),
+                    |provider| provider.credential(&headers),
+                ) {
                     Some(token) => token,
                     None => {
                         use axum::response::IntoResponse;
-                        return (
-                            axum::http::StatusCode::UNAUTHORIZED,
-                            axum::Json(serde_json::json!({
-                                "error": "Missing or invalid Authorization header"
-                            }))
-                        ).into_response();
+                        let (status, headers, body) = ras_rest_core::auth_failure(
+                            &ras_auth_core::AuthError::AuthenticationRequired,
+                            "Missing or invalid Authorization header",
+                        );
+                        return (status, headers, axum::Json(body)).into_response();
                     },
                 };
 
                 // Authenticate user
                 let user = match &auth_provider {
-                    Some(provider) => match provider.authenticate(token.to_string()).await {
+                    Some(provider) => match provider.authenticate_request(token.to_string(), &headers).await {
                         Ok(user) => user,
-                        Err(_) => {
+                        Err(error) => {
                             use axum::response::IntoResponse;
-                            return (
-                                axum::http::StatusCode::UNAUTHORIZED,
-                                axum::Json(serde_json::json!({
-                                    "error": "Authentication failed"
-                                }))
-                            ).into_response();
+                            let (status, headers, body) = ras_rest_core::auth_failure(&error, "Authentication failed");
+                            return (status, headers, axum::Json(body)).into_response();
                         },
                     },
                     None => {
@@ -1678,57 +2315,29 @@ fn generate_handler_body(
                     },
                 };
 
-                // Check permissions - AND within groups, OR between groups
-                // Only check permissions if we have non-empty groups
-                let has_non_empty_groups = required_permission_groups.iter().any(|g| !g.is_empty());
-                if has_non_empty_groups {
-                    let mut has_permission = false;
-
-                    // Check each permission group (OR logic between groups)
-                    for permission_group in &required_permission_groups {
-                        // Check if user has ALL permissions in this group (AND logic within group)
-                        if permission_group.is_empty() {
-                            // Empty group means any authenticated user can access
-                            has_permission = true;
-                            break;
-                        } else {
-                            // Check if user has all permissions in this group
-                            let group_result = auth_provider.as_ref().unwrap().check_permissions(&user, permission_group);
-                            if group_result.is_ok() {
-                                has_permission = true;
-                                break;
-                            }
-                        }
-                    }
+                ras_rest_core::tracing::Span::current().record("user_id", user.user_id.as_str());
+                let _ = caller.set(user.clone());
 
-                    if !has_permission {
-                        use axum::response::IntoResponse;
-                        return (
-                            axum::http::StatusCode::FORBIDDEN,
-                            axum::Json(serde_json::json!({
-                                "error": "Insufficient permissions"
-                            }))
-                        ).into_response();
-                    }
-                }
+                #permission_check
 
                 // Call usage tracker if configured
                 if let Some(tracker) = &with_usage_tracker {
-                    tracker(&headers, Some(&user), #method, #path).await;
+                    tracker_guard.call("usage_tracker", tracker_metrics, || tracker(&headers, Some(&user), #method, #path)).await;
                 }
 
-                // Track duration
-                let start_time = std::time::Instant::now();
+                #authorize_call
 
-                let result = match service.#handler_name(#(#args),*).await {
+                match ras_auth_core::scope_user(Some(std::sync::Arc::new(user.clone())), service.#handler_name(#(#args),*)).await {
                     Ok(rest_response) => {
                         use axum::response::IntoResponse;
                         let status_code = axum::http::StatusCode::from_u16(rest_response.status)
                             .unwrap_or(axum::http::StatusCode::OK);
-                        (
+                        let mut response = (
                             status_code,
                             axum::Json(rest_response.body)
-                        ).into_response()
+                        ).into_response();
+                        #apply_response_headers
+                        response
                     },
                     Err(rest_error) => {
                         use axum::response::IntoResponse;
@@ -1748,15 +2357,7 @@ fn generate_handler_body(
                             }))
                         ).into_response()
                     },
-                };
-
-                // Call duration tracker if configured
-                let duration = start_time.elapsed();
-                if let Some(tracker) = &with_method_duration_tracker {
-                    tracker(#method, #path, Some(&user), duration).await;
                 }
-
-                result
             }
         }
     }
@@ -0,0 +1,129 @@
+//! Service manifest generation module
+//!
+//! Generates the `{servicename}_service_manifest()` function listing every endpoint
+//! with its auth requirement.
+
+use crate::{AuthRequirement, EndpointDefinition, PermissionRequirement, ServiceDefinition};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Type;
+
+/// Generates the service manifest function
+pub fn generate_manifest_code(service_def: &ServiceDefinition) -> TokenStream {
+    let service_name_str = service_def.service_name.to_string();
+    let manifest_fn_name =
+        quote::format_ident!("{}_service_manifest", service_name_str.to_lowercase());
+    let base_path = service_def.base_path.trim_end_matches('/');
+
+    let operations = service_def.endpoints.iter().flat_map(|endpoint| {
+        let canonical = operation_code(
+            endpoint,
+            format!("{base_path}{}", endpoint.path),
+            endpoint.request_type.as_ref(),
+            &endpoint.response_type,
+            endpoint.version.as_deref(),
+        );
+        let versions = endpoint.versions.iter().map(move |version| {
+            operation_code(
+                endpoint,
+                format!("{base_path}{}", version.path),
+                version.request_type.as_ref(),
+                &version.response_type,
+                Some(&version.version),
+            )
+        });
+        std::iter::once(canonical).chain(versions)
+    });
+
+    quote! {
+        /// Describe every endpoint of the service with its auth requirement
+        #[cfg(feature = "server")]
+        pub fn #manifest_fn_name() -> ras_rest_core::ServiceManifest {
+            ras_rest_core::ServiceManifest {
+                service_name: #service_name_str.to_string(),
+                protocol: ras_rest_core::ServiceProtocol::Rest,
+                operations: vec![#(#operations),*],
+            }
+        }
+    }
+}
+
+fn operation_code(
+    endpoint: &EndpointDefinition,
+    path: String,
+    request_type: Option<&Type>,
+    response_type: &Type,
+    version: Option<&str>,
+) -> TokenStream {
+    let name = endpoint.handler_name.to_string();
+    let http_method = endpoint.method.as_str();
+    let (auth, permission_groups) = match &endpoint.auth {
+        AuthRequirement::Unauthorized => (
+            quote! { ras_rest_core::AuthMode::Unauthorized },
+            quote! { Vec::new() },
+        ),
+        AuthRequirement::WithPermissions(PermissionRequirement::Expression(_)) => (
+            quote! { ras_rest_core::AuthMode::Permissions },
+            quote! { Vec::new() },
+        ),
+        AuthRequirement::WithPermissions(PermissionRequirement::Groups(groups)) => {
+            let groups = groups.iter().map(|group| {
+                quote! { vec![#(#group.to_string()),*] }
+            });
+            let permission_groups = quote! { vec![#(#groups),*] };
+            (
+                quote! { ras_rest_core::AuthMode::for_permission_groups(&permission_groups) },
+                permission_groups,
+            )
+        }
+    };
+    let permission_expression = match &endpoint.auth {
+        AuthRequirement::WithPermissions(PermissionRequirement::Expression(expression)) => {
+            let expression = expression.to_string();
+            quote! { Some(#expression.to_string()) }
+        }
+        _ => quote! { None },
+    };
+    let request_type = match request_type {
+        Some(request_type) => {
+            let request_type = type_name(request_type);
+            quote! { Some(#request_type.to_string()) }
+        }
+        None => quote! { None },
+    };
+    let response_type = type_name(response_type);
+    let docs = match &endpoint.docs {
+        Some(docs) => {
+            let description = &docs.description;
+            quote! { Some(#description.to_string()) }
+        }
+        None => quote! { None },
+    };
+    let version = match version {
+        Some(version) => quote! { Some(#version.to_string()) },
+        None => quote! { None },
+    };
+
+    quote! {
+        {
+            let permission_groups: Vec<Vec<String>> = #permission_groups;
+            ras_rest_core::OperationManifest {
+                name: #name.to_string(),
+                http_method: Some(#http_method.to_string()),
+                path: Some(#path.to_string()),
+                auth: #auth,
+                permission_groups,
+                permission_expression: #permission_expression,
+                request_type: #request_type,
+                response_type: #response_type.to_string(),
+                docs: #docs,
+                version: #version,
+                deprecated: false,
+            }
+        }
+    }
+}
+
+fn type_name(ty: &Type) -> String {
+    quote!(#ty).to_string().replace(' ', "")
+}
@@ -3,7 +3,7 @@
 //! This module provides functionality to generate OpenAPI 3.0 specification documents
 //! from the rest_service macro definitions.
 
-use crate::{AuthRequirement, OpenApiConfig, ServiceDefinition};
+use crate::{AuthRequirement, OpenApiConfig, PermissionRequirement, ServiceDefinition};
 use proc_macro2::TokenStream;
 use quote::quote;
 use std::collections::HashMap;
@@ -39,8 +39,9 @@ pub fn generate_openapi_code(
         }
     };
 
-    // Collect unique types for schema generation
-    let mut unique_types = std::collections::HashMap::new();
+    // Collect unique types for schema generation, in a stable order so colliding
+    // definitions are qualified the same way on every build
+    let mut unique_types = std::collections::BTreeMap::new();
     for endpoint in &service_def.endpoints {
         if let Some(request_type) = &endpoint.request_type {
             let request_type_str = quote!(#request_type).to_string();
@@ -120,8 +121,11 @@ pub fn generate_openapi_code(
                 );
                 quote! {
                     #[cfg(feature = "server")]
-                    fn #fn_name() -> serde_json::Value {
-                        let schema = schemars::schema_for!(#type_tokens);
+                    fn #fn_name() -> (serde_json::Value, serde_json::Map<String, serde_json::Value>) {
+                        // A shared generator references named types instead of inlining them
+                        let mut generator = schemars::SchemaGenerator::default();
+                        let schema = generator.subschema_for::<#type_tokens>();
+                        let definitions = generator.take_definitions(true);
                         let mut schema_value = serde_json::to_value(&schema).unwrap_or_else(|_| {
                             serde_json::json!({
                                 "type": "object",
@@ -129,10 +133,18 @@ pub fn generate_openapi_code(
                             })
                         });
 
-                        // Post-process schema to make it more Swagger UI friendly
+                        // Post-process schemas to make them more Swagger UI friendly
                         normalize_nullable_properties(&mut schema_value);
                         fix_option_types(&mut schema_value);
-                        schema_value
+                        let definitions = definitions
+                            .into_iter()
+                            .map(|(name, mut definition)| {
+                                normalize_nullable_properties(&mut definition);
+                                fix_option_types(&mut definition);
+                                (name, definition)
+                            })
+                            .collect();
+                        (schema_value, definitions)
                     }
                 }
             }
@@ -145,7 +157,7 @@ pub fn generate_openapi_code(
         .map(|type_name| {
             if type_name == "()" {
                 quote! {
-                    schemas.insert("Unit".to_string(), serde_json::json!({
+                    type_schemas.insert("Unit".to_string(), serde_json::json!({
                         "type": "null",
                         "description": "Unit type (empty response)"
                     }));
@@ -158,7 +170,11 @@ pub fn generate_openapi_code(
                     sanitized_name
                 );
                 quote! {
-                    schemas.insert(#sanitized_name.to_string(), #fn_name());
+                    let (schema, definitions) = #fn_name();
+                    type_schemas.insert(
+                        #sanitized_name.to_string(),
+                        merge_schema_components(schema, definitions, &mut schemas),
+                    );
                 }
             }
         })
@@ -190,12 +206,21 @@ pub fn generate_openapi_code(
             let auth_required = matches!(endpoint.auth, AuthRequirement::WithPermissions(_));
             // Flatten permission groups for OpenAPI documentation
             let permissions = match &endpoint.auth {
-                AuthRequirement::Unauthorized => vec![],
-                AuthRequirement::WithPermissions(groups) => {
+                AuthRequirement::Unauthorized
+                | AuthRequirement::WithPermissions(PermissionRequirement::Expression(_)) => vec![],
+                AuthRequirement::WithPermissions(PermissionRequirement::Groups(groups)) => {
                     // For OpenAPI docs, flatten all permission groups into a single list
                     groups.iter().flatten().cloned().collect()
                 }
             };
+            // Expressions are documented as written, since NOT has no list form
+            let permission_expression = match &endpoint.auth {
+                AuthRequirement::WithPermissions(PermissionRequirement::Expression(expression)) => {
+                    let expression = expression.to_string();
+                    quote! { Some(#expression.to_string()) }
+                }
+                _ => quote! { None },
+            };
 
             let request_type_name = if let Some(request_type) = &endpoint.request_type {
                 sanitize_type_name(&quote!(#request_type).to_string())
@@ -243,6 +268,7 @@ pub fn generate_openapi_code(
                     description: #description,
                     auth_required: #auth_required,
                     permissions: vec![#(#permissions.to_string()),*],
+                    permission_expression: #permission_expression,
                     request_type_name: #request_type_name.to_string(),
                     response_type_name: #response_type_name.to_string(),
                     path_params: vec![#(#path_param_infos),*] as Vec<(String, String)>,
@@ -296,6 +322,7 @@ pub fn generate_openapi_code(
                     })
                     .collect();
                 let permissions = permissions.clone();
+                let permission_expression = permission_expression.clone();
                 let summary = summary.clone();
                 let description = description.clone();
 
@@ -307,6 +334,7 @@ pub fn generate_openapi_code(
                         description: #description,
                         auth_required: #auth_required,
                         permissions: vec![#(#permissions.to_string()),*],
+                        permission_expression: #permission_expression,
                         request_type_name: #request_type_name.to_string(),
                         response_type_name: #response_type_name.to_string(),
                         path_params: vec![#(#path_param_infos),*] as Vec<(String, String)>,
@@ -332,6 +360,7 @@ pub fn generate_openapi_code(
             description: Option<String>,
             auth_required: bool,
             permissions: Vec<String>,
+            permission_expression: Option<String>,
             request_type_name: String,
             response_type_name: String,
             path_params: Vec<(String, String)>, // (name, type)
@@ -341,66 +370,105 @@ pub fn generate_openapi_code(
             canonical_path: String,
         }
 
-        // Helper function to fix schema references and flatten nested definitions
+        // Helper function to merge the definitions a schema depends on into the shared
+        // components, returning the schema with its references pointing at them.
+        // Definitions already in the components are shared, and a definition named like
+        // a different one, e.g. two `User` types from different modules, is qualified
+        // with a hash of its schema
         #[cfg(feature = "server")]
-        fn fix_schema_refs(value: &mut serde_json::Value, schemas: &mut serde_json::Map<String, serde_json::Value>) {
-            match value {
-                serde_json::Value::Object(obj) => {
-                    // Extract nested definitions and move them to top-level schemas
-                    if let Some(defs) = obj.remove("definitions") {
-                        if let serde_json::Value::Object(defs_obj) = defs {
-                            for (name, schema) in defs_obj {
-                                // Recursively fix the definition before adding it
-                                let mut schema_copy = schema.clone();
-                                fix_schema_refs(&mut schema_copy, schemas);
-                                schemas.insert(name, schema_copy);
-                            }
-                        }
-                    }
+        fn merge_schema_components(
+            mut schema: serde_json::Value,
+            definitions: serde_json::Map<String, serde_json::Value>,
+            components: &mut serde_json::Map<String, serde_json::Value>,
+        ) -> serde_json::Value {
+            let mut names: std::collections::HashMap<String, String> = definitions
+                .keys()
+                .map(|name| (name.clone(), name.clone()))
+                .collect();
 
-                    // Extract $defs and move them to top-level schemas
-                    if let Some(defs) = obj.remove("$defs") {
-                        if let serde_json::Value::Object(defs_obj) = defs {
-                            for (name, schema) in defs_obj {
-                                // Recursively fix the definition before adding it
-                                let mut schema_copy = schema.clone();
-                                fix_schema_refs(&mut schema_copy, schemas);
-                                schemas.insert(name, schema_copy);
-                            }
+            // Qualifying a definition changes the schemas referencing it, which may then
+            // collide in turn, so settle the names first
+            for _ in 0..=definitions.len() {
+                let mut settled = true;
+                for (name, definition) in &definitions {
+                    let mut definition = definition.clone();
+                    rewrite_schema_refs(&mut definition, &names);
+                    let component = match components.get(name) {
+                        Some(existing) if *existing != definition => {
+                            format!("{}_{:08x}", name, schema_hash(&definition, 0x811c_9dc5))
                         }
+                        _ => name.clone(),
+                    };
+                    if names[name] != component {
+                        names.insert(name.clone(), component);
+                        settled = false;
                     }
+                }
+                if settled {
+                    break;
+                }
+            }
+
+            for (name, mut definition) in definitions {
+                rewrite_schema_refs(&mut definition, &names);
+                components.entry(names[&name].clone()).or_insert(definition);
+            }
+            rewrite_schema_refs(&mut schema, &names);
+            schema
+        }
 
-                    // Fix $ref strings to point to components/schemas
-                    if let Some(ref_val) = obj.get_mut("$ref") {
-                        if let serde_json::Value::String(ref_str) = ref_val {
-                            // Replace any reference to definitions or $defs with components/schemas
-                            if ref_str.starts_with("#/definitions/") {
-                                let name = ref_str.trim_start_matches("#/definitions/");
-                                *ref_str = format!("#/components/schemas/{}", name);
-                            } else if ref_str.starts_with("#/$defs/") {
-                                let name = ref_str.trim_start_matches("#/$defs/");
-                                *ref_str = format!("#/components/schemas/{}", name);
+        // Helper function to point `#/$defs/` references at the renamed components
+        #[cfg(feature = "server")]
+        fn rewrite_schema_refs(
+            value: &mut serde_json::Value,
+            names: &std::collections::HashMap<String, String>,
+        ) {
+            match value {
+                serde_json::Value::Object(obj) => {
+                    for (key, val) in obj.iter_mut() {
+                        if key == "$ref" {
+                            if let Some(name) = val.as_str().and_then(|r| r.strip_prefix("#/$defs/")) {
+                                let name = names.get(name).map(String::as_str).unwrap_or(name);
+                                *val = serde_json::Value::String(format!("#/components/schemas/{}", name));
                             }
+                        } else {
+                            rewrite_schema_refs(val, names);
                         }
                     }
-
-                    // Remove $schema field as it's not needed in OpenAPI
-                    obj.remove("$schema");
-
-                    // Recursively process all values
-                    for (_, v) in obj.iter_mut() {
-                        fix_schema_refs(v, schemas);
-                    }
                 }
                 serde_json::Value::Array(arr) => {
                     for item in arr.iter_mut() {
-                        fix_schema_refs(item, schemas);
+                        rewrite_schema_refs(item, names);
                     }
                 }
                 _ => {}
             }
         }
 
+        // Helper function hashing a schema with FNV-1a, visiting object keys in sorted
+        // order so the hash doesn't depend on how serde_json orders maps
+        #[cfg(feature = "server")]
+        fn schema_hash(value: &serde_json::Value, hash: u32) -> u32 {
+            let feed = |hash: u32, bytes: &[u8]| {
+                bytes
+                    .iter()
+                    .fold(hash, |hash, byte| (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193))
+            };
+            match value {
+                serde_json::Value::Object(obj) => {
+                    let mut keys: Vec<&String> = obj.keys().collect();
+                    keys.sort();
+                    keys.into_iter().fold(feed(hash, b"{"), |hash, key| {
+                        schema_hash(&obj[key.as_str()], feed(hash, key.as_bytes()))
+                    })
+                }
+                serde_json::Value::Array(arr) => arr
+                    .iter()
+                    .fold(feed(hash, b"["), |hash, item| schema_hash(item, hash)),
+                other => feed(hash, other.to_string().as_bytes()),
+            }
+        }
+
         // Helper function to normalize nullable properties for better Swagger UI compatibility
         #[cfg(feature = "server")]
         fn normalize_nullable_properties(value: &mut serde_json::Value) {
@@ -557,19 +625,20 @@ pub fn generate_openapi_code(
                 #(#endpoint_infos),*
             ];
 
-            // Generate schemas for all unique types
-            let mut schemas = HashMap::new();
+            // Generate schemas for all unique types, collecting the named types they
+            // use into shared components so each is documented once
+            let mut schemas = serde_json::Map::new();
+            let mut type_schemas: HashMap<String, serde_json::Value> = HashMap::new();
 
             // Insert all the generated schemas
             #(#schema_insertions)*
 
-            // Fix all schema references and flatten nested definitions
-            let mut final_schemas = serde_json::Map::new();
-            for (name, mut schema) in schemas {
-                fix_schema_refs(&mut schema, &mut final_schemas);
-                fix_option_types(&mut schema);
-                final_schemas.insert(name, schema);
-            }
+            let schema_of = |type_name: &str| {
+                type_schemas
+                    .get(type_name)
+                    .cloned()
+                    .unwrap_or_else(|| json!({ "description": format!("Schema for {}", type_name) }))
+            };
 
             // Group endpoints by path to create OpenAPI paths
             let mut paths = serde_json::Map::new();
@@ -595,9 +664,7 @@ pub fn generate_openapi_code(
                             "description": "Successful response",
                             "content": {
                                 "application/json": {
-                                    "schema": {
-                                        "$ref": format!("#/components/schemas/{}", endpoint.response_type_name)
-                                    }
+                                    "schema": schema_of(&endpoint.response_type_name)
                                 }
                             }
                         },
@@ -626,9 +693,7 @@ pub fn generate_openapi_code(
                         "in": "path",
                         "required": true,
                         "description": format!("Path parameter of type {}", param_type),
-                        "schema": {
-                            "$ref": format!("#/components/schemas/{}", param_type)
-                        }
+                        "schema": schema_of(param_type)
                     }));
                 }
 
@@ -641,9 +706,7 @@ pub fn generate_openapi_code(
                         "in": "query",
                         "required": !is_optional,
                         "description": format!("Query parameter of type {}", param_type),
-                        "schema": {
-                            "$ref": format!("#/components/schemas/{}", param_type)
-                        }
+                        "schema": schema_of(param_type)
                     }));
                 }
 
@@ -667,9 +730,7 @@ pub fn generate_openapi_code(
                         "required": true,
                         "content": {
                             "application/json": {
-                                "schema": {
-                                    "$ref": format!("#/components/schemas/{}", endpoint.request_type_name)
-                                }
+                                "schema": schema_of(&endpoint.request_type_name)
                             }
                         }
                     });
@@ -684,6 +745,10 @@ pub fn generate_openapi_code(
                     if !endpoint.permissions.is_empty() {
                         operation["x-permissions"] = json!(endpoint.permissions);
                     }
+
+                    if let Some(expression) = &endpoint.permission_expression {
+                        operation["x-permission-expression"] = json!(expression);
+                    }
                 }
 
                 // Add the operation to the path item
@@ -699,7 +764,7 @@ pub fn generate_openapi_code(
                 },
                 "paths": paths,
                 "components": {
-                    "schemas": final_schemas,
+                    "schemas": schemas,
                     "securitySchemes": {
                         "bearerAuth": {
                             "type": "http",
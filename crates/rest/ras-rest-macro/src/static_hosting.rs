@@ -1,9 +1,12 @@
 //! Static API explorer hosting for REST services
 
-use crate::ServiceDefinition;
+use crate::{PermissionRequirement, ServiceDefinition, static_permission_expr_code};
 use proc_macro2::TokenStream;
 use quote::quote;
 
+/// Number of requests the explorer keeps in its history.
+const EXPLORER_HISTORY_LIMIT: usize = 50;
+
 /// Configuration for static file hosting.
 #[derive(Debug, Clone)]
 pub struct StaticHostingConfig {
@@ -13,6 +16,8 @@ pub struct StaticHostingConfig {
     pub docs_path: String,
     /// UI theme selection retained for macro compatibility.
     pub ui_theme: String,
+    /// Permissions required to view the docs; `None` keeps them public.
+    pub docs_auth: Option<PermissionRequirement>,
 }
 
 impl Default for StaticHostingConfig {
@@ -21,6 +26,7 @@ impl Default for StaticHostingConfig {
             serve_docs: false,
             docs_path: "/docs".to_string(),
             ui_theme: "default".to_string(),
+            docs_auth: None,
         }
     }
 }
@@ -66,7 +72,8 @@ pub fn generate_static_hosting_code(
                     "serviceName": stringify!(#service_name),
                     "protocol": "rest",
                     "specPath": #spec_path,
-                    "apiBasePath": #api_base_path
+                    "apiBasePath": #api_base_path,
+                    "historyLimit": #EXPLORER_HISTORY_LIMIT
                 })
                 .to_string()
                 .replace("<", "\\u003c");
@@ -100,12 +107,129 @@ pub fn generate_static_routes(
         service_def.service_name.to_string().to_lowercase()
     );
 
+    let docs_auth = match &static_config.docs_auth {
+        Some(requirement) => {
+            let layer = generate_docs_auth_layer(
+                &service_def.service_name.to_string(),
+                "rest",
+                requirement,
+                quote! { self.auth_provider.clone() },
+                quote! { auth_source.as_deref() },
+            );
+            quote! { let docs_router = docs_router.route_layer(#layer); }
+        }
+        None => quote! {},
+    };
+
     quote! {
         #[cfg(feature = "server")]
         {
-            router = router
+            let docs_router = ::axum::Router::new()
                 .route(#docs_path, ::axum::routing::get(#docs_handler_name))
                 .route(#openapi_path, ::axum::routing::get(openapi_json_handler));
+            #docs_auth
+            router = router.merge(docs_router);
+        }
+    }
+}
+
+/// Generates a middleware layer running the Bearer-token check of
+/// `WITH_PERMISSIONS` endpoints in front of the docs routes.
+///
+/// `auth_source` is evaluated once and cloned into every request;
+/// `provider_ref` turns that clone (bound as `auth_source`) into an
+/// `Option<&dyn AuthProvider>`. Browsers get a sign-in page that stores the
+/// token for the explorer, other clients a JSON error.
+pub fn generate_docs_auth_layer(
+    service_name: &str,
+    protocol: &str,
+    requirement: &PermissionRequirement,
+    auth_source: TokenStream,
+    provider_ref: TokenStream,
+) -> TokenStream {
+    const LOGIN_TEMPLATE: &str = include_str!("docs_login_template.html");
+    let login_template = syn::LitStr::new(LOGIN_TEMPLATE, proc_macro2::Span::call_site());
+    let (required, capture, permitted) = match requirement {
+        PermissionRequirement::Groups(permission_groups) => {
+            let groups = permission_groups.iter().map(|group| {
+                quote! { vec![#(#group.to_string()),*] }
+            });
+            (
+                quote! { let required_permission_groups: Vec<Vec<String>> = vec![#(#groups),*]; },
+                quote! { let required_permission_groups = required_permission_groups.clone(); },
+                quote! {
+                    required_permission_groups.is_empty()
+                        || required_permission_groups
+                            .iter()
+                            .any(|group| group.is_empty() || provider.check_permissions(&user, group).is_ok())
+                },
+            )
+        }
+        PermissionRequirement::Expression(expression) => {
+            let expression_code = static_permission_expr_code(expression);
+            (
+                quote! { let required_permissions: &'static ::ras_auth_core::PermissionExpr = #expression_code; },
+                quote! {},
+                quote! { provider.check_permission_expr(&user, required_permissions).is_ok() },
+            )
+        }
+    };
+
+    quote! {
+        {
+            let docs_auth_source = #auth_source;
+            #required
+            ::axum::middleware::from_fn(move |request: ::axum::extract::Request, next: ::axum::middleware::Next| {
+                let auth_source = docs_auth_source.clone();
+                #capture
+                async move {
+                    use ::axum::response::IntoResponse;
+
+                    let wants_html = request
+                        .headers()
+                        .get(::axum::http::header::ACCEPT)
+                        .and_then(|value| value.to_str().ok())
+                        .is_some_and(|accept| accept.contains("text/html"));
+                    let denied = |status: ::axum::http::StatusCode, message: &str| {
+                        if wants_html {
+                            const TEMPLATE: &str = #login_template;
+                            let config_json = ::serde_json::json!({
+                                "serviceName": #service_name,
+                                "protocol": #protocol,
+                                "status": status.as_u16(),
+                                "message": message
+                            })
+                            .to_string()
+                            .replace("<", "\\u003c");
+                            (status, ::axum::response::Html(TEMPLATE.replace("{DOCS_LOGIN_CONFIG_JSON}", &config_json))).into_response()
+                        } else {
+                            (status, ::axum::Json(::serde_json::json!({ "error": message }))).into_response()
+                        }
+                    };
+
+                    let token = match #provider_ref {
+                        Some(provider) => provider.credential(request.headers()),
+                        None => ::ras_auth_core::header_credential(request.headers()),
+                    };
+                    let Some(token) = token else {
+                        return denied(::axum::http::StatusCode::UNAUTHORIZED, "Sign in to view the API documentation");
+                    };
+                    let Some(provider) = #provider_ref else {
+                        return denied(::axum::http::StatusCode::INTERNAL_SERVER_ERROR, "No auth provider configured");
+                    };
+                    let user = match provider.authenticate_request(token, request.headers()).await {
+                        Ok(user) => user,
+                        Err(_) => return denied(::axum::http::StatusCode::UNAUTHORIZED, "Authentication failed"),
+                    };
+
+                    let permitted = #permitted;
+                    if !permitted {
+                        return denied(::axum::http::StatusCode::FORBIDDEN, "Insufficient permissions");
+                    }
+
+                    next.run(request).await
+                }
+            })
         }
     }
 }
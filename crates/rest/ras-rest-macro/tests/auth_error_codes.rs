@@ -0,0 +1,114 @@
+//! Machine-readable codes of refused requests, in response bodies and as
+//! read by generated clients.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
+use ras_rest_core::{HttpError, RestResponse, RestResult};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::spawn_http;
+
+rest_service!({
+    service_name: Vault,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        GET WITH_PERMISSIONS(["admin"]) secret() -> String,
+    ]
+});
+
+struct VaultImpl;
+
+#[async_trait::async_trait]
+impl VaultTrait for VaultImpl {
+    async fn get_secret(&self, _user: &AuthenticatedUser) -> RestResult<String> {
+        Ok(RestResponse::ok("opened".to_string()))
+    }
+}
+
+/// Accepts `user`, and refuses `locked` and `expired` tokens as such
+struct Accounts;
+
+impl AuthProvider for Accounts {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        Box::pin(async move {
+            match token.as_str() {
+                "user" => Ok(AuthenticatedUser {
+                    user_id: "user-1".to_string(),
+                    permissions: HashSet::from(["user".to_string()]),
+                    metadata: None,
+                    kind: PrincipalKind::User,
+                }),
+                "locked" => Err(AuthError::AccountLocked {
+                    retry_after: Some(Duration::from_secs(30)),
+                }),
+                "expired" => Err(AuthError::TokenExpired),
+                _ => Err(AuthError::InvalidToken),
+            }
+        })
+    }
+}
+
+fn router() -> axum::Router {
+    VaultBuilder::new(VaultImpl).auth_provider(Accounts).build()
+}
+
+#[tokio::test]
+async fn refusals_carry_their_code_and_details() {
+    let server = spawn_http(router());
+    let url = server.server_url("/api/secret").unwrap();
+
+    let response = reqwest::Client::new()
+        .get(url.clone())
+        .bearer_auth("locked")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+    assert_eq!(response.headers()["retry-after"], "30");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({ "error": "Authentication failed", "code": "account_locked" })
+    );
+
+    let response = reqwest::Client::new()
+        .get(url)
+        .bearer_auth("user")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "Insufficient permissions");
+    assert_eq!(body["code"], "insufficient_permissions");
+    assert_eq!(
+        body["details"],
+        serde_json::json!({ "required": ["admin"], "has": ["user"] })
+    );
+}
+
+#[tokio::test]
+async fn clients_read_the_code_of_refused_requests() {
+    let server = spawn_http(router());
+    let base = server.server_address().unwrap().to_string();
+
+    for (token, status, code) in [
+        (None, 401, "authentication_required"),
+        (Some("expired"), 401, "token_expired"),
+        (Some("bogus"), 401, "invalid_token"),
+        (Some("locked"), 403, "account_locked"),
+        (Some("user"), 403, "insufficient_permissions"),
+    ] {
+        let mut client = VaultClient::builder(&base).build().unwrap();
+        client.set_bearer_token(token);
+        let error = client.get_secret().await.unwrap_err();
+        let error = error
+            .downcast_ref::<HttpError>()
+            .expect("not an HTTP error");
+        assert_eq!(error.status, status, "{token:?}");
+        assert_eq!(error.code(), Some(code), "{token:?}");
+    }
+}
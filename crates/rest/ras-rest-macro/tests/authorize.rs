@@ -0,0 +1,291 @@
+//! Authorization callbacks checking requests against their path parameters and body.
+
+use std::future::{Ready, ready};
+
+use ras_auth_core::{AccessDenied, AuthenticatedUser};
+use ras_rest_core::{RestResponse, RestResult};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::{MockAuthProvider, mock_user, spawn_http};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+rest_service!({
+    service_name: Board,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        PUT WITH_PERMISSIONS(["user"]) tasks/{id: u64}(TaskUpdate) -> String,
+        DELETE WITH_PERMISSIONS(["user"]) boards/{board: String}/tasks/{id: u64}() -> (),
+        GET WITH_PERMISSIONS(["user"]) tasks() -> Vec<u64>,
+        PUT WITH_PERMISSIONS(["user"]) v2/tasks/{id: u64}(TaskUpdate) -> String {
+            version: v2,
+            versions: [
+                v1 {
+                    path: v1/tasks/{id: u64},
+                    body: TaskUpdate,
+                    response: String,
+                    migration: TitleCompat,
+                },
+            ],
+        },
+    ]
+});
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct TaskUpdate {
+    pub title: String,
+}
+
+struct TitleCompat;
+
+impl ras_rest_core::VersionMigration<BoardPutV2TasksByIdV1Request, BoardPutV2TasksByIdV2Request>
+    for TitleCompat
+{
+    type Error = std::convert::Infallible;
+
+    fn migrate(
+        value: BoardPutV2TasksByIdV1Request,
+    ) -> Result<BoardPutV2TasksByIdV2Request, Self::Error> {
+        Ok(BoardPutV2TasksByIdV2Request {
+            path: BoardPutV2TasksByIdV2Path { id: value.path.id },
+            query: BoardPutV2TasksByIdV2Query {},
+            body: TaskUpdate {
+                title: value.body.title.to_uppercase(),
+            },
+        })
+    }
+}
+
+impl ras_rest_core::VersionMigration<String, String> for TitleCompat {
+    type Error = std::convert::Infallible;
+
+    fn migrate(value: String) -> Result<String, Self::Error> {
+        Ok(value)
+    }
+}
+
+struct BoardImpl;
+
+#[async_trait::async_trait]
+impl BoardTrait for BoardImpl {
+    async fn put_tasks_by_id(
+        &self,
+        _user: &AuthenticatedUser,
+        id: u64,
+        update: TaskUpdate,
+    ) -> RestResult<String> {
+        Ok(RestResponse::ok(format!("{id}: {}", update.title)))
+    }
+
+    async fn delete_boards_by_board_tasks_by_id(
+        &self,
+        _user: &AuthenticatedUser,
+        _board: String,
+        _id: u64,
+    ) -> RestResult<()> {
+        Ok(RestResponse::ok(()))
+    }
+
+    async fn get_tasks(&self, _user: &AuthenticatedUser) -> RestResult<Vec<u64>> {
+        Ok(RestResponse::ok(vec![1, 2]))
+    }
+
+    async fn put_v2_tasks_by_id(
+        &self,
+        _user: &AuthenticatedUser,
+        id: u64,
+        update: TaskUpdate,
+    ) -> RestResult<String> {
+        Ok(RestResponse::ok(format!("{id}: {}", update.title)))
+    }
+}
+
+/// Task 1 belongs to `user-1`, every other task to `admin-1`
+fn owner_of(task: u64) -> &'static str {
+    if task == 1 { "user-1" } else { "admin-1" }
+}
+
+/// Users may only edit their own tasks, and never give them an empty title
+fn only_owner(
+    user: &AuthenticatedUser,
+    (id,): (&u64,),
+    update: &TaskUpdate,
+) -> Ready<Result<(), AccessDenied>> {
+    let owner = owner_of(*id);
+    ready(if user.user_id != owner {
+        Err(AccessDenied::new("not_owner").with_detail("owner", owner))
+    } else if update.title.is_empty() {
+        Err(AccessDenied::new("empty_title"))
+    } else {
+        Ok(())
+    })
+}
+
+fn serve() -> axum_test::TestServer {
+    let provider = MockAuthProvider::default()
+        .with_token("guest-token", mock_user("guest-1", &["user", "guest"]));
+    spawn_http(
+        BoardBuilder::new(BoardImpl)
+            .auth_provider(provider)
+            .authorize_put_tasks_by_id(only_owner)
+            .authorize_delete_boards_by_board_tasks_by_id(|user, (board, id)| {
+                let allowed = board == "shared" || owner_of(*id) == user.user_id;
+                async move {
+                    if allowed {
+                        Ok(())
+                    } else {
+                        Err(AccessDenied::new("not_owner"))
+                    }
+                }
+            })
+            .authorize_get_tasks(|user, ()| {
+                let guest = user.permissions.contains("guest");
+                async move {
+                    if guest {
+                        Err(AccessDenied::new("guest"))
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .authorize_put_v2_tasks_by_id(only_owner)
+            .build(),
+    )
+}
+
+async fn send(
+    server: &axum_test::TestServer,
+    method: reqwest::Method,
+    path: &str,
+    token: &str,
+    title: Option<&str>,
+) -> (StatusCode, serde_json::Value) {
+    let mut request = reqwest::Client::new()
+        .request(method, server.server_url(path).unwrap())
+        .bearer_auth(token);
+    if let Some(title) = title {
+        request = request.json(&serde_json::json!({ "title": title }));
+    }
+    let response = request.send().await.unwrap();
+    (response.status(), response.json().await.unwrap())
+}
+
+#[test]
+fn callbacks_are_testable_alone() {
+    let user = mock_user("user-1", &["user"]);
+    let update = TaskUpdate {
+        title: "ship it".to_string(),
+    };
+    assert!(only_owner(&user, (&1,), &update).into_inner().is_ok());
+    assert_eq!(
+        only_owner(&user, (&2,), &update).into_inner(),
+        Err(AccessDenied::new("not_owner").with_detail("owner", "admin-1"))
+    );
+}
+
+#[tokio::test]
+async fn owners_pass_and_others_get_403_with_the_reason() {
+    let server = serve();
+    let put = reqwest::Method::PUT;
+
+    let (status, body) = send(
+        &server,
+        put.clone(),
+        "/api/tasks/1",
+        "user-token",
+        Some("mine"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "1: mine");
+
+    let (status, body) = send(
+        &server,
+        put.clone(),
+        "/api/tasks/2",
+        "user-token",
+        Some("theirs"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "error": "Access denied",
+            "code": "access_denied",
+            "details": { "reason": "not_owner", "owner": "admin-1" }
+        })
+    );
+
+    // The body is checked too
+    let (status, body) = send(&server, put.clone(), "/api/tasks/1", "user-token", Some("")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["details"]["reason"], "empty_title");
+
+    // The static permission check still comes first
+    let (status, body) = send(&server, put, "/api/tasks/1", "readonly-token", Some("mine")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "insufficient_permissions");
+}
+
+#[tokio::test]
+async fn callbacks_see_every_path_parameter() {
+    let server = serve();
+    let delete = reqwest::Method::DELETE;
+
+    for (path, status) in [
+        ("/api/boards/shared/tasks/2", StatusCode::OK),
+        ("/api/boards/private/tasks/1", StatusCode::OK),
+        ("/api/boards/private/tasks/2", StatusCode::FORBIDDEN),
+    ] {
+        let response = reqwest::Client::new()
+            .request(delete.clone(), server.server_url(path).unwrap())
+            .bearer_auth("user-token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status, "{path}");
+    }
+
+    let (status, _) = send(
+        &server,
+        reqwest::Method::GET,
+        "/api/tasks",
+        "user-token",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = send(
+        &server,
+        reqwest::Method::GET,
+        "/api/tasks",
+        "guest-token",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["details"]["reason"], "guest");
+}
+
+#[tokio::test]
+async fn legacy_versions_are_checked_after_migration() {
+    let server = serve();
+    let put = reqwest::Method::PUT;
+
+    let (status, body) = send(
+        &server,
+        put.clone(),
+        "/api/v1/tasks/1",
+        "user-token",
+        Some("old"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "1: OLD");
+
+    let (status, body) = send(&server, put, "/api/v1/tasks/2", "user-token", Some("old")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["details"]["reason"], "not_owner");
+}
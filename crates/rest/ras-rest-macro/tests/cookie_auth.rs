@@ -0,0 +1,148 @@
+//! Browser clients authenticated by the session cookie, with CSRF checks.
+
+use axum::http::Response;
+use ras_identity_session::{
+    CookieAuthProvider, CookieConfig, csrf_protection, issue_session_cookie,
+};
+use ras_rest_core::{RestResponse, RestResult};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+use reqwest::StatusCode;
+use std::sync::Arc;
+
+rest_service!({
+    service_name: Notes,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        GET WITH_PERMISSIONS(["user"]) notes() -> Vec<String>,
+        POST WITH_PERMISSIONS(["user"]) notes(String) -> String,
+    ]
+});
+
+struct NotesImpl;
+
+#[async_trait::async_trait]
+impl NotesTrait for NotesImpl {
+    async fn get_notes(&self, _user: &ras_auth_core::AuthenticatedUser) -> RestResult<Vec<String>> {
+        Ok(RestResponse::ok(vec!["first".to_string()]))
+    }
+
+    async fn post_notes(
+        &self,
+        _user: &ras_auth_core::AuthenticatedUser,
+        note: String,
+    ) -> RestResult<String> {
+        Ok(RestResponse::ok(note))
+    }
+}
+
+fn serve() -> axum_test::TestServer {
+    let config = CookieConfig {
+        secure: false,
+        ..CookieConfig::default()
+    };
+    spawn_http(
+        NotesBuilder::new(NotesImpl)
+            .auth_provider(CookieAuthProvider::new(
+                MockAuthProvider::default(),
+                config.clone(),
+            ))
+            .build()
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(config),
+                csrf_protection,
+            )),
+    )
+}
+
+/// The `Cookie` header a browser would send after login, and the CSRF token
+fn login(token: &str) -> (String, String) {
+    let mut response = Response::new(());
+    let csrf = issue_session_cookie(&mut response, token, &CookieConfig::default()).unwrap();
+    let cookies = response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap().split(';').next().unwrap())
+        .collect::<Vec<_>>()
+        .join("; ");
+    (cookies, csrf)
+}
+
+#[tokio::test]
+async fn the_session_cookie_authenticates() {
+    let server = serve();
+    let client = reqwest::Client::new();
+    let url = server.server_url("/api/notes").unwrap();
+
+    let (cookies, _) = login("user-token");
+    let response = client
+        .get(url.clone())
+        .header("cookie", &cookies)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Clients without the cookie still authenticate by header
+    let response = client
+        .get(url.clone())
+        .bearer_auth("user-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (cookies, _) = login("bogus");
+    let response = client
+        .get(url)
+        .header("cookie", cookies)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn state_changes_need_the_csrf_token() {
+    let server = serve();
+    let client = reqwest::Client::new();
+    let url = server.server_url("/api/notes").unwrap();
+    let (cookies, csrf) = login("user-token");
+
+    for header in [None, Some("forged")] {
+        let mut request = client
+            .post(url.clone())
+            .header("cookie", &cookies)
+            .json(&"note");
+        if let Some(header) = header {
+            request = request.header("x-csrf-token", header);
+        }
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "Missing or invalid CSRF token");
+    }
+
+    let response = client
+        .post(url.clone())
+        .header("cookie", &cookies)
+        .header("x-csrf-token", &csrf)
+        .json(&"note")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Header-authenticated clients can't be made to send it by another site
+    let response = client
+        .post(url)
+        .bearer_auth("user-token")
+        .json(&"note")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
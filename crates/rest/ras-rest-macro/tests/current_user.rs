@@ -0,0 +1,62 @@
+//! The user of a request, read by code the handlers call through `current_user`.
+
+use ras_rest_core::{RestResponse, RestResult};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+
+rest_service!({
+    service_name: Profile,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        GET WITH_PERMISSIONS(["user"]) me() -> String,
+    ]
+});
+
+/// Domain code far from the handler, including a task it spawns
+async fn describe() -> String {
+    let spawned = tokio::spawn(ras_auth_core::propagate_user(async {
+        ras_auth_core::current_user().map(|user| user.user_id.clone())
+    }))
+    .await
+    .unwrap();
+    let user = ras_auth_core::current_user().expect("no current user");
+    format!("{} ({:?})", user.user_id, spawned)
+}
+
+struct ProfileImpl;
+
+#[async_trait::async_trait]
+impl ProfileTrait for ProfileImpl {
+    async fn get_me(&self, _user: &ras_auth_core::AuthenticatedUser) -> RestResult<String> {
+        Ok(RestResponse::ok(describe().await))
+    }
+}
+
+#[tokio::test]
+async fn handlers_and_spawned_tasks_see_the_user() {
+    let server = spawn_http(
+        ProfileBuilder::new(ProfileImpl)
+            .auth_provider(MockAuthProvider::default())
+            .build(),
+    );
+    let url = server.server_url("/api/me").unwrap();
+
+    for (token, expected) in [
+        ("user-token", "user-1 (Some(\"user-1\"))"),
+        ("admin-token", "admin-1 (Some(\"admin-1\"))"),
+    ] {
+        let me: String = reqwest::Client::new()
+            .get(url.clone())
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(me, expected);
+    }
+    assert!(ras_auth_core::current_user().is_none());
+}
@@ -0,0 +1,123 @@
+//! Bearer-token protection of the generated docs routes.
+
+use ras_rest_core::{RestResponse, RestResult};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+use reqwest::StatusCode;
+
+rest_service!({
+    service_name: Guarded,
+    base_path: "/api",
+    openapi: true,
+    serve_docs: true,
+    docs_path: "/docs",
+    docs_auth: WITH_PERMISSIONS(["admin"]),
+    endpoints: [
+        GET UNAUTHORIZED ping() -> String,
+    ]
+});
+
+struct GuardedImpl;
+
+#[async_trait::async_trait]
+impl GuardedTrait for GuardedImpl {
+    async fn get_ping(&self) -> RestResult<String> {
+        Ok(RestResponse::ok("pong".to_string()))
+    }
+}
+
+fn serve() -> axum_test::TestServer {
+    spawn_http(
+        GuardedBuilder::new(GuardedImpl)
+            .auth_provider(MockAuthProvider::default())
+            .build(),
+    )
+}
+
+async fn get(
+    server: &axum_test::TestServer,
+    path: &str,
+    accept: &str,
+    token: Option<&str>,
+) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .get(server.server_url(path).unwrap())
+        .header("accept", accept);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn browsers_without_a_token_get_the_login_page() {
+    let server = serve();
+
+    let response = get(&server, "/api/docs", "text/html", None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("id=\"login-form\""));
+    assert!(html.contains("Sign in to view the API documentation"));
+    assert!(!html.contains("{DOCS_LOGIN_CONFIG_JSON}"));
+}
+
+#[tokio::test]
+async fn openapi_json_requires_a_permitted_token() {
+    let server = serve();
+
+    let response = get(&server, "/api/docs/openapi.json", "application/json", None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "Sign in to view the API documentation");
+
+    let response = get(
+        &server,
+        "/api/docs/openapi.json",
+        "application/json",
+        Some("bogus"),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = get(
+        &server,
+        "/api/docs/openapi.json",
+        "application/json",
+        Some("user-token"),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = get(
+        &server,
+        "/api/docs/openapi.json",
+        "application/json",
+        Some("admin-token"),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["paths"].get("/ping").is_some());
+}
+
+#[tokio::test]
+async fn permitted_tokens_get_the_docs_and_endpoints_stay_open() {
+    let server = serve();
+
+    let response = get(&server, "/api/docs", "text/html", Some("admin-token")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.text().await.unwrap().contains("id=\"login-form\""));
+
+    let response = get(&server, "/api/docs", "text/html", Some("user-token")).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("Insufficient permissions")
+    );
+
+    let response = get(&server, "/api/ping", "application/json", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
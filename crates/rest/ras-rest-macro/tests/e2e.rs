@@ -131,7 +131,9 @@ impl DemoTrait for DemoImpl {
                 id: 1,
                 name: "alpha".into(),
             }],
-        }))
+        })
+        .with_header("X-Total-Count", "1")
+        .with_header("Link", "</api/items?page=2>; rel=\"next\""))
     }
 
     async fn get_items_by_id(&self, _user: &AuthenticatedUser, id: u32) -> RestResult<Item> {
@@ -275,6 +277,33 @@ async fn unauth_get_round_trips() {
     assert_eq!(resp.items[0].name, "alpha");
 }
 
+#[tokio::test]
+async fn with_meta_exposes_handler_headers_and_status() {
+    let server = spawn_http(router());
+    let base = server.server_address().unwrap().to_string();
+
+    let envelope = client(&base)
+        .get_items_with_meta()
+        .await
+        .expect("get_items_with_meta ok");
+    assert_eq!(envelope.status, 200);
+    assert_eq!(envelope.header("x-total-count"), Some("1"));
+    assert_eq!(
+        envelope.header("link"),
+        Some("</api/items?page=2>; rel=\"next\"")
+    );
+    assert_eq!(envelope.body.items[0].name, "alpha");
+
+    let mut c = client(&base);
+    c.set_bearer_token(Some("admin-token".to_string()));
+    let envelope = c
+        .post_items_with_meta(CreateItem { name: "foo".into() })
+        .await
+        .expect("post_items_with_meta ok");
+    assert_eq!(envelope.status, 201);
+    assert_eq!(envelope.body.name, "foo");
+}
+
 #[tokio::test]
 async fn legacy_rest_version_round_trips_through_canonical_handler() {
     let server = spawn_http(router());
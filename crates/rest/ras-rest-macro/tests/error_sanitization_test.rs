@@ -1,5 +1,5 @@
 use axum::{Router, http::StatusCode};
-use ras_auth_core::{AuthError, AuthProvider, AuthenticatedUser};
+use ras_auth_core::{AuthError, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_rest_core::{RestError, RestResponse};
 use ras_rest_macro::rest_service;
 use serde::{Deserialize, Serialize};
@@ -44,6 +44,7 @@ impl AuthProvider for MockAuthProvider {
                     user_id: "test-user".to_string(),
                     permissions,
                     metadata: None,
+                    kind: PrincipalKind::User,
                 })
             } else {
                 Err(AuthError::InvalidToken)
@@ -1,5 +1,5 @@
 use rand::Rng;
-use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_rest_core::{RestError, RestResponse};
 use ras_rest_macro::rest_service;
 use serde::{Deserialize, Serialize};
@@ -102,6 +102,7 @@ impl AuthProvider for TestRestAuthProvider {
                 user_id: user_id.to_string(),
                 permissions: permissions.into_iter().collect(),
                 metadata: None,
+                kind: PrincipalKind::User,
             })
         })
     }
@@ -594,6 +595,17 @@ async fn test_authentication_required_endpoints() {
     assert_eq!(post.id, Some(456));
     assert_eq!(post.user_id, 123);
     assert_eq!(post.title, "Protected Post");
+
+    // API keys are sent with their own scheme; other schemes are refused
+    for (scheme, expected) in [("ApiKey", 200), ("Basic", 401)] {
+        let response = reqwest::Client::new()
+            .get(format!("{}/api/v1/status", base_url))
+            .header("Authorization", format!("{} user-token", scheme))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected, "{} scheme", scheme);
+    }
 }
 
 #[tokio::test]
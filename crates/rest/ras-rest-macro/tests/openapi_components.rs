@@ -0,0 +1,197 @@
+//! Schemas in the OpenAPI document: named types are documented once, as
+//! components, and referenced with `$ref` wherever they're used.
+
+use ras_rest_macro::rest_service;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+mod billing {
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, JsonSchema)]
+    pub struct Account {
+        pub iban: String,
+    }
+}
+
+mod identity {
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, JsonSchema)]
+    pub struct Account {
+        pub username: String,
+        pub admin: bool,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Address {
+    pub street: String,
+    pub city: String,
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Customer {
+    pub id: u64,
+    pub name: String,
+    pub account: billing::Account,
+    pub address: Address,
+    pub previous_addresses: Vec<Address>,
+}
+
+rest_service!({
+    service_name: Ledger,
+    base_path: "/api",
+    openapi: true,
+    endpoints: [
+        GET UNAUTHORIZED customers() -> Vec<Customer>,
+        POST UNAUTHORIZED customers(Customer) -> Customer,
+        GET UNAUTHORIZED customers/{id: u64}() -> Customer,
+        PUT UNAUTHORIZED customers/{id: u64}(Customer) -> Customer,
+        GET UNAUTHORIZED customers/{id: u64}/account() -> billing::Account,
+        GET UNAUTHORIZED session() -> identity::Account,
+    ]
+});
+
+const GOLDEN: &str = "tests/golden/ledger_openapi_components.json";
+
+/// Every `$ref` in `value`
+fn references(value: &Value, found: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match (key.as_str(), value) {
+                    ("$ref", Value::String(reference)) => found.push(reference.clone()),
+                    _ => references(value, found),
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| references(item, found)),
+        _ => {}
+    }
+}
+
+/// `value` with every component reference replaced by the component itself
+fn inlined(value: &Value, schemas: &Value) -> Value {
+    match value {
+        Value::Object(object) => match object.get("$ref").and_then(Value::as_str) {
+            Some(reference) => {
+                let name = reference.strip_prefix("#/components/schemas/").unwrap();
+                inlined(&schemas[name], schemas)
+            }
+            None => object
+                .iter()
+                .map(|(key, value)| (key.clone(), inlined(value, schemas)))
+                .collect(),
+        },
+        Value::Array(items) => items.iter().map(|item| inlined(item, schemas)).collect(),
+        other => other.clone(),
+    }
+}
+
+#[test]
+fn components_match_the_golden_file() {
+    let schemas = generate_ledger_openapi()["components"]["schemas"].clone();
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(GOLDEN);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let pretty = serde_json::to_string_pretty(&schemas).unwrap();
+        std::fs::write(&path, pretty + "\n").unwrap();
+    }
+
+    let golden: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(
+        schemas, golden,
+        "the schemas changed, rerun with UPDATE_GOLDEN=1 to accept them"
+    );
+}
+
+#[test]
+fn every_reference_resolves_to_a_component() {
+    let doc = generate_ledger_openapi();
+    let schemas = doc["components"]["schemas"].as_object().unwrap();
+
+    let mut found = Vec::new();
+    references(&doc, &mut found);
+    assert!(!found.is_empty());
+    for reference in found {
+        let name = reference.strip_prefix("#/components/schemas/").unwrap();
+        assert!(schemas.contains_key(name), "{reference} doesn't resolve");
+    }
+}
+
+#[test]
+fn shared_types_are_documented_once() {
+    let doc = generate_ledger_openapi();
+    let schemas = doc["components"]["schemas"].as_object().unwrap();
+
+    let customer = serde_json::json!({ "$ref": "#/components/schemas/Customer" });
+    let response = |path: &str, method: &str| {
+        doc["paths"][path][method]["responses"]["200"]["content"]["application/json"]["schema"]
+            .clone()
+    };
+    assert_eq!(response("/customers", "post"), customer);
+    assert_eq!(response("/customers/{id}", "get"), customer);
+    assert_eq!(response("/customers/{id}", "put"), customer);
+    assert_eq!(response("/customers", "get")["items"], customer);
+    assert_eq!(
+        doc["paths"]["/customers"]["post"]["requestBody"]["content"]["application/json"]["schema"],
+        customer
+    );
+    assert_eq!(
+        doc["paths"]["/customers/{id}"]["get"]["parameters"][0]["schema"]["type"],
+        "integer"
+    );
+
+    let document = serde_json::to_string(&doc).unwrap();
+    assert_eq!(document.matches("\"previous_addresses\":").count(), 1);
+    assert_eq!(document.matches("\"street\":").count(), 1);
+    assert_eq!(
+        schemas["Customer"]["properties"]["address"]["$ref"],
+        "#/components/schemas/Address"
+    );
+    assert_eq!(
+        schemas["Address"]["properties"]["country"]["nullable"],
+        true
+    );
+}
+
+#[test]
+fn types_with_the_same_name_are_qualified() {
+    let doc = generate_ledger_openapi();
+    let schemas = doc["components"]["schemas"].as_object().unwrap();
+
+    let accounts: Vec<&String> = schemas
+        .keys()
+        .filter(|name| name.starts_with("Account"))
+        .collect();
+    assert_eq!(accounts.len(), 2, "{accounts:?}");
+    assert!(schemas["Account"]["properties"].get("iban").is_some());
+
+    let qualified = accounts
+        .into_iter()
+        .find(|name| *name != "Account")
+        .unwrap();
+    let hash = qualified.strip_prefix("Account_").unwrap();
+    assert!(hash.len() == 8 && hash.chars().all(|c| c.is_ascii_hexdigit()));
+    assert!(schemas[qualified]["properties"].get("username").is_some());
+    assert_eq!(
+        doc["paths"]["/session"]["get"]["responses"]["200"]["content"]["application/json"]["schema"]
+            ["$ref"],
+        format!("#/components/schemas/{qualified}")
+    );
+}
+
+#[test]
+fn referencing_components_shrinks_the_document() {
+    let doc = generate_ledger_openapi();
+    let paths = inlined(&doc["paths"], &doc["components"]["schemas"]);
+
+    let size = serde_json::to_string(&doc).unwrap().len();
+    let inlined_size = serde_json::to_string(&paths).unwrap().len();
+    assert!(
+        size < inlined_size,
+        "{size} bytes isn't smaller than the {inlined_size} bytes of inlined paths"
+    );
+}
@@ -0,0 +1,138 @@
+//! `WITH_PERMISSIONS(expr = "...")` endpoints: their checks, docs and manifest.
+
+use ras_auth_core::AuthenticatedUser;
+use ras_rest_core::{AuthMode, RestResponse, RestResult};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::{MockAuthProvider, mock_user, spawn_http};
+use reqwest::StatusCode;
+
+rest_service!({
+    service_name: Tasks,
+    base_path: "/api",
+    openapi: true,
+    serve_docs: true,
+    docs_path: "/docs",
+    docs_auth: WITH_PERMISSIONS(expr = "admin | docs & !suspended"),
+    endpoints: [
+        POST WITH_PERMISSIONS(expr = "admin | (tasks:write & !suspended)") tasks(String) -> String,
+        GET WITH_PERMISSIONS(["tasks:read"]) tasks() -> Vec<String>,
+    ]
+});
+
+struct TasksImpl;
+
+#[async_trait::async_trait]
+impl TasksTrait for TasksImpl {
+    async fn post_tasks(&self, user: &AuthenticatedUser, title: String) -> RestResult<String> {
+        Ok(RestResponse::ok(format!("{title} by {}", user.user_id)))
+    }
+
+    async fn get_tasks(&self, _user: &AuthenticatedUser) -> RestResult<Vec<String>> {
+        Ok(RestResponse::ok(Vec::new()))
+    }
+}
+
+fn serve() -> axum_test::TestServer {
+    let provider = MockAuthProvider::default()
+        .with_token("writer-token", mock_user("writer-1", &["tasks:write"]))
+        .with_token(
+            "suspended-token",
+            mock_user("suspended-1", &["tasks:write", "docs", "suspended"]),
+        )
+        .with_token("docs-token", mock_user("docs-1", &["docs"]));
+    spawn_http(TasksBuilder::new(TasksImpl).auth_provider(provider).build())
+}
+
+async fn post_task(server: &axum_test::TestServer, token: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(server.server_url("/api/tasks").unwrap())
+        .json("write docs");
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn expressions_decide_who_may_call() {
+    let server = serve();
+
+    for (token, user) in [("admin-token", "admin-1"), ("writer-token", "writer-1")] {
+        let response = post_task(&server, Some(token)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let created: String = response.json().await.unwrap();
+        assert_eq!(created, format!("write docs by {user}"));
+    }
+
+    // A suspended writer is refused by the NOT, a plain user by both branches
+    for token in ["suspended-token", "user-token"] {
+        let response = post_task(&server, Some(token)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "insufficient_permissions");
+        assert_eq!(
+            body["details"]["required"],
+            serde_json::json!(["admin | tasks:write & !suspended"])
+        );
+    }
+
+    let response = post_task(&server, None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn docs_auth_accepts_expressions() {
+    let server = serve();
+
+    for (token, status) in [
+        ("docs-token", StatusCode::OK),
+        ("admin-token", StatusCode::OK),
+        ("suspended-token", StatusCode::FORBIDDEN),
+        ("user-token", StatusCode::FORBIDDEN),
+    ] {
+        let response = reqwest::Client::new()
+            .get(server.server_url("/api/docs/openapi.json").unwrap())
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status, "{token}");
+    }
+}
+
+#[test]
+fn openapi_documents_the_expression() {
+    let openapi = generate_tasks_openapi();
+
+    let post = &openapi["paths"]["/tasks"]["post"];
+    assert_eq!(
+        post["x-permission-expression"],
+        "admin | tasks:write & !suspended"
+    );
+    assert!(post.get("x-permissions").is_none());
+
+    let get = &openapi["paths"]["/tasks"]["get"];
+    assert_eq!(get["x-permissions"], serde_json::json!(["tasks:read"]));
+    assert!(get.get("x-permission-expression").is_none());
+}
+
+#[test]
+fn manifest_records_the_expression() {
+    let manifest = tasks_service_manifest();
+
+    let post = manifest.operation("post_tasks").unwrap();
+    assert_eq!(post.auth, AuthMode::Permissions);
+    assert!(post.permission_groups.is_empty());
+    assert_eq!(
+        post.permission_expression.as_deref(),
+        Some("admin | tasks:write & !suspended")
+    );
+
+    let get = manifest.operation("get_tasks").unwrap();
+    assert_eq!(get.permission_expression, None);
+    assert!(
+        manifest
+            .to_string()
+            .contains("POST /api/tasks: admin | tasks:write & !suspended")
+    );
+}
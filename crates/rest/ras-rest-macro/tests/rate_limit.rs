@@ -0,0 +1,122 @@
+//! Client-side rate limiting and 429 handling in the generated REST client.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ras_rest_core::RateLimiter;
+use ras_rest_macro::rest_service;
+use serde::{Deserialize, Serialize};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate, matchers};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Spend {
+    pub amount: u32,
+}
+
+rest_service!({
+    service_name: Quota,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        POST UNAUTHORIZED spend(Spend) -> Spend,
+    ]
+});
+
+/// Answers requests with the scripted `Retry-After` values, `None` meaning success,
+/// and records when each request arrived.
+#[derive(Clone, Default)]
+struct Throttling {
+    replies: Arc<Mutex<VecDeque<Option<&'static str>>>>,
+    arrivals: Arc<Mutex<Vec<Instant>>>,
+}
+
+impl Respond for Throttling {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        self.arrivals.lock().unwrap().push(Instant::now());
+
+        match self.replies.lock().unwrap().pop_front().flatten() {
+            Some(retry_after) => {
+                ResponseTemplate::new(429).insert_header("Retry-After", retry_after)
+            }
+            None => ResponseTemplate::new(200).set_body_json(body),
+        }
+    }
+}
+
+impl Throttling {
+    async fn serve(replies: impl IntoIterator<Item = Option<&'static str>>) -> (MockServer, Self) {
+        let throttling = Throttling::default();
+        throttling.replies.lock().unwrap().extend(replies);
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/api/spend"))
+            .respond_with(throttling.clone())
+            .mount(&server)
+            .await;
+        (server, throttling)
+    }
+
+    fn arrivals(&self) -> Vec<Instant> {
+        self.arrivals.lock().unwrap().clone()
+    }
+}
+
+#[tokio::test]
+async fn rate_limited_requests_wait_for_retry_after() {
+    let (server, throttling) = Throttling::serve([Some("1"), None]).await;
+    let client = QuotaClient::builder(server.uri()).build().unwrap();
+
+    let spent = client.post_spend(Spend { amount: 3 }).await.unwrap();
+    assert_eq!(spent, Spend { amount: 3 });
+
+    let arrivals = throttling.arrivals();
+    assert_eq!(arrivals.len(), 2);
+    assert!(arrivals[1] - arrivals[0] >= Duration::from_millis(950));
+}
+
+#[tokio::test]
+async fn rate_limited_retries_are_bounded() {
+    let (server, throttling) = Throttling::serve([Some("0"); 5]).await;
+    let client = QuotaClient::builder(server.uri())
+        .with_rate_limited_attempts(2)
+        .build()
+        .unwrap();
+
+    let error = client.post_spend(Spend { amount: 1 }).await.unwrap_err();
+    assert!(error.to_string().contains("429"), "{error}");
+    assert_eq!(throttling.arrivals().len(), 2);
+}
+
+#[tokio::test]
+async fn long_retry_after_is_returned_to_the_caller() {
+    let (server, throttling) = Throttling::serve([Some("3600"), None]).await;
+    let client = QuotaClient::builder(server.uri()).build().unwrap();
+
+    let start = Instant::now();
+    assert!(client.post_spend(Spend { amount: 1 }).await.is_err());
+    assert_eq!(throttling.arrivals().len(), 1);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn clones_share_the_rate_limit() {
+    let (server, throttling) = Throttling::serve([None; 6]).await;
+    let client = QuotaClient::builder(server.uri())
+        .with_rate_limiter(RateLimiter::new(20))
+        .build()
+        .unwrap();
+    let clone = client.clone();
+
+    let calls = (0..3).map(|_| client.post_spend(Spend { amount: 1 }));
+    let clone_calls = (0..3).map(|_| clone.post_spend(Spend { amount: 1 }));
+    let results = futures::future::join_all(calls.chain(clone_calls)).await;
+    assert!(results.iter().all(Result::is_ok));
+
+    // Six requests at 50ms intervals span at least 250ms
+    let arrivals = throttling.arrivals();
+    let span = *arrivals.iter().max().unwrap() - *arrivals.iter().min().unwrap();
+    assert!(span >= Duration::from_millis(240), "{span:?}");
+}
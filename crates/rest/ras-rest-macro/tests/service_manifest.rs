@@ -0,0 +1,118 @@
+//! Service manifests generated for REST services.
+
+use ras_auth_core::AuthenticatedUser;
+use ras_rest_core::{AuthMode, RestResponse, RestResult, ServiceManifest};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+
+rest_service!({
+    service_name: Ledger,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        /// Current balance
+        GET UNAUTHORIZED balance() -> u32,
+        POST WITH_PERMISSIONS([]) deposits(u32) -> u32,
+        DELETE WITH_PERMISSIONS(["admin"] | ["owner", "user"]) accounts/{id: String}() -> (),
+    ]
+});
+
+struct LedgerImpl;
+
+#[async_trait::async_trait]
+impl LedgerTrait for LedgerImpl {
+    async fn get_balance(&self) -> RestResult<u32> {
+        Ok(RestResponse::ok(40))
+    }
+
+    async fn post_deposits(&self, _user: &AuthenticatedUser, amount: u32) -> RestResult<u32> {
+        Ok(RestResponse::ok(amount))
+    }
+
+    async fn delete_accounts_by_id(
+        &self,
+        _user: &AuthenticatedUser,
+        _id: String,
+    ) -> RestResult<()> {
+        Ok(RestResponse::ok(()))
+    }
+}
+
+#[test]
+fn manifest_lists_every_endpoint() {
+    let manifest = ledger_service_manifest();
+    assert_eq!(manifest.service_name, "Ledger");
+
+    let balance = manifest.operation("get_balance").unwrap();
+    assert_eq!(balance.http_method.as_deref(), Some("GET"));
+    assert_eq!(balance.path.as_deref(), Some("/api/balance"));
+    assert_eq!(balance.docs.as_deref(), Some("Current balance"));
+    assert_eq!(balance.request_type, None);
+    assert_eq!(balance.response_type, "u32");
+
+    let deposits = manifest.operation("post_deposits").unwrap();
+    assert_eq!(deposits.auth, AuthMode::Authenticated);
+    assert_eq!(deposits.request_type.as_deref(), Some("u32"));
+
+    let delete = manifest.operation("delete_accounts_by_id").unwrap();
+    assert_eq!(delete.auth, AuthMode::Permissions);
+    assert_eq!(
+        delete.permission_groups,
+        [
+            vec!["admin".to_string()],
+            vec!["owner".to_string(), "user".to_string()]
+        ]
+    );
+}
+
+#[test]
+fn only_intended_endpoints_are_unauthorized() {
+    let unauthorized: Vec<String> = ledger_service_manifest()
+        .unauthorized_operations()
+        .map(|operation| operation.name.clone())
+        .collect();
+
+    assert_eq!(unauthorized, ["get_balance"]);
+}
+
+#[tokio::test]
+async fn manifest_route_requires_the_permission() {
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_manifest_route("admin")
+        .build();
+    let server = spawn_http(router);
+    let url = server.server_url("/api/_manifest").unwrap();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(url.clone())
+        .bearer_auth("admin-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let manifest: ServiceManifest = response.json().await.unwrap();
+    assert_eq!(manifest, ledger_service_manifest());
+
+    let response = client
+        .get(url.clone())
+        .bearer_auth("user-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = client.get(url).send().await.unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn manifest_route_is_off_by_default() {
+    let server = spawn_http(LedgerBuilder::new(LedgerImpl).build());
+    let url = server.server_url("/api/_manifest").unwrap();
+
+    let response = reqwest::Client::new().get(url).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+}
@@ -0,0 +1,658 @@
+//! Service metrics reported by generated REST handlers.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ras_auth_core::{AuthProviderChain, AuthenticatedUser, StaticTokenAuthProvider};
+use ras_rest_core::{
+    MethodDurationTracker, Observability, ObservabilityBuilder, RequestContext, RequestOutcome,
+    RestError, RestResponse, RestResult, ServiceMetrics, TrackerGuard, TrustedProxies,
+    UsageTracker,
+};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::{
+    CompletedRequest, MockAuthProvider, PayloadSize, RecordingMetrics, TrackerError, spawn_http,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+struct Deposit {
+    amount: u32,
+}
+
+rest_service!({
+    service_name: Ledger,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        GET UNAUTHORIZED balance() -> u32,
+        POST UNAUTHORIZED deposits(Deposit) -> u32,
+        POST WITH_PERMISSIONS(["admin"]) close() -> (),
+        GET UNAUTHORIZED broken() -> (),
+    ]
+});
+
+struct LedgerImpl;
+
+#[async_trait::async_trait]
+impl LedgerTrait for LedgerImpl {
+    async fn get_balance(&self) -> RestResult<u32> {
+        Ok(RestResponse::ok(40))
+    }
+
+    async fn post_deposits(&self, deposit: Deposit) -> RestResult<u32> {
+        Ok(RestResponse::ok(deposit.amount))
+    }
+
+    async fn post_close(&self, _user: &AuthenticatedUser) -> RestResult<()> {
+        Ok(RestResponse::ok(()))
+    }
+
+    async fn get_broken(&self) -> RestResult<()> {
+        Err(RestError::internal_server_error("ledger offline"))
+    }
+}
+
+/// Reports to recording metrics, with trackers keeping the methods of the
+/// requests they are told about, and how completed ones ended
+#[derive(Default)]
+struct Recorded {
+    metrics: RecordingMetrics,
+    seen: Arc<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    started: Mutex<Vec<String>>,
+    completed: Mutex<Vec<(String, RequestOutcome)>>,
+    contexts: Mutex<Vec<RequestContext>>,
+}
+
+#[async_trait::async_trait]
+impl UsageTracker for Seen {
+    async fn track_request(
+        &self,
+        _headers: &axum::http::HeaderMap,
+        _user: Option<&AuthenticatedUser>,
+        context: &RequestContext,
+    ) {
+        self.started.lock().unwrap().push(context.method.clone());
+        self.contexts.lock().unwrap().push(context.clone());
+    }
+}
+
+#[async_trait::async_trait]
+impl MethodDurationTracker for Seen {
+    async fn track_duration(
+        &self,
+        _context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: std::time::Duration,
+    ) {
+        unreachable!("generated services report completions");
+    }
+
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: std::time::Duration,
+        outcome: RequestOutcome,
+    ) {
+        self.completed
+            .lock()
+            .unwrap()
+            .push((context.method.clone(), outcome));
+        self.contexts.lock().unwrap().push(context.clone());
+    }
+}
+
+impl Observability for Recorded {
+    fn service_metrics(&self) -> Arc<dyn ServiceMetrics> {
+        Arc::new(self.metrics.clone())
+    }
+
+    fn usage_tracker(&self) -> Option<Arc<dyn UsageTracker>> {
+        Some(self.seen.clone())
+    }
+
+    fn method_duration_tracker(&self) -> Option<Arc<dyn MethodDurationTracker>> {
+        Some(self.seen.clone())
+    }
+}
+
+fn completed(method: &str, error_kind: Option<&str>) -> CompletedRequest {
+    CompletedRequest {
+        method: method.to_string(),
+        success: error_kind.is_none(),
+        error_kind: error_kind.map(str::to_string),
+    }
+}
+
+#[tokio::test]
+async fn requests_are_reported_with_their_outcome() {
+    let metrics = RecordingMetrics::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .build();
+    let server = spawn_http(router);
+    let url = |path: &str| server.server_url(path).unwrap();
+    let client = reqwest::Client::new();
+
+    client.get(url("/api/balance")).send().await.unwrap();
+    client
+        .post(url("/api/deposits"))
+        .header("Content-Type", "application/json")
+        .body("{\"amount\": \"lots\"}")
+        .send()
+        .await
+        .unwrap();
+    client
+        .post(url("/api/close"))
+        .bearer_auth("user-token")
+        .send()
+        .await
+        .unwrap();
+    client.post(url("/api/close")).send().await.unwrap();
+    client.get(url("/api/broken")).send().await.unwrap();
+
+    assert_eq!(
+        metrics.started(),
+        [
+            "GET /balance",
+            "POST /deposits",
+            "POST /close",
+            "POST /close",
+            "GET /broken"
+        ]
+    );
+    assert_eq!(
+        metrics.completed(),
+        [
+            completed("GET /balance", None),
+            completed("POST /deposits", Some("invalid_params")),
+            completed("POST /close", Some("forbidden")),
+            completed("POST /close", Some("unauthenticated")),
+            completed("GET /broken", Some("handler_error")),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn completed_requests_are_labelled_with_the_kind_of_caller() {
+    let metrics = RecordingMetrics::default();
+    let auth = AuthProviderChain::new()
+        .with(StaticTokenAuthProvider::new().with_token("cron-secret", "nightly-close", ["admin"]))
+        .with(MockAuthProvider::default());
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(auth)
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .build();
+    let server = spawn_http(router);
+    let url = server.server_url("/api/close").unwrap();
+    let client = reqwest::Client::new();
+
+    client
+        .post(url.clone())
+        .bearer_auth("cron-secret")
+        .send()
+        .await
+        .unwrap();
+    client
+        .post(url.clone())
+        .bearer_auth("user-token")
+        .send()
+        .await
+        .unwrap();
+    client.post(url).send().await.unwrap();
+
+    assert_eq!(
+        metrics.principal_kinds(),
+        [
+            Some("service_account".to_string()),
+            Some("user".to_string()),
+            Some("anonymous".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn observability_installs_metrics_and_trackers() {
+    let observability = Recorded::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_observability(&observability)
+        .build();
+    let server = spawn_http(router);
+    let client = reqwest::Client::new();
+
+    client
+        .get(server.server_url("/api/balance").unwrap())
+        .send()
+        .await
+        .unwrap();
+    client
+        .get(server.server_url("/api/broken").unwrap())
+        .send()
+        .await
+        .unwrap();
+    client
+        .post(server.server_url("/api/close").unwrap())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        observability.metrics.completed(),
+        [
+            completed("GET /balance", None),
+            completed("GET /broken", Some("handler_error")),
+            completed("POST /close", Some("unauthenticated")),
+        ]
+    );
+    assert_eq!(
+        *observability.seen.started.lock().unwrap(),
+        ["GET /balance", "GET /broken"]
+    );
+    assert_eq!(
+        *observability.seen.completed.lock().unwrap(),
+        [
+            ("GET /balance".to_string(), RequestOutcome::Success),
+            ("GET /broken".to_string(), RequestOutcome::HandlerError),
+            ("POST /close".to_string(), RequestOutcome::AuthFailure),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn the_last_completion_tracker_set_wins() {
+    let replaced = Recorded::default();
+    let installed = Arc::new(Seen::default());
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_observability(&replaced)
+        .with_completion_tracker(installed.clone())
+        .build();
+    let server = spawn_http(router);
+
+    reqwest::get(server.server_url("/api/broken").unwrap())
+        .await
+        .unwrap();
+
+    // Only the completion tracker is replaced
+    assert_eq!(
+        *installed.completed.lock().unwrap(),
+        [("GET /broken".to_string(), RequestOutcome::HandlerError)]
+    );
+    assert!(replaced.seen.completed.lock().unwrap().is_empty());
+    assert_eq!(*replaced.seen.started.lock().unwrap(), ["GET /broken"]);
+    assert_eq!(
+        replaced.metrics.completed(),
+        [completed("GET /broken", Some("handler_error"))]
+    );
+}
+
+#[tokio::test]
+async fn request_and_response_sizes_are_reported() {
+    let metrics = RecordingMetrics::default();
+    let seen = Arc::new(SizesSeen::default());
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .with_completion_tracker(seen.clone())
+        .build();
+    let server = spawn_http(router);
+    let url = |path: &str| server.server_url(path).unwrap();
+    let client = reqwest::Client::new();
+
+    client.get(url("/api/balance")).send().await.unwrap();
+    client
+        .post(url("/api/deposits"))
+        .header("Content-Type", "application/json")
+        .body("{\"amount\": 125}")
+        .send()
+        .await
+        .unwrap();
+
+    let size = |method: &str, bytes| PayloadSize {
+        method: method.to_string(),
+        bytes,
+    };
+    assert_eq!(
+        metrics.request_sizes(),
+        [size("GET /balance", 0), size("POST /deposits", 15)]
+    );
+    assert_eq!(
+        metrics.response_sizes(),
+        [size("GET /balance", 2), size("POST /deposits", 3)]
+    );
+    assert_eq!(
+        *seen.0.lock().unwrap(),
+        [
+            (Some("0".to_string()), Some("2".to_string())),
+            (Some("15".to_string()), Some("3".to_string()))
+        ]
+    );
+}
+
+/// Keeps the `request_bytes` and `response_bytes` of the completed requests it
+/// is told about
+#[derive(Default)]
+struct SizesSeen(Mutex<Vec<(Option<String>, Option<String>)>>);
+
+#[async_trait::async_trait]
+impl MethodDurationTracker for SizesSeen {
+    async fn track_duration(
+        &self,
+        context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: std::time::Duration,
+    ) {
+        self.0.lock().unwrap().push((
+            context.metadata.get("request_bytes").cloned(),
+            context.metadata.get("response_bytes").cloned(),
+        ));
+    }
+}
+
+/// A second service, in a module of its own since generated helpers are module-level
+mod gauge {
+    use ras_rest_core::{RestResponse, RestResult};
+    use ras_rest_macro::rest_service;
+    use ras_test_helpers::RecordingMetrics;
+
+    rest_service!({
+        service_name: Gauge,
+        base_path: "/api",
+        openapi: false,
+        serve_docs: false,
+        endpoints: [
+            GET UNAUTHORIZED in_flight() -> usize,
+        ]
+    });
+
+    /// Answers with the number of requests in flight while its handler runs
+    pub struct GaugeImpl {
+        pub metrics: RecordingMetrics,
+    }
+
+    #[async_trait::async_trait]
+    impl GaugeTrait for GaugeImpl {
+        async fn get_in_flight(&self) -> RestResult<usize> {
+            Ok(RestResponse::ok(self.metrics.in_flight()))
+        }
+    }
+}
+
+#[tokio::test]
+async fn requests_count_as_in_flight_while_their_handler_runs() {
+    use gauge::{GaugeBuilder, GaugeImpl};
+
+    let metrics = RecordingMetrics::default();
+    let router = GaugeBuilder::new(GaugeImpl {
+        metrics: metrics.clone(),
+    })
+    .with_service_metrics(Arc::new(metrics.clone()))
+    .build();
+    let server = spawn_http(router);
+
+    let in_flight: usize = reqwest::get(server.server_url("/api/in_flight").unwrap())
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(in_flight, 1);
+    assert_eq!(metrics.in_flight(), 0);
+    assert_eq!(metrics.peak_in_flight(), 1);
+}
+
+#[tokio::test]
+async fn failing_trackers_neither_fail_nor_stall_requests() {
+    let metrics = RecordingMetrics::default();
+    let guard = TrackerGuard::new().with_timeout(Duration::from_millis(50));
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .with_tracker_guard(guard.clone())
+        .with_usage_tracker(|_headers, _user, _method, _path| async {
+            panic!("usage backend unavailable")
+        })
+        .with_method_duration_tracker(|_method, _path, _user, _duration| {
+            tokio::time::sleep(Duration::from_secs(60))
+        })
+        .build();
+    let server = spawn_http(router);
+    let client = reqwest::Client::new();
+
+    let started = std::time::Instant::now();
+    for _ in 0..2 {
+        let response = client
+            .get(server.server_url("/api/balance").unwrap())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.json::<u32>().await.unwrap(), 40);
+    }
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let error = |tracker: &str, error: &str| TrackerError {
+        tracker: tracker.to_string(),
+        error: error.to_string(),
+    };
+    assert_eq!(
+        metrics.tracker_errors(),
+        [
+            error("usage_tracker", "panic"),
+            error("method_duration_tracker", "timeout"),
+            error("usage_tracker", "panic"),
+            error("method_duration_tracker", "timeout"),
+        ]
+    );
+    let health = guard.health();
+    let failing: Vec<_> = health
+        .failing(2)
+        .iter()
+        .map(|status| status.tracker.as_str())
+        .collect();
+    assert_eq!(failing, ["method_duration_tracker", "usage_tracker"]);
+}
+
+#[tokio::test]
+async fn contexts_carry_the_client_route_and_request_id() {
+    let observability = Recorded::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_observability(&observability)
+        .with_trusted_proxies(TrustedProxies::new(["10.0.0.0/8"]).unwrap())
+        .build()
+        .layer(axum::extract::connect_info::MockConnectInfo(
+            std::net::SocketAddr::from(([10, 0, 0, 1], 4000)),
+        ));
+    let server = spawn_http(router);
+
+    reqwest::Client::new()
+        .get(server.server_url("/api/balance").unwrap())
+        .header("x-forwarded-for", "198.51.100.7, 203.0.113.9")
+        .header("x-request-id", "req-7")
+        .send()
+        .await
+        .unwrap();
+
+    let contexts = observability.seen.contexts.lock().unwrap();
+    assert_eq!(contexts.len(), 2);
+    for context in contexts.iter() {
+        // 198.51.100.7 was forwarded by 203.0.113.9, which isn't trusted
+        assert_eq!(context.client_ip, Some("203.0.113.9".parse().unwrap()));
+        assert_eq!(context.route.as_deref(), Some("/balance"));
+        assert_eq!(context.request_id.as_deref(), Some("req-7"));
+    }
+}
+
+#[tokio::test]
+async fn contexts_carry_the_extracted_tenant() {
+    let observability = Recorded::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_observability(&observability)
+        .with_tenant_extractor(|headers, user, _context| {
+            // The caller's tenant, once known, over the one the caller claims
+            user.map(|user| format!("tenant-of-{}", user.user_id))
+                .or_else(|| Some(headers.get("x-tenant")?.to_str().ok()?.to_string()))
+        })
+        .build();
+    let server = spawn_http(router);
+
+    reqwest::Client::new()
+        .post(server.server_url("/api/close").unwrap())
+        .bearer_auth("admin-token")
+        .header("x-tenant", "acme")
+        .send()
+        .await
+        .unwrap();
+
+    // Started before the caller is authenticated
+    assert_eq!(
+        observability.metrics.tenants(),
+        [
+            Some("acme".to_string()),
+            Some("tenant-of-admin-1".to_string())
+        ]
+    );
+    let contexts = observability.seen.contexts.lock().unwrap();
+    assert_eq!(contexts.len(), 2);
+    assert_eq!(contexts[1].tenant(), Some("tenant-of-admin-1"));
+}
+
+#[tokio::test]
+async fn an_observability_config_drives_the_service() {
+    let metrics = RecordingMetrics::default();
+    let tracked = Arc::new(Mutex::new(Vec::new()));
+    let config = ObservabilityBuilder::new()
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .with_usage_tracker({
+            let tracked = tracked.clone();
+            move |_headers, _user, context: RequestContext| {
+                tracked
+                    .lock()
+                    .unwrap()
+                    .push(format!("usage {}", context.method));
+                async {}
+            }
+        })
+        .with_method_duration_tracker({
+            let tracked = tracked.clone();
+            move |context: RequestContext, _user, _duration| {
+                tracked
+                    .lock()
+                    .unwrap()
+                    .push(format!("duration {}", context.method));
+                async {}
+            }
+        })
+        .with_tenant_extractor(|headers, _user, _context| {
+            Some(headers.get("x-tenant")?.to_str().ok()?.to_string())
+        });
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_observability_config(config)
+        .build();
+    let server = spawn_http(router);
+
+    reqwest::Client::new()
+        .get(server.server_url("/api/balance").unwrap())
+        .header("x-tenant", "acme")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(metrics.started(), ["GET /balance"]);
+    assert_eq!(metrics.completed(), [completed("GET /balance", None)]);
+    assert_eq!(
+        metrics.tenants(),
+        [Some("acme".to_string()), Some("acme".to_string())]
+    );
+    assert_eq!(
+        *tracked.lock().unwrap(),
+        ["usage GET /balance", "duration GET /balance"]
+    );
+}
+
+#[tokio::test]
+async fn every_exit_is_reported_to_the_duration_tracker() {
+    let metrics = RecordingMetrics::default();
+    let durations = Arc::new(Mutex::new(Vec::new()));
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .with_method_duration_tracker({
+            let durations = durations.clone();
+            move |method, path, user: Option<&AuthenticatedUser>, _duration| {
+                let user = user.map(|user| user.user_id.clone());
+                durations
+                    .lock()
+                    .unwrap()
+                    .push((format!("{method} {path}"), user));
+                async {}
+            }
+        })
+        .build();
+    let server = spawn_http(router);
+    let url = |path: &str| server.server_url(path).unwrap();
+    let client = reqwest::Client::new();
+
+    client.post(url("/api/close")).send().await.unwrap();
+    client
+        .post(url("/api/close"))
+        .bearer_auth("forged-token")
+        .send()
+        .await
+        .unwrap();
+    client
+        .post(url("/api/close"))
+        .bearer_auth("user-token")
+        .send()
+        .await
+        .unwrap();
+    client
+        .post(url("/api/deposits"))
+        .header("Content-Type", "application/json")
+        .body("{\"amount\":")
+        .send()
+        .await
+        .unwrap();
+    client.get(url("/api/broken")).send().await.unwrap();
+    client
+        .post(url("/api/close"))
+        .bearer_auth("admin-token")
+        .send()
+        .await
+        .unwrap();
+
+    let durations = durations.lock().unwrap();
+    let tracked: Vec<(&str, Option<&str>)> = durations
+        .iter()
+        .map(|(route, user)| (route.as_str(), user.as_deref()))
+        .collect();
+    assert_eq!(
+        tracked,
+        [
+            ("POST /close", None),
+            ("POST /close", None),
+            ("POST /close", Some("user-1")),
+            ("POST /deposits", None),
+            ("GET /broken", None),
+            ("POST /close", Some("admin-1")),
+        ]
+    );
+    assert_eq!(
+        metrics.completed(),
+        [
+            completed("POST /close", Some("unauthenticated")),
+            completed("POST /close", Some("unauthenticated")),
+            completed("POST /close", Some("forbidden")),
+            completed("POST /deposits", Some("invalid_params")),
+            completed("GET /broken", Some("handler_error")),
+            completed("POST /close", None),
+        ]
+    );
+}
@@ -0,0 +1,115 @@
+//! Request-scoped tracing spans around generated REST handlers.
+
+use ras_auth_core::AuthenticatedUser;
+use ras_rest_core::{RestError, RestResponse, RestResult};
+use ras_rest_macro::rest_service;
+use ras_test_helpers::{MockAuthProvider, capture_spans, spawn_http};
+
+rest_service!({
+    service_name: Traced,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        GET WITH_PERMISSIONS(["user"]) profile() -> String,
+        GET WITH_PERMISSIONS(["admin"]) audit() -> String,
+        GET UNAUTHORIZED broken() -> String,
+        GET UNAUTHORIZED quiet() -> String,
+    ]
+});
+
+struct TracedImpl;
+
+#[async_trait::async_trait]
+impl TracedTrait for TracedImpl {
+    async fn get_profile(&self, user: &AuthenticatedUser) -> RestResult<String> {
+        Ok(RestResponse::ok(user.user_id.clone()))
+    }
+
+    async fn get_audit(&self, _user: &AuthenticatedUser) -> RestResult<String> {
+        Ok(RestResponse::ok("audit".to_string()))
+    }
+
+    async fn get_broken(&self) -> RestResult<String> {
+        Err(RestError::internal_server_error("broken"))
+    }
+
+    async fn get_quiet(&self) -> RestResult<String> {
+        Ok(RestResponse::ok("quiet".to_string()))
+    }
+}
+
+const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+async fn get(server: &axum_test::TestServer, path: &str, request_id: &str) -> reqwest::StatusCode {
+    reqwest::Client::new()
+        .get(server.server_url(path).unwrap())
+        .bearer_auth("user-token")
+        .header("x-request-id", request_id)
+        .header("traceparent", TRACEPARENT)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn spans_record_user_permission_and_status() {
+    let spans = capture_spans();
+    let router = TracedBuilder::new(TracedImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_tracing_spans(true)
+        .build();
+    let server = spawn_http(router);
+
+    assert_eq!(
+        get(&server, "/api/profile", "req-1").await,
+        reqwest::StatusCode::OK
+    );
+    assert_eq!(
+        get(&server, "/api/audit", "req-2").await,
+        reqwest::StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        get(&server, "/api/broken", "req-3").await,
+        reqwest::StatusCode::INTERNAL_SERVER_ERROR
+    );
+
+    let profile = spans
+        .spans_named("get_profile")
+        .pop()
+        .expect("profile span");
+    assert_eq!(profile.field("protocol"), Some("rest"));
+    assert_eq!(profile.field("http.method"), Some("GET"));
+    assert_eq!(profile.field("http.route"), Some("/profile"));
+    assert_eq!(profile.field("request_id"), Some("req-1"));
+    assert_eq!(profile.field("user_id"), Some("user-1"));
+    assert_eq!(profile.field("traceparent"), Some(TRACEPARENT));
+    assert_eq!(profile.field("permission"), Some("granted"));
+    assert_eq!(profile.field("outcome"), Some("success"));
+    assert_eq!(profile.field("http.status_code"), Some("200"));
+    assert!(profile.field("duration_ms").is_some());
+
+    let audit = spans.spans_named("get_audit").pop().expect("audit span");
+    assert_eq!(audit.field("user_id"), Some("user-1"));
+    assert_eq!(audit.field("permission"), Some("denied"));
+    assert_eq!(audit.field("outcome"), Some("error"));
+    assert_eq!(audit.field("otel.status_code"), None);
+
+    let broken = spans.spans_named("get_broken").pop().expect("broken span");
+    assert_eq!(broken.field("permission"), Some("not_required"));
+    assert_eq!(broken.field("http.status_code"), Some("500"));
+    assert_eq!(broken.field("otel.status_code"), Some("ERROR"));
+}
+
+#[tokio::test]
+async fn spans_are_disabled_by_default() {
+    let spans = capture_spans();
+    let server = spawn_http(TracedBuilder::new(TracedImpl).build());
+
+    assert_eq!(
+        get(&server, "/api/quiet", "req-4").await,
+        reqwest::StatusCode::OK
+    );
+    assert!(spans.spans_named("get_quiet").is_empty());
+}
@@ -0,0 +1,13 @@
+use ras_rest_macro::rest_service;
+
+rest_service!({
+    service_name: Tasks,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    endpoints: [
+        DELETE WITH_PERMISSIONS(expr = "") tasks/{id: u64}() -> (),
+    ]
+});
+
+fn main() {}
@@ -0,0 +1,14 @@
+use ras_rest_macro::rest_service;
+
+rest_service!({
+    service_name: Tasks,
+    base_path: "/api",
+    openapi: false,
+    serve_docs: false,
+    docs_auth: WITH_PERMISSIONS(expr = "admin | owner)"),
+    endpoints: [
+        DELETE WITH_PERMISSIONS(["admin"]) tasks/{id: u64}() -> (),
+    ]
+});
+
+fn main() {}
@@ -18,13 +18,19 @@ fn test_xss_protection_in_generated_html() {
 }
 
 #[test]
-fn test_generated_docs_do_not_store_jwt_in_local_storage() {
+fn test_generated_docs_store_jwt_in_local_storage_only_when_remembered() {
     let template = include_str!("../src/api_explorer_template.html");
     assert!(!template.contains("localStorage.getItem('jwt-token')"));
     assert!(!template.contains("localStorage.setItem('jwt-token'"));
     assert!(!template.contains("localStorage.removeItem('jwt-token'"));
-    assert!(!template.contains("localStorage.setItem(`${storagePrefix}:bearer-token`"));
+    // Tokens go to sessionStorage unless "Remember on this device" is ticked,
+    // and forgetting a token clears both stores
     assert!(template.contains("sessionStorage.setItem(`${storagePrefix}:${key}`"));
+    assert!(template.contains("id=\"remember-token\""));
+    assert!(template.contains("if (remember) {"));
+    assert!(template.contains("localStorage.setItem(tokenStorageKey, token)"));
+    assert!(template.contains("localStorage.removeItem(tokenStorageKey)"));
+    assert!(template.contains("sessionStorage.removeItem(tokenStorageKey)"));
     assert!(template.contains("localStorage.setItem(\"ras-explorer-theme\""));
 }
 
@@ -36,18 +36,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ConnectionEvent::Connected { connection_id } => {
                 println!("✅ Connected to server with ID: {}", connection_id);
             }
-            ConnectionEvent::Disconnected { reason } => {
-                println!("❌ Disconnected from server. Reason: {:?}", reason);
+            ConnectionEvent::Disconnected { close_reason, .. } => {
+                println!("❌ Disconnected from server. Reason: {:?}", close_reason);
             }
             ConnectionEvent::Reconnecting { attempt } => {
                 println!("🔄 Reconnecting... (attempt {})", attempt);
             }
+            ConnectionEvent::Reconnected { attempts } => {
+                println!("✅ Reconnected after {} attempt(s)", attempts);
+            }
             ConnectionEvent::ReconnectFailed { attempt, error } => {
                 println!("❌ Reconnection failed (attempt {}): {}", attempt, error);
             }
             ConnectionEvent::AuthenticationFailed { error } => {
                 println!("🔐 Authentication failed: {}", error);
             }
+            ConnectionEvent::TopicGap { topic, last_seq } => {
+                println!("⚠️ Missed broadcasts on {} after #{}", topic, last_seq);
+            }
         }),
     );
 
@@ -5,18 +5,27 @@ use crate::{
     RpcRequestHandler, Subscription, WebSocketTransport,
     config::{AuthConfig, ClientConfig, ReconnectConfig},
     error::{ClientError, ClientResult},
+    runtime::{self, Instant, Interval},
 };
 use dashmap::DashMap;
-use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId};
+use ras_auth_core::AuthenticatedUser;
+use ras_jsonrpc_bidirectional_types::{
+    BidirectionalError, BidirectionalMessage, CANCEL_REQUEST_METHOD, CancelRequest, CloseReason,
+    Codec, ConnectionId, FILTER_SET_METHOD, FilterSet, PRESENCE_JOIN_METHOD,
+    PRESENCE_JOINED_NOTIFICATION, PRESENCE_LEAVE_METHOD, PRESENCE_LEFT_NOTIFICATION,
+    PRESENCE_LIST_METHOD, PresenceEntry, PresenceEvent, PresenceJoin, PresenceKey,
+    SESSION_EXPIRING_NOTIFICATION, SESSION_REFRESH_METHOD, SHUTDOWN_NOTIFICATION, SessionExpiring,
+    SessionRefresh, ShutdownNotice, TOPIC_RESUME_METHOD, TopicResume, TopicResumed,
+};
 use ras_jsonrpc_types::{JsonRpcRequest, JsonRpcResponse};
 use serde_json::Value;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{
         Arc,
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicU32, AtomicU64, Ordering},
     },
-    time::{Duration, Instant},
+    time::Duration,
 };
 use tokio::sync::{RwLock, mpsc, oneshot};
 use tracing::{debug, error, info, warn};
@@ -35,12 +44,16 @@ pub struct Client {
     connection_id: Arc<RwLock<Option<ConnectionId>>>,
     pending_requests: Arc<DashMap<Value, PendingRequest>>,
     subscriptions: Arc<DashMap<String, Subscription>>,
+    /// Notification filters, registered again on every new connection
+    filters: Arc<DashMap<String, Value>>,
     notification_handlers: Arc<DashMap<String, NotificationHandler>>,
     rpc_request_handlers: Arc<DashMap<String, RpcRequestHandler>>,
     connection_event_handlers: Arc<DashMap<String, ConnectionEventHandler>>,
     request_id_counter: Arc<AtomicU64>,
     shutdown_tx: Arc<RwLock<Option<oneshot::Sender<()>>>>,
     message_tx: Arc<RwLock<Option<mpsc::Sender<BidirectionalMessage>>>>,
+    /// Heartbeats sent since the server was last heard from
+    missed_heartbeats: Arc<AtomicU32>,
 }
 
 impl Client {
@@ -63,19 +76,21 @@ impl Client {
             connection_id: Arc::new(RwLock::new(None)),
             pending_requests: Arc::new(DashMap::new()),
             subscriptions: Arc::new(DashMap::new()),
+            filters: Arc::new(DashMap::new()),
             notification_handlers: Arc::new(DashMap::new()),
             rpc_request_handlers: Arc::new(DashMap::new()),
             connection_event_handlers: Arc::new(DashMap::new()),
             request_id_counter: Arc::new(AtomicU64::new(1)),
             shutdown_tx: Arc::new(RwLock::new(None)),
             message_tx: Arc::new(RwLock::new(None)),
+            missed_heartbeats: Arc::new(AtomicU32::new(0)),
         })
     }
 
     /// Connect to the WebSocket server
     pub async fn connect(&self) -> ClientResult<()> {
         let mut state = self.state.write().await;
-        if *state != ClientState::Disconnected {
+        if !matches!(*state, ClientState::Disconnected | ClientState::Failed) {
             return Err(ClientError::AlreadyConnected);
         }
         *state = ClientState::Connecting;
@@ -83,10 +98,13 @@ impl Client {
 
         // Connect transport
         let mut transport = self.transport.write().await;
-        transport
-            .connect()
-            .await
-            .map_err(|e| ClientError::connection(format!("Failed to connect: {}", e)))?;
+        if let Err(e) = transport.connect().await {
+            *self.state.write().await = ClientState::Disconnected;
+            return Err(match e {
+                ClientError::Authentication(_) => e,
+                e => ClientError::connection(format!("Failed to connect: {}", e)),
+            });
+        }
         drop(transport);
 
         // Set up message handling
@@ -95,6 +113,7 @@ impl Client {
 
         *self.shutdown_tx.write().await = Some(shutdown_tx);
         *self.message_tx.write().await = Some(message_tx);
+        self.missed_heartbeats.store(0, Ordering::SeqCst);
 
         // Start message handling task
         self.start_message_handler(message_rx, shutdown_rx).await?;
@@ -107,10 +126,9 @@ impl Client {
         *self.state.write().await = ClientState::Connected;
         info!("Client connected to {}", self.config.url);
 
-        loop {
-            if self.connection_id.read().await.is_some() {
-                break;
-            }
+        // The server announces the connection id as its first message
+        while self.connection_id.read().await.is_none() {
+            runtime::sleep(Duration::from_millis(1)).await;
         }
 
         Ok(())
@@ -142,37 +160,36 @@ impl Client {
         *self.message_tx.write().await = None;
 
         // Fail all pending requests
-        let pending_ids: Vec<Value> = self
-            .pending_requests
-            .iter()
-            .map(|entry| entry.key().clone())
-            .collect();
-        for id in pending_ids {
-            if let Some((_, pending)) = self.pending_requests.remove(&id) {
-                let _ = pending.sender.send(JsonRpcResponse::error(
-                    ras_jsonrpc_types::JsonRpcError::internal_error(
-                        "Client disconnected".to_string(),
-                    ),
-                    Some(pending.id),
-                ));
-            }
-        }
-        self.pending_requests.clear();
+        Self::fail_all_requests(&self.pending_requests, "Client disconnected");
 
-        self.emit_connection_event(ConnectionEvent::Disconnected { reason: None })
-            .await;
+        self.emit_connection_event(ConnectionEvent::Disconnected {
+            reason: None,
+            close_reason: Some(CloseReason::ClientClosed),
+        })
+        .await;
         info!("Client disconnected");
 
         Ok(())
     }
 
-    /// Make a JSON-RPC call and wait for the response
+    /// Make a JSON-RPC call and wait for the response, up to the configured
+    /// request timeout
     pub async fn call(&self, method: &str, params: Option<Value>) -> ClientResult<JsonRpcResponse> {
-        let state = self.state.read().await;
-        if *state != ClientState::Connected {
-            return Err(ClientError::NotConnected);
-        }
-        drop(state);
+        self.call_with_timeout(method, params, self.config.request_timeout)
+            .await
+    }
+
+    /// Make a JSON-RPC call and wait up to `timeout` for the response
+    ///
+    /// A call that times out fails with [`ClientError::Timeout`] and, unless
+    /// `cancel_on_timeout` is off, is cancelled on the server so its handler stops.
+    pub async fn call_with_timeout(
+        &self,
+        method: &str,
+        params: Option<Value>,
+        timeout: Duration,
+    ) -> ClientResult<JsonRpcResponse> {
+        self.ensure_sendable().await?;
 
         let request_id = Value::Number(serde_json::Number::from(
             self.request_id_counter.fetch_add(1, Ordering::SeqCst),
@@ -192,28 +209,50 @@ impl Client {
             return Err(ClientError::internal("Too many pending requests"));
         }
 
-        self.pending_requests.insert(request_id, pending);
+        self.pending_requests.insert(request_id.clone(), pending);
 
         // Send the request
         let message = BidirectionalMessage::Request(request);
-        self.send_message(message).await?;
+        if let Err(e) = self.send_message(message).await {
+            self.pending_requests.remove(&request_id);
+            return Err(e);
+        }
 
         // Wait for response with timeout
-        let response = tokio::time::timeout(self.config.request_timeout, response_rx)
-            .await
-            .map_err(|_| ClientError::timeout(self.config.request_timeout.as_secs()))?
-            .map_err(|_| ClientError::internal("Response channel closed"))?;
+        let Some(response) = runtime::timeout(timeout, response_rx).await else {
+            self.pending_requests.remove(&request_id);
+            if self.config.cancel_on_timeout {
+                let params = serde_json::to_value(CancelRequest { id: request_id })?;
+                if let Err(e) = self.notify(CANCEL_REQUEST_METHOD, Some(params)).await {
+                    debug!("Could not cancel timed out {} call: {}", method, e);
+                }
+            }
+            return Err(ClientError::timeout(timeout.as_secs()));
+        };
+        response.map_err(|_| ClientError::internal("Response channel closed"))
+    }
 
-        Ok(response)
+    /// Re-authenticate the open connection with a new bearer token
+    ///
+    /// The server swaps the connection's user for the one `token` identifies, and
+    /// fails calls in flight that the new user is not permitted to make. A rejected
+    /// token leaves the session as it was. Reconnects still present the token the
+    /// client was built with.
+    pub async fn refresh_session(&self, token: String) -> ClientResult<AuthenticatedUser> {
+        let params = serde_json::to_value(SessionRefresh { token })?;
+        let response = self.call(SESSION_REFRESH_METHOD, Some(params)).await?;
+        if let Some(error) = response.error {
+            return Err(ClientError::authentication(error.message));
+        }
+        let user = response
+            .result
+            .ok_or_else(|| ClientError::internal("Response has no result or error"))?;
+        Ok(serde_json::from_value(user)?)
     }
 
     /// Send a notification (fire-and-forget)
     pub async fn notify(&self, method: &str, params: Option<Value>) -> ClientResult<()> {
-        let state = self.state.read().await;
-        if *state != ClientState::Connected {
-            return Err(ClientError::NotConnected);
-        }
-        drop(state);
+        self.ensure_sendable().await?;
 
         let request = JsonRpcRequest::new(method.to_string(), params, None);
         let message = BidirectionalMessage::Request(request);
@@ -222,16 +261,13 @@ impl Client {
 
     /// Subscribe to a topic for receiving notifications
     pub async fn subscribe(&self, topic: &str, handler: NotificationHandler) -> ClientResult<()> {
-        let state = self.state.read().await;
-        if *state != ClientState::Connected {
-            return Err(ClientError::NotConnected);
-        }
-        drop(state);
+        self.ensure_sendable().await?;
 
         let subscription = Subscription {
             topic: topic.to_string(),
             handler: handler.clone(),
             created_at: Instant::now(),
+            last_seq: None,
         };
 
         self.subscriptions.insert(topic.to_string(), subscription);
@@ -246,13 +282,146 @@ impl Client {
         Ok(())
     }
 
+    /// Subscribe to a topic again, having last received its broadcast `last_seq`
+    ///
+    /// The server replays the broadcasts kept since then before any new one. When
+    /// some were no longer kept, the result reports a `gap` and the client should
+    /// reload whatever state it builds from the topic. Subscriptions that received
+    /// numbered broadcasts are resumed like this automatically on reconnect.
+    pub async fn resume(
+        &self,
+        topic: &str,
+        last_seq: u64,
+        handler: NotificationHandler,
+    ) -> ClientResult<TopicResumed> {
+        self.ensure_sendable().await?;
+
+        self.subscriptions.insert(
+            topic.to_string(),
+            Subscription {
+                topic: topic.to_string(),
+                handler,
+                created_at: Instant::now(),
+                last_seq: Some(last_seq),
+            },
+        );
+        let params = serde_json::to_value(TopicResume {
+            topic: topic.to_string(),
+            last_seq,
+        })?;
+        let response = self.call(TOPIC_RESUME_METHOD, Some(params)).await?;
+        if let Some(error) = response.error {
+            self.subscriptions.remove(topic);
+            return Err(ClientError::subscription(error.message));
+        }
+        let resumed = response
+            .result
+            .ok_or_else(|| ClientError::internal("Response has no result or error"))?;
+        Ok(serde_json::from_value(resumed)?)
+    }
+
+    /// Sequence number of the last numbered broadcast received on a topic
+    pub fn last_seq(&self, topic: &str) -> Option<u64> {
+        self.subscriptions
+            .get(topic)
+            .and_then(|subscription| subscription.last_seq)
+    }
+
+    /// Become present under `key`, such as a document being viewed, returning
+    /// the connection's entry
+    ///
+    /// The key's other members are told unless the user is already present
+    /// through another connection. Joining again replaces `metadata`.
+    pub async fn join_presence(
+        &self,
+        key: &str,
+        metadata: Option<Value>,
+    ) -> ClientResult<PresenceEntry> {
+        let params = serde_json::to_value(PresenceJoin {
+            key: key.to_string(),
+            metadata,
+        })?;
+        self.call_presence(PRESENCE_JOIN_METHOD, params).await
+    }
+
+    /// Withdraw the connection's presence under `key`, returning whether it was present
+    ///
+    /// Closing the connection leaves every key.
+    pub async fn leave_presence(&self, key: &str) -> ClientResult<bool> {
+        let params = serde_json::to_value(PresenceKey {
+            key: key.to_string(),
+        })?;
+        self.call_presence(PRESENCE_LEAVE_METHOD, params).await
+    }
+
+    /// Every connection present under `key`, oldest first
+    pub async fn list_presence(&self, key: &str) -> ClientResult<Vec<PresenceEntry>> {
+        let params = serde_json::to_value(PresenceKey {
+            key: key.to_string(),
+        })?;
+        self.call_presence(PRESENCE_LIST_METHOD, params).await
+    }
+
+    /// Register `filter` for the server's filtered sends of `notification`, or
+    /// remove it with `None`
+    ///
+    /// Filters are registered again after a reconnect.
+    pub async fn set_notification_filter(
+        &self,
+        notification: &str,
+        filter: Option<Value>,
+    ) -> ClientResult<()> {
+        let response = self
+            .call(
+                FILTER_SET_METHOD,
+                Some(Self::filter_params(notification, filter.clone())?),
+            )
+            .await?;
+        if let Some(error) = response.error {
+            return Err(ClientError::internal(format!(
+                "JSON-RPC error: {}",
+                error.message
+            )));
+        }
+        match filter {
+            Some(filter) => {
+                self.filters.insert(notification.to_string(), filter);
+            }
+            None => {
+                self.filters.remove(notification);
+            }
+        }
+        Ok(())
+    }
+
+    fn filter_params(notification: &str, filter: Option<Value>) -> serde_json::Result<Value> {
+        serde_json::to_value(FilterSet {
+            notification: notification.to_string(),
+            filter,
+        })
+    }
+
+    async fn call_presence<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> ClientResult<T> {
+        let response = self.call(method, Some(params)).await?;
+        if let Some(error) = response.error {
+            return Err(ClientError::internal(format!(
+                "JSON-RPC error: {}",
+                error.message
+            )));
+        }
+        let result = response
+            .result
+            .ok_or_else(|| ClientError::internal("Response has no result or error"))?;
+        Ok(serde_json::from_value(result)?)
+    }
+
     /// Unsubscribe from a topic
     pub async fn unsubscribe(&self, topic: &str) -> ClientResult<()> {
-        let state = self.state.read().await;
-        if *state != ClientState::Connected {
-            return Err(ClientError::NotConnected);
-        }
-        drop(state);
+        self.ensure_sendable().await?;
 
         self.subscriptions.remove(topic);
 
@@ -273,6 +442,78 @@ impl Client {
         debug!("Registered notification handler for method: {}", method);
     }
 
+    /// Register a handler for the server's shutdown notice
+    ///
+    /// The server closes the connection at the notice's deadline, so this is the
+    /// place to move to another server.
+    pub fn on_server_shutdown<F>(&self, handler: F)
+    where
+        F: Fn(ShutdownNotice) + Send + Sync + 'static,
+    {
+        self.on_notification(
+            SHUTDOWN_NOTIFICATION,
+            Arc::new(move |_, params| {
+                match serde_json::from_value::<ShutdownNotice>(params.clone()) {
+                    Ok(notice) => handler(notice),
+                    Err(e) => warn!("Ignoring malformed shutdown notice: {}", e),
+                }
+            }),
+        );
+    }
+
+    /// Register a handler for the server's warning that the session's token was
+    /// rejected
+    ///
+    /// The server closes the connection at the warning's deadline unless
+    /// [`refresh_session`](Self::refresh_session) succeeds before then.
+    pub fn on_session_expiring<F>(&self, handler: F)
+    where
+        F: Fn(SessionExpiring) + Send + Sync + 'static,
+    {
+        self.on_notification(
+            SESSION_EXPIRING_NOTIFICATION,
+            Arc::new(move |_, params| {
+                match serde_json::from_value::<SessionExpiring>(params.clone()) {
+                    Ok(warning) => handler(warning),
+                    Err(e) => warn!("Ignoring malformed session expiry warning: {}", e),
+                }
+            }),
+        );
+    }
+
+    /// Register a handler for users becoming present under a key this connection
+    /// is present in
+    pub fn on_presence_joined<F>(&self, handler: F)
+    where
+        F: Fn(PresenceEvent) + Send + Sync + 'static,
+    {
+        self.on_presence_event(PRESENCE_JOINED_NOTIFICATION, handler);
+    }
+
+    /// Register a handler for users leaving a key this connection is present in,
+    /// once they are present on no other connection
+    pub fn on_presence_left<F>(&self, handler: F)
+    where
+        F: Fn(PresenceEvent) + Send + Sync + 'static,
+    {
+        self.on_presence_event(PRESENCE_LEFT_NOTIFICATION, handler);
+    }
+
+    fn on_presence_event<F>(&self, method: &str, handler: F)
+    where
+        F: Fn(PresenceEvent) + Send + Sync + 'static,
+    {
+        self.on_notification(
+            method,
+            Arc::new(move |_, params| {
+                match serde_json::from_value::<PresenceEvent>(params.clone()) {
+                    Ok(event) => handler(event),
+                    Err(e) => warn!("Ignoring malformed presence event: {}", e),
+                }
+            }),
+        );
+    }
+
     /// Register a handler for connection events
     pub fn on_connection_event(&self, name: &str, handler: ConnectionEventHandler) {
         self.connection_event_handlers
@@ -280,6 +521,24 @@ impl Client {
         debug!("Registered connection event handler: {}", name);
     }
 
+    /// Register a handler for the connection closing, told why it closed
+    ///
+    /// Connections that drop without the server saying why, such as on a network
+    /// error, close with [`CloseReason::ConnectionLost`].
+    pub fn on_disconnected<F>(&self, handler: F)
+    where
+        F: Fn(CloseReason) + Send + Sync + 'static,
+    {
+        self.on_connection_event(
+            "on_disconnected",
+            Arc::new(move |event| {
+                if let ConnectionEvent::Disconnected { close_reason, .. } = event {
+                    handler(close_reason.unwrap_or(CloseReason::ConnectionLost));
+                }
+            }),
+        );
+    }
+
     /// Register a handler for RPC requests from the server
     pub fn on_rpc_request(&self, method: &str, handler: RpcRequestHandler) {
         self.rpc_request_handlers
@@ -322,6 +581,15 @@ impl Client {
 
     // Internal helper methods
 
+    /// Check that messages can be sent now, or buffered until a reconnect completes
+    async fn ensure_sendable(&self) -> ClientResult<()> {
+        match *self.state.read().await {
+            ClientState::Connected => Ok(()),
+            ClientState::Reconnecting if self.config.reconnect.buffer_while_reconnecting => Ok(()),
+            _ => Err(ClientError::NotConnected),
+        }
+    }
+
     async fn send_message(&self, message: BidirectionalMessage) -> ClientResult<()> {
         if let Some(tx) = self.message_tx.read().await.as_ref() {
             tx.send(message)
@@ -341,15 +609,21 @@ impl Client {
         let transport = Arc::clone(&self.transport);
         let pending_requests = Arc::clone(&self.pending_requests);
         let subscriptions = Arc::clone(&self.subscriptions);
+        let filters = Arc::clone(&self.filters);
         let notification_handlers = Arc::clone(&self.notification_handlers);
         let rpc_request_handlers = Arc::clone(&self.rpc_request_handlers);
         let connection_event_handlers = Arc::clone(&self.connection_event_handlers);
         let connection_id = Arc::clone(&self.connection_id);
         let state = Arc::clone(&self.state);
         let message_tx_clone = Arc::clone(&self.message_tx);
+        let missed_heartbeats = Arc::clone(&self.missed_heartbeats);
+        let request_id_counter = Arc::clone(&self.request_id_counter);
+        let config = self.config.clone();
 
-        tokio::spawn(async move {
-            let mut receive_interval = tokio::time::interval(Duration::from_millis(10));
+        runtime::spawn(async move {
+            let mut receive_interval = Interval::new(Duration::from_millis(10));
+            // Requests written to the current connection, failed if it drops
+            let mut in_flight = HashSet::new();
 
             loop {
                 tokio::select! {
@@ -362,6 +636,13 @@ impl Client {
                     // Handle outgoing messages
                     message = message_rx.recv() => {
                         if let Some(message) = message {
+                            if let BidirectionalMessage::Request(JsonRpcRequest { id: Some(id), .. }) = &message {
+                                if in_flight.len() >= config.max_pending_requests {
+                                    in_flight.retain(|id| pending_requests.contains_key(id));
+                                }
+                                in_flight.insert(id.clone());
+                            }
+
                             let mut transport = transport.write().await;
                             if let Err(e) = transport.send(&message).await {
                                 error!("Failed to send message: {}", e);
@@ -374,10 +655,17 @@ impl Client {
 
                     // Handle incoming messages
                     _ = receive_interval.tick() => {
-                        let transport_clone = Arc::clone(&transport);
-                        let mut transport = transport_clone.write().await;
-                        match transport.receive().await {
+                        let received = if config.max_missed_heartbeats > 0
+                            && missed_heartbeats.load(Ordering::SeqCst) > config.max_missed_heartbeats
+                        {
+                            Err(ClientError::connection("Server stopped answering heartbeats"))
+                        } else {
+                            transport.write().await.receive().await
+                        };
+                        match received {
                             Ok(Some(message)) => {
+                                // Anything from the server proves the connection is alive
+                                missed_heartbeats.store(0, Ordering::SeqCst);
                                 Self::handle_incoming_message(
                                     message,
                                     &pending_requests,
@@ -392,10 +680,55 @@ impl Client {
                             Ok(None) => {
                                 // No message available, continue
                             }
+                            Err(ClientError::Json(e)) => {
+                                warn!("Ignoring malformed message: {}", e);
+                            }
+                            Err(ClientError::Bidirectional(
+                                e @ (BidirectionalError::CodecError(_)
+                                | BidirectionalError::SerializationError(_)),
+                            )) => {
+                                warn!("Ignoring malformed message: {}", e);
+                            }
                             Err(e) => {
                                 error!("Failed to receive message: {}", e);
-                                *state.write().await = ClientState::Failed;
-                                break;
+                                // Unless the server already said why in its `ConnectionClosed`
+                                let announced = connection_id.write().await.take().is_none();
+                                let close_reason = (!announced).then(|| {
+                                    e.close_reason().cloned().unwrap_or(CloseReason::ConnectionLost)
+                                });
+
+                                // The server may or may not have handled these
+                                Self::fail_requests(
+                                    &pending_requests,
+                                    in_flight.drain(),
+                                    "Connection lost before the response arrived",
+                                );
+
+                                let reconnected = Self::reconnect(
+                                    &config,
+                                    &transport,
+                                    &state,
+                                    &subscriptions,
+                                    &connection_event_handlers,
+                                    &mut shutdown_rx,
+                                    close_reason,
+                                )
+                                .await;
+                                missed_heartbeats.store(0, Ordering::SeqCst);
+                                if !reconnected {
+                                    *message_tx_clone.write().await = None;
+                                    Self::fail_all_requests(&pending_requests, "Client disconnected");
+                                    break;
+                                }
+                                Self::renew_filters(&transport, &filters).await;
+                                Self::resume_topics(
+                                    &transport,
+                                    &subscriptions,
+                                    &pending_requests,
+                                    &request_id_counter,
+                                    &connection_event_handlers,
+                                )
+                                .await;
                             }
                         }
                     }
@@ -434,8 +767,21 @@ impl Client {
             }
             BidirectionalMessage::Broadcast(broadcast) => {
                 // Handle broadcast to subscribed topics
-                if let Some(subscription) = subscriptions.get(&broadcast.topic) {
-                    (subscription.value().handler)(&broadcast.method, &broadcast.params);
+                let handler =
+                    subscriptions
+                        .get_mut(&broadcast.topic)
+                        .and_then(|mut subscription| {
+                            if let Some(seq) = broadcast.seq {
+                                // Already received, before a replay
+                                if subscription.last_seq.is_some_and(|last| seq <= last) {
+                                    return None;
+                                }
+                                subscription.last_seq = Some(seq);
+                            }
+                            Some(Arc::clone(&subscription.handler))
+                        });
+                if let Some(handler) = handler {
+                    handler(&broadcast.method, &broadcast.params);
                 }
             }
             BidirectionalMessage::ConnectionEstablished {
@@ -450,10 +796,17 @@ impl Client {
                 )
                 .await;
             }
-            BidirectionalMessage::ConnectionClosed { reason, .. } => {
+            BidirectionalMessage::ConnectionClosed {
+                reason,
+                close_reason,
+                ..
+            } => {
                 *connection_id.write().await = None;
                 Self::emit_connection_event_static(
-                    ConnectionEvent::Disconnected { reason },
+                    ConnectionEvent::Disconnected {
+                        reason,
+                        close_reason,
+                    },
                     connection_event_handlers,
                 )
                 .await;
@@ -463,15 +816,21 @@ impl Client {
                 if let Some(_id) = &request.id {
                     if let Some(handler) = rpc_request_handlers.get(&request.method) {
                         debug!("Handling RPC request: {}", request.method);
-                        let response = handler(request).await;
-
-                        // Send response back to server
-                        let response_message = BidirectionalMessage::Response(response);
-                        if let Some(tx) = message_tx.read().await.as_ref() {
-                            if let Err(e) = tx.send(response_message).await {
+                        let handler = Arc::clone(handler.value());
+                        let tx = message_tx.read().await.clone();
+
+                        // Run the handler off the receive loop so it can make calls of its own
+                        runtime::spawn(async move {
+                            let response = handler(request).await;
+
+                            // Send response back to server
+                            let response_message = BidirectionalMessage::Response(response);
+                            if let Some(tx) = tx
+                                && let Err(e) = tx.send(response_message).await
+                            {
                                 error!("Failed to send RPC response: {}", e);
                             }
-                        }
+                        });
                     } else {
                         warn!("No handler registered for RPC method: {}", request.method);
                         // Send method not found error
@@ -484,10 +843,10 @@ impl Client {
                             request.id.clone(),
                         );
                         let response_message = BidirectionalMessage::Response(error_response);
-                        if let Some(tx) = message_tx.read().await.as_ref() {
-                            if let Err(e) = tx.send(response_message).await {
-                                error!("Failed to send error response: {}", e);
-                            }
+                        if let Some(tx) = message_tx.read().await.as_ref()
+                            && let Err(e) = tx.send(response_message).await
+                        {
+                            error!("Failed to send error response: {}", e);
                         }
                     }
                 } else {
@@ -506,6 +865,254 @@ impl Client {
         }
     }
 
+    /// Re-establish a lost connection following the client's reconnect policy
+    ///
+    /// The new connection authenticates with the client's credentials like the first
+    /// one, and the client's subscriptions are renewed on it. The loss is announced
+    /// with `close_reason`, unless it is `None` because it already was. Returns
+    /// `false` if the client gave up or was disconnected in the meantime.
+    async fn reconnect(
+        config: &ClientConfig,
+        transport: &RwLock<Box<dyn WebSocketTransport>>,
+        state: &RwLock<ClientState>,
+        subscriptions: &DashMap<String, Subscription>,
+        connection_event_handlers: &DashMap<String, ConnectionEventHandler>,
+        shutdown_rx: &mut oneshot::Receiver<()>,
+        close_reason: Option<CloseReason>,
+    ) -> bool {
+        {
+            let mut state = state.write().await;
+            if *state != ClientState::Connected {
+                return false;
+            }
+            *state = if config.reconnect.enabled {
+                ClientState::Reconnecting
+            } else {
+                ClientState::Failed
+            };
+        }
+        if let Some(close_reason) = close_reason {
+            Self::emit_connection_event_static(
+                ConnectionEvent::Disconnected {
+                    reason: Some(close_reason.to_string()),
+                    close_reason: Some(close_reason),
+                },
+                connection_event_handlers,
+            )
+            .await;
+        }
+
+        let mut attempt = 0;
+        while config.reconnect.should_attempt(attempt) {
+            attempt += 1;
+            tokio::select! {
+                _ = &mut *shutdown_rx => return false,
+                _ = runtime::sleep(config.reconnect.calculate_delay(attempt)) => {}
+            }
+
+            Self::emit_connection_event_static(
+                ConnectionEvent::Reconnecting { attempt },
+                connection_event_handlers,
+            )
+            .await;
+
+            let result = {
+                let mut transport = transport.write().await;
+                let _ = transport.disconnect().await;
+                match transport.connect().await {
+                    Ok(()) => {
+                        // Topics with numbered broadcasts are resumed instead
+                        let topics: Vec<String> = subscriptions
+                            .iter()
+                            .filter(|entry| entry.last_seq.is_none())
+                            .map(|entry| entry.key().clone())
+                            .collect();
+                        if topics.is_empty() {
+                            Ok(())
+                        } else {
+                            transport
+                                .send(&BidirectionalMessage::Subscribe { topics })
+                                .await
+                        }
+                    }
+                    Err(e) => Err(e),
+                }
+            };
+
+            let mut current_state = state.write().await;
+            if *current_state != ClientState::Reconnecting {
+                // Disconnected while the attempt was in progress
+                return false;
+            }
+            match result {
+                Ok(()) => {
+                    *current_state = ClientState::Connected;
+                    drop(current_state);
+                    info!("Reconnected to {} after {} attempt(s)", config.url, attempt);
+                    Self::emit_connection_event_static(
+                        ConnectionEvent::Reconnected { attempts: attempt },
+                        connection_event_handlers,
+                    )
+                    .await;
+                    return true;
+                }
+                Err(ClientError::Authentication(error)) => {
+                    *current_state = ClientState::Failed;
+                    drop(current_state);
+                    error!("Reconnection rejected by the server: {}", error);
+                    Self::emit_connection_event_static(
+                        ConnectionEvent::AuthenticationFailed { error },
+                        connection_event_handlers,
+                    )
+                    .await;
+                    return false;
+                }
+                Err(e) => {
+                    drop(current_state);
+                    warn!("Reconnection attempt {} failed: {}", attempt, e);
+                    Self::emit_connection_event_static(
+                        ConnectionEvent::ReconnectFailed {
+                            attempt,
+                            error: e.to_string(),
+                        },
+                        connection_event_handlers,
+                    )
+                    .await;
+                }
+            }
+        }
+
+        let mut state = state.write().await;
+        if *state == ClientState::Reconnecting {
+            *state = ClientState::Failed;
+            error!(
+                "Giving up reconnecting to {} after {} attempts",
+                config.url, attempt
+            );
+        }
+        false
+    }
+
+    /// Register the client's notification filters on a new connection
+    async fn renew_filters(
+        transport: &RwLock<Box<dyn WebSocketTransport>>,
+        filters: &DashMap<String, Value>,
+    ) {
+        let renewals: Vec<(String, Value)> = filters
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        for (notification, filter) in renewals {
+            let Ok(params) = Self::filter_params(&notification, Some(filter)) else {
+                continue;
+            };
+            // Sent as a notification, which gets no response
+            let request = JsonRpcRequest::new(FILTER_SET_METHOD.to_string(), Some(params), None);
+            let message = BidirectionalMessage::Request(request);
+            if let Err(e) = transport.write().await.send(&message).await {
+                warn!("Failed to renew the filter of {}: {}", notification, e);
+            }
+        }
+    }
+
+    /// Resume the subscriptions that received numbered broadcasts on a new
+    /// connection, so the server replays what was missed meanwhile
+    ///
+    /// Topics whose missed broadcasts are no longer kept raise a `TopicGap` event.
+    async fn resume_topics(
+        transport: &RwLock<Box<dyn WebSocketTransport>>,
+        subscriptions: &DashMap<String, Subscription>,
+        pending_requests: &DashMap<Value, PendingRequest>,
+        request_id_counter: &AtomicU64,
+        connection_event_handlers: &Arc<DashMap<String, ConnectionEventHandler>>,
+    ) {
+        let resumes: Vec<TopicResume> = subscriptions
+            .iter()
+            .filter_map(|entry| {
+                entry.last_seq.map(|last_seq| TopicResume {
+                    topic: entry.key().clone(),
+                    last_seq,
+                })
+            })
+            .collect();
+        for resume in resumes {
+            let id = Value::from(request_id_counter.fetch_add(1, Ordering::SeqCst));
+            let request = JsonRpcRequest::new(
+                TOPIC_RESUME_METHOD.to_string(),
+                serde_json::to_value(&resume).ok(),
+                Some(id.clone()),
+            );
+            let (sender, response) = oneshot::channel();
+            pending_requests.insert(
+                id.clone(),
+                PendingRequest {
+                    id,
+                    sender,
+                    created_at: Instant::now(),
+                },
+            );
+            let message = BidirectionalMessage::Request(request);
+            if let Err(e) = transport.write().await.send(&message).await {
+                warn!("Failed to resume topic {}: {}", resume.topic, e);
+                continue;
+            }
+
+            let handlers = Arc::clone(connection_event_handlers);
+            runtime::spawn(async move {
+                let Ok(response) = response.await else {
+                    return;
+                };
+                let resumed = response
+                    .result
+                    .and_then(|result| serde_json::from_value::<TopicResumed>(result).ok());
+                match resumed {
+                    Some(resumed) if !resumed.gap => {
+                        debug!(
+                            "Resumed topic {} with {} replayed broadcasts",
+                            resume.topic, resumed.replayed
+                        );
+                        return;
+                    }
+                    Some(_) => {}
+                    None => warn!("Server failed to resume topic {}", resume.topic),
+                }
+                Self::emit_connection_event_static(
+                    ConnectionEvent::TopicGap {
+                        topic: resume.topic,
+                        last_seq: resume.last_seq,
+                    },
+                    &handlers,
+                )
+                .await;
+            });
+        }
+    }
+
+    /// Answer the pending requests with the given ids with an error
+    fn fail_requests(
+        pending_requests: &DashMap<Value, PendingRequest>,
+        ids: impl IntoIterator<Item = Value>,
+        message: &str,
+    ) {
+        for id in ids {
+            if let Some((_, pending)) = pending_requests.remove(&id) {
+                let _ = pending.sender.send(JsonRpcResponse::error(
+                    ras_jsonrpc_types::JsonRpcError::internal_error(message.to_string()),
+                    Some(pending.id),
+                ));
+            }
+        }
+    }
+
+    /// Answer all pending requests with an error
+    fn fail_all_requests(pending_requests: &DashMap<Value, PendingRequest>, message: &str) {
+        let ids: Vec<Value> = pending_requests
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        Self::fail_requests(pending_requests, ids, message);
+    }
+
     async fn emit_connection_event(&self, event: ConnectionEvent) {
         Self::emit_connection_event_static(event, &self.connection_event_handlers).await;
     }
@@ -522,17 +1129,26 @@ impl Client {
     async fn start_heartbeat(&self, interval: Duration) {
         let message_tx = Arc::clone(&self.message_tx);
         let state = Arc::clone(&self.state);
+        let missed_heartbeats = Arc::clone(&self.missed_heartbeats);
+        let max_missed = self.config.max_missed_heartbeats;
 
-        tokio::spawn(async move {
-            let mut heartbeat_interval = tokio::time::interval(interval);
-            heartbeat_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        runtime::spawn(async move {
+            let mut heartbeat_interval = Interval::new(interval);
 
             loop {
                 heartbeat_interval.tick().await;
 
-                let current_state = *state.read().await;
-                if current_state != ClientState::Connected {
-                    break;
+                match *state.read().await {
+                    ClientState::Connected => {}
+                    // Pings would only pile up behind buffered calls
+                    ClientState::Reconnecting => continue,
+                    _ => break,
+                }
+
+                // Once too many went unanswered the message handler drops the connection
+                let missed = missed_heartbeats.fetch_add(1, Ordering::SeqCst);
+                if max_missed > 0 && missed >= max_missed {
+                    continue;
                 }
 
                 let tx_guard = message_tx.read().await;
@@ -592,17 +1208,29 @@ pub struct ClientBuilder {
     /// Request timeout
     request_timeout: Duration,
 
+    /// Whether timed out requests are cancelled on the server
+    cancel_on_timeout: bool,
+
     /// Reconnection configuration
     reconnect_config: Option<ReconnectConfig>,
 
     /// Heartbeat interval
     heartbeat_interval: Option<Duration>,
 
+    /// Unanswered heartbeats after which the connection is considered lost
+    max_missed_heartbeats: u32,
+
     /// Connection timeout
     connection_timeout: Duration,
 
     /// Auto-connect after building
     auto_connect: bool,
+
+    /// Called with the number of attempts after each successful reconnect
+    on_reconnect: Option<Arc<dyn Fn(u32) + Send + Sync>>,
+
+    /// Binary codec to offer the server
+    codec: Codec,
 }
 
 impl ClientBuilder {
@@ -614,10 +1242,14 @@ impl ClientBuilder {
             jwt_in_header: true,
             custom_headers: HashMap::new(),
             request_timeout: Duration::from_secs(30),
+            cancel_on_timeout: true,
             reconnect_config: None,
             heartbeat_interval: Some(Duration::from_secs(30)),
+            max_missed_heartbeats: 2,
             connection_timeout: Duration::from_secs(10),
             auto_connect: false,
+            on_reconnect: None,
+            codec: Codec::Json,
         }
     }
 
@@ -645,6 +1277,13 @@ impl ClientBuilder {
         self
     }
 
+    /// Set whether requests that time out are cancelled on the server (on by
+    /// default)
+    pub fn with_cancel_on_timeout(mut self, cancel: bool) -> Self {
+        self.cancel_on_timeout = cancel;
+        self
+    }
+
     /// Set reconnection configuration
     pub fn with_reconnect_config(mut self, config: ReconnectConfig) -> Self {
         self.reconnect_config = Some(config);
@@ -657,12 +1296,36 @@ impl ClientBuilder {
         self
     }
 
+    /// Set how many heartbeats may go unanswered before the connection is
+    /// considered lost (0 = never)
+    pub fn with_max_missed_heartbeats(mut self, max_missed: u32) -> Self {
+        self.max_missed_heartbeats = max_missed;
+        self
+    }
+
     /// Set connection timeout
     pub fn with_connection_timeout(mut self, timeout: Duration) -> Self {
         self.connection_timeout = timeout;
         self
     }
 
+    /// Call `handler` with the number of attempts each time the client reconnects
+    /// after losing its connection
+    pub fn with_on_reconnect<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(u32) + Send + Sync + 'static,
+    {
+        self.on_reconnect = Some(Arc::new(handler));
+        self
+    }
+
+    /// Offer `codec` to the server, exchanging binary frames in it if the server
+    /// accepts and JSON text otherwise
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
     /// Enable auto-connect after building
     pub fn with_auto_connect(mut self, auto_connect: bool) -> Self {
         self.auto_connect = auto_connect;
@@ -687,16 +1350,30 @@ impl ClientBuilder {
             auth,
             reconnect: self.reconnect_config.unwrap_or_default(),
             request_timeout: self.request_timeout,
+            cancel_on_timeout: self.cancel_on_timeout,
             heartbeat_interval: self.heartbeat_interval,
+            max_missed_heartbeats: self.max_missed_heartbeats,
             max_pending_requests: 1000,
             custom_headers: self.custom_headers,
             connection_timeout: self.connection_timeout,
             message_buffer_size: 1024,
             auto_subscribe_events: true,
+            codec: self.codec,
         };
 
         let client = Client::new(config).await?;
 
+        if let Some(on_reconnect) = self.on_reconnect {
+            client.on_connection_event(
+                "on_reconnect",
+                Arc::new(move |event| {
+                    if let ConnectionEvent::Reconnected { attempts } = event {
+                        on_reconnect(attempts);
+                    }
+                }),
+            );
+        }
+
         if self.auto_connect {
             client.connect().await?;
         }
@@ -705,7 +1382,7 @@ impl ClientBuilder {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use super::*;
 
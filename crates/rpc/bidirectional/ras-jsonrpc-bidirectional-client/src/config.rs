@@ -1,6 +1,7 @@
 //! Configuration types for the bidirectional JSON-RPC client
 
 use bon::Builder;
+use ras_jsonrpc_bidirectional_types::Codec;
 use std::collections::HashMap;
 use std::time::Duration;
 
@@ -51,6 +52,11 @@ pub struct ReconnectConfig {
     /// Jitter to add to delays (0.0 = no jitter, 1.0 = up to 100% jitter)
     #[builder(default = 0.1)]
     pub jitter: f64,
+
+    /// Whether calls made while reconnecting wait for the new connection (true)
+    /// or fail with `ClientError::NotConnected` (false)
+    #[builder(default = true)]
+    pub buffer_while_reconnecting: bool,
 }
 
 impl Default for ReconnectConfig {
@@ -62,6 +68,7 @@ impl Default for ReconnectConfig {
             max_delay: Duration::from_secs(30),
             backoff_multiplier: 2.0,
             jitter: 0.1,
+            buffer_while_reconnecting: true,
         }
     }
 }
@@ -114,9 +121,19 @@ pub struct ClientConfig {
     #[builder(default = Duration::from_secs(30))]
     pub request_timeout: Duration,
 
+    /// Whether a request that times out is cancelled on the server with a
+    /// `$/cancel` notification
+    #[builder(default = true)]
+    pub cancel_on_timeout: bool,
+
     /// Heartbeat/keepalive interval (None = disabled)
     pub heartbeat_interval: Option<Duration>,
 
+    /// Unanswered heartbeats after which the connection is considered lost
+    /// (0 = never)
+    #[builder(default = 2)]
+    pub max_missed_heartbeats: u32,
+
     /// Maximum number of pending requests
     #[builder(default = 1000)]
     pub max_pending_requests: usize,
@@ -136,6 +153,11 @@ pub struct ClientConfig {
     /// Whether to automatically subscribe to connection events
     #[builder(default = true)]
     pub auto_subscribe_events: bool,
+
+    /// Binary codec to offer the server; messages stay JSON text unless the
+    /// server accepts it
+    #[builder(default)]
+    pub codec: Codec,
 }
 
 impl ClientConfig {
@@ -146,12 +168,15 @@ impl ClientConfig {
             auth: AuthConfig::default(),
             reconnect: ReconnectConfig::default(),
             request_timeout: Duration::from_secs(30),
+            cancel_on_timeout: true,
             heartbeat_interval: Some(Duration::from_secs(30)),
+            max_missed_heartbeats: 2,
             max_pending_requests: 1000,
             custom_headers: HashMap::new(),
             connection_timeout: Duration::from_secs(10),
             message_buffer_size: 1024,
             auto_subscribe_events: true,
+            codec: Codec::Json,
         }
     }
 
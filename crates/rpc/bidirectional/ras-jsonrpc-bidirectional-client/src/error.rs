@@ -1,6 +1,6 @@
 //! Error types for the bidirectional JSON-RPC client
 
-use ras_jsonrpc_bidirectional_types::BidirectionalError;
+use ras_jsonrpc_bidirectional_types::{BidirectionalError, CloseReason};
 use thiserror::Error;
 
 /// Errors that can occur in the bidirectional JSON-RPC client
@@ -14,6 +14,10 @@ pub enum ClientError {
     #[error("Authentication error: {0}")]
     Authentication(String),
 
+    /// The server closed the connection, for the given reason
+    #[error("Connection closed by server: {0}")]
+    Closed(CloseReason),
+
     /// JSON serialization/deserialization error
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
@@ -153,6 +157,7 @@ impl ClientError {
         matches!(
             self,
             Self::Connection(_)
+                | Self::Closed(_)
                 | Self::Timeout { .. }
                 | Self::SendFailed(_)
                 | Self::ReceiveFailed(_)
@@ -161,11 +166,28 @@ impl ClientError {
     }
 
     /// Check if this error should trigger a reconnection
+    ///
+    /// Connections closed because the session expired or was revoked are not
+    /// retried, since the same credentials would be rejected again.
     pub fn should_reconnect(&self) -> bool {
-        matches!(
-            self,
-            Self::Connection(_) | Self::ReceiveFailed(_) | Self::NotConnected
-        )
+        match self {
+            Self::Closed(reason) => !matches!(
+                reason,
+                CloseReason::SessionExpired | CloseReason::SessionRevoked
+            ),
+            _ => matches!(
+                self,
+                Self::Connection(_) | Self::ReceiveFailed(_) | Self::NotConnected
+            ),
+        }
+    }
+
+    /// Why the server closed the connection, if it said
+    pub fn close_reason(&self) -> Option<&CloseReason> {
+        match self {
+            Self::Closed(reason) => Some(reason),
+            _ => None,
+        }
     }
 
     #[cfg(target_arch = "wasm32")]
@@ -213,6 +235,23 @@ mod tests {
         assert!(should_reconnect.should_reconnect());
     }
 
+    #[test]
+    fn closed_connections_reconnect_unless_the_session_expired() {
+        let closed = ClientError::Closed(CloseReason::ServerShutdown);
+        assert!(closed.should_reconnect());
+        assert_eq!(closed.close_reason(), Some(&CloseReason::ServerShutdown));
+        assert_eq!(
+            closed.to_string(),
+            "Connection closed by server: Server shutting down"
+        );
+
+        let expired = ClientError::Closed(CloseReason::SessionExpired);
+        assert!(expired.is_recoverable());
+        assert!(!expired.should_reconnect());
+        assert!(!ClientError::Closed(CloseReason::SessionRevoked).should_reconnect());
+        assert_eq!(ClientError::NotConnected.close_reason(), None);
+    }
+
     #[test]
     fn test_timeout_error() {
         let err = ClientError::timeout(45);
@@ -9,11 +9,18 @@
 //! - Connection lifecycle management (connect, disconnect, reconnect)
 //! - Subscription management
 //! - Builder pattern for client configuration
+//! - A WebSocket transport for clients generated by `jsonrpc_service!` (native only)
+//!
+//! Transports implement [`WebSocketTransport`]; the client picks the one for the
+//! target it is compiled for, so the same API and reconnection policy apply in
+//! the browser.
 //!
 //! # Platform Support
 //!
 //! - **Native**: Uses `tokio-tungstenite` for WebSocket communication
-//! - **WASM**: Uses `web-sys` WebSocket API for browser compatibility
+//! - **WASM**: Uses `web-sys` WebSocket API for browser compatibility. Browsers
+//!   cannot set handshake headers, so a JWT configured for the `Authorization`
+//!   header is offered as a `token.<jwt>` subprotocol instead
 //!
 //! # Examples
 //!
@@ -45,16 +52,32 @@ use std::sync::Arc;
 pub mod client;
 pub mod config;
 pub mod error;
+pub mod runtime;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod native;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rpc_transport;
+
 #[cfg(target_arch = "wasm32")]
 pub mod wasm;
 
 pub use client::{Client, ClientBuilder};
 pub use config::{ClientConfig, ReconnectConfig};
 pub use error::ClientError;
+pub use ras_auth_core::AuthenticatedUser;
+pub use ras_jsonrpc_bidirectional_types::{
+    CloseReason, Codec, PresenceEntry, PresenceEvent, SessionExpiring, ShutdownNotice, TopicResumed,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use rpc_transport::WebSocketRpcTransport;
+
+// Re-exported so generated clients can name the channels they use without a
+// direct `tokio` dependency, whose feature set differs per target.
+#[doc(hidden)]
+pub use tokio;
 
 /// Type alias for notification handlers
 pub type NotificationHandler = Arc<dyn Fn(&str, &Value) + Send + Sync>;
@@ -75,11 +98,33 @@ pub type ConnectionEventHandler = Arc<dyn Fn(ConnectionEvent) + Send + Sync>;
 /// Connection lifecycle events
 #[derive(Debug, Clone)]
 pub enum ConnectionEvent {
-    Connected { connection_id: ConnectionId },
-    Disconnected { reason: Option<String> },
-    Reconnecting { attempt: u32 },
-    ReconnectFailed { attempt: u32, error: String },
-    AuthenticationFailed { error: String },
+    Connected {
+        connection_id: ConnectionId,
+    },
+    /// The connection closed; `close_reason` tells why, such as a session that
+    /// expired or a server shutting down, when the server said
+    Disconnected {
+        reason: Option<String>,
+        close_reason: Option<CloseReason>,
+    },
+    Reconnecting {
+        attempt: u32,
+    },
+    Reconnected {
+        attempts: u32,
+    },
+    ReconnectFailed {
+        attempt: u32,
+        error: String,
+    },
+    AuthenticationFailed {
+        error: String,
+    },
+    /// Broadcasts on a resumed topic were missed and could not be replayed
+    TopicGap {
+        topic: String,
+        last_seq: u64,
+    },
 }
 
 /// Trait for WebSocket transport implementations
@@ -109,7 +154,7 @@ pub trait WebSocketTransport: Send + Sync {
 pub struct PendingRequest {
     pub id: Value,
     pub sender: tokio::sync::oneshot::Sender<JsonRpcResponse>,
-    pub created_at: std::time::Instant,
+    pub created_at: runtime::Instant,
 }
 
 /// Request timeout configuration
@@ -141,7 +186,9 @@ pub enum ClientState {
 pub struct Subscription {
     pub topic: String,
     pub handler: NotificationHandler,
-    pub created_at: std::time::Instant,
+    pub created_at: runtime::Instant,
+    /// Sequence number of the last numbered broadcast received on the topic
+    pub last_seq: Option<u64>,
 }
 
 impl std::fmt::Debug for Subscription {
@@ -149,6 +196,7 @@ impl std::fmt::Debug for Subscription {
         f.debug_struct("Subscription")
             .field("topic", &self.topic)
             .field("created_at", &self.created_at)
+            .field("last_seq", &self.last_seq)
             .finish()
     }
 }
@@ -7,22 +7,28 @@ use crate::{
 };
 use async_trait::async_trait;
 use futures::{FutureExt, SinkExt, StreamExt};
-use ras_jsonrpc_bidirectional_types::BidirectionalMessage;
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, CloseReason, Codec, Frame};
 use std::sync::Arc;
 use tokio::net::TcpStream;
 use tokio::sync::RwLock;
 use tokio_tungstenite::{
     MaybeTlsStream, WebSocketStream, connect_async_with_config,
     tungstenite::Message,
-    tungstenite::{handshake::client::generate_key, http::Request},
+    tungstenite::error::ProtocolError,
+    tungstenite::handshake::client::{Response, generate_key},
+    tungstenite::http::Request,
 };
 use tracing::{debug, info, warn};
 use url::Url;
 
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
 /// Native WebSocket transport using tokio-tungstenite
 pub struct NativeWebSocketTransport {
     config: ClientConfig,
-    connection: Arc<RwLock<Option<WebSocketStream<MaybeTlsStream<TcpStream>>>>>,
+    connection: Arc<RwLock<Option<Socket>>>,
+    /// Codec the server accepted for the current connection
+    codec: Codec,
     url: Url,
 }
 
@@ -35,10 +41,16 @@ impl NativeWebSocketTransport {
         Self {
             config,
             connection: Arc::new(RwLock::new(None)),
+            codec: Codec::Json,
             url,
         }
     }
 
+    /// Codec the server accepted for the current connection
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
     /// Build request headers for the WebSocket connection
     fn build_request_headers(&self) -> http::HeaderMap {
         let mut headers = http::HeaderMap::new();
@@ -54,15 +66,15 @@ impl NativeWebSocketTransport {
 
         headers
     }
-}
-
-#[async_trait]
-impl WebSocketTransport for NativeWebSocketTransport {
-    async fn connect(&mut self) -> ClientResult<()> {
-        info!("Connecting to WebSocket server: {}", self.url);
-
-        // Create connection request with headers using IntoClientRequest
 
+    /// Perform the opening handshake, offering `codec` if it is binary
+    ///
+    /// The outer error covers building the request and timing out; the inner one
+    /// is the handshake's own, left for the caller to interpret.
+    async fn handshake(
+        &self,
+        codec: Codec,
+    ) -> ClientResult<Result<(Socket, Response), tokio_tungstenite::tungstenite::Error>> {
         // Extract host from URL for Host header
         let host = self.url.host_str().unwrap_or("localhost");
         let host_header = if let Some(port) = self.url.port() {
@@ -79,6 +91,9 @@ impl WebSocketTransport for NativeWebSocketTransport {
             .header("Upgrade", "websocket")
             .header("Sec-WebSocket-Version", "13")
             .header("Sec-WebSocket-Key", generate_key());
+        if let Some(protocol) = codec.subprotocol() {
+            request = request.header("Sec-WebSocket-Protocol", protocol);
+        }
 
         // Add custom headers from build_request_headers()
         let headers = self.build_request_headers();
@@ -106,18 +121,53 @@ impl WebSocketTransport for NativeWebSocketTransport {
 
         // Connect with timeout
         let connect_future = connect_async_with_config(request, Some(config), false);
-        let (ws_stream, response) =
-            tokio::time::timeout(self.config.connection_timeout, connect_future)
-                .await
-                .map_err(|_| ClientError::timeout(self.config.connection_timeout.as_secs()))?
-                .map_err(|e| {
-                    ClientError::connection(format!("WebSocket connection failed: {}", e))
-                })?;
+        tokio::time::timeout(self.config.connection_timeout, connect_future)
+            .await
+            .map_err(|_| ClientError::timeout(self.config.connection_timeout.as_secs()))
+    }
+}
+
+#[async_trait]
+impl WebSocketTransport for NativeWebSocketTransport {
+    async fn connect(&mut self) -> ClientResult<()> {
+        info!("Connecting to WebSocket server: {}", self.url);
+
+        let offered = self.config.codec;
+        let connected = match self.handshake(offered).await? {
+            // Servers that select no subprotocol fail the handshake once one is offered
+            Err(tokio_tungstenite::tungstenite::Error::Protocol(
+                ProtocolError::SecWebSocketSubProtocolError(e),
+            )) if offered.is_binary() => {
+                warn!(
+                    "Server did not accept the {:?} codec ({}), falling back to JSON",
+                    offered, e
+                );
+                self.handshake(Codec::Json).await?
+            }
+            connected => connected,
+        };
+        let (ws_stream, response) = connected.map_err(|e| match e {
+            tokio_tungstenite::tungstenite::Error::Http(response)
+                if matches!(response.status().as_u16(), 401 | 403) =>
+            {
+                ClientError::authentication(format!(
+                    "WebSocket upgrade rejected with status {}",
+                    response.status()
+                ))
+            }
+            e => ClientError::connection(format!("WebSocket connection failed: {}", e)),
+        })?;
 
         debug!(
             "WebSocket connection established, status: {}",
             response.status()
         );
+        self.codec = response
+            .headers()
+            .get("sec-websocket-protocol")
+            .and_then(|protocol| protocol.to_str().ok())
+            .and_then(Codec::from_subprotocol)
+            .unwrap_or_default();
 
         // Store the connection
         *self.connection.write().await = Some(ws_stream);
@@ -142,11 +192,16 @@ impl WebSocketTransport for NativeWebSocketTransport {
     }
 
     async fn send(&mut self, message: &BidirectionalMessage) -> ClientResult<()> {
-        let json = serde_json::to_string(message).map_err(ClientError::Json)?;
-
-        debug!("Sending message: {}", json);
-
-        let ws_message = Message::Text(json.into());
+        let ws_message = match self.codec.encode(message)? {
+            Frame::Text(json) => {
+                debug!("Sending message: {}", json);
+                Message::Text(json.into())
+            }
+            Frame::Binary(bytes) => {
+                debug!("Sending binary message ({} bytes)", bytes.len());
+                Message::Binary(bytes.into())
+            }
+        };
 
         let mut connection_guard = self.connection.write().await;
         if let Some(ref mut ws) = *connection_guard {
@@ -178,14 +233,18 @@ impl WebSocketTransport for NativeWebSocketTransport {
                         }
                         Message::Binary(data) => {
                             debug!("Received binary message ({} bytes)", data.len());
-                            let bidirectional_message: BidirectionalMessage =
-                                serde_json::from_slice(&data).map_err(ClientError::Json)?;
-                            Ok(Some(bidirectional_message))
+                            // Without a binary codec, binary frames carry JSON
+                            Ok(Some(self.codec.decode(&data)?))
                         }
                         Message::Close(close_frame) => {
                             info!("Received close frame: {:?}", close_frame);
-                            *self.connection.write().await = None;
-                            Err(ClientError::connection("Connection closed by server"))
+                            *connection_guard = None;
+                            let reason = close_frame
+                                .and_then(|frame| {
+                                    CloseReason::from_close_frame(frame.code.into(), &frame.reason)
+                                })
+                                .unwrap_or(CloseReason::ConnectionLost);
+                            Err(ClientError::Closed(reason))
                         }
                         Message::Ping(data) => {
                             debug!("Received ping, sending pong");
@@ -208,7 +267,7 @@ impl WebSocketTransport for NativeWebSocketTransport {
                 Some(None) => {
                     // Stream ended
                     info!("WebSocket stream ended");
-                    *self.connection.write().await = None;
+                    *connection_guard = None;
                     Err(ClientError::connection("WebSocket stream ended"))
                 }
                 None => {
@@ -0,0 +1,409 @@
+//! WebSocket transport for clients generated by `jsonrpc_service!`
+//!
+//! Multiplexes the calls of a generated client over one persistent WebSocket
+//! connection instead of sending an HTTP request per call.
+
+use crate::error::ClientError;
+use dashmap::DashMap;
+use futures::{SinkExt, StreamExt};
+use ras_jsonrpc_bidirectional_types::BidirectionalMessage;
+use ras_jsonrpc_types::{
+    CallOptions, ClientTransport, JsonRpcRequest, JsonRpcResponse, RetryOn, TransportError,
+    TransportFuture,
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tokio::time::Instant;
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream, connect_async,
+    tungstenite::{
+        self, Message,
+        client::IntoClientRequest,
+        http::{HeaderName, HeaderValue, header::AUTHORIZATION},
+    },
+};
+use tracing::{debug, info, warn};
+
+/// Calls waiting for their response, keyed by wire request id
+type PendingCalls = Arc<DashMap<u64, oneshot::Sender<JsonRpcResponse>>>;
+
+/// [`ClientTransport`] sending calls over a persistent WebSocket connection
+///
+/// The connection is opened by the first call, with the client's bearer token
+/// in the `Authorization` header of the upgrade request. Responses are matched
+/// to calls by request id, so any number of calls can be in flight at once.
+///
+/// When the connection drops, every call still waiting fails with a
+/// connection error (retryable as [`RetryOn::ConnectionError`]) and the next
+/// call reconnects. Setting a different bearer token on the client also
+/// reconnects, failing the calls still in flight on the old connection.
+///
+/// Serve the methods with `jsonrpc_websocket_route` from
+/// `ras-jsonrpc-bidirectional-server`.
+pub struct WebSocketRpcTransport {
+    url: String,
+    custom_headers: HashMap<String, String>,
+    connection_timeout: Duration,
+    connection: Mutex<Option<Connection>>,
+    next_id: AtomicU64,
+}
+
+/// An open connection and the calls waiting on it
+struct Connection {
+    outgoing: mpsc::UnboundedSender<Message>,
+    pending: PendingCalls,
+    bearer_token: Option<String>,
+}
+
+/// A call that was sent and waits for its response
+struct InFlightCall {
+    id: u64,
+    request_id: Option<Value>,
+    response: oneshot::Receiver<JsonRpcResponse>,
+    pending: PendingCalls,
+}
+
+impl WebSocketRpcTransport {
+    /// Create a transport connecting to the WebSocket endpoint at `url`
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        Self {
+            url: url.into(),
+            custom_headers: HashMap::new(),
+            connection_timeout: Duration::from_secs(10),
+            connection: Mutex::new(None),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Add a custom header to the upgrade request
+    pub fn with_header<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.custom_headers.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set connection timeout
+    pub fn with_connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = timeout;
+        self
+    }
+
+    /// Close the connection, failing every call still waiting on it
+    ///
+    /// The next call opens a new connection.
+    pub async fn disconnect(&self) {
+        self.connection.lock().await.take();
+    }
+
+    /// Number of calls waiting for their response
+    pub async fn pending_calls_count(&self) -> usize {
+        self.connection
+            .lock()
+            .await
+            .as_ref()
+            .map_or(0, |connection| connection.pending.len())
+    }
+
+    /// The open connection for `bearer_token`, connecting if there is none
+    async fn connection(
+        &self,
+        bearer_token: Option<&str>,
+    ) -> Result<(mpsc::UnboundedSender<Message>, PendingCalls), TransportError> {
+        let mut connection = self.connection.lock().await;
+
+        // Dropping a replaced connection closes it and fails its pending calls
+        let current = match connection.take() {
+            Some(current)
+                if !current.outgoing.is_closed()
+                    && current.bearer_token.as_deref() == bearer_token =>
+            {
+                current
+            }
+            _ => self.connect(bearer_token).await?,
+        };
+
+        let handles = (current.outgoing.clone(), current.pending.clone());
+        *connection = Some(current);
+        Ok(handles)
+    }
+
+    /// Open a new connection and start its message loop
+    async fn connect(&self, bearer_token: Option<&str>) -> Result<Connection, TransportError> {
+        info!("Connecting to WebSocket server: {}", self.url);
+
+        let mut request = self
+            .url
+            .as_str()
+            .into_client_request()
+            .map_err(|e| TransportError::new(ClientError::invalid_url(e.to_string())))?;
+
+        for (key, value) in &self.custom_headers {
+            let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(key.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) else {
+                return Err(TransportError::new(ClientError::configuration(format!(
+                    "Invalid header: {key}"
+                ))));
+            };
+            request.headers_mut().insert(name, value);
+        }
+
+        if let Some(token) = bearer_token {
+            let value = HeaderValue::try_from(format!("Bearer {token}")).map_err(|_| {
+                TransportError::new(ClientError::authentication("Invalid bearer token"))
+            })?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+
+        let socket = match tokio::time::timeout(self.connection_timeout, connect_async(request))
+            .await
+        {
+            Ok(Ok((socket, _))) => socket,
+            // A rejected upgrade carries the HTTP status, e.g. 401 or 503
+            Ok(Err(tungstenite::Error::Http(response))) => {
+                let status = response.status();
+                return Err(TransportError {
+                    error: ClientError::connection(format!("WebSocket upgrade failed: {status}"))
+                        .into(),
+                    retry_on: vec![RetryOn::HttpStatus(status.as_u16())],
+                });
+            }
+            Ok(Err(e)) => return Err(TransportError::connection(ClientError::from(e))),
+            Err(_) => {
+                return Err(TransportError::connection(ClientError::timeout(
+                    self.connection_timeout.as_secs(),
+                )));
+            }
+        };
+
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let pending = PendingCalls::default();
+        tokio::spawn(run_connection(socket, outgoing_rx, pending.clone()));
+
+        Ok(Connection {
+            outgoing,
+            pending,
+            bearer_token: bearer_token.map(str::to_string),
+        })
+    }
+
+    /// Send `request` under a fresh wire id and register it as pending
+    async fn start_call(
+        &self,
+        mut request: JsonRpcRequest,
+        bearer_token: Option<&str>,
+    ) -> Result<InFlightCall, TransportError> {
+        let (outgoing, pending) = self.connection(bearer_token).await?;
+
+        // Ids are reassigned so clients sharing the transport cannot collide
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request_id = request.id.replace(Value::from(id));
+
+        let (response_tx, response) = oneshot::channel();
+        pending.insert(id, response_tx);
+
+        if let Err(e) = send(&outgoing, BidirectionalMessage::Request(request)) {
+            pending.remove(&id);
+            return Err(e);
+        }
+
+        Ok(InFlightCall {
+            id,
+            request_id,
+            response,
+            pending,
+        })
+    }
+}
+
+impl InFlightCall {
+    /// Wait for the response until `deadline`
+    async fn finish(self, deadline: Option<Instant>) -> Result<JsonRpcResponse, TransportError> {
+        let response = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, self.response).await {
+                Ok(response) => response,
+                Err(_) => {
+                    self.pending.remove(&self.id);
+                    return Err(TransportError::connection(ClientError::internal(
+                        "Request timed out",
+                    )));
+                }
+            },
+            None => self.response.await,
+        };
+
+        let mut response = response.map_err(|_| {
+            TransportError::connection(ClientError::connection(
+                "Connection closed before the response arrived",
+            ))
+        })?;
+        response.id = self.request_id;
+        Ok(response)
+    }
+}
+
+impl ClientTransport for WebSocketRpcTransport {
+    fn call<'a>(
+        &'a self,
+        request: JsonRpcRequest,
+        options: CallOptions<'a>,
+    ) -> TransportFuture<'a, Result<JsonRpcResponse, TransportError>> {
+        Box::pin(async move {
+            let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+            self.start_call(request, options.bearer_token)
+                .await?
+                .finish(deadline)
+                .await
+        })
+    }
+
+    fn notify<'a>(
+        &'a self,
+        request: JsonRpcRequest,
+        options: CallOptions<'a>,
+    ) -> TransportFuture<'a, Result<(), TransportError>> {
+        Box::pin(async move {
+            let (outgoing, _) = self.connection(options.bearer_token).await?;
+            send(&outgoing, BidirectionalMessage::Request(request))
+        })
+    }
+
+    fn batch<'a>(
+        &'a self,
+        requests: Vec<JsonRpcRequest>,
+        options: CallOptions<'a>,
+    ) -> TransportFuture<'a, Result<Vec<JsonRpcResponse>, TransportError>> {
+        Box::pin(async move {
+            let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+
+            // Send every call before waiting, so they are all in flight at once
+            let mut calls = Vec::with_capacity(requests.len());
+            for request in requests {
+                calls.push(self.start_call(request, options.bearer_token).await?);
+            }
+
+            let mut responses = Vec::with_capacity(calls.len());
+            for call in calls {
+                responses.push(call.finish(deadline).await?);
+            }
+            Ok(responses)
+        })
+    }
+}
+
+/// Queue `message` on a connection
+fn send(
+    outgoing: &mpsc::UnboundedSender<Message>,
+    message: BidirectionalMessage,
+) -> Result<(), TransportError> {
+    let json =
+        serde_json::to_string(&message).map_err(|e| TransportError::new(ClientError::Json(e)))?;
+    outgoing
+        .send(Message::Text(json.into()))
+        .map_err(|_| TransportError::connection(ClientError::NotConnected))
+}
+
+/// Pump queued messages to the socket and responses to their pending calls
+async fn run_connection(
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    mut outgoing: mpsc::UnboundedReceiver<Message>,
+    pending: PendingCalls,
+) {
+    let (mut sink, mut stream) = socket.split();
+
+    loop {
+        tokio::select! {
+            message = outgoing.recv() => match message {
+                Some(message) => {
+                    if let Err(e) = sink.send(message).await {
+                        warn!("Failed to send message: {}", e);
+                        break;
+                    }
+                }
+                None => {
+                    debug!("Transport dropped the connection");
+                    let _ = sink.close().await;
+                    break;
+                }
+            },
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => complete_call(text.as_bytes(), &pending),
+                Some(Ok(Message::Binary(data))) => complete_call(&data, &pending),
+                Some(Ok(Message::Close(frame))) => {
+                    info!("Received close frame: {:?}", frame);
+                    break;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    warn!("WebSocket error: {}", e);
+                    break;
+                }
+                None => {
+                    info!("WebSocket stream ended");
+                    break;
+                }
+            },
+        }
+    }
+
+    // Closing the queue first keeps calls from registering after the drain;
+    // dropping their senders fails the calls still waiting
+    outgoing.close();
+    pending.clear();
+}
+
+/// Hand a response message to the call waiting for it
+fn complete_call(message: &[u8], pending: &PendingCalls) {
+    match serde_json::from_slice::<BidirectionalMessage>(message) {
+        Ok(BidirectionalMessage::Response(response)) => {
+            let call = response
+                .id
+                .as_ref()
+                .and_then(Value::as_u64)
+                .and_then(|id| pending.remove(&id));
+            match call {
+                Some((_, sender)) => {
+                    let _ = sender.send(response);
+                }
+                None => warn!(
+                    "Received response for unknown request ID: {:?}",
+                    response.id
+                ),
+            }
+        }
+        Ok(message) => debug!("Ignoring message: {:?}", message),
+        Err(e) => warn!("Failed to parse message: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn refused_connection_is_retryable() {
+        // Reserve a port, then close it so connections are refused
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        drop(listener);
+
+        let transport = WebSocketRpcTransport::new(url);
+        let request = JsonRpcRequest::new("ping".to_string(), None, Some(Value::from(1)));
+        let failure = transport
+            .call(request, CallOptions::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(failure.retry_on, vec![RetryOn::ConnectionError]);
+        assert_eq!(transport.pending_calls_count().await, 0);
+    }
+}
@@ -0,0 +1,143 @@
+//! Task and timer primitives that work on both native and WASM targets
+//!
+//! Native builds run on tokio. In the browser there is no tokio runtime, so tasks
+//! go to `wasm_bindgen_futures::spawn_local` and timers to `gloo-timers`; the
+//! event loop is single-threaded there, which is what lets the WASM transport
+//! hold a `web_sys::WebSocket`.
+
+use std::future::Future;
+use std::time::Duration;
+
+/// Run `future` in the background
+#[cfg(not(target_arch = "wasm32"))]
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(future);
+}
+
+/// Run `future` in the background
+#[cfg(target_arch = "wasm32")]
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    wasm_bindgen_futures::spawn_local(future);
+}
+
+/// Wait for `duration` to pass
+pub async fn sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(duration).await;
+}
+
+/// Await `future` for at most `duration`, returning `None` if it ran out
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        tokio::time::timeout(duration, future).await.ok()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        use futures::future::{Either, select};
+
+        let future = std::pin::pin!(future);
+        let deadline = std::pin::pin!(sleep(duration));
+        match select(future, deadline).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        }
+    }
+}
+
+/// A periodic ticker whose first tick completes immediately
+///
+/// Ticks missed while the owner was busy are skipped rather than bunched up.
+pub struct Interval {
+    #[cfg(not(target_arch = "wasm32"))]
+    inner: tokio::time::Interval,
+    #[cfg(target_arch = "wasm32")]
+    period: Duration,
+    #[cfg(target_arch = "wasm32")]
+    started: bool,
+}
+
+impl Interval {
+    pub fn new(period: Duration) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut inner = tokio::time::interval(period);
+            inner.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            Self { inner }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            Self {
+                period,
+                started: false,
+            }
+        }
+    }
+
+    /// Wait for the next tick
+    pub async fn tick(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.inner.tick().await;
+
+        #[cfg(target_arch = "wasm32")]
+        if std::mem::replace(&mut self.started, true) {
+            sleep(self.period).await;
+        }
+    }
+}
+
+/// A monotonic-enough point in time
+///
+/// `std::time::Instant` panics on `wasm32-unknown-unknown`, so the browser build
+/// measures from `Date.now()` instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub type Instant = std::time::Instant;
+
+/// A monotonic-enough point in time
+///
+/// `std::time::Instant` panics on `wasm32-unknown-unknown`, so the browser build
+/// measures from `Date.now()` instead.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Instant(f64);
+
+#[cfg(target_arch = "wasm32")]
+impl Instant {
+    pub fn now() -> Self {
+        Self(js_sys::Date::now())
+    }
+
+    /// Time elapsed from `earlier` to `self`, zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_secs_f64(((self.0 - earlier.0) / 1000.0).max(0.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn timeout_reports_expiry() {
+        assert_eq!(timeout(Duration::from_secs(1), async { 7 }).await, Some(7));
+        assert_eq!(
+            timeout(Duration::from_millis(5), sleep(Duration::from_secs(5))).await,
+            None
+        );
+    }
+}
@@ -2,36 +2,54 @@
 
 use crate::{
     WebSocketTransport,
-    config::ClientConfig,
+    config::{AuthConfig, ClientConfig},
     error::{ClientError, ClientResult},
 };
 use async_trait::async_trait;
 use futures::channel::oneshot;
 use js_sys::Uint8Array;
-use ras_jsonrpc_bidirectional_types::BidirectionalMessage;
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, CloseReason, Codec, Frame};
 use std::{
     collections::VecDeque,
     sync::{Arc, Mutex},
 };
 use wasm_bindgen::{JsCast, JsValue, closure::Closure};
-use web_sys::{BinaryType, CloseEvent, ErrorEvent, MessageEvent, WebSocket};
+use web_sys::{BinaryType, CloseEvent, Event, MessageEvent, WebSocket};
 
 /// WASM WebSocket transport using web-sys
 pub struct WasmWebSocketTransport {
     config: ClientConfig,
     websocket: Arc<Mutex<Option<WebSocket>>>,
+    callbacks: Option<EventCallbacks>,
     message_queue: Arc<Mutex<VecDeque<BidirectionalMessage>>>,
     connection_state: Arc<Mutex<WasmConnectionState>>,
+    /// Codec the server accepted for the current connection
+    codec: Arc<Mutex<Codec>>,
     url: String,
 }
 
+// SAFETY: `wasm32-unknown-unknown` runs everything on the browser's single
+// thread, so the `WebSocket` and callbacks are never touched from another one.
+// The client needs `Send` transports because native builds spawn on tokio.
+unsafe impl Send for WasmWebSocketTransport {}
+unsafe impl Sync for WasmWebSocketTransport {}
+
+/// The socket's event handlers, kept alive for as long as the socket is
+struct EventCallbacks {
+    _onopen: Closure<dyn FnMut(JsValue)>,
+    _onmessage: Closure<dyn FnMut(MessageEvent)>,
+    _onerror: Closure<dyn FnMut(Event)>,
+    _onclose: Closure<dyn FnMut(CloseEvent)>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum WasmConnectionState {
     Disconnected,
     Connecting,
     Connected,
     Closing,
-    Closed,
+    /// Closed by the server, for the reason read from its close frame
+    Closed(CloseReason),
     Error(String),
 }
 
@@ -43,69 +61,109 @@ impl WasmWebSocketTransport {
         Self {
             config,
             websocket: Arc::new(Mutex::new(None)),
+            callbacks: None,
             message_queue: Arc::new(Mutex::new(VecDeque::new())),
             connection_state: Arc::new(Mutex::new(WasmConnectionState::Disconnected)),
+            codec: Arc::new(Mutex::new(Codec::Json)),
             url,
         }
     }
 
+    /// Codec the server accepted for the current connection
+    pub fn codec(&self) -> Codec {
+        *self.codec.lock().unwrap()
+    }
+
+    /// Open a socket, offering a header JWT as a `token.<jwt>` subprotocol
+    /// alongside the configured codec
+    ///
+    /// Browsers cannot set handshake headers; the server reads the token from
+    /// `Sec-WebSocket-Protocol` instead and selects the codec if it accepts it,
+    /// or else echoes the token protocol.
+    fn open(&self) -> ClientResult<WebSocket> {
+        let protocols = js_sys::Array::new();
+        if let Some(codec) = self.config.codec.subprotocol() {
+            protocols.push(&JsValue::from_str(codec));
+        }
+        if let AuthConfig::JwtHeader { token } = &self.config.auth {
+            protocols.push(&JsValue::from_str(&format!("token.{token}")));
+        }
+        let websocket = if protocols.length() == 0 {
+            WebSocket::new(&self.url)
+        } else {
+            WebSocket::new_with_str_sequence(&self.url, &protocols)
+        };
+        websocket
+            .map_err(|e| ClientError::javascript(format!("Failed to create WebSocket: {:?}", e)))
+    }
+
     /// Set up WebSocket event handlers
     fn setup_event_handlers(
         &self,
         websocket: &WebSocket,
         connect_tx: oneshot::Sender<ClientResult<()>>,
-    ) -> ClientResult<()> {
+    ) -> EventCallbacks {
         let message_queue = Arc::clone(&self.message_queue);
         let connection_state = Arc::clone(&self.connection_state);
+        let codec = Arc::clone(&self.codec);
         let connect_tx = Arc::new(Mutex::new(Some(connect_tx)));
 
         // Handle connection open
-        {
+        let onopen = {
             let connection_state = Arc::clone(&connection_state);
             let connect_tx = Arc::clone(&connect_tx);
+            let codec = Arc::clone(&codec);
+            let socket = websocket.clone();
             let onopen_callback = Closure::wrap(Box::new(move |_event: JsValue| {
+                // The protocol the server selected, empty when it selected none
+                *codec.lock().unwrap() =
+                    Codec::from_subprotocol(&socket.protocol()).unwrap_or_default();
                 *connection_state.lock().unwrap() = WasmConnectionState::Connected;
                 if let Some(tx) = connect_tx.lock().unwrap().take() {
                     let _ = tx.send(Ok(()));
                 }
             }) as Box<dyn FnMut(JsValue)>);
             websocket.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
-            onopen_callback.forget();
-        }
+            onopen_callback
+        };
 
         // Handle messages
-        {
+        let onmessage = {
             let message_queue = Arc::clone(&message_queue);
             let onmessage_callback = Closure::wrap(Box::new(move |event: MessageEvent| {
-                if let Ok(message) = Self::parse_message_event(&event) {
+                let codec = *codec.lock().unwrap();
+                if let Ok(message) = Self::parse_message_event(&event, codec) {
                     message_queue.lock().unwrap().push_back(message);
                 }
             }) as Box<dyn FnMut(MessageEvent)>);
             websocket.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
-            onmessage_callback.forget();
-        }
+            onmessage_callback
+        };
 
         // Handle errors
-        {
+        let onerror = {
             let connection_state = Arc::clone(&connection_state);
             let connect_tx = Arc::clone(&connect_tx);
-            let onerror_callback = Closure::wrap(Box::new(move |event: ErrorEvent| {
-                let error_msg = format!("WebSocket error: {}", event.message());
+            // Browsers fire a bare `Event` here and keep the cause to themselves
+            let onerror_callback = Closure::wrap(Box::new(move |_event: Event| {
+                let error_msg = "WebSocket error".to_string();
                 *connection_state.lock().unwrap() = WasmConnectionState::Error(error_msg.clone());
                 if let Some(tx) = connect_tx.lock().unwrap().take() {
                     let _ = tx.send(Err(ClientError::javascript(error_msg)));
                 }
-            }) as Box<dyn FnMut(ErrorEvent)>);
+            }) as Box<dyn FnMut(Event)>);
             websocket.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
-            onerror_callback.forget();
-        }
+            onerror_callback
+        };
 
         // Handle connection close
-        {
+        let onclose = {
             let connection_state = Arc::clone(&connection_state);
             let connect_tx = Arc::clone(&connect_tx);
             let onclose_callback = Closure::wrap(Box::new(move |event: CloseEvent| {
-                *connection_state.lock().unwrap() = WasmConnectionState::Closed;
+                let reason = CloseReason::from_close_frame(event.code(), &event.reason())
+                    .unwrap_or(CloseReason::ConnectionLost);
+                *connection_state.lock().unwrap() = WasmConnectionState::Closed(reason);
                 if let Some(tx) = connect_tx.lock().unwrap().take() {
                     let error_msg =
                         format!("Connection closed: {} - {}", event.code(), event.reason());
@@ -113,14 +171,22 @@ impl WasmWebSocketTransport {
                 }
             }) as Box<dyn FnMut(CloseEvent)>);
             websocket.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
-            onclose_callback.forget();
+            onclose_callback
+        };
+
+        EventCallbacks {
+            _onopen: onopen,
+            _onmessage: onmessage,
+            _onerror: onerror,
+            _onclose: onclose,
         }
-
-        Ok(())
     }
 
     /// Parse a WebSocket message event into a BidirectionalMessage
-    fn parse_message_event(event: &MessageEvent) -> ClientResult<BidirectionalMessage> {
+    fn parse_message_event(
+        event: &MessageEvent,
+        codec: Codec,
+    ) -> ClientResult<BidirectionalMessage> {
         let data = event.data();
 
         // Handle text messages
@@ -130,29 +196,25 @@ impl WasmWebSocketTransport {
             return Ok(message);
         }
 
-        // Handle binary messages (ArrayBuffer or Blob)
+        // Handle binary messages, which carry JSON unless a binary codec was negotiated
         if let Ok(array_buffer) = data.dyn_into::<js_sys::ArrayBuffer>() {
             let uint8_array = Uint8Array::new(&array_buffer);
             let bytes = uint8_array.to_vec();
-            let message: BidirectionalMessage =
-                serde_json::from_slice(&bytes).map_err(ClientError::Json)?;
-            return Ok(message);
+            return Ok(codec.decode(&bytes)?);
         }
 
         Err(ClientError::javascript("Unsupported message data type"))
     }
 
-    /// Send data to the WebSocket
-    fn send_data(&self, data: &[u8]) -> ClientResult<()> {
+    /// Send a frame to the WebSocket
+    fn send_frame(&self, frame: Frame) -> ClientResult<()> {
         let websocket_guard = self.websocket.lock().unwrap();
         if let Some(ref websocket) = *websocket_guard {
-            // Send as text (JSON-RPC is typically text-based)
-            let text = String::from_utf8(data.to_vec())
-                .map_err(|_| ClientError::javascript("Invalid UTF-8 data"))?;
-
-            websocket
-                .send_with_str(&text)
-                .map_err(|e| ClientError::javascript(format!("Failed to send message: {:?}", e)))?;
+            match frame {
+                Frame::Text(text) => websocket.send_with_str(&text),
+                Frame::Binary(bytes) => websocket.send_with_u8_array(&bytes),
+            }
+            .map_err(|e| ClientError::javascript(format!("Failed to send message: {:?}", e)))?;
 
             Ok(())
         } else {
@@ -177,21 +239,24 @@ impl WebSocketTransport for WasmWebSocketTransport {
 
         *self.connection_state.lock().unwrap() = WasmConnectionState::Connecting;
 
-        // Create WebSocket
-        let websocket = WebSocket::new(&self.url)
-            .map_err(|e| ClientError::javascript(format!("Failed to create WebSocket: {:?}", e)))?;
-
-        // Set binary type to arraybuffer for better binary message handling
-        websocket.set_binary_type(BinaryType::Arraybuffer);
-
         // Set up connection completion channel
         let (connect_tx, connect_rx) = oneshot::channel();
 
-        // Set up event handlers
-        self.setup_event_handlers(&websocket, connect_tx)?;
+        {
+            let websocket = match self.open() {
+                Ok(websocket) => websocket,
+                Err(e) => {
+                    *self.connection_state.lock().unwrap() = WasmConnectionState::Disconnected;
+                    return Err(e);
+                }
+            };
 
-        // Store the WebSocket
-        *self.websocket.lock().unwrap() = Some(websocket);
+            // Set binary type to arraybuffer for better binary message handling
+            websocket.set_binary_type(BinaryType::Arraybuffer);
+
+            self.callbacks = Some(self.setup_event_handlers(&websocket, connect_tx));
+            *self.websocket.lock().unwrap() = Some(websocket);
+        }
 
         // Wait for connection to complete or fail
         connect_rx
@@ -205,12 +270,19 @@ impl WebSocketTransport for WasmWebSocketTransport {
         if let Some(websocket) = websocket {
             *self.connection_state.lock().unwrap() = WasmConnectionState::Closing;
 
+            // A late close event from this socket must not mark its successor closed
+            websocket.set_onopen(None);
+            websocket.set_onmessage(None);
+            websocket.set_onerror(None);
+            websocket.set_onclose(None);
+
             // Close the WebSocket connection
             websocket.close().map_err(|e| {
                 ClientError::javascript(format!("Failed to close WebSocket: {:?}", e))
             })?;
         }
 
+        self.callbacks = None;
         *self.connection_state.lock().unwrap() = WasmConnectionState::Disconnected;
         self.message_queue.lock().unwrap().clear();
 
@@ -218,9 +290,9 @@ impl WebSocketTransport for WasmWebSocketTransport {
     }
 
     async fn send(&mut self, message: &BidirectionalMessage) -> ClientResult<()> {
-        let json = serde_json::to_string(message).map_err(ClientError::Json)?;
+        let frame = self.codec().encode(message)?;
 
-        self.send_data(json.as_bytes())
+        self.send_frame(frame)
     }
 
     async fn receive(&mut self) -> ClientResult<Option<BidirectionalMessage>> {
@@ -232,8 +304,8 @@ impl WebSocketTransport for WasmWebSocketTransport {
                 WasmConnectionState::Error(ref error) => {
                     return Err(ClientError::connection(error.clone()));
                 }
-                WasmConnectionState::Closed => {
-                    return Err(ClientError::connection("Connection closed"));
+                WasmConnectionState::Closed(ref reason) => {
+                    return Err(ClientError::Closed(reason.clone()));
                 }
                 _ => {
                     return Err(ClientError::NotConnected);
@@ -291,8 +363,9 @@ pub mod utils {
 mod tests {
     use super::*;
     use crate::config::ClientConfig;
+    use wasm_bindgen_test::wasm_bindgen_test;
 
-    #[test]
+    #[wasm_bindgen_test]
     fn test_wasm_transport_creation() {
         let config = ClientConfig::new("ws://localhost:8080/ws");
         let transport = WasmWebSocketTransport::new(config);
@@ -301,7 +374,7 @@ mod tests {
         assert!(!transport.is_connected());
     }
 
-    #[test]
+    #[wasm_bindgen_test]
     fn test_connection_state() {
         let config = ClientConfig::new("ws://localhost:8080/ws");
         let transport = WasmWebSocketTransport::new(config);
@@ -310,7 +383,7 @@ mod tests {
         assert_eq!(*state, WasmConnectionState::Disconnected);
     }
 
-    #[tokio::test]
+    #[wasm_bindgen_test]
     async fn test_disconnect_without_connection() {
         let config = ClientConfig::new("ws://localhost:8080/ws");
         let mut transport = WasmWebSocketTransport::new(config);
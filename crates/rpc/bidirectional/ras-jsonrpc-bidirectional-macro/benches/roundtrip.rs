@@ -50,6 +50,7 @@ impl BenchSvcService for BenchImpl {
         &self,
         _client: ConnectionId,
         _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         req: EchoIn,
     ) -> Result<EchoOut, Box<dyn std::error::Error + Send + Sync>> {
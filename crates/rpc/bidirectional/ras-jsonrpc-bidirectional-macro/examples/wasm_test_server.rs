@@ -0,0 +1,97 @@
+//! Server for the browser tests in `tests/wasm.rs`
+//!
+//! Start it before running them:
+//!
+//! ```text
+//! cargo run -p ras-jsonrpc-bidirectional-macro --example wasm_test_server
+//! ```
+//!
+//! It listens on `127.0.0.1:3999` unless `RAS_WASM_TEST_ADDR` says otherwise,
+//! and accepts the tokens `MockAuthProvider` knows, such as `user-token`.
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_auth_core::AuthenticatedUser;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId, ServerNotification};
+use ras_test_helpers::MockAuthProvider;
+
+// Keep in step with the definition in `tests/wasm.rs`
+jsonrpc_bidirectional_service!({
+    service_name: Bell,
+    client_to_server: [
+        WITH_PERMISSIONS(["user"]) echo(String) -> String,
+        WITH_PERMISSIONS(["user"]) ring(String) -> (),
+    ],
+    server_to_client: [
+        rung(String),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct BellImpl;
+
+#[async_trait]
+impl BellService for BellImpl {
+    async fn echo(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _user: &AuthenticatedUser,
+        text: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(text)
+    }
+
+    /// Answers the caller with a `rung` notification carrying `text`
+    async fn ring(
+        &self,
+        client: ConnectionId,
+        conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _user: &AuthenticatedUser,
+        text: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = BidirectionalMessage::ServerNotification(ServerNotification {
+            method: "rung".to_string(),
+            params: serde_json::json!(text),
+            metadata: None,
+        });
+        conns.send_to_connection(client, message).await?;
+        Ok(())
+    }
+
+    async fn notify_rung(
+        &self,
+        _connection_id: ConnectionId,
+        _params: String,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    BellHandler<BellImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+#[tokio::main]
+async fn main() {
+    let addr = std::env::var("RAS_WASM_TEST_ADDR").unwrap_or_else(|_| "127.0.0.1:3999".into());
+    let service: Service = BellBuilder::new(BellImpl, MockAuthProvider::default())
+        .require_auth(true)
+        .build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+
+    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+    println!("wasm test server listening on ws://{addr}/ws");
+    axum::serve(listener, app).await.unwrap();
+}
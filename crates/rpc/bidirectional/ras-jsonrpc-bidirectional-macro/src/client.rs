@@ -25,18 +25,26 @@ pub fn generate_client_code(
         quote::format_ident!("{}ClientToServerMessage", service_name);
     let server_to_client_notification_name =
         quote::format_ident!("{}ServerToClientNotification", service_name);
+    let topic_handlers_name = quote::format_ident!("{}TopicHandlers", service_name);
 
     // Generate client method implementations for client_to_server calls
     let client_methods = service_def.client_to_server.iter().map(|method| {
         let method_name = &method.name;
         let method_str = method_name.to_string();
+        let with_timeout_name = quote::format_ident!("{}_with_timeout", method_name);
         let request_type = &method.request_type;
         let response_type = &method.response_type;
 
         quote! {
             /// Call the #method_name method on the server
             pub async fn #method_name(&self, request: #request_type) -> ras_jsonrpc_bidirectional_client::error::ClientResult<#response_type> {
-                let response = self.client.call(#method_str, Some(serde_json::to_value(request)?)).await?;
+                self.#with_timeout_name(request, self.client.config().request_timeout).await
+            }
+
+            /// Call the #method_name method on the server, waiting up to `timeout` for
+            /// the response
+            pub async fn #with_timeout_name(&self, request: #request_type, timeout: std::time::Duration) -> ras_jsonrpc_bidirectional_client::error::ClientResult<#response_type> {
+                let response = self.client.call_with_timeout(#method_str, Some(serde_json::to_value(request)?), timeout).await?;
                 match response.result {
                     Some(result) => {
                         Ok(serde_json::from_value(result)?)
@@ -46,7 +54,8 @@ pub fn generate_client_code(
                         if let Some(error) = response.error {
                             Err(ras_jsonrpc_bidirectional_client::ClientError::internal(format!("JSON-RPC error: {}", error.message)))
                         } else {
-                            Err(ras_jsonrpc_bidirectional_client::ClientError::internal("Response has no result or error"))
+                            // A `null` result, such as a unit response, reads back as missing
+                            Ok(serde_json::from_value(serde_json::Value::Null)?)
                         }
                     }
                 }
@@ -82,6 +91,61 @@ pub fn generate_client_code(
         }
     });
 
+    // Generate filter registration methods for notifications declared with a filter type
+    let filter_methods = service_def.server_to_client.iter().filter_map(|notification| {
+        let filter_type = notification.filter_type.as_ref()?;
+        let notification_name = &notification.name;
+        let set_name = quote::format_ident!("set_{}_filter", notification_name);
+        let clear_name = quote::format_ident!("clear_{}_filter", notification_name);
+        let notification_str = notification_name.to_string();
+
+        let set_doc = format!(
+            "Receive the `{notification_name}` notifications the server sends to filters matching `filter`, replacing the previous filter; it is registered again after a reconnect"
+        );
+        let clear_doc = format!(
+            "Remove the `{notification_name}` filter, no longer receiving the notifications sent to matching filters"
+        );
+
+        Some(quote! {
+            #[doc = #set_doc]
+            pub async fn #set_name(&self, filter: #filter_type) -> ras_jsonrpc_bidirectional_client::error::ClientResult<()> {
+                self.client.set_notification_filter(#notification_str, Some(serde_json::to_value(filter)?)).await
+            }
+
+            #[doc = #clear_doc]
+            pub async fn #clear_name(&self) -> ras_jsonrpc_bidirectional_client::error::ClientResult<()> {
+                self.client.set_notification_filter(#notification_str, None).await
+            }
+        })
+    });
+
+    // Generate typed handler registration methods scoped to a topic subscription
+    let topic_notification_handlers = service_def.server_to_client.iter().map(|notification| {
+        let notification_name = &notification.name;
+        let params_type = &notification.params_type;
+        let handler_method_name = quote::format_ident!("on_{}", notification_name);
+        let notification_str = notification_name.to_string();
+
+        quote! {
+            /// Handle #notification_name notifications broadcast to the topic
+            pub fn #handler_method_name<F>(mut self, handler: F) -> Self
+            where
+                F: Fn(#params_type) + Send + Sync + 'static,
+            {
+                let handler = std::sync::Arc::new(move |params: &serde_json::Value| {
+                    match serde_json::from_value::<#params_type>(params.clone()) {
+                        Ok(typed_params) => handler(typed_params),
+                        Err(e) => {
+                            eprintln!("Failed to deserialize notification parameters: {}", e);
+                        }
+                    }
+                });
+                self.handlers.insert(#notification_str, handler);
+                self
+            }
+        }
+    });
+
     // Generate RPC handler registration methods for server_to_client calls
     let rpc_handlers = service_def.server_to_client_calls.iter().map(|method| {
         let method_name = &method.name;
@@ -90,15 +154,20 @@ pub fn generate_client_code(
         let handler_method_name = quote::format_ident!("on_{}", method_name);
         let method_str = method_name.to_string();
 
+        let doc = format!(
+            "Answer the server's `{method_name}` calls with `handler`; an `Err` is returned to the server as a JSON-RPC error"
+        );
+
         quote! {
-            /// Register a handler for #method_name RPC calls from the server
+            #[doc = #doc]
             pub fn #handler_method_name<F, Fut>(&mut self, handler: F)
             where
                 F: Fn(#request_type) -> Fut + Send + Sync + 'static,
                 Fut: std::future::Future<Output = Result<#response_type, String>> + Send + 'static,
             {
+                let callback = std::sync::Arc::new(handler);
                 let handler = std::sync::Arc::new(move |request: ras_jsonrpc_types::JsonRpcRequest| {
-                    let handler = handler.clone();
+                    let callback = callback.clone();
                     Box::pin(async move {
                         // Parse request parameters
                         let params: #request_type = if let Some(params) = request.params {
@@ -124,7 +193,7 @@ pub fn generate_client_code(
                         };
 
                         // Call handler
-                        match handler(params).await {
+                        match callback(params).await {
                             Ok(result) => {
                                 match serde_json::to_value(result) {
                                     Ok(result_value) => ras_jsonrpc_types::JsonRpcResponse::success(result_value, request.id),
@@ -158,7 +227,7 @@ pub fn generate_client_code(
         quote! {
             #variant_name {
                 request: #request_type,
-                response_sender: tokio::sync::oneshot::Sender<Result<#response_type, ras_jsonrpc_bidirectional_client::ClientError>>,
+                response_sender: ras_jsonrpc_bidirectional_client::tokio::sync::oneshot::Sender<Result<#response_type, ras_jsonrpc_bidirectional_client::ClientError>>,
             },
         }
     });
@@ -200,7 +269,82 @@ pub fn generate_client_code(
 
             #(#client_methods)*
 
+            /// Register a handler for the server's shutdown notice, sent before it
+            /// closes the connection at the notice's deadline
+            pub fn on_server_shutdown<F>(&mut self, handler: F)
+            where
+                F: Fn(ras_jsonrpc_bidirectional_client::ShutdownNotice) + Send + Sync + 'static,
+            {
+                self.client.on_server_shutdown(handler);
+            }
+
+            /// Re-authenticate the connection with a new bearer token, returning the
+            /// user the server now associates with it
+            pub async fn refresh_session(&self, token: String) -> ras_jsonrpc_bidirectional_client::error::ClientResult<ras_jsonrpc_bidirectional_client::AuthenticatedUser> {
+                self.client.refresh_session(token).await
+            }
+
+            /// Register a handler for the server's warning that the session's token
+            /// was rejected; refresh the session before its deadline to stay connected
+            pub fn on_session_expiring<F>(&mut self, handler: F)
+            where
+                F: Fn(ras_jsonrpc_bidirectional_client::SessionExpiring) + Send + Sync + 'static,
+            {
+                self.client.on_session_expiring(handler);
+            }
+
+            /// Register a handler for the connection closing, told why it closed, such
+            /// as a session that expired or a server shutting down
+            pub fn on_disconnected<F>(&mut self, handler: F)
+            where
+                F: Fn(ras_jsonrpc_bidirectional_client::CloseReason) + Send + Sync + 'static,
+            {
+                self.client.on_disconnected(handler);
+            }
+
+            /// Register a handler, under `name`, for every change in the connection's
+            /// status; a handler registered under the same name is replaced
+            pub fn on_connection_event<F>(&mut self, name: &str, handler: F)
+            where
+                F: Fn(ras_jsonrpc_bidirectional_client::ConnectionEvent) + Send + Sync + 'static,
+            {
+                self.client.on_connection_event(name, std::sync::Arc::new(handler));
+            }
+
+            /// Become present under `key`, telling its other members; closing the
+            /// connection leaves every key
+            pub async fn join_presence(&self, key: &str, metadata: Option<serde_json::Value>) -> ras_jsonrpc_bidirectional_client::error::ClientResult<ras_jsonrpc_bidirectional_client::PresenceEntry> {
+                self.client.join_presence(key, metadata).await
+            }
+
+            /// Withdraw the connection's presence under `key`, returning whether it was present
+            pub async fn leave_presence(&self, key: &str) -> ras_jsonrpc_bidirectional_client::error::ClientResult<bool> {
+                self.client.leave_presence(key).await
+            }
+
+            /// Every connection present under `key`, oldest first
+            pub async fn list_presence(&self, key: &str) -> ras_jsonrpc_bidirectional_client::error::ClientResult<Vec<ras_jsonrpc_bidirectional_client::PresenceEntry>> {
+                self.client.list_presence(key).await
+            }
+
+            /// Register a handler for users becoming present under a key this client is present in
+            pub fn on_presence_joined<F>(&mut self, handler: F)
+            where
+                F: Fn(ras_jsonrpc_bidirectional_client::PresenceEvent) + Send + Sync + 'static,
+            {
+                self.client.on_presence_joined(handler);
+            }
+
+            /// Register a handler for users leaving a key this client is present in
+            pub fn on_presence_left<F>(&mut self, handler: F)
+            where
+                F: Fn(ras_jsonrpc_bidirectional_client::PresenceEvent) + Send + Sync + 'static,
+            {
+                self.client.on_presence_left(handler);
+            }
+
             #(#notification_handlers)*
+            #(#filter_methods)*
 
             #(#rpc_handlers)*
 
@@ -228,6 +372,57 @@ pub fn generate_client_code(
             pub async fn unsubscribe(&self, topic: &str) -> ras_jsonrpc_bidirectional_client::error::ClientResult<()> {
                 self.client.unsubscribe(topic).await
             }
+
+            /// Subscribe to a topic (room), dispatching the notifications broadcast to it
+            /// to `handlers`; the subscription is renewed when the client reconnects
+            pub async fn subscribe_topic(&self, topic: &str, handlers: #topic_handlers_name) -> ras_jsonrpc_bidirectional_client::error::ClientResult<()> {
+                self.client.subscribe(topic, handlers.into_handler()).await
+            }
+
+            /// Subscribe to a topic again after its broadcast `last_seq`, dispatching the
+            /// broadcasts missed since then and the ones that follow to `handlers`
+            ///
+            /// The result reports a `gap` when the server no longer kept every missed
+            /// broadcast. Topics whose broadcasts are numbered are resumed like this
+            /// automatically on reconnect.
+            pub async fn resume_topic(&self, topic: &str, last_seq: u64, handlers: #topic_handlers_name) -> ras_jsonrpc_bidirectional_client::error::ClientResult<ras_jsonrpc_bidirectional_client::TopicResumed> {
+                self.client.resume(topic, last_seq, handlers.into_handler()).await
+            }
+
+            /// Sequence number of the last numbered broadcast received on `topic`
+            pub fn last_seq(&self, topic: &str) -> Option<u64> {
+                self.client.last_seq(topic)
+            }
+
+            /// Unsubscribe from a topic subscribed with `subscribe_topic`
+            pub async fn unsubscribe_topic(&self, topic: &str) -> ras_jsonrpc_bidirectional_client::error::ClientResult<()> {
+                self.client.unsubscribe(topic).await
+            }
+        }
+
+        #[cfg(feature = "client")]
+        /// Typed notification handlers for one topic subscription
+        #[derive(Default, Clone)]
+        pub struct #topic_handlers_name {
+            handlers: std::collections::HashMap<&'static str, std::sync::Arc<dyn Fn(&serde_json::Value) + Send + Sync>>,
+        }
+
+        #[cfg(feature = "client")]
+        impl #topic_handlers_name {
+            /// Create an empty set of handlers
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            #(#topic_notification_handlers)*
+
+            fn into_handler(self) -> ras_jsonrpc_bidirectional_client::NotificationHandler {
+                std::sync::Arc::new(move |method: &str, params: &serde_json::Value| {
+                    if let Some(handler) = self.handlers.get(method) {
+                        handler(params);
+                    }
+                })
+            }
         }
 
         #[cfg(feature = "client")]
@@ -236,6 +431,12 @@ pub fn generate_client_code(
             url: String,
             jwt_token: Option<String>,
             timeout: Option<std::time::Duration>,
+            cancel_on_timeout: Option<bool>,
+            reconnect: Option<ras_jsonrpc_bidirectional_client::ReconnectConfig>,
+            heartbeat_interval: Option<Option<std::time::Duration>>,
+            max_missed_heartbeats: Option<u32>,
+            on_reconnect: Option<std::sync::Arc<dyn Fn(u32) + Send + Sync>>,
+            codec: ras_jsonrpc_bidirectional_client::Codec,
         }
 
         #[cfg(feature = "client")]
@@ -246,6 +447,12 @@ pub fn generate_client_code(
                     url: url.into(),
                     jwt_token: None,
                     timeout: None,
+                    cancel_on_timeout: None,
+                    reconnect: None,
+                    heartbeat_interval: None,
+                    max_missed_heartbeats: None,
+                    on_reconnect: None,
+                    codec: ras_jsonrpc_bidirectional_client::Codec::Json,
                 }
             }
 
@@ -261,9 +468,53 @@ pub fn generate_client_code(
                 self
             }
 
+            /// Set whether calls that time out are cancelled on the server, stopping
+            /// their handlers (on by default)
+            pub fn with_cancel_on_timeout(mut self, cancel: bool) -> Self {
+                self.cancel_on_timeout = Some(cancel);
+                self
+            }
+
+            /// Set the reconnect policy: attempts, backoff with jitter, and whether calls
+            /// made while reconnecting are buffered or fail
+            pub fn with_reconnect_config(mut self, config: ras_jsonrpc_bidirectional_client::ReconnectConfig) -> Self {
+                self.reconnect = Some(config);
+                self
+            }
+
+            /// Set how often the client pings the server (`None` disables heartbeats)
+            pub fn with_heartbeat_interval(mut self, interval: Option<std::time::Duration>) -> Self {
+                self.heartbeat_interval = Some(interval);
+                self
+            }
+
+            /// Set how many heartbeats may go unanswered before the connection is
+            /// considered lost and the reconnect policy applies
+            pub fn with_max_missed_heartbeats(mut self, max_missed: u32) -> Self {
+                self.max_missed_heartbeats = Some(max_missed);
+                self
+            }
+
+            /// Call `handler` with the number of attempts each time the client reconnects
+            pub fn with_on_reconnect<F>(mut self, handler: F) -> Self
+            where
+                F: Fn(u32) + Send + Sync + 'static,
+            {
+                self.on_reconnect = Some(std::sync::Arc::new(handler));
+                self
+            }
+
+            /// Offer a binary codec, used for every frame if the server accepts it;
+            /// servers that do not keep the connection on JSON text
+            pub fn with_codec(mut self, codec: ras_jsonrpc_bidirectional_client::Codec) -> Self {
+                self.codec = codec;
+                self
+            }
+
             /// Build the client
             pub async fn build(self) -> ras_jsonrpc_bidirectional_client::error::ClientResult<#client_name> {
-                let mut builder = ras_jsonrpc_bidirectional_client::ClientBuilder::new(&self.url);
+                let mut builder = ras_jsonrpc_bidirectional_client::ClientBuilder::new(&self.url)
+                    .with_codec(self.codec);
 
                 if let Some(token) = self.jwt_token {
                     builder = builder.with_jwt_token(token);
@@ -273,6 +524,26 @@ pub fn generate_client_code(
                     builder = builder.with_request_timeout(timeout);
                 }
 
+                if let Some(cancel) = self.cancel_on_timeout {
+                    builder = builder.with_cancel_on_timeout(cancel);
+                }
+
+                if let Some(config) = self.reconnect {
+                    builder = builder.with_reconnect_config(config);
+                }
+
+                if let Some(interval) = self.heartbeat_interval {
+                    builder = builder.with_heartbeat_interval(interval);
+                }
+
+                if let Some(max_missed) = self.max_missed_heartbeats {
+                    builder = builder.with_max_missed_heartbeats(max_missed);
+                }
+
+                if let Some(on_reconnect) = self.on_reconnect {
+                    builder = builder.with_on_reconnect(move |attempts| on_reconnect(attempts));
+                }
+
                 let client = builder.build().await?;
                 Ok(#client_name::new(client))
             }
@@ -4,6 +4,7 @@ use syn::{Ident, LitStr, Token, Type, parse::Parse, parse_macro_input};
 
 mod client;
 mod server;
+mod validate;
 
 #[cfg(test)]
 mod tests;
@@ -47,6 +48,9 @@ struct MethodDefinition {
 struct NotificationDefinition {
     name: Ident,
     params_type: Type,
+    /// Type of the filters connections register for the notification, if it
+    /// is declared with `filter FilterType`
+    filter_type: Option<Type>,
 }
 
 #[derive(Debug)]
@@ -116,7 +120,15 @@ impl Parse for BidirectionalServiceDefinition {
 
         let mut server_to_client_calls = Vec::new();
         while !server_to_client_calls_content.is_empty() {
-            let method = server_to_client_calls_content.parse::<MethodDefinition>()?;
+            // Calls are answered by the client, so the auth requirement is optional
+            let method = if MethodDefinition::peek_auth(&server_to_client_calls_content) {
+                server_to_client_calls_content.parse::<MethodDefinition>()?
+            } else {
+                MethodDefinition::parse_signature(
+                    &server_to_client_calls_content,
+                    AuthRequirement::Unauthorized,
+                )?
+            };
             server_to_client_calls.push(method);
 
             // Handle optional trailing comma
@@ -125,12 +137,15 @@ impl Parse for BidirectionalServiceDefinition {
             }
         }
 
-        Ok(BidirectionalServiceDefinition {
+        let service = BidirectionalServiceDefinition {
             service_name,
             client_to_server,
             server_to_client,
             server_to_client_calls,
-        })
+        };
+        validate::validate_names(&service)?;
+
+        Ok(service)
     }
 }
 
@@ -198,6 +213,21 @@ impl Parse for MethodDefinition {
             ));
         };
 
+        Self::parse_signature(input, auth)
+    }
+}
+
+impl MethodDefinition {
+    /// Whether the input starts with an auth requirement
+    fn peek_auth(input: syn::parse::ParseStream) -> bool {
+        input
+            .fork()
+            .parse::<Ident>()
+            .is_ok_and(|ident| ident == "UNAUTHORIZED" || ident == "WITH_PERMISSIONS")
+    }
+
+    /// Parse `name(RequestType) -> ResponseType`
+    fn parse_signature(input: syn::parse::ParseStream, auth: AuthRequirement) -> syn::Result<Self> {
         // Parse method name
         let name = input.parse::<Ident>()?;
 
@@ -229,7 +259,22 @@ impl Parse for NotificationDefinition {
         syn::parenthesized!(params_content in input);
         let params_type = params_content.parse::<Type>()?;
 
-        Ok(NotificationDefinition { name, params_type })
+        // Parse optional `filter FilterType`
+        let filter_type = if input.peek(Ident) {
+            let keyword = input.parse::<Ident>()?;
+            if keyword != "filter" {
+                return Err(syn::Error::new(keyword.span(), "Expected `filter` or `,`"));
+            }
+            Some(input.parse::<Type>()?)
+        } else {
+            None
+        };
+
+        Ok(NotificationDefinition {
+            name,
+            params_type,
+            filter_type,
+        })
     }
 }
 
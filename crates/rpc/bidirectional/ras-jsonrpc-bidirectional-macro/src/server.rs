@@ -10,6 +10,7 @@ pub fn generate_server_code(
     let service_trait_name = quote::format_ident!("{}Service", service_name);
     let handler_name = quote::format_ident!("{}Handler", service_name);
     let builder_name = quote::format_ident!("{}Builder", service_name);
+    let topic_manager_name = quote::format_ident!("{}TopicManager", service_name);
 
     // Generate a client manager trait that provides access to typed client handles
     let _client_manager_trait_name = quote::format_ident!("{}ClientManager", service_name);
@@ -23,12 +24,12 @@ pub fn generate_server_code(
         match &method.auth {
             AuthRequirement::Unauthorized => {
                 quote! {
-                    async fn #method_name(&self, client_id: ras_jsonrpc_bidirectional_types::ConnectionId, connection_manager: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager, request: #request_type) -> Result<#response_type, Box<dyn std::error::Error + Send + Sync>>;
+                    async fn #method_name(&self, client_id: ras_jsonrpc_bidirectional_types::ConnectionId, connection_manager: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager, ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext, request: #request_type) -> Result<#response_type, Box<dyn std::error::Error + Send + Sync>>;
                 }
             }
             AuthRequirement::WithPermissions(_) => {
                 quote! {
-                    async fn #method_name(&self, client_id: ras_jsonrpc_bidirectional_types::ConnectionId, connection_manager: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager, user: &ras_auth_core::AuthenticatedUser, request: #request_type) -> Result<#response_type, Box<dyn std::error::Error + Send + Sync>>;
+                    async fn #method_name(&self, client_id: ras_jsonrpc_bidirectional_types::ConnectionId, connection_manager: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager, ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext, user: &ras_auth_core::AuthenticatedUser, request: #request_type) -> Result<#response_type, Box<dyn std::error::Error + Send + Sync>>;
                 }
             }
         }
@@ -70,7 +71,8 @@ pub fn generate_server_code(
                         };
 
                         // Call handler with client ID and connection manager reference
-                        match self.service.#method_name(context.id, self.connection_manager.as_ref(), params).await {
+                        let call = self.service.#method_name(context.id, self.connection_manager.as_ref(), &context, params);
+                        match ras_auth_core::scope_user(context.get_user().await, call).await {
                             Ok(result) => {
                                 let result_value = serde_json::to_value(result)
                                     .map_err(|e| ras_jsonrpc_bidirectional_server::ServerError::Internal(e.to_string()))?;
@@ -101,41 +103,18 @@ pub fn generate_server_code(
 
                         // Check permissions - AND within groups, OR between groups
                         let required_permission_groups: Vec<Vec<String>> = #permission_groups_code;
-                        // Only check permissions if we have non-empty groups
-                        let has_non_empty_groups = required_permission_groups.iter().any(|g| !g.is_empty());
-                        if has_non_empty_groups {
-                            let mut has_permission = false;
-
-                            // Check each permission group (OR logic between groups)
-                            for permission_group in &required_permission_groups {
-                                // Check if user has ALL permissions in this group (AND logic within group)
-                                if permission_group.is_empty() {
-                                    // Empty group means any authenticated user can access
-                                    has_permission = true;
-                                    break;
-                                } else {
-                                    // Check if user has all permissions in this group
-                                    let group_satisfied = permission_group.iter()
-                                        .all(|perm| user.permissions.contains(perm));
-                                    if group_satisfied {
-                                        has_permission = true;
-                                        break;
-                                    }
-                                }
-                            }
-
-                            if !has_permission {
-                                // Find the first non-empty group for error reporting
-                                let first_group = required_permission_groups.iter()
-                                    .find(|g| !g.is_empty())
-                                    .cloned()
-                                    .unwrap_or_default();
-                                let error_response = ras_jsonrpc_types::JsonRpcResponse::error(
-                                    ras_jsonrpc_types::JsonRpcError::new(-32002, "Insufficient permissions".to_string(), None),
-                                    request.id.clone()
-                                );
-                                return Ok(Some(error_response));
-                            }
+                        let permitted = |user: &ras_auth_core::AuthenticatedUser| {
+                            required_permission_groups.is_empty()
+                                || required_permission_groups.iter().any(|group| {
+                                    group.iter().all(|perm| user.permissions.contains(perm))
+                                })
+                        };
+                        let insufficient_permissions = || ras_jsonrpc_types::JsonRpcResponse::error(
+                            ras_jsonrpc_types::JsonRpcError::new(-32002, "Insufficient permissions".to_string(), None),
+                            request.id.clone()
+                        );
+                        if !permitted(&user) {
+                            return Ok(Some(insufficient_permissions()));
                         }
 
                         // Parse parameters
@@ -148,14 +127,18 @@ pub fn generate_server_code(
                                 .map_err(|e| ras_jsonrpc_bidirectional_server::ServerError::InvalidRequest(format!("Invalid params: {}", e)))?
                         };
 
-                        // Call handler with client ID, connection manager reference, and user
-                        match self.service.#method_name(context.id, self.connection_manager.as_ref(), &user, params).await {
-                            Ok(result) => {
+                        // Call handler with client ID, connection manager reference, and user,
+                        // failing the call if a session refresh takes the permissions away
+                        let call = self.service.#method_name(context.id, self.connection_manager.as_ref(), &context, &user, params);
+                        let call = ras_auth_core::scope_user(Some(user.clone()), call);
+                        match context.while_permitted(&permitted, call).await {
+                            Some(Ok(result)) => {
                                 let result_value = serde_json::to_value(result)
                                     .map_err(|e| ras_jsonrpc_bidirectional_server::ServerError::Internal(e.to_string()))?;
                                 Ok(Some(ras_jsonrpc_types::JsonRpcResponse::success(result_value, request.id.clone())))
                             }
-                            Err(e) => Err(ras_jsonrpc_bidirectional_server::ServerError::Internal(e.to_string())),
+                            Some(Err(e)) => Err(ras_jsonrpc_bidirectional_server::ServerError::Internal(e.to_string())),
+                            None => Ok(Some(insufficient_permissions())),
                         }
                     }
                 }
@@ -185,6 +168,62 @@ pub fn generate_server_code(
         }
     });
 
+    // Generate filtered sends for notifications declared with a filter type
+    let filtered_notification_methods = service_def.server_to_client.iter().filter_map(|notification| {
+        let filter_type = notification.filter_type.as_ref()?;
+        let notification_name = &notification.name;
+        let params_type = &notification.params_type;
+        let method_name = quote::format_ident!("notify_{}_where", notification_name);
+        let notification_str = notification_name.to_string();
+
+        let doc = format!(
+            "Send a `{notification_name}` notification to every connection whose registered filter `matches` accepts, returning how many received it; connections without a filter receive none"
+        );
+
+        Some(quote! {
+            #[doc = #doc]
+            pub async fn #method_name<F>(&self, matches: F, params: #params_type) -> ras_jsonrpc_bidirectional_types::Result<usize>
+            where
+                F: Fn(&#filter_type) -> bool + Send + Sync,
+            {
+                let notification = ras_jsonrpc_bidirectional_types::ServerNotification {
+                    method: #notification_str.to_string(),
+                    params: serde_json::to_value(params)
+                        .map_err(ras_jsonrpc_bidirectional_types::BidirectionalError::from)?,
+                    metadata: None,
+                };
+                let message = ras_jsonrpc_bidirectional_types::BidirectionalMessage::ServerNotification(notification);
+
+                let mut sent = 0;
+                for (connection_id, filter) in self.connection_manager.notification_filters(#notification_str).await? {
+                    // Filters were checked when registered, but may predate a change of the type
+                    let Ok(filter) = serde_json::from_value::<#filter_type>(filter) else {
+                        continue;
+                    };
+                    if matches(&filter) && self.connection_manager.send_to_connection(connection_id, message.clone()).await.is_ok() {
+                        sent += 1;
+                    }
+                }
+                Ok(sent)
+            }
+        })
+    });
+
+    // Check filters registered by clients against the notification's filter type
+    let filter_checks = service_def
+        .server_to_client
+        .iter()
+        .filter_map(|notification| {
+            let filter_type = notification.filter_type.as_ref()?;
+            let notification_str = notification.name.to_string();
+
+            Some(quote! {
+                #notification_str => serde_json::from_value::<#filter_type>(filter.clone())
+                    .map(|_| ())
+                    .map_err(|e| format!("Invalid filter for {}: {}", notification, e)),
+            })
+        });
+
     // Generate typed client handle for server-side management
     let client_handle_name = quote::format_ident!("{}ClientHandle", service_name);
 
@@ -219,8 +258,14 @@ pub fn generate_server_code(
         let method_str = method_name.to_string();
 
         quote! {
-            /// Make an RPC call to this specific client and wait for response
+            /// Make an RPC call to this specific client and wait for its response, up to the
+            /// handle's call timeout
             pub async fn #method_name(&self, params: #request_type) -> Result<#response_type, ras_jsonrpc_bidirectional_types::BidirectionalError> {
+                // Sending to a closed connection is a no-op, so fail fast instead of timing out
+                if self.connection_manager.get_connection(self.client_id).await?.is_none() {
+                    return Err(ras_jsonrpc_bidirectional_types::BidirectionalError::ConnectionNotFound(self.client_id));
+                }
+
                 // Generate request ID
                 let request_id = serde_json::Value::String(uuid::Uuid::new_v4().to_string());
 
@@ -237,7 +282,7 @@ pub fn generate_server_code(
                 let (response_sender, response_receiver) = tokio::sync::oneshot::channel();
 
                 // Register pending request with connection manager
-                if let Err(_) = self.connection_manager.register_pending_request(self.client_id, request_id.clone(), response_sender).await {
+                if self.connection_manager.register_pending_request(self.client_id, request_id.clone(), response_sender).await.is_err() {
                     return Err(ras_jsonrpc_bidirectional_types::BidirectionalError::ConnectionError("Failed to register pending request".to_string()));
                 }
 
@@ -250,11 +295,13 @@ pub fn generate_server_code(
                 }
 
                 // Wait for response with timeout
-                let response = tokio::time::timeout(
-                    std::time::Duration::from_secs(30), // TODO: Make configurable
-                    response_receiver
-                ).await
-                .map_err(|_| ras_jsonrpc_bidirectional_types::BidirectionalError::Timeout)?
+                let response = match tokio::time::timeout(self.call_timeout, response_receiver).await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        let _ = self.connection_manager.remove_pending_request(self.client_id, &request_id).await;
+                        return Err(ras_jsonrpc_bidirectional_types::BidirectionalError::Timeout);
+                    }
+                }
                 .map_err(|_| ras_jsonrpc_bidirectional_types::BidirectionalError::ConnectionError("Response channel closed".to_string()))?;
 
                 // Check for error response
@@ -272,19 +319,125 @@ pub fn generate_server_code(
         }
     });
 
+    // Generate server-to-client call methods addressed by connection ID on the handler
+    let handler_call_methods = service_def.server_to_client_calls.iter().map(|method| {
+        let method_name = &method.name;
+        let call_method_name = quote::format_ident!("call_{}", method_name);
+        let request_type = &method.request_type;
+        let response_type = &method.response_type;
+
+        let doc = format!(
+            "Call `{method_name}` on a connected client and wait for its response, up to the handler's call timeout"
+        );
+
+        quote! {
+            #[doc = #doc]
+            pub async fn #call_method_name(&self, connection_id: ras_jsonrpc_bidirectional_types::ConnectionId, params: #request_type) -> Result<#response_type, ras_jsonrpc_bidirectional_types::BidirectionalError> {
+                #client_handle_name::new(connection_id, self.connection_manager.as_ref())
+                    .with_call_timeout(self.call_timeout)
+                    .#method_name(params)
+                    .await
+            }
+        }
+    });
+
+    // Generate typed topic broadcast methods for the topic manager
+    let topic_broadcast_methods = service_def.server_to_client.iter().map(|notification| {
+        let notification_name = &notification.name;
+        let params_type = &notification.params_type;
+        let method_name = quote::format_ident!("broadcast_{}", notification_name);
+        let notification_str = notification_name.to_string();
+
+        quote! {
+            /// Send a notification to every connection subscribed to `topic`, returning how many received it
+            pub async fn #method_name(&self, topic: &str, params: #params_type) -> ras_jsonrpc_bidirectional_types::Result<usize> {
+                let notification = ras_jsonrpc_bidirectional_types::ServerNotification {
+                    method: #notification_str.to_string(),
+                    params: serde_json::to_value(params)
+                        .map_err(ras_jsonrpc_bidirectional_types::BidirectionalError::from)?,
+                    metadata: None,
+                };
+                self.broadcast_to_topic(topic, notification).await
+            }
+        }
+    });
+
     quote! {
+        #[cfg(feature = "server")]
+        /// Topic (room) subscriptions and broadcasts for the service's connections
+        ///
+        /// Subscriptions live in the connection manager and are removed when the
+        /// connection closes.
+        pub struct #topic_manager_name<'a> {
+            connection_manager: &'a dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        }
+
+        #[cfg(feature = "server")]
+        impl<'a> #topic_manager_name<'a> {
+            /// Create a topic manager over a connection manager
+            pub fn new(connection_manager: &'a dyn ras_jsonrpc_bidirectional_types::ConnectionManager) -> Self {
+                Self { connection_manager }
+            }
+
+            /// Subscribe a connection to a topic
+            pub async fn subscribe(&self, connection_id: ras_jsonrpc_bidirectional_types::ConnectionId, topic: impl Into<String>) -> ras_jsonrpc_bidirectional_types::Result<()> {
+                self.connection_manager.add_subscription(connection_id, topic.into()).await
+            }
+
+            /// Unsubscribe a connection from a topic
+            pub async fn unsubscribe(&self, connection_id: ras_jsonrpc_bidirectional_types::ConnectionId, topic: &str) -> ras_jsonrpc_bidirectional_types::Result<()> {
+                self.connection_manager.remove_subscription(connection_id, topic).await
+            }
+
+            /// Topics a connection is subscribed to
+            pub async fn subscriptions(&self, connection_id: ras_jsonrpc_bidirectional_types::ConnectionId) -> ras_jsonrpc_bidirectional_types::Result<Vec<String>> {
+                self.connection_manager.get_subscriptions(connection_id).await
+            }
+
+            /// Connections subscribed to a topic
+            pub async fn subscribers(&self, topic: &str) -> ras_jsonrpc_bidirectional_types::Result<Vec<ras_jsonrpc_bidirectional_types::ConnectionId>> {
+                Ok(self.connection_manager.get_subscribed_connections(topic).await?
+                    .into_iter()
+                    .map(|info| info.id)
+                    .collect())
+            }
+
+            /// Send a notification to every connection subscribed to `topic`, returning how many received it
+            pub async fn broadcast_to_topic(&self, topic: &str, notification: ras_jsonrpc_bidirectional_types::ServerNotification) -> ras_jsonrpc_bidirectional_types::Result<usize> {
+                let message = ras_jsonrpc_bidirectional_types::BidirectionalMessage::Broadcast(
+                    ras_jsonrpc_bidirectional_types::BroadcastMessage {
+                        topic: topic.to_string(),
+                        method: notification.method,
+                        params: notification.params,
+                        metadata: notification.metadata,
+                        seq: None,
+                    },
+                );
+                self.connection_manager.broadcast_to_topic(topic, message).await
+            }
+
+            #(#topic_broadcast_methods)*
+        }
+
         #[cfg(feature = "server")]
         /// Typed client handle for server-side client management
         pub struct #client_handle_name<'a> {
             client_id: ras_jsonrpc_bidirectional_types::ConnectionId,
             connection_manager: &'a dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+            call_timeout: std::time::Duration,
         }
 
         #[cfg(feature = "server")]
         impl<'a> #client_handle_name<'a> {
             /// Create a new client handle
             pub fn new(client_id: ras_jsonrpc_bidirectional_types::ConnectionId, connection_manager: &'a dyn ras_jsonrpc_bidirectional_types::ConnectionManager) -> Self {
-                Self { client_id, connection_manager }
+                Self { client_id, connection_manager, call_timeout: std::time::Duration::from_secs(30) }
+            }
+
+            /// Set how long calls to the client wait for a response (30 seconds by default)
+            pub fn with_call_timeout(mut self, call_timeout: std::time::Duration) -> Self {
+                self.call_timeout = call_timeout;
+                self
             }
 
             /// Get the client connection ID
@@ -344,6 +497,10 @@ pub fn generate_server_code(
         pub struct #handler_name<T: #service_trait_name, M: ras_jsonrpc_bidirectional_types::ConnectionManager + 'static> {
             service: std::sync::Arc<T>,
             connection_manager: std::sync::Arc<M>,
+            topic_authorizer: Option<ras_jsonrpc_bidirectional_server::TopicAuthorizer>,
+            on_connect: Option<ras_jsonrpc_bidirectional_server::ConnectionHook>,
+            on_disconnect: Option<ras_jsonrpc_bidirectional_server::ConnectionHook>,
+            call_timeout: std::time::Duration,
         }
 
         #[cfg(feature = "server")]
@@ -352,7 +509,57 @@ pub fn generate_server_code(
                 service: std::sync::Arc<T>,
                 connection_manager: std::sync::Arc<M>,
             ) -> Self {
-                Self { service, connection_manager }
+                Self {
+                    service,
+                    connection_manager,
+                    topic_authorizer: None,
+                    on_connect: None,
+                    on_disconnect: None,
+                    call_timeout: std::time::Duration::from_secs(30),
+                }
+            }
+
+            /// Set how long calls to clients wait for a response (30 seconds by default)
+            pub fn with_call_timeout(mut self, call_timeout: std::time::Duration) -> Self {
+                self.call_timeout = call_timeout;
+                self
+            }
+
+            /// Run `hook` with each connection's context once it is established, after
+            /// the service's `on_client_connected`
+            pub fn with_on_connect<F, Fut>(mut self, hook: F) -> Self
+            where
+                F: Fn(std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = ()> + Send + 'static,
+            {
+                self.on_connect = Some(ras_jsonrpc_bidirectional_server::connection_hook(hook));
+                self
+            }
+
+            /// Run `hook` with each connection's context when it closes, before its
+            /// connection-scoped state is dropped; `close_reason()` tells why it closed
+            pub fn with_on_disconnect<F, Fut>(mut self, hook: F) -> Self
+            where
+                F: Fn(std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = ()> + Send + 'static,
+            {
+                self.on_disconnect = Some(ras_jsonrpc_bidirectional_server::connection_hook(hook));
+                self
+            }
+
+            /// Only let clients subscribe to topics `authorizer` accepts; other
+            /// subscription requests are ignored. Presence keys are checked the same way.
+            pub fn with_topic_authorizer<F>(mut self, authorizer: F) -> Self
+            where
+                F: Fn(Option<&ras_auth_core::AuthenticatedUser>, &str) -> bool + Send + Sync + 'static,
+            {
+                self.topic_authorizer = Some(std::sync::Arc::new(authorizer));
+                self
+            }
+
+            /// Topic subscriptions and broadcasts for this service's connections
+            pub fn topics(&self) -> #topic_manager_name<'_> {
+                #topic_manager_name::new(self.connection_manager.as_ref())
             }
 
             /// Get a typed client handle for a connection
@@ -361,7 +568,7 @@ pub fn generate_server_code(
                 if self.connection_manager.get_connection(client_id).await
                     .map(|conn| conn.is_some())
                     .unwrap_or(false) {
-                    Some(#client_handle_name::new(client_id, self.connection_manager.as_ref()))
+                    Some(#client_handle_name::new(client_id, self.connection_manager.as_ref()).with_call_timeout(self.call_timeout))
                 } else {
                     None
                 }
@@ -371,7 +578,7 @@ pub fn generate_server_code(
             pub async fn get_all_clients(&self) -> Result<Vec<#client_handle_name<'_>>, ras_jsonrpc_bidirectional_types::BidirectionalError> {
                 let connections = self.connection_manager.get_all_connections().await?;
                 Ok(connections.into_iter()
-                    .map(|conn| #client_handle_name::new(conn.id, self.connection_manager.as_ref()))
+                    .map(|conn| #client_handle_name::new(conn.id, self.connection_manager.as_ref()).with_call_timeout(self.call_timeout))
                     .collect())
             }
 
@@ -390,7 +597,11 @@ pub fn generate_server_code(
                 self.service.on_client_authenticated(client_id, self.connection_manager.as_ref(), user).await
             }
 
+            #(#handler_call_methods)*
+
             #(#default_notification_impls)*
+
+            #(#filtered_notification_methods)*
         }
 
         #[cfg(feature = "server")]
@@ -408,6 +619,61 @@ pub fn generate_server_code(
                 }
             }
 
+            async fn handle_subscribe(&self, topics: Vec<String>, context: std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>) -> ras_jsonrpc_bidirectional_server::ServerResult<()> {
+                let user = context.get_user().await;
+                for topic in topics {
+                    if let Some(authorizer) = &self.topic_authorizer {
+                        if !authorizer(user.as_deref(), &topic) {
+                            // Denied topics are skipped; the client simply receives nothing
+                            continue;
+                        }
+                    }
+                    self.connection_manager.add_subscription(context.id, topic.clone()).await
+                        .map_err(|e| ras_jsonrpc_bidirectional_server::ServerError::Internal(e.to_string()))?;
+                    context.subscribe(topic).await;
+                }
+                Ok(())
+            }
+
+            async fn handle_resume(&self, resume: ras_jsonrpc_bidirectional_types::TopicResume, context: std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>) -> ras_jsonrpc_bidirectional_server::ServerResult<ras_jsonrpc_bidirectional_types::TopicResumed> {
+                if let Some(authorizer) = &self.topic_authorizer {
+                    let user = context.get_user().await;
+                    if !authorizer(user.as_deref(), &resume.topic) {
+                        return Err(ras_jsonrpc_bidirectional_server::ServerError::PermissionDenied(format!("Not allowed to subscribe to {}", resume.topic)));
+                    }
+                }
+                let resumed = self.connection_manager.resume_topic(context.id, &resume.topic, resume.last_seq).await
+                    .map_err(|e| ras_jsonrpc_bidirectional_server::ServerError::Internal(e.to_string()))?;
+                context.subscribe(resume.topic).await;
+                Ok(resumed)
+            }
+
+            async fn authorize_presence(&self, key: &str, context: std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>) -> bool {
+                match &self.topic_authorizer {
+                    Some(authorizer) => {
+                        let user = context.get_user().await;
+                        authorizer(user.as_deref(), key)
+                    }
+                    None => true,
+                }
+            }
+
+            async fn check_filter(&self, notification: &str, filter: &serde_json::Value) -> Result<(), String> {
+                match notification {
+                    #(#filter_checks)*
+                    _ => Err(format!("{} takes no filter", notification)),
+                }
+            }
+
+            async fn handle_unsubscribe(&self, topics: Vec<String>, context: std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>) -> ras_jsonrpc_bidirectional_server::ServerResult<()> {
+                for topic in topics {
+                    self.connection_manager.remove_subscription(context.id, &topic).await
+                        .map_err(|e| ras_jsonrpc_bidirectional_server::ServerError::Internal(e.to_string()))?;
+                    context.unsubscribe(&topic).await;
+                }
+                Ok(())
+            }
+
             async fn on_connect(&self, context: std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>) -> ras_jsonrpc_bidirectional_server::ServerResult<()> {
                 // Call the service's on_client_connected
                 if let Err(e) = self.service.on_client_connected(context.id, self.connection_manager.as_ref()).await {
@@ -421,11 +687,20 @@ pub fn generate_server_code(
                     }
                 }
 
+                if let Some(hook) = &self.on_connect {
+                    hook(context).await;
+                }
+
                 Ok(())
             }
 
-            async fn on_disconnect(&self, context: std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>, reason: Option<String>) -> ras_jsonrpc_bidirectional_server::ServerResult<()> {
-                let _ = reason; // Unused for now
+            async fn on_disconnect(&self, context: std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>, reason: ras_jsonrpc_bidirectional_types::CloseReason) -> ras_jsonrpc_bidirectional_server::ServerResult<()> {
+                // Hooks read the reason from `context.close_reason()`
+                let _ = reason;
+                if let Some(hook) = &self.on_disconnect {
+                    hook(context.clone()).await;
+                }
+
                 // Call the service's on_client_disconnected
                 if let Err(e) = self.service.on_client_disconnected(context.id, self.connection_manager.as_ref()).await {
                     return Err(ras_jsonrpc_bidirectional_server::ServerError::Internal(e.to_string()));
@@ -440,6 +715,24 @@ pub fn generate_server_code(
             service: std::sync::Arc<T>,
             auth_provider: std::sync::Arc<A>,
             require_auth: bool,
+            keepalive: ras_jsonrpc_bidirectional_server::KeepaliveConfig,
+            topic_authorizer: Option<ras_jsonrpc_bidirectional_server::TopicAuthorizer>,
+            on_connect: Option<ras_jsonrpc_bidirectional_server::ConnectionHook>,
+            on_disconnect: Option<ras_jsonrpc_bidirectional_server::ConnectionHook>,
+            call_timeout: std::time::Duration,
+            queue_capacity: Option<usize>,
+            overflow_policy: ras_jsonrpc_bidirectional_server::OverflowPolicy,
+            max_message_size: Option<usize>,
+            max_outgoing_message_size: Option<usize>,
+            max_malformed_frames: Option<u32>,
+            revalidate_interval: Option<std::time::Duration>,
+            codecs: Option<Vec<ras_jsonrpc_bidirectional_types::Codec>>,
+            topic_history: usize,
+            rate_limits: Option<ras_jsonrpc_bidirectional_server::RateLimitConfig>,
+            middleware: ras_jsonrpc_bidirectional_server::MiddlewareChain,
+            service_metrics: Option<std::sync::Arc<dyn ras_jsonrpc_bidirectional_server::ServiceMetrics>>,
+            session_revocations: Option<std::sync::Arc<dyn ras_jsonrpc_bidirectional_server::SessionRevocations>>,
+            connection_manager: Option<std::sync::Arc<ras_jsonrpc_bidirectional_server::DefaultConnectionManager>>,
         }
 
         #[cfg(feature = "server")]
@@ -450,6 +743,24 @@ pub fn generate_server_code(
                     service: std::sync::Arc::new(service),
                     auth_provider: std::sync::Arc::new(auth_provider),
                     require_auth: false,
+                    keepalive: ras_jsonrpc_bidirectional_server::KeepaliveConfig::default(),
+                    topic_authorizer: None,
+                    on_connect: None,
+                    on_disconnect: None,
+                    call_timeout: std::time::Duration::from_secs(30),
+                    queue_capacity: None,
+                    overflow_policy: ras_jsonrpc_bidirectional_server::OverflowPolicy::default(),
+                    max_message_size: None,
+                    max_outgoing_message_size: None,
+                    max_malformed_frames: None,
+                    revalidate_interval: None,
+                    codecs: None,
+                    topic_history: 0,
+                    rate_limits: None,
+                    middleware: ras_jsonrpc_bidirectional_server::MiddlewareChain::new(),
+                    service_metrics: None,
+                    session_revocations: None,
+                    connection_manager: None,
                 }
             }
 
@@ -459,22 +770,223 @@ pub fn generate_server_code(
                 self
             }
 
+            /// Set how often connections are pinged and how many missed pongs close them
+            pub fn keepalive(mut self, keepalive: ras_jsonrpc_bidirectional_server::KeepaliveConfig) -> Self {
+                self.keepalive = keepalive;
+                self
+            }
+
+            /// Only let clients subscribe to topics `authorizer` accepts, given the
+            /// connection's user and the topic; it also decides which presence keys
+            /// they may join and list
+            pub fn with_topic_authorizer<F>(mut self, authorizer: F) -> Self
+            where
+                F: Fn(Option<&ras_auth_core::AuthenticatedUser>, &str) -> bool + Send + Sync + 'static,
+            {
+                self.topic_authorizer = Some(std::sync::Arc::new(authorizer));
+                self
+            }
+
+            /// Run `hook` with each connection's context once it is established
+            pub fn on_connect<F, Fut>(mut self, hook: F) -> Self
+            where
+                F: Fn(std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = ()> + Send + 'static,
+            {
+                self.on_connect = Some(ras_jsonrpc_bidirectional_server::connection_hook(hook));
+                self
+            }
+
+            /// Run `hook` with each connection's context when it closes, before its
+            /// connection-scoped state is dropped; `close_reason()` tells why it closed
+            pub fn on_disconnect<F, Fut>(mut self, hook: F) -> Self
+            where
+                F: Fn(std::sync::Arc<ras_jsonrpc_bidirectional_server::ConnectionContext>) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = ()> + Send + 'static,
+            {
+                self.on_disconnect = Some(ras_jsonrpc_bidirectional_server::connection_hook(hook));
+                self
+            }
+
+            /// Set how long server-to-client calls wait for the client's response
+            /// (30 seconds by default)
+            pub fn call_timeout(mut self, call_timeout: std::time::Duration) -> Self {
+                self.call_timeout = call_timeout;
+                self
+            }
+
+            /// Bound each connection's outgoing queue to `capacity` messages and
+            /// apply `policy` to messages sent while it is full
+            ///
+            /// Broadcasts skip connections whose queue is full instead of waiting
+            /// for them.
+            pub fn outgoing_queue(
+                mut self,
+                capacity: usize,
+                policy: ras_jsonrpc_bidirectional_server::OverflowPolicy,
+            ) -> Self {
+                self.queue_capacity = Some(capacity);
+                self.overflow_policy = policy;
+                self
+            }
+
+            /// Refuse messages from clients larger than `max_size` bytes (1 MiB by default)
+            ///
+            /// Oversized messages are rejected while they are read, closing the
+            /// connection with code 1009.
+            pub fn max_message_size(mut self, max_size: usize) -> Self {
+                self.max_message_size = Some(max_size);
+                self
+            }
+
+            /// Withhold messages to clients larger than `max_size` bytes; calls
+            /// whose response is withheld get an error instead
+            pub fn max_outgoing_message_size(mut self, max_size: usize) -> Self {
+                self.max_outgoing_message_size = Some(max_size);
+                self
+            }
+
+            /// Close connections after `max` malformed messages, each of which is
+            /// answered with a parse error (10 by default, `0` to never close)
+            pub fn max_malformed_frames(mut self, max: u32) -> Self {
+                self.max_malformed_frames = Some(max);
+                self
+            }
+
+            /// Re-authenticate each connection's token every `interval`
+            ///
+            /// A client whose token is rejected is sent a `session.expiring`
+            /// notification, and its connection is closed if the token is still
+            /// rejected at the next check; calling `session.refresh` in between
+            /// keeps it open.
+            pub fn revalidate_every(mut self, interval: std::time::Duration) -> Self {
+                self.revalidate_interval = Some(interval);
+                self
+            }
+
+            /// Binary codecs clients may negotiate (MessagePack and CBOR by default);
+            /// an empty list keeps every connection on JSON text
+            pub fn codecs(mut self, codecs: impl IntoIterator<Item = ras_jsonrpc_bidirectional_types::Codec>) -> Self {
+                self.codecs = Some(codecs.into_iter().collect());
+                self
+            }
+
+            /// Number each topic's broadcasts and keep the last `capacity` of them, so
+            /// clients resuming after a reconnect are sent the ones they missed
+            ///
+            /// Off by default; clients resuming further back than the kept history
+            /// are told there is a gap.
+            pub fn topic_history(mut self, capacity: usize) -> Self {
+                self.topic_history = capacity;
+                self
+            }
+
+            /// Limit how fast each connection may send requests
+            ///
+            /// Requests over a limit are answered with a `rate_limited` error, and
+            /// connections refused too many are closed with code 1008.
+            pub fn rate_limit(mut self, limits: ras_jsonrpc_bidirectional_server::RateLimitConfig) -> Self {
+                self.rate_limits = Some(limits);
+                self
+            }
+
+            /// Run `middleware` around every request and outgoing message, after the
+            /// middleware added before it
+            pub fn with_middleware(mut self, middleware: impl ras_jsonrpc_bidirectional_server::MessageMiddleware) -> Self {
+                self.middleware = self.middleware.with(middleware);
+                self
+            }
+
+            /// Answer requests the middleware rejects with `error` rather than
+            /// an "invalid request" error
+            pub fn middleware_rejection(mut self, error: ras_jsonrpc_types::JsonRpcError) -> Self {
+                self.middleware = self.middleware.rejection(error);
+                self
+            }
+
+            /// Record connections, messages, broadcast fan-out and the duration of
+            /// each call to `metrics`
+            pub fn with_service_metrics(
+                mut self,
+                metrics: std::sync::Arc<dyn ras_jsonrpc_bidirectional_server::ServiceMetrics>,
+            ) -> Self {
+                self.service_metrics = Some(metrics);
+                self
+            }
+
+            /// Record to the service metrics of `observability`, such as an
+            /// `OtelSetup`, as with `with_service_metrics`
+            ///
+            /// Calls over a WebSocket carry no headers of their own, so its usage
+            /// and duration trackers aren't called.
+            pub fn with_observability(
+                self,
+                observability: &dyn ras_jsonrpc_bidirectional_server::Observability,
+            ) -> Self {
+                self.with_service_metrics(observability.service_metrics())
+            }
+
+            /// Close connections with `CloseReason::SessionRevoked` once
+            /// `revocations` reports the session of their token ended, such as a
+            /// `SessionService` with the server's `session` feature
+            pub fn with_session_revocations(
+                mut self,
+                revocations: std::sync::Arc<dyn ras_jsonrpc_bidirectional_server::SessionRevocations>,
+            ) -> Self {
+                self.session_revocations = Some(revocations);
+                self
+            }
+
+            /// Track connections in `manager`, such as one shared with other nodes
+            /// through `DefaultConnectionManager::with_registry`
+            pub fn connection_manager(
+                mut self,
+                manager: std::sync::Arc<ras_jsonrpc_bidirectional_server::DefaultConnectionManager>,
+            ) -> Self {
+                self.connection_manager = Some(manager);
+                self
+            }
+
             /// Build the WebSocket service
             pub fn build(self) -> ras_jsonrpc_bidirectional_server::service::BuiltWebSocketService<#handler_name<T, ras_jsonrpc_bidirectional_server::DefaultConnectionManager>, A, ras_jsonrpc_bidirectional_server::DefaultConnectionManager> {
                 use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
 
-                let connection_manager = std::sync::Arc::new(DefaultConnectionManager::new());
-                let handler = #handler_name::new(
+                let connection_manager = self
+                    .connection_manager
+                    .unwrap_or_else(|| std::sync::Arc::new(DefaultConnectionManager::new()));
+                if let Some(metrics) = &self.service_metrics {
+                    connection_manager.set_service_metrics(metrics.clone());
+                }
+                if self.topic_history > 0 {
+                    connection_manager.set_topic_history(self.topic_history);
+                }
+                let mut handler = #handler_name::new(
                     self.service.clone(),
                     connection_manager.clone(),
                 );
+                handler.topic_authorizer = self.topic_authorizer;
+                handler.on_connect = self.on_connect;
+                handler.on_disconnect = self.on_disconnect;
+                handler.call_timeout = self.call_timeout;
 
                 let builder = ras_jsonrpc_bidirectional_server::WebSocketServiceBuilder::builder()
                     .handler(std::sync::Arc::new(handler))
                     .auth_provider(self.auth_provider)
                     .require_auth(self.require_auth)
+                    .keepalive(self.keepalive)
+                    .maybe_message_channel_capacity(self.queue_capacity)
+                    .overflow_policy(self.overflow_policy)
+                    .maybe_max_message_size(self.max_message_size)
+                    .maybe_max_outgoing_message_size(self.max_outgoing_message_size)
+                    .maybe_max_malformed_frames(self.max_malformed_frames)
+                    .maybe_revalidate_interval(self.revalidate_interval)
+                    .maybe_codecs(self.codecs)
+                    .maybe_service_metrics(self.service_metrics)
+                    .maybe_rate_limits(self.rate_limits)
+                    .maybe_session_revocations(self.session_revocations)
+                    .middleware(self.middleware)
                     .build();
-                builder.build()
+                builder.build_with_manager(connection_manager)
             }
         }
     }
@@ -76,4 +76,110 @@ mod tests {
             panic!("Expected WithPermissions auth requirement");
         }
     }
+
+    #[test]
+    fn test_server_to_client_calls_need_no_auth() {
+        let input = r#"{
+            service_name: AgentService,
+            client_to_server: [],
+            server_to_client: [],
+            server_to_client_calls: [
+                ask_confirmation(String) -> bool,
+                UNAUTHORIZED ask_name(()) -> String,
+            ]
+        }"#;
+
+        let parsed: BidirectionalServiceDefinition = syn::parse_str(input).unwrap();
+
+        assert_eq!(parsed.server_to_client_calls.len(), 2);
+        assert_eq!(parsed.server_to_client_calls[0].name, "ask_confirmation");
+        assert_eq!(parsed.server_to_client_calls[1].name, "ask_name");
+    }
+
+    #[test]
+    fn test_notifications_can_declare_a_filter_type() {
+        let input = r#"{
+            service_name: Board,
+            client_to_server: [],
+            server_to_client: [
+                task_updated(TaskUpdate) filter TaskFilter,
+                task_deleted(u64),
+            ],
+            server_to_client_calls: []
+        }"#;
+
+        let parsed: BidirectionalServiceDefinition = syn::parse_str(input).unwrap();
+        assert!(parsed.server_to_client[0].filter_type.is_some());
+        assert!(parsed.server_to_client[1].filter_type.is_none());
+
+        let error = parse_error(
+            "client_to_server: [],
+             server_to_client: [task_updated(TaskUpdate) matching TaskFilter],
+             server_to_client_calls: []",
+        );
+        assert_eq!(error, "Expected `filter` or `,`");
+    }
+
+    fn parse_error(lists: &str) -> String {
+        let input = format!("{{ service_name: Clash, {lists} }}");
+        syn::parse_str::<BidirectionalServiceDefinition>(&input)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_names_declared_twice_are_rejected() {
+        let error = parse_error(
+            "client_to_server: [UNAUTHORIZED add(i64) -> i64, UNAUTHORIZED add(u8) -> u8],
+             server_to_client: [],
+             server_to_client_calls: []",
+        );
+        assert_eq!(
+            error,
+            "duplicate name `add`: already declared in client_to_server"
+        );
+
+        let error = parse_error(
+            "client_to_server: [UNAUTHORIZED add(i64) -> i64],
+             server_to_client: [add(i64)],
+             server_to_client_calls: []",
+        );
+        assert_eq!(
+            error,
+            "duplicate name `add`: already declared in client_to_server"
+        );
+    }
+
+    #[test]
+    fn test_names_clashing_with_generated_methods_are_rejected() {
+        let error = parse_error(
+            "client_to_server: [UNAUTHORIZED refresh_session(String) -> ()],
+             server_to_client: [],
+             server_to_client_calls: []",
+        );
+        assert_eq!(
+            error,
+            "`refresh_session` is already defined on the generated client for `session.refresh`"
+        );
+
+        let error = parse_error(
+            "client_to_server: [],
+             server_to_client: [presence_joined(String)],
+             server_to_client_calls: []",
+        );
+        assert_eq!(
+            error,
+            "`presence_joined` would generate `on_presence_joined`, which is already defined on the generated client for `presence.joined`"
+        );
+
+        let error = parse_error(
+            "client_to_server: [UNAUTHORIZED add(i64) -> i64, UNAUTHORIZED add_with_timeout(i64) -> i64],
+             server_to_client: [],
+             server_to_client_calls: []",
+        );
+        assert_eq!(
+            error,
+            "`add_with_timeout` is already defined on the generated client for `add` in client_to_server"
+        );
+    }
 }
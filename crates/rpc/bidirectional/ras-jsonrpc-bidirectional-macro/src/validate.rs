@@ -0,0 +1,204 @@
+//! Checks that the names declared in a service don't clash with each other or
+//! with the names the generated code and the runtime already use
+
+use crate::BidirectionalServiceDefinition;
+use std::collections::HashMap;
+use syn::Ident;
+
+/// A generated type that methods are added to for each declared name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Target {
+    /// `<Service>Client`
+    Client,
+    /// `<Service>ClientHandle`
+    ClientHandle,
+    /// `<Service>Service`
+    ServiceTrait,
+}
+
+impl Target {
+    fn describe(self) -> &'static str {
+        match self {
+            Target::Client => "the generated client",
+            Target::ClientHandle => "the generated client handle",
+            Target::ServiceTrait => "the generated service trait",
+        }
+    }
+}
+
+/// Methods the generated types always define, and what for
+///
+/// The runtime's own wire methods, such as `session.refresh` or `presence.join`,
+/// can't be spelled as identifiers; the methods wrapping them are reserved instead.
+const RESERVED: &[(Target, &str, &str)] = &[
+    (Target::Client, "new", "as its constructor"),
+    (Target::Client, "client", "to expose the underlying client"),
+    (
+        Target::Client,
+        "client_mut",
+        "to expose the underlying client",
+    ),
+    (Target::Client, "connect", "to open the connection"),
+    (Target::Client, "disconnect", "to close the connection"),
+    (
+        Target::Client,
+        "is_connected",
+        "to report the connection state",
+    ),
+    (Target::Client, "on_disconnected", "for close reasons"),
+    (
+        Target::Client,
+        "on_connection_event",
+        "for connection events",
+    ),
+    (Target::Client, "on_server_shutdown", "for shutdown notices"),
+    (Target::Client, "refresh_session", "for `session.refresh`"),
+    (
+        Target::Client,
+        "on_session_expiring",
+        "for `session.expiring`",
+    ),
+    (Target::Client, "subscribe", "for topic subscriptions"),
+    (Target::Client, "unsubscribe", "for topic subscriptions"),
+    (Target::Client, "subscribe_topic", "for topic subscriptions"),
+    (
+        Target::Client,
+        "unsubscribe_topic",
+        "for topic subscriptions",
+    ),
+    (Target::Client, "resume_topic", "for `topic.resume`"),
+    (Target::Client, "last_seq", "for `topic.resume`"),
+    (Target::Client, "join_presence", "for `presence.join`"),
+    (Target::Client, "leave_presence", "for `presence.leave`"),
+    (Target::Client, "list_presence", "for `presence.list`"),
+    (
+        Target::Client,
+        "on_presence_joined",
+        "for `presence.joined`",
+    ),
+    (Target::Client, "on_presence_left", "for `presence.left`"),
+    (Target::ClientHandle, "new", "as its constructor"),
+    (
+        Target::ClientHandle,
+        "client_id",
+        "to identify the connection",
+    ),
+    (
+        Target::ClientHandle,
+        "is_connected",
+        "to report the connection state",
+    ),
+    (
+        Target::ClientHandle,
+        "get_connection_info",
+        "to describe the connection",
+    ),
+    (
+        Target::ClientHandle,
+        "disconnect",
+        "to close the connection",
+    ),
+    (
+        Target::ClientHandle,
+        "with_call_timeout",
+        "to set its call timeout",
+    ),
+    (
+        Target::ServiceTrait,
+        "on_client_connected",
+        "as a connection hook",
+    ),
+    (
+        Target::ServiceTrait,
+        "on_client_disconnected",
+        "as a connection hook",
+    ),
+    (
+        Target::ServiceTrait,
+        "on_client_authenticated",
+        "as a connection hook",
+    ),
+];
+
+/// Reject names declared twice, and names whose generated methods clash with
+/// another declaration's or with a reserved one
+///
+/// Errors point at the later of the two clashing names.
+pub(crate) fn validate_names(service: &BidirectionalServiceDefinition) -> syn::Result<()> {
+    let declared = service
+        .client_to_server
+        .iter()
+        .map(|method| (&method.name, "client_to_server", false))
+        .chain(service.server_to_client.iter().map(|notification| {
+            let filtered = notification.filter_type.is_some();
+            (&notification.name, "server_to_client", filtered)
+        }))
+        .chain(
+            service
+                .server_to_client_calls
+                .iter()
+                .map(|method| (&method.name, "server_to_client_calls", false)),
+        );
+
+    let mut lists: HashMap<String, &str> = HashMap::new();
+    let mut generated: HashMap<(Target, String), String> = RESERVED
+        .iter()
+        .map(|(target, name, purpose)| ((*target, name.to_string()), purpose.to_string()))
+        .collect();
+
+    for (name, list, filtered) in declared {
+        if let Some(first) = lists.insert(name.to_string(), list) {
+            return Err(syn::Error::new(
+                name.span(),
+                format!("duplicate name `{name}`: already declared in {first}"),
+            ));
+        }
+
+        for (target, method) in generated_methods(name, list, filtered) {
+            let key = (target, method.clone());
+            if let Some(purpose) = generated.get(&key) {
+                let message = if *name == method {
+                    format!(
+                        "`{name}` is already defined on {} {purpose}",
+                        target.describe()
+                    )
+                } else {
+                    format!(
+                        "`{name}` would generate `{method}`, which is already defined on {} {purpose}",
+                        target.describe()
+                    )
+                };
+                return Err(syn::Error::new(name.span(), message));
+            }
+            generated.insert(key, format!("for `{name}` in {list}"));
+        }
+    }
+
+    Ok(())
+}
+
+/// The methods the generated types get for a name declared in `list`, with a
+/// filter type if `filtered`
+fn generated_methods(name: &Ident, list: &str, filtered: bool) -> Vec<(Target, String)> {
+    let mut methods = match list {
+        "client_to_server" => vec![
+            (Target::Client, name.to_string()),
+            (Target::Client, format!("{name}_with_timeout")),
+            (Target::ServiceTrait, name.to_string()),
+        ],
+        "server_to_client" => vec![
+            (Target::Client, format!("on_{name}")),
+            (Target::ClientHandle, name.to_string()),
+            (Target::ServiceTrait, format!("notify_{name}")),
+        ],
+        _ => vec![
+            (Target::Client, format!("on_{name}")),
+            (Target::ClientHandle, name.to_string()),
+        ],
+    };
+    if filtered {
+        methods.push((Target::Client, format!("set_{name}_filter")));
+        methods.push((Target::Client, format!("clear_{name}_filter")));
+    }
+    methods
+}
@@ -0,0 +1,231 @@
+//! Bounded outgoing queues: a client that stops reading must not hold up
+//! broadcasts, and the configured overflow policy decides what it misses.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::StreamExt;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_server::{DefaultConnectionManager, OverflowPolicy};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId, ConnectionManager};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    pub seq: u64,
+    pub data: String,
+}
+
+jsonrpc_bidirectional_service!({
+    service_name: Feed,
+    client_to_server: [
+        UNAUTHORIZED status(()) -> (),
+    ],
+    server_to_client: [
+        chunk(Chunk),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct FeedImpl;
+
+#[async_trait]
+impl FeedService for FeedImpl {
+    async fn status(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _request: (),
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+
+    async fn notify_chunk(
+        &self,
+        _connection_id: ConnectionId,
+        _params: Chunk,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    FeedHandler<FeedImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const QUEUE_CAPACITY: usize = 8;
+/// Enough 64 KiB chunks to fill the socket buffers of a client that never reads
+const CHUNKS: u64 = 512;
+
+/// A subscriber that stopped reading, flooded with more than its socket buffers
+/// and queue can hold
+struct Stalled {
+    manager: Arc<DefaultConnectionManager>,
+    id: ConnectionId,
+    socket: Socket,
+}
+
+async fn stall_and_flood(policy: OverflowPolicy) -> Stalled {
+    let service: Service = FeedBuilder::new(FeedImpl, MockAuthProvider::default())
+        .outgoing_queue(QUEUE_CAPACITY, policy)
+        .build();
+    let manager = service.connection_manager();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+
+    let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+        .await
+        .expect("connect");
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    let id = loop {
+        if let Some(id) = manager.get_connection_ids().first() {
+            break *id;
+        }
+        assert!(tokio::time::Instant::now() < deadline, "never connected");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    manager
+        .add_subscription(id, "feed".to_string())
+        .await
+        .unwrap();
+
+    // The broadcaster never waits on the stalled client
+    let topics = FeedTopicManager::new(manager.as_ref());
+    let data = "x".repeat(64 * 1024);
+    let flood = async {
+        for seq in 0..CHUNKS {
+            let chunk = Chunk {
+                seq,
+                data: data.clone(),
+            };
+            topics.broadcast_chunk("feed", chunk).await.unwrap();
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), flood)
+        .await
+        .expect("broadcasts blocked on a stalled client");
+
+    Stalled {
+        manager,
+        id,
+        socket,
+    }
+}
+
+/// Read the chunks the client was sent until it goes quiet, and the close code
+/// if the server closed the connection
+async fn drain(socket: &mut Socket) -> (Vec<u64>, Option<CloseCode>) {
+    let mut seqs = Vec::new();
+    loop {
+        let next = tokio::time::timeout(Duration::from_millis(500), socket.next()).await;
+        match next {
+            Ok(Some(Ok(Message::Text(text)))) => {
+                // Topic broadcasts arrive as broadcast messages
+                if let Ok(BidirectionalMessage::Broadcast(broadcast)) =
+                    serde_json::from_str::<BidirectionalMessage>(&text)
+                    && broadcast.method == "chunk"
+                {
+                    let chunk: Chunk = serde_json::from_value(broadcast.params).unwrap();
+                    seqs.push(chunk.seq);
+                }
+            }
+            Ok(Some(Ok(Message::Close(frame)))) => return (seqs, frame.map(|f| f.code)),
+            Ok(Some(Ok(_))) => {}
+            Ok(Some(Err(_))) | Ok(None) | Err(_) => return (seqs, None),
+        }
+    }
+}
+
+fn assert_in_order(seqs: &[u64]) {
+    assert!(
+        seqs.windows(2).all(|w| w[0] < w[1]),
+        "out of order: {seqs:?}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drop_newest_keeps_the_queued_messages_and_skips_the_rest() {
+    let Stalled {
+        manager,
+        id,
+        mut socket,
+    } = stall_and_flood(OverflowPolicy::DropNewest).await;
+
+    let stats = manager.queue_stats(id).expect("still connected");
+    assert_eq!(stats.queued, QUEUE_CAPACITY);
+    assert!(stats.dropped > 0);
+    assert_eq!(manager.skipped_broadcasts(), stats.dropped);
+    assert_eq!(manager.get_topic_connections("feed"), [id]);
+
+    let (seqs, close) = drain(&mut socket).await;
+    assert_eq!(close, None);
+    assert_in_order(&seqs);
+    assert_eq!(seqs.len() as u64 + stats.dropped, CHUNKS);
+    assert!(
+        !seqs.contains(&(CHUNKS - 1)),
+        "newest message was delivered"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drop_oldest_delivers_the_latest_messages() {
+    let Stalled {
+        manager,
+        id,
+        mut socket,
+    } = stall_and_flood(OverflowPolicy::DropOldest).await;
+
+    let stats = manager.queue_stats(id).expect("still connected");
+    assert_eq!(stats.queued, QUEUE_CAPACITY);
+    assert!(stats.dropped > 0);
+
+    let (seqs, close) = drain(&mut socket).await;
+    assert_eq!(close, None);
+    assert_in_order(&seqs);
+    assert_eq!(seqs.len() as u64 + stats.dropped, CHUNKS);
+    let latest: Vec<u64> = (CHUNKS - QUEUE_CAPACITY as u64..CHUNKS).collect();
+    assert!(seqs.ends_with(&latest), "latest messages missing: {seqs:?}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn disconnect_closes_a_client_that_falls_behind() {
+    let Stalled {
+        manager,
+        mut socket,
+        ..
+    } = stall_and_flood(OverflowPolicy::Disconnect).await;
+
+    assert!(manager.skipped_broadcasts() > 0);
+    // Once the queue overflowed the connection left the topic
+    assert!(manager.get_topic_connections("feed").is_empty());
+
+    let (seqs, close) = drain(&mut socket).await;
+    assert_eq!(close, Some(CloseCode::Again));
+    assert_in_order(&seqs);
+    assert!((seqs.len() as u64) < CHUNKS);
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while manager.connection_count() != 0 {
+        assert!(tokio::time::Instant::now() < deadline, "connection kept");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
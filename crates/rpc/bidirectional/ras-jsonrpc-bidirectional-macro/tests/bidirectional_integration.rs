@@ -5,7 +5,7 @@
 
 use async_trait::async_trait;
 use axum::{Router, routing::get};
-use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_jsonrpc_bidirectional_client::ClientBuilder;
 use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
 use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
@@ -115,6 +115,7 @@ impl AuthProvider for TestAuthProvider {
                 user_id: user_id.to_string(),
                 permissions: permissions.into_iter().collect(),
                 metadata: None,
+                kind: PrincipalKind::User,
             })
         })
     }
@@ -149,6 +150,7 @@ impl ChatServiceService for MockChatService {
         &self,
         client_id: ConnectionId,
         connection_manager: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         username: String,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         println!("Client {} joined chat as {}", client_id, username);
@@ -183,6 +185,7 @@ impl ChatServiceService for MockChatService {
         &self,
         client_id: ConnectionId,
         connection_manager: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         message: ChatMessage,
     ) -> Result<ChatResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -226,6 +229,7 @@ impl ChatServiceService for MockChatService {
         &self,
         client_id: ConnectionId,
         connection_manager: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         request: KickUserRequest,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
@@ -260,6 +264,7 @@ impl ChatServiceService for MockChatService {
         &self,
         client_id: ConnectionId,
         connection_manager: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         message: String,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
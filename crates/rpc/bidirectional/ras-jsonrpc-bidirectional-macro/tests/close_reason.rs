@@ -0,0 +1,168 @@
+//! Close reasons: why a connection closed reaches the server's disconnect
+//! hooks, the client's connection events, the close frame and the metrics.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::{SinkExt, StreamExt};
+use ras_jsonrpc_bidirectional_client::{CloseReason, ReconnectConfig};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_server::{DefaultConnectionManager, RateLimit, RateLimitConfig};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId};
+use ras_test_helpers::{MockAuthProvider, RecordingMetrics, spawn_tcp};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+jsonrpc_bidirectional_service!({
+    service_name: Counter,
+    client_to_server: [
+        UNAUTHORIZED add(i64) -> i64,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct CounterImpl;
+
+#[async_trait]
+impl CounterService for CounterImpl {
+    async fn add(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        amount: i64,
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(amount)
+    }
+}
+
+type Service = BuiltWebSocketService<
+    CounterHandler<CounterImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+type Reasons = Arc<Mutex<Vec<CloseReason>>>;
+
+/// Start a server allowing one request a minute per connection, recording the
+/// close reasons its disconnect hook sees
+async fn start_server(metrics: &RecordingMetrics) -> (String, Reasons) {
+    let closed = Reasons::default();
+    let on_disconnect = closed.clone();
+    let service: Service = CounterBuilder::new(CounterImpl, MockAuthProvider::default())
+        .rate_limit(
+            RateLimitConfig::new(RateLimit::new(1, Duration::from_secs(60))).max_violations(1),
+        )
+        .max_malformed_frames(1)
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .on_disconnect(move |ctx| {
+            let closed = on_disconnect.clone();
+            async move {
+                closed.lock().unwrap().push(ctx.close_reason().unwrap());
+            }
+        })
+        .build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), closed)
+}
+
+/// Connect a client that does not reconnect, recording why it was disconnected
+async fn connect(url: &str) -> (CounterClient, Reasons) {
+    let mut client = CounterClientBuilder::new(url)
+        .with_reconnect_config(ReconnectConfig {
+            enabled: false,
+            ..ReconnectConfig::default()
+        })
+        .build()
+        .await
+        .unwrap();
+    let reasons = Reasons::default();
+    let disconnected = reasons.clone();
+    client.on_disconnected(move |reason| disconnected.lock().unwrap().push(reason));
+    client.connect().await.unwrap();
+    (client, reasons)
+}
+
+async fn wait_for(reasons: &Reasons) -> Vec<CloseReason> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while reasons.lock().unwrap().is_empty() {
+        assert!(tokio::time::Instant::now() < deadline, "no close reason");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    reasons.lock().unwrap().clone()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_closed_by_the_server_are_told_why() {
+    let metrics = RecordingMetrics::default();
+    let (url, server_side) = start_server(&metrics).await;
+    let (client, client_side) = connect(&url).await;
+
+    assert_eq!(client.add(1).await.unwrap(), 1);
+    assert!(client.add(1).await.is_err());
+
+    assert_eq!(wait_for(&client_side).await, [CloseReason::RateLimited]);
+    assert_eq!(wait_for(&server_side).await, [CloseReason::RateLimited]);
+    assert_eq!(metrics.connections_closed(), [Some(1008)]);
+    assert_eq!(metrics.close_reasons(), ["rate_limited"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_leaving_are_reported_as_closed_by_the_client() {
+    let metrics = RecordingMetrics::default();
+    let (url, server_side) = start_server(&metrics).await;
+    let (client, client_side) = connect(&url).await;
+
+    client.disconnect().await.unwrap();
+    assert_eq!(wait_for(&client_side).await, [CloseReason::ClientClosed]);
+    assert_eq!(wait_for(&server_side).await, [CloseReason::ClientClosed]);
+    assert_eq!(metrics.close_reasons(), ["client_closed"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_reason_is_sent_before_and_in_the_close_frame() {
+    let metrics = RecordingMetrics::default();
+    let (url, server_side) = start_server(&metrics).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    socket.send(Message::Text("not json".into())).await.unwrap();
+
+    let mut announced = None;
+    let frame = loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("connection left open");
+        match message {
+            Some(Ok(Message::Text(text))) => {
+                if let Ok(BidirectionalMessage::ConnectionClosed { close_reason, .. }) =
+                    serde_json::from_str(&text)
+                {
+                    announced = close_reason;
+                }
+            }
+            Some(Ok(Message::Close(frame))) => break frame.expect("close frame"),
+            Some(Ok(_)) => {}
+            Some(Err(e)) => panic!("connection failed: {e}"),
+            None => panic!("connection dropped without a close frame"),
+        }
+    };
+
+    let expected = CloseReason::ProtocolViolation("Too many malformed messages".to_string());
+    assert_eq!(announced, Some(expected.clone()));
+    assert_eq!(frame.code, CloseCode::Policy);
+    assert_eq!(
+        CloseReason::from_close_frame(frame.code.into(), &frame.reason),
+        Some(expected.clone())
+    );
+    assert_eq!(wait_for(&server_side).await, [expected]);
+    assert_eq!(metrics.close_reasons(), ["protocol_violation"]);
+}
@@ -0,0 +1,173 @@
+//! Two generated services sharing connections through a `MemoryBus`, as two
+//! nodes behind a load balancer would through Redis.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_auth_core::AuthenticatedUser;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_server::{DefaultConnectionManager, MemoryBus};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId, ServerNotification};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Shout {
+    pub room: String,
+    pub text: String,
+}
+
+jsonrpc_bidirectional_service!({
+    service_name: Relay,
+    client_to_server: [
+        WITH_PERMISSIONS(["user"]) shout(Shout) -> usize,
+        WITH_PERMISSIONS(["user"]) whisper(ConnectionId) -> (),
+    ],
+    server_to_client: [
+        heard(String),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct RelayImpl;
+
+#[async_trait]
+impl RelayService for RelayImpl {
+    async fn shout(
+        &self,
+        _client: ConnectionId,
+        conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _user: &AuthenticatedUser,
+        shout: Shout,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(RelayTopicManager::new(conns)
+            .broadcast_heard(&shout.room, shout.text)
+            .await?)
+    }
+
+    async fn whisper(
+        &self,
+        client: ConnectionId,
+        conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _user: &AuthenticatedUser,
+        to: ConnectionId,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = BidirectionalMessage::ServerNotification(ServerNotification {
+            method: "heard".to_string(),
+            params: serde_json::json!(format!("from {client}")),
+            metadata: None,
+        });
+        conns.send_to_connection(to, message).await?;
+        Ok(())
+    }
+
+    async fn notify_heard(
+        &self,
+        _connection_id: ConnectionId,
+        _params: String,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    RelayHandler<RelayImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+/// Starts one node on `bus`, returning its URL and connection manager
+async fn start_node(bus: &MemoryBus, name: &str) -> (String, Arc<DefaultConnectionManager>) {
+    let manager = DefaultConnectionManager::with_registry(Arc::new(bus.node(name)))
+        .await
+        .unwrap();
+    let service: Service = RelayBuilder::new(RelayImpl, MockAuthProvider::default())
+        .require_auth(true)
+        .connection_manager(manager.clone())
+        .build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), manager)
+}
+
+async fn connect(url: &str, heard: &Arc<Mutex<Vec<String>>>) -> RelayClient {
+    let mut client = RelayClientBuilder::new(url)
+        .with_jwt_token("user-token".to_string())
+        .build()
+        .await
+        .expect("client build");
+    let heard = heard.clone();
+    client.on_heard(move |text: String| heard.lock().unwrap().push(text));
+    client.connect().await.expect("connect");
+    client
+}
+
+async fn wait_until(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(tokio::time::Instant::now() < deadline, "{what}");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn broadcasts_and_sends_cross_nodes() {
+    let bus = MemoryBus::new();
+    let (url_a, manager_a) = start_node(&bus, "a").await;
+    let (url_b, _manager_b) = start_node(&bus, "b").await;
+
+    let listener_heard = Arc::new(Mutex::new(Vec::new()));
+    let listener = connect(&url_a, &listener_heard).await;
+    let speaker = connect(&url_b, &Arc::default()).await;
+
+    let topic_heard = listener_heard.clone();
+    listener
+        .subscribe_topic(
+            "lobby",
+            RelayTopicHandlers::new()
+                .on_heard(move |text: String| topic_heard.lock().unwrap().push(text)),
+        )
+        .await
+        .unwrap();
+    wait_until("listener never subscribed", || {
+        manager_a.get_topic_connections("lobby").len() == 1
+    })
+    .await;
+
+    // Node b holds no subscribers itself, so it counts no local deliveries
+    let delivered = speaker
+        .shout(Shout {
+            room: "lobby".to_string(),
+            text: "hello".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(delivered, 0);
+    wait_until("shout never crossed nodes", || {
+        listener_heard
+            .lock()
+            .unwrap()
+            .contains(&"hello".to_string())
+    })
+    .await;
+
+    let listener_id = manager_a.get_connection_ids()[0];
+    speaker.whisper(listener_id).await.unwrap();
+    wait_until("whisper never crossed nodes", || {
+        listener_heard
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|text| text.starts_with("from "))
+    })
+    .await;
+}
@@ -0,0 +1,208 @@
+//! Binary codecs negotiated through the WebSocket subprotocol, and the JSON
+//! fallback for clients or servers that do not negotiate one.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::{SinkExt, StreamExt};
+use ras_jsonrpc_bidirectional_client::Codec;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId, ServerNotification};
+use ras_jsonrpc_types::JsonRpcRequest;
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chunk {
+    pub name: String,
+    pub embedding: Vec<f32>,
+    pub bytes: Vec<u8>,
+}
+
+jsonrpc_bidirectional_service!({
+    service_name: Store,
+    client_to_server: [
+        UNAUTHORIZED put(Chunk) -> Chunk,
+    ],
+    server_to_client: [
+        stored(Chunk),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct StoreImpl;
+
+#[async_trait]
+impl StoreService for StoreImpl {
+    /// Echoes the chunk, and announces it to the caller
+    async fn put(
+        &self,
+        client: ConnectionId,
+        conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        chunk: Chunk,
+    ) -> Result<Chunk, Box<dyn std::error::Error + Send + Sync>> {
+        let message = BidirectionalMessage::ServerNotification(ServerNotification {
+            method: "stored".to_string(),
+            params: serde_json::to_value(&chunk)?,
+            metadata: None,
+        });
+        conns.send_to_connection(client, message).await?;
+        Ok(chunk)
+    }
+
+    async fn notify_stored(
+        &self,
+        _connection_id: ConnectionId,
+        _params: Chunk,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    StoreHandler<StoreImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+async fn start_server(builder: StoreBuilder<StoreImpl, MockAuthProvider>) -> String {
+    let service: Service = builder.build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    format!("ws://{addr}/ws")
+}
+
+fn builder() -> StoreBuilder<StoreImpl, MockAuthProvider> {
+    StoreBuilder::new(StoreImpl, MockAuthProvider::default())
+}
+
+fn chunk() -> Chunk {
+    Chunk {
+        name: "page-1".to_string(),
+        embedding: vec![0.5, -0.25, 1.0e-3],
+        bytes: (0..=255).collect(),
+    }
+}
+
+/// Puts a chunk through the generated client, checking the response and the
+/// notification it triggers
+async fn round_trip(url: &str, codec: Codec) {
+    let stored = Arc::new(Mutex::new(Vec::new()));
+    let mut client = StoreClientBuilder::new(url)
+        .with_codec(codec)
+        .build()
+        .await
+        .expect("client build");
+    let recorder = stored.clone();
+    client.on_stored(move |chunk: Chunk| recorder.lock().unwrap().push(chunk));
+    client.connect().await.expect("connect");
+
+    assert_eq!(client.put(chunk()).await.unwrap(), chunk());
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while stored.lock().unwrap().is_empty() {
+        assert!(tokio::time::Instant::now() < deadline, "no notification");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(*stored.lock().unwrap(), [chunk()]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn messagepack_and_cbor_round_trip() {
+    let url = start_server(builder()).await;
+    round_trip(&url, Codec::MessagePack).await;
+    round_trip(&url, Codec::Cbor).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn negotiated_connections_use_binary_frames() {
+    let url = start_server(builder()).await;
+    let mut request = url.into_client_request().unwrap();
+    request
+        .headers_mut()
+        .insert("sec-websocket-protocol", "ras.cbor".parse().unwrap());
+    let (mut socket, response) = tokio_tungstenite::connect_async(request)
+        .await
+        .expect("connect");
+    assert_eq!(response.headers()["sec-websocket-protocol"], "ras.cbor");
+
+    let Some(Ok(Message::Binary(established))) = socket.next().await else {
+        panic!("expected a binary frame");
+    };
+    assert!(matches!(
+        Codec::Cbor.decode(&established).unwrap(),
+        BidirectionalMessage::ConnectionEstablished { .. }
+    ));
+
+    let request = BidirectionalMessage::Request(JsonRpcRequest::new(
+        "put".to_string(),
+        Some(serde_json::to_value(chunk()).unwrap()),
+        Some(json!(1)),
+    ));
+    let ras_jsonrpc_bidirectional_types::Frame::Binary(bytes) =
+        Codec::Cbor.encode(&request).unwrap()
+    else {
+        panic!("CBOR encodes to binary");
+    };
+    socket.send(Message::Binary(bytes.into())).await.unwrap();
+
+    // A JSON text frame is still understood on a binary connection
+    let ping = serde_json::to_string(&BidirectionalMessage::Ping).unwrap();
+    socket.send(Message::Text(ping.into())).await.unwrap();
+
+    let mut response = None;
+    let mut pong = false;
+    while response.is_none() || !pong {
+        let frame = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("server went quiet")
+            .unwrap()
+            .unwrap();
+        let Message::Binary(bytes) = frame else {
+            panic!("expected binary frames only, got {frame:?}");
+        };
+        match Codec::Cbor.decode(&bytes).unwrap() {
+            BidirectionalMessage::Response(r) => response = Some(r),
+            BidirectionalMessage::Pong => pong = true,
+            _ => {}
+        }
+    }
+    let response = response.unwrap();
+    assert_eq!(response.id, Some(json!(1)));
+    assert_eq!(
+        serde_json::from_value::<Chunk>(response.result.unwrap()).unwrap(),
+        chunk()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn json_clients_keep_text_frames_on_a_binary_server() {
+    let url = start_server(builder()).await;
+    round_trip(&url, Codec::Json).await;
+
+    let (mut socket, response) = tokio_tungstenite::connect_async(&url)
+        .await
+        .expect("connect");
+    assert!(!response.headers().contains_key("sec-websocket-protocol"));
+    let Some(Ok(Message::Text(established))) = socket.next().await else {
+        panic!("expected a text frame");
+    };
+    assert!(established.contains("connection_established"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn binary_clients_fall_back_to_json_when_the_server_declines() {
+    let url = start_server(builder().codecs([])).await;
+    round_trip(&url, Codec::MessagePack).await;
+}
@@ -0,0 +1,202 @@
+//! Connection-scoped state in the `ConnectionContext` passed to generated
+//! handlers, and the builder's connect/disconnect hooks.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_server::{ConnectionContext, DefaultConnectionManager};
+use ras_jsonrpc_bidirectional_types::ConnectionId;
+use ras_test_helpers::MockAuthProvider;
+
+jsonrpc_bidirectional_service!({
+    service_name: Docs,
+    client_to_server: [
+        UNAUTHORIZED open(String) -> (),
+        UNAUTHORIZED current(()) -> Option<String>,
+        UNAUTHORIZED peer(()) -> String,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+/// The document a connection is viewing
+#[derive(Clone)]
+struct ViewedDocument(String);
+
+/// Options agreed on when the connection opened
+#[derive(Clone)]
+struct Negotiated {
+    peer: String,
+    /// Lets tests observe when the connection's state is dropped
+    _alive: Arc<()>,
+}
+
+#[derive(Clone)]
+struct DocsImpl;
+
+#[async_trait]
+impl DocsService for DocsImpl {
+    async fn open(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        ctx: &ConnectionContext,
+        document: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        ctx.insert(ViewedDocument(document));
+        Ok(())
+    }
+
+    async fn current(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        ctx: &ConnectionContext,
+        _request: (),
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ctx.get::<ViewedDocument>().map(|ViewedDocument(name)| name))
+    }
+
+    async fn peer(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        ctx: &ConnectionContext,
+        _request: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let negotiated = ctx
+            .get::<Negotiated>()
+            .ok_or("connection was not negotiated")?;
+        Ok(negotiated.peer)
+    }
+}
+
+type Service = BuiltWebSocketService<
+    DocsHandler<DocsImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+/// What the hooks observed
+#[derive(Default)]
+struct Observed {
+    /// Weak references to each connection's negotiated state
+    negotiated: Mutex<Vec<Weak<()>>>,
+    /// Documents still open when connections closed
+    closed_with: Mutex<Vec<Option<String>>>,
+}
+
+async fn start_server(observed: Arc<Observed>) -> String {
+    let on_connect = observed.clone();
+    let on_disconnect = observed;
+    let service: Service = DocsBuilder::new(DocsImpl, MockAuthProvider::default())
+        .on_connect(move |ctx| {
+            let observed = on_connect.clone();
+            async move {
+                let alive = Arc::new(());
+                observed
+                    .negotiated
+                    .lock()
+                    .unwrap()
+                    .push(Arc::downgrade(&alive));
+                let peer = ctx.remote_addr.expect("connect info").ip().to_string();
+                assert!(ctx.connected_at().await <= chrono::Utc::now());
+                ctx.insert(Negotiated {
+                    peer,
+                    _alive: alive,
+                });
+            }
+        })
+        .on_disconnect(move |ctx| {
+            let observed = on_disconnect.clone();
+            async move {
+                let document = ctx.get::<ViewedDocument>().map(|ViewedDocument(name)| name);
+                observed.closed_with.lock().unwrap().push(document);
+            }
+        })
+        .build();
+
+    let app = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+    format!("ws://{addr}/ws")
+}
+
+async fn connect(url: &str) -> DocsClient {
+    let client = DocsClientBuilder::new(url)
+        .build()
+        .await
+        .expect("client build");
+    client.connect().await.expect("connect");
+    client
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn handlers_share_state_scoped_to_their_connection() {
+    let observed = Arc::new(Observed::default());
+    let url = start_server(observed.clone()).await;
+    let first = connect(&url).await;
+    let second = connect(&url).await;
+
+    assert_eq!(first.current(()).await.unwrap(), None);
+    first.open("design.md".to_string()).await.unwrap();
+    second.open("notes.md".to_string()).await.unwrap();
+    assert_eq!(
+        first.current(()).await.unwrap().as_deref(),
+        Some("design.md")
+    );
+    assert_eq!(
+        second.current(()).await.unwrap().as_deref(),
+        Some("notes.md")
+    );
+
+    // Set by the connect hook, from the peer address
+    assert_eq!(first.peer(()).await.unwrap(), "127.0.0.1");
+
+    first.disconnect().await.unwrap();
+    second.disconnect().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn state_is_dropped_after_the_disconnect_hook() {
+    let observed = Arc::new(Observed::default());
+    let url = start_server(observed.clone()).await;
+    let client = connect(&url).await;
+    client.open("draft.md".to_string()).await.unwrap();
+
+    let negotiated = observed.negotiated.lock().unwrap()[0].clone();
+    assert!(negotiated.upgrade().is_some());
+
+    client.disconnect().await.unwrap();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while negotiated.upgrade().is_some() {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "connection state outlived the connection"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // The disconnect hook still saw the connection's state
+    assert_eq!(
+        *observed.closed_with.lock().unwrap(),
+        [Some("draft.md".to_string())]
+    );
+}
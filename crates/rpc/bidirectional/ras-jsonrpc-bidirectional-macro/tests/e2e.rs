@@ -57,6 +57,7 @@ impl DemoService for DemoImpl {
         &self,
         _client: ConnectionId,
         _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         name: String,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         Ok(format!("hello, {name}"))
@@ -66,6 +67,7 @@ impl DemoService for DemoImpl {
         &self,
         client: ConnectionId,
         conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         user: &AuthenticatedUser,
         req: EchoIn,
     ) -> Result<EchoOut, Box<dyn std::error::Error + Send + Sync>> {
@@ -0,0 +1,210 @@
+//! Notification filters: clients register a typed filter, and filtered sends
+//! only reach the connections whose filter matches, on whichever node they are.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_server::{DefaultConnectionManager, MemoryBus};
+use ras_jsonrpc_bidirectional_types::ConnectionId;
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskUpdate {
+    pub project: u32,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskFilter {
+    pub project: u32,
+}
+
+jsonrpc_bidirectional_service!({
+    service_name: Board,
+    client_to_server: [
+        UNAUTHORIZED ping(()) -> (),
+    ],
+    server_to_client: [
+        task_updated(TaskUpdate) filter TaskFilter,
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct BoardImpl;
+
+#[async_trait]
+impl BoardService for BoardImpl {
+    async fn ping(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _request: (),
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+
+    async fn notify_task_updated(
+        &self,
+        _connection_id: ConnectionId,
+        _params: TaskUpdate,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    BoardHandler<BoardImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+type Updates = Arc<Mutex<Vec<TaskUpdate>>>;
+
+/// Starts one node on `bus`, returning its URL and service
+async fn start_node(bus: &MemoryBus, name: &str) -> (String, Service) {
+    let manager = DefaultConnectionManager::with_registry(Arc::new(bus.node(name)))
+        .await
+        .unwrap();
+    let service: Service = BoardBuilder::new(BoardImpl, MockAuthProvider::default())
+        .connection_manager(manager)
+        .build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service.clone());
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), service)
+}
+
+/// Connects a client recording the task updates it receives, filtering them
+/// to `project` if given
+async fn connect(url: &str, project: Option<u32>) -> (BoardClient, Updates) {
+    let mut client = BoardClientBuilder::new(url).build().await.unwrap();
+    let updates = Updates::default();
+    let received = updates.clone();
+    client.on_task_updated(move |update| received.lock().unwrap().push(update));
+    client.connect().await.unwrap();
+    if let Some(project) = project {
+        client
+            .set_task_updated_filter(TaskFilter { project })
+            .await
+            .unwrap();
+    }
+    (client, updates)
+}
+
+fn update(project: u32) -> TaskUpdate {
+    TaskUpdate {
+        project,
+        title: format!("Task of project {project}"),
+    }
+}
+
+async fn wait_for(updates: &Updates) -> Vec<TaskUpdate> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while updates.lock().unwrap().is_empty() {
+        assert!(tokio::time::Instant::now() < deadline, "no update arrived");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    updates.lock().unwrap().clone()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn filtered_notifications_reach_matching_connections_on_every_node() {
+    let bus = MemoryBus::new();
+    let (url_a, service_a) = start_node(&bus, "a").await;
+    let (url_b, _service_b) = start_node(&bus, "b").await;
+    let (_alice, alice_updates) = connect(&url_a, Some(1)).await;
+    let (_bob, bob_updates) = connect(&url_b, Some(2)).await;
+    let (_carol, carol_updates) = connect(&url_a, None).await;
+
+    let handler = service_a.handler();
+    let sent = handler
+        .notify_task_updated_where(|filter| filter.project == 1, update(1))
+        .await
+        .unwrap();
+    assert_eq!(sent, 1);
+    assert_eq!(wait_for(&alice_updates).await, [update(1)]);
+
+    // Bob's filter was registered on node b, and is matched from node a
+    let sent = handler
+        .notify_task_updated_where(|filter| filter.project == 2, update(2))
+        .await
+        .unwrap();
+    assert_eq!(sent, 1);
+    assert_eq!(wait_for(&bob_updates).await, [update(2)]);
+
+    // Connections without a filter receive no filtered notifications
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(carol_updates.lock().unwrap().is_empty());
+    assert_eq!(alice_updates.lock().unwrap().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn filters_are_checked_and_can_be_cleared() {
+    let bus = MemoryBus::new();
+    let (url, service) = start_node(&bus, "a").await;
+    let (alice, _updates) = connect(&url, Some(1)).await;
+
+    // Filters must read back as the declared type, for a filtered notification
+    let refused = alice
+        .client()
+        .set_notification_filter("task_updated", Some(json!({ "project": "one" })))
+        .await
+        .unwrap_err();
+    assert!(refused.to_string().contains("Invalid filter"), "{refused}");
+    let refused = alice
+        .client()
+        .set_notification_filter("ping", Some(json!({})))
+        .await
+        .unwrap_err();
+    assert!(refused.to_string().contains("takes no filter"), "{refused}");
+
+    let handler = service.handler();
+    let matching = |filter: &TaskFilter| filter.project == 1;
+    assert_eq!(
+        handler
+            .notify_task_updated_where(matching, update(1))
+            .await
+            .unwrap(),
+        1
+    );
+    alice.clear_task_updated_filter().await.unwrap();
+    assert_eq!(
+        handler
+            .notify_task_updated_where(matching, update(1))
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Closing the connection drops its filter
+    alice
+        .set_task_updated_filter(TaskFilter { project: 1 })
+        .await
+        .unwrap();
+    alice.disconnect().await.unwrap();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while handler
+        .notify_task_updated_where(matching, update(1))
+        .await
+        .unwrap()
+        > 0
+    {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "filter outlived its connection"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
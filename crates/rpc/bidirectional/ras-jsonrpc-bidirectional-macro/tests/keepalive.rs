@@ -0,0 +1,179 @@
+//! Keepalive between the generated client and server: the server closes
+//! connections that stop answering its pings, and the client drops servers that
+//! stop answering its heartbeats.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::SinkExt;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_server::{DefaultConnectionManager, KeepaliveConfig};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId, ConnectionManager};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use tokio_tungstenite::tungstenite::Message;
+
+jsonrpc_bidirectional_service!({
+    service_name: Heartbeat,
+    client_to_server: [
+        UNAUTHORIZED echo(String) -> String,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct HeartbeatImpl;
+
+#[async_trait]
+impl HeartbeatService for HeartbeatImpl {
+    async fn echo(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        text: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(text)
+    }
+}
+
+type Service = BuiltWebSocketService<
+    HeartbeatHandler<HeartbeatImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+/// Starts a server pinging every 50ms and closing connections after 2 missed pongs.
+async fn start_server() -> (String, Arc<DefaultConnectionManager>) {
+    let service = HeartbeatBuilder::new(HeartbeatImpl, MockAuthProvider::default())
+        .keepalive(KeepaliveConfig::new(Duration::from_millis(50), 2))
+        .build();
+    let connection_manager = service.connection_manager();
+
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), connection_manager)
+}
+
+async fn wait_for_connections(manager: &DefaultConnectionManager, count: usize) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while manager.connection_count() != count {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "expected {count} connections, found {}",
+            manager.connection_count()
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_drops_connections_that_stop_answering_pings() {
+    let (url, manager) = start_server().await;
+
+    // Pongs are only sent while the stream is read, so this client never answers
+    let (_silent, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .expect("connect");
+    wait_for_connections(&manager, 1).await;
+
+    wait_for_connections(&manager, 0).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn healthy_clients_stay_connected_and_their_last_seen_advances() {
+    let (url, manager) = start_server().await;
+
+    let client = HeartbeatClientBuilder::new(url)
+        .with_heartbeat_interval(None)
+        .build()
+        .await
+        .expect("client build");
+    client.connect().await.expect("connect");
+    wait_for_connections(&manager, 1).await;
+
+    let info = manager.get_all_connections().await.unwrap().remove(0);
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    // Several pings went out; the client's pongs kept the connection open
+    assert_eq!(manager.connection_count(), 1);
+    let last_seen = manager.last_seen(info.id).await.unwrap().unwrap();
+    assert!(last_seen > info.connected_at);
+    assert_eq!(
+        client.echo("still here".to_string()).await.unwrap(),
+        "still here"
+    );
+
+    client.disconnect().await.expect("disconnect");
+}
+
+/// Accepts WebSocket connections and announces them, but never reads from them.
+async fn start_unresponsive_server() -> (String, Arc<AtomicU32>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = Arc::new(AtomicU32::new(0));
+    let accepted_count = accepted.clone();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            accepted_count.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                let established = BidirectionalMessage::ConnectionEstablished {
+                    connection_id: ConnectionId::new(),
+                };
+                let text = serde_json::to_string(&established).unwrap();
+                ws.send(Message::Text(text.into())).await.unwrap();
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                drop(ws);
+            });
+        }
+    });
+
+    (format!("ws://{addr}/ws"), accepted)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_reconnects_when_heartbeats_go_unanswered() {
+    let (url, accepted) = start_unresponsive_server().await;
+
+    let reconnects = Arc::new(AtomicU32::new(0));
+    let reconnects_seen = reconnects.clone();
+    let client = HeartbeatClientBuilder::new(url)
+        .with_heartbeat_interval(Some(Duration::from_millis(50)))
+        .with_max_missed_heartbeats(2)
+        .with_reconnect_config(
+            ras_jsonrpc_bidirectional_client::ReconnectConfig::builder()
+                .initial_delay(Duration::from_millis(10))
+                .build(),
+        )
+        .with_on_reconnect(move |_attempts| {
+            reconnects_seen.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .await
+        .expect("client build");
+    client.connect().await.expect("connect");
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while reconnects.load(Ordering::SeqCst) == 0 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "client never gave up on the silent server"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(accepted.load(Ordering::SeqCst) >= 2);
+
+    client.disconnect().await.expect("disconnect");
+}
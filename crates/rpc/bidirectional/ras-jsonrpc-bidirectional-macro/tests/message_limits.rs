@@ -0,0 +1,193 @@
+//! Message size limits and malformed frames: bad input is answered with
+//! JSON-RPC errors and counted, and only closes the connection past the limits.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::{SinkExt, StreamExt};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId};
+use ras_jsonrpc_types::{JsonRpcResponse, error_codes};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde_json::json;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+jsonrpc_bidirectional_service!({
+    service_name: Echo,
+    client_to_server: [
+        UNAUTHORIZED echo(String) -> String,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct EchoImpl;
+
+#[async_trait]
+impl EchoService for EchoImpl {
+    async fn echo(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        text: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(text)
+    }
+}
+
+type Service = BuiltWebSocketService<
+    EchoHandler<EchoImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(builder: EchoBuilder<EchoImpl, MockAuthProvider>) -> (Socket, Service) {
+    let service: Service = builder.build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service.clone());
+    let (addr, _handle) = spawn_tcp(app).await;
+    let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+        .await
+        .expect("connect");
+    (socket, service)
+}
+
+fn builder() -> EchoBuilder<EchoImpl, MockAuthProvider> {
+    EchoBuilder::new(EchoImpl, MockAuthProvider::default())
+}
+
+async fn send(socket: &mut Socket, text: impl Into<String>) {
+    socket
+        .send(Message::Text(text.into().into()))
+        .await
+        .unwrap();
+}
+
+async fn call(socket: &mut Socket, id: u64, text: &str) {
+    let request = json!({"jsonrpc": "2.0", "method": "echo", "params": text, "id": id});
+    send(socket, request.to_string()).await;
+}
+
+/// What the server sent next: a response, or how it closed the connection
+#[derive(Debug)]
+enum Next {
+    Response(JsonRpcResponse),
+    Closed(Option<CloseCode>),
+}
+
+async fn next(socket: &mut Socket) -> Next {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("server went quiet");
+        match message {
+            Some(Ok(Message::Text(text))) => {
+                if let Ok(BidirectionalMessage::Response(response)) =
+                    serde_json::from_str::<BidirectionalMessage>(&text)
+                {
+                    return Next::Response(response);
+                }
+            }
+            Some(Ok(Message::Close(frame))) => return Next::Closed(frame.map(|f| f.code)),
+            Some(Ok(_)) => {}
+            Some(Err(_)) | None => return Next::Closed(None),
+        }
+    }
+}
+
+async fn response(socket: &mut Socket) -> JsonRpcResponse {
+    match next(socket).await {
+        Next::Response(response) => response,
+        Next::Closed(code) => panic!("connection closed with {code:?}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn malformed_frames_get_parse_errors_and_keep_the_connection() {
+    let (mut socket, service) = connect(builder()).await;
+
+    send(&mut socket, r#"{"jsonrpc": "2.0", "method": "echo", "#).await;
+    let error = response(&mut socket).await;
+    assert_eq!(error.error.unwrap().code, error_codes::PARSE_ERROR);
+    assert_eq!(error.id, None);
+
+    // Valid JSON that is not a message keeps its id
+    send(&mut socket, r#"{"id": 7, "nonsense": true}"#).await;
+    let error = response(&mut socket).await;
+    assert_eq!(error.error.unwrap().code, error_codes::PARSE_ERROR);
+    assert_eq!(error.id, Some(json!(7)));
+
+    socket
+        .send(Message::Binary(vec![0xff, 0xfe].into()))
+        .await
+        .unwrap();
+    let error = response(&mut socket).await;
+    assert_eq!(error.error.unwrap().code, error_codes::PARSE_ERROR);
+
+    call(&mut socket, 8, "still here").await;
+    let echoed = response(&mut socket).await;
+    assert_eq!(echoed.result, Some(json!("still here")));
+    assert_eq!(service.frame_stats().unwrap().malformed(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn too_many_malformed_frames_close_the_connection() {
+    let (mut socket, service) = connect(builder().max_malformed_frames(2)).await;
+
+    send(&mut socket, "not json").await;
+    assert!(matches!(next(&mut socket).await, Next::Response(_)));
+    send(&mut socket, "still not json").await;
+    assert!(matches!(next(&mut socket).await, Next::Response(_)));
+
+    assert!(matches!(
+        next(&mut socket).await,
+        Next::Closed(Some(CloseCode::Policy))
+    ));
+    assert_eq!(service.frame_stats().unwrap().malformed(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversized_messages_close_with_message_too_big() {
+    let (mut socket, service) = connect(builder().max_message_size(1024)).await;
+
+    call(&mut socket, 1, "small").await;
+    assert_eq!(response(&mut socket).await.result, Some(json!("small")));
+
+    call(&mut socket, 2, &"x".repeat(4096)).await;
+    assert!(matches!(
+        next(&mut socket).await,
+        Next::Closed(Some(CloseCode::Size))
+    ));
+    assert_eq!(service.frame_stats().unwrap().oversized_incoming(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversized_responses_are_replaced_with_an_error() {
+    let (mut socket, service) = connect(builder().max_outgoing_message_size(256)).await;
+
+    call(&mut socket, 1, &"x".repeat(1000)).await;
+    let error = response(&mut socket).await;
+    assert_eq!(error.id, Some(json!(1)));
+    let error = error.error.unwrap();
+    assert_eq!(error.code, error_codes::INTERNAL_ERROR);
+    assert_eq!(error.message, "Response too large");
+
+    call(&mut socket, 2, "fits").await;
+    assert_eq!(response(&mut socket).await.result, Some(json!("fits")));
+    assert_eq!(service.frame_stats().unwrap().oversized_outgoing(), 1);
+}
@@ -0,0 +1,171 @@
+//! Connection, message, call and broadcast metrics reported through
+//! `with_service_metrics`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::{SinkExt, StreamExt};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_types::ConnectionId;
+use ras_test_helpers::{CompletedRequest, Fanout, MockAuthProvider, RecordingMetrics, spawn_tcp};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+jsonrpc_bidirectional_service!({
+    service_name: Counter,
+    client_to_server: [
+        UNAUTHORIZED add(i64) -> i64,
+        UNAUTHORIZED fail(()) -> (),
+    ],
+    server_to_client: [
+        total(i64),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct CounterImpl;
+
+#[async_trait]
+impl CounterService for CounterImpl {
+    async fn add(
+        &self,
+        _client: ConnectionId,
+        conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        amount: i64,
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        CounterTopicManager::new(conns)
+            .broadcast_total("totals", amount)
+            .await?;
+        Ok(amount)
+    }
+
+    async fn fail(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _request: (),
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Err("nope".into())
+    }
+
+    async fn notify_total(
+        &self,
+        _connection_id: ConnectionId,
+        _params: i64,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    CounterHandler<CounterImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+async fn start_server() -> (String, Arc<DefaultConnectionManager>, RecordingMetrics) {
+    let metrics = RecordingMetrics::default();
+    let service = CounterBuilder::new(CounterImpl, MockAuthProvider::default())
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .build();
+    let manager = service.connection_manager();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), manager, metrics)
+}
+
+async fn wait_until(what: &str, done: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(tokio::time::Instant::now() < deadline, "{what}");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn calls_and_broadcasts_are_recorded() {
+    let (url, manager, metrics) = start_server().await;
+    let client = CounterClientBuilder::new(&url).build().await.unwrap();
+    client.connect().await.unwrap();
+    client
+        .subscribe_topic("totals", CounterTopicHandlers::new())
+        .await
+        .unwrap();
+    wait_until("never subscribed", || {
+        manager.get_topic_connections("totals").len() == 1
+    })
+    .await;
+
+    assert_eq!(client.add(3).await.unwrap(), 3);
+    assert!(client.fail(()).await.is_err());
+    assert_eq!(metrics.connections_opened(), 1);
+    assert_eq!(metrics.started(), ["add", "fail"]);
+    assert_eq!(
+        metrics.completed(),
+        [
+            CompletedRequest {
+                method: "add".to_string(),
+                success: true,
+                error_kind: None,
+            },
+            CompletedRequest {
+                method: "fail".to_string(),
+                success: false,
+                error_kind: Some("handler_error".to_string()),
+            },
+        ]
+    );
+    assert_eq!(
+        metrics.fanouts(),
+        [Fanout {
+            method: "total".to_string(),
+            recipients: 1,
+        }]
+    );
+    // Received: the subscription and both calls; sent: their replies and the broadcast
+    assert!(metrics.messages_received() >= 3);
+    assert!(metrics.messages_sent() >= 4);
+
+    client.disconnect().await.unwrap();
+    wait_until("close not recorded", || {
+        metrics.connections_closed().len() == 1
+    })
+    .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_clients_close_code_is_recorded() {
+    let (url, _manager, metrics) = start_server().await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .unwrap();
+    // Wait for the connection established message
+    socket.next().await.unwrap().unwrap();
+
+    socket
+        .send(Message::Close(Some(CloseFrame {
+            code: CloseCode::Normal,
+            reason: "bye".into(),
+        })))
+        .await
+        .unwrap();
+    wait_until("close not recorded", || {
+        !metrics.connections_closed().is_empty()
+    })
+    .await;
+    assert_eq!(metrics.connections_opened(), 1);
+    assert_eq!(metrics.connections_closed(), [Some(1000)]);
+}
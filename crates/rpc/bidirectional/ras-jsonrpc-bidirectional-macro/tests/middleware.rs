@@ -0,0 +1,184 @@
+//! Message middleware: requests can be rewritten or refused before they are
+//! dispatched, and outgoing messages rewritten before they are sent.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::{SinkExt, StreamExt};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_server::{
+    ConnectionContext, ControlFlow, DefaultConnectionManager, MessageMiddleware, OutgoingMessage,
+};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId};
+use ras_jsonrpc_types::{JsonRpcError, JsonRpcRequest, JsonRpcResponse};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde_json::json;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+jsonrpc_bidirectional_service!({
+    service_name: Calc,
+    client_to_server: [
+        UNAUTHORIZED add(i64) -> i64,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct CalcImpl;
+
+#[async_trait]
+impl CalcService for CalcImpl {
+    async fn add(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        amount: i64,
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(amount + 1)
+    }
+}
+
+type Service = BuiltWebSocketService<
+    CalcHandler<CalcImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Serves `add_v1` as `add`, refuses `legacy` and `banned`
+struct VersionShim;
+
+#[async_trait]
+impl MessageMiddleware for VersionShim {
+    async fn on_incoming(
+        &self,
+        _ctx: &ConnectionContext,
+        request: &mut JsonRpcRequest,
+    ) -> ControlFlow {
+        match request.method.as_str() {
+            "add_v1" => {
+                request.method = "add".to_string();
+                ControlFlow::Continue
+            }
+            "legacy" => ControlFlow::Reject,
+            "banned" => ControlFlow::RejectWith(JsonRpcError::new(
+                -32090,
+                "Banned method".to_string(),
+                None,
+            )),
+            _ => ControlFlow::Continue,
+        }
+    }
+}
+
+/// Records the methods it sees and doubles numeric results
+#[derive(Clone, Default)]
+struct Doubler {
+    seen: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl MessageMiddleware for Doubler {
+    async fn on_incoming(
+        &self,
+        _ctx: &ConnectionContext,
+        request: &mut JsonRpcRequest,
+    ) -> ControlFlow {
+        self.seen.lock().unwrap().push(request.method.clone());
+        ControlFlow::Continue
+    }
+
+    async fn on_outgoing(&self, _ctx: &ConnectionContext, message: &mut OutgoingMessage) {
+        if let BidirectionalMessage::Response(JsonRpcResponse {
+            result: Some(result),
+            ..
+        }) = message
+            && let Some(value) = result.as_i64()
+        {
+            *result = json!(value * 2);
+        }
+    }
+}
+
+async fn connect(doubler: Doubler) -> Socket {
+    let service = CalcBuilder::new(CalcImpl, MockAuthProvider::default())
+        .with_middleware(VersionShim)
+        .with_middleware(doubler)
+        .middleware_rejection(JsonRpcError::new(
+            -32091,
+            "Upgrade your client".to_string(),
+            None,
+        ))
+        .build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+        .await
+        .unwrap();
+    // Wait for the connection established message
+    socket.next().await.unwrap().unwrap();
+    socket
+}
+
+async fn call(socket: &mut Socket, method: &str, params: serde_json::Value) -> JsonRpcResponse {
+    let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+    socket
+        .send(Message::Text(request.to_string().into()))
+        .await
+        .unwrap();
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("response missing")
+            .unwrap()
+            .unwrap();
+        if let Message::Text(text) = message
+            && let Ok(BidirectionalMessage::Response(response)) = serde_json::from_str(&text)
+        {
+            return response;
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_are_rewritten_before_dispatch() {
+    let doubler = Doubler::default();
+    let mut socket = connect(doubler.clone()).await;
+
+    let response = call(&mut socket, "add_v1", json!(2)).await;
+    assert_eq!(response.result, Some(json!(6)));
+    let response = call(&mut socket, "add", json!(4)).await;
+    assert_eq!(response.result, Some(json!(10)));
+    // Later layers see the request as rewritten by earlier ones
+    assert_eq!(*doubler.seen.lock().unwrap(), ["add", "add"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refused_requests_are_answered_with_the_rejection_error() {
+    let doubler = Doubler::default();
+    let mut socket = connect(doubler.clone()).await;
+
+    let response = call(&mut socket, "legacy", json!(null)).await;
+    let error = response.error.unwrap();
+    assert_eq!(error.code, -32091);
+    assert_eq!(error.message, "Upgrade your client");
+
+    let response = call(&mut socket, "banned", json!(null)).await;
+    assert_eq!(response.error.unwrap().code, -32090);
+
+    // Refused requests never reach later layers
+    assert!(doubler.seen.lock().unwrap().is_empty());
+}
@@ -0,0 +1,223 @@
+//! Presence: who is present under a key, with join and leave events for the
+//! other members, counted per user across connections and cleaned up when a
+//! connection drops.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::{SinkExt, StreamExt};
+use ras_jsonrpc_bidirectional_client::PresenceEvent;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId, ConnectionManager};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+
+jsonrpc_bidirectional_service!({
+    service_name: Docs,
+    client_to_server: [
+        UNAUTHORIZED status(()) -> (),
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct DocsImpl;
+
+#[async_trait]
+impl DocsService for DocsImpl {
+    async fn status(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _request: (),
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    DocsHandler<DocsImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+/// Presence events seen by one client, as `(event, user or connection)`
+type Events = Arc<Mutex<Vec<(&'static str, String)>>>;
+
+async fn start_server() -> (String, Arc<DefaultConnectionManager>) {
+    let service = DocsBuilder::new(DocsImpl, MockAuthProvider::default())
+        .with_topic_authorizer(|_user, key| key != "secret")
+        .build();
+    let manager = service.connection_manager();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), manager)
+}
+
+fn member(event: &PresenceEvent) -> String {
+    event
+        .entry
+        .user_id
+        .clone()
+        .unwrap_or_else(|| event.entry.connection_id.to_string())
+}
+
+/// Connect as the user of `token`, recording the presence events it receives
+async fn connect(url: &str, token: &str) -> (DocsClient, Events) {
+    let mut client = DocsClientBuilder::new(url)
+        .with_jwt_token(token.to_string())
+        .build()
+        .await
+        .unwrap();
+    let events = Events::default();
+    let joined = events.clone();
+    client.on_presence_joined(move |event| joined.lock().unwrap().push(("joined", member(&event))));
+    let left = events.clone();
+    client.on_presence_left(move |event| left.lock().unwrap().push(("left", member(&event))));
+    client.connect().await.unwrap();
+    (client, events)
+}
+
+async fn wait_for(events: &Events, count: usize) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while events.lock().unwrap().len() < count {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "events missing: {:?}",
+            events.lock().unwrap()
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+fn recorded(events: &Events) -> Vec<(&'static str, String)> {
+    events.lock().unwrap().clone()
+}
+
+fn joined(user: &str) -> (&'static str, String) {
+    ("joined", user.to_string())
+}
+
+fn left(user: &str) -> (&'static str, String) {
+    ("left", user.to_string())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn members_are_told_when_others_join_and_leave() {
+    let (url, _manager) = start_server().await;
+    let (alice, alice_events) = connect(&url, "user-token").await;
+    let (bob, bob_events) = connect(&url, "admin-token").await;
+
+    let entry = alice
+        .join_presence("doc", Some(json!({ "color": "red" })))
+        .await
+        .unwrap();
+    assert_eq!(entry.user_id.as_deref(), Some("user-1"));
+    assert_eq!(entry.metadata, Some(json!({ "color": "red" })));
+    bob.join_presence("doc", None).await.unwrap();
+    wait_for(&alice_events, 1).await;
+    assert_eq!(recorded(&alice_events), [joined("admin-1")]);
+
+    let present = bob.list_presence("doc").await.unwrap();
+    let users: Vec<_> = present.iter().map(|e| e.user_id.clone().unwrap()).collect();
+    assert_eq!(users, ["user-1", "admin-1"]);
+    assert_eq!(present[0].metadata, Some(json!({ "color": "red" })));
+
+    assert!(bob.leave_presence("doc").await.unwrap());
+    assert!(!bob.leave_presence("doc").await.unwrap());
+    wait_for(&alice_events, 2).await;
+    assert_eq!(
+        recorded(&alice_events),
+        [joined("admin-1"), left("admin-1")]
+    );
+    assert_eq!(alice.list_presence("doc").await.unwrap().len(), 1);
+    // Bob joined after Alice, so he never heard of her joining
+    assert!(recorded(&bob_events).is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn users_on_several_connections_are_announced_once() {
+    let (url, _manager) = start_server().await;
+    let (watcher, events) = connect(&url, "admin-token").await;
+    watcher.join_presence("doc", None).await.unwrap();
+
+    let (first, _) = connect(&url, "user-token").await;
+    let (second, _) = connect(&url, "user-token").await;
+    first.join_presence("doc", None).await.unwrap();
+    second.join_presence("doc", None).await.unwrap();
+    assert_eq!(watcher.list_presence("doc").await.unwrap().len(), 3);
+
+    // The user is still present through the second connection
+    first.leave_presence("doc").await.unwrap();
+    let (other, _) = connect(&url, "readonly-token").await;
+    other.join_presence("doc", None).await.unwrap();
+    wait_for(&events, 2).await;
+    assert_eq!(recorded(&events), [joined("user-1"), joined("ro-1")]);
+
+    second.disconnect().await.unwrap();
+    wait_for(&events, 3).await;
+    assert_eq!(
+        recorded(&events),
+        [joined("user-1"), joined("ro-1"), left("user-1")]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn crashed_connections_leave_their_keys() {
+    let (url, manager) = start_server().await;
+    let (watcher, events) = connect(&url, "user-token").await;
+    watcher.join_presence("doc", None).await.unwrap();
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    let Some(Ok(Message::Text(text))) = socket.next().await else {
+        panic!("connection not established");
+    };
+    let Ok(BidirectionalMessage::ConnectionEstablished { connection_id }) =
+        serde_json::from_str(&text)
+    else {
+        panic!("unexpected first message: {text}");
+    };
+    let request = json!({
+        "jsonrpc": "2.0",
+        "method": "presence.join",
+        "params": { "key": "doc" },
+        "id": 1,
+    });
+    socket
+        .send(Message::Text(request.to_string().into()))
+        .await
+        .unwrap();
+    wait_for(&events, 1).await;
+    assert_eq!(recorded(&events), [joined(&connection_id.to_string())]);
+
+    // Drop the connection without a close frame
+    drop(socket);
+    wait_for(&events, 2).await;
+    assert_eq!(recorded(&events)[1], left(&connection_id.to_string()));
+    let present = manager.list_presence("doc").await.unwrap();
+    assert_eq!(present.len(), 1);
+    assert_eq!(present[0].user_id.as_deref(), Some("user-1"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn presence_is_subject_to_the_topic_authorizer() {
+    let (url, manager) = start_server().await;
+    let (client, _) = connect(&url, "user-token").await;
+
+    assert!(client.join_presence("secret", None).await.is_err());
+    assert!(client.list_presence("secret").await.is_err());
+    assert!(manager.list_presence("secret").await.unwrap().is_empty());
+}
@@ -0,0 +1,198 @@
+//! Per-connection rate limits: requests over a limit are refused with
+//! `rate_limited`, and connections that keep exceeding it are closed.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::{SinkExt, StreamExt};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_server::{DefaultConnectionManager, RateLimit, RateLimitConfig};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId};
+use ras_jsonrpc_types::{JsonRpcResponse, error_codes};
+use ras_test_helpers::{CompletedRequest, MockAuthProvider, RecordingMetrics, spawn_tcp};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+jsonrpc_bidirectional_service!({
+    service_name: Counter,
+    client_to_server: [
+        UNAUTHORIZED add(i64) -> i64,
+        UNAUTHORIZED status(()) -> (),
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct CounterImpl;
+
+#[async_trait]
+impl CounterService for CounterImpl {
+    async fn add(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        amount: i64,
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(amount)
+    }
+
+    async fn status(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _request: (),
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    CounterHandler<CounterImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(limits: RateLimitConfig, metrics: &RecordingMetrics) -> Socket {
+    let service = CounterBuilder::new(CounterImpl, MockAuthProvider::default())
+        .rate_limit(limits)
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+        .await
+        .unwrap();
+    // Wait for the connection established message
+    socket.next().await.unwrap().unwrap();
+    socket
+}
+
+/// Send requests to `method` with ids `ids`, without waiting for their responses
+async fn send(socket: &mut Socket, method: &str, ids: std::ops::Range<u64>) {
+    for id in ids {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": if method == "add" { serde_json::json!(1) } else { serde_json::json!(null) },
+            "id": id,
+        });
+        socket
+            .send(Message::Text(request.to_string().into()))
+            .await
+            .unwrap();
+    }
+}
+
+/// Read `count` responses, ordered by request id
+async fn responses(socket: &mut Socket, count: usize) -> Vec<JsonRpcResponse> {
+    let mut responses = Vec::new();
+    while responses.len() < count {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("response missing")
+            .unwrap()
+            .unwrap();
+        if let Message::Text(text) = message
+            && let Ok(BidirectionalMessage::Response(response)) = serde_json::from_str(&text)
+        {
+            responses.push(response);
+        }
+    }
+    responses.sort_by_key(|response| response.id.as_ref().and_then(|id| id.as_u64()));
+    responses
+}
+
+fn error_code(response: &JsonRpcResponse) -> Option<i32> {
+    response.error.as_ref().map(|error| error.code)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_over_the_limit_are_refused() {
+    let metrics = RecordingMetrics::default();
+    let limits = RateLimitConfig::new(RateLimit::new(3, Duration::from_secs(60)));
+    let mut socket = connect(limits, &metrics).await;
+
+    send(&mut socket, "add", 0..5).await;
+    let responses = responses(&mut socket, 5).await;
+    let codes: Vec<_> = responses.iter().map(error_code).collect();
+    assert_eq!(
+        codes,
+        [
+            None,
+            None,
+            None,
+            Some(error_codes::RATE_LIMITED),
+            Some(error_codes::RATE_LIMITED),
+        ]
+    );
+    let retry_after = responses[3].error.as_ref().unwrap().data.as_ref().unwrap();
+    assert!(retry_after["retry_after_ms"].as_u64().unwrap() > 0);
+
+    // Refused requests are reported as failures of their method
+    let rate_limited = CompletedRequest {
+        method: "add".to_string(),
+        success: false,
+        error_kind: Some("rate_limited".to_string()),
+    };
+    let completed = metrics.completed();
+    assert_eq!(completed.iter().filter(|c| **c == rate_limited).count(), 2);
+    assert_eq!(completed.iter().filter(|c| c.success).count(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn methods_can_have_their_own_limit() {
+    let metrics = RecordingMetrics::default();
+    let limits = RateLimitConfig::new(RateLimit::new(2, Duration::from_secs(60)))
+        .method("status", RateLimit::new(100, Duration::from_secs(1)));
+    let mut socket = connect(limits, &metrics).await;
+
+    send(&mut socket, "status", 0..10).await;
+    send(&mut socket, "add", 10..13).await;
+    let responses = responses(&mut socket, 13).await;
+    assert!(responses[..12].iter().all(|r| r.error.is_none()));
+    assert_eq!(error_code(&responses[12]), Some(error_codes::RATE_LIMITED));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn repeated_violations_close_the_connection() {
+    let metrics = RecordingMetrics::default();
+    let limits = RateLimitConfig::new(RateLimit::new(1, Duration::from_secs(60))).max_violations(3);
+    let mut socket = connect(limits, &metrics).await;
+
+    send(&mut socket, "add", 0..10).await;
+    let mut rate_limited = 0;
+    let close = loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("connection left open");
+        match message {
+            Some(Ok(Message::Text(text))) => {
+                if let Ok(BidirectionalMessage::Response(response)) = serde_json::from_str(&text)
+                    && error_code(&response) == Some(error_codes::RATE_LIMITED)
+                {
+                    rate_limited += 1;
+                }
+            }
+            Some(Ok(Message::Close(frame))) => break frame.map(|frame| frame.code),
+            Some(Ok(_)) => {}
+            Some(Err(_)) | None => break None,
+        }
+    };
+    assert_eq!(close, Some(CloseCode::Policy));
+    assert_eq!(rate_limited, 3);
+}
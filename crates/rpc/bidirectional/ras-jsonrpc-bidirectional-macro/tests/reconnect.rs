@@ -0,0 +1,342 @@
+//! Automatic reconnection of the generated bidirectional client: the server is
+//! killed mid-session and restarted on the same address.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_auth_core::AuthenticatedUser;
+use ras_jsonrpc_bidirectional_client::{ClientState, ReconnectConfig};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_server::{
+    ConnectionContext, DefaultConnectionManager, MessageHandler, ServerResult,
+};
+use ras_jsonrpc_bidirectional_types::{CloseReason, ConnectionId};
+use ras_jsonrpc_types::{JsonRpcRequest, JsonRpcResponse};
+use ras_test_helpers::MockAuthProvider;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub text: String,
+}
+
+jsonrpc_bidirectional_service!({
+    service_name: Session,
+    client_to_server: [
+        WITH_PERMISSIONS(["user"]) whoami(()) -> String,
+        WITH_PERMISSIONS(["user"]) announce(String) -> bool,
+    ],
+    server_to_client: [
+        note(Note),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct SessionImpl;
+
+#[async_trait]
+impl SessionService for SessionImpl {
+    async fn whoami(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        user: &AuthenticatedUser,
+        _request: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(user.user_id.clone())
+    }
+
+    async fn announce(
+        &self,
+        client: ConnectionId,
+        conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _user: &AuthenticatedUser,
+        text: String,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let note = ras_jsonrpc_bidirectional_types::ServerNotification {
+            method: "note".to_string(),
+            params: serde_json::to_value(Note { text }).unwrap(),
+            metadata: None,
+        };
+        conns
+            .send_to_connection(
+                client,
+                ras_jsonrpc_bidirectional_types::BidirectionalMessage::ServerNotification(note),
+            )
+            .await?;
+        Ok(true)
+    }
+
+    async fn notify_note(
+        &self,
+        _connection_id: ConnectionId,
+        _params: Note,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+}
+
+/// Delegates to the generated handler, recording the topics clients subscribe to.
+struct RecordingHandler<H> {
+    inner: H,
+    subscribed: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl<H: MessageHandler> MessageHandler for RecordingHandler<H> {
+    async fn handle_request(
+        &self,
+        request: JsonRpcRequest,
+        context: Arc<ConnectionContext>,
+    ) -> ServerResult<Option<JsonRpcResponse>> {
+        self.inner.handle_request(request, context).await
+    }
+
+    async fn handle_subscribe(
+        &self,
+        topics: Vec<String>,
+        context: Arc<ConnectionContext>,
+    ) -> ServerResult<()> {
+        self.subscribed
+            .lock()
+            .unwrap()
+            .extend(topics.iter().cloned());
+        self.inner.handle_subscribe(topics, context).await
+    }
+
+    async fn on_connect(&self, context: Arc<ConnectionContext>) -> ServerResult<()> {
+        self.inner.on_connect(context).await
+    }
+
+    async fn on_disconnect(
+        &self,
+        context: Arc<ConnectionContext>,
+        reason: CloseReason,
+    ) -> ServerResult<()> {
+        self.inner.on_disconnect(context, reason).await
+    }
+}
+
+type Handler = RecordingHandler<SessionHandler<SessionImpl, DefaultConnectionManager>>;
+type Service = BuiltWebSocketService<Handler, MockAuthProvider, DefaultConnectionManager>;
+
+/// A server on its own runtime, so stopping it drops every open connection
+/// the way a crashed process would.
+struct TestServer {
+    addr: SocketAddr,
+    stop: Option<tokio::sync::oneshot::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl TestServer {
+    fn start(addr: SocketAddr, subscribed: Arc<Mutex<Vec<String>>>) -> Self {
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let (bound_tx, bound_rx) = std::sync::mpsc::channel();
+
+        let thread = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let connection_manager = Arc::new(DefaultConnectionManager::new());
+                let handler = Arc::new(RecordingHandler {
+                    inner: SessionHandler::new(Arc::new(SessionImpl), connection_manager.clone()),
+                    subscribed,
+                });
+                let service: Service =
+                    ras_jsonrpc_bidirectional_server::WebSocketServiceBuilder::builder()
+                        .handler(handler)
+                        .auth_provider(Arc::new(MockAuthProvider::default()))
+                        .require_auth(true)
+                        .build()
+                        .build_with_manager(connection_manager);
+                let app: Router = Router::new()
+                    .route("/ws", get(websocket_handler::<Service>))
+                    .with_state(service);
+
+                let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+                bound_tx.send(listener.local_addr().unwrap()).unwrap();
+                tokio::select! {
+                    _ = axum::serve(listener, app) => {}
+                    _ = stopped => {}
+                }
+            });
+            // Dropping the runtime cancels every connection task
+        });
+
+        Self {
+            addr: bound_rx.recv().unwrap(),
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    fn url(&self) -> String {
+        format!("ws://{}/ws", self.addr)
+    }
+
+    fn kill(mut self) -> SocketAddr {
+        self.shutdown();
+        self.addr
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn reconnect_policy(max_attempts: u32) -> ReconnectConfig {
+    ReconnectConfig::builder()
+        .max_attempts(max_attempts)
+        .initial_delay(Duration::from_millis(50))
+        .max_delay(Duration::from_millis(200))
+        .build()
+}
+
+async fn wait_for_state(client: &SessionClient, state: ClientState) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while client.client().state().await != state {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "client never reached {state:?}"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_recovers_and_completes_a_call_made_while_the_server_was_down() {
+    let subscribed = Arc::new(Mutex::new(Vec::new()));
+    let server = TestServer::start("127.0.0.1:0".parse().unwrap(), subscribed.clone());
+
+    let reconnects = Arc::new(AtomicU32::new(0));
+    let reconnects_seen = reconnects.clone();
+    let mut client = SessionClientBuilder::new(server.url())
+        .with_jwt_token("user-token".to_string())
+        .with_request_timeout(Duration::from_secs(10))
+        .with_reconnect_config(reconnect_policy(0))
+        .with_on_reconnect(move |_attempts| {
+            reconnects_seen.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .await
+        .expect("client build");
+    client.connect().await.expect("connect");
+
+    let notes = Arc::new(Mutex::new(Vec::new()));
+    let notes_seen = notes.clone();
+    client.on_note(move |note: Note| notes_seen.lock().unwrap().push(note.text));
+    client
+        .subscribe(
+            "news",
+            Arc::new(|_method: &str, _params: &serde_json::Value| {}),
+        )
+        .await
+        .expect("subscribe");
+    assert_eq!(client.whoami(()).await.unwrap(), "user-1");
+
+    let addr = server.kill();
+    wait_for_state(&client, ClientState::Reconnecting).await;
+
+    // Buffered until the server is back
+    let client = Arc::new(client);
+    let pending = tokio::spawn({
+        let client = client.clone();
+        async move { client.whoami(()).await }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!pending.is_finished());
+
+    let _server = TestServer::start(addr, subscribed.clone());
+    assert_eq!(pending.await.unwrap().expect("pending call"), "user-1");
+    assert_eq!(client.client().state().await, ClientState::Connected);
+    assert_eq!(reconnects.load(Ordering::SeqCst), 1);
+
+    // The subscription was renewed and notification handlers still fire
+    assert_eq!(*subscribed.lock().unwrap(), ["news", "news"]);
+    assert!(client.announce("back".to_string()).await.unwrap());
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+    while notes.lock().unwrap().is_empty() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(*notes.lock().unwrap(), ["back"]);
+
+    client.disconnect().await.expect("disconnect");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn calls_fail_fast_while_reconnecting_when_buffering_is_off() {
+    let server = TestServer::start("127.0.0.1:0".parse().unwrap(), Default::default());
+    let client = SessionClientBuilder::new(server.url())
+        .with_jwt_token("user-token".to_string())
+        .with_reconnect_config(ReconnectConfig {
+            buffer_while_reconnecting: false,
+            ..reconnect_policy(0)
+        })
+        .build()
+        .await
+        .expect("client build");
+    client.connect().await.expect("connect");
+
+    server.kill();
+    wait_for_state(&client, ClientState::Reconnecting).await;
+
+    let error = client.whoami(()).await.unwrap_err();
+    assert!(matches!(
+        error,
+        ras_jsonrpc_bidirectional_client::ClientError::NotConnected
+    ));
+
+    client.disconnect().await.expect("disconnect");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_gives_up_after_max_attempts() {
+    let server = TestServer::start("127.0.0.1:0".parse().unwrap(), Default::default());
+    let client = SessionClientBuilder::new(server.url())
+        .with_jwt_token("user-token".to_string())
+        .with_reconnect_config(reconnect_policy(2))
+        .build()
+        .await
+        .expect("client build");
+    let failures = Arc::new(AtomicU32::new(0));
+    let failures_seen = failures.clone();
+    client.client().on_connection_event(
+        "failures",
+        Arc::new(move |event| {
+            if let ras_jsonrpc_bidirectional_client::ConnectionEvent::ReconnectFailed { .. } = event
+            {
+                failures_seen.fetch_add(1, Ordering::SeqCst);
+            }
+        }),
+    );
+    client.connect().await.expect("connect");
+
+    server.kill();
+    wait_for_state(&client, ClientState::Failed).await;
+
+    assert_eq!(failures.load(Ordering::SeqCst), 2);
+    assert!(client.whoami(()).await.is_err());
+}
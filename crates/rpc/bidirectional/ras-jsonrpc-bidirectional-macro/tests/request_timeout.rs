@@ -0,0 +1,169 @@
+//! Request timeouts: calls the server doesn't answer in time fail with a
+//! timeout, are forgotten by the client, and are cancelled on the server.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_jsonrpc_bidirectional_client::ClientError;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_types::ConnectionId;
+use ras_test_helpers::{CompletedRequest, MockAuthProvider, RecordingMetrics, spawn_tcp};
+
+jsonrpc_bidirectional_service!({
+    service_name: Worker,
+    client_to_server: [
+        UNAUTHORIZED sleep(u64) -> u64,
+        UNAUTHORIZED ping(()) -> (),
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+/// How each `sleep` call ended: `finished`, or `cancelled` if it was dropped
+type Outcomes = Arc<Mutex<Vec<&'static str>>>;
+
+/// Records how the call it guards ended once it is dropped
+struct Outcome {
+    outcomes: Outcomes,
+    finished: bool,
+}
+
+impl Drop for Outcome {
+    fn drop(&mut self) {
+        let outcome = if self.finished {
+            "finished"
+        } else {
+            "cancelled"
+        };
+        self.outcomes.lock().unwrap().push(outcome);
+    }
+}
+
+#[derive(Clone, Default)]
+struct WorkerImpl {
+    outcomes: Outcomes,
+}
+
+#[async_trait]
+impl WorkerService for WorkerImpl {
+    async fn sleep(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        millis: u64,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let mut outcome = Outcome {
+            outcomes: self.outcomes.clone(),
+            finished: false,
+        };
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        outcome.finished = true;
+        Ok(millis)
+    }
+
+    async fn ping(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _request: (),
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    WorkerHandler<WorkerImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+async fn start_server(worker: WorkerImpl, metrics: &RecordingMetrics) -> String {
+    let service: Service = WorkerBuilder::new(worker, MockAuthProvider::default())
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    format!("ws://{addr}/ws")
+}
+
+async fn wait_for(outcomes: &Outcomes) -> Vec<&'static str> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while outcomes.lock().unwrap().is_empty() {
+        assert!(tokio::time::Instant::now() < deadline, "call never ended");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    outcomes.lock().unwrap().clone()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn timed_out_calls_are_cancelled_on_the_server() {
+    let worker = WorkerImpl::default();
+    let metrics = RecordingMetrics::default();
+    let url = start_server(worker.clone(), &metrics).await;
+    let client = WorkerClientBuilder::new(&url)
+        .with_request_timeout(Duration::from_millis(200))
+        .build()
+        .await
+        .unwrap();
+    client.connect().await.unwrap();
+
+    let error = client.sleep(60_000).await.unwrap_err();
+    assert!(matches!(error, ClientError::Timeout { .. }), "{error}");
+    assert_eq!(client.client().pending_requests_count(), 0);
+    assert_eq!(wait_for(&worker.outcomes).await, ["cancelled"]);
+
+    // The connection is still usable, and the cancelled call counts as timed out
+    client.ping(()).await.unwrap();
+    let cancelled = CompletedRequest {
+        method: "sleep".to_string(),
+        success: false,
+        error_kind: Some("timeout".to_string()),
+    };
+    assert!(metrics.completed().contains(&cancelled));
+
+    // Neither call is left counted as in flight
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while metrics.in_flight() > 0 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "calls still in flight"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn calls_can_have_their_own_timeout() {
+    let worker = WorkerImpl::default();
+    let url = start_server(worker.clone(), &RecordingMetrics::default()).await;
+    let client = WorkerClientBuilder::new(&url)
+        .with_cancel_on_timeout(false)
+        .build()
+        .await
+        .unwrap();
+    client.connect().await.unwrap();
+
+    let started = tokio::time::Instant::now();
+    let error = client
+        .sleep_with_timeout(300, Duration::from_millis(50))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ClientError::Timeout { .. }), "{error}");
+    assert!(started.elapsed() < Duration::from_millis(300));
+    assert_eq!(client.client().pending_requests_count(), 0);
+
+    // Without cancellation the handler runs to completion, and its late
+    // response is dropped
+    assert_eq!(wait_for(&worker.outcomes).await, ["finished"]);
+    assert_eq!(client.sleep(1).await.unwrap(), 1);
+}
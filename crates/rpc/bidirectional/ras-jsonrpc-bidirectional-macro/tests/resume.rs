@@ -0,0 +1,299 @@
+//! Numbered topic broadcasts: clients resuming a topic are replayed what they
+//! missed, told when the history no longer reaches back far enough, and resume
+//! automatically after a reconnect.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_jsonrpc_bidirectional_client::{ConnectionEvent, ReconnectConfig};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_types::{ConnectionId, TopicResumed};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+
+jsonrpc_bidirectional_service!({
+    service_name: Ticker,
+    client_to_server: [
+        UNAUTHORIZED status(()) -> (),
+    ],
+    server_to_client: [
+        tick(u64),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct TickerImpl;
+
+#[async_trait]
+impl TickerService for TickerImpl {
+    async fn status(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _request: (),
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+
+    async fn notify_tick(
+        &self,
+        _connection_id: ConnectionId,
+        _params: u64,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    TickerHandler<TickerImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+const HISTORY: usize = 4;
+
+async fn start_server() -> (String, Arc<DefaultConnectionManager>) {
+    let service = TickerBuilder::new(TickerImpl, MockAuthProvider::default())
+        .topic_history(HISTORY)
+        .with_topic_authorizer(|_user, topic| topic != "secret")
+        .build();
+    let manager = service.connection_manager();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), manager)
+}
+
+async fn connect(url: &str) -> TickerClient {
+    let client = TickerClientBuilder::new(url).build().await.unwrap();
+    client.connect().await.unwrap();
+    client
+}
+
+fn recording(ticks: &Arc<Mutex<Vec<u64>>>) -> TickerTopicHandlers {
+    let ticks = ticks.clone();
+    TickerTopicHandlers::new().on_tick(move |tick: u64| ticks.lock().unwrap().push(tick))
+}
+
+async fn broadcast(manager: &DefaultConnectionManager, ticks: impl IntoIterator<Item = u64>) {
+    let topics = TickerTopicManager::new(manager);
+    for tick in ticks {
+        topics.broadcast_tick("feed", tick).await.unwrap();
+    }
+}
+
+async fn wait_for(ticks: &Arc<Mutex<Vec<u64>>>, count: usize) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while ticks.lock().unwrap().len() < count {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "ticks missing: {:?}",
+            ticks.lock().unwrap()
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+async fn wait_for_subscribers(manager: &DefaultConnectionManager, count: usize) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while manager.get_topic_connections("feed").len() != count {
+        assert!(tokio::time::Instant::now() < deadline, "never subscribed");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_track_the_last_sequence_number() {
+    let (url, manager) = start_server().await;
+    let client = connect(&url).await;
+    let ticks = Arc::new(Mutex::new(Vec::new()));
+    client
+        .subscribe_topic("feed", recording(&ticks))
+        .await
+        .unwrap();
+    wait_for_subscribers(&manager, 1).await;
+
+    broadcast(&manager, [10, 20, 30]).await;
+    wait_for(&ticks, 3).await;
+    assert_eq!(client.last_seq("feed"), Some(3));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resuming_replays_the_missed_broadcasts() {
+    let (url, manager) = start_server().await;
+    broadcast(&manager, [10, 20, 30]).await;
+
+    let client = connect(&url).await;
+    let ticks = Arc::new(Mutex::new(Vec::new()));
+    let resumed = client
+        .resume_topic("feed", 1, recording(&ticks))
+        .await
+        .unwrap();
+    assert_eq!(
+        resumed,
+        TopicResumed {
+            replayed: 2,
+            gap: false,
+            latest_seq: Some(3),
+        }
+    );
+
+    // Replayed broadcasts come first, then live ones
+    broadcast(&manager, [40]).await;
+    wait_for(&ticks, 3).await;
+    assert_eq!(*ticks.lock().unwrap(), [20, 30, 40]);
+    assert_eq!(client.last_seq("feed"), Some(4));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resuming_past_the_history_reports_a_gap() {
+    let (url, manager) = start_server().await;
+    broadcast(&manager, 1..=10).await;
+
+    let client = connect(&url).await;
+    let ticks = Arc::new(Mutex::new(Vec::new()));
+    let resumed = client
+        .resume_topic("feed", 2, recording(&ticks))
+        .await
+        .unwrap();
+    assert!(resumed.gap);
+    assert_eq!(resumed.replayed, HISTORY);
+    wait_for(&ticks, HISTORY).await;
+    assert_eq!(*ticks.lock().unwrap(), [7, 8, 9, 10]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resuming_is_subject_to_the_topic_authorizer() {
+    let (url, manager) = start_server().await;
+    let client = connect(&url).await;
+
+    let result = client
+        .resume_topic("secret", 0, TickerTopicHandlers::new())
+        .await;
+    assert!(result.is_err());
+    assert_eq!(client.last_seq("secret"), None);
+    assert!(manager.get_topic_connections("secret").is_empty());
+}
+
+/// A server on its own runtime around a connection manager that outlives it,
+/// so stopping it drops every connection while the topic history is kept
+struct TestServer {
+    addr: SocketAddr,
+    stop: Option<tokio::sync::oneshot::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl TestServer {
+    fn start(addr: SocketAddr, manager: Arc<DefaultConnectionManager>) -> Self {
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let (bound_tx, bound_rx) = std::sync::mpsc::channel();
+
+        let thread = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let service = TickerBuilder::new(TickerImpl, MockAuthProvider::default())
+                    .topic_history(HISTORY)
+                    .connection_manager(manager)
+                    .build();
+                let app: Router = Router::new()
+                    .route("/ws", get(websocket_handler::<Service>))
+                    .with_state(service);
+                let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+                bound_tx.send(listener.local_addr().unwrap()).unwrap();
+                tokio::select! {
+                    _ = axum::serve(listener, app) => {}
+                    _ = stopped => {}
+                }
+            });
+        });
+
+        Self {
+            addr: bound_rx.recv().unwrap(),
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    fn kill(mut self) -> SocketAddr {
+        self.shutdown();
+        self.addr
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reconnecting_clients_resume_their_topics() {
+    let manager = Arc::new(DefaultConnectionManager::new());
+    let server = TestServer::start("127.0.0.1:0".parse().unwrap(), manager.clone());
+
+    let gaps = Arc::new(Mutex::new(Vec::new()));
+    let client = TickerClientBuilder::new(format!("ws://{}/ws", server.addr))
+        .with_reconnect_config(
+            ReconnectConfig::builder()
+                .max_attempts(0)
+                .initial_delay(Duration::from_millis(50))
+                .max_delay(Duration::from_millis(200))
+                .build(),
+        )
+        .build()
+        .await
+        .unwrap();
+    let gaps_seen = gaps.clone();
+    client.client().on_connection_event(
+        "gaps",
+        Arc::new(move |event| {
+            if let ConnectionEvent::TopicGap { topic, last_seq } = event {
+                gaps_seen.lock().unwrap().push((topic, last_seq));
+            }
+        }),
+    );
+    client.connect().await.unwrap();
+    let ticks = Arc::new(Mutex::new(Vec::new()));
+    client
+        .subscribe_topic("feed", recording(&ticks))
+        .await
+        .unwrap();
+    wait_for_subscribers(&manager, 1).await;
+    broadcast(&manager, [1, 2]).await;
+    wait_for(&ticks, 2).await;
+
+    // Broadcasts sent while the client is away are replayed once it is back
+    let addr = server.kill();
+    broadcast(&manager, [3, 4]).await;
+    let _server = TestServer::start(addr, manager.clone());
+    wait_for(&ticks, 4).await;
+    broadcast(&manager, [5]).await;
+    wait_for(&ticks, 5).await;
+
+    assert_eq!(*ticks.lock().unwrap(), [1, 2, 3, 4, 5]);
+    assert_eq!(client.last_seq("feed"), Some(5));
+    assert!(gaps.lock().unwrap().is_empty());
+}
@@ -0,0 +1,189 @@
+//! Server-to-client calls: the server asks a client a question and awaits the
+//! typed answer its registered handler produces.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_types::{BidirectionalError, ConnectionId};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmationRequest {
+    pub action: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfirmationResponse {
+    pub approved: bool,
+}
+
+jsonrpc_bidirectional_service!({
+    service_name: Agent,
+    client_to_server: [
+        UNAUTHORIZED run_task(String) -> bool,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+        ask_confirmation(ConfirmationRequest) -> ConfirmationResponse,
+    ]
+});
+
+#[derive(Clone)]
+struct AgentImpl;
+
+#[async_trait]
+impl AgentService for AgentImpl {
+    async fn run_task(
+        &self,
+        client: ConnectionId,
+        conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        task: String,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // Ask the caller while its own request is still in flight
+        let answer = AgentClientHandle::new(client, conns)
+            .ask_confirmation(ConfirmationRequest { action: task })
+            .await?;
+        Ok(answer.approved)
+    }
+}
+
+type Service = BuiltWebSocketService<
+    AgentHandler<AgentImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+async fn start_server() -> (String, Service) {
+    let service = AgentBuilder::new(AgentImpl, MockAuthProvider::default())
+        .call_timeout(Duration::from_millis(200))
+        .build();
+
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service.clone());
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), service)
+}
+
+/// Connects a client that approves everything except actions starting with "rm"
+/// and never answers about actions starting with "slow".
+async fn connect(url: &str) -> AgentClient {
+    let mut client = AgentClientBuilder::new(url)
+        .build()
+        .await
+        .expect("client build");
+    client.on_ask_confirmation(|request: ConfirmationRequest| async move {
+        if request.action.starts_with("slow") {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+        if request.action == "explode" {
+            return Err("cannot decide".to_string());
+        }
+        Ok(ConfirmationResponse {
+            approved: !request.action.starts_with("rm"),
+        })
+    });
+    client.connect().await.expect("connect");
+    client
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_calls_a_client_and_awaits_its_typed_answer() {
+    let (url, service) = start_server().await;
+    let client = connect(&url).await;
+    let id = client.client().connection_id().await.unwrap();
+
+    let handler = service.handler();
+    let answer = handler
+        .call_ask_confirmation(
+            id,
+            ConfirmationRequest {
+                action: "deploy".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(answer, ConfirmationResponse { approved: true });
+
+    // Concurrent calls are correlated by request id
+    let (yes, no) = tokio::join!(
+        handler.call_ask_confirmation(
+            id,
+            ConfirmationRequest {
+                action: "ls".to_string()
+            }
+        ),
+        handler.call_ask_confirmation(
+            id,
+            ConfirmationRequest {
+                action: "rm -rf".to_string()
+            }
+        ),
+    );
+    assert!(yes.unwrap().approved);
+    assert!(!no.unwrap().approved);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn methods_can_call_back_into_their_client() {
+    let (url, _service) = start_server().await;
+    let client = connect(&url).await;
+
+    assert!(client.run_task("build".to_string()).await.unwrap());
+    assert!(!client.run_task("rm cache".to_string()).await.unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_and_unanswered_calls_surface_as_errors() {
+    let (url, service) = start_server().await;
+    let client = connect(&url).await;
+    let id = client.client().connection_id().await.unwrap();
+    let handler = service.handler();
+
+    let refused = handler
+        .call_ask_confirmation(
+            id,
+            ConfirmationRequest {
+                action: "explode".to_string(),
+            },
+        )
+        .await;
+    assert!(
+        matches!(refused, Err(BidirectionalError::RpcError(message)) if message == "cannot decide")
+    );
+
+    let unanswered = handler
+        .call_ask_confirmation(
+            id,
+            ConfirmationRequest {
+                action: "slow down".to_string(),
+            },
+        )
+        .await;
+    assert!(matches!(unanswered, Err(BidirectionalError::Timeout)));
+
+    let unknown = handler
+        .call_ask_confirmation(
+            ConnectionId::new(),
+            ConfirmationRequest {
+                action: "deploy".to_string(),
+            },
+        )
+        .await;
+    assert!(matches!(
+        unknown,
+        Err(BidirectionalError::ConnectionNotFound(_))
+    ));
+
+    // The connection is still usable after a timed-out call
+    assert!(client.run_task("build".to_string()).await.unwrap());
+}
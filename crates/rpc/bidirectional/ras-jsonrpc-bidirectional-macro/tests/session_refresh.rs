@@ -0,0 +1,225 @@
+//! Session refresh over an open connection: `session.refresh` swaps the
+//! connection's user, calls in flight lose access with their permissions, and
+//! periodic re-validation warns before closing connections with rejected tokens.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_bidirectional_client::SessionExpiring;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_types::ConnectionId;
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use tokio::sync::{Notify, mpsc};
+
+jsonrpc_bidirectional_service!({
+    service_name: Vault,
+    client_to_server: [
+        WITH_PERMISSIONS(["user"]) whoami(()) -> String,
+        WITH_PERMISSIONS(["admin"]) purge(()) -> u32,
+        WITH_PERMISSIONS(["admin"]) hold(()) -> (),
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone, Default)]
+struct VaultImpl {
+    /// Signalled once a `hold` call is running
+    holding: Arc<Notify>,
+}
+
+#[async_trait]
+impl VaultService for VaultImpl {
+    async fn whoami(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        user: &AuthenticatedUser,
+        _request: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        // Code the handler calls sees the same user, refreshed or not
+        let current = ras_auth_core::current_user().ok_or("no current user")?;
+        if current.user_id != user.user_id {
+            return Err("the current user is stale".into());
+        }
+        Ok(current.user_id.clone())
+    }
+
+    async fn purge(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _user: &AuthenticatedUser,
+        _request: (),
+    ) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(3)
+    }
+
+    async fn hold(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _user: &AuthenticatedUser,
+        _request: (),
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.holding.notify_one();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        Ok(())
+    }
+}
+
+/// The mock token table, with tokens that can be expired mid-test
+#[derive(Clone, Default)]
+struct Revocable {
+    tokens: MockAuthProvider,
+    expired: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Revocable {
+    fn expire(&self, token: &str) {
+        self.expired.lock().unwrap().insert(token.to_string());
+    }
+}
+
+impl AuthProvider for Revocable {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        if self.expired.lock().unwrap().contains(&token) {
+            return Box::pin(async { Err(AuthError::TokenExpired) });
+        }
+        self.tokens.authenticate(token)
+    }
+}
+
+type Service = BuiltWebSocketService<
+    VaultHandler<VaultImpl, DefaultConnectionManager>,
+    Revocable,
+    DefaultConnectionManager,
+>;
+
+async fn connect(builder: VaultBuilder<VaultImpl, Revocable>, token: &str) -> VaultClient {
+    let service: Service = builder.build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+
+    let client = VaultClientBuilder::new(format!("ws://{addr}/ws"))
+        .with_jwt_token(token.to_string())
+        .build()
+        .await
+        .expect("client build");
+    client.connect().await.expect("connect");
+    client
+}
+
+async fn wait_until_disconnected(client: &VaultClient) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while client.is_connected().await {
+        assert!(tokio::time::Instant::now() < deadline, "connection kept");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refresh_swaps_the_connections_permissions() {
+    let builder = VaultBuilder::new(VaultImpl::default(), Revocable::default());
+    let client = connect(builder, "admin-token").await;
+    assert_eq!(client.purge(()).await.unwrap(), 3);
+
+    let user = client
+        .refresh_session("user-token".to_string())
+        .await
+        .unwrap();
+    assert_eq!(user.user_id, "user-1");
+    assert_eq!(client.whoami(()).await.unwrap(), "user-1");
+    assert!(client.purge(()).await.is_err());
+
+    // A rejected token leaves the session as it was
+    assert!(client.refresh_session("bogus".to_string()).await.is_err());
+    assert_eq!(client.whoami(()).await.unwrap(), "user-1");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn calls_in_flight_fail_when_a_refresh_drops_their_permissions() {
+    let vault = VaultImpl::default();
+    let holding = vault.holding.clone();
+    let client = connect(
+        VaultBuilder::new(vault, Revocable::default()),
+        "admin-token",
+    )
+    .await;
+
+    let downgrade = async {
+        holding.notified().await;
+        client.refresh_session("readonly-token".to_string()).await
+    };
+    let (held, downgraded) = tokio::time::timeout(
+        Duration::from_secs(5),
+        futures::future::join(client.hold(()), downgrade),
+    )
+    .await
+    .expect("held call was not cancelled");
+
+    assert_eq!(downgraded.unwrap().user_id, "ro-1");
+    let error = held.unwrap_err().to_string();
+    assert!(error.contains("Insufficient permissions"), "{error}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn expired_sessions_are_warned_then_closed() {
+    let auth = Revocable::default();
+    let builder = VaultBuilder::new(VaultImpl::default(), auth.clone())
+        .revalidate_every(Duration::from_millis(200));
+    let mut client = connect(builder, "admin-token").await;
+    let (warnings_tx, mut warnings) = mpsc::unbounded_channel::<SessionExpiring>();
+    client.on_session_expiring(move |warning| {
+        let _ = warnings_tx.send(warning);
+    });
+
+    auth.expire("admin-token");
+    let warning = tokio::time::timeout(Duration::from_secs(5), warnings.recv())
+        .await
+        .expect("no expiry warning")
+        .unwrap();
+    assert_eq!(warning.reason, AuthError::TokenExpired.to_string());
+    assert!(client.is_connected().await);
+
+    wait_until_disconnected(&client).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refreshing_after_the_warning_keeps_the_connection() {
+    let auth = Revocable::default();
+    let builder = VaultBuilder::new(VaultImpl::default(), auth.clone())
+        .revalidate_every(Duration::from_millis(300));
+    let mut client = connect(builder, "admin-token").await;
+    let (warnings_tx, mut warnings) = mpsc::unbounded_channel::<SessionExpiring>();
+    client.on_session_expiring(move |warning| {
+        let _ = warnings_tx.send(warning);
+    });
+
+    auth.expire("admin-token");
+    tokio::time::timeout(Duration::from_secs(5), warnings.recv())
+        .await
+        .expect("no expiry warning");
+    client
+        .refresh_session("user-token".to_string())
+        .await
+        .unwrap();
+
+    // Several re-validations later the refreshed session is still open
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    assert!(client.is_connected().await);
+    assert_eq!(client.whoami(()).await.unwrap(), "user-1");
+}
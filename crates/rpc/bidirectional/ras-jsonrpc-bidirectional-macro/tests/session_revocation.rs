@@ -0,0 +1,186 @@
+//! Session revocation: ending a session in the `SessionService` closes the
+//! connections authenticated with it, and only those.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_identity_local::LocalUserProvider;
+use ras_identity_session::{JwtAuthProvider, SessionConfig, SessionService};
+use ras_jsonrpc_bidirectional_client::{CloseReason, ReconnectConfig};
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_types::ConnectionId;
+use ras_test_helpers::spawn_tcp;
+use serde_json::json;
+
+jsonrpc_bidirectional_service!({
+    service_name: Notes,
+    client_to_server: [
+        UNAUTHORIZED whoami(()) -> String,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct NotesImpl;
+
+#[async_trait]
+impl NotesService for NotesImpl {
+    async fn whoami(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _request: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ctx.get_user().await.unwrap().user_id.clone())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    NotesHandler<NotesImpl, DefaultConnectionManager>,
+    JwtAuthProvider,
+    DefaultConnectionManager,
+>;
+
+type Reasons = Arc<Mutex<Vec<CloseReason>>>;
+
+/// Start a server authenticating with `sessions` and closing revoked ones,
+/// recording the close reasons its disconnect hook sees
+async fn start_server(sessions: &Arc<SessionService>) -> (String, Reasons) {
+    let closed = Reasons::default();
+    let on_disconnect = closed.clone();
+    let service: Service = NotesBuilder::new(NotesImpl, JwtAuthProvider::new(sessions.clone()))
+        .require_auth(true)
+        .with_session_revocations(sessions.clone())
+        .on_disconnect(move |ctx| {
+            let closed = on_disconnect.clone();
+            async move {
+                closed.lock().unwrap().push(ctx.close_reason().unwrap());
+            }
+        })
+        .build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), closed)
+}
+
+async fn session_service() -> Arc<SessionService> {
+    let config = SessionConfig::new("a-test-secret-that-is-long-enough-for-hs256").unwrap();
+    let sessions = SessionService::new(config).unwrap();
+    let users = LocalUserProvider::default();
+    for name in ["alice", "bob"] {
+        users
+            .add_user(name.to_string(), "password123".to_string(), None, None)
+            .await
+            .unwrap();
+    }
+    sessions.register_provider(Box::new(users)).await;
+    Arc::new(sessions)
+}
+
+async fn log_in(sessions: &SessionService, name: &str) -> String {
+    sessions
+        .begin_session(
+            "local",
+            json!({ "username": name, "password": "password123" }),
+        )
+        .await
+        .unwrap()
+}
+
+/// Connect a client that does not reconnect, recording why it was disconnected
+async fn connect(url: &str, token: &str) -> (NotesClient, Reasons) {
+    let mut client = NotesClientBuilder::new(url)
+        .with_jwt_token(token.to_string())
+        .with_reconnect_config(ReconnectConfig {
+            enabled: false,
+            ..ReconnectConfig::default()
+        })
+        .build()
+        .await
+        .unwrap();
+    let reasons = Reasons::default();
+    let disconnected = reasons.clone();
+    client.on_disconnected(move |reason| disconnected.lock().unwrap().push(reason));
+    client.connect().await.unwrap();
+    (client, reasons)
+}
+
+async fn wait_for(reasons: &Reasons, count: usize) -> Vec<CloseReason> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while reasons.lock().unwrap().len() < count {
+        assert!(tokio::time::Instant::now() < deadline, "no close reason");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    reasons.lock().unwrap().clone()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ending_a_session_closes_its_connections() {
+    let sessions = session_service().await;
+    let (url, server_side) = start_server(&sessions).await;
+    let alice_token = log_in(&sessions, "alice").await;
+    let (alice, alice_closed) = connect(&url, &alice_token).await;
+    let (_alice_tab, tab_closed) = connect(&url, &alice_token).await;
+    let (bob, bob_closed) = connect(&url, &log_in(&sessions, "bob").await).await;
+    assert_eq!(alice.whoami(()).await.unwrap(), "alice");
+
+    let jti = sessions.jti(&alice_token).unwrap();
+    sessions.end_session(&jti).await.unwrap();
+
+    assert_eq!(
+        wait_for(&alice_closed, 1).await,
+        [CloseReason::SessionRevoked]
+    );
+    assert_eq!(
+        wait_for(&tab_closed, 1).await,
+        [CloseReason::SessionRevoked]
+    );
+    assert_eq!(
+        wait_for(&server_side, 2).await,
+        [CloseReason::SessionRevoked, CloseReason::SessionRevoked]
+    );
+
+    // Other sessions are left alone
+    assert_eq!(bob.whoami(()).await.unwrap(), "bob");
+    assert!(bob_closed.lock().unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refreshed_connections_follow_their_new_session() {
+    let sessions = session_service().await;
+    let (url, _server_side) = start_server(&sessions).await;
+    let first = log_in(&sessions, "alice").await;
+    let (alice, closed) = connect(&url, &first).await;
+
+    let second = log_in(&sessions, "alice").await;
+    alice
+        .client()
+        .refresh_session(second.clone())
+        .await
+        .unwrap();
+
+    // The session the connection started with no longer applies
+    sessions
+        .end_session(&sessions.jti(&first).unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(alice.whoami(()).await.unwrap(), "alice");
+    assert!(closed.lock().unwrap().is_empty());
+
+    sessions
+        .end_session(&sessions.jti(&second).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(wait_for(&closed, 1).await, [CloseReason::SessionRevoked]);
+}
@@ -0,0 +1,135 @@
+//! Graceful shutdown of generated services: clients are warned, in-flight calls
+//! finish within the grace period, and connections close with a proper frame.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::StreamExt;
+use ras_jsonrpc_bidirectional_client::ShutdownNotice;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId, SHUTDOWN_NOTIFICATION};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+jsonrpc_bidirectional_service!({
+    service_name: Worker,
+    client_to_server: [
+        UNAUTHORIZED work(u64) -> u64,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct WorkerImpl;
+
+#[async_trait]
+impl WorkerService for WorkerImpl {
+    async fn work(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        millis: u64,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        Ok(millis)
+    }
+}
+
+type Service = BuiltWebSocketService<
+    WorkerHandler<WorkerImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+async fn start_server() -> (String, Service) {
+    let service = WorkerBuilder::new(WorkerImpl, MockAuthProvider::default()).build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service.clone());
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), service)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn in_flight_calls_finish_and_clients_are_warned() {
+    let (url, service) = start_server().await;
+    let notices = Arc::new(Mutex::new(Vec::<ShutdownNotice>::new()));
+    let mut client = WorkerClientBuilder::new(url.as_str())
+        .build()
+        .await
+        .expect("client build");
+    let received = notices.clone();
+    client.on_server_shutdown(move |notice| received.lock().unwrap().push(notice));
+    client.connect().await.expect("connect");
+
+    let client = Arc::new(client);
+    let caller = client.clone();
+    let call = tokio::spawn(async move { caller.work(300).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    service.shutdown(Duration::from_secs(5)).await;
+
+    // The call started before shutdown completed, well within the grace period
+    assert_eq!(call.await.unwrap().unwrap(), 300);
+    let notices = notices.lock().unwrap().clone();
+    assert_eq!(notices.len(), 1);
+    assert_eq!(notices[0].grace_ms, 5000);
+    assert!(notices[0].deadline > chrono::Utc::now());
+    assert_eq!(service.connection_manager().connection_count(), 0);
+
+    // New connections are refused while the server is down
+    assert!(
+        tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connections_close_with_a_going_away_frame_after_the_grace_period() {
+    let (url, service) = start_server().await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .expect("connect");
+
+    // A call that outlives the grace period
+    let request =
+        serde_json::json!({"jsonrpc": "2.0", "method": "work", "params": 10_000, "id": 1});
+    futures::SinkExt::send(&mut socket, Message::Text(request.to_string().into()))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let started = tokio::time::Instant::now();
+    service.shutdown(Duration::from_millis(100)).await;
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    let mut methods = Vec::new();
+    let close = loop {
+        match socket.next().await.expect("stream ended before close") {
+            Ok(Message::Text(text)) => {
+                if let Ok(BidirectionalMessage::ServerNotification(notification)) =
+                    serde_json::from_str::<BidirectionalMessage>(&text)
+                {
+                    methods.push(notification.method);
+                }
+            }
+            Ok(Message::Close(frame)) => break frame.expect("close frame"),
+            Ok(_) => {}
+            Err(e) => panic!("socket error: {e}"),
+        }
+    };
+    assert_eq!(methods, [SHUTDOWN_NOTIFICATION]);
+    assert_eq!(close.code, CloseCode::Away);
+}
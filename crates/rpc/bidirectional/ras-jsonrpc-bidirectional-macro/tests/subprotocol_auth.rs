@@ -0,0 +1,108 @@
+//! Tokens offered as a `token.<jwt>` subprotocol, the way browser clients
+//! authenticate since they cannot set an `Authorization` header.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use futures::{SinkExt, StreamExt};
+use ras_auth_core::AuthenticatedUser;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{BuiltWebSocketService, websocket_handler};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+jsonrpc_bidirectional_service!({
+    service_name: Whoami,
+    client_to_server: [
+        WITH_PERMISSIONS(["user"]) whoami(()) -> String,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct WhoamiImpl;
+
+#[async_trait]
+impl WhoamiService for WhoamiImpl {
+    async fn whoami(
+        &self,
+        _client: ConnectionId,
+        _conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        user: &AuthenticatedUser,
+        _request: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(user.user_id.clone())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    WhoamiHandler<WhoamiImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+async fn start_server() -> String {
+    let service: Service = WhoamiBuilder::new(WhoamiImpl, MockAuthProvider::default())
+        .require_auth(true)
+        .build();
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    format!("ws://{addr}/ws")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_token_protocol_authenticates_and_is_echoed() {
+    let url = start_server().await;
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert(
+        "sec-websocket-protocol",
+        "json, token.user-token".parse().unwrap(),
+    );
+    let (mut socket, response) = tokio_tungstenite::connect_async(request)
+        .await
+        .expect("connect");
+    assert_eq!(
+        response.headers()["sec-websocket-protocol"],
+        "token.user-token"
+    );
+
+    let call = json!({"jsonrpc": "2.0", "method": "whoami", "params": null, "id": 1});
+    socket
+        .send(Message::Text(call.to_string().into()))
+        .await
+        .unwrap();
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("server went quiet")
+            .expect("connection closed")
+            .unwrap();
+        if let Message::Text(text) = message
+            && let Ok(BidirectionalMessage::Response(response)) = serde_json::from_str(&text)
+        {
+            assert_eq!(response.result, Some(json!("user-1")));
+            break;
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn an_unknown_token_protocol_is_refused() {
+    let url = start_server().await;
+    let mut request = url.into_client_request().unwrap();
+    request
+        .headers_mut()
+        .insert("sec-websocket-protocol", "token.bogus".parse().unwrap());
+    assert!(tokio_tungstenite::connect_async(request).await.is_err());
+}
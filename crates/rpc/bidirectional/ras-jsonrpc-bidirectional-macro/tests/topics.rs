@@ -0,0 +1,242 @@
+//! Topic (room) subscriptions and broadcasts through the generated
+//! `TopicManager` and `subscribe_topic` client helper.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{Router, routing::get};
+use ras_auth_core::AuthenticatedUser;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_jsonrpc_bidirectional_server::service::{
+    BuiltWebSocketService, WebSocketService, websocket_handler,
+};
+use ras_jsonrpc_bidirectional_types::ConnectionId;
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Post {
+    pub room: String,
+    pub text: String,
+}
+
+jsonrpc_bidirectional_service!({
+    service_name: Rooms,
+    client_to_server: [
+        WITH_PERMISSIONS(["user"]) post(Post) -> usize,
+    ],
+    server_to_client: [
+        message(ChatMessage),
+        closing(String),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+#[derive(Clone)]
+struct RoomsImpl;
+
+#[async_trait]
+impl RoomsService for RoomsImpl {
+    async fn post(
+        &self,
+        _client: ConnectionId,
+        conns: &dyn ras_jsonrpc_bidirectional_types::ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
+        _user: &AuthenticatedUser,
+        post: Post,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let delivered = RoomsTopicManager::new(conns)
+            .broadcast_message(&post.room, ChatMessage { text: post.text })
+            .await?;
+        Ok(delivered)
+    }
+
+    async fn notify_message(
+        &self,
+        _connection_id: ConnectionId,
+        _params: ChatMessage,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+
+    async fn notify_closing(
+        &self,
+        _connection_id: ConnectionId,
+        _params: String,
+    ) -> ras_jsonrpc_bidirectional_types::Result<()> {
+        Ok(())
+    }
+}
+
+type Service = BuiltWebSocketService<
+    RoomsHandler<RoomsImpl, DefaultConnectionManager>,
+    MockAuthProvider,
+    DefaultConnectionManager,
+>;
+
+/// Starts a server where only admins may subscribe to `admin:` topics.
+async fn start_server() -> (String, Arc<DefaultConnectionManager>) {
+    let service = RoomsBuilder::new(RoomsImpl, MockAuthProvider::default())
+        .require_auth(true)
+        .with_topic_authorizer(|user, topic| {
+            !topic.starts_with("admin:")
+                || user.is_some_and(|user| user.permissions.contains("admin"))
+        })
+        .build();
+    let connection_manager = service.connection_manager();
+
+    let app: Router = Router::new()
+        .route("/ws", get(websocket_handler::<Service>))
+        .with_state(service);
+    let (addr, _handle) = spawn_tcp(app).await;
+    (format!("ws://{addr}/ws"), connection_manager)
+}
+
+async fn connect(url: &str, token: &str) -> RoomsClient {
+    let client = RoomsClientBuilder::new(url)
+        .with_jwt_token(token.to_string())
+        .build()
+        .await
+        .expect("client build");
+    client.connect().await.expect("connect");
+    client
+}
+
+async fn wait_for_subscribers(manager: &DefaultConnectionManager, topic: &str, count: usize) {
+    let topics = RoomsTopicManager::new(manager);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while topics.subscribers(topic).await.unwrap().len() != count {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "expected {count} subscribers to {topic}"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+fn recording_handlers(received: &Arc<Mutex<Vec<String>>>) -> RoomsTopicHandlers {
+    let messages = received.clone();
+    let closings = received.clone();
+    RoomsTopicHandlers::new()
+        .on_message(move |message: ChatMessage| messages.lock().unwrap().push(message.text))
+        .on_closing(move |reason: String| closings.lock().unwrap().push(reason))
+}
+
+async fn wait_for(received: &Arc<Mutex<Vec<String>>>, count: usize) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while received.lock().unwrap().len() < count {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "notifications missing"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn broadcasts_reach_only_the_topics_subscribers() {
+    let (url, manager) = start_server().await;
+    let alice = connect(&url, "user-token").await;
+    let bob = connect(&url, "admin-token").await;
+
+    let alice_received = Arc::new(Mutex::new(Vec::new()));
+    let bob_received = Arc::new(Mutex::new(Vec::new()));
+    alice
+        .subscribe_topic("tasks:1", recording_handlers(&alice_received))
+        .await
+        .unwrap();
+    bob.subscribe_topic("tasks:2", recording_handlers(&bob_received))
+        .await
+        .unwrap();
+    wait_for_subscribers(&manager, "tasks:1", 1).await;
+    wait_for_subscribers(&manager, "tasks:2", 1).await;
+
+    let delivered = bob
+        .post(Post {
+            room: "tasks:1".to_string(),
+            text: "progress".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(delivered, 1);
+    RoomsTopicManager::new(manager.as_ref())
+        .broadcast_closing("tasks:1", "done".to_string())
+        .await
+        .unwrap();
+
+    wait_for(&alice_received, 2).await;
+    assert_eq!(*alice_received.lock().unwrap(), ["progress", "done"]);
+    assert!(bob_received.lock().unwrap().is_empty());
+
+    // Unsubscribed connections no longer receive the topic's broadcasts
+    alice.unsubscribe_topic("tasks:1").await.unwrap();
+    wait_for_subscribers(&manager, "tasks:1", 0).await;
+    let delivered = bob
+        .post(Post {
+            room: "tasks:1".to_string(),
+            text: "ignored".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(delivered, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_authorizer_gates_subscriptions() {
+    let (url, manager) = start_server().await;
+    let user = connect(&url, "user-token").await;
+    let admin = connect(&url, "admin-token").await;
+
+    user.subscribe_topic("admin:alerts", RoomsTopicHandlers::new())
+        .await
+        .unwrap();
+    admin
+        .subscribe_topic("admin:alerts", RoomsTopicHandlers::new())
+        .await
+        .unwrap();
+    wait_for_subscribers(&manager, "admin:alerts", 1).await;
+    // A round trip after the subscribe request means the server has handled it
+    user.post(Post {
+        room: "lobby".to_string(),
+        text: "hello".to_string(),
+    })
+    .await
+    .unwrap();
+
+    let topics = RoomsTopicManager::new(manager.as_ref());
+    let subscribers = topics.subscribers("admin:alerts").await.unwrap();
+    let admin_id = admin.client().connection_id().await.unwrap();
+    assert_eq!(subscribers, [admin_id]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn subscriptions_are_removed_when_connections_close() {
+    let (url, manager) = start_server().await;
+    let client = connect(&url, "user-token").await;
+
+    // Server-side subscriptions are tracked alongside the client's own
+    let topics = RoomsTopicManager::new(manager.as_ref());
+    let id = client.client().connection_id().await.unwrap();
+    topics.subscribe(id, "tasks:7").await.unwrap();
+    client
+        .subscribe_topic("tasks:8", RoomsTopicHandlers::new())
+        .await
+        .unwrap();
+    wait_for_subscribers(&manager, "tasks:8", 1).await;
+    let mut subscriptions = topics.subscriptions(id).await.unwrap();
+    subscriptions.sort();
+    assert_eq!(subscriptions, ["tasks:7", "tasks:8"]);
+
+    client.disconnect().await.unwrap();
+    wait_for_subscribers(&manager, "tasks:7", 0).await;
+    wait_for_subscribers(&manager, "tasks:8", 0).await;
+    assert!(manager.get_active_topics().is_empty());
+}
@@ -0,0 +1,7 @@
+//! Snapshots of the errors reported for service definitions whose names clash
+
+#[test]
+fn clashing_names_are_rejected() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
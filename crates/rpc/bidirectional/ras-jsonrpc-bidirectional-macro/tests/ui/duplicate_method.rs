@@ -0,0 +1,15 @@
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+
+jsonrpc_bidirectional_service!({
+    service_name: Counter,
+    client_to_server: [
+        UNAUTHORIZED add(i64) -> i64,
+        UNAUTHORIZED add(u8) -> u8,
+    ],
+    server_to_client: [
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+fn main() {}
@@ -0,0 +1,14 @@
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+
+jsonrpc_bidirectional_service!({
+    service_name: Chat,
+    client_to_server: [
+    ],
+    server_to_client: [
+        session_expiring(u64),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+fn main() {}
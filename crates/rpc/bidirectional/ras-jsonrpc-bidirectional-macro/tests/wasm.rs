@@ -0,0 +1,64 @@
+//! The generated client in a browser, against `examples/wasm_test_server.rs`.
+//!
+//! Start the server, then run:
+//!
+//! ```text
+//! wasm-pack test --headless --firefox --no-default-features --features client -- --test wasm
+//! ```
+//!
+//! Set `RAS_WASM_TEST_URL` at build time to point at a server elsewhere.
+
+#![cfg(target_arch = "wasm32")]
+
+use std::sync::Mutex;
+
+use futures::channel::oneshot;
+use ras_jsonrpc_bidirectional_macro::jsonrpc_bidirectional_service;
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+// Keep in step with the definition in `examples/wasm_test_server.rs`
+jsonrpc_bidirectional_service!({
+    service_name: Bell,
+    client_to_server: [
+        WITH_PERMISSIONS(["user"]) echo(String) -> String,
+        WITH_PERMISSIONS(["user"]) ring(String) -> (),
+    ],
+    server_to_client: [
+        rung(String),
+    ],
+    server_to_client_calls: [
+    ]
+});
+
+const URL: &str = match option_env!("RAS_WASM_TEST_URL") {
+    Some(url) => url,
+    None => "ws://127.0.0.1:3999/ws",
+};
+
+#[wasm_bindgen_test]
+async fn calls_and_notifications_work_in_the_browser() {
+    // Browsers send the token as a `token.<jwt>` subprotocol
+    let mut client = BellClientBuilder::new(URL)
+        .with_jwt_token("user-token".to_string())
+        .build()
+        .await
+        .expect("client build");
+    let (rung_tx, rung_rx) = oneshot::channel();
+    let rung_tx = Mutex::new(Some(rung_tx));
+    client.on_rung(move |text: String| {
+        if let Some(tx) = rung_tx.lock().unwrap().take() {
+            let _ = tx.send(text);
+        }
+    });
+    client.connect().await.expect("connect");
+
+    assert_eq!(client.echo("hello".to_string()).await.unwrap(), "hello");
+
+    client.ring("ding".to_string()).await.unwrap();
+    assert_eq!(rung_rx.await.unwrap(), "ding");
+
+    client.disconnect().await.unwrap();
+    assert!(!client.is_connected().await);
+}
@@ -1,29 +1,53 @@
 //! Connection context and management
 
+use crate::queue::{Enqueued, OutboundSender, QueueStats};
+use crate::rate_limit::{RateLimitConfig, RateLimiter};
+use axum::http::Extensions;
 use ras_auth_core::AuthenticatedUser;
-use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId, ConnectionInfo};
-use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
-
-/// A simple channel-based message sender
+use ras_jsonrpc_bidirectional_types::{
+    BidirectionalMessage, CloseReason, ConnectionId, ConnectionInfo,
+};
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{RwLock, watch};
+
+/// Sends messages to a connection through its bounded outgoing queue
 #[derive(Debug, Clone)]
 pub struct ChannelMessageSender {
     connection_id: ConnectionId,
-    sender: mpsc::Sender<BidirectionalMessage>,
+    sender: OutboundSender,
 }
 
 impl ChannelMessageSender {
     /// Create a new channel message sender
-    pub fn new(connection_id: ConnectionId, sender: mpsc::Sender<BidirectionalMessage>) -> Self {
+    pub fn new(connection_id: ConnectionId, sender: OutboundSender) -> Self {
         Self {
             connection_id,
             sender,
         }
     }
 
-    /// Send a message through the channel
+    /// Queue a message, failing if it was discarded or the connection is closed
+    ///
+    /// This never waits for the client; a full queue is handled by the
+    /// connection's [`OverflowPolicy`](crate::OverflowPolicy).
     pub async fn send(&self, message: BidirectionalMessage) -> Result<(), String> {
-        self.sender.send(message).await.map_err(|e| e.to_string())
+        match self.try_send(message) {
+            Enqueued::Queued | Enqueued::ReplacedOldest => Ok(()),
+            Enqueued::Dropped => Err("outgoing queue full, message dropped".to_string()),
+            Enqueued::Disconnected => Err("outgoing queue full, closing connection".to_string()),
+            Enqueued::Closed => Err("connection closed".to_string()),
+        }
+    }
+
+    /// Queue a message and report what the overflow policy did with it
+    pub fn try_send(&self, message: BidirectionalMessage) -> Enqueued {
+        self.sender.push(message)
+    }
+
+    /// Current depth, capacity and drop count of the outgoing queue
+    pub fn queue_stats(&self) -> QueueStats {
+        self.sender.stats()
     }
 
     /// Get the connection ID
@@ -43,6 +67,37 @@ pub struct ConnectionContext {
 
     /// Message sender for this connection
     pub sender: ChannelMessageSender,
+
+    /// Address of the peer, when the server was started with connect info
+    pub remote_addr: Option<SocketAddr>,
+
+    /// Typed connection-scoped state, dropped when the connection closes
+    extensions: Arc<std::sync::RwLock<Extensions>>,
+
+    /// Publishes every change of the authenticated user to calls in flight
+    user_changes: Arc<watch::Sender<Option<Arc<AuthenticatedUser>>>>,
+
+    /// Bearer token the connection is authenticated with
+    token: Arc<std::sync::RwLock<Option<SessionToken>>>,
+
+    /// Id of the session the token belongs to, if it can be revoked
+    session_id: Arc<std::sync::RwLock<Option<String>>>,
+
+    /// Token buckets metering the connection's requests, if they are limited
+    rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// Why the connection closed, once it has
+    close_reason: Arc<OnceLock<CloseReason>>,
+}
+
+/// A bearer token kept out of `Debug` output
+#[derive(Clone)]
+struct SessionToken(String);
+
+impl std::fmt::Debug for SessionToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SessionToken(..)")
+    }
 }
 
 impl ConnectionContext {
@@ -53,9 +108,88 @@ impl ConnectionContext {
             id,
             info: Arc::new(RwLock::new(info)),
             sender,
+            remote_addr: None,
+            extensions: Arc::default(),
+            user_changes: Arc::new(watch::Sender::new(None)),
+            token: Arc::default(),
+            session_id: Arc::default(),
+            rate_limiter: None,
+            close_reason: Arc::default(),
         }
     }
 
+    /// Record the peer's address
+    pub fn with_remote_addr(mut self, remote_addr: Option<SocketAddr>) -> Self {
+        self.remote_addr = remote_addr;
+        self
+    }
+
+    /// Meter the connection's requests by `limits`
+    pub fn with_rate_limits(mut self, limits: RateLimitConfig) -> Self {
+        self.rate_limiter = Some(Arc::new(RateLimiter::new(limits)));
+        self
+    }
+
+    /// Token buckets metering the connection's requests, if they are limited
+    pub(crate) fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_deref()
+    }
+
+    /// Why the connection closed; `None` while it is open, so disconnect hooks
+    /// can tell a client leaving from the server closing it
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.close_reason.get().cloned()
+    }
+
+    /// Record why the connection closed; only the first reason is kept
+    pub(crate) fn set_close_reason(&self, reason: CloseReason) {
+        let _ = self.close_reason.set(reason);
+    }
+
+    /// When the connection was established
+    pub async fn connected_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.info.read().await.connected_at
+    }
+
+    /// Store a value of type `T` for the rest of the connection, returning the
+    /// previous one
+    pub fn insert<T: Clone + Send + Sync + 'static>(&self, value: T) -> Option<T> {
+        self.extensions
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(value)
+    }
+
+    /// A copy of the stored value of type `T`, if any
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.extensions
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get::<T>()
+            .cloned()
+    }
+
+    /// Remove and return the stored value of type `T`
+    pub fn remove<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.extensions
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove::<T>()
+    }
+
+    /// Drop all connection-scoped state; called when the connection closes
+    pub fn clear_extensions(&self) {
+        self.extensions
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Current depth, capacity and drop count of the outgoing queue
+    pub fn queue_stats(&self) -> QueueStats {
+        self.sender.queue_stats()
+    }
+
     /// Check if the connection is authenticated
     pub async fn is_authenticated(&self) -> bool {
         self.info.read().await.is_authenticated()
@@ -68,12 +202,84 @@ impl ConnectionContext {
 
     /// Set the authenticated user
     pub async fn set_user(&self, user: AuthenticatedUser) {
-        self.info.write().await.set_user(user);
+        let mut info = self.info.write().await;
+        info.set_user(user);
+        self.user_changes.send_replace(info.user.clone());
     }
 
     /// Clear the authenticated user
     pub async fn clear_user(&self) {
-        self.info.write().await.clear_user();
+        let mut info = self.info.write().await;
+        info.clear_user();
+        self.user_changes.send_replace(None);
+    }
+
+    /// Bearer token the connection is authenticated with, if any
+    pub fn token(&self) -> Option<String> {
+        self.token
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|SessionToken(token)| token.clone())
+    }
+
+    /// Record the bearer token the connection is authenticated with
+    pub fn set_token(&self, token: Option<String>) {
+        *self.token.write().unwrap_or_else(|e| e.into_inner()) = token.map(SessionToken);
+    }
+
+    /// Id of the session the connection's token belongs to, if the service
+    /// closes connections on session revocations
+    pub fn session_id(&self) -> Option<String> {
+        self.session_id
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Record the id of the session the connection's token belongs to
+    pub fn set_session_id(&self, session_id: Option<String>) {
+        *self.session_id.write().unwrap_or_else(|e| e.into_inner()) = session_id;
+    }
+
+    /// Wait until the connection's user is cleared or replaced by one `permitted`
+    /// rejects
+    ///
+    /// Resolves immediately if the current user already fails the check.
+    pub async fn revoked<F>(&self, permitted: F)
+    where
+        F: Fn(&AuthenticatedUser) -> bool,
+    {
+        let mut changes = self.user_changes.subscribe();
+        loop {
+            let allowed = changes
+                .borrow_and_update()
+                .as_deref()
+                .is_some_and(&permitted);
+            if !allowed {
+                return;
+            }
+            if changes.changed().await.is_err() {
+                return std::future::pending().await;
+            }
+        }
+    }
+
+    /// Run `call` for as long as the connection's user passes `permitted`
+    ///
+    /// Returns `None` if the user was cleared or swapped for one lacking the
+    /// permissions before `call` finished; `call` is dropped in that case, and
+    /// never polled if the current user already fails the check.
+    pub async fn while_permitted<F, Fut>(&self, permitted: F, call: Fut) -> Option<Fut::Output>
+    where
+        F: Fn(&AuthenticatedUser) -> bool,
+        Fut: std::future::Future,
+    {
+        tokio::select! {
+            biased;
+            _ = self.revoked(permitted) => None,
+            output = call => Some(output),
+        }
     }
 
     /// Check if the connection has a specific permission
@@ -129,20 +335,23 @@ impl ConnectionContext {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ras_auth_core::AuthenticatedUser;
+    use crate::queue::{OverflowPolicy, outbound_queue};
+    use ras_auth_core::{AuthenticatedUser, PrincipalKind};
     use std::collections::HashSet;
+    use tokio::sync::oneshot;
 
     fn user(id: &str, perms: &[&str]) -> AuthenticatedUser {
         AuthenticatedUser {
             user_id: id.to_string(),
             permissions: perms.iter().map(|s| s.to_string()).collect::<HashSet<_>>(),
             metadata: None,
+            kind: PrincipalKind::User,
         }
     }
 
     fn ctx() -> ConnectionContext {
         let id = ConnectionId::new();
-        let (tx, _rx) = mpsc::channel(8);
+        let (tx, _rx) = outbound_queue(8, OverflowPolicy::default());
         let sender = ChannelMessageSender::new(id, tx);
         ConnectionContext::new(id, sender)
     }
@@ -150,19 +359,30 @@ mod tests {
     #[tokio::test]
     async fn channel_sender_send_propagates_and_id_round_trips() {
         let id = ConnectionId::new();
-        let (tx, mut rx) = mpsc::channel(2);
+        let (tx, mut rx) = outbound_queue(2, OverflowPolicy::default());
         let sender = ChannelMessageSender::new(id, tx);
         assert_eq!(sender.connection_id(), id);
 
         sender.send(BidirectionalMessage::Ping).await.unwrap();
+        assert_eq!(sender.queue_stats().queued, 1);
         let received = rx.recv().await.unwrap();
         assert!(matches!(received, BidirectionalMessage::Ping));
     }
 
+    #[tokio::test]
+    async fn channel_sender_errors_when_the_queue_drops_a_message() {
+        let id = ConnectionId::new();
+        let (tx, _rx) = outbound_queue(1, OverflowPolicy::DropNewest);
+        let sender = ChannelMessageSender::new(id, tx);
+        sender.send(BidirectionalMessage::Ping).await.unwrap();
+        assert!(sender.send(BidirectionalMessage::Pong).await.is_err());
+        assert_eq!(sender.queue_stats().dropped, 1);
+    }
+
     #[tokio::test]
     async fn channel_sender_returns_string_error_when_closed() {
         let id = ConnectionId::new();
-        let (tx, rx) = mpsc::channel(1);
+        let (tx, rx) = outbound_queue(1, OverflowPolicy::default());
         drop(rx);
         let sender = ChannelMessageSender::new(id, tx);
         let err = sender.send(BidirectionalMessage::Ping).await.unwrap_err();
@@ -186,6 +406,42 @@ mod tests {
         assert!(!c.is_authenticated().await);
     }
 
+    #[tokio::test]
+    async fn token_is_stored_and_kept_out_of_debug() {
+        let c = ctx();
+        assert_eq!(c.token(), None);
+        c.set_token(Some("secret-token".into()));
+        assert_eq!(c.clone().token().as_deref(), Some("secret-token"));
+        assert!(!format!("{c:?}").contains("secret-token"));
+    }
+
+    #[tokio::test]
+    async fn while_permitted_cancels_calls_once_the_user_loses_permissions() {
+        let c = ctx();
+        c.set_user(user("alice", &["admin"])).await;
+        let is_admin = |u: &AuthenticatedUser| u.permissions.contains("admin");
+
+        assert_eq!(c.while_permitted(is_admin, async { 1 }).await, Some(1));
+
+        // Swapping for a user that still qualifies keeps the call running
+        let (tx, rx) = oneshot::channel::<u32>();
+        let call = tokio::spawn({
+            let c = c.clone();
+            async move { c.while_permitted(is_admin, rx).await }
+        });
+        c.set_user(user("alice", &["admin", "read"])).await;
+        tokio::task::yield_now().await;
+        assert!(!call.is_finished());
+
+        c.set_user(user("alice", &["read"])).await;
+        assert_eq!(call.await.unwrap(), None);
+        drop(tx);
+
+        // A cleared user revokes everything, even calls started without a check
+        c.clear_user().await;
+        assert_eq!(c.while_permitted(|_| true, async { 2 }).await, None);
+    }
+
     #[tokio::test]
     async fn subscriptions_round_trip() {
         let c = ctx();
@@ -203,6 +459,33 @@ mod tests {
         assert!(!c.unsubscribe("t1").await);
     }
 
+    #[test]
+    fn extensions_are_typed_and_cleared() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct ViewedDocument(String);
+
+        let c = ctx();
+        assert_eq!(c.get::<ViewedDocument>(), None);
+        assert_eq!(c.insert(ViewedDocument("a".into())), None);
+        assert_eq!(
+            c.insert(ViewedDocument("b".into())),
+            Some(ViewedDocument("a".into()))
+        );
+        c.insert(7u32);
+
+        // Clones of the context share its state
+        let clone = c.clone();
+        assert_eq!(
+            clone.get::<ViewedDocument>(),
+            Some(ViewedDocument("b".into()))
+        );
+        assert_eq!(clone.remove::<u32>(), Some(7));
+        assert_eq!(c.get::<u32>(), None);
+
+        c.clear_extensions();
+        assert_eq!(clone.get::<ViewedDocument>(), None);
+    }
+
     #[tokio::test]
     async fn metadata_get_set() {
         let c = ctx();
@@ -57,6 +57,12 @@ impl ServerError {
     /// Convert to HTTP status code for upgrade errors
     pub fn to_status_code(&self) -> StatusCode {
         match self {
+            ServerError::AuthenticationFailed(AuthError::AccountLocked { .. }) => {
+                StatusCode::FORBIDDEN
+            }
+            ServerError::AuthenticationFailed(AuthError::RateLimited { .. }) => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
             ServerError::AuthenticationFailed(_) => StatusCode::UNAUTHORIZED,
             ServerError::PermissionDenied(_) => StatusCode::FORBIDDEN,
             ServerError::ConnectionNotFound(_) => StatusCode::NOT_FOUND,
@@ -85,6 +91,11 @@ mod tests {
             ServerError::AuthenticationFailed(AuthError::InvalidToken).to_status_code(),
             StatusCode::UNAUTHORIZED
         );
+        assert_eq!(
+            ServerError::AuthenticationFailed(AuthError::AccountLocked { retry_after: None })
+                .to_status_code(),
+            StatusCode::FORBIDDEN
+        );
         assert_eq!(
             ServerError::PermissionDenied("nope".into()).to_status_code(),
             StatusCode::FORBIDDEN
@@ -0,0 +1,56 @@
+//! Answering the built-in `filter.set` request
+
+use crate::ConnectionContext;
+use crate::handler::MessageHandler;
+use ras_jsonrpc_bidirectional_types::{ConnectionManager, FilterSet};
+use ras_jsonrpc_types::{JsonRpcError, JsonRpcRequest, JsonRpcResponse, error_codes};
+use std::sync::Arc;
+
+/// Answer a `filter.set` request, registering or removing the connection's
+/// filter for a notification
+///
+/// Filters are checked with the handler's
+/// [`check_filter`](MessageHandler::check_filter) before they are registered.
+pub(crate) async fn handle<H: MessageHandler + ?Sized>(
+    handler: &H,
+    manager: Option<&dyn ConnectionManager>,
+    context: Arc<ConnectionContext>,
+    request: JsonRpcRequest,
+) -> Option<JsonRpcResponse> {
+    let params = request.params.unwrap_or_default();
+    let result = match manager {
+        Some(manager) => set(handler, manager, &context, params).await,
+        None => Err(JsonRpcError::internal_error(
+            "Filters need a connection manager".to_string(),
+        )),
+    };
+
+    // Notifications get no reply
+    let id = request.id?;
+    Some(match result {
+        Ok(()) => JsonRpcResponse::success(serde_json::Value::Null, Some(id)),
+        Err(error) => JsonRpcResponse::error(error, Some(id)),
+    })
+}
+
+async fn set<H: MessageHandler + ?Sized>(
+    handler: &H,
+    manager: &dyn ConnectionManager,
+    context: &ConnectionContext,
+    params: serde_json::Value,
+) -> Result<(), JsonRpcError> {
+    let FilterSet {
+        notification,
+        filter,
+    } = serde_json::from_value(params).map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+    if let Some(filter) = &filter {
+        handler
+            .check_filter(&notification, filter)
+            .await
+            .map_err(|e| JsonRpcError::new(error_codes::INVALID_PARAMS, e, None))?;
+    }
+    manager
+        .set_notification_filter(context.id, &notification, filter)
+        .await
+        .map_err(|e| JsonRpcError::internal_error(e.to_string()))
+}
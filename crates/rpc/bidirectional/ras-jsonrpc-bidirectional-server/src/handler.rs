@@ -1,15 +1,52 @@
 //! Message handlers for WebSocket communication
 
-use crate::{ConnectionContext, ServerError, ServerResult};
+use crate::filters;
+use crate::limits::{FrameStats, MessageLimits, is_size_limit_error};
+use crate::middleware::MiddlewareChain;
+use crate::presence;
+use crate::queue::OutboundReceiver;
+use crate::revocation::{self, SessionRevocations};
+use crate::session::{self, Revalidation};
+use crate::shutdown::closing;
+use crate::{ConnectionContext, KeepaliveConfig, ServerError, ServerResult, ShutdownCoordinator};
 use async_trait::async_trait;
-use axum::extract::ws::{Message, WebSocket};
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use dashmap::DashMap;
 use futures::stream::StreamExt;
-use ras_jsonrpc_bidirectional_types::BidirectionalMessage;
-use ras_jsonrpc_types::{JsonRpcRequest, JsonRpcResponse};
+use ras_auth_core::{AuthProvider, AuthenticatedUser, PrincipalKind};
+use ras_jsonrpc_bidirectional_types::{
+    BidirectionalMessage, CANCEL_REQUEST_METHOD, CancelRequest, CloseReason, Codec,
+    ConnectionManager, FILTER_SET_METHOD, Frame, SESSION_EXPIRING_NOTIFICATION,
+    SESSION_REFRESH_METHOD, ServerNotification, SessionExpiring, TOPIC_RESUME_METHOD, TopicResume,
+    TopicResumed,
+};
+use ras_jsonrpc_core::{RequestContext, ServiceMetrics, record_request_completed};
+use ras_jsonrpc_types::{JsonRpcError, JsonRpcRequest, JsonRpcResponse, error_codes};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
 use tracing::{debug, error, info, warn};
 
+/// Future returned by a [`ConnectionHook`]
+pub type HookFuture = std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>;
+
+/// Called with a connection's context when it opens or closes
+pub type ConnectionHook = Arc<dyn Fn(Arc<ConnectionContext>) -> HookFuture + Send + Sync>;
+
+/// Wrap an async closure as a [`ConnectionHook`]
+pub fn connection_hook<F, Fut>(hook: F) -> ConnectionHook
+where
+    F: Fn(Arc<ConnectionContext>) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    Arc::new(move |context| -> HookFuture { Box::pin(hook(context)) })
+}
+
+/// Decides whether a connection may subscribe to a topic, given its authenticated
+/// user (if any) and the topic name
+pub type TopicAuthorizer = Arc<dyn Fn(Option<&AuthenticatedUser>, &str) -> bool + Send + Sync>;
+
 /// Trait for handling JSON-RPC requests within a WebSocket context
 #[async_trait]
 pub trait MessageHandler: Send + Sync + 'static {
@@ -55,6 +92,41 @@ pub trait MessageHandler: Send + Sync + 'static {
         Ok(())
     }
 
+    /// Handle a `topic.resume` request, subscribing to the topic again and
+    /// replaying the broadcasts missed after `last_seq`
+    async fn handle_resume(
+        &self,
+        resume: TopicResume,
+        context: Arc<ConnectionContext>,
+    ) -> ServerResult<TopicResumed> {
+        // Default implementation - subscribe without any history to replay
+        context.subscribe(resume.topic).await;
+        Ok(TopicResumed {
+            replayed: 0,
+            gap: true,
+            latest_seq: None,
+        })
+    }
+
+    /// Whether the connection may join and list the presence of `key`
+    async fn authorize_presence(&self, key: &str, context: Arc<ConnectionContext>) -> bool {
+        // Default implementation - presence is open to every connection
+        let _ = (key, context);
+        true
+    }
+
+    /// Check a filter a connection registers for `notification`, returning why
+    /// it is refused
+    async fn check_filter(
+        &self,
+        notification: &str,
+        filter: &serde_json::Value,
+    ) -> Result<(), String> {
+        // Default implementation - any filter is accepted
+        let _ = (notification, filter);
+        Ok(())
+    }
+
     /// Handle connection established event
     async fn on_connect(&self, context: Arc<ConnectionContext>) -> ServerResult<()> {
         info!("Connection established: {}", context.id);
@@ -65,9 +137,9 @@ pub trait MessageHandler: Send + Sync + 'static {
     async fn on_disconnect(
         &self,
         context: Arc<ConnectionContext>,
-        reason: Option<String>,
+        reason: CloseReason,
     ) -> ServerResult<()> {
-        info!("Connection closed: {} (reason: {:?})", context.id, reason);
+        info!("Connection closed: {} (reason: {})", context.id, reason);
         Ok(())
     }
 
@@ -92,9 +164,42 @@ pub struct WebSocketHandler<H: MessageHandler> {
     handler: Arc<H>,
     /// Connection context
     context: Arc<ConnectionContext>,
-    /// Channel for receiving messages to send to client
-    message_rx: mpsc::Receiver<BidirectionalMessage>,
-    max_message_size: usize,
+    /// Bounded queue of messages to send to the client
+    message_rx: OutboundReceiver,
+    /// Size limits and how many malformed frames are tolerated
+    limits: MessageLimits,
+    /// Encoding negotiated for the connection's frames
+    codec: Codec,
+    /// Malformed frames received so far
+    malformed_frames: u32,
+    /// Requests refused for exceeding the connection's rate limits so far
+    rate_limit_violations: u32,
+    /// Service-wide counters of rejected frames
+    frame_stats: Option<FrameStats>,
+    /// Why the server decided to close the connection while handling a message
+    close_reason: Option<CloseReason>,
+    /// Keepalive pings sent to the client
+    keepalive: KeepaliveConfig,
+    /// Pings sent since the client last answered with a pong
+    missed_pongs: u32,
+    /// Connection manager notified whenever the client is seen
+    connection_manager: Option<Arc<dyn ConnectionManager>>,
+    /// Drains the connection when the service shuts down
+    shutdown: Option<ShutdownCoordinator>,
+    /// Authenticates `session.refresh` tokens and re-validates the session
+    auth_provider: Option<Arc<dyn AuthProvider>>,
+    /// How often the session's token is re-validated
+    revalidate_every: Option<Duration>,
+    /// Whether the client was warned that its token was rejected
+    session_expiring: bool,
+    /// Reports ended sessions, closing the connection once its own ends
+    revocations: Option<Arc<dyn SessionRevocations>>,
+    /// Connection, message and per-method metrics
+    service_metrics: Option<Arc<dyn ServiceMetrics>>,
+    /// Middleware run around every request and outgoing message
+    middleware: MiddlewareChain,
+    /// Cancels the handlers of requests still running, by request id
+    running: Arc<DashMap<String, oneshot::Sender<()>>>,
 }
 
 impl<H: MessageHandler> WebSocketHandler<H> {
@@ -102,23 +207,122 @@ impl<H: MessageHandler> WebSocketHandler<H> {
     pub fn new(
         handler: Arc<H>,
         context: Arc<ConnectionContext>,
-        message_rx: mpsc::Receiver<BidirectionalMessage>,
+        message_rx: OutboundReceiver,
         max_message_size: usize,
     ) -> Self {
         Self {
             handler,
             context,
             message_rx,
-            max_message_size,
+            limits: MessageLimits {
+                max_incoming_size: max_message_size,
+                ..MessageLimits::default()
+            },
+            codec: Codec::Json,
+            malformed_frames: 0,
+            rate_limit_violations: 0,
+            frame_stats: None,
+            close_reason: None,
+            keepalive: KeepaliveConfig::disabled(),
+            missed_pongs: 0,
+            connection_manager: None,
+            shutdown: None,
+            auth_provider: None,
+            revalidate_every: None,
+            session_expiring: false,
+            revocations: None,
+            service_metrics: None,
+            middleware: MiddlewareChain::new(),
+            running: Arc::default(),
         }
     }
 
+    /// Replace the size limits and malformed-frame tolerance
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Exchange messages in `codec` rather than JSON text
+    ///
+    /// Text frames from the client are still read as JSON.
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Count rejected frames in `stats`
+    pub fn with_frame_stats(mut self, stats: FrameStats) -> Self {
+        self.frame_stats = Some(stats);
+        self
+    }
+
+    /// Ping the client as configured, closing the connection when it stops answering
+    pub fn with_keepalive(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+
+    /// Record the client's liveness in `manager` as messages arrive
+    pub fn with_connection_manager<M: ConnectionManager + 'static>(
+        mut self,
+        manager: Arc<M>,
+    ) -> Self {
+        self.connection_manager = Some(manager);
+        self
+    }
+
+    /// Track requests with `shutdown`, and close the connection once it says to
+    pub fn with_shutdown(mut self, shutdown: ShutdownCoordinator) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Answer `session.refresh` requests by authenticating their token with `provider`
+    pub fn with_auth_provider<A: AuthProvider>(mut self, provider: Arc<A>) -> Self {
+        self.auth_provider = Some(provider);
+        self
+    }
+
+    /// Re-validate the session's token every `interval`
+    ///
+    /// The first time the token is rejected the client is sent a
+    /// `session.expiring` notification; if it is still rejected at the next
+    /// check, the connection is closed. Requires an auth provider.
+    pub fn with_revalidation(mut self, interval: Duration) -> Self {
+        self.revalidate_every = Some(interval);
+        self
+    }
+
+    /// Close the connection once `revocations` reports its session ended
+    ///
+    /// The context's session id is kept up to date as `session.refresh` swaps
+    /// its token.
+    pub fn with_session_revocations(mut self, revocations: Arc<dyn SessionRevocations>) -> Self {
+        self.revocations = Some(revocations);
+        self
+    }
+
+    /// Report the connection, its messages and the duration of each call to `metrics`
+    pub fn with_service_metrics(mut self, metrics: Arc<dyn ServiceMetrics>) -> Self {
+        self.service_metrics = Some(metrics);
+        self
+    }
+
+    /// Run every request and outgoing message through `middleware`
+    pub fn with_middleware(mut self, middleware: MiddlewareChain) -> Self {
+        self.middleware = middleware;
+        self
+    }
+
     /// Run the WebSocket handler loop
     pub async fn run(mut self, mut socket: WebSocket) -> ServerResult<()> {
         info!(
             "Starting WebSocket handler for connection: {}",
             self.context.id
         );
+        let mut close_signal = self.shutdown.as_ref().map(|s| s.close_signal());
+        self.report(|metrics| metrics.record_connection_opened());
 
         // Notify handler of connection
         if let Err(e) = self.handler.on_connect(self.context.clone()).await {
@@ -129,27 +333,58 @@ impl<H: MessageHandler> WebSocketHandler<H> {
         let established_msg = BidirectionalMessage::ConnectionEstablished {
             connection_id: self.context.id,
         };
-        if let Err(e) = socket
-            .send(Message::Text(
-                serde_json::to_string(&established_msg)?.into(),
-            ))
-            .await
-        {
-            error!("Failed to send connection established message: {}", e);
+        match socket.send(self.encode(&established_msg)?).await {
+            Ok(()) => self.report(|metrics| metrics.record_message_sent()),
+            Err(e) => error!("Failed to send connection established message: {}", e),
         }
 
+        let mut ping_timer = self.keepalive.ping_interval.map(|period| {
+            let mut timer = tokio::time::interval_at(Instant::now() + period, period);
+            timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            timer
+        });
+        let mut revalidate_timer = self
+            .revalidate_every
+            .filter(|_| self.auth_provider.is_some())
+            .map(|period| {
+                let mut timer = tokio::time::interval_at(Instant::now() + period, period);
+                timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                timer
+            });
+        let mut revocations = self
+            .revocations
+            .as_ref()
+            .map(|revocations| revocations.revocations());
+        // Close code sent by the client, if it closed the connection
+        let mut client_close_code = None;
+
         // Main message handling loop
         loop {
             tokio::select! {
                 // Handle incoming WebSocket messages
                 msg = socket.next() => {
                     match msg {
+                        Some(Ok(Message::Close(close_frame))) => {
+                            debug!("Received close frame: {:?}", close_frame);
+                            client_close_code = close_frame.as_ref().map(|f| f.code);
+                            self.close_reason = Some(CloseReason::ClientClosed);
+                            break;
+                        }
                         Some(Ok(msg)) => {
+                            self.mark_seen().await;
                             if let Err(e) = self.handle_websocket_message(msg, &mut socket).await {
                                 error!("Error handling WebSocket message: {}", e);
+                                self.close_reason
+                                    .get_or_insert_with(|| CloseReason::ServerError(e.to_string()));
                                 break;
                             }
                         }
+                        Some(Err(e)) if is_size_limit_error(&e) => {
+                            warn!("Connection {} sent an oversized message: {}", self.context.id, e);
+                            self.record(FrameStats::record_oversized_incoming);
+                            self.close_reason = Some(CloseReason::MessageTooBig);
+                            break;
+                        }
                         Some(Err(e)) => {
                             error!("WebSocket error: {}", e);
                             break;
@@ -170,28 +405,107 @@ impl<H: MessageHandler> WebSocketHandler<H> {
                                 break;
                             }
                         }
+                        None if self.message_rx.overflowed() => {
+                            let stats = self.message_rx.stats();
+                            warn!(
+                                "Connection {} fell {} messages behind, closing",
+                                self.context.id, stats.capacity
+                            );
+                            self.close_reason = Some(CloseReason::Overloaded);
+                            break;
+                        }
                         None => {
                             debug!("Message channel closed");
                             break;
                         }
                     }
                 }
+
+                // Ping the client, giving up once it stops answering
+                _ = next_tick(&mut ping_timer) => {
+                    if self.missed_pongs >= self.keepalive.max_missed_pongs {
+                        warn!(
+                            "Connection {} missed {} pongs, closing",
+                            self.context.id, self.missed_pongs
+                        );
+                        self.close_reason = Some(CloseReason::KeepaliveTimeout);
+                        break;
+                    }
+                    if let Err(e) = socket.send(Message::Ping(Default::default())).await {
+                        error!("Failed to send ping: {}", e);
+                        break;
+                    }
+                    self.missed_pongs += 1;
+                }
+
+                // Close connections whose token stopped validating
+                _ = next_tick(&mut revalidate_timer) => {
+                    if !self.revalidate_session().await {
+                        self.close_reason = Some(CloseReason::SessionExpired);
+                        break;
+                    }
+                }
+
+                // Close connections whose session was ended
+                session_id = revocation::next_revocation(&mut revocations) => {
+                    if self.context.session_id().as_deref() == Some(session_id.as_str()) {
+                        info!("Session of {} was revoked", self.context.id);
+                        self.close_reason = Some(CloseReason::SessionRevoked);
+                        break;
+                    }
+                }
+
+                // Close once the service has drained for shutdown
+                _ = closing(&mut close_signal) => {
+                    // Deliver responses queued by requests that finished while draining
+                    while let Some(msg) = self.message_rx.try_recv() {
+                        if self.send_message(&mut socket, msg).await.is_err() {
+                            break;
+                        }
+                    }
+                    self.close_reason = Some(CloseReason::ServerShutdown);
+                    break;
+                }
             }
         }
 
+        // The loop also ends when the socket fails or the client vanishes
+        let close_reason = self
+            .close_reason
+            .take()
+            .unwrap_or(CloseReason::ConnectionLost);
+        self.context.set_close_reason(close_reason.clone());
+
         // Notify handler of disconnection
-        if let Err(e) = self.handler.on_disconnect(self.context.clone(), None).await {
+        if let Err(e) = self
+            .handler
+            .on_disconnect(self.context.clone(), close_reason.clone())
+            .await
+        {
             error!("Error in on_disconnect handler: {}", e);
         }
+        self.context.clear_extensions();
 
         // Send connection closed message
         let closed_msg = BidirectionalMessage::ConnectionClosed {
             connection_id: self.context.id,
-            reason: None,
+            reason: Some(close_reason.to_string()),
+            close_reason: Some(close_reason.clone()),
         };
-        let _ = socket
-            .send(Message::Text(serde_json::to_string(&closed_msg)?.into()))
-            .await;
+        if socket.send(self.encode(&closed_msg)?).await.is_ok() {
+            self.report(|metrics| metrics.record_message_sent());
+        }
+        let close_code = close_reason.close_code();
+        if let Some(code) = close_code {
+            let frame = CloseFrame {
+                code,
+                reason: close_reason.to_string().into(),
+            };
+            let _ = socket.send(Message::Close(Some(frame))).await;
+        }
+        self.report(|metrics| {
+            metrics.record_connection_closed(close_code.or(client_close_code), close_reason.label())
+        });
 
         info!(
             "WebSocket handler finished for connection: {}",
@@ -208,29 +522,28 @@ impl<H: MessageHandler> WebSocketHandler<H> {
     ) -> ServerResult<()> {
         match msg {
             Message::Text(text) => {
-                if text.len() > self.max_message_size {
-                    warn!("Received oversized text message: {} bytes", text.len());
-                    return Err(ServerError::InvalidRequest(
-                        "Message exceeds maximum size".to_string(),
-                    ));
+                self.report(|metrics| metrics.record_message_received());
+                if text.len() > self.limits.max_incoming_size {
+                    return self.reject_oversized(text.len(), socket).await;
                 }
                 debug!("Received text message ({} bytes)", text.len());
                 self.handle_text_message(text.to_string(), socket).await
             }
             Message::Binary(data) => {
-                if data.len() > self.max_message_size {
-                    warn!("Received oversized binary message: {} bytes", data.len());
-                    return Err(ServerError::InvalidRequest(
-                        "Message exceeds maximum size".to_string(),
-                    ));
+                self.report(|metrics| metrics.record_message_received());
+                if data.len() > self.limits.max_incoming_size {
+                    return self.reject_oversized(data.len(), socket).await;
                 }
                 debug!("Received binary message ({} bytes)", data.len());
+                if self.codec.is_binary() {
+                    return self.handle_binary_message(&data, socket).await;
+                }
                 // Try to parse as UTF-8 text
                 match String::from_utf8(data.to_vec()) {
                     Ok(text) => self.handle_text_message(text, socket).await,
-                    Err(_) => {
-                        warn!("Received non-UTF-8 binary message, ignoring");
-                        Ok(())
+                    Err(e) => {
+                        let error = JsonRpcError::invalid_utf8(e.utf8_error().valid_up_to());
+                        self.reject_malformed(None, error, socket).await
                     }
                 }
             }
@@ -244,16 +557,11 @@ impl<H: MessageHandler> WebSocketHandler<H> {
             }
             Message::Pong(_) => {
                 debug!("Received pong");
+                self.missed_pongs = 0;
                 self.handler.on_pong(self.context.clone()).await
             }
-            Message::Close(close_frame) => {
-                debug!("Received close frame: {:?}", close_frame);
-                let reason = close_frame.map(|f| f.reason.to_string());
-                self.handler
-                    .on_disconnect(self.context.clone(), reason)
-                    .await?;
-                Err(ServerError::WebSocketError("Connection closed".to_string()))
-            }
+            // Handled by `run`, which ends the connection
+            Message::Close(_) => Ok(()),
         }
     }
 
@@ -270,13 +578,98 @@ impl<H: MessageHandler> WebSocketHandler<H> {
 
         // Try to parse as JSON-RPC request
         if let Ok(request) = serde_json::from_str::<JsonRpcRequest>(&text) {
-            return self.handle_jsonrpc_request(request, socket).await;
+            return self.dispatch_request(request, socket).await;
         }
 
-        // If neither worked, return error
-        Err(ServerError::InvalidRequest(
-            "Could not parse message as JSON-RPC or bidirectional message".to_string(),
-        ))
+        // Answer with a parse error, keeping the request id when there is one
+        let id = serde_json::from_str::<serde_json::Value>(&text)
+            .ok()
+            .and_then(|value| value.get("id").cloned())
+            .filter(|id| !id.is_null());
+        self.reject_malformed(id, JsonRpcError::parse_error(), socket)
+            .await
+    }
+
+    /// Handle a frame in the negotiated binary codec
+    async fn handle_binary_message(
+        &mut self,
+        data: &[u8],
+        socket: &mut WebSocket,
+    ) -> ServerResult<()> {
+        if let Ok(msg) = self.codec.decode::<BidirectionalMessage>(data) {
+            return self.handle_bidirectional_message(msg, socket).await;
+        }
+        if let Ok(request) = self.codec.decode::<JsonRpcRequest>(data) {
+            return self.dispatch_request(request, socket).await;
+        }
+
+        let id = self
+            .codec
+            .decode::<serde_json::Value>(data)
+            .ok()
+            .and_then(|value| value.get("id").cloned())
+            .filter(|id| !id.is_null());
+        self.reject_malformed(id, JsonRpcError::parse_error(), socket)
+            .await
+    }
+
+    /// Answer a message over the size limit with an error, keeping the connection
+    async fn reject_oversized(&mut self, size: usize, socket: &mut WebSocket) -> ServerResult<()> {
+        let max_size = self.limits.max_incoming_size;
+        warn!(
+            "Connection {} sent a {} byte message, over the {} byte limit",
+            self.context.id, size, max_size
+        );
+        self.record(FrameStats::record_oversized_incoming);
+        let error = JsonRpcError::request_too_large(Some(size), max_size);
+        let response = JsonRpcResponse::error(error, None);
+        self.send_message(socket, BidirectionalMessage::Response(response))
+            .await
+    }
+
+    /// Answer a frame that could not be understood, closing the connection once
+    /// too many have been received
+    async fn reject_malformed(
+        &mut self,
+        id: Option<serde_json::Value>,
+        error: JsonRpcError,
+        socket: &mut WebSocket,
+    ) -> ServerResult<()> {
+        self.malformed_frames += 1;
+        self.record(FrameStats::record_malformed);
+        warn!(
+            "Connection {} sent a malformed message ({} so far)",
+            self.context.id, self.malformed_frames
+        );
+        let response = JsonRpcResponse::error(error, id);
+        self.send_message(socket, BidirectionalMessage::Response(response))
+            .await?;
+
+        let max = self.limits.max_malformed_frames;
+        if max > 0 && self.malformed_frames >= max {
+            self.close_reason = Some(CloseReason::ProtocolViolation(
+                "Too many malformed messages".to_string(),
+            ));
+            return Err(ServerError::InvalidRequest(format!(
+                "{} malformed messages received",
+                self.malformed_frames
+            )));
+        }
+        Ok(())
+    }
+
+    /// Bump one of the service's frame counters, if it keeps them
+    fn record(&self, counter: fn(&FrameStats)) {
+        if let Some(stats) = &self.frame_stats {
+            counter(stats);
+        }
+    }
+
+    /// Report to the service's metrics, if it has any
+    fn report(&self, record: impl FnOnce(&dyn ServiceMetrics)) {
+        if let Some(metrics) = &self.service_metrics {
+            record(metrics.as_ref());
+        }
     }
 
     /// Handle bidirectional messages
@@ -288,7 +681,7 @@ impl<H: MessageHandler> WebSocketHandler<H> {
         match msg {
             BidirectionalMessage::Request(request) => {
                 // Handle as JSON-RPC request
-                self.handle_jsonrpc_request(request, _socket).await
+                self.dispatch_request(request, _socket).await
             }
             BidirectionalMessage::Subscribe { topics } => {
                 self.handler
@@ -300,8 +693,27 @@ impl<H: MessageHandler> WebSocketHandler<H> {
                     .handle_unsubscribe(topics, self.context.clone())
                     .await
             }
-            BidirectionalMessage::Ping => self.handler.on_ping(self.context.clone()).await,
+            BidirectionalMessage::Ping => {
+                self.send_message(_socket, BidirectionalMessage::Pong)
+                    .await?;
+                self.handler.on_ping(self.context.clone()).await
+            }
             BidirectionalMessage::Pong => self.handler.on_pong(self.context.clone()).await,
+            BidirectionalMessage::Response(response) => {
+                // Answer to a server-to-client call awaiting in the connection manager
+                let handled = match &self.connection_manager {
+                    Some(manager) => {
+                        manager
+                            .handle_pending_response(self.context.id, response)
+                            .await?
+                    }
+                    None => false,
+                };
+                if !handled {
+                    warn!("Received response to an unknown request from client");
+                }
+                Ok(())
+            }
             // Other message types are typically server-to-client
             _ => {
                 warn!("Received unexpected bidirectional message type from client");
@@ -310,54 +722,387 @@ impl<H: MessageHandler> WebSocketHandler<H> {
         }
     }
 
-    /// Handle JSON-RPC requests
-    async fn handle_jsonrpc_request(
+    /// Dispatch a request unless it exceeds the connection's rate limits or
+    /// the middleware refuses it
+    ///
+    /// Rate-limited requests are answered with a `rate_limited` error, and the
+    /// connection is closed once too many have been refused. Requests over the
+    /// limits never reach the middleware.
+    async fn dispatch_request(
         &mut self,
+        mut request: JsonRpcRequest,
+        socket: &mut WebSocket,
+    ) -> ServerResult<()> {
+        if request.method == CANCEL_REQUEST_METHOD {
+            self.cancel(request);
+            return Ok(());
+        }
+
+        let limited = self.context.rate_limiter().and_then(|limiter| {
+            let retry_after = limiter.check(&request.method).err()?;
+            Some((retry_after, limiter.max_violations()))
+        });
+        if let Some((retry_after, max)) = limited {
+            self.rate_limit_violations += 1;
+            warn!(
+                "Connection {} exceeded the rate limit of {} ({} so far)",
+                self.context.id, request.method, self.rate_limit_violations
+            );
+            self.refuse(request, JsonRpcError::rate_limited(retry_after), socket)
+                .await?;
+            if max > 0 && self.rate_limit_violations >= max {
+                self.close_reason = Some(CloseReason::RateLimited);
+                return Err(ServerError::InvalidRequest(format!(
+                    "{} rate-limited requests received",
+                    self.rate_limit_violations
+                )));
+            }
+            return Ok(());
+        }
+
+        if let Err(error) = self.middleware.incoming(&self.context, &mut request).await {
+            debug!(
+                "Middleware refused {} from connection {}: {}",
+                request.method, self.context.id, error.message
+            );
+            return self.refuse(request, error, socket).await;
+        }
+        self.handle_jsonrpc_request(request)
+    }
+
+    /// Drop the handler of the request a `$/cancel` notification names, if it is
+    /// still running, so that it is never answered
+    fn cancel(&self, request: JsonRpcRequest) {
+        let params = request.params.unwrap_or_default();
+        let Ok(CancelRequest { id }) = serde_json::from_value(params) else {
+            warn!("Ignoring malformed cancellation from {}", self.context.id);
+            return;
+        };
+        match self.running.remove(&id.to_string()) {
+            Some((_, cancel)) => {
+                debug!("Cancelling request {} of {}", id, self.context.id);
+                let _ = cancel.send(());
+            }
+            None => debug!("Request {} of {} already finished", id, self.context.id),
+        }
+    }
+
+    /// Answer a request refused before dispatch with `error`, reporting it as a
+    /// failed call of its method
+    async fn refuse(
+        &self,
         request: JsonRpcRequest,
+        error: JsonRpcError,
         socket: &mut WebSocket,
     ) -> ServerResult<()> {
+        let kind = PrincipalKind::of(self.context.get_user().await.as_deref());
+        self.report(|metrics| {
+            let context =
+                RequestContext::websocket(request.method.clone()).with_principal_kind(kind);
+            metrics.increment_requests_started(&context);
+            let response = JsonRpcResponse::error(error.clone(), None);
+            record_request_completed(metrics, context, &response, Duration::ZERO);
+        });
+        // Notifications get no reply
+        let Some(id) = request.id else {
+            return Ok(());
+        };
+        let response = JsonRpcResponse::error(error, Some(id));
+        self.send_message(socket, BidirectionalMessage::Response(response))
+            .await
+    }
+
+    /// Handle JSON-RPC requests
+    ///
+    /// Each request is dispatched on its own task and its response queued on the
+    /// connection, so a handler awaiting a server-to-client call on the same
+    /// connection doesn't hold up the reply it is waiting for. Requests with an id
+    /// can be cancelled until they finish, dropping the handler unanswered.
+    fn handle_jsonrpc_request(&self, request: JsonRpcRequest) -> ServerResult<()> {
         debug!("Handling JSON-RPC request: {}", request.method);
 
-        match self
-            .handler
-            .handle_request(request, self.context.clone())
-            .await
-        {
-            Ok(Some(response)) => {
-                // Send response back to client
-                let response_msg = BidirectionalMessage::Response(response);
-                self.send_message(socket, response_msg).await
+        // Registered before the task starts, so it can't finish first
+        let running = self.running.clone();
+        let key = request.id.as_ref().map(|id| id.to_string());
+        let cancelled = key.clone().map(|key| {
+            let (cancel, cancelled) = oneshot::channel();
+            running.insert(key, cancel);
+            cancelled
+        });
+
+        let handler = self.handler.clone();
+        let context = self.context.clone();
+        let auth_provider = self.auth_provider.clone();
+        let manager = self.connection_manager.clone();
+        let in_flight = self.shutdown.as_ref().map(|s| s.begin_request());
+        let metrics = self.service_metrics.clone();
+        let revocations = self.revocations.clone();
+        tokio::spawn(async move {
+            // Shutdown waits for this request until the response is queued
+            let _in_flight = in_flight;
+            let id = request.id.clone();
+            let kind = PrincipalKind::of(context.get_user().await.as_deref());
+            let metrics_context = metrics.as_ref().map(|metrics| {
+                let context =
+                    RequestContext::websocket(request.method.clone()).with_principal_kind(kind);
+                metrics.increment_requests_started(&context);
+                context
+            });
+            // Counted as executing until the task ends, cancelled or not
+            let _executing = metrics
+                .as_ref()
+                .zip(metrics_context.as_ref())
+                .map(|(metrics, context)| metrics.in_flight_guard(context));
+            let started = Instant::now();
+            let call = async {
+                match auth_provider {
+                    Some(provider) if request.method == SESSION_REFRESH_METHOD => {
+                        let response =
+                            session::refresh(&*provider, &context, manager.as_deref(), request)
+                                .await;
+                        if let Some(revocations) = &revocations {
+                            revocation::track(&**revocations, &context);
+                        }
+                        Ok(response)
+                    }
+                    _ if request.method == TOPIC_RESUME_METHOD => {
+                        Ok(resume_topic(&*handler, context.clone(), request).await)
+                    }
+                    _ if request.method == FILTER_SET_METHOD => Ok(filters::handle(
+                        &*handler,
+                        manager.as_deref(),
+                        context.clone(),
+                        request,
+                    )
+                    .await),
+                    _ if presence::is_presence_method(&request.method) => Ok(presence::handle(
+                        &*handler,
+                        manager.as_deref(),
+                        context.clone(),
+                        request,
+                    )
+                    .await),
+                    _ => handler.handle_request(request, context.clone()).await,
+                }
+            };
+            let result = match cancelled {
+                Some(cancelled) => tokio::select! {
+                    result = call => Some(result),
+                    // A replaced sender is dropped rather than sent on
+                    Ok(()) = cancelled => None,
+                },
+                None => Some(call.await),
+            };
+            let Some(result) = result else {
+                if let (Some(metrics), Some(context)) = (&metrics, metrics_context) {
+                    let error = JsonRpcError::new(
+                        error_codes::REQUEST_TIMEOUT,
+                        "Request cancelled".to_string(),
+                        None,
+                    );
+                    let outcome = JsonRpcResponse::error(error, None);
+                    record_request_completed(
+                        metrics.as_ref(),
+                        context,
+                        &outcome,
+                        started.elapsed(),
+                    );
+                }
+                return;
+            };
+            if let Some(key) = &key {
+                running.remove(key);
             }
-            Ok(None) => {
-                // No response needed (notification)
-                Ok(())
+            let (response, failure) = match result {
+                Ok(response) => (response, None),
+                Err(e) => {
+                    error!("Error handling request: {}", e);
+                    let error = match e {
+                        ServerError::HandlerNotFound(method) => {
+                            JsonRpcError::method_not_found(&method)
+                        }
+                        e => JsonRpcError::internal_error(e.to_string()),
+                    };
+                    // Notifications get no reply, even on failure
+                    let response = id.map(|id| JsonRpcResponse::error(error.clone(), Some(id)));
+                    (response, Some(error))
+                }
+            };
+
+            if let (Some(metrics), Some(context)) = (&metrics, metrics_context) {
+                // Failed notifications have no response to report, so stand one in
+                let outcome = match (failure, &response) {
+                    (Some(error), _) => JsonRpcResponse::error(error, None),
+                    (None, Some(response)) => response.clone(),
+                    (None, None) => JsonRpcResponse::success(serde_json::Value::Null, None),
+                };
+                record_request_completed(metrics.as_ref(), context, &outcome, started.elapsed());
             }
-            Err(e) => {
-                error!("Error handling request: {}", e);
-                // Could send error response here if needed
-                Err(e)
+
+            if let Some(response) = response {
+                let message = BidirectionalMessage::Response(response);
+                if let Err(e) = context.sender.send(message).await {
+                    warn!("Failed to queue response for {}: {}", context.id, e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Re-validate the session's token, warning the client the first time it is
+    /// rejected; returns `false` once the connection should be closed
+    async fn revalidate_session(&mut self) -> bool {
+        let Some(provider) = self.auth_provider.clone() else {
+            return true;
+        };
+        let manager = self.connection_manager.as_deref();
+        match session::revalidate(&*provider, &self.context, manager).await {
+            Revalidation::Valid | Revalidation::Anonymous => {
+                self.session_expiring = false;
+                true
+            }
+            Revalidation::Unavailable(e) => {
+                warn!(
+                    "Could not re-validate the session of {}: {}",
+                    self.context.id, e
+                );
+                true
+            }
+            Revalidation::Rejected(e) if self.session_expiring => {
+                info!("Session of {} expired: {}", self.context.id, e);
+                false
+            }
+            Revalidation::Rejected(e) => {
+                info!("Session of {} is expiring: {}", self.context.id, e);
+                self.session_expiring = true;
+                let interval = self.revalidate_every.unwrap_or_default();
+                let notice = SessionExpiring {
+                    deadline: chrono::Utc::now()
+                        + chrono::Duration::from_std(interval).unwrap_or_default(),
+                    reason: e.to_string(),
+                };
+                let message = BidirectionalMessage::ServerNotification(ServerNotification {
+                    method: SESSION_EXPIRING_NOTIFICATION.to_string(),
+                    params: serde_json::to_value(notice).unwrap_or_default(),
+                    metadata: None,
+                });
+                if let Err(e) = self.context.sender.send(message).await {
+                    warn!(
+                        "Failed to warn {} of session expiry: {}",
+                        self.context.id, e
+                    );
+                }
+                true
             }
         }
     }
 
+    /// Record that the client was just heard from
+    async fn mark_seen(&self) {
+        if let Some(manager) = &self.connection_manager
+            && let Err(e) = manager.mark_seen(self.context.id).await
+        {
+            warn!("Failed to record activity for {}: {}", self.context.id, e);
+        }
+    }
+
     /// Send a message to the WebSocket client
     async fn send_message(
         &self,
         socket: &mut WebSocket,
-        msg: BidirectionalMessage,
+        mut msg: BidirectionalMessage,
     ) -> ServerResult<()> {
-        let json = serde_json::to_string(&msg)?;
+        self.middleware.outgoing(&self.context, &mut msg).await;
+        let mut frame = self.codec.encode(&msg)?;
+        if let Some(max_size) = self.limits.max_outgoing_size
+            && frame.len() > max_size
+        {
+            warn!(
+                "Withholding a {} byte message to {}, over the {} byte limit",
+                frame.len(),
+                self.context.id,
+                max_size
+            );
+            self.record(FrameStats::record_oversized_outgoing);
+            // A caller waiting on a response still gets an answer
+            let BidirectionalMessage::Response(response) = msg else {
+                return Ok(());
+            };
+            let error = JsonRpcError::new(
+                ras_jsonrpc_types::error_codes::INTERNAL_ERROR,
+                "Response too large".to_string(),
+                Some(serde_json::json!({ "size": frame.len(), "max_size": max_size })),
+            );
+            let response = JsonRpcResponse::error(error, response.id);
+            frame = self
+                .codec
+                .encode(&BidirectionalMessage::Response(response))?;
+        }
         socket
-            .send(Message::Text(json.into()))
+            .send(frame_message(frame))
             .await
-            .map_err(|e| ServerError::WebSocketError(e.to_string()))
+            .map_err(|e| ServerError::WebSocketError(e.to_string()))?;
+        self.report(|metrics| metrics.record_message_sent());
+        Ok(())
+    }
+
+    /// Encode a message as a frame in the connection's codec
+    fn encode(&self, msg: &BidirectionalMessage) -> ServerResult<Message> {
+        Ok(frame_message(self.codec.encode(msg)?))
+    }
+}
+
+fn frame_message(frame: Frame) -> Message {
+    match frame {
+        Frame::Text(text) => Message::Text(text.into()),
+        Frame::Binary(bytes) => Message::Binary(bytes.into()),
+    }
+}
+
+/// Wait for the next keepalive tick, or forever when keepalive is disabled
+async fn next_tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
     }
 }
 
+/// Answer a `topic.resume` request through the message handler
+async fn resume_topic<H: MessageHandler + ?Sized>(
+    handler: &H,
+    context: Arc<ConnectionContext>,
+    request: JsonRpcRequest,
+) -> Option<JsonRpcResponse> {
+    let params = request.params.unwrap_or_default();
+    let result = match serde_json::from_value::<TopicResume>(params) {
+        Ok(resume) => match handler.handle_resume(resume, context).await {
+            Ok(resumed) => serde_json::to_value(resumed)
+                .map_err(|e| JsonRpcError::internal_error(e.to_string())),
+            Err(ServerError::PermissionDenied(message)) => Err(JsonRpcError::new(
+                error_codes::INSUFFICIENT_PERMISSIONS,
+                message,
+                None,
+            )),
+            Err(e) => Err(JsonRpcError::internal_error(e.to_string())),
+        },
+        Err(e) => Err(JsonRpcError::invalid_params(e.to_string())),
+    };
+
+    // Notifications get no reply
+    let id = request.id?;
+    Some(match result {
+        Ok(resumed) => JsonRpcResponse::success(resumed, Some(id)),
+        Err(error) => JsonRpcResponse::error(error, Some(id)),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::connection::ChannelMessageSender;
+    use crate::queue::{OverflowPolicy, outbound_queue};
     use ras_jsonrpc_bidirectional_types::ConnectionId;
 
     /// A minimal MessageHandler that only implements the required method —
@@ -378,7 +1123,7 @@ mod tests {
 
     fn ctx() -> Arc<ConnectionContext> {
         let id = ConnectionId::new();
-        let (tx, _rx) = mpsc::channel(4);
+        let (tx, _rx) = outbound_queue(4, OverflowPolicy::default());
         let sender = ChannelMessageSender::new(id, tx);
         Arc::new(ConnectionContext::new(id, sender))
     }
@@ -414,10 +1159,11 @@ mod tests {
         h.on_connect(c.clone()).await.unwrap();
         h.on_ping(c.clone()).await.unwrap();
         h.on_pong(c.clone()).await.unwrap();
-        h.on_disconnect(c.clone(), Some("bye".into()))
+        h.on_disconnect(c.clone(), CloseReason::ClientClosed)
+            .await
+            .unwrap();
+        h.on_disconnect(c, CloseReason::ServerError("boom".into()))
             .await
             .unwrap();
-        // None reason path too.
-        h.on_disconnect(c, None).await.unwrap();
     }
 }
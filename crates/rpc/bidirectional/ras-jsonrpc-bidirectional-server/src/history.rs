@@ -0,0 +1,159 @@
+//! Recent topic broadcasts, kept for clients resuming after a reconnect
+
+use ras_jsonrpc_bidirectional_types::BroadcastMessage;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The last broadcasts of each topic, in sequence order
+#[derive(Debug, Default)]
+pub(crate) struct TopicHistory {
+    /// Broadcasts kept per topic; sequencing is off while this is zero
+    capacity: AtomicUsize,
+    topics: Mutex<HashMap<String, VecDeque<BroadcastMessage>>>,
+}
+
+/// Broadcasts a client missed, as far as the history still holds them
+#[derive(Debug)]
+pub(crate) struct Replay {
+    pub messages: Vec<BroadcastMessage>,
+    /// Whether broadcasts after the client's last one are missing from the history
+    pub gap: bool,
+    pub latest_seq: Option<u64>,
+}
+
+impl TopicHistory {
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        for messages in self.lock().values_mut() {
+            trim(messages, capacity);
+        }
+    }
+
+    /// Whether broadcasts are numbered and kept
+    pub fn is_enabled(&self) -> bool {
+        self.capacity.load(Ordering::Relaxed) > 0
+    }
+
+    /// Keep a numbered broadcast, evicting the topic's oldest beyond the capacity
+    pub fn record(&self, broadcast: &BroadcastMessage) {
+        let Some(seq) = broadcast.seq else {
+            return;
+        };
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return;
+        }
+        let mut topics = self.lock();
+        let messages = topics.entry(broadcast.topic.clone()).or_default();
+        // Broadcasts from other nodes may arrive out of order
+        let position = messages.partition_point(|kept| kept.seq < Some(seq));
+        if messages
+            .get(position)
+            .is_some_and(|kept| kept.seq == Some(seq))
+        {
+            return;
+        }
+        messages.insert(position, broadcast.clone());
+        trim(messages, capacity);
+    }
+
+    /// The broadcasts of `topic` after `last_seq`
+    pub fn since(&self, topic: &str, last_seq: u64) -> Replay {
+        let topics = self.lock();
+        let Some(kept) = topics.get(topic) else {
+            return Replay {
+                messages: Vec::new(),
+                gap: false,
+                latest_seq: None,
+            };
+        };
+        let messages: Vec<BroadcastMessage> = kept
+            .iter()
+            .filter(|message| message.seq.is_some_and(|seq| seq > last_seq))
+            .cloned()
+            .collect();
+        let mut expected = last_seq + 1;
+        let mut gap = false;
+        for seq in messages.iter().filter_map(|message| message.seq) {
+            gap |= seq != expected;
+            expected = seq + 1;
+        }
+        Replay {
+            messages,
+            gap,
+            latest_seq: kept.back().and_then(|message| message.seq),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, VecDeque<BroadcastMessage>>> {
+        self.topics.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn trim(messages: &mut VecDeque<BroadcastMessage>, capacity: usize) {
+    while messages.len() > capacity {
+        messages.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn broadcast(seq: u64) -> BroadcastMessage {
+        BroadcastMessage {
+            topic: "room".to_string(),
+            method: "message".to_string(),
+            params: json!(seq),
+            metadata: None,
+            seq: Some(seq),
+        }
+    }
+
+    fn seqs(replay: &Replay) -> Vec<u64> {
+        replay.messages.iter().filter_map(|m| m.seq).collect()
+    }
+
+    #[test]
+    fn replays_what_was_missed_in_order() {
+        let history = TopicHistory::default();
+        history.set_capacity(8);
+        for seq in [1, 2, 4, 3, 5] {
+            history.record(&broadcast(seq));
+        }
+        history.record(&broadcast(4));
+
+        let replay = history.since("room", 2);
+        assert_eq!(seqs(&replay), [3, 4, 5]);
+        assert!(!replay.gap);
+        assert_eq!(replay.latest_seq, Some(5));
+
+        let replay = history.since("room", 5);
+        assert!(replay.messages.is_empty());
+        assert!(!replay.gap);
+    }
+
+    #[test]
+    fn evicted_broadcasts_are_reported_as_a_gap() {
+        let history = TopicHistory::default();
+        history.set_capacity(3);
+        for seq in 1..=6 {
+            history.record(&broadcast(seq));
+        }
+
+        let replay = history.since("room", 1);
+        assert_eq!(seqs(&replay), [4, 5, 6]);
+        assert!(replay.gap);
+        assert!(!history.since("room", 3).gap);
+    }
+
+    #[test]
+    fn nothing_is_kept_while_disabled() {
+        let history = TopicHistory::default();
+        assert!(!history.is_enabled());
+        history.record(&broadcast(1));
+        assert_eq!(history.since("room", 0).latest_seq, None);
+    }
+}
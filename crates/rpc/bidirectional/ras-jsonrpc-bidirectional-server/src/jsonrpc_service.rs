@@ -0,0 +1,123 @@
+//! Serving `jsonrpc_service!` methods over WebSocket
+//!
+//! Plain request/response services are dispatched per message through the
+//! same [`JsonRpcService`] the HTTP endpoint uses, so auth, limits and
+//! trackers behave the same on both transports.
+
+use crate::{
+    ConnectionContext, MessageHandler, OverflowPolicy, ServerResult, WebSocketHandler,
+    WebSocketUpgrade,
+    connection::ChannelMessageSender,
+    queue::outbound_queue,
+    service::{DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_MESSAGE_CHANNEL_CAPACITY},
+};
+use async_trait::async_trait;
+use axum::{
+    extract::ws::{WebSocket, WebSocketUpgrade as AxumWebSocketUpgrade},
+    http::HeaderMap,
+    routing::MethodRouter,
+};
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId};
+use ras_jsonrpc_core::JsonRpcService;
+use ras_jsonrpc_types::{JsonRpcRequest, JsonRpcResponse};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Message handler dispatching requests to a [`JsonRpcService`]
+///
+/// Requests are authenticated from the headers of the upgrade request, like
+/// HTTP requests are from their own headers. Each request runs on its own
+/// task, so a slow method does not hold up later calls on the connection.
+pub struct JsonRpcServiceHandler<S> {
+    service: Arc<S>,
+    headers: HeaderMap,
+}
+
+impl<S: JsonRpcService> JsonRpcServiceHandler<S> {
+    /// Create a handler for a connection upgraded with `headers`
+    pub fn new(service: Arc<S>, headers: HeaderMap) -> Self {
+        Self { service, headers }
+    }
+}
+
+#[async_trait]
+impl<S: JsonRpcService> MessageHandler for JsonRpcServiceHandler<S> {
+    async fn handle_request(
+        &self,
+        request: JsonRpcRequest,
+        context: Arc<ConnectionContext>,
+    ) -> ServerResult<Option<JsonRpcResponse>> {
+        let is_notification = request.id.is_none();
+        let request = serde_json::to_value(request)?;
+        // The message was parsed already, so its re-serialized size stands in
+        let request_size = request.to_string().len();
+
+        let service = self.service.clone();
+        let headers = self.headers.clone();
+        tokio::spawn(async move {
+            let response = service.dispatch(&headers, request, request_size).await;
+            if is_notification {
+                return;
+            }
+            if let Err(e) = context
+                .sender
+                .send(BidirectionalMessage::Response(response))
+                .await
+            {
+                warn!("Failed to send response to {}: {}", context.id, e);
+            }
+        });
+
+        // The response is sent by the task above
+        Ok(None)
+    }
+}
+
+/// Axum route serving `service` over WebSocket
+///
+/// Pair it with the `WebSocketRpcTransport` of
+/// `ras-jsonrpc-bidirectional-client` to call the service's generated client
+/// over one persistent connection:
+///
+/// ```rust,ignore
+/// let app = Router::new()
+///     .route("/ws", jsonrpc_websocket_route(TasksBuilder::new(tasks).auth_provider(auth)));
+/// ```
+pub fn jsonrpc_websocket_route<S: JsonRpcService>(service: S) -> MethodRouter {
+    let service = Arc::new(service);
+
+    axum::routing::get(move |upgrade: AxumWebSocketUpgrade, headers: HeaderMap| {
+        let service = service.clone();
+        async move {
+            let upgrade = WebSocketUpgrade::new(upgrade, headers.clone())
+                .max_message_size(DEFAULT_MAX_MESSAGE_SIZE);
+            upgrade.on_upgrade(move |socket| {
+                Box::pin(async move {
+                    if let Err(e) = serve_connection(service, headers, socket).await {
+                        error!("WebSocket connection error: {}", e);
+                    }
+                })
+            })
+        }
+    })
+}
+
+/// Run the message loop of one connection
+async fn serve_connection<S: JsonRpcService>(
+    service: Arc<S>,
+    headers: HeaderMap,
+    socket: WebSocket,
+) -> ServerResult<()> {
+    let connection_id = ConnectionId::new();
+    info!("New JSON-RPC WebSocket connection: {}", connection_id);
+
+    let (message_tx, message_rx) =
+        outbound_queue(DEFAULT_MESSAGE_CHANNEL_CAPACITY, OverflowPolicy::default());
+    let sender = ChannelMessageSender::new(connection_id, message_tx);
+    let context = Arc::new(ConnectionContext::new(connection_id, sender));
+    let handler = Arc::new(JsonRpcServiceHandler::new(service, headers));
+
+    WebSocketHandler::new(handler, context, message_rx, DEFAULT_MAX_MESSAGE_SIZE)
+        .run(socket)
+        .await
+}
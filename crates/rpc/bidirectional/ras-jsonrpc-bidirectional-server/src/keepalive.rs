@@ -0,0 +1,44 @@
+//! Protocol-level keepalive for WebSocket connections
+
+use std::time::Duration;
+
+/// How often the server pings its connections, and how many unanswered pings it
+/// tolerates before closing one
+///
+/// Pings keep idle connections open behind load balancers, and connections whose
+/// peer vanished without closing them are closed and removed from the connection
+/// manager instead of lingering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    /// Interval between pings; `None` disables keepalive
+    pub ping_interval: Option<Duration>,
+
+    /// Consecutive pings without a pong after which the connection is closed
+    pub max_missed_pongs: u32,
+}
+
+impl KeepaliveConfig {
+    /// Ping every `ping_interval`, closing connections after `max_missed_pongs`
+    /// unanswered pings
+    pub fn new(ping_interval: Duration, max_missed_pongs: u32) -> Self {
+        Self {
+            ping_interval: Some(ping_interval),
+            max_missed_pongs,
+        }
+    }
+
+    /// Never ping connections
+    pub fn disabled() -> Self {
+        Self {
+            ping_interval: None,
+            max_missed_pongs: 0,
+        }
+    }
+}
+
+impl Default for KeepaliveConfig {
+    /// Ping every 30 seconds, closing connections after 2 unanswered pings
+    fn default() -> Self {
+        Self::new(Duration::from_secs(30), 2)
+    }
+}
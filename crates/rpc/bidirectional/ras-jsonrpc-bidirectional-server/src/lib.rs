@@ -5,24 +5,59 @@
 
 pub mod connection;
 pub mod error;
+mod filters;
 pub mod handler;
+mod history;
+pub mod jsonrpc_service;
+pub mod keepalive;
+pub mod limits;
 pub mod manager;
+pub mod middleware;
+mod presence;
+pub mod queue;
+pub mod rate_limit;
+#[cfg(feature = "redis")]
+pub mod redis_registry;
+pub mod registry;
+pub mod revocation;
 pub mod router;
 pub mod service;
+mod session;
+pub mod shutdown;
 pub mod upgrade;
 
 pub use connection::ConnectionContext;
 pub use error::{ServerError, ServerResult};
-pub use handler::{MessageHandler, WebSocketHandler};
+pub use handler::{
+    ConnectionHook, MessageHandler, TopicAuthorizer, WebSocketHandler, connection_hook,
+};
+pub use jsonrpc_service::{JsonRpcServiceHandler, jsonrpc_websocket_route};
+pub use keepalive::KeepaliveConfig;
+pub use limits::{FrameStats, MessageLimits};
 pub use manager::DefaultConnectionManager;
+pub use middleware::{ControlFlow, MessageMiddleware, MiddlewareChain, OutgoingMessage};
+pub use queue::{OverflowPolicy, QueueStats};
+pub use rate_limit::{RateLimit, RateLimitConfig};
+#[cfg(feature = "redis")]
+pub use redis_registry::RedisRegistry;
+pub use registry::{
+    Audience, ConnectionRegistry, Delivery, LocalRegistry, MemoryBus, MemoryRegistry, NodeId,
+};
+pub use revocation::{SessionRevocationStream, SessionRevocations};
 pub use router::MessageRouter;
 pub use service::{WebSocketService, WebSocketServiceBuilder};
+pub use shutdown::ShutdownCoordinator;
 pub use upgrade::WebSocketUpgrade;
 
 // Re-export types from bidirectional-types for convenience
 pub use ras_jsonrpc_bidirectional_types::{
-    BidirectionalMessage, BroadcastMessage, ConnectionId, ConnectionInfo, MessageSender,
-    ServerMessage, ServerNotification,
+    BidirectionalMessage, BroadcastMessage, CANCEL_REQUEST_METHOD, CancelRequest, CloseReason,
+    Codec, ConnectionId, ConnectionInfo, FILTER_SET_METHOD, FilterSet, MessageSender,
+    PRESENCE_JOIN_METHOD, PRESENCE_JOINED_NOTIFICATION, PRESENCE_LEAVE_METHOD,
+    PRESENCE_LEFT_NOTIFICATION, PRESENCE_LIST_METHOD, PresenceEntry, PresenceEvent, PresenceJoin,
+    PresenceKey, SESSION_EXPIRING_NOTIFICATION, SESSION_REFRESH_METHOD, SHUTDOWN_NOTIFICATION,
+    ServerMessage, ServerNotification, SessionExpiring, SessionRefresh, ShutdownNotice,
+    TOPIC_RESUME_METHOD, TopicResume, TopicResumed,
 };
 
 // Re-export auth types for convenience
@@ -30,3 +65,6 @@ pub use ras_auth_core::{AuthError, AuthProvider, AuthenticatedUser};
 
 // Re-export JSON-RPC types
 pub use ras_jsonrpc_types::{JsonRpcRequest, JsonRpcResponse};
+
+// Re-export observability types
+pub use ras_jsonrpc_core::{Observability, Protocol, RequestContext, ServiceMetrics};
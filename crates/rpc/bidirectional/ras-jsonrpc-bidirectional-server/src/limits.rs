@@ -0,0 +1,137 @@
+//! Message size limits and accounting of rejected frames
+
+use crate::service::DEFAULT_MAX_MESSAGE_SIZE;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Malformed frames a connection may send before it is closed, by default
+pub const DEFAULT_MAX_MALFORMED_FRAMES: u32 = 10;
+
+/// Limits applied to the messages of each connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLimits {
+    /// Largest message accepted from a client, in bytes
+    pub max_incoming_size: usize,
+    /// Largest message sent to a client, in bytes; unlimited when `None`
+    pub max_outgoing_size: Option<usize>,
+    /// Malformed frames tolerated before the connection is closed; `0` never closes it
+    pub max_malformed_frames: u32,
+}
+
+impl Default for MessageLimits {
+    fn default() -> Self {
+        Self {
+            max_incoming_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_outgoing_size: None,
+            max_malformed_frames: DEFAULT_MAX_MALFORMED_FRAMES,
+        }
+    }
+}
+
+/// Counts frames rejected across all of a service's connections
+///
+/// Cloning shares the same counters.
+#[derive(Clone, Default)]
+pub struct FrameStats {
+    inner: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    malformed: AtomicU64,
+    oversized_incoming: AtomicU64,
+    oversized_outgoing: AtomicU64,
+}
+
+impl FrameStats {
+    /// Create counters starting at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Frames that were not valid UTF-8, JSON or a known message
+    pub fn malformed(&self) -> u64 {
+        self.inner.malformed.load(Ordering::Relaxed)
+    }
+
+    /// Messages from clients rejected for exceeding the size limit
+    pub fn oversized_incoming(&self) -> u64 {
+        self.inner.oversized_incoming.load(Ordering::Relaxed)
+    }
+
+    /// Messages to clients withheld for exceeding the size limit
+    pub fn oversized_outgoing(&self) -> u64 {
+        self.inner.oversized_outgoing.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_malformed(&self) {
+        self.inner.malformed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_oversized_incoming(&self) {
+        self.inner
+            .oversized_incoming
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_oversized_outgoing(&self) {
+        self.inner
+            .oversized_outgoing
+            .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl std::fmt::Debug for FrameStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameStats")
+            .field("malformed", &self.malformed())
+            .field("oversized_incoming", &self.oversized_incoming())
+            .field("oversized_outgoing", &self.oversized_outgoing())
+            .finish()
+    }
+}
+
+/// Whether a WebSocket read failed because the message exceeded the configured limit
+///
+/// axum does not expose tungstenite's error type, so this matches on the
+/// message of its capacity error.
+pub(crate) fn is_size_limit_error(error: &axum::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if error.to_string().contains("Space limit exceeded") {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_counters() {
+        let stats = FrameStats::new();
+        let clone = stats.clone();
+        clone.record_malformed();
+        clone.record_malformed();
+        clone.record_oversized_incoming();
+        stats.record_oversized_outgoing();
+
+        assert_eq!(stats.malformed(), 2);
+        assert_eq!(stats.oversized_incoming(), 1);
+        assert_eq!(clone.oversized_outgoing(), 1);
+    }
+
+    #[test]
+    fn size_limit_errors_are_recognized() {
+        let capacity = axum::Error::new(std::io::Error::other(
+            "Space limit exceeded: Message too long: 2048 > 1024",
+        ));
+        assert!(is_size_limit_error(&capacity));
+
+        let reset = axum::Error::new(std::io::Error::other("Connection reset"));
+        assert!(!is_size_limit_error(&reset));
+    }
+}
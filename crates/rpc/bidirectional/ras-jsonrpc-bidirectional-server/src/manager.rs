@@ -1,18 +1,31 @@
 //! Default connection manager implementation using DashMap
 
 use crate::connection::ChannelMessageSender;
+use crate::history::TopicHistory;
+use crate::queue::{Enqueued, OverflowPolicy, QueueStats, outbound_queue};
+use crate::registry::{Audience, ConnectionRegistry, Delivery, LocalRegistry};
 use async_trait::async_trait;
 use dashmap::DashMap;
 use ras_auth_core::AuthenticatedUser;
 use ras_jsonrpc_bidirectional_types::{
-    BidirectionalMessage, ConnectionId, ConnectionInfo, ConnectionManager, Result,
+    BidirectionalError, BidirectionalMessage, ConnectionId, ConnectionInfo, ConnectionManager,
+    PRESENCE_JOINED_NOTIFICATION, PRESENCE_LEFT_NOTIFICATION, PresenceEntry, PresenceEvent, Result,
+    ServerNotification, TopicResumed,
 };
-use std::collections::HashMap;
-use tokio::sync::{mpsc, oneshot};
+use ras_jsonrpc_core::{RequestContext, ServiceMetrics};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::{Mutex, mpsc, oneshot};
 use tracing::{debug, info, warn};
 
 /// Thread-safe connection manager using DashMap for high-performance concurrent access
-#[derive(Debug, Default)]
+///
+/// Connections held by other server nodes are reached through a
+/// [`ConnectionRegistry`]; see [`with_registry`](Self::with_registry). Server-to-client
+/// calls must still be made on the node holding the connection, since the response
+/// arrives there.
+#[derive(Debug)]
 pub struct DefaultConnectionManager {
     /// Active connections indexed by ConnectionId
     connections: DashMap<ConnectionId, (ConnectionInfo, ChannelMessageSender)>,
@@ -26,6 +39,68 @@ pub struct DefaultConnectionManager {
         ConnectionId,
         HashMap<serde_json::Value, oneshot::Sender<ras_jsonrpc_types::JsonRpcResponse>>,
     >,
+
+    /// Broadcast deliveries skipped because the connection's queue was full
+    skipped_broadcasts: AtomicU64,
+
+    /// Where connections held by other nodes are found
+    registry: Arc<dyn ConnectionRegistry>,
+
+    /// Records how many local connections each broadcast reached
+    metrics: BroadcastMetrics,
+
+    /// Recent broadcasts of each topic, replayed to resuming connections
+    history: TopicHistory,
+
+    /// Held while a topic broadcast is numbered and queued, or a replay is queued,
+    /// so connections receive each topic's broadcasts in sequence order
+    topic_order: Mutex<()>,
+
+    /// Keys each local connection is present under, left when it closes
+    presence: DashMap<ConnectionId, HashSet<String>>,
+
+    /// Held while presence changes, so a user joining or leaving on two
+    /// connections at once is announced exactly once
+    presence_changes: Mutex<()>,
+
+    /// Notifications each local connection registered a filter for, dropped when
+    /// it closes
+    filters: DashMap<ConnectionId, HashSet<String>>,
+}
+
+/// Service metrics shared with the manager after it was created
+#[derive(Default)]
+struct BroadcastMetrics(RwLock<Option<Arc<dyn ServiceMetrics>>>);
+
+impl std::fmt::Debug for BroadcastMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let enabled = self.0.read().is_ok_and(|metrics| metrics.is_some());
+        f.debug_tuple("BroadcastMetrics").field(&enabled).finish()
+    }
+}
+
+impl BroadcastMetrics {
+    fn record(&self, message: &BidirectionalMessage, recipients: usize) {
+        let Ok(metrics) = self.0.read() else {
+            return;
+        };
+        let Some(metrics) = metrics.as_ref() else {
+            return;
+        };
+        let method = match message {
+            BidirectionalMessage::Broadcast(broadcast) => broadcast.method.clone(),
+            BidirectionalMessage::ServerNotification(notification) => notification.method.clone(),
+            BidirectionalMessage::Request(request) => request.method.clone(),
+            _ => String::new(),
+        };
+        metrics.record_broadcast_fanout(&RequestContext::websocket(method), recipients);
+    }
+}
+
+impl Default for DefaultConnectionManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DefaultConnectionManager {
@@ -35,9 +110,64 @@ impl DefaultConnectionManager {
             connections: DashMap::new(),
             subscriptions: DashMap::new(),
             pending_requests: DashMap::new(),
+            skipped_broadcasts: AtomicU64::new(0),
+            registry: Arc::new(LocalRegistry::new()),
+            metrics: BroadcastMetrics::default(),
+            history: TopicHistory::default(),
+            topic_order: Mutex::new(()),
+            presence: DashMap::new(),
+            presence_changes: Mutex::new(()),
+            filters: DashMap::new(),
         }
     }
 
+    /// Number topic broadcasts and keep the last `capacity` of each topic for
+    /// connections resuming after a reconnect; `0` turns this off (the default)
+    ///
+    /// Sequence numbers come from the registry, so nodes sharing one number a
+    /// topic's broadcasts together.
+    pub fn set_topic_history(&self, capacity: usize) {
+        self.history.set_capacity(capacity);
+    }
+
+    /// Record the number of local connections each broadcast reaches to `metrics`
+    pub fn set_service_metrics(&self, metrics: Arc<dyn ServiceMetrics>) {
+        if let Ok(mut slot) = self.metrics.0.write() {
+            *slot = Some(metrics);
+        }
+    }
+
+    /// Create a connection manager sharing its connections with other nodes
+    ///
+    /// Sends and broadcasts reach matching connections on every node attached to the
+    /// same registry. Messages from other nodes are delivered by a background task,
+    /// which stops once the manager is dropped.
+    pub async fn with_registry(registry: Arc<dyn ConnectionRegistry>) -> Result<Arc<Self>> {
+        let (inbox, mut deliveries) = mpsc::unbounded_channel();
+        registry.attach(inbox).await?;
+        info!("Connection manager joined as node {}", registry.node_id());
+
+        let manager = Arc::new(Self {
+            registry,
+            ..Self::new()
+        });
+        let weak = Arc::downgrade(&manager);
+        tokio::spawn(async move {
+            while let Some(delivery) = deliveries.recv().await {
+                let Some(manager) = weak.upgrade() else {
+                    break;
+                };
+                manager.deliver(delivery).await;
+            }
+        });
+        Ok(manager)
+    }
+
+    /// The registry reaching other nodes' connections
+    pub fn registry(&self) -> &Arc<dyn ConnectionRegistry> {
+        &self.registry
+    }
+
     /// Get the number of active connections
     pub fn connection_count(&self) -> usize {
         self.connections.len()
@@ -70,8 +200,7 @@ impl DefaultConnectionManager {
         info: ConnectionInfo,
         sender: ChannelMessageSender,
     ) -> Result<()> {
-        self.connections.insert(info.id, (info.clone(), sender));
-        info!("Added connection: {}", info.id);
+        self.insert(info, sender).await;
         Ok(())
     }
 
@@ -79,16 +208,231 @@ impl DefaultConnectionManager {
     pub fn get_sender(&self, id: ConnectionId) -> Option<ChannelMessageSender> {
         self.connections.get(&id).map(|entry| entry.1.clone())
     }
+
+    /// Outgoing queue depth of a connection
+    pub fn queue_stats(&self, id: ConnectionId) -> Option<QueueStats> {
+        self.connections.get(&id).map(|entry| entry.1.queue_stats())
+    }
+
+    /// Outgoing queue depth of every connection, for exporting as a gauge
+    pub fn queue_depths(&self) -> Vec<(ConnectionId, QueueStats)> {
+        self.connections
+            .iter()
+            .map(|entry| (*entry.key(), entry.1.queue_stats()))
+            .collect()
+    }
+
+    /// Broadcast deliveries skipped so far because a connection's queue was full
+    pub fn skipped_broadcasts(&self) -> u64 {
+        self.skipped_broadcasts.load(Ordering::Relaxed)
+    }
+
+    /// Queue a broadcast message without waiting, counting full queues as skipped
+    fn offer(&self, sender: &ChannelMessageSender, message: BidirectionalMessage) -> Enqueued {
+        let outcome = sender.try_send(message);
+        if matches!(outcome, Enqueued::Dropped | Enqueued::Disconnected) {
+            self.skipped_broadcasts.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "Skipped broadcast to {}: outgoing queue full",
+                sender.connection_id()
+            );
+        }
+        outcome
+    }
+
+    /// Hold a connection on this node
+    async fn insert(&self, info: ConnectionInfo, sender: ChannelMessageSender) {
+        let id = info.id;
+        self.connections.insert(id, (info, sender));
+        if let Err(e) = self.registry.register(id).await {
+            warn!("Failed to register connection {}: {}", id, e);
+        }
+        info!("Added connection: {}", id);
+    }
+
+    /// Take a connection out of a topic, leaving the topic once it has no local subscribers
+    async fn unsubscribe_local(&self, id: ConnectionId, topic: &str) {
+        let Some(mut entry) = self.subscriptions.get_mut(topic) else {
+            return;
+        };
+        entry.retain(|&connection_id| connection_id != id);
+        if entry.is_empty() {
+            drop(entry);
+            self.subscriptions.remove(topic);
+            if let Err(e) = self.registry.unsubscribe(topic).await {
+                warn!("Failed to leave topic {}: {}", topic, e);
+            }
+        }
+    }
+
+    /// Queue a message for the local subscribers of a topic
+    ///
+    /// Callers hold `topic_order`, so numbered broadcasts go out in order.
+    async fn broadcast_local_topic(&self, topic: &str, message: BidirectionalMessage) -> usize {
+        if let BidirectionalMessage::Broadcast(broadcast) = &message {
+            self.history.record(broadcast);
+        }
+        let topic_connections = self.get_topic_connections(topic);
+
+        if topic_connections.is_empty() {
+            debug!("No connections subscribed to topic: {}", topic);
+            return 0;
+        }
+
+        let mut failed_connections = Vec::new();
+        let mut sent_count = 0;
+
+        for connection_id in &topic_connections {
+            let Some(sender) = self.get_sender(*connection_id) else {
+                failed_connections.push(*connection_id);
+                continue;
+            };
+            // A full queue skips this message but keeps the subscription
+            match self.offer(&sender, message.clone()) {
+                Enqueued::Queued | Enqueued::ReplacedOldest => sent_count += 1,
+                Enqueued::Dropped | Enqueued::Disconnected => {}
+                Enqueued::Closed => {
+                    warn!(
+                        "Failed to broadcast to connection {}: connection closed",
+                        connection_id
+                    );
+                    failed_connections.push(*connection_id);
+                }
+            }
+        }
+
+        // Clean up failed connections from topic subscriptions
+        for connection_id in failed_connections {
+            let _ = self.remove_subscription(connection_id, topic).await;
+        }
+
+        debug!(
+            "Broadcasted to {} connections on topic: {}",
+            sent_count, topic
+        );
+        sent_count
+    }
+
+    /// Queue a message for every local connection matching `filter`
+    fn broadcast_local(
+        &self,
+        filter: impl Fn(&ConnectionInfo) -> bool,
+        message: BidirectionalMessage,
+    ) -> usize {
+        let mut sent_count = 0;
+        for entry in self.connections.iter() {
+            let (info, sender) = entry.value();
+            if filter(info) && self.offer(sender, message.clone()).is_queued() {
+                sent_count += 1;
+            }
+        }
+        sent_count
+    }
+
+    /// Pass a broadcast on to the other nodes; local deliveries already went out
+    async fn broadcast_remote(&self, audience: Audience, message: BidirectionalMessage) {
+        let result = match audience {
+            Audience::Topic { topic } => self.registry.publish(&topic, message).await,
+            audience => self.registry.broadcast(audience, message).await,
+        };
+        if let Err(e) = result {
+            warn!("Failed to forward broadcast to other nodes: {}", e);
+        }
+    }
+
+    /// Hand a message from another node to the local connections it is for
+    ///
+    /// Like broadcasts, these are queued without waiting on a full queue.
+    /// Withdraw a connection from `key`, announcing it once its user is gone
+    async fn leave(&self, id: ConnectionId, key: &str) -> Result<bool> {
+        let _changes = self.presence_changes.lock().await;
+        let Some(entry) = self.registry.remove_presence(key, id).await? else {
+            return Ok(false);
+        };
+        let members = self.registry.presence(key).await?;
+        if !members.iter().any(|member| member.same_member(&entry)) {
+            self.announce_presence(PRESENCE_LEFT_NOTIFICATION, key, &entry, &members)
+                .await;
+        }
+        debug!("Connection {} left presence of {}", id, key);
+        Ok(true)
+    }
+
+    /// Send a presence notification about `entry` to the other `members` of `key`
+    async fn announce_presence(
+        &self,
+        method: &str,
+        key: &str,
+        entry: &PresenceEntry,
+        members: &[PresenceEntry],
+    ) {
+        let event = PresenceEvent {
+            key: key.to_string(),
+            entry: entry.clone(),
+        };
+        let params = match serde_json::to_value(event) {
+            Ok(params) => params,
+            Err(e) => {
+                warn!("Failed to serialize presence event for {}: {}", key, e);
+                return;
+            }
+        };
+        let message = BidirectionalMessage::ServerNotification(ServerNotification {
+            method: method.to_string(),
+            params,
+            metadata: None,
+        });
+        for member in members {
+            if member.connection_id == entry.connection_id {
+                continue;
+            }
+            if let Err(e) = self
+                .send_to_connection(member.connection_id, message.clone())
+                .await
+            {
+                warn!(
+                    "Failed to send {} to connection {}: {}",
+                    method, member.connection_id, e
+                );
+            }
+        }
+    }
+
+    async fn deliver(&self, delivery: Delivery) {
+        let Delivery {
+            origin,
+            audience,
+            message,
+        } = delivery;
+        let delivered = match audience {
+            Audience::Connection { id } => self.get_sender(id).map_or(0, |sender| {
+                usize::from(self.offer(&sender, message).is_queued())
+            }),
+            Audience::Topic { topic } => {
+                let _order = self.topic_order.lock().await;
+                self.broadcast_local_topic(&topic, message).await
+            }
+            Audience::Authenticated => {
+                self.broadcast_local(ConnectionInfo::is_authenticated, message)
+            }
+            Audience::Permission { permission } => {
+                self.broadcast_local(|info| info.has_permission(&permission), message)
+            }
+        };
+        debug!(
+            "Delivered message from node {} to {} connections",
+            origin, delivered
+        );
+    }
 }
 
 #[async_trait]
 impl ConnectionManager for DefaultConnectionManager {
     async fn add_connection(&self, info: ConnectionInfo) -> Result<()> {
         // Create a dummy sender - real senders should be added via add_connection_with_sender
-        let (tx, _rx) = mpsc::channel(1);
+        let (tx, _rx) = outbound_queue(1, OverflowPolicy::default());
         let sender = ChannelMessageSender::new(info.id, tx);
-        self.connections.insert(info.id, (info.clone(), sender));
-        info!("Added connection: {}", info.id);
+        self.insert(info, sender).await;
         Ok(())
     }
 
@@ -99,9 +443,7 @@ impl ConnectionManager for DefaultConnectionManager {
     ) -> Result<()> {
         // Try to downcast to ChannelMessageSender
         if let Ok(channel_sender) = sender.downcast::<ChannelMessageSender>() {
-            self.connections
-                .insert(info.id, (info.clone(), *channel_sender));
-            info!("Added connection with sender: {}", info.id);
+            self.insert(info, *channel_sender).await;
             Ok(())
         } else {
             // Fallback to dummy sender if downcast fails
@@ -113,18 +455,36 @@ impl ConnectionManager for DefaultConnectionManager {
         if let Some((_, (info, _))) = self.connections.remove(&id) {
             // Remove from all topic subscriptions
             for topic in info.subscriptions.iter() {
-                if let Some(mut entry) = self.subscriptions.get_mut(topic) {
-                    entry.retain(|&connection_id| connection_id != id);
-                    if entry.is_empty() {
-                        drop(entry);
-                        self.subscriptions.remove(topic);
-                    }
-                }
+                self.unsubscribe_local(id, topic).await;
             }
 
             // Clean up pending requests for this connection
             self.pending_requests.remove(&id);
 
+            // Leave every key, telling the remaining members
+            if let Some((_, keys)) = self.presence.remove(&id) {
+                for key in keys {
+                    if let Err(e) = self.leave(id, &key).await {
+                        warn!("Failed to leave presence of {} for {}: {}", key, id, e);
+                    }
+                }
+            }
+
+            if let Some((_, notifications)) = self.filters.remove(&id) {
+                for notification in notifications {
+                    if let Err(e) = self.registry.remove_filter(&notification, id).await {
+                        warn!(
+                            "Failed to drop filter of {} for {}: {}",
+                            notification, id, e
+                        );
+                    }
+                }
+            }
+
+            if let Err(e) = self.registry.deregister(id).await {
+                warn!("Failed to deregister connection {}: {}", id, e);
+            }
+
             info!("Removed connection: {}", id);
         } else {
             warn!("Attempted to remove non-existent connection: {}", id);
@@ -181,12 +541,24 @@ impl ConnectionManager for DefaultConnectionManager {
         Ok(())
     }
 
+    async fn mark_seen(&self, id: ConnectionId) -> Result<()> {
+        if let Some(mut entry) = self.connections.get_mut(&id) {
+            entry.0.last_seen = chrono::Utc::now();
+        }
+        Ok(())
+    }
+
     async fn add_subscription(&self, id: ConnectionId, topic: String) -> Result<()> {
         // Update topic subscriptions
-        self.subscriptions
-            .entry(topic.clone())
-            .or_default()
-            .push(id);
+        let mut subscribers = self.subscriptions.entry(topic.clone()).or_default();
+        let first = subscribers.is_empty();
+        if !subscribers.contains(&id) {
+            subscribers.push(id);
+        }
+        drop(subscribers);
+        if first {
+            self.registry.subscribe(&topic).await?;
+        }
 
         // Update connection subscriptions
         if let Some(mut entry) = self.connections.get_mut(&id) {
@@ -199,13 +571,7 @@ impl ConnectionManager for DefaultConnectionManager {
 
     async fn remove_subscription(&self, id: ConnectionId, topic: &str) -> Result<()> {
         // Update topic subscriptions
-        if let Some(mut entry) = self.subscriptions.get_mut(topic) {
-            entry.retain(|&connection_id| connection_id != id);
-            if entry.is_empty() {
-                drop(entry);
-                self.subscriptions.remove(topic);
-            }
-        }
+        self.unsubscribe_local(id, topic).await;
 
         // Update connection subscriptions
         if let Some(mut entry) = self.connections.get_mut(&id) {
@@ -235,7 +601,7 @@ impl ConnectionManager for DefaultConnectionManager {
                 .send(message)
                 .await
                 .map_err(ras_jsonrpc_bidirectional_types::BidirectionalError::SendError)?;
-        } else {
+        } else if !self.registry.send_to(id, message).await? {
             warn!("Attempted to send to non-existent connection: {}", id);
         }
         Ok(())
@@ -246,59 +612,31 @@ impl ConnectionManager for DefaultConnectionManager {
         topic: &str,
         message: BidirectionalMessage,
     ) -> Result<usize> {
-        let topic_connections = self.get_topic_connections(topic);
-
-        if topic_connections.is_empty() {
-            debug!("No connections subscribed to topic: {}", topic);
-            return Ok(0);
-        }
-
-        let mut failed_connections = Vec::new();
-        let mut sent_count = 0;
-
-        for connection_id in &topic_connections {
-            if let Some(entry) = self.connections.get(connection_id) {
-                if let Err(e) = entry.1.send(message.clone()).await {
-                    warn!("Failed to broadcast to connection {}: {}", connection_id, e);
-                    failed_connections.push(*connection_id);
-                } else {
-                    sent_count += 1;
+        let (message, sent_count) = {
+            let _order = self.topic_order.lock().await;
+            let message = match message {
+                BidirectionalMessage::Broadcast(mut broadcast) if self.history.is_enabled() => {
+                    broadcast.seq = Some(self.registry.next_sequence(topic).await?);
+                    BidirectionalMessage::Broadcast(broadcast)
                 }
-            } else {
-                failed_connections.push(*connection_id);
-            }
-        }
-
-        // Clean up failed connections from topic subscriptions
-        if !failed_connections.is_empty() {
-            for connection_id in failed_connections {
-                let _ = self.remove_subscription(connection_id, topic).await;
-            }
-        }
-
-        debug!(
-            "Broadcasted to {} connections on topic: {}",
-            sent_count, topic
-        );
+                message => message,
+            };
+            let sent_count = self.broadcast_local_topic(topic, message.clone()).await;
+            (message, sent_count)
+        };
+        self.metrics.record(&message, sent_count);
+        let audience = Audience::Topic {
+            topic: topic.to_string(),
+        };
+        self.broadcast_remote(audience, message).await;
         Ok(sent_count)
     }
 
     async fn broadcast_to_authenticated(&self, message: BidirectionalMessage) -> Result<usize> {
-        let mut sent_count = 0;
-
-        for entry in self.connections.iter() {
-            let (info, sender) = entry.value();
-            if info.is_authenticated() {
-                if let Err(e) = sender.send(message.clone()).await {
-                    warn!(
-                        "Failed to broadcast to authenticated connection {}: {}",
-                        info.id, e
-                    );
-                } else {
-                    sent_count += 1;
-                }
-            }
-        }
+        let sent_count = self.broadcast_local(ConnectionInfo::is_authenticated, message.clone());
+        self.metrics.record(&message, sent_count);
+        self.broadcast_remote(Audience::Authenticated, message)
+            .await;
 
         debug!("Broadcasted to {} authenticated connections", sent_count);
         Ok(sent_count)
@@ -309,27 +647,135 @@ impl ConnectionManager for DefaultConnectionManager {
         permission: &str,
         message: BidirectionalMessage,
     ) -> Result<usize> {
-        let mut sent_count = 0;
+        let sent_count =
+            self.broadcast_local(|info| info.has_permission(permission), message.clone());
+        self.metrics.record(&message, sent_count);
+        let audience = Audience::Permission {
+            permission: permission.to_string(),
+        };
+        self.broadcast_remote(audience, message).await;
 
-        for entry in self.connections.iter() {
-            let (info, sender) = entry.value();
-            if info.has_permission(permission) {
-                if let Err(e) = sender.send(message.clone()).await {
-                    warn!(
-                        "Failed to broadcast to connection {} with permission {}: {}",
-                        info.id, permission, e
-                    );
+        debug!(
+            "Broadcasted to {} connections with permission: {}",
+            sent_count, permission
+        );
+        Ok(sent_count)
+    }
+
+    async fn resume_topic(
+        &self,
+        id: ConnectionId,
+        topic: &str,
+        last_seq: u64,
+    ) -> Result<TopicResumed> {
+        // No broadcast to the topic can slip in between the replay and the subscription
+        let _order = self.topic_order.lock().await;
+        self.add_subscription(id, topic.to_string()).await?;
+        let replay = self.history.since(topic, last_seq);
+        let mut replayed = 0;
+        let mut gap = replay.gap;
+        if let Some(sender) = self.get_sender(id) {
+            for broadcast in replay.messages {
+                let message = BidirectionalMessage::Broadcast(broadcast);
+                if self.offer(&sender, message).is_queued() {
+                    replayed += 1;
                 } else {
-                    sent_count += 1;
+                    gap = true;
                 }
             }
         }
-
         debug!(
-            "Broadcasted to {} connections with permission: {}",
-            sent_count, permission
+            "Replayed {} broadcasts on topic {} to connection {}",
+            replayed, topic, id
         );
-        Ok(sent_count)
+        Ok(TopicResumed {
+            replayed,
+            gap,
+            latest_seq: replay.latest_seq,
+        })
+    }
+
+    async fn join_presence(
+        &self,
+        id: ConnectionId,
+        key: &str,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<PresenceEntry> {
+        let user_id = self
+            .connections
+            .get(&id)
+            .map(|entry| entry.0.user.as_ref().map(|user| user.user_id.clone()))
+            .ok_or(BidirectionalError::ConnectionNotFound(id))?;
+        let mut entry = PresenceEntry {
+            user_id,
+            connection_id: id,
+            joined_at: chrono::Utc::now(),
+            metadata,
+        };
+
+        let _changes = self.presence_changes.lock().await;
+        let members = self.registry.presence(key).await?;
+        if let Some(previous) = members.iter().find(|member| member.connection_id == id) {
+            // Joining again only updates the metadata
+            entry.joined_at = previous.joined_at;
+        }
+        let already_present = members.iter().any(|member| member.same_member(&entry));
+        self.registry.add_presence(key, entry.clone()).await?;
+        self.presence.entry(id).or_default().insert(key.to_string());
+        if !already_present {
+            self.announce_presence(PRESENCE_JOINED_NOTIFICATION, key, &entry, &members)
+                .await;
+        }
+
+        debug!("Connection {} joined presence of {}", id, key);
+        Ok(entry)
+    }
+
+    async fn leave_presence(&self, id: ConnectionId, key: &str) -> Result<bool> {
+        if let Some(mut keys) = self.presence.get_mut(&id) {
+            keys.remove(key);
+        }
+        self.leave(id, key).await
+    }
+
+    async fn list_presence(&self, key: &str) -> Result<Vec<PresenceEntry>> {
+        self.registry.presence(key).await
+    }
+
+    async fn set_notification_filter(
+        &self,
+        id: ConnectionId,
+        notification: &str,
+        filter: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let Some(filter) = filter else {
+            if let Some(mut notifications) = self.filters.get_mut(&id) {
+                notifications.remove(notification);
+            }
+            self.registry.remove_filter(notification, id).await?;
+            return Ok(());
+        };
+        if !self.connections.contains_key(&id) {
+            return Err(BidirectionalError::ConnectionNotFound(id));
+        }
+        self.registry.set_filter(notification, id, filter).await?;
+        self.filters
+            .entry(id)
+            .or_default()
+            .insert(notification.to_string());
+        debug!("Connection {} filtered {}", id, notification);
+        Ok(())
+    }
+
+    async fn notification_filters(
+        &self,
+        notification: &str,
+    ) -> Result<Vec<(ConnectionId, serde_json::Value)>> {
+        self.registry.filters(notification).await
+    }
+
+    async fn cleanup_stale_connections(&self) -> Result<usize> {
+        self.registry.reap_stale().await
     }
 
     async fn register_pending_request(
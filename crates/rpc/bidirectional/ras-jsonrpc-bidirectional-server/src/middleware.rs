@@ -0,0 +1,238 @@
+//! Hooks run around the messages of every connection
+//!
+//! A [`MessageMiddleware`] sees each request a client sends before it is
+//! dispatched, and each message sent to the client before it is encoded. Layers
+//! are registered in order on a [`MiddlewareChain`]: requests pass through them
+//! first to last, outgoing messages last to first, so the first layer is the
+//! outermost.
+
+use crate::ConnectionContext;
+use async_trait::async_trait;
+use ras_jsonrpc_bidirectional_types::BidirectionalMessage;
+use ras_jsonrpc_types::{JsonRpcError, JsonRpcRequest, error_codes};
+use std::fmt;
+use std::sync::Arc;
+
+/// A message about to be sent to a client: a response, notification, broadcast
+/// or server-to-client call
+pub type OutgoingMessage = BidirectionalMessage;
+
+/// What happens to a request once a middleware has seen it
+#[derive(Debug, Clone)]
+pub enum ControlFlow {
+    /// Pass the request on to the next layer, then the handler
+    Continue,
+    /// Refuse the request with the chain's rejection error
+    Reject,
+    /// Refuse the request with this error
+    RejectWith(JsonRpcError),
+}
+
+/// Observes, rewrites or refuses the messages of a connection
+#[async_trait]
+pub trait MessageMiddleware: Send + Sync + 'static {
+    /// Called with each request the client sends, before it is dispatched
+    ///
+    /// The request may be changed in place, such as to rename a method for
+    /// older clients. Refused requests are answered with an error and never
+    /// reach later layers or the handler.
+    async fn on_incoming(
+        &self,
+        ctx: &ConnectionContext,
+        request: &mut JsonRpcRequest,
+    ) -> ControlFlow {
+        let _ = (ctx, request);
+        ControlFlow::Continue
+    }
+
+    /// Called with each message before it is sent to the client
+    async fn on_outgoing(&self, ctx: &ConnectionContext, message: &mut OutgoingMessage) {
+        let _ = (ctx, message);
+    }
+}
+
+/// Middleware layers run in order around every message of a connection
+#[derive(Clone)]
+pub struct MiddlewareChain {
+    layers: Vec<Arc<dyn MessageMiddleware>>,
+    rejection: JsonRpcError,
+}
+
+impl MiddlewareChain {
+    /// An empty chain, refusing requests with an "invalid request" error
+    pub fn new() -> Self {
+        Self {
+            layers: Vec::new(),
+            rejection: JsonRpcError::new(
+                error_codes::INVALID_REQUEST,
+                "Request rejected".to_string(),
+                None,
+            ),
+        }
+    }
+
+    /// Add `middleware` after the layers already in the chain
+    pub fn with(mut self, middleware: impl MessageMiddleware) -> Self {
+        self.layers.push(Arc::new(middleware));
+        self
+    }
+
+    /// Answer requests refused with [`ControlFlow::Reject`] with `error`
+    pub fn rejection(mut self, error: JsonRpcError) -> Self {
+        self.rejection = error;
+        self
+    }
+
+    /// Whether the chain has no layers
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Run a request through every layer, returning the error to answer it with
+    /// if one of them refused it
+    pub(crate) async fn incoming(
+        &self,
+        ctx: &ConnectionContext,
+        request: &mut JsonRpcRequest,
+    ) -> Result<(), JsonRpcError> {
+        for layer in &self.layers {
+            match layer.on_incoming(ctx, request).await {
+                ControlFlow::Continue => {}
+                ControlFlow::Reject => return Err(self.rejection.clone()),
+                ControlFlow::RejectWith(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a message about to be sent through every layer, last to first
+    pub(crate) async fn outgoing(&self, ctx: &ConnectionContext, message: &mut OutgoingMessage) {
+        for layer in self.layers.iter().rev() {
+            layer.on_outgoing(ctx, message).await;
+        }
+    }
+}
+
+impl Default for MiddlewareChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for MiddlewareChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MiddlewareChain")
+            .field("layers", &self.layers.len())
+            .field("rejection", &self.rejection)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::ChannelMessageSender;
+    use crate::queue::{OverflowPolicy, outbound_queue};
+    use ras_jsonrpc_bidirectional_types::{ConnectionId, ServerNotification};
+    use std::sync::Mutex;
+
+    /// Records the order it is called in, refusing requests to `refuse`
+    struct Layer {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<String>>>,
+        refuse: Option<(&'static str, ControlFlow)>,
+    }
+
+    #[async_trait]
+    impl MessageMiddleware for Layer {
+        async fn on_incoming(
+            &self,
+            _ctx: &ConnectionContext,
+            request: &mut JsonRpcRequest,
+        ) -> ControlFlow {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} in {}", self.name, request.method));
+            match &self.refuse {
+                Some((method, flow)) if request.method == *method => flow.clone(),
+                _ => ControlFlow::Continue,
+            }
+        }
+
+        async fn on_outgoing(&self, _ctx: &ConnectionContext, _message: &mut OutgoingMessage) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} out", self.name));
+        }
+    }
+
+    fn context() -> ConnectionContext {
+        let id = ConnectionId::new();
+        let (tx, _rx) = outbound_queue(1, OverflowPolicy::default());
+        ConnectionContext::new(id, ChannelMessageSender::new(id, tx))
+    }
+
+    fn request(method: &str) -> JsonRpcRequest {
+        JsonRpcRequest::new(method.to_string(), None, Some(serde_json::json!(1)))
+    }
+
+    #[tokio::test]
+    async fn layers_wrap_each_other_in_registration_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let layer = |name| Layer {
+            name,
+            calls: calls.clone(),
+            refuse: None,
+        };
+        let chain = MiddlewareChain::new()
+            .with(layer("outer"))
+            .with(layer("inner"));
+        let ctx = context();
+
+        chain.incoming(&ctx, &mut request("add")).await.unwrap();
+        let mut message = BidirectionalMessage::ServerNotification(ServerNotification {
+            method: "tick".into(),
+            params: serde_json::Value::Null,
+            metadata: None,
+        });
+        chain.outgoing(&ctx, &mut message).await;
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["outer in add", "inner in add", "inner out", "outer out"]
+        );
+    }
+
+    #[tokio::test]
+    async fn refused_requests_skip_later_layers() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let custom = JsonRpcError::new(-32050, "Upgrade your client".to_string(), None);
+        let chain = MiddlewareChain::new()
+            .with(Layer {
+                name: "first",
+                calls: calls.clone(),
+                refuse: Some(("old", ControlFlow::Reject)),
+            })
+            .with(Layer {
+                name: "second",
+                calls: calls.clone(),
+                refuse: Some(("legacy", ControlFlow::RejectWith(custom.clone()))),
+            })
+            .rejection(JsonRpcError::new(-32051, "Refused".to_string(), None));
+        let ctx = context();
+
+        let refused = chain.incoming(&ctx, &mut request("old")).await.unwrap_err();
+        assert_eq!(refused.code, -32051);
+        let refused = chain
+            .incoming(&ctx, &mut request("legacy"))
+            .await
+            .unwrap_err();
+        assert_eq!(refused.code, custom.code);
+        assert_eq!(refused.message, custom.message);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["first in old", "first in legacy", "second in legacy"]
+        );
+    }
+}
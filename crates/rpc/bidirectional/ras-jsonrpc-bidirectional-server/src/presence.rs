@@ -0,0 +1,112 @@
+//! Answering the built-in presence requests
+
+use crate::ConnectionContext;
+use crate::handler::MessageHandler;
+use ras_jsonrpc_bidirectional_types::{
+    ConnectionManager, PRESENCE_JOIN_METHOD, PRESENCE_LEAVE_METHOD, PRESENCE_LIST_METHOD,
+    PresenceJoin, PresenceKey,
+};
+use ras_jsonrpc_types::{JsonRpcError, JsonRpcRequest, JsonRpcResponse, error_codes};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+/// Whether `method` is one of the presence requests answered here
+pub(crate) fn is_presence_method(method: &str) -> bool {
+    matches!(
+        method,
+        PRESENCE_JOIN_METHOD | PRESENCE_LEAVE_METHOD | PRESENCE_LIST_METHOD
+    )
+}
+
+/// Answer a `presence.join`, `presence.leave` or `presence.list` request
+///
+/// Joining and listing a key are subject to the handler's
+/// [`authorize_presence`](MessageHandler::authorize_presence); leaving never is.
+pub(crate) async fn handle<H: MessageHandler + ?Sized>(
+    handler: &H,
+    manager: Option<&dyn ConnectionManager>,
+    context: Arc<ConnectionContext>,
+    request: JsonRpcRequest,
+) -> Option<JsonRpcResponse> {
+    let params = request.params.unwrap_or_default();
+    let result = match manager {
+        Some(manager) => match request.method.as_str() {
+            PRESENCE_JOIN_METHOD => join(handler, manager, context, params).await,
+            PRESENCE_LEAVE_METHOD => leave(manager, &context, params).await,
+            _ => list(handler, manager, context, params).await,
+        },
+        None => Err(JsonRpcError::internal_error(
+            "Presence needs a connection manager".to_string(),
+        )),
+    };
+
+    // Notifications get no reply
+    let id = request.id?;
+    Some(match result {
+        Ok(value) => JsonRpcResponse::success(value, Some(id)),
+        Err(error) => JsonRpcResponse::error(error, Some(id)),
+    })
+}
+
+async fn join<H: MessageHandler + ?Sized>(
+    handler: &H,
+    manager: &dyn ConnectionManager,
+    context: Arc<ConnectionContext>,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let PresenceJoin { key, metadata } = parse(params)?;
+    authorize(handler, &key, context.clone()).await?;
+    let entry = manager
+        .join_presence(context.id, &key, metadata)
+        .await
+        .map_err(|e| JsonRpcError::internal_error(e.to_string()))?;
+    serde_json::to_value(entry).map_err(|e| JsonRpcError::internal_error(e.to_string()))
+}
+
+async fn leave(
+    manager: &dyn ConnectionManager,
+    context: &ConnectionContext,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let PresenceKey { key } = parse(params)?;
+    let left = manager
+        .leave_presence(context.id, &key)
+        .await
+        .map_err(|e| JsonRpcError::internal_error(e.to_string()))?;
+    Ok(serde_json::Value::Bool(left))
+}
+
+async fn list<H: MessageHandler + ?Sized>(
+    handler: &H,
+    manager: &dyn ConnectionManager,
+    context: Arc<ConnectionContext>,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let PresenceKey { key } = parse(params)?;
+    authorize(handler, &key, context).await?;
+    let entries = manager
+        .list_presence(&key)
+        .await
+        .map_err(|e| JsonRpcError::internal_error(e.to_string()))?;
+    serde_json::to_value(entries).map_err(|e| JsonRpcError::internal_error(e.to_string()))
+}
+
+fn parse<T: DeserializeOwned>(params: serde_json::Value) -> Result<T, JsonRpcError> {
+    serde_json::from_value(params).map_err(|e| JsonRpcError::invalid_params(e.to_string()))
+}
+
+async fn authorize<H: MessageHandler + ?Sized>(
+    handler: &H,
+    key: &str,
+    context: Arc<ConnectionContext>,
+) -> Result<(), JsonRpcError> {
+    if handler.authorize_presence(key, context).await {
+        Ok(())
+    } else {
+        Err(JsonRpcError::new(
+            error_codes::INSUFFICIENT_PERMISSIONS,
+            format!("Not allowed to see the presence of {}", key),
+            None,
+        ))
+    }
+}
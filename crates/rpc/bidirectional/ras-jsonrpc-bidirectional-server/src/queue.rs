@@ -0,0 +1,352 @@
+//! Bounded outgoing message queues with overflow policies
+
+use ras_jsonrpc_bidirectional_types::BidirectionalMessage;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::Notify;
+
+/// What to do with a message when a connection's outgoing queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message to make room
+    DropOldest,
+    /// Discard the message being sent
+    DropNewest,
+    /// Close the connection; a client that cannot keep up is not sent a
+    /// partial stream
+    #[default]
+    Disconnect,
+}
+
+/// Outcome of pushing a message onto an outgoing queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Enqueued {
+    /// The message was queued
+    Queued,
+    /// The message was queued after discarding the oldest one
+    ReplacedOldest,
+    /// The queue was full and the message was discarded
+    Dropped,
+    /// The queue was full and the connection is being closed
+    Disconnected,
+    /// The connection is already closed
+    Closed,
+}
+
+impl Enqueued {
+    /// Whether the message will be delivered
+    pub fn is_queued(self) -> bool {
+        matches!(self, Self::Queued | Self::ReplacedOldest)
+    }
+}
+
+/// Snapshot of a connection's outgoing queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Messages waiting to be written to the socket
+    pub queued: usize,
+    /// Maximum number of queued messages
+    pub capacity: usize,
+    /// Messages discarded because the queue was full
+    pub dropped: u64,
+}
+
+/// Create a bounded outgoing queue
+///
+/// A capacity of zero is treated as one.
+pub fn outbound_queue(
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (OutboundSender, OutboundReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            senders: 1,
+            receiver_alive: true,
+            overflowed: false,
+        }),
+        ready: Notify::new(),
+        capacity: capacity.max(1),
+        policy,
+        dropped: AtomicU64::new(0),
+    });
+    (
+        OutboundSender {
+            shared: shared.clone(),
+        },
+        OutboundReceiver { shared },
+    )
+}
+
+struct Shared {
+    state: Mutex<State>,
+    ready: Notify,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: AtomicU64,
+}
+
+struct State {
+    queue: VecDeque<BidirectionalMessage>,
+    senders: usize,
+    receiver_alive: bool,
+    overflowed: bool,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn stats(&self) -> QueueStats {
+        QueueStats {
+            queued: self.state().queue.len(),
+            capacity: self.capacity,
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Sending half of an outgoing queue; never waits for the socket
+pub struct OutboundSender {
+    shared: Arc<Shared>,
+}
+
+impl OutboundSender {
+    /// Queue a message, applying the overflow policy when the queue is full
+    pub fn push(&self, message: BidirectionalMessage) -> Enqueued {
+        let mut state = self.shared.state();
+        if !state.receiver_alive || state.overflowed {
+            return Enqueued::Closed;
+        }
+
+        let outcome = if state.queue.len() < self.shared.capacity {
+            state.queue.push_back(message);
+            Enqueued::Queued
+        } else {
+            match self.shared.policy {
+                OverflowPolicy::DropOldest => {
+                    state.queue.pop_front();
+                    state.queue.push_back(message);
+                    self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    Enqueued::ReplacedOldest
+                }
+                OverflowPolicy::DropNewest => {
+                    self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Enqueued::Dropped;
+                }
+                OverflowPolicy::Disconnect => {
+                    state.overflowed = true;
+                    self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    Enqueued::Disconnected
+                }
+            }
+        };
+        drop(state);
+        self.shared.ready.notify_one();
+        outcome
+    }
+
+    /// Whether the receiving side has gone away or given up on the connection
+    pub fn is_closed(&self) -> bool {
+        let state = self.shared.state();
+        !state.receiver_alive || state.overflowed
+    }
+
+    /// Current queue depth, capacity and drop count
+    pub fn stats(&self) -> QueueStats {
+        self.shared.stats()
+    }
+
+    /// The policy applied when the queue is full
+    pub fn policy(&self) -> OverflowPolicy {
+        self.shared.policy
+    }
+}
+
+impl Clone for OutboundSender {
+    fn clone(&self) -> Self {
+        self.shared.state().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for OutboundSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.ready.notify_one();
+        }
+    }
+}
+
+impl std::fmt::Debug for OutboundSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutboundSender")
+            .field("policy", &self.shared.policy)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Receiving half of an outgoing queue, drained by the connection's writer
+pub struct OutboundReceiver {
+    shared: Arc<Shared>,
+}
+
+impl OutboundReceiver {
+    /// Wait for the next message
+    ///
+    /// Returns `None` once every sender is gone and the queue is empty, or as
+    /// soon as the queue overflowed under [`OverflowPolicy::Disconnect`].
+    pub async fn recv(&mut self) -> Option<BidirectionalMessage> {
+        loop {
+            {
+                let mut state = self.shared.state();
+                if state.overflowed {
+                    return None;
+                }
+                if let Some(message) = state.queue.pop_front() {
+                    return Some(message);
+                }
+                if state.senders == 0 {
+                    return None;
+                }
+            }
+            self.shared.ready.notified().await;
+        }
+    }
+
+    /// Take the next message if one is queued
+    pub fn try_recv(&mut self) -> Option<BidirectionalMessage> {
+        let mut state = self.shared.state();
+        if state.overflowed {
+            return None;
+        }
+        state.queue.pop_front()
+    }
+
+    /// Whether the queue overflowed and the connection should be closed
+    pub fn overflowed(&self) -> bool {
+        self.shared.state().overflowed
+    }
+
+    /// Current queue depth, capacity and drop count
+    pub fn stats(&self) -> QueueStats {
+        self.shared.stats()
+    }
+}
+
+impl Drop for OutboundReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.receiver_alive = false;
+        state.queue.clear();
+    }
+}
+
+impl std::fmt::Debug for OutboundReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutboundReceiver")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_jsonrpc_bidirectional_types::ServerNotification;
+
+    fn note(n: u64) -> BidirectionalMessage {
+        BidirectionalMessage::ServerNotification(ServerNotification {
+            method: "tick".to_string(),
+            params: serde_json::json!(n),
+            metadata: None,
+        })
+    }
+
+    fn number(message: BidirectionalMessage) -> u64 {
+        match message {
+            BidirectionalMessage::ServerNotification(n) => n.params.as_u64().unwrap(),
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    fn drain(rx: &mut OutboundReceiver) -> Vec<u64> {
+        std::iter::from_fn(|| rx.try_recv()).map(number).collect()
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest_messages() {
+        let (tx, mut rx) = outbound_queue(2, OverflowPolicy::DropOldest);
+        assert_eq!(tx.push(note(1)), Enqueued::Queued);
+        assert_eq!(tx.push(note(2)), Enqueued::Queued);
+        assert_eq!(tx.push(note(3)), Enqueued::ReplacedOldest);
+        assert_eq!(
+            tx.stats(),
+            QueueStats {
+                queued: 2,
+                capacity: 2,
+                dropped: 1
+            }
+        );
+        assert_eq!(drain(&mut rx), [2, 3]);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_earliest_messages() {
+        let (tx, mut rx) = outbound_queue(2, OverflowPolicy::DropNewest);
+        tx.push(note(1));
+        tx.push(note(2));
+        assert_eq!(tx.push(note(3)), Enqueued::Dropped);
+        assert!(!tx.push(note(4)).is_queued());
+        assert_eq!(tx.stats().dropped, 2);
+        assert_eq!(drain(&mut rx), [1, 2]);
+        assert_eq!(tx.push(note(5)), Enqueued::Queued);
+    }
+
+    #[tokio::test]
+    async fn disconnect_ends_the_receiver() {
+        let (tx, mut rx) = outbound_queue(1, OverflowPolicy::Disconnect);
+        tx.push(note(1));
+        assert_eq!(tx.push(note(2)), Enqueued::Disconnected);
+        assert!(rx.overflowed());
+        assert!(tx.is_closed());
+        assert_eq!(tx.push(note(3)), Enqueued::Closed);
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn recv_waits_for_messages_and_ends_with_the_last_sender() {
+        let (tx, mut rx) = outbound_queue(4, OverflowPolicy::default());
+        let second = tx.clone();
+        let waiter = tokio::spawn(async move {
+            let mut received = Vec::new();
+            while let Some(message) = rx.recv().await {
+                received.push(number(message));
+            }
+            received
+        });
+        tokio::task::yield_now().await;
+        tx.push(note(1));
+        drop(tx);
+        second.push(note(2));
+        drop(second);
+        assert_eq!(waiter.await.unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn pushing_after_the_receiver_is_dropped_reports_closed() {
+        let (tx, rx) = outbound_queue(0, OverflowPolicy::DropNewest);
+        assert_eq!(tx.stats().capacity, 1);
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.push(note(1)), Enqueued::Closed);
+    }
+}
@@ -0,0 +1,216 @@
+//! Per-connection rate limits on client-to-server requests
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Rate-limited requests a connection may send before it is closed, by default
+pub const DEFAULT_MAX_RATE_LIMIT_VIOLATIONS: u32 = 10;
+
+/// A number of requests allowed per period
+///
+/// Requests are metered by a token bucket: a connection may burst up to
+/// `requests` at once, and regains capacity evenly over `per`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests allowed per period, and the largest burst
+    pub requests: u32,
+    /// Length of the period
+    pub per: Duration,
+}
+
+impl RateLimit {
+    /// Allow `requests` requests every `per`
+    pub fn new(requests: u32, per: Duration) -> Self {
+        Self { requests, per }
+    }
+
+    /// Allow `requests` requests every second
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(1))
+    }
+}
+
+/// Rate limits applied to the requests of each connection
+///
+/// The connection-wide limit counts every request; a method with its own limit
+/// is metered by that limit instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    /// Limit on all requests of a connection; unlimited when `None`
+    pub per_connection: Option<RateLimit>,
+    /// Limits on individual methods, replacing the connection-wide one
+    pub methods: HashMap<String, RateLimit>,
+    /// Rate-limited requests tolerated before the connection is closed; `0` never closes it
+    pub max_violations: u32,
+}
+
+impl RateLimitConfig {
+    /// Limit every connection to `limit` across all its requests
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            per_connection: Some(limit),
+            ..Self::default()
+        }
+    }
+
+    /// Meter `method` by `limit` rather than the connection-wide limit
+    pub fn method(mut self, method: impl Into<String>, limit: RateLimit) -> Self {
+        self.methods.insert(method.into(), limit);
+        self
+    }
+
+    /// Close connections after `max` rate-limited requests (`0` to never close them)
+    pub fn max_violations(mut self, max: u32) -> Self {
+        self.max_violations = max;
+        self
+    }
+}
+
+impl Default for RateLimitConfig {
+    /// No limits, closing connections after 10 rate-limited requests once limits are added
+    fn default() -> Self {
+        Self {
+            per_connection: None,
+            methods: HashMap::new(),
+            max_violations: DEFAULT_MAX_RATE_LIMIT_VIOLATIONS,
+        }
+    }
+}
+
+/// Token buckets of one connection
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    connection: Option<TokenBucket>,
+    methods: HashMap<String, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Rate-limited requests tolerated before the connection is closed
+    pub fn max_violations(&self) -> u32 {
+        self.config.max_violations
+    }
+
+    /// Take a token for a request to `method`, or tell how long until one is available
+    pub fn check(&self, method: &str) -> Result<(), Duration> {
+        self.check_at(method, Instant::now())
+    }
+
+    fn check_at(&self, method: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(limit) = self.config.methods.get(method) {
+            return buckets
+                .methods
+                .entry(method.to_string())
+                .or_insert_with(|| TokenBucket::full(*limit, now))
+                .take(now);
+        }
+        match self.config.per_connection {
+            Some(limit) => buckets
+                .connection
+                .get_or_insert_with(|| TokenBucket::full(limit, now))
+                .take(now),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    /// Tokens regained per second
+    refill_rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: RateLimit, now: Instant) -> Self {
+        let capacity = f64::from(limit.requests);
+        Self {
+            capacity,
+            refill_rate: capacity / limit.per.as_secs_f64().max(f64::EPSILON),
+            tokens: capacity,
+            refilled_at: now,
+        }
+    }
+
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        if self.refill_rate <= 0.0 {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.refill_rate,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_up_to_the_limit_then_refills_evenly() {
+        let limiter = RateLimiter::new(RateLimitConfig::new(RateLimit::per_second(2)));
+        let start = Instant::now();
+        assert!(limiter.check_at("add", start).is_ok());
+        assert!(limiter.check_at("sub", start).is_ok());
+        let retry_after = limiter.check_at("add", start).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check_at("add", later).is_ok());
+        assert!(limiter.check_at("add", later).is_err());
+
+        // Capacity never exceeds the burst, however long the connection idles
+        let idle = later + Duration::from_secs(60);
+        assert!(limiter.check_at("add", idle).is_ok());
+        assert!(limiter.check_at("add", idle).is_ok());
+        assert!(limiter.check_at("add", idle).is_err());
+    }
+
+    #[test]
+    fn methods_with_their_own_limit_are_metered_separately() {
+        let config = RateLimitConfig::new(RateLimit::per_second(1))
+            .method("chat", RateLimit::new(3, Duration::from_secs(10)));
+        let limiter = RateLimiter::new(config);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at("chat", now).is_ok());
+        }
+        assert!(limiter.check_at("chat", now).is_err());
+        // The connection-wide bucket was untouched by the chat messages
+        assert!(limiter.check_at("status", now).is_ok());
+        assert!(limiter.check_at("status", now).is_err());
+    }
+
+    #[test]
+    fn methods_without_a_limit_are_unlimited() {
+        let config = RateLimitConfig::default().method("chat", RateLimit::per_second(1));
+        let limiter = RateLimiter::new(config);
+        for _ in 0..100 {
+            assert!(limiter.check("status").is_ok());
+        }
+    }
+}
@@ -0,0 +1,554 @@
+//! Sharing connections between nodes through Redis pub/sub
+//!
+//! Each node keeps a heartbeat key alive in Redis and records which connections it
+//! holds. Messages for another node's connection are published on that node's
+//! channel; topic publishes and broadcasts go out on shared channels that every node
+//! listens to. When a node's heartbeat lapses, the first surviving node to notice
+//! drops its records.
+//!
+//! Keys and channels, under a configurable prefix (`ras` by default):
+//!
+//! | Key / channel                 | Holds                                    |
+//! |-------------------------------|------------------------------------------|
+//! | `{prefix}:nodes`              | set of node IDs                          |
+//! | `{prefix}:node:{node}:alive`  | heartbeat, expiring after the TTL        |
+//! | `{prefix}:node:{node}:conns`  | set of the node's connection IDs         |
+//! | `{prefix}:conn:{id}`          | ID of the node holding the connection    |
+//! | `{prefix}:node:{node}`        | channel for the node's connections       |
+//! | `{prefix}:topic:{topic}`      | channel for a topic's subscribers        |
+//! | `{prefix}:broadcast`          | channel for permission-wide broadcasts   |
+//! | `{prefix}:seq:{topic}`        | last sequence number of a topic          |
+//! | `{prefix}:presence:{key}`     | hash of presence entries by connection   |
+//! | `{prefix}:conn:{id}:presence` | set of keys the connection is present in |
+//! | `{prefix}:filter:{method}`    | hash of notification filters by connection |
+//! | `{prefix}:conn:{id}:filters`  | set of notifications the connection filters |
+
+use crate::registry::{Audience, ConnectionRegistry, Delivery, NodeId};
+use async_trait::async_trait;
+use futures::StreamExt;
+use ras_jsonrpc_bidirectional_types::{
+    BidirectionalError, BidirectionalMessage, ConnectionId, PresenceEntry, Result,
+};
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// A [`ConnectionRegistry`] backed by Redis
+pub struct RedisRegistry {
+    client: redis::Client,
+    connection: MultiplexedConnection,
+    keys: Keys,
+    heartbeat_ttl: Duration,
+    /// Topics with subscribers on this node
+    topics: Arc<Mutex<HashSet<String>>>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl RedisRegistry {
+    /// Connect to Redis at `url`, as a node with a random ID
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url).map_err(BidirectionalError::internal)?;
+        let connection = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(BidirectionalError::internal)?;
+        Ok(Self {
+            client,
+            connection,
+            keys: Keys {
+                prefix: "ras".to_string(),
+                node: NodeId::random(),
+            },
+            heartbeat_ttl: Duration::from_secs(15),
+            topics: Arc::default(),
+            tasks: Mutex::default(),
+        })
+    }
+
+    /// Set this node's ID, which must be unique among the nodes
+    pub fn with_node_id(mut self, id: impl Into<NodeId>) -> Self {
+        self.keys.node = id.into();
+        self
+    }
+
+    /// Set the prefix of every key and channel, to share a Redis between services
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.keys.prefix = prefix.into();
+        self
+    }
+
+    /// Set how long this node counts as alive after its last heartbeat
+    ///
+    /// Heartbeats are sent every third of the TTL.
+    pub fn with_heartbeat_ttl(mut self, ttl: Duration) -> Self {
+        self.heartbeat_ttl = ttl.max(Duration::from_secs(1));
+        self
+    }
+
+    fn track(&self, task: JoinHandle<()>) {
+        self.tasks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(task);
+    }
+
+    fn topics(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.topics.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn send(&self, channel: String, delivery: &Delivery) -> Result<usize> {
+        let payload = serde_json::to_string(delivery)?;
+        self.connection
+            .clone()
+            .publish(channel, payload)
+            .await
+            .map_err(BidirectionalError::internal)
+    }
+
+    fn delivery(&self, audience: Audience, message: BidirectionalMessage) -> Delivery {
+        Delivery {
+            origin: self.keys.node.clone(),
+            audience,
+            message,
+        }
+    }
+}
+
+impl fmt::Debug for RedisRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisRegistry")
+            .field("node_id", &self.keys.node)
+            .field("prefix", &self.keys.prefix)
+            .field("heartbeat_ttl", &self.heartbeat_ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for RedisRegistry {
+    fn drop(&mut self) {
+        for task in self
+            .tasks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain(..)
+        {
+            task.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl ConnectionRegistry for RedisRegistry {
+    fn node_id(&self) -> &NodeId {
+        &self.keys.node
+    }
+
+    async fn attach(&self, inbox: mpsc::UnboundedSender<Delivery>) -> Result<()> {
+        let mut pubsub = self
+            .client
+            .get_async_pubsub()
+            .await
+            .map_err(BidirectionalError::internal)?;
+        pubsub
+            .subscribe(self.keys.inbox(&self.keys.node))
+            .await
+            .map_err(BidirectionalError::internal)?;
+        pubsub
+            .subscribe(self.keys.broadcast())
+            .await
+            .map_err(BidirectionalError::internal)?;
+        pubsub
+            .psubscribe(self.keys.topic("*"))
+            .await
+            .map_err(BidirectionalError::internal)?;
+
+        let mut connection = self.connection.clone();
+        heartbeat(&mut connection, &self.keys, self.heartbeat_ttl).await?;
+        info!("Node {} joined the connection registry", self.keys.node);
+
+        let node = self.keys.node.clone();
+        let topics = self.topics.clone();
+        self.track(tokio::spawn(async move {
+            let mut messages = pubsub.into_on_message();
+            while let Some(message) = messages.next().await {
+                let delivery = match message
+                    .get_payload::<String>()
+                    .map_err(BidirectionalError::internal)
+                    .and_then(|payload| {
+                        serde_json::from_str::<Delivery>(&payload).map_err(Into::into)
+                    }) {
+                    Ok(delivery) => delivery,
+                    Err(e) => {
+                        warn!("Ignoring malformed registry message: {}", e);
+                        continue;
+                    }
+                };
+                if delivery.origin == node {
+                    continue;
+                }
+                if let Audience::Topic { topic } = &delivery.audience
+                    && !topics
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .contains(topic)
+                {
+                    continue;
+                }
+                if inbox.send(delivery).is_err() {
+                    break;
+                }
+            }
+            debug!("Registry subscription of node {} ended", node);
+        }));
+
+        let keys = self.keys.clone();
+        let ttl = self.heartbeat_ttl;
+        self.track(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(ttl / 3);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                if let Err(e) = heartbeat(&mut connection, &keys, ttl).await {
+                    warn!("Node {} failed to send its heartbeat: {}", keys.node, e);
+                    continue;
+                }
+                match reap(&mut connection, &keys).await {
+                    Ok(0) => {}
+                    Ok(reaped) => info!("Dropped {} connections of dead nodes", reaped),
+                    Err(e) => warn!("Failed to reap dead nodes: {}", e),
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    async fn register(&self, id: ConnectionId) -> Result<()> {
+        redis::pipe()
+            .set(self.keys.owner(id), self.keys.node.as_str())
+            .ignore()
+            .sadd(self.keys.connections(&self.keys.node), id.to_string())
+            .ignore()
+            .query_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(BidirectionalError::internal)
+    }
+
+    async fn deregister(&self, id: ConnectionId) -> Result<()> {
+        let mut connection = self.connection.clone();
+        let owner: Option<String> = connection
+            .get(self.keys.owner(id))
+            .await
+            .map_err(BidirectionalError::internal)?;
+        // The connection may have been registered again by another node
+        if owner.as_deref() == Some(self.keys.node.as_str()) {
+            let _: () = connection
+                .del(self.keys.owner(id))
+                .await
+                .map_err(BidirectionalError::internal)?;
+        }
+        let _: () = connection
+            .srem(self.keys.connections(&self.keys.node), id.to_string())
+            .await
+            .map_err(BidirectionalError::internal)?;
+        Ok(())
+    }
+
+    async fn send_to(&self, id: ConnectionId, message: BidirectionalMessage) -> Result<bool> {
+        let mut connection = self.connection.clone();
+        let owner: Option<String> = connection
+            .get(self.keys.owner(id))
+            .await
+            .map_err(BidirectionalError::internal)?;
+        let Some(owner) = owner.map(NodeId::from) else {
+            return Ok(false);
+        };
+        if owner == self.keys.node {
+            return Ok(false);
+        }
+        let alive: bool = connection
+            .exists(self.keys.alive(&owner))
+            .await
+            .map_err(BidirectionalError::internal)?;
+        if !alive {
+            return Ok(false);
+        }
+        let delivery = self.delivery(Audience::Connection { id }, message);
+        Ok(self.send(self.keys.inbox(&owner), &delivery).await? > 0)
+    }
+
+    async fn broadcast(&self, audience: Audience, message: BidirectionalMessage) -> Result<()> {
+        let delivery = self.delivery(audience, message);
+        self.send(self.keys.broadcast(), &delivery).await?;
+        Ok(())
+    }
+
+    async fn subscribe(&self, topic: &str) -> Result<()> {
+        self.topics().insert(topic.to_string());
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, topic: &str) -> Result<()> {
+        self.topics().remove(topic);
+        Ok(())
+    }
+
+    async fn publish(&self, topic: &str, message: BidirectionalMessage) -> Result<()> {
+        let audience = Audience::Topic {
+            topic: topic.to_string(),
+        };
+        let delivery = self.delivery(audience, message);
+        self.send(self.keys.topic(topic), &delivery).await?;
+        Ok(())
+    }
+
+    async fn next_sequence(&self, topic: &str) -> Result<u64> {
+        self.connection
+            .clone()
+            .incr(self.keys.sequence(topic), 1)
+            .await
+            .map_err(BidirectionalError::internal)
+    }
+
+    async fn add_presence(&self, key: &str, entry: PresenceEntry) -> Result<()> {
+        let id = entry.connection_id.to_string();
+        let payload = serde_json::to_string(&entry)?;
+        redis::pipe()
+            .hset(self.keys.presence(key), &id, payload)
+            .ignore()
+            .sadd(self.keys.presence_keys(&id), key)
+            .ignore()
+            .query_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(BidirectionalError::internal)
+    }
+
+    async fn remove_presence(&self, key: &str, id: ConnectionId) -> Result<Option<PresenceEntry>> {
+        let id = id.to_string();
+        let (payload, ..): (Option<String>, (), ()) = redis::pipe()
+            .hget(self.keys.presence(key), &id)
+            .hdel(self.keys.presence(key), &id)
+            .srem(self.keys.presence_keys(&id), key)
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(BidirectionalError::internal)?;
+        payload
+            .map(|payload| serde_json::from_str(&payload).map_err(Into::into))
+            .transpose()
+    }
+
+    async fn presence(&self, key: &str) -> Result<Vec<PresenceEntry>> {
+        let payloads: Vec<String> = self
+            .connection
+            .clone()
+            .hvals(self.keys.presence(key))
+            .await
+            .map_err(BidirectionalError::internal)?;
+        let mut entries = payloads
+            .iter()
+            .map(|payload| serde_json::from_str(payload))
+            .collect::<std::result::Result<Vec<PresenceEntry>, _>>()?;
+        entries.sort_by_key(|entry| entry.joined_at);
+        Ok(entries)
+    }
+
+    async fn set_filter(
+        &self,
+        notification: &str,
+        id: ConnectionId,
+        filter: serde_json::Value,
+    ) -> Result<()> {
+        let id = id.to_string();
+        let payload = serde_json::to_string(&filter)?;
+        redis::pipe()
+            .hset(self.keys.filter(notification), &id, payload)
+            .ignore()
+            .sadd(self.keys.filtered(&id), notification)
+            .ignore()
+            .query_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(BidirectionalError::internal)
+    }
+
+    async fn remove_filter(&self, notification: &str, id: ConnectionId) -> Result<bool> {
+        let id = id.to_string();
+        let (removed, _): (usize, ()) = redis::pipe()
+            .hdel(self.keys.filter(notification), &id)
+            .srem(self.keys.filtered(&id), notification)
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(BidirectionalError::internal)?;
+        Ok(removed > 0)
+    }
+
+    async fn filters(&self, notification: &str) -> Result<Vec<(ConnectionId, serde_json::Value)>> {
+        let payloads: Vec<(String, String)> = self
+            .connection
+            .clone()
+            .hgetall(self.keys.filter(notification))
+            .await
+            .map_err(BidirectionalError::internal)?;
+        let mut filters = Vec::with_capacity(payloads.len());
+        for (id, payload) in payloads {
+            let Ok(id) = id.parse::<uuid::Uuid>() else {
+                warn!("Ignoring filter of malformed connection ID {}", id);
+                continue;
+            };
+            filters.push((ConnectionId::from_uuid(id), serde_json::from_str(&payload)?));
+        }
+        Ok(filters)
+    }
+
+    async fn reap_stale(&self) -> Result<usize> {
+        reap(&mut self.connection.clone(), &self.keys).await
+    }
+}
+
+/// Key and channel names of one node
+#[derive(Debug, Clone)]
+struct Keys {
+    prefix: String,
+    node: NodeId,
+}
+
+impl Keys {
+    fn nodes(&self) -> String {
+        format!("{}:nodes", self.prefix)
+    }
+
+    fn alive(&self, node: &NodeId) -> String {
+        format!("{}:node:{}:alive", self.prefix, node)
+    }
+
+    fn connections(&self, node: &NodeId) -> String {
+        format!("{}:node:{}:conns", self.prefix, node)
+    }
+
+    fn owner(&self, id: impl fmt::Display) -> String {
+        format!("{}:conn:{}", self.prefix, id)
+    }
+
+    fn inbox(&self, node: &NodeId) -> String {
+        format!("{}:node:{}", self.prefix, node)
+    }
+
+    fn topic(&self, topic: &str) -> String {
+        format!("{}:topic:{}", self.prefix, topic)
+    }
+
+    fn broadcast(&self) -> String {
+        format!("{}:broadcast", self.prefix)
+    }
+
+    fn sequence(&self, topic: &str) -> String {
+        format!("{}:seq:{}", self.prefix, topic)
+    }
+
+    fn presence(&self, key: &str) -> String {
+        format!("{}:presence:{}", self.prefix, key)
+    }
+
+    fn presence_keys(&self, id: impl fmt::Display) -> String {
+        format!("{}:conn:{}:presence", self.prefix, id)
+    }
+
+    fn filter(&self, notification: &str) -> String {
+        format!("{}:filter:{}", self.prefix, notification)
+    }
+
+    fn filtered(&self, id: impl fmt::Display) -> String {
+        format!("{}:conn:{}:filters", self.prefix, id)
+    }
+}
+
+/// Mark the node alive for another `ttl`
+async fn heartbeat(
+    connection: &mut MultiplexedConnection,
+    keys: &Keys,
+    ttl: Duration,
+) -> Result<()> {
+    redis::pipe()
+        .set_ex(keys.alive(&keys.node), 1, ttl.as_secs().max(1))
+        .ignore()
+        .sadd(keys.nodes(), keys.node.as_str())
+        .ignore()
+        .query_async::<()>(connection)
+        .await
+        .map_err(BidirectionalError::internal)
+}
+
+/// Drop the records of every node whose heartbeat lapsed
+async fn reap(connection: &mut MultiplexedConnection, keys: &Keys) -> Result<usize> {
+    let nodes: Vec<String> = connection
+        .smembers(keys.nodes())
+        .await
+        .map_err(BidirectionalError::internal)?;
+    let mut reaped = 0;
+    for node in nodes.into_iter().map(NodeId::from) {
+        if node == keys.node {
+            continue;
+        }
+        let alive: bool = connection
+            .exists(keys.alive(&node))
+            .await
+            .map_err(BidirectionalError::internal)?;
+        if alive {
+            continue;
+        }
+
+        let ids: Vec<String> = connection
+            .smembers(keys.connections(&node))
+            .await
+            .map_err(BidirectionalError::internal)?;
+        for id in ids {
+            let owner: Option<String> = connection
+                .get(keys.owner(&id))
+                .await
+                .map_err(BidirectionalError::internal)?;
+            if owner.as_deref() == Some(node.as_str()) {
+                let _: () = connection
+                    .del(keys.owner(&id))
+                    .await
+                    .map_err(BidirectionalError::internal)?;
+                reaped += 1;
+            }
+            // The connection is no longer present anywhere, nor filtering
+            let present_in: Vec<String> = connection
+                .smembers(keys.presence_keys(&id))
+                .await
+                .map_err(BidirectionalError::internal)?;
+            let filtered: Vec<String> = connection
+                .smembers(keys.filtered(&id))
+                .await
+                .map_err(BidirectionalError::internal)?;
+            let mut cleanup = redis::pipe();
+            for key in present_in {
+                cleanup.hdel(keys.presence(&key), &id).ignore();
+            }
+            for notification in filtered {
+                cleanup.hdel(keys.filter(&notification), &id).ignore();
+            }
+            cleanup.del(keys.presence_keys(&id)).ignore();
+            cleanup.del(keys.filtered(&id)).ignore();
+            cleanup
+                .query_async::<()>(&mut *connection)
+                .await
+                .map_err(BidirectionalError::internal)?;
+        }
+        redis::pipe()
+            .del(keys.connections(&node))
+            .ignore()
+            .srem(keys.nodes(), node.as_str())
+            .ignore()
+            .query_async::<()>(&mut *connection)
+            .await
+            .map_err(BidirectionalError::internal)?;
+        info!("Node {} stopped responding; dropped its connections", node);
+    }
+    Ok(reaped)
+}
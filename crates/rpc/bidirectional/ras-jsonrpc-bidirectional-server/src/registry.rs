@@ -0,0 +1,771 @@
+//! Sharing connections between server nodes
+//!
+//! A [`DefaultConnectionManager`](crate::DefaultConnectionManager) only holds the
+//! connections of its own process. A [`ConnectionRegistry`] records which node holds
+//! each connection and carries messages to the other nodes, so a notification sent
+//! or broadcast on one node reaches connections held by another.
+//!
+//! Connection ids are random UUIDs, so they are unique across nodes; the registry
+//! namespaces its records by [`NodeId`] so that a node's entries can be dropped as a
+//! whole once it stops responding.
+
+use async_trait::async_trait;
+use ras_jsonrpc_bidirectional_types::{BidirectionalMessage, ConnectionId, PresenceEntry, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::mpsc;
+
+/// Identifies one server process sharing a registry
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NodeId(String);
+
+impl NodeId {
+    /// Create a node ID from a name, such as a host name or pod name
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    /// Create a new random node ID
+    pub fn random() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    /// The node ID as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for NodeId {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<String> for NodeId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+/// Which of a node's connections a delivery is for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Audience {
+    /// One connection
+    Connection { id: ConnectionId },
+    /// The connections subscribed to a topic
+    Topic { topic: String },
+    /// Every authenticated connection
+    Authenticated,
+    /// Every connection whose user has a permission
+    Permission { permission: String },
+}
+
+/// A message sent by one node for connections held by another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delivery {
+    /// The node that sent the message
+    pub origin: NodeId,
+    /// Which connections should receive it
+    pub audience: Audience,
+    /// The message itself
+    pub message: BidirectionalMessage,
+}
+
+/// Routes messages to connections held by other nodes
+///
+/// The connection manager calls into the registry as connections come and go, and
+/// whenever a message is meant for connections it does not hold. Deliveries from
+/// other nodes arrive on the inbox passed to [`attach`](Self::attach) and are
+/// handed to the node's own connections.
+#[async_trait]
+pub trait ConnectionRegistry: fmt::Debug + Send + Sync + 'static {
+    /// This node's ID
+    fn node_id(&self) -> &NodeId;
+
+    /// Start receiving deliveries for this node's connections on `inbox`
+    async fn attach(&self, inbox: mpsc::UnboundedSender<Delivery>) -> Result<()>;
+
+    /// Record that this node holds a connection
+    async fn register(&self, id: ConnectionId) -> Result<()>;
+
+    /// Forget a connection this node closed
+    async fn deregister(&self, id: ConnectionId) -> Result<()>;
+
+    /// Send to a connection held by another node
+    ///
+    /// Returns `false` when no live node holds the connection.
+    async fn send_to(&self, id: ConnectionId, message: BidirectionalMessage) -> Result<bool>;
+
+    /// Send to the matching connections of every other node
+    async fn broadcast(&self, audience: Audience, message: BidirectionalMessage) -> Result<()>;
+
+    /// Receive publishes to a topic, once this node has a subscriber to it
+    async fn subscribe(&self, topic: &str) -> Result<()>;
+
+    /// Stop receiving publishes to a topic, once this node has no subscribers left
+    async fn unsubscribe(&self, topic: &str) -> Result<()>;
+
+    /// Send to the subscribers of a topic on every other node
+    async fn publish(&self, topic: &str, message: BidirectionalMessage) -> Result<()>;
+
+    /// Take the next sequence number of a topic's broadcasts
+    ///
+    /// Numbers start at 1 and are shared by every node, so a topic's broadcasts
+    /// are numbered the same wherever they were sent from.
+    async fn next_sequence(&self, topic: &str) -> Result<u64>;
+
+    /// Record a connection as present under `key`, replacing its previous entry
+    async fn add_presence(&self, key: &str, entry: PresenceEntry) -> Result<()>;
+
+    /// Drop a connection's presence under `key`, returning its entry if it had one
+    async fn remove_presence(&self, key: &str, id: ConnectionId) -> Result<Option<PresenceEntry>>;
+
+    /// The connections present under `key` on every node, oldest first
+    async fn presence(&self, key: &str) -> Result<Vec<PresenceEntry>>;
+
+    /// Register a connection's filter for a notification, replacing its previous one
+    async fn set_filter(
+        &self,
+        notification: &str,
+        id: ConnectionId,
+        filter: serde_json::Value,
+    ) -> Result<()>;
+
+    /// Drop a connection's filter for a notification, returning whether it had one
+    async fn remove_filter(&self, notification: &str, id: ConnectionId) -> Result<bool>;
+
+    /// The filters registered for a notification by connections on every node
+    async fn filters(&self, notification: &str) -> Result<Vec<(ConnectionId, serde_json::Value)>>;
+
+    /// Drop the entries of nodes that stopped responding, returning how many
+    /// connections were removed
+    async fn reap_stale(&self) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+/// The registry of a server running on its own, with no other nodes to reach
+#[derive(Debug)]
+pub struct LocalRegistry {
+    node_id: NodeId,
+    sequences: Mutex<HashMap<String, u64>>,
+    presence: Mutex<PresenceTable>,
+    filters: Mutex<FilterTable>,
+}
+
+impl LocalRegistry {
+    /// Create a registry for a single node
+    pub fn new() -> Self {
+        Self {
+            node_id: NodeId::random(),
+            sequences: Mutex::default(),
+            presence: Mutex::default(),
+            filters: Mutex::default(),
+        }
+    }
+
+    fn filter_table(&self) -> MutexGuard<'_, FilterTable> {
+        self.filters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn presence_table(&self) -> MutexGuard<'_, PresenceTable> {
+        self.presence.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for LocalRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ConnectionRegistry for LocalRegistry {
+    fn node_id(&self) -> &NodeId {
+        &self.node_id
+    }
+
+    async fn attach(&self, _inbox: mpsc::UnboundedSender<Delivery>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn register(&self, _id: ConnectionId) -> Result<()> {
+        Ok(())
+    }
+
+    async fn deregister(&self, _id: ConnectionId) -> Result<()> {
+        Ok(())
+    }
+
+    async fn send_to(&self, _id: ConnectionId, _message: BidirectionalMessage) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn broadcast(&self, _audience: Audience, _message: BidirectionalMessage) -> Result<()> {
+        Ok(())
+    }
+
+    async fn subscribe(&self, _topic: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, _topic: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn publish(&self, _topic: &str, _message: BidirectionalMessage) -> Result<()> {
+        Ok(())
+    }
+
+    async fn next_sequence(&self, topic: &str) -> Result<u64> {
+        let mut sequences = self.sequences.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(next_in(&mut sequences, topic))
+    }
+
+    async fn add_presence(&self, key: &str, entry: PresenceEntry) -> Result<()> {
+        self.presence_table().add(key, entry);
+        Ok(())
+    }
+
+    async fn remove_presence(&self, key: &str, id: ConnectionId) -> Result<Option<PresenceEntry>> {
+        Ok(self.presence_table().remove(key, id))
+    }
+
+    async fn presence(&self, key: &str) -> Result<Vec<PresenceEntry>> {
+        Ok(self.presence_table().entries(key))
+    }
+
+    async fn set_filter(
+        &self,
+        notification: &str,
+        id: ConnectionId,
+        filter: serde_json::Value,
+    ) -> Result<()> {
+        self.filter_table().set(notification, id, filter);
+        Ok(())
+    }
+
+    async fn remove_filter(&self, notification: &str, id: ConnectionId) -> Result<bool> {
+        Ok(self.filter_table().remove(notification, id))
+    }
+
+    async fn filters(&self, notification: &str) -> Result<Vec<(ConnectionId, serde_json::Value)>> {
+        Ok(self.filter_table().entries(notification))
+    }
+}
+
+/// Advance a topic's counter, returning its new value
+fn next_in(sequences: &mut HashMap<String, u64>, topic: &str) -> u64 {
+    let seq = sequences.entry(topic.to_string()).or_default();
+    *seq += 1;
+    *seq
+}
+
+/// Presence entries by key, then by connection
+#[derive(Debug, Default)]
+struct PresenceTable(HashMap<String, HashMap<ConnectionId, PresenceEntry>>);
+
+impl PresenceTable {
+    fn add(&mut self, key: &str, entry: PresenceEntry) {
+        self.0
+            .entry(key.to_string())
+            .or_default()
+            .insert(entry.connection_id, entry);
+    }
+
+    fn remove(&mut self, key: &str, id: ConnectionId) -> Option<PresenceEntry> {
+        let entries = self.0.get_mut(key)?;
+        let entry = entries.remove(&id);
+        if entries.is_empty() {
+            self.0.remove(key);
+        }
+        entry
+    }
+
+    fn entries(&self, key: &str) -> Vec<PresenceEntry> {
+        let mut entries: Vec<_> = self
+            .0
+            .get(key)
+            .map(|entries| entries.values().cloned().collect())
+            .unwrap_or_default();
+        entries.sort_by_key(|entry| entry.joined_at);
+        entries
+    }
+
+    /// Keep only the entries of connections `keep` accepts
+    fn retain(&mut self, keep: impl Fn(ConnectionId) -> bool) {
+        for entries in self.0.values_mut() {
+            entries.retain(|id, _| keep(*id));
+        }
+        self.0.retain(|_, entries| !entries.is_empty());
+    }
+}
+
+/// Notification filters by notification, then by connection
+#[derive(Debug, Default)]
+struct FilterTable(HashMap<String, HashMap<ConnectionId, serde_json::Value>>);
+
+impl FilterTable {
+    fn set(&mut self, notification: &str, id: ConnectionId, filter: serde_json::Value) {
+        self.0
+            .entry(notification.to_string())
+            .or_default()
+            .insert(id, filter);
+    }
+
+    fn remove(&mut self, notification: &str, id: ConnectionId) -> bool {
+        let Some(filters) = self.0.get_mut(notification) else {
+            return false;
+        };
+        let removed = filters.remove(&id).is_some();
+        if filters.is_empty() {
+            self.0.remove(notification);
+        }
+        removed
+    }
+
+    fn entries(&self, notification: &str) -> Vec<(ConnectionId, serde_json::Value)> {
+        self.0
+            .get(notification)
+            .map(|filters| {
+                filters
+                    .iter()
+                    .map(|(id, filter)| (*id, filter.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Keep only the filters of connections `keep` accepts
+    fn retain(&mut self, keep: impl Fn(ConnectionId) -> bool) {
+        for filters in self.0.values_mut() {
+            filters.retain(|id, _| keep(*id));
+        }
+        self.0.retain(|_, filters| !filters.is_empty());
+    }
+}
+
+/// An in-process message bus shared by several nodes
+///
+/// Useful for running several services in one process, and for testing
+/// multi-node behaviour without an external broker.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBus {
+    state: Arc<Mutex<BusState>>,
+}
+
+#[derive(Debug, Default)]
+struct BusState {
+    inboxes: HashMap<NodeId, mpsc::UnboundedSender<Delivery>>,
+    dead: HashSet<NodeId>,
+    owners: HashMap<ConnectionId, NodeId>,
+    topics: HashMap<String, HashSet<NodeId>>,
+    sequences: HashMap<String, u64>,
+    presence: PresenceTable,
+    filters: FilterTable,
+}
+
+impl BusState {
+    /// The inboxes of the live nodes other than `origin`
+    fn peers(
+        &self,
+        origin: &NodeId,
+    ) -> impl Iterator<Item = (&NodeId, &mpsc::UnboundedSender<Delivery>)> {
+        self.inboxes
+            .iter()
+            .filter(move |(node, _)| *node != origin && !self.dead.contains(*node))
+    }
+}
+
+impl MemoryBus {
+    /// Create an empty bus
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry for one node on this bus
+    pub fn node(&self, id: impl Into<NodeId>) -> MemoryRegistry {
+        MemoryRegistry {
+            node_id: id.into(),
+            bus: self.clone(),
+        }
+    }
+
+    /// Stop delivering to a node, as if its process died
+    ///
+    /// Its connections stay registered until [`ConnectionRegistry::reap_stale`]
+    /// runs on a surviving node.
+    pub fn kill(&self, node: &NodeId) {
+        self.lock().dead.insert(node.clone());
+    }
+
+    /// The node holding a connection, if any
+    pub fn owner(&self, id: ConnectionId) -> Option<NodeId> {
+        self.lock().owners.get(&id).cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BusState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// One node's view of a [`MemoryBus`]
+#[derive(Debug, Clone)]
+pub struct MemoryRegistry {
+    node_id: NodeId,
+    bus: MemoryBus,
+}
+
+impl MemoryRegistry {
+    fn delivery(&self, audience: Audience, message: BidirectionalMessage) -> Delivery {
+        Delivery {
+            origin: self.node_id.clone(),
+            audience,
+            message,
+        }
+    }
+}
+
+#[async_trait]
+impl ConnectionRegistry for MemoryRegistry {
+    fn node_id(&self) -> &NodeId {
+        &self.node_id
+    }
+
+    async fn attach(&self, inbox: mpsc::UnboundedSender<Delivery>) -> Result<()> {
+        let mut state = self.bus.lock();
+        state.dead.remove(&self.node_id);
+        state.inboxes.insert(self.node_id.clone(), inbox);
+        Ok(())
+    }
+
+    async fn register(&self, id: ConnectionId) -> Result<()> {
+        self.bus.lock().owners.insert(id, self.node_id.clone());
+        Ok(())
+    }
+
+    async fn deregister(&self, id: ConnectionId) -> Result<()> {
+        let mut state = self.bus.lock();
+        if state.owners.get(&id) == Some(&self.node_id) {
+            state.owners.remove(&id);
+        }
+        Ok(())
+    }
+
+    async fn send_to(&self, id: ConnectionId, message: BidirectionalMessage) -> Result<bool> {
+        let state = self.bus.lock();
+        let Some(owner) = state.owners.get(&id) else {
+            return Ok(false);
+        };
+        let delivered = state
+            .peers(&self.node_id)
+            .find(|(node, _)| *node == owner)
+            .is_some_and(|(_, inbox)| {
+                inbox
+                    .send(self.delivery(Audience::Connection { id }, message))
+                    .is_ok()
+            });
+        Ok(delivered)
+    }
+
+    async fn broadcast(&self, audience: Audience, message: BidirectionalMessage) -> Result<()> {
+        let state = self.bus.lock();
+        for (_, inbox) in state.peers(&self.node_id) {
+            let _ = inbox.send(self.delivery(audience.clone(), message.clone()));
+        }
+        Ok(())
+    }
+
+    async fn subscribe(&self, topic: &str) -> Result<()> {
+        self.bus
+            .lock()
+            .topics
+            .entry(topic.to_string())
+            .or_default()
+            .insert(self.node_id.clone());
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, topic: &str) -> Result<()> {
+        let mut state = self.bus.lock();
+        if let Some(nodes) = state.topics.get_mut(topic) {
+            nodes.remove(&self.node_id);
+            if nodes.is_empty() {
+                state.topics.remove(topic);
+            }
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, topic: &str, message: BidirectionalMessage) -> Result<()> {
+        let state = self.bus.lock();
+        let Some(subscribers) = state.topics.get(topic) else {
+            return Ok(());
+        };
+        for (_, inbox) in state
+            .peers(&self.node_id)
+            .filter(|(node, _)| subscribers.contains(*node))
+        {
+            let audience = Audience::Topic {
+                topic: topic.to_string(),
+            };
+            let _ = inbox.send(self.delivery(audience, message.clone()));
+        }
+        Ok(())
+    }
+
+    async fn next_sequence(&self, topic: &str) -> Result<u64> {
+        Ok(next_in(&mut self.bus.lock().sequences, topic))
+    }
+
+    async fn add_presence(&self, key: &str, entry: PresenceEntry) -> Result<()> {
+        self.bus.lock().presence.add(key, entry);
+        Ok(())
+    }
+
+    async fn remove_presence(&self, key: &str, id: ConnectionId) -> Result<Option<PresenceEntry>> {
+        Ok(self.bus.lock().presence.remove(key, id))
+    }
+
+    async fn presence(&self, key: &str) -> Result<Vec<PresenceEntry>> {
+        Ok(self.bus.lock().presence.entries(key))
+    }
+
+    async fn set_filter(
+        &self,
+        notification: &str,
+        id: ConnectionId,
+        filter: serde_json::Value,
+    ) -> Result<()> {
+        self.bus.lock().filters.set(notification, id, filter);
+        Ok(())
+    }
+
+    async fn remove_filter(&self, notification: &str, id: ConnectionId) -> Result<bool> {
+        Ok(self.bus.lock().filters.remove(notification, id))
+    }
+
+    async fn filters(&self, notification: &str) -> Result<Vec<(ConnectionId, serde_json::Value)>> {
+        Ok(self.bus.lock().filters.entries(notification))
+    }
+
+    async fn reap_stale(&self) -> Result<usize> {
+        let mut state = self.bus.lock();
+        let dead = std::mem::take(&mut state.dead);
+        let before = state.owners.len();
+        state.owners.retain(|_, node| !dead.contains(node));
+        // Connections of dead nodes are no longer present anywhere, nor filtering
+        let BusState {
+            owners,
+            presence,
+            filters,
+            ..
+        } = &mut *state;
+        presence.retain(|id| owners.contains_key(&id));
+        filters.retain(|id| owners.contains_key(&id));
+        state.inboxes.retain(|node, _| !dead.contains(node));
+        for nodes in state.topics.values_mut() {
+            nodes.retain(|node| !dead.contains(node));
+        }
+        state.topics.retain(|_, nodes| !nodes.is_empty());
+        Ok(before - state.owners.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_jsonrpc_bidirectional_types::ServerNotification;
+    use serde_json::json;
+
+    fn ping() -> BidirectionalMessage {
+        BidirectionalMessage::ServerNotification(ServerNotification {
+            method: "ping".into(),
+            params: json!({}),
+            metadata: None,
+        })
+    }
+
+    async fn attached(
+        bus: &MemoryBus,
+        name: &str,
+    ) -> (MemoryRegistry, mpsc::UnboundedReceiver<Delivery>) {
+        let registry = bus.node(name);
+        let (tx, rx) = mpsc::unbounded_channel();
+        registry.attach(tx).await.unwrap();
+        (registry, rx)
+    }
+
+    #[tokio::test]
+    async fn send_to_reaches_only_the_owning_node() {
+        let bus = MemoryBus::new();
+        let (a, mut a_rx) = attached(&bus, "a").await;
+        let (b, mut b_rx) = attached(&bus, "b").await;
+        let (_c, mut c_rx) = attached(&bus, "c").await;
+
+        let id = ConnectionId::new();
+        b.register(id).await.unwrap();
+        assert!(a.send_to(id, ping()).await.unwrap());
+        let delivery = b_rx.try_recv().unwrap();
+        assert_eq!(delivery.origin, NodeId::new("a"));
+        assert_eq!(delivery.audience, Audience::Connection { id });
+        assert!(c_rx.try_recv().is_err());
+
+        // Connections held locally and unknown ones are not routed
+        assert!(!b.send_to(id, ping()).await.unwrap());
+        assert!(!a.send_to(ConnectionId::new(), ping()).await.unwrap());
+        assert!(a_rx.try_recv().is_err());
+
+        b.deregister(id).await.unwrap();
+        assert!(!a.send_to(id, ping()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn publish_reaches_subscribed_nodes_only() {
+        let bus = MemoryBus::new();
+        let (a, mut a_rx) = attached(&bus, "a").await;
+        let (b, mut b_rx) = attached(&bus, "b").await;
+        let (_c, mut c_rx) = attached(&bus, "c").await;
+
+        a.subscribe("room").await.unwrap();
+        b.subscribe("room").await.unwrap();
+        a.publish("room", ping()).await.unwrap();
+        assert!(matches!(
+            b_rx.try_recv().unwrap().audience,
+            Audience::Topic { topic } if topic == "room"
+        ));
+        assert!(a_rx.try_recv().is_err());
+        assert!(c_rx.try_recv().is_err());
+
+        b.unsubscribe("room").await.unwrap();
+        a.publish("room", ping()).await.unwrap();
+        assert!(b_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn dead_nodes_are_skipped_then_reaped() {
+        let bus = MemoryBus::new();
+        let (a, _a_rx) = attached(&bus, "a").await;
+        let (b, _b_rx) = attached(&bus, "b").await;
+        let held = [ConnectionId::new(), ConnectionId::new()];
+        for id in held {
+            b.register(id).await.unwrap();
+        }
+
+        bus.kill(b.node_id());
+        assert!(!a.send_to(held[0], ping()).await.unwrap());
+        assert_eq!(bus.owner(held[0]), Some(NodeId::new("b")));
+
+        assert_eq!(a.reap_stale().await.unwrap(), 2);
+        assert_eq!(bus.owner(held[0]), None);
+        assert_eq!(a.reap_stale().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn sequences_are_shared_between_nodes_per_topic() {
+        let bus = MemoryBus::new();
+        let (a, _a_rx) = attached(&bus, "a").await;
+        let (b, _b_rx) = attached(&bus, "b").await;
+
+        assert_eq!(a.next_sequence("room").await.unwrap(), 1);
+        assert_eq!(b.next_sequence("room").await.unwrap(), 2);
+        assert_eq!(b.next_sequence("other").await.unwrap(), 1);
+        assert_eq!(a.next_sequence("room").await.unwrap(), 3);
+    }
+
+    fn entry(id: ConnectionId, user: &str) -> PresenceEntry {
+        PresenceEntry {
+            user_id: Some(user.to_string()),
+            connection_id: id,
+            joined_at: chrono::Utc::now(),
+            metadata: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn presence_is_shared_between_nodes_until_reaped() {
+        let bus = MemoryBus::new();
+        let (a, _a_rx) = attached(&bus, "a").await;
+        let (b, _b_rx) = attached(&bus, "b").await;
+        let (on_a, on_b) = (ConnectionId::new(), ConnectionId::new());
+        a.register(on_a).await.unwrap();
+        b.register(on_b).await.unwrap();
+
+        let alice = entry(on_a, "alice");
+        let bob = PresenceEntry {
+            joined_at: alice.joined_at + chrono::Duration::seconds(1),
+            ..entry(on_b, "bob")
+        };
+        a.add_presence("doc", alice).await.unwrap();
+        b.add_presence("doc", bob).await.unwrap();
+        let users: Vec<_> = a
+            .presence("doc")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.user_id.unwrap())
+            .collect();
+        assert_eq!(users, ["alice", "bob"]);
+
+        // A dead node's connections are no longer present once it is reaped
+        bus.kill(b.node_id());
+        a.reap_stale().await.unwrap();
+        let remaining = a.presence("doc").await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].connection_id, on_a);
+
+        assert!(a.remove_presence("doc", on_a).await.unwrap().is_some());
+        assert!(a.remove_presence("doc", on_a).await.unwrap().is_none());
+        assert!(a.presence("doc").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn filters_are_shared_between_nodes_until_reaped() {
+        let bus = MemoryBus::new();
+        let (a, _a_rx) = attached(&bus, "a").await;
+        let (b, _b_rx) = attached(&bus, "b").await;
+        let (on_a, on_b) = (ConnectionId::new(), ConnectionId::new());
+        a.register(on_a).await.unwrap();
+        b.register(on_b).await.unwrap();
+
+        a.set_filter("task_updated", on_a, serde_json::json!({ "project": 1 }))
+            .await
+            .unwrap();
+        b.set_filter("task_updated", on_b, serde_json::json!({ "project": 2 }))
+            .await
+            .unwrap();
+        a.set_filter("task_updated", on_a, serde_json::json!({ "project": 3 }))
+            .await
+            .unwrap();
+        let mut filters = b.filters("task_updated").await.unwrap();
+        filters.sort_by_key(|(_, filter)| filter["project"].as_u64());
+        assert_eq!(
+            filters,
+            [
+                (on_b, serde_json::json!({ "project": 2 })),
+                (on_a, serde_json::json!({ "project": 3 })),
+            ]
+        );
+        assert!(a.filters("other").await.unwrap().is_empty());
+
+        // A dead node's connections stop filtering once it is reaped
+        bus.kill(b.node_id());
+        a.reap_stale().await.unwrap();
+        assert_eq!(a.filters("task_updated").await.unwrap().len(), 1);
+
+        assert!(a.remove_filter("task_updated", on_a).await.unwrap());
+        assert!(!a.remove_filter("task_updated", on_a).await.unwrap());
+        assert!(a.filters("task_updated").await.unwrap().is_empty());
+    }
+}
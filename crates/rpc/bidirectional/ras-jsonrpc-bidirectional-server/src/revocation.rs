@@ -0,0 +1,121 @@
+//! Closing connections whose session was ended, such as by a logout
+//!
+//! A [`SessionRevocations`] source names the session each token belongs to and
+//! reports sessions as they end. Each connection records the session of the
+//! token it authenticated with, and is closed with
+//! [`CloseReason::SessionRevoked`](crate::CloseReason::SessionRevoked) once that
+//! session is reported ended. With the `session` feature, a
+//! `ras_identity_session::SessionService` is such a source.
+
+use crate::ConnectionContext;
+use futures::stream::{BoxStream, StreamExt};
+
+/// Ids of the sessions that end, as they end
+pub type SessionRevocationStream = BoxStream<'static, String>;
+
+/// Tells which session a token belongs to, and when sessions end
+pub trait SessionRevocations: Send + Sync + 'static {
+    /// Id of the session `token` belongs to, if it is one that can be ended
+    fn session_id(&self, token: &str) -> Option<String>;
+
+    /// Ids of the sessions ended from now on
+    ///
+    /// Each connection takes its own stream; when it ends, the connection is no
+    /// longer closed on revocations.
+    fn revocations(&self) -> SessionRevocationStream;
+}
+
+#[cfg(feature = "session")]
+impl SessionRevocations for ras_identity_session::SessionService {
+    fn session_id(&self, token: &str) -> Option<String> {
+        self.jti(token)
+    }
+
+    fn revocations(&self) -> SessionRevocationStream {
+        use ras_identity_session::SessionEvent;
+        use tokio::sync::broadcast::error::RecvError;
+
+        futures::stream::unfold(self.subscribe(), |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(SessionEvent::Ended { jti, .. }) => return Some((jti, events)),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!("Missed {} session events", missed);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+/// Record the session of the token `context` is authenticated with
+pub(crate) fn track(revocations: &dyn SessionRevocations, context: &ConnectionContext) {
+    let session_id = context
+        .token()
+        .and_then(|token| revocations.session_id(&token));
+    context.set_session_id(session_id);
+}
+
+/// Wait for the next ended session, or forever once there are no more
+pub(crate) async fn next_revocation(stream: &mut Option<SessionRevocationStream>) -> String {
+    if let Some(revocations) = stream {
+        if let Some(session_id) = revocations.next().await {
+            return session_id;
+        }
+        *stream = None;
+    }
+    std::future::pending().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::ChannelMessageSender;
+    use crate::queue::{OverflowPolicy, outbound_queue};
+    use ras_jsonrpc_bidirectional_types::ConnectionId;
+    use std::time::Duration;
+
+    /// Tokens are `session:<id>`, and ended sessions come from a fixed list
+    struct Ended(Vec<&'static str>);
+
+    impl SessionRevocations for Ended {
+        fn session_id(&self, token: &str) -> Option<String> {
+            token.strip_prefix("session:").map(str::to_string)
+        }
+
+        fn revocations(&self) -> SessionRevocationStream {
+            futures::stream::iter(self.0.clone())
+                .map(str::to_string)
+                .boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn connections_track_the_session_of_their_token() {
+        let id = ConnectionId::new();
+        let (tx, _rx) = outbound_queue(4, OverflowPolicy::default());
+        let context = ConnectionContext::new(id, ChannelMessageSender::new(id, tx));
+        let revocations = Ended(vec!["a"]);
+
+        track(&revocations, &context);
+        assert_eq!(context.session_id(), None);
+        context.set_token(Some("session:a".into()));
+        track(&revocations, &context);
+        assert_eq!(context.session_id().as_deref(), Some("a"));
+        context.set_token(Some("opaque".into()));
+        track(&revocations, &context);
+        assert_eq!(context.session_id(), None);
+    }
+
+    #[tokio::test]
+    async fn finished_streams_stop_yielding() {
+        let mut stream = Some(Ended(vec!["a"]).revocations());
+        assert_eq!(next_revocation(&mut stream).await, "a");
+        let next = tokio::time::timeout(Duration::from_millis(20), next_revocation(&mut stream));
+        assert!(next.await.is_err());
+        assert!(stream.is_none());
+    }
+}
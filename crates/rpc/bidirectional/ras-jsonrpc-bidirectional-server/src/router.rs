@@ -188,9 +188,9 @@ impl MessageHandler for MessageRouter {
 mod tests {
     use super::*;
     use crate::ServerError;
+    use crate::queue::{OverflowPolicy, outbound_queue};
     use ras_jsonrpc_bidirectional_types::ConnectionId;
     use serde_json::json;
-    use tokio::sync::mpsc;
 
     #[tokio::test]
     async fn test_router_registration() {
@@ -219,7 +219,7 @@ mod tests {
 
         // Create test context
         let connection_id = ConnectionId::new();
-        let (tx, _rx) = mpsc::channel(1);
+        let (tx, _rx) = outbound_queue(1, OverflowPolicy::default());
         let sender = crate::connection::ChannelMessageSender::new(connection_id, tx);
         let context = Arc::new(ConnectionContext::new(connection_id, sender));
 
@@ -261,7 +261,7 @@ mod tests {
 
     fn test_context() -> Arc<ConnectionContext> {
         let connection_id = ConnectionId::new();
-        let (tx, _rx) = mpsc::channel(1);
+        let (tx, _rx) = outbound_queue(1, OverflowPolicy::default());
         let sender = crate::connection::ChannelMessageSender::new(connection_id, tx);
         Arc::new(ConnectionContext::new(connection_id, sender))
     }
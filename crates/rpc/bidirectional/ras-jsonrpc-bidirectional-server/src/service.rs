@@ -1,23 +1,28 @@
 //! WebSocket service implementation with builder pattern
 
 use crate::{
-    ConnectionContext, DefaultConnectionManager, MessageHandler, MessageRouter, ServerError,
-    ServerResult, WebSocketHandler, WebSocketUpgrade, connection::ChannelMessageSender,
+    ConnectionContext, DefaultConnectionManager, FrameStats, KeepaliveConfig, MessageHandler,
+    MessageLimits, MessageRouter, MiddlewareChain, OverflowPolicy, RateLimitConfig, ServerError,
+    ServerResult, SessionRevocations, ShutdownCoordinator, WebSocketHandler, WebSocketUpgrade,
+    connection::ChannelMessageSender, limits::DEFAULT_MAX_MALFORMED_FRAMES, queue::outbound_queue,
+    revocation,
 };
 use axum::{
-    extract::{State, ws::WebSocketUpgrade as AxumWebSocketUpgrade},
-    http::HeaderMap,
+    extract::{ConnectInfo, State, ws::WebSocketUpgrade as AxumWebSocketUpgrade},
+    http::{Extensions, HeaderMap},
     response::Response,
 };
 use bon::Builder;
 use ras_auth_core::AuthProvider;
-use ras_jsonrpc_bidirectional_types::{ConnectionId, ConnectionInfo, ConnectionManager};
+use ras_jsonrpc_bidirectional_types::{Codec, ConnectionId, ConnectionInfo, ConnectionManager};
+use ras_jsonrpc_core::ServiceMetrics;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
 use tracing::{error, info};
 
-const DEFAULT_MESSAGE_CHANNEL_CAPACITY: usize = 1024;
-const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+pub(crate) const DEFAULT_MESSAGE_CHANNEL_CAPACITY: usize = 1024;
+pub(crate) const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
 
 /// Trait for services that handle WebSocket JSON-RPC communication
 #[allow(async_fn_in_trait)]
@@ -46,18 +51,92 @@ pub trait WebSocketService: Clone + Send + Sync + 'static {
         DEFAULT_MESSAGE_CHANNEL_CAPACITY
     }
 
+    /// What happens to outbound messages once a connection's queue is full.
+    fn overflow_policy(&self) -> OverflowPolicy {
+        OverflowPolicy::default()
+    }
+
     /// Maximum accepted inbound WebSocket message size in bytes.
     fn max_message_size(&self) -> usize {
         DEFAULT_MAX_MESSAGE_SIZE
     }
 
-    /// Handle WebSocket upgrade
+    /// Maximum outbound WebSocket message size in bytes, if limited.
+    fn max_outgoing_message_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Malformed messages a connection may send before it is closed; `0` never closes it.
+    fn max_malformed_frames(&self) -> u32 {
+        DEFAULT_MAX_MALFORMED_FRAMES
+    }
+
+    /// Counters of rejected frames across the service's connections, if it keeps them.
+    fn frame_stats(&self) -> Option<FrameStats> {
+        None
+    }
+
+    /// Keepalive pings sent to each connection.
+    fn keepalive(&self) -> KeepaliveConfig {
+        KeepaliveConfig::default()
+    }
+
+    /// Binary codecs clients may negotiate, in the order preferred when offered.
+    fn codecs(&self) -> Vec<Codec> {
+        Codec::BINARY.to_vec()
+    }
+
+    /// Coordinator draining the service's connections on shutdown, if it supports it.
+    fn shutdown_coordinator(&self) -> Option<ShutdownCoordinator> {
+        None
+    }
+
+    /// How often each connection's token is re-validated, if at all.
+    fn revalidate_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Metrics recording connections, messages and call durations, if any.
+    fn service_metrics(&self) -> Option<Arc<dyn ServiceMetrics>> {
+        None
+    }
+
+    /// Rate limits on each connection's requests, if any.
+    fn rate_limits(&self) -> Option<RateLimitConfig> {
+        None
+    }
+
+    /// Middleware run around each connection's requests and outgoing messages.
+    fn middleware(&self) -> MiddlewareChain {
+        MiddlewareChain::new()
+    }
+
+    /// Source of ended sessions whose connections are closed, if any.
+    fn session_revocations(&self) -> Option<Arc<dyn SessionRevocations>> {
+        None
+    }
+
+    /// Handle WebSocket upgrade from a peer at `remote_addr`, if known
     async fn handle_upgrade(
         &self,
         upgrade: AxumWebSocketUpgrade,
         headers: HeaderMap,
+        remote_addr: Option<SocketAddr>,
     ) -> Result<Response, (axum::http::StatusCode, String)> {
-        let ws_upgrade = WebSocketUpgrade::new(upgrade, headers);
+        if self
+            .shutdown_coordinator()
+            .is_some_and(|shutdown| shutdown.is_draining())
+        {
+            return Err((
+                axum::http::StatusCode::SERVICE_UNAVAILABLE,
+                "Server is shutting down".to_string(),
+            ));
+        }
+
+        let ws_upgrade = WebSocketUpgrade::new(upgrade, headers)
+            .max_message_size(self.max_message_size())
+            .codecs(&self.codecs());
+        let token = ws_upgrade.credential(&*self.auth_provider());
         let service = self.clone();
 
         ws_upgrade
@@ -65,8 +144,13 @@ pub trait WebSocketService: Clone + Send + Sync + 'static {
                 &*self.auth_provider(),
                 self.require_auth(),
                 move |socket, user| {
+                    // Keep the token the connection authenticated with for re-validation
+                    let session = user.map(|user| (user, token.unwrap_or_default()));
                     Box::pin(async move {
-                        if let Err(e) = service.handle_connection(socket, user).await {
+                        if let Err(e) = service
+                            .handle_connection(socket, session, remote_addr)
+                            .await
+                        {
                             error!("WebSocket connection error: {}", e);
                         }
                     })
@@ -75,33 +159,57 @@ pub trait WebSocketService: Clone + Send + Sync + 'static {
             .await
     }
 
-    /// Handle an individual WebSocket connection
+    /// Handle an individual WebSocket connection, authenticated as a user with
+    /// the bearer token they presented, if any
     fn handle_connection(
         &self,
         socket: axum::extract::ws::WebSocket,
-        user: Option<ras_auth_core::AuthenticatedUser>,
+        session: Option<(ras_auth_core::AuthenticatedUser, String)>,
+        remote_addr: Option<SocketAddr>,
     ) -> impl std::future::Future<Output = ServerResult<()>> + Send {
         let service = self.clone();
         async move {
             let connection_id = ConnectionId::new();
             info!("New WebSocket connection: {}", connection_id);
-
-            // Create message channel for this connection
-            let channel_capacity = service.message_channel_capacity().max(1);
-            let (message_tx, message_rx) = mpsc::channel(channel_capacity);
+            // The subprotocol selected during the upgrade names the codec, if any
+            let codec = socket
+                .protocol()
+                .and_then(|protocol| protocol.to_str().ok())
+                .and_then(Codec::from_subprotocol)
+                .unwrap_or_default();
+            // Shutdown waits for the connection until it is unregistered below
+            let _connection = service
+                .shutdown_coordinator()
+                .map(|shutdown| shutdown.begin_connection());
+
+            // Create the bounded outgoing queue for this connection
+            let (message_tx, message_rx) = outbound_queue(
+                service.message_channel_capacity(),
+                service.overflow_policy(),
+            );
             let sender = ChannelMessageSender::new(connection_id, message_tx);
 
             // Create connection info and add to manager
             let mut info = ConnectionInfo::new(connection_id);
-            if let Some(user) = user.clone() {
+            if let Some((user, _)) = session.clone() {
                 info.set_user(user);
             }
 
             // Create connection context
-            let context = Arc::new(ConnectionContext::new(connection_id, sender.clone()));
-            if let Some(user) = user {
+            let mut context =
+                ConnectionContext::new(connection_id, sender.clone()).with_remote_addr(remote_addr);
+            if let Some(limits) = service.rate_limits() {
+                context = context.with_rate_limits(limits);
+            }
+            let context = Arc::new(context);
+            if let Some((user, token)) = session {
+                context.set_token(Some(token));
                 context.set_user(user).await;
             }
+            let revocations = service.session_revocations();
+            if let Some(revocations) = &revocations {
+                revocation::track(&**revocations, &context);
+            }
 
             // Add connection to manager with the real sender
             service
@@ -111,12 +219,37 @@ pub trait WebSocketService: Clone + Send + Sync + 'static {
                 .map_err(ServerError::ConnectionError)?;
 
             // Create and run WebSocket handler
-            let handler = WebSocketHandler::new(
+            let mut handler = WebSocketHandler::new(
                 service.handler(),
                 context.clone(),
                 message_rx,
                 service.max_message_size(),
-            );
+            )
+            .with_limits(MessageLimits {
+                max_incoming_size: service.max_message_size(),
+                max_outgoing_size: service.max_outgoing_message_size(),
+                max_malformed_frames: service.max_malformed_frames(),
+            })
+            .with_keepalive(service.keepalive())
+            .with_codec(codec)
+            .with_connection_manager(service.connection_manager())
+            .with_auth_provider(service.auth_provider())
+            .with_middleware(service.middleware());
+            if let Some(stats) = service.frame_stats() {
+                handler = handler.with_frame_stats(stats);
+            }
+            if let Some(shutdown) = service.shutdown_coordinator() {
+                handler = handler.with_shutdown(shutdown);
+            }
+            if let Some(interval) = service.revalidate_interval() {
+                handler = handler.with_revalidation(interval);
+            }
+            if let Some(metrics) = service.service_metrics() {
+                handler = handler.with_service_metrics(metrics);
+            }
+            if let Some(revocations) = revocations {
+                handler = handler.with_session_revocations(revocations);
+            }
 
             // Handle the connection (this will block until connection closes)
             let result = handler.run(socket).await;
@@ -150,9 +283,34 @@ pub struct WebSocketServiceBuilder<H, A, M = DefaultConnectionManager> {
     /// Maximum queued outbound messages per connection
     #[builder(default = DEFAULT_MESSAGE_CHANNEL_CAPACITY)]
     message_channel_capacity: usize,
+    /// What happens to outbound messages once a connection's queue is full
+    #[builder(default)]
+    overflow_policy: OverflowPolicy,
     /// Maximum accepted inbound WebSocket message size in bytes
     #[builder(default = DEFAULT_MAX_MESSAGE_SIZE)]
     max_message_size: usize,
+    /// Maximum outbound WebSocket message size in bytes; unlimited when unset
+    max_outgoing_message_size: Option<usize>,
+    /// Malformed messages a connection may send before it is closed
+    #[builder(default = DEFAULT_MAX_MALFORMED_FRAMES)]
+    max_malformed_frames: u32,
+    /// Keepalive pings sent to each connection
+    #[builder(default)]
+    keepalive: KeepaliveConfig,
+    /// Binary codecs clients may negotiate; both MessagePack and CBOR by default
+    #[builder(default = Codec::BINARY.to_vec())]
+    codecs: Vec<Codec>,
+    /// How often each connection's token is re-validated; never when unset
+    revalidate_interval: Option<Duration>,
+    /// Metrics recording connections, messages and call durations
+    service_metrics: Option<Arc<dyn ServiceMetrics>>,
+    /// Rate limits on each connection's requests; unlimited when unset
+    rate_limits: Option<RateLimitConfig>,
+    /// Middleware run around each connection's requests and outgoing messages
+    #[builder(default)]
+    middleware: MiddlewareChain,
+    /// Source of ended sessions whose connections are closed
+    session_revocations: Option<Arc<dyn SessionRevocations>>,
 }
 
 impl<H, A> WebSocketServiceBuilder<H, A, DefaultConnectionManager>
@@ -170,7 +328,19 @@ where
                 .unwrap_or_else(|| Arc::new(DefaultConnectionManager::new())),
             require_auth: self.require_auth,
             message_channel_capacity: self.message_channel_capacity,
+            overflow_policy: self.overflow_policy,
             max_message_size: self.max_message_size,
+            max_outgoing_message_size: self.max_outgoing_message_size,
+            max_malformed_frames: self.max_malformed_frames,
+            keepalive: self.keepalive,
+            codecs: self.codecs,
+            revalidate_interval: self.revalidate_interval,
+            service_metrics: self.service_metrics,
+            rate_limits: self.rate_limits,
+            middleware: self.middleware,
+            session_revocations: self.session_revocations,
+            shutdown: ShutdownCoordinator::new(),
+            frame_stats: FrameStats::new(),
         }
     }
 }
@@ -189,7 +359,19 @@ where
             connection_manager: manager,
             require_auth: self.require_auth,
             message_channel_capacity: self.message_channel_capacity,
+            overflow_policy: self.overflow_policy,
             max_message_size: self.max_message_size,
+            max_outgoing_message_size: self.max_outgoing_message_size,
+            max_malformed_frames: self.max_malformed_frames,
+            keepalive: self.keepalive,
+            codecs: self.codecs,
+            revalidate_interval: self.revalidate_interval,
+            service_metrics: self.service_metrics,
+            rate_limits: self.rate_limits,
+            middleware: self.middleware,
+            session_revocations: self.session_revocations,
+            shutdown: ShutdownCoordinator::new(),
+            frame_stats: FrameStats::new(),
         }
     }
 }
@@ -201,7 +383,35 @@ pub struct BuiltWebSocketService<H, A, M> {
     connection_manager: Arc<M>,
     require_auth: bool,
     message_channel_capacity: usize,
+    overflow_policy: OverflowPolicy,
     max_message_size: usize,
+    max_outgoing_message_size: Option<usize>,
+    max_malformed_frames: u32,
+    keepalive: KeepaliveConfig,
+    codecs: Vec<Codec>,
+    revalidate_interval: Option<Duration>,
+    service_metrics: Option<Arc<dyn ServiceMetrics>>,
+    rate_limits: Option<RateLimitConfig>,
+    middleware: MiddlewareChain,
+    session_revocations: Option<Arc<dyn SessionRevocations>>,
+    shutdown: ShutdownCoordinator,
+    frame_stats: FrameStats,
+}
+
+impl<H, A, M> BuiltWebSocketService<H, A, M>
+where
+    M: ConnectionManager,
+{
+    /// Shut the service down gracefully
+    ///
+    /// New upgrades are refused, every client is sent a `server.shutdown`
+    /// notification with the deadline, and requests in flight get up to `grace` to
+    /// finish before all connections are closed with a "going away" close frame.
+    pub async fn shutdown(&self, grace: Duration) {
+        self.shutdown
+            .shutdown(self.connection_manager.as_ref(), grace)
+            .await
+    }
 }
 
 impl<H, A, M> Clone for BuiltWebSocketService<H, A, M> {
@@ -212,7 +422,19 @@ impl<H, A, M> Clone for BuiltWebSocketService<H, A, M> {
             connection_manager: self.connection_manager.clone(),
             require_auth: self.require_auth,
             message_channel_capacity: self.message_channel_capacity,
+            overflow_policy: self.overflow_policy,
             max_message_size: self.max_message_size,
+            max_outgoing_message_size: self.max_outgoing_message_size,
+            max_malformed_frames: self.max_malformed_frames,
+            keepalive: self.keepalive,
+            codecs: self.codecs.clone(),
+            revalidate_interval: self.revalidate_interval,
+            service_metrics: self.service_metrics.clone(),
+            rate_limits: self.rate_limits.clone(),
+            middleware: self.middleware.clone(),
+            session_revocations: self.session_revocations.clone(),
+            shutdown: self.shutdown.clone(),
+            frame_stats: self.frame_stats.clone(),
         }
     }
 }
@@ -247,9 +469,57 @@ where
         self.message_channel_capacity
     }
 
+    fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
     fn max_message_size(&self) -> usize {
         self.max_message_size
     }
+
+    fn max_outgoing_message_size(&self) -> Option<usize> {
+        self.max_outgoing_message_size
+    }
+
+    fn max_malformed_frames(&self) -> u32 {
+        self.max_malformed_frames
+    }
+
+    fn frame_stats(&self) -> Option<FrameStats> {
+        Some(self.frame_stats.clone())
+    }
+
+    fn keepalive(&self) -> KeepaliveConfig {
+        self.keepalive
+    }
+
+    fn codecs(&self) -> Vec<Codec> {
+        self.codecs.clone()
+    }
+
+    fn shutdown_coordinator(&self) -> Option<ShutdownCoordinator> {
+        Some(self.shutdown.clone())
+    }
+
+    fn revalidate_interval(&self) -> Option<Duration> {
+        self.revalidate_interval
+    }
+
+    fn service_metrics(&self) -> Option<Arc<dyn ServiceMetrics>> {
+        self.service_metrics.clone()
+    }
+
+    fn rate_limits(&self) -> Option<RateLimitConfig> {
+        self.rate_limits.clone()
+    }
+
+    fn middleware(&self) -> MiddlewareChain {
+        self.middleware.clone()
+    }
+
+    fn session_revocations(&self) -> Option<Arc<dyn SessionRevocations>> {
+        self.session_revocations.clone()
+    }
 }
 
 /// Convenience function to create a simple router-based service
@@ -270,21 +540,28 @@ where
 }
 
 /// Axum handler function for WebSocket upgrade
+///
+/// Connections record the peer address when the app is served with
+/// `into_make_service_with_connect_info::<SocketAddr>()`.
 pub async fn websocket_handler<S>(
     ws: AxumWebSocketUpgrade,
     headers: HeaderMap,
+    extensions: Extensions,
     State(service): State<S>,
 ) -> Result<Response, (axum::http::StatusCode, String)>
 where
     S: WebSocketService,
 {
-    service.handle_upgrade(ws, headers).await
+    let remote_addr = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    service.handle_upgrade(ws, headers, remote_addr).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ras_auth_core::{AuthError, AuthenticatedUser};
+    use ras_auth_core::{AuthError, AuthenticatedUser, PrincipalKind};
     use std::collections::HashSet;
 
     // Mock auth provider for testing
@@ -299,6 +576,7 @@ mod tests {
                         user_id: "test_user".to_string(),
                         permissions: HashSet::new(),
                         metadata: None,
+                        kind: PrincipalKind::User,
                     })
                 } else {
                     Err(AuthError::InvalidToken)
@@ -0,0 +1,275 @@
+//! Re-authenticating connections without reconnecting
+
+use crate::ConnectionContext;
+use ras_auth_core::{AuthError, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_bidirectional_types::{ConnectionManager, SessionRefresh};
+use ras_jsonrpc_core::jsonrpc_auth_error;
+use ras_jsonrpc_types::{JsonRpcError, JsonRpcRequest, JsonRpcResponse};
+use tracing::{info, warn};
+
+/// Outcome of running a connection's token through the auth provider again
+#[derive(Debug)]
+pub(crate) enum Revalidation {
+    /// The token is still valid; the connection's user was updated from it
+    Valid,
+    /// The connection never authenticated, so there is nothing to check
+    Anonymous,
+    /// The provider rejected the token
+    Rejected(AuthError),
+    /// The provider failed, so the token could not be checked
+    Unavailable(AuthError),
+}
+
+/// Answer a `session.refresh` request
+///
+/// The token in the params is authenticated, and on success replaces the
+/// connection's token and user in one step, so calls in flight are checked
+/// against the new user. A rejected token leaves the session unchanged.
+pub(crate) async fn refresh(
+    provider: &dyn AuthProvider,
+    context: &ConnectionContext,
+    manager: Option<&dyn ConnectionManager>,
+    request: JsonRpcRequest,
+) -> Option<JsonRpcResponse> {
+    let params = request.params.unwrap_or_default();
+    let result = match serde_json::from_value::<SessionRefresh>(params) {
+        Ok(SessionRefresh { token }) => match provider.authenticate(token.clone()).await {
+            Ok(user) => {
+                info!(
+                    "Connection {} refreshed its session as {}",
+                    context.id, user.user_id
+                );
+                context.set_token(Some(token));
+                adopt(context, manager, user.clone()).await;
+                serde_json::to_value(user).map_err(|e| JsonRpcError::internal_error(e.to_string()))
+            }
+            Err(e) => {
+                warn!(
+                    "Connection {} failed to refresh its session: {}",
+                    context.id, e
+                );
+                Err(jsonrpc_auth_error(&e))
+            }
+        },
+        Err(e) => Err(JsonRpcError::invalid_params(e.to_string())),
+    };
+
+    // Notifications get no reply
+    let id = request.id?;
+    Some(match result {
+        Ok(user) => JsonRpcResponse::success(user, Some(id)),
+        Err(error) => JsonRpcResponse::error(error, Some(id)),
+    })
+}
+
+/// Authenticate the connection's token again, keeping its user up to date
+pub(crate) async fn revalidate(
+    provider: &dyn AuthProvider,
+    context: &ConnectionContext,
+    manager: Option<&dyn ConnectionManager>,
+) -> Revalidation {
+    let Some(token) = context.token() else {
+        return Revalidation::Anonymous;
+    };
+    match provider.authenticate(token.clone()).await {
+        // A refresh that landed meanwhile already installed a newer user
+        Ok(user) if context.token().as_deref() == Some(token.as_str()) => {
+            adopt(context, manager, user).await;
+            Revalidation::Valid
+        }
+        Ok(_) => Revalidation::Valid,
+        Err(e @ AuthError::Internal(_)) => Revalidation::Unavailable(e),
+        Err(e) => Revalidation::Rejected(e),
+    }
+}
+
+/// Make `user` the connection's user, for its calls and the connection manager
+async fn adopt(
+    context: &ConnectionContext,
+    manager: Option<&dyn ConnectionManager>,
+    user: AuthenticatedUser,
+) {
+    context.set_user(user.clone()).await;
+    if let Some(manager) = manager
+        && let Err(e) = manager.set_connection_user(context.id, user).await
+    {
+        warn!("Failed to update the user of {}: {}", context.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::ChannelMessageSender;
+    use crate::queue::{OverflowPolicy, outbound_queue};
+    use ras_auth_core::{AuthFuture, AuthProviderChain, PrincipalKind};
+    use ras_jsonrpc_bidirectional_types::ConnectionId;
+    use ras_jsonrpc_types::error_codes;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    /// Accepts `admin` and `reader` tokens, and fails on `outage`
+    struct Tokens;
+
+    impl AuthProvider for Tokens {
+        fn authenticate(&self, token: String) -> AuthFuture<'_> {
+            Box::pin(async move {
+                let permissions: &[&str] = match token.as_str() {
+                    "admin" => &["admin", "read"],
+                    "reader" => &["read"],
+                    "outage" => return Err(AuthError::Internal("provider down".into())),
+                    _ => return Err(AuthError::TokenExpired),
+                };
+                Ok(AuthenticatedUser {
+                    user_id: token,
+                    permissions: permissions.iter().map(|p| p.to_string()).collect(),
+                    metadata: None,
+                    kind: PrincipalKind::User,
+                })
+            })
+        }
+    }
+
+    /// Accepts the `rak_bot` API key
+    struct Keys;
+
+    impl AuthProvider for Keys {
+        fn authenticate(&self, token: String) -> AuthFuture<'_> {
+            Box::pin(async move {
+                if token != "rak_bot" {
+                    return Err(AuthError::InvalidToken);
+                }
+                Ok(AuthenticatedUser {
+                    user_id: "bot".into(),
+                    permissions: HashSet::from(["read".to_string()]),
+                    metadata: None,
+                    kind: PrincipalKind::User,
+                })
+            })
+        }
+    }
+
+    fn ctx() -> ConnectionContext {
+        let id = ConnectionId::new();
+        let (tx, _rx) = outbound_queue(4, OverflowPolicy::default());
+        ConnectionContext::new(id, ChannelMessageSender::new(id, tx))
+    }
+
+    fn refresh_request(params: serde_json::Value) -> JsonRpcRequest {
+        JsonRpcRequest {
+            jsonrpc: "2.0".into(),
+            method: "session.refresh".into(),
+            params: Some(params),
+            id: Some(json!(1)),
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_swaps_the_user_and_token() {
+        let c = ctx();
+        let response = refresh(
+            &Tokens,
+            &c,
+            None,
+            refresh_request(json!({"token": "reader"})),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.result.unwrap()["user_id"], "reader");
+        assert_eq!(c.token().as_deref(), Some("reader"));
+        let permissions: HashSet<_> = c.get_user().await.unwrap().permissions.clone();
+        assert_eq!(permissions, HashSet::from(["read".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn rejected_refresh_keeps_the_session() {
+        let c = ctx();
+        c.set_token(Some("admin".into()));
+        c.set_user(Tokens.authenticate("admin".into()).await.unwrap())
+            .await;
+
+        let response = refresh(
+            &Tokens,
+            &c,
+            None,
+            refresh_request(json!({"token": "stale"})),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.error.unwrap().code, error_codes::TOKEN_EXPIRED);
+        let response = refresh(&Tokens, &c, None, refresh_request(json!("not params")))
+            .await
+            .unwrap();
+        assert_eq!(response.error.unwrap().code, error_codes::INVALID_PARAMS);
+
+        assert_eq!(c.token().as_deref(), Some("admin"));
+        assert_eq!(c.get_user().await.unwrap().user_id, "admin");
+    }
+
+    #[tokio::test]
+    async fn revalidation_tells_rejection_from_outage() {
+        let c = ctx();
+        assert!(matches!(
+            revalidate(&Tokens, &c, None).await,
+            Revalidation::Anonymous
+        ));
+
+        c.set_token(Some("reader".into()));
+        assert!(matches!(
+            revalidate(&Tokens, &c, None).await,
+            Revalidation::Valid
+        ));
+        assert_eq!(c.get_user().await.unwrap().user_id, "reader");
+
+        c.set_token(Some("outage".into()));
+        assert!(matches!(
+            revalidate(&Tokens, &c, None).await,
+            Revalidation::Unavailable(_)
+        ));
+
+        c.set_token(Some("stale".into()));
+        assert!(matches!(
+            revalidate(&Tokens, &c, None).await,
+            Revalidation::Rejected(AuthError::TokenExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn sessions_refresh_through_provider_chains() {
+        let chain = AuthProviderChain::new()
+            .with_prefix("rak_", Keys)
+            .with(Tokens);
+        let c = ctx();
+        let response = refresh(
+            &chain,
+            &c,
+            None,
+            refresh_request(json!({"token": "rak_bot"})),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.result.unwrap()["user_id"], "bot");
+        let response = refresh(&chain, &c, None, refresh_request(json!({"token": "admin"})))
+            .await
+            .unwrap();
+        assert_eq!(response.result.unwrap()["user_id"], "admin");
+
+        // The key provider's invalid token loses to the expiry the next one reports
+        let response = refresh(
+            &chain,
+            &c,
+            None,
+            refresh_request(json!({"token": "rak_revoked"})),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.error.unwrap().code, error_codes::TOKEN_EXPIRED);
+        assert_eq!(c.get_user().await.unwrap().user_id, "admin");
+
+        c.set_token(Some("outage".into()));
+        assert!(matches!(
+            revalidate(&chain, &c, None).await,
+            Revalidation::Unavailable(_)
+        ));
+    }
+}
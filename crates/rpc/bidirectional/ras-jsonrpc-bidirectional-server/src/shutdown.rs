@@ -0,0 +1,264 @@
+//! Graceful shutdown with connection draining
+
+use ras_jsonrpc_bidirectional_types::{
+    BidirectionalMessage, ConnectionManager, SHUTDOWN_NOTIFICATION, ServerNotification,
+    ShutdownNotice,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{Notify, watch};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// How long closing connections get to finish once the grace period is over
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Coordinates a service's shutdown with its open connections
+///
+/// Cloning shares the same state. Once [`shutdown`](Self::shutdown) starts, new
+/// upgrades are refused, clients receive a [`SHUTDOWN_NOTIFICATION`], in-flight
+/// requests get until the deadline to finish, and every connection is then closed
+/// with a "going away" close frame.
+#[derive(Clone)]
+pub struct ShutdownCoordinator {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    connections: AtomicUsize,
+    changed: Notify,
+    close: watch::Sender<bool>,
+}
+
+impl ShutdownCoordinator {
+    /// Create a coordinator for a service that is accepting connections
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                draining: AtomicBool::new(false),
+                in_flight: AtomicUsize::new(0),
+                connections: AtomicUsize::new(0),
+                changed: Notify::new(),
+                close: watch::channel(false).0,
+            }),
+        }
+    }
+
+    /// Whether shutdown has started, so new connections should be refused
+    pub fn is_draining(&self) -> bool {
+        self.inner.draining.load(Ordering::SeqCst)
+    }
+
+    /// Requests currently being handled across all connections
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Connections currently open
+    pub fn connections(&self) -> usize {
+        self.inner.connections.load(Ordering::SeqCst)
+    }
+
+    /// Drain and close every connection, waiting at most `grace` for in-flight requests
+    ///
+    /// Connections still open after the grace period are closed regardless; this
+    /// returns once they have all closed or shortly after the deadline.
+    pub async fn shutdown(&self, connection_manager: &dyn ConnectionManager, grace: Duration) {
+        let deadline = Instant::now() + grace;
+        self.inner.draining.store(true, Ordering::SeqCst);
+        info!(
+            "Shutting down, draining {} connection(s) within {:?}",
+            self.connections(),
+            grace
+        );
+
+        self.notify_clients(connection_manager, grace).await;
+
+        if tokio::time::timeout_at(
+            deadline,
+            self.wait_until(|inner| inner.in_flight.load(Ordering::SeqCst) == 0),
+        )
+        .await
+        .is_err()
+        {
+            warn!(
+                "Grace period elapsed with {} request(s) still in flight",
+                self.in_flight()
+            );
+        }
+
+        self.inner.close.send_replace(true);
+        let closed = tokio::time::timeout(
+            CLOSE_TIMEOUT,
+            self.wait_until(|inner| inner.connections.load(Ordering::SeqCst) == 0),
+        )
+        .await;
+        if closed.is_err() {
+            warn!("{} connection(s) did not close in time", self.connections());
+        }
+    }
+
+    /// Tell every connected client when the server will close its connection
+    async fn notify_clients(&self, connection_manager: &dyn ConnectionManager, grace: Duration) {
+        let notice = ShutdownNotice {
+            deadline: chrono::Duration::from_std(grace)
+                .ok()
+                .and_then(|grace| chrono::Utc::now().checked_add_signed(grace))
+                .unwrap_or(chrono::DateTime::<chrono::Utc>::MAX_UTC),
+            grace_ms: grace.as_millis().try_into().unwrap_or(u64::MAX),
+        };
+        let params = match serde_json::to_value(notice) {
+            Ok(params) => params,
+            Err(e) => {
+                warn!("Failed to serialize shutdown notice: {}", e);
+                return;
+            }
+        };
+        let connections = match connection_manager.get_all_connections().await {
+            Ok(connections) => connections,
+            Err(e) => {
+                warn!("Failed to list connections to notify of shutdown: {}", e);
+                return;
+            }
+        };
+        for connection in connections {
+            let message = BidirectionalMessage::ServerNotification(ServerNotification {
+                method: SHUTDOWN_NOTIFICATION.to_string(),
+                params: params.clone(),
+                metadata: None,
+            });
+            if let Err(e) = connection_manager
+                .send_to_connection(connection.id, message)
+                .await
+            {
+                warn!("Failed to notify {} of shutdown: {}", connection.id, e);
+            }
+        }
+    }
+
+    /// Track a request until the returned guard is dropped
+    pub(crate) fn begin_request(&self) -> ActivityGuard {
+        ActivityGuard::new(self.inner.clone(), |inner| &inner.in_flight)
+    }
+
+    /// Track a connection until the returned guard is dropped
+    pub(crate) fn begin_connection(&self) -> ActivityGuard {
+        ActivityGuard::new(self.inner.clone(), |inner| &inner.connections)
+    }
+
+    /// Resolves once connections should close
+    pub(crate) fn close_signal(&self) -> watch::Receiver<bool> {
+        self.inner.close.subscribe()
+    }
+
+    async fn wait_until(&self, done: impl Fn(&Inner) -> bool) {
+        loop {
+            let changed = self.inner.changed.notified();
+            if done(&self.inner) {
+                return;
+            }
+            changed.await;
+        }
+    }
+}
+
+impl Default for ShutdownCoordinator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for ShutdownCoordinator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShutdownCoordinator")
+            .field("draining", &self.is_draining())
+            .field("in_flight", &self.in_flight())
+            .field("connections", &self.connections())
+            .finish()
+    }
+}
+
+/// Counts one request or connection while alive
+pub(crate) struct ActivityGuard {
+    inner: Arc<Inner>,
+    counter: fn(&Inner) -> &AtomicUsize,
+}
+
+impl ActivityGuard {
+    fn new(inner: Arc<Inner>, counter: fn(&Inner) -> &AtomicUsize) -> Self {
+        counter(&inner).fetch_add(1, Ordering::SeqCst);
+        Self { inner, counter }
+    }
+}
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        (self.counter)(&self.inner).fetch_sub(1, Ordering::SeqCst);
+        self.inner.changed.notify_waiters();
+    }
+}
+
+/// Wait until `signal` says connections should close, or forever without one
+pub(crate) async fn closing(signal: &mut Option<watch::Receiver<bool>>) {
+    match signal {
+        Some(signal) => {
+            if signal.wait_for(|close| *close).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DefaultConnectionManager;
+
+    #[tokio::test]
+    async fn shutdown_waits_for_in_flight_requests_within_the_grace_period() {
+        let coordinator = ShutdownCoordinator::new();
+        let request = coordinator.begin_request();
+        let mut signal = Some(coordinator.close_signal());
+
+        let draining = coordinator.clone();
+        let shutdown = tokio::spawn(async move {
+            draining
+                .shutdown(&DefaultConnectionManager::new(), Duration::from_secs(5))
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(coordinator.is_draining());
+        assert!(!*signal.as_ref().unwrap().borrow());
+
+        // Finishing the last request lets connections close well before the deadline
+        drop(request);
+        tokio::time::timeout(Duration::from_secs(1), closing(&mut signal))
+            .await
+            .unwrap();
+        shutdown.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_connections_once_the_grace_period_ends() {
+        let coordinator = ShutdownCoordinator::new();
+        let _stuck = coordinator.begin_request();
+        let connection = coordinator.begin_connection();
+        let mut signal = Some(coordinator.close_signal());
+
+        let draining = coordinator.clone();
+        let shutdown = tokio::spawn(async move {
+            draining
+                .shutdown(&DefaultConnectionManager::new(), Duration::from_millis(50))
+                .await
+        });
+        closing(&mut signal).await;
+        drop(connection);
+        shutdown.await.unwrap();
+        assert_eq!(coordinator.connections(), 0);
+        assert_eq!(coordinator.in_flight(), 1);
+    }
+}
@@ -7,6 +7,7 @@ use axum::{
     response::Response,
 };
 use ras_auth_core::{AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_bidirectional_types::Codec;
 use tracing::{debug, error, info, warn};
 
 /// WebSocket upgrade handler with authentication support
@@ -15,35 +16,80 @@ pub struct WebSocketUpgrade {
     upgrade: AxumWebSocketUpgrade,
     /// Request headers for authentication
     headers: HeaderMap,
+    /// Codec negotiated from the offered subprotocols
+    codec: Codec,
 }
 
 impl WebSocketUpgrade {
     /// Create a new WebSocket upgrade from Axum extractor
     pub fn new(upgrade: AxumWebSocketUpgrade, headers: HeaderMap) -> Self {
-        Self { upgrade, headers }
+        Self {
+            upgrade,
+            headers,
+            codec: Codec::Json,
+        }
+    }
+
+    /// Negotiate the first binary codec the client offers that is `accepted`
+    ///
+    /// The codec's subprotocol is selected in the handshake; clients that offer
+    /// none keep exchanging JSON text.
+    pub fn codecs(mut self, accepted: &[Codec]) -> Self {
+        self.codec = Codec::negotiate(self.offered_protocols(), accepted);
+        self
+    }
+
+    /// The codec the connection will use
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Refuse messages and frames over `max_size` bytes while reading them
+    ///
+    /// Oversized messages fail before they are buffered in full, and the
+    /// connection is closed with code 1009.
+    pub fn max_message_size(mut self, max_size: usize) -> Self {
+        self.upgrade = self
+            .upgrade
+            .max_message_size(max_size)
+            .max_frame_size(max_size);
+        self
+    }
+
+    /// Subprotocols the client offered
+    ///
+    /// Clients may offer several protocols in one comma-separated header.
+    fn offered_protocols(&self) -> impl Iterator<Item = &str> {
+        self.headers
+            .get_all("sec-websocket-protocol")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+    }
+
+    /// The offered `token.{jwt_token}` subprotocol, if any
+    fn token_protocol(&self) -> Option<&str> {
+        self.offered_protocols()
+            .find(|protocol| protocol.starts_with("token."))
     }
 
     /// Extract authentication token from headers
     pub fn extract_auth_token(&self) -> Option<String> {
-        // Try Authorization header first (Bearer token)
+        // Try Authorization header first (Bearer token or API key)
         if let Some(auth_header) = self.headers.get("authorization")
             && let Ok(auth_str) = auth_header.to_str()
         {
-            if let Some(token) = auth_str.strip_prefix("Bearer ") {
+            if let Some(token) = ras_auth_core::authorization_credential(auth_str) {
                 return Some(token.to_string());
             }
-            // Also support just the token without "Bearer " prefix
+            // Also support just the token without a scheme
             return Some(auth_str.to_string());
         }
 
-        // Try custom WebSocket auth headers
-        if let Some(token_header) = self.headers.get("sec-websocket-protocol")
-            && let Ok(token_str) = token_header.to_str()
-        {
-            // Support protocols like "token.{jwt_token}"
-            if let Some(token) = token_str.strip_prefix("token.") {
-                return Some(token.to_string());
-            }
+        // Browsers cannot set headers, so they offer the token as a subprotocol
+        if let Some(protocol) = self.token_protocol() {
+            return protocol.strip_prefix("token.").map(str::to_string);
         }
 
         // Try X-Auth-Token header
@@ -56,14 +102,25 @@ impl WebSocketUpgrade {
         None
     }
 
+    /// The token to authenticate the connection with: one the client
+    /// presented as above, else the credential `auth_provider` takes from the
+    /// headers, such as a session cookie
+    pub fn credential<A: AuthProvider>(&self, auth_provider: &A) -> Option<String> {
+        self.extract_auth_token()
+            .or_else(|| auth_provider.credential(&self.headers))
+    }
+
     /// Authenticate the connection using the provided auth provider
     pub async fn authenticate<A: AuthProvider>(
         &self,
         auth_provider: &A,
     ) -> ServerResult<Option<AuthenticatedUser>> {
-        if let Some(token) = self.extract_auth_token() {
+        if let Some(token) = self.credential(auth_provider) {
             debug!("Attempting to authenticate WebSocket connection");
-            match auth_provider.authenticate(token).await {
+            match auth_provider
+                .authenticate_request(token, &self.headers)
+                .await
+            {
                 Ok(user) => {
                     info!(
                         "WebSocket connection authenticated for user: {}",
@@ -117,8 +174,18 @@ impl WebSocketUpgrade {
                     ));
                 }
 
+                // Browsers drop the connection unless an offered protocol is selected
+                let protocol = match self.codec.subprotocol() {
+                    Some(codec) => Some(codec.to_string()),
+                    None => self.token_protocol().map(str::to_string),
+                };
+                let mut upgrade = self.upgrade;
+                if let Some(protocol) = protocol {
+                    upgrade = upgrade.protocols([protocol]);
+                }
+
                 // Complete the upgrade
-                let response = self.upgrade.on_upgrade(move |socket| {
+                let response = upgrade.on_upgrade(move |socket| {
                     Box::pin(async move {
                         callback(socket, user).await;
                     })
@@ -10,29 +10,38 @@
 use std::collections::HashSet;
 use std::sync::Arc;
 
-use ras_auth_core::AuthenticatedUser;
-use ras_jsonrpc_bidirectional_server::DefaultConnectionManager;
+use ras_auth_core::{AuthenticatedUser, PrincipalKind};
 use ras_jsonrpc_bidirectional_server::connection::ChannelMessageSender;
+use ras_jsonrpc_bidirectional_server::queue::{OutboundReceiver, outbound_queue};
+use ras_jsonrpc_bidirectional_server::{DefaultConnectionManager, OverflowPolicy};
 use ras_jsonrpc_bidirectional_types::{
     BidirectionalMessage, ConnectionId, ConnectionInfo, ConnectionManager,
 };
 use ras_jsonrpc_types::JsonRpcResponse;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::oneshot;
 
 fn user(id: &str, perms: &[&str]) -> AuthenticatedUser {
     AuthenticatedUser {
         user_id: id.to_string(),
         permissions: perms.iter().map(|s| s.to_string()).collect::<HashSet<_>>(),
         metadata: None,
+        kind: PrincipalKind::User,
     }
 }
 
 /// Build a connection paired with a real receiver so we can observe sends.
-async fn join(
+async fn join(mgr: &DefaultConnectionManager) -> (ConnectionId, OutboundReceiver) {
+    join_with_queue(mgr, 16, OverflowPolicy::default()).await
+}
+
+/// Like [`join`], with the connection's outgoing queue configured.
+async fn join_with_queue(
     mgr: &DefaultConnectionManager,
-) -> (ConnectionId, mpsc::Receiver<BidirectionalMessage>) {
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (ConnectionId, OutboundReceiver) {
     let id = ConnectionId::new();
-    let (tx, rx) = mpsc::channel(16);
+    let (tx, rx) = outbound_queue(capacity, policy);
     let sender = ChannelMessageSender::new(id, tx);
     let info = ConnectionInfo::new(id);
     mgr.add_connection_with_sender_direct(info, sender)
@@ -68,7 +77,7 @@ async fn add_remove_round_trip_and_inspect() {
 async fn add_connection_with_sender_box_downcasts() {
     let mgr = DefaultConnectionManager::new();
     let id = ConnectionId::new();
-    let (tx, _rx) = mpsc::channel(1);
+    let (tx, _rx) = outbound_queue(1, OverflowPolicy::default());
     let sender = ChannelMessageSender::new(id, tx);
     // Round-trip through Box<dyn Any> as the trait method requires.
     let boxed: Box<dyn std::any::Any + Send + Sync> = Box::new(sender);
@@ -154,9 +163,9 @@ async fn user_set_clear_filter_paths_for_broadcasts() {
         .await
         .unwrap();
     assert_eq!(n, 2);
-    assert!(auth_rx.try_recv().is_ok());
-    assert!(admin_rx.try_recv().is_ok());
-    assert!(anon_rx.try_recv().is_err());
+    assert!(auth_rx.try_recv().is_some());
+    assert!(admin_rx.try_recv().is_some());
+    assert!(anon_rx.try_recv().is_none());
 
     // broadcast_to_permission only reaches the admin.
     let n = mgr
@@ -164,7 +173,7 @@ async fn user_set_clear_filter_paths_for_broadcasts() {
         .await
         .unwrap();
     assert_eq!(n, 1);
-    assert!(admin_rx.try_recv().is_ok());
+    assert!(admin_rx.try_recv().is_some());
 
     // clear_connection_user flips the auth flag back.
     mgr.clear_connection_user(auth_id).await.unwrap();
@@ -195,7 +204,7 @@ async fn broadcast_to_topic_counts_recipients_and_skips_empty() {
         .await
         .unwrap();
     assert_eq!(n, 1);
-    assert!(ra.try_recv().is_ok());
+    assert!(ra.try_recv().is_some());
 
     // Topic with no subscribers reports zero.
     let n = mgr
@@ -205,6 +214,41 @@ async fn broadcast_to_topic_counts_recipients_and_skips_empty() {
     assert_eq!(n, 0);
 }
 
+#[tokio::test]
+async fn broadcast_to_topic_skips_full_queues_without_blocking() {
+    let mgr = DefaultConnectionManager::new();
+    let (fast, mut fast_rx) = join(&mgr).await;
+    let (stalled, _stalled_rx) = join_with_queue(&mgr, 1, OverflowPolicy::DropNewest).await;
+    let (gone, gone_rx) = join(&mgr).await;
+    for id in [fast, stalled, gone] {
+        mgr.add_subscription(id, "t".into()).await.unwrap();
+    }
+    drop(gone_rx);
+
+    let n = mgr
+        .broadcast_to_topic("t", BidirectionalMessage::Ping)
+        .await
+        .unwrap();
+    assert_eq!(n, 2);
+    assert_eq!(mgr.skipped_broadcasts(), 0);
+
+    // The stalled connection's queue is now full: it is skipped and counted,
+    // but stays subscribed, while the closed connection is unsubscribed.
+    let n = mgr
+        .broadcast_to_topic("t", BidirectionalMessage::Pong)
+        .await
+        .unwrap();
+    assert_eq!(n, 1);
+    assert_eq!(mgr.skipped_broadcasts(), 1);
+    assert_eq!(mgr.get_topic_connections("t"), [fast, stalled]);
+    assert_eq!(fast_rx.stats().queued, 2);
+
+    let stats = mgr.queue_stats(stalled).unwrap();
+    assert_eq!((stats.queued, stats.capacity, stats.dropped), (1, 1, 1));
+    assert_eq!(mgr.queue_depths().len(), 3);
+    assert!(fast_rx.try_recv().is_some());
+}
+
 #[tokio::test]
 async fn pending_request_lifecycle() {
     let mgr = DefaultConnectionManager::new();
@@ -0,0 +1,146 @@
+//! Connection managers on separate nodes sharing one registry.
+//!
+//! Two `DefaultConnectionManager`s joined through a `MemoryBus` stand in for two
+//! server processes behind a load balancer: each holds its own connections, and
+//! sends and broadcasts made on either must reach the other's connections.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ras_auth_core::{AuthenticatedUser, PrincipalKind};
+use ras_jsonrpc_bidirectional_server::connection::ChannelMessageSender;
+use ras_jsonrpc_bidirectional_server::queue::{OutboundReceiver, outbound_queue};
+use ras_jsonrpc_bidirectional_server::{
+    DefaultConnectionManager, MemoryBus, NodeId, OverflowPolicy,
+};
+use ras_jsonrpc_bidirectional_types::{
+    BidirectionalMessage, ConnectionId, ConnectionInfo, ConnectionManager, ServerNotification,
+};
+use serde_json::json;
+
+fn note(method: &str) -> BidirectionalMessage {
+    BidirectionalMessage::ServerNotification(ServerNotification {
+        method: method.to_string(),
+        params: json!({}),
+        metadata: None,
+    })
+}
+
+async fn node(bus: &MemoryBus, name: &str) -> Arc<DefaultConnectionManager> {
+    DefaultConnectionManager::with_registry(Arc::new(bus.node(name)))
+        .await
+        .unwrap()
+}
+
+async fn join(
+    mgr: &DefaultConnectionManager,
+    perms: Option<&[&str]>,
+) -> (ConnectionId, OutboundReceiver) {
+    let id = ConnectionId::new();
+    let (tx, rx) = outbound_queue(16, OverflowPolicy::default());
+    let mut info = ConnectionInfo::new(id);
+    if let Some(perms) = perms {
+        info.set_user(AuthenticatedUser {
+            user_id: id.to_string(),
+            permissions: perms.iter().map(|p| p.to_string()).collect::<HashSet<_>>(),
+            metadata: None,
+            kind: PrincipalKind::User,
+        });
+    }
+    mgr.add_connection_with_sender_direct(info, ChannelMessageSender::new(id, tx))
+        .await
+        .unwrap();
+    (id, rx)
+}
+
+async fn method_of(rx: &mut OutboundReceiver) -> String {
+    let message = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+        .await
+        .expect("nothing delivered")
+        .unwrap();
+    match message {
+        BidirectionalMessage::ServerNotification(n) => n.method,
+        other => panic!("unexpected message: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn sends_reach_connections_on_other_nodes() {
+    let bus = MemoryBus::new();
+    let a = node(&bus, "a").await;
+    let b = node(&bus, "b").await;
+    let (held_by_b, mut rx) = join(&b, None).await;
+    assert_eq!(bus.owner(held_by_b), Some(NodeId::new("b")));
+
+    a.send_to_connection(held_by_b, note("hello"))
+        .await
+        .unwrap();
+    assert_eq!(method_of(&mut rx).await, "hello");
+
+    b.remove_connection(held_by_b).await.unwrap();
+    assert_eq!(bus.owner(held_by_b), None);
+    // Unknown everywhere: logged and ignored, like a local miss
+    a.send_to_connection(held_by_b, note("gone")).await.unwrap();
+}
+
+#[tokio::test]
+async fn topic_broadcasts_reach_every_nodes_subscribers() {
+    let bus = MemoryBus::new();
+    let a = node(&bus, "a").await;
+    let b = node(&bus, "b").await;
+    let (on_a, mut a_rx) = join(&a, None).await;
+    let (on_b, mut b_rx) = join(&b, None).await;
+    let (_idle, mut idle_rx) = join(&b, None).await;
+    a.add_subscription(on_a, "room".into()).await.unwrap();
+    b.add_subscription(on_b, "room".into()).await.unwrap();
+
+    // The count covers this node's deliveries only
+    assert_eq!(a.broadcast_to_topic("room", note("msg")).await.unwrap(), 1);
+    assert_eq!(method_of(&mut a_rx).await, "msg");
+    assert_eq!(method_of(&mut b_rx).await, "msg");
+
+    // Once b's last subscriber leaves, b stops receiving the topic
+    b.remove_subscription(on_b, "room").await.unwrap();
+    a.broadcast_to_topic("room", note("later")).await.unwrap();
+    assert_eq!(method_of(&mut a_rx).await, "later");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(b_rx.try_recv().is_none());
+    assert!(idle_rx.try_recv().is_none());
+}
+
+#[tokio::test]
+async fn permission_broadcasts_filter_on_the_receiving_node() {
+    let bus = MemoryBus::new();
+    let a = node(&bus, "a").await;
+    let b = node(&bus, "b").await;
+    let (_admin, mut admin_rx) = join(&b, Some(&["admin"])).await;
+    let (_user, mut user_rx) = join(&b, Some(&["user"])).await;
+    let (_anonymous, mut anonymous_rx) = join(&b, None).await;
+
+    a.broadcast_to_permission("admin", note("admins"))
+        .await
+        .unwrap();
+    a.broadcast_to_authenticated(note("everyone"))
+        .await
+        .unwrap();
+
+    assert_eq!(method_of(&mut admin_rx).await, "admins");
+    assert_eq!(method_of(&mut admin_rx).await, "everyone");
+    assert_eq!(method_of(&mut user_rx).await, "everyone");
+    assert!(anonymous_rx.try_recv().is_none());
+}
+
+#[tokio::test]
+async fn dead_nodes_are_cleaned_up_by_survivors() {
+    let bus = MemoryBus::new();
+    let a = node(&bus, "a").await;
+    let b = node(&bus, "b").await;
+    let (on_b, _rx) = join(&b, None).await;
+    join(&b, None).await;
+
+    bus.kill(b.registry().node_id());
+    assert_eq!(a.cleanup_stale_connections().await.unwrap(), 2);
+    assert_eq!(bus.owner(on_b), None);
+    assert_eq!(a.cleanup_stale_connections().await.unwrap(), 0);
+}
@@ -0,0 +1,154 @@
+//! Why a connection closed
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why a connection closed, as told to the application on both ends
+///
+/// The server passes it to its disconnect hooks, sends it to the client in the
+/// `ConnectionClosed` message, and, where a close frame can still be sent, as
+/// the frame's code and reason.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum CloseReason {
+    /// The client closed the connection
+    ClientClosed,
+    /// The connection dropped without a close handshake, such as on a network error
+    ConnectionLost,
+    /// The client stopped answering keepalive pings
+    KeepaliveTimeout,
+    /// The session's token was rejected when it was re-validated
+    SessionExpired,
+    /// The session the connection authenticated with was ended, such as by a logout
+    SessionRevoked,
+    /// The server is shutting down
+    ServerShutdown,
+    /// The client fell too far behind on its messages and was shed
+    Overloaded,
+    /// The client sent a message over the size limit
+    MessageTooBig,
+    /// The client kept exceeding its rate limits
+    RateLimited,
+    /// The client kept sending messages that break the protocol
+    ProtocolViolation(String),
+    /// The server failed while serving the connection
+    ServerError(String),
+}
+
+impl CloseReason {
+    /// WebSocket close code sent for this reason; `None` when no close frame is
+    /// sent, because the client already closed or the connection is gone
+    pub fn close_code(&self) -> Option<u16> {
+        match self {
+            Self::ClientClosed | Self::ConnectionLost => None,
+            Self::KeepaliveTimeout | Self::ServerShutdown => Some(1001),
+            Self::SessionExpired
+            | Self::SessionRevoked
+            | Self::RateLimited
+            | Self::ProtocolViolation(_) => Some(1008),
+            Self::MessageTooBig => Some(1009),
+            Self::ServerError(_) => Some(1011),
+            Self::Overloaded => Some(1013),
+        }
+    }
+
+    /// Short, fixed name of the reason, such as `session_expired`, for metric labels
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::ClientClosed => "client_closed",
+            Self::ConnectionLost => "connection_lost",
+            Self::KeepaliveTimeout => "keepalive_timeout",
+            Self::SessionExpired => "session_expired",
+            Self::SessionRevoked => "session_revoked",
+            Self::ServerShutdown => "server_shutdown",
+            Self::Overloaded => "overloaded",
+            Self::MessageTooBig => "message_too_big",
+            Self::RateLimited => "rate_limited",
+            Self::ProtocolViolation(_) => "protocol_violation",
+            Self::ServerError(_) => "server_error",
+        }
+    }
+
+    /// Read the reason back from a close frame the server sent, if it is one of ours
+    pub fn from_close_frame(code: u16, reason: &str) -> Option<Self> {
+        let fixed = [
+            Self::KeepaliveTimeout,
+            Self::SessionExpired,
+            Self::SessionRevoked,
+            Self::ServerShutdown,
+            Self::Overloaded,
+            Self::MessageTooBig,
+            Self::RateLimited,
+        ];
+        if let Some(known) = fixed
+            .into_iter()
+            .find(|known| known.close_code() == Some(code) && known.to_string() == reason)
+        {
+            return Some(known);
+        }
+        match code {
+            1001 => Some(Self::ServerShutdown),
+            1008 => Some(Self::ProtocolViolation(reason.to_string())),
+            1009 => Some(Self::MessageTooBig),
+            1011 => Some(Self::ServerError(reason.to_string())),
+            1013 => Some(Self::Overloaded),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ClientClosed => f.write_str("Client closed the connection"),
+            Self::ConnectionLost => f.write_str("Connection lost"),
+            Self::KeepaliveTimeout => f.write_str("Keepalive timeout"),
+            Self::SessionExpired => f.write_str("Session expired"),
+            Self::SessionRevoked => f.write_str("Session revoked"),
+            Self::ServerShutdown => f.write_str("Server shutting down"),
+            Self::Overloaded => f.write_str("Outgoing queue full"),
+            Self::MessageTooBig => f.write_str("Message too big"),
+            Self::RateLimited => f.write_str("Rate limit exceeded"),
+            Self::ProtocolViolation(detail) | Self::ServerError(detail) => f.write_str(detail),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn close_frames_read_back_as_the_reason_sent() {
+        let reasons = [
+            CloseReason::KeepaliveTimeout,
+            CloseReason::SessionExpired,
+            CloseReason::SessionRevoked,
+            CloseReason::ServerShutdown,
+            CloseReason::Overloaded,
+            CloseReason::MessageTooBig,
+            CloseReason::RateLimited,
+            CloseReason::ProtocolViolation("Too many malformed messages".into()),
+            CloseReason::ServerError("Handler crashed".into()),
+        ];
+        for reason in reasons {
+            let code = reason.close_code().unwrap();
+            assert_eq!(
+                CloseReason::from_close_frame(code, &reason.to_string()),
+                Some(reason)
+            );
+        }
+        assert_eq!(CloseReason::from_close_frame(1000, "Bye"), None);
+    }
+
+    #[test]
+    fn serializes_with_a_kind_and_detail() {
+        let json = serde_json::to_value(CloseReason::SessionExpired).unwrap();
+        assert_eq!(json, serde_json::json!({ "kind": "session_expired" }));
+        let json = serde_json::to_value(CloseReason::ServerError("boom".into())).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "kind": "server_error", "detail": "boom" })
+        );
+    }
+}
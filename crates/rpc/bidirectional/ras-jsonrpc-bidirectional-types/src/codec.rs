@@ -0,0 +1,205 @@
+//! Wire codecs for bidirectional messages
+//!
+//! Messages travel as JSON text frames unless the client offers a binary codec
+//! as a WebSocket subprotocol and the server selects it, after which both sides
+//! send binary frames in that codec. Text frames are always read as JSON, so a
+//! peer that never negotiated keeps working.
+
+use crate::{BidirectionalError, Result};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Encoding of messages on a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Codec {
+    /// JSON text frames, used when nothing else was negotiated
+    #[default]
+    Json,
+    /// MessagePack binary frames, negotiated as `ras.msgpack`
+    MessagePack,
+    /// CBOR binary frames, negotiated as `ras.cbor`
+    Cbor,
+}
+
+impl Codec {
+    /// Every binary codec, all of which servers accept by default
+    pub const BINARY: [Codec; 2] = [Codec::MessagePack, Codec::Cbor];
+
+    /// The subprotocol a client offers to negotiate this codec
+    pub fn subprotocol(self) -> Option<&'static str> {
+        match self {
+            Self::Json => None,
+            Self::MessagePack => Some("ras.msgpack"),
+            Self::Cbor => Some("ras.cbor"),
+        }
+    }
+
+    /// The codec a selected subprotocol stands for, if any
+    pub fn from_subprotocol(protocol: &str) -> Option<Self> {
+        Self::BINARY
+            .into_iter()
+            .find(|codec| codec.subprotocol() == Some(protocol.trim()))
+    }
+
+    /// Pick the first of the client's `offered` subprotocols that names an
+    /// `accepted` codec, falling back to JSON
+    pub fn negotiate<'a>(offered: impl IntoIterator<Item = &'a str>, accepted: &[Codec]) -> Self {
+        offered
+            .into_iter()
+            .filter_map(Self::from_subprotocol)
+            .find(|codec| accepted.contains(codec))
+            .unwrap_or_default()
+    }
+
+    /// Whether messages in this codec are sent as binary frames
+    pub fn is_binary(self) -> bool {
+        self != Self::Json
+    }
+
+    /// Encode `value` as a frame in this codec
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Frame> {
+        match self {
+            Self::Json => Ok(Frame::Text(serde_json::to_string(value)?)),
+            // Named fields, so internally tagged enums and `serde_json::Value`
+            // payloads survive the trip
+            Self::MessagePack => rmp_serde::to_vec_named(value)
+                .map(Frame::Binary)
+                .map_err(|e| BidirectionalError::CodecError(e.to_string())),
+            Self::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|e| BidirectionalError::CodecError(e.to_string()))?;
+                Ok(Frame::Binary(bytes))
+            }
+        }
+    }
+
+    /// Decode a frame's payload in this codec
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T> {
+        match self {
+            Self::Json => Ok(serde_json::from_slice(bytes)?),
+            Self::MessagePack => rmp_serde::from_slice(bytes)
+                .map_err(|e| BidirectionalError::CodecError(e.to_string())),
+            Self::Cbor => ciborium::from_reader(bytes)
+                .map_err(|e| BidirectionalError::CodecError(e.to_string())),
+        }
+    }
+}
+
+/// An encoded message, ready to be sent as a WebSocket frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Frame {
+    /// Size of the payload in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Text(text) => text.len(),
+            Self::Binary(bytes) => bytes.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BidirectionalMessage, CloseReason, ConnectionId, ServerNotification};
+    use ras_jsonrpc_types::{JsonRpcError, JsonRpcRequest, JsonRpcResponse};
+    use serde_json::json;
+
+    fn messages() -> Vec<BidirectionalMessage> {
+        vec![
+            BidirectionalMessage::Request(JsonRpcRequest::new(
+                "embed".to_string(),
+                Some(json!({"vector": [0.25, -1.5, 3.0], "chunk": "abc", "n": null})),
+                Some(json!(7)),
+            )),
+            BidirectionalMessage::Response(JsonRpcResponse::success(
+                json!([1, 2, 3]),
+                Some(json!("id")),
+            )),
+            BidirectionalMessage::Response(JsonRpcResponse::error(
+                JsonRpcError::parse_error(),
+                None,
+            )),
+            BidirectionalMessage::ServerNotification(ServerNotification {
+                method: "progress".to_string(),
+                params: json!({"done": 3, "of": 10}),
+                metadata: Some(json!({"topic": "jobs"})),
+            }),
+            BidirectionalMessage::Subscribe {
+                topics: vec!["a".to_string(), "b".to_string()],
+            },
+            BidirectionalMessage::ConnectionEstablished {
+                connection_id: ConnectionId::new(),
+            },
+            BidirectionalMessage::ConnectionClosed {
+                connection_id: ConnectionId::new(),
+                reason: None,
+                close_reason: None,
+            },
+            BidirectionalMessage::ConnectionClosed {
+                connection_id: ConnectionId::new(),
+                reason: Some("Session expired".to_string()),
+                close_reason: Some(CloseReason::SessionExpired),
+            },
+            BidirectionalMessage::Ping,
+        ]
+    }
+
+    #[test]
+    fn every_codec_round_trips_every_message() {
+        for codec in [Codec::Json, Codec::MessagePack, Codec::Cbor] {
+            for message in messages() {
+                let frame = codec.encode(&message).unwrap();
+                assert_eq!(matches!(frame, Frame::Binary(_)), codec.is_binary());
+                let bytes = match &frame {
+                    Frame::Text(text) => text.as_bytes(),
+                    Frame::Binary(bytes) => bytes.as_slice(),
+                };
+                let decoded: BidirectionalMessage = codec.decode(bytes).unwrap();
+                // Compare through JSON, since the messages are not `PartialEq`
+                assert_eq!(
+                    serde_json::to_value(&decoded).unwrap(),
+                    serde_json::to_value(&message).unwrap(),
+                    "{codec:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn binary_codecs_reject_garbage() {
+        for codec in Codec::BINARY {
+            assert!(matches!(
+                codec.decode::<BidirectionalMessage>(&[0xc1, 0xff, 0x00]),
+                Err(BidirectionalError::CodecError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn negotiation_follows_the_clients_order_among_accepted_codecs() {
+        let all = Codec::BINARY;
+        assert_eq!(
+            Codec::negotiate(["token.abc", "ras.cbor", "ras.msgpack"], &all),
+            Codec::Cbor
+        );
+        assert_eq!(
+            Codec::negotiate(["ras.cbor", "ras.msgpack"], &[Codec::MessagePack]),
+            Codec::MessagePack
+        );
+        assert_eq!(Codec::negotiate(["ras.cbor"], &[]), Codec::Json);
+        assert_eq!(Codec::negotiate([], &all), Codec::Json);
+        assert_eq!(
+            Codec::from_subprotocol(" ras.msgpack"),
+            Some(Codec::MessagePack)
+        );
+    }
+}
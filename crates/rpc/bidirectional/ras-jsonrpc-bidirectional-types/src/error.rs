@@ -41,6 +41,10 @@ pub enum BidirectionalError {
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
+    /// A binary frame could not be encoded or decoded with the negotiated codec
+    #[error("Codec error: {0}")]
+    CodecError(String),
+
     /// WebSocket error
     #[error("WebSocket error: {0}")]
     WebSocketError(String),
@@ -12,10 +12,14 @@ use std::fmt;
 use std::sync::Arc;
 use uuid::Uuid;
 
+pub mod close;
+pub mod codec;
 pub mod error;
 pub mod manager;
 pub mod sender;
 
+pub use close::CloseReason;
+pub use codec::{Codec, Frame};
 pub use error::BidirectionalError;
 pub use manager::ConnectionManager;
 pub use sender::{MessageSender, NoOpMessageSender};
@@ -79,6 +83,9 @@ pub enum BidirectionalMessage {
     ConnectionClosed {
         connection_id: ConnectionId,
         reason: Option<String>,
+        /// Why the connection closed; absent from servers predating close reasons
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        close_reason: Option<CloseReason>,
     },
     /// Heartbeat/keepalive
     Ping,
@@ -105,6 +112,190 @@ pub struct ServerNotification {
     pub metadata: Option<serde_json::Value>,
 }
 
+/// Notification method sent to every client when the server starts shutting down
+pub const SHUTDOWN_NOTIFICATION: &str = "server.shutdown";
+
+/// Parameters of the [`SHUTDOWN_NOTIFICATION`]
+///
+/// Connections still open at `deadline` are closed by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShutdownNotice {
+    /// When the server closes remaining connections
+    pub deadline: chrono::DateTime<chrono::Utc>,
+    /// Grace period the server allowed, in milliseconds
+    pub grace_ms: u64,
+}
+
+/// Built-in request method that re-authenticates a connection with a new token
+///
+/// Params are a [`SessionRefresh`]; the result is the [`AuthenticatedUser`] the
+/// token now identifies.
+pub const SESSION_REFRESH_METHOD: &str = "session.refresh";
+
+/// Parameters of a [`SESSION_REFRESH_METHOD`] request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRefresh {
+    /// Bearer token replacing the one the connection authenticated with
+    pub token: String,
+}
+
+/// Notification method warning a client that its token no longer validates
+///
+/// Unless the session is refreshed before the next re-validation, the server
+/// closes the connection.
+pub const SESSION_EXPIRING_NOTIFICATION: &str = "session.expiring";
+
+/// Parameters of the [`SESSION_EXPIRING_NOTIFICATION`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionExpiring {
+    /// When the server closes the connection unless the session is refreshed
+    pub deadline: chrono::DateTime<chrono::Utc>,
+    /// Why the token was rejected
+    pub reason: String,
+}
+
+/// Built-in request method that resubscribes to a topic and replays the
+/// broadcasts a client missed
+///
+/// Params are a [`TopicResume`]; the result is a [`TopicResumed`]. Missed
+/// broadcasts are sent ahead of the response, in sequence order.
+pub const TOPIC_RESUME_METHOD: &str = "topic.resume";
+
+/// Parameters of a [`TOPIC_RESUME_METHOD`] request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopicResume {
+    /// Topic to resume
+    pub topic: String,
+    /// Sequence number of the last broadcast the client received
+    pub last_seq: u64,
+}
+
+/// Result of a [`TOPIC_RESUME_METHOD`] request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopicResumed {
+    /// How many missed broadcasts were replayed
+    pub replayed: usize,
+    /// Whether broadcasts after `last_seq` were missed but are no longer kept,
+    /// so the client should reload the topic's state
+    pub gap: bool,
+    /// Sequence number of the topic's latest broadcast, if it has any
+    pub latest_seq: Option<u64>,
+}
+
+/// Built-in notification abandoning a request the client stopped waiting for,
+/// such as one that timed out
+///
+/// Params are a [`CancelRequest`]. If the request's handler is still running,
+/// the server drops it and sends no response.
+pub const CANCEL_REQUEST_METHOD: &str = "$/cancel";
+
+/// Parameters of a [`CANCEL_REQUEST_METHOD`] notification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelRequest {
+    /// Id of the request to cancel
+    pub id: serde_json::Value,
+}
+
+/// Built-in request method that marks a connection present under a key, such as
+/// a document or task being viewed
+///
+/// Params are a [`PresenceJoin`]; the result is the connection's
+/// [`PresenceEntry`]. The other members of the key are sent a
+/// [`PRESENCE_JOINED_NOTIFICATION`] unless the user was already present through
+/// another connection.
+pub const PRESENCE_JOIN_METHOD: &str = "presence.join";
+
+/// Built-in request method that withdraws a connection's presence under a key
+///
+/// Params are a [`PresenceKey`]; the result is whether the connection was
+/// present. Connections leave every key when they close.
+pub const PRESENCE_LEAVE_METHOD: &str = "presence.leave";
+
+/// Built-in request method listing who is present under a key
+///
+/// Params are a [`PresenceKey`]; the result is a `Vec<PresenceEntry>`, one per
+/// connection.
+pub const PRESENCE_LIST_METHOD: &str = "presence.list";
+
+/// Notification method telling the members of a key that a user became present
+///
+/// Params are a [`PresenceEvent`].
+pub const PRESENCE_JOINED_NOTIFICATION: &str = "presence.joined";
+
+/// Notification method telling the members of a key that a user is no longer
+/// present on any connection
+///
+/// Params are a [`PresenceEvent`].
+pub const PRESENCE_LEFT_NOTIFICATION: &str = "presence.left";
+
+/// Parameters of a [`PRESENCE_JOIN_METHOD`] request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceJoin {
+    /// Key to become present under
+    pub key: String,
+    /// Shown to the other members, such as a cursor color or status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// Parameters of [`PRESENCE_LEAVE_METHOD`] and [`PRESENCE_LIST_METHOD`] requests
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceKey {
+    /// Key the request is about
+    pub key: String,
+}
+
+/// One connection present under a key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceEntry {
+    /// User of the connection, if it is authenticated
+    pub user_id: Option<String>,
+    /// The present connection
+    pub connection_id: ConnectionId,
+    /// When the connection joined the key
+    pub joined_at: chrono::DateTime<chrono::Utc>,
+    /// Metadata the connection joined with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl PresenceEntry {
+    /// Whether both entries stand for the same member: the same user, or the
+    /// same connection for anonymous ones
+    pub fn same_member(&self, other: &PresenceEntry) -> bool {
+        match (&self.user_id, &other.user_id) {
+            (Some(user), Some(other_user)) => user == other_user,
+            _ => self.connection_id == other.connection_id,
+        }
+    }
+}
+
+/// Parameters of [`PRESENCE_JOINED_NOTIFICATION`] and [`PRESENCE_LEFT_NOTIFICATION`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceEvent {
+    /// Key whose members changed
+    pub key: String,
+    /// Entry of the connection that joined or left
+    pub entry: PresenceEntry,
+}
+
+/// Built-in request method that registers a connection's filter for a
+/// notification, so the server only sends it the notifications the filter matches
+///
+/// Params are a [`FilterSet`]; the result is `null`. Filters are plain JSON so
+/// that every node sharing a registry can read them.
+pub const FILTER_SET_METHOD: &str = "filter.set";
+
+/// Parameters of a [`FILTER_SET_METHOD`] request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterSet {
+    /// Notification method the filter applies to
+    pub notification: String,
+    /// The filter, replacing the connection's previous one; `None` removes it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<serde_json::Value>,
+}
+
 /// Broadcast message from server to multiple clients
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BroadcastMessage {
@@ -116,6 +307,9 @@ pub struct BroadcastMessage {
     pub params: serde_json::Value,
     /// Optional metadata
     pub metadata: Option<serde_json::Value>,
+    /// Position of the broadcast in its topic, when the server keeps topic history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
 }
 
 /// Information about a connected client
@@ -131,17 +325,21 @@ pub struct ConnectionInfo {
     pub metadata: serde_json::Value,
     /// When the connection was established
     pub connected_at: chrono::DateTime<chrono::Utc>,
+    /// When a message, including a pong, last arrived on the connection
+    pub last_seen: chrono::DateTime<chrono::Utc>,
 }
 
 impl ConnectionInfo {
     /// Create a new connection info
     pub fn new(id: ConnectionId) -> Self {
+        let now = chrono::Utc::now();
         Self {
             id,
             user: None,
             subscriptions: HashSet::new(),
             metadata: serde_json::Value::Object(serde_json::Map::new()),
-            connected_at: chrono::Utc::now(),
+            connected_at: now,
+            last_seen: now,
         }
     }
 
@@ -1,6 +1,9 @@
 //! Connection manager trait for bidirectional JSON-RPC
 
-use crate::{BidirectionalMessage, ConnectionId, ConnectionInfo, Result};
+use crate::{
+    BidirectionalError, BidirectionalMessage, ConnectionId, ConnectionInfo, PresenceEntry, Result,
+    TopicResumed,
+};
 use async_trait::async_trait;
 use ras_auth_core::AuthenticatedUser;
 
@@ -69,6 +72,99 @@ pub trait ConnectionManager: Send + Sync {
         message: BidirectionalMessage,
     ) -> Result<usize>;
 
+    /// Subscribe a connection to a topic again and replay the broadcasts it
+    /// missed after `last_seq`
+    ///
+    /// Managers that keep no topic history only subscribe the connection and
+    /// report a gap.
+    async fn resume_topic(
+        &self,
+        id: ConnectionId,
+        topic: &str,
+        last_seq: u64,
+    ) -> Result<TopicResumed> {
+        let _ = last_seq;
+        self.add_subscription(id, topic.to_string()).await?;
+        Ok(TopicResumed {
+            replayed: 0,
+            gap: true,
+            latest_seq: None,
+        })
+    }
+
+    /// Mark a connection present under `key`, telling the key's other members
+    /// when its user was not present before
+    ///
+    /// Joining again replaces the connection's metadata. Managers that keep no
+    /// presence refuse to join.
+    async fn join_presence(
+        &self,
+        id: ConnectionId,
+        key: &str,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<PresenceEntry> {
+        let _ = (id, metadata);
+        Err(BidirectionalError::Custom(format!(
+            "Presence is not supported, cannot join {}",
+            key
+        )))
+    }
+
+    /// Withdraw a connection's presence under `key`, telling the key's other
+    /// members once its user is present on no other connection
+    ///
+    /// Returns whether the connection was present.
+    async fn leave_presence(&self, id: ConnectionId, key: &str) -> Result<bool> {
+        let _ = (id, key);
+        Ok(false)
+    }
+
+    /// Every connection present under `key`
+    async fn list_presence(&self, key: &str) -> Result<Vec<PresenceEntry>> {
+        let _ = key;
+        Ok(Vec::new())
+    }
+
+    /// Register a connection's filter for `notification`, replacing its previous
+    /// one, or remove it with `None`
+    ///
+    /// Managers that keep no filters refuse to register one.
+    async fn set_notification_filter(
+        &self,
+        id: ConnectionId,
+        notification: &str,
+        filter: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let _ = id;
+        match filter {
+            Some(_) => Err(BidirectionalError::Custom(format!(
+                "Notification filters are not supported, cannot filter {}",
+                notification
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// The filters connections registered for `notification`
+    async fn notification_filters(
+        &self,
+        notification: &str,
+    ) -> Result<Vec<(ConnectionId, serde_json::Value)>> {
+        let _ = notification;
+        Ok(Vec::new())
+    }
+
+    /// Record that a message arrived on a connection, updating its `last_seen`
+    async fn mark_seen(&self, id: ConnectionId) -> Result<()> {
+        let _ = id;
+        Ok(())
+    }
+
+    /// When a message last arrived on a connection, for inspecting liveness
+    async fn last_seen(&self, id: ConnectionId) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        Ok(self.get_connection(id).await?.map(|info| info.last_seen))
+    }
+
     /// Check if a connection exists
     async fn connection_exists(&self, id: ConnectionId) -> Result<bool> {
         Ok(self.get_connection(id).await?.is_some())
@@ -143,6 +239,7 @@ pub trait ConnectionManagerExt: ConnectionManager {
             method: method.to_string(),
             params,
             metadata: None,
+            seq: None,
         });
         self.broadcast_to_topic(topic, message).await
     }
@@ -189,7 +286,7 @@ impl<T: ConnectionManager> ConnectionManagerExt for T {}
 mod tests {
     use super::*;
     use crate::{BidirectionalError, BroadcastMessage, ConnectionId, ConnectionInfo};
-    use ras_auth_core::AuthenticatedUser;
+    use ras_auth_core::{AuthenticatedUser, PrincipalKind};
     use ras_jsonrpc_types::JsonRpcResponse;
     use std::collections::HashMap;
     use std::collections::HashSet;
@@ -362,6 +459,7 @@ mod tests {
             user_id: id.to_string(),
             permissions: perms.iter().map(|s| s.to_string()).collect(),
             metadata: None,
+            kind: PrincipalKind::User,
         }
     }
 
@@ -2,9 +2,13 @@
 
 use crate::{BidirectionalError, BidirectionalMessage, ConnectionId, Result};
 use async_trait::async_trait;
+#[cfg(not(target_arch = "wasm32"))]
 use futures::sink::SinkExt;
+#[cfg(not(target_arch = "wasm32"))]
 use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
 use tokio::sync::Mutex;
+#[cfg(not(target_arch = "wasm32"))]
 use tokio_tungstenite::tungstenite::Message as WsMessage;
 
 /// Trait for sending messages over WebSocket connections
@@ -24,6 +28,7 @@ pub trait MessageSender: Send + Sync {
 }
 
 /// A message sender implementation using tokio-tungstenite
+#[cfg(not(target_arch = "wasm32"))]
 pub struct WebSocketMessageSender<S>
 where
     S: SinkExt<WsMessage> + Send + Unpin,
@@ -33,6 +38,7 @@ where
     is_closed: Arc<Mutex<bool>>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl<S> WebSocketMessageSender<S>
 where
     S: SinkExt<WsMessage> + Send + Unpin,
@@ -48,6 +54,7 @@ where
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 #[async_trait]
 impl<S> MessageSender for WebSocketMessageSender<S>
 where
@@ -0,0 +1,95 @@
+//! JSON-RPC errors for failed authentication and authorization.
+
+use ras_auth_core::{AccessDenied, AuthError};
+use ras_jsonrpc_types::{JsonRpcError, error_codes};
+
+/// The JSON-RPC error telling a client why its request was refused with
+/// `error`.
+///
+/// Its `data` carries the error's [`code`](AuthError::code), read back by
+/// clients with [`JsonRpcError::auth_code`], along with its details and how
+/// long to wait before retrying, if it says.
+pub fn jsonrpc_auth_error(error: &AuthError) -> JsonRpcError {
+    match error {
+        AuthError::TokenExpired => JsonRpcError::token_expired(),
+        AuthError::TokenNotYetValid => JsonRpcError::token_not_yet_valid(),
+        AuthError::AuthenticationRequired => JsonRpcError::authentication_required(),
+        AuthError::InsufficientPermissions { required, has } => {
+            JsonRpcError::insufficient_permissions(required.clone(), has.clone())
+        }
+        AuthError::AccountLocked { retry_after } => JsonRpcError::account_locked(*retry_after),
+        AuthError::RateLimited { retry_after } => JsonRpcError::rate_limited(*retry_after),
+        AuthError::InvalidToken => JsonRpcError::new(
+            error_codes::AUTHENTICATION_REQUIRED,
+            error.to_string(),
+            Some(serde_json::json!({ "code": error.code() })),
+        ),
+        // Internal details stay on the server
+        AuthError::Internal(_) => JsonRpcError::new(
+            error_codes::INTERNAL_ERROR,
+            "Internal error".to_string(),
+            Some(serde_json::json!({ "code": error.code() })),
+        ),
+    }
+}
+
+/// The JSON-RPC error for a request an authorization callback refused.
+///
+/// It is an insufficient permissions error whose `data` carries the
+/// `access_denied` code, the reason and any details.
+pub fn jsonrpc_access_denied(denied: &AccessDenied) -> JsonRpcError {
+    let mut data = denied.response_details();
+    data.insert("code".to_string(), AccessDenied::CODE.into());
+    JsonRpcError::new(
+        error_codes::INSUFFICIENT_PERMISSIONS,
+        "Access denied".to_string(),
+        Some(serde_json::Value::Object(data)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn auth_errors_keep_their_code() {
+        for error in [
+            AuthError::InvalidToken,
+            AuthError::TokenExpired,
+            AuthError::TokenNotYetValid,
+            AuthError::AuthenticationRequired,
+            AuthError::InsufficientPermissions {
+                required: vec!["admin".to_string()],
+                has: vec![],
+            },
+            AuthError::Internal("database down".to_string()),
+            AuthError::AccountLocked { retry_after: None },
+            AuthError::RateLimited {
+                retry_after: Duration::from_secs(1),
+            },
+        ] {
+            let rpc_error = jsonrpc_auth_error(&error);
+            assert_eq!(rpc_error.auth_code(), Some(error.code()));
+            assert!(!rpc_error.message.contains("database"));
+        }
+
+        let rpc_error = jsonrpc_auth_error(&AuthError::RateLimited {
+            retry_after: Duration::from_millis(1500),
+        });
+        assert_eq!(rpc_error.code, error_codes::RATE_LIMITED);
+        assert_eq!(rpc_error.data.unwrap()["retry_after_ms"], 1500);
+    }
+
+    #[test]
+    fn access_denied_carries_its_reason() {
+        let rpc_error =
+            jsonrpc_access_denied(&AccessDenied::new("not_owner").with_detail("owner", "u2"));
+        assert_eq!(rpc_error.code, error_codes::INSUFFICIENT_PERMISSIONS);
+        assert_eq!(rpc_error.auth_code(), Some("access_denied"));
+        assert_eq!(
+            rpc_error.data.unwrap(),
+            serde_json::json!({ "code": "access_denied", "reason": "not_owner", "owner": "u2" })
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Helpers for dispatching JSON-RPC 2.0 batch requests.
+
+use std::future::Future;
+
+use futures::StreamExt;
+use ras_jsonrpc_types::JsonRpcResponse;
+
+/// Default number of batch entries the generated server dispatches concurrently.
+pub const DEFAULT_MAX_BATCH_CONCURRENCY: usize = 16;
+
+/// Returns true if the raw request object names a method but has no `id`
+/// member, i.e. it is a notification that must not receive a response.
+///
+/// An explicit `"id": null` is still a request and is answered, as are objects
+/// without a `method`, which get an Invalid Request error.
+pub fn is_notification(request: &serde_json::Value) -> bool {
+    request
+        .as_object()
+        .is_some_and(|object| object.contains_key("method") && !object.contains_key("id"))
+}
+
+/// Dispatches every element of a batch through `handle`, running at most
+/// `max_concurrency` handlers at a time.
+///
+/// Responses are returned in the same order as the requests that produced
+/// them. Elements for which `handle` yields `None` (notifications) are omitted.
+pub async fn dispatch_batch<T, F, Fut>(
+    requests: Vec<T>,
+    max_concurrency: usize,
+    handle: F,
+) -> Vec<JsonRpcResponse>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Option<JsonRpcResponse>>,
+{
+    futures::stream::iter(requests)
+        .map(handle)
+        .buffered(max_concurrency.max(1))
+        .filter_map(std::future::ready)
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn notification_detection_requires_absent_id() {
+        assert!(is_notification(&json!({"jsonrpc": "2.0", "method": "m"})));
+        assert!(!is_notification(
+            &json!({"jsonrpc": "2.0", "method": "m", "id": 1})
+        ));
+        assert!(!is_notification(
+            &json!({"jsonrpc": "2.0", "method": "m", "id": null})
+        ));
+        assert!(!is_notification(&json!({"jsonrpc": "2.0"})));
+        assert!(!is_notification(&json!(1)));
+    }
+
+    #[tokio::test]
+    async fn dispatch_preserves_order_and_skips_notifications() {
+        let requests = vec![
+            json!({"method": "m", "id": 1}),
+            json!({"method": "m"}),
+            json!({"method": "m", "id": 3}),
+        ];
+
+        let responses = dispatch_batch(requests, 3, |request| async move {
+            let id = request.get("id").cloned()?;
+            // Later entries finish first so ordering comes from the dispatcher.
+            let delay = 30 - id.as_u64().unwrap() * 10;
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Some(JsonRpcResponse::success(json!("ok"), Some(id)))
+        })
+        .await;
+
+        let ids: Vec<_> = responses.iter().map(|r| r.id.clone()).collect();
+        assert_eq!(ids, vec![Some(json!(1)), Some(json!(3))]);
+    }
+}
@@ -0,0 +1,216 @@
+//! Concurrency limits for generated JSON-RPC method dispatch.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ras_jsonrpc_types::JsonRpcError;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// What a request does when its concurrency limit is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverloadBehavior {
+    /// Fail immediately with a `server_busy` error.
+    #[default]
+    Reject,
+    /// Wait up to `max_wait` for a slot, then fail with `server_busy`.
+    Queue {
+        /// Longest time a request waits for a slot.
+        max_wait: Duration,
+    },
+}
+
+/// Number of requests currently executing, per method.
+///
+/// Cloning the handle shares the same counters, so it can be kept for metrics
+/// after the service is moved into a router.
+#[derive(Debug, Clone, Default)]
+pub struct InFlightRequests {
+    counts: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl InFlightRequests {
+    /// Requests currently executing for `method`.
+    pub fn get(&self, method: &str) -> usize {
+        self.counts
+            .lock()
+            .unwrap()
+            .get(method)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// In-flight counts for every method that has received a request.
+    pub fn snapshot(&self) -> HashMap<String, usize> {
+        self.counts.lock().unwrap().clone()
+    }
+
+    fn increment(&self, method: &str) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default() += 1;
+    }
+
+    fn decrement(&self, method: &str) {
+        if let Some(count) = self.counts.lock().unwrap().get_mut(method) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+/// Global and per-method concurrency limits for a service.
+#[derive(Debug, Default)]
+pub struct ConcurrencyLimiter {
+    global: Option<Arc<Semaphore>>,
+    methods: HashMap<String, Arc<Semaphore>>,
+    behavior: OverloadBehavior,
+    in_flight: InFlightRequests,
+}
+
+impl ConcurrencyLimiter {
+    /// Create a limiter without any limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of requests executing across all methods.
+    pub fn with_global_limit(mut self, limit: usize) -> Self {
+        self.global = Some(Arc::new(Semaphore::new(limit.max(1))));
+        self
+    }
+
+    /// Limit the number of concurrently executing requests for one method.
+    pub fn with_method_limit(mut self, method: impl Into<String>, limit: usize) -> Self {
+        self.methods
+            .insert(method.into(), Arc::new(Semaphore::new(limit.max(1))));
+        self
+    }
+
+    /// Set what happens when a limit is reached.
+    pub fn with_overload_behavior(mut self, behavior: OverloadBehavior) -> Self {
+        self.behavior = behavior;
+        self
+    }
+
+    /// Shared handle to the per-method in-flight counters.
+    pub fn in_flight(&self) -> InFlightRequests {
+        self.in_flight.clone()
+    }
+
+    /// Wait for (or fail to get) a slot to run `method`.
+    ///
+    /// The method limit is acquired before the global one so that requests
+    /// queued behind a saturated method do not hold global slots.
+    pub async fn acquire(&self, method: &str) -> Result<ConcurrencyPermit, JsonRpcError> {
+        let method_permit = match self.methods.get(method) {
+            Some(semaphore) => Some(self.acquire_slot(semaphore).await?),
+            None => None,
+        };
+        let global_permit = match &self.global {
+            Some(semaphore) => Some(self.acquire_slot(semaphore).await?),
+            None => None,
+        };
+
+        self.in_flight.increment(method);
+
+        Ok(ConcurrencyPermit {
+            method: method.to_string(),
+            in_flight: self.in_flight.clone(),
+            _permits: [method_permit, global_permit],
+        })
+    }
+
+    async fn acquire_slot(
+        &self,
+        semaphore: &Arc<Semaphore>,
+    ) -> Result<OwnedSemaphorePermit, JsonRpcError> {
+        match self.behavior {
+            OverloadBehavior::Reject => semaphore
+                .clone()
+                .try_acquire_owned()
+                .map_err(|_| JsonRpcError::server_busy()),
+            OverloadBehavior::Queue { max_wait } => {
+                match tokio::time::timeout(max_wait, semaphore.clone().acquire_owned()).await {
+                    Ok(Ok(permit)) => Ok(permit),
+                    _ => Err(JsonRpcError::server_busy()),
+                }
+            }
+        }
+    }
+}
+
+/// Slot held while a request executes; releasing it happens on drop.
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+    method: String,
+    in_flight: InFlightRequests,
+    _permits: [Option<OwnedSemaphorePermit>; 2],
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        self.in_flight.decrement(&self.method);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_jsonrpc_types::error_codes;
+
+    #[tokio::test]
+    async fn method_limit_rejects_when_saturated() {
+        let limiter = ConcurrencyLimiter::new().with_method_limit("report", 1);
+
+        let permit = limiter.acquire("report").await.expect("first slot");
+        assert_eq!(limiter.in_flight().get("report"), 1);
+
+        let err = limiter.acquire("report").await.expect_err("limit reached");
+        assert_eq!(err.code, error_codes::SERVER_BUSY);
+
+        // Other methods are not limited
+        let _other = limiter.acquire("ping").await.expect("unlimited method");
+
+        drop(permit);
+        assert_eq!(limiter.in_flight().get("report"), 0);
+        assert!(limiter.acquire("report").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn queued_requests_wait_for_a_free_slot() {
+        let limiter = Arc::new(
+            ConcurrencyLimiter::new()
+                .with_global_limit(1)
+                .with_overload_behavior(OverloadBehavior::Queue {
+                    max_wait: Duration::from_secs(1),
+                }),
+        );
+
+        let permit = limiter.acquire("a").await.unwrap();
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire("b").await.map(|_| ()) }
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(permit);
+        assert!(waiter.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn queued_requests_give_up_after_max_wait() {
+        let limiter = ConcurrencyLimiter::new()
+            .with_global_limit(1)
+            .with_overload_behavior(OverloadBehavior::Queue {
+                max_wait: Duration::from_millis(10),
+            });
+
+        let _permit = limiter.acquire("a").await.unwrap();
+        let err = limiter.acquire("b").await.expect_err("timed out");
+        assert_eq!(err.code, error_codes::SERVER_BUSY);
+        assert_eq!(limiter.in_flight().get("b"), 0);
+    }
+}
@@ -0,0 +1,62 @@
+//! CORS for generated JSON-RPC endpoints.
+
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::idempotency::IDEMPOTENCY_KEY_HEADER;
+
+/// Build the CORS layer of a JSON-RPC endpoint.
+///
+/// Browsers at `allow_origins` may POST requests carrying `Authorization`,
+/// `Content-Type`, `Idempotency-Key` and trace context headers, and
+/// `allow_credentials` lets them send cookies and read responses in
+/// credentials mode. An origin of `"*"` selects a permissive development
+/// policy that mirrors the request's origin and headers and allows
+/// credentials; do not use it in production.
+pub fn jsonrpc_cors_layer(
+    allow_origins: &[&str],
+    allow_credentials: bool,
+) -> Result<CorsLayer, String> {
+    if allow_origins.contains(&"*") {
+        return Ok(CorsLayer::very_permissive());
+    }
+
+    let origins = allow_origins
+        .iter()
+        .map(|origin| {
+            HeaderValue::from_str(origin.trim_end_matches('/'))
+                .map_err(|_| format!("invalid CORS origin `{origin}`"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::POST, Method::OPTIONS])
+        .allow_headers([
+            AUTHORIZATION,
+            CONTENT_TYPE,
+            HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
+            HeaderName::from_static("traceparent"),
+            HeaderName::from_static("tracestate"),
+        ])
+        .expose_headers([AUTHORIZATION])
+        .allow_credentials(allow_credentials))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_origins_are_rejected() {
+        let error = jsonrpc_cors_layer(&["https://app.example.com\n"], false).unwrap_err();
+        assert!(error.contains("invalid CORS origin"));
+    }
+
+    #[test]
+    fn origins_and_wildcard_build() {
+        assert!(jsonrpc_cors_layer(&["https://app.example.com/"], true).is_ok());
+        assert!(jsonrpc_cors_layer(&["*"], true).is_ok());
+    }
+}
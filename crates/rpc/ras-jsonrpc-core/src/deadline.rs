@@ -0,0 +1,221 @@
+//! Caller deadlines for generated JSON-RPC method dispatch.
+
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::http::HeaderMap;
+use futures::StreamExt;
+use ras_jsonrpc_types::{JsonRpcError, REQUEST_DEADLINE_HEADER};
+
+use crate::handler_error::JsonRpcHandlerError;
+use crate::streaming::ChunkStream;
+
+tokio::task_local! {
+    static REQUEST_DEADLINE: Instant;
+}
+
+/// The instant the JSON-RPC call being handled must complete by.
+///
+/// Available inside method handlers (but not in tasks they spawn) when the
+/// method has a timeout or the caller sent a deadline; the handler is dropped
+/// once it passes.
+pub fn request_deadline() -> Option<Instant> {
+    REQUEST_DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Time left until [`request_deadline`], e.g. to skip work that can't finish
+/// in time or to pass a budget on to downstream calls.
+pub fn remaining_budget() -> Option<Duration> {
+    request_deadline().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Time left until an `X-Request-Deadline` header value: relative
+/// milliseconds such as `1500ms`, or absolute unix milliseconds. Deadlines
+/// that already passed give zero; malformed values give `None`.
+pub fn parse_request_deadline(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Some(millis) = value.strip_suffix("ms") {
+        return millis.trim().parse().ok().map(Duration::from_millis);
+    }
+
+    let deadline = UNIX_EPOCH + Duration::from_millis(value.parse().ok()?);
+    Some(
+        deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Run a method handler within its `timeout` and the caller's deadline.
+///
+/// The handler gets the shorter of the two budgets, exposed through
+/// [`request_deadline`] and [`remaining_budget`]. A handler that runs over
+/// budget is dropped, cancelling it at its current await point, and a call
+/// whose deadline already passed gets a `request_timeout` error without
+/// running the handler. Malformed deadline headers are ignored.
+pub async fn with_request_deadline<F: Future>(
+    handler: F,
+    timeout: Option<Duration>,
+    headers: &HeaderMap,
+) -> Result<F::Output, JsonRpcError> {
+    let Some(budget) = request_budget(timeout, headers) else {
+        return Ok(handler.await);
+    };
+    if budget.is_zero() {
+        return Err(JsonRpcError::request_timeout(Duration::ZERO, budget));
+    }
+
+    let start = Instant::now();
+    REQUEST_DEADLINE
+        .scope(start + budget, tokio::time::timeout(budget, handler))
+        .await
+        .map_err(|_| JsonRpcError::request_timeout(start.elapsed(), budget))
+}
+
+/// Produce the chunks of a streaming handler within its `timeout` and the
+/// caller's deadline, as [`with_request_deadline`] runs a unary handler.
+///
+/// The budget covers the whole stream: chunks are produced with the deadline
+/// exposed through [`request_deadline`], and a stream still running once it
+/// passes ends with a `request_timeout` error. A call whose deadline already
+/// passed gets that error without a stream.
+pub fn with_stream_deadline<T>(
+    chunks: ChunkStream<T>,
+    timeout: Option<Duration>,
+    headers: &HeaderMap,
+) -> Result<ChunkStream<T>, JsonRpcError>
+where
+    T: Send + 'static,
+{
+    let Some(budget) = request_budget(timeout, headers) else {
+        return Ok(chunks);
+    };
+    if budget.is_zero() {
+        return Err(JsonRpcError::request_timeout(Duration::ZERO, budget));
+    }
+
+    let start = Instant::now();
+    let deadline = start + budget;
+    let chunks = futures::stream::unfold(Some(chunks), move |chunks| async move {
+        let mut chunks = chunks?;
+        let next = REQUEST_DEADLINE
+            .scope(
+                deadline,
+                tokio::time::timeout_at(deadline.into(), chunks.next()),
+            )
+            .await;
+        match next {
+            Ok(chunk) => Some((chunk?, Some(chunks))),
+            Err(_) => {
+                let error = JsonRpcError::request_timeout(start.elapsed(), budget);
+                let error = JsonRpcHandlerError {
+                    code: error.code,
+                    message: error.message,
+                    data: error.data,
+                };
+                Some((Err(error.into()), None))
+            }
+        }
+    });
+    Ok(chunks.boxed())
+}
+
+/// The shorter of `timeout` and the budget left by the caller's deadline
+fn request_budget(timeout: Option<Duration>, headers: &HeaderMap) -> Option<Duration> {
+    let caller_budget = headers
+        .get(REQUEST_DEADLINE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_request_deadline);
+    match (timeout, caller_budget) {
+        (Some(timeout), Some(caller_budget)) => Some(timeout.min(caller_budget)),
+        (timeout, caller_budget) => timeout.or(caller_budget),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_jsonrpc_types::error_codes;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn deadline_headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_DEADLINE_HEADER, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn deadlines_parse_in_both_forms() {
+        assert_eq!(
+            parse_request_deadline("1500ms"),
+            Some(Duration::from_millis(1500))
+        );
+
+        let in_a_minute = SystemTime::now() + Duration::from_secs(60);
+        let millis = in_a_minute.duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let remaining = parse_request_deadline(&millis.to_string()).unwrap();
+        assert!(remaining > Duration::from_secs(55) && remaining <= Duration::from_secs(60));
+
+        assert_eq!(parse_request_deadline("1000"), Some(Duration::ZERO));
+        assert_eq!(parse_request_deadline("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn the_shorter_budget_applies() {
+        let headers = deadline_headers("50ms");
+        let budget = with_request_deadline(
+            async { remaining_budget().unwrap() },
+            Some(Duration::from_secs(5)),
+            &headers,
+        )
+        .await
+        .unwrap();
+        assert!(budget <= Duration::from_millis(50));
+
+        let budget = with_request_deadline(
+            async { remaining_budget().unwrap() },
+            Some(Duration::from_millis(20)),
+            &deadline_headers("5000ms"),
+        )
+        .await
+        .unwrap();
+        assert!(budget <= Duration::from_millis(20));
+
+        let budget = with_request_deadline(async { remaining_budget() }, None, &HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(budget, None);
+    }
+
+    #[tokio::test]
+    async fn streams_running_past_their_budget_end_with_a_timeout() {
+        let chunks = futures::stream::iter(0..)
+            .then(|n| async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Ok(n)
+            })
+            .boxed();
+        let chunks =
+            with_stream_deadline(chunks, Some(Duration::from_millis(35)), &HeaderMap::new())
+                .unwrap();
+
+        let mut results: Vec<_> = chunks.collect().await;
+        let error = crate::jsonrpc_error_from_handler(results.pop().unwrap().unwrap_err());
+        assert_eq!(error.code, error_codes::REQUEST_TIMEOUT);
+        assert!(!results.is_empty() && results.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn passed_deadlines_fail_without_running_the_handler() {
+        let ran = AtomicBool::new(false);
+        let error = with_request_deadline(
+            async { ran.store(true, Ordering::SeqCst) },
+            None,
+            &deadline_headers("0ms"),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, error_codes::REQUEST_TIMEOUT);
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,59 @@
+//! Warnings for calls of deprecated JSON-RPC methods.
+
+use axum::http::HeaderValue;
+use axum::http::header::WARNING;
+use axum::response::Response;
+
+use crate::service::JsonRpcService;
+
+/// `Warning` header value for `text`, using the RFC 7234 code 299
+/// ("miscellaneous persistent warning").
+fn deprecation_warning_header(text: &str) -> Option<HeaderValue> {
+    let text = text.replace('\\', "\\\\").replace('"', "\\\"");
+    HeaderValue::from_str(&format!("299 - \"{text}\"")).ok()
+}
+
+/// Warnings of the deprecated methods among `methods`, logging each call.
+pub(crate) fn deprecation_warnings<'a, S>(
+    service: &S,
+    methods: impl IntoIterator<Item = &'a str>,
+) -> Vec<String>
+where
+    S: JsonRpcService + ?Sized,
+{
+    let mut warnings = Vec::new();
+    for method in methods {
+        let Some(warning) = service.deprecation_warning(method) else {
+            continue;
+        };
+        tracing::warn!(method, "{warning}");
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+    warnings
+}
+
+/// Add a `Warning` header to `response` for each of `warnings`.
+pub(crate) fn add_warning_headers(mut response: Response, warnings: &[String]) -> Response {
+    for warning in warnings {
+        if let Some(value) = deprecation_warning_header(warning) {
+            response.headers_mut().append(WARNING, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warning_text_is_quoted() {
+        let header = deprecation_warning_header(r#"Method a is deprecated; use "b""#).unwrap();
+        assert_eq!(
+            header.to_str().unwrap(),
+            r#"299 - "Method a is deprecated; use \"b\"""#
+        );
+    }
+}
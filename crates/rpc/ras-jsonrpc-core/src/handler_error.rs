@@ -0,0 +1,139 @@
+//! Application errors returned from generated JSON-RPC service handlers.
+
+use std::fmt;
+
+use ras_jsonrpc_types::JsonRpcError;
+
+/// Smallest code in the implementation-defined server error range.
+pub const APPLICATION_ERROR_MIN: i32 = -32099;
+
+/// Largest code in the implementation-defined server error range.
+pub const APPLICATION_ERROR_MAX: i32 = -32000;
+
+/// A domain error with its own JSON-RPC error code, message and optional data.
+///
+/// Return it from a handler (boxed, e.g. with `?` or `.into()`) and the
+/// generated server answers with this code instead of the generic internal
+/// error. Codes should be in the `-32099..=-32000` application range.
+///
+/// ```rust,ignore
+/// return Err(JsonRpcHandlerError::new(-32010, "Task not found")
+///     .with_data(serde_json::json!({ "task_id": id }))
+///     .into());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcHandlerError {
+    /// JSON-RPC error code.
+    pub code: i32,
+    /// Message sent to the client.
+    pub message: String,
+    /// Structured data sent to the client.
+    pub data: Option<serde_json::Value>,
+}
+
+impl JsonRpcHandlerError {
+    /// Creates an error with the given code and message.
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Attaches structured data to the error.
+    pub fn with_data(mut self, data: serde_json::Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// Returns true if the code is in the application error range.
+    pub fn is_application_code(&self) -> bool {
+        (APPLICATION_ERROR_MIN..=APPLICATION_ERROR_MAX).contains(&self.code)
+    }
+}
+
+impl fmt::Display for JsonRpcHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for JsonRpcHandlerError {}
+
+impl From<JsonRpcHandlerError> for JsonRpcError {
+    fn from(error: JsonRpcHandlerError) -> Self {
+        JsonRpcError::new(error.code, error.message, error.data)
+    }
+}
+
+/// Conversion from a domain error type into a [`JsonRpcHandlerError`].
+///
+/// Implement it for your own error enums and convert at the handler boundary
+/// with `.map_err(JsonRpcHandlerError::from)?`.
+pub trait IntoJsonRpcError {
+    /// Converts the error into its JSON-RPC representation.
+    fn into_jsonrpc_error(self) -> JsonRpcHandlerError;
+}
+
+impl<E: IntoJsonRpcError> From<E> for JsonRpcHandlerError {
+    fn from(error: E) -> Self {
+        error.into_jsonrpc_error()
+    }
+}
+
+/// Maps an error returned by a handler to the JSON-RPC error sent to the client.
+///
+/// [`JsonRpcHandlerError`]s keep their code, message and data; anything else
+/// becomes a sanitized internal error.
+pub fn jsonrpc_error_from_handler(error: Box<dyn std::error::Error + Send + Sync>) -> JsonRpcError {
+    match error.downcast::<JsonRpcHandlerError>() {
+        Ok(error) => (*error).into(),
+        Err(error) => JsonRpcError::internal_error(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_jsonrpc_types::error_codes;
+    use serde_json::json;
+
+    enum TaskError {
+        NotFound(u64),
+    }
+
+    impl IntoJsonRpcError for TaskError {
+        fn into_jsonrpc_error(self) -> JsonRpcHandlerError {
+            match self {
+                TaskError::NotFound(id) => JsonRpcHandlerError::new(-32010, "Task not found")
+                    .with_data(json!({ "task_id": id })),
+            }
+        }
+    }
+
+    #[test]
+    fn handler_errors_keep_code_message_and_data() {
+        let error: Box<dyn std::error::Error + Send + Sync> =
+            JsonRpcHandlerError::from(TaskError::NotFound(7)).into();
+
+        let error = jsonrpc_error_from_handler(error);
+        assert_eq!(error.code, -32010);
+        assert_eq!(error.message, "Task not found");
+        assert_eq!(error.data, Some(json!({ "task_id": 7 })));
+    }
+
+    #[test]
+    fn other_errors_become_internal_errors() {
+        let error = jsonrpc_error_from_handler("connection refused".into());
+        assert_eq!(error.code, error_codes::INTERNAL_ERROR);
+        assert_eq!(error.message, "Internal error");
+    }
+
+    #[test]
+    fn application_range_is_inclusive() {
+        assert!(JsonRpcHandlerError::new(-32000, "").is_application_code());
+        assert!(JsonRpcHandlerError::new(-32099, "").is_application_code());
+        assert!(!JsonRpcHandlerError::new(-32603, "").is_application_code());
+    }
+}
@@ -0,0 +1,413 @@
+//! Idempotency keys for generated JSON-RPC method dispatch.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use futures::future::BoxFuture;
+use ras_jsonrpc_types::{JsonRpcError, error_codes};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::OwnedMutexGuard;
+
+/// HTTP header carrying a request's idempotency key.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Params member read as the idempotency key when the header is absent.
+pub const IDEMPOTENCY_KEY_PARAM: &str = "idempotency_key";
+
+/// How long stored results are replayed by default.
+pub const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Storage for the results of idempotent method calls.
+///
+/// Keys are already scoped to the method and calling user. Each value is a
+/// call's result along with a fingerprint of its params, which stores keep
+/// as they are given.
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// The value stored under `key`, unless it is missing or expired.
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<serde_json::Value>>;
+
+    /// Store `result` under `key` for `ttl`.
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        result: serde_json::Value,
+        ttl: Duration,
+    ) -> BoxFuture<'a, ()>;
+}
+
+/// Entries a map may hold before expired or abandoned ones are swept out
+const SWEEP_THRESHOLD: usize = 1024;
+
+/// Process-local [`IdempotencyStore`] that drops entries once they expire.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryIdempotencyStore {
+    entries: Arc<Mutex<StoredEntries>>,
+}
+
+#[derive(Debug, Default)]
+struct StoredEntries {
+    results: HashMap<String, (serde_json::Value, Instant)>,
+    // Swept when it holds this many, so each sweep is paid for by the puts before it
+    sweep_at: usize,
+}
+
+impl InMemoryIdempotencyStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<serde_json::Value>> {
+        Box::pin(async move {
+            let mut entries = self.entries.lock().unwrap();
+            match entries.results.get(key) {
+                Some((result, expires_at)) if *expires_at > Instant::now() => Some(result.clone()),
+                Some(_) => {
+                    entries.results.remove(key);
+                    None
+                }
+                None => None,
+            }
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        result: serde_json::Value,
+        ttl: Duration,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let mut entries = self.entries.lock().unwrap();
+            let now = Instant::now();
+            if entries.results.len() >= entries.sweep_at {
+                entries
+                    .results
+                    .retain(|_, (_, expires_at)| *expires_at > now);
+                entries.sweep_at = (entries.results.len() * 2).max(SWEEP_THRESHOLD);
+            }
+            entries.results.insert(key.to_string(), (result, now + ttl));
+        })
+    }
+}
+
+/// The idempotency key sent with a request.
+///
+/// Read from the `Idempotency-Key` header, falling back to an
+/// `idempotency_key` string member of object params.
+pub fn idempotency_key(headers: &HeaderMap, params: Option<&serde_json::Value>) -> Option<String> {
+    headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| params?.get(IDEMPOTENCY_KEY_PARAM)?.as_str())
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+}
+
+/// Replays stored results for repeated idempotency keys and coalesces
+/// concurrent requests that share a key.
+pub struct Idempotency {
+    store: Arc<dyn IdempotencyStore>,
+    ttl: Duration,
+    in_flight: Mutex<InFlightKeys>,
+}
+
+/// The keys of executing calls, which duplicates wait on
+#[derive(Default)]
+struct InFlightKeys {
+    locks: HashMap<String, Weak<tokio::sync::Mutex<()>>>,
+    // Swept of finished calls when it holds this many
+    sweep_at: usize,
+}
+
+/// A call's result as stored, with the SHA-256 of the params it was called with
+#[derive(Serialize, Deserialize)]
+struct StoredResult {
+    params_sha256: String,
+    result: serde_json::Value,
+}
+
+/// Whether an idempotent call runs its handler or replays a stored result.
+pub enum IdempotencyClaim {
+    /// A previous call with the same key succeeded with this result.
+    Replay(serde_json::Value),
+    /// No result is stored; run the handler and complete the lease.
+    Execute(IdempotencyLease),
+}
+
+/// Held while the first request for a key executes; duplicates wait on it.
+pub struct IdempotencyLease {
+    key: String,
+    params_sha256: String,
+    store: Arc<dyn IdempotencyStore>,
+    ttl: Duration,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl Idempotency {
+    /// Create an idempotency layer backed by `store`.
+    pub fn new(store: impl IdempotencyStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttl: DEFAULT_IDEMPOTENCY_TTL,
+            in_flight: Mutex::new(InFlightKeys::default()),
+        }
+    }
+
+    /// Set how long stored results are replayed.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long stored results are replayed.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Claim `key` for a call of `method` with `params` on behalf of `user_id`.
+    ///
+    /// Waits while another request with the same key executes, then either
+    /// replays the result it stored or hands out the lease to execute. Keys of
+    /// anonymous callers, who would share their results, are refused as an
+    /// authentication error, and keys used before with other params as an
+    /// invalid params error.
+    pub async fn claim(
+        &self,
+        method: &str,
+        user_id: Option<&str>,
+        key: &str,
+        params: Option<&serde_json::Value>,
+    ) -> Result<IdempotencyClaim, JsonRpcError> {
+        let Some(user_id) = user_id else {
+            return Err(JsonRpcError::new(
+                error_codes::AUTHENTICATION_REQUIRED,
+                "Idempotency keys require authentication".to_string(),
+                Some(serde_json::json!({ "code": "authentication_required" })),
+            ));
+        };
+        let params_sha256 = params_sha256(params);
+        // Encoded so that no user or key, whatever it contains, stands for another
+        let scoped_key = serde_json::json!([method, user_id, key]).to_string();
+
+        let lock = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.locks.get(&scoped_key).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    if in_flight.locks.len() >= in_flight.sweep_at {
+                        in_flight.locks.retain(|_, lock| lock.strong_count() > 0);
+                        in_flight.sweep_at = (in_flight.locks.len() * 2).max(SWEEP_THRESHOLD);
+                    }
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    in_flight
+                        .locks
+                        .insert(scoped_key.clone(), Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        let guard = lock.lock_owned().await;
+
+        // A duplicate that waited finds the first request's result here
+        let stored = self
+            .store
+            .get(&scoped_key)
+            .await
+            .and_then(|stored| serde_json::from_value::<StoredResult>(stored).ok());
+        match stored {
+            Some(stored) if stored.params_sha256 == params_sha256 => {
+                Ok(IdempotencyClaim::Replay(stored.result))
+            }
+            Some(_) => Err(JsonRpcError::new(
+                error_codes::INVALID_PARAMS,
+                "Idempotency key was already used with different params".to_string(),
+                Some(serde_json::json!({ "idempotency_key": key })),
+            )),
+            None => Ok(IdempotencyClaim::Execute(IdempotencyLease {
+                key: scoped_key,
+                params_sha256,
+                store: self.store.clone(),
+                ttl: self.ttl,
+                _guard: guard,
+            })),
+        }
+    }
+}
+
+impl Default for Idempotency {
+    fn default() -> Self {
+        Self::new(InMemoryIdempotencyStore::new())
+    }
+}
+
+impl IdempotencyLease {
+    /// Store the serialized result so later requests with the key replay it.
+    ///
+    /// Dropping the lease without completing it lets the next duplicate run
+    /// the handler itself.
+    pub async fn complete(self, result: &serde_json::Value) {
+        let stored = StoredResult {
+            params_sha256: self.params_sha256,
+            result: result.clone(),
+        };
+        let stored = serde_json::to_value(stored).unwrap_or_default();
+        self.store.put(&self.key, stored, self.ttl).await;
+    }
+}
+
+/// The SHA-256 of `params`, in hex
+fn params_sha256(params: Option<&serde_json::Value>) -> String {
+    let params = params.map(serde_json::Value::to_string).unwrap_or_default();
+    format!("{:x}", Sha256::digest(params.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn header_key_takes_precedence_over_params() {
+        let params = json!({"idempotency_key": "from-params"});
+        assert_eq!(
+            idempotency_key(&HeaderMap::new(), Some(&params)).as_deref(),
+            Some("from-params")
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, "from-header".parse().unwrap());
+        assert_eq!(
+            idempotency_key(&headers, Some(&params)).as_deref(),
+            Some("from-header")
+        );
+        assert_eq!(idempotency_key(&HeaderMap::new(), Some(&json!("a"))), None);
+    }
+
+    #[tokio::test]
+    async fn completed_results_are_replayed_per_user() {
+        let idempotency = Idempotency::default();
+        let params = json!({"title": "a"});
+
+        match idempotency
+            .claim("create", Some("ada"), "k1", Some(&params))
+            .await
+        {
+            Ok(IdempotencyClaim::Execute(lease)) => lease.complete(&json!(7)).await,
+            _ => panic!("nothing stored yet"),
+        }
+        assert!(matches!(
+            idempotency.claim("create", Some("ada"), "k1", Some(&params)).await,
+            Ok(IdempotencyClaim::Replay(result)) if result == json!(7)
+        ));
+        assert!(matches!(
+            idempotency
+                .claim("create", Some("bob"), "k1", Some(&params))
+                .await,
+            Ok(IdempotencyClaim::Execute(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn users_and_keys_containing_colons_are_kept_apart() {
+        let idempotency = Idempotency::default();
+
+        if let Ok(IdempotencyClaim::Execute(lease)) =
+            idempotency.claim("create", Some("a:b"), "c", None).await
+        {
+            lease.complete(&json!("a:b's result")).await;
+        }
+
+        assert!(matches!(
+            idempotency.claim("create", Some("a"), "b:c", None).await,
+            Ok(IdempotencyClaim::Execute(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn expired_entries_are_swept_as_the_store_grows() {
+        let store = InMemoryIdempotencyStore::new();
+        let ttl = Duration::from_millis(10);
+        for index in 0..SWEEP_THRESHOLD {
+            store.put(&format!("old-{index}"), json!(index), ttl).await;
+        }
+        assert_eq!(store.entries.lock().unwrap().results.len(), SWEEP_THRESHOLD);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        store.put("new", json!(1), ttl * 100).await;
+
+        assert_eq!(store.entries.lock().unwrap().results.len(), 1);
+        assert_eq!(store.get("new").await, Some(json!(1)));
+        assert_eq!(store.get("old-0").await, None);
+    }
+
+    #[tokio::test]
+    async fn keys_reused_with_other_params_are_refused() {
+        let idempotency = Idempotency::default();
+
+        if let Ok(IdempotencyClaim::Execute(lease)) = idempotency
+            .claim("create", Some("ada"), "k1", Some(&json!({"title": "a"})))
+            .await
+        {
+            lease.complete(&json!(7)).await;
+        }
+
+        let Err(error) = idempotency
+            .claim("create", Some("ada"), "k1", Some(&json!({"title": "b"})))
+            .await
+        else {
+            panic!("the key was used with other params");
+        };
+        assert_eq!(error.code, error_codes::INVALID_PARAMS);
+        assert_eq!(error.data.unwrap()["idempotency_key"], "k1");
+    }
+
+    #[tokio::test]
+    async fn anonymous_callers_cannot_use_keys() {
+        let idempotency = Idempotency::default();
+
+        let Err(error) = idempotency.claim("create", None, "k1", None).await else {
+            panic!("anonymous callers would share their results");
+        };
+        assert_eq!(error.code, error_codes::AUTHENTICATION_REQUIRED);
+    }
+
+    #[tokio::test]
+    async fn expired_results_are_not_replayed() {
+        let idempotency = Idempotency::default().with_ttl(Duration::from_millis(10));
+
+        if let Ok(IdempotencyClaim::Execute(lease)) =
+            idempotency.claim("create", Some("ada"), "k1", None).await
+        {
+            lease.complete(&json!(1)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert!(matches!(
+            idempotency.claim("create", Some("ada"), "k1", None).await,
+            Ok(IdempotencyClaim::Execute(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn abandoned_lease_lets_the_duplicate_execute() {
+        let idempotency = Idempotency::default();
+
+        let Ok(IdempotencyClaim::Execute(lease)) =
+            idempotency.claim("create", Some("ada"), "k1", None).await
+        else {
+            panic!("nothing stored yet");
+        };
+        drop(lease);
+
+        assert!(matches!(
+            idempotency.claim("create", Some("ada"), "k1", None).await,
+            Ok(IdempotencyClaim::Execute(_))
+        ));
+    }
+}
@@ -12,3 +12,73 @@ pub use ras_jsonrpc_types::*;
 
 // Re-export version migration traits for generated compatibility dispatch.
 pub use ras_version_core::*;
+
+// Re-export service manifest types for the generated manifest function and route.
+pub use ras_manifest_core::{
+    AuthMode, MANIFEST_PATH, OperationManifest, ServiceManifest, ServiceProtocol, manifest_response,
+};
+
+mod auth_error;
+pub use auth_error::{jsonrpc_access_denied, jsonrpc_auth_error};
+
+mod batch;
+pub use batch::{DEFAULT_MAX_BATCH_CONCURRENCY, dispatch_batch, is_notification};
+
+mod service;
+pub use service::{
+    DEFAULT_MAX_REQUEST_SIZE, JSON_CONTENT_TYPE, JsonRpcRouter, JsonRpcService, handle_http_body,
+    handle_http_request, is_json_content_type,
+};
+
+mod deprecation;
+mod handler_error;
+pub use handler_error::{
+    APPLICATION_ERROR_MAX, APPLICATION_ERROR_MIN, IntoJsonRpcError, JsonRpcHandlerError,
+    jsonrpc_error_from_handler,
+};
+
+mod concurrency;
+pub use concurrency::{ConcurrencyLimiter, ConcurrencyPermit, InFlightRequests, OverloadBehavior};
+
+mod idempotency;
+pub use idempotency::{
+    DEFAULT_IDEMPOTENCY_TTL, IDEMPOTENCY_KEY_HEADER, IDEMPOTENCY_KEY_PARAM, Idempotency,
+    IdempotencyClaim, IdempotencyLease, IdempotencyStore, InMemoryIdempotencyStore,
+    idempotency_key,
+};
+
+mod positional;
+pub use positional::positional_params;
+
+mod timeout;
+pub use timeout::with_method_timeout;
+
+mod streaming;
+pub use streaming::{ChunkStream, FrameStream, on_stream_end, scope_stream, stream_frames};
+
+mod deadline;
+pub use deadline::{
+    parse_request_deadline, remaining_budget, request_deadline, with_request_deadline,
+    with_stream_deadline,
+};
+
+mod cors;
+pub use cors::jsonrpc_cors_layer;
+
+mod spans;
+pub use spans::{record_span_outcome, set_span_parent, span_request_id};
+
+mod metrics;
+pub use metrics::{error_kind, record_request_completed, request_outcome};
+pub use ras_observability_core::{
+    CompositeMethodDurationTracker, CompositeUsageTracker, InFlightGuard, InvalidProxyNetwork,
+    MethodDurationTracker, MethodDurationTrackerFn, Observability, ObservabilityBuilder,
+    ObservabilityConfig, Protocol, RequestContext, RequestOrigin, RequestOutcome,
+    SampledUsageTracker, SamplingPolicy, ServiceMetrics, SlowRequest, SlowRequestTracker,
+    TenantExtractor, TrackerFailures, TrackerGuard, TrackerHealth, TrackerStatus, TrustedProxies,
+    UsageTracker, client_ip, request_id,
+};
+
+// Re-exported so generated code can create spans without a direct `tracing` dependency.
+#[doc(hidden)]
+pub use tracing;
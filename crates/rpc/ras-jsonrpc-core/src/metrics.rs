@@ -0,0 +1,114 @@
+//! Service metrics for generated JSON-RPC method dispatch.
+
+use std::time::Duration;
+
+use ras_jsonrpc_types::{JsonRpcError, JsonRpcResponse, error_codes};
+use ras_observability_core::{RequestContext, RequestOutcome, ServiceMetrics};
+
+/// Error kind reported for a failed request, derived from its error code.
+pub fn error_kind(error: &JsonRpcError) -> &'static str {
+    match error.code {
+        error_codes::AUTHENTICATION_REQUIRED
+        | error_codes::TOKEN_EXPIRED
+        | error_codes::TOKEN_NOT_YET_VALID => "unauthenticated",
+        error_codes::INSUFFICIENT_PERMISSIONS | error_codes::ACCOUNT_LOCKED => "forbidden",
+        error_codes::INVALID_PARAMS => "invalid_params",
+        error_codes::METHOD_NOT_FOUND => "method_not_found",
+        error_codes::INVALID_REQUEST => "invalid_request",
+        error_codes::PARSE_ERROR => "parse_error",
+        error_codes::REQUEST_TIMEOUT => "timeout",
+        error_codes::SERVER_BUSY => "server_busy",
+        error_codes::RATE_LIMITED => "rate_limited",
+        _ => "handler_error",
+    }
+}
+
+/// Outcome of a request answered with `response`, from the [`error_kind`] of
+/// its error.
+pub fn request_outcome(response: &JsonRpcResponse) -> RequestOutcome {
+    RequestOutcome::from_error_kind(response.error.as_ref().map(error_kind))
+}
+
+/// Report a completed request to `metrics`.
+///
+/// Error responses count as failures and are tagged with an `error_kind`
+/// metadata entry (see [`error_kind`]) and the name of their code (see
+/// [`RequestContext::with_jsonrpc_error_code`]); the duration is recorded
+/// either way.
+pub fn record_request_completed(
+    metrics: &dyn ServiceMetrics,
+    context: RequestContext,
+    response: &JsonRpcResponse,
+    duration: Duration,
+) {
+    let context = match &response.error {
+        Some(error) => context
+            .with_metadata("error_kind", error_kind(error))
+            .with_jsonrpc_error_code(error.code),
+        None => context,
+    };
+
+    metrics.increment_requests_completed(&context, response.error.is_none());
+    metrics.record_method_duration(&context, duration);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Method, success, error kind and error code name of completed requests
+    type Completion = (String, bool, Option<String>, Option<String>);
+
+    #[derive(Default)]
+    struct Recorded(Mutex<Vec<Completion>>);
+
+    impl ServiceMetrics for Recorded {
+        fn increment_requests_started(&self, _context: &RequestContext) {}
+
+        fn increment_requests_completed(&self, context: &RequestContext, success: bool) {
+            self.0.lock().unwrap().push((
+                context.method.clone(),
+                success,
+                context.metadata.get("error_kind").cloned(),
+                context.metadata.get("error_code").cloned(),
+            ));
+        }
+
+        fn record_method_duration(&self, _context: &RequestContext, _duration: Duration) {}
+    }
+
+    #[test]
+    fn errors_are_failures_tagged_with_their_kind() {
+        let metrics = Recorded::default();
+        let ok = JsonRpcResponse::success(serde_json::json!(1), None);
+        let denied = JsonRpcResponse::error(JsonRpcError::invalid_params("bad".into()), None);
+
+        for response in [&ok, &denied] {
+            let context = RequestContext::jsonrpc("create".to_string());
+            record_request_completed(&metrics, context, response, Duration::ZERO);
+        }
+
+        assert_eq!(
+            *metrics.0.lock().unwrap(),
+            [
+                ("create".to_string(), true, None, None),
+                (
+                    "create".to_string(),
+                    false,
+                    Some("invalid_params".to_string()),
+                    Some("invalid_params".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn outcomes_follow_the_error_kind() {
+        let ok = JsonRpcResponse::success(serde_json::json!(1), None);
+        let denied = JsonRpcResponse::error(JsonRpcError::invalid_params("bad".into()), None);
+
+        assert_eq!(request_outcome(&ok), RequestOutcome::Success);
+        assert_eq!(request_outcome(&denied), RequestOutcome::InvalidParams);
+    }
+}
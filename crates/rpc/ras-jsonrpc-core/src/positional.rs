@@ -0,0 +1,131 @@
+//! Positional (by-position) params for generated JSON-RPC method dispatch.
+
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde_json::{Map, Value};
+
+use ras_jsonrpc_types::JsonRpcError;
+
+/// Map a params array onto the fields of `T`, in declaration order.
+///
+/// Object params and params of types that are not structs (tuples,
+/// sequences, primitives) are returned unchanged. Trailing fields may be left
+/// out of the array; they are then missing from the object, so `Option`
+/// fields deserialize to `None` and `#[serde(default)]` fields to their
+/// default, while any other missing field is still an error.
+pub fn positional_params<T: DeserializeOwned>(params: Value) -> Result<Value, JsonRpcError> {
+    let Value::Array(values) = params else {
+        return Ok(params);
+    };
+    let Some(fields) = struct_fields::<T>() else {
+        return Ok(Value::Array(values));
+    };
+
+    if values.len() > fields.len() {
+        return Err(JsonRpcError::invalid_params(format!(
+            "expected at most {} positional params, got {}",
+            fields.len(),
+            values.len()
+        )));
+    }
+
+    let object: Map<String, Value> = fields
+        .iter()
+        .map(|field| field.to_string())
+        .zip(values)
+        .collect();
+    Ok(Value::Object(object))
+}
+
+/// Field names of `T` in declaration order, if it deserializes as a struct.
+fn struct_fields<T: DeserializeOwned>() -> Option<&'static [&'static str]> {
+    let mut fields = None;
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+/// Deserializer that records the fields a struct asks for and then fails.
+struct FieldNames<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = Some(fields);
+        Err(de::Error::custom("struct fields recorded"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_jsonrpc_types::error_codes;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Search {
+        query: String,
+        #[serde(rename = "max")]
+        limit: u32,
+        page: Option<u32>,
+    }
+
+    fn parse<T: DeserializeOwned>(params: Value) -> Result<T, JsonRpcError> {
+        let params = positional_params::<T>(params)?;
+        serde_json::from_value(params).map_err(|e| JsonRpcError::invalid_params(e.to_string()))
+    }
+
+    #[test]
+    fn arrays_map_to_fields_in_declaration_order() {
+        let search: Search = parse(json!(["rust", 10, 2])).unwrap();
+        assert_eq!(
+            search,
+            Search {
+                query: "rust".into(),
+                limit: 10,
+                page: Some(2)
+            }
+        );
+
+        let by_name: Search = parse(json!({ "query": "rust", "max": 10, "page": 2 })).unwrap();
+        assert_eq!(by_name, search);
+    }
+
+    #[test]
+    fn trailing_optional_fields_may_be_left_out() {
+        let search: Search = parse(json!(["rust", 10])).unwrap();
+        assert_eq!(search.page, None);
+
+        let missing = parse::<Search>(json!(["rust"])).unwrap_err();
+        assert_eq!(missing.code, error_codes::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn extra_positional_params_are_rejected() {
+        let error = parse::<Search>(json!(["rust", 10, 2, true])).unwrap_err();
+        assert_eq!(error.code, error_codes::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn non_struct_types_keep_their_array() {
+        let pair: (String, u32) = parse(json!(["rust", 10])).unwrap();
+        assert_eq!(pair, ("rust".to_string(), 10));
+        let list: Vec<u32> = parse(json!([1, 2])).unwrap();
+        assert_eq!(list, [1, 2]);
+    }
+}
@@ -0,0 +1,693 @@
+//! HTTP endpoint handling shared by generated JSON-RPC services.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::extract::rejection::ExtensionRejection;
+use axum::http::header::{CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use ras_jsonrpc_types::{JsonRpcError, JsonRpcRequest, JsonRpcResponse, error_codes};
+use serde_json::value::RawValue;
+
+use crate::batch::{DEFAULT_MAX_BATCH_CONCURRENCY, dispatch_batch, is_notification};
+use crate::deprecation::{add_warning_headers, deprecation_warnings};
+use crate::streaming::{FrameStream, stream_response};
+use crate::{RequestOrigin, TrustedProxies, client_ip};
+
+/// Default cap on the size of a JSON-RPC HTTP body, matching axum's default
+/// body limit.
+pub const DEFAULT_MAX_REQUEST_SIZE: usize = 2 * 1024 * 1024;
+
+/// `Content-Type` of JSON-RPC HTTP responses.
+pub const JSON_CONTENT_TYPE: &str = "application/json; charset=utf-8";
+
+/// A JSON-RPC service that can be served from an HTTP endpoint.
+///
+/// Implemented by the builders generated by `jsonrpc_service!`, so several
+/// services can share one route through [`JsonRpcRouter`].
+pub trait JsonRpcService: Send + Sync + 'static {
+    /// Wire-level names of every method this service dispatches.
+    fn method_names(&self) -> Vec<String>;
+
+    /// Handle a single raw request object and produce its response.
+    ///
+    /// `request_size` is the size in bytes of the request object as it was
+    /// received, used for per-method size limits and payload metrics.
+    fn dispatch<'a>(
+        &'a self,
+        headers: &'a HeaderMap,
+        request: serde_json::Value,
+        request_size: usize,
+    ) -> BoxFuture<'a, JsonRpcResponse>;
+
+    /// Deprecation warning for a call of `method`, sent in a `Warning` response
+    /// header and logged. `None` for methods that are not deprecated or when
+    /// the service does not emit deprecation warnings.
+    fn deprecation_warning(&self, _method: &str) -> Option<String> {
+        None
+    }
+
+    /// Whether `method` streams its result, so single HTTP requests of it are
+    /// handled by [`dispatch_stream`](Self::dispatch_stream).
+    fn is_streaming_method(&self, _method: &str) -> bool {
+        false
+    }
+
+    /// Handle a single request of a streaming method, producing the frames of
+    /// its result or an error response sent instead of them.
+    ///
+    /// `request_size` is as for [`dispatch`](Self::dispatch).
+    fn dispatch_stream<'a>(
+        &'a self,
+        _headers: &'a HeaderMap,
+        request: serde_json::Value,
+        _request_size: usize,
+    ) -> BoxFuture<'a, Result<FrameStream, JsonRpcResponse>> {
+        let id = request.get("id").cloned();
+        Box::pin(async move { Err(JsonRpcResponse::error(JsonRpcError::invalid_request(), id)) })
+    }
+}
+
+/// Read and handle a JSON-RPC HTTP POST body.
+///
+/// Unless `lenient_content_type` is set, requests whose `Content-Type` is not
+/// JSON (see [`is_json_content_type`]) are rejected with HTTP 415 and a parse
+/// error. Bodies larger than `max_request_size` (or
+/// [`DEFAULT_MAX_REQUEST_SIZE`]) are rejected with HTTP 413 and an Invalid
+/// Request error before any JSON is parsed, and bodies that are not UTF-8 get a
+/// parse error; everything else is handled by [`handle_http_body`].
+pub async fn handle_http_request<S>(
+    service: &S,
+    headers: &HeaderMap,
+    body: Body,
+    max_batch_concurrency: usize,
+    max_request_size: Option<usize>,
+    lenient_content_type: bool,
+) -> Response
+where
+    S: JsonRpcService + ?Sized,
+{
+    if !lenient_content_type {
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        if !content_type.is_some_and(is_json_content_type) {
+            let response =
+                JsonRpcResponse::error(JsonRpcError::unsupported_content_type(content_type), None);
+            return json_response(StatusCode::UNSUPPORTED_MEDIA_TYPE, &response);
+        }
+    }
+
+    let max_request_size = max_request_size.unwrap_or(DEFAULT_MAX_REQUEST_SIZE);
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+
+    // A declared length over the limit is rejected without reading the body
+    let body = match content_length {
+        Some(size) if size > max_request_size => None,
+        _ => axum::body::to_bytes(body, max_request_size).await.ok(),
+    };
+    let Some(body) = body else {
+        let response = JsonRpcResponse::error(
+            JsonRpcError::request_too_large(content_length, max_request_size),
+            None,
+        );
+        return json_response(StatusCode::PAYLOAD_TOO_LARGE, &response);
+    };
+
+    match std::str::from_utf8(&body) {
+        Ok(body) => handle_http_body(service, headers, body, max_batch_concurrency).await,
+        Err(error) => single_response(JsonRpcResponse::error(
+            JsonRpcError::invalid_utf8(error.valid_up_to()),
+            None,
+        )),
+    }
+}
+
+/// Whether a `Content-Type` header value declares a JSON body.
+///
+/// Accepts `application/json` and `+json` media types such as
+/// `application/json-rpc+json`, with an optional UTF-8 `charset` parameter.
+pub fn is_json_content_type(content_type: &str) -> bool {
+    let mut parts = content_type.split(';');
+    let media_type = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let is_json = media_type == "application/json"
+        || (media_type.starts_with("application/") && media_type.ends_with("+json"));
+
+    is_json
+        && parts.all(|parameter| match parameter.split_once('=') {
+            Some((name, value)) if name.trim().eq_ignore_ascii_case("charset") => {
+                let charset = value.trim().trim_matches('"');
+                charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("utf8")
+            }
+            _ => true,
+        })
+}
+
+/// Handle the body of a JSON-RPC HTTP POST.
+///
+/// Parses single requests and batches, answers notifications with HTTP 204 and
+/// maps authentication errors on single responses to 401/403. Calls of
+/// deprecated methods add a `Warning` header (see
+/// [`JsonRpcService::deprecation_warning`]). Single calls of streaming methods
+/// are answered with newline-delimited JSON frames as the result is produced.
+pub async fn handle_http_body<S>(
+    service: &S,
+    headers: &HeaderMap,
+    body: &str,
+    max_batch_concurrency: usize,
+) -> Response
+where
+    S: JsonRpcService + ?Sized,
+{
+    if body.trim_start().starts_with('[') {
+        // Batch entries are kept raw first so each one's size is known
+        let entries: Vec<&RawValue> = match serde_json::from_str(body) {
+            Ok(entries) => entries,
+            Err(_) => {
+                return single_response(JsonRpcResponse::error(JsonRpcError::parse_error(), None));
+            }
+        };
+
+        // An empty batch is an invalid request and gets a single error object
+        if entries.is_empty() {
+            return single_response(JsonRpcResponse::error(
+                JsonRpcError::invalid_request(),
+                None,
+            ));
+        }
+
+        let requests: Vec<(serde_json::Value, usize)> = entries
+            .into_iter()
+            .map(|entry| {
+                let request = serde_json::from_str(entry.get()).unwrap_or_default();
+                (request, entry.get().len())
+            })
+            .collect();
+        let warnings = deprecation_warnings(
+            service,
+            requests
+                .iter()
+                .filter_map(|(request, _)| request.get("method")?.as_str()),
+        );
+
+        let responses = dispatch_batch(
+            requests,
+            max_batch_concurrency,
+            |(request, request_size)| async move {
+                let is_notification = is_notification(&request);
+                let response = service.dispatch(headers, request, request_size).await;
+                (!is_notification).then_some(response)
+            },
+        )
+        .await;
+
+        // A batch made up only of notifications gets no response body
+        let response = if responses.is_empty() {
+            StatusCode::NO_CONTENT.into_response()
+        } else {
+            (
+                StatusCode::OK,
+                [(CONTENT_TYPE, JSON_CONTENT_TYPE)],
+                serde_json::to_string(&responses).unwrap_or_else(|_| "[]".to_string()),
+            )
+                .into_response()
+        };
+        return add_warning_headers(response, &warnings);
+    }
+
+    let request: serde_json::Value = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(_) => {
+            return single_response(JsonRpcResponse::error(JsonRpcError::parse_error(), None));
+        }
+    };
+
+    let warnings = deprecation_warnings(
+        service,
+        request.get("method").and_then(|method| method.as_str()),
+    );
+
+    let is_streaming = request
+        .get("method")
+        .and_then(|method| method.as_str())
+        .is_some_and(|method| service.is_streaming_method(method));
+
+    let response = if is_notification(&request) {
+        // Notifications are still dispatched, but never answered
+        service.dispatch(headers, request, body.len()).await;
+        StatusCode::NO_CONTENT.into_response()
+    } else if is_streaming {
+        match service.dispatch_stream(headers, request, body.len()).await {
+            Ok(frames) => stream_response(frames),
+            Err(response) => single_response(response),
+        }
+    } else {
+        single_response(service.dispatch(headers, request, body.len()).await)
+    };
+    add_warning_headers(response, &warnings)
+}
+
+fn single_response(response: JsonRpcResponse) -> Response {
+    // Map authentication/authorization errors to appropriate HTTP status codes
+    // while maintaining JSON-RPC protocol compatibility
+    let status_code = match response.error.as_ref().map(|error| error.code) {
+        Some(error_codes::AUTHENTICATION_REQUIRED) => StatusCode::UNAUTHORIZED,
+        Some(error_codes::INSUFFICIENT_PERMISSIONS) => StatusCode::FORBIDDEN,
+        Some(error_codes::ACCOUNT_LOCKED) => StatusCode::FORBIDDEN,
+        Some(error_codes::RATE_LIMITED) => StatusCode::TOO_MANY_REQUESTS,
+        Some(error_codes::TOKEN_EXPIRED) => StatusCode::UNAUTHORIZED,
+        Some(error_codes::TOKEN_NOT_YET_VALID) => StatusCode::UNAUTHORIZED,
+        Some(error_codes::SERVER_BUSY) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK, // Other JSON-RPC errors still return 200 OK
+    };
+
+    let mut http_response = json_response(status_code, &response);
+    if let Some(seconds) = response.error.as_ref().and_then(retry_after_seconds) {
+        http_response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(seconds));
+    }
+    http_response
+}
+
+/// When to try again after a rate limited or locked out call, in whole
+/// seconds rounded up, if `error` says
+fn retry_after_seconds(error: &JsonRpcError) -> Option<u64> {
+    if !matches!(
+        error.code,
+        error_codes::RATE_LIMITED | error_codes::ACCOUNT_LOCKED
+    ) {
+        return None;
+    }
+    let retry_after_ms = error.data.as_ref()?.get("retry_after_ms")?.as_u64()?;
+    Some(retry_after_ms.div_ceil(1000))
+}
+
+fn json_response(status_code: StatusCode, response: &JsonRpcResponse) -> Response {
+    (
+        status_code,
+        [(CONTENT_TYPE, JSON_CONTENT_TYPE)],
+        serde_json::to_string(response).unwrap_or_else(|_| "{}".to_string()),
+    )
+        .into_response()
+}
+
+/// Serves several JSON-RPC services from a single POST route.
+///
+/// Requests are routed by method name to the service that declares it; a method
+/// no service declares gets a method-not-found error. Give each service its own
+/// `namespace` so their method names cannot collide.
+///
+/// ```rust,ignore
+/// let router = JsonRpcRouter::new("/rpc")
+///     .service(TasksBuilder::new(TasksImpl).auth_provider(auth.clone()))
+///     .service(UsersBuilder::new(UsersImpl).auth_provider(auth))
+///     .build()?;
+/// ```
+pub struct JsonRpcRouter {
+    base_url: String,
+    services: Vec<Arc<dyn JsonRpcService>>,
+    max_batch_concurrency: usize,
+    max_request_size: Option<usize>,
+    lenient_content_type: bool,
+    trusted_proxies: TrustedProxies,
+}
+
+impl JsonRpcRouter {
+    /// Create a router serving its services at `base_url`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            services: Vec::new(),
+            max_batch_concurrency: DEFAULT_MAX_BATCH_CONCURRENCY,
+            max_request_size: None,
+            lenient_content_type: false,
+            trusted_proxies: TrustedProxies::none(),
+        }
+    }
+
+    /// Add a service to the shared route.
+    ///
+    /// The service's own base URL and explorer routes are not used.
+    pub fn service<S: JsonRpcService>(mut self, service: S) -> Self {
+        self.services.push(Arc::new(service));
+        self
+    }
+
+    /// Set how many entries of a batch request are dispatched concurrently.
+    pub fn with_max_batch_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_batch_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Reject HTTP bodies larger than `bytes` before they are parsed.
+    ///
+    /// Defaults to [`DEFAULT_MAX_REQUEST_SIZE`].
+    pub fn with_max_request_size(mut self, bytes: usize) -> Self {
+        self.max_request_size = Some(bytes);
+        self
+    }
+
+    /// Accept request bodies whatever their `Content-Type`.
+    ///
+    /// By default only JSON media types are accepted and other requests get
+    /// HTTP 415.
+    pub fn with_lenient_content_type(mut self, lenient: bool) -> Self {
+        self.lenient_content_type = lenient;
+        self
+    }
+
+    /// Believe the `X-Forwarded-For` addresses appended by `proxies` when
+    /// setting the client address of request contexts.
+    ///
+    /// The services' own trusted proxies are not used. By default none are
+    /// trusted and the client is the address that connected.
+    pub fn with_trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = proxies;
+        self
+    }
+
+    /// Build the axum router.
+    ///
+    /// Fails if two services declare the same wire method name.
+    pub fn build(self) -> Result<axum::Router, String> {
+        let merged = Arc::new(MergedServices::new(self.services)?);
+        let max_batch_concurrency = self.max_batch_concurrency;
+        let max_request_size = self.max_request_size;
+        let lenient_content_type = self.lenient_content_type;
+        let trusted_proxies = self.trusted_proxies;
+        let route = self.base_url.clone();
+
+        let rpc_handler = axum::routing::post(
+            move |peer: Result<ConnectInfo<SocketAddr>, ExtensionRejection>,
+                  headers: HeaderMap,
+                  body: Body| {
+                let merged = merged.clone();
+                let peer = peer.ok().map(|ConnectInfo(peer)| peer.ip());
+                let origin =
+                    RequestOrigin::new(client_ip(&headers, peer, &trusted_proxies), route.clone());
+                origin.scope(async move {
+                    handle_http_request(
+                        &*merged,
+                        &headers,
+                        body,
+                        max_batch_concurrency,
+                        max_request_size,
+                        lenient_content_type,
+                    )
+                    .await
+                })
+            },
+        );
+
+        Ok(axum::Router::new().route(&self.base_url, rpc_handler))
+    }
+}
+
+struct MergedServices {
+    services: Vec<Arc<dyn JsonRpcService>>,
+    owners: HashMap<String, usize>,
+}
+
+impl MergedServices {
+    fn new(services: Vec<Arc<dyn JsonRpcService>>) -> Result<Self, String> {
+        let mut owners = HashMap::new();
+        for (index, service) in services.iter().enumerate() {
+            for method in service.method_names() {
+                if owners.insert(method.clone(), index).is_some() {
+                    return Err(format!(
+                        "JSON-RPC method `{method}` is declared by more than one service"
+                    ));
+                }
+            }
+        }
+
+        Ok(Self { services, owners })
+    }
+}
+
+impl JsonRpcService for MergedServices {
+    fn method_names(&self) -> Vec<String> {
+        self.owners.keys().cloned().collect()
+    }
+
+    fn dispatch<'a>(
+        &'a self,
+        headers: &'a HeaderMap,
+        request: serde_json::Value,
+        request_size: usize,
+    ) -> BoxFuture<'a, JsonRpcResponse> {
+        Box::pin(async move {
+            let owner = request
+                .get("method")
+                .and_then(|method| method.as_str())
+                .and_then(|method| self.owners.get(method))
+                .map(|&index| &self.services[index]);
+
+            if let Some(service) = owner {
+                return service.dispatch(headers, request, request_size).await;
+            }
+
+            match serde_json::from_value::<JsonRpcRequest>(request) {
+                Ok(request) if request.jsonrpc == "2.0" => JsonRpcResponse::error(
+                    JsonRpcError::method_not_found(&request.method),
+                    request.id,
+                ),
+                Ok(request) => JsonRpcResponse::error(JsonRpcError::invalid_request(), request.id),
+                Err(_) => JsonRpcResponse::error(JsonRpcError::invalid_request(), None),
+            }
+        })
+    }
+
+    fn deprecation_warning(&self, method: &str) -> Option<String> {
+        let &index = self.owners.get(method)?;
+        self.services[index].deprecation_warning(method)
+    }
+
+    fn is_streaming_method(&self, method: &str) -> bool {
+        self.owners
+            .get(method)
+            .is_some_and(|&index| self.services[index].is_streaming_method(method))
+    }
+
+    fn dispatch_stream<'a>(
+        &'a self,
+        headers: &'a HeaderMap,
+        request: serde_json::Value,
+        request_size: usize,
+    ) -> BoxFuture<'a, Result<FrameStream, JsonRpcResponse>> {
+        let owner = request
+            .get("method")
+            .and_then(|method| method.as_str())
+            .and_then(|method| self.owners.get(method))
+            .map(|&index| &self.services[index]);
+
+        match owner {
+            Some(service) => service.dispatch_stream(headers, request, request_size),
+            None => {
+                let id = request.get("id").cloned();
+                Box::pin(
+                    async move { Err(JsonRpcResponse::error(JsonRpcError::invalid_request(), id)) },
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct Fixed {
+        methods: &'static [&'static str],
+    }
+
+    impl JsonRpcService for Fixed {
+        fn method_names(&self) -> Vec<String> {
+            self.methods.iter().map(|name| name.to_string()).collect()
+        }
+
+        fn dispatch<'a>(
+            &'a self,
+            _headers: &'a HeaderMap,
+            request: serde_json::Value,
+            _request_size: usize,
+        ) -> BoxFuture<'a, JsonRpcResponse> {
+            Box::pin(async move {
+                let method = request["method"].clone();
+                JsonRpcResponse::success(method, request.get("id").cloned())
+            })
+        }
+    }
+
+    fn merged() -> MergedServices {
+        MergedServices::new(vec![
+            Arc::new(Fixed {
+                methods: &["tasks.create"],
+            }),
+            Arc::new(Fixed {
+                methods: &["users.create"],
+            }),
+        ])
+        .expect("distinct method names")
+    }
+
+    #[tokio::test]
+    async fn merged_services_route_by_method_name() {
+        let headers = HeaderMap::new();
+        let services = merged();
+
+        let response = services
+            .dispatch(
+                &headers,
+                json!({"jsonrpc": "2.0", "method": "users.create", "id": 1}),
+                0,
+            )
+            .await;
+        assert_eq!(response.result, Some(json!("users.create")));
+
+        let response = services
+            .dispatch(
+                &headers,
+                json!({"jsonrpc": "2.0", "method": "create", "id": 2}),
+                0,
+            )
+            .await;
+        let error = response.error.expect("method not found");
+        assert_eq!(error.code, error_codes::METHOD_NOT_FOUND);
+        assert_eq!(response.id, Some(json!(2)));
+    }
+
+    #[tokio::test]
+    async fn request_size_limit_is_inclusive() {
+        let service = Fixed {
+            methods: &["create"],
+        };
+        let body = r#"{"jsonrpc":"2.0","method":"create","id":1}"#;
+        let headers = HeaderMap::new();
+
+        let response = handle_http_request(
+            &service,
+            &headers,
+            Body::from(body),
+            1,
+            Some(body.len()),
+            true,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = handle_http_request(
+            &service,
+            &headers,
+            Body::from(body),
+            1,
+            Some(body.len() - 1),
+            true,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn json_content_types_are_recognised() {
+        assert!(is_json_content_type("application/json"));
+        assert!(is_json_content_type("Application/JSON; charset=UTF-8"));
+        assert!(is_json_content_type("application/json-rpc+json"));
+        assert!(is_json_content_type("application/json; charset=\"utf-8\""));
+
+        assert!(!is_json_content_type("text/plain"));
+        assert!(!is_json_content_type("application/x-www-form-urlencoded"));
+        assert!(!is_json_content_type("application/json; charset=latin1"));
+        assert!(!is_json_content_type("text/json+xml"));
+    }
+
+    #[tokio::test]
+    async fn non_json_content_types_are_rejected_unless_lenient() {
+        let service = Fixed {
+            methods: &["create"],
+        };
+        let body = r#"{"jsonrpc":"2.0","method":"create","id":1}"#;
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "text/plain".parse().unwrap());
+
+        let response =
+            handle_http_request(&service, &headers, Body::from(body), 1, None, false).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(response.headers()[CONTENT_TYPE], JSON_CONTENT_TYPE);
+
+        let response =
+            handle_http_request(&service, &headers, Body::from(body), 1, None, true).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], JSON_CONTENT_TYPE);
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_bodies_get_a_parse_error() {
+        let service = Fixed {
+            methods: &["create"],
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        let body: &[u8] = b"{\"method\":\"cr\xffeate\"}";
+
+        let response =
+            handle_http_request(&service, &headers, Body::from(body), 1, None, false).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let response: JsonRpcResponse = serde_json::from_slice(&body).unwrap();
+        let error = response.error.expect("parse error");
+        assert_eq!(error.code, error_codes::PARSE_ERROR);
+        assert_eq!(error.data.unwrap()["valid_up_to"], 13);
+    }
+
+    #[test]
+    fn refused_attempts_say_when_to_retry() {
+        let response = single_response(JsonRpcResponse::error(
+            JsonRpcError::rate_limited(std::time::Duration::from_millis(1500)),
+            Some(json!(1)),
+        ));
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "2");
+
+        let response = single_response(JsonRpcResponse::error(
+            JsonRpcError::account_locked(Some(std::time::Duration::from_secs(30))),
+            Some(json!(2)),
+        ));
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.headers()[RETRY_AFTER], "30");
+
+        // Accounts locked until an administrator unlocks them
+        let response = single_response(JsonRpcResponse::error(
+            JsonRpcError::account_locked(None),
+            Some(json!(3)),
+        ));
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!response.headers().contains_key(RETRY_AFTER));
+    }
+
+    #[test]
+    fn router_rejects_colliding_method_names() {
+        let result = JsonRpcRouter::new("/rpc")
+            .service(Fixed {
+                methods: &["create"],
+            })
+            .service(Fixed {
+                methods: &["create"],
+            })
+            .build();
+
+        assert!(result.unwrap_err().contains("`create`"));
+    }
+}
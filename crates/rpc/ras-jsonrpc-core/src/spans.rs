@@ -0,0 +1,75 @@
+//! Helpers for the request spans created by generated JSON-RPC dispatch.
+
+use std::time::Duration;
+
+use ras_jsonrpc_types::{JsonRpcResponse, error_codes};
+
+/// Renders a request id for the `request_id` span field.
+pub fn span_request_id(id: Option<&serde_json::Value>) -> String {
+    match id {
+        Some(serde_json::Value::String(id)) => id.clone(),
+        Some(id) => id.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Records the outcome of a dispatched request on its span.
+///
+/// Sets `permission`, `outcome`, `error_code` and `duration_ms`, and marks the
+/// span as failed through `otel.status_code` when the response is an error.
+pub fn record_span_outcome(
+    span: &tracing::Span,
+    response: &JsonRpcResponse,
+    requires_auth: bool,
+    duration: Duration,
+) {
+    let error_code = response.error.as_ref().map(|error| error.code);
+
+    let permission = match error_code {
+        Some(
+            error_codes::AUTHENTICATION_REQUIRED
+            | error_codes::TOKEN_EXPIRED
+            | error_codes::TOKEN_NOT_YET_VALID,
+        ) => "unauthenticated",
+        Some(error_codes::INSUFFICIENT_PERMISSIONS | error_codes::ACCOUNT_LOCKED) => "denied",
+        _ if requires_auth => "granted",
+        _ => "not_required",
+    };
+
+    span.record("permission", permission);
+    span.record("duration_ms", duration.as_millis() as u64);
+
+    match error_code {
+        Some(code) => {
+            span.record("outcome", "error");
+            span.record("error_code", code);
+            span.record("otel.status_code", "ERROR");
+        }
+        None => {
+            span.record("outcome", "success");
+        }
+    }
+}
+
+/// Makes the caller's span, sent in the `traceparent` header, the parent of `span`.
+///
+/// Only has an effect with the `otel` feature enabled.
+pub fn set_span_parent(span: &tracing::Span, headers: &axum::http::HeaderMap) {
+    #[cfg(feature = "otel")]
+    ras_observability_core::set_span_parent(span, headers);
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, headers);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn request_ids_render_without_json_quotes() {
+        assert_eq!(span_request_id(Some(&json!("abc"))), "abc");
+        assert_eq!(span_request_id(Some(&json!(7))), "7");
+        assert_eq!(span_request_id(None), "");
+    }
+}
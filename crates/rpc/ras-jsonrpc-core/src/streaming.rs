@@ -0,0 +1,201 @@
+//! Streamed results of `STREAMING` methods in generated JSON-RPC services.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use ras_auth_core::{AuthenticatedUser, scope_user};
+use ras_jsonrpc_types::{JsonRpcError, JsonRpcResponse, NDJSON_CONTENT_TYPE, partial_result_frame};
+use serde::Serialize;
+use tracing::Instrument;
+
+use crate::handler_error::jsonrpc_error_from_handler;
+
+/// Chunks returned by the handler of a `STREAMING` method.
+pub type ChunkStream<T> = BoxStream<'static, Result<T, Box<dyn std::error::Error + Send + Sync>>>;
+
+/// Newline-terminated JSON frames of a streamed response.
+pub type FrameStream = BoxStream<'static, String>;
+
+/// Encode the chunks of the call with `id` as frames.
+///
+/// Each chunk becomes a partial result notification; the stream ends with a
+/// `null` result once the chunks run out, or with the error of the first
+/// chunk that fails.
+pub fn stream_frames<T>(id: Option<serde_json::Value>, chunks: ChunkStream<T>) -> FrameStream
+where
+    T: Serialize + Send + 'static,
+{
+    futures::stream::unfold(Some(chunks), move |chunks| {
+        let id = id.clone();
+        async move {
+            let mut chunks = chunks?;
+            let end = match chunks.next().await {
+                Some(Ok(chunk)) => match serde_json::to_value(chunk) {
+                    Ok(chunk) => {
+                        let frame = frame_line(&partial_result_frame(id, chunk));
+                        return Some((frame, Some(chunks)));
+                    }
+                    Err(e) => {
+                        JsonRpcResponse::error(JsonRpcError::internal_error(e.to_string()), id)
+                    }
+                },
+                Some(Err(e)) => JsonRpcResponse::error(jsonrpc_error_from_handler(e), id),
+                None => JsonRpcResponse::success(serde_json::Value::Null, id),
+            };
+            Some((frame_line(&end), None))
+        }
+    })
+    .boxed()
+}
+
+/// Produce `chunks` as the handler of a unary call runs: as `user`, seen
+/// through `current_user`, and inside `span`.
+pub fn scope_stream<T>(
+    chunks: ChunkStream<T>,
+    user: Option<Arc<AuthenticatedUser>>,
+    span: tracing::Span,
+) -> ChunkStream<T>
+where
+    T: Send + 'static,
+{
+    futures::stream::unfold(chunks, move |mut chunks| {
+        let user = user.clone();
+        let span = span.clone();
+        async move {
+            let chunk = scope_user(user, chunks.next()).instrument(span).await?;
+            Some((chunk, chunks))
+        }
+    })
+    .boxed()
+}
+
+/// Call `on_end` with the final response and the number of bytes sent once
+/// the last of `frames` has been sent.
+///
+/// Whatever `on_end` captures, such as a concurrency permit, is held until
+/// then. A stream dropped before its end, when the client disconnects, drops
+/// `on_end` without calling it.
+pub fn on_stream_end<F, Fut>(frames: FrameStream, on_end: F) -> FrameStream
+where
+    F: FnOnce(JsonRpcResponse, usize) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    struct State<F> {
+        frames: FrameStream,
+        on_end: F,
+        last: Option<String>,
+        size: usize,
+    }
+
+    let state = State {
+        frames,
+        on_end,
+        last: None,
+        size: 0,
+    };
+    futures::stream::unfold(Some(state), |state| async move {
+        let mut state = state?;
+        match state.frames.next().await {
+            Some(frame) => {
+                state.size += frame.len();
+                state.last = Some(frame.clone());
+                Some((frame, Some(state)))
+            }
+            None => {
+                // The final frame is the response ending the stream
+                let response = state
+                    .last
+                    .and_then(|frame| serde_json::from_str(&frame).ok())
+                    .unwrap_or_else(|| JsonRpcResponse::success(serde_json::Value::Null, None));
+                (state.on_end)(response, state.size).await;
+                None
+            }
+        }
+    })
+    .boxed()
+}
+
+fn frame_line(frame: &impl Serialize) -> String {
+    let mut line = serde_json::to_string(frame).unwrap_or_else(|_| "{}".to_string());
+    line.push('\n');
+    line
+}
+
+/// Newline-delimited JSON response streaming `frames` as they are produced.
+pub(crate) fn stream_response(frames: FrameStream) -> Response {
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, NDJSON_CONTENT_TYPE)],
+        Body::from_stream(frames.map(Ok::<_, Infallible>)),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(chunks: Vec<Result<u32, &'static str>>) -> Vec<serde_json::Value> {
+        let chunks =
+            futures::stream::iter(chunks.into_iter().map(|chunk| chunk.map_err(Into::into)));
+        let lines: Vec<String> = futures::executor::block_on(
+            stream_frames(Some(serde_json::json!(7)), chunks.boxed()).collect(),
+        );
+        lines
+            .iter()
+            .map(|line| {
+                assert!(line.ends_with('\n'));
+                serde_json::from_str(line).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunks_are_followed_by_a_final_result() {
+        let frames = frames(vec![Ok(1), Ok(2)]);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0]["method"], "rpc.partial");
+        assert_eq!(
+            frames[0]["params"],
+            serde_json::json!({ "id": 7, "chunk": 1 })
+        );
+        assert_eq!(frames[1]["params"]["chunk"], 2);
+        assert_eq!(
+            frames[2],
+            serde_json::json!({ "jsonrpc": "2.0", "result": null, "id": 7 })
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_end_reports_the_final_response_and_size() {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let frames = stream_frames(
+            Some(serde_json::json!(7)),
+            futures::stream::iter([Ok(1u32)]).boxed(),
+        );
+        let frames = on_stream_end(frames, move |response, size| async move {
+            let _ = sender.send((response, size));
+        });
+
+        let lines: Vec<String> = frames.collect().await;
+        let (response, size) = receiver.await.unwrap();
+        assert_eq!(size, lines.iter().map(String::len).sum::<usize>());
+        assert!(response.error.is_none());
+        assert_eq!(response.id, Some(serde_json::json!(7)));
+    }
+
+    #[test]
+    fn a_failing_chunk_ends_the_stream_with_its_error() {
+        let frames = frames(vec![Ok(1), Err("boom"), Ok(3)]);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1]["id"], 7);
+        assert!(frames[1]["error"]["code"].is_i64());
+    }
+}
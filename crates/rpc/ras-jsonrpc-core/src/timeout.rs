@@ -0,0 +1,61 @@
+//! Method timeouts for generated JSON-RPC method dispatch.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use ras_jsonrpc_types::JsonRpcError;
+
+/// Run a method handler within `timeout`, if one is set.
+///
+/// A handler that runs over budget is dropped, cancelling it at its current
+/// await point, and a `request_timeout` error carrying the elapsed time is
+/// returned instead of its result.
+pub async fn with_method_timeout<F: Future>(
+    handler: F,
+    timeout: Option<Duration>,
+) -> Result<F::Output, JsonRpcError> {
+    let Some(timeout) = timeout else {
+        return Ok(handler.await);
+    };
+
+    let start = Instant::now();
+    tokio::time::timeout(timeout, handler)
+        .await
+        .map_err(|_| JsonRpcError::request_timeout(start.elapsed(), timeout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ras_jsonrpc_types::error_codes;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test]
+    async fn handlers_within_budget_return_their_output() {
+        let output = with_method_timeout(async { 7 }, Some(Duration::from_secs(1))).await;
+        assert_eq!(output.unwrap(), 7);
+        assert_eq!(with_method_timeout(async { 8 }, None).await.unwrap(), 8);
+    }
+
+    #[tokio::test]
+    async fn slow_handlers_are_dropped() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let handler = {
+            let finished = finished.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                finished.store(true, Ordering::SeqCst);
+            }
+        };
+
+        let error = with_method_timeout(handler, Some(Duration::from_millis(20)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, error_codes::REQUEST_TIMEOUT);
+        assert_eq!(error.data.unwrap()["timeout_ms"], serde_json::json!(20));
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+}
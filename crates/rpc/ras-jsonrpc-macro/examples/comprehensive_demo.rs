@@ -7,7 +7,7 @@
 //! - Various authentication requirements
 //! - Multiple permission combinations
 
-use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
@@ -98,6 +98,7 @@ impl AuthProvider for DemoAuthProvider {
                         user_id: "admin-user".to_string(),
                         permissions,
                         metadata: None,
+                        kind: PrincipalKind::User,
                     })
                 }
                 "user-token" => {
@@ -108,6 +109,7 @@ impl AuthProvider for DemoAuthProvider {
                         user_id: "regular-user".to_string(),
                         permissions,
                         metadata: None,
+                        kind: PrincipalKind::User,
                     })
                 }
                 "service-token" => {
@@ -117,6 +119,7 @@ impl AuthProvider for DemoAuthProvider {
                         user_id: "service-user".to_string(),
                         permissions,
                         metadata: None,
+                        kind: PrincipalKind::User,
                     })
                 }
                 _ => Err(AuthError::InvalidToken),
@@ -1,4 +1,4 @@
-use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_jsonrpc_macro::jsonrpc_service;
 #[cfg(all(feature = "server", feature = "client"))]
 use serde::{Deserialize, Serialize};
@@ -105,6 +105,7 @@ impl AuthProvider for MockAuthProvider {
                         "username": "demo_user",
                         "email": "demo@example.com"
                     })),
+                    kind: PrincipalKind::User,
                 })
             } else if token == "admin-token" {
                 let mut permissions = HashSet::new();
@@ -119,6 +120,7 @@ impl AuthProvider for MockAuthProvider {
                         "username": "admin",
                         "email": "admin@example.com"
                     })),
+                    kind: PrincipalKind::User,
                 })
             } else {
                 Err(AuthError::InvalidToken)
@@ -1,7 +1,7 @@
 //! Example demonstrating the trait-based JSON-RPC service setup.
 //! All methods must be implemented by the generated trait.
 
-use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use serde::{Deserialize, Serialize};
 
 // Example types
@@ -27,6 +27,7 @@ impl AuthProvider for DemoAuthProvider {
                     user_id: "demo-user".to_string(),
                     permissions: ["user".to_string()].into_iter().collect(),
                     metadata: None,
+                    kind: PrincipalKind::User,
                 })
             } else {
                 Err(AuthError::InvalidToken)
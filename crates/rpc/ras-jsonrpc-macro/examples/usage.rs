@@ -1,4 +1,4 @@
-use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_jsonrpc_macro::jsonrpc_service;
 use serde::{Deserialize, Serialize};
 
@@ -24,6 +24,7 @@ impl AuthProvider for MyAuthProvider {
                     user_id: "test-user".to_string(),
                     permissions: ["admin".to_string()].into_iter().collect(),
                     metadata: None,
+                    kind: PrincipalKind::User,
                 })
             } else {
                 Err(AuthError::InvalidToken)
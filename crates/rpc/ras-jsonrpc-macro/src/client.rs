@@ -6,6 +6,8 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
     let service_name = &service_def.service_name;
     let client_name = quote::format_ident!("{}Client", service_name);
     let client_builder_name = quote::format_ident!("{}ClientBuilder", service_name);
+    let batch_name = quote::format_ident!("{}Batch", service_name);
+    let batch_results_name = quote::format_ident!("{}BatchResults", service_name);
 
     // Generate client methods
     let client_methods = service_def
@@ -18,6 +20,22 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
         .iter()
         .flat_map(generate_client_methods_with_timeout_for_method);
 
+    let notify_methods = service_def
+        .methods
+        .iter()
+        .flat_map(generate_notify_methods_for_method);
+
+    let batch_methods = service_def
+        .methods
+        .iter()
+        .flat_map(generate_batch_methods_for_method);
+
+    let stream_helpers = if service_def.methods.iter().any(|method| method.streaming) {
+        generate_stream_helpers()
+    } else {
+        quote! {}
+    };
+
     let output = quote! {
         /// Generated client for the JSON-RPC service
         #[derive(Clone)]
@@ -26,12 +44,21 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
             server_url: String,
             bearer_token: Option<String>,
             default_timeout: Option<std::time::Duration>,
+            retry_policy: Option<ras_jsonrpc_types::RetryPolicy>,
+            retry_all_methods: bool,
+            transport: Option<std::sync::Arc<dyn ras_jsonrpc_types::ClientTransport>>,
+            rate_limiter: Option<ras_jsonrpc_types::RateLimiter>,
+            next_id: std::sync::Arc<std::sync::atomic::AtomicU64>,
         }
 
         /// Builder for the JSON-RPC client
         pub struct #client_builder_name {
             server_url: Option<String>,
             timeout: Option<std::time::Duration>,
+            retry_policy: Option<ras_jsonrpc_types::RetryPolicy>,
+            retry_all_methods: bool,
+            transport: Option<std::sync::Arc<dyn ras_jsonrpc_types::ClientTransport>>,
+            rate_limiter: Option<ras_jsonrpc_types::RateLimiter>,
         }
 
         impl #client_builder_name {
@@ -40,6 +67,10 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
                 Self {
                     server_url: None,
                     timeout: None,
+                    retry_policy: None,
+                    retry_all_methods: false,
+                    transport: None,
+                    rate_limiter: None,
                 }
             }
 
@@ -55,9 +86,51 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
                 self
             }
 
+            /// Retry failed calls according to `policy`
+            ///
+            /// Only methods declared `IDEMPOTENT` are retried unless `with_retry_all_methods`
+            /// is enabled. A call's timeout bounds all of its attempts together.
+            pub fn with_retry(mut self, policy: ras_jsonrpc_types::RetryPolicy) -> Self {
+                self.retry_policy = Some(policy);
+                self
+            }
+
+            /// Apply the retry policy to methods not declared `IDEMPOTENT` as well
+            pub fn with_retry_all_methods(mut self, enabled: bool) -> Self {
+                self.retry_all_methods = enabled;
+                self
+            }
+
+            /// Send at most `requests_per_second` requests, spaced evenly
+            ///
+            /// Clones of the client share the budget.
+            pub fn with_rate_limit(mut self, requests_per_second: u32) -> Self {
+                self.rate_limiter = Some(ras_jsonrpc_types::RateLimiter::new(requests_per_second));
+                self
+            }
+
+            /// Share `limiter`'s request budget, e.g. with clients of other services
+            pub fn with_rate_limiter(mut self, limiter: ras_jsonrpc_types::RateLimiter) -> Self {
+                self.rate_limiter = Some(limiter);
+                self
+            }
+
+            /// Send calls through `transport` instead of posting them over HTTP
+            ///
+            /// The server URL is optional when a transport is set. The bearer token is
+            /// passed to the transport with every call.
+            pub fn with_transport(mut self, transport: impl ras_jsonrpc_types::ClientTransport) -> Self {
+                self.transport = Some(std::sync::Arc::new(transport));
+                self
+            }
+
             /// Build the client
             pub fn build(self) -> Result<#client_name, Box<dyn std::error::Error + Send + Sync>> {
-                let server_url = self.server_url.ok_or("Server URL is required")?;
+                let server_url = match self.server_url {
+                    Some(server_url) => server_url,
+                    None if self.transport.is_some() => String::new(),
+                    None => return Err("Server URL is required".into()),
+                };
 
                 let mut client_builder = reqwest::Client::builder();
 
@@ -73,6 +146,11 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
                     server_url,
                     bearer_token: None,
                     default_timeout: self.timeout,
+                    retry_policy: self.retry_policy,
+                    retry_all_methods: self.retry_all_methods,
+                    transport: self.transport,
+                    rate_limiter: self.rate_limiter,
+                    next_id: std::sync::Arc::new(std::sync::atomic::AtomicU64::new(1)),
                 })
             }
         }
@@ -88,25 +166,148 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
                 self.bearer_token.as_deref()
             }
 
+            /// Start a batch request
+            ///
+            /// Calls added to the batch are sent together in a single HTTP request by `send`,
+            /// or handed to the client's transport together.
+            pub fn batch(&self) -> #batch_name<'_> {
+                #batch_name {
+                    client: self,
+                    requests: Vec::new(),
+                    next_id: 1,
+                    serialization_error: None,
+                    timeout: None,
+                }
+            }
+
             #(#client_methods)*
             #(#client_methods_with_timeout)*
-
-            /// Make a JSON-RPC request with optional timeout
+            #(#notify_methods)*
+            #stream_helpers
+
+            /// Make a JSON-RPC request with optional timeout, retrying it if the
+            /// retry policy applies to the method
+            ///
+            /// Calls answered with HTTP 429 are retried whatever the method, after the
+            /// server's `Retry-After`, as long as the retry policy's attempts allow.
             async fn make_request<T, R>(
                 &self,
                 method: &str,
                 params: T,
                 timeout: Option<std::time::Duration>,
+                idempotent: bool,
             ) -> Result<R, Box<dyn std::error::Error + Send + Sync>>
             where
                 T: serde::Serialize,
                 R: serde::de::DeserializeOwned,
             {
+                let params = serde_json::to_value(params)?;
+                let retry_policy = self.retry_policy
+                    .as_ref()
+                    .filter(|_| idempotent || self.retry_all_methods);
+                let max_attempts = retry_policy.map_or(1, |policy| policy.max_attempts.max(1));
+                let rate_limited_attempts = self.retry_policy
+                    .as_ref()
+                    .map_or(ras_jsonrpc_types::DEFAULT_RATE_LIMITED_ATTEMPTS, |policy| policy.max_attempts.max(1));
+
+                // The call's timeout is a deadline shared by all attempts (not supported in WASM)
+                #[cfg(not(target_arch = "wasm32"))]
+                let deadline = timeout
+                    .or(self.default_timeout)
+                    .map(|timeout| std::time::Instant::now() + timeout);
+
+                let mut attempt = 1;
+                loop {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let attempt_timeout = deadline
+                        .map(|deadline| deadline.saturating_duration_since(std::time::Instant::now()));
+                    #[cfg(target_arch = "wasm32")]
+                    let attempt_timeout = timeout;
+
+                    let (error, failures, retry_after) = match self.send_request(method, &params, attempt_timeout).await {
+                        Ok(result) => return Ok(serde_json::from_value(result)?),
+                        Err(failure) => failure,
+                    };
+
+                    // Rate-limited calls never reached the handler, so any method may be retried
+                    let rate_limited = failures.contains(&ras_jsonrpc_types::RetryOn::HttpStatus(ras_jsonrpc_types::TOO_MANY_REQUESTS));
+                    let delay = if rate_limited {
+                        if attempt >= rate_limited_attempts {
+                            return Err(error);
+                        }
+                        let backoff = self.retry_policy.as_ref().map_or_else(
+                            || ras_jsonrpc_types::RetryPolicy::default().backoff,
+                            |policy| policy.backoff,
+                        );
+                        match ras_jsonrpc_types::rate_limited_delay(retry_after, backoff.delay(attempt)) {
+                            Some(delay) => delay,
+                            None => return Err(error),
+                        }
+                    } else {
+                        match retry_policy {
+                            Some(policy) if attempt < max_attempts && policy.should_retry(&failures) => policy.backoff.delay(attempt),
+                            _ => return Err(error),
+                        }
+                    };
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if deadline.is_some_and(|deadline| std::time::Instant::now() + delay >= deadline) {
+                        return Err(error);
+                    }
+
+                    if rate_limited {
+                        ras_jsonrpc_types::warn_rate_limited(method, delay, attempt);
+                        if let Some(limiter) = &self.rate_limiter {
+                            limiter.defer(delay);
+                        }
+                    }
+
+                    ras_jsonrpc_types::retry_sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+
+            /// Send one attempt of a JSON-RPC request under a fresh request id
+            ///
+            /// Failures come with the retryable conditions they match and the
+            /// server's `Retry-After`, if any.
+            async fn send_request(
+                &self,
+                method: &str,
+                params: &serde_json::Value,
+                timeout: Option<std::time::Duration>,
+            ) -> Result<serde_json::Value, (Box<dyn std::error::Error + Send + Sync>, Vec<ras_jsonrpc_types::RetryOn>, Option<std::time::Duration>)> {
+                if let Some(limiter) = &self.rate_limiter {
+                    limiter.acquire().await;
+                }
+
+                let id = self.next_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+                if let Some(transport) = &self.transport {
+                    let request = ras_jsonrpc_types::JsonRpcRequest::new(
+                        method.to_string(),
+                        Some(params.clone()),
+                        Some(serde_json::json!(id)),
+                    );
+                    let response = transport
+                        .call(request, self.call_options(timeout))
+                        .await
+                        .map_err(|failure| (failure.error, failure.retry_on, None))?;
+
+                    if let Some(error) = response.error {
+                        let failures = vec![ras_jsonrpc_types::RetryOn::ErrorCode(error.code)];
+                        return Err((error.into(), failures, None));
+                    }
+                    return response
+                        .result
+                        .ok_or_else(|| ("Missing result in JSON-RPC response".into(), Vec::new(), None));
+                }
+
                 let request_body = serde_json::json!({
                     "jsonrpc": "2.0",
                     "method": method,
                     "params": params,
-                    "id": 1
+                    "id": id
                 });
 
                 let mut request_builder = self.client
@@ -119,26 +320,275 @@ pub fn generate_client_code(service_def: &ServiceDefinition) -> proc_macro2::Tok
                     request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
                 }
 
+                // Propagate the current trace context (with the `otel` feature)
+                for (name, value) in ras_jsonrpc_types::trace_context_headers() {
+                    request_builder = request_builder.header(name, value);
+                }
+
+                // Tell the server how long we will wait, so it doesn't start work
+                // that can't finish in time
+                if let Some(timeout) = timeout {
+                    request_builder = request_builder.header(
+                        ras_jsonrpc_types::REQUEST_DEADLINE_HEADER,
+                        format!("{}ms", timeout.as_millis()),
+                    );
+                }
+
                 // Override timeout if provided (not supported in WASM)
                 #[cfg(not(target_arch = "wasm32"))]
                 if let Some(timeout) = timeout {
                     request_builder = request_builder.timeout(timeout);
                 }
 
+                let response = match request_builder.send().await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        let failures = if e.is_builder() {
+                            Vec::new()
+                        } else {
+                            vec![ras_jsonrpc_types::RetryOn::ConnectionError]
+                        };
+                        return Err((e.into(), failures, None));
+                    }
+                };
+
+                let status = response.status();
+                let mut failures = Vec::new();
+                if !status.is_success() {
+                    failures.push(ras_jsonrpc_types::RetryOn::HttpStatus(status.as_u16()));
+                }
+                let retry_after = ras_jsonrpc_types::retry_after(response.headers());
+
+                let json_response: serde_json::Value = match response.json().await {
+                    Ok(json_response) => json_response,
+                    Err(_) if !status.is_success() => {
+                        return Err((format!("HTTP error status {status}").into(), failures, retry_after));
+                    }
+                    Err(e) => return Err((e.into(), failures, retry_after)),
+                };
+
+                // Check for JSON-RPC error; callers can downcast to `JsonRpcError` to read its code and data
+                if let Some(error) = json_response.get("error") {
+                    let error: ras_jsonrpc_types::JsonRpcError = match serde_json::from_value(error.clone()) {
+                        Ok(error) => error,
+                        Err(e) => return Err((e.into(), failures, retry_after)),
+                    };
+                    failures.push(ras_jsonrpc_types::RetryOn::ErrorCode(error.code));
+                    return Err((error.into(), failures, retry_after));
+                }
+
+                // Extract result
+                json_response
+                    .get("result")
+                    .cloned()
+                    .ok_or_else(|| ("Missing result in JSON-RPC response".into(), failures, retry_after))
+            }
+
+            /// Send a JSON-RPC notification, which carries no id and gets no response
+            async fn make_notification<T>(
+                &self,
+                method: &str,
+                params: T,
+            ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+            where
+                T: serde::Serialize,
+            {
+                if let Some(limiter) = &self.rate_limiter {
+                    limiter.acquire().await;
+                }
+
+                if let Some(transport) = &self.transport {
+                    let request = ras_jsonrpc_types::JsonRpcRequest::new(
+                        method.to_string(),
+                        Some(serde_json::to_value(params)?),
+                        None,
+                    );
+                    return transport
+                        .notify(request, self.call_options(None))
+                        .await
+                        .map_err(|failure| failure.error);
+                }
+
+                let request_body = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": params
+                });
+
+                let mut request_builder = self.client
+                    .post(&self.server_url)
+                    .header("Content-Type", "application/json")
+                    .json(&request_body);
+
+                // Add bearer token if available
+                if let Some(token) = &self.bearer_token {
+                    request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
+                }
+
+                // Propagate the current trace context (with the `otel` feature)
+                for (name, value) in ras_jsonrpc_types::trace_context_headers() {
+                    request_builder = request_builder.header(name, value);
+                }
+
+                request_builder.send().await?.error_for_status()?;
+                Ok(())
+            }
+
+            /// Options passed to the transport with a call
+            fn call_options(&self, timeout: Option<std::time::Duration>) -> ras_jsonrpc_types::CallOptions<'_> {
+                ras_jsonrpc_types::CallOptions {
+                    bearer_token: self.bearer_token.as_deref(),
+                    timeout,
+                }
+            }
+        }
+
+        /// Batch of JSON-RPC calls sent in a single HTTP request
+        ///
+        /// Each call returns a typed handle that is used to look up its result in the
+        /// results returned by `send`.
+        pub struct #batch_name<'a> {
+            client: &'a #client_name,
+            requests: Vec<ras_jsonrpc_types::JsonRpcRequest>,
+            next_id: u64,
+            serialization_error: Option<serde_json::Error>,
+            timeout: Option<std::time::Duration>,
+        }
+
+        impl #batch_name<'_> {
+            #(#batch_methods)*
+
+            /// Set a timeout for the batch HTTP request
+            pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+                self.timeout = Some(timeout);
+                self
+            }
+
+            fn push<T, R>(&mut self, method: &str, params: T) -> ras_jsonrpc_types::BatchCall<R>
+            where
+                T: serde::Serialize,
+            {
+                let id = self.next_id;
+                self.next_id += 1;
+
+                match serde_json::to_value(params) {
+                    Ok(params) => self.requests.push(ras_jsonrpc_types::JsonRpcRequest::new(
+                        method.to_string(),
+                        Some(params),
+                        Some(serde_json::json!(id)),
+                    )),
+                    Err(e) => {
+                        self.serialization_error.get_or_insert(e);
+                    }
+                }
+
+                ras_jsonrpc_types::BatchCall::new(id)
+            }
+
+            /// Send all queued calls in a single HTTP request, or through the client's transport
+            pub async fn send(self) -> Result<#batch_results_name, Box<dyn std::error::Error + Send + Sync>> {
+                if let Some(e) = self.serialization_error {
+                    return Err(e.into());
+                }
+
+                if self.requests.is_empty() {
+                    return Ok(#batch_results_name {
+                        responses: std::collections::HashMap::new(),
+                    });
+                }
+
+                if let Some(limiter) = &self.client.rate_limiter {
+                    limiter.acquire().await;
+                }
+
+                if let Some(transport) = &self.client.transport {
+                    let responses = transport
+                        .batch(self.requests, self.client.call_options(self.timeout))
+                        .await
+                        .map_err(|failure| failure.error)?;
+                    return Ok(#batch_results_name::new(responses));
+                }
+
+                let mut request_builder = self.client.client
+                    .post(&self.client.server_url)
+                    .header("Content-Type", "application/json")
+                    .json(&self.requests);
+
+                // Add bearer token if available
+                if let Some(token) = &self.client.bearer_token {
+                    request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
+                }
+
+                // Propagate the current trace context (with the `otel` feature)
+                for (name, value) in ras_jsonrpc_types::trace_context_headers() {
+                    request_builder = request_builder.header(name, value);
+                }
+
+                if let Some(timeout) = self.timeout {
+                    request_builder = request_builder.header(
+                        ras_jsonrpc_types::REQUEST_DEADLINE_HEADER,
+                        format!("{}ms", timeout.as_millis()),
+                    );
+                }
+
+                // Override timeout if provided (not supported in WASM)
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(timeout) = self.timeout {
+                    request_builder = request_builder.timeout(timeout);
+                }
+
                 let response = request_builder.send().await?;
                 let json_response: serde_json::Value = response.json().await?;
 
-                // Check for JSON-RPC error
+                // Failures of the batch as a whole are reported as a single error object
                 if let Some(error) = json_response.get("error") {
-                    return Err(format!("JSON-RPC error: {}", error).into());
+                    let error: ras_jsonrpc_types::JsonRpcError = serde_json::from_value(error.clone())?;
+                    return Err(error.into());
                 }
 
-                // Extract result
-                let result = json_response.get("result")
-                    .ok_or("Missing result in JSON-RPC response")?;
+                let responses: Vec<ras_jsonrpc_types::JsonRpcResponse> = serde_json::from_value(json_response)?;
+                Ok(#batch_results_name::new(responses))
+            }
+        }
 
-                let deserialized_result: R = serde_json::from_value(result.clone())?;
-                Ok(deserialized_result)
+        /// Results of a sent batch, correlated with their calls by request id
+        #[derive(Debug, Clone)]
+        pub struct #batch_results_name {
+            responses: std::collections::HashMap<u64, ras_jsonrpc_types::JsonRpcResponse>,
+        }
+
+        impl #batch_results_name {
+            fn new(responses: Vec<ras_jsonrpc_types::JsonRpcResponse>) -> Self {
+                let responses = responses
+                    .into_iter()
+                    .filter_map(|response| {
+                        let id = response.id.as_ref()?.as_u64()?;
+                        Some((id, response))
+                    })
+                    .collect();
+
+                Self { responses }
+            }
+
+            /// Get the result of a call in the batch
+            ///
+            /// Returns an error if the server answered the call with a JSON-RPC error
+            /// (as a `JsonRpcError`), did not answer it at all, or the result does not
+            /// deserialize into `R`.
+            pub fn get<R>(&self, call: &ras_jsonrpc_types::BatchCall<R>) -> Result<R, Box<dyn std::error::Error + Send + Sync>>
+            where
+                R: serde::de::DeserializeOwned,
+            {
+                let response = self.responses
+                    .get(&call.id())
+                    .ok_or("Missing response for batch call")?;
+
+                if let Some(error) = &response.error {
+                    return Err(error.clone().into());
+                }
+
+                let result = response.result.clone().unwrap_or(serde_json::Value::Null);
+                Ok(serde_json::from_value(result)?)
             }
         }
     };
@@ -155,11 +605,17 @@ fn method_wire_name(method: &MethodDefinition) -> String {
 
 /// Generate client methods for the JSON-RPC service.
 fn generate_client_methods_for_method(method: &MethodDefinition) -> Vec<proc_macro2::TokenStream> {
+    if method.streaming {
+        return vec![generate_streaming_client_method(method)];
+    }
+    let deprecated = deprecated_attr(method);
     let mut methods = vec![generate_client_method(
         &method.name,
         method_wire_name(method),
         &method.request_type,
         &method.response_type,
+        method.idempotent,
+        &deprecated,
     )];
 
     methods.extend(method.versions.iter().map(|version| {
@@ -169,6 +625,8 @@ fn generate_client_methods_for_method(method: &MethodDefinition) -> Vec<proc_mac
             version.wire_name.clone(),
             &version.request_type,
             &version.response_type,
+            method.idempotent,
+            &deprecated,
         )
     }));
 
@@ -178,11 +636,18 @@ fn generate_client_methods_for_method(method: &MethodDefinition) -> Vec<proc_mac
 fn generate_client_methods_with_timeout_for_method(
     method: &MethodDefinition,
 ) -> Vec<proc_macro2::TokenStream> {
+    // Streaming methods are only exposed as a stream of chunks
+    if method.streaming {
+        return Vec::new();
+    }
+    let deprecated = deprecated_attr(method);
     let mut methods = vec![generate_client_method_with_timeout(
         &method.name,
         method_wire_name(method),
         &method.request_type,
         &method.response_type,
+        method.idempotent,
+        &deprecated,
     )];
 
     methods.extend(method.versions.iter().map(|version| {
@@ -192,23 +657,111 @@ fn generate_client_methods_with_timeout_for_method(
             version.wire_name.clone(),
             &version.request_type,
             &version.response_type,
+            method.idempotent,
+            &deprecated,
+        )
+    }));
+
+    methods
+}
+
+fn generate_notify_methods_for_method(method: &MethodDefinition) -> Vec<proc_macro2::TokenStream> {
+    // Streaming methods are only exposed as a stream of chunks
+    if method.streaming {
+        return Vec::new();
+    }
+    let deprecated = deprecated_attr(method);
+    let mut methods = vec![generate_notify_method(
+        &method.name,
+        method_wire_name(method),
+        &method.request_type,
+        &deprecated,
+    )];
+
+    methods.extend(method.versions.iter().map(|version| {
+        let method_name = quote::format_ident!("{}_{}", method.name, version.version);
+        generate_notify_method(
+            &method_name,
+            version.wire_name.clone(),
+            &version.request_type,
+            &deprecated,
         )
     }));
 
     methods
 }
 
-/// Generate a client method for the JSON-RPC service
+fn generate_batch_methods_for_method(method: &MethodDefinition) -> Vec<proc_macro2::TokenStream> {
+    // Streaming methods are only exposed as a stream of chunks
+    if method.streaming {
+        return Vec::new();
+    }
+    let deprecated = deprecated_attr(method);
+    let mut methods = vec![generate_batch_method(
+        &method.name,
+        method_wire_name(method),
+        &method.request_type,
+        &method.response_type,
+        &deprecated,
+    )];
+
+    methods.extend(method.versions.iter().map(|version| {
+        let method_name = quote::format_ident!("{}_{}", method.name, version.version);
+        generate_batch_method(
+            &method_name,
+            version.wire_name.clone(),
+            &version.request_type,
+            &version.response_type,
+            &deprecated,
+        )
+    }));
+
+    methods
+}
+
+/// Generate a method queueing a call on the batch builder
+fn generate_batch_method(
+    method_name: &syn::Ident,
+    method_str: String,
+    request_type: &syn::Type,
+    response_type: &syn::Type,
+    deprecated: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        /// Queue a call to the #method_name method
+        #deprecated
+        pub fn #method_name(&mut self, params: #request_type) -> ras_jsonrpc_types::BatchCall<#response_type> {
+            self.push(#method_str, params)
+        }
+    }
+}
+
+/// Generate a client method and its cancellable variant for the JSON-RPC service
 fn generate_client_method(
     method_name: &syn::Ident,
     method_str: String,
     request_type: &syn::Type,
     response_type: &syn::Type,
+    idempotent: bool,
+    deprecated: &proc_macro2::TokenStream,
 ) -> proc_macro2::TokenStream {
+    let method_name_cancellable = quote::format_ident!("{}_cancellable", method_name);
+
     quote! {
         /// Call the #method_name method
+        #deprecated
         pub async fn #method_name(&self, params: #request_type) -> Result<#response_type, Box<dyn std::error::Error + Send + Sync>> {
-            self.make_request(#method_str, params, None).await
+            self.make_request(#method_str, params, None, #idempotent).await
+        }
+
+        /// Call the #method_name method, returning a handle that aborts the request
+        /// when dropped or cancelled
+        #deprecated
+        pub fn #method_name_cancellable(&self, params: #request_type) -> ras_jsonrpc_types::CancellableCall<#response_type> {
+            let client = self.clone();
+            ras_jsonrpc_types::CancellableCall::new(async move {
+                client.make_request(#method_str, params, None, #idempotent).await
+            })
         }
     }
 }
@@ -219,17 +772,164 @@ fn generate_client_method_with_timeout(
     method_str: String,
     request_type: &syn::Type,
     response_type: &syn::Type,
+    idempotent: bool,
+    deprecated: &proc_macro2::TokenStream,
 ) -> proc_macro2::TokenStream {
     let method_name_with_timeout = quote::format_ident!("{}_with_timeout", method_name);
 
     quote! {
         /// Call the #method_name method with a custom timeout
+        #deprecated
         pub async fn #method_name_with_timeout(
             &self,
             params: #request_type,
             timeout: std::time::Duration
         ) -> Result<#response_type, Box<dyn std::error::Error + Send + Sync>> {
-            self.make_request(#method_str, params, Some(timeout)).await
+            self.make_request(#method_str, params, Some(timeout), #idempotent).await
+        }
+    }
+}
+
+/// Generate a client method sending the JSON-RPC method as a notification
+fn generate_notify_method(
+    method_name: &syn::Ident,
+    method_str: String,
+    request_type: &syn::Type,
+    deprecated: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let notify_method_name = quote::format_ident!("notify_{}", method_name);
+
+    quote! {
+        /// Send the #method_name method as a notification without waiting for a result
+        #deprecated
+        pub async fn #notify_method_name(&self, params: #request_type) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            self.make_notification(#method_str, params).await
+        }
+    }
+}
+
+/// `#[deprecated]` attribute mirroring the method's deprecation, if any
+fn deprecated_attr(method: &MethodDefinition) -> proc_macro2::TokenStream {
+    let Some(deprecation) = &method.deprecation else {
+        return quote! {};
+    };
+    let since = deprecation
+        .since
+        .iter()
+        .map(|since| quote! { since = #since });
+    let note = deprecation.note.iter().map(|note| quote! { note = #note });
+    let args: Vec<_> = since.chain(note).collect();
+    if args.is_empty() {
+        quote! { #[deprecated] }
+    } else {
+        quote! { #[deprecated(#(#args),*)] }
+    }
+}
+
+/// Generate the client method of a `STREAMING` method
+fn generate_streaming_client_method(method: &MethodDefinition) -> proc_macro2::TokenStream {
+    let method_name = &method.name;
+    let method_str = method_wire_name(method);
+    let request_type = &method.request_type;
+    let response_type = &method.response_type;
+    let deprecated = deprecated_attr(method);
+
+    quote! {
+        /// Call the #method_name method, yielding the chunks of its result as the server
+        /// produces them. The response is only read as the stream is polled, and dropping
+        /// the stream aborts the call.
+        #deprecated
+        pub fn #method_name(&self, params: #request_type) -> impl ras_jsonrpc_types::Stream<Item = Result<#response_type, Box<dyn std::error::Error + Send + Sync>>> + use<> {
+            let client = self.clone();
+            ras_jsonrpc_types::streaming_call(async move {
+                client.open_stream(#method_str, params).await
+            })
+        }
+    }
+}
+
+/// Generate the helpers sending calls of streaming methods
+fn generate_stream_helpers() -> proc_macro2::TokenStream {
+    quote! {
+        /// Send a call of a streaming method and return its response body as it arrives
+        ///
+        /// Takes the client by value, so the stream owns everything it reads from
+        #[cfg(not(target_arch = "wasm32"))]
+        async fn open_stream<T: serde::Serialize>(
+            self,
+            method: &'static str,
+            params: T,
+        ) -> Result<
+            impl ras_jsonrpc_types::Stream<Item = Result<impl AsRef<[u8]>, Box<dyn std::error::Error + Send + Sync>>> + use<T>,
+            Box<dyn std::error::Error + Send + Sync>,
+        > {
+            let response = self.send_stream_request(method, params).await?;
+            Ok(ras_jsonrpc_types::futures::stream::unfold(Some(response), |response| async move {
+                let mut response = response?;
+                match response.chunk().await {
+                    Ok(Some(chunk)) => Some((Ok(chunk), Some(response))),
+                    Ok(None) => None,
+                    Err(e) => Some((Err(Box::<dyn std::error::Error + Send + Sync>::from(e)), None)),
+                }
+            }))
+        }
+
+        /// Send a call of a streaming method and return its response body; browsers
+        /// hand it over once the response completes
+        #[cfg(target_arch = "wasm32")]
+        async fn open_stream<T: serde::Serialize>(
+            self,
+            method: &'static str,
+            params: T,
+        ) -> Result<
+            impl ras_jsonrpc_types::Stream<Item = Result<impl AsRef<[u8]>, Box<dyn std::error::Error + Send + Sync>>> + use<T>,
+            Box<dyn std::error::Error + Send + Sync>,
+        > {
+            let response = self.send_stream_request(method, params).await?;
+            Ok(ras_jsonrpc_types::futures::stream::once(async move {
+                response
+                    .bytes()
+                    .await
+                    .map_err(Box::<dyn std::error::Error + Send + Sync>::from)
+            }))
+        }
+
+        async fn send_stream_request<T: serde::Serialize>(
+            &self,
+            method: &str,
+            params: T,
+        ) -> Result<reqwest::Response, Box<dyn std::error::Error + Send + Sync>> {
+            if self.transport.is_some() {
+                return Err("Streaming methods are only supported over HTTP".into());
+            }
+
+            if let Some(limiter) = &self.rate_limiter {
+                limiter.acquire().await;
+            }
+
+            let id = self.next_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let request_body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": serde_json::to_value(params)?,
+                "id": id
+            });
+
+            let mut request_builder = self.client
+                .post(&self.server_url)
+                .header("Content-Type", "application/json")
+                .header("Accept", ras_jsonrpc_types::NDJSON_CONTENT_TYPE)
+                .json(&request_body);
+
+            if let Some(token) = &self.bearer_token {
+                request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
+            }
+
+            for (name, value) in ras_jsonrpc_types::trace_context_headers() {
+                request_builder = request_builder.header(name, value);
+            }
+
+            Ok(request_builder.send().await?)
         }
     }
 }
@@ -3,6 +3,7 @@ use quote::quote;
 use syn::{Ident, LitStr, Token, Type, parse::Parse, parse_macro_input};
 
 mod client;
+mod manifest;
 mod openrpc;
 mod static_hosting;
 
@@ -27,9 +28,57 @@ struct ServiceDefinition {
     service_name: Ident,
     openrpc: Option<OpenRpcConfig>,
     explorer: Option<ExplorerConfig>,
+    docs_auth: Option<PermissionRequirement>,
+    cors: Option<CorsConfig>,
     methods: Vec<MethodDefinition>,
 }
 
+/// `cors: { allow_origins: [...], allow_credentials: bool }`
+#[derive(Debug)]
+struct CorsConfig {
+    allow_origins: Vec<String>,
+    allow_credentials: bool,
+}
+
+impl Parse for CorsConfig {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let content;
+        syn::braced!(content in input);
+
+        let mut allow_origins = None;
+        let mut allow_credentials = false;
+        while !content.is_empty() {
+            let field = content.parse::<Ident>()?;
+            let _ = content.parse::<Token![:]>()?;
+            if field == "allow_origins" {
+                let origins_content;
+                syn::bracketed!(origins_content in content);
+                let origins =
+                    origins_content.parse_terminated(|input| input.parse::<LitStr>(), Token![,])?;
+                allow_origins = Some(origins.iter().map(LitStr::value).collect::<Vec<_>>());
+            } else if field == "allow_credentials" {
+                allow_credentials = content.parse::<syn::LitBool>()?.value();
+            } else {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "Expected allow_origins or allow_credentials",
+                ));
+            }
+
+            if content.peek(Token![,]) {
+                let _ = content.parse::<Token![,]>()?;
+            }
+        }
+
+        let allow_origins =
+            allow_origins.ok_or_else(|| content.error("cors requires allow_origins"))?;
+        Ok(CorsConfig {
+            allow_origins,
+            allow_credentials,
+        })
+    }
+}
+
 #[derive(Debug)]
 enum OpenRpcConfig {
     Enabled,
@@ -45,6 +94,8 @@ enum ExplorerConfig {
 #[derive(Debug)]
 struct MethodDefinition {
     docs: Option<DocComment>,
+    since: Option<String>,
+    deprecation: Option<Deprecation>,
     auth: AuthRequirement,
     name: Ident,
     request_type: Type,
@@ -52,6 +103,19 @@ struct MethodDefinition {
     version: Option<String>,
     wire_name: Option<String>,
     versions: Vec<MethodVersionDefinition>,
+    errors: Vec<DeclaredError>,
+    concurrency: Option<usize>,
+    max_request_size: Option<usize>,
+    timeout: Option<std::time::Duration>,
+    idempotent: bool,
+    streaming: bool,
+    positional_params: bool,
+}
+
+#[derive(Debug)]
+struct DeclaredError {
+    code: i32,
+    message: String,
 }
 
 #[derive(Debug)]
@@ -63,6 +127,27 @@ struct MethodVersionDefinition {
     migration_type: Type,
 }
 
+/// `#[deprecated(since = "..", note = "..")]` on a method
+#[derive(Debug, Default)]
+struct Deprecation {
+    since: Option<String>,
+    note: Option<String>,
+}
+
+impl Deprecation {
+    /// Text of the `Warning` header sent when the method is called
+    fn warning(&self, method: &str) -> String {
+        let mut warning = format!("Method {method} is deprecated");
+        if let Some(since) = &self.since {
+            warning.push_str(&format!(" since {since}"));
+        }
+        if let Some(note) = &self.note {
+            warning.push_str(&format!("; {note}"));
+        }
+        warning
+    }
+}
+
 #[derive(Debug)]
 struct DocComment {
     summary: String,
@@ -89,7 +174,151 @@ impl DocComment {
 #[derive(Debug)]
 enum AuthRequirement {
     Unauthorized,
-    WithPermissions(Vec<Vec<String>>), // Vec of permission groups - OR between groups, AND within groups
+    WithPermissions(PermissionRequirement),
+}
+
+/// The permissions `WITH_PERMISSIONS(...)` asks of the user
+#[derive(Debug, Clone)]
+enum PermissionRequirement {
+    /// `[...] | [...]`: OR between groups, AND within groups
+    Groups(Vec<Vec<String>>),
+    /// `expr = "..."`, parsed when the macro expands
+    Expression(ras_auth_core::PermissionExpr),
+}
+
+/// Parse `expr = "..."` inside `WITH_PERMISSIONS(...)`, reporting invalid
+/// expressions at the string literal
+fn parse_permission_expression(
+    input: syn::parse::ParseStream,
+) -> syn::Result<ras_auth_core::PermissionExpr> {
+    let key = input.parse::<Ident>()?;
+    if key != "expr" {
+        return Err(syn::Error::new(
+            key.span(),
+            "Expected `expr = \"...\"` or permission groups like [\"admin\"]",
+        ));
+    }
+    let _ = input.parse::<Token![=]>()?;
+    let expression = input.parse::<LitStr>()?;
+    ras_auth_core::PermissionExpr::parse(&expression.value()).map_err(|error| {
+        syn::Error::new(
+            expression.span(),
+            format!("invalid permission expression: {error}"),
+        )
+    })
+}
+
+/// Code building `expression` at runtime
+fn permission_expr_code(expression: &ras_auth_core::PermissionExpr) -> proc_macro2::TokenStream {
+    use ras_auth_core::PermissionExpr;
+    match expression {
+        PermissionExpr::Permission(permission) => {
+            quote! { ::ras_jsonrpc_core::PermissionExpr::Permission(#permission.to_string()) }
+        }
+        PermissionExpr::Not(inner) => {
+            let inner = permission_expr_code(inner);
+            quote! { ::ras_jsonrpc_core::PermissionExpr::Not(Box::new(#inner)) }
+        }
+        PermissionExpr::All(items) => {
+            let items = items.iter().map(permission_expr_code);
+            quote! { ::ras_jsonrpc_core::PermissionExpr::All(vec![#(#items),*]) }
+        }
+        PermissionExpr::Any(items) => {
+            let items = items.iter().map(permission_expr_code);
+            quote! { ::ras_jsonrpc_core::PermissionExpr::Any(vec![#(#items),*]) }
+        }
+    }
+}
+
+/// Code evaluating to a `&'static PermissionExpr`, built on first use
+fn static_permission_expr_code(
+    expression: &ras_auth_core::PermissionExpr,
+) -> proc_macro2::TokenStream {
+    let expression_code = permission_expr_code(expression);
+    quote! {
+        {
+            static REQUIRED_PERMISSIONS: ::std::sync::LazyLock<::ras_jsonrpc_core::PermissionExpr> =
+                ::std::sync::LazyLock::new(|| #expression_code);
+            &*REQUIRED_PERMISSIONS
+        }
+    }
+}
+
+impl Parse for AuthRequirement {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        // Parse UNAUTHORIZED, WITH_PERMISSIONS([...] | [...]) or WITH_PERMISSIONS(expr = "...")
+        let auth = if input.peek(syn::Ident) {
+            let auth_ident = input.parse::<Ident>()?;
+            match auth_ident.to_string().as_str() {
+                "UNAUTHORIZED" => AuthRequirement::Unauthorized,
+                "WITH_PERMISSIONS" => {
+                    // Parse ([...] | [...] | ...)
+                    let perms_content;
+                    syn::parenthesized!(perms_content in input);
+
+                    if perms_content.peek(syn::Ident) {
+                        let expression = parse_permission_expression(&perms_content)?;
+                        return Ok(AuthRequirement::WithPermissions(
+                            PermissionRequirement::Expression(expression),
+                        ));
+                    }
+
+                    let mut permission_groups = Vec::new();
+
+                    // Parse first permission group
+                    let first_group_content;
+                    syn::bracketed!(first_group_content in perms_content);
+
+                    let mut first_group = Vec::new();
+                    while !first_group_content.is_empty() {
+                        let perm = first_group_content.parse::<LitStr>()?;
+                        first_group.push(perm.value());
+
+                        if first_group_content.peek(Token![,]) {
+                            let _ = first_group_content.parse::<Token![,]>()?;
+                        }
+                    }
+                    permission_groups.push(first_group);
+
+                    // Parse additional permission groups separated by |
+                    while perms_content.peek(Token![|]) {
+                        let _ = perms_content.parse::<Token![|]>()?;
+
+                        let group_content;
+                        syn::bracketed!(group_content in perms_content);
+
+                        let mut group = Vec::new();
+                        while !group_content.is_empty() {
+                            let perm = group_content.parse::<LitStr>()?;
+                            group.push(perm.value());
+
+                            if group_content.peek(Token![,]) {
+                                let _ = group_content.parse::<Token![,]>()?;
+                            }
+                        }
+                        permission_groups.push(group);
+                    }
+
+                    AuthRequirement::WithPermissions(PermissionRequirement::Groups(
+                        permission_groups,
+                    ))
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        auth_ident.span(),
+                        "Expected UNAUTHORIZED or WITH_PERMISSIONS",
+                    ));
+                }
+            }
+        } else {
+            return Err(syn::Error::new(
+                input.span(),
+                "Expected authentication requirement",
+            ));
+        };
+
+        Ok(auth)
+    }
 }
 
 const DOC_COMMENT_EXPECTED: &str = "Expected doc comment in the form `/// ...`";
@@ -102,6 +331,30 @@ fn parse_label(input: syn::parse::ParseStream) -> syn::Result<String> {
     }
 }
 
+/// Parse a duration literal such as `500ms`, `5s` or `2m`
+fn parse_duration(input: syn::parse::ParseStream) -> syn::Result<std::time::Duration> {
+    let literal = input.parse::<syn::LitInt>()?;
+    let value = literal.base10_parse::<u64>()?;
+    let duration = match literal.suffix() {
+        "ms" => std::time::Duration::from_millis(value),
+        "s" => std::time::Duration::from_secs(value),
+        "m" => std::time::Duration::from_secs(value * 60),
+        _ => {
+            return Err(syn::Error::new(
+                literal.span(),
+                "Expected a duration such as 500ms, 5s or 2m",
+            ));
+        }
+    };
+    if duration.is_zero() {
+        return Err(syn::Error::new(
+            literal.span(),
+            "TIMEOUT must be longer than zero",
+        ));
+    }
+    Ok(duration)
+}
+
 fn parse_doc_comment_attrs(
     attrs: Vec<syn::Attribute>,
     entry_kind: &str,
@@ -114,6 +367,81 @@ fn parse_doc_comment_attrs(
     Ok(DocComment::from_lines(lines))
 }
 
+/// Attributes accepted before a method: doc comments, `#[since = ".."]` and
+/// `#[deprecated]` in any of the forms Rust accepts
+#[derive(Default)]
+struct MethodAttributes {
+    docs: Option<DocComment>,
+    since: Option<String>,
+    deprecation: Option<Deprecation>,
+}
+
+fn parse_method_attrs(attrs: Vec<syn::Attribute>) -> syn::Result<MethodAttributes> {
+    let mut parsed = MethodAttributes::default();
+    let mut doc_attrs = Vec::new();
+
+    for attr in attrs {
+        if attr.path().is_ident("since") {
+            if parsed.since.is_some() {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "`since` is declared more than once",
+                ));
+            }
+            parsed.since = Some(parse_string_attr_value(&attr)?);
+        } else if attr.path().is_ident("deprecated") {
+            if parsed.deprecation.is_some() {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "`deprecated` is declared more than once",
+                ));
+            }
+            parsed.deprecation = Some(parse_deprecated_attr(&attr)?);
+        } else {
+            doc_attrs.push(attr);
+        }
+    }
+
+    parsed.docs = parse_doc_comment_attrs(doc_attrs, "method")?;
+    Ok(parsed)
+}
+
+fn parse_string_attr_value(attr: &syn::Attribute) -> syn::Result<String> {
+    if let syn::Meta::NameValue(name_value) = &attr.meta
+        && let syn::Expr::Lit(expr_lit) = &name_value.value
+        && let syn::Lit::Str(value) = &expr_lit.lit
+    {
+        return Ok(value.value());
+    }
+
+    Err(syn::Error::new_spanned(
+        attr,
+        "Expected a string value such as `#[since = \"1.1\"]`",
+    ))
+}
+
+fn parse_deprecated_attr(attr: &syn::Attribute) -> syn::Result<Deprecation> {
+    let mut deprecation = Deprecation::default();
+    match &attr.meta {
+        syn::Meta::Path(_) => {}
+        syn::Meta::NameValue(_) => deprecation.note = Some(parse_string_attr_value(attr)?),
+        syn::Meta::List(_) => {
+            attr.parse_nested_meta(|meta| {
+                let value = meta.value()?.parse::<LitStr>()?.value();
+                if meta.path.is_ident("since") {
+                    deprecation.since = Some(value);
+                } else if meta.path.is_ident("note") {
+                    deprecation.note = Some(value);
+                } else {
+                    return Err(meta.error("Expected `since` or `note`"));
+                }
+                Ok(())
+            })?;
+        }
+    }
+    Ok(deprecation)
+}
+
 fn parse_doc_comment_attr(attr: syn::Attribute, entry_kind: &str) -> syn::Result<String> {
     if !attr.path().is_ident("doc") {
         return Err(syn::Error::new_spanned(
@@ -145,8 +473,12 @@ impl Parse for ServiceDefinition {
         let _ = content.parse::<Token![,]>()?;
 
         // Check if openrpc field is present
+        let mut namespace = None;
         let mut openrpc = None;
         let mut explorer = None;
+        let mut docs_auth = None;
+        let mut cors = None;
+        let mut positional_params = false;
 
         // Parse optional fields until we hit "methods"
         while content.peek(Ident) {
@@ -158,7 +490,9 @@ impl Parse for ServiceDefinition {
             let _ = content.parse::<Ident>()?; // field name
             let _ = content.parse::<Token![:]>()?;
 
-            if field_name == "openrpc" {
+            if field_name == "namespace" {
+                namespace = Some(content.parse::<LitStr>()?.value());
+            } else if field_name == "openrpc" {
                 // Parse openrpc value - can be true/false or { output: "path" }
                 if content.peek(syn::LitBool) {
                     let enabled = content.parse::<syn::LitBool>()?;
@@ -192,6 +526,15 @@ impl Parse for ServiceDefinition {
                     let path = explorer_content.parse::<LitStr>()?;
                     explorer = Some(ExplorerConfig::WithPath(path.value()));
                 }
+            } else if field_name == "docs_auth" {
+                docs_auth = match content.parse::<AuthRequirement>()? {
+                    AuthRequirement::Unauthorized => None,
+                    AuthRequirement::WithPermissions(requirement) => Some(requirement),
+                };
+            } else if field_name == "cors" {
+                cors = Some(content.parse::<CorsConfig>()?);
+            } else if field_name == "positional_params" {
+                positional_params = content.parse::<syn::LitBool>()?.value();
             }
 
             let _ = content.parse::<Token![,]>()?;
@@ -215,10 +558,34 @@ impl Parse for ServiceDefinition {
             }
         }
 
+        // A service-wide `positional_params: true` applies to every method
+        if positional_params {
+            for method in &mut methods {
+                method.positional_params = true;
+            }
+        }
+
+        // Prefix wire-level method names with the namespace; Rust identifiers stay unchanged
+        if let Some(namespace) = &namespace {
+            for method in &mut methods {
+                let wire_name = method
+                    .wire_name
+                    .take()
+                    .unwrap_or_else(|| method.name.to_string());
+                method.wire_name = Some(format!("{namespace}.{wire_name}"));
+
+                for version in &mut method.versions {
+                    version.wire_name = format!("{namespace}.{}", version.wire_name);
+                }
+            }
+        }
+
         Ok(ServiceDefinition {
             service_name,
             openrpc,
             explorer,
+            docs_auth,
+            cors,
             methods,
         })
     }
@@ -226,69 +593,87 @@ impl Parse for ServiceDefinition {
 
 impl Parse for MethodDefinition {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        let docs = parse_doc_comment_attrs(input.call(syn::Attribute::parse_outer)?, "method")?;
-
-        // Parse auth requirement (UNAUTHORIZED or WITH_PERMISSIONS([...]))
-        let auth = if input.peek(syn::Ident) {
-            let auth_ident = input.parse::<Ident>()?;
-            match auth_ident.to_string().as_str() {
-                "UNAUTHORIZED" => AuthRequirement::Unauthorized,
-                "WITH_PERMISSIONS" => {
-                    // Parse ([...] | [...] | ...)
-                    let perms_content;
-                    syn::parenthesized!(perms_content in input);
-
-                    let mut permission_groups = Vec::new();
-
-                    // Parse first permission group
-                    let first_group_content;
-                    syn::bracketed!(first_group_content in perms_content);
-
-                    let mut first_group = Vec::new();
-                    while !first_group_content.is_empty() {
-                        let perm = first_group_content.parse::<LitStr>()?;
-                        first_group.push(perm.value());
-
-                        if first_group_content.peek(Token![,]) {
-                            let _ = first_group_content.parse::<Token![,]>()?;
-                        }
-                    }
-                    permission_groups.push(first_group);
-
-                    // Parse additional permission groups separated by |
-                    while perms_content.peek(Token![|]) {
-                        let _ = perms_content.parse::<Token![|]>()?;
-
-                        let group_content;
-                        syn::bracketed!(group_content in perms_content);
-
-                        let mut group = Vec::new();
-                        while !group_content.is_empty() {
-                            let perm = group_content.parse::<LitStr>()?;
-                            group.push(perm.value());
-
-                            if group_content.peek(Token![,]) {
-                                let _ = group_content.parse::<Token![,]>()?;
-                            }
-                        }
-                        permission_groups.push(group);
-                    }
-
-                    AuthRequirement::WithPermissions(permission_groups)
+        let MethodAttributes {
+            docs,
+            since,
+            deprecation,
+        } = parse_method_attrs(input.call(syn::Attribute::parse_outer)?)?;
+
+        let auth = input.parse::<AuthRequirement>()?;
+
+        // Parse optional IDEMPOTENT, STREAMING, CONCURRENCY(n), MAX_REQUEST_SIZE(bytes)
+        // and TIMEOUT(duration) modifiers
+        let mut idempotent = false;
+        let mut streaming = None;
+        let mut concurrency = None;
+        let mut max_request_size = None;
+        let mut timeout = None;
+        while input.peek(Ident) {
+            let modifier = input.fork().parse::<Ident>()?;
+            if !input.peek2(syn::token::Paren) {
+                let flag = if modifier == "IDEMPOTENT" {
+                    idempotent
+                } else if modifier == "STREAMING" {
+                    streaming.is_some()
+                } else {
+                    break;
+                };
+                if flag {
+                    return Err(syn::Error::new(
+                        modifier.span(),
+                        format!("{modifier} is declared more than once"),
+                    ));
                 }
-                _ => {
+                let _ = input.parse::<Ident>()?;
+                if modifier == "IDEMPOTENT" {
+                    idempotent = true;
+                } else {
+                    streaming = Some(modifier.span());
+                }
+                continue;
+            }
+
+            if modifier == "TIMEOUT" {
+                if timeout.is_some() {
                     return Err(syn::Error::new(
-                        auth_ident.span(),
-                        "Expected UNAUTHORIZED or WITH_PERMISSIONS",
+                        modifier.span(),
+                        "TIMEOUT is declared more than once",
                     ));
                 }
+                let _ = input.parse::<Ident>()?;
+                let timeout_content;
+                syn::parenthesized!(timeout_content in input);
+                timeout = Some(parse_duration(&timeout_content)?);
+                continue;
             }
-        } else {
-            return Err(syn::Error::new(
-                input.span(),
-                "Expected authentication requirement",
-            ));
-        };
+
+            let slot = if modifier == "CONCURRENCY" {
+                &mut concurrency
+            } else if modifier == "MAX_REQUEST_SIZE" {
+                &mut max_request_size
+            } else {
+                break;
+            };
+            if slot.is_some() {
+                return Err(syn::Error::new(
+                    modifier.span(),
+                    format!("{modifier} is declared more than once"),
+                ));
+            }
+
+            let _ = input.parse::<Ident>()?;
+            let limit_content;
+            syn::parenthesized!(limit_content in input);
+            let limit = limit_content.parse::<syn::LitInt>()?;
+            let value = limit.base10_parse::<usize>()?;
+            if value == 0 {
+                return Err(syn::Error::new(
+                    limit.span(),
+                    format!("{modifier} limit must be at least 1"),
+                ));
+            }
+            *slot = Some(value);
+        }
 
         // Parse method name
         let name = input.parse::<Ident>()?;
@@ -305,6 +690,8 @@ impl Parse for MethodDefinition {
         let mut version = None;
         let mut wire_name = None;
         let mut versions = Vec::new();
+        let mut errors = Vec::new();
+        let mut positional_params = false;
 
         if input.peek(syn::token::Brace) {
             let content;
@@ -333,10 +720,25 @@ impl Parse for MethodDefinition {
                             }
                         }
                     }
+                    "errors" => {
+                        let errors_content;
+                        syn::bracketed!(errors_content in content);
+
+                        while !errors_content.is_empty() {
+                            errors.push(errors_content.parse::<DeclaredError>()?);
+
+                            if errors_content.peek(Token![,]) {
+                                let _ = errors_content.parse::<Token![,]>()?;
+                            }
+                        }
+                    }
+                    "positional_params" => {
+                        positional_params = content.parse::<syn::LitBool>()?.value();
+                    }
                     _ => {
                         return Err(syn::Error::new(
                             field_name.span(),
-                            "Expected version, wire, or versions",
+                            "Expected version, wire, versions, errors, or positional_params",
                         ));
                     }
                 }
@@ -347,8 +749,20 @@ impl Parse for MethodDefinition {
             }
         }
 
+        // Streamed results can't be stored for replay or migrated between versions
+        if let Some(span) = streaming
+            && (idempotent || !versions.is_empty())
+        {
+            return Err(syn::Error::new(
+                span,
+                "STREAMING methods can't be IDEMPOTENT or declare versions",
+            ));
+        }
+
         Ok(MethodDefinition {
             docs,
+            since,
+            deprecation,
             auth,
             name,
             request_type,
@@ -356,10 +770,39 @@ impl Parse for MethodDefinition {
             version,
             wire_name,
             versions,
+            errors,
+            concurrency,
+            max_request_size,
+            timeout,
+            idempotent,
+            streaming: streaming.is_some(),
+            positional_params,
         })
     }
 }
 
+impl Parse for DeclaredError {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        // Parse -32010 => "Message"
+        let negative = input.parse::<Option<Token![-]>>()?.is_some();
+        let literal = input.parse::<syn::LitInt>()?;
+        let code = literal.base10_parse::<i32>()?;
+        let code = if negative { -code } else { code };
+
+        if !(-32099..=-32000).contains(&code) {
+            return Err(syn::Error::new(
+                literal.span(),
+                "Application error codes must be in the range -32099..=-32000",
+            ));
+        }
+
+        let _ = input.parse::<Token![=>]>()?;
+        let message = input.parse::<LitStr>()?.value();
+
+        Ok(Self { code, message })
+    }
+}
+
 impl Parse for MethodVersionDefinition {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let version = parse_label(input)?;
@@ -420,8 +863,7 @@ impl Parse for MethodVersionDefinition {
 
 fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_macro2::TokenStream> {
     // Generate OpenRPC code if enabled in the macro input
-    let (openrpc_code, schema_checks) = if service_def.openrpc.is_some() {
-        let openrpc_config = service_def.openrpc.as_ref().unwrap();
+    let (openrpc_code, schema_checks) = if let Some(openrpc_config) = &service_def.openrpc {
         (
             openrpc::generate_openrpc_code(&service_def, openrpc_config),
             openrpc::generate_schema_impl_checks(&service_def),
@@ -459,10 +901,16 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
             quote! {}
         };
 
+        // Named after the service, so several services can be defined in one module
+        let server_mod = quote::format_ident!(
+            "_generated_{}_server",
+            service_def.service_name.to_string().to_lowercase()
+        );
+
         // Wrap all server code in a cfg attribute to ensure it's only compiled when server feature is enabled
         quote! {
             #[cfg(feature = "server")]
-            mod _generated_server {
+            mod #server_mod {
                 use super::*;
 
                 #server_impl
@@ -470,7 +918,7 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
             }
 
             #[cfg(feature = "server")]
-            pub use _generated_server::*;
+            pub use #server_mod::*;
         }
     } else {
         quote! {}
@@ -480,17 +928,22 @@ fn generate_service_code(service_def: ServiceDefinition) -> syn::Result<proc_mac
     let client_code = if cfg!(feature = "client") {
         let client_impl = crate::client::generate_client_code(&service_def);
 
+        let client_mod = quote::format_ident!(
+            "_generated_{}_client",
+            service_def.service_name.to_string().to_lowercase()
+        );
+
         // Wrap all client code in a cfg attribute to ensure it's only compiled when client feature is enabled
         quote! {
             #[cfg(feature = "client")]
-            mod _generated_client {
+            mod #client_mod {
                 use super::*;
 
                 #client_impl
             }
 
             #[cfg(feature = "client")]
-            pub use _generated_client::*;
+            pub use #client_mod::*;
         }
     } else {
         quote! {}
@@ -510,25 +963,81 @@ fn generate_server_code(service_def: &ServiceDefinition) -> proc_macro2::TokenSt
     let service_name = &service_def.service_name;
     let service_trait_name = quote::format_ident!("{}Trait", service_name);
     let builder_name = quote::format_ident!("{}Builder", service_name);
+    let reporting_name = quote::format_ident!("{}Reporting", builder_name);
+    let pending_name = quote::format_ident!("{}PendingRequest", builder_name);
+    let manifest_code = manifest::generate_manifest_code(service_def);
+    let manifest_fn_name = quote::format_ident!(
+        "{}_service_manifest",
+        service_name.to_string().to_lowercase()
+    );
 
     // Generate explorer route integration if enabled
-    let explorer_route_integration =
-        if service_def.explorer.is_some() && service_def.openrpc.is_some() {
-            let service_name_str = service_name.to_string();
-            let service_name_lower = service_name_str.to_lowercase();
-            let explorer_routes_fn_str = [&service_name_lower, "_explorer_routes"].concat();
-            let explorer_routes_fn = syn::Ident::new(&explorer_routes_fn_str, service_name.span());
-            quote! { router = router.merge(#explorer_routes_fn(&base_url)); }
-        } else {
-            quote! {}
-        };
-
-    // Generate trait methods
+    let explorer_route_integration = if service_def.explorer.is_some()
+        && service_def.openrpc.is_some()
+    {
+        let service_name_str = service_name.to_string();
+        let service_name_lower = service_name_str.to_lowercase();
+        let explorer_routes_fn_str = [&service_name_lower, "_explorer_routes"].concat();
+        let explorer_routes_fn = syn::Ident::new(&explorer_routes_fn_str, service_name.span());
+        match &service_def.docs_auth {
+            Some(requirement) => {
+                let layer = static_hosting::generate_docs_auth_layer(
+                    &service_name_str,
+                    "jsonrpc",
+                    requirement,
+                    quote! { docs_auth_source },
+                    quote! { auth_source.auth_provider.as_deref() },
+                );
+                quote! { router = router.merge(#explorer_routes_fn(&base_url).route_layer(#layer)); }
+            }
+            None => quote! { router = router.merge(#explorer_routes_fn(&base_url)); },
+        }
+    } else {
+        quote! {}
+    };
+
+    // Answer CORS preflight requests and add Access-Control-* headers to RPC responses
+    let cors_integration = match &service_def.cors {
+        Some(cors) => {
+            let allow_origins = &cors.allow_origins;
+            let allow_credentials = cors.allow_credentials;
+            quote! {
+                let rpc_handler = rpc_handler
+                    .options(|| async { axum::http::StatusCode::NO_CONTENT })
+                    .layer(ras_jsonrpc_core::jsonrpc_cors_layer(&[#(#allow_origins),*], #allow_credentials)?);
+            }
+        }
+        None => quote! {},
+    };
+
+    // The docs auth layer checks tokens with the service's auth provider
+    let docs_auth_source = if service_def.docs_auth.is_some()
+        && service_def.explorer.is_some()
+        && service_def.openrpc.is_some()
+    {
+        quote! { let docs_auth_source = service.clone(); }
+    } else {
+        quote! {}
+    };
+
+    // Generate trait methods
     let trait_methods = service_def.methods.iter().map(|method| {
         let method_name = &method.name;
         let request_type = &method.request_type;
         let response_type = &method.response_type;
 
+        // Streaming handlers return their chunks as they are produced
+        if method.streaming {
+            return match &method.auth {
+                AuthRequirement::Unauthorized => quote! {
+                    fn #method_name(&self, request: #request_type) -> ras_jsonrpc_core::ChunkStream<#response_type>;
+                },
+                AuthRequirement::WithPermissions(_) => quote! {
+                    fn #method_name(&self, user: &ras_jsonrpc_core::AuthenticatedUser, request: #request_type) -> ras_jsonrpc_core::ChunkStream<#response_type>;
+                },
+            };
+        }
+
         match &method.auth {
             AuthRequirement::Unauthorized => {
                 quote! {
@@ -543,6 +1052,156 @@ fn generate_server_code(service_def: &ServiceDefinition) -> proc_macro2::TokenSt
         }
     });
 
+    // Wire-level method names, including versioned compatibility methods
+    let method_wire_names = service_def.methods.iter().flat_map(|method| {
+        std::iter::once(jsonrpc_method_wire_name(method)).chain(
+            method
+                .versions
+                .iter()
+                .map(|version| version.wire_name.clone()),
+        )
+    });
+
+    // Whether `method` names a declared method, for metric labels
+    let declared_wire_names: Vec<String> = method_wire_names.clone().collect();
+    let known_method = if declared_wire_names.is_empty() {
+        quote! { false }
+    } else {
+        quote! { matches!(method, #(#declared_wire_names)|*) }
+    };
+
+    // Deprecation warnings, keyed by wire name; versions share their method's deprecation
+    let (deprecated_wire_names, deprecation_warnings): (Vec<String>, Vec<String>) = service_def
+        .methods
+        .iter()
+        .filter_map(|method| Some((method, method.deprecation.as_ref()?)))
+        .flat_map(|(method, deprecation)| {
+            std::iter::once(jsonrpc_method_wire_name(method))
+                .chain(
+                    method
+                        .versions
+                        .iter()
+                        .map(|version| version.wire_name.clone()),
+                )
+                .map(|wire_name| {
+                    let warning = deprecation.warning(&wire_name);
+                    (wire_name, warning)
+                })
+        })
+        .unzip();
+    let deprecation_warning_lookup = if deprecated_wire_names.is_empty() {
+        quote! {
+            let _ = method;
+            None
+        }
+    } else {
+        quote! {
+            match method {
+                #(#deprecated_wire_names => Some(#deprecation_warnings.to_string()),)*
+                _ => None,
+            }
+        }
+    };
+
+    // Streaming methods are answered by `handle_stream_request` instead of `handle_request`
+    let streaming_methods: Vec<&MethodDefinition> = service_def
+        .methods
+        .iter()
+        .filter(|method| method.streaming)
+        .collect();
+    let (stream_dispatch_fn, streaming_service_methods) = if streaming_methods.is_empty() {
+        (quote! {}, quote! {})
+    } else {
+        let streaming_wire_names = streaming_methods
+            .iter()
+            .map(|method| jsonrpc_method_wire_name(method));
+        let stream_dispatch = streaming_methods
+            .iter()
+            .map(|method| generate_jsonrpc_stream_dispatch(method));
+        (
+            quote! {
+                /// Answer a request of a streaming method as `handle_request` answers
+                /// others, reporting it once the last frame of its result is sent
+                async fn handle_stream_request(&self, headers: &axum::http::HeaderMap, request: serde_json::Value, request_size: usize) -> Result<ras_jsonrpc_core::FrameStream, ras_jsonrpc_types::JsonRpcResponse> {
+                    if !self.reporting.is_enabled() {
+                        return self.answer_stream_request(headers, request, request_size).await.0;
+                    }
+
+                    let pending = self.reporting.start(headers, &request, request_size);
+                    let (answer, caller) = self.answer_stream_request(headers, request, request_size).await;
+                    match answer {
+                        Ok(frames) => {
+                            let reporting = self.reporting.clone();
+                            let headers = headers.clone();
+                            Ok(ras_jsonrpc_core::on_stream_end(frames, move |response, response_size| async move {
+                                reporting.finish(&headers, pending, caller.as_ref(), &response, Some(response_size)).await;
+                            }))
+                        }
+                        Err(response) => {
+                            self.reporting.finish(headers, pending, caller.as_ref(), &response, None).await;
+                            Err(response)
+                        }
+                    }
+                }
+
+                /// Answer a request of a streaming method with the frames of its result, along
+                /// with the caller that made it, if authenticated
+                async fn answer_stream_request(&self, headers: &axum::http::HeaderMap, request: serde_json::Value, request_size: usize) -> (Result<ras_jsonrpc_core::FrameStream, ras_jsonrpc_types::JsonRpcResponse>, Option<ras_jsonrpc_core::AuthenticatedUser>) {
+                    let (request, authenticated_user) = match self.prepare_request(headers, request, Some(request_size)).await {
+                        Ok(prepared) => prepared,
+                        Err(response) => return (Err(response), None),
+                    };
+                    let caller = authenticated_user.clone();
+
+                    let answer = match request.method.as_str() {
+                        #(#stream_dispatch)*
+                        _ => Err(ras_jsonrpc_types::JsonRpcResponse::error(
+                            ras_jsonrpc_types::JsonRpcError::method_not_found(&request.method),
+                            request.id.clone()
+                        )),
+                    };
+
+                    (answer, caller)
+                }
+            },
+            quote! {
+                fn is_streaming_method(&self, method: &str) -> bool {
+                    matches!(method, #(#streaming_wire_names)|*)
+                }
+
+                fn dispatch_stream<'a>(
+                    &'a self,
+                    headers: &'a axum::http::HeaderMap,
+                    request: serde_json::Value,
+                    request_size: usize,
+                ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ras_jsonrpc_core::FrameStream, ras_jsonrpc_types::JsonRpcResponse>> + Send + 'a>> {
+                    Box::pin(self.handle_stream_request(headers, request, request_size))
+                }
+            },
+        )
+    };
+
+    // Per-method concurrency limits, keyed by canonical wire name
+    let (method_limit_names, method_limit_values): (Vec<String>, Vec<usize>) = service_def
+        .methods
+        .iter()
+        .filter_map(|method| Some((jsonrpc_method_wire_name(method), method.concurrency?)))
+        .unzip();
+
+    // Authorization callbacks of the WITH_PERMISSIONS methods
+    let mut authorize_fields = Vec::new();
+    let mut authorize_setters = Vec::new();
+    let mut authorize_inits = Vec::new();
+    for (field, setter, init) in service_def
+        .methods
+        .iter()
+        .filter_map(jsonrpc_authorize_builder_code)
+    {
+        authorize_fields.push(field);
+        authorize_setters.push(setter);
+        authorize_inits.push(init);
+    }
+
     // Generate method dispatch logic for the JSON-RPC handler
     let method_dispatch = service_def
         .methods
@@ -550,6 +1209,8 @@ fn generate_server_code(service_def: &ServiceDefinition) -> proc_macro2::TokenSt
         .flat_map(generate_jsonrpc_method_dispatches);
 
     quote! {
+        #manifest_code
+
         /// Generated service trait
         pub trait #service_trait_name: Send + Sync + 'static {
             #(#trait_methods)*
@@ -560,8 +1221,131 @@ fn generate_server_code(service_def: &ServiceDefinition) -> proc_macro2::TokenSt
             base_url: String,
             service: std::sync::Arc<T>,
             auth_provider: Option<Box<dyn ras_jsonrpc_core::AuthProvider>>,
-            usage_tracker: Option<Box<dyn Fn(&axum::http::HeaderMap, Option<&ras_jsonrpc_core::AuthenticatedUser>, &ras_jsonrpc_types::JsonRpcRequest) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> + Send + Sync>>,
-            method_duration_tracker: Option<Box<dyn Fn(&str, Option<&ras_jsonrpc_core::AuthenticatedUser>, std::time::Duration) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> + Send + Sync>>,
+            // Also given the size of the request object, when known
+            usage_tracker: Option<Box<dyn Fn(&axum::http::HeaderMap, Option<&ras_jsonrpc_core::AuthenticatedUser>, &ras_jsonrpc_types::JsonRpcRequest, Option<usize>) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> + Send + Sync>>,
+            method_timeout: Option<std::time::Duration>,
+            reporting: #reporting_name,
+            trusted_proxies: ras_jsonrpc_core::TrustedProxies,
+            max_batch_concurrency: usize,
+            concurrency: ras_jsonrpc_core::ConcurrencyLimiter,
+            tracing_spans: bool,
+            max_request_size: Option<usize>,
+            lenient_content_type: bool,
+            deprecation_warnings: bool,
+            manifest_permission: Option<String>,
+            idempotency: ras_jsonrpc_core::Idempotency,
+            #(#authorize_fields)*
+        }
+
+        /// Where the requests of the service are reported once answered, kept apart
+        /// from its builder so streamed results can report once their last frame is sent
+        #[derive(Clone, Default)]
+        struct #reporting_name {
+            method_duration_tracker: Option<std::sync::Arc<dyn Fn(&str, Option<&ras_jsonrpc_core::AuthenticatedUser>, std::time::Duration) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> + Send + Sync>>,
+            payload_size_tracker: Option<std::sync::Arc<dyn Fn(&str, usize, usize) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> + Send + Sync>>,
+            service_metrics: Option<std::sync::Arc<dyn ras_jsonrpc_core::ServiceMetrics>>,
+            completion_tracker: Option<std::sync::Arc<dyn ras_jsonrpc_core::MethodDurationTracker>>,
+            tracker_guard: ras_jsonrpc_core::TrackerGuard,
+            tenant_extractor: Option<ras_jsonrpc_core::TenantExtractor>,
+        }
+
+        /// A request being answered, as reported to the service metrics when it started
+        struct #pending_name {
+            // Set when there are service metrics or a completion tracker
+            context: Option<ras_jsonrpc_core::RequestContext>,
+            in_flight: Option<ras_jsonrpc_core::InFlightGuard>,
+            // Undeclared methods are not reported to the method trackers
+            method: Option<String>,
+            is_notification: bool,
+            request_size: usize,
+            started: std::time::Instant,
+        }
+
+        impl #reporting_name {
+            /// Whether anything is told about answered requests
+            fn is_enabled(&self) -> bool {
+                self.service_metrics.is_some()
+                    || self.completion_tracker.is_some()
+                    || self.method_duration_tracker.is_some()
+                    || self.payload_size_tracker.is_some()
+            }
+
+            /// Report a request object to the service metrics as started
+            fn start(&self, headers: &axum::http::HeaderMap, request: &serde_json::Value, request_size: usize) -> #pending_name {
+                // Undeclared method names are not used as-is to keep metric labels bounded
+                let method = request.get("method").and_then(|method| method.as_str()).filter(|&method| #known_method);
+                let context = (self.service_metrics.is_some() || self.completion_tracker.is_some()).then(|| {
+                    let context = ras_jsonrpc_core::RequestContext::jsonrpc(method.unwrap_or("unknown").to_string()).with_request_info(headers).with_trace_context(headers);
+                    match &self.tenant_extractor {
+                        Some(extractor) => context.with_extracted_tenant(extractor, headers, None),
+                        None => context,
+                    }
+                });
+
+                // Counted as executing until answered, or dropped
+                let in_flight = match (&self.service_metrics, &context) {
+                    (Some(metrics), Some(context)) => {
+                        metrics.increment_requests_started(context);
+                        Some(metrics.in_flight_guard(context))
+                    }
+                    _ => None,
+                };
+
+                #pending_name {
+                    context,
+                    in_flight,
+                    method: method.map(str::to_string),
+                    is_notification: ras_jsonrpc_core::is_notification(request),
+                    request_size,
+                    started: std::time::Instant::now(),
+                }
+            }
+
+            /// Report a request answered with `response` to the trackers and service metrics.
+            /// Every exit, calls refused before the handler runs included, is timed from `start`.
+            /// `response_size` is the number of bytes sent, when not the serialized response.
+            async fn finish(&self, headers: &axum::http::HeaderMap, pending: #pending_name, caller: Option<&ras_jsonrpc_core::AuthenticatedUser>, response: &ras_jsonrpc_types::JsonRpcResponse, response_size: Option<usize>) {
+                let duration = pending.started.elapsed();
+                drop(pending.in_flight);
+
+                // Sizes are only measured when reported; notifications send no response body
+                let measured = self.service_metrics.is_some() || self.payload_size_tracker.is_some();
+                let response_size = match response_size {
+                    Some(response_size) => response_size,
+                    None if pending.is_notification || !measured => 0,
+                    None => serde_json::to_vec(response).map(|body| body.len()).unwrap_or(0),
+                };
+                let request_size = pending.request_size;
+
+                if let Some(method) = &pending.method {
+                    if let Some(tracker) = &self.payload_size_tracker {
+                        self.tracker_guard.call("payload_size_tracker", self.service_metrics.as_deref(), || tracker(method, request_size, response_size)).await;
+                    }
+                    if let Some(duration_tracker) = &self.method_duration_tracker {
+                        self.tracker_guard.call("method_duration_tracker", self.service_metrics.as_deref(), || duration_tracker(method, caller, duration)).await;
+                    }
+                }
+
+                let Some(context) = pending.context else {
+                    return;
+                };
+                let mut context = context.with_principal_kind(ras_jsonrpc_core::PrincipalKind::of(caller));
+                if let Some(extractor) = &self.tenant_extractor {
+                    context = context.with_extracted_tenant(extractor, headers, caller);
+                }
+                if let Some(metrics) = &self.service_metrics {
+                    context = context.with_request_size(request_size).with_response_size(response_size);
+                    metrics.record_request_size(&context, request_size);
+                    metrics.record_response_size(&context, response_size);
+                }
+                if let Some(tracker) = &self.completion_tracker {
+                    let outcome = ras_jsonrpc_core::request_outcome(response);
+                    self.tracker_guard.call("completion_tracker", self.service_metrics.as_deref(), || ras_jsonrpc_core::MethodDurationTracker::track_completion(&**tracker, &context, caller, duration, outcome)).await;
+                }
+                if let Some(metrics) = &self.service_metrics {
+                    ras_jsonrpc_core::record_request_completed(metrics.as_ref(), context, response, duration);
+                }
+            }
         }
 
         impl<T: #service_trait_name> #builder_name<T> {
@@ -574,7 +1358,19 @@ fn generate_server_code(service_def: &ServiceDefinition) -> proc_macro2::TokenSt
                     service: std::sync::Arc::new(service),
                     auth_provider: None,
                     usage_tracker: None,
-                    method_duration_tracker: None,
+                    method_timeout: None,
+                    reporting: #reporting_name::default(),
+                    trusted_proxies: ras_jsonrpc_core::TrustedProxies::none(),
+                    max_batch_concurrency: ras_jsonrpc_core::DEFAULT_MAX_BATCH_CONCURRENCY,
+                    concurrency: ras_jsonrpc_core::ConcurrencyLimiter::new()
+                        #(.with_method_limit(#method_limit_names, #method_limit_values))*,
+                    tracing_spans: false,
+                    max_request_size: None,
+                    lenient_content_type: false,
+                    deprecation_warnings: false,
+                    manifest_permission: None,
+                    idempotency: ras_jsonrpc_core::Idempotency::default(),
+                    #(#authorize_inits)*
                 }
             }
 
@@ -597,89 +1393,327 @@ fn generate_server_code(service_def: &ServiceDefinition) -> proc_macro2::TokenSt
                 F: Fn(&axum::http::HeaderMap, Option<&ras_jsonrpc_core::AuthenticatedUser>, &ras_jsonrpc_types::JsonRpcRequest) -> Fut + Send + Sync + 'static,
                 Fut: std::future::Future<Output = ()> + Send + 'static,
             {
-                self.usage_tracker = Some(Box::new(move |headers, user, request| {
+                self.usage_tracker = Some(Box::new(move |headers, user, request, _request_size| {
                     Box::pin(tracker(headers, user, request))
                 }));
                 self
             }
 
             /// Set the method duration tracker function
-            /// This function will be called after each method completes with the method name, authenticated user (if any), and the duration
+            /// This function will be called after each call to a declared method completes with the method name,
+            /// authenticated user (if any), and the duration, timed from when the call was received. Calls
+            /// refused for a missing, invalid or expired token, missing permissions or invalid params are included
             pub fn with_method_duration_tracker<F, Fut>(mut self, tracker: F) -> Self
             where
                 F: Fn(&str, Option<&ras_jsonrpc_core::AuthenticatedUser>, std::time::Duration) -> Fut + Send + Sync + 'static,
                 Fut: std::future::Future<Output = ()> + Send + 'static,
             {
-                self.method_duration_tracker = Some(Box::new(move |method, user, duration| {
+                self.reporting.method_duration_tracker = Some(std::sync::Arc::new(move |method, user, duration| {
                     Box::pin(tracker(method, user, duration))
                 }));
                 self
             }
 
+            /// Set the method outcome tracker function
+            /// Like the duration tracker, but also told whether the call succeeded; calls
+            /// that are refused, fail or run past their timeout are reported with `false`.
+            /// It is installed as the completion tracker through `with_observability_config`,
+            /// replacing any set before, and is replaced by any set after.
+            pub fn with_method_outcome_tracker<F, Fut>(self, tracker: F) -> Self
+            where
+                F: Fn(&str, Option<&ras_jsonrpc_core::AuthenticatedUser>, std::time::Duration, bool) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = ()> + Send + 'static,
+            {
+                let duration_tracker: ras_jsonrpc_core::MethodDurationTrackerFn = std::sync::Arc::new(move |context, user, duration| {
+                    // Undeclared methods, reported to completion trackers as `unknown`, are not reported
+                    let method = context.method.as_str();
+                    if !(#known_method) {
+                        return Box::pin(std::future::ready(()));
+                    }
+                    let success = ras_jsonrpc_core::RequestOutcome::of(&context, true).is_success();
+                    Box::pin(tracker(method, user.as_ref(), duration, success))
+                });
+                self.with_observability_config(ras_jsonrpc_core::ObservabilityConfig {
+                    duration_tracker: Some(duration_tracker),
+                    ..Default::default()
+                })
+            }
+
+            /// Cut off method handlers that run longer than `timeout` with a
+            /// `request_timeout` error. Per-method `TIMEOUT(..)` annotations take precedence.
+            /// The results of `STREAMING` methods must all be sent within the timeout.
+            pub fn with_method_timeout(mut self, timeout: std::time::Duration) -> Self {
+                self.method_timeout = Some(timeout);
+                self
+            }
+
+            /// Set how many entries of a batch request are dispatched concurrently.
+            /// Responses are always returned in request order. Defaults to 16.
+            pub fn with_max_batch_concurrency(mut self, max_concurrency: usize) -> Self {
+                self.max_batch_concurrency = max_concurrency.max(1);
+                self
+            }
+
+            /// Limit how many requests execute concurrently across all methods.
+            /// Per-method `CONCURRENCY(n)` limits declared in the macro apply in addition.
+            pub fn with_max_concurrent_requests(mut self, max_concurrent_requests: usize) -> Self {
+                self.concurrency = self.concurrency.with_global_limit(max_concurrent_requests);
+                self
+            }
+
+            /// Set whether requests over a concurrency limit are rejected with `server_busy`
+            /// (the default) or queued for a bounded time.
+            pub fn with_overload_behavior(mut self, behavior: ras_jsonrpc_core::OverloadBehavior) -> Self {
+                self.concurrency = self.concurrency.with_overload_behavior(behavior);
+                self
+            }
+
+            /// Run each method call inside a `tracing` span named after the method.
+            /// Spans carry the protocol, request id, user id, permission result,
+            /// outcome and duration. Disabled by default.
+            pub fn with_tracing_spans(mut self, enabled: bool) -> Self {
+                self.tracing_spans = enabled;
+                self
+            }
+
+            /// Reject HTTP bodies larger than `bytes` before any JSON is parsed.
+            /// Per-method `MAX_REQUEST_SIZE(n)` limits apply in addition. Defaults to 2 MiB.
+            pub fn with_max_request_size(mut self, bytes: usize) -> Self {
+                self.max_request_size = Some(bytes);
+                self
+            }
+
+            /// Accept HTTP bodies whatever their `Content-Type`. By default only
+            /// `application/json` and `+json` media types are accepted and other
+            /// requests get HTTP 415 with a parse error.
+            pub fn with_lenient_content_type(mut self, lenient: bool) -> Self {
+                self.lenient_content_type = lenient;
+                self
+            }
+
+            /// Log calls of `#[deprecated]` methods and answer them with a
+            /// `Warning: 299 - "..."` header naming the method and the deprecation note.
+            pub fn with_deprecation_warnings(mut self, enabled: bool) -> Self {
+                self.deprecation_warnings = enabled;
+                self
+            }
+
+            /// Serve the service manifest as JSON on `GET {base_url}/_manifest` to callers
+            /// whose token carries `permission`. Requires an auth provider.
+            pub fn with_manifest_route(mut self, permission: impl Into<String>) -> Self {
+                self.manifest_permission = Some(permission.into());
+                self
+            }
+
+            /// Set the payload size tracker function
+            /// This function will be called after each method completes with the method name, request size and response size in bytes
+            pub fn with_payload_size_tracker<F, Fut>(mut self, tracker: F) -> Self
+            where
+                F: Fn(&str, usize, usize) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = ()> + Send + 'static,
+            {
+                self.reporting.payload_size_tracker = Some(std::sync::Arc::new(move |method, request_size, response_size| {
+                    Box::pin(tracker(method, request_size, response_size))
+                }));
+                self
+            }
+
+            /// Set where `IDEMPOTENT` methods store results for replay.
+            /// Defaults to an in-memory store.
+            pub fn with_idempotency_store<S: ras_jsonrpc_core::IdempotencyStore>(mut self, store: S) -> Self {
+                let ttl = self.idempotency.ttl();
+                self.idempotency = ras_jsonrpc_core::Idempotency::new(store).with_ttl(ttl);
+                self
+            }
+
+            /// Set how long `IDEMPOTENT` method results are replayed. Defaults to 24 hours.
+            pub fn with_idempotency_ttl(mut self, ttl: std::time::Duration) -> Self {
+                self.idempotency = self.idempotency.with_ttl(ttl);
+                self
+            }
+
+            /// Report each request to `metrics`: started before dispatch, then completed
+            /// with its duration and whether it succeeded. Failures (auth errors, invalid
+            /// params, unknown methods, handler errors, ...) carry an `error_kind`
+            /// metadata entry; calls to undeclared methods are reported as `unknown`.
+            pub fn with_service_metrics(mut self, metrics: std::sync::Arc<dyn ras_jsonrpc_core::ServiceMetrics>) -> Self {
+                self.reporting.service_metrics = Some(metrics);
+                self
+            }
+
+            /// Report each completed request to `tracker` with how it ended, through
+            /// `MethodDurationTracker::track_completion`: failures before the handler
+            /// runs (auth errors, invalid params, unknown methods, ...) included.
+            /// It is installed through `with_observability_config`, replacing any
+            /// completion tracker set before, and is replaced by any set after.
+            pub fn with_completion_tracker(self, tracker: std::sync::Arc<dyn ras_jsonrpc_core::MethodDurationTracker>) -> Self {
+                self.with_observability_config(ras_jsonrpc_core::ObservabilityConfig::default().with_completion_tracker(tracker))
+            }
+
+            /// Call the trackers through `guard`, rather than a guard of the service's
+            /// own with the default timeout. The guard catches tracker panics, cancels
+            /// trackers outliving its timeout, and counts both in the service metrics
+            /// as `usage_tracker`, `method_duration_tracker`, `payload_size_tracker` or
+            /// `completion_tracker`, which the outcome tracker is called as; keep a clone to report
+            /// its `health` from a readiness check.
+            pub fn with_tracker_guard(mut self, guard: ras_jsonrpc_core::TrackerGuard) -> Self {
+                self.reporting.tracker_guard = guard;
+                self
+            }
+
+            /// Believe the `X-Forwarded-For` addresses appended by `proxies` when
+            /// setting the client address of request contexts. By default none are
+            /// trusted and the client is the address that connected, known when the
+            /// router is served with `into_make_service_with_connect_info::<SocketAddr>()`.
+            pub fn with_trusted_proxies(mut self, proxies: ras_jsonrpc_core::TrustedProxies) -> Self {
+                self.trusted_proxies = proxies;
+                self
+            }
+
+            /// Record the tenant `extractor` names on the contexts given to the service
+            /// metrics, which may label their counters by it. It is called with the
+            /// request headers when a request starts, and with its authenticated caller
+            /// as well when it completes.
+            pub fn with_tenant_extractor<F>(mut self, extractor: F) -> Self
+            where
+                F: Fn(&axum::http::HeaderMap, Option<&ras_jsonrpc_core::AuthenticatedUser>, &ras_jsonrpc_core::RequestContext) -> Option<String> + Send + Sync + 'static,
+            {
+                self.reporting.tenant_extractor = Some(std::sync::Arc::new(extractor));
+                self
+            }
+
+            /// Report to `observability`, such as an `OtelSetup`: its service metrics
+            /// as with `with_service_metrics`, its usage tracker with a JSON-RPC
+            /// `RequestContext` of the method, and its duration tracker as with
+            /// `with_completion_tracker`. It is installed through `with_observability_config`.
+            pub fn with_observability(self, observability: &dyn ras_jsonrpc_core::Observability) -> Self {
+                self.with_observability_config(observability)
+            }
+
+            /// Report to what `config`, an `ObservabilityConfig` or the
+            /// `ObservabilityBuilder` making one, sets: its service metrics, its usage
+            /// tracker, its duration tracker as the completion tracker and its tenant
+            /// extractor. `with_observability`, `with_completion_tracker` and
+            /// `with_method_outcome_tracker` install theirs through it. The service has
+            /// one of each, so what the last call sets wins, and what it doesn't set is
+            /// left as it was. Clones of a config share its trackers, so one config can
+            /// drive several services.
+            pub fn with_observability_config(mut self, config: impl Into<ras_jsonrpc_core::ObservabilityConfig>) -> Self {
+                let config = config.into();
+                if let Some(metrics) = config.service_metrics.clone() {
+                    self.reporting.service_metrics = Some(metrics);
+                }
+                if let Some(tracker) = config.shared_usage_tracker() {
+                    self = self.with_context_usage_tracker(tracker);
+                }
+                if let Some(tracker) = config.shared_method_duration_tracker() {
+                    self.reporting.completion_tracker = Some(tracker);
+                }
+                if let Some(extractor) = config.tenant_extractor {
+                    self.reporting.tenant_extractor = Some(extractor);
+                }
+                self
+            }
+
+            /// Call `tracker` before each request with a JSON-RPC `RequestContext`
+            /// of the method
+            fn with_context_usage_tracker(mut self, tracker: std::sync::Arc<dyn ras_jsonrpc_core::UsageTracker>) -> Self {
+                self.usage_tracker = Some(Box::new(move |headers, user, request, request_size| {
+                    let tracker = tracker.clone();
+                    let headers = headers.clone();
+                    let user = user.cloned();
+                    let mut context = ras_jsonrpc_core::RequestContext::jsonrpc(request.method.clone()).with_request_info(&headers);
+                    if let Some(bytes) = request_size {
+                        context = context.with_request_size(bytes);
+                    }
+                    Box::pin(async move {
+                        ras_jsonrpc_core::UsageTracker::track_request(&*tracker, &headers, user.as_ref(), &context).await;
+                    })
+                }));
+                self
+            }
+
+            #(#authorize_setters)*
+
+            /// Handle to the number of requests currently executing per method
+            pub fn in_flight_requests(&self) -> ras_jsonrpc_core::InFlightRequests {
+                self.concurrency.in_flight()
+            }
+
             /// Build the axum router for the JSON-RPC service
             pub fn build(self) -> Result<axum::Router, String> {
                 let base_url = self.base_url.clone();
                 let service = std::sync::Arc::new(self);
+                #docs_auth_source
+                let manifest_source = service.clone();
 
-                let rpc_handler = axum::routing::post(move |headers: axum::http::HeaderMap, body: String| {
+                let route = base_url.clone();
+                let rpc_handler = axum::routing::post(move |peer: Result<axum::extract::ConnectInfo<std::net::SocketAddr>, axum::extract::rejection::ExtensionRejection>, headers: axum::http::HeaderMap, body: axum::body::Body| {
                     let service = service.clone();
-                    async move {
-                        let response = service.handle_request(headers, body).await;
-
-                        // Determine HTTP status code based on JSON-RPC error code
-                        // Map authentication/authorization errors to appropriate HTTP status codes
-                        // while maintaining JSON-RPC protocol compatibility
-                        let status_code = if let Some(ref error) = response.error {
-                            match error.code {
-                                ras_jsonrpc_types::error_codes::AUTHENTICATION_REQUIRED => axum::http::StatusCode::UNAUTHORIZED,
-                                ras_jsonrpc_types::error_codes::INSUFFICIENT_PERMISSIONS => axum::http::StatusCode::FORBIDDEN,
-                                ras_jsonrpc_types::error_codes::TOKEN_EXPIRED => axum::http::StatusCode::UNAUTHORIZED,
-                                _ => axum::http::StatusCode::OK, // Other JSON-RPC errors still return 200 OK
-                            }
-                        } else {
-                            axum::http::StatusCode::OK
-                        };
-
-                        (
-                            status_code,
-                            [("Content-Type", "application/json")],
-                            serde_json::to_string(&response).unwrap_or_else(|_| "{}".to_string())
-                        )
-                    }
+                    // Handled as from the client behind any trusted proxies, for the request contexts
+                    let peer = peer.ok().map(|axum::extract::ConnectInfo(peer)| peer.ip());
+                    let origin = ras_jsonrpc_core::RequestOrigin::new(ras_jsonrpc_core::client_ip(&headers, peer, &service.trusted_proxies), route.clone());
+                    origin.scope(async move {
+                        let max_batch_concurrency = service.max_batch_concurrency;
+                        let max_request_size = service.max_request_size;
+                        let lenient_content_type = service.lenient_content_type;
+                        ras_jsonrpc_core::handle_http_request(&*service, &headers, body, max_batch_concurrency, max_request_size, lenient_content_type).await
+                    })
                 });
+                #cors_integration
 
                 let mut router = axum::Router::new();
 
                 // Add the JSON-RPC endpoint
                 router = router.route(&base_url, rpc_handler);
 
+                // Serve the service manifest if enabled
+                if manifest_source.manifest_permission.is_some() {
+                    let manifest_path = format!("{}{}", base_url.trim_end_matches('/'), ras_jsonrpc_core::MANIFEST_PATH);
+                    router = router.route(&manifest_path, axum::routing::get(move |headers: axum::http::HeaderMap| {
+                        let service = manifest_source.clone();
+                        async move {
+                            let permission = service.manifest_permission.as_deref().unwrap_or_default();
+                            ras_jsonrpc_core::manifest_response(&#manifest_fn_name(), &headers, service.auth_provider.as_deref(), permission).await
+                        }
+                    }));
+                }
+
                 // Include explorer routes if explorer is enabled
                 #explorer_route_integration
 
                 Ok(router)
             }
 
-            async fn handle_request(&self, headers: axum::http::HeaderMap, body: String) -> ras_jsonrpc_types::JsonRpcResponse {
-                // Parse JSON-RPC request
-                let request: ras_jsonrpc_types::JsonRpcRequest = match serde_json::from_str(&body) {
+            /// Answer a request object, reporting it to the trackers and service metrics
+            async fn handle_request(&self, headers: &axum::http::HeaderMap, request: serde_json::Value, request_size: usize) -> ras_jsonrpc_types::JsonRpcResponse {
+                if !self.reporting.is_enabled() {
+                    return self.answer_request(headers, request, request_size).await.0;
+                }
+
+                let pending = self.reporting.start(headers, &request, request_size);
+                let (response, caller) = self.answer_request(headers, request, request_size).await;
+                self.reporting.finish(headers, pending, caller.as_ref(), &response, None).await;
+                response
+            }
+
+            /// Parse a request object, authenticate its caller and report it to the usage tracker
+            async fn prepare_request(&self, headers: &axum::http::HeaderMap, request: serde_json::Value, request_size: Option<usize>) -> Result<(ras_jsonrpc_types::JsonRpcRequest, Option<ras_jsonrpc_core::AuthenticatedUser>), ras_jsonrpc_types::JsonRpcResponse> {
+                // Parse JSON-RPC request object
+                let request: ras_jsonrpc_types::JsonRpcRequest = match serde_json::from_value(request) {
                     Ok(req) => req,
-                    Err(_) => return ras_jsonrpc_types::JsonRpcResponse::error(ras_jsonrpc_types::JsonRpcError::parse_error(), None),
+                    Err(_) => return Err(ras_jsonrpc_types::JsonRpcResponse::error(ras_jsonrpc_types::JsonRpcError::invalid_request(), None)),
                 };
 
-                let request_id = request.id.clone();
-
                 // Validate JSON-RPC version
                 if request.jsonrpc != "2.0" {
-                    return ras_jsonrpc_types::JsonRpcResponse::error(ras_jsonrpc_types::JsonRpcError::invalid_request(), request_id);
+                    return Err(ras_jsonrpc_types::JsonRpcResponse::error(ras_jsonrpc_types::JsonRpcError::invalid_request(), request.id.clone()));
                 }
 
                 // Try to authenticate user if auth provider is available
                 let auth_result = if let Some(auth_provider) = &self.auth_provider {
-                    if let Some(token) = headers
-                        .get("Authorization")
-                        .and_then(|h| h.to_str().ok())
-                        .and_then(|s| s.strip_prefix("Bearer ")) {
-                        Some(auth_provider.authenticate(token.to_string()).await)
+                    if let Some(token) = auth_provider.credential(headers) {
+                        Some(auth_provider.authenticate_request(token, headers).await)
                     } else {
                         None
                     }
@@ -689,11 +1723,16 @@ fn generate_server_code(service_def: &ServiceDefinition) -> proc_macro2::TokenSt
 
                 let authenticated_user = match auth_result {
                     Some(Ok(user)) => Some(user),
-                    Some(Err(ras_jsonrpc_core::AuthError::TokenExpired)) => {
-                        return ras_jsonrpc_types::JsonRpcResponse::error(
-                            ras_jsonrpc_types::JsonRpcError::token_expired(),
-                            request_id
-                        );
+                    // Tokens the client must renew, and callers told to wait, are refused
+                    // outright; other failures leave the caller anonymous
+                    Some(Err(error @ (ras_jsonrpc_core::AuthError::TokenExpired
+                        | ras_jsonrpc_core::AuthError::TokenNotYetValid
+                        | ras_jsonrpc_core::AuthError::AccountLocked { .. }
+                        | ras_jsonrpc_core::AuthError::RateLimited { .. }))) => {
+                        return Err(ras_jsonrpc_types::JsonRpcResponse::error(
+                            ras_jsonrpc_core::jsonrpc_auth_error(&error),
+                            request.id.clone()
+                        ));
                     }
                     _ => None,
                 };
@@ -701,18 +1740,58 @@ fn generate_server_code(service_def: &ServiceDefinition) -> proc_macro2::TokenSt
                 // Call usage tracker if configured
                 if let Some(tracker) = &self.usage_tracker {
                     let user_ref = authenticated_user.as_ref();
-                    tracker(&headers, user_ref, &request).await;
+                    self.reporting.tracker_guard.call("usage_tracker", self.reporting.service_metrics.as_deref(), || tracker(headers, user_ref, &request, request_size)).await;
                 }
 
+                Ok((request, authenticated_user))
+            }
+
+            #stream_dispatch_fn
+
+            /// Answer a request object, along with the caller that made it, if authenticated
+            async fn answer_request(&self, headers: &axum::http::HeaderMap, request: serde_json::Value, request_size: usize) -> (ras_jsonrpc_types::JsonRpcResponse, Option<ras_jsonrpc_core::AuthenticatedUser>) {
+                let (request, authenticated_user) = match self.prepare_request(headers, request, Some(request_size)).await {
+                    Ok(prepared) => prepared,
+                    Err(response) => return (response, None),
+                };
+                let request_id = request.id.clone();
+                let caller = authenticated_user.clone();
+
                 // Dispatch method
-                match request.method.as_str() {
+                let response = match request.method.as_str() {
                     #(#method_dispatch)*
                     _ => ras_jsonrpc_types::JsonRpcResponse::error(
                         ras_jsonrpc_types::JsonRpcError::method_not_found(&request.method),
                         request_id
                     )
+                };
+
+                (response, caller)
+            }
+        }
+
+        impl<T: #service_trait_name> ras_jsonrpc_core::JsonRpcService for #builder_name<T> {
+            fn method_names(&self) -> Vec<String> {
+                vec![#(#method_wire_names.to_string()),*]
+            }
+
+            fn dispatch<'a>(
+                &'a self,
+                headers: &'a axum::http::HeaderMap,
+                request: serde_json::Value,
+                request_size: usize,
+            ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ras_jsonrpc_types::JsonRpcResponse> + Send + 'a>> {
+                Box::pin(self.handle_request(headers, request, request_size))
+            }
+
+            fn deprecation_warning(&self, method: &str) -> Option<String> {
+                if !self.deprecation_warnings {
+                    return None;
                 }
+                #deprecation_warning_lookup
             }
+
+            #streaming_service_methods
         }
     }
 }
@@ -724,12 +1803,7 @@ fn jsonrpc_method_wire_name(method: &MethodDefinition) -> String {
         .unwrap_or_else(|| method.name.to_string())
 }
 
-fn jsonrpc_permission_groups_code(auth: &AuthRequirement) -> proc_macro2::TokenStream {
-    let permission_groups = match auth {
-        AuthRequirement::Unauthorized => Vec::new(),
-        AuthRequirement::WithPermissions(groups) => groups.clone(),
-    };
-
+fn jsonrpc_permission_groups_code(permission_groups: &[Vec<String>]) -> proc_macro2::TokenStream {
     if permission_groups.is_empty() {
         quote! { Vec::<Vec<String>>::new() }
     } else {
@@ -741,61 +1815,80 @@ fn jsonrpc_permission_groups_code(auth: &AuthRequirement) -> proc_macro2::TokenS
     }
 }
 
-fn jsonrpc_auth_check_code(
-    auth: &AuthRequirement,
-) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+fn jsonrpc_auth_check_code(auth: &AuthRequirement) -> proc_macro2::TokenStream {
     match auth {
-        AuthRequirement::Unauthorized => (quote! {}, quote! { None }),
-        AuthRequirement::WithPermissions(_) => {
-            let permission_groups_code = jsonrpc_permission_groups_code(auth);
-            (
-                quote! {
-                    let user = match &authenticated_user {
-                        Some(u) => u,
-                        None => return ras_jsonrpc_types::JsonRpcResponse::error(
-                            ras_jsonrpc_types::JsonRpcError::authentication_required(),
-                            request.id.clone()
-                        ),
-                    };
+        AuthRequirement::Unauthorized => quote! {},
+        AuthRequirement::WithPermissions(PermissionRequirement::Expression(expression)) => {
+            let expression_code = static_permission_expr_code(expression);
+            quote! {
+                let user = match &authenticated_user {
+                    Some(u) => u,
+                    None => return ras_jsonrpc_types::JsonRpcResponse::error(
+                        ras_jsonrpc_types::JsonRpcError::authentication_required(),
+                        request.id.clone()
+                    ),
+                };
+
+                let required_permissions: &ras_jsonrpc_core::PermissionExpr = #expression_code;
+                if let Err(error) = self.auth_provider
+                    .as_ref()
+                    .unwrap()
+                    .check_permission_expr(user, required_permissions)
+                {
+                    return ras_jsonrpc_types::JsonRpcResponse::error(
+                        ras_jsonrpc_core::jsonrpc_auth_error(&error),
+                        request.id.clone()
+                    );
+                }
+            }
+        }
+        AuthRequirement::WithPermissions(PermissionRequirement::Groups(groups)) => {
+            let permission_groups_code = jsonrpc_permission_groups_code(groups);
+            quote! {
+                let user = match &authenticated_user {
+                    Some(u) => u,
+                    None => return ras_jsonrpc_types::JsonRpcResponse::error(
+                        ras_jsonrpc_types::JsonRpcError::authentication_required(),
+                        request.id.clone()
+                    ),
+                };
 
-                    let required_permission_groups: Vec<Vec<String>> = #permission_groups_code;
-                    let has_non_empty_groups = required_permission_groups.iter().any(|g| !g.is_empty());
-                    if has_non_empty_groups {
-                        let mut has_permission = false;
+                let required_permission_groups: Vec<Vec<String>> = #permission_groups_code;
+                let has_non_empty_groups = required_permission_groups.iter().any(|g| !g.is_empty());
+                if has_non_empty_groups {
+                    let mut has_permission = false;
 
-                        for permission_group in &required_permission_groups {
-                            if permission_group.is_empty() {
+                    for permission_group in &required_permission_groups {
+                        if permission_group.is_empty() {
+                            has_permission = true;
+                            break;
+                        } else {
+                            let group_result = self.auth_provider
+                                .as_ref()
+                                .unwrap()
+                                .check_permissions(user, permission_group);
+                            if group_result.is_ok() {
                                 has_permission = true;
                                 break;
-                            } else {
-                                let group_result = self.auth_provider
-                                    .as_ref()
-                                    .unwrap()
-                                    .check_permissions(user, permission_group);
-                                if group_result.is_ok() {
-                                    has_permission = true;
-                                    break;
-                                }
                             }
                         }
+                    }
 
-                        if !has_permission {
-                            let first_group = required_permission_groups.iter()
-                                .find(|g| !g.is_empty())
-                                .cloned()
-                                .unwrap_or_default();
-                            return ras_jsonrpc_types::JsonRpcResponse::error(
-                                ras_jsonrpc_types::JsonRpcError::insufficient_permissions(
-                                    first_group,
-                                    user.permissions.iter().cloned().collect()
-                                ),
-                                request.id.clone()
-                            );
-                        }
+                    if !has_permission {
+                        let first_group = required_permission_groups.iter()
+                            .find(|g| !g.is_empty())
+                            .cloned()
+                            .unwrap_or_default();
+                        return ras_jsonrpc_types::JsonRpcResponse::error(
+                            ras_jsonrpc_types::JsonRpcError::insufficient_permissions(
+                                first_group,
+                                user.permissions.iter().cloned().collect()
+                            ),
+                            request.id.clone()
+                        );
                     }
-                },
-                quote! { Some(user) },
-            )
+                }
+            }
         }
     }
 }
@@ -803,10 +1896,23 @@ fn jsonrpc_auth_check_code(
 fn jsonrpc_parse_params_code(
     params_ident: &Ident,
     request_type: &Type,
+    positional: bool,
 ) -> proc_macro2::TokenStream {
+    // Positional methods map params arrays onto the request struct's fields first
+    let params_value = if positional {
+        quote! {
+            match ras_jsonrpc_core::positional_params::<#request_type>(params) {
+                Ok(params) => params,
+                Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(e, request.id.clone()),
+            }
+        }
+    } else {
+        quote! { params }
+    };
+
     quote! {
         let #params_ident: #request_type = match request.params {
-            Some(params) => match serde_json::from_value(params) {
+            Some(params) => match serde_json::from_value(#params_value) {
                 Ok(p) => p,
                 Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(
                     ras_jsonrpc_types::JsonRpcError::invalid_params(e.to_string()),
@@ -824,7 +1930,216 @@ fn jsonrpc_parse_params_code(
     }
 }
 
+/// Name of the builder field and setter holding a method's authorization callback
+fn jsonrpc_authorize_ident(method: &MethodDefinition) -> Ident {
+    quote::format_ident!("authorize_{}", method.name)
+}
+
+/// The builder field and setter of a `WITH_PERMISSIONS` method's
+/// authorization callback, and how `new` initializes the field
+fn jsonrpc_authorize_builder_code(
+    method: &MethodDefinition,
+) -> Option<(
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+)> {
+    if matches!(method.auth, AuthRequirement::Unauthorized) {
+        return None;
+    }
+
+    let ident = jsonrpc_authorize_ident(method);
+    let request_type = &method.request_type;
+    let doc = format!(
+        "Authorize `{}` calls past their permission check, such as letting users edit only \
+         their own records. The callback gets the user and the canonical params; refusing \
+         answers with an insufficient permissions error carrying the `access_denied` code.",
+        method.name
+    );
+
+    Some((
+        quote! {
+            #ident: Option<Box<dyn Fn(&ras_jsonrpc_core::AuthenticatedUser, &#request_type) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ras_jsonrpc_core::AccessDenied>> + Send>> + Send + Sync>>,
+        },
+        quote! {
+            #[doc = #doc]
+            pub fn #ident<F, Fut>(mut self, authorize: F) -> Self
+            where
+                F: Fn(&ras_jsonrpc_core::AuthenticatedUser, &#request_type) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = Result<(), ras_jsonrpc_core::AccessDenied>> + Send + 'static,
+            {
+                self.#ident = Some(Box::new(
+                    move |user: &ras_jsonrpc_core::AuthenticatedUser, params: &#request_type|
+                        -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), ras_jsonrpc_core::AccessDenied>> + Send>> {
+                        Box::pin(authorize(user, params))
+                    },
+                ));
+                self
+            }
+        },
+        quote! { #ident: None, },
+    ))
+}
+
+/// Runs the method's authorization callback on its canonical params, if set,
+/// refusing the call when it denies
+fn jsonrpc_authorize_call_code(
+    method: &MethodDefinition,
+    params_ident: &Ident,
+) -> proc_macro2::TokenStream {
+    match method.auth {
+        AuthRequirement::Unauthorized => quote! {},
+        AuthRequirement::WithPermissions(_) => {
+            let ident = jsonrpc_authorize_ident(method);
+            quote! {
+                if let Some(authorize) = &self.#ident {
+                    if let Err(denied) = authorize(user, &#params_ident).await {
+                        return ras_jsonrpc_types::JsonRpcResponse::error(
+                            ras_jsonrpc_core::jsonrpc_access_denied(&denied),
+                            request.id.clone()
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn jsonrpc_request_size_check(method: &MethodDefinition) -> proc_macro2::TokenStream {
+    match method.max_request_size {
+        Some(max_request_size) => quote! {
+            if request_size > #max_request_size {
+                return ras_jsonrpc_types::JsonRpcResponse::error(
+                    ras_jsonrpc_types::JsonRpcError::request_too_large(Some(request_size), #max_request_size),
+                    request.id.clone()
+                );
+            }
+        },
+        None => quote! {},
+    }
+}
+
+/// The timeout a method's handler runs under: its `TIMEOUT(..)` annotation,
+/// falling back to the builder's default.
+fn jsonrpc_handler_timeout(method: &MethodDefinition) -> proc_macro2::TokenStream {
+    match method.timeout {
+        Some(timeout) => {
+            let millis = timeout.as_millis() as u64;
+            quote! { Some(std::time::Duration::from_millis(#millis)) }
+        }
+        None => quote! { self.method_timeout },
+    }
+}
+
+/// Code reading a method's idempotency key, claiming it once the call is
+/// authorized, and storing its serialized result after the handler runs.
+fn jsonrpc_idempotency_code(
+    method: &MethodDefinition,
+    method_wire: &str,
+) -> (
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+) {
+    if !method.idempotent {
+        return (quote! {}, quote! {}, quote! {});
+    }
+
+    (
+        // Read before parsing moves the params
+        quote! {
+            let idempotency_request = ras_jsonrpc_core::idempotency_key(headers, request.params.as_ref())
+                .map(|key| (key, request.params.clone()));
+        },
+        quote! {
+            let idempotency_lease = match idempotency_request {
+                Some((key, params)) => {
+                    let user_id = authenticated_user.as_ref().map(|u| u.user_id.as_str());
+                    match self.idempotency.claim(#method_wire, user_id, &key, params.as_ref()).await {
+                        Ok(ras_jsonrpc_core::IdempotencyClaim::Replay(result)) => {
+                            return ras_jsonrpc_types::JsonRpcResponse::success(result, request.id.clone());
+                        }
+                        Ok(ras_jsonrpc_core::IdempotencyClaim::Execute(lease)) => Some(lease),
+                        Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(e, request.id.clone()),
+                    }
+                }
+                None => None,
+            };
+        },
+        quote! {
+            if let Some(lease) = idempotency_lease {
+                lease.complete(&result_value).await;
+            }
+        },
+    )
+}
+
+/// Creates the span a method's call runs inside, when spans are enabled.
+fn jsonrpc_span_code(method_wire: &str) -> proc_macro2::TokenStream {
+    quote! {
+        let span = if self.tracing_spans {
+            let span = ras_jsonrpc_core::tracing::info_span!(
+                #method_wire,
+                protocol = "jsonrpc",
+                rpc.method = #method_wire,
+                request_id = %ras_jsonrpc_core::span_request_id(request.id.as_ref()),
+                user_id = authenticated_user.as_ref().map(|u| u.user_id.as_str()),
+                permission = ras_jsonrpc_core::tracing::field::Empty,
+                outcome = ras_jsonrpc_core::tracing::field::Empty,
+                error_code = ras_jsonrpc_core::tracing::field::Empty,
+                duration_ms = ras_jsonrpc_core::tracing::field::Empty,
+                otel.status_code = ras_jsonrpc_core::tracing::field::Empty,
+                traceparent = headers.get("traceparent").and_then(|value| value.to_str().ok()),
+            );
+            ras_jsonrpc_core::set_span_parent(&span, headers);
+            span
+        } else {
+            ras_jsonrpc_core::tracing::Span::none()
+        };
+    }
+}
+
+/// Wraps a dispatch body in its match arm, running it inside the method's span.
+fn jsonrpc_dispatch_arm(
+    method_wire: &str,
+    auth: &AuthRequirement,
+    body: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let requires_auth = matches!(auth, AuthRequirement::WithPermissions(_));
+    let span = jsonrpc_span_code(method_wire);
+
+    quote! {
+        #method_wire => {
+            #span
+
+            let span_start = std::time::Instant::now();
+            let response = ras_jsonrpc_core::tracing::Instrument::instrument(
+                async move { #body },
+                span.clone(),
+            )
+            .await;
+            ras_jsonrpc_core::record_span_outcome(&span, &response, #requires_auth, span_start.elapsed());
+            response
+        }
+    }
+}
+
 fn generate_jsonrpc_method_dispatches(method: &MethodDefinition) -> Vec<proc_macro2::TokenStream> {
+    // Streamed results can only be sent as the whole body of an HTTP response
+    if method.streaming {
+        let method_wire = jsonrpc_method_wire_name(method);
+        return vec![quote! {
+            #method_wire => ras_jsonrpc_types::JsonRpcResponse::error(
+                ras_jsonrpc_types::JsonRpcError::new(
+                    ras_jsonrpc_types::error_codes::INVALID_REQUEST,
+                    "Streaming methods must be called in a single HTTP request".to_string(),
+                    Some(serde_json::json!({ "method": #method_wire })),
+                ),
+                request_id.clone()
+            ),
+        }];
+    }
+
     let mut dispatches = vec![generate_jsonrpc_canonical_dispatch(method)];
     dispatches.extend(
         method
@@ -835,51 +2150,147 @@ fn generate_jsonrpc_method_dispatches(method: &MethodDefinition) -> Vec<proc_mac
     dispatches
 }
 
-fn generate_jsonrpc_canonical_dispatch(method: &MethodDefinition) -> proc_macro2::TokenStream {
+/// Match arm of `answer_stream_request` for a `STREAMING` method
+fn generate_jsonrpc_stream_dispatch(method: &MethodDefinition) -> proc_macro2::TokenStream {
     let method_name = &method.name;
     let method_wire = jsonrpc_method_wire_name(method);
-    let request_type = &method.request_type;
+    let limit_key = &method_wire;
     let params_ident = quote::format_ident!("params");
-    let parse_params = jsonrpc_parse_params_code(&params_ident, request_type);
-    let (auth_check, tracker_user) = jsonrpc_auth_check_code(&method.auth);
+    let parse_params = jsonrpc_parse_params_code(
+        &params_ident,
+        &method.request_type,
+        method.positional_params,
+    );
+    let auth_check = jsonrpc_auth_check_code(&method.auth);
+    let authorize_call = jsonrpc_authorize_call_code(method, &params_ident);
+    let size_check = jsonrpc_request_size_check(method);
+    let handler_timeout = jsonrpc_handler_timeout(method);
+    let requires_auth = matches!(method.auth, AuthRequirement::WithPermissions(_));
+    let span = jsonrpc_span_code(&method_wire);
 
     let handler_call = match &method.auth {
-        AuthRequirement::Unauthorized => quote! { self.service.#method_name(#params_ident).await },
+        AuthRequirement::Unauthorized => quote! { self.service.#method_name(#params_ident) },
         AuthRequirement::WithPermissions(_) => {
-            quote! { self.service.#method_name(user, #params_ident).await }
+            quote! { self.service.#method_name(user, #params_ident) }
         }
     };
 
+    // The checks return their error response from the async block, leaving `started` unset
     quote! {
         #method_wire => {
-            #auth_check
-            #parse_params
-
-            let start_time = std::time::Instant::now();
-            let handler_result = #handler_call;
-            let duration = start_time.elapsed();
+            #span
+
+            let span_start = std::time::Instant::now();
+            let request_id = request.id.clone();
+            let mut started = None;
+            let rejection = ras_jsonrpc_core::tracing::Instrument::instrument(
+                async {
+                    #size_check
+                    #auth_check
+                    #parse_params
+                    #authorize_call
+
+                    let permit = match self.concurrency.acquire(#limit_key).await {
+                        Ok(permit) => permit,
+                        Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(e, request.id.clone()),
+                    };
 
-            if let Some(duration_tracker) = &self.method_duration_tracker {
-                duration_tracker(#method_wire, #tracker_user, duration).await;
+                    let scoped_user = authenticated_user.clone().map(std::sync::Arc::new);
+                    let chunks = ras_jsonrpc_core::scope_user(scoped_user.clone(), async { #handler_call }).await;
+                    let chunks = match ras_jsonrpc_core::with_stream_deadline(chunks, #handler_timeout, headers) {
+                        Ok(chunks) => chunks,
+                        Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(e, request.id.clone()),
+                    };
+                    started = Some((ras_jsonrpc_core::scope_stream(chunks, scoped_user, span.clone()), permit));
+                    ras_jsonrpc_types::JsonRpcResponse::success(serde_json::Value::Null, None)
+                },
+                span.clone(),
+            )
+            .await;
+
+            match started {
+                // The permit is held, and the span's outcome left unset, until the last frame is sent
+                Some((chunks, permit)) => Ok(ras_jsonrpc_core::on_stream_end(
+                    ras_jsonrpc_core::stream_frames(request_id, chunks),
+                    move |response, _| {
+                        drop(permit);
+                        ras_jsonrpc_core::record_span_outcome(&span, &response, #requires_auth, span_start.elapsed());
+                        std::future::ready(())
+                    },
+                )),
+                None => {
+                    ras_jsonrpc_core::record_span_outcome(&span, &rejection, #requires_auth, span_start.elapsed());
+                    Err(rejection)
+                }
             }
+        }
+    }
+}
 
-            match handler_result {
-                Ok(result) => {
-                    match serde_json::to_value(result) {
-                        Ok(result_value) => ras_jsonrpc_types::JsonRpcResponse::success(result_value, request.id.clone()),
-                        Err(e) => ras_jsonrpc_types::JsonRpcResponse::error(
-                            ras_jsonrpc_types::JsonRpcError::internal_error(e.to_string()),
-                            request.id.clone()
-                        ),
+fn generate_jsonrpc_canonical_dispatch(method: &MethodDefinition) -> proc_macro2::TokenStream {
+    let method_name = &method.name;
+    let method_wire = jsonrpc_method_wire_name(method);
+    let limit_key = &method_wire;
+    let request_type = &method.request_type;
+    let params_ident = quote::format_ident!("params");
+    let parse_params =
+        jsonrpc_parse_params_code(&params_ident, request_type, method.positional_params);
+    let auth_check = jsonrpc_auth_check_code(&method.auth);
+    let authorize_call = jsonrpc_authorize_call_code(method, &params_ident);
+    let size_check = jsonrpc_request_size_check(method);
+    let (idempotency_read, idempotency_claim, idempotency_complete) =
+        jsonrpc_idempotency_code(method, &method_wire);
+
+    let handler_call = match &method.auth {
+        AuthRequirement::Unauthorized => quote! { self.service.#method_name(#params_ident) },
+        AuthRequirement::WithPermissions(_) => {
+            quote! { self.service.#method_name(user, #params_ident) }
+        }
+    };
+    let handler_timeout = jsonrpc_handler_timeout(method);
+
+    let body = quote! {
+        #size_check
+        #auth_check
+        #idempotency_read
+        #parse_params
+        #authorize_call
+        #idempotency_claim
+
+        let _permit = match self.concurrency.acquire(#limit_key).await {
+            Ok(permit) => permit,
+            Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(e, request.id.clone()),
+        };
+
+        let handler_call = ras_jsonrpc_core::scope_user(authenticated_user.clone().map(std::sync::Arc::new), #handler_call);
+        let handler_result = ras_jsonrpc_core::with_request_deadline(handler_call, #handler_timeout, headers).await;
+
+        let handler_result = match handler_result {
+            Ok(handler_result) => handler_result,
+            Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(e, request.id.clone()),
+        };
+
+        match handler_result {
+            Ok(result) => {
+                match serde_json::to_value(result) {
+                    Ok(result_value) => {
+                        #idempotency_complete
+                        ras_jsonrpc_types::JsonRpcResponse::success(result_value, request.id.clone())
                     }
+                    Err(e) => ras_jsonrpc_types::JsonRpcResponse::error(
+                        ras_jsonrpc_types::JsonRpcError::internal_error(e.to_string()),
+                        request.id.clone()
+                    ),
                 }
-                Err(e) => ras_jsonrpc_types::JsonRpcResponse::error(
-                    ras_jsonrpc_types::JsonRpcError::internal_error(e.to_string()),
-                    request.id.clone()
-                ),
             }
+            Err(e) => ras_jsonrpc_types::JsonRpcResponse::error(
+                ras_jsonrpc_core::jsonrpc_error_from_handler(e),
+                request.id.clone()
+            ),
         }
-    }
+    };
+
+    jsonrpc_dispatch_arm(&method_wire, &method.auth, body)
 }
 
 fn generate_jsonrpc_legacy_dispatch(
@@ -888,6 +2299,8 @@ fn generate_jsonrpc_legacy_dispatch(
 ) -> proc_macro2::TokenStream {
     let method_name = &method.name;
     let method_wire = &version.wire_name;
+    // Legacy versions share the canonical method's concurrency limit
+    let limit_key = jsonrpc_method_wire_name(method);
     let canonical_request_type = &method.request_type;
     let canonical_response_type = &method.response_type;
     let legacy_request_type = &version.request_type;
@@ -895,62 +2308,83 @@ fn generate_jsonrpc_legacy_dispatch(
     let migration_type = &version.migration_type;
     let legacy_params_ident = quote::format_ident!("legacy_params");
     let params_ident = quote::format_ident!("params");
-    let parse_params = jsonrpc_parse_params_code(&legacy_params_ident, legacy_request_type);
-    let (auth_check, tracker_user) = jsonrpc_auth_check_code(&method.auth);
+    let parse_params = jsonrpc_parse_params_code(
+        &legacy_params_ident,
+        legacy_request_type,
+        method.positional_params,
+    );
+    let auth_check = jsonrpc_auth_check_code(&method.auth);
+    let authorize_call = jsonrpc_authorize_call_code(method, &params_ident);
+    let size_check = jsonrpc_request_size_check(method);
+    let (idempotency_read, idempotency_claim, idempotency_complete) =
+        jsonrpc_idempotency_code(method, method_wire);
 
     let handler_call = match &method.auth {
-        AuthRequirement::Unauthorized => quote! { self.service.#method_name(#params_ident).await },
+        AuthRequirement::Unauthorized => quote! { self.service.#method_name(#params_ident) },
         AuthRequirement::WithPermissions(_) => {
-            quote! { self.service.#method_name(user, #params_ident).await }
+            quote! { self.service.#method_name(user, #params_ident) }
         }
     };
+    let handler_timeout = jsonrpc_handler_timeout(method);
 
-    quote! {
-        #method_wire => {
-            #auth_check
-            #parse_params
-
-            let #params_ident: #canonical_request_type =
-                match <#migration_type as ras_jsonrpc_core::VersionMigration<#legacy_request_type, #canonical_request_type>>::migrate(#legacy_params_ident) {
-                    Ok(params) => params,
-                    Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(
-                        ras_jsonrpc_types::JsonRpcError::invalid_params(e.to_string()),
-                        request.id.clone()
-                    ),
-                };
+    let body = quote! {
+        #size_check
+        #auth_check
+        #idempotency_read
+        #parse_params
 
-            let start_time = std::time::Instant::now();
-            let handler_result = #handler_call;
-            let duration = start_time.elapsed();
+        let #params_ident: #canonical_request_type =
+            match <#migration_type as ras_jsonrpc_core::VersionMigration<#legacy_request_type, #canonical_request_type>>::migrate(#legacy_params_ident) {
+                Ok(params) => params,
+                Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(
+                    ras_jsonrpc_types::JsonRpcError::invalid_params(e.to_string()),
+                    request.id.clone()
+                ),
+            };
+        #authorize_call
+        #idempotency_claim
 
-            if let Some(duration_tracker) = &self.method_duration_tracker {
-                duration_tracker(#method_wire, #tracker_user, duration).await;
-            }
+        let _permit = match self.concurrency.acquire(#limit_key).await {
+            Ok(permit) => permit,
+            Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(e, request.id.clone()),
+        };
 
-            match handler_result {
-                Ok(result) => {
-                    let result: #legacy_response_type =
-                        match <#migration_type as ras_jsonrpc_core::VersionMigration<#canonical_response_type, #legacy_response_type>>::migrate(result) {
-                            Ok(result) => result,
-                            Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(
-                                ras_jsonrpc_types::JsonRpcError::internal_error(e.to_string()),
-                                request.id.clone()
-                            ),
-                        };
+        let handler_call = ras_jsonrpc_core::scope_user(authenticated_user.clone().map(std::sync::Arc::new), #handler_call);
+        let handler_result = ras_jsonrpc_core::with_request_deadline(handler_call, #handler_timeout, headers).await;
+
+        let handler_result = match handler_result {
+            Ok(handler_result) => handler_result,
+            Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(e, request.id.clone()),
+        };
 
-                    match serde_json::to_value(result) {
-                        Ok(result_value) => ras_jsonrpc_types::JsonRpcResponse::success(result_value, request.id.clone()),
-                        Err(e) => ras_jsonrpc_types::JsonRpcResponse::error(
+        match handler_result {
+            Ok(result) => {
+                let result: #legacy_response_type =
+                    match <#migration_type as ras_jsonrpc_core::VersionMigration<#canonical_response_type, #legacy_response_type>>::migrate(result) {
+                        Ok(result) => result,
+                        Err(e) => return ras_jsonrpc_types::JsonRpcResponse::error(
                             ras_jsonrpc_types::JsonRpcError::internal_error(e.to_string()),
                             request.id.clone()
                         ),
+                    };
+
+                match serde_json::to_value(result) {
+                    Ok(result_value) => {
+                        #idempotency_complete
+                        ras_jsonrpc_types::JsonRpcResponse::success(result_value, request.id.clone())
                     }
+                    Err(e) => ras_jsonrpc_types::JsonRpcResponse::error(
+                        ras_jsonrpc_types::JsonRpcError::internal_error(e.to_string()),
+                        request.id.clone()
+                    ),
                 }
-                Err(e) => ras_jsonrpc_types::JsonRpcResponse::error(
-                    ras_jsonrpc_types::JsonRpcError::internal_error(e.to_string()),
-                    request.id.clone()
-                ),
             }
+            Err(e) => ras_jsonrpc_types::JsonRpcResponse::error(
+                ras_jsonrpc_core::jsonrpc_error_from_handler(e),
+                request.id.clone()
+            ),
         }
-    }
+    };
+
+    jsonrpc_dispatch_arm(method_wire, &method.auth, body)
 }
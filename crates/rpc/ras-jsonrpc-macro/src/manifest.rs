@@ -0,0 +1,124 @@
+//! Service manifest generation module
+//!
+//! Generates the `{servicename}_service_manifest()` function listing every method
+//! with its auth requirement.
+
+use crate::{
+    AuthRequirement, MethodDefinition, PermissionRequirement, ServiceDefinition,
+    jsonrpc_method_wire_name,
+};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Type;
+
+/// Generates the service manifest function
+pub fn generate_manifest_code(service_def: &ServiceDefinition) -> TokenStream {
+    let service_name = &service_def.service_name;
+    let service_name_str = service_name.to_string();
+    let manifest_fn_name =
+        quote::format_ident!("{}_service_manifest", service_name_str.to_lowercase());
+
+    let operations = service_def.methods.iter().flat_map(|method| {
+        let canonical = operation_code(
+            method,
+            jsonrpc_method_wire_name(method),
+            &method.request_type,
+            &method.response_type,
+            method.version.as_deref(),
+        );
+        let versions = method.versions.iter().map(move |version| {
+            operation_code(
+                method,
+                version.wire_name.clone(),
+                &version.request_type,
+                &version.response_type,
+                Some(&version.version),
+            )
+        });
+        std::iter::once(canonical).chain(versions)
+    });
+
+    quote! {
+        /// Describe every method of the service with its auth requirement
+        pub fn #manifest_fn_name() -> ras_jsonrpc_core::ServiceManifest {
+            ras_jsonrpc_core::ServiceManifest {
+                service_name: #service_name_str.to_string(),
+                protocol: ras_jsonrpc_core::ServiceProtocol::JsonRpc,
+                operations: vec![#(#operations),*],
+            }
+        }
+    }
+}
+
+fn operation_code(
+    method: &MethodDefinition,
+    wire_name: String,
+    request_type: &Type,
+    response_type: &Type,
+    version: Option<&str>,
+) -> TokenStream {
+    let (auth, permission_groups) = match &method.auth {
+        AuthRequirement::Unauthorized => (
+            quote! { ras_jsonrpc_core::AuthMode::Unauthorized },
+            quote! { Vec::new() },
+        ),
+        AuthRequirement::WithPermissions(PermissionRequirement::Expression(_)) => (
+            quote! { ras_jsonrpc_core::AuthMode::Permissions },
+            quote! { Vec::new() },
+        ),
+        AuthRequirement::WithPermissions(PermissionRequirement::Groups(groups)) => {
+            let groups = groups.iter().map(|group| {
+                quote! { vec![#(#group.to_string()),*] }
+            });
+            let permission_groups = quote! { vec![#(#groups),*] };
+            (
+                quote! { ras_jsonrpc_core::AuthMode::for_permission_groups(&permission_groups) },
+                permission_groups,
+            )
+        }
+    };
+    let permission_expression = match &method.auth {
+        AuthRequirement::WithPermissions(PermissionRequirement::Expression(expression)) => {
+            let expression = expression.to_string();
+            quote! { Some(#expression.to_string()) }
+        }
+        _ => quote! { None },
+    };
+    let request_type = type_name(request_type);
+    let response_type = type_name(response_type);
+    let docs = match &method.docs {
+        Some(docs) => {
+            let description = &docs.description;
+            quote! { Some(#description.to_string()) }
+        }
+        None => quote! { None },
+    };
+    let version = match version {
+        Some(version) => quote! { Some(#version.to_string()) },
+        None => quote! { None },
+    };
+    let deprecated = method.deprecation.is_some();
+
+    quote! {
+        {
+            let permission_groups: Vec<Vec<String>> = #permission_groups;
+            ras_jsonrpc_core::OperationManifest {
+                name: #wire_name.to_string(),
+                http_method: None,
+                path: None,
+                auth: #auth,
+                permission_groups,
+                permission_expression: #permission_expression,
+                request_type: Some(#request_type.to_string()),
+                response_type: #response_type.to_string(),
+                docs: #docs,
+                version: #version,
+                deprecated: #deprecated,
+            }
+        }
+    }
+}
+
+fn type_name(ty: &Type) -> String {
+    quote!(#ty).to_string().replace(' ', "")
+}
@@ -3,7 +3,7 @@
 //! This module provides functionality to generate OpenRPC specification documents
 //! from the jsonrpc_service macro definitions.
 
-use crate::{AuthRequirement, OpenRpcConfig, ServiceDefinition};
+use crate::{AuthRequirement, OpenRpcConfig, PermissionRequirement, ServiceDefinition};
 use proc_macro2::TokenStream;
 use quote::quote;
 use std::collections::HashMap;
@@ -40,10 +40,12 @@ pub fn generate_openrpc_code(
     };
 
     // Generate unique function names for each service
-    let flatten_fn_name = quote::format_ident!(
-        "_flatten_schema_defs_{}",
+    let merge_components_fn_name = quote::format_ident!(
+        "_merge_schema_components_{}",
         service_name.to_string().to_lowercase()
     );
+    let schema_hash_fn_name =
+        quote::format_ident!("_schema_hash_{}", service_name.to_string().to_lowercase());
     let update_refs_fn_name = quote::format_ident!(
         "_update_refs_recursive_{}",
         service_name.to_string().to_lowercase()
@@ -53,8 +55,9 @@ pub fn generate_openrpc_code(
         service_name.to_string().to_lowercase()
     );
 
-    // Collect unique types for schema generation
-    let mut unique_types = std::collections::HashMap::new();
+    // Collect unique types for schema generation, in a stable order so colliding
+    // definitions are qualified the same way on every build
+    let mut unique_types = std::collections::BTreeMap::new();
     for method in &service_def.methods {
         let request_type = &method.request_type;
         let response_type = &method.response_type;
@@ -93,19 +96,18 @@ pub fn generate_openrpc_code(
                         .replace(" ", "_")
                 );
                 quote! {
-                    fn #fn_name() -> (serde_json::Value, std::collections::HashMap<String, serde_json::Value>) {
-                        let schema = schemars::schema_for!(#type_tokens);
+                    fn #fn_name() -> (serde_json::Value, serde_json::Map<String, serde_json::Value>) {
+                        // A shared generator references named types instead of inlining them
+                        let mut generator = schemars::SchemaGenerator::default();
+                        let schema = generator.subschema_for::<#type_tokens>();
+                        let definitions = generator.take_definitions(true);
                         let schema_value = serde_json::to_value(&schema).unwrap_or_else(|_| {
                             serde_json::json!({
                                 "type": "object",
                                 "description": format!("Schema for {}", #type_name)
                             })
                         });
-
-                        // Extract $defs and flatten them
-                        let mut extracted_defs = std::collections::HashMap::new();
-                        let flattened_schema = #flatten_fn_name(schema_value, &mut extracted_defs);
-                        (flattened_schema, extracted_defs)
+                        (schema_value, definitions)
                     }
                 }
             }
@@ -118,7 +120,7 @@ pub fn generate_openrpc_code(
         .map(|type_name| {
             if type_name == "()" {
                 quote! {
-                    schemas.insert("()".to_string(), serde_json::json!({
+                    type_schemas.insert("()".to_string(), serde_json::json!({
                         "type": "null",
                         "description": "Unit type"
                     }));
@@ -137,13 +139,10 @@ pub fn generate_openrpc_code(
                     let (schema, defs) = #fn_name();
                     // Sanitize the type name by removing spaces
                     let sanitized_name = #type_name.to_string().replace(" ", "");
-                    schemas.insert(sanitized_name, schema);
-                    // Merge extracted defs into the main schemas collection
-                    for (def_name, def_schema) in defs {
-                        // Also sanitize def names
-                        let sanitized_def_name = def_name.replace(" ", "");
-                        schemas.insert(sanitized_def_name, def_schema);
-                    }
+                    type_schemas.insert(
+                        sanitized_name,
+                        #merge_components_fn_name(schema, defs, &mut schemas),
+                    );
                 }
             }
         })
@@ -166,12 +165,51 @@ pub fn generate_openrpc_code(
             let auth_required = matches!(method.auth, AuthRequirement::WithPermissions(_));
             // Flatten permission groups for OpenRPC documentation
             let permissions = match &method.auth {
-                AuthRequirement::Unauthorized => vec![],
-                AuthRequirement::WithPermissions(groups) => {
+                AuthRequirement::Unauthorized
+                | AuthRequirement::WithPermissions(PermissionRequirement::Expression(_)) => vec![],
+                AuthRequirement::WithPermissions(PermissionRequirement::Groups(groups)) => {
                     // For OpenRPC docs, flatten all permission groups into a single list
                     groups.iter().flatten().cloned().collect()
                 }
             };
+            // Expressions are documented as written, since NOT has no list form
+            let permission_expression = match &method.auth {
+                AuthRequirement::WithPermissions(PermissionRequirement::Expression(expression)) => {
+                    optional_string_tokens(Some(&expression.to_string()))
+                }
+                _ => quote! { None },
+            };
+
+            let error_codes: Vec<i32> = method.errors.iter().map(|error| error.code).collect();
+            let error_messages: Vec<&str> = method
+                .errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect();
+
+            let positional_params = method.positional_params;
+            let streaming = method.streaming;
+            let since = optional_string_tokens(method.since.as_ref());
+            let deprecated = method.deprecation.is_some();
+            let deprecated_since = optional_string_tokens(
+                method
+                    .deprecation
+                    .as_ref()
+                    .and_then(|deprecation| deprecation.since.as_ref()),
+            );
+            let deprecation_note = optional_string_tokens(
+                method
+                    .deprecation
+                    .as_ref()
+                    .and_then(|deprecation| deprecation.note.as_ref()),
+            );
+            let timeout_ms = match method.timeout {
+                Some(timeout) => {
+                    let millis = timeout.as_millis() as u64;
+                    quote! { Some(#millis) }
+                }
+                None => quote! { None },
+            };
 
             let request_type = &method.request_type;
             let response_type = &method.response_type;
@@ -194,11 +232,20 @@ pub fn generate_openrpc_code(
                     description: #description,
                     auth_required: #auth_required,
                     permissions: vec![#(#permissions.to_string()),*],
+                    permission_expression: #permission_expression,
                     request_type_name: stringify!(#request_type).to_string(),
                     response_type_name: stringify!(#response_type).to_string(),
                     version: #canonical_version_tokens,
                     canonical_version: #canonical_version_tokens,
                     canonical_method: #canonical_method_name.to_string(),
+                    errors: vec![#((#error_codes, #error_messages.to_string())),*],
+                    timeout_ms: #timeout_ms,
+                    positional_params: #positional_params,
+                    streaming: #streaming,
+                    since: #since,
+                    deprecated: #deprecated,
+                    deprecated_since: #deprecated_since,
+                    deprecation_note: #deprecation_note,
                 }
             }];
 
@@ -212,8 +259,15 @@ pub fn generate_openrpc_code(
                     .unwrap_or_else(|| "current".to_string());
                 let canonical_method_name = canonical_method_name.clone();
                 let permissions = permissions.clone();
+                let permission_expression = permission_expression.clone();
                 let summary = summary.clone();
                 let description = description.clone();
+                let error_codes = error_codes.clone();
+                let error_messages = error_messages.clone();
+                let timeout_ms = timeout_ms.clone();
+                let since = since.clone();
+                let deprecated_since = deprecated_since.clone();
+                let deprecation_note = deprecation_note.clone();
 
                 quote! {
                     #method_info_struct_name {
@@ -222,11 +276,20 @@ pub fn generate_openrpc_code(
                         description: #description,
                         auth_required: #auth_required,
                         permissions: vec![#(#permissions.to_string()),*],
+                        permission_expression: #permission_expression,
                         request_type_name: stringify!(#request_type).to_string(),
                         response_type_name: stringify!(#response_type).to_string(),
                         version: Some(#version_label.to_string()),
                         canonical_version: Some(#canonical_version.to_string()),
                         canonical_method: #canonical_method_name.to_string(),
+                        errors: vec![#((#error_codes, #error_messages.to_string())),*],
+                        timeout_ms: #timeout_ms,
+                        positional_params: #positional_params,
+                        streaming: #streaming,
+                        since: #since,
+                        deprecated: #deprecated,
+                        deprecated_since: #deprecated_since,
+                        deprecation_note: #deprecation_note,
                     }
                 }
             }));
@@ -243,66 +306,120 @@ pub fn generate_openrpc_code(
             description: Option<String>,
             auth_required: bool,
             permissions: Vec<String>,
+            permission_expression: Option<String>,
             request_type_name: String,
             response_type_name: String,
             version: Option<String>,
             canonical_version: Option<String>,
             canonical_method: String,
+            errors: Vec<(i32, String)>,
+            timeout_ms: Option<u64>,
+            positional_params: bool,
+            streaming: bool,
+            since: Option<String>,
+            deprecated: bool,
+            deprecated_since: Option<String>,
+            deprecation_note: Option<String>,
         }
 
-        /// Helper function to extract examples from a JSON schema
-        fn #flatten_fn_name(
+        /// Merge the definitions a schema depends on into the shared components, returning
+        /// the schema with its references pointing at them. Definitions already in the
+        /// components are shared, and a definition named like a different one, e.g. two
+        /// `User` types from different modules, is qualified with a hash of its schema
+        fn #merge_components_fn_name(
             mut schema: serde_json::Value,
-            extracted_defs: &mut std::collections::HashMap<String, serde_json::Value>
+            defs: serde_json::Map<String, serde_json::Value>,
+            components: &mut serde_json::Map<String, serde_json::Value>,
         ) -> serde_json::Value {
-            if let Some(obj) = schema.as_object_mut() {
-                // If this schema has $defs, extract them
-                if let Some(defs) = obj.remove("$defs") {
-                    if let Some(defs_obj) = defs.as_object() {
-                        for (def_name, def_schema) in defs_obj {
-                            // Recursively flatten nested $defs in the extracted definitions
-                            let flattened_def = #flatten_fn_name(def_schema.clone(), extracted_defs);
-                            extracted_defs.insert(def_name.clone(), flattened_def);
+            let mut names: std::collections::HashMap<String, String> = defs
+                .keys()
+                .map(|name| (name.clone(), name.clone()))
+                .collect();
+
+            // Qualifying a definition changes the schemas referencing it, which may then
+            // collide in turn, so settle the names first
+            for _ in 0..=defs.len() {
+                let mut settled = true;
+                for (name, def) in &defs {
+                    let mut def = def.clone();
+                    #update_refs_fn_name(&mut def, &names);
+                    let component = match components.get(name) {
+                        Some(existing) if *existing != def => {
+                            format!("{}_{:08x}", name, #schema_hash_fn_name(&def, 0x811c_9dc5))
                         }
+                        _ => name.clone(),
+                    };
+                    if names[name] != component {
+                        names.insert(name.clone(), component);
+                        settled = false;
                     }
                 }
-
-                // Update all $ref paths to point to components/schemas
-                #update_refs_fn_name(&mut schema);
+                if settled {
+                    break;
+                }
             }
 
+            for (name, mut def) in defs {
+                #update_refs_fn_name(&mut def, &names);
+                components.entry(names[&name].clone()).or_insert(def);
+            }
+            #update_refs_fn_name(&mut schema, &names);
             schema
         }
 
-        /// Recursively update all $ref paths from #/$defs/ to #/components/schemas/
-        fn #update_refs_fn_name(value: &mut serde_json::Value) {
+        /// Recursively update all $ref paths from #/$defs/ to #/components/schemas/,
+        /// using the component names definitions were merged under
+        fn #update_refs_fn_name(
+            value: &mut serde_json::Value,
+            names: &std::collections::HashMap<String, String>,
+        ) {
             match value {
                 serde_json::Value::Object(obj) => {
                     for (key, val) in obj.iter_mut() {
                         if key == "$ref" {
-                            if let Some(ref_str) = val.as_str() {
-                                if ref_str.starts_with("#/$defs/") {
-                                    *val = serde_json::Value::String(
-                                        ref_str.replace("#/$defs/", "#/components/schemas/")
-                                    );
-                                }
+                            if let Some(name) = val.as_str().and_then(|r| r.strip_prefix("#/$defs/")) {
+                                let name = names.get(name).map(String::as_str).unwrap_or(name);
+                                *val = serde_json::Value::String(format!("#/components/schemas/{}", name));
                             }
                         } else {
-                            #update_refs_fn_name(val);
+                            #update_refs_fn_name(val, names);
                         }
                     }
                 }
                 serde_json::Value::Array(arr) => {
                     for item in arr.iter_mut() {
-                        #update_refs_fn_name(item);
+                        #update_refs_fn_name(item, names);
                     }
                 }
                 _ => {}
             }
         }
 
+        /// Hash a schema with FNV-1a, visiting object keys in sorted order so the hash
+        /// doesn't depend on how serde_json orders maps
+        fn #schema_hash_fn_name(value: &serde_json::Value, hash: u32) -> u32 {
+            let feed = |hash: u32, bytes: &[u8]| {
+                bytes
+                    .iter()
+                    .fold(hash, |hash, byte| (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193))
+            };
+            match value {
+                serde_json::Value::Object(obj) => {
+                    let mut keys: Vec<&String> = obj.keys().collect();
+                    keys.sort();
+                    keys.into_iter().fold(feed(hash, b"{"), |hash, key| {
+                        #schema_hash_fn_name(&obj[key.as_str()], feed(hash, key.as_bytes()))
+                    })
+                }
+                serde_json::Value::Array(arr) => arr
+                    .iter()
+                    .fold(feed(hash, b"["), |hash, item| #schema_hash_fn_name(item, hash)),
+                other => feed(hash, other.to_string().as_bytes()),
+            }
+        }
+
         /// Generate example value from schema
-        fn #generate_example_fn_name(schema: &serde_json::Value, schemas: &std::collections::HashMap<String, serde_json::Value>) -> serde_json::Value {
+        fn #generate_example_fn_name(schema: &serde_json::Value, schemas: &serde_json::Map<String, serde_json::Value>) -> serde_json::Value {
             // Check if schema has examples field
             if let Some(examples) = schema.get("examples") {
                 if let Some(arr) = examples.as_array() {
@@ -379,12 +496,31 @@ pub fn generate_openrpc_code(
                 #(#method_infos),*
             ];
 
-            // Generate schemas for all unique types
-            let mut schemas = HashMap::new();
+            // Generate schemas for all unique types, collecting the named types they
+            // use into shared components so each is documented once
+            let mut schemas = serde_json::Map::new();
+            let mut type_schemas: HashMap<String, serde_json::Value> = HashMap::new();
 
             // Insert all the generated schemas
             #(#schema_insertions)*
 
+            let schema_of = |type_name: &str| {
+                type_schemas
+                    .get(type_name)
+                    .cloned()
+                    .unwrap_or_else(|| json!({ "description": format!("Schema for {}", type_name) }))
+            };
+            // The component a `$ref` schema points at, or the schema itself
+            let resolve = |schema: serde_json::Value| {
+                schema
+                    .get("$ref")
+                    .and_then(|r| r.as_str())
+                    .and_then(|r| r.strip_prefix("#/components/schemas/"))
+                    .and_then(|name| schemas.get(name))
+                    .cloned()
+                    .unwrap_or(schema)
+            };
+
             let openrpc_methods: Vec<serde_json::Value> = methods.iter().map(|method| {
                 let mut params = vec![];
 
@@ -393,7 +529,7 @@ pub fn generate_openrpc_code(
                     // Sanitize the type name for schema reference
                     let sanitized_request_type = method.request_type_name.replace(" ", "");
                     // Get the schema for the request type to generate an example
-                    let example = if let Some(schema) = schemas.get(&sanitized_request_type) {
+                    let example = if let Some(schema) = type_schemas.get(&sanitized_request_type) {
                         #generate_example_fn_name(schema, &schemas)
                     } else {
                         json!({"example": "value"})
@@ -403,9 +539,7 @@ pub fn generate_openrpc_code(
                         "name": "params",
                         "summary": format!("Request parameters of type {}", method.request_type_name),
                         "required": true,
-                        "schema": {
-                            "$ref": format!("#/components/schemas/{}", sanitized_request_type)
-                        }
+                        "schema": schema_of(&sanitized_request_type)
                     }));
                 }
 
@@ -420,6 +554,10 @@ pub fn generate_openrpc_code(
                     if !method.permissions.is_empty() {
                         extensions.insert("x-permissions".to_string(), json!(method.permissions));
                     }
+
+                    if let Some(expression) = &method.permission_expression {
+                        extensions.insert("x-permission-expression".to_string(), json!(expression));
+                    }
                 }
 
                 if let Some(version) = &method.version {
@@ -431,38 +569,50 @@ pub fn generate_openrpc_code(
                     extensions.insert("x-ras-canonical-method".to_string(), json!(method.canonical_method));
                 }
 
-                // Generate example pairing for the method
-                let mut examples = vec![];
-                if method.request_type_name != "()" {
-                    // Sanitize type names for schema lookups
-                    let sanitized_request_type = method.request_type_name.replace(" ", "");
-                    let sanitized_response_type = method.response_type_name.replace(" ", "");
+                if let Some(timeout_ms) = method.timeout_ms {
+                    extensions.insert("x-ras-timeout-ms".to_string(), json!(timeout_ms));
+                }
 
-                    // Get the schema for the request type to generate an example
-                    let request_example = if let Some(schema) = schemas.get(&sanitized_request_type) {
-                        #generate_example_fn_name(schema, &schemas)
-                    } else {
-                        json!({"example": "value"})
-                    };
+                // Streaming methods answer with `rpc.partial` notifications carrying chunks of
+                // the result schema, followed by a final `null` result
+                if method.streaming {
+                    extensions.insert("x-ras-streaming".to_string(), json!({
+                        "contentType": "application/x-ndjson",
+                        "notification": "rpc.partial"
+                    }));
+                }
 
-                    let response_example = if method.response_type_name != "()" {
-                        if let Some(schema) = schemas.get(&sanitized_response_type) {
-                            #generate_example_fn_name(schema, &schemas)
-                        } else {
-                            json!({"example": "response"})
-                        }
-                    } else {
-                        json!(null)
-                    };
+                if let Some(since) = &method.since {
+                    extensions.insert("x-ras-since".to_string(), json!(since));
+                }
 
-                    examples.push(json!({
-                        "name": format!("{}_example", method.name),
-                        "description": format!("Example call to {}", method.name),
-                        "params": [{"name": "params", "value": request_example}],
-                        "result": {"name": "result", "value": response_example}
-                    }));
+                if let Some(deprecated_since) = &method.deprecated_since {
+                    extensions.insert("x-ras-deprecated-since".to_string(), json!(deprecated_since));
                 }
 
+                if let Some(deprecation_note) = &method.deprecation_note {
+                    extensions.insert("x-ras-deprecation-note".to_string(), json!(deprecation_note));
+                }
+
+                // Publish the examples declared on the request type, e.g. with
+                // `#[schemars(example = ..)]`, so clients can offer them as starting points
+                let sanitized_request_type = method.request_type_name.replace(" ", "");
+                let request_schema = resolve(schema_of(&sanitized_request_type));
+                let examples: Vec<serde_json::Value> = request_schema
+                    .get("examples")
+                    .and_then(|examples| examples.as_array())
+                    .map(|declared| {
+                        declared
+                            .iter()
+                            .enumerate()
+                            .map(|(index, value)| json!({
+                                "name": format!("{}_example_{}", method.name, index + 1),
+                                "params": [{"name": "params", "value": value}]
+                            }))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
                 // Sanitize the response type name for schema reference
                 let sanitized_response_type = method.response_type_name.replace(" ", "");
                 let method_summary = method
@@ -470,28 +620,51 @@ pub fn generate_openrpc_code(
                     .clone()
                     .unwrap_or_else(|| format!("Calls the {} method", method.name));
 
+                let result_description = if method.streaming {
+                    format!("Streamed chunks of type {}", method.response_type_name)
+                } else {
+                    format!("Response of type {}", method.response_type_name)
+                };
+
                 let mut method_obj = json!({
                     "name": method.name,
                     "summary": method_summary,
                     "params": params,
                     "result": {
                         "name": "result",
-                        "description": format!("Response of type {}", method.response_type_name),
-                        "schema": {
-                            "$ref": format!("#/components/schemas/{}", sanitized_response_type)
-                        }
+                        "description": result_description,
+                        "schema": schema_of(&sanitized_response_type)
                     }
                 });
 
-                // Note: Examples are intentionally omitted as they're optional in OpenRPC
-                // and can cause validation issues with some validators
-
                 // Add extensions to the method object
                 if let Some(obj) = method_obj.as_object_mut() {
                     if let Some(description) = &method.description {
                         obj.insert("description".to_string(), json!(description));
                     }
 
+                    if method.deprecated {
+                        obj.insert("deprecated".to_string(), json!(true));
+                    }
+
+                    // Positional methods also accept their fields as an array, in declaration order
+                    if method.positional_params {
+                        obj.insert("paramStructure".to_string(), json!("either"));
+                    }
+
+                    if !method.errors.is_empty() {
+                        let errors: Vec<serde_json::Value> = method
+                            .errors
+                            .iter()
+                            .map(|(code, message)| json!({ "code": code, "message": message }))
+                            .collect();
+                        obj.insert("errors".to_string(), json!(errors));
+                    }
+
+                    if !examples.is_empty() {
+                        obj.insert("examples".to_string(), json!(examples));
+                    }
+
                     for (key, value) in extensions {
                         obj.insert(key, value);
                     }
@@ -542,6 +715,10 @@ pub fn generate_openrpc_code(
                         "TokenExpired": {
                             "code": -32003,
                             "message": "Token expired"
+                        },
+                        "TokenNotYetValid": {
+                            "code": -32004,
+                            "message": "Token not yet valid"
                         }
                     }
                 }
@@ -569,6 +746,13 @@ pub fn generate_openrpc_code(
 }
 
 /// Generates code to include schema generation for types when schemars is available
+fn optional_string_tokens(value: Option<&String>) -> TokenStream {
+    match value {
+        Some(value) => quote! { Some(#value.to_string()) },
+        None => quote! { None },
+    }
+}
+
 pub fn generate_schema_impl_checks(service_def: &ServiceDefinition) -> TokenStream {
     let mut unique_types = HashMap::new();
 
@@ -1,6 +1,10 @@
+use crate::{PermissionRequirement, static_permission_expr_code};
 use proc_macro2::TokenStream;
 use quote::quote;
 
+/// Number of requests the explorer keeps in its history
+const EXPLORER_HISTORY_LIMIT: usize = 50;
+
 /// Configuration for static hosting of the JSON-RPC explorer
 #[derive(Debug, Clone)]
 pub struct StaticHostingConfig {
@@ -57,7 +61,8 @@ pub fn generate_static_hosting_code(
                     "serviceName": #service_name_str,
                     "protocol": "jsonrpc",
                     "specPath": &openrpc_path,
-                    "apiBasePath": base_path
+                    "apiBasePath": base_path,
+                    "historyLimit": #EXPLORER_HISTORY_LIMIT
                 })
                 .to_string()
                 .replace("<", "\\u003c");
@@ -86,3 +91,107 @@ pub fn generate_static_hosting_code(
         }
     }
 }
+
+/// Generate a middleware layer running the Bearer-token check of
+/// `WITH_PERMISSIONS` methods in front of the explorer routes.
+///
+/// `auth_source` is evaluated once and cloned into every request;
+/// `provider_ref` turns that clone (bound as `auth_source`) into an
+/// `Option<&dyn AuthProvider>`. Browsers get a sign-in page that stores the
+/// token for the explorer, other clients a JSON error.
+pub fn generate_docs_auth_layer(
+    service_name: &str,
+    protocol: &str,
+    requirement: &PermissionRequirement,
+    auth_source: TokenStream,
+    provider_ref: TokenStream,
+) -> TokenStream {
+    const LOGIN_TEMPLATE: &str =
+        include_str!("../../../rest/ras-rest-macro/src/docs_login_template.html");
+    let login_template = syn::LitStr::new(LOGIN_TEMPLATE, proc_macro2::Span::call_site());
+    let (required, capture, permitted) = match requirement {
+        PermissionRequirement::Groups(permission_groups) => {
+            let groups = permission_groups.iter().map(|group| {
+                quote! { vec![#(#group.to_string()),*] }
+            });
+            (
+                quote! { let required_permission_groups: Vec<Vec<String>> = vec![#(#groups),*]; },
+                quote! { let required_permission_groups = required_permission_groups.clone(); },
+                quote! {
+                    required_permission_groups.is_empty()
+                        || required_permission_groups
+                            .iter()
+                            .any(|group| group.is_empty() || provider.check_permissions(&user, group).is_ok())
+                },
+            )
+        }
+        PermissionRequirement::Expression(expression) => {
+            let expression_code = static_permission_expr_code(expression);
+            (
+                quote! { let required_permissions: &'static ::ras_jsonrpc_core::PermissionExpr = #expression_code; },
+                quote! {},
+                quote! { provider.check_permission_expr(&user, required_permissions).is_ok() },
+            )
+        }
+    };
+
+    quote! {
+        {
+            let docs_auth_source = #auth_source;
+            #required
+            ::axum::middleware::from_fn(move |request: ::axum::extract::Request, next: ::axum::middleware::Next| {
+                let auth_source = docs_auth_source.clone();
+                #capture
+                async move {
+                    use ::axum::response::IntoResponse;
+
+                    let wants_html = request
+                        .headers()
+                        .get(::axum::http::header::ACCEPT)
+                        .and_then(|value| value.to_str().ok())
+                        .is_some_and(|accept| accept.contains("text/html"));
+                    let denied = |status: ::axum::http::StatusCode, message: &str| {
+                        if wants_html {
+                            const TEMPLATE: &str = #login_template;
+                            let config_json = ::serde_json::json!({
+                                "serviceName": #service_name,
+                                "protocol": #protocol,
+                                "status": status.as_u16(),
+                                "message": message
+                            })
+                            .to_string()
+                            .replace("<", "\\u003c");
+                            (status, ::axum::response::Html(TEMPLATE.replace("{DOCS_LOGIN_CONFIG_JSON}", &config_json))).into_response()
+                        } else {
+                            (status, ::axum::Json(::serde_json::json!({ "error": message }))).into_response()
+                        }
+                    };
+
+                    let token = request
+                        .headers()
+                        .get(::axum::http::header::AUTHORIZATION)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(::ras_jsonrpc_core::authorization_credential)
+                        .map(str::to_string);
+                    let Some(token) = token else {
+                        return denied(::axum::http::StatusCode::UNAUTHORIZED, "Sign in to view the API documentation");
+                    };
+                    let Some(provider) = #provider_ref else {
+                        return denied(::axum::http::StatusCode::INTERNAL_SERVER_ERROR, "No auth provider configured");
+                    };
+                    let user = match provider.authenticate_request(token, request.headers()).await {
+                        Ok(user) => user,
+                        Err(_) => return denied(::axum::http::StatusCode::UNAUTHORIZED, "Authentication failed"),
+                    };
+
+                    let permitted = #permitted;
+                    if !permitted {
+                        return denied(::axum::http::StatusCode::FORBIDDEN, "Insufficient permissions");
+                    }
+
+                    next.run(request).await
+                }
+            })
+        }
+    }
+}
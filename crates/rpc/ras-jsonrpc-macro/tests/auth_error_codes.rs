@@ -0,0 +1,84 @@
+//! Machine-readable codes of refused requests, as read by generated clients.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use ras_jsonrpc_core::{
+    AuthError, AuthFuture, AuthProvider, AuthenticatedUser, JsonRpcError, PrincipalKind,
+};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::spawn_http;
+
+jsonrpc_service!({
+    service_name: Vault,
+    methods: [
+        WITH_PERMISSIONS(["admin"]) open(()) -> String,
+    ]
+});
+
+struct VaultImpl;
+
+impl VaultTrait for VaultImpl {
+    async fn open(
+        &self,
+        _user: &AuthenticatedUser,
+        _request: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok("opened".to_string())
+    }
+}
+
+/// Accepts `user`, and refuses `locked` and `expired` tokens as such
+struct Accounts;
+
+impl AuthProvider for Accounts {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        Box::pin(async move {
+            match token.as_str() {
+                "user" => Ok(AuthenticatedUser {
+                    user_id: "user-1".to_string(),
+                    permissions: HashSet::from(["user".to_string()]),
+                    metadata: None,
+                    kind: PrincipalKind::User,
+                }),
+                "locked" => Err(AuthError::AccountLocked {
+                    retry_after: Some(Duration::from_secs(30)),
+                }),
+                "expired" => Err(AuthError::TokenExpired),
+                _ => Err(AuthError::InvalidToken),
+            }
+        })
+    }
+}
+
+#[tokio::test]
+async fn clients_read_the_code_of_refused_calls() {
+    let server = spawn_http(
+        VaultBuilder::new(VaultImpl)
+            .base_url("/rpc")
+            .auth_provider(Accounts)
+            .build()
+            .unwrap(),
+    );
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    for (token, code) in [
+        ("locked", "account_locked"),
+        ("expired", "token_expired"),
+        ("user", "insufficient_permissions"),
+    ] {
+        let mut client = VaultClientBuilder::new().server_url(&url).build().unwrap();
+        client.set_bearer_token(Some(token));
+        let error = client.open(()).await.unwrap_err();
+        let error = error
+            .downcast_ref::<JsonRpcError>()
+            .expect("not a JSON-RPC error");
+        assert_eq!(error.auth_code(), Some(code), "{token}");
+    }
+
+    let mut client = VaultClientBuilder::new().server_url(&url).build().unwrap();
+    client.set_bearer_token(Some("locked"));
+    let error = client.open(()).await.unwrap_err();
+    let data = error.downcast_ref::<JsonRpcError>().unwrap().data.clone();
+    assert_eq!(data.unwrap()["retry_after_ms"], 30_000);
+}
@@ -0,0 +1,218 @@
+//! Authorization callbacks checking calls against their params.
+
+use std::future::{Ready, ready};
+
+use ras_jsonrpc_core::{AccessDenied, AuthenticatedUser};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{mock_user, spawn_http};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct RenameTask {
+    pub id: u64,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct RenameTaskV1 {
+    pub id: u64,
+    pub name: String,
+}
+
+struct RenameTaskCompat;
+
+impl ras_jsonrpc_core::VersionMigration<RenameTaskV1, RenameTask> for RenameTaskCompat {
+    type Error = std::convert::Infallible;
+
+    fn migrate(value: RenameTaskV1) -> Result<RenameTask, Self::Error> {
+        Ok(RenameTask {
+            id: value.id,
+            title: value.name,
+        })
+    }
+}
+
+impl ras_jsonrpc_core::VersionMigration<String, String> for RenameTaskCompat {
+    type Error = std::convert::Infallible;
+
+    fn migrate(value: String) -> Result<String, Self::Error> {
+        Ok(value)
+    }
+}
+
+jsonrpc_service!({
+    service_name: Tasks,
+    openrpc: false,
+    methods: [
+        WITH_PERMISSIONS(["user"]) rename_task(RenameTask) -> String {
+            version: v2,
+            wire: "rename_task.v2",
+            versions: [
+                v1 {
+                    wire: "rename_task.v1",
+                    request: RenameTaskV1,
+                    response: String,
+                    migration: RenameTaskCompat,
+                },
+            ],
+        },
+        WITH_PERMISSIONS(["user"]) list_tasks(()) -> Vec<u64>,
+    ]
+});
+
+struct TasksImpl;
+
+impl TasksTrait for TasksImpl {
+    async fn rename_task(
+        &self,
+        _user: &AuthenticatedUser,
+        request: RenameTask,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(format!("{}: {}", request.id, request.title))
+    }
+
+    async fn list_tasks(
+        &self,
+        _user: &AuthenticatedUser,
+        _request: (),
+    ) -> Result<Vec<u64>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(vec![1, 2])
+    }
+}
+
+/// Task 1 belongs to `user-1`, every other task to `admin-1`
+fn owner_of(task: u64) -> &'static str {
+    if task == 1 { "user-1" } else { "admin-1" }
+}
+
+/// Users may only rename their own tasks
+fn only_owner(user: &AuthenticatedUser, request: &RenameTask) -> Ready<Result<(), AccessDenied>> {
+    let owner = owner_of(request.id);
+    ready(if user.user_id == owner {
+        Ok(())
+    } else {
+        Err(AccessDenied::new("not_owner").with_detail("owner", owner))
+    })
+}
+
+fn serve() -> axum_test::TestServer {
+    spawn_http(
+        TasksBuilder::new(TasksImpl)
+            .auth_provider(ras_test_helpers::MockAuthProvider::default())
+            .authorize_rename_task(only_owner)
+            .build()
+            .unwrap(),
+    )
+}
+
+async fn call(
+    server: &axum_test::TestServer,
+    method: &str,
+    token: &str,
+    params: serde_json::Value,
+) -> serde_json::Value {
+    reqwest::Client::new()
+        .post(server.server_url("/rpc").unwrap())
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 1
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[test]
+fn callbacks_are_testable_alone() {
+    let user = mock_user("user-1", &["user"]);
+    let request = RenameTask {
+        id: 2,
+        title: "ship it".to_string(),
+    };
+    assert_eq!(
+        only_owner(&user, &request).into_inner(),
+        Err(AccessDenied::new("not_owner").with_detail("owner", "admin-1"))
+    );
+}
+
+#[tokio::test]
+async fn owners_pass_and_others_are_denied_with_the_reason() {
+    let server = serve();
+
+    let response = call(
+        &server,
+        "rename_task.v2",
+        "user-token",
+        serde_json::json!({ "id": 1, "title": "mine" }),
+    )
+    .await;
+    assert_eq!(response["result"], "1: mine");
+
+    let response = call(
+        &server,
+        "rename_task.v2",
+        "user-token",
+        serde_json::json!({ "id": 2, "title": "theirs" }),
+    )
+    .await;
+    let error = &response["error"];
+    assert_eq!(
+        error["code"],
+        ras_jsonrpc_types::error_codes::INSUFFICIENT_PERMISSIONS
+    );
+    assert_eq!(error["message"], "Access denied");
+    assert_eq!(
+        error["data"],
+        serde_json::json!({ "code": "access_denied", "reason": "not_owner", "owner": "admin-1" })
+    );
+
+    // Methods without a callback only get the permission check
+    let response = call(&server, "list_tasks", "user-token", serde_json::Value::Null).await;
+    assert_eq!(response["result"], serde_json::json!([1, 2]));
+}
+
+#[tokio::test]
+async fn the_permission_check_comes_first() {
+    let server = serve();
+
+    let response = call(
+        &server,
+        "rename_task.v2",
+        "readonly-token",
+        serde_json::json!({ "id": 1, "title": "mine" }),
+    )
+    .await;
+    assert_eq!(
+        response["error"]["data"]["code"],
+        "insufficient_permissions"
+    );
+}
+
+#[tokio::test]
+async fn legacy_versions_are_checked_after_migration() {
+    let server = serve();
+
+    let response = call(
+        &server,
+        "rename_task.v1",
+        "user-token",
+        serde_json::json!({ "id": 1, "name": "old" }),
+    )
+    .await;
+    assert_eq!(response["result"], "1: old");
+
+    let response = call(
+        &server,
+        "rename_task.v1",
+        "user-token",
+        serde_json::json!({ "id": 2, "name": "old" }),
+    )
+    .await;
+    assert_eq!(response["error"]["data"]["reason"], "not_owner");
+}
@@ -0,0 +1,91 @@
+//! Cancellable calls of the generated JSON-RPC client.
+
+use std::time::{Duration, Instant};
+
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_jsonrpc_types::is_cancelled;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate, matchers};
+
+jsonrpc_service!({
+    service_name: Search,
+    methods: [
+        UNAUTHORIZED search(String) -> Vec<String>,
+    ]
+});
+
+/// Echoes the query back after `delay`.
+struct Slow {
+    delay: Duration,
+}
+
+impl Respond for Slow {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        ResponseTemplate::new(200)
+            .set_delay(self.delay)
+            .set_body_json(serde_json::json!({
+                "jsonrpc": "2.0",
+                "result": [body["params"].clone()],
+                "id": body["id"].clone(),
+            }))
+    }
+}
+
+async fn client(delay: Duration) -> (MockServer, SearchClient) {
+    let server = MockServer::start().await;
+    Mock::given(matchers::method("POST"))
+        .respond_with(Slow { delay })
+        .mount(&server)
+        .await;
+    let client = SearchClientBuilder::new()
+        .server_url(format!("{}/rpc", server.uri()))
+        .build()
+        .unwrap();
+    (server, client)
+}
+
+#[tokio::test]
+async fn awaited_cancellable_calls_return_the_result() {
+    let (_server, client) = client(Duration::ZERO).await;
+
+    let result = client.search_cancellable("rust".to_string()).await.unwrap();
+    assert_eq!(result, vec!["rust".to_string()]);
+}
+
+#[tokio::test]
+async fn cancelled_calls_resolve_to_cancelled_without_waiting_for_the_server() {
+    let (server, client) = client(Duration::from_secs(30)).await;
+
+    let call = client.search_cancellable("rust".to_string());
+    let canceller = call.canceller();
+    let started = Instant::now();
+    let task = tokio::spawn(call);
+
+    while server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .is_empty()
+    {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    canceller.cancel();
+
+    let error = task.await.unwrap().unwrap_err();
+    assert!(is_cancelled(error.as_ref()));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn other_errors_are_not_cancellations() {
+    let client = SearchClientBuilder::new()
+        .server_url("http://127.0.0.1:1/rpc")
+        .build()
+        .unwrap();
+
+    let error = client
+        .search_cancellable("rust".to_string())
+        .await
+        .unwrap_err();
+    assert!(!is_cancelled(error.as_ref()));
+}
@@ -0,0 +1,202 @@
+//! Retry and backoff in the generated JSON-RPC client.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_jsonrpc_types::{Backoff, JsonRpcError, RetryOn, RetryPolicy, error_codes};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate, matchers};
+
+jsonrpc_service!({
+    service_name: Jobs,
+    methods: [
+        UNAUTHORIZED IDEMPOTENT submit(String) -> u32,
+        UNAUTHORIZED cancel(String) -> bool,
+    ]
+});
+
+/// One scripted server reply.
+#[derive(Clone)]
+enum Reply {
+    Status(u16),
+    Error(u16, i32),
+    Result(serde_json::Value),
+}
+
+/// Answers requests from a script and records when each one arrived.
+#[derive(Clone, Default)]
+struct Scripted {
+    replies: Arc<Mutex<VecDeque<Reply>>>,
+    arrivals: Arc<Mutex<Vec<(Instant, serde_json::Value)>>>,
+}
+
+impl Respond for Scripted {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let id = body["id"].clone();
+        self.arrivals.lock().unwrap().push((Instant::now(), body));
+
+        let reply = self.replies.lock().unwrap().pop_front();
+        match reply.expect("unexpected request") {
+            Reply::Status(status) => ResponseTemplate::new(status),
+            Reply::Error(status, code) => ResponseTemplate::new(status).set_body_json(
+                serde_json::json!({ "jsonrpc": "2.0", "error": { "code": code, "message": "failed" }, "id": id }),
+            ),
+            Reply::Result(result) => ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "jsonrpc": "2.0", "result": result, "id": id })),
+        }
+    }
+}
+
+impl Scripted {
+    async fn serve(replies: impl IntoIterator<Item = Reply>) -> (MockServer, Self) {
+        let scripted = Scripted::default();
+        scripted.replies.lock().unwrap().extend(replies);
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .respond_with(scripted.clone())
+            .mount(&server)
+            .await;
+        (server, scripted)
+    }
+
+    fn arrivals(&self) -> Vec<(Instant, serde_json::Value)> {
+        self.arrivals.lock().unwrap().clone()
+    }
+}
+
+fn client(server: &MockServer, policy: RetryPolicy) -> JobsClientBuilder {
+    JobsClientBuilder::new()
+        .server_url(format!("{}/rpc", server.uri()))
+        .with_retry(policy)
+}
+
+fn policy(max_attempts: u32, backoff: Backoff) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        backoff,
+        ..RetryPolicy::default()
+    }
+}
+
+#[tokio::test]
+async fn retries_follow_the_backoff_schedule_with_fresh_ids() {
+    let (server, scripted) = Scripted::serve([
+        Reply::Status(502),
+        Reply::Error(503, error_codes::SERVER_BUSY),
+        Reply::Result(serde_json::json!(7)),
+    ])
+    .await;
+    let backoff = Backoff::Exponential {
+        initial: Duration::from_millis(50),
+        max: Duration::from_secs(1),
+    };
+    let client = client(&server, policy(3, backoff)).build().unwrap();
+
+    assert_eq!(client.submit("job".to_string()).await.unwrap(), 7);
+
+    let arrivals = scripted.arrivals();
+    assert_eq!(arrivals.len(), 3);
+    assert!(arrivals[1].0 - arrivals[0].0 >= Duration::from_millis(50));
+    assert!(arrivals[2].0 - arrivals[1].0 >= Duration::from_millis(100));
+
+    let ids: Vec<_> = arrivals
+        .iter()
+        .map(|(_, body)| body["id"].clone())
+        .collect();
+    assert!(ids[0] != ids[1] && ids[1] != ids[2] && ids[0] != ids[2]);
+}
+
+#[tokio::test]
+async fn only_idempotent_methods_are_retried_by_default() {
+    let (server, scripted) =
+        Scripted::serve([Reply::Status(503), Reply::Result(serde_json::json!(true))]).await;
+    let backoff = Backoff::Fixed(Duration::from_millis(10));
+
+    let client = client(&server, policy(3, backoff)).build().unwrap();
+    assert!(client.cancel("job".to_string()).await.is_err());
+    assert_eq!(scripted.arrivals().len(), 1);
+
+    let client = self::client(&server, policy(3, backoff))
+        .with_retry_all_methods(true)
+        .build()
+        .unwrap();
+    assert!(client.cancel("job".to_string()).await.unwrap());
+    assert_eq!(scripted.arrivals().len(), 2);
+}
+
+#[tokio::test]
+async fn unlisted_failures_are_returned_immediately() {
+    let (server, scripted) =
+        Scripted::serve([Reply::Error(200, error_codes::INVALID_PARAMS)]).await;
+    let client = client(&server, policy(3, Backoff::Fixed(Duration::ZERO)))
+        .build()
+        .unwrap();
+
+    let error = client.submit("job".to_string()).await.unwrap_err();
+    let error = error
+        .downcast_ref::<JsonRpcError>()
+        .expect("JSON-RPC error");
+    assert_eq!(error.code, error_codes::INVALID_PARAMS);
+    assert_eq!(scripted.arrivals().len(), 1);
+}
+
+#[tokio::test]
+async fn listed_error_codes_are_retried_until_attempts_run_out() {
+    let (server, scripted) = Scripted::serve([
+        Reply::Error(200, -32050),
+        Reply::Error(200, -32050),
+        Reply::Error(200, -32050),
+    ])
+    .await;
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        backoff: Backoff::Fixed(Duration::ZERO),
+        retry_on: vec![RetryOn::ErrorCode(-32050)],
+    };
+    let client = client(&server, policy).build().unwrap();
+
+    let error = client.submit("job".to_string()).await.unwrap_err();
+    assert_eq!(error.downcast_ref::<JsonRpcError>().unwrap().code, -32050);
+    assert_eq!(scripted.arrivals().len(), 3);
+}
+
+#[tokio::test]
+async fn per_call_timeout_bounds_all_attempts() {
+    let (server, scripted) = Scripted::serve(vec![Reply::Status(503); 5]).await;
+    let client = client(
+        &server,
+        policy(5, Backoff::Fixed(Duration::from_millis(100))),
+    )
+    .build()
+    .unwrap();
+
+    let started = Instant::now();
+    let result = client
+        .submit_with_timeout("job".to_string(), Duration::from_millis(150))
+        .await;
+
+    // The third attempt would start after the deadline, so it is never sent
+    assert!(result.is_err());
+    assert_eq!(scripted.arrivals().len(), 2);
+    assert!(started.elapsed() < Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn connection_errors_are_retried() {
+    // Reserve a port, then close it so connections are refused
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/rpc", listener.local_addr().unwrap());
+    drop(listener);
+
+    let client = JobsClientBuilder::new()
+        .server_url(url)
+        .with_retry(policy(3, Backoff::Fixed(Duration::from_millis(50))))
+        .build()
+        .unwrap();
+
+    let started = Instant::now();
+    assert!(client.submit("job".to_string()).await.is_err());
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
@@ -0,0 +1,119 @@
+//! Global and per-method concurrency limits in the generated server.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use ras_jsonrpc_core::OverloadBehavior;
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+use tokio::sync::Semaphore;
+
+jsonrpc_service!({
+    service_name: Reports,
+    methods: [
+        WITH_PERMISSIONS(["user"]) CONCURRENCY(1) generate_report(()) -> String,
+        UNAUTHORIZED ping(()) -> String,
+    ]
+});
+
+/// Reports block until the test releases a permit on `gate`.
+struct ReportsImpl {
+    gate: Arc<Semaphore>,
+}
+
+impl ReportsTrait for ReportsImpl {
+    async fn generate_report(
+        &self,
+        _user: &ras_jsonrpc_core::AuthenticatedUser,
+        _req: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        self.gate.acquire().await?.forget();
+        Ok("report".to_string())
+    }
+
+    async fn ping(&self, _req: ()) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok("pong".to_string())
+    }
+}
+
+fn client(url: String) -> ReportsClient {
+    let mut client = ReportsClientBuilder::new().server_url(url).build().unwrap();
+    client.set_bearer_token(Some("user-token"));
+    client
+}
+
+async fn wait_for_in_flight(in_flight: &ras_jsonrpc_core::InFlightRequests, method: &str) {
+    for _ in 0..100 {
+        if in_flight.get(method) == 1 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("{method} never started");
+}
+
+#[tokio::test]
+async fn method_limit_rejects_with_server_busy() {
+    let gate = Arc::new(Semaphore::new(0));
+    let builder = ReportsBuilder::new(ReportsImpl { gate: gate.clone() })
+        .auth_provider(MockAuthProvider::default());
+    let in_flight = builder.in_flight_requests();
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let first = tokio::spawn({
+        let client = client(url.clone());
+        async move { client.generate_report(()).await.unwrap() }
+    });
+    wait_for_in_flight(&in_flight, "generate_report").await;
+
+    let resp = reqwest::Client::new()
+        .post(&url)
+        .bearer_auth("user-token")
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": "generate_report", "id": 2 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["error"]["code"], -32005);
+
+    // Methods without a limit keep working
+    assert_eq!(client(url).ping(()).await.unwrap(), "pong");
+
+    gate.add_permits(1);
+    assert_eq!(first.await.unwrap(), "report");
+    assert_eq!(in_flight.get("generate_report"), 0);
+}
+
+#[tokio::test]
+async fn queued_requests_run_once_a_slot_frees_up() {
+    let gate = Arc::new(Semaphore::new(0));
+    let builder = ReportsBuilder::new(ReportsImpl { gate: gate.clone() })
+        .auth_provider(MockAuthProvider::default())
+        .with_max_concurrent_requests(1)
+        .with_overload_behavior(OverloadBehavior::Queue {
+            max_wait: Duration::from_secs(5),
+        });
+    let in_flight = builder.in_flight_requests();
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let report = tokio::spawn({
+        let client = client(url.clone());
+        async move { client.generate_report(()).await.unwrap() }
+    });
+    wait_for_in_flight(&in_flight, "generate_report").await;
+
+    // The global limit makes ping wait behind the running report
+    let ping = tokio::spawn({
+        let client = client(url);
+        async move { client.ping(()).await.unwrap() }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!ping.is_finished());
+
+    gate.add_permits(1);
+    assert_eq!(report.await.unwrap(), "report");
+    assert_eq!(ping.await.unwrap(), "pong");
+}
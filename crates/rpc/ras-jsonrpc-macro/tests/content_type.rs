@@ -0,0 +1,106 @@
+//! Content-Type validation and response charset of the generated endpoint.
+
+use ras_jsonrpc_core::error_codes;
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::spawn_http;
+use reqwest::StatusCode;
+
+jsonrpc_service!({
+    service_name: Echo,
+    methods: [
+        UNAUTHORIZED echo(String) -> String,
+    ]
+});
+
+struct EchoImpl;
+
+impl EchoTrait for EchoImpl {
+    async fn echo(&self, text: String) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(text)
+    }
+}
+
+const REQUEST: &str = r#"{"jsonrpc":"2.0","method":"echo","params":"hi","id":1}"#;
+
+fn serve(builder: EchoBuilder<EchoImpl>) -> (axum_test::TestServer, String) {
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+    (server, url)
+}
+
+async fn post(
+    url: &str,
+    content_type: Option<&str>,
+    body: impl Into<reqwest::Body>,
+) -> (StatusCode, String, serde_json::Value) {
+    let mut request = reqwest::Client::new().post(url).body(body);
+    if let Some(content_type) = content_type {
+        request = request.header("Content-Type", content_type);
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let content_type = response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    (status, content_type, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn json_media_types_are_accepted() {
+    let (_server, url) = serve(EchoBuilder::new(EchoImpl));
+
+    for content_type in [
+        "application/json",
+        "application/json; charset=utf-8",
+        "application/json-rpc+json",
+    ] {
+        let (status, response_type, body) = post(&url, Some(content_type), REQUEST).await;
+        assert_eq!(status, StatusCode::OK, "{content_type}");
+        assert_eq!(response_type, "application/json; charset=utf-8");
+        assert_eq!(body["result"], "hi");
+    }
+}
+
+#[tokio::test]
+async fn other_media_types_get_415_and_a_parse_error() {
+    let (_server, url) = serve(EchoBuilder::new(EchoImpl));
+
+    for content_type in [
+        Some("text/plain"),
+        Some("application/x-www-form-urlencoded"),
+        Some("application/json; charset=iso-8859-1"),
+        None,
+    ] {
+        let (status, response_type, body) = post(&url, content_type, REQUEST).await;
+        assert_eq!(
+            status,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "{content_type:?}"
+        );
+        assert_eq!(response_type, "application/json; charset=utf-8");
+        assert_eq!(body["error"]["code"], error_codes::PARSE_ERROR);
+        assert_eq!(body["error"]["data"]["expected"], "application/json");
+    }
+}
+
+#[tokio::test]
+async fn lenient_mode_accepts_any_content_type() {
+    let (_server, url) = serve(EchoBuilder::new(EchoImpl).with_lenient_content_type(true));
+
+    let (status, _, body) = post(&url, Some("text/plain"), REQUEST).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["result"], "hi");
+}
+
+#[tokio::test]
+async fn invalid_utf8_bodies_get_a_parse_error() {
+    let (_server, url) = serve(EchoBuilder::new(EchoImpl));
+
+    let mut body = REQUEST.as_bytes().to_vec();
+    body[42] = 0xff;
+    let (status, _, body) = post(&url, Some("application/json"), body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["error"]["code"], error_codes::PARSE_ERROR);
+    assert_eq!(body["error"]["data"]["valid_up_to"], 42);
+}
@@ -0,0 +1,144 @@
+//! CORS preflight and cross-origin requests against the generated endpoint.
+
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+use reqwest::{Method, StatusCode};
+
+jsonrpc_service!({
+    service_name: Shared,
+    cors: {
+        allow_origins: ["https://app.example.com"],
+        allow_credentials: true,
+    },
+    methods: [
+        WITH_PERMISSIONS(["user"]) whoami(()) -> String,
+    ]
+});
+
+jsonrpc_service!({
+    service_name: Dev,
+    cors: { allow_origins: ["*"] },
+    methods: [
+        UNAUTHORIZED ping(()) -> String,
+    ]
+});
+
+struct SharedImpl;
+
+impl SharedTrait for SharedImpl {
+    async fn whoami(
+        &self,
+        user: &ras_jsonrpc_core::AuthenticatedUser,
+        _request: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(user.user_id.clone())
+    }
+}
+
+struct DevImpl;
+
+impl DevTrait for DevImpl {
+    async fn ping(&self, _request: ()) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok("pong".to_string())
+    }
+}
+
+fn serve_shared() -> (axum_test::TestServer, String) {
+    let router = SharedBuilder::new(SharedImpl)
+        .auth_provider(MockAuthProvider::default())
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap().to_string();
+    (server, url)
+}
+
+async fn preflight(url: &str, origin: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .request(Method::OPTIONS, url)
+        .header("Origin", origin)
+        .header("Access-Control-Request-Method", "POST")
+        .header(
+            "Access-Control-Request-Headers",
+            "authorization, content-type",
+        )
+        .send()
+        .await
+        .unwrap()
+}
+
+fn header<'a>(response: &'a reqwest::Response, name: &str) -> Option<&'a str> {
+    response
+        .headers()
+        .get(name)
+        .map(|value| value.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn preflight_from_an_allowed_origin_is_answered() {
+    let (_server, url) = serve_shared();
+
+    let response = preflight(&url, "https://app.example.com").await;
+    assert!(response.status().is_success());
+    assert_eq!(
+        header(&response, "access-control-allow-origin"),
+        Some("https://app.example.com")
+    );
+    assert_eq!(
+        header(&response, "access-control-allow-credentials"),
+        Some("true")
+    );
+    let methods = header(&response, "access-control-allow-methods").unwrap();
+    assert!(methods.contains("POST"));
+    let headers = header(&response, "access-control-allow-headers")
+        .unwrap()
+        .to_ascii_lowercase();
+    assert!(headers.contains("authorization"));
+    assert!(headers.contains("content-type"));
+}
+
+#[tokio::test]
+async fn preflight_from_another_origin_gets_no_allow_headers() {
+    let (_server, url) = serve_shared();
+
+    let response = preflight(&url, "https://evil.example.com").await;
+    assert!(header(&response, "access-control-allow-origin").is_none());
+}
+
+#[tokio::test]
+async fn cross_origin_post_carries_cors_headers() {
+    let (_server, url) = serve_shared();
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .header("Origin", "https://app.example.com")
+        .bearer_auth("user-token")
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": "whoami", "params": null, "id": 1 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        header(&response, "access-control-allow-origin"),
+        Some("https://app.example.com")
+    );
+    assert_eq!(
+        header(&response, "access-control-allow-credentials"),
+        Some("true")
+    );
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["result"], "user-1");
+}
+
+#[tokio::test]
+async fn wildcard_origin_mirrors_any_origin() {
+    let server = spawn_http(DevBuilder::new(DevImpl).build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let response = preflight(&url, "http://localhost:5173").await;
+    assert!(response.status().is_success());
+    assert_eq!(
+        header(&response, "access-control-allow-origin"),
+        Some("http://localhost:5173")
+    );
+}
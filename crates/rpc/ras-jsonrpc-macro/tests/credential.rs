@@ -0,0 +1,94 @@
+//! Credentials the auth provider takes from elsewhere than the `Authorization`
+//! header, such as a session cookie, over HTTP and WebSocket alike.
+
+use std::sync::Arc;
+
+use axum::Router;
+use ras_jsonrpc_bidirectional_client::WebSocketRpcTransport;
+use ras_jsonrpc_bidirectional_server::jsonrpc_websocket_route;
+use ras_jsonrpc_core::{AuthFuture, AuthProvider};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http, spawn_tcp};
+
+jsonrpc_service!({
+    service_name: Profile,
+    methods: [
+        WITH_PERMISSIONS(["user"]) whoami(()) -> String,
+    ]
+});
+
+struct ProfileImpl;
+
+impl ProfileTrait for ProfileImpl {
+    async fn whoami(
+        &self,
+        user: &ras_jsonrpc_core::AuthenticatedUser,
+        _request: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(user.user_id.clone())
+    }
+}
+
+/// The mock tokens, read from the `session` cookie
+#[derive(Default)]
+struct CookieSessions(MockAuthProvider);
+
+impl AuthProvider for CookieSessions {
+    fn authenticate(&self, token: String) -> AuthFuture<'_> {
+        self.0.authenticate(token)
+    }
+
+    fn credential(&self, headers: &axum::http::HeaderMap) -> Option<String> {
+        headers
+            .get(axum::http::header::COOKIE)?
+            .to_str()
+            .ok()?
+            .split(';')
+            .find_map(|pair| pair.trim().strip_prefix("session="))
+            .map(str::to_string)
+    }
+}
+
+fn builder() -> ProfileBuilder<ProfileImpl> {
+    ProfileBuilder::new(ProfileImpl).auth_provider(CookieSessions::default())
+}
+
+#[tokio::test]
+async fn http_requests_authenticate_with_the_providers_credential() {
+    let server = spawn_http(builder().base_url("/rpc").build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+    let call = |cookie: &'static str| {
+        reqwest::Client::new()
+            .post(&url)
+            .header("cookie", cookie)
+            .json(&serde_json::json!({ "jsonrpc": "2.0", "method": "whoami", "params": null, "id": 1 }))
+            .send()
+    };
+
+    let response: serde_json::Value = call("theme=dark; session=user-token")
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["result"], "user-1");
+
+    let response: serde_json::Value = call("theme=dark").await.unwrap().json().await.unwrap();
+    assert!(response["error"].is_object());
+}
+
+#[tokio::test]
+async fn websocket_connections_authenticate_with_the_providers_credential() {
+    let (addr, _server) =
+        spawn_tcp(Router::new().route("/ws", jsonrpc_websocket_route(builder()))).await;
+    let transport = Arc::new(
+        WebSocketRpcTransport::new(format!("ws://{addr}/ws"))
+            .with_header("cookie", "session=user-token"),
+    );
+    let client = ProfileClientBuilder::new()
+        .with_transport(transport)
+        .build()
+        .unwrap();
+
+    assert_eq!(client.whoami(()).await.unwrap(), "user-1");
+}
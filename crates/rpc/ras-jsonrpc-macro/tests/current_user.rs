@@ -0,0 +1,104 @@
+//! The user of a request, read by code the handlers call through `current_user`.
+
+use std::time::Duration;
+
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+
+jsonrpc_service!({
+    service_name: Orders,
+    methods: [
+        WITH_PERMISSIONS(["user"]) place_order(u64) -> String,
+        UNAUTHORIZED whoami(()) -> Option<String>,
+    ]
+});
+
+/// Domain code far from the handler, which knows nothing of requests
+async fn audit(delay_ms: u64) -> String {
+    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+    let user = ras_jsonrpc_core::current_user().expect("no current user");
+    format!("placed by {}", user.user_id)
+}
+
+struct OrdersImpl;
+
+impl OrdersTrait for OrdersImpl {
+    async fn place_order(
+        &self,
+        _user: &ras_jsonrpc_core::AuthenticatedUser,
+        delay_ms: u64,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(audit(delay_ms).await)
+    }
+
+    async fn whoami(
+        &self,
+        _request: (),
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ras_jsonrpc_core::current_user().map(|user| user.user_id.clone()))
+    }
+}
+
+async fn call(
+    url: &str,
+    token: Option<&str>,
+    method: &str,
+    params: serde_json::Value,
+) -> serde_json::Value {
+    let mut request = reqwest::Client::new().post(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response: serde_json::Value = request
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    response["result"].clone()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn handlers_and_their_callees_see_their_own_user() {
+    let router = OrdersBuilder::new(OrdersImpl)
+        .auth_provider(MockAuthProvider::default())
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    // The slower requests start first, so they overlap the faster ones
+    let requests = (0..8).map(|i| {
+        let url = url.clone();
+        let (token, user) = if i % 2 == 0 {
+            ("user-token", "user-1")
+        } else {
+            ("admin-token", "admin-1")
+        };
+        tokio::spawn(async move {
+            let placed = call(
+                &url,
+                Some(token),
+                "place_order",
+                serde_json::json!(40 - i * 5),
+            )
+            .await;
+            assert_eq!(placed, format!("placed by {user}"));
+        })
+    });
+    for request in requests.collect::<Vec<_>>() {
+        request.await.unwrap();
+    }
+
+    // Methods anyone can call still see the user of authenticated requests
+    assert_eq!(
+        call(&url, Some("admin-token"), "whoami", serde_json::json!(null)).await,
+        "admin-1"
+    );
+    assert_eq!(
+        call(&url, None, "whoami", serde_json::json!(null)).await,
+        serde_json::Value::Null
+    );
+}
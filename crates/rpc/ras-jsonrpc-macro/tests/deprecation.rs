@@ -0,0 +1,125 @@
+//! Deprecation and `since` metadata on methods.
+
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::spawn_http;
+
+jsonrpc_service!({
+    service_name: Tasks,
+    openrpc: true,
+    methods: [
+        /// Create a task
+        #[deprecated(since = "1.2.0", note = "use create_task_v2")]
+        UNAUTHORIZED create_task(String) -> u32,
+        #[since = "1.2.0"]
+        UNAUTHORIZED create_task_v2(String) -> u32,
+        #[deprecated]
+        UNAUTHORIZED purge(()) -> (),
+    ]
+});
+
+struct TasksImpl;
+
+impl TasksTrait for TasksImpl {
+    async fn create_task(
+        &self,
+        _: String,
+    ) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(1)
+    }
+
+    async fn create_task_v2(
+        &self,
+        _: String,
+    ) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(2)
+    }
+
+    async fn purge(&self, _: ()) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+}
+
+fn serve(builder: TasksBuilder<TasksImpl>) -> (axum_test::TestServer, String) {
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+    (server, url)
+}
+
+async fn warnings(url: &str, body: serde_json::Value) -> Vec<String> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    response
+        .headers()
+        .get_all("warning")
+        .iter()
+        .map(|value| value.to_str().unwrap().to_string())
+        .collect()
+}
+
+fn call(method: &str, id: u32) -> serde_json::Value {
+    serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": "task", "id": id })
+}
+
+#[tokio::test]
+async fn deprecated_calls_get_a_warning_header() {
+    let (_server, url) = serve(TasksBuilder::new(TasksImpl).with_deprecation_warnings(true));
+
+    assert_eq!(
+        warnings(&url, call("create_task", 1)).await,
+        [r#"299 - "Method create_task is deprecated since 1.2.0; use create_task_v2""#]
+    );
+    assert!(warnings(&url, call("create_task_v2", 1)).await.is_empty());
+
+    let batch = serde_json::json!([
+        call("create_task", 1),
+        call("create_task", 2),
+        { "jsonrpc": "2.0", "method": "purge", "params": null, "id": 3 },
+    ]);
+    assert_eq!(
+        warnings(&url, batch).await,
+        [
+            r#"299 - "Method create_task is deprecated since 1.2.0; use create_task_v2""#,
+            r#"299 - "Method purge is deprecated""#,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn warnings_are_off_by_default() {
+    let (_server, url) = serve(TasksBuilder::new(TasksImpl));
+
+    assert!(warnings(&url, call("create_task", 1)).await.is_empty());
+}
+
+#[tokio::test]
+#[allow(deprecated)]
+async fn deprecated_methods_stay_callable_from_the_client() {
+    let (_server, url) = serve(TasksBuilder::new(TasksImpl));
+    let client = TasksClientBuilder::new().server_url(url).build().unwrap();
+
+    assert_eq!(client.create_task("task".to_string()).await.unwrap(), 1);
+}
+
+#[test]
+fn openrpc_documents_deprecation_and_since() {
+    let doc = generate_tasks_openrpc();
+    let methods = doc["methods"].as_array().unwrap();
+    let method = |name: &str| methods.iter().find(|m| m["name"] == name).unwrap();
+
+    let create_task = method("create_task");
+    assert_eq!(create_task["deprecated"], true);
+    assert_eq!(create_task["x-ras-deprecated-since"], "1.2.0");
+    assert_eq!(create_task["x-ras-deprecation-note"], "use create_task_v2");
+    assert_eq!(create_task["summary"], "Create a task");
+
+    let create_task_v2 = method("create_task_v2");
+    assert!(create_task_v2.get("deprecated").is_none());
+    assert_eq!(create_task_v2["x-ras-since"], "1.2.0");
+
+    assert_eq!(method("purge")["deprecated"], true);
+    assert!(method("purge").get("x-ras-deprecation-note").is_none());
+}
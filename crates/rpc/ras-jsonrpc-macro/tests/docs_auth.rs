@@ -0,0 +1,93 @@
+//! Bearer-token protection of the generated explorer routes.
+
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+use reqwest::StatusCode;
+
+jsonrpc_service!({
+    service_name: Vault,
+    openrpc: true,
+    explorer: true,
+    docs_auth: WITH_PERMISSIONS(["admin"]),
+    methods: [
+        UNAUTHORIZED ping(()) -> String,
+    ]
+});
+
+struct VaultImpl;
+
+impl VaultTrait for VaultImpl {
+    async fn ping(&self, _request: ()) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok("pong".to_string())
+    }
+}
+
+fn serve() -> axum_test::TestServer {
+    spawn_http(
+        VaultBuilder::new(VaultImpl)
+            .auth_provider(MockAuthProvider::default())
+            .build()
+            .unwrap(),
+    )
+}
+
+async fn get(
+    server: &axum_test::TestServer,
+    path: &str,
+    accept: &str,
+    token: Option<&str>,
+) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .get(server.server_url(path).unwrap())
+        .header("accept", accept);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn browsers_without_a_token_get_the_login_page() {
+    let server = serve();
+
+    let response = get(&server, "/rpc/explorer", "text/html", None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("id=\"login-form\""));
+    assert!(html.contains("\"protocol\":\"jsonrpc\""));
+}
+
+#[tokio::test]
+async fn openrpc_json_requires_a_permitted_token() {
+    let server = serve();
+    let path = "/rpc/explorer/openrpc.json";
+
+    let response = get(&server, path, "application/json", None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "Sign in to view the API documentation");
+
+    let response = get(&server, path, "application/json", Some("user-token")).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = get(&server, path, "application/json", Some("admin-token")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["methods"][0]["name"], "ping");
+}
+
+#[tokio::test]
+async fn rpc_endpoint_is_not_behind_docs_auth() {
+    let server = serve();
+
+    let body: serde_json::Value = reqwest::Client::new()
+        .post(server.server_url("/rpc").unwrap())
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": "ping", "params": null, "id": 1 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["result"], "pong");
+}
@@ -318,3 +318,150 @@ async fn malformed_params_yield_jsonrpc_error() {
     let code = resp["error"]["code"].as_i64().unwrap();
     assert_eq!(code, -32602, "expected invalid_params (-32602), got {code}");
 }
+
+#[tokio::test]
+async fn client_batch_correlates_typed_results() {
+    let server = spawn_http(router());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let mut c = client(url);
+    c.set_bearer_token(Some("user-token".to_string()));
+
+    let mut batch = c.batch();
+    let ping = batch.ping(EchoRequest {
+        msg: "batched".to_string(),
+    });
+    let sum = batch.add(AddRequest { a: 40, b: 2 });
+    let denied = batch.admin_only(EchoRequest {
+        msg: "nope".to_string(),
+    });
+    let results = batch.send().await.expect("batch sent");
+
+    assert_eq!(results.get(&ping).expect("ping result").msg, "batched");
+    assert_eq!(results.get(&sum).expect("add result").sum, 42);
+    let err = results
+        .get(&denied)
+        .expect_err("user-token cannot call admin_only");
+    assert!(err.to_string().contains("-32002"), "got: {err}");
+}
+
+#[tokio::test]
+async fn batch_responses_follow_request_order_and_skip_notifications() {
+    let server = spawn_http(router());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let body = serde_json::json!([
+        { "jsonrpc": "2.0", "method": "ping", "params": { "msg": "first" }, "id": "a" },
+        { "jsonrpc": "2.0", "method": "ping", "params": { "msg": "notify" } },
+        { "jsonrpc": "2.0", "method": "missing", "id": 2 },
+        42,
+        { "jsonrpc": "2.0", "method": "ping", "params": { "msg": "last" }, "id": 3 },
+    ]);
+
+    let resp: serde_json::Value = reqwest::Client::new()
+        .post(url)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let responses = resp.as_array().expect("batch response array");
+    assert_eq!(responses.len(), 4, "notification must not be answered");
+    assert_eq!(responses[0]["id"], "a");
+    assert_eq!(responses[0]["result"]["msg"], "first");
+    assert_eq!(responses[1]["id"], 2);
+    assert_eq!(responses[1]["error"]["code"], -32601);
+    assert_eq!(responses[2]["error"]["code"], -32600);
+    assert_eq!(responses[3]["id"], 3);
+    assert_eq!(responses[3]["result"]["msg"], "last");
+}
+
+#[tokio::test]
+async fn empty_batch_is_a_single_invalid_request_error() {
+    let server = spawn_http(router());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let resp: serde_json::Value = reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!([]))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert!(resp.is_object(), "expected a single error object: {resp}");
+    assert_eq!(resp["error"]["code"], -32600);
+    assert!(resp["id"].is_null());
+}
+
+#[tokio::test]
+async fn batch_of_only_notifications_has_no_body() {
+    let server = spawn_http(router());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let body = serde_json::json!([
+        { "jsonrpc": "2.0", "method": "ping", "params": { "msg": "one" } },
+        { "jsonrpc": "2.0", "method": "ping", "params": { "msg": "two" } },
+    ]);
+
+    let resp = reqwest::Client::new()
+        .post(url)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), reqwest::StatusCode::NO_CONTENT);
+    assert!(resp.text().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn client_notification_runs_handler_without_response() {
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let tracked = seen.clone();
+    let router = DemoBuilder::new(DemoImpl)
+        .base_url("/rpc")
+        .auth_provider(MockAuthProvider::default())
+        .with_usage_tracker(move |_headers, _user, request| {
+            let tracked = tracked.clone();
+            let entry = (request.method.clone(), request.id.clone());
+            async move {
+                tracked.lock().unwrap().push(entry);
+            }
+        })
+        .build()
+        .expect("build router");
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    client(url.clone())
+        .notify_ping(EchoRequest {
+            msg: "fire and forget".to_string(),
+        })
+        .await
+        .expect("notification sent");
+
+    assert_eq!(
+        seen.lock().unwrap().as_slice(),
+        &[("ping".to_string(), None)]
+    );
+
+    let resp = reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "missing",
+            "params": {}
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), reqwest::StatusCode::NO_CONTENT);
+    assert!(resp.text().await.unwrap().is_empty());
+}
@@ -78,6 +78,66 @@ mod tests {
         assert!(openrpc_doc["methods"].is_array());
     }
 
+    mod task_service {
+        use ras_jsonrpc_macro::jsonrpc_service;
+        use serde::{Deserialize, Serialize};
+
+        fn create_task_example() -> CreateTaskRequest {
+            CreateTaskRequest {
+                title: "Write release notes".to_string(),
+            }
+        }
+
+        #[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+        #[schemars(example = create_task_example())]
+        pub struct CreateTaskRequest {
+            pub title: String,
+        }
+
+        jsonrpc_service!({
+            service_name: TaskService,
+            openrpc: true,
+            explorer: true,
+            methods: [
+                UNAUTHORIZED create_task(CreateTaskRequest) -> String,
+                UNAUTHORIZED list_tasks(()) -> Vec<String>,
+                WITH_PERMISSIONS(["admin"]) delete_task(String) -> bool,
+            ]
+        });
+    }
+
+    #[tokio::test]
+    async fn test_explorer_wires_token_history_and_examples() {
+        let app = Router::new().merge(task_service::taskservice_explorer_routes(""));
+        let server = ras_test_helpers::spawn_http(app);
+
+        let page = server.get("/explorer").await.text();
+        // Tokens can be remembered on this device and forgotten again
+        assert!(page.contains("id=\"remember-token\""));
+        assert!(page.contains("Forget token"));
+        // History keeps the configured number of requests with their responses
+        assert!(page.contains("\"historyLimit\":50"));
+        assert!(page.contains("response: state.lastResponse.body"));
+        assert!(page.contains("Replay"));
+        // Examples from the OpenRPC document pre-fill the params editor
+        assert!(page.contains("Array.isArray(method.examples)"));
+        assert!(page.contains("exampleSelect(examples)"));
+
+        let doc: serde_json::Value = server.get("/explorer/openrpc.json").await.json();
+        let methods = doc["methods"].as_array().unwrap();
+        assert_eq!(methods.len(), 3);
+        let method = |name: &str| methods.iter().find(|m| m["name"] == name).unwrap();
+        assert_eq!(
+            method("create_task")["examples"],
+            serde_json::json!([{
+                "name": "create_task_example_1",
+                "params": [{ "name": "params", "value": { "title": "Write release notes" } }]
+            }])
+        );
+        assert!(method("list_tasks").get("examples").is_none());
+        assert!(method("delete_task").get("examples").is_none());
+    }
+
     #[test]
     fn test_explorer_with_custom_path() {
         mod custom_path_service {
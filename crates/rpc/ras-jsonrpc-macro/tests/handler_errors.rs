@@ -0,0 +1,128 @@
+//! Application error codes and data returned from service handlers.
+
+use ras_jsonrpc_core::{IntoJsonRpcError, JsonRpcError, JsonRpcHandlerError};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::spawn_http;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FindTaskRequest {
+    id: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Task {
+    id: u64,
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotFoundData {
+    task_id: u64,
+}
+
+enum TaskError {
+    NotFound(u64),
+}
+
+impl IntoJsonRpcError for TaskError {
+    fn into_jsonrpc_error(self) -> JsonRpcHandlerError {
+        match self {
+            TaskError::NotFound(id) => JsonRpcHandlerError::new(-32010, "Task not found")
+                .with_data(serde_json::json!({ "task_id": id })),
+        }
+    }
+}
+
+jsonrpc_service!({
+    service_name: Tasks,
+    openrpc: true,
+    methods: [
+        UNAUTHORIZED find_task(FindTaskRequest) -> Task {
+            errors: [-32010 => "Task not found", -32011 => "Quota exceeded"],
+        },
+        UNAUTHORIZED crash(()) -> (),
+    ]
+});
+
+struct TasksImpl;
+
+impl TasksTrait for TasksImpl {
+    async fn find_task(
+        &self,
+        req: FindTaskRequest,
+    ) -> Result<Task, Box<dyn std::error::Error + Send + Sync>> {
+        if req.id != 1 {
+            return Err(JsonRpcHandlerError::from(TaskError::NotFound(req.id)).into());
+        }
+
+        Ok(Task {
+            id: 1,
+            title: "write docs".to_string(),
+        })
+    }
+
+    async fn crash(&self, _req: ()) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Err("disk full at /var/lib/tasks".into())
+    }
+}
+
+fn client(url: String) -> TasksClient {
+    TasksClientBuilder::new().server_url(url).build().unwrap()
+}
+
+#[tokio::test]
+async fn handler_error_code_and_data_reach_the_client() {
+    let server = spawn_http(TasksBuilder::new(TasksImpl).build().unwrap());
+    let client = client(server.server_url("/rpc").unwrap().to_string());
+
+    assert_eq!(
+        client
+            .find_task(FindTaskRequest { id: 1 })
+            .await
+            .unwrap()
+            .id,
+        1
+    );
+
+    let err = client
+        .find_task(FindTaskRequest { id: 9 })
+        .await
+        .expect_err("unknown task");
+    let err = err.downcast_ref::<JsonRpcError>().expect("JSON-RPC error");
+    assert_eq!(err.code, -32010);
+    assert_eq!(err.message, "Task not found");
+    let data: NotFoundData = err.data_as().unwrap().expect("error data");
+    assert_eq!(data.task_id, 9);
+}
+
+#[tokio::test]
+async fn other_handler_errors_stay_sanitized() {
+    let server = spawn_http(TasksBuilder::new(TasksImpl).build().unwrap());
+    let client = client(server.server_url("/rpc").unwrap().to_string());
+
+    let err = client.crash(()).await.expect_err("crash fails");
+    let err = err.downcast_ref::<JsonRpcError>().expect("JSON-RPC error");
+    assert_eq!(err.code, -32603);
+    assert_eq!(err.message, "Internal error");
+    assert!(err.data.is_none());
+}
+
+#[test]
+fn openrpc_lists_declared_errors_per_method() {
+    let doc = generate_tasks_openrpc();
+    let methods = doc["methods"].as_array().unwrap();
+
+    let find_task = methods.iter().find(|m| m["name"] == "find_task").unwrap();
+    assert_eq!(
+        find_task["errors"],
+        serde_json::json!([
+            { "code": -32010, "message": "Task not found" },
+            { "code": -32011, "message": "Quota exceeded" },
+        ])
+    );
+
+    let crash = methods.iter().find(|m| m["name"] == "crash").unwrap();
+    assert!(crash.get("errors").is_none());
+}
@@ -1,5 +1,5 @@
 use rand::Rng;
-use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_jsonrpc_macro::jsonrpc_service;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
@@ -96,6 +96,7 @@ impl AuthProvider for TestAuthProvider {
                 user_id: user_id.to_string(),
                 permissions: permissions.into_iter().collect(),
                 metadata: None,
+                kind: PrincipalKind::User,
             })
         })
     }
@@ -1,6 +1,6 @@
 use axum::Router;
 use axum::http::StatusCode;
-use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_jsonrpc_macro::jsonrpc_service;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
@@ -72,6 +72,7 @@ impl AuthProvider for MockAuthProvider {
                         user_id: "user1".to_string(),
                         permissions,
                         metadata: None,
+                        kind: PrincipalKind::User,
                     })
                 }
                 "admin-token" => {
@@ -81,9 +82,11 @@ impl AuthProvider for MockAuthProvider {
                         user_id: "admin1".to_string(),
                         permissions,
                         metadata: None,
+                        kind: PrincipalKind::User,
                     })
                 }
                 "expired-token" => Err(AuthError::TokenExpired),
+                "future-token" => Err(AuthError::TokenNotYetValid),
                 _ => Err(AuthError::InvalidToken),
             }
         })
@@ -246,3 +249,24 @@ async fn test_token_expired_returns_401() {
     let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
     assert_eq!(json["error"]["code"], -32003); // TOKEN_EXPIRED
 }
+
+#[tokio::test]
+async fn test_token_not_yet_valid_returns_401() {
+    let app = test_app();
+
+    let response = make_jsonrpc_request(
+        app.clone(),
+        "user_method",
+        serde_json::json!({"value": "test"}),
+        Some("Bearer future-token"),
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["error"]["code"], -32004); // TOKEN_NOT_YET_VALID
+}
@@ -0,0 +1,234 @@
+//! Idempotency keys on `IDEMPOTENT` methods in the generated server.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+use serde::{Deserialize, Serialize};
+
+/// A params-embedded `idempotency_key` is read from the raw params, so the
+/// request type does not need to declare it.
+#[derive(Serialize, Deserialize)]
+struct CreateTask {
+    title: String,
+}
+
+jsonrpc_service!({
+    service_name: Tasks,
+    methods: [
+        WITH_PERMISSIONS(["user"]) IDEMPOTENT create_task(CreateTask) -> u64,
+        UNAUTHORIZED IDEMPOTENT flaky(()) -> u64,
+        UNAUTHORIZED count(()) -> u64,
+    ]
+});
+
+/// Numbers created tasks, and fails every first `flaky` call.
+#[derive(Default)]
+struct TasksImpl {
+    created: AtomicU64,
+    flaky_calls: AtomicU64,
+}
+
+impl TasksTrait for TasksImpl {
+    async fn create_task(
+        &self,
+        _user: &ras_jsonrpc_core::AuthenticatedUser,
+        req: CreateTask,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        if req.title.is_empty() {
+            return Err("title is required".into());
+        }
+        // Slow enough for concurrent duplicates to overlap
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        Ok(self.created.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    async fn flaky(&self, _req: ()) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        match self.flaky_calls.fetch_add(1, Ordering::SeqCst) {
+            0 => Err("transient failure".into()),
+            calls => Ok(calls + 1),
+        }
+    }
+
+    async fn count(&self, _req: ()) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.created.load(Ordering::SeqCst))
+    }
+}
+
+fn serve() -> (axum_test::TestServer, String) {
+    let builder =
+        TasksBuilder::new(TasksImpl::default()).auth_provider(MockAuthProvider::default());
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+    (server, url)
+}
+
+async fn call(
+    url: &str,
+    method: &str,
+    params: serde_json::Value,
+    key: Option<&str>,
+) -> serde_json::Value {
+    call_as(url, Some("user-token"), method, params, key).await
+}
+
+async fn call_as(
+    url: &str,
+    token: Option<&str>,
+    method: &str,
+    params: serde_json::Value,
+    key: Option<&str>,
+) -> serde_json::Value {
+    let mut request = reqwest::Client::new().post(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(key) = key {
+        request = request.header("Idempotency-Key", key);
+    }
+    request
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+fn task(title: &str) -> serde_json::Value {
+    serde_json::json!({ "title": title })
+}
+
+#[tokio::test]
+async fn duplicate_keys_replay_the_first_result() {
+    let (_server, url) = serve();
+
+    let first = call(&url, "create_task", task("a"), Some("key-1")).await;
+    let retry = call(&url, "create_task", task("a"), Some("key-1")).await;
+    assert_eq!(first["result"], 1);
+    assert_eq!(retry["result"], 1);
+
+    let other = call(&url, "create_task", task("b"), Some("key-2")).await;
+    assert_eq!(other["result"], 2);
+
+    // Calls without a key always run
+    call(&url, "create_task", task("c"), None).await;
+    call(&url, "create_task", task("c"), None).await;
+    assert_eq!(
+        call(&url, "count", serde_json::Value::Null, None).await["result"],
+        4
+    );
+}
+
+#[tokio::test]
+async fn params_embedded_key_is_used_without_header() {
+    let (_server, url) = serve();
+    let params = serde_json::json!({ "title": "a", "idempotency_key": "embedded" });
+
+    let first = call(&url, "create_task", params.clone(), None).await;
+    let retry = call(&url, "create_task", params, None).await;
+    assert_eq!(first["result"], 1);
+    assert_eq!(retry["result"], 1);
+}
+
+#[tokio::test]
+async fn concurrent_duplicates_coalesce_onto_one_execution() {
+    let (_server, url) = serve();
+
+    let (first, second) = tokio::join!(
+        call(&url, "create_task", task("a"), Some("shared")),
+        call(&url, "create_task", task("a"), Some("shared")),
+    );
+    assert_eq!(first["result"], 1);
+    assert_eq!(second["result"], 1);
+    assert_eq!(
+        call(&url, "count", serde_json::Value::Null, None).await["result"],
+        1
+    );
+}
+
+#[tokio::test]
+async fn failed_calls_are_not_stored() {
+    let (_server, url) = serve();
+
+    let failed = call(&url, "flaky", serde_json::Value::Null, Some("retry")).await;
+    assert!(failed["error"].is_object());
+
+    let retried = call(&url, "flaky", serde_json::Value::Null, Some("retry")).await;
+    let replayed = call(&url, "flaky", serde_json::Value::Null, Some("retry")).await;
+    assert_eq!(retried["result"], 2);
+    assert_eq!(replayed["result"], 2);
+}
+
+#[tokio::test]
+async fn stored_results_expire_after_the_ttl() {
+    let builder = TasksBuilder::new(TasksImpl::default())
+        .auth_provider(MockAuthProvider::default())
+        .with_idempotency_ttl(Duration::from_millis(10));
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let first = call(&url, "create_task", task("a"), Some("key")).await;
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let later = call(&url, "create_task", task("a"), Some("key")).await;
+    assert_eq!(first["result"], 1);
+    assert_eq!(later["result"], 2);
+}
+
+#[tokio::test]
+async fn keys_reused_with_other_params_are_refused() {
+    let (_server, url) = serve();
+
+    let first = call(&url, "create_task", task("a"), Some("key")).await;
+    let reused = call(&url, "create_task", task("b"), Some("key")).await;
+    assert_eq!(first["result"], 1);
+    assert_eq!(reused["error"]["code"], -32602);
+    assert_eq!(reused["error"]["data"]["idempotency_key"], "key");
+    assert_eq!(
+        call(&url, "count", serde_json::Value::Null, None).await["result"],
+        1
+    );
+}
+
+#[tokio::test]
+async fn anonymous_callers_cannot_use_keys() {
+    let (_server, url) = serve();
+
+    let refused = call_as(&url, None, "flaky", serde_json::Value::Null, Some("key")).await;
+    assert_eq!(refused["error"]["code"], -32001);
+
+    // Without a key they call as usual, and the first call fails
+    let failed = call_as(&url, None, "flaky", serde_json::Value::Null, None).await;
+    let called = call_as(&url, None, "flaky", serde_json::Value::Null, None).await;
+    assert!(failed["error"].is_object());
+    assert_eq!(called["result"], 2);
+}
+
+#[tokio::test]
+async fn stored_results_are_only_replayed_to_authorized_calls() {
+    let allowed = Arc::new(AtomicBool::new(true));
+    let builder = TasksBuilder::new(TasksImpl::default())
+        .auth_provider(MockAuthProvider::default())
+        .authorize_create_task({
+            let allowed = allowed.clone();
+            move |_user: &ras_jsonrpc_core::AuthenticatedUser, _request: &CreateTask| {
+                std::future::ready(if allowed.load(Ordering::SeqCst) {
+                    Ok(())
+                } else {
+                    Err(ras_jsonrpc_core::AccessDenied::new("suspended"))
+                })
+            }
+        });
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let first = call(&url, "create_task", task("a"), Some("key")).await;
+    assert_eq!(first["result"], 1);
+
+    allowed.store(false, Ordering::SeqCst);
+    let retry = call(&url, "create_task", task("a"), Some("key")).await;
+    assert_eq!(retry["error"]["code"], -32002);
+}
@@ -1,4 +1,4 @@
-use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use serde::{Deserialize, Serialize};
 
 // Test types for requests and responses
@@ -38,6 +38,7 @@ impl AuthProvider for TestAuthProvider {
                     user_id: "test-user".to_string(),
                     permissions: ["admin".to_string()].into_iter().collect(),
                     metadata: None,
+                    kind: PrincipalKind::User,
                 })
             } else {
                 Err(AuthError::InvalidToken)
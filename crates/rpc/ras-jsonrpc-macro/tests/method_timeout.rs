@@ -0,0 +1,149 @@
+//! Method timeouts in the generated server.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ras_jsonrpc_core::error_codes;
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::spawn_http;
+
+jsonrpc_service!({
+    service_name: Jobs,
+    openrpc: true,
+    methods: [
+        UNAUTHORIZED TIMEOUT(100ms) quick(u64) -> u64,
+        UNAUTHORIZED sleep(u64) -> u64,
+        UNAUTHORIZED TIMEOUT(2s) slow(u64) -> u64,
+    ]
+});
+
+#[derive(Default)]
+struct JobsImpl {
+    finished: Arc<AtomicBool>,
+}
+
+impl JobsImpl {
+    async fn run(&self, millis: u64) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        self.finished.store(true, Ordering::SeqCst);
+        Ok(millis)
+    }
+}
+
+impl JobsTrait for JobsImpl {
+    async fn quick(&self, millis: u64) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        self.run(millis).await
+    }
+
+    async fn sleep(&self, millis: u64) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        self.run(millis).await
+    }
+
+    async fn slow(&self, millis: u64) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        self.run(millis).await
+    }
+}
+
+async fn call(url: &str, method: &str, millis: u64) -> serde_json::Value {
+    reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": millis,
+            "id": 1,
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+fn serve(builder: JobsBuilder<JobsImpl>) -> (axum_test::TestServer, String) {
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+    (server, url)
+}
+
+#[tokio::test]
+async fn handlers_over_budget_return_request_timeout() {
+    let service = JobsImpl::default();
+    let finished = service.finished.clone();
+    let (_server, url) = serve(JobsBuilder::new(service));
+
+    let body = call(&url, "quick", 500).await;
+    assert_eq!(body["error"]["code"], error_codes::REQUEST_TIMEOUT);
+    assert_eq!(body["error"]["data"]["timeout_ms"], 100);
+    assert!(body["error"]["data"]["elapsed_ms"].as_u64().unwrap() >= 100);
+    assert_eq!(body["id"], 1);
+
+    // The handler future was dropped instead of running to completion
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert!(!finished.load(Ordering::SeqCst));
+
+    assert_eq!(call(&url, "quick", 10).await["result"], 10);
+}
+
+#[tokio::test]
+async fn method_annotation_overrides_builder_timeout() {
+    let builder =
+        JobsBuilder::new(JobsImpl::default()).with_method_timeout(Duration::from_millis(50));
+    let (_server, url) = serve(builder);
+
+    let body = call(&url, "sleep", 200).await;
+    assert_eq!(body["error"]["code"], error_codes::REQUEST_TIMEOUT);
+    assert_eq!(body["error"]["data"]["timeout_ms"], 50);
+
+    assert_eq!(call(&url, "slow", 200).await["result"], 200);
+}
+
+#[tokio::test]
+async fn timeouts_are_tracked_as_failures() {
+    let durations = Arc::new(Mutex::new(Vec::new()));
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    let builder = JobsBuilder::new(JobsImpl::default())
+        .with_method_duration_tracker({
+            let durations = durations.clone();
+            move |method, _user, duration| {
+                durations
+                    .lock()
+                    .unwrap()
+                    .push((method.to_string(), duration));
+                async {}
+            }
+        })
+        .with_method_outcome_tracker({
+            let outcomes = outcomes.clone();
+            move |method, _user, _duration, success| {
+                outcomes.lock().unwrap().push((method.to_string(), success));
+                async {}
+            }
+        });
+    let (_server, url) = serve(builder);
+
+    call(&url, "quick", 10).await;
+    call(&url, "quick", 500).await;
+
+    assert_eq!(
+        *outcomes.lock().unwrap(),
+        [("quick".to_string(), true), ("quick".to_string(), false)]
+    );
+    let durations = durations.lock().unwrap();
+    assert_eq!(durations.len(), 2);
+    assert!(durations[1].1 >= Duration::from_millis(100));
+    assert!(durations[1].1 < Duration::from_millis(500));
+}
+
+#[test]
+fn openrpc_documents_method_timeouts() {
+    let doc = generate_jobs_openrpc();
+    let methods = doc["methods"].as_array().unwrap();
+    let method = |name: &str| methods.iter().find(|m| m["name"] == name).unwrap();
+
+    assert_eq!(method("quick")["x-ras-timeout-ms"], 100);
+    assert_eq!(method("slow")["x-ras-timeout-ms"], 2000);
+    assert!(method("sleep").get("x-ras-timeout-ms").is_none());
+}
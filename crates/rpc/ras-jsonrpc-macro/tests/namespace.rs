@@ -0,0 +1,147 @@
+//! Namespaced services sharing a single JSON-RPC route through `JsonRpcRouter`.
+
+use ras_jsonrpc_core::JsonRpcRouter;
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CreateRequest {
+    title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CreateResponse {
+    created: String,
+}
+
+jsonrpc_service!({
+    service_name: Tasks,
+    namespace: "tasks",
+    methods: [
+        UNAUTHORIZED create(CreateRequest) -> CreateResponse,
+        WITH_PERMISSIONS(["user"]) list(()) -> Vec<String>,
+    ]
+});
+
+jsonrpc_service!({
+    service_name: Projects,
+    namespace: "projects",
+    methods: [
+        UNAUTHORIZED create(CreateRequest) -> CreateResponse,
+    ]
+});
+
+struct TasksImpl;
+
+impl TasksTrait for TasksImpl {
+    async fn create(
+        &self,
+        req: CreateRequest,
+    ) -> Result<CreateResponse, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(CreateResponse {
+            created: format!("task:{}", req.title),
+        })
+    }
+
+    async fn list(
+        &self,
+        user: &ras_jsonrpc_core::AuthenticatedUser,
+        _req: (),
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(vec![user.user_id.clone()])
+    }
+}
+
+struct ProjectsImpl;
+
+impl ProjectsTrait for ProjectsImpl {
+    async fn create(
+        &self,
+        req: CreateRequest,
+    ) -> Result<CreateResponse, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(CreateResponse {
+            created: format!("project:{}", req.title),
+        })
+    }
+}
+
+fn shared_router() -> axum::Router {
+    JsonRpcRouter::new("/rpc")
+        .service(TasksBuilder::new(TasksImpl).auth_provider(MockAuthProvider::default()))
+        .service(ProjectsBuilder::new(ProjectsImpl))
+        .build()
+        .expect("distinct namespaces")
+}
+
+async fn post(url: &str, body: serde_json::Value) -> serde_json::Value {
+    reqwest::Client::new()
+        .post(url)
+        .bearer_auth("user-token")
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn generated_clients_call_namespaced_methods_on_a_shared_route() {
+    let server = spawn_http(shared_router());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let tasks = TasksClientBuilder::new()
+        .server_url(url.clone())
+        .build()
+        .unwrap();
+    let projects = ProjectsClientBuilder::new()
+        .server_url(url)
+        .build()
+        .unwrap();
+
+    let request = CreateRequest {
+        title: "docs".to_string(),
+    };
+    assert_eq!(
+        tasks.create(request.clone()).await.unwrap().created,
+        "task:docs"
+    );
+    assert_eq!(
+        projects.create(request).await.unwrap().created,
+        "project:docs"
+    );
+}
+
+#[tokio::test]
+async fn shared_route_dispatches_by_wire_name() {
+    let server = spawn_http(shared_router());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let resp = post(
+        &url,
+        serde_json::json!({ "jsonrpc": "2.0", "method": "tasks.list", "params": null, "id": 1 }),
+    )
+    .await;
+    assert_eq!(resp["result"], serde_json::json!(["user-1"]));
+
+    // The bare Rust identifier is not a wire method once a namespace is set
+    let resp = post(
+        &url,
+        serde_json::json!({ "jsonrpc": "2.0", "method": "create", "params": { "title": "x" }, "id": 2 }),
+    )
+    .await;
+    assert_eq!(resp["error"]["code"], -32601);
+    assert_eq!(resp["id"], 2);
+}
+
+#[test]
+fn shared_route_rejects_colliding_services() {
+    let result = JsonRpcRouter::new("/rpc")
+        .service(ProjectsBuilder::new(ProjectsImpl))
+        .service(ProjectsBuilder::new(ProjectsImpl))
+        .build();
+
+    assert!(result.is_err());
+}
@@ -0,0 +1,144 @@
+//! Request size limits and payload size tracking in the generated server.
+
+use std::sync::{Arc, Mutex};
+
+use ras_jsonrpc_core::error_codes;
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::spawn_http;
+
+const METHOD_LIMIT: usize = 256;
+const BODY_LIMIT: usize = 1024;
+
+jsonrpc_service!({
+    service_name: Notes,
+    methods: [
+        UNAUTHORIZED MAX_REQUEST_SIZE(256) submit(String) -> usize,
+        UNAUTHORIZED echo(String) -> usize,
+    ]
+});
+
+struct NotesImpl;
+
+impl NotesTrait for NotesImpl {
+    async fn submit(
+        &self,
+        note: String,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(note.len())
+    }
+
+    async fn echo(&self, note: String) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(note.len())
+    }
+}
+
+/// A request object for `method` that is exactly `size` bytes long.
+fn request_of_size(method: &str, size: usize) -> String {
+    let request =
+        |note: &str| format!(r#"{{"jsonrpc":"2.0","method":"{method}","params":"{note}","id":1}}"#);
+    let padding = size - request("").len();
+    let request = request(&"x".repeat(padding));
+    assert_eq!(request.len(), size);
+    request
+}
+
+async fn post(url: &str, body: String) -> (reqwest::StatusCode, serde_json::Value) {
+    let response = reqwest::Client::new()
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+fn serve() -> (axum_test::TestServer, String) {
+    let builder = NotesBuilder::new(NotesImpl).with_max_request_size(BODY_LIMIT);
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+    (server, url)
+}
+
+#[tokio::test]
+async fn method_limit_accepts_exactly_the_limit() {
+    let (_server, url) = serve();
+
+    let (status, body) = post(&url, request_of_size("submit", METHOD_LIMIT)).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert!(body["result"].is_u64(), "{body}");
+
+    let (status, body) = post(&url, request_of_size("submit", METHOD_LIMIT + 1)).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(body["error"]["code"], error_codes::INVALID_REQUEST);
+    assert_eq!(body["error"]["data"]["size"], METHOD_LIMIT + 1);
+    assert_eq!(body["error"]["data"]["max_size"], METHOD_LIMIT);
+    assert_eq!(body["id"], 1);
+}
+
+#[tokio::test]
+async fn body_limit_accepts_exactly_the_limit() {
+    let (_server, url) = serve();
+
+    let (status, body) = post(&url, request_of_size("echo", BODY_LIMIT)).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert!(body["result"].is_u64(), "{body}");
+
+    let (status, body) = post(&url, request_of_size("echo", BODY_LIMIT + 1)).await;
+    assert_eq!(status, reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["error"]["code"], error_codes::INVALID_REQUEST);
+    assert_eq!(body["error"]["data"]["size"], BODY_LIMIT + 1);
+    assert_eq!(body["error"]["data"]["max_size"], BODY_LIMIT);
+}
+
+#[tokio::test]
+async fn method_limit_applies_to_each_batch_entry() {
+    let (_server, url) = serve();
+    let batch = format!(
+        "[{},{}]",
+        request_of_size("submit", METHOD_LIMIT).replace(r#""id":1"#, r#""id":2"#),
+        request_of_size("submit", METHOD_LIMIT + 1),
+    );
+
+    let (status, body) = post(&url, batch).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let responses = body.as_array().unwrap();
+    assert_eq!(responses.len(), 2);
+    assert!(responses[0]["result"].is_u64(), "{body}");
+    assert_eq!(responses[1]["error"]["code"], error_codes::INVALID_REQUEST);
+}
+
+#[tokio::test]
+async fn payload_sizes_are_reported_per_method() {
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    let builder = NotesBuilder::new(NotesImpl).with_payload_size_tracker({
+        let recorded = recorded.clone();
+        move |method, request_size, response_size| {
+            recorded
+                .lock()
+                .unwrap()
+                .push((method.to_string(), request_size, response_size));
+            async {}
+        }
+    });
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .body(request_of_size("echo", 100))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Undeclared methods are not reported
+    post(&url, request_of_size("missing", 100)).await;
+
+    let recorded = recorded.lock().unwrap();
+    assert_eq!(*recorded, vec![("echo".to_string(), 100, response.len())]);
+}
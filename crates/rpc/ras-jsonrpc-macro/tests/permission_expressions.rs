@@ -0,0 +1,162 @@
+//! `WITH_PERMISSIONS(expr = "...")` methods: their checks, docs and manifest.
+
+use ras_jsonrpc_core::{AuthMode, AuthenticatedUser};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, mock_user, spawn_http};
+use reqwest::StatusCode;
+
+jsonrpc_service!({
+    service_name: Tasks,
+    openrpc: true,
+    explorer: true,
+    docs_auth: WITH_PERMISSIONS(expr = "admin | docs & !suspended"),
+    methods: [
+        WITH_PERMISSIONS(expr = "admin | (tasks:write & !suspended)") create_task(String) -> String,
+        WITH_PERMISSIONS(["tasks:read"]) list_tasks(()) -> Vec<String>,
+    ]
+});
+
+struct TasksImpl;
+
+impl TasksTrait for TasksImpl {
+    async fn create_task(
+        &self,
+        user: &AuthenticatedUser,
+        title: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(format!("{title} by {}", user.user_id))
+    }
+
+    async fn list_tasks(
+        &self,
+        _user: &AuthenticatedUser,
+        _request: (),
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Vec::new())
+    }
+}
+
+fn serve() -> axum_test::TestServer {
+    let provider = MockAuthProvider::default()
+        .with_token("writer-token", mock_user("writer-1", &["tasks:write"]))
+        .with_token(
+            "suspended-token",
+            mock_user("suspended-1", &["tasks:write", "docs", "suspended"]),
+        )
+        .with_token("docs-token", mock_user("docs-1", &["docs"]));
+    spawn_http(
+        TasksBuilder::new(TasksImpl)
+            .auth_provider(provider)
+            .build()
+            .unwrap(),
+    )
+}
+
+async fn create_task(server: &axum_test::TestServer, token: Option<&str>) -> serde_json::Value {
+    let mut request = reqwest::Client::new().post(server.server_url("/rpc").unwrap());
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "create_task",
+            "params": "write docs",
+            "id": 1
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn expressions_decide_who_may_call() {
+    let server = serve();
+
+    for (token, user) in [("admin-token", "admin-1"), ("writer-token", "writer-1")] {
+        let response = create_task(&server, Some(token)).await;
+        assert_eq!(response["result"], format!("write docs by {user}"));
+    }
+
+    // A suspended writer is refused by the NOT, a plain user by both branches
+    for token in ["suspended-token", "user-token"] {
+        let response = create_task(&server, Some(token)).await;
+        let error = &response["error"];
+        assert_eq!(
+            error["code"],
+            ras_jsonrpc_types::error_codes::INSUFFICIENT_PERMISSIONS
+        );
+        assert_eq!(error["data"]["code"], "insufficient_permissions");
+        assert_eq!(
+            error["data"]["required"],
+            serde_json::json!(["admin | tasks:write & !suspended"])
+        );
+    }
+
+    let response = create_task(&server, None).await;
+    assert_eq!(
+        response["error"]["code"],
+        ras_jsonrpc_types::error_codes::AUTHENTICATION_REQUIRED
+    );
+}
+
+#[tokio::test]
+async fn docs_auth_accepts_expressions() {
+    let server = serve();
+
+    for (token, status) in [
+        ("docs-token", StatusCode::OK),
+        ("admin-token", StatusCode::OK),
+        ("suspended-token", StatusCode::FORBIDDEN),
+        ("user-token", StatusCode::FORBIDDEN),
+    ] {
+        let response = reqwest::Client::new()
+            .get(server.server_url("/rpc/explorer").unwrap())
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status, "{token}");
+    }
+}
+
+#[test]
+fn openrpc_documents_the_expression() {
+    let openrpc = generate_tasks_openrpc();
+    let methods = openrpc["methods"].as_array().unwrap();
+
+    let create = methods.iter().find(|m| m["name"] == "create_task").unwrap();
+    assert_eq!(
+        create["x-permission-expression"],
+        "admin | tasks:write & !suspended"
+    );
+    assert!(create.get("x-permissions").is_none());
+
+    let list = methods.iter().find(|m| m["name"] == "list_tasks").unwrap();
+    assert_eq!(list["x-permissions"], serde_json::json!(["tasks:read"]));
+    assert!(list.get("x-permission-expression").is_none());
+}
+
+#[test]
+fn manifest_records_the_expression() {
+    let manifest = tasks_service_manifest();
+
+    let create = manifest.operation("create_task").unwrap();
+    assert_eq!(create.auth, AuthMode::Permissions);
+    assert!(create.permission_groups.is_empty());
+    assert_eq!(
+        create.permission_expression.as_deref(),
+        Some("admin | tasks:write & !suspended")
+    );
+
+    let list = manifest.operation("list_tasks").unwrap();
+    assert_eq!(list.permission_expression, None);
+    assert!(
+        manifest
+            .to_string()
+            .contains("create_task: admin | tasks:write & !suspended")
+    );
+}
@@ -0,0 +1,159 @@
+//! Params-by-position arrays in the generated server.
+
+use ras_jsonrpc_core::error_codes;
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::spawn_http;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+struct SearchRequest {
+    query: String,
+    limit: u32,
+    #[serde(default)]
+    offset: u32,
+    tag: Option<String>,
+}
+
+jsonrpc_service!({
+    service_name: Catalog,
+    openrpc: true,
+    methods: [
+        UNAUTHORIZED search(SearchRequest) -> String {
+            positional_params: true,
+        },
+        UNAUTHORIZED lookup(SearchRequest) -> String,
+    ]
+});
+
+jsonrpc_service!({
+    service_name: Archive,
+    positional_params: true,
+    methods: [
+        UNAUTHORIZED find(SearchRequest) -> String,
+    ]
+});
+
+fn describe(request: SearchRequest) -> String {
+    format!(
+        "{}:{}:{}:{}",
+        request.query,
+        request.limit,
+        request.offset,
+        request.tag.unwrap_or_default()
+    )
+}
+
+struct CatalogImpl;
+
+impl CatalogTrait for CatalogImpl {
+    async fn search(
+        &self,
+        request: SearchRequest,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(describe(request))
+    }
+
+    async fn lookup(
+        &self,
+        request: SearchRequest,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(describe(request))
+    }
+}
+
+struct ArchiveImpl;
+
+impl ArchiveTrait for ArchiveImpl {
+    async fn find(
+        &self,
+        request: SearchRequest,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(describe(request))
+    }
+}
+
+async fn call(url: &str, method: &str, params: serde_json::Value) -> serde_json::Value {
+    reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 1,
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+fn serve(router: axum::Router) -> (axum_test::TestServer, String) {
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap().to_string();
+    (server, url)
+}
+
+#[tokio::test]
+async fn positional_methods_accept_arrays_and_objects() {
+    let (_server, url) = serve(CatalogBuilder::new(CatalogImpl).build().unwrap());
+
+    let body = call(&url, "search", serde_json::json!(["rust", 10, 5, "book"])).await;
+    assert_eq!(body["result"], "rust:10:5:book");
+
+    let body = call(
+        &url,
+        "search",
+        serde_json::json!({ "query": "rust", "limit": 10, "offset": 5, "tag": "book" }),
+    )
+    .await;
+    assert_eq!(body["result"], "rust:10:5:book");
+}
+
+#[tokio::test]
+async fn trailing_defaulted_and_optional_fields_may_be_omitted() {
+    let (_server, url) = serve(CatalogBuilder::new(CatalogImpl).build().unwrap());
+
+    let body = call(&url, "search", serde_json::json!(["rust", 10])).await;
+    assert_eq!(body["result"], "rust:10:0:");
+
+    let body = call(&url, "search", serde_json::json!(["rust"])).await;
+    assert_eq!(body["error"]["code"], error_codes::INVALID_PARAMS);
+
+    let body = call(
+        &url,
+        "search",
+        serde_json::json!(["rust", 10, 5, "book", 1]),
+    )
+    .await;
+    assert_eq!(body["error"]["code"], error_codes::INVALID_PARAMS);
+}
+
+#[tokio::test]
+async fn other_methods_keep_serde_sequence_handling() {
+    let (_server, url) = serve(CatalogBuilder::new(CatalogImpl).build().unwrap());
+
+    // Plain serde only fills structs from arrays that carry every non-default field
+
+    let body = call(&url, "lookup", serde_json::json!(["rust", 10])).await;
+    assert_eq!(body["error"]["code"], error_codes::INVALID_PARAMS);
+}
+
+#[tokio::test]
+async fn service_wide_setting_applies_to_every_method() {
+    let (_server, url) = serve(ArchiveBuilder::new(ArchiveImpl).build().unwrap());
+
+    let body = call(&url, "find", serde_json::json!(["rust", 3])).await;
+    assert_eq!(body["result"], "rust:3:0:");
+}
+
+#[test]
+fn openrpc_marks_positional_methods() {
+    let doc = generate_catalog_openrpc();
+    let methods = doc["methods"].as_array().unwrap();
+    let method = |name: &str| methods.iter().find(|m| m["name"] == name).unwrap();
+
+    assert_eq!(method("search")["paramStructure"], "either");
+    assert!(method("lookup").get("paramStructure").is_none());
+}
@@ -0,0 +1,122 @@
+//! Client-side rate limiting and 429 handling in the generated JSON-RPC client.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_jsonrpc_types::RateLimiter;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate, matchers};
+
+jsonrpc_service!({
+    service_name: Quota,
+    methods: [
+        UNAUTHORIZED spend(u32) -> u32,
+    ]
+});
+
+/// Answers requests with the scripted `Retry-After` values, `None` meaning success,
+/// and records when each request arrived.
+#[derive(Clone, Default)]
+struct Throttling {
+    replies: Arc<Mutex<VecDeque<Option<&'static str>>>>,
+    arrivals: Arc<Mutex<Vec<Instant>>>,
+}
+
+impl Respond for Throttling {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        self.arrivals.lock().unwrap().push(Instant::now());
+
+        match self.replies.lock().unwrap().pop_front().flatten() {
+            Some(retry_after) => {
+                ResponseTemplate::new(429).insert_header("Retry-After", retry_after)
+            }
+            None => ResponseTemplate::new(200).set_body_json(
+                serde_json::json!({ "jsonrpc": "2.0", "result": body["params"], "id": body["id"] }),
+            ),
+        }
+    }
+}
+
+impl Throttling {
+    async fn serve(replies: impl IntoIterator<Item = Option<&'static str>>) -> (MockServer, Self) {
+        let throttling = Throttling::default();
+        throttling.replies.lock().unwrap().extend(replies);
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .respond_with(throttling.clone())
+            .mount(&server)
+            .await;
+        (server, throttling)
+    }
+
+    fn arrivals(&self) -> Vec<Instant> {
+        self.arrivals.lock().unwrap().clone()
+    }
+}
+
+fn client(server: &MockServer) -> QuotaClientBuilder {
+    QuotaClientBuilder::new().server_url(format!("{}/rpc", server.uri()))
+}
+
+#[tokio::test]
+async fn rate_limited_calls_wait_for_retry_after() {
+    let (server, throttling) = Throttling::serve([Some("1"), None]).await;
+    let client = client(&server).build().unwrap();
+
+    // Not IDEMPOTENT: rate-limited requests never reached the handler
+    assert_eq!(client.spend(3).await.unwrap(), 3);
+
+    let arrivals = throttling.arrivals();
+    assert_eq!(arrivals.len(), 2);
+    assert!(arrivals[1] - arrivals[0] >= Duration::from_millis(950));
+}
+
+#[tokio::test]
+async fn rate_limited_retries_are_bounded() {
+    let (server, throttling) = Throttling::serve([Some("0"); 5]).await;
+    let client = client(&server).build().unwrap();
+
+    assert!(client.spend(1).await.is_err());
+    assert_eq!(
+        throttling.arrivals().len() as u32,
+        ras_jsonrpc_types::DEFAULT_RATE_LIMITED_ATTEMPTS
+    );
+}
+
+#[tokio::test]
+async fn long_retry_after_is_returned_to_the_caller() {
+    let (server, throttling) = Throttling::serve([Some("3600"), None]).await;
+    let client = client(&server).build().unwrap();
+
+    let start = Instant::now();
+    let error = client.spend(1).await.unwrap_err();
+
+    assert!(error.to_string().contains("429"), "{error}");
+    assert_eq!(throttling.arrivals().len(), 1);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn clones_and_clients_sharing_a_limiter_share_its_budget() {
+    let (server, throttling) = Throttling::serve([None; 6]).await;
+    let limiter = RateLimiter::new(20);
+    let first = client(&server)
+        .with_rate_limiter(limiter.clone())
+        .build()
+        .unwrap();
+    let second = client(&server).with_rate_limiter(limiter).build().unwrap();
+    let first_clone = first.clone();
+
+    let calls = (0..2).map(|_| first.spend(1));
+    let clone_calls = (0..2).map(|_| first_clone.spend(1));
+    let second_calls = (0..2).map(|_| second.spend(1));
+    let results = futures::future::join_all(calls.chain(clone_calls).chain(second_calls)).await;
+    assert!(results.iter().all(Result::is_ok));
+
+    // Six requests at 50ms intervals span at least 250ms
+    let arrivals = throttling.arrivals();
+    let span = *arrivals.iter().max().unwrap() - *arrivals.iter().min().unwrap();
+    assert!(span >= Duration::from_millis(240), "{span:?}");
+}
@@ -0,0 +1,142 @@
+//! Caller deadlines sent with `X-Request-Deadline`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use ras_jsonrpc_core::{REQUEST_DEADLINE_HEADER, error_codes, remaining_budget};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::spawn_http;
+
+jsonrpc_service!({
+    service_name: Reports,
+    methods: [
+        UNAUTHORIZED render(u64) -> u64,
+        UNAUTHORIZED budget(()) -> Option<u64>,
+        UNAUTHORIZED TIMEOUT(200ms) clamped_budget(()) -> Option<u64>,
+    ]
+});
+
+#[derive(Default)]
+struct ReportsImpl {
+    started: Arc<AtomicUsize>,
+    finished: Arc<AtomicUsize>,
+}
+
+impl ReportsTrait for ReportsImpl {
+    async fn render(&self, millis: u64) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        self.started.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        self.finished.fetch_add(1, Ordering::SeqCst);
+        Ok(millis)
+    }
+
+    async fn budget(&self, _: ()) -> Result<Option<u64>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(remaining_budget().map(|budget| budget.as_millis() as u64))
+    }
+
+    async fn clamped_budget(
+        &self,
+        _: (),
+    ) -> Result<Option<u64>, Box<dyn std::error::Error + Send + Sync>> {
+        self.budget(()).await
+    }
+}
+
+async fn call(
+    url: &str,
+    method: &str,
+    params: serde_json::Value,
+    deadline: &str,
+) -> serde_json::Value {
+    reqwest::Client::new()
+        .post(url)
+        .header(REQUEST_DEADLINE_HEADER, deadline)
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+fn serve(service: ReportsImpl) -> (axum_test::TestServer, String) {
+    let server = spawn_http(ReportsBuilder::new(service).build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+    (server, url)
+}
+
+#[tokio::test]
+async fn deadlines_expiring_mid_handler_cancel_it() {
+    let service = ReportsImpl::default();
+    let finished = service.finished.clone();
+    let (_server, url) = serve(service);
+
+    let start = Instant::now();
+    let body = call(&url, "render", serde_json::json!(2000), "100ms").await;
+    assert_eq!(body["error"]["code"], error_codes::REQUEST_TIMEOUT);
+    assert_eq!(body["error"]["data"]["timeout_ms"], 100);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(finished.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn passed_deadlines_fail_before_the_handler_starts() {
+    let service = ReportsImpl::default();
+    let started = service.started.clone();
+    let (_server, url) = serve(service);
+
+    let past = std::time::SystemTime::now() - Duration::from_secs(1);
+    let past = past
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let body = call(&url, "render", serde_json::json!(10), &past.to_string()).await;
+
+    assert_eq!(body["error"]["code"], error_codes::REQUEST_TIMEOUT);
+    assert_eq!(started.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn handlers_see_the_budget_clamped_by_the_method_timeout() {
+    let (_server, url) = serve(ReportsImpl::default());
+
+    let budget = call(&url, "budget", serde_json::Value::Null, "50ms").await["result"]
+        .as_u64()
+        .unwrap();
+    assert!(budget <= 50);
+
+    let budget = call(&url, "clamped_budget", serde_json::Value::Null, "60000ms").await["result"]
+        .as_u64()
+        .unwrap();
+    assert!(budget <= 200);
+
+    let body = call(&url, "render", serde_json::json!(10), "5000ms").await;
+    assert_eq!(body["result"], 10);
+}
+
+#[tokio::test]
+async fn generated_clients_send_their_remaining_timeout() {
+    let (_server, url) = serve(ReportsImpl::default());
+    let client = ReportsClientBuilder::new()
+        .server_url(url.clone())
+        .with_timeout(Duration::from_secs(2))
+        .build()
+        .unwrap();
+
+    let budget = client.budget(()).await.unwrap().unwrap();
+    assert!(budget > 1000 && budget <= 2000);
+
+    let client_without_timeout = ReportsClientBuilder::new().server_url(url).build().unwrap();
+    assert_eq!(client_without_timeout.budget(()).await.unwrap(), None);
+
+    let error = client
+        .render_with_timeout(2000, Duration::from_millis(100))
+        .await
+        .unwrap_err();
+    let error = error.downcast_ref::<ras_jsonrpc_core::JsonRpcError>();
+    assert!(error.is_none_or(|error| error.code == error_codes::REQUEST_TIMEOUT));
+}
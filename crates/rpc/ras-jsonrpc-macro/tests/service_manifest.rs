@@ -0,0 +1,120 @@
+//! Service manifests generated for JSON-RPC services.
+
+use ras_jsonrpc_core::{AuthMode, ServiceManifest};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+
+jsonrpc_service!({
+    service_name: Tasks,
+    methods: [
+        /// Check the service is up
+        UNAUTHORIZED health(()) -> String,
+        WITH_PERMISSIONS([]) list_tasks(()) -> Vec<String>,
+        #[deprecated(note = "use archive_task")]
+        WITH_PERMISSIONS(["admin"] | ["owner", "user"]) delete_task(u32) -> (),
+    ]
+});
+
+struct TasksImpl;
+
+impl TasksTrait for TasksImpl {
+    async fn health(&self, _: ()) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok("ok".to_string())
+    }
+
+    async fn list_tasks(
+        &self,
+        _: &ras_jsonrpc_core::AuthenticatedUser,
+        _: (),
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Vec::new())
+    }
+
+    async fn delete_task(
+        &self,
+        _: &ras_jsonrpc_core::AuthenticatedUser,
+        _: u32,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+}
+
+#[test]
+fn manifest_lists_every_method() {
+    let manifest = tasks_service_manifest();
+    assert_eq!(manifest.service_name, "Tasks");
+    assert_eq!(manifest.operations.len(), 3);
+
+    let health = manifest.operation("health").unwrap();
+    assert_eq!(health.auth, AuthMode::Unauthorized);
+    assert_eq!(health.docs.as_deref(), Some("Check the service is up"));
+    assert_eq!(health.request_type.as_deref(), Some("()"));
+    assert_eq!(health.response_type, "String");
+
+    let list_tasks = manifest.operation("list_tasks").unwrap();
+    assert_eq!(list_tasks.auth, AuthMode::Authenticated);
+    assert_eq!(list_tasks.response_type, "Vec<String>");
+
+    let delete_task = manifest.operation("delete_task").unwrap();
+    assert_eq!(delete_task.auth, AuthMode::Permissions);
+    assert_eq!(
+        delete_task.permission_groups,
+        [
+            vec!["admin".to_string()],
+            vec!["owner".to_string(), "user".to_string()]
+        ]
+    );
+    assert!(delete_task.deprecated);
+}
+
+#[test]
+fn only_intended_methods_are_unauthorized() {
+    let unauthorized: Vec<String> = tasks_service_manifest()
+        .unauthorized_operations()
+        .map(|operation| operation.name.clone())
+        .collect();
+
+    assert_eq!(unauthorized, ["health"]);
+}
+
+#[tokio::test]
+async fn manifest_route_requires_the_permission() {
+    let router = TasksBuilder::new(TasksImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_manifest_route("admin")
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc/_manifest").unwrap();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(url.clone())
+        .bearer_auth("admin-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let manifest: ServiceManifest = response.json().await.unwrap();
+    assert_eq!(manifest, tasks_service_manifest());
+
+    let response = client
+        .get(url.clone())
+        .bearer_auth("user-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = client.get(url).send().await.unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn manifest_route_is_off_by_default() {
+    let server = spawn_http(TasksBuilder::new(TasksImpl).build().unwrap());
+    let url = server.server_url("/rpc/_manifest").unwrap();
+
+    let response = reqwest::Client::new().get(url).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+}
@@ -0,0 +1,717 @@
+//! Service metrics reported by the generated server.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ras_auth_core::{AuthProviderChain, StaticTokenAuthProvider};
+use ras_jsonrpc_core::{
+    AuthenticatedUser, MethodDurationTracker, Observability, ObservabilityBuilder, RequestContext,
+    RequestOutcome, ServiceMetrics, TrackerGuard, TrustedProxies, UsageTracker,
+};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{
+    CompletedRequest, MockAuthProvider, PayloadSize, RecordingMetrics, TrackerError, spawn_http,
+};
+
+jsonrpc_service!({
+    service_name: Ledger,
+    methods: [
+        WITH_PERMISSIONS(["admin"]) close_books(()) -> String,
+        UNAUTHORIZED balance(u32) -> u32,
+        UNAUTHORIZED fail(()) -> (),
+    ]
+});
+
+struct LedgerImpl;
+
+impl LedgerTrait for LedgerImpl {
+    async fn close_books(
+        &self,
+        _user: &AuthenticatedUser,
+        _request: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok("closed".to_string())
+    }
+
+    async fn balance(&self, account: u32) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(account * 10)
+    }
+
+    async fn fail(&self, _request: ()) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Err("ledger offline".into())
+    }
+}
+
+/// Reports to recording metrics, with trackers keeping the methods of the
+/// requests they are told about, and how completed ones ended
+#[derive(Default)]
+struct Recorded {
+    metrics: RecordingMetrics,
+    seen: Arc<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    started: Mutex<Vec<String>>,
+    completed: Mutex<Vec<(String, RequestOutcome)>>,
+    contexts: Mutex<Vec<RequestContext>>,
+}
+
+#[async_trait::async_trait]
+impl UsageTracker for Seen {
+    async fn track_request(
+        &self,
+        _headers: &axum::http::HeaderMap,
+        _user: Option<&AuthenticatedUser>,
+        context: &RequestContext,
+    ) {
+        self.started.lock().unwrap().push(context.method.clone());
+        self.contexts.lock().unwrap().push(context.clone());
+    }
+}
+
+#[async_trait::async_trait]
+impl MethodDurationTracker for Seen {
+    async fn track_duration(
+        &self,
+        _context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: std::time::Duration,
+    ) {
+        unreachable!("generated services report completions");
+    }
+
+    async fn track_completion(
+        &self,
+        context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: std::time::Duration,
+        outcome: RequestOutcome,
+    ) {
+        self.completed
+            .lock()
+            .unwrap()
+            .push((context.method.clone(), outcome));
+        self.contexts.lock().unwrap().push(context.clone());
+    }
+}
+
+impl Observability for Recorded {
+    fn service_metrics(&self) -> Arc<dyn ServiceMetrics> {
+        Arc::new(self.metrics.clone())
+    }
+
+    fn usage_tracker(&self) -> Option<Arc<dyn UsageTracker>> {
+        Some(self.seen.clone())
+    }
+
+    fn method_duration_tracker(&self) -> Option<Arc<dyn MethodDurationTracker>> {
+        Some(self.seen.clone())
+    }
+}
+
+async fn call(url: &str, method: &str, params: serde_json::Value) {
+    call_as(url, Some("user-token"), method, params).await;
+}
+
+async fn call_as(url: &str, token: Option<&str>, method: &str, params: serde_json::Value) {
+    let mut request = reqwest::Client::new().post(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 }))
+        .send()
+        .await
+        .unwrap();
+}
+
+fn completed(method: &str, error_kind: Option<&str>) -> CompletedRequest {
+    CompletedRequest {
+        method: method.to_string(),
+        success: error_kind.is_none(),
+        error_kind: error_kind.map(str::to_string),
+    }
+}
+
+#[tokio::test]
+async fn requests_are_reported_with_their_outcome() {
+    let metrics = RecordingMetrics::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    call(&url, "balance", serde_json::json!(4)).await;
+    call(&url, "balance", serde_json::json!("four")).await;
+    call(&url, "close_books", serde_json::json!(null)).await;
+    call(&url, "fail", serde_json::json!(null)).await;
+    call(&url, "drop_tables", serde_json::json!(null)).await;
+
+    assert_eq!(
+        metrics.started(),
+        ["balance", "balance", "close_books", "fail", "unknown"]
+    );
+    assert_eq!(
+        metrics.completed(),
+        [
+            completed("balance", None),
+            completed("balance", Some("invalid_params")),
+            completed("close_books", Some("forbidden")),
+            completed("fail", Some("handler_error")),
+            completed("unknown", Some("method_not_found")),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn completed_requests_are_labelled_with_the_kind_of_caller() {
+    let metrics = RecordingMetrics::default();
+    let auth = AuthProviderChain::new()
+        .with(StaticTokenAuthProvider::new().with_token("cron-secret", "nightly-close", ["admin"]))
+        .with(MockAuthProvider::default());
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(auth)
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    call_as(
+        &url,
+        Some("cron-secret"),
+        "close_books",
+        serde_json::json!(null),
+    )
+    .await;
+    call_as(
+        &url,
+        Some("user-token"),
+        "close_books",
+        serde_json::json!(null),
+    )
+    .await;
+    call_as(&url, None, "close_books", serde_json::json!(null)).await;
+
+    assert_eq!(
+        metrics.principal_kinds(),
+        [
+            Some("service_account".to_string()),
+            Some("user".to_string()),
+            Some("anonymous".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn observability_installs_metrics_and_trackers() {
+    let observability = Recorded::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_observability(&observability)
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    call(&url, "balance", serde_json::json!(4)).await;
+    call(&url, "fail", serde_json::json!(null)).await;
+    call(&url, "balance", serde_json::json!("four")).await;
+    call(&url, "close_books", serde_json::json!(null)).await;
+
+    assert_eq!(
+        observability.metrics.completed(),
+        [
+            completed("balance", None),
+            completed("fail", Some("handler_error")),
+            completed("balance", Some("invalid_params")),
+            completed("close_books", Some("forbidden")),
+        ]
+    );
+    assert_eq!(
+        *observability.seen.started.lock().unwrap(),
+        ["balance", "fail", "balance", "close_books"]
+    );
+    assert_eq!(
+        *observability.seen.completed.lock().unwrap(),
+        [
+            ("balance".to_string(), RequestOutcome::Success),
+            ("fail".to_string(), RequestOutcome::HandlerError),
+            ("balance".to_string(), RequestOutcome::InvalidParams),
+            ("close_books".to_string(), RequestOutcome::AuthFailure),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn request_and_response_sizes_are_reported() {
+    let metrics = RecordingMetrics::default();
+    let seen = Arc::new(SizesSeen::default());
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_observability(&SizeObservability {
+            metrics: metrics.clone(),
+            seen: seen.clone(),
+        })
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap();
+
+    let request = r#"{"jsonrpc": "2.0", "method": "balance", "params": 4, "id": 1}"#;
+    let response = reqwest::Client::new()
+        .post(url.clone())
+        .header("Content-Type", "application/json")
+        .body(request)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let notification = r#"{"jsonrpc": "2.0", "method": "balance", "params": 4}"#;
+    reqwest::Client::new()
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(notification)
+        .send()
+        .await
+        .unwrap();
+
+    let size = |bytes| PayloadSize {
+        method: "balance".to_string(),
+        bytes,
+    };
+    assert_eq!(
+        metrics.request_sizes(),
+        [size(request.len()), size(notification.len())]
+    );
+    assert_eq!(metrics.response_sizes(), [size(response.len()), size(0)]);
+    // Usage trackers see the request size before the request is handled
+    assert_eq!(
+        *seen.0.lock().unwrap(),
+        [request.len().to_string(), notification.len().to_string()]
+    );
+}
+
+/// Service metrics, with a usage tracker keeping the `request_bytes` of the
+/// requests it is told about
+struct SizeObservability {
+    metrics: RecordingMetrics,
+    seen: Arc<SizesSeen>,
+}
+
+#[derive(Default)]
+struct SizesSeen(Mutex<Vec<String>>);
+
+#[async_trait::async_trait]
+impl UsageTracker for SizesSeen {
+    async fn track_request(
+        &self,
+        _headers: &axum::http::HeaderMap,
+        _user: Option<&AuthenticatedUser>,
+        context: &RequestContext,
+    ) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(context.metadata["request_bytes"].clone());
+    }
+}
+
+impl Observability for SizeObservability {
+    fn service_metrics(&self) -> Arc<dyn ServiceMetrics> {
+        Arc::new(self.metrics.clone())
+    }
+
+    fn usage_tracker(&self) -> Option<Arc<dyn UsageTracker>> {
+        Some(self.seen.clone())
+    }
+}
+
+/// A second service, in a module of its own since the generated server and client modules are module-level
+mod gauge {
+    use ras_jsonrpc_macro::jsonrpc_service;
+    use ras_test_helpers::RecordingMetrics;
+
+    jsonrpc_service!({
+        service_name: Gauge,
+        methods: [
+            UNAUTHORIZED in_flight(()) -> usize,
+        ]
+    });
+
+    /// Answers with the number of requests in flight while its handler runs
+    pub struct GaugeImpl {
+        pub metrics: RecordingMetrics,
+    }
+
+    impl GaugeTrait for GaugeImpl {
+        async fn in_flight(
+            &self,
+            _request: (),
+        ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(self.metrics.in_flight())
+        }
+    }
+}
+
+#[tokio::test]
+async fn requests_count_as_in_flight_while_their_handler_runs() {
+    use gauge::{GaugeBuilder, GaugeImpl};
+
+    let metrics = RecordingMetrics::default();
+    let router = GaugeBuilder::new(GaugeImpl {
+        metrics: metrics.clone(),
+    })
+    .with_service_metrics(Arc::new(metrics.clone()))
+    .build()
+    .unwrap();
+    let server = spawn_http(router);
+
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(server.server_url("/rpc").unwrap())
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": "in_flight", "params": null, "id": 1 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(response["result"], 1);
+    assert_eq!(metrics.in_flight(), 0);
+    assert_eq!(metrics.peak_in_flight(), 1);
+}
+
+/// A completion tracker that never finishes
+struct Hanging;
+
+#[async_trait::async_trait]
+impl MethodDurationTracker for Hanging {
+    async fn track_duration(
+        &self,
+        _context: &RequestContext,
+        _user: Option<&AuthenticatedUser>,
+        _duration: Duration,
+    ) {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+#[tokio::test]
+async fn failing_trackers_neither_fail_nor_stall_requests() {
+    let metrics = RecordingMetrics::default();
+    let guard = TrackerGuard::new().with_timeout(Duration::from_millis(50));
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .with_tracker_guard(guard.clone())
+        .with_usage_tracker(|_headers, _user, _request| async {
+            panic!("usage backend unavailable")
+        })
+        .with_method_duration_tracker(|_method, _user, _duration| -> std::future::Ready<()> {
+            panic!("duration backend unavailable")
+        })
+        .with_completion_tracker(Arc::new(Hanging))
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    let started = std::time::Instant::now();
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(&url)
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": "balance", "params": 4, "id": 1 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["result"], 40);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let error = |tracker: &str, error: &str| TrackerError {
+        tracker: tracker.to_string(),
+        error: error.to_string(),
+    };
+    assert_eq!(
+        metrics.tracker_errors(),
+        [
+            error("usage_tracker", "panic"),
+            error("method_duration_tracker", "panic"),
+            error("completion_tracker", "timeout"),
+        ]
+    );
+    let health = guard.health();
+    assert_eq!(health.failing(1).len(), 3);
+    assert!(health.is_healthy(2));
+}
+
+#[tokio::test]
+async fn contexts_carry_the_client_route_and_request_id() {
+    let observability = Recorded::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_observability(&observability)
+        .with_trusted_proxies(TrustedProxies::new(["10.0.0.0/8"]).unwrap())
+        .build()
+        .unwrap()
+        .layer(axum::extract::connect_info::MockConnectInfo(
+            std::net::SocketAddr::from(([10, 0, 0, 1], 4000)),
+        ));
+    let server = spawn_http(router);
+
+    reqwest::Client::new()
+        .post(server.server_url("/rpc").unwrap())
+        .header("x-forwarded-for", "198.51.100.7, 203.0.113.9")
+        .header("x-request-id", "req-7")
+        .json(&serde_json::json!([
+            { "jsonrpc": "2.0", "method": "balance", "params": 4, "id": 1 },
+            { "jsonrpc": "2.0", "method": "fail", "params": null, "id": 2 },
+        ]))
+        .send()
+        .await
+        .unwrap();
+
+    // Each batch entry is tracked on starting and completing
+    let contexts = observability.seen.contexts.lock().unwrap();
+    assert_eq!(contexts.len(), 4);
+    for context in contexts.iter() {
+        // 198.51.100.7 was forwarded by 203.0.113.9, which isn't trusted
+        assert_eq!(context.client_ip, Some("203.0.113.9".parse().unwrap()));
+        assert_eq!(context.route.as_deref(), Some("/rpc"));
+        assert_eq!(context.request_id.as_deref(), Some("req-7"));
+    }
+}
+
+#[tokio::test]
+async fn contexts_carry_the_extracted_tenant() {
+    let observability = Recorded::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_observability(&observability)
+        .with_tenant_extractor(|headers, user, _context| {
+            // The caller's tenant, once known, over the one the caller claims
+            user.map(|user| format!("tenant-of-{}", user.user_id))
+                .or_else(|| Some(headers.get("x-tenant")?.to_str().ok()?.to_string()))
+        })
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+
+    reqwest::Client::new()
+        .post(server.server_url("/rpc").unwrap())
+        .bearer_auth("admin-token")
+        .header("x-tenant", "acme")
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0", "method": "close_books", "params": null, "id": 1
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Started before the caller is authenticated
+    assert_eq!(
+        observability.metrics.tenants(),
+        [
+            Some("acme".to_string()),
+            Some("tenant-of-admin-1".to_string())
+        ]
+    );
+    let contexts = observability.seen.contexts.lock().unwrap();
+    assert_eq!(contexts.len(), 2);
+    assert_eq!(contexts[1].tenant(), Some("tenant-of-admin-1"));
+}
+
+#[tokio::test]
+async fn an_observability_config_drives_the_service() {
+    let metrics = RecordingMetrics::default();
+    let tracked = Arc::new(Mutex::new(Vec::new()));
+    let config = ObservabilityBuilder::new()
+        .with_service_metrics(Arc::new(metrics.clone()))
+        .with_usage_tracker({
+            let tracked = tracked.clone();
+            move |_headers, _user, context: RequestContext| {
+                tracked
+                    .lock()
+                    .unwrap()
+                    .push(format!("usage {}", context.method));
+                async {}
+            }
+        })
+        .with_method_duration_tracker({
+            let tracked = tracked.clone();
+            move |context: RequestContext, _user, _duration| {
+                tracked
+                    .lock()
+                    .unwrap()
+                    .push(format!("duration {}", context.method));
+                async {}
+            }
+        })
+        .with_tenant_extractor(|headers, _user, _context| {
+            Some(headers.get("x-tenant")?.to_str().ok()?.to_string())
+        });
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_observability_config(config)
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+
+    reqwest::Client::new()
+        .post(server.server_url("/rpc").unwrap())
+        .header("x-tenant", "acme")
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": "balance", "params": 1, "id": 1 }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(metrics.started(), ["balance"]);
+    assert_eq!(
+        metrics.tenants(),
+        [Some("acme".to_string()), Some("acme".to_string())]
+    );
+    assert_eq!(
+        *tracked.lock().unwrap(),
+        ["usage balance", "duration balance"]
+    );
+}
+
+#[tokio::test]
+async fn the_last_completion_tracker_set_wins() {
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    let outcome_tracker = {
+        let outcomes = outcomes.clone();
+        move |method: &str, _user: Option<&AuthenticatedUser>, _duration, success| {
+            outcomes.lock().unwrap().push((method.to_string(), success));
+            async {}
+        }
+    };
+
+    let replaced = Recorded::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_observability(&replaced)
+        .with_method_outcome_tracker(outcome_tracker.clone())
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    call(
+        server.server_url("/rpc").unwrap().as_str(),
+        "fail",
+        serde_json::json!(null),
+    )
+    .await;
+
+    // Only the completion tracker is replaced
+    assert_eq!(*outcomes.lock().unwrap(), [("fail".to_string(), false)]);
+    assert!(replaced.seen.completed.lock().unwrap().is_empty());
+    assert_eq!(*replaced.seen.started.lock().unwrap(), ["fail"]);
+    assert_eq!(
+        replaced.metrics.completed(),
+        [completed("fail", Some("handler_error"))]
+    );
+
+    let installed = Recorded::default();
+    let router = LedgerBuilder::new(LedgerImpl)
+        .with_method_outcome_tracker(outcome_tracker)
+        .with_observability(&installed)
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    call(
+        server.server_url("/rpc").unwrap().as_str(),
+        "fail",
+        serde_json::json!(null),
+    )
+    .await;
+
+    assert_eq!(outcomes.lock().unwrap().len(), 1);
+    assert_eq!(
+        *installed.seen.completed.lock().unwrap(),
+        [("fail".to_string(), RequestOutcome::HandlerError)]
+    );
+}
+
+#[tokio::test]
+async fn every_exit_is_reported_to_the_duration_and_outcome_trackers() {
+    let durations = Arc::new(Mutex::new(Vec::new()));
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    let router = LedgerBuilder::new(LedgerImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_method_duration_tracker({
+            let durations = durations.clone();
+            move |method, user: Option<&AuthenticatedUser>, _duration| {
+                let user = user.map(|user| user.user_id.clone());
+                durations.lock().unwrap().push((method.to_string(), user));
+                async {}
+            }
+        })
+        .with_method_outcome_tracker({
+            let outcomes = outcomes.clone();
+            move |method, _user, _duration, success| {
+                outcomes.lock().unwrap().push((method.to_string(), success));
+                async {}
+            }
+        })
+        .build()
+        .unwrap();
+    let server = spawn_http(router);
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    call_as(&url, None, "close_books", serde_json::json!(null)).await;
+    call_as(
+        &url,
+        Some("forged-token"),
+        "close_books",
+        serde_json::json!(null),
+    )
+    .await;
+    call(&url, "close_books", serde_json::json!(null)).await;
+    call(&url, "balance", serde_json::json!("four")).await;
+    call(&url, "fail", serde_json::json!(null)).await;
+    call_as(
+        &url,
+        Some("admin-token"),
+        "close_books",
+        serde_json::json!(null),
+    )
+    .await;
+    // Undeclared methods are not reported
+    call(&url, "drop_tables", serde_json::json!(null)).await;
+
+    let durations = durations.lock().unwrap();
+    let tracked: Vec<(&str, Option<&str>)> = durations
+        .iter()
+        .map(|(method, user)| (method.as_str(), user.as_deref()))
+        .collect();
+    assert_eq!(
+        tracked,
+        [
+            ("close_books", None),
+            ("close_books", None),
+            ("close_books", Some("user-1")),
+            ("balance", Some("user-1")),
+            ("fail", Some("user-1")),
+            ("close_books", Some("admin-1")),
+        ]
+    );
+    let outcomes = outcomes.lock().unwrap();
+    let outcomes: Vec<(&str, bool)> = outcomes
+        .iter()
+        .map(|(method, success)| (method.as_str(), *success))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            ("close_books", false),
+            ("close_books", false),
+            ("close_books", false),
+            ("balance", false),
+            ("fail", false),
+            ("close_books", true),
+        ]
+    );
+}
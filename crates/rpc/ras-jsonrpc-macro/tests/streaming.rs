@@ -0,0 +1,365 @@
+//! `STREAMING` methods answered with newline-delimited JSON frames.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use ras_jsonrpc_core::{ChunkStream, JsonRpcError, error_codes};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, spawn_http};
+use tokio::sync::Semaphore;
+
+jsonrpc_service!({
+    service_name: Agent,
+    openrpc: true,
+    methods: [
+        UNAUTHORIZED STREAMING count(u32) -> u32,
+        UNAUTHORIZED STREAMING fail_after(u32) -> u32,
+        UNAUTHORIZED STREAMING CONCURRENCY(1) MAX_REQUEST_SIZE(128) TIMEOUT(2s) follow(String) -> u32,
+        UNAUTHORIZED STREAMING TIMEOUT(50ms) watch(()) -> u32,
+        WITH_PERMISSIONS(["user"]) STREAMING complete(String) -> String,
+        UNAUTHORIZED ping(()) -> String,
+    ]
+});
+
+struct AgentImpl {
+    produced: Arc<AtomicUsize>,
+    // `follow` streams end once the test releases a permit
+    gate: Arc<Semaphore>,
+}
+
+impl Default for AgentImpl {
+    fn default() -> Self {
+        Self {
+            produced: Arc::default(),
+            gate: Arc::new(Semaphore::new(0)),
+        }
+    }
+}
+
+impl AgentTrait for AgentImpl {
+    fn count(&self, total: u32) -> ChunkStream<u32> {
+        let produced = self.produced.clone();
+        futures::stream::iter(0..total)
+            .map(move |n| {
+                produced.fetch_add(1, Ordering::SeqCst);
+                Ok(n)
+            })
+            .boxed()
+    }
+
+    fn fail_after(&self, total: u32) -> ChunkStream<u32> {
+        futures::stream::iter(0..=total)
+            .map(
+                move |n| -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+                    if n == total {
+                        Err("model crashed".into())
+                    } else {
+                        Ok(n)
+                    }
+                },
+            )
+            .boxed()
+    }
+
+    fn follow(&self, _topic: String) -> ChunkStream<u32> {
+        let gate = self.gate.clone();
+        futures::stream::once(async { Ok(1) })
+            .chain(futures::stream::once(async move {
+                gate.acquire().await?.forget();
+                Ok(2)
+            }))
+            .boxed()
+    }
+
+    fn watch(&self, _: ()) -> ChunkStream<u32> {
+        futures::stream::iter(0..)
+            .then(|n| async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(n)
+            })
+            .boxed()
+    }
+
+    fn complete(
+        &self,
+        user: &ras_jsonrpc_core::AuthenticatedUser,
+        prompt: String,
+    ) -> ChunkStream<String> {
+        let words = vec![user.user_id.clone(), prompt];
+        futures::stream::iter(words.into_iter().map(Ok))
+            .then(|word| async move {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                word
+            })
+            .boxed()
+    }
+
+    async fn ping(&self, _: ()) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok("pong".to_string())
+    }
+}
+
+fn serve(service: AgentImpl) -> (axum_test::TestServer, String) {
+    serve_builder(AgentBuilder::new(service))
+}
+
+fn serve_builder(builder: AgentBuilder<AgentImpl>) -> (axum_test::TestServer, String) {
+    let builder = builder.auth_provider(MockAuthProvider::default());
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+    (server, url)
+}
+
+fn client(url: &str, token: Option<&str>) -> AgentClient {
+    let mut client = AgentClientBuilder::new().server_url(url).build().unwrap();
+    client.set_bearer_token(token);
+    client
+}
+
+#[tokio::test]
+async fn results_arrive_as_ndjson_frames() {
+    let (_server, url) = serve(AgentImpl::default());
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "method": "count", "params": 2, "id": 9 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    let body = response.text().await.unwrap();
+    let frames: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        frames,
+        [
+            serde_json::json!({ "jsonrpc": "2.0", "method": "rpc.partial", "params": { "id": 9, "chunk": 0 } }),
+            serde_json::json!({ "jsonrpc": "2.0", "method": "rpc.partial", "params": { "id": 9, "chunk": 1 } }),
+            serde_json::json!({ "jsonrpc": "2.0", "result": null, "id": 9 }),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn clients_yield_chunks_and_stream_errors() {
+    let (_server, url) = serve(AgentImpl::default());
+    let client = client(&url, Some("user-token"));
+
+    let chunks: Vec<u32> = client.count(3).map(Result::unwrap).collect().await;
+    assert_eq!(chunks, [0, 1, 2]);
+
+    let words: Vec<String> = client
+        .complete("hello".to_string())
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert_eq!(words, ["user-1", "hello"]);
+
+    let results: Vec<_> = client.fail_after(2).collect().await;
+    assert_eq!(results.len(), 3);
+    assert_eq!(*results[1].as_ref().unwrap(), 1);
+    let error = results[2].as_ref().unwrap_err();
+    let error = error.downcast_ref::<JsonRpcError>().unwrap();
+    assert_eq!(error.code, error_codes::INTERNAL_ERROR);
+}
+
+#[tokio::test]
+async fn rejected_calls_yield_a_single_error() {
+    let (_server, url) = serve(AgentImpl::default());
+
+    let results: Vec<_> = client(&url, None)
+        .complete("hello".to_string())
+        .collect()
+        .await;
+    assert_eq!(results.len(), 1);
+    let error = results[0].as_ref().unwrap_err();
+    let error = error.downcast_ref::<JsonRpcError>().unwrap();
+    assert_eq!(error.code, error_codes::AUTHENTICATION_REQUIRED);
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .json(
+            &serde_json::json!({ "jsonrpc": "2.0", "method": "complete", "params": "hi", "id": 1 }),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn streaming_methods_cannot_be_batched() {
+    let (_server, url) = serve(AgentImpl::default());
+
+    let responses: serde_json::Value = reqwest::Client::new()
+        .post(&url)
+        .json(&serde_json::json!([
+            { "jsonrpc": "2.0", "method": "count", "params": 2, "id": 1 },
+            { "jsonrpc": "2.0", "method": "ping", "params": null, "id": 2 },
+        ]))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(responses[0]["error"]["code"], error_codes::INVALID_REQUEST);
+    assert_eq!(responses[1]["result"], "pong");
+}
+
+#[tokio::test]
+async fn slow_consumers_hold_back_the_handler() {
+    let service = AgentImpl::default();
+    let produced = service.produced.clone();
+    let (_server, url) = serve(service);
+    let total = 1_000_000;
+
+    let mut chunks = Box::pin(client(&url, None).count(total));
+    assert_eq!(chunks.next().await.unwrap().unwrap(), 0);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(produced.load(Ordering::SeqCst) < total as usize);
+}
+
+async fn post(url: &str, request: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(url)
+        .json(&request)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn follow_request(topic: &str, id: u32) -> serde_json::Value {
+    serde_json::json!({ "jsonrpc": "2.0", "method": "follow", "params": topic, "id": id })
+}
+
+#[tokio::test]
+async fn streams_hold_their_concurrency_slot_until_they_end() {
+    let service = AgentImpl::default();
+    let gate = service.gate.clone();
+    let builder = AgentBuilder::new(service);
+    let in_flight = builder.in_flight_requests();
+    let (_server, url) = serve_builder(builder);
+
+    let mut first = post(&url, follow_request("news", 1)).await;
+    assert_eq!(first.status(), 200);
+    let frame = first.chunk().await.unwrap().unwrap();
+    assert!(std::str::from_utf8(&frame).unwrap().contains("rpc.partial"));
+    assert_eq!(in_flight.get("follow"), 1);
+
+    let busy = post(&url, follow_request("news", 2)).await;
+    assert_eq!(busy.status(), 503);
+    let body: serde_json::Value = busy.json().await.unwrap();
+    assert_eq!(body["error"]["code"], error_codes::SERVER_BUSY);
+
+    gate.add_permits(1);
+    let rest = first.text().await.unwrap();
+    assert!(rest.lines().last().unwrap().contains(r#""result":null"#));
+    assert_eq!(in_flight.get("follow"), 0);
+
+    gate.add_permits(1);
+    let again = post(&url, follow_request("news", 3)).await;
+    assert_eq!(again.status(), 200);
+    assert_eq!(again.text().await.unwrap().lines().count(), 3);
+}
+
+#[tokio::test]
+async fn streams_over_their_request_size_are_refused() {
+    let (_server, url) = serve(AgentImpl::default());
+
+    let response = post(&url, follow_request(&"x".repeat(128), 1)).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/json; charset=utf-8"
+    );
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], error_codes::INVALID_REQUEST);
+    assert_eq!(body["error"]["message"], "Request too large");
+    assert_eq!(body["id"], 1);
+}
+
+#[tokio::test]
+async fn streams_running_past_their_timeout_end_with_an_error() {
+    let (_server, url) = serve(AgentImpl::default());
+
+    let body = post(
+        &url,
+        serde_json::json!({ "jsonrpc": "2.0", "method": "watch", "params": null, "id": 4 }),
+    )
+    .await
+    .text()
+    .await
+    .unwrap();
+    let last: serde_json::Value = serde_json::from_str(body.lines().last().unwrap()).unwrap();
+    assert_eq!(last["error"]["code"], error_codes::REQUEST_TIMEOUT);
+    assert_eq!(last["id"], 4);
+}
+
+#[tokio::test]
+async fn streams_are_reported_once_they_end() {
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    let sizes = Arc::new(Mutex::new(Vec::new()));
+    let builder = AgentBuilder::new(AgentImpl::default())
+        .with_method_outcome_tracker({
+            let outcomes = outcomes.clone();
+            move |method, _user, _duration, success| {
+                outcomes.lock().unwrap().push((method.to_string(), success));
+                async {}
+            }
+        })
+        .with_payload_size_tracker({
+            let sizes = sizes.clone();
+            move |method, _request_size, response_size| {
+                sizes
+                    .lock()
+                    .unwrap()
+                    .push((method.to_string(), response_size));
+                async {}
+            }
+        });
+    let (_server, url) = serve_builder(builder);
+
+    let body = post(
+        &url,
+        serde_json::json!({ "jsonrpc": "2.0", "method": "fail_after", "params": 1, "id": 1 }),
+    )
+    .await
+    .text()
+    .await
+    .unwrap();
+    post(&url, follow_request(&"x".repeat(128), 2)).await;
+
+    assert_eq!(
+        *outcomes.lock().unwrap(),
+        [
+            ("fail_after".to_string(), false),
+            ("follow".to_string(), false)
+        ]
+    );
+    assert_eq!(
+        sizes.lock().unwrap()[0],
+        ("fail_after".to_string(), body.len())
+    );
+}
+
+#[test]
+fn openrpc_marks_streaming_methods() {
+    let doc = generate_agent_openrpc();
+    let methods = doc["methods"].as_array().unwrap();
+    let method = |name: &str| methods.iter().find(|m| m["name"] == name).unwrap();
+
+    assert_eq!(
+        method("count")["x-ras-streaming"]["notification"],
+        "rpc.partial"
+    );
+    assert!(method("ping").get("x-ras-streaming").is_none());
+}
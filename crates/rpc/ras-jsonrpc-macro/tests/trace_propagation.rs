@@ -0,0 +1,111 @@
+//! W3C trace context propagation through chained generated clients and servers.
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::spawn_http;
+use tracing::Instrument;
+use tracing_subscriber::layer::SubscriberExt;
+
+jsonrpc_service!({
+    service_name: Inventory,
+    methods: [
+        UNAUTHORIZED stock_level(String) -> u32,
+    ]
+});
+
+jsonrpc_service!({
+    service_name: Storefront,
+    methods: [
+        UNAUTHORIZED check_item(String) -> bool,
+    ]
+});
+
+struct InventoryImpl;
+
+impl InventoryTrait for InventoryImpl {
+    async fn stock_level(
+        &self,
+        item: String,
+    ) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(if item == "widget" { 3 } else { 0 })
+    }
+}
+
+/// Answers by calling the inventory service through its generated client.
+struct StorefrontImpl {
+    inventory: InventoryClient,
+}
+
+impl StorefrontTrait for StorefrontImpl {
+    async fn check_item(
+        &self,
+        item: String,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.inventory.stock_level(item).await? > 0)
+    }
+}
+
+#[tokio::test]
+async fn chained_services_share_one_trace() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("trace-propagation")));
+    tracing::subscriber::set_global_default(subscriber).unwrap();
+    ras_observability_core::install_trace_context_propagator();
+
+    let inventory = spawn_http(
+        InventoryBuilder::new(InventoryImpl)
+            .with_tracing_spans(true)
+            .build()
+            .unwrap(),
+    );
+    let inventory_client = InventoryClientBuilder::new()
+        .server_url(inventory.server_url("/rpc").unwrap().to_string())
+        .build()
+        .unwrap();
+    let storefront = spawn_http(
+        StorefrontBuilder::new(StorefrontImpl {
+            inventory: inventory_client,
+        })
+        .with_tracing_spans(true)
+        .build()
+        .unwrap(),
+    );
+    let client = StorefrontClientBuilder::new()
+        .server_url(storefront.server_url("/rpc").unwrap().to_string())
+        .build()
+        .unwrap();
+
+    let in_stock = client
+        .check_item("widget".to_string())
+        .instrument(tracing::info_span!("checkout"))
+        .await
+        .unwrap();
+    assert!(in_stock);
+
+    provider.force_flush().unwrap();
+    let spans = exporter.get_finished_spans().unwrap();
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("missing span {name}"))
+    };
+    let checkout = span("checkout");
+    let check_item = span("check_item");
+    let stock_level = span("stock_level");
+
+    let trace_id = checkout.span_context.trace_id();
+    assert_eq!(check_item.span_context.trace_id(), trace_id);
+    assert_eq!(stock_level.span_context.trace_id(), trace_id);
+
+    assert_eq!(check_item.parent_span_id, checkout.span_context.span_id());
+    assert_eq!(
+        stock_level.parent_span_id,
+        check_item.span_context.span_id()
+    );
+}
@@ -0,0 +1,132 @@
+//! Request-scoped tracing spans around generated method dispatch.
+
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_test_helpers::{MockAuthProvider, capture_spans, spawn_http};
+
+jsonrpc_service!({
+    service_name: Traced,
+    methods: [
+        WITH_PERMISSIONS(["user"]) traced_greet(String) -> String,
+        WITH_PERMISSIONS(["admin"]) traced_admin_only(()) -> (),
+        UNAUTHORIZED traced_fail(()) -> (),
+        UNAUTHORIZED untraced_ping(()) -> String,
+    ]
+});
+
+struct TracedImpl;
+
+impl TracedTrait for TracedImpl {
+    async fn traced_greet(
+        &self,
+        _user: &ras_jsonrpc_core::AuthenticatedUser,
+        name: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(format!("hello {name}"))
+    }
+
+    async fn traced_admin_only(
+        &self,
+        _user: &ras_jsonrpc_core::AuthenticatedUser,
+        _req: (),
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+
+    async fn traced_fail(&self, _req: ()) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Err("boom".into())
+    }
+
+    async fn untraced_ping(
+        &self,
+        _req: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok("pong".to_string())
+    }
+}
+
+async fn call(url: &str, token: &str, method: &str, params: serde_json::Value, id: &str) {
+    reqwest::Client::new()
+        .post(url)
+        .bearer_auth(token)
+        .json(
+            &serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": id }),
+        )
+        .send()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn spans_record_user_permission_and_outcome() {
+    let spans = capture_spans();
+    let builder = TracedBuilder::new(TracedImpl)
+        .auth_provider(MockAuthProvider::default())
+        .with_tracing_spans(true);
+    let server = spawn_http(builder.build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    call(
+        &url,
+        "user-token",
+        "traced_greet",
+        serde_json::json!("ada"),
+        "req-1",
+    )
+    .await;
+    call(
+        &url,
+        "user-token",
+        "traced_admin_only",
+        serde_json::Value::Null,
+        "req-2",
+    )
+    .await;
+    call(
+        &url,
+        "user-token",
+        "traced_fail",
+        serde_json::Value::Null,
+        "req-3",
+    )
+    .await;
+
+    let greet = spans.spans_named("traced_greet").pop().expect("greet span");
+    assert_eq!(greet.field("protocol"), Some("jsonrpc"));
+    assert_eq!(greet.field("request_id"), Some("req-1"));
+    assert_eq!(greet.field("user_id"), Some("user-1"));
+    assert_eq!(greet.field("permission"), Some("granted"));
+    assert_eq!(greet.field("outcome"), Some("success"));
+    assert!(greet.field("duration_ms").is_some());
+    assert_eq!(greet.field("otel.status_code"), None);
+
+    let denied = spans
+        .spans_named("traced_admin_only")
+        .pop()
+        .expect("admin span");
+    assert_eq!(denied.field("permission"), Some("denied"));
+    assert_eq!(denied.field("outcome"), Some("error"));
+    assert_eq!(denied.field("otel.status_code"), Some("ERROR"));
+
+    let failed = spans.spans_named("traced_fail").pop().expect("fail span");
+    assert_eq!(failed.field("permission"), Some("not_required"));
+    assert_eq!(failed.field("error_code"), Some("-32603"));
+    assert_eq!(failed.field("otel.status_code"), Some("ERROR"));
+}
+
+#[tokio::test]
+async fn spans_are_disabled_by_default() {
+    let spans = capture_spans();
+    let server = spawn_http(TracedBuilder::new(TracedImpl).build().unwrap());
+    let url = server.server_url("/rpc").unwrap().to_string();
+
+    call(
+        &url,
+        "user-token",
+        "untraced_ping",
+        serde_json::Value::Null,
+        "req-4",
+    )
+    .await;
+
+    assert!(spans.spans_named("untraced_ping").is_empty());
+}
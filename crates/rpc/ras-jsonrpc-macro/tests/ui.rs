@@ -0,0 +1,7 @@
+//! Snapshots of the errors reported for invalid permission expressions
+
+#[test]
+fn invalid_permission_expressions_are_rejected() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,10 @@
+use ras_jsonrpc_macro::jsonrpc_service;
+
+jsonrpc_service!({
+    service_name: Tasks,
+    methods: [
+        WITH_PERMISSIONS(expression = "admin") delete_task(u64) -> (),
+    ]
+});
+
+fn main() {}
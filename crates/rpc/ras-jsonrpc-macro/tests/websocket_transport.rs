@@ -0,0 +1,133 @@
+//! The generated JSON-RPC client over a WebSocket transport.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::Router;
+use ras_jsonrpc_bidirectional_client::WebSocketRpcTransport;
+use ras_jsonrpc_bidirectional_server::jsonrpc_websocket_route;
+use ras_jsonrpc_macro::jsonrpc_service;
+use ras_jsonrpc_types::{JsonRpcError, error_codes};
+use ras_test_helpers::{MockAuthProvider, spawn_tcp};
+
+jsonrpc_service!({
+    service_name: Agents,
+    methods: [
+        UNAUTHORIZED echo(String) -> String,
+        UNAUTHORIZED sleep(u64) -> u64,
+        WITH_PERMISSIONS(["user"]) whoami(()) -> String,
+    ]
+});
+
+struct AgentsImpl;
+
+impl AgentsTrait for AgentsImpl {
+    async fn echo(
+        &self,
+        message: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(message)
+    }
+
+    async fn sleep(&self, millis: u64) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        Ok(millis)
+    }
+
+    async fn whoami(
+        &self,
+        user: &ras_jsonrpc_core::AuthenticatedUser,
+        _req: (),
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(user.user_id.clone())
+    }
+}
+
+async fn serve() -> Arc<WebSocketRpcTransport> {
+    let service = AgentsBuilder::new(AgentsImpl).auth_provider(MockAuthProvider::default());
+    let (addr, _server) =
+        spawn_tcp(Router::new().route("/ws", jsonrpc_websocket_route(service))).await;
+    Arc::new(WebSocketRpcTransport::new(format!("ws://{addr}/ws")))
+}
+
+fn client(transport: &Arc<WebSocketRpcTransport>) -> AgentsClient {
+    AgentsClientBuilder::new()
+        .with_transport(transport.clone())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn concurrent_calls_share_one_connection() {
+    let transport = serve().await;
+    let client = client(&transport);
+
+    let started = Instant::now();
+    let (slow, fast, echo) = tokio::join!(
+        client.sleep(300),
+        client.sleep(10),
+        client.echo("hello".to_string()),
+    );
+
+    assert_eq!(slow.unwrap(), 300);
+    assert_eq!(fast.unwrap(), 10);
+    assert_eq!(echo.unwrap(), "hello");
+    // Calls run side by side instead of one after another
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[tokio::test]
+async fn bearer_token_authenticates_the_connection() {
+    let transport = serve().await;
+    let mut client = client(&transport);
+
+    let error = client.whoami(()).await.unwrap_err();
+    let error = error
+        .downcast_ref::<JsonRpcError>()
+        .expect("JSON-RPC error");
+    assert_eq!(error.code, error_codes::AUTHENTICATION_REQUIRED);
+
+    client.set_bearer_token(Some("user-token"));
+    assert_eq!(client.whoami(()).await.unwrap(), "user-1");
+}
+
+#[tokio::test]
+async fn dropped_connection_fails_pending_calls_and_reconnects() {
+    let transport = serve().await;
+    let client = client(&transport);
+
+    let pending = tokio::spawn({
+        let client = client.clone();
+        async move { client.sleep(1_000).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(transport.pending_calls_count().await, 1);
+
+    transport.disconnect().await;
+    let result = tokio::time::timeout(Duration::from_millis(500), pending)
+        .await
+        .expect("pending call fails instead of hanging")
+        .unwrap();
+    assert!(result.is_err());
+
+    assert_eq!(client.echo("again".to_string()).await.unwrap(), "again");
+}
+
+#[tokio::test]
+async fn batches_and_timeouts_use_the_transport() {
+    let transport = serve().await;
+    let client = client(&transport);
+
+    let mut batch = client.batch();
+    let first = batch.echo("a".to_string());
+    let second = batch.sleep(5);
+    let results = batch.send().await.unwrap();
+    assert_eq!(results.get(&first).unwrap(), "a");
+    assert_eq!(results.get(&second).unwrap(), 5);
+
+    let result = client
+        .sleep_with_timeout(1_000, Duration::from_millis(50))
+        .await;
+    assert!(result.is_err());
+    assert_eq!(transport.pending_calls_count().await, 0);
+}
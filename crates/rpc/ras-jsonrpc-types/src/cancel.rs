@@ -0,0 +1,176 @@
+//! Cancellable calls of generated JSON-RPC clients.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::TransportFuture;
+
+type CallResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Error of a client call that was cancelled before it completed.
+///
+/// Generated clients return it boxed like their other errors; check for it
+/// with [`is_cancelled`] to ignore cancelled calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("request cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Whether a client error is a [`Cancelled`] call.
+pub fn is_cancelled(error: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    error.is::<Cancelled>()
+}
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: bool,
+    waker: Option<Waker>,
+}
+
+/// Cancels the call it was taken from; see [`CancellableCall::canceller`].
+#[derive(Clone)]
+pub struct Canceller(Arc<Mutex<CancelState>>);
+
+impl Canceller {
+    /// Abort the call. It completes with a [`Cancelled`] error if it is
+    /// still in flight.
+    pub fn cancel(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.cancelled = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Handle to an in-flight call of a generated client.
+///
+/// Await the handle for the call's result. Dropping it, or cancelling it with
+/// [`cancel`](Self::cancel) or a [`Canceller`], drops the HTTP request: on
+/// wasm the browser fetch is aborted through its `AbortController`, natively
+/// the connection is closed. A call cancelled before it completes resolves to
+/// a [`Cancelled`] error.
+pub struct CancellableCall<T> {
+    call: Option<TransportFuture<'static, CallResult<T>>>,
+    state: Arc<Mutex<CancelState>>,
+}
+
+impl<T> CancellableCall<T> {
+    /// Wrap the future of a client call.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(call: impl Future<Output = CallResult<T>> + Send + 'static) -> Self {
+        Self {
+            call: Some(Box::pin(call)),
+            state: Arc::default(),
+        }
+    }
+
+    /// Wrap the future of a client call.
+    #[cfg(target_arch = "wasm32")]
+    pub fn new(call: impl Future<Output = CallResult<T>> + 'static) -> Self {
+        Self {
+            call: Some(Box::pin(call)),
+            state: Arc::default(),
+        }
+    }
+
+    /// Abort the call.
+    pub fn cancel(&self) {
+        self.canceller().cancel();
+    }
+
+    /// A handle that aborts the call from elsewhere, e.g. when a component
+    /// unmounts while a spawned task awaits the call.
+    pub fn canceller(&self) -> Canceller {
+        Canceller(self.state.clone())
+    }
+}
+
+impl<T> Future for CancellableCall<T> {
+    type Output = CallResult<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.cancelled {
+                drop(state);
+                self.call = None;
+                return Poll::Ready(Err(Cancelled.into()));
+            }
+            state.waker = Some(cx.waker().clone());
+        }
+
+        match self.call.as_mut() {
+            Some(call) => {
+                let result = std::task::ready!(call.as_mut().poll(cx));
+                self.call = None;
+                Poll::Ready(result)
+            }
+            None => Poll::Ready(Err("call already completed".into())),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    struct SetOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn slow_call(dropped: Arc<AtomicBool>) -> CancellableCall<u32> {
+        let guard = SetOnDrop(dropped);
+        CancellableCall::new(async move {
+            let _guard = guard;
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(1)
+        })
+    }
+
+    #[tokio::test]
+    async fn completed_calls_return_their_result() {
+        let result = CancellableCall::new(async { Ok(7) }).await;
+        assert_eq!(result.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn cancelling_from_elsewhere_drops_the_call() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let call = slow_call(dropped.clone());
+        let canceller = call.canceller();
+
+        let task = tokio::spawn(call);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        canceller.cancel();
+
+        let error = task.await.unwrap().unwrap_err();
+        assert!(is_cancelled(error.as_ref()));
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn cancelled_before_polling_never_runs() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let call = slow_call(dropped.clone());
+        call.cancel();
+
+        let error = call.await.unwrap_err();
+        assert!(is_cancelled(error.as_ref()));
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+}
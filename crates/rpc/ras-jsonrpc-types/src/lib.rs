@@ -5,6 +5,43 @@
 
 use serde::{Deserialize, Serialize};
 
+mod cancel;
+mod retry;
+mod stream;
+mod transport;
+pub use cancel::{CancellableCall, Cancelled, Canceller, is_cancelled};
+pub use retry::{Backoff, RetryOn, RetryPolicy};
+pub use stream::{NDJSON_CONTENT_TYPE, PARTIAL_RESULT_METHOD, partial_result_frame};
+pub use transport::{CallOptions, ClientTransport, TransportError, TransportFuture};
+
+// What generated clients run on, with the `client` feature
+#[cfg(feature = "client")]
+pub use retry::retry_sleep;
+#[cfg(feature = "client")]
+pub use stream::streaming_call;
+
+// Rate limiting shared with the generated REST clients
+#[cfg(feature = "client")]
+pub use ras_client_core::{
+    DEFAULT_RATE_LIMITED_ATTEMPTS, MAX_RETRY_AFTER, RateLimiter, TOO_MANY_REQUESTS,
+    rate_limited_delay, retry_after, warn_rate_limited,
+};
+
+#[cfg(feature = "client")]
+pub use futures::Stream;
+
+// Re-exported so generated clients can build streams without a direct `futures` dependency.
+#[cfg(feature = "client")]
+#[doc(hidden)]
+pub use futures;
+
+/// Header carrying the time a caller is still willing to wait for a response.
+///
+/// Values are either relative, such as `1500ms`, or absolute unix timestamps in
+/// milliseconds. Generated clients send the relative form of their remaining
+/// timeout, so clock skew between client and server doesn't matter.
+pub const REQUEST_DEADLINE_HEADER: &str = "x-request-deadline";
+
 /// JSON-RPC 2.0 request structure.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRpcRequest {
@@ -56,6 +93,39 @@ pub struct JsonRpcError {
     pub data: Option<serde_json::Value>,
 }
 
+/// Typed handle to a call queued in a client-side batch request.
+///
+/// Generated clients hand these out when a call is added to a batch and use
+/// them to look up the correlated, deserialized result once the batch is sent.
+#[derive(Debug)]
+pub struct BatchCall<R> {
+    id: u64,
+    _response: std::marker::PhantomData<fn() -> R>,
+}
+
+impl<R> BatchCall<R> {
+    /// Creates a handle for the batch entry sent with the given request id.
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            _response: std::marker::PhantomData,
+        }
+    }
+
+    /// The request id used on the wire for this call.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<R> Clone for BatchCall<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for BatchCall<R> {}
+
 /// Standard JSON-RPC error codes.
 pub mod error_codes {
     /// Invalid JSON was received by the server.
@@ -81,6 +151,21 @@ pub mod error_codes {
 
     /// Token expired.
     pub const TOKEN_EXPIRED: i32 = -32003;
+
+    /// Token not yet valid.
+    pub const TOKEN_NOT_YET_VALID: i32 = -32004;
+
+    /// The server is at its concurrency limit for the request.
+    pub const SERVER_BUSY: i32 = -32005;
+
+    /// The account is locked.
+    pub const ACCOUNT_LOCKED: i32 = -32006;
+
+    /// The method did not finish within its timeout.
+    pub const REQUEST_TIMEOUT: i32 = -32008;
+
+    /// The client sent requests faster than its rate limit allows.
+    pub const RATE_LIMITED: i32 = -32029;
 }
 
 impl JsonRpcRequest {
@@ -177,7 +262,7 @@ impl JsonRpcError {
         Self::new(
             error_codes::AUTHENTICATION_REQUIRED,
             "Authentication required".to_string(),
-            None,
+            Some(serde_json::json!({ "code": "authentication_required" })),
         )
     }
 
@@ -187,6 +272,7 @@ impl JsonRpcError {
             error_codes::INSUFFICIENT_PERMISSIONS,
             "Insufficient permissions".to_string(),
             Some(serde_json::json!({
+                "code": "insufficient_permissions",
                 "required": required,
                 "has": has
             })),
@@ -198,9 +284,138 @@ impl JsonRpcError {
         Self::new(
             error_codes::TOKEN_EXPIRED,
             "Token expired".to_string(),
-            None,
+            Some(serde_json::json!({ "code": "token_expired" })),
+        )
+    }
+
+    /// Creates a token not yet valid error.
+    pub fn token_not_yet_valid() -> Self {
+        Self::new(
+            error_codes::TOKEN_NOT_YET_VALID,
+            "Token not yet valid".to_string(),
+            Some(serde_json::json!({ "code": "token_not_yet_valid" })),
+        )
+    }
+
+    /// Creates an account locked error, for an account unlocking after
+    /// `retry_after` if it unlocks by itself.
+    pub fn account_locked(retry_after: Option<std::time::Duration>) -> Self {
+        Self::new(
+            error_codes::ACCOUNT_LOCKED,
+            "Account locked".to_string(),
+            Some(serde_json::json!({
+                "code": "account_locked",
+                "retry_after_ms": retry_after.map(|retry_after| retry_after.as_millis() as u64)
+            })),
+        )
+    }
+
+    /// Creates a server busy error.
+    pub fn server_busy() -> Self {
+        Self::new(error_codes::SERVER_BUSY, "Server busy".to_string(), None)
+    }
+
+    /// Creates a rate limited error for a request refused until `retry_after` has passed.
+    pub fn rate_limited(retry_after: std::time::Duration) -> Self {
+        Self::new(
+            error_codes::RATE_LIMITED,
+            "Rate limited".to_string(),
+            Some(serde_json::json!({
+                "code": "rate_limited",
+                "retry_after_ms": retry_after.as_millis() as u64
+            })),
+        )
+    }
+
+    /// Creates an invalid request error for a request over the size limit.
+    ///
+    /// `size` is the request's size in bytes when it is known.
+    pub fn request_too_large(size: Option<usize>, max_size: usize) -> Self {
+        Self::new(
+            error_codes::INVALID_REQUEST,
+            "Request too large".to_string(),
+            Some(serde_json::json!({
+                "size": size,
+                "max_size": max_size
+            })),
+        )
+    }
+
+    /// Creates a request timeout error for a method cut off after `elapsed`.
+    pub fn request_timeout(elapsed: std::time::Duration, timeout: std::time::Duration) -> Self {
+        Self::new(
+            error_codes::REQUEST_TIMEOUT,
+            "Request timed out".to_string(),
+            Some(serde_json::json!({
+                "elapsed_ms": elapsed.as_millis() as u64,
+                "timeout_ms": timeout.as_millis() as u64
+            })),
+        )
+    }
+
+    /// Creates a parse error for a body that is not declared as JSON.
+    ///
+    /// `content_type` is the request's `Content-Type` header, if any.
+    pub fn unsupported_content_type(content_type: Option<&str>) -> Self {
+        Self::new(
+            error_codes::PARSE_ERROR,
+            "Unsupported Content-Type".to_string(),
+            Some(serde_json::json!({
+                "content_type": content_type,
+                "expected": "application/json"
+            })),
+        )
+    }
+
+    /// Creates a parse error for a body that is not valid UTF-8.
+    ///
+    /// `valid_up_to` is the byte offset of the first invalid sequence.
+    pub fn invalid_utf8(valid_up_to: usize) -> Self {
+        Self::new(
+            error_codes::PARSE_ERROR,
+            "Parse error".to_string(),
+            Some(serde_json::json!({
+                "reason": "body is not valid UTF-8",
+                "valid_up_to": valid_up_to
+            })),
         )
     }
+
+    /// Deserializes the error's `data` member into `T`.
+    ///
+    /// Returns `Ok(None)` when the error carries no data.
+    pub fn data_as<T: serde::de::DeserializeOwned>(&self) -> Result<Option<T>, serde_json::Error> {
+        self.data.clone().map(serde_json::from_value).transpose()
+    }
+
+    /// The machine-readable code of an authentication or authorization
+    /// error, such as `"token_expired"` or `"account_locked"`, from its `data`.
+    pub fn auth_code(&self) -> Option<&str> {
+        self.data.as_ref()?.get("code")?.as_str()
+    }
+}
+
+impl std::fmt::Display for JsonRpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JSON-RPC error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for JsonRpcError {}
+
+/// Trace context headers for an outgoing request, used by generated clients.
+///
+/// Carries the current span's W3C trace context when the `otel` feature is
+/// enabled and is empty otherwise.
+pub fn trace_context_headers() -> Vec<(String, String)> {
+    #[cfg(feature = "otel")]
+    {
+        ras_observability_core::trace_context_headers()
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        Vec::new()
+    }
 }
 
 #[cfg(test)]
@@ -256,6 +471,11 @@ mod tests {
             JsonRpcError::token_expired().code,
             error_codes::TOKEN_EXPIRED
         );
+        assert_eq!(
+            JsonRpcError::token_not_yet_valid().code,
+            error_codes::TOKEN_NOT_YET_VALID
+        );
+        assert_eq!(JsonRpcError::server_busy().code, error_codes::SERVER_BUSY);
     }
 
     #[test]
@@ -267,6 +487,94 @@ mod tests {
         assert_eq!(data["has"], serde_json::json!(["user"]));
     }
 
+    #[test]
+    fn auth_errors_carry_their_code() {
+        assert_eq!(
+            JsonRpcError::token_expired().auth_code(),
+            Some("token_expired")
+        );
+        assert_eq!(
+            JsonRpcError::insufficient_permissions(vec![], vec![]).auth_code(),
+            Some("insufficient_permissions")
+        );
+
+        let err = JsonRpcError::account_locked(Some(std::time::Duration::from_secs(2)));
+        assert_eq!(err.code, error_codes::ACCOUNT_LOCKED);
+        assert_eq!(err.auth_code(), Some("account_locked"));
+        assert_eq!(err.data.unwrap()["retry_after_ms"], 2000);
+
+        assert_eq!(JsonRpcError::internal_error("e".into()).auth_code(), None);
+    }
+
+    #[test]
+    fn request_too_large_carries_size_hint() {
+        let err = JsonRpcError::request_too_large(Some(2048), 1024);
+        assert_eq!(err.code, error_codes::INVALID_REQUEST);
+        let data = err.data.unwrap();
+        assert_eq!(data["size"], serde_json::json!(2048));
+        assert_eq!(data["max_size"], serde_json::json!(1024));
+    }
+
+    #[test]
+    fn request_timeout_carries_elapsed_time() {
+        let err = JsonRpcError::request_timeout(
+            std::time::Duration::from_millis(5_012),
+            std::time::Duration::from_secs(5),
+        );
+        assert_eq!(err.code, error_codes::REQUEST_TIMEOUT);
+        let data = err.data.unwrap();
+        assert_eq!(data["elapsed_ms"], serde_json::json!(5012));
+        assert_eq!(data["timeout_ms"], serde_json::json!(5000));
+    }
+
+    #[test]
+    fn rate_limited_carries_retry_after() {
+        let err = JsonRpcError::rate_limited(std::time::Duration::from_millis(250));
+        assert_eq!(err.code, error_codes::RATE_LIMITED);
+        assert_eq!(err.data.unwrap()["retry_after_ms"], serde_json::json!(250));
+    }
+
+    #[test]
+    fn content_type_and_utf8_errors_are_parse_errors() {
+        let err = JsonRpcError::unsupported_content_type(Some("text/plain"));
+        assert_eq!(err.code, error_codes::PARSE_ERROR);
+        assert_eq!(err.data.unwrap()["content_type"], "text/plain");
+
+        let err = JsonRpcError::invalid_utf8(7);
+        assert_eq!(err.code, error_codes::PARSE_ERROR);
+        assert_eq!(err.data.unwrap()["valid_up_to"], 7);
+    }
+
+    #[test]
+    fn error_data_deserializes_into_caller_type() {
+        #[derive(serde::Deserialize)]
+        struct Perms {
+            required: Vec<String>,
+        }
+
+        let err = JsonRpcError::insufficient_permissions(vec!["admin".into()], vec![]);
+        let perms: Perms = err.data_as().unwrap().expect("permission data");
+        assert_eq!(perms.required, vec!["admin".to_string()]);
+        assert!(
+            JsonRpcError::parse_error()
+                .data_as::<Perms>()
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            err.to_string(),
+            "JSON-RPC error -32002: Insufficient permissions"
+        );
+    }
+
+    #[test]
+    fn batch_call_is_copy_and_keeps_id() {
+        let call: BatchCall<String> = BatchCall::new(7);
+        let copy = call;
+        assert_eq!(call.id(), 7);
+        assert_eq!(copy.id(), 7);
+    }
+
     #[test]
     fn request_with_no_id_skips_field_in_serialization() {
         let req = JsonRpcRequest::new("notify".into(), None, None);
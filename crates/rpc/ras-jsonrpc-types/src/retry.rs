@@ -0,0 +1,132 @@
+//! Retry policies for generated JSON-RPC clients.
+
+use std::time::Duration;
+
+use crate::error_codes;
+
+/// A failure a client may retry a call after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOn {
+    /// The request could not be sent or its response was lost in transit.
+    ConnectionError,
+    /// The server answered with this HTTP status, e.g. 502 or 503.
+    HttpStatus(u16),
+    /// The server answered with a JSON-RPC error with this code, e.g.
+    /// [`error_codes::SERVER_BUSY`].
+    ErrorCode(i32),
+}
+
+/// How long a client waits before each retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// Wait the same time before every retry.
+    Fixed(Duration),
+    /// Wait `initial` before the first retry and double the wait for every
+    /// following one, up to `max`.
+    Exponential {
+        /// Wait before the first retry.
+        initial: Duration,
+        /// Longest wait between two attempts.
+        max: Duration,
+    },
+}
+
+impl Backoff {
+    /// The wait before retry number `retry`, counting from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+                initial.saturating_mul(factor).min(max)
+            }
+        }
+    }
+}
+
+/// When and how often a generated client retries a failed call.
+///
+/// Only methods declared `IDEMPOTENT` are retried unless the client builder
+/// opts every method in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts per call, including the first one.
+    pub max_attempts: u32,
+    /// Wait between attempts.
+    pub backoff: Backoff,
+    /// Failures that are retried; any other failure is returned immediately.
+    pub retry_on: Vec<RetryOn>,
+}
+
+impl RetryPolicy {
+    /// Whether an attempt that failed with any of `failures` is retried.
+    pub fn should_retry(&self, failures: &[RetryOn]) -> bool {
+        failures
+            .iter()
+            .any(|failure| self.retry_on.contains(failure))
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts with exponential backoff from 100ms, retrying
+    /// connection errors, HTTP 502/503 and `server_busy` errors.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(100),
+                max: Duration::from_secs(2),
+            },
+            retry_on: vec![
+                RetryOn::ConnectionError,
+                RetryOn::HttpStatus(502),
+                RetryOn::HttpStatus(503),
+                RetryOn::ErrorCode(error_codes::SERVER_BUSY),
+            ],
+        }
+    }
+}
+
+/// Wait before a retry, used by generated clients.
+#[cfg(feature = "client")]
+pub async fn retry_sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(duration).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_backoff_doubles_up_to_max() {
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(350),
+        };
+        let delays: Vec<_> = (1..=4).map(|retry| backoff.delay(retry)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 350, 350].map(Duration::from_millis).to_vec()
+        );
+        assert_eq!(
+            Backoff::Fixed(Duration::from_millis(5)).delay(9),
+            Duration::from_millis(5)
+        );
+    }
+
+    #[test]
+    fn default_policy_retries_transient_failures_only() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry(&[RetryOn::ConnectionError]));
+        assert!(policy.should_retry(&[
+            RetryOn::HttpStatus(503),
+            RetryOn::ErrorCode(error_codes::SERVER_BUSY)
+        ]));
+        assert!(!policy.should_retry(&[RetryOn::HttpStatus(500)]));
+        assert!(!policy.should_retry(&[RetryOn::ErrorCode(error_codes::INVALID_PARAMS)]));
+        assert!(!policy.should_retry(&[]));
+    }
+}
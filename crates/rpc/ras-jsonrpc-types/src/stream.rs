@@ -0,0 +1,219 @@
+//! Streamed results of `STREAMING` JSON-RPC methods.
+//!
+//! A streaming call is answered with newline-delimited JSON: one
+//! [`PARTIAL_RESULT_METHOD`] notification per chunk, carrying the call's id,
+//! followed by a final response object with a `null` result or an error.
+
+use crate::JsonRpcRequest;
+
+#[cfg(feature = "client")]
+pub use client::streaming_call;
+
+/// Method name of the notifications carrying the chunks of a streamed result.
+pub const PARTIAL_RESULT_METHOD: &str = "rpc.partial";
+
+/// `Content-Type` of streamed responses.
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Frame carrying one chunk of the result of the call with `id`.
+pub fn partial_result_frame(
+    id: Option<serde_json::Value>,
+    chunk: serde_json::Value,
+) -> JsonRpcRequest {
+    JsonRpcRequest::new(
+        PARTIAL_RESULT_METHOD.to_string(),
+        Some(serde_json::json!({ "id": id, "chunk": chunk })),
+        None,
+    )
+}
+
+/// Reading streamed responses, for generated clients
+#[cfg(feature = "client")]
+mod client {
+    use std::future::Future;
+    use std::pin::Pin;
+
+    use futures::{Stream, StreamExt};
+    use serde::de::DeserializeOwned;
+
+    use super::PARTIAL_RESULT_METHOD;
+    use crate::JsonRpcResponse;
+
+    type CallResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+    enum State<F, S> {
+        Opening(F),
+        Reading {
+            body: Pin<Box<S>>,
+            buffer: Vec<u8>,
+            ended: bool,
+        },
+        Done,
+    }
+
+    enum Frame<T> {
+        Chunk(CallResult<T>),
+        End(Option<Box<dyn std::error::Error + Send + Sync>>),
+    }
+
+    /// Decode the streamed response of a call into its chunks.
+    ///
+    /// `open` sends the request and resolves to the response body. The body is
+    /// only read as the returned stream is polled, so a slow consumer slows the
+    /// server down instead of buffering chunks. The stream ends after the final
+    /// response, yielding its error if it carries one.
+    pub fn streaming_call<T, F, S, B>(open: F) -> impl Stream<Item = CallResult<T>>
+    where
+        T: DeserializeOwned,
+        F: Future<Output = CallResult<S>>,
+        S: Stream<Item = CallResult<B>>,
+        B: AsRef<[u8]>,
+    {
+        futures::stream::unfold(State::Opening(open), |state| async move {
+            let (mut body, mut buffer, mut ended) = match state {
+                State::Opening(open) => match open.await {
+                    Ok(body) => (Box::pin(body), Vec::new(), false),
+                    Err(error) => return Some((Err(error), State::Done)),
+                },
+                State::Reading {
+                    body,
+                    buffer,
+                    ended,
+                } => (body, buffer, ended),
+                State::Done => return None,
+            };
+
+            loop {
+                if let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    match decode_frame(&line) {
+                        None => continue,
+                        Some(Frame::Chunk(Ok(chunk))) => {
+                            let state = State::Reading {
+                                body,
+                                buffer,
+                                ended,
+                            };
+                            return Some((Ok(chunk), state));
+                        }
+                        Some(Frame::Chunk(Err(error))) | Some(Frame::End(Some(error))) => {
+                            return Some((Err(error), State::Done));
+                        }
+                        Some(Frame::End(None)) => return None,
+                    }
+                }
+
+                if ended {
+                    // The last frame may come without a trailing newline
+                    if buffer.iter().any(|byte| !byte.is_ascii_whitespace()) {
+                        buffer.push(b'\n');
+                        continue;
+                    }
+                    let error: Box<dyn std::error::Error + Send + Sync> =
+                        "Stream ended without a final response".into();
+                    return Some((Err(error), State::Done));
+                }
+
+                match body.next().await {
+                    Some(Ok(bytes)) => buffer.extend_from_slice(bytes.as_ref()),
+                    Some(Err(error)) => return Some((Err(error), State::Done)),
+                    None => ended = true,
+                }
+            }
+        })
+    }
+
+    /// Decode one line of a streamed response; blank lines give `None`.
+    fn decode_frame<T: DeserializeOwned>(line: &[u8]) -> Option<Frame<T>> {
+        if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+            return None;
+        }
+
+        let frame: serde_json::Value = match serde_json::from_slice(line) {
+            Ok(frame) => frame,
+            Err(error) => return Some(Frame::End(Some(error.into()))),
+        };
+
+        if frame.get("method").and_then(|method| method.as_str()) == Some(PARTIAL_RESULT_METHOD) {
+            let chunk = frame
+                .get("params")
+                .and_then(|params| params.get("chunk"))
+                .cloned()
+                .unwrap_or_default();
+            return Some(Frame::Chunk(
+                serde_json::from_value(chunk).map_err(Into::into),
+            ));
+        }
+
+        match serde_json::from_value::<JsonRpcResponse>(frame) {
+            Ok(response) => Some(Frame::End(response.error.map(Into::into))),
+            Err(error) => Some(Frame::End(Some(error.into()))),
+        }
+    }
+
+    #[cfg(all(test, not(target_arch = "wasm32")))]
+    mod tests {
+        use super::*;
+        use crate::stream::partial_result_frame;
+        use crate::{JsonRpcError, error_codes};
+
+        async fn decode(parts: &[&str]) -> Vec<CallResult<u32>> {
+            let parts: Vec<CallResult<Vec<u8>>> = parts
+                .iter()
+                .map(|part| Ok(part.as_bytes().to_vec()))
+                .collect();
+            streaming_call(async { Ok(futures::stream::iter(parts)) })
+                .collect()
+                .await
+        }
+
+        fn line(frame: impl serde::Serialize) -> String {
+            format!("{}\n", serde_json::to_string(&frame).unwrap())
+        }
+
+        #[tokio::test]
+        async fn chunks_split_across_reads_are_reassembled() {
+            let id = Some(serde_json::json!(1));
+            let frames = [
+                line(partial_result_frame(id.clone(), serde_json::json!(1))),
+                line(partial_result_frame(id.clone(), serde_json::json!(2))),
+                line(JsonRpcResponse::success(serde_json::Value::Null, id)),
+            ]
+            .concat();
+            let (head, tail) = frames.split_at(30);
+
+            let chunks = decode(&[head, tail]).await;
+            let chunks: Vec<u32> = chunks.into_iter().map(Result::unwrap).collect();
+            assert_eq!(chunks, [1, 2]);
+        }
+
+        #[tokio::test]
+        async fn error_frames_end_the_stream() {
+            let id = Some(serde_json::json!(1));
+            let frames = [
+                line(partial_result_frame(id.clone(), serde_json::json!(1))),
+                line(JsonRpcResponse::error(
+                    JsonRpcError::internal_error("boom".into()),
+                    id,
+                )),
+            ]
+            .concat();
+
+            let mut chunks = decode(&[&frames]).await.into_iter();
+            assert_eq!(chunks.next().unwrap().unwrap(), 1);
+            let error = chunks.next().unwrap().unwrap_err();
+            let error = error.downcast_ref::<JsonRpcError>().unwrap();
+            assert_eq!(error.code, error_codes::INTERNAL_ERROR);
+            assert!(chunks.next().is_none());
+        }
+
+        #[tokio::test]
+        async fn truncated_streams_are_errors() {
+            let frame = line(partial_result_frame(None, serde_json::json!(1)));
+
+            let chunks = decode(&[&frame]).await;
+            assert_eq!(chunks.len(), 2);
+            assert!(chunks[1].is_err());
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Pluggable transports for generated JSON-RPC clients.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{JsonRpcRequest, JsonRpcResponse, RetryOn};
+
+/// Future returned by [`ClientTransport`] methods.
+#[cfg(not(target_arch = "wasm32"))]
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Future returned by [`ClientTransport`] methods.
+#[cfg(target_arch = "wasm32")]
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Per-call settings a generated client hands to its transport.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CallOptions<'a> {
+    /// The client's bearer token, if one is set.
+    pub bearer_token: Option<&'a str>,
+    /// Time left for the call, if it has a deadline.
+    pub timeout: Option<Duration>,
+}
+
+/// A request that did not produce a JSON-RPC response.
+#[derive(Debug)]
+pub struct TransportError {
+    /// What went wrong.
+    pub error: Box<dyn std::error::Error + Send + Sync>,
+    /// The retryable conditions the failure matches.
+    pub retry_on: Vec<RetryOn>,
+}
+
+impl TransportError {
+    /// A failure that no retry policy applies to.
+    pub fn new(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self {
+            error: error.into(),
+            retry_on: Vec::new(),
+        }
+    }
+
+    /// A lost connection or response, retryable as [`RetryOn::ConnectionError`].
+    pub fn connection(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self {
+            error: error.into(),
+            retry_on: vec![RetryOn::ConnectionError],
+        }
+    }
+}
+
+/// Carries requests of a generated client to the server.
+///
+/// Generated clients post each request over HTTP unless the builder is given
+/// another transport with `with_transport`. JSON-RPC errors are returned as
+/// responses; `Err` is reserved for requests that got no response at all.
+pub trait ClientTransport: Send + Sync + 'static {
+    /// Send a request and wait for its response.
+    fn call<'a>(
+        &'a self,
+        request: JsonRpcRequest,
+        options: CallOptions<'a>,
+    ) -> TransportFuture<'a, Result<JsonRpcResponse, TransportError>>;
+
+    /// Send a notification, which gets no response.
+    fn notify<'a>(
+        &'a self,
+        request: JsonRpcRequest,
+        options: CallOptions<'a>,
+    ) -> TransportFuture<'a, Result<(), TransportError>>;
+
+    /// Send the requests of a batch and wait for all of their responses.
+    ///
+    /// Calls are sent one after another by default; transports that multiplex
+    /// calls override this to keep them all in flight at once.
+    fn batch<'a>(
+        &'a self,
+        requests: Vec<JsonRpcRequest>,
+        options: CallOptions<'a>,
+    ) -> TransportFuture<'a, Result<Vec<JsonRpcResponse>, TransportError>> {
+        Box::pin(async move {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.push(self.call(request, options).await?);
+            }
+            Ok(responses)
+        })
+    }
+}
+
+/// A shared transport, e.g. one connection used by several clients.
+impl<T: ClientTransport> ClientTransport for Arc<T> {
+    fn call<'a>(
+        &'a self,
+        request: JsonRpcRequest,
+        options: CallOptions<'a>,
+    ) -> TransportFuture<'a, Result<JsonRpcResponse, TransportError>> {
+        (**self).call(request, options)
+    }
+
+    fn notify<'a>(
+        &'a self,
+        request: JsonRpcRequest,
+        options: CallOptions<'a>,
+    ) -> TransportFuture<'a, Result<(), TransportError>> {
+        (**self).notify(request, options)
+    }
+
+    fn batch<'a>(
+        &'a self,
+        requests: Vec<JsonRpcRequest>,
+        options: CallOptions<'a>,
+    ) -> TransportFuture<'a, Result<Vec<JsonRpcResponse>, TransportError>> {
+        (**self).batch(requests, options)
+    }
+}
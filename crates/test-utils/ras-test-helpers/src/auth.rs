@@ -1,6 +1,6 @@
 use std::collections::{HashMap, HashSet};
 
-use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 
 /// A small fixed-token auth provider for tests.
 ///
@@ -65,6 +65,7 @@ pub fn mock_user(user_id: &str, perms: &[&str]) -> AuthenticatedUser {
             .map(|p| (*p).to_string())
             .collect::<HashSet<_>>(),
         metadata: None,
+        kind: PrincipalKind::User,
     }
 }
 
@@ -5,7 +5,11 @@
 //! providers and server-spawn boilerplate across crates.
 
 mod auth;
+mod metrics;
 mod server;
+mod spans;
 
 pub use auth::{MockAuthProvider, mock_user};
+pub use metrics::{CompletedRequest, Fanout, PayloadSize, RecordingMetrics, TrackerError};
 pub use server::{spawn_http, spawn_tcp};
+pub use spans::{CapturedSpan, SpanCapture, capture_spans};
@@ -0,0 +1,303 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ras_observability_core::{InFlightGuard, RequestContext, ServiceMetrics};
+
+/// A request completion reported to [`RecordingMetrics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedRequest {
+    pub method: String,
+    pub success: bool,
+    pub error_kind: Option<String>,
+}
+
+/// A broadcast fan-out reported to [`RecordingMetrics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fanout {
+    pub method: String,
+    pub recipients: usize,
+}
+
+/// A request or response body size reported to [`RecordingMetrics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadSize {
+    pub method: String,
+    pub bytes: usize,
+}
+
+/// A failed tracker call reported to [`RecordingMetrics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackerError {
+    pub tracker: String,
+    pub error: String,
+}
+
+/// Close code and reason of a closed connection
+type ClosedConnection = (Option<u16>, String);
+
+/// Reason and count of sessions ended together
+type EndedSessions = (String, usize);
+
+/// Requests currently in flight, and the most there have been at once
+#[derive(Default)]
+struct InFlight {
+    current: usize,
+    peak: usize,
+}
+
+/// `ServiceMetrics` that records requests and connection activity in memory.
+///
+/// Clones share the same records, so keep one handle and pass another to the
+/// service builder (as `Arc::new(metrics.clone())`).
+#[derive(Clone, Default)]
+pub struct RecordingMetrics {
+    started: Arc<Mutex<Vec<String>>>,
+    completed: Arc<Mutex<Vec<CompletedRequest>>>,
+    principal_kinds: Arc<Mutex<Vec<Option<String>>>>,
+    tenants: Arc<Mutex<Vec<Option<String>>>>,
+    in_flight: Arc<Mutex<InFlight>>,
+    request_sizes: Arc<Mutex<Vec<PayloadSize>>>,
+    response_sizes: Arc<Mutex<Vec<PayloadSize>>>,
+    connections_opened: Arc<Mutex<usize>>,
+    connections_closed: Arc<Mutex<Vec<ClosedConnection>>>,
+    messages_received: Arc<Mutex<usize>>,
+    messages_sent: Arc<Mutex<usize>>,
+    fanouts: Arc<Mutex<Vec<Fanout>>>,
+    sessions_started: Arc<Mutex<usize>>,
+    sessions_ended: Arc<Mutex<Vec<EndedSessions>>>,
+    active_sessions: Arc<Mutex<Option<usize>>>,
+    slow_requests: Arc<Mutex<Vec<String>>>,
+    tracker_errors: Arc<Mutex<Vec<TrackerError>>>,
+    permission_overlay_hits: Arc<Mutex<usize>>,
+    permission_overlay_denials: Arc<Mutex<usize>>,
+}
+
+impl RecordingMetrics {
+    /// Methods of started requests, in order.
+    pub fn started(&self) -> Vec<String> {
+        self.started.lock().unwrap().clone()
+    }
+
+    /// Completed requests, in order.
+    pub fn completed(&self) -> Vec<CompletedRequest> {
+        self.completed.lock().unwrap().clone()
+    }
+
+    /// The `principal_kind` label of each completed request, in order.
+    pub fn principal_kinds(&self) -> Vec<Option<String>> {
+        self.principal_kinds.lock().unwrap().clone()
+    }
+
+    /// The tenant of each started and then each completed request, in the
+    /// order reported.
+    pub fn tenants(&self) -> Vec<Option<String>> {
+        self.tenants.lock().unwrap().clone()
+    }
+
+    /// Number of requests holding an in-flight guard.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.lock().unwrap().current
+    }
+
+    /// The most requests that have held an in-flight guard at once.
+    pub fn peak_in_flight(&self) -> usize {
+        self.in_flight.lock().unwrap().peak
+    }
+
+    /// Request body sizes, in order.
+    pub fn request_sizes(&self) -> Vec<PayloadSize> {
+        self.request_sizes.lock().unwrap().clone()
+    }
+
+    /// Response body sizes, in order.
+    pub fn response_sizes(&self) -> Vec<PayloadSize> {
+        self.response_sizes.lock().unwrap().clone()
+    }
+
+    /// Number of connections opened.
+    pub fn connections_opened(&self) -> usize {
+        *self.connections_opened.lock().unwrap()
+    }
+
+    /// Close codes of closed connections, in order.
+    pub fn connections_closed(&self) -> Vec<Option<u16>> {
+        let closed = self.connections_closed.lock().unwrap();
+        closed.iter().map(|(code, _)| *code).collect()
+    }
+
+    /// Close reasons of closed connections, such as `client_closed`, in order.
+    pub fn close_reasons(&self) -> Vec<String> {
+        let closed = self.connections_closed.lock().unwrap();
+        closed.iter().map(|(_, reason)| reason.clone()).collect()
+    }
+
+    /// Number of messages received from clients.
+    pub fn messages_received(&self) -> usize {
+        *self.messages_received.lock().unwrap()
+    }
+
+    /// Number of messages sent to clients.
+    pub fn messages_sent(&self) -> usize {
+        *self.messages_sent.lock().unwrap()
+    }
+
+    /// Broadcast fan-outs, in order.
+    pub fn fanouts(&self) -> Vec<Fanout> {
+        self.fanouts.lock().unwrap().clone()
+    }
+
+    /// Number of sessions started.
+    pub fn sessions_started(&self) -> usize {
+        *self.sessions_started.lock().unwrap()
+    }
+
+    /// Number of sessions ended for `reason`, such as `logout`.
+    pub fn sessions_ended(&self, reason: &str) -> usize {
+        let ended = self.sessions_ended.lock().unwrap();
+        ended
+            .iter()
+            .filter(|(ended_for, _)| ended_for == reason)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// The last active session count reported, if any.
+    pub fn active_sessions(&self) -> Option<usize> {
+        *self.active_sessions.lock().unwrap()
+    }
+
+    /// Methods of slow requests, in order.
+    pub fn slow_requests(&self) -> Vec<String> {
+        self.slow_requests.lock().unwrap().clone()
+    }
+
+    /// Failed tracker calls, in order.
+    pub fn tracker_errors(&self) -> Vec<TrackerError> {
+        self.tracker_errors.lock().unwrap().clone()
+    }
+
+    /// Number of users whose permissions an overlay changed.
+    pub fn permission_overlay_hits(&self) -> usize {
+        *self.permission_overlay_hits.lock().unwrap()
+    }
+
+    /// Number of requests refused a permission an overlay revoked.
+    pub fn permission_overlay_denials(&self) -> usize {
+        *self.permission_overlay_denials.lock().unwrap()
+    }
+}
+
+impl ServiceMetrics for RecordingMetrics {
+    fn increment_requests_started(&self, context: &RequestContext) {
+        self.started.lock().unwrap().push(context.method.clone());
+        self.tenants
+            .lock()
+            .unwrap()
+            .push(context.tenant().map(str::to_string));
+    }
+
+    fn increment_requests_completed(&self, context: &RequestContext, success: bool) {
+        self.completed.lock().unwrap().push(CompletedRequest {
+            method: context.method.clone(),
+            success,
+            error_kind: context.metadata.get("error_kind").cloned(),
+        });
+        self.principal_kinds
+            .lock()
+            .unwrap()
+            .push(context.metadata.get("principal_kind").cloned());
+        self.tenants
+            .lock()
+            .unwrap()
+            .push(context.tenant().map(str::to_string));
+    }
+
+    fn record_method_duration(&self, _context: &RequestContext, _duration: Duration) {}
+
+    fn in_flight_guard(&self, _context: &RequestContext) -> InFlightGuard {
+        {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            in_flight.current += 1;
+            in_flight.peak = in_flight.peak.max(in_flight.current);
+        }
+        let in_flight = self.in_flight.clone();
+        InFlightGuard::new(move || in_flight.lock().unwrap().current -= 1)
+    }
+
+    fn record_request_size(&self, context: &RequestContext, bytes: usize) {
+        self.request_sizes.lock().unwrap().push(PayloadSize {
+            method: context.method.clone(),
+            bytes,
+        });
+    }
+
+    fn record_response_size(&self, context: &RequestContext, bytes: usize) {
+        self.response_sizes.lock().unwrap().push(PayloadSize {
+            method: context.method.clone(),
+            bytes,
+        });
+    }
+
+    fn record_connection_opened(&self) {
+        *self.connections_opened.lock().unwrap() += 1;
+    }
+
+    fn record_connection_closed(&self, close_code: Option<u16>, reason: &str) {
+        self.connections_closed
+            .lock()
+            .unwrap()
+            .push((close_code, reason.to_string()));
+    }
+
+    fn record_message_received(&self) {
+        *self.messages_received.lock().unwrap() += 1;
+    }
+
+    fn record_message_sent(&self) {
+        *self.messages_sent.lock().unwrap() += 1;
+    }
+
+    fn record_broadcast_fanout(&self, context: &RequestContext, recipients: usize) {
+        self.fanouts.lock().unwrap().push(Fanout {
+            method: context.method.clone(),
+            recipients,
+        });
+    }
+
+    fn record_session_started(&self) {
+        *self.sessions_started.lock().unwrap() += 1;
+    }
+
+    fn record_sessions_ended(&self, reason: &str, count: usize) {
+        self.sessions_ended
+            .lock()
+            .unwrap()
+            .push((reason.to_string(), count));
+    }
+
+    fn record_active_sessions(&self, count: usize) {
+        *self.active_sessions.lock().unwrap() = Some(count);
+    }
+
+    fn record_tracker_error(&self, tracker: &str, error: &str) {
+        self.tracker_errors.lock().unwrap().push(TrackerError {
+            tracker: tracker.to_string(),
+            error: error.to_string(),
+        });
+    }
+
+    fn record_slow_request(&self, context: &RequestContext) {
+        self.slow_requests
+            .lock()
+            .unwrap()
+            .push(context.method.clone());
+    }
+
+    fn record_permission_overlay_hit(&self) {
+        *self.permission_overlay_hits.lock().unwrap() += 1;
+    }
+
+    fn record_permission_overlay_denial(&self) {
+        *self.permission_overlay_denials.lock().unwrap() += 1;
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use tracing::Subscriber;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// A span recorded by [`capture_spans`], with its fields rendered as strings.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedSpan {
+    pub name: String,
+    pub parent: Option<String>,
+    pub fields: HashMap<String, String>,
+}
+
+impl CapturedSpan {
+    /// The rendered value of a recorded field, if any.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+/// Handle to the spans captured by the global test subscriber.
+#[derive(Clone, Default)]
+pub struct SpanCapture {
+    spans: Arc<Mutex<Vec<(Id, CapturedSpan)>>>,
+}
+
+impl SpanCapture {
+    /// All captured spans with the given name, in creation order.
+    pub fn spans_named(&self, name: &str) -> Vec<CapturedSpan> {
+        self.spans
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, span)| span.name == name)
+            .map(|(_, span)| span.clone())
+            .collect()
+    }
+
+    fn update(&self, id: &Id, update: impl FnOnce(&mut CapturedSpan)) {
+        let mut spans = self.spans.lock().unwrap();
+        // Span ids are reused once a span closes, so the latest entry wins
+        if let Some((_, span)) = spans.iter_mut().rev().find(|(span_id, _)| span_id == id) {
+            update(span);
+        }
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+}
+
+impl<S> Layer<S> for SpanCapture
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut span = CapturedSpan {
+            name: attrs.metadata().name().to_string(),
+            parent: ctx
+                .span(id)
+                .and_then(|span| span.parent())
+                .map(|parent| parent.name().to_string()),
+            fields: HashMap::new(),
+        };
+        attrs.record(&mut FieldVisitor(&mut span.fields));
+        self.spans.lock().unwrap().push((id.clone(), span));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+        self.update(id, |span| {
+            values.record(&mut FieldVisitor(&mut span.fields))
+        });
+    }
+}
+
+/// Install a global subscriber that records every span, and return a handle
+/// to the captured spans.
+///
+/// The subscriber is installed once per test binary; every call returns a
+/// handle to the same capture, so give spans under test unique names.
+pub fn capture_spans() -> SpanCapture {
+    static CAPTURE: OnceLock<SpanCapture> = OnceLock::new();
+
+    CAPTURE
+        .get_or_init(|| {
+            let capture = SpanCapture::default();
+            let subscriber = tracing_subscriber::registry().with(capture.clone());
+            tracing::subscriber::set_global_default(subscriber)
+                .expect("no other global subscriber is installed in this test binary");
+            capture
+        })
+        .clone()
+}
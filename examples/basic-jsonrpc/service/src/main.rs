@@ -5,8 +5,7 @@ use basic_jsonrpc_api::{
     UserProfile,
 };
 use chrono::Utc;
-use ras_jsonrpc_core::{AuthFuture, AuthProvider, AuthenticatedUser};
-use ras_observability_core::{MethodDurationTracker, RequestContext, UsageTracker};
+use ras_jsonrpc_core::{AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_observability_otel::OtelSetupBuilder;
 use std::{
     collections::{HashMap, HashSet},
@@ -30,6 +29,7 @@ impl AuthProvider for MyAuthProvider {
                     user_id: "user123".to_string(),
                     permissions,
                     metadata: None,
+                    kind: PrincipalKind::User,
                 })
             } else if token == "admin_token" {
                 let mut permissions = HashSet::new();
@@ -40,6 +40,7 @@ impl AuthProvider for MyAuthProvider {
                     user_id: "admin123".to_string(),
                     permissions,
                     metadata: None,
+                    kind: PrincipalKind::User,
                 })
             } else {
                 Err(ras_jsonrpc_core::AuthError::InvalidToken)
@@ -301,52 +302,7 @@ async fn main() {
         storage: task_storage.clone(),
     })
     .base_url("/rpc")
-    .with_usage_tracker({
-        let usage_tracker = otel.usage_tracker();
-        move |headers, user, payload| {
-            let method = payload.method.clone();
-            let context = RequestContext::jsonrpc(method);
-            let usage_tracker = usage_tracker.clone();
-            let headers_clone = headers.clone();
-            let user_clone = user.cloned();
-
-            async move {
-                // Log the request
-                match &user_clone {
-                    Some(u) => {
-                        info!(
-                            "RPC call: method={}, user={}, permissions={:?}",
-                            context.method, u.user_id, u.permissions,
-                        );
-                    }
-                    None => {
-                        info!("RPC call: method={}, user=anonymous", context.method,);
-                    }
-                }
-
-                // Track the request
-                usage_tracker
-                    .track_request(&headers_clone, user_clone.as_ref(), &context)
-                    .await;
-            }
-        }
-    })
-    .with_method_duration_tracker({
-        let duration_tracker = otel.method_duration_tracker();
-        move |method: &str,
-              user: Option<&ras_jsonrpc_core::AuthenticatedUser>,
-              duration: std::time::Duration| {
-            let context = RequestContext::jsonrpc(method.to_string());
-            let duration_tracker = duration_tracker.clone();
-            let user_clone = user.cloned();
-
-            async move {
-                duration_tracker
-                    .track_duration(&context, user_clone.as_ref(), duration)
-                    .await;
-            }
-        }
-    })
+    .with_observability(&otel)
     .auth_provider(MyAuthProvider)
     .build()
     .expect("Failed to build JSON-RPC router");
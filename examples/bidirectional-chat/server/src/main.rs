@@ -19,9 +19,11 @@ use bidirectional_chat_api::*;
 use chrono::Utc;
 use dashmap::DashMap;
 use ras_auth_core::AuthenticatedUser;
-use ras_identity_core::{UserPermissions, VerifiedIdentity};
+use ras_identity_core::{AuthSource, IdentityError, UserPermissions, VerifiedIdentity};
 use ras_identity_local::LocalUserProvider;
-use ras_identity_session::{JwtAuthProvider, SessionConfig, SessionService};
+use ras_identity_session::{
+    JwtAuthProvider, KeySource, SessionConfig, SessionError, SessionService, SlidingWindowLimiter,
+};
 use ras_jsonrpc_bidirectional_server::{
     DefaultConnectionManager, WebSocketServiceBuilder,
     service::{BuiltWebSocketService, websocket_handler},
@@ -31,6 +33,7 @@ use ras_rest_core::{RestError, RestResponse, RestResult};
 use serde_json::json;
 use std::{
     collections::{HashMap, HashSet},
+    net::SocketAddr,
     sync::Arc,
     time::Duration,
 };
@@ -272,6 +275,7 @@ impl ChatServiceService for ChatServer {
         &self,
         client_id: ConnectionId,
         connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         request: SendMessageRequest,
     ) -> Result<SendMessageResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -402,6 +406,7 @@ impl ChatServiceService for ChatServer {
         &self,
         client_id: ConnectionId,
         connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         request: JoinRoomRequest,
     ) -> Result<JoinRoomResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -596,6 +601,7 @@ impl ChatServiceService for ChatServer {
         &self,
         client_id: ConnectionId,
         connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         request: LeaveRoomRequest,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -659,6 +665,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: ListRoomsRequest,
     ) -> Result<ListRoomsResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -681,6 +688,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         request: KickUserRequest,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
@@ -740,6 +748,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         request: BroadcastAnnouncementRequest,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -777,6 +786,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         request: GetProfileRequest,
     ) -> Result<GetProfileResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -852,6 +862,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         user: &AuthenticatedUser,
         request: UpdateProfileRequest,
     ) -> Result<UpdateProfileResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -951,6 +962,7 @@ impl ChatServiceService for ChatServer {
         &self,
         client_id: ConnectionId,
         connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: StartTypingRequest,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -999,6 +1011,7 @@ impl ChatServiceService for ChatServer {
         &self,
         client_id: ConnectionId,
         connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: StopTypingRequest,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -1279,6 +1292,28 @@ struct ChatPermissions {
     admin_users: Vec<config::AdminUser>,
 }
 
+tokio::task_local! {
+    /// Where the REST request being handled came from, set by `record_client_source`
+    static CLIENT_SOURCE: AuthSource;
+}
+
+/// Make the client's address and user agent available to the REST handlers
+async fn record_client_source(
+    axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let source = AuthSource {
+        ip: Some(addr.ip()),
+        user_agent: request
+            .headers()
+            .get(axum::http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    };
+    CLIENT_SOURCE.scope(source, next.run(request)).await
+}
+
 // REST API handlers
 #[derive(Clone)]
 struct AuthHandlers {
@@ -1322,14 +1357,18 @@ impl AuthHandlers {
             "provider": provider_id,
         });
 
-        // Begin session
+        // Begin session, limiting attempts per username and client address
+        let source = CLIENT_SOURCE.try_with(Clone::clone).unwrap_or_default();
         let token = self
             .session_service
-            .begin_session(provider_id, auth_payload)
+            .begin_session_from(provider_id, auth_payload, source)
             .await
             .map_err(|e| {
                 warn!(provider = %provider_id, "Login failed: {}", e);
-                RestError::unauthorized("Invalid credentials")
+                match e {
+                    SessionError::RateLimited { .. } => RestError::new(429, e.to_string()),
+                    _ => RestError::unauthorized("Invalid credentials"),
+                }
             })?;
 
         // Parse token to get user info (for response)
@@ -1364,7 +1403,12 @@ impl AuthHandlers {
             .await
             .map_err(|e| {
                 warn!(username = %request.username, "Registration failed: {}", e);
-                RestError::conflict("Username already exists")
+                match e {
+                    IdentityError::WeakPassword(_) => {
+                        RestError::unprocessable_entity(e.to_string())
+                    }
+                    _ => RestError::conflict("Username already exists"),
+                }
             })?;
 
         info!(username = %request.username, email = ?request.email, "User registered successfully");
@@ -1427,7 +1471,7 @@ async fn main() -> Result<()> {
 
     // Create identity provider - use Arc to share between session service and registration
     info!("Setting up identity provider");
-    let identity_provider = Arc::new(LocalUserProvider::new());
+    let identity_provider = Arc::new(LocalUserProvider::default());
 
     // Add admin users from configuration
     if config.admin.auto_create {
@@ -1458,11 +1502,11 @@ async fn main() -> Result<()> {
         let test_users = vec![
             (
                 "alice",
-                "alice123",
+                "alice12345",
                 Some("alice@example.com"),
                 Some("Alice"),
             ),
-            ("bob", "bob123", Some("bob@example.com"), Some("Bob")),
+            ("bob", "bob12345", Some("bob@example.com"), Some("Bob")),
         ];
 
         for (username, password, email, display_name) in test_users {
@@ -1486,13 +1530,23 @@ async fn main() -> Result<()> {
         jwt_secret: config.auth.jwt_secret.clone(),
         jwt_ttl: chrono::Duration::seconds(config.auth.jwt_ttl_seconds),
         refresh_enabled: config.auth.refresh_enabled,
+        refresh_ttl: chrono::Duration::days(30),
+        refreshable_jwt_ttl: chrono::Duration::minutes(15),
         enforce_active_sessions: true,
+        sliding_ttl: None,
+        absolute_max: None,
         algorithm: match config.auth.jwt_algorithm.as_str() {
             "HS256" => jsonwebtoken::Algorithm::HS256,
             "HS384" => jsonwebtoken::Algorithm::HS384,
             "HS512" => jsonwebtoken::Algorithm::HS512,
             _ => jsonwebtoken::Algorithm::HS256, // Default
         },
+        key_source: KeySource::Secret,
+        issuer: None,
+        audience: Vec::new(),
+        leeway: chrono::Duration::seconds(60),
+        not_before: None,
+        binding: Default::default(),
     };
     info!(
         "Creating session service with JWT TTL: {} seconds",
@@ -1501,7 +1555,8 @@ async fn main() -> Result<()> {
     let session_service = Arc::new(
         SessionService::new(session_config)
             .map_err(anyhow::Error::from)?
-            .with_permissions(Arc::new(ChatPermissions::new(config.admin.users.clone()))),
+            .with_permissions(Arc::new(ChatPermissions::new(config.admin.users.clone())))
+            .with_rate_limiter(Arc::new(SlidingWindowLimiter::default())),
     );
 
     // Register the identity provider with the session service
@@ -1572,7 +1627,8 @@ async fn main() -> Result<()> {
 
     let auth_router = ChatAuthServiceBuilder::new(auth_service_impl)
         .auth_provider(auth_provider.as_ref().clone())
-        .build();
+        .build()
+        .layer(axum::middleware::from_fn(record_client_source));
 
     // Create WebSocket endpoint
     type ChatServiceType = BuiltWebSocketService<
@@ -1615,7 +1671,12 @@ async fn main() -> Result<()> {
 
     info!("Server started successfully, ready to accept connections");
 
-    axum::serve(listener, app).await.map_err(|e| {
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .map_err(|e| {
         error!("Server error: {}", e);
         e
     })?;
@@ -20,7 +20,7 @@ use chrono::Utc;
 use ras_auth_core::AuthenticatedUser;
 use ras_identity_core::{UserPermissions, VerifiedIdentity};
 use ras_identity_local::LocalUserProvider;
-use ras_identity_session::{JwtAuthProvider, SessionConfig, SessionService};
+use ras_identity_session::{JwtAuthProvider, KeySource, SessionConfig, SessionService};
 use ras_jsonrpc_bidirectional_server::{
     DefaultConnectionManager, WebSocketServiceBuilder,
     service::{BuiltWebSocketService, websocket_handler},
@@ -99,7 +99,7 @@ impl TestChatServer {
         };
 
         // Set up server components
-        let identity_provider = Arc::new(LocalUserProvider::new());
+        let identity_provider = Arc::new(LocalUserProvider::default());
 
         // Add admin users
         for admin_user in &config.admin.users {
@@ -115,11 +115,11 @@ impl TestChatServer {
 
         // Add test users
         let test_users = vec![
-            ("alice", "alice123", Some("alice@test.com"), Some("Alice")),
-            ("bob", "bob123", Some("bob@test.com"), Some("Bob")),
+            ("alice", "alice12345", Some("alice@test.com"), Some("Alice")),
+            ("bob", "bob12345", Some("bob@test.com"), Some("Bob")),
             (
                 "charlie",
-                "charlie123",
+                "charlie12345",
                 Some("charlie@test.com"),
                 Some("Charlie"),
             ),
@@ -141,8 +141,18 @@ impl TestChatServer {
             jwt_secret: config.auth.jwt_secret.clone(),
             jwt_ttl: chrono::Duration::seconds(config.auth.jwt_ttl_seconds),
             refresh_enabled: config.auth.refresh_enabled,
+            refresh_ttl: chrono::Duration::days(30),
+            refreshable_jwt_ttl: chrono::Duration::minutes(15),
             enforce_active_sessions: true,
+            sliding_ttl: None,
+            absolute_max: None,
             algorithm: jsonwebtoken::Algorithm::HS256,
+            key_source: KeySource::Secret,
+            issuer: None,
+            audience: Vec::new(),
+            leeway: chrono::Duration::seconds(60),
+            not_before: None,
+            binding: Default::default(),
         };
 
         let session_service = Arc::new(
@@ -154,7 +164,7 @@ impl TestChatServer {
         );
 
         // Register identity provider with session service
-        let session_identity_provider = LocalUserProvider::new();
+        let session_identity_provider = LocalUserProvider::default();
         for admin_user in &config.admin.users {
             let _ = session_identity_provider
                 .add_user(
@@ -492,6 +502,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: SendMessageRequest,
     ) -> Result<SendMessageResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -509,6 +520,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         request: JoinRoomRequest,
     ) -> Result<JoinRoomResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -527,6 +539,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: LeaveRoomRequest,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -537,6 +550,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: ListRoomsRequest,
     ) -> Result<ListRoomsResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -557,6 +571,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: KickUserRequest,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
@@ -567,6 +582,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: BroadcastAnnouncementRequest,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -577,6 +593,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         request: GetProfileRequest,
     ) -> Result<GetProfileResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -600,6 +617,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         user: &AuthenticatedUser,
         request: UpdateProfileRequest,
     ) -> Result<UpdateProfileResponse, Box<dyn std::error::Error + Send + Sync>> {
@@ -680,6 +698,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: StartTypingRequest,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -690,6 +709,7 @@ impl ChatServiceService for ChatServer {
         &self,
         _client_id: ConnectionId,
         _connection_manager: &dyn ConnectionManager,
+        _ctx: &ras_jsonrpc_bidirectional_server::ConnectionContext,
         _user: &AuthenticatedUser,
         _request: StopTypingRequest,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -772,7 +792,7 @@ async fn test_user_authentication() -> Result<()> {
     let server = TestChatServer::start().await?;
 
     // Test login with valid credentials
-    let token = server.login("alice", "alice123").await?;
+    let token = server.login("alice", "alice12345").await?;
     assert!(!token.is_empty());
 
     // Test login with invalid credentials
@@ -837,7 +857,7 @@ async fn test_admin_permissions() -> Result<()> {
     assert!(!admin_token.is_empty());
 
     // Login as regular user
-    let user_token = server.login("alice", "alice123").await?;
+    let user_token = server.login("alice", "alice12345").await?;
     assert!(!user_token.is_empty());
 
     // TODO: Test permission-based operations when WebSocket client is available
@@ -861,7 +881,7 @@ async fn test_multiple_concurrent_users() -> Result<()> {
                     .post(format!("{}/auth/login", url))
                     .json(&json!({
                         "username": username,
-                        "password": format!("{}123", username),
+                        "password": format!("{}12345", username),
                     }))
                     .send()
                     .await
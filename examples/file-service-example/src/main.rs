@@ -4,7 +4,7 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_file_macro::file_service;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
@@ -56,6 +56,7 @@ impl AuthProvider for DemoAuthProvider {
                         .into_iter()
                         .collect::<HashSet<_>>(),
                     metadata: None,
+                    kind: PrincipalKind::User,
                 }),
                 "admin-token" => Ok(AuthenticatedUser {
                     user_id: "admin-456".to_string(),
@@ -63,6 +64,7 @@ impl AuthProvider for DemoAuthProvider {
                         .into_iter()
                         .collect::<HashSet<_>>(),
                     metadata: None,
+                    kind: PrincipalKind::User,
                 }),
                 _ => Err(AuthError::InvalidToken),
             }
@@ -1,4 +1,4 @@
-use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use std::collections::HashSet;
 
 /// Simple mock auth provider that accepts "validtoken" as a valid JWT
@@ -16,6 +16,7 @@ impl AuthProvider for SimpleAuthProvider {
                     user_id: "testuser".to_string(),
                     permissions,
                     metadata: None,
+                    kind: PrincipalKind::User,
                 })
             } else {
                 Err(AuthError::InvalidToken)
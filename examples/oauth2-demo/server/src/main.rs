@@ -1,19 +1,22 @@
 use anyhow::{Context, Result};
-use axum::http::Method;
+use axum::http::{HeaderMap, Method, header::USER_AGENT};
 use axum::{
     Json, Router,
-    extract::{Query, State},
+    extract::{ConnectInfo, Query, State},
     response::{Html, Redirect},
     routing::{get, post},
 };
-use ras_identity_core::{IdentityError, IdentityProvider};
+use ras_identity_core::{AuthSource, IdentityError, IdentityProvider};
 use ras_identity_oauth2::{
     InMemoryStateStore, OAuth2AuthPayload, OAuth2Config, OAuth2Provider, OAuth2ProviderConfig,
     OAuth2Response,
 };
-use ras_identity_session::{JwtAuthProvider, SessionConfig, SessionService};
+use ras_identity_session::{
+    JwtAuthProvider, KeySource, SessionConfig, SessionService, SlidingWindowLimiter,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
@@ -159,14 +162,25 @@ fn create_session_service(config: &AppConfig) -> Result<SessionService> {
         jwt_secret: config.jwt_secret.clone(),
         jwt_ttl: chrono::Duration::hours(24),
         refresh_enabled: true,
+        refresh_ttl: chrono::Duration::days(30),
+        refreshable_jwt_ttl: chrono::Duration::minutes(15),
         enforce_active_sessions: false,
+        sliding_ttl: None,
+        absolute_max: None,
         algorithm: jsonwebtoken::Algorithm::HS256,
+        key_source: KeySource::Secret,
+        issuer: None,
+        audience: Vec::new(),
+        leeway: chrono::Duration::seconds(60),
+        not_before: None,
+        binding: Default::default(),
     };
 
     let permissions_provider = Arc::new(GoogleOAuth2Permissions::new());
     let session_service = SessionService::new(session_config)
         .map_err(anyhow::Error::from)?
-        .with_permissions(permissions_provider);
+        .with_permissions(permissions_provider)
+        .with_rate_limiter(Arc::new(SlidingWindowLimiter::default()));
 
     Ok(session_service)
 }
@@ -213,6 +227,8 @@ async fn start_oauth2_handler(
 /// Handler for OAuth2 callback
 async fn oauth2_callback_handler(
     State(state): State<AppState>,
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Query(callback_query): Query<CallbackQuery>,
 ) -> Result<Redirect, String> {
     info!("Handling OAuth2 callback");
@@ -247,10 +263,17 @@ async fn oauth2_callback_handler(
     let payload_json = serde_json::to_value(auth_payload)
         .map_err(|e| format!("Failed to serialize callback payload: {}", e))?;
 
-    // Create session using the session service
+    // Create session using the session service, limiting attempts per client address
+    let source = AuthSource {
+        ip: Some(client_addr.ip()),
+        user_agent: headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    };
     let token = state
         .session_service
-        .begin_session("oauth2", payload_json)
+        .begin_session_from("oauth2", payload_json, source)
         .await
         .map_err(|e| format!("Failed to create session: {}", e))?;
 
@@ -391,9 +414,12 @@ async fn main() -> Result<()> {
         "This is an example application. Do not use in production without proper security review."
     );
 
-    axum::serve(listener, combined_app)
-        .await
-        .context("Server error")?;
+    axum::serve(
+        listener,
+        combined_app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .context("Server error")?;
 
     Ok(())
 }
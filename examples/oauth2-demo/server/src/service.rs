@@ -160,7 +160,7 @@ pub fn create_api_router<A: AuthProvider + Clone + Send + Sync + 'static>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ras_jsonrpc_core::{AuthFuture, AuthenticatedUser};
+    use ras_jsonrpc_core::{AuthFuture, AuthenticatedUser, PrincipalKind};
     use std::collections::HashSet;
 
     // Mock auth provider for testing
@@ -185,6 +185,7 @@ mod tests {
                     user_id: "test_user".to_string(),
                     permissions,
                     metadata: None,
+                    kind: PrincipalKind::User,
                 })
             })
         }
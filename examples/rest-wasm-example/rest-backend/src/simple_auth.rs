@@ -1,4 +1,4 @@
-use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use std::collections::HashSet;
 
 /// Simple mock auth provider that accepts "validtoken" for user and "admintoken" for admin
@@ -17,6 +17,7 @@ impl AuthProvider for SimpleAuthProvider {
                         user_id: "testuser".to_string(),
                         permissions,
                         metadata: None,
+                        kind: PrincipalKind::User,
                     })
                 }
                 "admintoken" => {
@@ -28,6 +29,7 @@ impl AuthProvider for SimpleAuthProvider {
                         user_id: "admin".to_string(),
                         permissions,
                         metadata: None,
+                        kind: PrincipalKind::User,
                     })
                 }
                 _ => Err(AuthError::InvalidToken),
@@ -2,7 +2,7 @@ use std::collections::HashSet;
 
 use anyhow::Result;
 use axum::Router;
-use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_jsonrpc_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_jsonrpc_macro::jsonrpc_service;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -146,6 +146,7 @@ impl AuthProvider for FixtureAuthProvider {
                     .map(str::to_string)
                     .collect::<HashSet<_>>(),
                 metadata: None,
+                kind: PrincipalKind::User,
             })
         })
     }
@@ -1,7 +1,7 @@
 use std::collections::HashSet;
 
 use anyhow::Result;
-use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser};
+use ras_auth_core::{AuthError, AuthFuture, AuthProvider, AuthenticatedUser, PrincipalKind};
 use ras_rest_core::{RestResponse, RestResult};
 use ras_rest_macro::rest_service;
 use schemars::JsonSchema;
@@ -148,6 +148,7 @@ impl AuthProvider for FixtureAuthProvider {
                     .map(str::to_string)
                     .collect::<HashSet<_>>(),
                 metadata: None,
+                kind: PrincipalKind::User,
             })
         })
     }
@@ -150,6 +150,34 @@ test.describe('JSON-RPC API explorer', () => {
     expect(sessionStorageValues).toContain('admin-token');
   });
 
+  test('remembers tokens on request, forgets them, and replays history', async ({ page }) => {
+    await selectMethod(page, 'create_widget');
+    await page.locator('#jwt-token').fill('admin-token');
+    await page.locator('#remember-token').check();
+    await page.locator('#save-token').click();
+    await expect(page.locator('#auth-state')).toContainText('Token remembered');
+
+    await page.reload();
+    await expect(page.locator('#jwt-token')).toHaveValue('admin-token');
+    await expect(page.locator('#remember-token')).toBeChecked();
+
+    await selectMethod(page, 'create_widget');
+    await page.locator('#params-editor').fill(JSON.stringify({ name: 'Replayed RPC', owner: 'history' }, null, 2));
+    await send(page);
+    await expect(page.locator('#history-list')).toContainText('RPC create_widget');
+    await page.locator('#history-list').getByRole('button', { name: 'Replay' }).first().click();
+    await expect(page.locator('#history-list .history-item')).toHaveCount(2);
+    await page.locator('#history-list').getByRole('button', { name: 'Show response' }).first().click();
+    await expect(page.locator('#response-output')).toContainText('Replayed RPC');
+
+    await page.locator('#clear-token').click();
+    await expect(page.locator('#auth-state')).toContainText('No token');
+    const storedValues = await page.evaluate(() =>
+      [...Object.values(localStorage), ...Object.values(sessionStorage)].join('\n')
+    );
+    expect(storedValues).not.toContain('admin-token');
+  });
+
   test('persists theme preference in localStorage', async ({ page }) => {
     await page.locator('#theme-toggle').click();
     await expect(page.locator('html')).toHaveAttribute('data-theme', 'light');